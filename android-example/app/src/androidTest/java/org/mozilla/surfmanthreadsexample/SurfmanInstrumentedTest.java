@@ -16,16 +16,127 @@ import static org.junit.Assert.*;
  */
 @RunWith(AndroidJUnit4.class)
 public class SurfmanInstrumentedTest {
+    private static native void testAdoptNativeContext();
+    private static native void testBeginRendering();
+    private static native void testChooseConfigForVisualDepths();
+    private static native void testColorDepthRoundTrip();
+    private static native void testColorProfileFromIcc();
+    private static native void testConnectionIsSendAndSync();
     private static native void testContextCreation();
+    private static native void testContextInfo();
+    private static native void testContextLost();
+    private static native void testContextPriority();
+    private static native void testCreateAdapterByName();
+    private static native void testCreateVersionedContext();
     private static native void testCrossDeviceSurfaceTextureBlitFramebuffer();
     private static native void testCrossThreadSurfaceTextureBlitFramebuffer();
+    private static native void testDebugContextFlag();
     private static native void testDeviceAccessors();
     private static native void testDeviceCreation();
+    private static native void testEnvironmentReport();
+    private static native void testExtensionQueries();
+    private static native void testGenericSurfaceColorProfile();
     private static native void testGenericSurfaceCreation();
     private static native void testGL();
+    private static native void testGetProcAddress();
+    private static native void testGles2CapabilityClamp();
+    private static native void testGlesConnection();
+    private static native void testGlesMinorVersions();
+    private static native void testHeadlessConnection();
+    private static native void testLibrarySearchDirs();
+    private static native void testLowMemoryMode();
+    private static native void testMakeContextCurrentGuarded();
+    private static native void testMultipleFailuresDisplay();
     private static native void testNewlyCreatedContextsAreNotCurrent();
+    private static native void testPresentSurfaceOwnership();
+    private static native void testSharedContextObjects();
+    private static native void testSharedPlaceholderContexts();
     private static native void testSurfaceTextureBlitFramebuffer();
     private static native void testSurfaceTextureRightSideUp();
+    private static native void testAdapterKeys();
+    private static native void testCancellationToken();
+    private static native void testContextDescriptorRawParts();
+    private static native void testContextProfiles();
+    private static native void testCpuLayerDamage();
+    private static native void testDeviceIdentity();
+    private static native void testInternalOperationsPreserveCurrentContext();
+    private static native void testMixedGlApisOnOneThread();
+    private static native void testNoErrorFlagConflicts();
+    private static native void testPresentationTimestamps();
+    private static native void testReleaseBehaviorFlag();
+    private static native void testRobustAccessFlag();
+    private static native void testSchedulingClass();
+    private static native void testSurfaceAccessValidation();
+    private static native void testSurfaceFromTexture();
+    private static native void testUnbindAndRebindSurface();
+    private static native void testBindSurfaceMisuse();
+    private static native void testLeakCheck();
+    private static native void testPurgeStateMachine();
+    private static native void testSurfacePurgeability();
+    private static native void testIsContextCurrent();
+    private static native void testAppliedSwapInterval();
+    private static native void testChooseConfigForSamples();
+    private static native void testMultisampledSurface();
+    private static native void testChooseConfigForDepthAndStencilSizes();
+    private static native void testDepthAndStencilSizes();
+    private static native void testPresentingIdentity();
+    private static native void testGenericSurfaceFormatForOptions();
+    private static native void testSrgbSurface();
+    private static native void testChooseConfigForFloatColor();
+    private static native void testHalfFloatSurface();
+    private static native void testWidgetSurfaceFormatForOptions();
+    private static native void testConvertSurface();
+    private static native void testChooseWidgetConfigForOpacity();
+    private static native void testRecommendedSurfaceConfig();
+    private static native void testRecommendedSurfaceConfigTable();
+    private static native void testResizeSurface();
+    private static native void testSetSwapIntervalGenericSurface();
+    private static native void testWaitPreviousBufferReleasedGenericSurface();
+    private static native void testPostPresentInvalidationGenericSurface();
+    private static native void testPostPresentInvalidationRules();
+    private static native void testBufferAgeGenericSurface();
+    private static native void testContextCurrentThread();
+    private static native void testCurrentContextHolderDiagnostics();
+    private static native void testClearSurfaceRegion();
+    private static native void testPresentSurfaceWithDamageMisuse();
+    private static native void testExportSurfaceDmaBuf();
+    private static native void testGenericSurfaceIgnoresPresentMode();
+    private static native void testGenericSurfacePreservesContents();
+    private static native void testDebugSurfaceFillCheckerboard();
+    private static native void testDebugSurfaceFillFormats();
+    private static native void testDebugSurfaceFillSolidColor();
+    private static native void testQuerySurfaceNativeSizeGenericSurface();
+    private static native void testAutoSwapIntervalGenericSurface();
+    private static native void testAutoVsyncRefusedInterval();
+    private static native void testAutoVsyncSwitchesWithHysteresis();
+    private static native void testInvalidSurfaceSizes();
+    private static native void testDrainGlErrors();
+    private static native void testErrorTranslation();
+    private static native void testPreExistingGlErrorsNotBlamedOnSurfman();
+    private static native void testSurfaceTransformGenericSurface();
+    private static native void testSurfaceTransformSwapsDimensions();
+    private static native void testScaleTrackerAutoRescale();
+    private static native void testScaleTrackerReportsChanges();
+    private static native void testReadSurfaceData();
+    private static native void testThreadBoundHandle();
+    private static native void testWriteSurfaceData();
+    private static native void testSurfaceInfoDetails();
+    private static native void testHeldFrameReadRect();
+    private static native void testPausePresentationGenericSurface();
+    private static native void testPresentPause();
+    private static native void testPresentSurfaceWithFenceGenericSurface();
+    private static native void testContextIsHardwareAccelerated();
+    private static native void testSoftwareRendererPatterns();
+    private static native void testBulkOpsScope();
+    private static native void testWithContextCurrent();
+    private static native void testSyncFdRoundTrip();
+    private static native void testNativeFormatTable();
+    private static native void testSurfaceNativeFormat();
+    private static native void testPresentationTimeClocks();
+    private static native void testQueryPresentationTime();
+    private static native void testColorProfileTracker();
+    private static native void testCreateSurfacesCancellation();
+    private static native void testClipDamage();
 
     static {
         System.loadLibrary("surfman_android_threads");
@@ -39,11 +150,66 @@ public class SurfmanInstrumentedTest {
         assertEquals("org.mozilla.surfmanthreadsexample", appContext.getPackageName());
     }
 
+    @Test
+    public void adoptNativeContext() {
+        testAdoptNativeContext();
+    }
+
+    @Test
+    public void beginRendering() {
+        testBeginRendering();
+    }
+
+    @Test
+    public void chooseConfigForVisualDepths() {
+        testChooseConfigForVisualDepths();
+    }
+
+    @Test
+    public void colorDepthRoundTrip() {
+        testColorDepthRoundTrip();
+    }
+
+    @Test
+    public void colorProfileFromIcc() {
+        testColorProfileFromIcc();
+    }
+
+    @Test
+    public void connectionIsSendAndSync() {
+        testConnectionIsSendAndSync();
+    }
+
     @Test
     public void contextCreation() {
         testContextCreation();
     }
 
+    @Test
+    public void contextInfo() {
+        testContextInfo();
+    }
+
+    @Test
+    public void contextLost() {
+        testContextLost();
+    }
+
+    @Test
+    public void contextPriority() {
+        testContextPriority();
+    }
+
+    @Test
+    public void createAdapterByName() {
+        testCreateAdapterByName();
+    }
+
+    @Test
+    public void createVersionedContext() {
+        testCreateVersionedContext();
+    }
+
     @Test
     public void crossDeviceSurfaceTextureBlitFramebuffer() {
         testCrossDeviceSurfaceTextureBlitFramebuffer();
@@ -54,6 +220,11 @@ public class SurfmanInstrumentedTest {
         testCrossThreadSurfaceTextureBlitFramebuffer();
     }
 
+    @Test
+    public void debugContextFlag() {
+        testDebugContextFlag();
+    }
+
     @Test
     public void deviceAccessors() {
         testDeviceAccessors();
@@ -64,21 +235,96 @@ public class SurfmanInstrumentedTest {
         testDeviceCreation();
     }
 
+    @Test
+    public void environmentReport() {
+        testEnvironmentReport();
+    }
+
+    @Test
+    public void extensionQueries() {
+        testExtensionQueries();
+    }
+
+    @Test
+    public void genericSurfaceColorProfile() {
+        testGenericSurfaceColorProfile();
+    }
+
     @Test
     public void genericSurfaceCreation() {
         testGenericSurfaceCreation();
     }
 
+    @Test
+    public void getProcAddress() {
+        testGetProcAddress();
+    }
+
     @Test
     public void gl() {
         testGL();
     }
 
+    @Test
+    public void gles2CapabilityClamp() {
+        testGles2CapabilityClamp();
+    }
+
+    @Test
+    public void glesConnection() {
+        testGlesConnection();
+    }
+
+    @Test
+    public void glesMinorVersions() {
+        testGlesMinorVersions();
+    }
+
+    @Test
+    public void headlessConnection() {
+        testHeadlessConnection();
+    }
+
+    @Test
+    public void librarySearchDirs() {
+        testLibrarySearchDirs();
+    }
+
+    @Test
+    public void lowMemoryMode() {
+        testLowMemoryMode();
+    }
+
+    @Test
+    public void makeContextCurrentGuarded() {
+        testMakeContextCurrentGuarded();
+    }
+
+    @Test
+    public void multipleFailuresDisplay() {
+        testMultipleFailuresDisplay();
+    }
+
     @Test
     public void newlyCreatedContextsAreNotCurrent() {
         testNewlyCreatedContextsAreNotCurrent();
     }
 
+    @Test
+    public void presentSurfaceOwnership() {
+        testPresentSurfaceOwnership();
+    }
+
+    @Test
+    public void sharedContextObjects() {
+        testSharedContextObjects();
+    }
+
+    @Test
+    public void sharedPlaceholderContexts() {
+        testSharedPlaceholderContexts();
+    }
+
     @Test
     public void surfaceTextureBlitFramebuffer() {
         testSurfaceTextureBlitFramebuffer();
@@ -88,4 +334,424 @@ public class SurfmanInstrumentedTest {
     public void surfaceTextureRightSideUp() {
         testSurfaceTextureRightSideUp();
     }
+
+    @Test
+    public void adapterKeys() {
+        testAdapterKeys();
+    }
+
+    @Test
+    public void cancellationToken() {
+        testCancellationToken();
+    }
+
+    @Test
+    public void contextDescriptorRawParts() {
+        testContextDescriptorRawParts();
+    }
+
+    @Test
+    public void contextProfiles() {
+        testContextProfiles();
+    }
+
+    @Test
+    public void cpuLayerDamage() {
+        testCpuLayerDamage();
+    }
+
+    @Test
+    public void deviceIdentity() {
+        testDeviceIdentity();
+    }
+
+    @Test
+    public void internalOperationsPreserveCurrentContext() {
+        testInternalOperationsPreserveCurrentContext();
+    }
+
+    @Test
+    public void mixedGlApisOnOneThread() {
+        testMixedGlApisOnOneThread();
+    }
+
+    @Test
+    public void noErrorFlagConflicts() {
+        testNoErrorFlagConflicts();
+    }
+
+    @Test
+    public void presentationTimestamps() {
+        testPresentationTimestamps();
+    }
+
+    @Test
+    public void releaseBehaviorFlag() {
+        testReleaseBehaviorFlag();
+    }
+
+    @Test
+    public void robustAccessFlag() {
+        testRobustAccessFlag();
+    }
+
+    @Test
+    public void schedulingClass() {
+        testSchedulingClass();
+    }
+
+    @Test
+    public void surfaceAccessValidation() {
+        testSurfaceAccessValidation();
+    }
+
+    @Test
+    public void surfaceFromTexture() {
+        testSurfaceFromTexture();
+    }
+
+    @Test
+    public void unbindAndRebindSurface() {
+        testUnbindAndRebindSurface();
+    }
+
+    @Test
+    public void bindSurfaceMisuse() {
+        testBindSurfaceMisuse();
+    }
+
+    @Test
+    public void leakCheck() {
+        testLeakCheck();
+    }
+
+    @Test
+    public void purgeStateMachine() {
+        testPurgeStateMachine();
+    }
+
+    @Test
+    public void surfacePurgeability() {
+        testSurfacePurgeability();
+    }
+
+    @Test
+    public void isContextCurrent() {
+        testIsContextCurrent();
+    }
+
+    @Test
+    public void appliedSwapInterval() {
+        testAppliedSwapInterval();
+    }
+
+    @Test
+    public void chooseConfigForSamples() {
+        testChooseConfigForSamples();
+    }
+
+    @Test
+    public void multisampledSurface() {
+        testMultisampledSurface();
+    }
+
+    @Test
+    public void chooseConfigForDepthAndStencilSizes() {
+        testChooseConfigForDepthAndStencilSizes();
+    }
+
+    @Test
+    public void depthAndStencilSizes() {
+        testDepthAndStencilSizes();
+    }
+
+    @Test
+    public void presentingIdentity() {
+        testPresentingIdentity();
+    }
+
+    @Test
+    public void genericSurfaceFormatForOptions() {
+        testGenericSurfaceFormatForOptions();
+    }
+
+    @Test
+    public void srgbSurface() {
+        testSrgbSurface();
+    }
+
+    @Test
+    public void chooseConfigForFloatColor() {
+        testChooseConfigForFloatColor();
+    }
+
+    @Test
+    public void halfFloatSurface() {
+        testHalfFloatSurface();
+    }
+
+    @Test
+    public void widgetSurfaceFormatForOptions() {
+        testWidgetSurfaceFormatForOptions();
+    }
+
+    @Test
+    public void convertSurface() {
+        testConvertSurface();
+    }
+
+    @Test
+    public void chooseWidgetConfigForOpacity() {
+        testChooseWidgetConfigForOpacity();
+    }
+
+    @Test
+    public void recommendedSurfaceConfig() {
+        testRecommendedSurfaceConfig();
+    }
+
+    @Test
+    public void recommendedSurfaceConfigTable() {
+        testRecommendedSurfaceConfigTable();
+    }
+
+    @Test
+    public void resizeSurface() {
+        testResizeSurface();
+    }
+
+    @Test
+    public void setSwapIntervalGenericSurface() {
+        testSetSwapIntervalGenericSurface();
+    }
+
+    @Test
+    public void waitPreviousBufferReleasedGenericSurface() {
+        testWaitPreviousBufferReleasedGenericSurface();
+    }
+
+    @Test
+    public void postPresentInvalidationGenericSurface() {
+        testPostPresentInvalidationGenericSurface();
+    }
+
+    @Test
+    public void postPresentInvalidationRules() {
+        testPostPresentInvalidationRules();
+    }
+
+    @Test
+    public void bufferAgeGenericSurface() {
+        testBufferAgeGenericSurface();
+    }
+
+    @Test
+    public void contextCurrentThread() {
+        testContextCurrentThread();
+    }
+
+    @Test
+    public void currentContextHolderDiagnostics() {
+        testCurrentContextHolderDiagnostics();
+    }
+
+    @Test
+    public void clearSurfaceRegion() {
+        testClearSurfaceRegion();
+    }
+
+    @Test
+    public void presentSurfaceWithDamageMisuse() {
+        testPresentSurfaceWithDamageMisuse();
+    }
+
+    @Test
+    public void exportSurfaceDmaBuf() {
+        testExportSurfaceDmaBuf();
+    }
+
+    @Test
+    public void genericSurfaceIgnoresPresentMode() {
+        testGenericSurfaceIgnoresPresentMode();
+    }
+
+    @Test
+    public void genericSurfacePreservesContents() {
+        testGenericSurfacePreservesContents();
+    }
+
+    @Test
+    public void debugSurfaceFillCheckerboard() {
+        testDebugSurfaceFillCheckerboard();
+    }
+
+    @Test
+    public void debugSurfaceFillFormats() {
+        testDebugSurfaceFillFormats();
+    }
+
+    @Test
+    public void debugSurfaceFillSolidColor() {
+        testDebugSurfaceFillSolidColor();
+    }
+
+    @Test
+    public void querySurfaceNativeSizeGenericSurface() {
+        testQuerySurfaceNativeSizeGenericSurface();
+    }
+
+    @Test
+    public void autoSwapIntervalGenericSurface() {
+        testAutoSwapIntervalGenericSurface();
+    }
+
+    @Test
+    public void autoVsyncRefusedInterval() {
+        testAutoVsyncRefusedInterval();
+    }
+
+    @Test
+    public void autoVsyncSwitchesWithHysteresis() {
+        testAutoVsyncSwitchesWithHysteresis();
+    }
+
+    @Test
+    public void invalidSurfaceSizes() {
+        testInvalidSurfaceSizes();
+    }
+
+    @Test
+    public void drainGlErrors() {
+        testDrainGlErrors();
+    }
+
+    @Test
+    public void errorTranslation() {
+        testErrorTranslation();
+    }
+
+    @Test
+    public void preExistingGlErrorsNotBlamedOnSurfman() {
+        testPreExistingGlErrorsNotBlamedOnSurfman();
+    }
+
+    @Test
+    public void surfaceTransformGenericSurface() {
+        testSurfaceTransformGenericSurface();
+    }
+
+    @Test
+    public void surfaceTransformSwapsDimensions() {
+        testSurfaceTransformSwapsDimensions();
+    }
+
+    @Test
+    public void scaleTrackerAutoRescale() {
+        testScaleTrackerAutoRescale();
+    }
+
+    @Test
+    public void scaleTrackerReportsChanges() {
+        testScaleTrackerReportsChanges();
+    }
+
+    @Test
+    public void readSurfaceData() {
+        testReadSurfaceData();
+    }
+
+    @Test
+    public void threadBoundHandle() {
+        testThreadBoundHandle();
+    }
+
+    @Test
+    public void writeSurfaceData() {
+        testWriteSurfaceData();
+    }
+
+    @Test
+    public void surfaceInfoDetails() {
+        testSurfaceInfoDetails();
+    }
+
+    @Test
+    public void heldFrameReadRect() {
+        testHeldFrameReadRect();
+    }
+
+    @Test
+    public void pausePresentationGenericSurface() {
+        testPausePresentationGenericSurface();
+    }
+
+    @Test
+    public void presentPause() {
+        testPresentPause();
+    }
+
+    @Test
+    public void presentSurfaceWithFenceGenericSurface() {
+        testPresentSurfaceWithFenceGenericSurface();
+    }
+
+    @Test
+    public void contextIsHardwareAccelerated() {
+        testContextIsHardwareAccelerated();
+    }
+
+    @Test
+    public void softwareRendererPatterns() {
+        testSoftwareRendererPatterns();
+    }
+
+    @Test
+    public void bulkOpsScope() {
+        testBulkOpsScope();
+    }
+
+    @Test
+    public void withContextCurrent() {
+        testWithContextCurrent();
+    }
+
+    @Test
+    public void syncFdRoundTrip() {
+        testSyncFdRoundTrip();
+    }
+
+    @Test
+    public void nativeFormatTable() {
+        testNativeFormatTable();
+    }
+
+    @Test
+    public void surfaceNativeFormat() {
+        testSurfaceNativeFormat();
+    }
+
+    @Test
+    public void presentationTimeClocks() {
+        testPresentationTimeClocks();
+    }
+
+    @Test
+    public void queryPresentationTime() {
+        testQueryPresentationTime();
+    }
+
+    @Test
+    public void colorProfileTracker() {
+        testColorProfileTracker();
+    }
+
+    @Test
+    public void createSurfacesCancellation() {
+        testCreateSurfacesCancellation();
+    }
+
+    @Test
+    public void clipDamage() {
+        testClipDamage();
+    }
 }
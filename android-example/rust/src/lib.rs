@@ -71,6 +71,54 @@ pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanThre
 
 // NB: New tests should be added here.
 
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testAdoptNativeContext(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_adopt_native_context();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testBeginRendering(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_begin_rendering();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testChooseConfigForVisualDepths(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_choose_config_for_visual_depths();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testColorDepthRoundTrip(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_color_depth_round_trip();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testColorProfileFromIcc(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_color_profile_from_icc();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testConnectionIsSendAndSync(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_connection_is_send_and_sync();
+}
+
 #[no_mangle]
 pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testContextCreation(
     _env: JNIEnv,
@@ -79,6 +127,46 @@ pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInst
     tests::test_context_creation();
 }
 
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testContextInfo(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_context_info();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testContextLost(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_context_lost();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testContextPriority(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_context_priority();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testCreateAdapterByName(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_create_adapter_by_name();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testCreateVersionedContext(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_create_versioned_context();
+}
+
 #[no_mangle]
 pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testCrossDeviceSurfaceTextureBlitFramebuffer(
     _env: JNIEnv,
@@ -95,6 +183,14 @@ pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInst
     tests::test_cross_thread_surface_texture_blit_framebuffer();
 }
 
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testDebugContextFlag(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_debug_context_flag();
+}
+
 #[no_mangle]
 pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testDeviceAccessors(
     _env: JNIEnv,
@@ -111,6 +207,30 @@ pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInst
     tests::test_device_creation();
 }
 
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testEnvironmentReport(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_environment_report();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testExtensionQueries(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_extension_queries();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testGenericSurfaceColorProfile(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_generic_surface_color_profile();
+}
+
 #[no_mangle]
 pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testGenericSurfaceCreation(
     _env: JNIEnv,
@@ -127,6 +247,78 @@ pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInst
     tests::test_gl();
 }
 
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testGetProcAddress(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_get_proc_address();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testGles2CapabilityClamp(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_gles2_capability_clamp();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testGlesConnection(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_gles_connection();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testGlesMinorVersions(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_gles_minor_versions();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testHeadlessConnection(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_headless_connection();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testLibrarySearchDirs(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_library_search_dirs();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testLowMemoryMode(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_low_memory_mode();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testMakeContextCurrentGuarded(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_make_context_current_guarded();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testMultipleFailuresDisplay(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_multiple_failures_display();
+}
+
 #[no_mangle]
 pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testNewlyCreatedContextsAreNotCurrent(
     _env: JNIEnv,
@@ -135,6 +327,30 @@ pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInst
     tests::test_newly_created_contexts_are_not_current();
 }
 
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testPresentSurfaceOwnership(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_present_surface_ownership();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSharedContextObjects(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_shared_context_objects();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSharedPlaceholderContexts(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_shared_placeholder_contexts();
+}
+
 #[no_mangle]
 pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceTextureBlitFramebuffer(
     _env: JNIEnv,
@@ -151,6 +367,678 @@ pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInst
     tests::test_surface_texture_right_side_up();
 }
 
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testAdapterKeys(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_adapter_keys();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testCancellationToken(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_cancellation_token();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testContextDescriptorRawParts(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_context_descriptor_raw_parts();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testContextProfiles(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_context_profiles();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testCpuLayerDamage(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_cpu_layer_damage();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testDeviceIdentity(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_device_identity();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testInternalOperationsPreserveCurrentContext(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_internal_operations_preserve_current_context();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testMixedGlApisOnOneThread(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_mixed_gl_apis_on_one_thread();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testNoErrorFlagConflicts(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_no_error_flag_conflicts();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testPresentationTimestamps(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_presentation_timestamps();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testReleaseBehaviorFlag(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_release_behavior_flag();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testRobustAccessFlag(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_robust_access_flag();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSchedulingClass(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_scheduling_class();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceAccessValidation(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_access_validation();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceFromTexture(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_from_texture();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testUnbindAndRebindSurface(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_unbind_and_rebind_surface();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testBindSurfaceMisuse(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_bind_surface_misuse();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testLeakCheck(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_leak_check();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testPurgeStateMachine(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_purge_state_machine();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfacePurgeability(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_purgeability();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testIsContextCurrent(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_is_context_current();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testAppliedSwapInterval(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_applied_swap_interval();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testChooseConfigForSamples(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_choose_config_for_samples();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testMultisampledSurface(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_multisampled_surface();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testChooseConfigForDepthAndStencilSizes(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_choose_config_for_depth_and_stencil_sizes();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testDepthAndStencilSizes(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_depth_and_stencil_sizes();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testPresentingIdentity(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_presenting_identity();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testGenericSurfaceFormatForOptions(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_generic_surface_format_for_options();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSrgbSurface(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_srgb_surface();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testChooseConfigForFloatColor(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_choose_config_for_float_color();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testHalfFloatSurface(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_half_float_surface();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testWidgetSurfaceFormatForOptions(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_widget_surface_format_for_options();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testConvertSurface(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_convert_surface();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testChooseWidgetConfigForOpacity(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_choose_widget_config_for_opacity();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testRecommendedSurfaceConfig(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_recommended_surface_config();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testRecommendedSurfaceConfigTable(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_recommended_surface_config_table();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testResizeSurface(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_resize_surface();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSetSwapIntervalGenericSurface(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_set_swap_interval_generic_surface();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testWaitPreviousBufferReleasedGenericSurface(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_wait_previous_buffer_released_generic_surface();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testPostPresentInvalidationGenericSurface(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_post_present_invalidation_generic_surface();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testPostPresentInvalidationRules(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_post_present_invalidation_rules();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testBufferAgeGenericSurface(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_buffer_age_generic_surface();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testContextCurrentThread(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_context_current_thread();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testCurrentContextHolderDiagnostics(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_current_context_holder_diagnostics();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testClearSurfaceRegion(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_clear_surface_region();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testPresentSurfaceWithDamageMisuse(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_present_surface_with_damage_misuse();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testExportSurfaceDmaBuf(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_export_surface_dma_buf();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testGenericSurfaceIgnoresPresentMode(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_generic_surface_ignores_present_mode();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testGenericSurfacePreservesContents(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_generic_surface_preserves_contents();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testDebugSurfaceFillCheckerboard(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_debug_surface_fill_checkerboard();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testDebugSurfaceFillFormats(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_debug_surface_fill_formats();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testDebugSurfaceFillSolidColor(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_debug_surface_fill_solid_color();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testQuerySurfaceNativeSizeGenericSurface(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_query_surface_native_size_generic_surface();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testAutoSwapIntervalGenericSurface(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_auto_swap_interval_generic_surface();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testAutoVsyncRefusedInterval(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_auto_vsync_refused_interval();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testAutoVsyncSwitchesWithHysteresis(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_auto_vsync_switches_with_hysteresis();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testInvalidSurfaceSizes(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_invalid_surface_sizes();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testDrainGlErrors(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_drain_gl_errors();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testErrorTranslation(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_error_translation();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testPreExistingGlErrorsNotBlamedOnSurfman(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_pre_existing_gl_errors_not_blamed_on_surfman();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceTransformGenericSurface(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_transform_generic_surface();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceTransformSwapsDimensions(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_transform_swaps_dimensions();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testScaleTrackerAutoRescale(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_scale_tracker_auto_rescale();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testScaleTrackerReportsChanges(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_scale_tracker_reports_changes();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testReadSurfaceData(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_read_surface_data();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testThreadBoundHandle(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_thread_bound_handle();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testWriteSurfaceData(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_write_surface_data();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceInfoDetails(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_info_details();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testHeldFrameReadRect(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_held_frame_read_rect();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testPausePresentationGenericSurface(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_pause_presentation_generic_surface();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testPresentPause(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_present_pause();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testPresentSurfaceWithFenceGenericSurface(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_present_surface_with_fence_generic_surface();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testContextIsHardwareAccelerated(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_context_is_hardware_accelerated();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSoftwareRendererPatterns(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_software_renderer_patterns();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testBulkOpsScope(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_bulk_ops_scope();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testWithContextCurrent(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_with_context_current();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSyncFdRoundTrip(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_sync_fd_round_trip();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testNativeFormatTable(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_native_format_table();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceNativeFormat(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_native_format();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testPresentationTimeClocks(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_presentation_time_clocks();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testQueryPresentationTime(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_query_presentation_time();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testColorProfileTracker(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_color_profile_tracker();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testCreateSurfacesCancellation(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_create_surfaces_cancellation();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testClipDamage(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_clip_damage();
+}
+
 struct JavaResourceLoader {
     loader: GlobalRef,
     vm: JavaVM,
@@ -0,0 +1,86 @@
+// surfman/surfman/benches/cpu_layer.rs
+//
+//! Compares uploading a whole 4K `CpuLayer` every frame with uploading only a damaged line of text.
+//!
+//! Run with `cargo bench --bench cpu_layer`. This uses a headless connection, so it needs no
+//! window system.
+
+use euclid::default::{Point2D, Rect, Size2D};
+use std::time::{Duration, Instant};
+use surfman::cpu_layer::CpuLayer;
+use surfman::{Connection, ContextAttributeFlags, ContextAttributes, Device, GLVersion};
+
+const LAYER_SIZE: Size2D<i32> = Size2D::new(3840, 2160);
+
+// A line of text, roughly, which is what a UI usually damages in a frame.
+const LINE_SIZE: Size2D<i32> = Size2D::new(640, 32);
+
+const WARMUP_FRAMES: u32 = 10;
+const FRAMES: u32 = 120;
+
+fn main() {
+    let connection = Connection::new_headless().unwrap();
+    let adapter = connection.create_adapter().unwrap();
+    let mut device = connection.create_device(&adapter).unwrap();
+    let context_descriptor = device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::empty(),
+            ..Default::default()
+        })
+        .unwrap();
+    let mut context = device.create_context(&context_descriptor, None).unwrap();
+    device.make_context_current(&context).unwrap();
+    gl::load_with(device.proc_address_loader(&context));
+
+    let mut layer = CpuLayer::new(&mut device, &mut context, LAYER_SIZE).unwrap();
+    let full = Rect::new(Point2D::zero(), LAYER_SIZE);
+    let full_time = time_frames(&mut layer, |_| full);
+    let damaged_time = time_frames(&mut layer, |frame| {
+        let y = (frame as i32 * LINE_SIZE.height) % (LAYER_SIZE.height - LINE_SIZE.height);
+        Rect::new(Point2D::new(0, y), LINE_SIZE)
+    });
+    println!(
+        "{}x{} layer, per frame: full upload {:?}, {}x{} damage {:?}",
+        LAYER_SIZE.width,
+        LAYER_SIZE.height,
+        full_time,
+        LINE_SIZE.width,
+        LINE_SIZE.height,
+        damaged_time
+    );
+
+    layer.destroy(&device, &mut context).unwrap();
+    device.destroy_context(&mut context).unwrap();
+}
+
+// Returns the average time per frame to draw into, damage, and commit the rectangle that `damage`
+// returns for each frame, including the time the GPU takes to finish the uploads.
+fn time_frames<F>(layer: &mut CpuLayer<Device>, damage: F) -> Duration
+where
+    F: Fn(u32) -> Rect<i32>,
+{
+    let mut start = Instant::now();
+    for frame in 0..(WARMUP_FRAMES + FRAMES) {
+        if frame == WARMUP_FRAMES {
+            unsafe {
+                gl::Finish();
+            }
+            start = Instant::now();
+        }
+        let rect = damage(frame);
+        let stride = LAYER_SIZE.width as usize * 4;
+        let pixels = layer.pixels_mut();
+        for y in rect.min_y()..rect.max_y() {
+            let row = y as usize * stride;
+            pixels[row + rect.min_x() as usize * 4..row + rect.max_x() as usize * 4]
+                .fill(frame as u8);
+        }
+        layer.add_damage(&rect);
+        layer.commit().unwrap();
+    }
+    unsafe {
+        gl::Finish();
+    }
+    start.elapsed() / FRAMES
+}
@@ -0,0 +1,102 @@
+// surfman/surfman/benches/teardown.rs
+//
+//! Compares destroying a tab's worth of surfaces one by one with destroying them inside
+//! `Device::with_context_current()`, while another context is current, as it is when a browser
+//! closes a tab.
+//!
+//! Run with `cargo bench --bench teardown`. This uses a headless connection, so it needs no
+//! window system.
+
+use euclid::default::Size2D;
+use std::time::{Duration, Instant};
+use surfman::{Connection, Context, ContextAttributeFlags, ContextAttributes, Device, GLVersion};
+use surfman::{ContextDescriptor, Surface};
+use surfman::{SurfaceAccess, SurfaceType};
+
+const CONTEXTS: usize = 10;
+const SURFACES_PER_CONTEXT: usize = 5;
+const SURFACE_SIZE: Size2D<i32> = Size2D::new(256, 256);
+
+const ROUNDS: u32 = 20;
+
+fn main() {
+    let connection = Connection::new_headless().unwrap();
+    let adapter = connection.create_adapter().unwrap();
+    let mut device = connection.create_device(&adapter).unwrap();
+    let context_descriptor = device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::empty(),
+            ..Default::default()
+        })
+        .unwrap();
+    // The context that the embedder renders its own UI with, which stays current throughout.
+    let mut ui_context = device.create_context(&context_descriptor, None).unwrap();
+    device.make_context_current(&ui_context).unwrap();
+
+    let individual_time = time_teardowns(&mut device, &context_descriptor, |device, tab| {
+        for (context, surfaces) in tab {
+            for surface in surfaces.iter_mut() {
+                device.destroy_surface(context, surface).unwrap();
+            }
+        }
+    });
+    let bulk_time = time_teardowns(&mut device, &context_descriptor, |device, tab| {
+        for (context, surfaces) in tab {
+            device
+                .with_context_current(context, |ops| {
+                    for surface in surfaces.iter_mut() {
+                        ops.destroy_surface(surface).unwrap();
+                    }
+                })
+                .unwrap();
+        }
+    });
+    println!(
+        "{} surfaces on {} contexts, per teardown: one by one {:?}, bulk {:?}",
+        CONTEXTS * SURFACES_PER_CONTEXT,
+        CONTEXTS,
+        individual_time,
+        bulk_time
+    );
+
+    device.destroy_context(&mut ui_context).unwrap();
+}
+
+// Returns the average time that `destroy` takes to destroy the surfaces of a tab, not counting the
+// time to create them or to destroy the tab's contexts afterward.
+fn time_teardowns<F>(
+    device: &mut Device,
+    context_descriptor: &ContextDescriptor,
+    destroy: F,
+) -> Duration
+where
+    F: Fn(&Device, &mut [(Context, Vec<Surface>)]),
+{
+    let mut total = Duration::ZERO;
+    for _ in 0..ROUNDS {
+        let mut tab: Vec<(Context, Vec<Surface>)> = (0..CONTEXTS)
+            .map(|_| {
+                let context = device.create_context(context_descriptor, None).unwrap();
+                let surfaces = (0..SURFACES_PER_CONTEXT)
+                    .map(|_| {
+                        let surface_type = SurfaceType::Generic { size: SURFACE_SIZE };
+                        device
+                            .create_surface(&context, SurfaceAccess::GPUOnly, surface_type)
+                            .unwrap()
+                    })
+                    .collect();
+                (context, surfaces)
+            })
+            .collect();
+
+        let start = Instant::now();
+        destroy(device, &mut tab);
+        total += start.elapsed();
+
+        for (mut context, _) in tab {
+            device.destroy_context(&mut context).unwrap();
+        }
+    }
+    total / ROUNDS
+}
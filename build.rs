@@ -23,16 +23,33 @@ fn main() {
 
         // Native displays.
         x11_platform: { all(free_unix, feature = "sm-x11") },
-        wayland_platform: { all(free_unix) },
+        wayland_platform: { all(free_unix, feature = "sm-wayland") },
+        generic_egl_platform: { all(free_unix, feature = "sm-generic-egl") },
 
         // Features:
         // Here we collect the features that are only valid on certain platforms and
         // we add aliases that include checks for the correct platform.
+        android_backend: { all(android_platform, feature = "sm-android") },
+        ohos_backend: { all(ohos_platform, feature = "sm-ohos") },
+        cgl_backend: { all(macos_platform, feature = "sm-cgl") },
         angle: { all(windows, feature = "sm-angle") },
         angle_builtin: { all(windows_platform, feature = "sm-angle-builtin") },
-        angle_default: { all(windows_platform, feature = "sm-angle-default") },
         no_wgl: { all(windows_platform, feature = "sm-no-wgl") },
+        wgl_backend: { all(windows_platform, feature = "sm-wgl", not(no_wgl)) },
+        angle_default: { all(angle, any(feature = "sm-angle-default", not(wgl_backend))) },
         wayland_default: { all(wayland_platform, any(not(x11_platform), feature = "sm-wayland-default")) },
+        any_backend: {
+            any(
+                x11_platform,
+                wayland_platform,
+                generic_egl_platform,
+                android_backend,
+                ohos_backend,
+                cgl_backend,
+                wgl_backend,
+                angle
+            )
+        },
     }
 
     let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap();
@@ -58,7 +75,15 @@ fn main() {
         registry.write_bindings(StructGenerator, &mut file).unwrap();
     } else {
         let mut file = File::create(dest.join("gl_bindings.rs")).unwrap();
-        let registry = Registry::new(Api::Gl, (3, 3), Profile::Core, Fallbacks::All, []);
+        // `glInvalidateFramebuffer()` is only core from OpenGL 4.3, and comes from
+        // `GL_ARB_invalidate_subdata` before that.
+        let registry = Registry::new(
+            Api::Gl,
+            (3, 3),
+            Profile::Core,
+            Fallbacks::All,
+            ["GL_ARB_invalidate_subdata"],
+        );
         registry.write_bindings(StructGenerator, &mut file).unwrap();
     }
 }
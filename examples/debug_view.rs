@@ -0,0 +1,86 @@
+// surfman/examples/debug_view.rs
+//
+// This example demonstrates how to look at off-screen surfaces with `surfman::debug_view`. It
+// renders to two surfaces without any window of its own, and shows each of them in a debug view
+// for a few seconds, updating one of them every frame.
+//
+// Run it with `cargo run --example debug_view --features sm-debug-view`.
+
+use euclid::default::Size2D;
+use std::thread;
+use std::time::Duration;
+use surfman::{debug_view, declare_surfman, Connection, Context, Device, Surface};
+use surfman::{ContextAttributeFlags, ContextAttributes, GLVersion};
+use surfman::{SurfaceAccess, SurfaceType};
+
+declare_surfman!();
+
+const FRAME_COUNT: u32 = 300;
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+fn main() {
+    let connection = Connection::new().unwrap();
+    let adapter = connection.create_adapter().unwrap();
+    let mut device = connection.create_device(&adapter).unwrap();
+
+    let context_attributes = ContextAttributes {
+        version: GLVersion::new(3, 0),
+        flags: ContextAttributeFlags::ALPHA,
+        ..Default::default()
+    };
+    let context_descriptor = device
+        .create_context_descriptor(&context_attributes)
+        .unwrap();
+    let mut context = device.create_context(&context_descriptor, None).unwrap();
+    device.make_context_current(&context).unwrap();
+    gl::load_with(device.proc_address_loader(&context));
+
+    let mut wide_surface = make_surface(&mut device, &context, Size2D::new(320, 180));
+    let mut tall_surface = make_surface(&mut device, &context, Size2D::new(180, 320));
+    tall_surface = draw(&device, &mut context, tall_surface, [0.2, 0.6, 1.0]);
+    wide_surface = draw(&device, &mut context, wide_surface, [1.0, 0.0, 0.0]);
+
+    // Debug views close when they're dropped, so keep them around until the end.
+    let wide_view = debug_view::show(&device, &context, &wide_surface, "Wide surface").unwrap();
+    let _tall_view = debug_view::show(&device, &context, &tall_surface, "Tall surface").unwrap();
+
+    for frame in 0..FRAME_COUNT {
+        let shade = frame as f32 / FRAME_COUNT as f32;
+        let color = [1.0 - shade, 0.0, shade];
+        wide_surface = draw(&device, &mut context, wide_surface, color);
+        wide_view.update(&device, &context, &wide_surface).unwrap();
+        thread::sleep(FRAME_INTERVAL);
+    }
+
+    drop(wide_view);
+    device
+        .destroy_surface(&mut context, &mut wide_surface)
+        .unwrap();
+    device
+        .destroy_surface(&mut context, &mut tall_surface)
+        .unwrap();
+    device.destroy_context(&mut context).unwrap();
+}
+
+fn make_surface(device: &mut Device, context: &Context, size: Size2D<i32>) -> Surface {
+    let surface_type = SurfaceType::Generic { size };
+    device
+        .create_surface(context, SurfaceAccess::GPUOnly, surface_type)
+        .unwrap()
+}
+
+// Clears a surface to a color, and returns it unbound again, since debug views can only show
+// surfaces that aren't bound to a context.
+fn draw(device: &Device, context: &mut Context, surface: Surface, color: [f32; 3]) -> Surface {
+    device.bind_surface_to_context(context, surface).unwrap();
+    device.begin_rendering(context).unwrap();
+    unsafe {
+        gl::ClearColor(color[0], color[1], color[2], 1.0);
+        gl::Clear(gl::COLOR_BUFFER_BIT);
+    }
+    device.end_rendering(context, true).unwrap();
+    device
+        .unbind_surface_from_context(context)
+        .unwrap()
+        .unwrap()
+}
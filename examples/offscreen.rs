@@ -14,8 +14,8 @@ use std::fs::File;
 use std::mem;
 use std::path::Path;
 use std::slice;
+use surfman::SurfaceIntent;
 use surfman::{Connection, ContextAttributeFlags, ContextAttributes, GLApi, GLVersion};
-use surfman::{SurfaceAccess, SurfaceType};
 
 mod common;
 
@@ -103,18 +103,25 @@ fn main() {
     let context_attributes = ContextAttributes {
         version: GLVersion::new(3, 3),
         flags: ContextAttributeFlags::empty(),
+        ..Default::default()
     };
     let context_descriptor = device
         .create_context_descriptor(&context_attributes)
         .unwrap();
     let mut context = device.create_context(&context_descriptor, None).unwrap();
+    let surface_config = device
+        .recommended_surface_config(
+            &context,
+            SurfaceIntent::CpuReadbackHeavy,
+            Size2D::new(FRAMEBUFFER_WIDTH, FRAMEBUFFER_HEIGHT),
+        )
+        .unwrap();
     let surface = device
-        .create_surface(
+        .create_surface_with_options(
             &context,
-            SurfaceAccess::GPUOnly,
-            SurfaceType::Generic {
-                size: Size2D::new(FRAMEBUFFER_WIDTH, FRAMEBUFFER_HEIGHT),
-            },
+            surface_config.access,
+            surface_config.surface_type,
+            surface_config.options,
         )
         .unwrap();
     device
@@ -122,15 +129,13 @@ fn main() {
         .unwrap();
 
     device.make_context_current(&context).unwrap();
-    gl::load_with(|symbol_name| device.get_proc_address(&context, symbol_name));
+    gl::load_with(device.proc_address_loader(&context));
 
     let mut pixels: Vec<u8> = vec![0; FRAMEBUFFER_WIDTH as usize * FRAMEBUFFER_HEIGHT as usize * 4];
     let tri_vertex_array = TriVertexArray::new(device.gl_api(), device.surface_gl_texture_target());
 
+    device.begin_rendering(&context).unwrap();
     unsafe {
-        let surface_info = device.context_surface_info(&context).unwrap().unwrap();
-        gl::BindFramebuffer(gl::FRAMEBUFFER, surface_info.framebuffer_object);
-        gl::Viewport(0, 0, FRAMEBUFFER_WIDTH, FRAMEBUFFER_HEIGHT);
         ck();
         gl::ClearColor(0.0, 0.0, 0.0, 1.0);
         ck();
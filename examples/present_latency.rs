@@ -0,0 +1,210 @@
+// surfman/examples/present_latency.rs
+//
+// This example demonstrates how to measure presentation latency with
+// `Device::presentation_timestamps()`. It clears a window to a new color every frame and prints
+// percentiles of the time between submitting each frame and the platform reporting it on screen.
+
+use euclid::default::Size2D;
+use std::time::Duration;
+use surfman::{declare_surfman, Connection, PresentRecord, SurfaceIntent};
+
+#[cfg(not(target_os = "android"))]
+use surfman::{ContextAttributeFlags, ContextAttributes, GLVersion};
+#[cfg(not(target_os = "android"))]
+use winit::{
+    dpi::PhysicalSize,
+    event::{DeviceEvent, Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    window::WindowBuilder,
+};
+
+#[cfg(feature = "sm-raw-window-handle-05")]
+use rwh_05::{HasRawDisplayHandle, HasRawWindowHandle};
+#[cfg(not(feature = "sm-raw-window-handle-05"))]
+use rwh_06::{HasDisplayHandle, HasWindowHandle};
+
+declare_surfman!();
+
+const WINDOW_WIDTH: i32 = 800;
+const WINDOW_HEIGHT: i32 = 600;
+
+// How many frames to wait between printing statistics.
+const REPORT_INTERVAL: u64 = 120;
+
+#[cfg(feature = "sm-raw-window-handle-05")]
+fn make_connection(window: &winit::window::Window) -> surfman::Connection {
+    let raw_display_handle = window.raw_display_handle();
+    Connection::from_raw_display_handle(raw_display_handle).unwrap()
+}
+
+#[cfg(not(feature = "sm-raw-window-handle-05"))]
+fn make_connection(window: &winit::window::Window) -> surfman::Connection {
+    let display_handle = window
+        .display_handle()
+        .expect("failed to get display handle from window");
+    Connection::from_display_handle(display_handle).unwrap()
+}
+
+#[cfg(feature = "sm-raw-window-handle-05")]
+fn make_native_widget(
+    window: &winit::window::Window,
+    connection: &surfman::Connection,
+    window_size: Size2D<i32>,
+) -> surfman::NativeWidget {
+    let raw_window_handle = window.raw_window_handle();
+    connection
+        .create_native_widget_from_raw_window_handle(raw_window_handle, window_size)
+        .unwrap()
+}
+
+#[cfg(not(feature = "sm-raw-window-handle-05"))]
+fn make_native_widget(
+    window: &winit::window::Window,
+    connection: &surfman::Connection,
+    window_size: Size2D<i32>,
+) -> surfman::NativeWidget {
+    let window_handle = window
+        .window_handle()
+        .expect("couldn't get window handle from window");
+    connection
+        .create_native_widget_from_window_handle(window_handle, window_size)
+        .unwrap()
+}
+
+#[cfg(target_os = "android")]
+fn main() {
+    println!("The `present_latency` demo is not yet supported on this platform.");
+}
+
+#[cfg(not(target_os = "android"))]
+fn main() {
+    use winit::{
+        event::RawKeyEvent,
+        keyboard::{KeyCode, PhysicalKey},
+    };
+
+    let event_loop = EventLoop::new().expect("couldn't create eventloop");
+    let physical_size = PhysicalSize::new(WINDOW_WIDTH, WINDOW_HEIGHT);
+    let window = WindowBuilder::new()
+        .with_title("Presentation latency example")
+        .with_inner_size(physical_size)
+        .build(&event_loop)
+        .unwrap();
+
+    window.set_visible(true);
+
+    let connection = make_connection(&window);
+
+    let window_size = window.inner_size();
+    let window_size = Size2D::new(window_size.width as i32, window_size.height as i32);
+    let native_widget = make_native_widget(&window, &connection, window_size);
+    let adapter = connection.create_adapter().unwrap();
+    let mut device = connection.create_device(&adapter).unwrap();
+
+    let context_attributes = ContextAttributes {
+        version: GLVersion::new(3, 0),
+        flags: ContextAttributeFlags::ALPHA,
+        ..Default::default()
+    };
+    let context_descriptor = device
+        .create_context_descriptor(&context_attributes)
+        .unwrap();
+    let mut context = device.create_context(&context_descriptor, None).unwrap();
+
+    let intent = SurfaceIntent::DisplayedInWindow(native_widget);
+    let surface_config = device
+        .recommended_surface_config(&context, intent, Size2D::zero())
+        .unwrap();
+    let surface = device
+        .create_surface_with_options(
+            &context,
+            surface_config.access,
+            surface_config.surface_type,
+            surface_config.options,
+        )
+        .unwrap();
+    device
+        .bind_surface_to_context(&mut context, surface)
+        .unwrap();
+    device.make_context_current(&context).unwrap();
+    gl::load_with(device.proc_address_loader(&context));
+
+    let mut frame_count = 0;
+    event_loop
+        .run(move |event, target| match event {
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            }
+            | Event::DeviceEvent {
+                event:
+                    DeviceEvent::Key(RawKeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::Escape),
+                        ..
+                    }),
+                ..
+            } => {
+                device.destroy_context(&mut context).unwrap();
+                target.exit()
+            }
+            _ => {
+                device.begin_rendering(&context).unwrap();
+                let shade = (frame_count % 60) as f32 / 60.0;
+                unsafe {
+                    gl::ClearColor(shade, 0.0, 1.0 - shade, 1.0);
+                    gl::Clear(gl::COLOR_BUFFER_BIT);
+                }
+                device.end_rendering(&context, false).unwrap();
+
+                let mut surface = device
+                    .unbind_surface_from_context(&mut context)
+                    .unwrap()
+                    .unwrap();
+                device.present_surface(&context, &mut surface).unwrap();
+
+                frame_count += 1;
+                if frame_count % REPORT_INTERVAL == 0 {
+                    report_latency(&device.presentation_timestamps(&surface));
+                }
+
+                device
+                    .bind_surface_to_context(&mut context, surface)
+                    .unwrap();
+                target.set_control_flow(ControlFlow::Poll)
+            }
+        })
+        .expect("failed to run event loop");
+}
+
+// Prints percentiles of the latency of each presentation, using the latest stage that the
+// platform reports.
+fn report_latency(records: &[PresentRecord]) {
+    let mut latencies: Vec<Duration> = records
+        .iter()
+        .filter_map(|record| {
+            let end = record.displayed.or(record.compositor_ack)?;
+            Some(end.duration_since(record.submit))
+        })
+        .collect();
+    if latencies.is_empty() {
+        println!("No presentation timestamps are available on this platform.");
+        return;
+    }
+
+    latencies.sort();
+    let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+    let last = records.last().unwrap();
+    println!(
+        "frames {}-{}: p50 {:?}, p90 {:?}, p99 {:?} ({})",
+        records[0].frame_id,
+        last.frame_id,
+        percentile(50),
+        percentile(90),
+        percentile(99),
+        if last.displayed.is_some() {
+            "to display"
+        } else {
+            "to compositor"
+        },
+    );
+}
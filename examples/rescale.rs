@@ -0,0 +1,207 @@
+// surfman/examples/rescale.rs
+//
+// This example demonstrates widget surfaces that follow the scale factor of their display, with
+// `SurfaceOptions::auto_rescale` and `Device::set_surface_scale_changed_handler()`. It draws a grid
+// of lines one device pixel wide, which stays sharp as long as the surface matches its window.
+// Drag the window between displays with different scale factors: each change is printed, and the
+// grid should be blurry or cropped for at most one frame. Windows are only noticed moving between
+// displays on Windows and macOS.
+
+use euclid::default::Size2D;
+use surfman::{declare_surfman, Connection, Error, SurfaceIntent, SurfaceOptions};
+
+#[cfg(not(target_os = "android"))]
+use surfman::{ContextAttributeFlags, ContextAttributes, GLVersion};
+#[cfg(not(target_os = "android"))]
+use winit::{
+    dpi::PhysicalSize,
+    event::{DeviceEvent, Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    window::WindowBuilder,
+};
+
+#[cfg(feature = "sm-raw-window-handle-05")]
+use rwh_05::{HasRawDisplayHandle, HasRawWindowHandle};
+#[cfg(not(feature = "sm-raw-window-handle-05"))]
+use rwh_06::{HasDisplayHandle, HasWindowHandle};
+
+declare_surfman!();
+
+const WINDOW_WIDTH: i32 = 800;
+const WINDOW_HEIGHT: i32 = 600;
+
+// The distance between the lines of the grid, in device pixels.
+const GRID_SPACING: i32 = 4;
+
+#[cfg(feature = "sm-raw-window-handle-05")]
+fn make_connection(window: &winit::window::Window) -> surfman::Connection {
+    let raw_display_handle = window.raw_display_handle();
+    Connection::from_raw_display_handle(raw_display_handle).unwrap()
+}
+
+#[cfg(not(feature = "sm-raw-window-handle-05"))]
+fn make_connection(window: &winit::window::Window) -> surfman::Connection {
+    let display_handle = window
+        .display_handle()
+        .expect("failed to get display handle from window");
+    Connection::from_display_handle(display_handle).unwrap()
+}
+
+#[cfg(feature = "sm-raw-window-handle-05")]
+fn make_native_widget(
+    window: &winit::window::Window,
+    connection: &surfman::Connection,
+    window_size: Size2D<i32>,
+) -> surfman::NativeWidget {
+    let raw_window_handle = window.raw_window_handle();
+    connection
+        .create_native_widget_from_raw_window_handle(raw_window_handle, window_size)
+        .unwrap()
+}
+
+#[cfg(not(feature = "sm-raw-window-handle-05"))]
+fn make_native_widget(
+    window: &winit::window::Window,
+    connection: &surfman::Connection,
+    window_size: Size2D<i32>,
+) -> surfman::NativeWidget {
+    let window_handle = window
+        .window_handle()
+        .expect("couldn't get window handle from window");
+    connection
+        .create_native_widget_from_window_handle(window_handle, window_size)
+        .unwrap()
+}
+
+#[cfg(target_os = "android")]
+fn main() {
+    println!("The `rescale` demo is not yet supported on this platform.");
+}
+
+#[cfg(not(target_os = "android"))]
+fn main() {
+    use winit::{
+        event::RawKeyEvent,
+        keyboard::{KeyCode, PhysicalKey},
+    };
+
+    let event_loop = EventLoop::new().expect("couldn't create eventloop");
+    let physical_size = PhysicalSize::new(WINDOW_WIDTH, WINDOW_HEIGHT);
+    let window = WindowBuilder::new()
+        .with_title("Rescaling example")
+        .with_inner_size(physical_size)
+        .build(&event_loop)
+        .unwrap();
+
+    window.set_visible(true);
+
+    let connection = make_connection(&window);
+
+    let window_size = window.inner_size();
+    let window_size = Size2D::new(window_size.width as i32, window_size.height as i32);
+    let native_widget = make_native_widget(&window, &connection, window_size);
+    let adapter = connection.create_adapter().unwrap();
+    let mut device = connection.create_device(&adapter).unwrap();
+    let handler = device.set_surface_scale_changed_handler(Some(Box::new(|surface_id, change| {
+        println!(
+            "Surface {:?} moved to a display at {}x, suggested size {}x{}",
+            surface_id, change.new_scale, change.suggested_size.width, change.suggested_size.height,
+        );
+    })));
+    if let Err(err) = handler {
+        println!("Scale changes aren't reported on this platform: {:?}", err);
+    }
+
+    let context_attributes = ContextAttributes {
+        version: GLVersion::new(3, 0),
+        flags: ContextAttributeFlags::ALPHA,
+        ..Default::default()
+    };
+    let context_descriptor = device
+        .create_context_descriptor(&context_attributes)
+        .unwrap();
+    let mut context = device.create_context(&context_descriptor, None).unwrap();
+
+    let intent = SurfaceIntent::DisplayedInWindow(native_widget);
+    let surface_config = device
+        .recommended_surface_config(&context, intent, Size2D::zero())
+        .unwrap();
+    let options = SurfaceOptions {
+        auto_rescale: true,
+        ..surface_config.options
+    };
+    let surface = device
+        .create_surface_with_options(
+            &context,
+            surface_config.access,
+            surface_config.surface_type,
+            options,
+        )
+        .unwrap();
+    device
+        .bind_surface_to_context(&mut context, surface)
+        .unwrap();
+    device.make_context_current(&context).unwrap();
+    gl::load_with(device.proc_address_loader(&context));
+
+    event_loop
+        .run(move |event, target| match event {
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            }
+            | Event::DeviceEvent {
+                event:
+                    DeviceEvent::Key(RawKeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::Escape),
+                        ..
+                    }),
+                ..
+            } => {
+                device.destroy_context(&mut context).unwrap();
+                target.exit()
+            }
+            _ => {
+                let render_target = device.begin_rendering(&context).unwrap();
+                unsafe {
+                    draw_grid(render_target.size);
+                }
+                device.end_rendering(&context, false).unwrap();
+
+                let mut surface = device
+                    .unbind_surface_from_context(&mut context)
+                    .unwrap()
+                    .unwrap();
+                match device.present_surface(&context, &mut surface) {
+                    Ok(()) => {}
+                    // The window was resized for some other reason, which is left to us.
+                    Err(Error::SurfaceSizeMismatch { actual, .. }) => device
+                        .resize_surface(&context, &mut surface, actual)
+                        .unwrap(),
+                    Err(err) => panic!("Failed to present: {:?}", err),
+                }
+                device
+                    .bind_surface_to_context(&mut context, surface)
+                    .unwrap();
+                target.set_control_flow(ControlFlow::Poll)
+            }
+        })
+        .expect("failed to run event loop");
+}
+
+// Draws white lines one device pixel wide on black, which blur as soon as the surface is scaled.
+unsafe fn draw_grid(size: Size2D<i32>) {
+    gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+    gl::Clear(gl::COLOR_BUFFER_BIT);
+    gl::Enable(gl::SCISSOR_TEST);
+    gl::ClearColor(1.0, 1.0, 1.0, 1.0);
+    for x in (0..size.width).step_by(GRID_SPACING as usize) {
+        gl::Scissor(x, 0, 1, size.height);
+        gl::Clear(gl::COLOR_BUFFER_BIT);
+    }
+    for y in (0..size.height).step_by(GRID_SPACING as usize) {
+        gl::Scissor(0, y, size.width, 1);
+        gl::Clear(gl::COLOR_BUFFER_BIT);
+    }
+    gl::Disable(gl::SCISSOR_TEST);
+}
@@ -8,8 +8,8 @@ use euclid::default::{Point2D, Rect, Size2D, Vector2D};
 use gl::types::{GLchar, GLenum, GLint, GLuint, GLvoid};
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::thread;
-use surfman::{declare_surfman, SurfaceAccess, SurfaceTexture, SurfaceType};
-use surfman::{Adapter, Connection, Context, ContextDescriptor, Device, GLApi, Surface};
+use surfman::{declare_surfman, Surface, SurfaceIntent, SurfaceTexture};
+use surfman::{Adapter, Connection, Context, ContextDescriptor, Device, GLApi, NativeWidget};
 
 #[cfg(not(target_os = "android"))]
 use self::common::FilesystemResourceLoader;
@@ -164,16 +164,19 @@ fn main() {
     let context_attributes = ContextAttributes {
         version: GLVersion::new(3, 0),
         flags: ContextAttributeFlags::ALPHA,
+        ..Default::default()
     };
     let context_descriptor = device
         .create_context_descriptor(&context_attributes)
         .unwrap();
 
-    let surface_type = SurfaceType::Widget { native_widget };
     let mut context = device.create_context(&context_descriptor, None).unwrap();
-    let surface = device
-        .create_surface(&context, SurfaceAccess::GPUOnly, surface_type)
-        .unwrap();
+    let surface = make_surface(
+        &mut device,
+        &context,
+        SurfaceIntent::DisplayedInWindow(native_widget),
+        Size2D::zero(),
+    );
     device
         .bind_surface_to_context(&mut context, surface)
         .unwrap();
@@ -238,7 +241,7 @@ impl App {
     ) -> App {
         let context_descriptor = device.context_descriptor(&context);
 
-        gl::load_with(|symbol_name| device.get_proc_address(&context, symbol_name));
+        gl::load_with(device.proc_address_loader(&context));
 
         // Set up GL objects and state.
         let gl_api = device.gl_api();
@@ -305,17 +308,8 @@ impl App {
                 .unwrap(),
         );
 
+        self.device.begin_rendering(&self.context).unwrap();
         unsafe {
-            self.device.make_context_current(&self.context).unwrap();
-
-            let framebuffer_object = match self.device.context_surface_info(&self.context) {
-                Ok(Some(surface_info)) => surface_info.framebuffer_object,
-                _ => 0,
-            };
-
-            gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
-            gl::Viewport(0, 0, self.window_size.width, self.window_size.height);
-
             gl::ClearColor(0.0, 0.0, 1.0, 1.0);
             ck();
             gl::Clear(gl::COLOR_BUFFER_BIT);
@@ -434,6 +428,7 @@ impl App {
             ck();
             gl::Disable(gl::BLEND);
         }
+        self.device.end_rendering(&self.context, false).unwrap();
 
         if present {
             let mut surface = self
@@ -462,12 +457,14 @@ fn worker_thread(
 ) {
     // Open the device, create a context, and make it current.
     let size = Size2D::new(SUBSCREEN_WIDTH, SUBSCREEN_HEIGHT);
-    let surface_type = SurfaceType::Generic { size };
     let mut device = connection.create_device(&adapter).unwrap();
     let mut context = device.create_context(&context_descriptor, None).unwrap();
-    let surface = device
-        .create_surface(&context, SurfaceAccess::GPUOnly, surface_type)
-        .unwrap();
+    let surface = make_surface(
+        &mut device,
+        &context,
+        SurfaceIntent::SharedWithOtherContext,
+        size,
+    );
     device
         .bind_surface_to_context(&mut context, surface)
         .unwrap();
@@ -498,12 +495,12 @@ fn worker_thread(
     let mut theta_z = INITIAL_ROTATION_Z;
 
     // Send an initial surface back to the main thread.
-    let surface_type = SurfaceType::Generic { size };
-    let surface = Some(
-        device
-            .create_surface(&context, SurfaceAccess::GPUOnly, surface_type)
-            .unwrap(),
-    );
+    let surface = Some(make_surface(
+        &mut device,
+        &context,
+        SurfaceIntent::SharedWithOtherContext,
+        size,
+    ));
     worker_to_main_sender
         .send(Frame {
             surface,
@@ -514,16 +511,8 @@ fn worker_thread(
 
     loop {
         // Render to the surface.
+        device.begin_rendering(&context).unwrap();
         unsafe {
-            let framebuffer_object = device
-                .context_surface_info(&context)
-                .unwrap()
-                .unwrap()
-                .framebuffer_object;
-
-            gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
-            gl::Viewport(0, 0, size.width, size.height);
-
             gl::ClearColor(0.0, 0.0, 0.0, 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT);
 
@@ -592,6 +581,7 @@ fn worker_thread(
             gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
             ck();
         }
+        device.end_rendering(&context, true).unwrap();
 
         let old_surface = device.unbind_surface_from_context(&mut context).unwrap();
         let new_surface = match worker_from_main_receiver.recv() {
@@ -637,6 +627,26 @@ fn worker_thread(
     device.destroy_context(&mut context).unwrap();
 }
 
+// Creates a surface the way the device recommends for the given use.
+fn make_surface(
+    device: &mut Device,
+    context: &Context,
+    intent: SurfaceIntent<NativeWidget>,
+    size: Size2D<i32>,
+) -> Surface {
+    let surface_config = device
+        .recommended_surface_config(context, intent, size)
+        .unwrap();
+    device
+        .create_surface_with_options(
+            context,
+            surface_config.access,
+            surface_config.surface_type,
+            surface_config.options,
+        )
+        .unwrap()
+}
+
 struct Frame {
     surface: Option<Surface>,
     viewport_origin: Point2D<f32>,
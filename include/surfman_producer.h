@@ -0,0 +1,139 @@
+/* surfman/surfman/include/surfman_producer.h
+ *
+ * The C ABI for swap chain producers written in other languages. See `surfman::producer_abi`
+ * for how the Rust side creates chains and consumes their frames.
+ *
+ * A producer acquires the back buffer of a chain with `surfman_chain_acquire()`, imports the
+ * native image handle that it describes into its own GL or Vulkan, renders the frame there, and
+ * hands it back with `surfman_chain_submit()`, along with a sync file that signals once the
+ * rendering has finished and the rectangles that changed since the last frame. The pixels never
+ * pass through the CPU. Calls on one chain must not overlap.
+ *
+ * Producers that don't link surfman, such as plugins that a surfman application loads, are
+ * given `surfman_producer_api()` and get the entry points from it, which checks the version:
+ *
+ *     const SurfmanProducerApi *api = get_api(SURFMAN_PRODUCER_ABI_VERSION);
+ *     if (!api)
+ *         return SURFMAN_ERROR_VERSION_MISMATCH;
+ *
+ * Each frame, the producer also names the handle types that it can import, and the chain picks
+ * one of them or fails with `SURFMAN_ERROR_UNSUPPORTED_HANDLE_TYPE`.
+ */
+
+#ifndef SURFMAN_PRODUCER_H
+#define SURFMAN_PRODUCER_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The version of the ABI that this header describes. */
+#define SURFMAN_PRODUCER_ABI_VERSION 1
+
+/* Status codes, which every entry point returns. */
+#define SURFMAN_OK 0
+/* The caller asked for a version of the ABI that surfman doesn't speak. */
+#define SURFMAN_ERROR_VERSION_MISMATCH 1
+/* A pointer was null, or a damage rectangle had a negative size. */
+#define SURFMAN_ERROR_INVALID_ARGUMENT 2
+/* `surfman_chain_acquire()` was called while a frame was already acquired. */
+#define SURFMAN_ERROR_ALREADY_ACQUIRED 3
+/* `surfman_chain_submit()` was called without an acquired frame. */
+#define SURFMAN_ERROR_NOT_ACQUIRED 4
+/* The chain requires a fence, and the submission had none. */
+#define SURFMAN_ERROR_FENCE_REQUIRED 5
+/* A damage rectangle reached outside the frame. */
+#define SURFMAN_ERROR_DAMAGE_OUT_OF_BOUNDS 6
+/* Waiting for the fence failed. */
+#define SURFMAN_ERROR_FENCE_FAILED 7
+/* The chain can't hand out its frames as any of the handle types that the producer accepts. */
+#define SURFMAN_ERROR_UNSUPPORTED_HANDLE_TYPE 8
+/* Surfman failed to export or swap the frame. */
+#define SURFMAN_ERROR_FAILED 9
+
+/* Native image handle types, which producers or together in `handle_types`. Version 1 of the ABI
+ * hands out dma-bufs, which producers import with `EGL_EXT_image_dma_buf_import` or
+ * `VK_EXT_external_memory_dma_buf`. */
+#define SURFMAN_IMAGE_HANDLE_DMA_BUF (1u << 0)
+/* Reserved for later versions of the ABI. */
+#define SURFMAN_IMAGE_HANDLE_IOSURFACE (1u << 1)
+#define SURFMAN_IMAGE_HANDLE_D3D_SHARED (1u << 2)
+
+typedef struct SurfmanChain SurfmanChain;
+
+/* Describes an acquired frame. The producer sets `abi_version` and `handle_types`;
+ * `surfman_chain_acquire()` fills in the rest. */
+typedef struct SurfmanFrameDesc {
+    uint32_t abi_version;
+    /* The `SURFMAN_IMAGE_HANDLE_*` types that the producer can import. */
+    uint32_t handle_types;
+    /* The one of `handle_types` that `handle` is. */
+    uint32_t handle_type;
+    int32_t width;
+    int32_t height;
+    /* The DRM fourcc of the pixels' format. */
+    uint32_t format;
+    /* For a dma-buf, a file descriptor that stays owned by the chain until the frame is
+     * submitted. Duplicate it to keep it, or to hand it to an API that takes ownership of it. */
+    int64_t handle;
+    /* The DRM format modifier that describes how the pixels are laid out in memory. */
+    uint64_t modifier;
+    /* Frames with the same ID are the same image, so producers can keep their imports of it. */
+    uint64_t buffer_id;
+    /* Counts the frames that the chain has handed out, starting at 0. */
+    uint64_t frame_id;
+    /* The offset of the frame's first pixel from the start of the image, in bytes. */
+    uint32_t offset;
+    /* The number of bytes between the start of one row and the start of the next. */
+    uint32_t stride;
+} SurfmanFrameDesc;
+
+/* A damaged rectangle of a frame, measured in pixels from its first pixel in memory. */
+typedef struct SurfmanRect {
+    int32_t x;
+    int32_t y;
+    int32_t width;
+    int32_t height;
+} SurfmanRect;
+
+/* The entry points of a version of the ABI. */
+typedef struct SurfmanProducerApi {
+    uint32_t abi_version;
+    int32_t (*acquire)(SurfmanChain *chain, SurfmanFrameDesc *out_frame);
+    int32_t (*submit)(SurfmanChain *chain,
+                      int fence_fd,
+                      const SurfmanRect *damage,
+                      size_t n_damage);
+} SurfmanProducerApi;
+
+typedef const SurfmanProducerApi *(*SurfmanGetProducerApi)(uint32_t abi_version);
+
+/* Returns the version of the ABI that surfman speaks. */
+uint32_t surfman_producer_abi_version(void);
+
+/* Returns the entry points of version `abi_version` of the ABI, or NULL if surfman doesn't
+ * speak that version. */
+const SurfmanProducerApi *surfman_producer_api(uint32_t abi_version);
+
+/* Acquires the back buffer of `chain`, and describes it in `out_frame`. The back buffer starts
+ * out as a copy of the last frame. */
+int32_t surfman_chain_acquire(SurfmanChain *chain, SurfmanFrameDesc *out_frame);
+
+/* Submits the acquired frame of `chain`. `fence_fd` is a sync file that signals once the frame
+ * is rendered, or -1 for none; the chain takes ownership of it, whether or not the call
+ * succeeds. `damage` holds `n_damage` rectangles that changed since the last frame, or the whole
+ * frame changed if `n_damage` is 0. A submission that fails validation leaves the frame
+ * acquired. */
+int32_t surfman_chain_submit(SurfmanChain *chain,
+                             int fence_fd,
+                             const SurfmanRect *damage,
+                             size_t n_damage);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SURFMAN_PRODUCER_H */
@@ -0,0 +1,123 @@
+// surfman/surfman/src/acceleration.rs
+//
+//! Telling whether rendering is hardware accelerated.
+//!
+//! Drivers fall back to software rasterizers without failing: Mesa loads llvmpipe when the DRI
+//! driver for the GPU fails to load, and Windows substitutes the Microsoft Basic Render Driver
+//! when the GPU has none. Rendering still works, many times slower.
+//! `Device::context_is_hardware_accelerated()` tells whether a context renders on a GPU.
+//!
+//! A device that renders in software although it was created for a hardware adapter is reported
+//! as soon as it's created, to the `SoftwareFallbackHandler` registered with
+//! `set_software_fallback_handler()`, with a `SoftwareFallbackDetected` event.
+
+use crate::connection::Connection as ConnectionInterface;
+use crate::device::Device as DeviceInterface;
+use crate::AdapterKind;
+
+use std::sync::{Arc, RwLock};
+
+// Substrings that identify software rasterizers, in lowercase, matched against `GL_RENDERER`
+// strings and adapter names. Add new ones here as they turn up.
+const SOFTWARE_RENDERER_PATTERNS: [&str; 7] = [
+    // Mesa's.
+    "llvmpipe",
+    "softpipe",
+    "swrast",
+    // Google's, which ANGLE and Chrome can render with.
+    "swiftshader",
+    // WARP, as ANGLE names it.
+    "microsoft basic render driver",
+    // The OpenGL 1.1 implementation that Windows falls back to without a GPU driver.
+    "gdi generic",
+    // Apple's.
+    "apple software renderer",
+];
+
+/// A device that renders in software although it was created for a hardware adapter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SoftwareFallbackDetected {
+    /// The kind of adapter that the device was created for.
+    pub requested: AdapterKind,
+    /// The name of the renderer that the device got, where the platform reports one.
+    pub renderer: Option<String>,
+}
+
+/// A function that is called with each device found to render in software although it was
+/// created for a hardware adapter.
+///
+/// Register one with `set_software_fallback_handler()`. It is called on the thread that created
+/// the device, from inside `Connection::create_device()`, so it must not create devices itself.
+pub type SoftwareFallbackHandler = Box<dyn Fn(&SoftwareFallbackDetected) + Send + Sync>;
+
+type SharedHandler = Arc<dyn Fn(&SoftwareFallbackDetected) + Send + Sync>;
+
+lazy_static! {
+    static ref SOFTWARE_FALLBACK_HANDLER: RwLock<Option<SharedHandler>> = RwLock::new(None);
+}
+
+/// Sets the function that is called whenever a device is created for a hardware adapter but
+/// renders in software, for every connection in the process, or removes it if `None`.
+///
+/// Devices created before this is called aren't reported.
+pub fn set_software_fallback_handler(handler: Option<SoftwareFallbackHandler>) {
+    *SOFTWARE_FALLBACK_HANDLER.write().unwrap() = handler.map(Arc::from);
+}
+
+// Returns true if the `GL_RENDERER` string or adapter name is that of a software rasterizer.
+pub(crate) fn is_software_renderer(renderer: &str) -> bool {
+    let renderer = renderer.to_lowercase();
+    SOFTWARE_RENDERER_PATTERNS
+        .iter()
+        .any(|pattern| renderer.contains(pattern))
+}
+
+// Implements `Device::context_is_hardware_accelerated()` for the backends that have no better way
+// to tell than the device's identity and the context's renderer.
+pub(crate) fn context_is_hardware_accelerated<Device>(
+    device: &Device,
+    context: &Device::Context,
+) -> bool
+where
+    Device: DeviceInterface,
+{
+    if device.identity().software {
+        return false;
+    }
+    match device.context_info(context) {
+        Ok(info) => !is_software_renderer(&info.renderer),
+        Err(_) => false,
+    }
+}
+
+// Reports the device to the software fallback handler if it renders in software, going by its
+// identity and its adapter's name, although its adapter isn't the software one.
+pub(crate) fn check_device<Device>(device: &Device)
+where
+    Device: DeviceInterface,
+{
+    let key = match device.connection().adapter_key(&device.adapter()) {
+        Ok(key) => key,
+        Err(_) => return,
+    };
+    if key.kind == AdapterKind::Software {
+        return;
+    }
+    let software =
+        device.identity().software || key.driver.as_deref().is_some_and(is_software_renderer);
+    if !software {
+        return;
+    }
+    let event = SoftwareFallbackDetected {
+        requested: key.kind,
+        renderer: key.driver,
+    };
+    warn!(
+        "The device for a {} adapter renders in software, with {:?}",
+        event.requested, event.renderer
+    );
+    let handler = SOFTWARE_FALLBACK_HANDLER.read().unwrap().clone();
+    if let Some(handler) = handler {
+        handler(&event);
+    }
+}
@@ -0,0 +1,149 @@
+// surfman/surfman/src/bulk.rs
+//
+//! Running many operations on one context with it made current once.
+//!
+//! Destroying, resizing, and clearing surfaces each make the surface's context current and then
+//! restore whatever was current before. That costs little once, but tearing down a page's worth
+//! of surfaces pays it dozens of times over, and on ANGLE each make-current can cost as much as a
+//! small draw call. `Device::with_context_current()` makes the context current once, runs a
+//! closure with a `BulkOps` that offers those operations, and restores the previous context once
+//! when the closure returns.
+//!
+//! `BulkOps` calls the same device methods that the operations would otherwise be called with, so
+//! they behave exactly as they do on their own. Only their make-current and restore are skipped,
+//! for as long as the context stays current: if the closure makes another context current, the
+//! operations after that make their context current again, as they do on their own.
+//!
+//! `Device::create_surfaces()` creates a batch of surfaces, checking the device's cancellation
+//! token between them, and destroys the ones it created if any of them fails.
+
+use crate::connection::Connection as ConnectionAPI;
+use crate::current;
+use crate::device::Device as DeviceAPI;
+use crate::{Error, SurfaceAccess, SurfaceType};
+use euclid::default::{Rect, Size2D};
+
+/// The operations that `Device::with_context_current()` runs with its context current.
+///
+/// Each method behaves as the device method of the same name does when called with the context,
+/// and returns the same errors.
+pub struct BulkOps<'a, Device>
+where
+    Device: DeviceAPI,
+{
+    device: &'a Device,
+    context: &'a mut Device::Context,
+}
+
+// Implements `Device::with_context_current()` for every backend.
+pub(crate) fn with_context_current<Device, F, R>(
+    device: &Device,
+    context: &mut Device::Context,
+    f: F,
+) -> Result<R, Error>
+where
+    Device: DeviceAPI,
+    F: FnOnce(&mut BulkOps<Device>) -> R,
+{
+    let _guard = device.make_context_current_guarded(context)?;
+    // The scope ends before the guard restores the previous context.
+    let _scope = current::begin_bulk_ops(device.context_id(context));
+    Ok(f(&mut BulkOps { device, context }))
+}
+
+// Implements `Device::create_surfaces()` for every backend.
+pub(crate) fn create_surfaces<Device, I>(
+    device: &mut Device,
+    context: &mut Device::Context,
+    access: SurfaceAccess,
+    surface_types: I,
+) -> Result<Vec<Device::Surface>, Error>
+where
+    Device: DeviceAPI,
+    I: IntoIterator<Item = SurfaceType<<Device::Connection as ConnectionAPI>::NativeWidget>>,
+{
+    let cancel_token = device.cancellation_token();
+    let mut surfaces = vec![];
+    for surface_type in surface_types {
+        let result = cancel_token
+            .check()
+            .and_then(|()| device.create_surface(context, access, surface_type));
+        match result {
+            Ok(surface) => surfaces.push(surface),
+            Err(err) => {
+                // The error that stopped the batch is the one to report.
+                let _ = device.with_context_current(context, |ops| {
+                    for surface in &mut surfaces {
+                        let _ = ops.destroy_surface(surface);
+                    }
+                });
+                return Err(err);
+            }
+        }
+    }
+    Ok(surfaces)
+}
+
+impl<'a, Device> BulkOps<'a, Device>
+where
+    Device: DeviceAPI,
+{
+    /// Returns the device that the operations run on.
+    #[inline]
+    pub fn device(&self) -> &Device {
+        self.device
+    }
+
+    /// Returns the context that is current while the operations run.
+    #[inline]
+    pub fn context(&self) -> &Device::Context {
+        self.context
+    }
+
+    // Returns the device and the context, for swap chains, whose surface bookkeeping borrows both.
+    #[cfg(feature = "chains")]
+    pub(crate) fn device_and_context(&mut self) -> (&Device, &mut Device::Context) {
+        (self.device, self.context)
+    }
+
+    /// Destroys a surface, as `Device::destroy_surface()` does.
+    #[inline]
+    pub fn destroy_surface(&mut self, surface: &mut Device::Surface) -> Result<(), Error> {
+        self.device.destroy_surface(self.context, surface)
+    }
+
+    /// Destroys a surface texture and returns the underlying surface, as
+    /// `Device::destroy_surface_texture()` does.
+    #[inline]
+    pub fn destroy_surface_texture(
+        &mut self,
+        surface_texture: Device::SurfaceTexture,
+    ) -> Result<Device::Surface, (Error, Device::SurfaceTexture)> {
+        self.device
+            .destroy_surface_texture(self.context, surface_texture)
+    }
+
+    /// Resizes a surface, as `Device::resize_surface()` does.
+    #[inline]
+    pub fn resize_surface(
+        &mut self,
+        surface: &mut Device::Surface,
+        size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        self.device.resize_surface(self.context, surface, size)
+    }
+
+    /// Clears a region of a surface, as `Device::clear_surface_region()` does.
+    #[inline]
+    pub fn clear_surface_region(
+        &mut self,
+        surface: &mut Device::Surface,
+        rect: Option<Rect<i32>>,
+        color: [f32; 4],
+        depth: Option<f32>,
+        stencil: Option<i32>,
+    ) -> Result<(), Error> {
+        self.device
+            .clear_surface_region(self.context, surface, rect, color, depth, stencil)
+    }
+}
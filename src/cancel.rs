@@ -0,0 +1,65 @@
+// surfman/surfman/src/cancel.rs
+//
+//! Cooperative cancellation of long-running device operations.
+
+use crate::Error;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag that asks a device to abandon context and surface creation early.
+///
+/// Get one with `Device::cancellation_token()`. Clones share the same flag, and can be sent to
+/// and cancelled from any thread, for example when the application is shutting down while a
+/// worker thread is still creating contexts.
+///
+/// Cancellation is cooperative. The device checks the flag between the steps of
+/// `create_context_descriptor()`, `create_context()`, and `create_surface()`: before choosing a
+/// config, before and after creating the native context, after creating its placeholder surface,
+/// and before allocating surface storage. Once it sees the flag set, the operation destroys
+/// anything it has created so far and returns `Error::Cancelled`. A single call into the
+/// platform's windowing API can't be interrupted, so a slow driver can still delay cancellation
+/// by as long as its slowest step.
+///
+/// The flag stays set, and every later creation on the device fails with `Error::Cancelled`,
+/// until `reset()` is called. Destroying contexts and surfaces is never cancelled.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    /// Creates a new token that hasn't been cancelled.
+    #[inline]
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Asks any operation on the device that this token came from to stop at its next check.
+    #[inline]
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns true if `cancel()` has been called since the token was created or last reset.
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Clears the flag, so that the device can create contexts and surfaces again.
+    #[inline]
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::SeqCst);
+    }
+
+    // Returns `Error::Cancelled` if the token has been cancelled.
+    #[inline]
+    pub(crate) fn check(&self) -> Result<(), Error> {
+        if self.is_cancelled() {
+            Err(Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
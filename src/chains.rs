@@ -22,9 +22,15 @@
 
 #![allow(missing_docs)]
 
+use crate::bulk::BulkOps;
 use crate::device::Device as DeviceAPI;
-use crate::{ContextID, Error, SurfaceAccess, SurfaceInfo, SurfaceType};
-use euclid::default::Size2D;
+use crate::pause::PresentPause;
+use crate::scheduling::FramePacer;
+use crate::PausedPresentation;
+use crate::SurfaceType;
+use crate::{ContextID, DebugFill, Error, LowMemoryOptions, SchedulingEnforcement};
+use crate::{SurfaceAccess, SurfaceID};
+use euclid::default::{Rect, Size2D};
 use fnv::{FnvHashMap, FnvHashSet};
 use log::debug;
 use sparkle::gl::{self, GLuint, Gl};
@@ -33,21 +39,152 @@ use std::fmt::Debug;
 use std::hash::Hash;
 use std::mem;
 use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::thread;
+use std::time::{Duration, Instant};
 
 // The data stored for each swap chain.
 struct SwapChainData<Device: DeviceAPI> {
-    // The size of the back buffer
-    size: Size2D<i32>,
     // The id of the producer context
     context_id: ContextID,
     // The surface access mode for the context.
     surface_access: SurfaceAccess,
-    // The back buffer of the swap chain.
-    back_buffer: BackBuffer<Device>,
-    // Some if the producing context has finished drawing a new front buffer, ready to be displayed.
-    pending_surface: Option<Device::Surface>,
-    // All of the surfaces that have already been displayed, ready to be recycled.
-    recycled_surfaces: Vec<Device::Surface>,
+    // The buffers of the swap chain.
+    state: SwapChainState<Device::Surface>,
+    // Spaces out frames when the device's background scheduling class is enforced in software.
+    pacer: FramePacer,
+}
+
+// After this many resizes, the surfaces that survive them are reallocated once the size settles,
+// if the reallocation policy asks for it.
+const RESIZES_BEFORE_REALLOCATION: u32 = 4;
+
+// The number of frames without a resize after which the size counts as settled.
+const RESIZE_SETTLE_FRAMES: u64 = 60;
+
+// The most surfaces that a swap chain keeps alive, counting the ones that consumers hold, before
+// it destroys its spare surfaces. Three lets a consumer hold on to a frame across a swap without
+// the producer allocating a surface every other frame.
+const DEFAULT_CHAIN_DEPTH: usize = 3;
+
+// The back buffer and the front buffer.
+const MIN_CHAIN_DEPTH: usize = 2;
+
+// The depth that swap chains of a device in low-memory mode with `options` keep spare surfaces
+// within, or of a device not in low-memory mode if `None`.
+fn chain_depth(options: Option<LowMemoryOptions>) -> usize {
+    match options {
+        None => DEFAULT_CHAIN_DEPTH,
+        Some(options) if options.purge_pools => 0,
+        Some(options) => {
+            (options.min_chain_depth as usize).clamp(MIN_CHAIN_DEPTH, DEFAULT_CHAIN_DEPTH)
+        }
+    }
+}
+
+/// When a swap chain retires its surfaces and replaces them with freshly-allocated ones.
+///
+/// Long-lived swap chains reuse the same few surfaces indefinitely, and intermittent resizes
+/// leave them scattered among freed allocations of other sizes. On some drivers this fragments
+/// video memory until presenting slows down. Retiring surfaces periodically lets the driver place
+/// them afresh.
+///
+/// A surface is only retired when it is about to become the back buffer again, and its
+/// replacement is allocated before it is destroyed, so no frames are dropped. If allocating the
+/// replacement fails, the old surface stays in use.
+///
+/// The default policy never retires surfaces.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReallocationPolicy {
+    /// Retire each surface after it has been the back buffer this many times.
+    pub max_frames_per_buffer: Option<u64>,
+    /// Retire the surfaces that survived a series of resizes, once the size has stopped
+    /// changing.
+    pub realloc_on_resize_history: bool,
+}
+
+/// Counters describing the lifetime of a swap chain.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SwapChainStats {
+    /// The number of times the buffers have been swapped.
+    pub frames: u64,
+    /// The number of surfaces retired and replaced under the reallocation policy.
+    pub reallocations: u64,
+}
+
+// How many times a surface has been the back buffer, and the frame it was allocated in.
+struct SurfaceAge {
+    id: SurfaceID,
+    uses: u64,
+    allocated_frame: u64,
+}
+
+// Tracks the age of each surface in a swap chain, to retire surfaces according to a
+// `ReallocationPolicy`.
+#[derive(Default)]
+struct SurfaceAging {
+    policy: ReallocationPolicy,
+    ages: Vec<SurfaceAge>,
+    stats: SwapChainStats,
+    resizes_since_reallocation: u32,
+    last_resize_frame: u64,
+    // Surfaces allocated before this frame are retired the next time they are used.
+    retire_before_frame: u64,
+}
+
+impl SurfaceAging {
+    fn allocated(&mut self, id: SurfaceID) {
+        self.destroyed(id);
+        self.ages.push(SurfaceAge {
+            id,
+            uses: 0,
+            allocated_frame: self.stats.frames,
+        });
+    }
+
+    fn destroyed(&mut self, id: SurfaceID) {
+        self.ages.retain(|age| age.id != id);
+    }
+
+    fn retired(&mut self, id: SurfaceID) {
+        self.destroyed(id);
+        self.stats.reallocations += 1;
+    }
+
+    fn used(&mut self, id: SurfaceID) {
+        if let Some(age) = self.ages.iter_mut().find(|age| age.id == id) {
+            age.uses += 1;
+        }
+    }
+
+    fn resized(&mut self) {
+        self.resizes_since_reallocation += 1;
+        self.last_resize_frame = self.stats.frames;
+    }
+
+    fn swapped(&mut self) {
+        self.stats.frames += 1;
+        if self.policy.realloc_on_resize_history
+            && self.resizes_since_reallocation >= RESIZES_BEFORE_REALLOCATION
+            && self.stats.frames - self.last_resize_frame >= RESIZE_SETTLE_FRAMES
+        {
+            debug!("Size has settled after resizes; retiring surfaces");
+            self.resizes_since_reallocation = 0;
+            self.retire_before_frame = self.stats.frames;
+        }
+    }
+
+    // Whether the surface should be retired before it becomes the back buffer again.
+    fn is_due(&self, id: SurfaceID) -> bool {
+        let age = match self.ages.iter().find(|age| age.id == id) {
+            Some(age) => age,
+            None => return false,
+        };
+        let worn_out = match self.policy.max_frames_per_buffer {
+            Some(max_frames) => age.uses >= max_frames,
+            None => false,
+        };
+        worn_out || age.allocated_frame < self.retire_before_frame
+    }
 }
 
 pub enum PreserveBuffer<'a> {
@@ -55,55 +192,168 @@ pub enum PreserveBuffer<'a> {
     No,
 }
 
-enum BackBuffer<Device: DeviceAPI> {
+// Binds surfaces to the producer context and destroys them, on behalf of the back buffer of an
+// attached swap chain.
+pub(crate) trait SurfaceBinding {
+    type Surface;
+
+    // Makes `surface` the current surface of the producer context.
+    fn bind_surface(&mut self, surface: Self::Surface) -> Result<(), (Error, Self::Surface)>;
+
+    // Removes the current surface of the producer context.
+    fn unbind_surface(&mut self) -> Result<Self::Surface, Error>;
+
+    fn destroy_surface(&mut self, surface: &mut Self::Surface) -> Result<(), Error>;
+}
+
+// Everything that the buffer state machine of a swap chain needs from the producer. The tests
+// implement this without GL, to check the state machine on its own.
+pub(crate) trait SurfaceProvider: SurfaceBinding {
+    // The ID of the producer context, for logging.
+    fn context_id(&self) -> ContextID;
+
+    fn surface_id(&self, surface: &Self::Surface) -> SurfaceID;
+
+    fn surface_size(&self, surface: &Self::Surface) -> Size2D<i32>;
+
+    // Creates a generic surface for the producer context.
+    fn create_surface(&mut self, size: Size2D<i32>) -> Result<Self::Surface, Error>;
+
+    // Invalidates the attachments of a surface that has just been presented.
+    fn invalidate_surface(&mut self, surface: &mut Self::Surface) -> Result<(), Error>;
+
+    // Fills a recycled surface with the device's debug fill, if it has one.
+    fn debug_fill_surface(&mut self, surface: &mut Self::Surface) -> Result<(), Error>;
+
+    // The options that the producer's device is in low-memory mode with, if it is.
+    fn low_memory_options(&self) -> Option<LowMemoryOptions>;
+}
+
+// The producer context of a swap chain, for operations that may allocate surfaces.
+struct ProducerContext<'a, Device: DeviceAPI> {
+    device: &'a mut Device,
+    context: &'a mut Device::Context,
+    surface_access: SurfaceAccess,
+}
+
+impl<'a, Device: DeviceAPI> SurfaceBinding for ProducerContext<'a, Device> {
+    type Surface = Device::Surface;
+
+    fn bind_surface(&mut self, surface: Device::Surface) -> Result<(), (Error, Device::Surface)> {
+        self.device.bind_surface_to_context(self.context, surface)
+    }
+
+    fn unbind_surface(&mut self) -> Result<Device::Surface, Error> {
+        self.device
+            .unbind_surface_from_context(self.context)?
+            .ok_or(Error::Failed)
+    }
+
+    fn destroy_surface(&mut self, surface: &mut Device::Surface) -> Result<(), Error> {
+        self.device.destroy_surface(self.context, surface)
+    }
+}
+
+impl<'a, Device: DeviceAPI> SurfaceProvider for ProducerContext<'a, Device> {
+    fn context_id(&self) -> ContextID {
+        self.device.context_id(self.context)
+    }
+
+    fn surface_id(&self, surface: &Device::Surface) -> SurfaceID {
+        self.device.surface_info(surface).id
+    }
+
+    fn surface_size(&self, surface: &Device::Surface) -> Size2D<i32> {
+        self.device.surface_info(surface).size
+    }
+
+    fn create_surface(&mut self, size: Size2D<i32>) -> Result<Device::Surface, Error> {
+        let surface_type = SurfaceType::Generic { size };
+        self.device
+            .create_surface(self.context, self.surface_access, surface_type)
+    }
+
+    fn invalidate_surface(&mut self, surface: &mut Device::Surface) -> Result<(), Error> {
+        self.device.invalidate_surface(self.context, surface)
+    }
+
+    fn debug_fill_surface(&mut self, surface: &mut Device::Surface) -> Result<(), Error> {
+        self.device.debug_fill_surface(self.context, surface)
+    }
+
+    fn low_memory_options(&self) -> Option<LowMemoryOptions> {
+        self.device.low_memory_options()
+    }
+}
+
+// The producer context of a swap chain, for operations that only move the back buffer in and out
+// of the context.
+struct BoundContext<'a, Device: DeviceAPI> {
+    device: &'a Device,
+    context: &'a mut Device::Context,
+}
+
+impl<'a, Device: DeviceAPI> SurfaceBinding for BoundContext<'a, Device> {
+    type Surface = Device::Surface;
+
+    fn bind_surface(&mut self, surface: Device::Surface) -> Result<(), (Error, Device::Surface)> {
+        self.device.bind_surface_to_context(self.context, surface)
+    }
+
+    fn unbind_surface(&mut self) -> Result<Device::Surface, Error> {
+        self.device
+            .unbind_surface_from_context(self.context)?
+            .ok_or(Error::Failed)
+    }
+
+    fn destroy_surface(&mut self, surface: &mut Device::Surface) -> Result<(), Error> {
+        self.device.destroy_surface(self.context, surface)
+    }
+}
+
+enum BackBuffer<Surface> {
     Attached,
-    Detached(Device::Surface),
+    Detached(Surface),
     TakenAttached,
     TakenDetached,
 }
 
-impl<Device: DeviceAPI> BackBuffer<Device> {
-    fn take_surface(
-        &mut self,
-        device: &Device,
-        context: &mut Device::Context,
-    ) -> Result<Device::Surface, Error> {
-        let new_back_buffer = match self {
-            BackBuffer::Attached => BackBuffer::TakenAttached,
-            BackBuffer::Detached(_) => BackBuffer::TakenDetached,
-            _ => return Err(Error::Failed),
-        };
-        let surface = match mem::replace(self, new_back_buffer) {
-            BackBuffer::Attached => device.unbind_surface_from_context(context)?.unwrap(),
-            BackBuffer::Detached(surface) => surface,
-            _ => unreachable!(),
-        };
-        Ok(surface)
+impl<Surface> BackBuffer<Surface> {
+    // Whether the producer has taken the back buffer, as a surface texture or in the middle of
+    // an operation.
+    fn is_taken(&self) -> bool {
+        match *self {
+            BackBuffer::TakenAttached | BackBuffer::TakenDetached => true,
+            BackBuffer::Attached | BackBuffer::Detached(_) => false,
+        }
     }
-    fn take_surface_texture(
-        &mut self,
-        device: &Device,
-        context: &mut Device::Context,
-    ) -> Result<Device::SurfaceTexture, Error> {
-        let surface = self.take_surface(device, context)?;
-        device
-            .create_surface_texture(context, surface)
-            .map_err(|(err, surface)| {
-                let _ = self.replace_surface(device, context, surface);
-                err
-            })
+    fn take_surface<S>(&mut self, surfaces: &mut S) -> Result<Surface, Error>
+    where
+        S: SurfaceBinding<Surface = Surface>,
+    {
+        match *self {
+            BackBuffer::Attached => {
+                // Only change state once the surface has actually left the context.
+                let surface = surfaces.unbind_surface()?;
+                *self = BackBuffer::TakenAttached;
+                Ok(surface)
+            }
+            BackBuffer::Detached(_) => match mem::replace(self, BackBuffer::TakenDetached) {
+                BackBuffer::Detached(surface) => Ok(surface),
+                _ => unreachable!(),
+            },
+            BackBuffer::TakenAttached | BackBuffer::TakenDetached => Err(Error::Failed),
+        }
     }
-    fn replace_surface(
-        &mut self,
-        device: &Device,
-        context: &mut Device::Context,
-        surface: Device::Surface,
-    ) -> Result<(), Error> {
+    fn replace_surface<S>(&mut self, surfaces: &mut S, surface: Surface) -> Result<(), Error>
+    where
+        S: SurfaceBinding<Surface = Surface>,
+    {
         let new_back_buffer = match self {
             BackBuffer::TakenAttached => {
-                if let Err((err, mut surface)) = device.bind_surface_to_context(context, surface) {
+                if let Err((err, mut surface)) = surfaces.bind_surface(surface) {
                     debug!("Oh no, destroying surface");
-                    let _ = device.destroy_surface(context, &mut surface);
+                    let _ = surfaces.destroy_surface(&mut surface);
                     return Err(err);
                 }
                 BackBuffer::Attached
@@ -114,16 +364,492 @@ impl<Device: DeviceAPI> BackBuffer<Device> {
         *self = new_back_buffer;
         Ok(())
     }
-    fn replace_surface_texture(
+}
+
+// The buffers of a swap chain, and the transitions between them, apart from any device.
+//
+// Every surface that the swap chain owns is in exactly one place: the back buffer (bound to the
+// producer context if the swap chain is attached), the pending front buffer, the frame held while
+// presentation is paused, the recycled surfaces, or the spare surfaces. Surfaces that the producer
+// has taken as a surface texture, or that a consumer has taken, are in none until they are given
+// back. None of the transitions block, so the mutex of a `SwapChain` makes each of them atomic.
+pub(crate) struct SwapChainState<Surface> {
+    // The size of the back buffer
+    size: Size2D<i32>,
+    // The back buffer of the swap chain.
+    back_buffer: BackBuffer<Surface>,
+    // Some if the producing context has finished drawing a new front buffer, ready to be displayed.
+    pending_surface: Option<Surface>,
+    // The debug fill of the producer's device when the pending front buffer was swapped in.
+    pending_debug_fill: DebugFill,
+    // All of the surfaces that have already been displayed, ready to be recycled.
+    recycled_surfaces: Vec<Surface>,
+    // Recycled surfaces of the current size kept across swaps, to become back buffers later.
+    // Unlike recycled surfaces, consumers never take these.
+    spare_surfaces: Vec<Surface>,
+    // While presentation is paused, the front buffer swapped in most recently, which is held back
+    // from consumers.
+    pause: PresentPause<Surface>,
+    // How long each surface has been in use, for retiring surfaces.
+    aging: SurfaceAging,
+}
+
+impl<Surface> SwapChainState<Surface> {
+    // The state of a swap chain whose back buffer is the current surface of the producer context.
+    pub(crate) fn attached(
+        size: Size2D<i32>,
+        back_buffer_id: SurfaceID,
+    ) -> SwapChainState<Surface> {
+        SwapChainState::new(size, BackBuffer::Attached, back_buffer_id)
+    }
+
+    // The state of a swap chain whose back buffer is `surface`.
+    pub(crate) fn detached(
+        size: Size2D<i32>,
+        surface: Surface,
+        back_buffer_id: SurfaceID,
+    ) -> SwapChainState<Surface> {
+        SwapChainState::new(size, BackBuffer::Detached(surface), back_buffer_id)
+    }
+
+    fn new(
+        size: Size2D<i32>,
+        back_buffer: BackBuffer<Surface>,
+        back_buffer_id: SurfaceID,
+    ) -> SwapChainState<Surface> {
+        let mut aging = SurfaceAging::default();
+        aging.allocated(back_buffer_id);
+        aging.used(back_buffer_id);
+        SwapChainState {
+            size,
+            back_buffer,
+            pending_surface: None,
+            pending_debug_fill: DebugFill::None,
+            recycled_surfaces: Vec::new(),
+            spare_surfaces: Vec::new(),
+            pause: PresentPause::default(),
+            aging,
+        }
+    }
+
+    // Swap the back and front buffers.
+    // Returns an error if the producer has taken the back buffer.
+    pub(crate) fn swap_buffers<S>(&mut self, surfaces: &mut S) -> Result<(), Error>
+    where
+        S: SurfaceProvider<Surface = Surface>,
+    {
+        // Check this first, so that a new back buffer isn't fetched and then lost.
+        if self.back_buffer.is_taken() {
+            return Err(Error::Failed);
+        }
+
+        // Recycle the old front buffer, or the frame held before this one while paused.
+        self.end_dropped_pause();
+        let old_front_buffer = if self.pause.is_paused() {
+            self.pause.take_held_frame()
+        } else {
+            self.pending_surface.take()
+        };
+        if let Some(old_front_buffer) = old_front_buffer {
+            debug!(
+                "Recycling surface {:?} ({:?}) for context {:?}",
+                surfaces.surface_id(&old_front_buffer),
+                surfaces.surface_size(&old_front_buffer),
+                surfaces.context_id()
+            );
+            self.recycled_surfaces.push(old_front_buffer);
+        }
+
+        // Fetch a new back buffer, recycling presented buffers if possible, or else spare ones.
+        let size = self.size;
+        let reused = match self
+            .recycled_surfaces
+            .iter()
+            .position(|surface| surfaces.surface_size(surface) == size)
+        {
+            Some(index) => Some(self.recycled_surfaces.swap_remove(index)),
+            None => self.spare_surfaces.pop(),
+        };
+        let mut new_back_buffer = match reused {
+            Some(mut surface) => {
+                debug!("Recycling surface for context {:?}", surfaces.context_id());
+                if let Err(err) = surfaces.debug_fill_surface(&mut surface) {
+                    self.spare_surfaces.push(surface);
+                    return Err(err);
+                }
+                surface
+            }
+            None => {
+                debug!(
+                    "Creating a new surface ({:?}) for context {:?}",
+                    size,
+                    surfaces.context_id()
+                );
+                let surface = surfaces.create_surface(size)?;
+                self.aging.allocated(surfaces.surface_id(&surface));
+                surface
+            }
+        };
+
+        // Retire the new back buffer if it's due, allocating its replacement first.
+        let back_id = surfaces.surface_id(&new_back_buffer);
+        if self.aging.is_due(back_id) {
+            match surfaces.create_surface(size) {
+                Ok(replacement) => {
+                    debug!(
+                        "Retiring surface {:?} for context {:?}",
+                        back_id,
+                        surfaces.context_id()
+                    );
+                    let mut retired = mem::replace(&mut new_back_buffer, replacement);
+                    surfaces.destroy_surface(&mut retired)?;
+                    self.aging.retired(back_id);
+                    self.aging.allocated(surfaces.surface_id(&new_back_buffer));
+                }
+                Err(err) => debug!("Couldn't replace surface {:?}: {:?}", back_id, err),
+            }
+        }
+        self.aging.used(surfaces.surface_id(&new_back_buffer));
+
+        // Swap the buffers
+        debug!(
+            "Surface {:?} is the new back buffer for context {:?}",
+            surfaces.surface_id(&new_back_buffer),
+            surfaces.context_id()
+        );
+        let new_front_buffer = match self.back_buffer.take_surface(surfaces) {
+            Ok(surface) => surface,
+            Err(err) => {
+                self.recycled_surfaces.push(new_back_buffer);
+                return Err(err);
+            }
+        };
+        if let Err(err) = self.back_buffer.replace_surface(surfaces, new_back_buffer) {
+            self.set_front_buffer(new_front_buffer);
+            return Err(err);
+        }
+
+        // Update the state
+        debug!(
+            "Surface {:?} is the new front buffer for context {:?}",
+            surfaces.surface_id(&new_front_buffer),
+            surfaces.context_id()
+        );
+        self.set_front_buffer(new_front_buffer);
+        self.trim_spare_surfaces(surfaces)?;
+        self.aging.swapped();
+
+        Ok(())
+    }
+
+    // Keep the recycled surfaces of the current size as spares, and destroy the others, and then
+    // the spares that take the swap chain beyond the depth that the producer's device allows.
+    fn trim_spare_surfaces<S>(&mut self, surfaces: &mut S) -> Result<(), Error>
+    where
+        S: SurfaceProvider<Surface = Surface>,
+    {
+        let depth = chain_depth(surfaces.low_memory_options());
+        let size = self.size;
+        let (spares, mut surplus): (Vec<_>, Vec<_>) = self
+            .recycled_surfaces
+            .drain(..)
+            .partition(|surface| surfaces.surface_size(surface) == size);
+        self.spare_surfaces.extend(spares);
+        while self.aging.ages.len() - surplus.len() > depth {
+            match self.spare_surfaces.pop() {
+                Some(surface) => surplus.push(surface),
+                None => break,
+            }
+        }
+        for mut surface in surplus {
+            debug!(
+                "Destroying a surface for context {:?}",
+                surfaces.context_id()
+            );
+            self.aging.destroyed(surfaces.surface_id(&surface));
+            surfaces.destroy_surface(&mut surface)?;
+        }
+        Ok(())
+    }
+
+    // Destroy the spare surfaces, and the surfaces that consumers have given back since the last
+    // swap, returning how many were destroyed.
+    pub(crate) fn purge_pools<S>(&mut self, surfaces: &mut S) -> Result<usize, Error>
+    where
+        S: SurfaceProvider<Surface = Surface>,
+    {
+        let mut purged = 0;
+        let mut result = Ok(());
+        for mut surface in self
+            .spare_surfaces
+            .drain(..)
+            .chain(self.recycled_surfaces.drain(..))
+        {
+            self.aging.destroyed(surfaces.surface_id(&surface));
+            // Keep going after an error, so that the other surfaces aren't leaked.
+            match surfaces.destroy_surface(&mut surface) {
+                Ok(()) => purged += 1,
+                Err(err) => result = result.and(Err(err)),
+            }
+        }
+        debug!(
+            "Purged {} surfaces for context {:?}",
+            purged,
+            surfaces.context_id()
+        );
+        result.map(|()| purged)
+    }
+
+    // Make `surface` the pending front buffer, or hold it back from consumers while paused.
+    fn set_front_buffer(&mut self, surface: Surface) {
+        if let Some(surface) = self.pause.hold(surface) {
+            self.pending_surface = Some(surface);
+        }
+    }
+
+    // End a pause whose handle was dropped, recycling the frame that it held.
+    fn end_dropped_pause(&mut self) {
+        if let Some(surface) = self.pause.end_dropped() {
+            self.recycled_surfaces.push(surface);
+        }
+    }
+
+    // Swap the attached swap chain.
+    // Returns an error if the producer has taken the back buffer of either swap chain.
+    pub(crate) fn take_attachment_from<S>(
         &mut self,
-        device: &Device,
-        context: &mut Device::Context,
-        surface_texture: Device::SurfaceTexture,
+        surfaces: &mut S,
+        other: &mut SwapChainState<Surface>,
+    ) -> Result<(), Error>
+    where
+        S: SurfaceBinding<Surface = Surface>,
+    {
+        if self.back_buffer.is_taken() || other.back_buffer.is_taken() {
+            return Err(Error::Failed);
+        }
+        let our_surface = self.back_buffer.take_surface(surfaces)?;
+        let their_surface = match other.back_buffer.take_surface(surfaces) {
+            Ok(surface) => surface,
+            Err(err) => {
+                self.back_buffer.replace_surface(surfaces, our_surface)?;
+                return Err(err);
+            }
+        };
+        mem::swap(&mut self.back_buffer, &mut other.back_buffer);
+        self.back_buffer.replace_surface(surfaces, our_surface)?;
+        other.back_buffer.replace_surface(surfaces, their_surface)?;
+        Ok(())
+    }
+
+    // Resize the swap chain.
+    // This creates a new back buffer of the appropriate size,
+    // and destroys the old one.
+    // Returns an error if `size` is smaller than (1, 1), or the producer has taken the back
+    // buffer.
+    pub(crate) fn resize<S>(&mut self, surfaces: &mut S, size: Size2D<i32>) -> Result<(), Error>
+    where
+        S: SurfaceProvider<Surface = Surface>,
+    {
+        if (size.width < 1) || (size.height < 1) || self.back_buffer.is_taken() {
+            return Err(Error::Failed);
+        }
+        let new_back_buffer = surfaces.create_surface(size)?;
+        let new_back_id = surfaces.surface_id(&new_back_buffer);
+        let mut old_back_buffer = match self.back_buffer.take_surface(surfaces) {
+            Ok(surface) => surface,
+            Err(err) => {
+                let mut new_back_buffer = new_back_buffer;
+                let _ = surfaces.destroy_surface(&mut new_back_buffer);
+                return Err(err);
+            }
+        };
+        if let Err(err) = self.back_buffer.replace_surface(surfaces, new_back_buffer) {
+            self.recycled_surfaces.push(old_back_buffer);
+            return Err(err);
+        }
+        self.aging.destroyed(surfaces.surface_id(&old_back_buffer));
+        surfaces.destroy_surface(&mut old_back_buffer)?;
+        self.aging.allocated(new_back_id);
+        self.aging.used(new_back_id);
+        self.aging.resized();
+        self.size = size;
+        // The held frame is the wrong size now.
+        if let Some(held) = self.pause.force_resume() {
+            self.recycled_surfaces.push(held);
+        }
+        // So are the spare surfaces.
+        for mut surface in mem::take(&mut self.spare_surfaces) {
+            self.aging.destroyed(surfaces.surface_id(&surface));
+            surfaces.destroy_surface(&mut surface)?;
+        }
+        Ok(())
+    }
+
+    // Get the current size.
+    pub(crate) fn size(&self) -> Size2D<i32> {
+        self.size
+    }
+
+    // Take the current back buffer.
+    pub(crate) fn take_back_buffer<S>(&mut self, surfaces: &mut S) -> Result<Surface, Error>
+    where
+        S: SurfaceBinding<Surface = Surface>,
+    {
+        self.back_buffer.take_surface(surfaces)
+    }
+
+    // Give back the back buffer taken with `take_back_buffer`.
+    pub(crate) fn replace_back_buffer<S>(
+        &mut self,
+        surfaces: &mut S,
+        surface: Surface,
+    ) -> Result<(), Error>
+    where
+        S: SurfaceBinding<Surface = Surface>,
+    {
+        self.back_buffer.replace_surface(surfaces, surface)
+    }
+
+    // Invalidate the attachments of the front buffer that its consumers don't read.
+    pub(crate) fn invalidate_front_buffer<S>(&mut self, surfaces: &mut S) -> Result<(), Error>
+    where
+        S: SurfaceProvider<Surface = Surface>,
+    {
+        let front_buffer = match self.pause.held_frame_mut() {
+            Some(surface) => Some(surface),
+            None => self.pending_surface.as_mut(),
+        };
+        match front_buffer {
+            Some(surface) => surfaces.invalidate_surface(surface),
+            None => Ok(()),
+        }
+    }
+
+    // Take the current front buffer.
+    // Returns the most recent recycled surface if there is no current front buffer.
+    pub(crate) fn take_surface(&mut self) -> Option<Surface> {
+        self.pending_surface
+            .take()
+            .or_else(|| self.recycled_surfaces.pop())
+    }
+
+    // Take the current front buffer.
+    // Returns `None` if there is no current front buffer.
+    pub(crate) fn take_pending_surface(&mut self) -> Option<Surface> {
+        self.pending_surface.take()
+    }
+
+    // Recycle the current front buffer.
+    // While paused, it stays the front buffer instead, if no newer one has been swapped in, so that
+    // the producer doesn't draw over the frame that consumers were showing.
+    pub(crate) fn recycle_surface(&mut self, surface: Surface) {
+        self.end_dropped_pause();
+        if self.pause.is_paused() && self.pending_surface.is_none() {
+            self.pending_surface = Some(surface);
+        } else {
+            self.recycled_surfaces.push(surface)
+        }
+    }
+
+    // Pause presentation: until it's resumed, front buffers that the producer swaps in are held
+    // back from consumers.
+    // Returns an error if presentation is paused already.
+    pub(crate) fn pause(&mut self) -> Result<PausedPresentation, Error> {
+        self.end_dropped_pause();
+        self.pause.pause()
+    }
+
+    // Resume presentation, making the frame held most recently the pending front buffer if
+    // `present_pending` is true, and recycling it otherwise.
+    // Returns an error if `paused` is the handle of another swap chain's pause.
+    pub(crate) fn resume(
+        &mut self,
+        paused: PausedPresentation,
+        present_pending: bool,
     ) -> Result<(), Error> {
-        let surface = device
-            .destroy_surface_texture(context, surface_texture)
-            .map_err(|(err, _)| err)?;
-        self.replace_surface(device, context, surface)
+        let held = match self.pause.resume(paused)? {
+            Some(held) => held,
+            None => return Ok(()),
+        };
+        if !present_pending {
+            self.recycled_surfaces.push(held);
+        } else if let Some(old_front_buffer) = self.pending_surface.replace(held) {
+            self.recycled_surfaces.push(old_front_buffer);
+        }
+        Ok(())
+    }
+
+    // The front buffer held most recently while paused.
+    pub(crate) fn held_surface(&self) -> Option<&Surface> {
+        self.pause.held_frame()
+    }
+
+    // Give back a front buffer taken with `take_pending_surface`.
+    // If the producer has swapped in a newer front buffer since, the old one is recycled.
+    pub(crate) fn restore_pending_surface(&mut self, surface: Surface) {
+        if self.pending_surface.is_none() {
+            self.pending_surface = Some(surface);
+        } else {
+            self.recycle_surface(surface);
+        }
+    }
+
+    /// Is this the attached swap chain?
+    pub(crate) fn is_attached(&self) -> bool {
+        match self.back_buffer {
+            BackBuffer::Attached | BackBuffer::TakenAttached => true,
+            BackBuffer::Detached(_) | BackBuffer::TakenDetached => false,
+        }
+    }
+
+    // The surfaces that the swap chain holds itself, which excludes an attached back buffer.
+    #[cfg(test)]
+    pub(crate) fn surfaces(&self) -> impl Iterator<Item = &Surface> {
+        let back_buffer = match self.back_buffer {
+            BackBuffer::Detached(ref surface) => Some(surface),
+            _ => None,
+        };
+        back_buffer
+            .into_iter()
+            .chain(self.pending_surface.iter())
+            .chain(self.pause.frame())
+            .chain(self.recycled_surfaces.iter())
+            .chain(self.spare_surfaces.iter())
+    }
+
+    pub(crate) fn set_reallocation_policy(&mut self, policy: ReallocationPolicy) {
+        self.aging.policy = policy;
+    }
+
+    pub(crate) fn stats(&self) -> SwapChainStats {
+        self.aging.stats
+    }
+
+    // Destroy all the surfaces of the swap chain.
+    // Surfaces that consumers recycle afterwards are kept, and destroyed if this is called again.
+    pub(crate) fn destroy<S>(&mut self, surfaces: &mut S) -> Result<(), Error>
+    where
+        S: SurfaceBinding<Surface = Surface>,
+    {
+        let back_buffer = self.back_buffer.take_surface(surfaces).ok();
+        let held = self.pause.force_resume();
+        let mut result = Ok(());
+        for mut surface in self
+            .pending_surface
+            .take()
+            .into_iter()
+            .chain(back_buffer)
+            .chain(held)
+            .chain(self.recycled_surfaces.drain(..))
+            .chain(self.spare_surfaces.drain(..))
+        {
+            // Keep going after an error, so that the other surfaces aren't leaked.
+            if let Err(err) = surfaces.destroy_surface(&mut surface) {
+                result = result.and(Err(err));
+            }
+        }
+        self.aging.ages.clear();
+        result
     }
 }
 
@@ -137,6 +863,19 @@ impl<Device: DeviceAPI> SwapChainData<Device> {
         }
     }
 
+    // The producer context, for operations on the buffers.
+    fn producer<'a>(
+        &self,
+        device: &'a mut Device,
+        context: &'a mut Device::Context,
+    ) -> ProducerContext<'a, Device> {
+        ProducerContext {
+            device,
+            context,
+            surface_access: self.surface_access,
+        }
+    }
+
     // Swap the back and front buffers.
     // Called by the producer.
     // Returns an error if `context` is not the producer context for this swap chain.
@@ -148,49 +887,19 @@ impl<Device: DeviceAPI> SwapChainData<Device> {
     ) -> Result<(), Error> {
         debug!("Swap buffers on context {:?}", self.context_id);
         self.validate_context(device, context)?;
-
-        // Recycle the old front buffer
-        if let Some(old_front_buffer) = self.pending_surface.take() {
-            let SurfaceInfo { id, size, .. } = device.surface_info(&old_front_buffer);
-            debug!(
-                "Recycling surface {:?} ({:?}) for context {:?}",
-                id, size, self.context_id
-            );
-            self.recycle_surface(old_front_buffer);
-        }
-
-        // Fetch a new back buffer, recycling presented buffers if possible.
-        let new_back_buffer = self
-            .recycled_surfaces
-            .iter()
-            .position(|surface| device.surface_info(surface).size == self.size)
-            .map(|index| {
-                debug!("Recycling surface for context {:?}", self.context_id);
-                Ok(self.recycled_surfaces.swap_remove(index))
-            })
-            .unwrap_or_else(|| {
-                debug!(
-                    "Creating a new surface ({:?}) for context {:?}",
-                    self.size, self.context_id
-                );
-                let surface_type = SurfaceType::Generic { size: self.size };
-                device.create_surface(context, self.surface_access, surface_type)
-            })?;
-
-        let back_info = device.surface_info(&new_back_buffer);
-
-        // Swap the buffers
-        debug!(
-            "Surface {:?} is the new back buffer for context {:?}",
-            device.surface_info(&new_back_buffer).id,
-            self.context_id
-        );
-        let new_front_buffer = self.back_buffer.take_surface(device, context)?;
-        self.back_buffer
-            .replace_surface(device, context, new_back_buffer)?;
+        let mut producer = self.producer(device, context);
+        self.state.swap_buffers(&mut producer)?;
+        self.state.pending_debug_fill = device.debug_surface_fill();
 
         if let PreserveBuffer::Yes(gl) = preserve_buffer {
-            let front_info = device.surface_info(&new_front_buffer);
+            let front_info = match self.state.pending_surface {
+                Some(ref surface) => device.surface_info(surface),
+                None => unreachable!(),
+            };
+            let back_info = match self.state.back_buffer {
+                BackBuffer::Detached(ref surface) => device.surface_info(surface),
+                _ => device.context_surface_info(context)?.unwrap(),
+            };
             gl.bind_framebuffer(gl::READ_FRAMEBUFFER, front_info.framebuffer_object);
             debug_assert_eq!(gl.get_error(), gl::NO_ERROR);
             gl.bind_framebuffer(gl::DRAW_FRAMEBUFFER, back_info.framebuffer_object);
@@ -210,19 +919,18 @@ impl<Device: DeviceAPI> SwapChainData<Device> {
             debug_assert_eq!(gl.get_error(), gl::NO_ERROR);
         }
 
-        // Update the state
-        debug!(
-            "Surface {:?} is the new front buffer for context {:?}",
-            device.surface_info(&new_front_buffer).id,
-            self.context_id
-        );
-        self.pending_surface = Some(new_front_buffer);
-        for mut surface in self.recycled_surfaces.drain(..) {
-            debug!("Destroying a surface for context {:?}", self.context_id);
-            device.destroy_surface(context, &mut surface)?;
-        }
+        // This comes after the blit, which reads the depth and stencil of the front buffer.
+        let mut producer = self.producer(device, context);
+        self.state.invalidate_front_buffer(&mut producer)
+    }
 
-        Ok(())
+    // Returns how long the producer should wait after swapping, if the device's scheduling class
+    // is enforced in software.
+    fn pacing_delay(&mut self, device: &Device) -> Option<Duration> {
+        if device.scheduling_enforcement() != SchedulingEnforcement::Software {
+            return None;
+        }
+        Some(self.pacer.delay(Instant::now()))
     }
 
     // Swap the attached swap chain.
@@ -237,15 +945,9 @@ impl<Device: DeviceAPI> SwapChainData<Device> {
     ) -> Result<(), Error> {
         self.validate_context(device, context)?;
         other.validate_context(device, context)?;
-        let our_surface = self.back_buffer.take_surface(device, context)?;
-        let their_surface = other.back_buffer.take_surface(device, context)?;
-        mem::swap(&mut self.back_buffer, &mut other.back_buffer);
-        self.back_buffer
-            .replace_surface(device, context, our_surface)?;
-        other
-            .back_buffer
-            .replace_surface(device, context, their_surface)?;
-        Ok(())
+        let mut producer = self.producer(device, context);
+        self.state
+            .take_attachment_from(&mut producer, &mut other.state)
     }
 
     // Resize the swap chain.
@@ -266,23 +968,21 @@ impl<Device: DeviceAPI> SwapChainData<Device> {
             size
         );
         self.validate_context(device, context)?;
-        if (size.width < 1) || (size.height < 1) {
-            return Err(Error::Failed);
-        }
-        let surface_type = SurfaceType::Generic { size };
-        let new_back_buffer = device.create_surface(context, self.surface_access, surface_type)?;
-        let mut old_back_buffer = self.back_buffer.take_surface(device, context)?;
-        self.back_buffer
-            .replace_surface(device, context, new_back_buffer)?;
-        device.destroy_surface(context, &mut old_back_buffer)?;
-        self.size = size;
-        Ok(())
+        let mut producer = self.producer(device, context);
+        self.state.resize(&mut producer, size)
     }
 
-    // Get the current size.
-    // Called by a consumer.
-    fn size(&self) -> Size2D<i32> {
-        self.size
+    // Destroy the spare surfaces, and the surfaces given back since the last swap.
+    // Called by the producer.
+    // Returns an error if `context` is not the producer context for this swap chain.
+    fn purge_pools(
+        &mut self,
+        device: &mut Device,
+        context: &mut Device::Context,
+    ) -> Result<usize, Error> {
+        self.validate_context(device, context)?;
+        let mut producer = self.producer(device, context);
+        self.state.purge_pools(&mut producer)
     }
 
     // Take the current back buffer.
@@ -293,7 +993,17 @@ impl<Device: DeviceAPI> SwapChainData<Device> {
         context: &mut Device::Context,
     ) -> Result<Device::SurfaceTexture, Error> {
         self.validate_context(device, context)?;
-        self.back_buffer.take_surface_texture(device, context)
+        let surface = self
+            .state
+            .take_back_buffer(&mut BoundContext { device, context })?;
+        device
+            .create_surface_texture(context, surface)
+            .map_err(|(err, surface)| {
+                let _ = self
+                    .state
+                    .replace_back_buffer(&mut BoundContext { device, context }, surface);
+                err
+            })
     }
 
     // Recycle the current back buffer.
@@ -305,32 +1015,12 @@ impl<Device: DeviceAPI> SwapChainData<Device> {
         surface_texture: Device::SurfaceTexture,
     ) -> Result<(), Error> {
         self.validate_context(device, context)?;
-        self.back_buffer
-            .replace_surface_texture(device, context, surface_texture)
-    }
-
-    // Take the current front buffer.
-    // Returns the most recent recycled surface if there is no current front buffer.
-    // Called by a consumer.
-    fn take_surface(&mut self) -> Option<Device::Surface> {
-        self.pending_surface
-            .take()
-            .or_else(|| self.recycled_surfaces.pop())
-    }
-
-    // Take the current front buffer.
-    // Returns `None` if there is no current front buffer.
-    // Called by a consumer.
-    fn take_pending_surface(&mut self) -> Option<Device::Surface> {
-        self.pending_surface.take()
-    }
-
-    // Recycle the current front buffer.
-    // Called by a consumer.
-    fn recycle_surface(&mut self, surface: Device::Surface) {
-        self.recycled_surfaces.push(surface)
+        let surface = device
+            .destroy_surface_texture(context, surface_texture)
+            .map_err(|(err, _)| err)?;
+        self.state
+            .replace_back_buffer(&mut BoundContext { device, context }, surface)
     }
-
     // Clear the current back buffer.
     // Called by the producer.
     // Returns an error if `context` is not the producer context for this swap chain.
@@ -365,10 +1055,12 @@ impl<Device: DeviceAPI> SwapChainData<Device> {
         }
 
         // Make the back buffer the current surface
-        let reattach = if self.is_attached() {
+        let reattach = if self.state.is_attached() {
             None
         } else {
-            let surface = self.back_buffer.take_surface(device, context)?;
+            let surface = self
+                .state
+                .take_back_buffer(&mut BoundContext { device, context })?;
             let mut reattach = device.unbind_surface_from_context(context)?;
             if let Err((err, mut surface)) = device.bind_surface_to_context(context, surface) {
                 debug!("Oh no, destroying surfaces");
@@ -407,8 +1099,8 @@ impl<Device: DeviceAPI> SwapChainData<Device> {
                 let _ = device.destroy_surface(context, &mut old_surface);
                 return Err(err);
             }
-            self.back_buffer
-                .replace_surface(device, context, old_surface)?;
+            self.state
+                .replace_back_buffer(&mut BoundContext { device, context }, old_surface)?;
         }
 
         // Restore the GL state
@@ -440,29 +1132,21 @@ impl<Device: DeviceAPI> SwapChainData<Device> {
         Ok(())
     }
 
-    /// Is this the attached swap chain?
-    fn is_attached(&self) -> bool {
-        match self.back_buffer {
-            BackBuffer::Attached | BackBuffer::TakenAttached => true,
-            BackBuffer::Detached(_) | BackBuffer::TakenDetached => false,
-        }
-    }
-
     // Destroy the swap chain.
     // Called by the producer.
     // Returns an error if `context` is not the producer context for this swap chain.
     fn destroy(&mut self, device: &mut Device, context: &mut Device::Context) -> Result<(), Error> {
         self.validate_context(device, context)?;
-        let surfaces = self
-            .pending_surface
-            .take()
-            .into_iter()
-            .chain(self.back_buffer.take_surface(device, context).into_iter())
-            .chain(self.recycled_surfaces.drain(..));
-        for mut surface in surfaces {
-            device.destroy_surface(context, &mut surface)?;
-        }
-        Ok(())
+        let mut producer = self.producer(device, context);
+        self.state.destroy(&mut producer)
+    }
+
+    // Destroy the swap chain, with the producer context already current.
+    // Returns an error if the context is not the producer context for this swap chain.
+    fn destroy_with(&mut self, ops: &mut BulkOps<Device>) -> Result<(), Error> {
+        self.validate_context(ops.device(), ops.context())?;
+        let (device, context) = ops.device_and_context();
+        self.state.destroy(&mut BoundContext { device, context })
     }
 }
 
@@ -485,13 +1169,26 @@ impl<Device: DeviceAPI> SwapChain<Device> {
     /// Swap the back and front buffers.
     /// Called by the producer.
     /// Returns an error if `context` is not the producer context for this swap chain.
+    /// If the device's `Background` scheduling class is enforced in software, this yields the
+    /// CPU, and waits if necessary to keep frames apart, before returning.
     pub fn swap_buffers(
         &self,
         device: &mut Device,
         context: &mut Device::Context,
         preserve_buffer: PreserveBuffer<'_>,
     ) -> Result<(), Error> {
-        self.lock().swap_buffers(device, context, preserve_buffer)
+        let delay = {
+            let mut data = self.lock();
+            data.swap_buffers(device, context, preserve_buffer)?;
+            data.pacing_delay(device)
+        };
+        // Wait with the swap chain unlocked, so that consumers can take the new front buffer.
+        match delay {
+            Some(delay) if delay > Duration::ZERO => thread::sleep(delay),
+            Some(_) => thread::yield_now(),
+            None => {}
+        }
+        Ok(())
     }
 
     /// Swap the attached swap chain.
@@ -504,8 +1201,20 @@ impl<Device: DeviceAPI> SwapChain<Device> {
         context: &mut Device::Context,
         other: &SwapChain<Device>,
     ) -> Result<(), Error> {
-        self.lock()
-            .take_attachment_from(device, context, &mut *other.lock())
+        // A swap chain can't take the attachment from itself, and locking it twice would deadlock.
+        if Arc::ptr_eq(&self.0, &other.0) {
+            return Err(Error::Failed);
+        }
+        // Lock the two swap chains in a consistent order, so that two threads passing the same
+        // pair in opposite orders can't deadlock.
+        let (mut this, mut other) = if Arc::as_ptr(&self.0) < Arc::as_ptr(&other.0) {
+            let this = self.lock();
+            (this, other.lock())
+        } else {
+            let other = other.lock();
+            (self.lock(), other)
+        };
+        this.take_attachment_from(device, context, &mut other)
     }
 
     /// Resize the swap chain.
@@ -525,7 +1234,7 @@ impl<Device: DeviceAPI> SwapChain<Device> {
     /// Get the current size.
     /// Called by a consumer.
     pub fn size(&self) -> Size2D<i32> {
-        self.lock().size()
+        self.lock().state.size()
     }
 
     /// Take the current back buffer.
@@ -554,7 +1263,67 @@ impl<Device: DeviceAPI> SwapChain<Device> {
     /// Returns `None` if there is no current front buffer.
     /// Called by a consumer.
     pub fn take_pending_surface(&self) -> Option<Device::Surface> {
-        self.lock().take_pending_surface()
+        self.lock().state.take_pending_surface()
+    }
+
+    // Take the current front buffer, along with the debug fill that the producer's device had
+    // when it was swapped in.
+    pub(crate) fn take_pending_frame(&self) -> Option<(Device::Surface, DebugFill)> {
+        let mut data = self.lock();
+        let debug_fill = data.state.pending_debug_fill;
+        Some((data.state.take_pending_surface()?, debug_fill))
+    }
+
+    /// Give back a front buffer taken with `take_pending_surface`, so that it can still be
+    /// displayed.
+    /// If the producer has swapped in a newer front buffer since, the old one is recycled.
+    /// Called by a secondary consumer, such as a `FrameExporter`.
+    pub fn restore_pending_surface(&self, surface: Device::Surface) {
+        self.lock().state.restore_pending_surface(surface)
+    }
+
+    /// Pause presentation, so that the front buffers that the producer swaps in are held back from
+    /// consumers until presentation is resumed with `resume()`.
+    /// Meanwhile, the pending front buffer stays pending when a consumer recycles it, so that
+    /// consumers can keep showing the frame that was current when presentation was paused.
+    /// Dropping the returned handle resumes presentation, recycling the held frame, as does
+    /// resizing the swap chain.
+    /// Returns `PresentationPaused` if presentation is already paused.
+    /// Called by a consumer.
+    pub fn pause(&self) -> Result<PausedPresentation, Error> {
+        self.lock().state.pause()
+    }
+
+    /// Resume presentation.
+    /// If `present_pending` is true, the frame held most recently becomes the pending front
+    /// buffer; otherwise, it's recycled.
+    /// Returns `IncompatiblePausedPresentation` if `paused` was returned for another swap chain or
+    /// surface.
+    /// Called by a consumer.
+    pub fn resume(&self, paused: PausedPresentation, present_pending: bool) -> Result<(), Error> {
+        self.lock().state.resume(paused, present_pending)
+    }
+
+    /// Read a rectangle of the frame held most recently while presentation is paused, measured
+    /// from its top left, into `out` as tightly packed 8-bit RGBA, top row first.
+    /// Returns `false` if no frame has been held.
+    /// Called by the producer.
+    /// Returns an error if `context` is not the producer context for this swap chain.
+    pub fn read_held_frame(
+        &self,
+        device: &Device,
+        context: &Device::Context,
+        rect: Rect<i32>,
+        out: &mut [u8],
+    ) -> Result<bool, Error> {
+        let data = self.lock();
+        data.validate_context(device, context)?;
+        match data.state.held_surface() {
+            Some(surface) => device
+                .read_surface_data(context, surface, rect, out)
+                .map(|_| true),
+            None => Ok(false),
+        }
     }
 
     /// Clear the current back buffer.
@@ -572,7 +1341,30 @@ impl<Device: DeviceAPI> SwapChain<Device> {
 
     /// Is this the attached swap chain?
     pub fn is_attached(&self) -> bool {
-        self.lock().is_attached()
+        self.lock().state.is_attached()
+    }
+
+    /// Set when surfaces are retired and replaced with freshly-allocated ones.
+    /// Called by the producer.
+    pub fn set_reallocation_policy(&self, policy: ReallocationPolicy) {
+        self.lock().state.set_reallocation_policy(policy);
+    }
+
+    /// Get the counters describing the lifetime of this swap chain.
+    pub fn stats(&self) -> SwapChainStats {
+        self.lock().state.stats()
+    }
+
+    /// Destroy the spare surfaces of the swap chain, and the surfaces that consumers have given
+    /// back since the last swap, returning how many were destroyed.
+    /// Called by the producer.
+    /// Returns an error if `context` is not the producer context for this swap chain.
+    pub fn purge_pools(
+        &self,
+        device: &mut Device,
+        context: &mut Device::Context,
+    ) -> Result<usize, Error> {
+        self.lock().purge_pools(device, context)
     }
 
     /// Destroy the swap chain.
@@ -582,20 +1374,23 @@ impl<Device: DeviceAPI> SwapChain<Device> {
         self.lock().destroy(device, context)
     }
 
+    // Destroy the swap chain from inside `Device::with_context_current()`.
+    fn destroy_with(&self, ops: &mut BulkOps<Device>) -> Result<(), Error> {
+        self.lock().destroy_with(ops)
+    }
+
     /// Create a new attached swap chain
     pub fn create_attached(
         device: &mut Device,
         context: &mut Device::Context,
         surface_access: SurfaceAccess,
     ) -> Result<SwapChain<Device>, Error> {
-        let size = device.context_surface_info(context).unwrap().unwrap().size;
+        let surface_info = device.context_surface_info(context).unwrap().unwrap();
         Ok(SwapChain(Arc::new(Mutex::new(SwapChainData {
-            size,
             context_id: device.context_id(context),
             surface_access,
-            back_buffer: BackBuffer::Attached,
-            pending_surface: None,
-            recycled_surfaces: Vec::new(),
+            state: SwapChainState::attached(surface_info.size, surface_info.id),
+            pacer: FramePacer::default(),
         }))))
     }
 
@@ -608,13 +1403,12 @@ impl<Device: DeviceAPI> SwapChain<Device> {
     ) -> Result<SwapChain<Device>, Error> {
         let surface_type = SurfaceType::Generic { size };
         let surface = device.create_surface(context, surface_access, surface_type)?;
+        let surface_id = device.surface_info(&surface).id;
         Ok(SwapChain(Arc::new(Mutex::new(SwapChainData {
-            size,
             context_id: device.context_id(context),
             surface_access,
-            back_buffer: BackBuffer::Detached(surface),
-            pending_surface: None,
-            recycled_surfaces: Vec::new(),
+            state: SwapChainState::detached(size, surface, surface_id),
+            pacer: FramePacer::default(),
         }))))
     }
 }
@@ -630,13 +1424,13 @@ where
     /// Returns the most recent recycled surface if there is no current front buffer.
     /// Called by a consumer.
     fn take_surface(&self) -> Option<Device::Surface> {
-        self.lock().take_surface()
+        self.lock().state.take_surface()
     }
 
     /// Recycle the current front buffer.
     /// Called by a consumer.
     fn recycle_surface(&self, surface: Device::Surface) {
-        self.lock().recycle_surface(surface)
+        self.lock().state.recycle_surface(surface)
     }
 }
 
@@ -755,21 +1549,54 @@ where
 
     /// Destroy all the swap chains for a particular producer context.
     /// Called by the producer.
+    /// The context is made current once for all of the swap chains, rather than once for each of
+    /// their surfaces.
     pub fn destroy_all(
         &self,
         device: &mut Device,
         context: &mut Device::Context,
     ) -> Result<(), Error> {
-        if let Some(mut ids) = self.ids().remove(&device.context_id(context)) {
+        let mut ids = match self.ids().remove(&device.context_id(context)) {
+            Some(ids) => ids,
+            None => return Ok(()),
+        };
+        let destroyed = device.with_context_current(context, |ops| {
             for id in ids.drain() {
                 if let Some(swap_chain) = self.table_mut().remove(&id) {
-                    swap_chain.destroy(device, context)?;
+                    swap_chain.destroy_with(ops)?;
                 }
             }
+            Ok(())
+        });
+        if let Ok(result) = destroyed {
+            return result;
+        }
+        // A context that can't be made current, such as a lost one, still has its surfaces
+        // destroyed one by one.
+        for id in ids.drain() {
+            if let Some(swap_chain) = self.table_mut().remove(&id) {
+                swap_chain.destroy(device, context)?;
+            }
         }
         Ok(())
     }
 
+    /// Destroy the spare surfaces of all the swap chains for a particular producer context, and
+    /// the surfaces that consumers have given back to them since their last swap, returning how
+    /// many were destroyed.
+    /// Called by the producer, for example after `Device::enter_low_memory_mode()`.
+    pub fn purge_pools(
+        &self,
+        device: &mut Device,
+        context: &mut Device::Context,
+    ) -> Result<usize, Error> {
+        let mut purged = 0;
+        for (_, swap_chain) in self.iter(device, context) {
+            purged += swap_chain.purge_pools(device, context)?;
+        }
+        Ok(purged)
+    }
+
     /// Iterate over all the swap chains for a particular producer context.
     /// Called by the producer.
     pub fn iter(
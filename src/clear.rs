@@ -0,0 +1,365 @@
+// surfman/surfman/src/clear.rs
+//
+//! Clearing regions of surfaces, for embedders that only composite and don't load OpenGL.
+//!
+//! `Device::clear_surface_region` clears a rectangle of a surface with a scissored `glClear()`.
+//! Rectangles are measured from the top left of the surface, as window systems measure them, and
+//! are clamped to the surface's bounds. Everything that the clear touches is put back afterward,
+//! so callers that do use OpenGL on the same context don't see any of it.
+//!
+//! Debug fills, which `Device::set_debug_surface_fill()` turns on, are clears too. A checkerboard
+//! is cleared to one color, and then every other cell to the other, all with the state saved once.
+
+use crate::gl;
+use crate::gl::types::{GLboolean, GLenum, GLfloat, GLint, GLuint};
+use crate::gl_utils;
+use crate::{Error, GLVersion, Gl};
+use euclid::default::{Point2D, Rect, Size2D};
+
+// `GL_RASTERIZER_DISCARD` discards clears as well as draws, and only exists in OpenGL 3.0 and
+// OpenGL ES 3.0 and later.
+const GL3_CAPABILITIES: [GLenum; 1] = [gl::RASTERIZER_DISCARD];
+
+/// A recognizable pattern to fill new surfaces with, so that regions that nothing draws to stand
+/// out. Set one with `Device::set_debug_surface_fill()`.
+///
+/// Colors are stored as the surface's format stores them: clamped to between 0 and 1 in every
+/// format but `SurfaceFormat::Rgba16F`, and without alpha in formats that have none, such as the
+/// `GL_RGB565` of low-memory mode. Surfaces with a depth buffer have it cleared to
+/// `DebugFill::DEPTH`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DebugFill {
+    /// Leave the contents of new surfaces undefined, as usual.
+    ///
+    /// This is the default.
+    None,
+    /// Fill with a single color.
+    SolidColor([f32; 4]),
+    /// Fill with a checkerboard of `DebugFill::CHECKERBOARD_COLORS`, whose square cells are `cell`
+    /// pixels wide, starting at the top left of the surface with the first color.
+    Checkerboard {
+        /// The width of each cell, in device pixels. Zero counts as one.
+        cell: u32,
+    },
+}
+
+// A pattern to clear on top of a region's color.
+#[derive(Clone, Copy, Debug)]
+struct Checkerboard {
+    cell: i32,
+    color: [f32; 4],
+}
+
+// A region of a surface to clear, and the values to clear each of its attachments to.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ClearRegion {
+    rect: Option<Rect<i32>>,
+    color: [f32; 4],
+    depth: Option<f32>,
+    stencil: Option<i32>,
+    checkerboard: Option<Checkerboard>,
+}
+
+// The state that clearing changes, saved so that it can be put back.
+struct SavedState {
+    draw_framebuffer: GLint,
+    read_framebuffer: Option<GLint>,
+    scissor_test: bool,
+    scissor_box: [GLint; 4],
+    color_clear_value: [GLfloat; 4],
+    color_mask: [GLboolean; 4],
+    depth_clear_value: GLfloat,
+    depth_mask: GLboolean,
+    stencil_clear_value: GLint,
+    stencil_masks: [GLint; 2],
+    enabled: Vec<(GLenum, bool)>,
+}
+
+impl Default for DebugFill {
+    #[inline]
+    fn default() -> DebugFill {
+        DebugFill::None
+    }
+}
+
+impl DebugFill {
+    /// The colors of `DebugFill::Checkerboard`: opaque magenta and black, which every surface
+    /// format stores exactly.
+    pub const CHECKERBOARD_COLORS: [[f32; 4]; 2] = [[1.0, 0.0, 1.0, 1.0], [0.0, 0.0, 0.0, 1.0]];
+
+    /// The value that debug fills clear depth buffers to, which is halfway, rather than at the far
+    /// plane where depth buffers are usually cleared.
+    pub const DEPTH: f32 = 0.5;
+
+    // Returns the region to clear a whole surface with to fill it, or `None` if there's nothing
+    // to fill.
+    #[inline]
+    pub(crate) fn region(&self) -> Option<ClearRegion> {
+        match *self {
+            DebugFill::None => None,
+            DebugFill::SolidColor(color) => {
+                Some(ClearRegion::new(None, color, Some(DebugFill::DEPTH), None))
+            }
+            DebugFill::Checkerboard { cell } => {
+                let [first_color, second_color] = DebugFill::CHECKERBOARD_COLORS;
+                let mut region = ClearRegion::new(None, first_color, Some(DebugFill::DEPTH), None);
+                region.checkerboard = Some(Checkerboard {
+                    cell: cell.clamp(1, i32::MAX as u32) as i32,
+                    color: second_color,
+                });
+                Some(region)
+            }
+        }
+    }
+}
+
+impl ClearRegion {
+    // `rect` is measured from the top left of the surface. `None` clears all of it.
+    #[inline]
+    pub(crate) fn new(
+        rect: Option<Rect<i32>>,
+        color: [f32; 4],
+        depth: Option<f32>,
+        stencil: Option<i32>,
+    ) -> ClearRegion {
+        ClearRegion {
+            rect,
+            color,
+            depth,
+            stencil,
+            checkerboard: None,
+        }
+    }
+
+    // Returns the part of the region inside a surface of the given size, with the origin at the
+    // bottom left as OpenGL has it, or `None` if none of it is.
+    pub(crate) fn gl_rect(&self, surface_size: Size2D<i32>) -> Option<Rect<i32>> {
+        let bounds = Rect::new(Point2D::zero(), surface_size);
+        let rect = match self.rect {
+            None => bounds,
+            Some(rect) => rect.intersection(&bounds)?,
+        };
+        if rect.is_empty() {
+            return None;
+        }
+        let y = surface_size.height - rect.max_y();
+        Some(Rect::new(Point2D::new(rect.origin.x, y), rect.size))
+    }
+
+    // Clears the region of a framebuffer object of a surface of the given size, which may be 0
+    // for a window's own framebuffer. A context that can draw to it must be current.
+    //
+    // Depth and stencil are only cleared if they were asked for; clearing an attachment that the
+    // surface doesn't have does nothing.
+    pub(crate) unsafe fn clear(
+        &self,
+        gl: &Gl,
+        framebuffer_object: GLuint,
+        surface_size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        let rect = match self.gl_rect(surface_size) {
+            None => return Ok(()),
+            Some(rect) => rect,
+        };
+
+        gl_utils::drain_pre_existing_gl_errors(gl);
+        let gl3 = GLVersion::current(gl).major >= 3;
+        let saved_state = SavedState::save(gl, gl3);
+        let mut result = self.clear_rect(gl, gl3, framebuffer_object, &rect);
+        if let (Ok(()), Some(checkerboard)) = (&result, self.checkerboard) {
+            result = checkerboard.clear(gl, surface_size, &rect);
+        }
+        saved_state.restore(gl, gl3);
+        result
+    }
+
+    unsafe fn clear_rect(
+        &self,
+        gl: &Gl,
+        gl3: bool,
+        framebuffer_object: GLuint,
+        rect: &Rect<i32>,
+    ) -> Result<(), Error> {
+        gl.BindFramebuffer(draw_framebuffer_target(gl3), framebuffer_object);
+        gl.Enable(gl::SCISSOR_TEST);
+        gl.Scissor(
+            rect.origin.x,
+            rect.origin.y,
+            rect.size.width,
+            rect.size.height,
+        );
+
+        let [red, green, blue, alpha] = self.color;
+        gl.ClearColor(red, green, blue, alpha);
+        gl.ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+        let mut mask = gl::COLOR_BUFFER_BIT;
+        if let Some(depth) = self.depth {
+            clear_depth(gl, depth);
+            gl.DepthMask(gl::TRUE);
+            mask |= gl::DEPTH_BUFFER_BIT;
+        }
+        if let Some(stencil) = self.stencil {
+            gl.ClearStencil(stencil);
+            gl.StencilMask(!0);
+            mask |= gl::STENCIL_BUFFER_BIT;
+        }
+        gl.Clear(mask);
+
+        if gl.GetError() != gl::NO_ERROR {
+            return Err(Error::Failed);
+        }
+        Ok(())
+    }
+}
+
+impl Checkerboard {
+    // Clears every other cell inside `rect`, measured from the bottom left, to the second color,
+    // with the framebuffer and everything but the scissor box already set up by `clear_rect()`.
+    // Cells are counted from the top left of the surface, which is the first color.
+    unsafe fn clear(
+        &self,
+        gl: &Gl,
+        surface_size: Size2D<i32>,
+        rect: &Rect<i32>,
+    ) -> Result<(), Error> {
+        let [red, green, blue, alpha] = self.color;
+        gl.ClearColor(red, green, blue, alpha);
+        let top = surface_size.height - rect.max_y();
+        let (first_column, first_row) = (rect.origin.x / self.cell, top / self.cell);
+        let (end_column, end_row) = (
+            (rect.max_x() + self.cell - 1) / self.cell,
+            (surface_size.height - rect.origin.y + self.cell - 1) / self.cell,
+        );
+        for row in first_row..end_row {
+            let first_odd_column = first_column + (first_column + row + 1) % 2;
+            for column in (first_odd_column..end_column).step_by(2) {
+                let cell = Rect::new(
+                    Point2D::new(
+                        column * self.cell,
+                        surface_size.height - (row + 1) * self.cell,
+                    ),
+                    Size2D::new(self.cell, self.cell),
+                );
+                if let Some(cell) = cell.intersection(rect) {
+                    gl.Scissor(
+                        cell.origin.x,
+                        cell.origin.y,
+                        cell.size.width,
+                        cell.size.height,
+                    );
+                    gl.Clear(gl::COLOR_BUFFER_BIT);
+                }
+            }
+        }
+
+        if gl.GetError() != gl::NO_ERROR {
+            return Err(Error::Failed);
+        }
+        Ok(())
+    }
+}
+
+impl SavedState {
+    // Saves the state, then disables whatever would keep the clear from reaching the framebuffer.
+    unsafe fn save(gl: &Gl, gl3: bool) -> SavedState {
+        let mut saved_state = SavedState {
+            draw_framebuffer: 0,
+            read_framebuffer: None,
+            scissor_test: gl.IsEnabled(gl::SCISSOR_TEST) != gl::FALSE,
+            scissor_box: [0; 4],
+            color_clear_value: [0.0; 4],
+            color_mask: [gl::TRUE; 4],
+            depth_clear_value: 0.0,
+            depth_mask: gl::TRUE,
+            stencil_clear_value: 0,
+            stencil_masks: [0; 2],
+            enabled: vec![],
+        };
+        if gl3 {
+            let mut read_framebuffer = 0;
+            gl.GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut read_framebuffer);
+            saved_state.read_framebuffer = Some(read_framebuffer);
+            saved_state.enabled = GL3_CAPABILITIES
+                .iter()
+                .map(|&capability| {
+                    let enabled = gl.IsEnabled(capability) != gl::FALSE;
+                    gl.Disable(capability);
+                    (capability, enabled)
+                })
+                .collect();
+        }
+        gl.GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut saved_state.draw_framebuffer);
+        gl.GetIntegerv(gl::SCISSOR_BOX, saved_state.scissor_box.as_mut_ptr());
+        gl.GetFloatv(
+            gl::COLOR_CLEAR_VALUE,
+            saved_state.color_clear_value.as_mut_ptr(),
+        );
+        gl.GetBooleanv(gl::COLOR_WRITEMASK, saved_state.color_mask.as_mut_ptr());
+        gl.GetFloatv(gl::DEPTH_CLEAR_VALUE, &mut saved_state.depth_clear_value);
+        gl.GetBooleanv(gl::DEPTH_WRITEMASK, &mut saved_state.depth_mask);
+        gl.GetIntegerv(
+            gl::STENCIL_CLEAR_VALUE,
+            &mut saved_state.stencil_clear_value,
+        );
+        gl.GetIntegerv(gl::STENCIL_WRITEMASK, &mut saved_state.stencil_masks[0]);
+        gl.GetIntegerv(
+            gl::STENCIL_BACK_WRITEMASK,
+            &mut saved_state.stencil_masks[1],
+        );
+        saved_state
+    }
+
+    unsafe fn restore(&self, gl: &Gl, gl3: bool) {
+        for &(capability, enabled) in &self.enabled {
+            if enabled {
+                gl.Enable(capability);
+            }
+        }
+        let [front_mask, back_mask] = self.stencil_masks;
+        gl.StencilMaskSeparate(gl::FRONT, front_mask as GLuint);
+        gl.StencilMaskSeparate(gl::BACK, back_mask as GLuint);
+        gl.ClearStencil(self.stencil_clear_value);
+        gl.DepthMask(self.depth_mask);
+        clear_depth(gl, self.depth_clear_value);
+        let [red, green, blue, alpha] = self.color_mask;
+        gl.ColorMask(red, green, blue, alpha);
+        let [red, green, blue, alpha] = self.color_clear_value;
+        gl.ClearColor(red, green, blue, alpha);
+        let [x, y, width, height] = self.scissor_box;
+        gl.Scissor(x, y, width, height);
+        if !self.scissor_test {
+            gl.Disable(gl::SCISSOR_TEST);
+        }
+        gl.BindFramebuffer(
+            draw_framebuffer_target(gl3),
+            self.draw_framebuffer as GLuint,
+        );
+        if let Some(read_framebuffer) = self.read_framebuffer {
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, read_framebuffer as GLuint);
+        }
+    }
+}
+
+// OpenGL ES 2.0 has no separate draw framebuffer binding.
+#[inline]
+fn draw_framebuffer_target(gl3: bool) -> GLenum {
+    if gl3 {
+        gl::DRAW_FRAMEBUFFER
+    } else {
+        gl::FRAMEBUFFER
+    }
+}
+
+// The OpenGL ES bindings only have the single-precision `glClearDepthf()`, which desktop OpenGL
+// lacks before 4.1.
+#[cfg(any(target_os = "android", target_env = "ohos"))]
+#[inline]
+unsafe fn clear_depth(gl: &Gl, depth: GLfloat) {
+    gl.ClearDepthf(depth)
+}
+
+#[cfg(not(any(target_os = "android", target_env = "ohos")))]
+#[inline]
+unsafe fn clear_depth(gl: &Gl, depth: GLfloat) {
+    gl.ClearDepth(depth as f64)
+}
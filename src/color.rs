@@ -0,0 +1,198 @@
+// surfman/surfman/src/color.rs
+//
+//! Color profiles of the outputs that widget surfaces are displayed on.
+//!
+//! `Device::widget_color_profile()` reports the profile of the output that a widget is on now.
+//! Windows move between outputs, and color management daemons replace profiles, so presenting a
+//! widget surface also checks the profile every few frames, and reports a change to the handler
+//! registered with `Device::set_color_profile_changed_handler()`.
+
+use crate::SurfaceID;
+
+// How many presentations apart a widget surface's color profile is checked. Querying it costs a
+// round trip to the X server on X11, and profiles rarely change, so checking it every frame would
+// be wasteful; at 60 frames per second, a change is still noticed within half a second.
+pub(crate) const PROFILE_CHECK_INTERVAL: u32 = 30;
+
+/// A well-known RGB color space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorSpace {
+    /// sRGB (IEC 61966-2-1).
+    Srgb,
+    /// Display P3: DCI-P3 primaries with the sRGB transfer function and a D65 white point.
+    DisplayP3,
+    /// ITU-R BT.2020 primaries.
+    Bt2020,
+    /// Adobe RGB (1998).
+    AdobeRgb,
+}
+
+/// The color profile of the output that a widget surface is being displayed on.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColorProfile {
+    /// The platform had no information about the output, so sRGB should be assumed.
+    ///
+    /// This is always returned for generic surfaces.
+    AssumedSrgb,
+    /// The platform reported a well-known color space but no ICC profile.
+    Named(ColorSpace),
+    /// The platform reported an ICC profile.
+    Icc {
+        /// The well-known color space the profile describes, if it could be identified.
+        color_space: Option<ColorSpace>,
+        /// The raw bytes of the ICC profile.
+        data: Vec<u8>,
+    },
+}
+
+impl ColorProfile {
+    /// Wraps raw ICC profile data, identifying well-known color spaces by the profile
+    /// description.
+    ///
+    /// Returns `None` if the data isn't a well-formed RGB ICC profile.
+    pub fn from_icc(data: Vec<u8>) -> Option<ColorProfile> {
+        // See ICC.1:2022 § 7.2 for the layout of the header.
+        if data.len() < 132 || read_u32(&data, 0)? as usize > data.len() {
+            return None;
+        }
+        if &data[36..40] != b"acsp" || &data[16..20] != b"RGB " {
+            return None;
+        }
+
+        let color_space =
+            profile_description(&data).and_then(|description| identify_color_space(&description));
+        Some(ColorProfile::Icc { color_space, data })
+    }
+
+    /// Returns the well-known color space of this profile, if there is one.
+    ///
+    /// `AssumedSrgb` reports `ColorSpace::Srgb`.
+    pub fn color_space(&self) -> Option<ColorSpace> {
+        match *self {
+            ColorProfile::AssumedSrgb => Some(ColorSpace::Srgb),
+            ColorProfile::Named(color_space) => Some(color_space),
+            ColorProfile::Icc { color_space, .. } => color_space,
+        }
+    }
+
+    /// Returns the raw ICC profile data, if the platform provided any.
+    pub fn icc_data(&self) -> Option<&[u8]> {
+        match *self {
+            ColorProfile::Icc { ref data, .. } => Some(data),
+            ColorProfile::AssumedSrgb | ColorProfile::Named(_) => None,
+        }
+    }
+}
+
+/// A function that a device calls with the ID of each widget surface that it finds is displayed
+/// with a different color profile, and the new profile.
+///
+/// Register one with `Device::set_color_profile_changed_handler()`. It is called on the thread
+/// that presented the surface, from inside `Device::present_surface()`, so it must not call back
+/// into that device.
+pub type ColorProfileChangedHandler = Box<dyn Fn(SurfaceID, &ColorProfile) + Send + Sync>;
+
+// Follows the color profile of the output showing a widget surface, one presentation at a time.
+//
+// This only compares the profiles that it's given, and leaves querying and reporting them to the
+// caller, so that it can be driven with made-up profiles.
+#[derive(Default)]
+#[cfg_attr(
+    not(any(
+        target_os = "windows",
+        target_os = "macos",
+        x11_platform,
+        wayland_platform
+    )),
+    allow(dead_code)
+)]
+pub(crate) struct ColorProfileTracker {
+    profile: Option<ColorProfile>,
+    presents: u32,
+}
+
+#[cfg_attr(
+    not(any(
+        target_os = "windows",
+        target_os = "macos",
+        x11_platform,
+        wayland_platform
+    )),
+    allow(dead_code)
+)]
+impl ColorProfileTracker {
+    // Call this on every present, with a function that queries the profile of the output
+    // that the widget is on now. Returns the new profile if it has changed since the last check.
+    // The first check only records the profile.
+    pub(crate) fn update<F>(&mut self, query: F) -> Option<ColorProfile>
+    where
+        F: FnOnce() -> ColorProfile,
+    {
+        let due = self.presents == 0;
+        self.presents = (self.presents + 1) % PROFILE_CHECK_INTERVAL;
+        if !due {
+            return None;
+        }
+        let profile = query();
+        match self.profile.replace(profile.clone()) {
+            Some(old_profile) if old_profile != profile => Some(profile),
+            _ => None,
+        }
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+// Extracts the text of the `desc` tag, which is a `desc` (ICC v2) or `mluc` (ICC v4) element.
+fn profile_description(data: &[u8]) -> Option<String> {
+    let tag_count = read_u32(data, 128)? as usize;
+    let (mut offset, mut size) = (None, 0);
+    for tag_index in 0..tag_count {
+        let entry = 132 + tag_index * 12;
+        if data.get(entry..entry + 4)? == b"desc" {
+            offset = Some(read_u32(data, entry + 4)? as usize);
+            size = read_u32(data, entry + 8)? as usize;
+            break;
+        }
+    }
+
+    let element = data.get(offset?..offset? + size)?;
+    match element.get(0..4)? {
+        b"desc" => {
+            let length = read_u32(element, 8)? as usize;
+            let text = element.get(12..12 + length)?;
+            let text = text.split(|&byte| byte == 0).next()?;
+            Some(String::from_utf8_lossy(text).into_owned())
+        }
+        b"mluc" => {
+            // Use the first record, whatever its language.
+            let length = read_u32(element, 20)? as usize;
+            let start = read_u32(element, 24)? as usize;
+            let text = element.get(start..start + length)?;
+            let units: Vec<u16> = text
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            Some(String::from_utf16_lossy(&units))
+        }
+        _ => None,
+    }
+}
+
+fn identify_color_space(description: &str) -> Option<ColorSpace> {
+    let description = description.to_ascii_lowercase();
+    if description.contains("display p3") || description.contains("dci-p3") {
+        Some(ColorSpace::DisplayP3)
+    } else if description.contains("2020") {
+        Some(ColorSpace::Bt2020)
+    } else if description.contains("adobe rgb") {
+        Some(ColorSpace::AdobeRgb)
+    } else if description.contains("srgb") {
+        Some(ColorSpace::Srgb)
+    } else {
+        None
+    }
+}
@@ -3,6 +3,7 @@
 //! The abstract interface that all connections conform to.
 
 use crate::egl::types::EGLDisplay;
+use crate::AdapterKey;
 use crate::Error;
 use crate::GLApi;
 
@@ -32,12 +33,36 @@ pub trait Connection: Sized {
     /// Connects to the default display.
     fn new() -> Result<Self, Error>;
 
+    /// Opens a connection that needs no display server.
+    ///
+    /// Devices and contexts work as usual on the resulting connection, but only
+    /// `SurfaceType::Generic` surfaces can be created.
+    fn new_headless() -> Result<Self, Error>;
+
+    /// Connects to the default display, creating contexts for the given OpenGL API flavor.
+    ///
+    /// Returns `Error::UnsupportedGLType` if the platform can't create contexts of that flavor.
+    ///
+    /// Connections of both flavors can be used on the same thread. On EGL, the client API that
+    /// `eglBindAPI` sets is per-thread state, so surfman binds the right API around each call
+    /// that depends on it, and restores the caller's binding afterward.
+    fn new_with_api(gl_api: GLApi) -> Result<Self, Error>;
+
+    /// Wraps an existing native connection without taking ownership of it.
+    unsafe fn from_native_connection(
+        native_connection: Self::NativeConnection,
+    ) -> Result<Self, Error>;
+
     /// Returns the native connection corresponding to this connection.
     fn native_connection(&self) -> Self::NativeConnection;
 
     /// Returns the OpenGL API flavor that this connection supports (OpenGL or OpenGL ES).
     fn gl_api(&self) -> GLApi;
 
+    /// Returns true if the window system binding (EGL or WGL) advertises the named extension,
+    /// either for this connection's display or as a client extension.
+    fn supports_extension(&self, name: &str) -> bool;
+
     /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
     ///
     /// This is an alias for `Connection::create_hardware_adapter()`.
@@ -52,7 +77,42 @@ pub trait Connection: Sized {
     /// Returns the "best" adapter on this system, preferring software adapters.
     fn create_software_adapter(&self) -> Result<Self::Adapter, Error>;
 
+    /// Returns the adapter whose driver or device name contains `needle`, ignoring case.
+    ///
+    /// Returns `Error::NoAdapterFound` if no adapter matches, or if the platform can't report
+    /// adapter names.
+    fn create_adapter_by_name(&self, needle: &str) -> Result<Self::Adapter, Error>;
+
+    /// Returns a key that describes the adapter, which can be saved, for example in application
+    /// settings, and passed to `Connection::find_adapter()` in a later run.
+    fn adapter_key(&self, adapter: &Self::Adapter) -> Result<AdapterKey, Error>;
+
+    /// Returns the adapter that a key from `Connection::adapter_key()` describes, even if the
+    /// system now enumerates adapters in a different order.
+    ///
+    /// Returns `Error::IncompatibleAdapter` if the key was taken with another backend, and
+    /// `Error::NoAdapterFound` if the adapter's hardware is no longer present.
+    fn find_adapter(&self, key: &AdapterKey) -> Result<Self::Adapter, Error>;
+
+    /// Returns true if the two adapters are the same adapter.
+    fn is_same_adapter(&self, a: &Self::Adapter, b: &Self::Adapter) -> bool;
+
+    /// Returns the adapter for the GPU that drives the output the window is currently on, so
+    /// that a device can be created on it and presents don't go through a copy between GPUs.
+    ///
+    /// A window that spans several outputs belongs to the one that it covers the most of.
+    /// Returns `Error::NoAdapterFound` if the GPU can't be determined, and
+    /// `Error::UnsupportedOnThisPlatform` where the window system doesn't say which GPU drives
+    /// each output.
+    fn adapter_for_widget(
+        &self,
+        native_widget: &Self::NativeWidget,
+    ) -> Result<Self::Adapter, Error>;
+
     /// Opens a device.
+    ///
+    /// A device that renders in software although `adapter` is a hardware adapter is reported to
+    /// the handler set with `acceleration::set_software_fallback_handler()`.
     fn create_device(&self, adapter: &Self::Adapter) -> Result<Self::Device, Error>;
 
     /// Wraps an existing native device type in a device.
@@ -69,7 +129,11 @@ pub trait Connection: Sized {
     #[cfg(feature = "sm-raw-window-handle-06")]
     fn from_display_handle(handle: rwh_06::DisplayHandle) -> Result<Self, Error>;
 
-    /// Creates a native widget from a raw pointer
+    /// Creates a native widget from a raw pointer.
+    ///
+    /// Where the platform can report the widget's real size, `size` is checked against it, and
+    /// `native_widget_adjusted_size()` returns the real size if they disagree. Otherwise, `size`
+    /// is trusted.
     unsafe fn create_native_widget_from_ptr(
         &self,
         raw: *mut c_void,
@@ -91,4 +155,19 @@ pub trait Connection: Sized {
         window: rwh_06::WindowHandle,
         size: Size2D<i32>,
     ) -> Result<Self::NativeWidget, Error>;
+
+    /// Returns the real size of a native widget if it differs from the size that the widget was
+    /// created with.
+    ///
+    /// This is `None` when the sizes agree, and on platforms that can't report the size of a
+    /// widget, such as Wayland. Surfaces created from the widget use the real size either way.
+    fn native_widget_adjusted_size(
+        &self,
+        native_widget: &Self::NativeWidget,
+    ) -> Option<Size2D<i32>>;
+}
+
+// Returns true if the adapter name contains `needle`, ignoring case.
+pub(crate) fn adapter_name_matches(name: &str, needle: &str) -> bool {
+    name.to_lowercase().contains(&needle.to_lowercase())
 }
@@ -7,14 +7,76 @@
 use crate::egl::types::{EGLConfig, EGLContext, EGLDisplay};
 use crate::egl::EGLint;
 use crate::gl;
-use crate::gl::types::GLuint;
-use crate::info::GLVersion;
+use crate::gl::types::{GLenum, GLint, GLuint};
+use crate::gl_utils;
+use crate::info::{GLApi, GLVersion};
 use crate::Gl;
 
+use std::collections::HashMap;
 use std::ffi::CStr;
-use std::os::raw::c_char;
+use std::mem;
+use std::ops::RangeInclusive;
+use std::os::raw::{c_char, c_void};
 use std::sync::Mutex;
 
+// From `GL_KHR_debug`, which the generated bindings don't include.
+const GL_CONTEXT_FLAG_DEBUG_BIT: i32 = 0x2;
+
+// From `GL_KHR_no_error`, which the generated bindings don't include.
+const GL_CONTEXT_FLAG_NO_ERROR_BIT: i32 = 0x8;
+
+// From `GL_KHR_context_flush_control`, which the generated bindings don't include.
+const GL_CONTEXT_RELEASE_BEHAVIOR: GLenum = 0x82fb;
+const GL_NONE: GLint = 0;
+
+// From `GL_KHR_robustness`, which the generated bindings don't include.
+const GL_GUILTY_CONTEXT_RESET: GLenum = 0x8253;
+const GL_INNOCENT_CONTEXT_RESET: GLenum = 0x8254;
+const GL_UNKNOWN_CONTEXT_RESET: GLenum = 0x8255;
+const GL_RESET_NOTIFICATION_STRATEGY: GLenum = 0x8256;
+const GL_LOSE_CONTEXT_ON_RESET: GLint = 0x8252;
+
+// Every released version of OpenGL, newest first.
+static GL_VERSIONS: [GLVersion; 19] = [
+    GLVersion { major: 4, minor: 6 },
+    GLVersion { major: 4, minor: 5 },
+    GLVersion { major: 4, minor: 4 },
+    GLVersion { major: 4, minor: 3 },
+    GLVersion { major: 4, minor: 2 },
+    GLVersion { major: 4, minor: 1 },
+    GLVersion { major: 4, minor: 0 },
+    GLVersion { major: 3, minor: 3 },
+    GLVersion { major: 3, minor: 2 },
+    GLVersion { major: 3, minor: 1 },
+    GLVersion { major: 3, minor: 0 },
+    GLVersion { major: 2, minor: 1 },
+    GLVersion { major: 2, minor: 0 },
+    GLVersion { major: 1, minor: 5 },
+    GLVersion { major: 1, minor: 4 },
+    GLVersion { major: 1, minor: 3 },
+    GLVersion { major: 1, minor: 2 },
+    GLVersion { major: 1, minor: 1 },
+    GLVersion { major: 1, minor: 0 },
+];
+
+// Every released version of OpenGL ES, newest first.
+static GL_ES_VERSIONS: [GLVersion; 6] = [
+    GLVersion { major: 3, minor: 2 },
+    GLVersion { major: 3, minor: 1 },
+    GLVersion { major: 3, minor: 0 },
+    GLVersion { major: 2, minor: 0 },
+    GLVersion { major: 1, minor: 1 },
+    GLVersion { major: 1, minor: 0 },
+];
+
+// The entry points of `glGetGraphicsResetStatus`, in order of preference.
+static GET_GRAPHICS_RESET_STATUS_NAMES: [&str; 4] = [
+    "glGetGraphicsResetStatus",
+    "glGetGraphicsResetStatusKHR",
+    "glGetGraphicsResetStatusEXT",
+    "glGetGraphicsResetStatusARB",
+];
+
 /// Methods relating to native contexts.
 pub trait NativeContext {
     /// Get the EGLContext backing this.
@@ -51,19 +113,74 @@ bitflags! {
     /// https://www.khronos.org/registry/webgl/specs/latest/1.0/#WEBGLCONTEXTATTRIBUTES
     ///
     /// There are some extra `surfman`-specific flags as well.
-    pub struct ContextAttributeFlags: u8 {
+    ///
+    /// The flags are stored in a `u16`, as there are more than eight of them. This is a breaking
+    /// change from 0.9, where they were stored in a `u8`: code that passes `bits()` on as a `u8`,
+    /// or builds flags with `from_bits()` from one, must convert to and from `u16` instead.
+    pub struct ContextAttributeFlags: u16 {
         /// Surfaces created for this context will have an alpha channel (RGBA or BGRA; i.e. 4
         /// channels, 32 bits per pixel, 8 bits per channel). If this is not present, surfaces will
         /// be RGBX or BGRX (i.e. 3 channels, 32 bits per pixel, 8 bits per channel).
         const ALPHA                 = 0x01;
-        /// Surfaces created for this context will have a 24-bit depth buffer.
+        /// Surfaces created for this context will have a 24-bit depth buffer, unless
+        /// `ContextAttributes::depth_size` asks for another size.
         const DEPTH                 = 0x02;
         /// Surfaces created for this context will have an 8-bit stencil buffer, possibly using
-        /// packed depth/stencil if the GL implementation supports it.
+        /// packed depth/stencil if the GL implementation supports it, unless
+        /// `ContextAttributes::stencil_size` asks for another size.
         const STENCIL               = 0x04;
         /// The OpenGL compatibility profile will be used. If this is not present, the core profile
         /// is used.
+        ///
+        /// Only desktop OpenGL has profiles, so requesting this flag on an OpenGL ES device fails
+        /// with `Error::UnsupportedGLProfile`. Some drivers quietly hand out a core context
+        /// anyway; the attributes of the context's descriptor report the profile actually granted.
         const COMPATIBILITY_PROFILE = 0x08;
+        /// The context will be a debug context, so that `GL_KHR_debug` reports messages. If the
+        /// platform can't create debug contexts, an ordinary context is created instead, and its
+        /// descriptor's attributes won't include this flag.
+        const DEBUG                 = 0x10;
+        /// The context will have robust buffer access, and will be lost if the GPU is reset, so
+        /// that `Device::context_reset_status()` can report the reset. If the platform can't
+        /// create robust contexts, context descriptor creation fails with
+        /// `Error::RequiredExtensionUnavailable` rather than ignoring this flag.
+        const ROBUST_ACCESS         = 0x20;
+        /// The context will skip OpenGL error checking, per `KHR_no_error`, so that invalid calls
+        /// have undefined behavior instead of generating errors. If the platform can't create
+        /// no-error contexts, an ordinary context is created instead, and its descriptor's
+        /// attributes won't include this flag. The spec forbids combining this with `DEBUG` or
+        /// `ROBUST_ACCESS`; context descriptor creation fails with
+        /// `Error::ConflictingContextAttributeFlags` if either is present.
+        const NO_ERROR              = 0x40;
+        /// Making another context current won't implicitly flush this one, per
+        /// `KHR_context_flush_control`, which saves a flush on every switch between contexts.
+        /// The caller must call `glFlush` itself before another context reads what this one
+        /// rendered.
+        ///
+        /// If the platform can't create such contexts, context descriptor creation fails with
+        /// `Error::RequiredExtensionUnavailable`, unless `BEST_EFFORT` is present too. Check the
+        /// attributes of the context's descriptor to find out whether the flag was granted, and so
+        /// whether the explicit flush is needed.
+        const RELEASE_BEHAVIOR_NONE = 0x80;
+        /// Flags that would otherwise fail context descriptor creation when the platform doesn't
+        /// support them are dropped instead. Currently this only affects `RELEASE_BEHAVIOR_NONE`.
+        ///
+        /// This flag is never reported in a descriptor's attributes.
+        const BEST_EFFORT           = 0x100;
+        /// If the driver can't create a context of the requested version, lower minor versions of
+        /// the same major version are tried in turn, down to `.0`: for example, OpenGL ES 3.2,
+        /// then 3.1, then 3.0. Without this flag, context creation fails instead.
+        ///
+        /// Check the version in the attributes of the context's descriptor, or in
+        /// `Device::context_info()`, to find out which version was granted. Like `BEST_EFFORT`,
+        /// this flag is never reported in a descriptor's attributes.
+        const ALLOW_LOWER_VERSION   = 0x200;
+        /// If the platform doesn't offer the sample count in `ContextAttributes::samples`,
+        /// context descriptor creation fails with `Error::NoPixelFormatFound`. Without this flag,
+        /// the nearest lower count that the platform offers is used instead.
+        ///
+        /// Like `BEST_EFFORT`, this flag is never reported in a descriptor's attributes.
+        const EXACT_SAMPLES         = 0x400;
     }
 }
 
@@ -79,6 +196,230 @@ pub struct ContextAttributes {
     pub version: GLVersion,
     /// Various flags.
     pub flags: ContextAttributeFlags,
+    /// How the context obtains the surface it is bound to while no surface is attached.
+    pub placeholder_policy: PlaceholderPolicy,
+    /// How many bits per color channel surfaces created for this context may have.
+    ///
+    /// `Device::context_descriptor_attributes()` reports the depth that was actually chosen,
+    /// which is never `ColorDepth::Auto`.
+    pub color_depth: ColorDepth,
+    /// How urgently the GPU should schedule this context's work relative to other contexts.
+    ///
+    /// This is only a hint, and platforms that can't prioritize contexts ignore it.
+    /// `Device::context_priority()` reports the priority that was actually granted.
+    pub priority: ContextPriority,
+    /// How many samples per pixel surfaces created for this context have, for multisample
+    /// antialiasing. 0 and 1 both mean no multisampling.
+    ///
+    /// Widget surfaces resolve the samples themselves when they are presented. Generic surfaces
+    /// are rendered to multisampled renderbuffers, which are resolved into the surface's texture
+    /// when the surface is unbound from its context, so that surface textures and locked surface
+    /// data always see the resolved image. The `framebuffer_object` of a multisampled surface is
+    /// the multisampled one, which can't be read from with `glReadPixels()` or
+    /// `glCopyTexImage2D()`.
+    ///
+    /// Counts that the platform doesn't offer fall back to the nearest lower count, unless
+    /// `ContextAttributeFlags::EXACT_SAMPLES` is set. `Device::context_descriptor_attributes()`
+    /// reports the count that was actually granted, which is 0 without multisampling.
+    pub samples: u8,
+    /// How many bits the depth buffer of surfaces created for this context has.
+    ///
+    /// `None` leaves the size to `ContextAttributeFlags::DEPTH`: 24 bits if it is set, and no
+    /// depth buffer otherwise. `Some` overrides the flag, with `Some(0)` meaning no depth buffer.
+    /// Sizes are minimums, and the smallest size the platform offers that is at least as large
+    /// is chosen. Generic surfaces allocate 16-, 24-, or 32-bit depth renderbuffers; 32-bit ones
+    /// are floating point (`GL_DEPTH_COMPONENT32F`) where the context supports that.
+    ///
+    /// `Device::context_descriptor_attributes()` always reports `Some` with the actual size.
+    pub depth_size: Option<u8>,
+    /// How many bits the stencil buffer of surfaces created for this context has.
+    ///
+    /// `None` leaves the size to `ContextAttributeFlags::STENCIL`, like `depth_size`. Generic
+    /// surfaces only allocate 8-bit stencil renderbuffers.
+    ///
+    /// `Device::context_descriptor_attributes()` always reports `Some` with the actual size.
+    pub stencil_size: Option<u8>,
+}
+
+/// The properties of a context descriptor, as plain data.
+///
+/// Context descriptors are local to a device, but these attributes can be copied to another
+/// process and passed to `Device::create_context_descriptor_from_attributes()` there, to create a
+/// descriptor for contexts with the same version and buffers.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ContextDescriptorAttributes {
+    /// The attributes that the descriptor reports, as `Device::context_descriptor_attributes()`
+    /// returns them.
+    pub attributes: ContextAttributes,
+    /// The number of bits in each of the red, green, and blue channels of the color buffer.
+    pub color_channel_size: u8,
+    /// The number of bits in the alpha channel of the color buffer.
+    pub alpha_size: u8,
+    /// The number of bits in the depth buffer.
+    pub depth_size: u8,
+    /// The number of bits in the stencil buffer.
+    pub stencil_size: u8,
+}
+
+impl ContextDescriptorAttributes {
+    // Checks that a descriptor recreated from these attributes has the same version and buffers.
+    #[allow(dead_code)]
+    pub(crate) fn check_recreated(
+        &self,
+        recreated: &ContextDescriptorAttributes,
+    ) -> Result<(), crate::Error> {
+        if recreated.attributes.version != self.attributes.version {
+            return Err(crate::Error::UnsupportedGLVersion);
+        }
+        if (
+            recreated.color_channel_size,
+            recreated.alpha_size,
+            recreated.depth_size,
+            recreated.stencil_size,
+            recreated.attributes.samples,
+        ) != (
+            self.color_channel_size,
+            self.alpha_size,
+            self.depth_size,
+            self.stencil_size,
+            self.attributes.samples,
+        ) {
+            return Err(crate::Error::NoPixelFormatFound);
+        }
+        Ok(())
+    }
+}
+
+/// Whether a context has been lost to a GPU reset, as reported by `glGetGraphicsResetStatus`.
+///
+/// Only contexts created with `ContextAttributeFlags::ROBUST_ACCESS` are notified of resets;
+/// other contexts always report `NoError`. A context that reports anything else has been lost
+/// and must be destroyed and recreated, along with all of its objects.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ResetStatus {
+    /// No reset has happened since the context was created.
+    NoError,
+    /// This context caused a reset.
+    GuiltyContextReset,
+    /// Another context caused a reset.
+    InnocentContextReset,
+    /// A reset happened, but its cause is unknown.
+    UnknownContextReset,
+}
+
+/// The number of bits per color channel of the surfaces of a context.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ColorDepth {
+    /// 8 bits per channel, unless the display server only offers deep color (such as X servers
+    /// running with `DefaultDepth 30`) and no alpha channel was requested.
+    ///
+    /// This is the default.
+    Auto,
+    /// Always 8 bits per channel.
+    Standard,
+    /// 10 bits per channel where available, falling back to 8 bits per channel otherwise.
+    ///
+    /// 10-bit formats are RGB10A2, so if `ContextAttributeFlags::ALPHA` is set the alpha channel
+    /// may be only 2 bits wide. Compositors treat 2-bit alpha inconsistently: some blend with it,
+    /// some ignore it. Check the depth reported by `Device::context_descriptor_attributes()`, and
+    /// don't rely on translucent deep-color windows.
+    Deep,
+    /// 16-bit floating-point channels where available, falling back to 8 bits per channel
+    /// otherwise.
+    ///
+    /// Float configs need `EGL_EXT_pixel_format_float`, and are only offered by EGL. Widget
+    /// surfaces with `SurfaceFormat::Rgba16F` need a context that was granted this depth.
+    Float,
+}
+
+/// The scheduling priority of a context's GPU work, relative to other contexts.
+///
+/// On EGL this is `EGL_IMG_context_priority`, with `Realtime` needing
+/// `EGL_NV_context_priority_realtime`. Drivers may grant a lower priority than requested,
+/// especially to processes without the privileges for `High` or `Realtime`.
+///
+/// Priorities are ordered from `Low` to `Realtime`.
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub enum ContextPriority {
+    /// Lower than ordinary contexts, for background work.
+    Low,
+    /// The priority of ordinary contexts.
+    ///
+    /// This is the default.
+    Medium,
+    /// Higher than ordinary contexts, such as for a compositor that must preempt its clients.
+    High,
+    /// Higher than all other contexts. Where realtime priority isn't available, `High` is
+    /// requested instead.
+    Realtime,
+}
+
+impl Default for ContextPriority {
+    #[inline]
+    fn default() -> ContextPriority {
+        ContextPriority::Medium
+    }
+}
+
+impl Default for ColorDepth {
+    #[inline]
+    fn default() -> ColorDepth {
+        ColorDepth::Auto
+    }
+}
+
+/// Controls the placeholder surface a context is made current with while it has no surface
+/// attached.
+///
+/// Some EGL implementations cannot make a context current without a surface, so `surfman`
+/// allocates a small hidden pbuffer for that purpose. Drivers often limit the number of live
+/// surfaces, which makes one pbuffer per context a problem for applications with many contexts.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PlaceholderPolicy {
+    /// Each context gets its own placeholder surface, if the backend needs one.
+    ///
+    /// This is the default. Backends that always use `EGL_KHR_surfaceless_context` (the Linux
+    /// backends) allocate nothing.
+    Owned,
+    /// All contexts of the device with the same config share one cached pbuffer.
+    ///
+    /// EGL forbids a surface from being current on two threads at once. If the shared pbuffer is
+    /// already current on another thread, a second pbuffer is added to the cache for this thread.
+    Shared,
+    /// The context is made current with no surface at all.
+    ///
+    /// This requires the `EGL_KHR_surfaceless_context` extension. Context creation fails with
+    /// `RequiredExtensionUnavailable` if it isn't present.
+    None,
+}
+
+impl Default for PlaceholderPolicy {
+    #[inline]
+    fn default() -> PlaceholderPolicy {
+        PlaceholderPolicy::Owned
+    }
+}
+
+/// The default attributes ask for version 3.0 with no flags, and leave every other attribute at
+/// its default.
+///
+/// Check `Device::gl_api()` before relying on the default version, as OpenGL 3.0 and OpenGL ES
+/// 3.0 are quite different. Filling in the attributes that matter with `..Default::default()`
+/// for the rest keeps code building as attributes are added.
+impl Default for ContextAttributes {
+    #[inline]
+    fn default() -> ContextAttributes {
+        ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::empty(),
+            placeholder_policy: PlaceholderPolicy::default(),
+            color_depth: ColorDepth::default(),
+            priority: ContextPriority::default(),
+            samples: 0,
+            depth_size: None,
+            stencil_size: None,
+        }
+    }
 }
 
 impl ContextAttributes {
@@ -87,7 +428,401 @@ impl ContextAttributes {
         ContextAttributes {
             version: GLVersion::new(0, 0),
             flags: ContextAttributeFlags::empty(),
+            placeholder_policy: PlaceholderPolicy::Owned,
+            color_depth: ColorDepth::Auto,
+            priority: ContextPriority::Medium,
+            samples: 0,
+            depth_size: None,
+            stencil_size: None,
+        }
+    }
+}
+
+// The channel and buffer sizes of a pixel format, along with the depth of its native visual if it
+// has one.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ConfigCandidate {
+    pub(crate) red_size: EGLint,
+    pub(crate) green_size: EGLint,
+    pub(crate) blue_size: EGLint,
+    pub(crate) alpha_size: EGLint,
+    pub(crate) depth_size: EGLint,
+    pub(crate) stencil_size: EGLint,
+    pub(crate) samples: EGLint,
+    pub(crate) visual_depth: Option<EGLint>,
+    // Whether the channels are floating-point (`EGL_COLOR_COMPONENT_TYPE_FLOAT_EXT`).
+    pub(crate) float: bool,
+}
+
+// Returns the index of the candidate that best satisfies the attributes, or `None` if none do.
+//
+// `window_depth` is the depth of the visual that windows are created with on backends where
+// pixel formats must match the window's visual (X11). Candidates with a matching visual win over
+// other candidates, unless deep or float color was explicitly requested. Float candidates are
+// only chosen when float color was requested. Among otherwise equal candidates, the one with the
+// most samples that doesn't exceed the requested count wins, then the one with the smallest depth
+// and stencil buffers that are at least as large as requested.
+#[allow(dead_code)]
+pub(crate) fn choose_config(
+    candidates: &[ConfigCandidate],
+    attributes: &ContextAttributes,
+    window_depth: Option<EGLint>,
+) -> Option<usize> {
+    let alpha = attributes.flags.contains(ContextAttributeFlags::ALPHA);
+    let samples = requested_samples(attributes) as EGLint;
+    let exact_samples = attributes
+        .flags
+        .contains(ContextAttributeFlags::EXACT_SAMPLES);
+    let (depth_size, stencil_size) = requested_depth_stencil_sizes(attributes);
+    let (depth_size, stencil_size) = (depth_size as EGLint, stencil_size as EGLint);
+    candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(index, candidate)| {
+            let candidate_samples = if candidate.samples > 1 {
+                candidate.samples
+            } else {
+                0
+            };
+            if candidate_samples > samples || (exact_samples && candidate_samples != samples) {
+                return None;
+            }
+            if candidate.depth_size < depth_size || candidate.stencil_size < stencil_size {
+                return None;
+            }
+
+            let channel_size = candidate.red_size;
+            if candidate.green_size != channel_size || candidate.blue_size != channel_size {
+                return None;
+            }
+            if candidate.float
+                && (attributes.color_depth != ColorDepth::Float
+                    || channel_size != 16
+                    || (alpha && candidate.alpha_size < 16))
+            {
+                return None;
+            }
+            let is_deep = match channel_size {
+                16 if candidate.float => true,
+                8 if !alpha || candidate.alpha_size >= 8 => false,
+                10 if !alpha => true,
+                10 if candidate.alpha_size >= 2 && attributes.color_depth == ColorDepth::Deep => {
+                    true
+                }
+                _ => return None,
+            };
+
+            let visual_mismatch = match (window_depth, candidate.visual_depth) {
+                (None, _) => false,
+                (Some(window_depth), Some(visual_depth)) => {
+                    visual_depth != window_depth && !(alpha && visual_depth == 32)
+                }
+                (Some(_), None) => true,
+            };
+            let key = match attributes.color_depth {
+                ColorDepth::Standard if is_deep => return None,
+                ColorDepth::Auto | ColorDepth::Standard => (visual_mismatch, is_deep, false),
+                ColorDepth::Deep => (!is_deep, visual_mismatch, false),
+                ColorDepth::Float => (!candidate.float, visual_mismatch, is_deep),
+            };
+            let excess = (
+                samples - candidate_samples,
+                candidate.depth_size - depth_size,
+                candidate.stencil_size - stencil_size,
+            );
+            Some((key, excess, index))
+        })
+        .min()
+        .map(|(_, _, index)| index)
+}
+
+// Returns the index of the candidate that a context with `context_config` can render a widget
+// through, or `None` if none can.
+//
+// Candidates must have the same color, depth, stencil and sample sizes as the context's config,
+// and the requested alpha size if one is given. `window_depth` is the depth of the window's
+// visual on backends where pixel formats must match it (X11). Among the remaining candidates, one
+// with the context's alpha size wins, and then the first, since EGL sorts its best configs first.
+#[allow(dead_code)]
+pub(crate) fn choose_widget_config(
+    context_config: &ConfigCandidate,
+    candidates: &[ConfigCandidate],
+    alpha_size: Option<EGLint>,
+    window_depth: Option<EGLint>,
+) -> Option<usize> {
+    candidates
+        .iter()
+        .enumerate()
+        .filter(|(_, candidate)| {
+            candidate.red_size == context_config.red_size
+                && candidate.green_size == context_config.green_size
+                && candidate.blue_size == context_config.blue_size
+                && candidate.depth_size == context_config.depth_size
+                && candidate.stencil_size == context_config.stencil_size
+                && candidate.samples.max(1) == context_config.samples.max(1)
+                && candidate.float == context_config.float
+                && match alpha_size {
+                    Some(alpha_size) => candidate.alpha_size == alpha_size,
+                    None => true,
+                }
+                && match window_depth {
+                    Some(depth) => candidate.visual_depth == Some(depth),
+                    None => true,
+                }
+        })
+        .min_by_key(|(index, candidate)| {
+            (candidate.alpha_size != context_config.alpha_size, *index)
+        })
+        .map(|(index, _)| index)
+}
+
+// Returns the number of depth and stencil bits that the attributes ask for, falling back to the
+// flags where no size is given.
+#[allow(dead_code)]
+pub(crate) fn requested_depth_stencil_sizes(attributes: &ContextAttributes) -> (u8, u8) {
+    let flags = attributes.flags;
+    let depth_size = match attributes.depth_size {
+        Some(depth_size) => depth_size,
+        None if flags.contains(ContextAttributeFlags::DEPTH) => 24,
+        None => 0,
+    };
+    let stencil_size = match attributes.stencil_size {
+        Some(stencil_size) => stencil_size,
+        None if flags.contains(ContextAttributeFlags::STENCIL) => 8,
+        None => 0,
+    };
+    (depth_size, stencil_size)
+}
+
+// Returns the sample count that the attributes ask for, with 1 normalized to 0, since both mean no
+// multisampling.
+#[allow(dead_code)]
+pub(crate) fn requested_samples(attributes: &ContextAttributes) -> u8 {
+    if attributes.samples > 1 {
+        attributes.samples
+    } else {
+        0
+    }
+}
+
+// Returns the sample counts to ask the platform for in turn, most preferred first: the requested
+// count, then, unless `EXACT_SAMPLES` is set, each lower count down to 0.
+#[allow(dead_code)]
+pub(crate) fn sample_counts_to_try(attributes: &ContextAttributes) -> Vec<u8> {
+    let samples = requested_samples(attributes);
+    if samples == 0 {
+        vec![0]
+    } else if attributes
+        .flags
+        .contains(ContextAttributeFlags::EXACT_SAMPLES)
+    {
+        vec![samples]
+    } else {
+        (2..=samples).rev().chain(Some(0)).collect()
+    }
+}
+
+// Returns true if the current context was created as a debug context.
+#[allow(dead_code)]
+pub(crate) fn current_context_is_debug(gl: &Gl) -> bool {
+    gl_utils::drain_pre_existing_gl_errors(gl);
+    unsafe {
+        // `GL_CONTEXT_FLAGS` needs OpenGL 3.0, OpenGL ES 3.2, or `GL_KHR_debug`.
+        let mut context_flags = 0;
+        gl.GetIntegerv(gl::CONTEXT_FLAGS, &mut context_flags);
+        gl.GetError() == gl::NO_ERROR && (context_flags & GL_CONTEXT_FLAG_DEBUG_BIT) != 0
+    }
+}
+
+// Returns true if the current context was created without error checking.
+#[allow(dead_code)]
+pub(crate) fn current_context_is_no_error(gl: &Gl) -> bool {
+    gl_utils::drain_pre_existing_gl_errors(gl);
+    unsafe {
+        let mut context_flags = 0;
+        gl.GetIntegerv(gl::CONTEXT_FLAGS, &mut context_flags);
+        gl.GetError() == gl::NO_ERROR && (context_flags & GL_CONTEXT_FLAG_NO_ERROR_BIT) != 0
+    }
+}
+
+// Returns true if making another context current won't flush the current context.
+#[allow(dead_code)]
+pub(crate) fn current_context_release_behavior_is_none(gl: &Gl) -> bool {
+    gl_utils::drain_pre_existing_gl_errors(gl);
+    unsafe {
+        let mut release_behavior = 0;
+        gl.GetIntegerv(GL_CONTEXT_RELEASE_BEHAVIOR, &mut release_behavior);
+        gl.GetError() == gl::NO_ERROR && release_behavior == GL_NONE
+    }
+}
+
+// Decides whether a context with the given flags is created with a release behavior of none,
+// given whether the platform supports `KHR_context_flush_control`. Unsupported, the flag is only
+// dropped in best-effort mode, so that callers don't silently pay for the flushes they asked to
+// avoid.
+#[allow(dead_code)]
+pub(crate) fn release_behavior_none(
+    flags: ContextAttributeFlags,
+    supported: bool,
+) -> Result<bool, crate::Error> {
+    if !flags.contains(ContextAttributeFlags::RELEASE_BEHAVIOR_NONE) {
+        return Ok(false);
+    }
+    if !supported && !flags.contains(ContextAttributeFlags::BEST_EFFORT) {
+        return Err(crate::Error::RequiredExtensionUnavailable);
+    }
+    Ok(supported)
+}
+
+// Returns the versions to try creating a context with, in order: the requested version, followed
+// by each lower minor version of the same major version if lower versions are allowed.
+#[allow(dead_code)]
+pub(crate) fn versions_to_try(version: GLVersion, allow_lower_version: bool) -> Vec<GLVersion> {
+    if !allow_lower_version {
+        return vec![version];
+    }
+    (0..=version.minor)
+        .rev()
+        .map(|minor| GLVersion::new(version.major, minor))
+        .collect()
+}
+
+// The versions that `Device::create_versioned_context()` has settled on for a device, by the
+// requested range and flags, so that each device only searches for the best version once.
+#[derive(Clone, Default)]
+pub(crate) struct NegotiatedVersions {
+    versions: HashMap<(GLVersion, GLVersion, ContextAttributeFlags), GLVersion>,
+}
+
+impl NegotiatedVersions {
+    // Creates a context of the newest version in the range that the device supports, with
+    // `create`, which creates a descriptor with the given attributes and a context from it.
+    //
+    // The candidates are every released version of the API in the range, along with the bounds
+    // of the range themselves so that versions newer than surfman knows of can be asked for.
+    // Failures that the version may be to blame for move on to the next older candidate; other
+    // errors are returned immediately. Once a version succeeds, it's used for the same range and
+    // flags from then on.
+    pub(crate) fn create_context<C, D, F>(
+        &mut self,
+        gl_api: GLApi,
+        versions: RangeInclusive<GLVersion>,
+        flags: ContextAttributeFlags,
+        mut create: F,
+    ) -> Result<(C, D), crate::Error>
+    where
+        F: FnMut(&ContextAttributes) -> Result<(C, D), crate::Error>,
+    {
+        let key = (*versions.start(), *versions.end(), flags);
+        let attributes = |version| ContextAttributes {
+            version,
+            flags,
+            ..ContextAttributes::zeroed()
+        };
+        if let Some(&version) = self.versions.get(&key) {
+            return create(&attributes(version));
         }
+
+        let known_versions: &[GLVersion] = match gl_api {
+            GLApi::GL => &GL_VERSIONS,
+            GLApi::GLES => &GL_ES_VERSIONS,
+        };
+        let mut candidates: Vec<GLVersion> = known_versions
+            .iter()
+            .chain([*versions.start(), *versions.end()].iter())
+            .cloned()
+            .filter(|version| versions.contains(version))
+            .collect();
+        candidates.sort_by(|a, b| b.cmp(a));
+        candidates.dedup();
+
+        for version in candidates {
+            match create(&attributes(version)) {
+                Ok(result) => {
+                    self.versions.insert(key, version);
+                    return Ok(result);
+                }
+                Err(crate::Error::UnsupportedGLVersion)
+                | Err(crate::Error::NoPixelFormatFound)
+                | Err(crate::Error::ContextCreationFailed(_)) => {
+                    debug!(
+                        "Version {}.{} is unsupported, trying an older one",
+                        version.major, version.minor
+                    );
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Err(crate::Error::UnsupportedGLVersion)
+    }
+}
+
+// Checks for combinations of context attribute flags that no platform can create.
+#[allow(dead_code)]
+pub(crate) fn check_attribute_flags(flags: ContextAttributeFlags) -> Result<(), crate::Error> {
+    if flags.contains(ContextAttributeFlags::NO_ERROR)
+        && flags.intersects(ContextAttributeFlags::DEBUG | ContextAttributeFlags::ROBUST_ACCESS)
+    {
+        return Err(crate::Error::ConflictingContextAttributeFlags);
+    }
+    Ok(())
+}
+
+// Checks that the requested profile exists in the given API. OpenGL ES has no compatibility
+// profile.
+#[allow(dead_code)]
+pub(crate) fn check_profile(
+    gl_api: GLApi,
+    attributes: &ContextAttributes,
+) -> Result<(), crate::Error> {
+    if gl_api == GLApi::GLES
+        && attributes
+            .flags
+            .contains(ContextAttributeFlags::COMPATIBILITY_PROFILE)
+    {
+        return Err(crate::Error::UnsupportedGLProfile);
+    }
+    Ok(())
+}
+
+// Returns true if the current context was created with robust access and loses itself on reset.
+#[allow(dead_code)]
+pub(crate) fn current_context_is_robust(gl: &Gl) -> bool {
+    gl_utils::drain_pre_existing_gl_errors(gl);
+    unsafe {
+        // `GL_RESET_NOTIFICATION_STRATEGY` needs OpenGL 4.5, OpenGL ES 3.2, or an extension that
+        // provides robustness.
+        let mut strategy = 0;
+        gl.GetIntegerv(GL_RESET_NOTIFICATION_STRATEGY, &mut strategy);
+        gl.GetError() == gl::NO_ERROR && strategy == GL_LOSE_CONTEXT_ON_RESET
+    }
+}
+
+// Queries `glGetGraphicsResetStatus` on the current context. `get_proc_address` looks up GL
+// functions for that context.
+pub(crate) fn current_reset_status<F>(get_proc_address: F) -> ResetStatus
+where
+    F: Fn(&str) -> Option<*const c_void>,
+{
+    let function = GET_GRAPHICS_RESET_STATUS_NAMES
+        .iter()
+        .find_map(|name| get_proc_address(name));
+
+    // Without the function, the context can't have been created robust, so it isn't notified of
+    // resets.
+    let function = match function {
+        None => return ResetStatus::NoError,
+        Some(function) => function,
+    };
+    let status = unsafe {
+        let get_graphics_reset_status: extern "system" fn() -> GLenum = mem::transmute(function);
+        get_graphics_reset_status()
+    };
+    match status {
+        GL_GUILTY_CONTEXT_RESET => ResetStatus::GuiltyContextReset,
+        GL_INNOCENT_CONTEXT_RESET => ResetStatus::InnocentContextReset,
+        GL_UNKNOWN_CONTEXT_RESET => ResetStatus::UnknownContextReset,
+        _ => ResetStatus::NoError,
     }
 }
 
@@ -99,6 +834,7 @@ pub(crate) fn current_context_uses_compatibility_profile(_gl: &Gl) -> bool {
 #[cfg(not(any(target_os = "android", target_env = "ohos")))]
 #[allow(dead_code)]
 pub(crate) fn current_context_uses_compatibility_profile(gl: &Gl) -> bool {
+    gl_utils::drain_pre_existing_gl_errors(gl);
     unsafe {
         // First, try `GL_CONTEXT_PROFILE_MASK`.
         let mut context_profile_mask = 0;
@@ -0,0 +1,544 @@
+// surfman/surfman/src/convert.rs
+//
+//! Converting the contents of one surface into another on the GPU.
+//!
+//! `Device::convert_surface` copies a generic surface into another generic surface of the same
+//! size, converting between their formats and colorspaces and optionally exchanging the red and
+//! blue channels, for consumers that want BGRA. Copies that need neither a swizzle nor a
+//! colorspace conversion are done with `glBlitFramebuffer()`. The rest copy the source into a
+//! temporary texture, resolving its samples, and draw that into the destination with a small
+//! shader program. The program is compiled the first time each context needs it, and kept until
+//! the context is destroyed.
+//!
+//! The colorspaces of the surfaces are honored: an sRGB source is decoded when it is sampled, and
+//! an sRGB destination is encoded when it is written, so that the destination shows the same
+//! colors at whatever bit depth it has.
+
+use crate::device::Device as DeviceAPI;
+use crate::gl;
+use crate::gl::types::{GLboolean, GLchar, GLenum, GLint, GLsizeiptr, GLuint};
+use crate::gl_utils;
+use crate::memory;
+use crate::{ColorEncoding, ContextID, Error, GLApi, GLCapabilities, Gl, SurfaceInfo};
+use euclid::default::Size2D;
+use log::warn;
+use std::collections::HashMap;
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// `GL_FRAMEBUFFER_SRGB`, which the OpenGL ES bindings lack.
+const GL_FRAMEBUFFER_SRGB: GLenum = 0x8db9;
+
+// The capabilities that would change the result of a blit or of drawing the converted source,
+// which are disabled while converting.
+const STATE_CAPABILITIES: [GLenum; 8] = [
+    gl::BLEND,
+    gl::CULL_FACE,
+    gl::DEPTH_TEST,
+    gl::DITHER,
+    gl::SAMPLE_ALPHA_TO_COVERAGE,
+    gl::SAMPLE_COVERAGE,
+    gl::SCISSOR_TEST,
+    gl::STENCIL_TEST,
+];
+
+static VERTEX_SHADER_SOURCE: &str = "
+in vec2 aPosition;
+out vec2 vTexCoord;
+void main() {
+    vTexCoord = aPosition * 0.5 + 0.5;
+    gl_Position = vec4(aPosition, 0.0, 1.0);
+}
+";
+
+static FRAGMENT_SHADER_SOURCE: &str = "
+precision highp float;
+uniform highp sampler2D uSource;
+uniform bool uSwapRedBlue;
+in vec2 vTexCoord;
+out vec4 oColor;
+void main() {
+    vec4 color = texture(uSource, vTexCoord);
+    oColor = uSwapRedBlue ? color.bgra : color;
+}
+";
+
+// A triangle strip that covers the viewport.
+static QUAD_VERTICES: [f32; 8] = [-1.0, -1.0, 1.0, -1.0, -1.0, 1.0, 1.0, 1.0];
+
+lazy_static! {
+    static ref PROGRAMS: Mutex<HashMap<ContextID, ConversionProgram>> = Mutex::new(HashMap::new());
+}
+
+static BLITS: AtomicU64 = AtomicU64::new(0);
+static SHADER_PASSES: AtomicU64 = AtomicU64::new(0);
+static PROGRAMS_COMPILED: AtomicU64 = AtomicU64::new(0);
+
+/// Which channel of the source each channel of a converted surface is taken from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Swizzle {
+    /// Each channel is taken from the channel of the same name.
+    ///
+    /// This is the default.
+    Identity,
+    /// The red and blue channels are exchanged, which converts RGBA to BGRA and back.
+    SwapRedBlue,
+}
+
+impl Default for Swizzle {
+    #[inline]
+    fn default() -> Swizzle {
+        Swizzle::Identity
+    }
+}
+
+/// Counters describing the surface conversions done in this process.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ConversionStats {
+    /// The number of conversions done with `glBlitFramebuffer()`.
+    pub blits: u64,
+    /// The number of conversions done by drawing with the conversion shader.
+    pub shader_passes: u64,
+    /// The number of times the conversion shader has been compiled, which happens once for each
+    /// context that needs it.
+    pub programs_compiled: u64,
+}
+
+/// Returns the counters describing the surface conversions done so far in this process.
+pub fn stats() -> ConversionStats {
+    ConversionStats {
+        blits: BLITS.load(Ordering::Relaxed),
+        shader_passes: SHADER_PASSES.load(Ordering::Relaxed),
+        programs_compiled: PROGRAMS_COMPILED.load(Ordering::Relaxed),
+    }
+}
+
+// The conversion shader of a context, and the vertex array of the quad that it draws, whose
+// buffer is freed along with it.
+#[derive(Clone, Copy)]
+struct ConversionProgram {
+    program: GLuint,
+    vertex_array: GLuint,
+    swap_red_blue_location: GLint,
+}
+
+// Implements `Device::convert_surface` for every backend.
+pub(crate) fn convert_surface<Device: DeviceAPI>(
+    device: &Device,
+    context: &Device::Context,
+    source: &Device::Surface,
+    destination: &mut Device::Surface,
+    swizzle: Swizzle,
+) -> Result<(), Error> {
+    let context_id = device.context_id(context);
+    let source_info = device.surface_info(source);
+    let destination_info = device.surface_info(destination);
+    if source_info.context_id != context_id || destination_info.context_id != context_id {
+        return Err(Error::IncompatibleSurface);
+    }
+    if source_info.framebuffer_object == 0 || destination_info.framebuffer_object == 0 {
+        return Err(Error::WidgetAttached);
+    }
+    if source_info.size != destination_info.size {
+        return Err(Error::SurfaceSizeMismatch {
+            expected: destination_info.size,
+            actual: source_info.size,
+        });
+    }
+    if !device.is_context_current(context) {
+        return Err(Error::NoCurrentContext);
+    }
+    let capabilities = device.capabilities(context)?;
+    if !capabilities.blit_framebuffer {
+        return Err(Error::RequiredExtensionUnavailable);
+    }
+    let samples = device
+        .context_descriptor_attributes(&device.context_descriptor(context))
+        .samples;
+
+    let gl = Gl::load_with(device.proc_address_loader(context));
+    gl_utils::drain_pre_existing_gl_errors(&gl);
+    unsafe {
+        let saved_state = SavedState::save(&gl, &capabilities);
+        let result = convert(
+            &gl,
+            &capabilities,
+            context_id,
+            &source_info,
+            &destination_info,
+            samples,
+            swizzle,
+        );
+        saved_state.restore(&gl);
+        result
+    }
+}
+
+// Forgets the conversion shader of a destroyed context, whose ID may be reused. Its objects were
+// freed with the context, or will be with the last context that shares them.
+pub(crate) fn forget_context(context_id: ContextID) {
+    PROGRAMS.lock().unwrap().remove(&context_id);
+}
+
+// Converts `source` into `destination` with the state that converting needs already set, and
+// counts the conversion if it succeeds.
+unsafe fn convert(
+    gl: &Gl,
+    capabilities: &GLCapabilities,
+    context_id: ContextID,
+    source: &SurfaceInfo,
+    destination: &SurfaceInfo,
+    samples: u8,
+    swizzle: Swizzle,
+) -> Result<(), Error> {
+    gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, destination.framebuffer_object);
+    if gl.CheckFramebufferStatus(gl::DRAW_FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+        return Err(Error::UnsupportedSurfaceFormat);
+    }
+
+    // Multisampled framebuffers can only be blitted to framebuffers of the same format.
+    let blittable = swizzle == Swizzle::Identity
+        && source.colorspace == destination.colorspace
+        && (samples <= 1 || source.format == destination.format);
+    let counter = if blittable {
+        gl.BindFramebuffer(gl::READ_FRAMEBUFFER, source.framebuffer_object);
+        blit(gl, source.size);
+        &BLITS
+    } else {
+        draw_converted(gl, capabilities, context_id, source, destination, swizzle)?;
+        &SHADER_PASSES
+    };
+
+    if gl.GetError() != gl::NO_ERROR {
+        return Err(Error::Failed);
+    }
+    counter.fetch_add(1, Ordering::Relaxed);
+    Ok(())
+}
+
+// Copies the source into a texture, and draws that into the destination with the conversion
+// shader.
+unsafe fn draw_converted(
+    gl: &Gl,
+    capabilities: &GLCapabilities,
+    context_id: ContextID,
+    source: &SurfaceInfo,
+    destination: &SurfaceInfo,
+    swizzle: Swizzle,
+) -> Result<(), Error> {
+    // The shader needs GLSL 1.30 or GLSL ES 3.00, and vertex array objects.
+    if capabilities.version.major < 3 {
+        return Err(Error::RequiredExtensionUnavailable);
+    }
+    let program = program_for_context(gl, capabilities, context_id)?;
+
+    // Copy the source into a texture of its own format, which resolves its samples if it's
+    // multisampled. sRGB values are copied as they are, to be decoded when they're sampled.
+    let size = source.size;
+    let (internal_format, format, ty) = memory::texture_format_and_type(source.format);
+    let mut texture = 0;
+    gl.GenTextures(1, &mut texture);
+    gl.BindTexture(gl::TEXTURE_2D, texture);
+    gl.TexImage2D(
+        gl::TEXTURE_2D,
+        0,
+        internal_format as GLint,
+        size.width,
+        size.height,
+        0,
+        format,
+        ty,
+        ptr::null(),
+    );
+    for &(parameter, value) in &[
+        (gl::TEXTURE_MIN_FILTER, gl::NEAREST),
+        (gl::TEXTURE_MAG_FILTER, gl::NEAREST),
+        (gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE),
+        (gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE),
+    ] {
+        gl.TexParameteri(gl::TEXTURE_2D, parameter, value as GLint);
+    }
+    let mut framebuffer = 0;
+    gl.GenFramebuffers(1, &mut framebuffer);
+    gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, framebuffer);
+    gl.FramebufferTexture2D(
+        gl::DRAW_FRAMEBUFFER,
+        gl::COLOR_ATTACHMENT0,
+        gl::TEXTURE_2D,
+        texture,
+        0,
+    );
+    gl.BindFramebuffer(gl::READ_FRAMEBUFFER, source.framebuffer_object);
+    blit(gl, size);
+
+    // Desktop OpenGL only encodes sRGB writes while `GL_FRAMEBUFFER_SRGB` is enabled.
+    gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, destination.framebuffer_object);
+    if capabilities.gl_api == GLApi::GL && destination.colorspace == ColorEncoding::Srgb {
+        gl.Enable(GL_FRAMEBUFFER_SRGB);
+    }
+    gl.Viewport(0, 0, size.width, size.height);
+    gl.UseProgram(program.program);
+    gl.Uniform1i(
+        program.swap_red_blue_location,
+        (swizzle == Swizzle::SwapRedBlue) as GLint,
+    );
+    gl.BindVertexArray(program.vertex_array);
+    gl.DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
+
+    gl.DeleteFramebuffers(1, &framebuffer);
+    gl.DeleteTextures(1, &texture);
+    Ok(())
+}
+
+// Copies the whole of the read framebuffer into the draw framebuffer.
+unsafe fn blit(gl: &Gl, size: Size2D<i32>) {
+    gl.BlitFramebuffer(
+        0,
+        0,
+        size.width,
+        size.height,
+        0,
+        0,
+        size.width,
+        size.height,
+        gl::COLOR_BUFFER_BIT,
+        gl::NEAREST,
+    );
+}
+
+// Returns the conversion shader of the current context, compiling it if this is the first time
+// the context needs it.
+unsafe fn program_for_context(
+    gl: &Gl,
+    capabilities: &GLCapabilities,
+    context_id: ContextID,
+) -> Result<ConversionProgram, Error> {
+    let mut programs = PROGRAMS.lock().unwrap();
+    if let Some(program) = programs.get(&context_id) {
+        return Ok(*program);
+    }
+    let program = ConversionProgram::new(gl, capabilities)?;
+    PROGRAMS_COMPILED.fetch_add(1, Ordering::Relaxed);
+    programs.insert(context_id, program);
+    Ok(program)
+}
+
+impl ConversionProgram {
+    // Compiles the shader and uploads the quad. The bindings that this changes are restored by
+    // the caller.
+    unsafe fn new(gl: &Gl, capabilities: &GLCapabilities) -> Result<ConversionProgram, Error> {
+        let version = match capabilities.gl_api {
+            GLApi::GLES => "#version 300 es\n",
+            GLApi::GL if capabilities.version.major > 3 || capabilities.version.minor >= 2 => {
+                "#version 150\n"
+            }
+            GLApi::GL => "#version 130\n",
+        };
+        let vertex_shader = compile_shader(gl, gl::VERTEX_SHADER, version, VERTEX_SHADER_SOURCE)?;
+        let fragment_shader =
+            match compile_shader(gl, gl::FRAGMENT_SHADER, version, FRAGMENT_SHADER_SOURCE) {
+                Ok(fragment_shader) => fragment_shader,
+                Err(err) => {
+                    gl.DeleteShader(vertex_shader);
+                    return Err(err);
+                }
+            };
+
+        let program = gl.CreateProgram();
+        gl.AttachShader(program, vertex_shader);
+        gl.AttachShader(program, fragment_shader);
+        gl.BindAttribLocation(program, 0, b"aPosition\0".as_ptr() as *const GLchar);
+        gl.LinkProgram(program);
+        // The shaders are freed along with the program.
+        gl.DeleteShader(vertex_shader);
+        gl.DeleteShader(fragment_shader);
+        let mut link_status = 0;
+        gl.GetProgramiv(program, gl::LINK_STATUS, &mut link_status);
+        if link_status == gl::FALSE as GLint {
+            warn!("Failed to link the surface conversion shader");
+            gl.DeleteProgram(program);
+            return Err(Error::Failed);
+        }
+
+        gl.UseProgram(program);
+        let source_location =
+            gl.GetUniformLocation(program, b"uSource\0".as_ptr() as *const GLchar);
+        gl.Uniform1i(source_location, 0);
+        let swap_red_blue_location =
+            gl.GetUniformLocation(program, b"uSwapRedBlue\0".as_ptr() as *const GLchar);
+
+        let (mut vertex_array, mut vertex_buffer) = (0, 0);
+        gl.GenVertexArrays(1, &mut vertex_array);
+        gl.BindVertexArray(vertex_array);
+        gl.GenBuffers(1, &mut vertex_buffer);
+        gl.BindBuffer(gl::ARRAY_BUFFER, vertex_buffer);
+        gl.BufferData(
+            gl::ARRAY_BUFFER,
+            mem::size_of_val(&QUAD_VERTICES) as GLsizeiptr,
+            QUAD_VERTICES.as_ptr() as *const c_void,
+            gl::STATIC_DRAW,
+        );
+        gl.VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, 0, ptr::null());
+        gl.EnableVertexAttribArray(0);
+
+        Ok(ConversionProgram {
+            program,
+            vertex_array,
+            swap_red_blue_location,
+        })
+    }
+}
+
+// Compiles a shader whose source is `version` followed by `source`.
+unsafe fn compile_shader(
+    gl: &Gl,
+    kind: GLenum,
+    version: &str,
+    source: &str,
+) -> Result<GLuint, Error> {
+    let shader = gl.CreateShader(kind);
+    let sources = [
+        version.as_ptr() as *const GLchar,
+        source.as_ptr() as *const GLchar,
+    ];
+    let lengths = [version.len() as GLint, source.len() as GLint];
+    gl.ShaderSource(shader, 2, sources.as_ptr(), lengths.as_ptr());
+    gl.CompileShader(shader);
+    let mut compile_status = 0;
+    gl.GetShaderiv(shader, gl::COMPILE_STATUS, &mut compile_status);
+    if compile_status == gl::FALSE as GLint {
+        let mut log = vec![0u8; 1024];
+        let mut log_length = 0;
+        gl.GetShaderInfoLog(
+            shader,
+            log.len() as GLint,
+            &mut log_length,
+            log.as_mut_ptr() as *mut GLchar,
+        );
+        log.truncate(log_length as usize);
+        warn!(
+            "Failed to compile the surface conversion shader: {}",
+            String::from_utf8_lossy(&log)
+        );
+        gl.DeleteShader(shader);
+        return Err(Error::Failed);
+    }
+    Ok(shader)
+}
+
+// The OpenGL state that converting changes, to be restored afterward.
+struct SavedState {
+    draw_framebuffer: GLint,
+    read_framebuffer: GLint,
+    viewport: [GLint; 4],
+    color_mask: [GLboolean; 4],
+    enabled: Vec<(GLenum, bool)>,
+    // The state that only the shader pass touches, which needs OpenGL 3.0 or OpenGL ES 3.0.
+    shader_state: Option<ShaderState>,
+}
+
+struct ShaderState {
+    program: GLint,
+    vertex_array: GLint,
+    array_buffer: GLint,
+    pixel_unpack_buffer: GLint,
+    active_texture: GLint,
+    texture: GLint,
+}
+
+impl SavedState {
+    // Saves the state, then disables everything that would change the result of converting and
+    // unbinds the pixel unpack buffer, which would otherwise be read to fill textures.
+    unsafe fn save(gl: &Gl, capabilities: &GLCapabilities) -> SavedState {
+        let mut enabled_capabilities = STATE_CAPABILITIES.to_vec();
+        if capabilities.version.major >= 3 {
+            enabled_capabilities.push(gl::RASTERIZER_DISCARD);
+            if capabilities.gl_api == GLApi::GL {
+                enabled_capabilities.push(GL_FRAMEBUFFER_SRGB);
+            }
+        }
+        let enabled = enabled_capabilities
+            .into_iter()
+            .map(|capability| {
+                let enabled = gl.IsEnabled(capability) != gl::FALSE;
+                gl.Disable(capability);
+                (capability, enabled)
+            })
+            .collect();
+
+        let mut saved_state = SavedState {
+            draw_framebuffer: 0,
+            read_framebuffer: 0,
+            viewport: [0; 4],
+            color_mask: [gl::TRUE; 4],
+            enabled,
+            shader_state: None,
+        };
+        gl.GetIntegerv(
+            gl::DRAW_FRAMEBUFFER_BINDING,
+            &mut saved_state.draw_framebuffer,
+        );
+        gl.GetIntegerv(
+            gl::READ_FRAMEBUFFER_BINDING,
+            &mut saved_state.read_framebuffer,
+        );
+        gl.GetIntegerv(gl::VIEWPORT, saved_state.viewport.as_mut_ptr());
+        gl.GetBooleanv(gl::COLOR_WRITEMASK, saved_state.color_mask.as_mut_ptr());
+        gl.ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+
+        if capabilities.version.major >= 3 {
+            let mut shader_state = ShaderState {
+                program: 0,
+                vertex_array: 0,
+                array_buffer: 0,
+                pixel_unpack_buffer: 0,
+                active_texture: 0,
+                texture: 0,
+            };
+            gl.GetIntegerv(gl::CURRENT_PROGRAM, &mut shader_state.program);
+            gl.GetIntegerv(gl::VERTEX_ARRAY_BINDING, &mut shader_state.vertex_array);
+            gl.GetIntegerv(gl::ARRAY_BUFFER_BINDING, &mut shader_state.array_buffer);
+            gl.GetIntegerv(
+                gl::PIXEL_UNPACK_BUFFER_BINDING,
+                &mut shader_state.pixel_unpack_buffer,
+            );
+            gl.GetIntegerv(gl::ACTIVE_TEXTURE, &mut shader_state.active_texture);
+            gl.ActiveTexture(gl::TEXTURE0);
+            gl.GetIntegerv(gl::TEXTURE_BINDING_2D, &mut shader_state.texture);
+            gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, 0);
+            saved_state.shader_state = Some(shader_state);
+        }
+        saved_state
+    }
+
+    unsafe fn restore(self, gl: &Gl) {
+        if let Some(shader_state) = self.shader_state {
+            gl.BindTexture(gl::TEXTURE_2D, shader_state.texture as GLuint);
+            gl.ActiveTexture(shader_state.active_texture as GLenum);
+            gl.BindBuffer(
+                gl::PIXEL_UNPACK_BUFFER,
+                shader_state.pixel_unpack_buffer as GLuint,
+            );
+            gl.BindBuffer(gl::ARRAY_BUFFER, shader_state.array_buffer as GLuint);
+            gl.BindVertexArray(shader_state.vertex_array as GLuint);
+            gl.UseProgram(shader_state.program as GLuint);
+        }
+
+        let [red, green, blue, alpha] = self.color_mask;
+        gl.ColorMask(red, green, blue, alpha);
+        let [x, y, width, height] = self.viewport;
+        gl.Viewport(x, y, width, height);
+        gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.draw_framebuffer as GLuint);
+        gl.BindFramebuffer(gl::READ_FRAMEBUFFER, self.read_framebuffer as GLuint);
+        for (capability, enabled) in self.enabled {
+            if enabled {
+                gl.Enable(capability);
+            } else {
+                gl.Disable(capability);
+            }
+        }
+    }
+}
@@ -0,0 +1,379 @@
+// surfman/surfman/src/cpu_layer.rs
+//
+//! Layers that are rasterized on the CPU and uploaded to a surface, one damaged region at a time.
+//!
+//! A `CpuLayer` keeps two copies of its pixels on the CPU. The caller draws into the back buffer
+//! with `CpuLayer::pixels_mut`, and reports the rectangles that it changed with
+//! `CpuLayer::add_damage`. `CpuLayer::commit` then uploads only those rectangles to the layer's
+//! surface and swaps the buffers, so the front buffer always matches what the GPU has.
+//!
+//! Uploads go through a small ring of pixel buffer objects, each guarded by a fence. The CPU only
+//! waits for the GPU when it comes back around to a pixel buffer that is still being read from.
+//! Once the layer has been created, committing frames doesn't allocate.
+
+use crate::device::Device as DeviceAPI;
+use crate::gl;
+use crate::gl::types::{GLenum, GLsync, GLuint};
+use crate::{Error, Gl, SurfaceAccess, SurfaceType};
+use euclid::default::{Point2D, Rect, Size2D};
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+use std::slice;
+
+/// The most damage rectangles that a frame keeps track of separately. Once a frame has this many,
+/// further damage is merged with them into their bounding rectangle.
+pub const MAX_DAMAGE_RECTS: usize = 16;
+
+// The number of pixel buffer objects that uploads cycle through.
+const PIXEL_BUFFER_COUNT: usize = 3;
+
+// How long to wait for a fence before checking again, in nanoseconds.
+const FENCE_TIMEOUT: u64 = 1_000_000_000;
+
+/// Pixels rasterized on the CPU, uploaded to a surface as they are damaged.
+///
+/// Pixels are tightly-packed RGBA8, in rows from the bottom of the layer to the top, and damage
+/// rectangles use the same coordinates, with the origin at the bottom left.
+///
+/// The layer must be destroyed with `destroy` or `into_surface` before its context is.
+pub struct CpuLayer<Device: DeviceAPI> {
+    gl: Gl,
+    size: Size2D<i32>,
+    surface_texture: Device::SurfaceTexture,
+    texture_object: GLuint,
+    texture_target: GLenum,
+    buffers: [Vec<u8>; 2],
+    back_buffer: usize,
+    damage: Vec<Rect<i32>>,
+    frame_damage: Vec<Rect<i32>>,
+    pixel_buffers: [PixelBuffer; PIXEL_BUFFER_COUNT],
+    next_pixel_buffer: usize,
+}
+
+// A pixel buffer object, and the fence that signals when the GPU has finished reading it.
+struct PixelBuffer {
+    object: GLuint,
+    fence: GLsync,
+}
+
+impl<Device: DeviceAPI> CpuLayer<Device> {
+    /// Creates a layer of the given size, with a new generic surface in `context`.
+    ///
+    /// The context must be current. It also needs fence sync objects, which OpenGL 3.2 and OpenGL
+    /// ES 3.0 provide; otherwise this returns `Error::RequiredExtensionUnavailable`.
+    ///
+    /// The layer starts out transparent black, and the whole layer is damaged, so that the first
+    /// commit uploads all of it.
+    pub fn new(
+        device: &mut Device,
+        context: &mut Device::Context,
+        size: Size2D<i32>,
+    ) -> Result<CpuLayer<Device>, Error> {
+        if !device.capabilities(context)?.fence_sync {
+            return Err(Error::RequiredExtensionUnavailable);
+        }
+        let gl = Gl::load_with(device.proc_address_loader(context));
+
+        let surface = device.create_surface(
+            context,
+            SurfaceAccess::GPUOnly,
+            SurfaceType::Generic { size },
+        )?;
+        let surface_texture = match device.create_surface_texture(context, surface) {
+            Ok(surface_texture) => surface_texture,
+            Err((err, mut surface)) => {
+                drop(device.destroy_surface(context, &mut surface));
+                return Err(err);
+            }
+        };
+        let texture_object = device.surface_texture_object(&surface_texture);
+        let texture_target = device.surface_gl_texture_target();
+
+        // Each pixel buffer can hold the whole layer, so no amount of damage overflows it.
+        let length = size.width as usize * size.height as usize * 4;
+        let pixel_buffers = [(); PIXEL_BUFFER_COUNT].map(|_| unsafe {
+            let mut object = 0;
+            gl.GenBuffers(1, &mut object);
+            gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, object);
+            gl.BufferData(
+                gl::PIXEL_UNPACK_BUFFER,
+                length as _,
+                ptr::null(),
+                gl::STREAM_DRAW,
+            );
+            PixelBuffer {
+                object,
+                fence: ptr::null(),
+            }
+        });
+        unsafe {
+            gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, 0);
+        }
+
+        let mut damage = Vec::with_capacity(MAX_DAMAGE_RECTS);
+        damage.push(Rect::new(Point2D::zero(), size));
+        Ok(CpuLayer {
+            gl,
+            size,
+            surface_texture,
+            texture_object,
+            texture_target,
+            buffers: [vec![0; length], vec![0; length]],
+            back_buffer: 0,
+            damage,
+            frame_damage: Vec::with_capacity(MAX_DAMAGE_RECTS),
+            pixel_buffers,
+            next_pixel_buffer: 0,
+        })
+    }
+
+    /// Returns the size of the layer, in pixels.
+    #[inline]
+    pub fn size(&self) -> Size2D<i32> {
+        self.size
+    }
+
+    /// Returns the back buffer, which the next commit will upload from.
+    #[inline]
+    pub fn pixels(&self) -> &[u8] {
+        &self.buffers[self.back_buffer]
+    }
+
+    /// Returns the back buffer for drawing.
+    ///
+    /// Changes are only uploaded where they are covered by `add_damage`.
+    #[inline]
+    pub fn pixels_mut(&mut self) -> &mut [u8] {
+        &mut self.buffers[self.back_buffer]
+    }
+
+    /// Returns the front buffer, which holds the pixels that were last committed.
+    #[inline]
+    pub fn committed_pixels(&self) -> &[u8] {
+        &self.buffers[self.back_buffer ^ 1]
+    }
+
+    /// Marks a rectangle of the back buffer as changed since the last commit.
+    ///
+    /// The rectangle is clipped to the layer. After `MAX_DAMAGE_RECTS` rectangles, the damage is
+    /// merged into one bounding rectangle.
+    pub fn add_damage(&mut self, rect: &Rect<i32>) {
+        let rect = match rect.intersection(&Rect::new(Point2D::zero(), self.size)) {
+            Some(rect) if !rect.is_empty() => rect,
+            _ => return,
+        };
+        if self.damage.iter().any(|damage| damage.contains_rect(&rect)) {
+            return;
+        }
+        if self.damage.len() == MAX_DAMAGE_RECTS {
+            let bounds = self
+                .damage
+                .drain(..)
+                .fold(rect, |bounds, damage| bounds.union(&damage));
+            self.damage.push(bounds);
+        } else {
+            self.damage.push(rect);
+        }
+    }
+
+    /// Returns the rectangles that the last commit uploaded.
+    ///
+    /// Pass these on to compositors and presentation APIs that accept damage, so that they only
+    /// redraw the parts of the layer that changed.
+    #[inline]
+    pub fn frame_damage(&self) -> &[Rect<i32>] {
+        &self.frame_damage
+    }
+
+    /// Uploads the damaged parts of the back buffer to the surface, then swaps the buffers.
+    ///
+    /// The layer's context must be current. The upload is only queued; flush the context before
+    /// sampling the surface from another context. If nothing was damaged, this does nothing.
+    pub fn commit(&mut self) -> Result<(), Error> {
+        if self.damage.is_empty() {
+            self.frame_damage.clear();
+            return Ok(());
+        }
+
+        let pixel_buffer = &mut self.pixel_buffers[self.next_pixel_buffer];
+        unsafe {
+            wait_for_pixel_buffer(&self.gl, pixel_buffer)?;
+            upload_damage(
+                &self.gl,
+                pixel_buffer,
+                &self.buffers[self.back_buffer],
+                &self.damage,
+                self.size.width,
+                self.texture_target,
+                self.texture_object,
+            )?;
+        }
+        self.next_pixel_buffer = (self.next_pixel_buffer + 1) % PIXEL_BUFFER_COUNT;
+
+        // The new back buffer is out of date wherever this frame was damaged.
+        mem::swap(&mut self.damage, &mut self.frame_damage);
+        self.damage.clear();
+        self.back_buffer ^= 1;
+        let [ref mut first, ref mut second] = self.buffers;
+        let (front, back) = if self.back_buffer == 0 {
+            (&*second, first)
+        } else {
+            (&*first, second)
+        };
+        for rect in &self.frame_damage {
+            copy_rect(front, back, 0, self.size.width, rect);
+        }
+        Ok(())
+    }
+
+    /// Returns the surface texture that the layer is uploaded to.
+    #[inline]
+    pub fn surface_texture(&self) -> &Device::SurfaceTexture {
+        &self.surface_texture
+    }
+
+    /// Returns the OpenGL texture object of the layer's surface texture.
+    #[inline]
+    pub fn texture_object(&self) -> GLuint {
+        self.texture_object
+    }
+
+    /// Releases the layer's OpenGL objects, and returns its surface.
+    ///
+    /// The layer's context must be current.
+    pub fn into_surface(
+        self,
+        device: &Device,
+        context: &mut Device::Context,
+    ) -> Result<Device::Surface, Error> {
+        unsafe {
+            for pixel_buffer in &self.pixel_buffers {
+                if !pixel_buffer.fence.is_null() {
+                    self.gl.DeleteSync(pixel_buffer.fence);
+                }
+                self.gl.DeleteBuffers(1, &pixel_buffer.object);
+            }
+        }
+        device
+            .destroy_surface_texture(context, self.surface_texture)
+            .map_err(|(err, _)| err)
+    }
+
+    /// Destroys the layer and its surface.
+    ///
+    /// The layer's context must be current.
+    pub fn destroy(self, device: &Device, context: &mut Device::Context) -> Result<(), Error> {
+        let mut surface = self.into_surface(device, context)?;
+        device.destroy_surface(context, &mut surface)
+    }
+}
+
+// Waits until the GPU has finished reading the pixel buffer from its last upload.
+unsafe fn wait_for_pixel_buffer(gl: &Gl, pixel_buffer: &mut PixelBuffer) -> Result<(), Error> {
+    if pixel_buffer.fence.is_null() {
+        return Ok(());
+    }
+    let fence = mem::replace(&mut pixel_buffer.fence, ptr::null());
+    let mut result = gl::TIMEOUT_EXPIRED;
+    while result == gl::TIMEOUT_EXPIRED {
+        result = gl.ClientWaitSync(fence, gl::SYNC_FLUSH_COMMANDS_BIT, FENCE_TIMEOUT);
+    }
+    gl.DeleteSync(fence);
+    if result == gl::WAIT_FAILED {
+        return Err(Error::Failed);
+    }
+    Ok(())
+}
+
+// Copies the damaged rectangles of `pixels` into the pixel buffer, then queues uploads from it to
+// the texture and fences them.
+unsafe fn upload_damage(
+    gl: &Gl,
+    pixel_buffer: &mut PixelBuffer,
+    pixels: &[u8],
+    damage: &[Rect<i32>],
+    width: i32,
+    texture_target: GLenum,
+    texture_object: GLuint,
+) -> Result<(), Error> {
+    // Only map the rows that are damaged. The pixel buffer has the same layout as the layer.
+    let stride = width as usize * 4;
+    let min_y = damage.iter().map(|rect| rect.min_y()).min().unwrap() as usize;
+    let max_y = damage.iter().map(|rect| rect.max_y()).max().unwrap() as usize;
+    let (offset, length) = (min_y * stride, (max_y - min_y) * stride);
+
+    let mut old_unpack_buffer = 0;
+    gl.GetIntegerv(gl::PIXEL_UNPACK_BUFFER_BINDING, &mut old_unpack_buffer);
+    gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, pixel_buffer.object);
+
+    // The fence has already been waited on, so the mapping doesn't need to synchronize.
+    let data = gl.MapBufferRange(
+        gl::PIXEL_UNPACK_BUFFER,
+        offset as _,
+        length as _,
+        gl::MAP_WRITE_BIT | gl::MAP_INVALIDATE_RANGE_BIT | gl::MAP_UNSYNCHRONIZED_BIT,
+    );
+    if data.is_null() {
+        gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, old_unpack_buffer as GLuint);
+        return Err(Error::Failed);
+    }
+    let mapped = slice::from_raw_parts_mut(data as *mut u8, length);
+    for rect in damage {
+        copy_rect(pixels, mapped, offset, width, rect);
+    }
+    gl.UnmapBuffer(gl::PIXEL_UNPACK_BUFFER);
+
+    let (mut old_texture, mut old_row_length, mut old_alignment) = (0, 0, 0);
+    gl.GetIntegerv(texture_binding(texture_target), &mut old_texture);
+    gl.GetIntegerv(gl::UNPACK_ROW_LENGTH, &mut old_row_length);
+    gl.GetIntegerv(gl::UNPACK_ALIGNMENT, &mut old_alignment);
+    gl.BindTexture(texture_target, texture_object);
+    gl.PixelStorei(gl::UNPACK_ROW_LENGTH, width);
+    gl.PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+    for rect in damage {
+        let start = rect.min_y() as usize * stride + rect.min_x() as usize * 4;
+        gl.TexSubImage2D(
+            texture_target,
+            0,
+            rect.min_x(),
+            rect.min_y(),
+            rect.size.width,
+            rect.size.height,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            start as *const c_void,
+        );
+    }
+    pixel_buffer.fence = gl.FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+
+    gl.PixelStorei(gl::UNPACK_ALIGNMENT, old_alignment);
+    gl.PixelStorei(gl::UNPACK_ROW_LENGTH, old_row_length);
+    gl.BindTexture(texture_target, old_texture as GLuint);
+    gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, old_unpack_buffer as GLuint);
+    Ok(())
+}
+
+// Copies a rectangle between two images with the layout of a layer `width` pixels wide. `dest`
+// starts `dest_offset` bytes into that layout.
+fn copy_rect(src: &[u8], dest: &mut [u8], dest_offset: usize, width: i32, rect: &Rect<i32>) {
+    let stride = width as usize * 4;
+    let row_length = rect.size.width as usize * 4;
+    for y in rect.min_y()..rect.max_y() {
+        let start = y as usize * stride + rect.min_x() as usize * 4;
+        let dest_start = start - dest_offset;
+        dest[dest_start..dest_start + row_length].copy_from_slice(&src[start..start + row_length]);
+    }
+}
+
+// Returns the query for the texture that is bound to `texture_target`.
+pub(crate) fn texture_binding(texture_target: GLenum) -> GLenum {
+    #[cfg(target_os = "macos")]
+    {
+        if texture_target == gl::TEXTURE_RECTANGLE {
+            return gl::TEXTURE_BINDING_RECTANGLE;
+        }
+    }
+    debug_assert_eq!(texture_target, gl::TEXTURE_2D);
+    gl::TEXTURE_BINDING_2D
+}
@@ -0,0 +1,182 @@
+// surfman/surfman/src/current.rs
+//
+//! Keeping track of which thread each context is current on.
+//!
+//! A context can only be current on one thread at a time, and EGL and WGL refuse to make a context
+//! current while another thread holds it, with an error that doesn't say which thread. Devices
+//! record each context that they make current here, so that `Device::make_context_current()` can
+//! name the thread that holds the context when that happens, and so that
+//! `Device::context_current_thread()` can answer ahead of time.
+//!
+//! Making the same context current again on a thread touches no shared state. Otherwise, the
+//! contexts are spread over several independently locked shards, so that threads making different
+//! contexts current rarely wait for each other.
+//!
+//! `Device::with_context_current()` also marks its context here for as long as it runs, so that
+//! the operations it runs skip making the context current and restoring the previous one again,
+//! as long as the context is still the one recorded as current.
+
+use crate::{ContextID, Error, WindowingApiError};
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread::{self, Thread, ThreadId};
+use std::time::Instant;
+
+const SHARD_COUNT: usize = 16;
+
+lazy_static! {
+    static ref SHARDS: Vec<Mutex<HashMap<ContextID, Holder>>> = (0..SHARD_COUNT)
+        .map(|_| Mutex::new(HashMap::new()))
+        .collect();
+}
+
+thread_local! {
+    // The context that this thread has recorded as current, and since when.
+    static CURRENT: Cell<Option<(ContextID, Instant)>> = const { Cell::new(None) };
+    // The context that `Device::with_context_current()` is running operations on, if any.
+    static BULK_OPS: Cell<Option<ContextID>> = const { Cell::new(None) };
+}
+
+// The thread that a context is current on, and since when.
+struct Holder {
+    thread: Thread,
+    since: Instant,
+}
+
+// The context that a thread had recorded as current when a `CurrentContextGuard` was created,
+// which the guard records again when it restores that context.
+#[derive(Clone, Copy)]
+pub(crate) struct SavedCurrent(Option<(ContextID, Instant)>);
+
+// Marks a context as the one that `Device::with_context_current()` is running operations on, until
+// dropped, when the mark it replaced is put back.
+#[must_use]
+pub(crate) struct BulkOpsScope {
+    previous: Option<ContextID>,
+}
+
+impl Drop for BulkOpsScope {
+    #[inline]
+    fn drop(&mut self) {
+        BULK_OPS.with(|bulk_ops| bulk_ops.set(self.previous));
+    }
+}
+
+#[inline]
+fn shard(context_id: ContextID) -> &'static Mutex<HashMap<ContextID, Holder>> {
+    &SHARDS[context_id.0 as usize % SHARD_COUNT]
+}
+
+// Makes a context current on this thread with `make_current`, and records it. If the windowing
+// system refuses because another thread has the context current, the error names that thread.
+pub(crate) fn make_current<F>(context_id: ContextID, make_current: F) -> Result<(), Error>
+where
+    F: FnOnce() -> Result<(), Error>,
+{
+    match make_current() {
+        Ok(()) => {
+            record(Some((context_id, Instant::now())));
+            Ok(())
+        }
+        Err(Error::MakeCurrentFailed(WindowingApiError::BadAccess)) => {
+            let holder = shard(context_id)
+                .lock()
+                .unwrap()
+                .get(&context_id)
+                .filter(|holder| holder.thread.id() != thread::current().id())
+                .map(|holder| (holder.thread.clone(), holder.since));
+            match holder {
+                Some((thread, since)) => Err(Error::ContextCurrentOnAnotherThread {
+                    context_id,
+                    thread: thread.id(),
+                    thread_name: thread.name().map(str::to_owned),
+                    current_for: since.elapsed(),
+                }),
+                None => Err(Error::MakeCurrentFailed(WindowingApiError::BadAccess)),
+            }
+        }
+        Err(err) => Err(err),
+    }
+}
+
+// Records that no context is current on this thread any longer.
+#[inline]
+pub(crate) fn released() {
+    record(None)
+}
+
+// Forgets a destroyed context, whose ID may be reused.
+pub(crate) fn forget(context_id: ContextID) {
+    shard(context_id).lock().unwrap().remove(&context_id);
+    CURRENT.with(|current| {
+        if matches!(current.get(), Some((id, _)) if id == context_id) {
+            current.set(None);
+        }
+    });
+}
+
+// Returns the thread that the context is current on, if any.
+pub(crate) fn current_thread(context_id: ContextID) -> Option<ThreadId> {
+    shard(context_id)
+        .lock()
+        .unwrap()
+        .get(&context_id)
+        .map(|holder| holder.thread.id())
+}
+
+// Marks the context, which must have just been made current, as the one that bulk operations run
+// on, until the returned scope is dropped.
+#[inline]
+pub(crate) fn begin_bulk_ops(context_id: ContextID) -> BulkOpsScope {
+    BulkOpsScope {
+        previous: BULK_OPS.with(|bulk_ops| bulk_ops.replace(Some(context_id))),
+    }
+}
+
+// Returns true if `Device::with_context_current()` is running operations on the context on this
+// thread, and the context is still the one recorded as current, so it needn't be made current.
+#[inline]
+pub(crate) fn held_for_bulk_ops(context_id: ContextID) -> bool {
+    BULK_OPS.with(Cell::get) == Some(context_id)
+        && matches!(CURRENT.with(Cell::get), Some((id, _)) if id == context_id)
+}
+
+#[inline]
+pub(crate) fn save() -> SavedCurrent {
+    SavedCurrent(CURRENT.with(Cell::get))
+}
+
+#[inline]
+pub(crate) fn restore(saved: SavedCurrent) {
+    record(saved.0)
+}
+
+// Records `new` as the context current on this thread, replacing whatever was recorded before.
+// Nothing changes if it's already recorded, so a thread that keeps making the same context
+// current doesn't lock anything.
+fn record(new: Option<(ContextID, Instant)>) {
+    let old = CURRENT.with(Cell::get);
+    if old.map(|(id, _)| id) == new.map(|(id, _)| id) {
+        return;
+    }
+
+    let this_thread = thread::current();
+    if let Some((old_id, _)) = old {
+        let mut holders = shard(old_id).lock().unwrap();
+        // Another thread may have taken over a context that this thread released behind
+        // surfman's back.
+        if matches!(holders.get(&old_id), Some(holder) if holder.thread.id() == this_thread.id()) {
+            holders.remove(&old_id);
+        }
+    }
+    if let Some((new_id, since)) = new {
+        let holder = Holder {
+            thread: this_thread,
+            since,
+        };
+        shard(new_id).lock().unwrap().insert(new_id, holder);
+    }
+    CURRENT.with(|current| current.set(new));
+}
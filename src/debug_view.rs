@@ -0,0 +1,618 @@
+// surfman/surfman/src/debug_view.rs
+//
+//! Windows that show the contents of a surface, for debugging.
+//!
+//! `show` copies the pixels of a surface and opens a small window that displays them, without the
+//! caller having to set up a window, a widget surface or a compositor of its own. Every debug view
+//! is hosted by a single background thread that runs the window system's event loop. Each window
+//! there has its own device and context: the copy is uploaded to a texture in that context and
+//! blitted to the window's widget surface, letterboxed to keep its aspect ratio, whenever the
+//! window needs redrawing. `DebugView::update` sends a new copy, and dropping the view closes its
+//! window.
+//!
+//! Any number of views can be open at once, from any number of threads. The background thread is
+//! started by the first call to `show`, and keeps running, idle, once the last view is closed,
+//! because most window systems only allow one event loop per process.
+//!
+//! Debug views need an event loop that can run on a background thread. That rules out macOS,
+//! Android and OpenHarmony, where `show` returns `Error::UnsupportedOnThisPlatform`.
+
+#![cfg_attr(not(any(free_unix, windows_platform)), allow(dead_code))]
+
+use crate::device::Device as DeviceAPI;
+use crate::gl;
+use crate::gl_utils;
+use crate::{Error, Gl};
+use euclid::default::{Point2D, Rect, Size2D};
+use std::os::raw::c_void;
+
+/// A window that shows a copy of a surface.
+///
+/// Dropping the view closes the window. If the user closes the window first, the view stays valid,
+/// and `update` does nothing.
+pub struct DebugView {
+    #[cfg(any(free_unix, windows_platform))]
+    id: u64,
+    #[cfg(any(free_unix, windows_platform))]
+    proxy: host::Proxy,
+}
+
+/// Opens a window titled `title` that shows the current contents of `surface`.
+///
+/// `surface` must be a generic surface created with `context`, and `context` must be current on
+/// the calling thread. The surface is copied with `context` before this returns, and the window
+/// keeps showing that copy until `DebugView::update` is called; it never touches `context`
+/// afterward. The calling thread's current context is left as it was, so it is safe to call this
+/// in the middle of rendering, as long as the surface isn't bound to a context at the time.
+///
+/// Widget surfaces return `Error::WidgetAttached`, since their contents are already on screen.
+/// Copying needs framebuffer blits, which OpenGL 3.0 and OpenGL ES 3.0 provide; otherwise this
+/// returns `Error::RequiredExtensionUnavailable`.
+pub fn show<Device: DeviceAPI>(
+    device: &Device,
+    context: &Device::Context,
+    surface: &Device::Surface,
+    title: &str,
+) -> Result<DebugView, Error> {
+    let snapshot = Snapshot::read(device, context, surface)?;
+    DebugView::open(title, snapshot)
+}
+
+impl DebugView {
+    /// Replaces the copy of the surface that the window shows with the current contents of
+    /// `surface`, which has the same requirements as in `show`.
+    ///
+    /// The surface doesn't have to be the one that the view was opened with, or the same size.
+    pub fn update<Device: DeviceAPI>(
+        &self,
+        device: &Device,
+        context: &Device::Context,
+        surface: &Device::Surface,
+    ) -> Result<(), Error> {
+        let snapshot = Snapshot::read(device, context, surface)?;
+        self.send_snapshot(snapshot);
+        Ok(())
+    }
+
+    #[cfg(any(free_unix, windows_platform))]
+    fn open(title: &str, snapshot: Snapshot) -> Result<DebugView, Error> {
+        let (id, proxy) = host::open(title, snapshot)?;
+        Ok(DebugView { id, proxy })
+    }
+
+    #[cfg(not(any(free_unix, windows_platform)))]
+    fn open(_: &str, _: Snapshot) -> Result<DebugView, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    #[cfg(any(free_unix, windows_platform))]
+    fn send_snapshot(&self, snapshot: Snapshot) {
+        host::update(&self.proxy, self.id, snapshot)
+    }
+
+    #[cfg(not(any(free_unix, windows_platform)))]
+    fn send_snapshot(&self, _: Snapshot) {}
+}
+
+#[cfg(any(free_unix, windows_platform))]
+impl Drop for DebugView {
+    // Waits for the window to close, so that it is gone by the time the view is.
+    fn drop(&mut self) {
+        host::close(&self.proxy, self.id)
+    }
+}
+
+// A copy of the pixels of a surface, as tightly-packed RGBA8 rows from the bottom of the image to
+// the top.
+pub(crate) struct Snapshot {
+    pub(crate) size: Size2D<i32>,
+    pub(crate) pixels: Vec<u8>,
+}
+
+impl Snapshot {
+    // Reads the pixels of a surface with its context, which must be current.
+    //
+    // The surface's framebuffer is resolved into a temporary renderbuffer first, since it may be
+    // multisampled. Framebuffer bindings are restored afterward.
+    pub(crate) fn read<Device: DeviceAPI>(
+        device: &Device,
+        context: &Device::Context,
+        surface: &Device::Surface,
+    ) -> Result<Snapshot, Error> {
+        let info = device.surface_info(surface);
+        if info.context_id != device.context_id(context) {
+            return Err(Error::IncompatibleSurface);
+        }
+        if info.framebuffer_object == 0 {
+            return Err(Error::WidgetAttached);
+        }
+        if !device.is_context_current(context) {
+            return Err(Error::NoCurrentContext);
+        }
+        if !device.capabilities(context)?.blit_framebuffer {
+            return Err(Error::RequiredExtensionUnavailable);
+        }
+
+        let gl = Gl::load_with(device.proc_address_loader(context));
+        let size = info.size;
+        let mut pixels = vec![0; size.width as usize * size.height as usize * 4];
+        gl_utils::drain_pre_existing_gl_errors(&gl);
+        unsafe {
+            let (mut draw_framebuffer, mut read_framebuffer) = (0, 0);
+            gl.GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut draw_framebuffer);
+            gl.GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut read_framebuffer);
+            let mut pack_alignment = 0;
+            gl.GetIntegerv(gl::PACK_ALIGNMENT, &mut pack_alignment);
+
+            let (mut renderbuffer, mut framebuffer) = (0, 0);
+            gl.GenRenderbuffers(1, &mut renderbuffer);
+            gl.BindRenderbuffer(gl::RENDERBUFFER, renderbuffer);
+            gl.RenderbufferStorage(gl::RENDERBUFFER, info.format, size.width, size.height);
+            gl.BindRenderbuffer(gl::RENDERBUFFER, 0);
+            gl.GenFramebuffers(1, &mut framebuffer);
+            gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, framebuffer);
+            gl.FramebufferRenderbuffer(
+                gl::DRAW_FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::RENDERBUFFER,
+                renderbuffer,
+            );
+
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, info.framebuffer_object);
+            gl.BlitFramebuffer(
+                0,
+                0,
+                size.width,
+                size.height,
+                0,
+                0,
+                size.width,
+                size.height,
+                gl::COLOR_BUFFER_BIT,
+                gl::NEAREST,
+            );
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, framebuffer);
+            gl.PixelStorei(gl::PACK_ALIGNMENT, 4);
+            gl.ReadPixels(
+                0,
+                0,
+                size.width,
+                size.height,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as *mut c_void,
+            );
+            let error = gl.GetError();
+
+            gl.PixelStorei(gl::PACK_ALIGNMENT, pack_alignment);
+            gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, draw_framebuffer as _);
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, read_framebuffer as _);
+            gl.DeleteFramebuffers(1, &framebuffer);
+            gl.DeleteRenderbuffers(1, &renderbuffer);
+            if error != gl::NO_ERROR {
+                return Err(Error::Failed);
+            }
+        }
+        Ok(Snapshot { size, pixels })
+    }
+}
+
+// Returns the largest rectangle with the aspect ratio of `image` that fits in the middle of
+// `window`, in window coordinates.
+pub(crate) fn fit_rect(image: Size2D<i32>, window: Size2D<i32>) -> Rect<i32> {
+    if image.is_empty() || window.is_empty() {
+        return Rect::zero();
+    }
+    // Compare `image.width / image.height` with `window.width / window.height` without rounding.
+    let (image_width, image_height) = (image.width as i64, image.height as i64);
+    let (window_width, window_height) = (window.width as i64, window.height as i64);
+    let size = if image_width * window_height > window_width * image_height {
+        Size2D::new(window_width, window_width * image_height / image_width)
+    } else {
+        Size2D::new(window_height * image_width / image_height, window_height)
+    };
+    let origin = Point2D::new(
+        (window_width - size.width) / 2,
+        (window_height - size.height) / 2,
+    );
+    Rect::new(origin, size).cast()
+}
+
+#[cfg(any(free_unix, windows_platform))]
+mod host {
+    //! The background thread that hosts the windows of every debug view.
+
+    use super::{fit_rect, Snapshot};
+    use crate::gl;
+    use crate::gl::types::GLuint;
+    use crate::{Connection, Context, Device, Error, Gl, Surface};
+    use crate::{ContextAttributeFlags, ContextAttributes, ContextPriority, GLVersion};
+    use crate::{SurfaceAccess, SurfaceType};
+    use euclid::default::Size2D;
+    use log::debug;
+    use rwh_06::{HasDisplayHandle, HasWindowHandle};
+    use std::collections::HashMap;
+    use std::os::raw::c_void;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::mpsc::{self, Sender};
+    use std::sync::Mutex;
+    use std::thread;
+    use winit::dpi::PhysicalSize;
+    use winit::event::{Event, WindowEvent};
+    use winit::event_loop::{ControlFlow, EventLoopBuilder, EventLoopProxy, EventLoopWindowTarget};
+    use winit::window::{Window, WindowBuilder, WindowId};
+
+    pub(super) type Proxy = EventLoopProxy<Command>;
+
+    lazy_static! {
+        static ref HOST: Mutex<Option<Proxy>> = Mutex::new(None);
+    }
+
+    static NEXT_VIEW_ID: AtomicU64 = AtomicU64::new(0);
+
+    // The background color around letterboxed images, which is also all a window shows if its
+    // image can't be drawn.
+    const BACKGROUND_COLOR: [f32; 4] = [0.25, 0.25, 0.25, 1.0];
+
+    pub(super) enum Command {
+        Open {
+            id: u64,
+            title: String,
+            snapshot: Snapshot,
+            reply: Sender<Result<(), Error>>,
+        },
+        Update {
+            id: u64,
+            snapshot: Snapshot,
+        },
+        Close {
+            id: u64,
+            reply: Sender<()>,
+        },
+    }
+
+    pub(super) fn open(title: &str, snapshot: Snapshot) -> Result<(u64, Proxy), Error> {
+        let proxy = proxy()?;
+        let id = NEXT_VIEW_ID.fetch_add(1, Ordering::Relaxed);
+        let (reply, result) = mpsc::channel();
+        let title = title.to_owned();
+        let command = Command::Open {
+            id,
+            title,
+            snapshot,
+            reply,
+        };
+        proxy.send_event(command).map_err(|_| Error::Failed)?;
+        result.recv().map_err(|_| Error::Failed)??;
+        Ok((id, proxy))
+    }
+
+    pub(super) fn update(proxy: &Proxy, id: u64, snapshot: Snapshot) {
+        drop(proxy.send_event(Command::Update { id, snapshot }));
+    }
+
+    pub(super) fn close(proxy: &Proxy, id: u64) {
+        let (reply, closed) = mpsc::channel();
+        if proxy.send_event(Command::Close { id, reply }).is_ok() {
+            let _ = closed.recv();
+        }
+    }
+
+    // Returns a proxy for the event loop of the host thread, starting the thread if necessary.
+    fn proxy() -> Result<Proxy, Error> {
+        let mut host = HOST.lock().unwrap();
+        if let Some(ref proxy) = *host {
+            return Ok(proxy.clone());
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("surfman debug view".to_owned())
+            .spawn(move || run(sender))
+            .map_err(|_| Error::Failed)?;
+        let proxy = receiver.recv().map_err(|_| Error::Failed)??;
+        *host = Some(proxy.clone());
+        Ok(proxy)
+    }
+
+    fn run(sender: Sender<Result<Proxy, Error>>) {
+        let mut builder = EventLoopBuilder::with_user_event();
+        #[cfg(free_unix)]
+        winit::platform::x11::EventLoopBuilderExtX11::with_any_thread(&mut builder, true);
+        #[cfg(windows_platform)]
+        winit::platform::windows::EventLoopBuilderExtWindows::with_any_thread(&mut builder, true);
+        let event_loop = match builder.build() {
+            Ok(event_loop) => event_loop,
+            Err(err) => {
+                debug!("Failed to start the debug view event loop: {:?}", err);
+                drop(sender.send(Err(Error::ConnectionFailed)));
+                return;
+            }
+        };
+        drop(sender.send(Ok(event_loop.create_proxy())));
+
+        let mut windows: HashMap<u64, ViewWindow> = HashMap::new();
+        let result = event_loop.run(move |event, target| {
+            target.set_control_flow(ControlFlow::Wait);
+            match event {
+                Event::UserEvent(Command::Open {
+                    id,
+                    title,
+                    snapshot,
+                    reply,
+                }) => {
+                    let result = ViewWindow::new(target, &title, snapshot).map(|window| {
+                        windows.insert(id, window);
+                    });
+                    drop(reply.send(result));
+                }
+                Event::UserEvent(Command::Update { id, snapshot }) => {
+                    if let Some(window) = windows.get_mut(&id) {
+                        window.upload(snapshot);
+                        window.window.request_redraw();
+                    }
+                }
+                Event::UserEvent(Command::Close { id, reply }) => {
+                    if let Some(window) = windows.remove(&id) {
+                        window.destroy();
+                    }
+                    let _ = reply.send(());
+                }
+                Event::WindowEvent { window_id, event } => {
+                    let id = match find_window(&windows, window_id) {
+                        Some(id) => id,
+                        None => return,
+                    };
+                    match event {
+                        WindowEvent::CloseRequested => windows.remove(&id).unwrap().destroy(),
+                        WindowEvent::Resized(size) => {
+                            let window = windows.get_mut(&id).unwrap();
+                            window.resize(size);
+                            window.window.request_redraw();
+                        }
+                        WindowEvent::RedrawRequested => windows.get_mut(&id).unwrap().draw(),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        });
+        if let Err(err) = result {
+            debug!("The debug view event loop failed: {:?}", err);
+        }
+    }
+
+    fn find_window(windows: &HashMap<u64, ViewWindow>, window_id: WindowId) -> Option<u64> {
+        windows
+            .iter()
+            .find(|(_, window)| window.window.id() == window_id)
+            .map(|(&id, _)| id)
+    }
+
+    // A debug view's window, and the device and context that draw it.
+    struct ViewWindow {
+        window: Window,
+        device: Device,
+        context: Context,
+        gl: Gl,
+        texture: GLuint,
+        framebuffer: GLuint,
+        image_size: Size2D<i32>,
+    }
+
+    impl ViewWindow {
+        fn new(
+            target: &EventLoopWindowTarget<Command>,
+            title: &str,
+            snapshot: Snapshot,
+        ) -> Result<ViewWindow, Error> {
+            let inner_size =
+                PhysicalSize::new(snapshot.size.width as u32, snapshot.size.height as u32);
+            let window = WindowBuilder::new()
+                .with_title(title)
+                .with_inner_size(inner_size)
+                .build(target)
+                .map_err(|_| Error::Failed)?;
+
+            let display_handle = window
+                .display_handle()
+                .map_err(|_| Error::IncompatibleRawDisplayHandle)?;
+            let connection = Connection::from_display_handle(display_handle)?;
+            let adapter = connection.create_adapter()?;
+            let mut device = connection.create_device(&adapter)?;
+            let window_handle = window
+                .window_handle()
+                .map_err(|_| Error::IncompatibleNativeWidget)?;
+            let native_widget = connection
+                .create_native_widget_from_window_handle(window_handle, window_size(&window))?;
+
+            let attributes = ContextAttributes {
+                version: GLVersion::new(3, 0),
+                flags: ContextAttributeFlags::empty(),
+                priority: ContextPriority::Low,
+                ..Default::default()
+            };
+            let context_descriptor = device.create_context_descriptor(&attributes)?;
+            let mut context = device.create_context(&context_descriptor, None)?;
+            let surface_type = SurfaceType::Widget { native_widget };
+            let result = device
+                .create_surface(&context, SurfaceAccess::GPUOnly, surface_type)
+                .and_then(|surface| {
+                    device
+                        .bind_surface_to_context(&mut context, surface)
+                        .map_err(|(err, mut surface)| {
+                            drop(device.destroy_surface(&mut context, &mut surface));
+                            err
+                        })
+                })
+                .and_then(|()| device.make_context_current(&context));
+            if let Err(err) = result {
+                drop(device.destroy_context(&mut context));
+                return Err(err);
+            }
+
+            let gl = Gl::load_with(device.proc_address_loader(&context));
+            let (mut texture, mut framebuffer) = (0, 0);
+            unsafe {
+                gl.GenTextures(1, &mut texture);
+                gl.BindTexture(gl::TEXTURE_2D, texture);
+                gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as _);
+                gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as _);
+                gl.BindTexture(gl::TEXTURE_2D, 0);
+                gl.GenFramebuffers(1, &mut framebuffer);
+            }
+
+            let mut view_window = ViewWindow {
+                window,
+                device,
+                context,
+                gl,
+                texture,
+                framebuffer,
+                image_size: Size2D::zero(),
+            };
+            view_window.upload(snapshot);
+            view_window.window.request_redraw();
+            Ok(view_window)
+        }
+
+        // Replaces the window's image.
+        fn upload(&mut self, snapshot: Snapshot) {
+            if let Err(err) = self.device.make_context_current(&self.context) {
+                debug!("Failed to make the debug view context current: {:?}", err);
+                return;
+            }
+            let gl = &self.gl;
+            unsafe {
+                gl.BindTexture(gl::TEXTURE_2D, self.texture);
+                gl.PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+                gl.TexImage2D(
+                    gl::TEXTURE_2D,
+                    0,
+                    gl::RGBA8 as _,
+                    snapshot.size.width,
+                    snapshot.size.height,
+                    0,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    snapshot.pixels.as_ptr() as *const c_void,
+                );
+                gl.BindTexture(gl::TEXTURE_2D, 0);
+                gl.BindFramebuffer(gl::READ_FRAMEBUFFER, self.framebuffer);
+                gl.FramebufferTexture2D(
+                    gl::READ_FRAMEBUFFER,
+                    gl::COLOR_ATTACHMENT0,
+                    gl::TEXTURE_2D,
+                    self.texture,
+                    0,
+                );
+                gl.BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+            }
+            self.image_size = snapshot.size;
+        }
+
+        fn resize(&mut self, size: PhysicalSize<u32>) {
+            let size = Size2D::new(size.width as i32, size.height as i32);
+            if size.is_empty() {
+                return;
+            }
+            let mut surface = match self.unbind_surface() {
+                Some(surface) => surface,
+                None => return,
+            };
+            if let Err(err) = self
+                .device
+                .resize_surface(&self.context, &mut surface, size)
+            {
+                debug!("Failed to resize a debug view surface: {:?}", err);
+            }
+            self.bind_surface(surface);
+        }
+
+        // Blits the image to the window, letterboxed, and presents it.
+        fn draw(&mut self) {
+            if let Err(err) = self.device.make_context_current(&self.context) {
+                debug!("Failed to make the debug view context current: {:?}", err);
+                return;
+            }
+            let target = match self.device.begin_rendering(&self.context) {
+                Ok(target) => target,
+                Err(err) => {
+                    debug!("Failed to draw a debug view: {:?}", err);
+                    return;
+                }
+            };
+            let rect = fit_rect(self.image_size, target.size);
+            let gl = &self.gl;
+            unsafe {
+                let [red, green, blue, alpha] = BACKGROUND_COLOR;
+                gl.ClearColor(red, green, blue, alpha);
+                gl.Clear(gl::COLOR_BUFFER_BIT);
+                if !rect.is_empty() {
+                    gl.BindFramebuffer(gl::READ_FRAMEBUFFER, self.framebuffer);
+                    gl.BlitFramebuffer(
+                        0,
+                        0,
+                        self.image_size.width,
+                        self.image_size.height,
+                        rect.min_x(),
+                        rect.min_y(),
+                        rect.max_x(),
+                        rect.max_y(),
+                        gl::COLOR_BUFFER_BIT,
+                        gl::LINEAR,
+                    );
+                }
+            }
+            drop(self.device.end_rendering(&self.context, false));
+
+            let mut surface = match self.unbind_surface() {
+                Some(surface) => surface,
+                None => return,
+            };
+            if let Err(err) = self.device.present_surface(&self.context, &mut surface) {
+                debug!("Failed to present a debug view: {:?}", err);
+            }
+            self.bind_surface(surface);
+        }
+
+        fn unbind_surface(&mut self) -> Option<Surface> {
+            match self.device.unbind_surface_from_context(&mut self.context) {
+                Ok(surface) => surface,
+                Err(err) => {
+                    debug!("Failed to unbind a debug view surface: {:?}", err);
+                    None
+                }
+            }
+        }
+
+        fn bind_surface(&mut self, surface: Surface) {
+            if let Err((err, mut surface)) = self
+                .device
+                .bind_surface_to_context(&mut self.context, surface)
+            {
+                debug!("Failed to rebind a debug view surface: {:?}", err);
+                drop(self.device.destroy_surface(&mut self.context, &mut surface));
+            }
+        }
+
+        // Frees the window's GL objects, surface and context, and then closes the window.
+        fn destroy(mut self) {
+            if self.device.make_context_current(&self.context).is_ok() {
+                unsafe {
+                    self.gl.DeleteFramebuffers(1, &self.framebuffer);
+                    self.gl.DeleteTextures(1, &self.texture);
+                }
+            }
+            if let Some(mut surface) = self.unbind_surface() {
+                drop(self.device.destroy_surface(&mut self.context, &mut surface));
+            }
+            drop(self.device.destroy_context(&mut self.context));
+        }
+    }
+
+    fn window_size(window: &Window) -> Size2D<i32> {
+        let size = window.inner_size();
+        Size2D::new(size.width as i32, size.height as i32)
+    }
+}
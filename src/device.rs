@@ -5,10 +5,50 @@
 use super::connection::Connection as ConnectionInterface;
 use crate::context::{ContextDescriptorInterface, NativeContext};
 use crate::gl::types::{GLenum, GLuint};
-use crate::{ContextAttributes, ContextID, Error, GLApi, SurfaceAccess, SurfaceInfo, SurfaceType};
-use euclid::default::Size2D;
+use crate::BufferRelease;
+use crate::BulkOps;
+use crate::CancelToken;
+use crate::ContextAttributeFlags;
+use crate::ContextDescriptorAttributes;
+use crate::ContextInfo;
+use crate::ContextLostHandler;
+use crate::ContextPriority;
+use crate::DebugFill;
+#[cfg(unix)]
+use crate::DmaBuf;
+use crate::GLCapabilities;
+use crate::GLVersion;
+use crate::GlError;
+use crate::InvalidateSet;
+use crate::LowMemoryOptions;
+use crate::PausedPresentation;
+use crate::PresentationStatistics;
+use crate::SurfaceFence;
+use crate::SurfaceOptions;
+use crate::SurfaceResizedHandler;
+use crate::SurfaceScaleChangedHandler;
+use crate::Swizzle;
+use crate::TextureOwnership;
+use crate::{AutoVsyncPolicy, AutoVsyncStats};
+use crate::{
+    ColorProfile, ColorProfileChangedHandler, ContextAttributes, ContextID, DeviceIdentity, Error,
+    GLApi,
+};
+use crate::{
+    PresentRecord, RenderTargetInfo, ResetStatus, SurfaceAccess, SurfaceInfo, SurfaceInterface,
+    SurfaceType,
+};
+use crate::{Purgeability, ReclaimResult};
+use crate::{SchedulingClass, SchedulingEnforcement};
+use crate::{SurfaceConfig, SurfaceIntent, SurfaceTransform};
+use euclid::default::{Rect, Size2D};
 
+use std::ops::RangeInclusive;
+#[cfg(unix)]
+use std::os::fd::BorrowedFd;
 use std::os::raw::c_void;
+use std::thread::ThreadId;
+use std::time::Duration;
 
 /// A thread-local handle to a device.
 ///
@@ -20,12 +60,16 @@ pub trait Device: Sized {
     type Context;
     /// The context descriptor type associated with this device.
     type ContextDescriptor: ContextDescriptorInterface;
+    /// The guard type that `make_context_current_guarded()` returns.
+    type CurrentContextGuard;
     /// The native context type associated with this device.
     type NativeContext: NativeContext;
     /// The surface type associated with this device.
-    type Surface;
+    type Surface: SurfaceInterface;
     /// The surface texture type associated with this device.
     type SurfaceTexture;
+    /// The guard type that `lock_surface_data()` returns, which borrows the surface.
+    type SurfaceDataGuard<'s>;
 
     // device.rs
 
@@ -41,6 +85,48 @@ pub trait Device: Sized {
     /// Returns the OpenGL API flavor that this device supports (OpenGL or OpenGL ES).
     fn gl_api(&self) -> GLApi;
 
+    /// Returns the identity of the physical GPU that this device renders with.
+    ///
+    /// Compare it with the identities that other graphics APIs report using
+    /// `identities_match()`.
+    fn identity(&self) -> DeviceIdentity;
+
+    /// Returns a token that can cancel context and surface creation on this device from any
+    /// thread.
+    ///
+    /// All tokens for a device share the same flag. See `CancelToken` for where creation checks
+    /// it.
+    fn cancellation_token(&self) -> CancelToken;
+
+    /// Sets what kind of work this device's contexts do, so that background work can yield the
+    /// GPU to interactive rendering, and returns how the platform enforces that.
+    ///
+    /// The class applies to contexts created after this call, and to every swap chain frame
+    /// presented after it.
+    fn set_scheduling_class(&mut self, class: SchedulingClass) -> SchedulingEnforcement;
+
+    /// Returns the scheduling class that was last set with `set_scheduling_class()`.
+    ///
+    /// This is `SchedulingClass::Interactive` for new devices.
+    fn scheduling_class(&self) -> SchedulingClass;
+
+    /// Returns how this device enforces its scheduling class.
+    fn scheduling_enforcement(&self) -> SchedulingEnforcement;
+
+    /// Makes this device save memory at the expense of rendering quality, for example when the
+    /// system reports memory pressure, and returns how many pooled surfaces were destroyed.
+    ///
+    /// The mode lasts until `exit_low_memory_mode()` is called, and entering it again replaces the
+    /// options. Surfaces that already exist keep their formats. See `LowMemoryOptions`.
+    fn enter_low_memory_mode(&mut self, options: LowMemoryOptions) -> usize;
+
+    /// Leaves low-memory mode, so that surfaces created from now on get their usual formats.
+    fn exit_low_memory_mode(&mut self);
+
+    /// Returns the options that this device entered low-memory mode with, or `None` if it isn't
+    /// in low-memory mode.
+    fn low_memory_options(&self) -> Option<LowMemoryOptions>;
+
     // context.rs
 
     /// Creates a context descriptor with the given attributes.
@@ -55,12 +141,33 @@ pub trait Device: Sized {
     ///
     /// The context initially has no surface attached. Until a surface is bound to it, rendering
     /// commands will fail or have no effect.
+    ///
+    /// If `share_with` is supplied, the new context shares textures, buffers, and other OpenGL
+    /// objects with it. On EGL-based backends, the shared context must have been created from a
+    /// descriptor with the same config, or an `IncompatibleSharedContext` error is returned.
     fn create_context(
         &mut self,
         descriptor: &Self::ContextDescriptor,
         share_with: Option<&Self::Context>,
     ) -> Result<Self::Context, Error>;
 
+    /// Creates a context of the newest OpenGL or OpenGL ES version in `versions` that this device
+    /// supports, and returns it along with the descriptor that it was created from.
+    ///
+    /// Versions are tried from newest to oldest. The first time a range and set of flags is asked
+    /// for, each failed attempt costs a round of pixel format selection, so the version that
+    /// succeeds is remembered and used directly on later calls with the same range and flags.
+    /// Check the version in the attributes of the returned descriptor to find out which version
+    /// was chosen.
+    ///
+    /// If the device supports no version in the range, this returns
+    /// `Error::UnsupportedGLVersion`.
+    fn create_versioned_context(
+        &mut self,
+        versions: RangeInclusive<GLVersion>,
+        flags: ContextAttributeFlags,
+    ) -> Result<(Self::Context, Self::ContextDescriptor), Error>;
+
     /// Wraps a native context object in an OpenGL context.
     unsafe fn create_context_from_native_context(
         &self,
@@ -78,36 +185,105 @@ pub trait Device: Sized {
     /// Makes the context the current OpenGL context for this thread.
     ///
     /// After calling this function, it is valid to use OpenGL rendering commands.
+    ///
+    /// Returns `Error::ContextLost` if the context has been lost; see `poll_context_lost()`.
     fn make_context_current(&self, context: &Self::Context) -> Result<(), Error>;
 
+    /// Makes the context current for this thread, returning a guard that makes the previously
+    /// current context current again when dropped.
+    ///
+    /// The guard records the context and, where the platform has them, the draw and read surfaces
+    /// that were current. If no context was current, dropping the guard leaves no context current.
+    /// The previous state is restored even if the thread panics while the guard is alive.
+    ///
+    /// Inside `with_context_current()`, the context that it made current is left as it is, and
+    /// the guard restores nothing, since `with_context_current()` restores the previous state.
+    fn make_context_current_guarded(
+        &self,
+        context: &Self::Context,
+    ) -> Result<Self::CurrentContextGuard, Error>;
+
+    /// Makes the context current for this thread, runs `f` with the operations that can be run
+    /// on the context, and then makes the previously current context current again.
+    ///
+    /// The operations that `BulkOps` offers behave as the device methods of the same names do,
+    /// except that they don't each make the context current and restore the previous context,
+    /// which is what makes tearing down many surfaces slow. The previous state is restored once,
+    /// even if `f` panics.
+    ///
+    /// If the context can't be made current, `f` isn't run, and the error is returned.
+    fn with_context_current<F, R>(&self, context: &mut Self::Context, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut BulkOps<Self>) -> R;
+
     /// Removes the current OpenGL context from this thread.
     ///
     /// After calling this function, OpenGL rendering commands will fail until a new context is
     /// made current.
     fn make_no_context_current(&self) -> Result<(), Error>;
 
+    /// Returns the thread that the context is current on, or `None` if it isn't current anywhere.
+    ///
+    /// Only contexts made current through surfman are tracked. A context can be current on only
+    /// one thread at a time, so `make_context_current()` fails with
+    /// `Error::ContextCurrentOnAnotherThread` on any other thread until this thread makes it not
+    /// current, on platforms that enforce this.
+    fn context_current_thread(&self, context: &Self::Context) -> Option<ThreadId>;
+
     /// Returns the attributes that the context descriptor was created with.
     fn context_descriptor_attributes(
         &self,
         context_descriptor: &Self::ContextDescriptor,
     ) -> ContextAttributes;
 
-    /// Fetches the address of an OpenGL function associated with this context.
+    /// Returns the properties of the context descriptor as plain data, which can be sent to
+    /// another process.
+    fn context_descriptor_raw_parts(
+        &self,
+        context_descriptor: &Self::ContextDescriptor,
+    ) -> ContextDescriptorAttributes;
+
+    /// Creates a context descriptor equivalent to the one that `attributes` were taken from,
+    /// possibly on a device in another process.
+    ///
+    /// Contexts created from the new descriptor have the same version and the same color, depth,
+    /// and stencil buffer sizes. If this device can't match them exactly, this returns
+    /// `Error::UnsupportedGLVersion` or `Error::NoPixelFormatFound`.
+    fn create_context_descriptor_from_attributes(
+        &self,
+        attributes: &ContextDescriptorAttributes,
+    ) -> Result<Self::ContextDescriptor, Error>;
+
+    /// Fetches the address of an OpenGL function associated with this context, or `None` if the
+    /// function isn't available.
     ///
     /// OpenGL functions are local to a context. You should not use OpenGL functions on one context
     /// with any other context.
     ///
-    /// This method is typically used with a function like `gl::load_with()` from the `gl` crate to
-    /// load OpenGL function pointers.
-    fn get_proc_address(&self, context: &Self::Context, symbol_name: &str) -> *const c_void;
+    /// Platforms hand out addresses even for functions that the driver doesn't implement, so
+    /// functions named with an extension suffix, like `glDrawBuffersEXT`, are only returned if the
+    /// context supports an extension from the same vendor. Checking may briefly make the context
+    /// current; the context that was current before this call remains current afterward.
+    fn get_proc_address(&self, context: &Self::Context, symbol_name: &str)
+        -> Option<*const c_void>;
+
+    /// Returns a function that looks up the OpenGL functions of a context, for loaders that
+    /// expect a null pointer for functions that aren't available, as in
+    /// `gl::load_with(device.proc_address_loader(&context))`.
+    fn proc_address_loader<'a>(
+        &'a self,
+        context: &'a Self::Context,
+    ) -> Box<dyn Fn(&str) -> *const c_void + 'a>;
 
     /// Attaches a surface to a context for rendering.
     ///
-    /// This function takes ownership of the surface. The surface must have been created with this
-    /// context, or an `IncompatibleSurface` error is returned.
+    /// This function takes ownership of the surface. A surface belongs to the context that it was
+    /// created with, so binding it to any other context returns a `SurfaceAlreadyBound` error
+    /// naming that context. Check `Surface::context_id()` beforehand to avoid this, and wrap the
+    /// surface in a surface texture to read it from another context instead.
     ///
-    /// If this function is called with a surface already bound, a `SurfaceAlreadyBound` error is
-    /// returned. To avoid this error, first unbind the existing surface with
+    /// If this context already has a surface bound, a `SurfaceAlreadyBound` error naming this
+    /// context is returned. To avoid this error, first unbind the existing surface with
     /// `unbind_surface_from_context`.
     ///
     /// If an error is returned, the surface is returned alongside it.
@@ -132,20 +308,119 @@ pub trait Device: Sized {
     /// a new one, the new context might have the same ID as the destroyed one.
     fn context_id(&self, context: &Self::Context) -> ContextID;
 
+    /// Returns true if the given context is the current OpenGL context for this thread.
+    ///
+    /// This asks the windowing system which context is current, so it also notices contexts that
+    /// were made current behind surfman's back.
+    fn is_context_current(&self, context: &Self::Context) -> bool;
+
     /// Returns various information about the surface attached to a context.
     ///
     /// This includes, most notably, the OpenGL framebuffer object needed to render to the surface.
     fn context_surface_info(&self, context: &Self::Context) -> Result<Option<SurfaceInfo>, Error>;
 
+    /// Makes the context current, binds the framebuffer of its surface, and sets the viewport to
+    /// cover that surface.
+    ///
+    /// If no surface is attached, the context is still made current, the default framebuffer is
+    /// bound, and the viewport is left unchanged; the returned info then has a zero size and a
+    /// format of `GL_NONE`. On error, the previously-current context is restored.
+    fn begin_rendering(&self, context: &Self::Context) -> Result<RenderTargetInfo, Error>;
+
+    /// Finishes rendering started with `begin_rendering()` by unbinding the surface's framebuffer,
+    /// flushing the context if `flush` is true.
+    ///
+    /// The context that was current before this call remains current afterward.
+    fn end_rendering(&self, context: &Self::Context, flush: bool) -> Result<(), Error>;
+
+    /// Returns the OpenGL features beyond OpenGL ES 2.0 that the given context supports.
+    ///
+    /// The context that was current before this call remains current afterward.
+    fn capabilities(&self, context: &Self::Context) -> Result<GLCapabilities, Error>;
+
+    /// Returns the names of the OpenGL extensions that the given context supports.
+    ///
+    /// The context that was current before this call remains current afterward.
+    fn gl_extensions(&self, context: &Self::Context) -> Result<Vec<String>, Error>;
+
+    /// Returns what OpenGL reports about the given context: the version, profile, and flags that
+    /// it was actually granted, and the vendor and renderer strings.
+    ///
+    /// The context that was current before this call remains current afterward.
+    fn context_info(&self, context: &Self::Context) -> Result<ContextInfo, Error>;
+
+    /// Returns true if the given context renders on a GPU, and false if it renders in software,
+    /// as when Mesa falls back to llvmpipe because the GPU's driver failed to load.
+    ///
+    /// The device's identity is checked for a software rasterizer, such as WARP or Mesa's
+    /// software device, and the context's `GL_RENDERER` string for the names of known ones. CGL
+    /// also checks that the context's pixel format is accelerated. A context that can't be made
+    /// current, such as a lost one, returns false.
+    ///
+    /// The context that was current before this call remains current afterward.
+    fn context_is_hardware_accelerated(&self, context: &Self::Context) -> bool;
+
+    /// Empties the OpenGL error queue of the given context, returning each error that
+    /// `glGetError()` reported until it reported none.
+    ///
+    /// Before running GL commands whose errors it checks, surfman drains the queue itself and logs
+    /// what it finds, so that errors left behind by the application's own GL calls aren't blamed
+    /// on surfman. Call this first to handle those errors instead.
+    ///
+    /// The context that was current before this call remains current afterward.
+    fn drain_gl_errors(&self, context: &Self::Context) -> Result<Vec<GlError>, Error>;
+
+    /// Returns whether the given context has been lost to a GPU reset.
+    ///
+    /// Only contexts created with `ContextAttributeFlags::ROBUST_ACCESS` are notified of resets;
+    /// others always report `ResetStatus::NoError`. A lost context must be destroyed and
+    /// recreated.
+    ///
+    /// The context that was current before this call remains current afterward.
+    fn context_reset_status(&self, context: &Self::Context) -> Result<ResetStatus, Error>;
+
+    /// Returns true if the given context has been lost, for example to a GPU reset or because the
+    /// system reclaimed it while the application was in the background.
+    ///
+    /// A context counts as lost once the windowing system reports `EGL_CONTEXT_LOST` while making
+    /// it current or presenting one of its surfaces, or once `context_reset_status()` reports a
+    /// reset. From then on, `make_context_current()` and the other methods that need the context
+    /// current return `Error::ContextLost`, and the context can only be destroyed.
+    ///
+    /// The context that was current before this call remains current afterward.
+    fn poll_context_lost(&self, context: &Self::Context) -> bool;
+
+    /// Registers a function to call with the ID of each context of this device that is found to
+    /// be lost, replacing any previous one, or unregisters it if `handler` is `None`.
+    ///
+    /// Losses are only noticed when this device calls into the driver, for example in
+    /// `make_context_current()`, `present_surface()`, or `poll_context_lost()`.
+    fn set_context_lost_handler(&mut self, handler: Option<ContextLostHandler>);
+
+    /// Returns the scheduling priority that the given context was granted.
+    ///
+    /// This may be lower than the priority requested in the context attributes. Platforms that
+    /// can't prioritize contexts report `ContextPriority::Medium`.
+    fn context_priority(&self, context: &Self::Context) -> ContextPriority;
+
     /// Returns the native context associated with the given context.
     fn native_context(&self, context: &Self::Context) -> Self::NativeContext;
 
+    /// Returns the native context that is current on this thread, for wrapping with
+    /// `create_context_from_native_context()`.
+    ///
+    /// If no context is current, this returns a `NoCurrentContext` error.
+    fn current_native_context(&self) -> Result<Self::NativeContext, Error>;
+
     // surface.rs
 
     /// Creates either a generic or a widget surface, depending on the supplied surface type.
     ///
     /// Only the given context may ever render to the surface, but generic surfaces can be wrapped
     /// up in a `SurfaceTexture` for reading by other contexts.
+    ///
+    /// Generic surfaces whose size has a side that is zero, negative, or longer than
+    /// `GLCapabilities::max_texture_size` return `InvalidSurfaceSize`.
     fn create_surface(
         &mut self,
         context: &Self::Context,
@@ -153,6 +428,49 @@ pub trait Device: Sized {
         surface_type: SurfaceType<<Self::Connection as ConnectionInterface>::NativeWidget>,
     ) -> Result<Self::Surface, Error>;
 
+    /// Creates a surface of each of the given types, in order, as `create_surface()` does.
+    ///
+    /// The device's cancellation token is checked before each surface, as well as inside each
+    /// creation, and the types are only taken from `surface_types` as they are needed. If any
+    /// surface fails or is cancelled, the ones created so far are destroyed and the error is
+    /// returned, so the batch creates either all of its surfaces or none.
+    fn create_surfaces<I>(
+        &mut self,
+        context: &mut Self::Context,
+        surface_access: SurfaceAccess,
+        surface_types: I,
+    ) -> Result<Vec<Self::Surface>, Error>
+    where
+        I: IntoIterator<
+            Item = SurfaceType<<Self::Connection as ConnectionInterface>::NativeWidget>,
+        >;
+
+    /// Creates either a generic or a widget surface, with options such as its colorspace.
+    ///
+    /// `create_surface()` is this method with the default options. Returns
+    /// `Error::UnsupportedSurfaceFormat` if the platform can't create a surface with the options.
+    fn create_surface_with_options(
+        &mut self,
+        context: &Self::Context,
+        surface_access: SurfaceAccess,
+        surface_type: SurfaceType<<Self::Connection as ConnectionInterface>::NativeWidget>,
+        options: SurfaceOptions,
+    ) -> Result<Self::Surface, Error>;
+
+    /// Returns the access mode, type, and options that suit a surface of the given size, created
+    /// with the given context for the given use, on this device, along with hints for using it.
+    ///
+    /// The access mode asks for CPU access only where this backend can map surfaces and the use
+    /// moves pixels between the CPU and the surface, and the hints follow the capabilities of the
+    /// context. The size is ignored for `SurfaceIntent::DisplayedInWindow`, since widget surfaces
+    /// take the size of their widget.
+    fn recommended_surface_config(
+        &self,
+        context: &Self::Context,
+        intent: SurfaceIntent<<Self::Connection as ConnectionInterface>::NativeWidget>,
+        size: Size2D<i32>,
+    ) -> Result<SurfaceConfig<<Self::Connection as ConnectionInterface>::NativeWidget>, Error>;
+
     /// Creates a surface texture from an existing generic surface for use with the given context.
     ///
     /// The surface texture is local to the supplied context and takes ownership of the surface.
@@ -187,6 +505,29 @@ pub trait Device: Sized {
         egl_target: GLuint,
     ) -> Result<Self::SurfaceTexture, Error>;
 
+    /// Wraps an existing GL texture in a generic surface, without copying it.
+    ///
+    /// The texture must be a complete `TEXTURE_2D` of the given size and format, created in the
+    /// supplied context or in a context that shares with it. The surface can then be rendered to,
+    /// wrapped in surface textures by other contexts, and passed through swap chains like any
+    /// other generic surface.
+    ///
+    /// With `TextureOwnership::Borrowed`, surfman never deletes the texture, and the caller must
+    /// keep it alive, and not change its size or format, until the surface is destroyed. With
+    /// `TextureOwnership::Owned`, destroying the surface deletes the texture.
+    ///
+    /// Returns `IncompatibleTexture` if the texture doesn't exist in the supplied context, and
+    /// `UnsupportedOnThisPlatform` on backends that can't share a GL texture between contexts,
+    /// such as CGL.
+    fn create_surface_from_texture(
+        &mut self,
+        context: &mut Self::Context,
+        texture_object: GLuint,
+        size: &Size2D<i32>,
+        format: GLenum,
+        ownership: TextureOwnership,
+    ) -> Result<Self::Surface, Error>;
+
     /// Destroys a surface.
     ///
     /// The supplied context must be the context the surface is associated with, or this returns
@@ -224,14 +565,130 @@ pub trait Device: Sized {
     /// associated widgets until this method is called.
     ///
     /// The supplied context must match the context the surface was created with, or an
-    /// `IncompatibleSurface` error is returned.
+    /// `IncompatibleSurface` error is returned on every backend. The context doesn't need to be
+    /// current: presentation uses the surface's own context internally, and whatever context was
+    /// current before this call remains current afterward.
+    ///
+    /// If the native window has been resized since the surface was last sized, the frame isn't
+    /// presented, and `SurfaceSizeMismatch` is returned with both sizes, so that the application
+    /// can resize the surface and redraw rather than have the window system stretch the frame. On
+    /// Wayland, where the client decides the size of each buffer, nothing can be stretched, and
+    /// the surface instead takes on the size of a `wl_egl_window` that a toolkit resized.
     fn present_surface(
         &self,
         context: &Self::Context,
         surface: &mut Self::Surface,
     ) -> Result<(), Error>;
 
-    /// Resizes a widget surface.
+    /// Displays the changed parts of a widget surface on screen.
+    ///
+    /// `damage` lists the rectangles of the surface that changed since it was last presented,
+    /// measured from the bottom left as in OpenGL, as `CpuLayer::frame_damage()` returns them.
+    /// Where the platform accepts damage, only those rectangles are recomposited; elsewhere, this
+    /// presents the whole surface, like `present_surface()`. An empty list means that nothing
+    /// changed: the present is skipped entirely, and `Ok` is returned.
+    ///
+    /// Contexts and surfaces are checked as for `present_surface()`.
+    fn present_surface_with_damage(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error>;
+
+    /// Pauses the presentation of a widget surface, so that frames can be inspected without
+    /// racing the render loop.
+    ///
+    /// Until the pause ends, `present_surface()` and `present_surface_with_damage()` check their
+    /// arguments as usual, but then read the frame back into memory instead of showing it, and
+    /// return `PresentationPaused`. The most recent of those frames is held, and
+    /// `read_surface_data()` reads it as it reads a generic surface. End the pause with
+    /// `resume_presentation()`. Dropping the returned handle ends it too, discarding the held
+    /// frame, and so does resizing the surface, since the frame no longer fits.
+    ///
+    /// A surface that's already paused returns `PresentationPaused`. Single-buffered surfaces,
+    /// whose frames are on screen as soon as they're drawn, return `UnsupportedPresentMode`. The
+    /// held frame is drawn back with `glBlitFramebuffer()`, so contexts without it return
+    /// `RequiredExtensionUnavailable`. Generic surfaces are never presented, and return
+    /// `NoWidgetAttached`. Platforms other than those using EGL return
+    /// `UnsupportedOnThisPlatform`.
+    fn pause_presentation(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+    ) -> Result<PausedPresentation, Error>;
+
+    /// Ends a pause in the presentation of a widget surface, and presents the frame held during it
+    /// if `present_pending` is true, or discards it otherwise.
+    ///
+    /// Nothing is presented if no frame was presented during the pause, or if the pause already
+    /// ended because the surface was resized; `paused.is_paused()` tells which. A handle from
+    /// another surface returns `IncompatiblePausedPresentation`.
+    fn resume_presentation(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+        paused: PausedPresentation,
+        present_pending: bool,
+    ) -> Result<(), Error>;
+
+    /// Displays the contents of a widget surface on screen, as `present_surface()` does, and
+    /// returns a fence that signals once the GPU has finished rendering it.
+    ///
+    /// Another context that consumes the surface can pass the fence to `wait_fence()`, and wait
+    /// for the frame on the GPU instead of this context calling `glFinish()`. Generic surfaces
+    /// aren't presented, so for them the fence just follows the commands that `context` has
+    /// issued so far, which is what a context reading the surface through a surface texture
+    /// needs. `context` is made current to insert the fence, and the context that was current
+    /// beforehand is made current again.
+    ///
+    /// Contexts and surfaces are checked as for `present_surface()`, and a paused surface returns
+    /// `PresentationPaused` without a fence. Fences are supported on the EGL backends, where
+    /// displays without `eglCreateSync()` return `RequiredExtensionUnavailable`; elsewhere, this
+    /// returns `UnsupportedOnThisPlatform`.
+    fn present_surface_with_fence(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+    ) -> Result<SurfaceFence, Error>;
+
+    /// Makes `context` wait on the GPU until `fence` signals, without blocking the CPU.
+    ///
+    /// Commands that `context` issues after this call don't run until the fence has signaled.
+    /// `context` is made current for the wait, and the context that was current beforehand is
+    /// made current again. A fence from another device returns `IncompatibleSurfaceFence`, and
+    /// displays without `eglWaitSync()` return `RequiredExtensionUnavailable`.
+    fn wait_fence(&self, context: &Self::Context, fence: &SurfaceFence) -> Result<(), Error>;
+
+    /// Returns a fence that signals when the sync file descriptor `fd` does, such as one that
+    /// Vulkan, KMS, or another process exported.
+    ///
+    /// `fd` is duplicated, so it still belongs to the caller. The fence can be passed to
+    /// `wait_fence()` like one from `present_surface_with_fence()`, and exported again with
+    /// `SurfaceFence::export_sync_fd()`. `context` is made current to create the fence, and the
+    /// context that was current beforehand is made current again.
+    ///
+    /// This needs `EGL_ANDROID_native_fence_sync`, and displays without it return
+    /// `RequiredExtensionUnavailable`. Backends other than EGL return `UnsupportedOnThisPlatform`.
+    #[cfg(unix)]
+    fn import_sync_fd(
+        &self,
+        context: &Self::Context,
+        fd: BorrowedFd,
+    ) -> Result<SurfaceFence, Error>;
+
+    /// Resizes a surface in place.
+    ///
+    /// Generic surfaces have their texture and renderbuffers reallocated at the new size, which
+    /// leaves their contents undefined. Their framebuffer objects are kept, so a surface that is
+    /// bound to its context stays bound and doesn't need to be rebound, though its ID may change.
+    /// Generic surfaces that wrap a texture from the caller can't be reallocated, and return
+    /// `ForeignTexture`. Widget surfaces resize their native window where the platform needs it.
+    ///
+    /// Sizes that `create_surface()` refuses return `InvalidSurfaceSize`, leaving the surface as
+    /// it was, so a minimized window's 0x0 size can't be passed on as is.
+    ///
+    /// Afterward, `surface_info()` reports the new size.
     fn resize_surface(
         &self,
         context: &Self::Context,
@@ -239,6 +696,228 @@ pub trait Device: Sized {
         size: Size2D<i32>,
     ) -> Result<(), Error>;
 
+    /// Sets the number of vertical blanks that `present_surface()` waits for before showing each
+    /// frame on this widget surface, and returns the interval that took effect. An interval of 0
+    /// presents immediately, without waiting for vertical sync.
+    ///
+    /// A negative interval asks for adaptive vsync: frames wait for as many vertical blanks as
+    /// its absolute value, except that a frame that misses its blank is shown right away and
+    /// tears, instead of stalling for another one. Where `supports_adaptive_swap()` is false, the
+    /// absolute value is used instead, and returned.
+    ///
+    /// Other intervals that the platform can't honor return `UnsupportedOnThisPlatform` rather
+    /// than being clamped. Generic surfaces are never presented, and return `NoWidgetAttached`.
+    fn set_swap_interval(
+        &mut self,
+        context: &Self::Context,
+        surface: &Self::Surface,
+        interval: i32,
+    ) -> Result<i32, Error>;
+
+    /// Returns true if this widget surface accepts the negative swap intervals of adaptive vsync.
+    ///
+    /// This is always false for generic surfaces.
+    fn supports_adaptive_swap(&self, surface: &Self::Surface) -> bool;
+
+    /// Lets surfman switch this widget surface between vsync and immediate presentation by
+    /// itself, following `policy`, or stops it with `None`.
+    ///
+    /// The surface starts out with vsync, at a swap interval of 1. Each `present_surface()` then
+    /// measures how long the frame took to render, and once frames have been taking at least
+    /// `policy.target_margin_ms` less than the display's refresh interval for a while, the
+    /// interval is set to 0, so that they're shown as soon as they're ready. Once they take as
+    /// long as the refresh interval again, vsync is turned back on, since presenting immediately
+    /// would then only tear. `auto_swap_interval_stats()` reports what was decided.
+    ///
+    /// Intervals are set as by `set_swap_interval()`. If the platform refuses one, the surface is
+    /// left with the interval it has. Stopping also leaves the surface with its current interval.
+    /// Generic surfaces are never presented, and return `NoWidgetAttached`.
+    fn set_auto_swap_interval(
+        &mut self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+        policy: Option<AutoVsyncPolicy>,
+    ) -> Result<(), Error>;
+
+    /// Returns the current decision of automatic vsync for this surface, how often it has
+    /// switched, and the timings that it's based on, or `None` if `set_auto_swap_interval()`
+    /// hasn't enabled it.
+    fn auto_swap_interval_stats(&self, surface: &Self::Surface) -> Option<AutoVsyncStats>;
+
+    /// Waits until the window system has released the buffer of the last frame presented on this
+    /// widget surface, or until `timeout` elapses, and reports whether the call had to block.
+    ///
+    /// Call this before the first draw of a frame when rendering incrementally on top of the
+    /// previous one, so as not to write to a buffer that the compositor may still be reading.
+    /// Surfaces that haven't been presented yet report `Released`. Platforms that can't observe
+    /// the release return `UnsupportedOnThisPlatform`, and generic surfaces return
+    /// `NoWidgetAttached`.
+    fn wait_previous_buffer_released(
+        &self,
+        surface: &Self::Surface,
+        timeout: Duration,
+    ) -> Result<BufferRelease, Error>;
+
+    /// Chooses which attachments of this surface are invalidated with `glInvalidateFramebuffer()`
+    /// right after each present, so that tiled GPUs needn't write them back to memory or load
+    /// them again for the next frame.
+    ///
+    /// `present_surface()` invalidates the attachments of widget surfaces, and swap chains those
+    /// of the generic surfaces that they swap to the front. The default, `InvalidateSet::default()`,
+    /// invalidates the depth and stencil buffers. Invalidating a color buffer whose contents are
+    /// still needed after a present, such as that of a generic surface, which is what consumers
+    /// read, or that of a widget surface that preserves its buffer across swaps, returns
+    /// `PreservedColorBuffer`.
+    fn set_post_present_invalidation(
+        &self,
+        surface: &mut Self::Surface,
+        set: InvalidateSet,
+    ) -> Result<(), Error>;
+
+    /// Returns which attachments of this surface are invalidated right after each present.
+    fn post_present_invalidation(&self, surface: &Self::Surface) -> InvalidateSet;
+
+    /// Invalidates the attachments of this generic surface that were chosen with
+    /// `set_post_present_invalidation()`, once its contents have been presented by other means
+    /// than `present_surface()`.
+    ///
+    /// Swap chains call this on the new front buffer after each swap. The context must be the
+    /// one that the surface was created with; it's made current for the call. Widget surfaces are
+    /// invalidated by `present_surface()`, and return `WidgetAttached`.
+    fn invalidate_surface(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+    ) -> Result<(), Error>;
+
+    /// Returns how many frames ago the back buffer of this widget surface was last presented, for
+    /// redrawing only what has changed since then, or 0 if its contents are undefined.
+    ///
+    /// EGL only reports the age while the surface is current and before anything has been drawn
+    /// into it, so the age is taken by `present_surface()` right after each swap, and holds until
+    /// the next one: query it after presenting and before drawing the next frame. It's 0 before the
+    /// first present, after a resize, when the color buffer is invalidated after presents, and on
+    /// platforms without `EGL_EXT_buffer_age`. Generic surfaces return `NoWidgetAttached`.
+    fn surface_buffer_age(&self, surface: &Self::Surface) -> Result<u32, Error>;
+
+    /// Asks the window system for the current size of this widget surface's native window, in
+    /// device pixels, which goes stale in `surface_info()` as soon as the window is resized.
+    ///
+    /// X11 asks the server with `XGetGeometry()`, which is a round trip. Wayland windows have no
+    /// size of their own, so this is the size of the buffer last attached to the `wl_egl_window`,
+    /// or the surface's size before anything has been presented. Generic surfaces return
+    /// `NoWidgetAttached`, and windows that have gone away return `InvalidNativeWidget`.
+    fn query_surface_native_size(&self, surface: &Self::Surface) -> Result<Size2D<i32>, Error>;
+
+    /// Returns the rotation that the window system applies to a widget surface's frames to show
+    /// them on its display, for applications that render pre-rotated with
+    /// `SurfaceOptions::pre_transform` to save the compositor that pass.
+    ///
+    /// On Android, this is the transform hint of the surface's window, which follows the rotation
+    /// of the display and so may change from one frame to the next. Elsewhere, and for generic
+    /// surfaces, it is always `SurfaceTransform::Identity`: the other window systems rotate frames
+    /// themselves, and Wayland compositors only report the transforms of their outputs to the
+    /// clients that bind them, which surfman doesn't.
+    fn surface_transform(&self, surface: &Self::Surface) -> SurfaceTransform;
+
+    /// Clears a region of a surface to `color`, and its depth and stencil buffers to `depth` and
+    /// `stencil` if they're given and the surface has them, without the caller loading OpenGL.
+    ///
+    /// `rect` is in device pixels, measured from the top left of the surface, and is clamped to
+    /// the surface's bounds; `None` clears the whole surface. Both generic and widget surfaces can
+    /// be cleared, as long as they belong to `context` and aren't bound to it. The clear is
+    /// scissored, and the OpenGL state that it changes is put back, as is the context that was
+    /// current beforehand.
+    fn clear_surface_region(
+        &self,
+        context: &mut Self::Context,
+        surface: &mut Self::Surface,
+        rect: Option<Rect<i32>>,
+        color: [f32; 4],
+        depth: Option<f32>,
+        stencil: Option<i32>,
+    ) -> Result<(), Error>;
+
+    /// Makes this device fill each surface that it creates from now on, and each surface that a
+    /// swap chain recycles, with a recognizable pattern, so that regions that nothing draws to
+    /// stand out in screenshots.
+    ///
+    /// Surfaces are filled as `clear_surface_region()` clears them. New devices have
+    /// `DebugFill::None`, which leaves the contents of new surfaces undefined as usual and costs
+    /// nothing.
+    fn set_debug_surface_fill(&mut self, fill: DebugFill);
+
+    /// Returns the debug fill that was last set with `set_debug_surface_fill()`.
+    fn debug_surface_fill(&self) -> DebugFill;
+
+    /// Fills a surface with this device's debug fill, as if it had just been created, or does
+    /// nothing if the fill is `DebugFill::None`.
+    ///
+    /// Swap chains call this for each surface that they recycle, and applications that pool
+    /// surfaces of their own can call it as they reuse them. The surface must belong to `context`
+    /// and not be bound to it.
+    fn debug_fill_surface(
+        &self,
+        context: &mut Self::Context,
+        surface: &mut Self::Surface,
+    ) -> Result<(), Error>;
+
+    /// Registers a function to call with the ID and new size of each widget surface of this
+    /// device that is found to have been resized behind surfman's back, replacing any previous
+    /// one, or unregisters it if `handler` is `None`.
+    ///
+    /// Only the Wayland backend notices such resizes, which happen when a toolkit resizes the
+    /// `wl_egl_window` itself. The buffer only takes on the new size when a frame is drawn, so the
+    /// resize is noticed by the `present_surface()` after the first frame drawn at the new size.
+    /// From then on, `surface_info()` and `begin_rendering()` report the new size, and that frame's
+    /// `PresentRecord` is marked `resized_externally`. Other backends never call the handler.
+    fn set_surface_resized_handler(&mut self, handler: Option<SurfaceResizedHandler>);
+
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to have moved to a display with a different scale factor, with the new scale and the
+    /// size that the surface should take on, replacing any previous one, or unregisters it if
+    /// `handler` is `None`.
+    ///
+    /// Changes are noticed by `present_surface()`, which compares the scale of the widget's
+    /// display with the one that it last saw: `GetDpiForWindow()` on Windows 10 and later, and the
+    /// `backingScaleFactor` of the view's window on macOS. X11, Android, and surfaceless devices
+    /// have no scale changes to notice, and accept a handler that is never called.
+    ///
+    /// Wayland devices can't see scale changes, since only the toolkit that created a window is
+    /// told about them, and OpenHarmony devices don't watch for them, so both return
+    /// `UnsupportedOnThisPlatform` instead of registering a handler; unregistering one always
+    /// succeeds. On Wayland, toolkits resize the `wl_egl_window` to suit the new scale themselves,
+    /// which `set_surface_resized_handler()` reports.
+    ///
+    /// Surfaces created with `SurfaceOptions::auto_rescale` are also resized to the new size.
+    fn set_surface_scale_changed_handler(
+        &mut self,
+        handler: Option<SurfaceScaleChangedHandler>,
+    ) -> Result<(), Error>;
+
+    /// Sets whether the system may discard the contents of a generic surface to free memory.
+    ///
+    /// A volatile surface must be taken back with `reclaim_surface()` before it's bound, wrapped
+    /// in a surface texture, or locked, and the result checked to find out whether its contents
+    /// survived. In debug builds, using a surface that hasn't been reclaimed fails with
+    /// `Error::SurfaceNotReclaimed`. Making the surface non-purgeable with this method takes it
+    /// back too, but if its contents were discarded, it stays unusable until `reclaim_surface()`
+    /// has reported that.
+    ///
+    /// See `Purgeability` for what each platform does. Returns `Error::WidgetAttached` for widget
+    /// surfaces.
+    fn set_surface_purgeable(
+        &self,
+        surface: &mut Self::Surface,
+        purgeability: Purgeability,
+    ) -> Result<(), Error>;
+
+    /// Makes a surface non-purgeable again, and reports whether the system discarded its contents
+    /// since it was made volatile.
+    ///
+    /// Returns `ReclaimResult::Intact` for surfaces that were never made volatile.
+    fn reclaim_surface(&self, surface: &mut Self::Surface) -> Result<ReclaimResult, Error>;
+
     /// Returns various information about the surface, including the framebuffer object needed to
     /// render to this surface.
     ///
@@ -247,8 +926,147 @@ pub trait Device: Sized {
     /// 0, the default framebuffer, depending on platform.
     fn surface_info(&self, surface: &Self::Surface) -> SurfaceInfo;
 
+    /// Returns a pointer to the underlying surface data for reading or writing by the CPU.
+    ///
+    /// Returns `Error::InvalidSurfaceAccess` if the surface was created as `GPUOnly`, and
+    /// `Error::Unimplemented` on platforms that can't map surfaces.
+    fn lock_surface_data<'s>(
+        &self,
+        surface: &'s mut Self::Surface,
+    ) -> Result<Self::SurfaceDataGuard<'s>, Error>;
+
+    /// Exports the pixels of a generic surface as a dma-buf, which other APIs and processes can
+    /// import to render into the surface or sample from it without a copy.
+    ///
+    /// The dma-buf shares the surface's texture, so writes through it change the surface, and it
+    /// stays valid after the surface is destroyed. Each call returns a new file descriptor for the
+    /// same buffer. Widget surfaces return `WidgetAttached`, and multisampled surfaces, whose
+    /// samples aren't in the texture, and formats that need more than one plane return
+    /// `UnsupportedSurfaceFormat`.
+    ///
+    /// This needs `EGL_MESA_image_dma_buf_export`, and displays without it return
+    /// `RequiredExtensionUnavailable`. Backends other than EGL on Linux and the BSDs return
+    /// `UnsupportedOnThisPlatform`.
+    #[cfg(unix)]
+    fn export_surface_dma_buf(&self, surface: &Self::Surface) -> Result<DmaBuf, Error>;
+
+    /// Copies the contents of `source` into `destination` on the GPU, converting between their
+    /// formats and colorspaces and exchanging channels as `swizzle` says.
+    ///
+    /// Both surfaces must be generic surfaces created with `context`, neither bound to it, and
+    /// the same size, or this returns `Error::IncompatibleSurface`, `Error::WidgetAttached`, or
+    /// `Error::SurfaceSizeMismatch`. `context` must be current. Copies need framebuffer blits,
+    /// and conversions other than plain copies need OpenGL 3.0 or OpenGL ES 3.0; otherwise this
+    /// returns `Error::RequiredExtensionUnavailable`. The OpenGL state of the context is left as
+    /// it was. `convert::stats()` counts the conversions done each way.
+    fn convert_surface(
+        &self,
+        context: &mut Self::Context,
+        source: &Self::Surface,
+        destination: &mut Self::Surface,
+        swizzle: Swizzle,
+    ) -> Result<(), Error>;
+
+    /// Reads a rectangle of a generic surface into `out`, as tightly packed 8-bit RGBA with the
+    /// top row first.
+    ///
+    /// `rect` is in device pixels, measured from the top left of the surface as in
+    /// `clear_surface_region()`, and must lie within the surface or this returns
+    /// `Error::RectOutOfBounds`. `out` must hold at least `width * height * 4` bytes, or this
+    /// returns `Error::BufferTooSmall`; any bytes past those are left alone. The surface must be
+    /// a generic surface created with `context` and not bound to it, or this returns
+    /// `Error::IncompatibleSurface` or `Error::WidgetAttached`.
+    ///
+    /// Pixels are read as they're stored: sRGB-encoded surfaces give sRGB values, surfaces
+    /// without alpha give 255 alpha, and `SurfaceFormat::Rgba16F` surfaces are clamped to
+    /// [0, 1]. Multisampled contexts have their samples resolved first, which needs framebuffer
+    /// blits. `context` needn't be current; it's made current for the read, and the context that
+    /// was current beforehand is made current again, with the OpenGL state that the read changes
+    /// put back.
+    fn read_surface_data(
+        &self,
+        context: &Self::Context,
+        surface: &Self::Surface,
+        rect: Rect<i32>,
+        out: &mut [u8],
+    ) -> Result<(), Error>;
+
+    /// Writes 8-bit RGBA pixels from `data`, top row first, into a rectangle of a generic surface.
+    ///
+    /// This is the inverse of `read_surface_data()`, and takes `rect` and the surface the same
+    /// way. Each row of `data` starts `stride` bytes after the one before it, which must be at
+    /// least `width * 4` or this returns `Error::InvalidStride`, and `data` must reach the end of
+    /// the last row or this returns `Error::BufferTooSmall`.
+    ///
+    /// The pixels are uploaded to the texture attached to the surface's framebuffer, so surfaces
+    /// backed by a renderbuffer, as in multisampled contexts, return
+    /// `Error::UnsupportedSurfaceFormat`, as do formats that OpenGL ES can't upload 8-bit pixels
+    /// to. sRGB-encoded surfaces store the values as given. `context` needn't be current; it's
+    /// made current for the upload, and the context that was current beforehand is made current
+    /// again, with the OpenGL state that the upload changes put back. The upload is only queued,
+    /// so flush the context before reading the surface from another one.
+    fn write_surface_data(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+        rect: Rect<i32>,
+        data: &[u8],
+        stride: usize,
+    ) -> Result<(), Error>;
+
+    /// Returns the color profile of the output that a widget surface is displayed on.
+    ///
+    /// Returns `ColorProfile::AssumedSrgb` for generic surfaces and wherever the platform
+    /// provides no color information.
+    fn widget_color_profile(&self, surface: &Self::Surface) -> ColorProfile;
+
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to be displayed with a different color profile, with the new profile, replacing any
+    /// previous one, or unregisters it if `handler` is `None`.
+    ///
+    /// Changes are noticed by `present_surface()`, which compares the profile that
+    /// `widget_color_profile()` reports with the one that it last saw, every 30 presents, so a
+    /// change shows up within half a second at 60 frames per second. This covers both a window
+    /// moving to another output and a new profile being installed for the same output. Backends
+    /// that always report `ColorProfile::AssumedSrgb` never call the handler.
+    fn set_color_profile_changed_handler(&mut self, handler: Option<ColorProfileChangedHandler>);
+
+    /// Returns true if a widget surface is on an output driven by a different GPU than this
+    /// device, so that presenting it goes through a copy between GPUs.
+    ///
+    /// The window may have moved since the surface was created, so this can be checked again
+    /// when the window moves between outputs, and the device recreated on the adapter from
+    /// `Connection::adapter_for_widget()`. Returns false for generic surfaces, and wherever the
+    /// GPU driving the output can't be determined.
+    fn presenting_cross_adapter(&self, surface: &Self::Surface) -> bool;
+
+    /// Returns the timestamps of the most recent presentations of a widget surface, oldest first.
+    ///
+    /// Each widget surface remembers its last 64 presentations, for measuring the latency between
+    /// rendering a frame and the frame reaching the screen. Which timestamps are filled in depends
+    /// on the platform; see `PresentRecord`. Returns an empty list for generic surfaces.
+    fn presentation_timestamps(&self, surface: &Self::Surface) -> Vec<PresentRecord>;
+
+    /// Returns when the window system presented the most recent frame of a widget surface that
+    /// is known to have reached the display, or the most recent frame if none is yet.
+    ///
+    /// The times come from the window system, and are `None` where it doesn't report them; see
+    /// `presentation_time` for what each platform reports. Returns `Error::NoWidgetAttached` for
+    /// generic surfaces, and `Error::NotPresented` if nothing has been presented on the surface.
+    fn query_presentation_time(
+        &self,
+        surface: &Self::Surface,
+    ) -> Result<PresentationStatistics, Error>;
+
     /// Returns the OpenGL texture object containing the contents of this surface.
     ///
     /// It is only legal to read from, not write to, this texture object.
     fn surface_texture_object(&self, surface_texture: &Self::SurfaceTexture) -> GLuint;
+
+    /// Returns the sized internal format of the OpenGL texture of this surface texture, such as
+    /// `GL_RGBA8` or `GL_RGBA16F`.
+    ///
+    /// This is the `SurfaceInfo::format` of the wrapped surface, which tells shaders whether they
+    /// sample normalized or floating-point values.
+    fn surface_texture_format(&self, surface_texture: &Self::SurfaceTexture) -> GLenum;
 }
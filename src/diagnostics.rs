@@ -0,0 +1,291 @@
+// surfman/surfman/src/diagnostics.rs
+//
+//! Reports on the environment that surfman is running in.
+//!
+//! Sandboxes such as Flatpak and snap can hide the display server sockets and GPU device nodes
+//! that surfman needs, which usually surfaces as a bare `Error::ConnectionFailed`. An
+//! `EnvironmentReport` names what is missing so that applications can tell their users which
+//! permission to grant.
+//!
+//! Once a context has been created, `EnvironmentReport::check_context()` adds whether it renders
+//! on a GPU to the report, since a sandbox without GPU access falls back to software rendering
+//! without failing.
+
+use crate::device::Device as DeviceInterface;
+
+use std::env;
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+
+const FLATPAK_INFO_PATH: &str = "/.flatpak-info";
+const DRI_DIRECTORY: &str = "/dev/dri";
+const X11_SOCKET_DIRECTORY: &str = "/tmp/.X11-unix";
+
+#[cfg(target_arch = "x86_64")]
+const MULTIARCH_TRIPLET: Option<&str> = Some("x86_64-linux-gnu");
+#[cfg(target_arch = "x86")]
+const MULTIARCH_TRIPLET: Option<&str> = Some("i386-linux-gnu");
+#[cfg(target_arch = "aarch64")]
+const MULTIARCH_TRIPLET: Option<&str> = Some("aarch64-linux-gnu");
+#[cfg(target_arch = "arm")]
+const MULTIARCH_TRIPLET: Option<&str> = Some("arm-linux-gnueabihf");
+#[cfg(not(any(
+    target_arch = "x86_64",
+    target_arch = "x86",
+    target_arch = "aarch64",
+    target_arch = "arm"
+)))]
+const MULTIARCH_TRIPLET: Option<&str> = None;
+
+/// A container that the application is running inside.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sandbox {
+    /// A Flatpak sandbox, detected by the presence of `/.flatpak-info`.
+    Flatpak,
+    /// A snap, detected by the `SNAP` environment variable.
+    Snap,
+}
+
+/// Something that the environment prevents surfman from using.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Restriction {
+    /// No DRM render node under `/dev/dri` can be opened, so only software rendering is
+    /// available. In Flatpak, this usually means the `--device=dri` permission is missing.
+    NoRenderNodeAccess,
+    /// No X11 display server can be reached.
+    NoX11,
+    /// No Wayland compositor can be reached.
+    NoWayland,
+    /// The only X11 server is XWayland running under a reachable Wayland compositor, so Wayland
+    /// should be used instead.
+    ///
+    /// The Unix multi backend already tries Wayland before X11.
+    XWaylandOnly,
+    /// The session bus, and so the XDG desktop portals, can't be reached.
+    NoPortals,
+}
+
+/// What surfman found out about the environment it is running in.
+///
+/// Create one with `EnvironmentReport::probe()`. Its `Display` implementation prints a short
+/// section that can be included in bug reports.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnvironmentReport {
+    /// The sandbox that the application is running inside, if any.
+    pub sandbox: Option<Sandbox>,
+    /// Everything that the environment prevents surfman from using.
+    pub restrictions: Vec<Restriction>,
+    /// Whether the context passed to `check_context()` is hardware accelerated, or `None` if no
+    /// context has been checked.
+    pub hardware_accelerated: Option<bool>,
+    /// The `GL_RENDERER` string of the context passed to `check_context()`, if it could be read.
+    pub renderer: Option<String>,
+}
+
+// The queries that `EnvironmentReport` makes of the system, so that tests can mock them.
+pub(crate) trait EnvironmentProbe {
+    fn env_var(&self, name: &str) -> Option<String>;
+    fn path_exists(&self, path: &Path) -> bool;
+    // Returns whether the device node or file can be opened for reading and writing.
+    fn can_open(&self, path: &Path) -> bool;
+    fn read_dir(&self, path: &Path) -> Vec<PathBuf>;
+}
+
+// Probes the real system.
+pub(crate) struct SystemProbe;
+
+impl EnvironmentProbe for SystemProbe {
+    fn env_var(&self, name: &str) -> Option<String> {
+        env::var(name).ok().filter(|value| !value.is_empty())
+    }
+
+    fn path_exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn can_open(&self, path: &Path) -> bool {
+        OpenOptions::new().read(true).write(true).open(path).is_ok()
+    }
+
+    fn read_dir(&self, path: &Path) -> Vec<PathBuf> {
+        match fs::read_dir(path) {
+            Ok(entries) => entries
+                .filter_map(|entry| Some(entry.ok()?.path()))
+                .collect(),
+            Err(_) => vec![],
+        }
+    }
+}
+
+impl EnvironmentReport {
+    /// Examines the environment that the application is running in.
+    ///
+    /// This only looks at files, device nodes, and environment variables, and never connects to
+    /// a display server, so it is safe to call after a connection has failed.
+    pub fn probe() -> EnvironmentReport {
+        EnvironmentReport::from_probe(&SystemProbe)
+    }
+
+    pub(crate) fn from_probe(probe: &dyn EnvironmentProbe) -> EnvironmentReport {
+        let mut restrictions = vec![];
+        if !render_node_accessible(probe) {
+            restrictions.push(Restriction::NoRenderNodeAccess);
+        }
+        let x11 = x11_reachable(probe);
+        let wayland = wayland_reachable(probe);
+        if !x11 {
+            restrictions.push(Restriction::NoX11);
+        }
+        if !wayland {
+            restrictions.push(Restriction::NoWayland);
+        }
+        // In a Wayland session, the X11 server is XWayland.
+        if x11 && wayland && probe.env_var("XDG_SESSION_TYPE").as_deref() == Some("wayland") {
+            restrictions.push(Restriction::XWaylandOnly);
+        }
+        if !session_bus_reachable(probe) {
+            restrictions.push(Restriction::NoPortals);
+        }
+        EnvironmentReport {
+            sandbox: detect_sandbox(probe),
+            restrictions,
+            hardware_accelerated: None,
+            renderer: None,
+        }
+    }
+
+    /// Adds whether `context` is hardware accelerated, as
+    /// `Device::context_is_hardware_accelerated()` tells, and the name of its renderer to the
+    /// report.
+    pub fn check_context<D>(&mut self, device: &D, context: &D::Context)
+    where
+        D: DeviceInterface,
+    {
+        self.hardware_accelerated = Some(device.context_is_hardware_accelerated(context));
+        self.renderer = device.context_info(context).ok().map(|info| info.renderer);
+    }
+
+    /// Returns true if the environment prevents surfman from using `restriction`.
+    #[inline]
+    pub fn is_restricted(&self, restriction: Restriction) -> bool {
+        self.restrictions.contains(&restriction)
+    }
+}
+
+impl Display for EnvironmentReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "Environment:")?;
+        match self.sandbox {
+            Some(sandbox) => writeln!(f, "  sandbox: {:?}", sandbox)?,
+            None => writeln!(f, "  sandbox: none")?,
+        }
+        if let Some(hardware_accelerated) = self.hardware_accelerated {
+            let renderer = self.renderer.as_deref().unwrap_or("unknown");
+            match hardware_accelerated {
+                true => writeln!(f, "  renderer: {} (hardware)", renderer)?,
+                false => writeln!(f, "  renderer: {} (software)", renderer)?,
+            }
+        }
+        if self.restrictions.is_empty() {
+            return writeln!(f, "  restrictions: none");
+        }
+        writeln!(f, "  restrictions:")?;
+        for restriction in &self.restrictions {
+            writeln!(f, "    {:?}", restriction)?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn detect_sandbox(probe: &dyn EnvironmentProbe) -> Option<Sandbox> {
+    if probe.path_exists(Path::new(FLATPAK_INFO_PATH)) {
+        Some(Sandbox::Flatpak)
+    } else if probe.env_var("SNAP").is_some() {
+        Some(Sandbox::Snap)
+    } else {
+        None
+    }
+}
+
+// Directories to search for system libraries such as libEGL if loading them by name fails.
+// Outside a sandbox, the dynamic linker's own search path is all there is.
+#[allow(dead_code)]
+pub(crate) fn library_search_dirs(probe: &dyn EnvironmentProbe) -> Vec<PathBuf> {
+    let triplet = match MULTIARCH_TRIPLET {
+        Some(triplet) => triplet,
+        None => return vec![],
+    };
+    match detect_sandbox(probe) {
+        // The runtime's GL extension, which holds the host-matching driver.
+        Some(Sandbox::Flatpak) => vec![
+            Path::new("/usr/lib").join(triplet).join("GL/default/lib"),
+            Path::new("/usr/lib").join(triplet),
+        ],
+        // Libraries staged into the snap itself.
+        Some(Sandbox::Snap) => {
+            let snap = PathBuf::from(probe.env_var("SNAP").unwrap());
+            vec![
+                snap.join("usr/lib").join(triplet),
+                snap.join("lib").join(triplet),
+            ]
+        }
+        None => vec![],
+    }
+}
+
+// Logs an `EnvironmentReport` when running in a sandbox, to explain why a connection failed.
+pub(crate) fn warn_if_sandboxed() {
+    let report = EnvironmentReport::probe();
+    if report.sandbox.is_some() {
+        warn!("Connecting failed inside a sandbox. {}", report);
+    }
+}
+
+fn render_node_accessible(probe: &dyn EnvironmentProbe) -> bool {
+    probe.read_dir(Path::new(DRI_DIRECTORY)).iter().any(|path| {
+        let name = path.file_name().and_then(|name| name.to_str());
+        matches!(name, Some(name) if name.starts_with("renderD")) && probe.can_open(path)
+    })
+}
+
+fn x11_reachable(probe: &dyn EnvironmentProbe) -> bool {
+    let display = match probe.env_var("DISPLAY") {
+        Some(display) => display,
+        None => return false,
+    };
+    // Only local displays, like `:0` or `:0.0`, have sockets that can be checked.
+    let number = match display.strip_prefix(':') {
+        Some(rest) => rest.split('.').next().unwrap_or(rest),
+        None => return true,
+    };
+    probe.path_exists(&Path::new(X11_SOCKET_DIRECTORY).join(format!("X{}", number)))
+}
+
+fn wayland_reachable(probe: &dyn EnvironmentProbe) -> bool {
+    let display = probe
+        .env_var("WAYLAND_DISPLAY")
+        .unwrap_or_else(|| "wayland-0".to_owned());
+    let display = Path::new(&display);
+    if display.is_absolute() {
+        return probe.path_exists(display);
+    }
+    match probe.env_var("XDG_RUNTIME_DIR") {
+        Some(runtime_dir) => probe.path_exists(&Path::new(&runtime_dir).join(display)),
+        None => false,
+    }
+}
+
+fn session_bus_reachable(probe: &dyn EnvironmentProbe) -> bool {
+    match probe.env_var("DBUS_SESSION_BUS_ADDRESS") {
+        Some(address) => match address.strip_prefix("unix:path=") {
+            Some(rest) => probe.path_exists(Path::new(rest.split(',').next().unwrap_or(rest))),
+            // Abstract sockets and other transports can't be checked without connecting.
+            None => true,
+        },
+        None => match probe.env_var("XDG_RUNTIME_DIR") {
+            Some(runtime_dir) => probe.path_exists(&Path::new(&runtime_dir).join("bus")),
+            None => false,
+        },
+    }
+}
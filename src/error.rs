@@ -2,6 +2,13 @@
 //
 //! Various errors that methods can produce.
 
+use crate::gl;
+use crate::{ContextID, SurfaceAccess};
+use euclid::default::{Rect, Size2D};
+use std::fmt::{self, Display, Formatter};
+use std::thread::ThreadId;
+use std::time::Duration;
+
 /// Various errors that methods can produce.
 #[derive(Debug)]
 pub enum Error {
@@ -21,6 +28,9 @@ pub enum Error {
     UnsupportedGLProfile,
     /// The system doesn't support the requested OpenGL API version.
     UnsupportedGLVersion,
+    /// The requested context attribute flags can't be combined, such as
+    /// `ContextAttributeFlags::NO_ERROR` with `ContextAttributeFlags::DEBUG`.
+    ConflictingContextAttributeFlags,
     /// Choosing an OpenGL pixel format failed.
     PixelFormatSelectionFailed(WindowingApiError),
     /// The system couldn't choose an OpenGL pixel format.
@@ -31,6 +41,23 @@ pub enum Error {
     ContextDestructionFailed(WindowingApiError),
     /// The system couldn't make the OpenGL context current or not current.
     MakeCurrentFailed(WindowingApiError),
+    /// The context couldn't be made current because another thread has it current, and must make
+    /// it not current first.
+    ContextCurrentOnAnotherThread {
+        /// The context.
+        context_id: ContextID,
+        /// The thread that has the context current.
+        thread: ThreadId,
+        /// The name of that thread, if it has one.
+        thread_name: Option<String>,
+        /// How long that thread had had the context current.
+        current_for: Duration,
+    },
+    /// The context has been lost, for example to a GPU reset or because the system reclaimed it
+    /// while the application was in the background.
+    ///
+    /// A lost context can only be destroyed. Recreate it, and usually its device as well.
+    ContextLost,
     /// The system OpenGL library couldn't be located.
     NoGLLibraryFound,
     /// An extension necessary for this library to function isn't supported.
@@ -39,8 +66,13 @@ pub enum Error {
     GLFunctionNotFound,
     /// This context renders to an externally-managed render target.
     ExternalRenderTarget,
-    /// A surface was already attached to this context.
-    SurfaceAlreadyBound,
+    /// The surface couldn't be bound, because it belongs to another context or because the
+    /// context already has a surface bound.
+    SurfaceAlreadyBound {
+        /// The context that the surface was created with, if it isn't the one that it was being
+        /// bound to; otherwise that context, which already has a surface bound.
+        context_id: ContextID,
+    },
     /// No suitable adapter could be found.
     NoAdapterFound,
     /// The device couldn't be opened.
@@ -49,6 +81,8 @@ pub enum Error {
     SurfaceCreationFailed(WindowingApiError),
     /// The system couldn't import a surface from another thread.
     SurfaceImportFailed(WindowingApiError),
+    /// The system couldn't export a surface as a native image handle.
+    SurfaceExportFailed(WindowingApiError),
     /// The system couldn't create a surface texture from a surface.
     SurfaceTextureCreationFailed(WindowingApiError),
     /// The system couldn't present a widget surface.
@@ -59,6 +93,35 @@ pub enum Error {
     NoCurrentConnection,
     /// The surface was not created from this context.
     IncompatibleSurface,
+    /// Two sizes that the operation needs to match don't.
+    ///
+    /// `Device::convert_surface()` returns this for surfaces of different sizes. A widget surface
+    /// can't be presented once its native window has been resized since the surface was last
+    /// sized, since the frame would be stretched or cropped to fit: resize the surface to
+    /// `actual` with `Device::resize_surface()`, redraw, and present again.
+    SurfaceSizeMismatch {
+        /// The size of the destination surface, or of the widget surface being presented.
+        expected: Size2D<i32>,
+        /// The size of the source surface, or of the native window as
+        /// `Device::query_surface_native_size()` reports it.
+        actual: Size2D<i32>,
+    },
+    /// A surface can't be created at, or resized to, this size: a side is zero or negative, as
+    /// for a minimized window, or longer than `GLCapabilities::max_texture_size`.
+    InvalidSurfaceSize(Size2D<i32>),
+    /// The rectangle doesn't lie within the surface, or has a negative width or height.
+    RectOutOfBounds(Rect<i32>),
+    /// The buffer given to `Device::read_surface_data()` or `Device::write_surface_data()` is too
+    /// small for the pixels of the rectangle.
+    BufferTooSmall {
+        /// The number of bytes that the pixels take up.
+        required: usize,
+        /// The length of the buffer.
+        actual: usize,
+    },
+    /// The row stride given to `Device::write_surface_data()` is shorter than a row of the
+    /// rectangle.
+    InvalidStride(usize),
     /// The context descriptor is from a hardware device, but this is a software device, or vice
     /// versa.
     IncompatibleContextDescriptor,
@@ -69,19 +132,65 @@ pub enum Error {
     /// The surface texture is from a hardware device, but this is a software device, or vice
     /// versa.
     IncompatibleSurfaceTexture,
+    /// The OpenGL texture doesn't exist in the context, usually because it was created in a
+    /// context that doesn't share with it.
+    IncompatibleTexture,
+    /// The OpenGL texture's format can't be used for a surface.
+    UnsupportedTextureFormat,
+    /// The surface wraps a texture that surfman didn't allocate, so surfman can't reallocate it.
+    ForeignTexture,
+    /// The platform can't create surfaces with the requested options, such as an sRGB
+    /// colorspace.
+    UnsupportedSurfaceFormat,
+    /// The platform can't present a widget surface in the requested `PresentMode`.
+    UnsupportedPresentMode,
+    /// The platform can't keep a widget surface's contents across presents, as
+    /// `SurfaceOptions::preserve_buffer` asked.
+    UnsupportedSwapBehavior,
     /// The surface has no window attachment.
     NoWidgetAttached,
     /// The surface has a window attachment.
     WidgetAttached,
+    /// Nothing has been presented on the widget surface yet.
+    NotPresented,
+    /// The widget surface's presentation is paused with `Device::pause_presentation()`, so the
+    /// frame was held instead of being shown, or presentation can't be paused again until it's
+    /// resumed.
+    PresentationPaused,
+    /// The `PausedPresentation` is the pause of another surface.
+    IncompatiblePausedPresentation,
+    /// The system couldn't create a fence, or couldn't make a context wait for one.
+    FenceFailed(WindowingApiError),
+    /// The `SurfaceFence` was created by another device.
+    IncompatibleSurfaceFence,
     /// The native widget is invalid.
     InvalidNativeWidget,
-    /// The surface was not created with the `CPU_READ_WRITE` flag, so it cannot be accessed from
-    /// the CPU.
+    /// The surface cannot be accessed from the CPU.
+    ///
+    /// Surfaces created with `SurfaceAccess::GPUOnly` report `InvalidSurfaceAccess` instead.
     SurfaceDataInaccessible,
+    /// The CPU tried to access the data of a surface directly, but the surface was created with an
+    /// access mode that doesn't allow it.
+    InvalidSurfaceAccess {
+        /// The access mode that the operation needs.
+        required: SurfaceAccess,
+        /// The access mode that the surface was created with.
+        actual: SurfaceAccess,
+    },
+    /// The surface was made volatile with `Device::set_surface_purgeable()`, and must be taken
+    /// back with `Device::reclaim_surface()` before it can be used.
+    ///
+    /// This is only checked in debug builds.
+    SurfaceNotReclaimed,
+    /// The color buffer of the surface is still needed after it's presented, so it can't be
+    /// invalidated then.
+    PreservedColorBuffer,
     /// The surface could not be locked for CPU reading due to an OS error.
     SurfaceLockFailed,
     /// A connection to the display server could not be opened.
     ConnectionFailed,
+    /// A connection to the display server with the given name could not be opened.
+    DisplayConnectionFailed(String),
     /// A connection to the window server is required to open a hardware device.
     ConnectionRequired,
     /// The adapter type does not match the supplied connection.
@@ -92,14 +201,124 @@ pub enum Error {
     IncompatibleRawDisplayHandle,
     /// The native context does not match the supplied device.
     IncompatibleNativeContext,
+    /// The native context is current on another thread, which must release it before surfman can
+    /// wrap it or share with it.
+    NativeContextCurrentElsewhere,
+    /// The native object can only be used on the thread that created it, such as a Wayland
+    /// surface that was sent to another thread inside a `NativeWidget`.
+    WrongThread {
+        /// The thread that created the native object.
+        owner: ThreadId,
+    },
     /// The native device does not match the supplied connection.
     IncompatibleNativeDevice,
+    /// The operation was abandoned because its device's `CancelToken` was cancelled.
+    Cancelled,
+    /// No EGL entry point could create a display for the native display. Each field is the error
+    /// that an entry point failed with, in the order they were tried, or `None` if the EGL
+    /// implementation lacks that entry point.
+    PlatformDisplayFailed {
+        /// The error from the core EGL 1.5 `eglGetPlatformDisplay()`.
+        get_platform_display: Option<WindowingApiError>,
+        /// The error from `eglGetPlatformDisplayEXT()`.
+        get_platform_display_ext: Option<WindowingApiError>,
+        /// The error from the legacy `eglGetDisplay()`.
+        get_display: Option<WindowingApiError>,
+    },
+    /// Both backends of a multi connection failed, with the default backend's error first and the
+    /// alternate backend's error second.
+    MultipleFailures(Box<Error>, Box<Error>),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Error::MultipleFailures(ref default_error, ref alternate_error) => write!(
+                f,
+                "default backend failed: {}; alternate backend failed: {}",
+                default_error, alternate_error
+            ),
+            Error::SurfaceSizeMismatch { expected, actual } => write!(
+                f,
+                "expected a size of {}x{}, but found {}x{}",
+                expected.width, expected.height, actual.width, actual.height
+            ),
+            Error::InvalidSurfaceSize(size) => {
+                write!(f, "surfaces can't be {}x{}", size.width, size.height)
+            }
+            Error::RectOutOfBounds(rect) => write!(
+                f,
+                "the {}x{} rect at ({}, {}) isn't within the surface",
+                rect.size.width, rect.size.height, rect.origin.x, rect.origin.y
+            ),
+            Error::BufferTooSmall { required, actual } => write!(
+                f,
+                "the buffer holds {} bytes, but {} are needed",
+                actual, required
+            ),
+            Error::InvalidStride(stride) => {
+                write!(f, "a stride of {} bytes is shorter than a row", stride)
+            }
+            Error::InvalidSurfaceAccess { required, actual } => write!(
+                f,
+                "surface access {:?} required, but the surface was created with {:?}",
+                required, actual
+            ),
+            Error::ConflictingContextAttributeFlags => write!(
+                f,
+                "no-error contexts can't also be debug or robust contexts"
+            ),
+            Error::ContextCurrentOnAnotherThread {
+                context_id,
+                thread,
+                ref thread_name,
+                current_for,
+            } => {
+                write!(
+                    f,
+                    "context {:?} is current on thread {:?}",
+                    context_id, thread
+                )?;
+                if let Some(ref thread_name) = *thread_name {
+                    write!(f, " (`{}`)", thread_name)?;
+                }
+                write!(f, ", and has been for {:?}", current_for)
+            }
+            Error::DisplayConnectionFailed(ref display_name) => {
+                write!(f, "couldn't connect to display `{}`", display_name)
+            }
+            Error::PlatformDisplayFailed {
+                get_platform_display,
+                get_platform_display_ext,
+                get_display,
+            } => {
+                let describe = |error: Option<WindowingApiError>| match error {
+                    None => "unavailable".to_owned(),
+                    Some(error) => format!("{:?}", error),
+                };
+                write!(
+                    f,
+                    "couldn't create an EGL display: eglGetPlatformDisplay: {}; \
+                     eglGetPlatformDisplayEXT: {}; eglGetDisplay: {}",
+                    describe(get_platform_display),
+                    describe(get_platform_display_ext),
+                    describe(get_display)
+                )
+            }
+            Error::WrongThread { owner } => write!(
+                f,
+                "the native object belongs to thread {:?} and can't be used on this one",
+                owner
+            ),
+            _ => write!(f, "{:?}", *self),
+        }
+    }
 }
 
 /// Abstraction of the errors that EGL, CGL, GLX, CGL, etc. return.
 ///
 /// They all tend to follow similar patterns.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum WindowingApiError {
     /// Miscellaneous error.
     Failed,
@@ -150,6 +369,7 @@ pub enum WindowingApiError {
     BadCodeModule,
     /// CGL: Invalid memory allocation; i.e. CGL couldn't allocate memory.
     /// EGL: EGL failed to allocate resources for the requested operation.
+    /// GL: There is not enough memory left to execute the command.
     BadAlloc,
     /// CGL: Invalid Core Graphics connection.
     BadConnection,
@@ -188,4 +408,92 @@ pub enum WindowingApiError {
     BadOperation,
     /// EGL: The EGL configuration is unsupported.
     BadConfig,
+    /// GL: The framebuffer object is not complete.
+    BadFramebufferOperation,
+}
+
+/// An error taken from the OpenGL error queue of a context, as returned by
+/// `Device::drain_gl_errors()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GlError {
+    /// The error, or `WindowingApiError::Failed` for codes that surfman doesn't know.
+    pub error: WindowingApiError,
+    /// The code that `glGetError()` returned.
+    pub code: u32,
+}
+
+impl GlError {
+    /// Translates a code returned by `glGetError()`.
+    #[inline]
+    pub fn new(code: u32) -> GlError {
+        GlError {
+            error: from_gl(code),
+            code,
+        }
+    }
+}
+
+impl Display for GlError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{:?} (0x{:04x})", self.error, self.code)
+    }
+}
+
+// The error codes of `eglGetError()`, which are spelled out here because the EGL bindings aren't
+// built on every platform.
+const EGL_NOT_INITIALIZED: i32 = 0x3001;
+const EGL_BAD_ACCESS: i32 = 0x3002;
+const EGL_BAD_ALLOC: i32 = 0x3003;
+const EGL_BAD_ATTRIBUTE: i32 = 0x3004;
+const EGL_BAD_CONFIG: i32 = 0x3005;
+const EGL_BAD_CONTEXT: i32 = 0x3006;
+const EGL_BAD_CURRENT_SURFACE: i32 = 0x3007;
+const EGL_BAD_DISPLAY: i32 = 0x3008;
+const EGL_BAD_MATCH: i32 = 0x3009;
+const EGL_BAD_NATIVE_PIXMAP: i32 = 0x300a;
+const EGL_BAD_NATIVE_WINDOW: i32 = 0x300b;
+const EGL_BAD_PARAMETER: i32 = 0x300c;
+const EGL_BAD_SURFACE: i32 = 0x300d;
+const EGL_CONTEXT_LOST: i32 = 0x300e;
+
+// `GL_CONTEXT_LOST`, from OpenGL 4.5 and `GL_KHR_robustness`.
+const GL_CONTEXT_LOST: u32 = 0x0507;
+
+/// Translates an error code returned by `eglGetError()` to a `WindowingApiError`.
+///
+/// Codes that aren't EGL errors, including `EGL_SUCCESS`, translate to `Failed`.
+pub fn from_egl(code: i32) -> WindowingApiError {
+    match code {
+        EGL_NOT_INITIALIZED => WindowingApiError::NotInitialized,
+        EGL_BAD_ACCESS => WindowingApiError::BadAccess,
+        EGL_BAD_ALLOC => WindowingApiError::BadAlloc,
+        EGL_BAD_ATTRIBUTE => WindowingApiError::BadAttribute,
+        EGL_BAD_CONFIG => WindowingApiError::BadConfig,
+        EGL_BAD_CONTEXT => WindowingApiError::BadContext,
+        EGL_BAD_CURRENT_SURFACE => WindowingApiError::BadCurrentSurface,
+        EGL_BAD_DISPLAY => WindowingApiError::BadDisplay,
+        EGL_BAD_SURFACE => WindowingApiError::BadSurface,
+        EGL_BAD_MATCH => WindowingApiError::BadMatch,
+        EGL_BAD_PARAMETER => WindowingApiError::BadParameter,
+        EGL_BAD_NATIVE_PIXMAP => WindowingApiError::BadNativePixmap,
+        EGL_BAD_NATIVE_WINDOW => WindowingApiError::BadNativeWindow,
+        EGL_CONTEXT_LOST => WindowingApiError::ContextLost,
+        _ => WindowingApiError::Failed,
+    }
+}
+
+/// Translates an error code returned by `glGetError()` to a `WindowingApiError`.
+///
+/// Codes that aren't OpenGL errors, including `GL_NO_ERROR`, translate to `Failed`, as do the
+/// stack overflow and underflow errors of the compatibility profile.
+pub fn from_gl(code: u32) -> WindowingApiError {
+    match code {
+        gl::INVALID_ENUM => WindowingApiError::BadEnumeration,
+        gl::INVALID_VALUE => WindowingApiError::BadValue,
+        gl::INVALID_OPERATION => WindowingApiError::BadOperation,
+        gl::INVALID_FRAMEBUFFER_OPERATION => WindowingApiError::BadFramebufferOperation,
+        gl::OUT_OF_MEMORY => WindowingApiError::BadAlloc,
+        GL_CONTEXT_LOST => WindowingApiError::ContextLost,
+        _ => WindowingApiError::Failed,
+    }
 }
@@ -0,0 +1,54 @@
+// surfman/surfman/src/fence.rs
+//
+//! Fences that let another context wait for a surface's frame on the GPU.
+//!
+//! A surface that another context consumes has to be finished rendering before the consumer reads
+//! it, which otherwise takes a `glFinish()` that stalls the producer's CPU until the GPU catches
+//! up. `Device::present_surface_with_fence()` instead inserts a fence into the producer's command
+//! stream right after the surface is presented, and the consumer passes it to
+//! `Device::wait_fence()`, which makes the consumer's GPU wait for it without blocking either CPU.
+//!
+//! Fences are EGL sync objects, so they're supported on the EGL backends, which are those
+//! of X11, Wayland, Android, OpenHarmony, and ANGLE. Waiting needs the `eglWaitSync()` of EGL 1.5.
+//!
+//! Where the display supports `EGL_ANDROID_native_fence_sync`, as on Android and with Mesa, fences
+//! are native fences instead, which are backed by a Linux sync file.
+//! `SurfaceFence::export_sync_fd()` hands that file to Vulkan, KMS, a Wayland compositor that
+//! expects explicit sync, or another process, and `Device::import_sync_fd()` turns a sync file
+//! from any of those into a fence.
+
+#[cfg(any(android_backend, angle, generic_egl_platform, ohos_backend))]
+use crate::platform::generic::egl::surface::EGLFence;
+#[cfg(unix)]
+use crate::Error;
+#[cfg(unix)]
+use std::os::fd::OwnedFd;
+
+/// A fence that signals once the GPU has finished every command that a context issued before a
+/// surface was presented, from `Device::present_surface_with_fence()`.
+///
+/// Pass it to `Device::wait_fence()` to make another context wait for it on the GPU. A fence can
+/// be waited on any number of times, by any context of the device that created it, and is
+/// destroyed when dropped. Fences can be sent to and shared between threads.
+#[derive(Debug)]
+pub struct SurfaceFence {
+    #[cfg(any(android_backend, angle, generic_egl_platform, ohos_backend))]
+    pub(crate) egl_fence: EGLFence,
+}
+
+assert_send_sync!(SurfaceFence);
+
+#[cfg(unix)]
+impl SurfaceFence {
+    /// Returns a new sync file descriptor that signals when this fence does.
+    ///
+    /// The descriptor belongs to the caller, and stays valid after the fence is dropped. This
+    /// needs `EGL_ANDROID_native_fence_sync`: fences from displays without it return
+    /// `RequiredExtensionUnavailable`.
+    pub fn export_sync_fd(&self) -> Result<OwnedFd, Error> {
+        #[cfg(any(android_backend, generic_egl_platform, ohos_backend))]
+        return self.egl_fence.export_sync_fd();
+        #[cfg(not(any(android_backend, generic_egl_platform, ohos_backend)))]
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+}
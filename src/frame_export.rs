@@ -0,0 +1,529 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Exporting the frames of a swap chain to the CPU, for screenshots and video capture.
+//!
+//! A `FrameExporter` is a secondary consumer of a swap chain. After the producer swaps buffers,
+//! it calls `FrameExporter::capture`, which borrows the new front buffer and queues it for a
+//! worker thread. The producer never waits for a readback to finish, unless it asked to with
+//! `Backpressure::Block`.
+//!
+//! The worker thread reads frames back asynchronously with its own context. For each frame it
+//! starts a GPU copy of the surface into a pixel buffer object and inserts a fence after it, then
+//! goes on to the next frame without waiting. Up to `MAX_READBACKS_IN_FLIGHT` copies can be in
+//! flight at once. As each fence signals, in order, the worker gives the surface back to the swap
+//! chain, maps the pixel buffer, and hands the pixels to a callback. Contexts without fence sync
+//! objects, which OpenGL 3.2 and OpenGL ES 3.0 provide, fall back to reading each frame back
+//! synchronously with `glReadPixels`, on the worker thread.
+//!
+//! While a frame is queued or being read back, it isn't the swap chain's front buffer, so the
+//! primary consumer won't see it. If the producer swaps again in the meantime, the exported frame
+//! is recycled as soon as its copy has finished.
+//!
+//! Readback goes through OpenGL rather than mapping the surface, so frames can be exported from
+//! swap chains whose surfaces are `SurfaceAccess::GPUOnly`.
+
+use crate::chains::SwapChain;
+use crate::connection::Connection as ConnectionAPI;
+use crate::device::Device as DeviceAPI;
+use crate::{DebugFill, Error, GLApi};
+use euclid::default::Size2D;
+use log::debug;
+use sparkle::gl::{self, Gl};
+use sparkle::gl::{GLsync, GLuint};
+use std::collections::VecDeque;
+use std::ptr;
+use std::rc::Rc;
+use std::slice;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+/// The most frames that the worker thread copies into pixel buffers before it waits for the
+/// oldest copy to finish.
+pub const MAX_READBACKS_IN_FLIGHT: usize = 3;
+
+// How long the worker thread waits for a copy to finish before checking for new frames, in
+// nanoseconds.
+const POLL_INTERVAL: u64 = 1_000_000;
+
+/// What `FrameExporter::capture` does when the export queue is full.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backpressure {
+    /// Skip the frame and leave it in the swap chain. Skipped frames are counted by
+    /// `FrameExporter::dropped_frames`.
+    Drop,
+    /// Wait on the render thread until the worker thread has room for the frame.
+    Block,
+}
+
+/// A frame that has been read back to the CPU.
+///
+/// The data is only valid for the duration of the callback; copy it out to keep it.
+#[derive(Debug)]
+pub struct ExportedFrame<'a> {
+    /// The pixels, as tightly-packed RGBA8 rows from the bottom of the image to the top.
+    pub data: &'a [u8],
+    /// The number of bytes between the start of one row and the start of the next.
+    pub stride: usize,
+    /// The size of the frame in pixels.
+    pub size: Size2D<i32>,
+    /// The ID that `FrameExporter::capture` returned for this frame.
+    pub frame_id: u64,
+    /// When `FrameExporter::capture` was called for this frame.
+    pub timestamp: Instant,
+    /// The debug fill that the producer's device had when the frame was swapped in. Anything
+    /// but `DebugFill::None` means that undrawn regions of the frame hold the fill's pattern.
+    pub debug_fill: DebugFill,
+}
+
+// A front buffer on its way to the worker thread.
+struct CapturedFrame<Surface> {
+    surface: Surface,
+    frame_id: u64,
+    timestamp: Instant,
+    debug_fill: DebugFill,
+}
+
+/// Reads back the frames of a swap chain on a worker thread.
+///
+/// The exporter must be dropped before its swap chain is destroyed, so that any frames in flight
+/// are returned to the swap chain first.
+pub struct FrameExporter<Device: DeviceAPI> {
+    swap_chain: SwapChain<Device>,
+    sender: Option<SyncSender<CapturedFrame<Device::Surface>>>,
+    worker: Option<JoinHandle<()>>,
+    backpressure: Backpressure,
+    next_frame_id: AtomicU64,
+    dropped_frames: Arc<AtomicU64>,
+}
+
+impl<Device> FrameExporter<Device>
+where
+    Device: DeviceAPI + 'static,
+    Device::Connection: ConnectionAPI<Device = Device> + Send,
+    <Device::Connection as ConnectionAPI>::Adapter: Send,
+    Device::ContextDescriptor: Clone + Send,
+    Device::Surface: Send,
+{
+    /// Starts a worker thread that exports the frames of `swap_chain`.
+    ///
+    /// The worker thread opens its own device on the same adapter as `device`, and creates a
+    /// context with the same descriptor as `context`. Up to `queue_length` frames may be waiting
+    /// for readback at once; `backpressure` says what happens to further frames. `callback` is
+    /// called on the worker thread with each frame, in order.
+    pub fn new<F>(
+        device: &Device,
+        context: &Device::Context,
+        swap_chain: &SwapChain<Device>,
+        queue_length: usize,
+        backpressure: Backpressure,
+        callback: F,
+    ) -> Result<FrameExporter<Device>, Error>
+    where
+        F: FnMut(ExportedFrame) + Send + 'static,
+    {
+        let connection = device.connection();
+        let adapter = device.adapter();
+        let context_descriptor = device.context_descriptor(context);
+        let worker_swap_chain = swap_chain.clone();
+
+        let (sender, receiver) = mpsc::sync_channel(queue_length);
+        let (setup_sender, setup_receiver) = mpsc::channel();
+        let worker = thread::spawn(move || {
+            let mut device = match connection.create_device(&adapter) {
+                Ok(device) => device,
+                Err(err) => return setup_sender.send(Err(err)).unwrap(),
+            };
+            let mut context = match device.create_context(&context_descriptor, None) {
+                Ok(context) => context,
+                Err(err) => return setup_sender.send(Err(err)).unwrap(),
+            };
+            if let Err(err) = device.make_context_current(&context) {
+                drop(device.destroy_context(&mut context));
+                return setup_sender.send(Err(err)).unwrap();
+            }
+            let async_readback = match device.capabilities(&context) {
+                Ok(capabilities) => capabilities.fence_sync,
+                Err(err) => {
+                    drop(device.destroy_context(&mut context));
+                    return setup_sender.send(Err(err)).unwrap();
+                }
+            };
+            setup_sender.send(Ok(())).unwrap();
+
+            let mut worker = ExportWorker {
+                gl: load_gl(&device, &context),
+                device,
+                context,
+                swap_chain: worker_swap_chain,
+                async_readback,
+                staging_buffer: vec![],
+                pixel_buffers: vec![],
+                in_flight: VecDeque::new(),
+            };
+            worker.run(receiver, callback);
+            worker.gl.delete_buffers(&worker.pixel_buffers);
+            worker.device.destroy_context(&mut worker.context).unwrap();
+        });
+
+        // The worker thread reports back once it has a current context.
+        match setup_receiver.recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => {
+                worker.join().unwrap();
+                return Err(err);
+            }
+            Err(_) => {
+                drop(worker.join());
+                return Err(Error::Failed);
+            }
+        }
+
+        Ok(FrameExporter {
+            swap_chain: swap_chain.clone(),
+            sender: Some(sender),
+            worker: Some(worker),
+            backpressure,
+            next_frame_id: AtomicU64::new(0),
+            dropped_frames: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// Queues the front buffer of the swap chain for export.
+    /// Called by the producer, after `SwapChain::swap_buffers`. The producer must have flushed
+    /// its rendering, so that the worker thread's context sees it.
+    ///
+    /// Returns the ID of the frame, or `None` if the swap chain has no front buffer or the frame
+    /// was dropped. Frame IDs count every front buffer, including dropped ones, so gaps in the
+    /// IDs passed to the callback show where frames were dropped.
+    pub fn capture(&self) -> Option<u64> {
+        let (surface, debug_fill) = self.swap_chain.take_pending_frame()?;
+        let frame = CapturedFrame {
+            surface,
+            frame_id: self.next_frame_id.fetch_add(1, Ordering::Relaxed),
+            timestamp: Instant::now(),
+            debug_fill,
+        };
+        let frame_id = frame.frame_id;
+
+        let sender = self.sender.as_ref().unwrap();
+        let result = match self.backpressure {
+            Backpressure::Drop => sender.try_send(frame),
+            Backpressure::Block => sender
+                .send(frame)
+                .map_err(|err| TrySendError::Disconnected(err.0)),
+        };
+        match result {
+            Ok(()) => Some(frame_id),
+            Err(TrySendError::Full(frame)) | Err(TrySendError::Disconnected(frame)) => {
+                debug!("Dropping exported frame {}", frame_id);
+                self.dropped_frames.fetch_add(1, Ordering::Relaxed);
+                self.swap_chain.restore_pending_surface(frame.surface);
+                None
+            }
+        }
+    }
+
+    /// Returns the number of frames that `capture` has dropped so far.
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames.load(Ordering::Relaxed)
+    }
+}
+
+impl<Device: DeviceAPI> Drop for FrameExporter<Device> {
+    // Waits for the worker thread to export any queued frames and shut down.
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        if let Some(worker) = self.worker.take() {
+            drop(worker.join());
+        }
+    }
+}
+
+// The state owned by the worker thread.
+struct ExportWorker<Device: DeviceAPI> {
+    device: Device,
+    context: Device::Context,
+    gl: Rc<Gl>,
+    swap_chain: SwapChain<Device>,
+    // Whether the context has fence sync objects, so that readbacks can be pipelined.
+    async_readback: bool,
+    // The destination of synchronous readbacks.
+    staging_buffer: Vec<u8>,
+    // Pixel buffer objects that aren't in use by a readback.
+    pixel_buffers: Vec<GLuint>,
+    // Asynchronous readbacks, oldest first.
+    in_flight: VecDeque<PendingReadback<Device::SurfaceTexture>>,
+}
+
+// A frame that is being copied into a pixel buffer object.
+struct PendingReadback<SurfaceTexture> {
+    surface_texture: SurfaceTexture,
+    pixel_buffer: GLuint,
+    fence: GLsync,
+    size: Size2D<i32>,
+    frame_id: u64,
+    timestamp: Instant,
+    debug_fill: DebugFill,
+}
+
+impl<Device: DeviceAPI> ExportWorker<Device> {
+    fn run<F>(&mut self, receiver: Receiver<CapturedFrame<Device::Surface>>, mut callback: F)
+    where
+        F: FnMut(ExportedFrame),
+    {
+        loop {
+            // With copies in flight, finish them while waiting for the next frame.
+            let frame = if self.in_flight.is_empty() {
+                match receiver.recv() {
+                    Ok(frame) => frame,
+                    Err(_) => break,
+                }
+            } else {
+                match receiver.try_recv() {
+                    Ok(frame) => frame,
+                    Err(TryRecvError::Empty) => {
+                        self.finish_readback(POLL_INTERVAL, &mut callback);
+                        continue;
+                    }
+                    Err(TryRecvError::Disconnected) => break,
+                }
+            };
+
+            if !self.async_readback {
+                self.read_frame(frame, &mut callback);
+                continue;
+            }
+            if self.in_flight.len() == MAX_READBACKS_IN_FLIGHT {
+                self.finish_readback(gl::TIMEOUT_IGNORED, &mut callback);
+            }
+            self.start_readback(frame);
+        }
+
+        while !self.in_flight.is_empty() {
+            self.finish_readback(gl::TIMEOUT_IGNORED, &mut callback);
+        }
+    }
+
+    // Reads a frame back synchronously, and passes it to the callback.
+    fn read_frame<F>(&mut self, frame: CapturedFrame<Device::Surface>, callback: &mut F)
+    where
+        F: FnMut(ExportedFrame),
+    {
+        let size = self.device.surface_info(&frame.surface).size;
+        let surface_texture = match self
+            .device
+            .create_surface_texture(&mut self.context, frame.surface)
+        {
+            Ok(surface_texture) => surface_texture,
+            Err((err, surface)) => {
+                debug!("Failed to export frame {}: {:?}", frame.frame_id, err);
+                self.swap_chain.restore_pending_surface(surface);
+                return;
+            }
+        };
+
+        let framebuffer = self.bind_framebuffer(&surface_texture);
+        if let Some(framebuffer) = framebuffer {
+            self.staging_buffer
+                .resize(size.width as usize * size.height as usize * 4, 0);
+            self.gl.read_pixels_into_buffer(
+                0,
+                0,
+                size.width,
+                size.height,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                &mut self.staging_buffer,
+            );
+            self.unbind_framebuffer(framebuffer);
+        }
+
+        // Give the surface back before calling out, so slow callbacks don't hold it.
+        self.restore_surface(surface_texture);
+        match framebuffer {
+            Some(_) => callback(ExportedFrame {
+                data: &self.staging_buffer,
+                stride: size.width as usize * 4,
+                size,
+                frame_id: frame.frame_id,
+                timestamp: frame.timestamp,
+                debug_fill: frame.debug_fill,
+            }),
+            None => debug!(
+                "Failed to export frame {}: incomplete framebuffer",
+                frame.frame_id
+            ),
+        }
+    }
+
+    // Starts copying a frame into a pixel buffer object, and fences the copy.
+    fn start_readback(&mut self, frame: CapturedFrame<Device::Surface>) {
+        let size = self.device.surface_info(&frame.surface).size;
+        let surface_texture = match self
+            .device
+            .create_surface_texture(&mut self.context, frame.surface)
+        {
+            Ok(surface_texture) => surface_texture,
+            Err((err, surface)) => {
+                debug!("Failed to export frame {}: {:?}", frame.frame_id, err);
+                self.swap_chain.restore_pending_surface(surface);
+                return;
+            }
+        };
+        let framebuffer = match self.bind_framebuffer(&surface_texture) {
+            Some(framebuffer) => framebuffer,
+            None => {
+                debug!(
+                    "Failed to export frame {}: incomplete framebuffer",
+                    frame.frame_id
+                );
+                self.restore_surface(surface_texture);
+                return;
+            }
+        };
+
+        let gl = &self.gl;
+        let pixel_buffer = self
+            .pixel_buffers
+            .pop()
+            .unwrap_or_else(|| gl.gen_buffers(1)[0]);
+        let length = size.width as usize * size.height as usize * 4;
+        gl.bind_buffer(gl::PIXEL_PACK_BUFFER, pixel_buffer);
+        unsafe {
+            gl.buffer_data(
+                gl::PIXEL_PACK_BUFFER,
+                length as _,
+                ptr::null(),
+                gl::STREAM_READ,
+            );
+        }
+        gl.pixel_store_i(gl::PACK_ALIGNMENT, 1);
+        unsafe {
+            gl.read_pixels_into_pixel_pack_buffer(
+                0,
+                0,
+                size.width,
+                size.height,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                0,
+            );
+        }
+        gl.bind_buffer(gl::PIXEL_PACK_BUFFER, 0);
+        let fence = gl.fence_sync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+        gl.flush();
+        self.unbind_framebuffer(framebuffer);
+
+        self.in_flight.push_back(PendingReadback {
+            surface_texture,
+            pixel_buffer,
+            fence,
+            size,
+            frame_id: frame.frame_id,
+            timestamp: frame.timestamp,
+            debug_fill: frame.debug_fill,
+        });
+    }
+
+    // Waits up to `timeout` nanoseconds for the oldest copy to finish. If it does, gives its
+    // surface back to the swap chain and passes the pixels to the callback.
+    fn finish_readback<F>(&mut self, timeout: u64, callback: &mut F)
+    where
+        F: FnMut(ExportedFrame),
+    {
+        let fence = match self.in_flight.front() {
+            Some(readback) => readback.fence,
+            None => return,
+        };
+        let result = self
+            .gl
+            .client_wait_sync(fence, gl::SYNC_FLUSH_COMMANDS_BIT, timeout);
+        if result == gl::TIMEOUT_EXPIRED {
+            return;
+        }
+
+        let readback = self.in_flight.pop_front().unwrap();
+        self.gl.delete_sync(readback.fence);
+        // Give the surface back before calling out, so slow callbacks don't hold it.
+        self.restore_surface(readback.surface_texture);
+
+        let gl = &self.gl;
+        let length = readback.size.width as usize * readback.size.height as usize * 4;
+        gl.bind_buffer(gl::PIXEL_PACK_BUFFER, readback.pixel_buffer);
+        let data = match result {
+            gl::WAIT_FAILED => ptr::null_mut(),
+            _ => gl.map_buffer_range(gl::PIXEL_PACK_BUFFER, 0, length as _, gl::MAP_READ_BIT),
+        };
+        if data.is_null() {
+            debug!(
+                "Failed to export frame {}: couldn't map the pixels",
+                readback.frame_id
+            );
+        } else {
+            // The fence has been waited on, so the pixel buffer holds the whole frame.
+            callback(ExportedFrame {
+                data: unsafe { slice::from_raw_parts(data as *const u8, length) },
+                stride: readback.size.width as usize * 4,
+                size: readback.size,
+                frame_id: readback.frame_id,
+                timestamp: readback.timestamp,
+                debug_fill: readback.debug_fill,
+            });
+            gl.unmap_buffer(gl::PIXEL_PACK_BUFFER);
+        }
+        gl.bind_buffer(gl::PIXEL_PACK_BUFFER, 0);
+        self.pixel_buffers.push(readback.pixel_buffer);
+    }
+
+    // Binds a new framebuffer with the surface texture attached, or returns `None` if it's
+    // incomplete.
+    fn bind_framebuffer(&self, surface_texture: &Device::SurfaceTexture) -> Option<GLuint> {
+        let gl = &self.gl;
+        let framebuffer = gl.gen_framebuffers(1)[0];
+        gl.bind_framebuffer(gl::FRAMEBUFFER, framebuffer);
+        gl.framebuffer_texture_2d(
+            gl::FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            self.device.surface_gl_texture_target(),
+            self.device.surface_texture_object(surface_texture),
+            0,
+        );
+        if gl.check_framebuffer_status(gl::FRAMEBUFFER) == gl::FRAMEBUFFER_COMPLETE {
+            return Some(framebuffer);
+        }
+        self.unbind_framebuffer(framebuffer);
+        None
+    }
+
+    fn unbind_framebuffer(&self, framebuffer: GLuint) {
+        self.gl.bind_framebuffer(gl::FRAMEBUFFER, 0);
+        self.gl.delete_framebuffers(&[framebuffer]);
+    }
+
+    // Unwraps the surface texture and gives the surface back to the swap chain.
+    fn restore_surface(&mut self, surface_texture: Device::SurfaceTexture) {
+        match self
+            .device
+            .destroy_surface_texture(&mut self.context, surface_texture)
+        {
+            Ok(surface) => self.swap_chain.restore_pending_surface(surface),
+            Err((err, _)) => panic!("Failed to destroy the export surface texture: {:?}", err),
+        }
+    }
+}
+
+pub(crate) fn load_gl<Device: DeviceAPI>(device: &Device, context: &Device::Context) -> Rc<Gl> {
+    match device.gl_api() {
+        GLApi::GL => Gl::gl_fns(gl::ffi_gl::Gl::load_with(
+            device.proc_address_loader(context),
+        )),
+        GLApi::GLES => Gl::gles_fns(gl::ffi_gles::Gles2::load_with(
+            device.proc_address_loader(context),
+        )),
+    }
+}
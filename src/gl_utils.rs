@@ -2,9 +2,17 @@
 //
 //! Various OpenGL utilities used by the different backends.
 
+use crate::error::GlError;
 use crate::gl;
 use crate::gl::types::{GLenum, GLint, GLuint};
-use crate::Gl;
+use crate::memory::GL_RGB565;
+use crate::{ContextAttributeFlags, ContextAttributes, Gl, RenderTargetInfo, SurfaceInfo};
+use euclid::default::{Point2D, Size2D};
+use log::warn;
+
+// Drivers that have lost their context, or that have none current, may report an error on every
+// call to `glGetError()`, so the queue is only read this far.
+const MAX_DRAINED_ERRORS: usize = 64;
 
 #[allow(dead_code)]
 pub(crate) fn create_and_bind_framebuffer(
@@ -49,3 +57,122 @@ pub(crate) fn destroy_framebuffer(gl: &Gl, framebuffer_object: GLuint) {
         gl.DeleteFramebuffers(1, &framebuffer_object);
     }
 }
+
+// Binds the framebuffer of the surface attached to the current context and points the viewport at
+// all of it.
+//
+// Without a surface, the default framebuffer is bound and the viewport is left alone.
+pub(crate) fn bind_render_target(
+    gl: &Gl,
+    surface_info: Option<&SurfaceInfo>,
+    attributes: &ContextAttributes,
+) -> RenderTargetInfo {
+    let origin = Point2D::zero();
+    unsafe {
+        match surface_info {
+            None => {
+                gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+                RenderTargetInfo {
+                    framebuffer_object: 0,
+                    size: Size2D::zero(),
+                    origin,
+                    format: gl::NONE,
+                }
+            }
+            Some(surface_info) => {
+                gl.BindFramebuffer(gl::FRAMEBUFFER, surface_info.framebuffer_object);
+                gl.Viewport(
+                    origin.x,
+                    origin.y,
+                    surface_info.size.width,
+                    surface_info.size.height,
+                );
+                let format = if surface_info.format == GL_RGB565 || surface_info.format == gl::RGBA4
+                {
+                    surface_info.format
+                } else if attributes.flags.contains(ContextAttributeFlags::ALPHA) {
+                    gl::RGBA8
+                } else {
+                    gl::RGB8
+                };
+                RenderTargetInfo {
+                    framebuffer_object: surface_info.framebuffer_object,
+                    size: surface_info.size,
+                    origin,
+                    format,
+                }
+            }
+        }
+    }
+}
+
+// Undoes `bind_render_target()`, optionally flushing the current context.
+pub(crate) fn unbind_render_target(gl: &Gl, flush: bool) {
+    unsafe {
+        gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        if flush {
+            gl.Flush();
+        }
+    }
+}
+
+// Empties the error queue of the current context, returning each error that `glGetError()`
+// reported until it reported none.
+pub(crate) fn drain_gl_errors(gl: &Gl) -> Vec<GlError> {
+    let mut errors = vec![];
+    while errors.len() < MAX_DRAINED_ERRORS {
+        match unsafe { gl.GetError() } {
+            gl::NO_ERROR => break,
+            code => errors.push(GlError::new(code)),
+        }
+    }
+    errors
+}
+
+// Empties the error queue of the current context before surfman runs GL commands whose errors it
+// checks, so that errors that the application left behind aren't blamed on surfman. They are
+// logged instead.
+pub(crate) fn drain_pre_existing_gl_errors(gl: &Gl) {
+    let errors = drain_gl_errors(gl);
+    if !errors.is_empty() {
+        warn!(
+            "Pre-existing GL errors drained before a surfman check: {:?}",
+            errors
+        );
+    }
+}
+
+// Returns whether an entry point that the platform's loader returned is really available.
+//
+// Loaders hand out stubs for entry points that the driver doesn't implement, so entry points named
+// with an extension's vendor suffix, like the `EXT` of `glDrawBuffersEXT`, are only trusted if an
+// extension from that vendor, like `GL_EXT_draw_buffers`, is supported. `extensions` returns the
+// extensions of the API that the entry point belongs to: `"GL"`, `"EGL"`, or `"WGL"`.
+pub(crate) fn entry_point_available<F>(symbol_name: &str, extensions: F) -> bool
+where
+    F: FnOnce(&str) -> Vec<String>,
+{
+    let api = if symbol_name.starts_with("egl") {
+        "EGL"
+    } else if symbol_name.starts_with("wgl") {
+        "WGL"
+    } else {
+        "GL"
+    };
+    let suffix_start = match symbol_name.rfind(|c: char| !c.is_ascii_uppercase()) {
+        Some(index) => index + 1,
+        None => return true,
+    };
+    let mut suffix = &symbol_name[suffix_start..];
+    // The `D` of dimensions, as in `glTexImage3DOES`, isn't part of the suffix.
+    if symbol_name[..suffix_start].ends_with(|c: char| c.is_ascii_digit()) {
+        suffix = suffix.strip_prefix('D').unwrap_or(suffix);
+    }
+    if suffix.len() < 2 {
+        return true;
+    }
+    let prefix = format!("{}_{}_", api, suffix);
+    extensions(api)
+        .iter()
+        .any(|extension| extension.starts_with(&prefix))
+}
@@ -0,0 +1,429 @@
+// surfman/surfman/src/identity.rs
+//
+//! Identities of the physical GPUs behind devices, for matching them across graphics APIs, and
+//! keys for finding the same adapter again in later runs.
+
+use crate::Error;
+
+#[cfg(any(x11_platform, test))]
+use euclid::default::Rect;
+#[cfg(feature = "serde")]
+use serde::de::{self, Deserialize, Deserializer};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+#[cfg(any(x11_platform, test))]
+use std::cmp::{self, Reverse};
+use std::fmt::{self, Display, Formatter};
+#[cfg(all(unix, not(target_os = "macos")))]
+use std::fs;
+#[cfg(all(unix, not(target_os = "macos")))]
+use std::os::unix::fs::MetadataExt;
+#[cfg(all(unix, not(target_os = "macos")))]
+use std::path::Path;
+use std::str::FromStr;
+
+/// Identifies the physical GPU that a device renders with.
+///
+/// Other APIs (Vulkan, VA-API, Direct3D, Metal) can report the same information, so this can be
+/// used to check that they are on the same GPU as surfman before sharing memory with it. Each
+/// field is only filled in on the platforms that report it. Compare identities with
+/// `identities_match()` rather than `==`, since different APIs may know about different device
+/// nodes for the same GPU.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DeviceIdentity {
+    /// The device number (`st_rdev`) of the DRM primary node, such as `/dev/dri/card0`.
+    pub drm_primary_node: Option<u64>,
+    /// The device number (`st_rdev`) of the DRM render node, such as `/dev/dri/renderD128`.
+    pub drm_render_node: Option<u64>,
+    /// The locally unique identifier of the DXGI adapter, with the high part in the upper 32 bits.
+    pub adapter_luid: Option<u64>,
+    /// The IORegistry ID of the Metal device.
+    pub registry_id: Option<u64>,
+    /// True if the device is a software rasterizer rather than a GPU.
+    pub software: bool,
+}
+
+impl DeviceIdentity {
+    /// Returns the identity of a software rasterizer.
+    #[inline]
+    pub fn software() -> DeviceIdentity {
+        DeviceIdentity {
+            software: true,
+            ..DeviceIdentity::default()
+        }
+    }
+
+    /// Returns the identity of the GPU with the given DRM device node, such as `/dev/dri/card0`
+    /// or `/dev/dri/renderD128`.
+    ///
+    /// The GPU's other node is filled in too if sysfs lists it, so that the identity matches ones
+    /// that only know about that node. Returns `None` if the path isn't a DRM device node.
+    #[cfg(all(unix, not(target_os = "macos")))]
+    pub fn from_drm_node<P>(path: P) -> Option<DeviceIdentity>
+    where
+        P: AsRef<Path>,
+    {
+        let path = fs::canonicalize(path).ok()?;
+        let mut identity = DeviceIdentity::default();
+        identity.add_drm_node(&path)?;
+
+        let siblings = Path::new("/sys/class/drm")
+            .join(path.file_name()?)
+            .join("device/drm");
+        if let Ok(entries) = fs::read_dir(siblings) {
+            for entry in entries.flatten() {
+                let node = path.with_file_name(entry.file_name());
+                identity.add_drm_node(&node);
+            }
+        }
+        Some(identity)
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn add_drm_node(&mut self, path: &Path) -> Option<()> {
+        let name = path.file_name()?.to_str()?;
+        let slot = if name.starts_with("renderD") {
+            &mut self.drm_render_node
+        } else if name.starts_with("card") {
+            &mut self.drm_primary_node
+        } else {
+            return None;
+        };
+        *slot = Some(fs::metadata(path).ok()?.rdev());
+        Some(())
+    }
+
+    // Returns the PCI vendor and device IDs of the GPU, from sysfs.
+    #[cfg(all(unix, not(target_os = "macos")))]
+    pub(crate) fn pci_id(&self) -> Option<(u32, u32)> {
+        let rdev = self.drm_render_node.or(self.drm_primary_node)? as libc::dev_t;
+        let device = Path::new("/sys/dev/char")
+            .join(format!("{}:{}", libc::major(rdev), libc::minor(rdev)))
+            .join("device");
+        let read_id = |name: &str| {
+            let id = fs::read_to_string(device.join(name)).ok()?;
+            u32::from_str_radix(id.trim().trim_start_matches("0x"), 16).ok()
+        };
+        Some((read_id("vendor")?, read_id("device")?))
+    }
+}
+
+/// What an adapter was chosen for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AdapterKind {
+    /// A high-performance hardware adapter, as `Connection::create_hardware_adapter()` prefers.
+    HighPerformance,
+    /// A low-power hardware adapter, as `Connection::create_low_power_adapter()` prefers.
+    LowPower,
+    /// A software rasterizer.
+    Software,
+}
+
+/// Describes an adapter in a way that stays valid across runs of the application, so that the
+/// user's choice of GPU can be saved in settings.
+///
+/// Get one with `Connection::adapter_key()`, and find the same adapter again, even if the system
+/// enumerates adapters in a different order, with `Connection::find_adapter()`. Keys can be
+/// saved as strings with `to_string()` and read back with `parse()`, or with `serde` when the
+/// `serde` feature is enabled.
+///
+/// Keys match when they come from the same backend, and have the same kind and PCI IDs. The
+/// LUID, IORegistry ID, and driver name are only hints: Windows and macOS assign the IDs afresh
+/// at boot, and the driver name can change when the driver is updated. They are used to tell
+/// apart identical GPUs, when the platform reports them.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AdapterKey {
+    /// The backend that the adapter was enumerated with, such as `"x11"` or `"wayland"`.
+    ///
+    /// Keys from one backend never match adapters from another, even on the same GPU.
+    pub backend: String,
+    /// What the adapter was chosen for.
+    pub kind: AdapterKind,
+    /// The PCI vendor and device IDs of the GPU, if the platform reports them.
+    pub pci_id: Option<(u32, u32)>,
+    /// The locally unique identifier of the DXGI adapter, on Windows.
+    pub adapter_luid: Option<u64>,
+    /// The IORegistry ID of the Metal device, on macOS.
+    pub registry_id: Option<u64>,
+    /// The name of the driver or device, if the platform reports it.
+    pub driver: Option<String>,
+}
+
+impl AdapterKey {
+    /// Returns true if the two keys describe the same adapter.
+    ///
+    /// This ignores the hints, so that keys still match after a reboot or a driver update.
+    pub fn matches(&self, other: &AdapterKey) -> bool {
+        self.backend == other.backend && self.kind == other.kind && self.pci_id == other.pci_id
+    }
+
+    // Returns true if the hints, where both keys have them, agree.
+    fn hints_agree(&self, other: &AdapterKey) -> bool {
+        fn agree<T: PartialEq>(a: &Option<T>, b: &Option<T>) -> bool {
+            a.is_none() || b.is_none() || a == b
+        }
+        agree(&self.adapter_luid, &other.adapter_luid)
+            && agree(&self.registry_id, &other.registry_id)
+            && agree(&self.driver, &other.driver)
+    }
+}
+
+// Returns the adapter among `candidates` that `key` describes, preferring one whose hints agree.
+//
+// Returns `Error::IncompatibleAdapter` if the key is from another backend, and
+// `Error::NoAdapterFound` if the hardware is gone.
+pub(crate) fn find_adapter<A, I>(backend: &str, key: &AdapterKey, candidates: I) -> Result<A, Error>
+where
+    I: IntoIterator<Item = (A, AdapterKey)>,
+{
+    if key.backend != backend {
+        return Err(Error::IncompatibleAdapter);
+    }
+    let mut matching: Vec<_> = candidates
+        .into_iter()
+        .filter(|(_, candidate)| key.matches(candidate))
+        .collect();
+    match matching
+        .iter()
+        .position(|(_, candidate)| key.hints_agree(candidate))
+    {
+        Some(index) => Ok(matching.swap_remove(index).0),
+        None if !matching.is_empty() => Ok(matching.swap_remove(0).0),
+        None => Err(Error::NoAdapterFound),
+    }
+}
+
+impl Display for AdapterKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            AdapterKind::HighPerformance => "high-performance",
+            AdapterKind::LowPower => "low-power",
+            AdapterKind::Software => "software",
+        })
+    }
+}
+
+// The format is `backend=...;kind=...`, followed by whichever of `pci`, `luid`, `registry`, and
+// `driver` are known. The driver name comes last, and runs to the end of the string, so that it
+// may contain any character.
+impl Display for AdapterKey {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "backend={};kind={}", self.backend, self.kind)?;
+        if let Some((vendor_id, device_id)) = self.pci_id {
+            write!(f, ";pci={:04x}:{:04x}", vendor_id, device_id)?;
+        }
+        if let Some(adapter_luid) = self.adapter_luid {
+            write!(f, ";luid={:x}", adapter_luid)?;
+        }
+        if let Some(registry_id) = self.registry_id {
+            write!(f, ";registry={:x}", registry_id)?;
+        }
+        if let Some(ref driver) = self.driver {
+            write!(f, ";driver={}", driver)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for AdapterKey {
+    type Err = Error;
+
+    /// Parses a key written by `to_string()`. Unknown fields are ignored.
+    fn from_str(string: &str) -> Result<AdapterKey, Error> {
+        let (fields, driver) = match string.find(";driver=") {
+            Some(index) => (&string[..index], Some(string[index + 8..].to_owned())),
+            None => (string, None),
+        };
+        let (mut backend, mut kind) = (None, None);
+        let mut key = AdapterKey {
+            backend: String::new(),
+            kind: AdapterKind::HighPerformance,
+            pci_id: None,
+            adapter_luid: None,
+            registry_id: None,
+            driver,
+        };
+        for field in fields.split(';') {
+            let mut parts = field.splitn(2, '=');
+            let (name, value) = match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) => (name, value),
+                _ => return Err(Error::Failed),
+            };
+            let hex = |value: &str| u64::from_str_radix(value, 16).map_err(|_| Error::Failed);
+            match name {
+                "backend" => backend = Some(value.to_owned()),
+                "kind" => {
+                    kind = Some(match value {
+                        "high-performance" => AdapterKind::HighPerformance,
+                        "low-power" => AdapterKind::LowPower,
+                        "software" => AdapterKind::Software,
+                        _ => return Err(Error::Failed),
+                    })
+                }
+                "pci" => {
+                    let mut ids = value.splitn(2, ':');
+                    let mut id = || {
+                        let id = ids.next().ok_or(Error::Failed)?;
+                        u32::from_str_radix(id, 16).map_err(|_| Error::Failed)
+                    };
+                    key.pci_id = Some((id()?, id()?));
+                }
+                "luid" => key.adapter_luid = Some(hex(value)?),
+                "registry" => key.registry_id = Some(hex(value)?),
+                _ => {}
+            }
+        }
+        key.backend = backend.ok_or(Error::Failed)?;
+        key.kind = kind.ok_or(Error::Failed)?;
+        Ok(key)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for AdapterKey {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for AdapterKey {
+    fn deserialize<D>(deserializer: D) -> Result<AdapterKey, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let string = String::deserialize(deserializer)?;
+        string
+            .parse()
+            .map_err(|_| de::Error::invalid_value(de::Unexpected::Str(&string), &"an adapter key"))
+    }
+}
+
+/// Returns true if the two identities are known to be the same GPU.
+///
+/// A DRM device matches if either its primary node or its render node does, since some APIs only
+/// report one of them. Software rasterizers only match each other. Identities with nothing in
+/// common, including ones the platform couldn't determine at all, don't match.
+pub fn identities_match(a: &DeviceIdentity, b: &DeviceIdentity) -> bool {
+    if a.software || b.software {
+        return a.software && b.software;
+    }
+    fn same(a: Option<u64>, b: Option<u64>) -> bool {
+        a.is_some() && a == b
+    }
+    same(a.drm_primary_node, b.drm_primary_node)
+        || same(a.drm_render_node, b.drm_render_node)
+        || same(a.adapter_luid, b.adapter_luid)
+        || same(a.registry_id, b.registry_id)
+}
+
+// A display output: where it is on the desktop, and the GPU that scans it out, if known.
+#[cfg(any(x11_platform, test))]
+#[derive(Clone, Debug)]
+pub(crate) struct DisplayOutput {
+    pub(crate) bounds: Rect<i32>,
+    pub(crate) identity: DeviceIdentity,
+}
+
+// Returns the identity of the GPU that scans out the output that the window covers the most of.
+//
+// A window that covers no output at all, such as one that was moved off screen, belongs to the
+// nearest output, as with `MONITOR_DEFAULTTONEAREST` on Windows. Ties go to the first output.
+// Returns `None` if there are no outputs or the GPU behind the window's output isn't known.
+#[cfg(any(x11_platform, test))]
+pub(crate) fn presenting_identity(
+    window: &Rect<i32>,
+    outputs: &[DisplayOutput],
+) -> Option<DeviceIdentity> {
+    let overlap = |bounds: &Rect<i32>| {
+        bounds
+            .intersection(window)
+            .map_or(0, |overlap| overlap.area() as i64)
+    };
+    let distance = |bounds: &Rect<i32>| {
+        let dx = cmp::max(
+            bounds.min_x() - window.max_x(),
+            window.min_x() - bounds.max_x(),
+        );
+        let dy = cmp::max(
+            bounds.min_y() - window.max_y(),
+            window.min_y() - bounds.max_y(),
+        );
+        let (dx, dy) = (cmp::max(dx, 0) as i64, cmp::max(dy, 0) as i64);
+        dx * dx + dy * dy
+    };
+    let output = outputs
+        .iter()
+        .min_by_key(|output| (Reverse(overlap(&output.bounds)), distance(&output.bounds)))?;
+    if output.identity == DeviceIdentity::default() {
+        return None;
+    }
+    Some(output.identity.clone())
+}
+
+// A connector of a DRM card with a display attached, as sysfs lists it.
+#[cfg(any(
+    x11_platform,
+    all(
+        test,
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_env = "ohos"))
+    )
+))]
+#[derive(Clone, Debug)]
+pub(crate) struct DrmConnector {
+    // The name of the card's device node, such as `card1`.
+    pub(crate) card: String,
+    pub(crate) edid: Vec<u8>,
+}
+
+// Returns the connectors of every DRM card that have a display attached.
+#[cfg(x11_platform)]
+pub(crate) fn drm_connectors() -> Vec<DrmConnector> {
+    let entries = match fs::read_dir("/sys/class/drm") {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    let mut connectors = vec![];
+    for entry in entries.flatten() {
+        // Connectors are named after their card, as in `card1-HDMI-A-1`.
+        let name = entry.file_name();
+        let card = match name.to_str().and_then(|name| name.split_once('-')) {
+            Some((card, _)) if card.starts_with("card") => card.to_owned(),
+            _ => continue,
+        };
+        match fs::read(entry.path().join("edid")) {
+            Ok(edid) if !edid.is_empty() => connectors.push(DrmConnector { card, edid }),
+            _ => {}
+        }
+    }
+    connectors
+}
+
+// Returns the card with a connector showing the display whose EDID is `edid`.
+//
+// Only the base blocks of the EDIDs are compared, since window systems don't always report the
+// extension blocks. The base block has the display's serial number, which tells identical
+// displays apart.
+#[cfg(any(
+    x11_platform,
+    all(
+        test,
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_env = "ohos"))
+    )
+))]
+pub(crate) fn drm_card_for_edid<'a>(
+    edid: &[u8],
+    connectors: &'a [DrmConnector],
+) -> Option<&'a str> {
+    const EDID_BASE_BLOCK_LENGTH: usize = 128;
+    let base_block = edid.get(..EDID_BASE_BLOCK_LENGTH)?;
+    connectors
+        .iter()
+        .find(|connector| connector.edid.get(..EDID_BASE_BLOCK_LENGTH) == Some(base_block))
+        .map(|connector| &*connector.card)
+}
@@ -8,6 +8,7 @@ use super::super::device::{Adapter, Device, NativeDevice};
 use super::super::surface::NativeWidget;
 use crate::connection::Connection as ConnectionInterface;
 use crate::info::GLApi;
+use crate::AdapterKey;
 use crate::Error;
 
 use euclid::default::Size2D;
@@ -27,6 +28,23 @@ impl ConnectionInterface for Connection {
         Connection::new()
     }
 
+    #[inline]
+    fn new_headless() -> Result<Connection, Error> {
+        Connection::new_headless()
+    }
+
+    #[inline]
+    fn new_with_api(gl_api: GLApi) -> Result<Connection, Error> {
+        Connection::new_with_api(gl_api)
+    }
+
+    #[inline]
+    unsafe fn from_native_connection(
+        native_connection: NativeConnection,
+    ) -> Result<Connection, Error> {
+        Connection::from_native_connection(native_connection)
+    }
+
     #[inline]
     fn native_connection(&self) -> Self::NativeConnection {
         Connection::native_connection(self)
@@ -37,6 +55,11 @@ impl ConnectionInterface for Connection {
         Connection::gl_api(self)
     }
 
+    #[inline]
+    fn supports_extension(&self, name: &str) -> bool {
+        Connection::supports_extension(self, name)
+    }
+
     #[inline]
     fn create_adapter(&self) -> Result<Adapter, Error> {
         Connection::create_adapter(self)
@@ -57,6 +80,31 @@ impl ConnectionInterface for Connection {
         Connection::create_software_adapter(self)
     }
 
+    #[inline]
+    fn create_adapter_by_name(&self, needle: &str) -> Result<Adapter, Error> {
+        Connection::create_adapter_by_name(self, needle)
+    }
+
+    #[inline]
+    fn adapter_key(&self, adapter: &Adapter) -> Result<AdapterKey, Error> {
+        Connection::adapter_key(self, adapter)
+    }
+
+    #[inline]
+    fn find_adapter(&self, key: &AdapterKey) -> Result<Adapter, Error> {
+        Connection::find_adapter(self, key)
+    }
+
+    #[inline]
+    fn is_same_adapter(&self, a: &Adapter, b: &Adapter) -> bool {
+        Connection::is_same_adapter(self, a, b)
+    }
+
+    #[inline]
+    fn adapter_for_widget(&self, native_widget: &NativeWidget) -> Result<Adapter, Error> {
+        Connection::adapter_for_widget(self, native_widget)
+    }
+
     #[inline]
     fn create_device(&self, adapter: &Adapter) -> Result<Device, Error> {
         Connection::create_device(self, adapter)
@@ -110,4 +158,9 @@ impl ConnectionInterface for Connection {
     ) -> Result<NativeWidget, Error> {
         Connection::create_native_widget_from_window_handle(self, window, size)
     }
+
+    #[inline]
+    fn native_widget_adjusted_size(&self, native_widget: &NativeWidget) -> Option<Size2D<i32>> {
+        Connection::native_widget_adjusted_size(self, native_widget)
+    }
 }
@@ -4,25 +4,66 @@
 //! `Device` trait for a backend.
 
 use super::super::connection::Connection;
-use super::super::context::{Context, ContextDescriptor, NativeContext};
+use super::super::context::{Context, ContextDescriptor, CurrentContextGuard, NativeContext};
 use super::super::device::{Adapter, Device};
-use super::super::surface::{NativeWidget, Surface, SurfaceTexture};
+use super::super::surface::{NativeWidget, Surface, SurfaceDataGuard, SurfaceTexture};
 use crate::connection::Connection as ConnectionInterface;
 use crate::device::Device as DeviceInterface;
 use crate::gl::types::{GLenum, GLuint};
-use crate::{ContextAttributes, ContextID, Error, GLApi, SurfaceAccess, SurfaceInfo, SurfaceType};
-use euclid::default::Size2D;
-
+use crate::BufferRelease;
+use crate::BulkOps;
+use crate::CancelToken;
+use crate::ContextAttributeFlags;
+use crate::ContextDescriptorAttributes;
+use crate::ContextInfo;
+use crate::ContextLostHandler;
+use crate::ContextPriority;
+use crate::DebugFill;
+#[cfg(unix)]
+use crate::DmaBuf;
+use crate::GLCapabilities;
+use crate::GLVersion;
+use crate::GlError;
+use crate::InvalidateSet;
+use crate::LowMemoryOptions;
+use crate::PausedPresentation;
+use crate::PresentationStatistics;
+use crate::SurfaceFence;
+use crate::SurfaceOptions;
+use crate::SurfaceResizedHandler;
+use crate::SurfaceScaleChangedHandler;
+use crate::Swizzle;
+use crate::TextureOwnership;
+use crate::{AutoVsyncPolicy, AutoVsyncStats};
+use crate::{
+    ColorProfile, ColorProfileChangedHandler, ContextAttributes, ContextID, DeviceIdentity, Error,
+    GLApi,
+};
+use crate::{
+    PresentRecord, RenderTargetInfo, ResetStatus, SurfaceAccess, SurfaceInfo, SurfaceType,
+};
+use crate::{Purgeability, ReclaimResult};
+use crate::{SchedulingClass, SchedulingEnforcement};
+use crate::{SurfaceConfig, SurfaceIntent, SurfaceTransform};
+use euclid::default::{Rect, Size2D};
+
+use std::ops::RangeInclusive;
+#[cfg(unix)]
+use std::os::fd::BorrowedFd;
 use std::os::raw::c_void;
+use std::thread::ThreadId;
+use std::time::Duration;
 
 #[deny(unconditional_recursion)]
 impl DeviceInterface for Device {
     type Connection = Connection;
     type Context = Context;
     type ContextDescriptor = ContextDescriptor;
+    type CurrentContextGuard = CurrentContextGuard;
     type NativeContext = NativeContext;
     type Surface = Surface;
     type SurfaceTexture = SurfaceTexture;
+    type SurfaceDataGuard<'s> = SurfaceDataGuard<'s>;
 
     // device.rs
 
@@ -47,6 +88,46 @@ impl DeviceInterface for Device {
         Device::gl_api(self)
     }
 
+    #[inline]
+    fn identity(&self) -> DeviceIdentity {
+        Device::identity(self)
+    }
+
+    #[inline]
+    fn cancellation_token(&self) -> CancelToken {
+        Device::cancellation_token(self)
+    }
+
+    #[inline]
+    fn set_scheduling_class(&mut self, class: SchedulingClass) -> SchedulingEnforcement {
+        Device::set_scheduling_class(self, class)
+    }
+
+    #[inline]
+    fn scheduling_class(&self) -> SchedulingClass {
+        Device::scheduling_class(self)
+    }
+
+    #[inline]
+    fn scheduling_enforcement(&self) -> SchedulingEnforcement {
+        Device::scheduling_enforcement(self)
+    }
+
+    #[inline]
+    fn enter_low_memory_mode(&mut self, options: LowMemoryOptions) -> usize {
+        Device::enter_low_memory_mode(self, options)
+    }
+
+    #[inline]
+    fn exit_low_memory_mode(&mut self) {
+        Device::exit_low_memory_mode(self)
+    }
+
+    #[inline]
+    fn low_memory_options(&self) -> Option<LowMemoryOptions> {
+        Device::low_memory_options(self)
+    }
+
     // context.rs
 
     #[inline]
@@ -66,6 +147,15 @@ impl DeviceInterface for Device {
         Device::create_context(self, descriptor, share_with)
     }
 
+    #[inline]
+    fn create_versioned_context(
+        &mut self,
+        versions: RangeInclusive<GLVersion>,
+        flags: ContextAttributeFlags,
+    ) -> Result<(Self::Context, Self::ContextDescriptor), Error> {
+        Device::create_versioned_context(self, versions, flags)
+    }
+
     #[inline]
     unsafe fn create_context_from_native_context(
         &self,
@@ -89,11 +179,32 @@ impl DeviceInterface for Device {
         Device::make_context_current(self, context)
     }
 
+    #[inline]
+    fn make_context_current_guarded(
+        &self,
+        context: &Self::Context,
+    ) -> Result<CurrentContextGuard, Error> {
+        Device::make_context_current_guarded(self, context)
+    }
+
+    #[inline]
+    fn with_context_current<F, R>(&self, context: &mut Context, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut BulkOps<Self>) -> R,
+    {
+        Device::with_context_current(self, context, f)
+    }
+
     #[inline]
     fn make_no_context_current(&self) -> Result<(), Error> {
         Device::make_no_context_current(self)
     }
 
+    #[inline]
+    fn context_current_thread(&self, context: &Context) -> Option<ThreadId> {
+        Device::context_current_thread(self, context)
+    }
+
     #[inline]
     fn context_descriptor_attributes(
         &self,
@@ -103,10 +214,38 @@ impl DeviceInterface for Device {
     }
 
     #[inline]
-    fn get_proc_address(&self, context: &Self::Context, symbol_name: &str) -> *const c_void {
+    fn context_descriptor_raw_parts(
+        &self,
+        context_descriptor: &Self::ContextDescriptor,
+    ) -> ContextDescriptorAttributes {
+        Device::context_descriptor_raw_parts(self, context_descriptor)
+    }
+
+    #[inline]
+    fn create_context_descriptor_from_attributes(
+        &self,
+        attributes: &ContextDescriptorAttributes,
+    ) -> Result<Self::ContextDescriptor, Error> {
+        Device::create_context_descriptor_from_attributes(self, attributes)
+    }
+
+    #[inline]
+    fn get_proc_address(
+        &self,
+        context: &Self::Context,
+        symbol_name: &str,
+    ) -> Option<*const c_void> {
         Device::get_proc_address(self, context, symbol_name)
     }
 
+    #[inline]
+    fn proc_address_loader<'a>(
+        &'a self,
+        context: &'a Self::Context,
+    ) -> Box<dyn Fn(&str) -> *const c_void + 'a> {
+        Device::proc_address_loader(self, context)
+    }
+
     #[inline]
     fn bind_surface_to_context(
         &self,
@@ -129,16 +268,81 @@ impl DeviceInterface for Device {
         Device::context_id(self, context)
     }
 
+    #[inline]
+    fn is_context_current(&self, context: &Self::Context) -> bool {
+        Device::is_context_current(self, context)
+    }
+
     #[inline]
     fn context_surface_info(&self, context: &Self::Context) -> Result<Option<SurfaceInfo>, Error> {
         Device::context_surface_info(self, context)
     }
 
+    #[inline]
+    fn begin_rendering(&self, context: &Self::Context) -> Result<RenderTargetInfo, Error> {
+        Device::begin_rendering(self, context)
+    }
+
+    #[inline]
+    fn end_rendering(&self, context: &Self::Context, flush: bool) -> Result<(), Error> {
+        Device::end_rendering(self, context, flush)
+    }
+
+    #[inline]
+    fn capabilities(&self, context: &Self::Context) -> Result<GLCapabilities, Error> {
+        Device::capabilities(self, context)
+    }
+
+    #[inline]
+    fn gl_extensions(&self, context: &Self::Context) -> Result<Vec<String>, Error> {
+        Device::gl_extensions(self, context)
+    }
+
+    #[inline]
+    fn context_info(&self, context: &Self::Context) -> Result<ContextInfo, Error> {
+        Device::context_info(self, context)
+    }
+
+    #[inline]
+    fn context_is_hardware_accelerated(&self, context: &Self::Context) -> bool {
+        Device::context_is_hardware_accelerated(self, context)
+    }
+
+    #[inline]
+    fn drain_gl_errors(&self, context: &Self::Context) -> Result<Vec<GlError>, Error> {
+        Device::drain_gl_errors(self, context)
+    }
+
+    #[inline]
+    fn context_reset_status(&self, context: &Self::Context) -> Result<ResetStatus, Error> {
+        Device::context_reset_status(self, context)
+    }
+
+    #[inline]
+    fn poll_context_lost(&self, context: &Self::Context) -> bool {
+        Device::poll_context_lost(self, context)
+    }
+
+    #[inline]
+    fn set_context_lost_handler(&mut self, handler: Option<ContextLostHandler>) {
+        Device::set_context_lost_handler(self, handler)
+    }
+
+    #[inline]
+    fn context_priority(&self, context: &Self::Context) -> ContextPriority {
+        Device::context_priority(self, context)
+    }
+
     #[inline]
     fn native_context(&self, context: &Self::Context) -> Self::NativeContext {
         Device::native_context(self, context)
     }
 
+    #[inline]
+    fn current_native_context(&self) -> Result<Self::NativeContext, Error> {
+        Device::current_native_context(self)
+    }
+
     // surface.rs
 
     #[inline]
@@ -151,6 +355,40 @@ impl DeviceInterface for Device {
         Device::create_surface(self, context, surface_access, surface_type)
     }
 
+    #[inline]
+    fn create_surfaces<I>(
+        &mut self,
+        context: &mut Self::Context,
+        surface_access: SurfaceAccess,
+        surface_types: I,
+    ) -> Result<Vec<Self::Surface>, Error>
+    where
+        I: IntoIterator<Item = SurfaceType<NativeWidget>>,
+    {
+        Device::create_surfaces(self, context, surface_access, surface_types)
+    }
+
+    #[inline]
+    fn create_surface_with_options(
+        &mut self,
+        context: &Self::Context,
+        surface_access: SurfaceAccess,
+        surface_type: SurfaceType<NativeWidget>,
+        options: SurfaceOptions,
+    ) -> Result<Self::Surface, Error> {
+        Device::create_surface_with_options(self, context, surface_access, surface_type, options)
+    }
+
+    #[inline]
+    fn recommended_surface_config(
+        &self,
+        context: &Self::Context,
+        intent: SurfaceIntent<NativeWidget>,
+        size: Size2D<i32>,
+    ) -> Result<SurfaceConfig<NativeWidget>, Error> {
+        Device::recommended_surface_config(self, context, intent, size)
+    }
+
     #[inline]
     fn create_surface_texture(
         &self,
@@ -171,6 +409,18 @@ impl DeviceInterface for Device {
         Device::create_surface_texture_from_gl(self, context, size, texture_object, egl_target)
     }
 
+    #[inline]
+    fn create_surface_from_texture(
+        &mut self,
+        context: &mut Context,
+        texture_object: GLuint,
+        size: &Size2D<i32>,
+        format: GLenum,
+        ownership: TextureOwnership,
+    ) -> Result<Surface, Error> {
+        Device::create_surface_from_texture(self, context, texture_object, size, format, ownership)
+    }
+
     #[inline]
     fn destroy_surface(
         &self,
@@ -203,6 +453,60 @@ impl DeviceInterface for Device {
         Device::present_surface(self, context, surface)
     }
 
+    #[inline]
+    fn present_surface_with_damage(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        Device::present_surface_with_damage(self, context, surface, damage)
+    }
+
+    #[inline]
+    fn pause_presentation(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+    ) -> Result<PausedPresentation, Error> {
+        Device::pause_presentation(self, context, surface)
+    }
+
+    #[inline]
+    fn resume_presentation(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+        paused: PausedPresentation,
+        present_pending: bool,
+    ) -> Result<(), Error> {
+        Device::resume_presentation(self, context, surface, paused, present_pending)
+    }
+
+    #[inline]
+    fn present_surface_with_fence(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+    ) -> Result<SurfaceFence, Error> {
+        Device::present_surface_with_fence(self, context, surface)
+    }
+
+    #[inline]
+    fn wait_fence(&self, context: &Self::Context, fence: &SurfaceFence) -> Result<(), Error> {
+        Device::wait_fence(self, context, fence)
+    }
+
+    #[inline]
+    #[cfg(unix)]
+    fn import_sync_fd(
+        &self,
+        context: &Self::Context,
+        fd: BorrowedFd,
+    ) -> Result<SurfaceFence, Error> {
+        Device::import_sync_fd(self, context, fd)
+    }
+
     #[inline]
     fn resize_surface(
         &self,
@@ -213,13 +517,226 @@ impl DeviceInterface for Device {
         Device::resize_surface(self, context, surface, size)
     }
 
+    #[inline]
+    fn set_swap_interval(
+        &mut self,
+        context: &Context,
+        surface: &Surface,
+        interval: i32,
+    ) -> Result<i32, Error> {
+        Device::set_swap_interval(self, context, surface, interval)
+    }
+
+    #[inline]
+    fn supports_adaptive_swap(&self, surface: &Surface) -> bool {
+        Device::supports_adaptive_swap(self, surface)
+    }
+
+    #[inline]
+    fn set_auto_swap_interval(
+        &mut self,
+        context: &Context,
+        surface: &mut Surface,
+        policy: Option<AutoVsyncPolicy>,
+    ) -> Result<(), Error> {
+        Device::set_auto_swap_interval(self, context, surface, policy)
+    }
+
+    #[inline]
+    fn auto_swap_interval_stats(&self, surface: &Surface) -> Option<AutoVsyncStats> {
+        Device::auto_swap_interval_stats(self, surface)
+    }
+
+    #[inline]
+    fn wait_previous_buffer_released(
+        &self,
+        surface: &Surface,
+        timeout: Duration,
+    ) -> Result<BufferRelease, Error> {
+        Device::wait_previous_buffer_released(self, surface, timeout)
+    }
+
+    #[inline]
+    fn set_post_present_invalidation(
+        &self,
+        surface: &mut Surface,
+        set: InvalidateSet,
+    ) -> Result<(), Error> {
+        Device::set_post_present_invalidation(self, surface, set)
+    }
+
+    #[inline]
+    fn post_present_invalidation(&self, surface: &Surface) -> InvalidateSet {
+        Device::post_present_invalidation(self, surface)
+    }
+
+    #[inline]
+    fn invalidate_surface(&self, context: &Context, surface: &mut Surface) -> Result<(), Error> {
+        Device::invalidate_surface(self, context, surface)
+    }
+
+    #[inline]
+    fn surface_buffer_age(&self, surface: &Surface) -> Result<u32, Error> {
+        Device::surface_buffer_age(self, surface)
+    }
+
+    #[inline]
+    fn query_surface_native_size(&self, surface: &Surface) -> Result<Size2D<i32>, Error> {
+        Device::query_surface_native_size(self, surface)
+    }
+
+    #[inline]
+    fn surface_transform(&self, surface: &Surface) -> SurfaceTransform {
+        Device::surface_transform(self, surface)
+    }
+
+    #[inline]
+    fn clear_surface_region(
+        &self,
+        context: &mut Context,
+        surface: &mut Surface,
+        rect: Option<Rect<i32>>,
+        color: [f32; 4],
+        depth: Option<f32>,
+        stencil: Option<i32>,
+    ) -> Result<(), Error> {
+        Device::clear_surface_region(self, context, surface, rect, color, depth, stencil)
+    }
+
+    #[inline]
+    fn set_debug_surface_fill(&mut self, fill: DebugFill) {
+        Device::set_debug_surface_fill(self, fill)
+    }
+
+    #[inline]
+    fn debug_surface_fill(&self) -> DebugFill {
+        Device::debug_surface_fill(self)
+    }
+
+    #[inline]
+    fn debug_fill_surface(
+        &self,
+        context: &mut Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        Device::debug_fill_surface(self, context, surface)
+    }
+
+    #[inline]
+    fn set_surface_resized_handler(&mut self, handler: Option<SurfaceResizedHandler>) {
+        Device::set_surface_resized_handler(self, handler)
+    }
+
+    #[inline]
+    fn set_surface_scale_changed_handler(
+        &mut self,
+        handler: Option<SurfaceScaleChangedHandler>,
+    ) -> Result<(), Error> {
+        Device::set_surface_scale_changed_handler(self, handler)
+    }
+
+    #[inline]
+    fn set_surface_purgeable(
+        &self,
+        surface: &mut Surface,
+        purgeability: Purgeability,
+    ) -> Result<(), Error> {
+        Device::set_surface_purgeable(self, surface, purgeability)
+    }
+
+    #[inline]
+    fn reclaim_surface(&self, surface: &mut Surface) -> Result<ReclaimResult, Error> {
+        Device::reclaim_surface(self, surface)
+    }
+
     #[inline]
     fn surface_info(&self, surface: &Self::Surface) -> SurfaceInfo {
         Device::surface_info(self, surface)
     }
 
+    #[inline]
+    fn lock_surface_data<'s>(
+        &self,
+        surface: &'s mut Surface,
+    ) -> Result<SurfaceDataGuard<'s>, Error> {
+        Device::lock_surface_data(self, surface)
+    }
+
+    #[inline]
+    #[cfg(unix)]
+    fn export_surface_dma_buf(&self, surface: &Surface) -> Result<DmaBuf, Error> {
+        Device::export_surface_dma_buf(self, surface)
+    }
+
+    #[inline]
+    fn convert_surface(
+        &self,
+        context: &mut Self::Context,
+        source: &Self::Surface,
+        destination: &mut Self::Surface,
+        swizzle: Swizzle,
+    ) -> Result<(), Error> {
+        Device::convert_surface(self, context, source, destination, swizzle)
+    }
+
+    #[inline]
+    fn read_surface_data(
+        &self,
+        context: &Self::Context,
+        surface: &Self::Surface,
+        rect: Rect<i32>,
+        out: &mut [u8],
+    ) -> Result<(), Error> {
+        Device::read_surface_data(self, context, surface, rect, out)
+    }
+
+    #[inline]
+    fn write_surface_data(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+        rect: Rect<i32>,
+        data: &[u8],
+        stride: usize,
+    ) -> Result<(), Error> {
+        Device::write_surface_data(self, context, surface, rect, data, stride)
+    }
+
+    #[inline]
+    fn widget_color_profile(&self, surface: &Self::Surface) -> ColorProfile {
+        Device::widget_color_profile(self, surface)
+    }
+
+    #[inline]
+    fn set_color_profile_changed_handler(&mut self, handler: Option<ColorProfileChangedHandler>) {
+        Device::set_color_profile_changed_handler(self, handler)
+    }
+
+    #[inline]
+    fn presenting_cross_adapter(&self, surface: &Self::Surface) -> bool {
+        Device::presenting_cross_adapter(self, surface)
+    }
+
+    #[inline]
+    fn presentation_timestamps(&self, surface: &Self::Surface) -> Vec<PresentRecord> {
+        Device::presentation_timestamps(self, surface)
+    }
+
+    #[inline]
+    fn query_presentation_time(
+        &self,
+        surface: &Self::Surface,
+    ) -> Result<PresentationStatistics, Error> {
+        Device::query_presentation_time(self, surface)
+    }
+
     #[inline]
     fn surface_texture_object(&self, surface_texture: &Self::SurfaceTexture) -> GLuint {
         Device::surface_texture_object(self, surface_texture)
     }
+
+    #[inline]
+    fn surface_texture_format(&self, surface_texture: &Self::SurfaceTexture) -> GLenum {
+        Device::surface_texture_format(self, surface_texture)
+    }
 }
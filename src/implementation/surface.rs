@@ -0,0 +1,16 @@
+// surfman/surfman/src/implementation/surface.rs
+//
+//! This is an included private module that automatically produces the implementation of the
+//! `SurfaceInterface` trait for a backend.
+
+use super::super::surface::Surface;
+use crate::context::ContextID;
+use crate::surface::SurfaceInterface;
+
+#[deny(unconditional_recursion)]
+impl SurfaceInterface for Surface {
+    #[inline]
+    fn context_id(&self) -> ContextID {
+        Surface::context_id(self)
+    }
+}
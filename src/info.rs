@@ -2,9 +2,12 @@
 //
 //! OpenGL information.
 
+use crate::context;
 use crate::gl;
-use crate::Gl;
+use crate::gl::types::{GLenum, GLint, GLuint};
+use crate::{ContextAttributeFlags, Gl};
 
+use std::env;
 use std::ffi::CStr;
 use std::os::raw::c_char;
 
@@ -21,7 +24,9 @@ pub enum GLApi {
 ///
 /// Since OpenGL and OpenGL ES have different version numbering schemes, the valid values here
 /// depend on the value of `Device::gl_api()`.
-#[derive(Clone, Copy, Debug, PartialEq)]
+///
+/// Versions are ordered by major version, then by minor version.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct GLVersion {
     /// The major OpenGL version (e.g. 4 in 4.2).
     pub major: u8,
@@ -63,3 +68,280 @@ impl GLVersion {
         }
     }
 }
+
+// `GL_MAX_VARYING_VECTORS`, which the desktop bindings don't include before OpenGL 4.1.
+const GL_MAX_VARYING_VECTORS: GLenum = 0x8dfc;
+
+// `GL_MAX_VARYING_COMPONENTS`, called `GL_MAX_VARYING_FLOATS` before OpenGL 3.0, which the OpenGL
+// ES 2.0 bindings don't include.
+const GL_MAX_VARYING_COMPONENTS: GLenum = 0x8b4b;
+
+/// The environment variable that, when set, clamps every `GLCapabilities` snapshot to bare
+/// OpenGL ES 2.0.
+///
+/// This is a testing aid: running the test suite with it set exercises the fallbacks surfman
+/// uses on devices that lack OpenGL ES 3.0 entry points.
+pub(crate) static CLAMP_TO_GLES2_ENV_VAR: &str = "SURFMAN_CLAMP_TO_GLES2";
+
+/// The OpenGL features beyond OpenGL ES 2.0 that surfman relies on, as detected on a context.
+///
+/// Entry points that a context doesn't support are null and crash if called, so surfman checks
+/// these before using them and falls back to OpenGL ES 2.0 functionality otherwise.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GLCapabilities {
+    /// The API of the context (OpenGL or OpenGL ES).
+    pub gl_api: GLApi,
+    /// The version of the context.
+    pub version: GLVersion,
+    /// Whether `glBlitFramebuffer` is available.
+    pub blit_framebuffer: bool,
+    /// Whether `glInvalidateFramebuffer` is available.
+    pub invalidate_framebuffer: bool,
+    /// Whether `glReadBuffer` is available.
+    pub read_buffer: bool,
+    /// Whether `glFenceSync` and the other sync object functions are available.
+    pub fence_sync: bool,
+    /// Whether `GL_DEPTH24_STENCIL8` renderbuffers are available.
+    pub packed_depth_stencil: bool,
+    /// Whether `GL_DEPTH_COMPONENT24` renderbuffers are available.
+    pub depth24: bool,
+    /// Whether floating-point `GL_DEPTH_COMPONENT32F` and `GL_DEPTH32F_STENCIL8` renderbuffers
+    /// are available.
+    pub depth32f: bool,
+    /// How many four-component varyings can pass from the vertex shader to the fragment shader.
+    ///
+    /// OpenGL ES 2.0 only guarantees 8. ANGLE on a Direct3D feature level 9.3 device offers
+    /// exactly that, and shaders that need more fail to link.
+    pub max_varying_vectors: u32,
+    /// Whether vertex shaders can sample textures, that is, whether
+    /// `GL_MAX_VERTEX_TEXTURE_IMAGE_UNITS` is nonzero. OpenGL ES 2.0 doesn't guarantee it.
+    pub vertex_texture_fetch: bool,
+    /// Whether `GL_RGBA16F` textures can be rendered to, as `SurfaceFormat::Rgba16F` generic
+    /// surfaces need. OpenGL ES only guarantees it from 3.2.
+    pub color_buffer_half_float: bool,
+    /// The longest side that a texture can have, `GL_MAX_TEXTURE_SIZE`.
+    ///
+    /// Surfaces can't be larger than this in either direction, and trying returns
+    /// `Error::InvalidSurfaceSize`.
+    pub max_texture_size: u32,
+}
+
+impl GLCapabilities {
+    /// Detects the capabilities of the current context.
+    ///
+    /// If the `SURFMAN_CLAMP_TO_GLES2` environment variable is set, the result is clamped with
+    /// `clamp_to_gles2()`.
+    pub fn current(gl: &Gl) -> GLCapabilities {
+        let capabilities = GLCapabilities::detect(gl);
+        if env::var_os(CLAMP_TO_GLES2_ENV_VAR).is_some() {
+            capabilities.clamp_to_gles2()
+        } else {
+            capabilities
+        }
+    }
+
+    /// Returns these capabilities with everything beyond core OpenGL ES 2.0 removed.
+    pub fn clamp_to_gles2(self) -> GLCapabilities {
+        GLCapabilities {
+            gl_api: self.gl_api,
+            version: if self.version.major >= 2 {
+                GLVersion::new(2, 0)
+            } else {
+                self.version
+            },
+            blit_framebuffer: false,
+            invalidate_framebuffer: false,
+            read_buffer: false,
+            fence_sync: false,
+            packed_depth_stencil: false,
+            depth24: false,
+            depth32f: false,
+            max_varying_vectors: self.max_varying_vectors.min(8),
+            vertex_texture_fetch: false,
+            color_buffer_half_float: false,
+            max_texture_size: self.max_texture_size,
+        }
+    }
+
+    fn detect(gl: &Gl) -> GLCapabilities {
+        let gl_api = current_api(gl);
+        let version = GLVersion::current(gl);
+        let extensions = extensions(gl, version);
+        let has_extension = |name: &str| extensions.iter().any(|extension| extension == name);
+        let at_least = |major: u8, minor: u8| (version.major, version.minor) >= (major, minor);
+        let get_integer = |name: GLenum| unsafe {
+            let mut value: GLint = 0;
+            gl.GetIntegerv(name, &mut value);
+            value.max(0) as u32
+        };
+        let vertex_texture_fetch = get_integer(gl::MAX_VERTEX_TEXTURE_IMAGE_UNITS) > 0;
+        let max_texture_size = get_integer(gl::MAX_TEXTURE_SIZE);
+
+        match gl_api {
+            GLApi::GL => GLCapabilities {
+                gl_api,
+                version,
+                blit_framebuffer: at_least(3, 0)
+                    || has_extension("GL_ARB_framebuffer_object")
+                    || has_extension("GL_EXT_framebuffer_blit"),
+                invalidate_framebuffer: at_least(4, 3)
+                    || has_extension("GL_ARB_invalidate_subdata"),
+                read_buffer: true,
+                fence_sync: at_least(3, 2) || has_extension("GL_ARB_sync"),
+                packed_depth_stencil: at_least(3, 0)
+                    || has_extension("GL_ARB_framebuffer_object")
+                    || has_extension("GL_EXT_packed_depth_stencil"),
+                depth24: true,
+                depth32f: at_least(3, 0) || has_extension("GL_ARB_depth_buffer_float"),
+                max_varying_vectors: get_integer(GL_MAX_VARYING_COMPONENTS) / 4,
+                vertex_texture_fetch,
+                color_buffer_half_float: at_least(3, 0) || has_extension("GL_ARB_texture_float"),
+                max_texture_size,
+            },
+            // The OpenGL ES 2.0 extensions that provide blits, invalidation, and fences name
+            // their entry points with suffixes, which we don't load.
+            GLApi::GLES => GLCapabilities {
+                gl_api,
+                version,
+                blit_framebuffer: at_least(3, 0),
+                invalidate_framebuffer: at_least(3, 0),
+                read_buffer: at_least(3, 0),
+                fence_sync: at_least(3, 0),
+                packed_depth_stencil: at_least(3, 0)
+                    || has_extension("GL_OES_packed_depth_stencil"),
+                depth24: at_least(3, 0) || has_extension("GL_OES_depth24"),
+                depth32f: at_least(3, 0),
+                max_varying_vectors: get_integer(GL_MAX_VARYING_VECTORS),
+                vertex_texture_fetch,
+                // Sized `GL_RGBA16F` textures need OpenGL ES 3.0 either way.
+                color_buffer_half_float: at_least(3, 2)
+                    || (at_least(3, 0)
+                        && (has_extension("GL_EXT_color_buffer_half_float")
+                            || has_extension("GL_EXT_color_buffer_float"))),
+                max_texture_size,
+            },
+        }
+    }
+}
+
+/// What OpenGL reports about a live context.
+///
+/// This can differ from what the context was created with: drivers are free to grant a newer
+/// version than the one requested, so asking for OpenGL 3.3 may produce an OpenGL 4.6 core
+/// profile context.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContextInfo {
+    /// The API of the context (OpenGL or OpenGL ES).
+    pub gl_api: GLApi,
+    /// The version that the context was granted.
+    pub version: GLVersion,
+    /// The profile and flags that the context was granted.
+    ///
+    /// Only `COMPATIBILITY_PROFILE`, `DEBUG`, `ROBUST_ACCESS`, `NO_ERROR`, and
+    /// `RELEASE_BEHAVIOR_NONE` are reported here, since the others describe the pixel format
+    /// rather than the context.
+    pub flags: ContextAttributeFlags,
+    /// The `GL_VENDOR` string, naming the company responsible for the implementation.
+    pub vendor: String,
+    /// The `GL_RENDERER` string, usually naming the GPU.
+    pub renderer: String,
+}
+
+impl ContextInfo {
+    /// Queries the current context.
+    pub(crate) fn current(gl: &Gl) -> ContextInfo {
+        let mut flags = ContextAttributeFlags::empty();
+        flags.set(
+            ContextAttributeFlags::COMPATIBILITY_PROFILE,
+            context::current_context_uses_compatibility_profile(gl),
+        );
+        flags.set(
+            ContextAttributeFlags::DEBUG,
+            context::current_context_is_debug(gl),
+        );
+        flags.set(
+            ContextAttributeFlags::ROBUST_ACCESS,
+            context::current_context_is_robust(gl),
+        );
+        flags.set(
+            ContextAttributeFlags::NO_ERROR,
+            context::current_context_is_no_error(gl),
+        );
+        flags.set(
+            ContextAttributeFlags::RELEASE_BEHAVIOR_NONE,
+            context::current_context_release_behavior_is_none(gl),
+        );
+        ContextInfo {
+            gl_api: current_api(gl),
+            version: current_granted_version(gl),
+            flags,
+            vendor: current_string(gl, gl::VENDOR),
+            renderer: current_string(gl, gl::RENDERER),
+        }
+    }
+}
+
+fn current_api(gl: &Gl) -> GLApi {
+    if current_string(gl, gl::VERSION).starts_with("OpenGL ES") {
+        GLApi::GLES
+    } else {
+        GLApi::GL
+    }
+}
+
+// `GL_MAJOR_VERSION` and `GL_MINOR_VERSION` only exist in OpenGL 3.0 and OpenGL ES 3.0 and later,
+// so older contexts fall back on parsing the version string.
+fn current_granted_version(gl: &Gl) -> GLVersion {
+    let version = GLVersion::current(gl);
+    if version.major < 3 {
+        return version;
+    }
+    let (mut major, mut minor) = (0, 0);
+    unsafe {
+        gl.GetIntegerv(gl::MAJOR_VERSION, &mut major);
+        gl.GetIntegerv(gl::MINOR_VERSION, &mut minor);
+    }
+    GLVersion::new(major as u8, minor as u8)
+}
+
+fn current_string(gl: &Gl, name: GLenum) -> String {
+    unsafe {
+        let string = gl.GetString(name) as *const c_char;
+        if string.is_null() {
+            return String::new();
+        }
+        CStr::from_ptr(string).to_string_lossy().into_owned()
+    }
+}
+
+// Returns the names of the extensions that the current context supports.
+pub(crate) fn current_extensions(gl: &Gl) -> Vec<String> {
+    extensions(gl, GLVersion::current(gl))
+}
+
+// `glGetStringi` only exists in OpenGL 3.0 and OpenGL ES 3.0 and later, while
+// `glGetString(GL_EXTENSIONS)` is gone from core profiles, so pick based on the version.
+fn extensions(gl: &Gl, version: GLVersion) -> Vec<String> {
+    unsafe {
+        if version.major >= 3 {
+            let mut extension_count = 0;
+            gl.GetIntegerv(gl::NUM_EXTENSIONS, &mut extension_count);
+            (0..extension_count.max(0) as GLuint)
+                .map(|index| {
+                    let extension = gl.GetStringi(gl::EXTENSIONS, index) as *const c_char;
+                    CStr::from_ptr(extension).to_string_lossy().into_owned()
+                })
+                .collect()
+        } else {
+            let extensions = gl.GetString(gl::EXTENSIONS) as *const c_char;
+            if extensions.is_null() {
+                return vec![];
+            }
+            CStr::from_ptr(extensions)
+                .to_string_lossy()
+                .split_whitespace()
+                .map(|extension| extension.to_owned())
+                .collect()
+        }
+    }
+}
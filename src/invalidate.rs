@@ -0,0 +1,164 @@
+// surfman/surfman/src/invalidate.rs
+//
+//! Invalidating the attachments of a surface once it has been presented.
+//!
+//! Tiled GPUs, such as Mali and Adreno, keep the framebuffer in tile memory while drawing, and
+//! write each attachment back to memory, or load it from memory, unless they are told that its
+//! contents are no longer needed. `Device::set_post_present_invalidation()` chooses which
+//! attachments of a surface are invalidated with `glInvalidateFramebuffer()` right after each
+//! present: `Device::present_surface()` does it for widget surfaces, and swap chains do it for the
+//! front buffer of each swap with `Device::invalidate_surface()`.
+//!
+//! Contexts without `glInvalidateFramebuffer()` skip the invalidation, which is only a hint.
+
+use crate::gl;
+use crate::gl::types::{GLenum, GLint, GLuint};
+use crate::{Error, GLCapabilities, Gl};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static INVALIDATIONS: AtomicU64 = AtomicU64::new(0);
+static COLOR_INVALIDATIONS: AtomicU64 = AtomicU64::new(0);
+static DEPTH_INVALIDATIONS: AtomicU64 = AtomicU64::new(0);
+static STENCIL_INVALIDATIONS: AtomicU64 = AtomicU64::new(0);
+
+/// The attachments of a surface to invalidate after each present.
+///
+/// The default invalidates the depth and stencil buffers, which nothing reads once a frame has
+/// been presented, and keeps the color buffer.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct InvalidateSet {
+    /// Whether to invalidate the color buffer.
+    ///
+    /// Only widget surfaces whose color buffer isn't preserved across presents can invalidate it.
+    pub color: bool,
+    /// Whether to invalidate the depth buffer.
+    pub depth: bool,
+    /// Whether to invalidate the stencil buffer.
+    pub stencil: bool,
+}
+
+impl InvalidateSet {
+    /// Invalidates nothing.
+    pub const NONE: InvalidateSet = InvalidateSet {
+        color: false,
+        depth: false,
+        stencil: false,
+    };
+
+    /// Returns true if this invalidates nothing.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        *self == InvalidateSet::NONE
+    }
+}
+
+impl Default for InvalidateSet {
+    #[inline]
+    fn default() -> InvalidateSet {
+        InvalidateSet {
+            color: false,
+            depth: true,
+            stencil: true,
+        }
+    }
+}
+
+/// Counters describing the post-present invalidations done in this process.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct InvalidationStats {
+    /// The number of calls to `glInvalidateFramebuffer()`.
+    pub invalidations: u64,
+    /// The number of color buffers invalidated.
+    pub color: u64,
+    /// The number of depth buffers invalidated.
+    pub depth: u64,
+    /// The number of stencil buffers invalidated.
+    pub stencil: u64,
+}
+
+/// Returns the counters describing the post-present invalidations done so far in this process.
+pub fn stats() -> InvalidationStats {
+    InvalidationStats {
+        invalidations: INVALIDATIONS.load(Ordering::Relaxed),
+        color: COLOR_INVALIDATIONS.load(Ordering::Relaxed),
+        depth: DEPTH_INVALIDATIONS.load(Ordering::Relaxed),
+        stencil: STENCIL_INVALIDATIONS.load(Ordering::Relaxed),
+    }
+}
+
+// The attachments of a surface to invalidate after each present, and whether the surface's
+// context can.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct PostPresentInvalidation {
+    set: InvalidateSet,
+    // Whether the context has `glInvalidateFramebuffer()`, once it has been checked.
+    supported: Option<bool>,
+}
+
+impl PostPresentInvalidation {
+    #[inline]
+    pub(crate) fn set(&self) -> InvalidateSet {
+        self.set
+    }
+
+    // Changes the attachments to invalidate, refusing to invalidate a color buffer whose contents
+    // are still needed after a present.
+    pub(crate) fn change(
+        &mut self,
+        set: InvalidateSet,
+        color_preserved: bool,
+    ) -> Result<(), Error> {
+        if set.color && color_preserved {
+            return Err(Error::PreservedColorBuffer);
+        }
+        self.set = set;
+        Ok(())
+    }
+
+    // Invalidates the attachments of `framebuffer_object`, or of the default framebuffer if it's
+    // 0. The surface's context must be current.
+    pub(crate) unsafe fn invalidate(&mut self, gl: &Gl, framebuffer_object: GLuint) {
+        if self.set.is_empty() {
+            return;
+        }
+        let supported = *self
+            .supported
+            .get_or_insert_with(|| GLCapabilities::current(gl).invalidate_framebuffer);
+        if !supported {
+            return;
+        }
+
+        // The default framebuffer names its attachments differently.
+        let (color, depth, stencil) = if framebuffer_object == 0 {
+            (gl::COLOR, gl::DEPTH, gl::STENCIL)
+        } else {
+            (
+                gl::COLOR_ATTACHMENT0,
+                gl::DEPTH_ATTACHMENT,
+                gl::STENCIL_ATTACHMENT,
+            )
+        };
+        let mut attachments: Vec<GLenum> = Vec::with_capacity(3);
+        for &(invalidate, attachment, counter) in &[
+            (self.set.color, color, &COLOR_INVALIDATIONS),
+            (self.set.depth, depth, &DEPTH_INVALIDATIONS),
+            (self.set.stencil, stencil, &STENCIL_INVALIDATIONS),
+        ] {
+            if invalidate {
+                attachments.push(attachment);
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let mut old_framebuffer_object: GLint = 0;
+        gl.GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut old_framebuffer_object);
+        gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, framebuffer_object);
+        gl.InvalidateFramebuffer(
+            gl::DRAW_FRAMEBUFFER,
+            attachments.len() as _,
+            attachments.as_ptr(),
+        );
+        gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, old_framebuffer_object as GLuint);
+        INVALIDATIONS.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,167 @@
+// surfman/surfman/src/leak_check.rs
+//
+//! Checking that code gives back every context, surface, and surface texture that it creates.
+//!
+//! Every backend counts the objects of each `TrackedObject` kind that are alive in the process.
+//! A `LeakCheckScope`, from `scope()`, snapshots the counts and checks that they are back where
+//! they were when the scope ends, so that leaks fail tests instead of going unnoticed.
+
+use std::fmt::{self, Display, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// A kind of object whose live instances surfman counts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TrackedObject {
+    /// Contexts, including ones that wrap native contexts.
+    Context,
+    /// Surfaces, whether they are bound to a context, wrapped in a surface texture, or neither.
+    Surface,
+    /// Surface textures.
+    SurfaceTexture,
+    /// The pbuffers that EGL contexts are made current with while no surface is bound to them.
+    ///
+    /// Shared placeholders stay alive until the last context that uses them is destroyed, or
+    /// until they are purged in low-memory mode, so a scope that starts while other contexts
+    /// exist may need to allow for them.
+    Placeholder,
+}
+
+const TRACKED_OBJECT_COUNT: usize = 4;
+
+const TRACKED_OBJECTS: [TrackedObject; TRACKED_OBJECT_COUNT] = [
+    TrackedObject::Context,
+    TrackedObject::Surface,
+    TrackedObject::SurfaceTexture,
+    TrackedObject::Placeholder,
+];
+
+static LIVE_COUNTS: [AtomicUsize; TRACKED_OBJECT_COUNT] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+impl TrackedObject {
+    /// Returns how many objects of this kind are alive in this process.
+    #[inline]
+    pub fn live_count(self) -> usize {
+        LIVE_COUNTS[self as usize].load(Ordering::SeqCst)
+    }
+}
+
+// Counts a newly created object.
+#[inline]
+pub(crate) fn created(object: TrackedObject) {
+    LIVE_COUNTS[object as usize].fetch_add(1, Ordering::SeqCst);
+}
+
+// Stops counting a destroyed object.
+#[inline]
+pub(crate) fn destroyed(object: TrackedObject) {
+    LIVE_COUNTS[object as usize].fetch_sub(1, Ordering::SeqCst);
+}
+
+/// Starts a leak check, which ends when the returned scope is dropped or finished.
+#[inline]
+pub fn scope() -> LeakCheckScope {
+    LeakCheckScope {
+        baseline: TRACKED_OBJECTS.map(TrackedObject::live_count),
+        allowances: [0; TRACKED_OBJECT_COUNT],
+        finished: false,
+    }
+}
+
+/// A snapshot of the live object counts, which checks that the counts haven't grown once it ends.
+///
+/// Dropping the scope panics with the list of leaks, unless the thread is already panicking; call
+/// `finish()` to get the list instead. The counts are process-wide, so objects that other threads
+/// create while the scope is open count as leaks too. Tests that use scopes should run serially.
+///
+/// Only objects that surfman creates are counted. OpenGL objects that the application creates in
+/// surfman's contexts aren't.
+#[must_use]
+pub struct LeakCheckScope {
+    baseline: [usize; TRACKED_OBJECT_COUNT],
+    allowances: [usize; TRACKED_OBJECT_COUNT],
+    finished: bool,
+}
+
+/// Objects of one kind that outlived the scope that they were created in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Leak {
+    /// The kind of object that leaked.
+    pub object: TrackedObject,
+    /// How many objects of this kind were alive when the scope started.
+    pub before: usize,
+    /// How many objects of this kind were alive when the scope was checked.
+    pub after: usize,
+}
+
+impl LeakCheckScope {
+    /// Allows `count` more objects of the given kind to be alive when the scope ends than when it
+    /// started, for objects that are retained on purpose.
+    #[inline]
+    pub fn allow(&mut self, object: TrackedObject, count: usize) {
+        self.allowances[object as usize] += count;
+    }
+
+    /// Returns the kinds of object that there are more of now than when the scope started, beyond
+    /// what has been allowed.
+    pub fn leaks(&self) -> Vec<Leak> {
+        TRACKED_OBJECTS
+            .iter()
+            .filter_map(|&object| {
+                let before = self.baseline[object as usize];
+                let after = object.live_count();
+                if after > before + self.allowances[object as usize] {
+                    Some(Leak {
+                        object,
+                        before,
+                        after,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Ends the scope, returning the leaks instead of panicking if there are any.
+    pub fn finish(mut self) -> Result<(), Vec<Leak>> {
+        self.finished = true;
+        let leaks = self.leaks();
+        if leaks.is_empty() {
+            Ok(())
+        } else {
+            Err(leaks)
+        }
+    }
+}
+
+impl Drop for LeakCheckScope {
+    fn drop(&mut self) {
+        if self.finished || thread::panicking() {
+            return;
+        }
+        let leaks = self.leaks();
+        if !leaks.is_empty() {
+            let leaks: Vec<String> = leaks.iter().map(Leak::to_string).collect();
+            panic!("Leaked surfman objects: {}", leaks.join(", "));
+        }
+    }
+}
+
+impl Display for Leak {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {:?} object(s) ({} alive before, {} after)",
+            self.after - self.before,
+            self.object,
+            self.before,
+            self.after
+        )
+    }
+}
@@ -22,6 +22,9 @@ extern crate log;
 #[macro_use]
 extern crate objc;
 
+#[macro_use]
+mod thread_safety;
+
 pub mod platform;
 pub use platform::default::connection::{Connection, NativeConnection};
 pub use platform::default::context::{Context, ContextDescriptor, NativeContext};
@@ -29,32 +32,103 @@ pub use platform::default::device::{Adapter, Device, NativeDevice};
 pub use platform::default::surface::{NativeWidget, Surface, SurfaceTexture};
 
 // TODO(pcwalton): Fill this in with other OS's.
-#[cfg(target_os = "macos")]
+#[cfg(cgl_backend)]
 pub use platform::system::connection::Connection as SystemConnection;
-#[cfg(target_os = "macos")]
+#[cfg(cgl_backend)]
 pub use platform::system::device::{Adapter as SystemAdapter, Device as SystemDevice};
-#[cfg(target_os = "macos")]
+#[cfg(cgl_backend)]
 pub use platform::system::surface::Surface as SystemSurface;
 
+pub mod cancel;
+pub use crate::cancel::CancelToken;
+
+pub mod leak_check;
+
+pub mod lost;
+pub use crate::lost::ContextLostHandler;
+
+pub mod memory;
+pub use crate::memory::{LowMemoryOptions, Purgeability, ReclaimResult};
+
+pub mod scheduling;
+pub use crate::scheduling::{SchedulingClass, SchedulingEnforcement};
+
+pub mod scale;
+pub use crate::scale::{SurfaceScaleChanged, SurfaceScaleChangedHandler};
+
+pub mod vsync;
+pub use crate::vsync::{AutoVsyncPolicy, AutoVsyncStats, VsyncDecision};
+
+pub mod pause;
+pub use crate::pause::PausedPresentation;
+
+pub mod fence;
+pub use crate::fence::SurfaceFence;
+
+pub mod acceleration;
+pub use crate::acceleration::{SoftwareFallbackDetected, SoftwareFallbackHandler};
+
+pub mod bulk;
+pub use crate::bulk::BulkOps;
+
+pub mod native_format;
+pub use crate::native_format::{NativeFormat, NativeFormatKind};
+
+pub mod presentation_time;
+pub use crate::presentation_time::PresentationStatistics;
+
 #[cfg(feature = "chains")]
 pub mod chains;
 pub mod connection;
+pub mod convert;
+pub use crate::convert::Swizzle;
+pub mod cpu_layer;
+#[cfg(feature = "sm-debug-view")]
+pub mod debug_view;
 pub mod device;
+pub mod diagnostics;
+#[cfg(feature = "chains")]
+pub mod frame_export;
+pub mod invalidate;
+pub use crate::invalidate::InvalidateSet;
+#[cfg(feature = "chains")]
+pub mod pipeline;
+#[cfg(all(feature = "chains", target_os = "linux"))]
+pub mod producer_abi;
+pub mod readback;
 
 pub mod error;
-pub use crate::error::{Error, WindowingApiError};
+pub use crate::error::{Error, GlError, WindowingApiError};
+
+mod clear;
+pub use crate::clear::DebugFill;
+mod color;
+pub use crate::color::{ColorProfile, ColorProfileChangedHandler, ColorSpace};
 
 mod context;
+mod current;
 pub use crate::context::{
-    ContextAttributeFlags, ContextAttributes, ContextDescriptorInterface, ContextID,
-    NativeContext as NativeContextInterface,
+    ColorDepth, ContextAttributeFlags, ContextAttributes, ContextDescriptorAttributes,
+    ContextDescriptorInterface, ContextID, ContextPriority,
+    NativeContext as NativeContextInterface, PlaceholderPolicy, ResetStatus,
 };
 
+mod identity;
+pub use crate::identity::{identities_match, AdapterKey, AdapterKind, DeviceIdentity};
+
 mod info;
-pub use crate::info::{GLApi, GLVersion};
+pub use crate::info::{ContextInfo, GLApi, GLCapabilities, GLVersion};
 
 mod surface;
-pub use crate::surface::{SurfaceAccess, SurfaceID, SurfaceInfo, SurfaceType, SystemSurfaceInfo};
+pub use crate::surface::BufferRelease;
+#[cfg(unix)]
+pub use crate::surface::DmaBuf;
+pub use crate::surface::SurfaceResizedHandler;
+pub use crate::surface::SystemSurfaceInfo;
+pub use crate::surface::{ColorBits, ColorEncoding, PresentMode, SurfaceFormat, SurfaceOptions};
+pub use crate::surface::{PresentRecord, RenderTargetInfo, SurfaceAccess, SurfaceID};
+pub use crate::surface::{SurfaceConfig, SurfaceHints, SurfaceIntent, SurfaceTransform};
+pub use crate::surface::{SurfaceInfo, SurfaceInterface, SurfaceType, TextureOwnership};
 
 pub mod macros;
 
@@ -65,6 +139,7 @@ pub(crate) use crate::gl::Gles2 as Gl;
 
 mod gl_utils;
 mod renderbuffers;
+mod upload;
 
 #[allow(clippy::all)]
 mod gl {
@@ -0,0 +1,96 @@
+// surfman/surfman/src/lost.rs
+//
+//! Noticing when contexts are lost to GPU resets or to the system reclaiming them.
+
+use crate::{ContextID, Error, ResetStatus, WindowingApiError};
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+/// A function that a device calls with the ID of each of its contexts that it finds has been
+/// lost.
+///
+/// Register one with `Device::set_context_lost_handler()`. It is called once per context, on the
+/// thread that noticed the loss, from inside the device method that noticed it, so it must not
+/// call back into that device.
+pub type ContextLostHandler = Box<dyn Fn(ContextID) + Send + Sync>;
+
+// The contexts of a device that have been lost, and who to tell when another one is.
+//
+// Once a context is known to be lost, every operation on it that goes through the device fails
+// with `Error::ContextLost`, without calling into the driver, until the context is destroyed.
+#[derive(Clone, Default)]
+pub(crate) struct LostContexts {
+    ids: Arc<Mutex<HashSet<ContextID>>>,
+    handler: Option<Arc<dyn Fn(ContextID) + Send + Sync>>,
+}
+
+impl LostContexts {
+    #[inline]
+    pub(crate) fn set_handler(&mut self, handler: Option<ContextLostHandler>) {
+        self.handler = handler.map(Arc::from);
+    }
+
+    #[inline]
+    pub(crate) fn is_lost(&self, context_id: ContextID) -> bool {
+        self.ids.lock().unwrap().contains(&context_id)
+    }
+
+    // Records that the context has been lost, and calls the handler if this is news.
+    pub(crate) fn mark_lost(&self, context_id: ContextID) {
+        let newly_lost = self.ids.lock().unwrap().insert(context_id);
+        if newly_lost {
+            if let Some(ref handler) = self.handler {
+                handler(context_id);
+            }
+        }
+    }
+
+    // Forgets a destroyed context, whose ID may be reused.
+    #[inline]
+    pub(crate) fn forget(&self, context_id: ContextID) {
+        self.ids.lock().unwrap().remove(&context_id);
+    }
+
+    // Runs an operation on the context, unless it's known to be lost. If the operation fails
+    // because the windowing system reports the context lost, the context is marked lost and
+    // `Error::ContextLost` is returned instead of the windowing system's error.
+    pub(crate) fn check<T, F>(&self, context_id: ContextID, operation: F) -> Result<T, Error>
+    where
+        F: FnOnce() -> Result<T, Error>,
+    {
+        if self.is_lost(context_id) {
+            return Err(Error::ContextLost);
+        }
+        match operation() {
+            Err(Error::ContextLost)
+            | Err(Error::MakeCurrentFailed(WindowingApiError::ContextLost))
+            | Err(Error::PresentFailed(WindowingApiError::ContextLost)) => {
+                self.mark_lost(context_id);
+                Err(Error::ContextLost)
+            }
+            result => result,
+        }
+    }
+
+    // Returns whether the context has been lost, given a way to ask the driver for its reset
+    // status. A status other than `NoError`, or a failure to make the context current because it
+    // has been lost, marks the context lost.
+    pub(crate) fn poll<F>(&self, context_id: ContextID, reset_status: F) -> bool
+    where
+        F: FnOnce() -> Result<ResetStatus, Error>,
+    {
+        if self.is_lost(context_id) {
+            return true;
+        }
+        match self.check(context_id, reset_status) {
+            Ok(ResetStatus::NoError) => false,
+            Ok(_) => {
+                self.mark_lost(context_id);
+                true
+            }
+            Err(Error::ContextLost) => true,
+            Err(_) => false,
+        }
+    }
+}
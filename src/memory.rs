@@ -0,0 +1,225 @@
+// surfman/surfman/src/memory.rs
+//
+//! Trading rendering quality for memory when the system runs low on it.
+
+use crate::gl;
+use crate::gl::types::GLenum;
+use crate::Error;
+#[cfg(any(android_backend, generic_egl_platform))]
+use crate::{ColorDepth, ContextAttributeFlags, ContextAttributes, SurfaceAccess};
+
+use log::warn;
+
+/// The sized internal format of 16-bit surfaces without alpha, `GL_RGB565`.
+///
+/// Desktop GL bindings don't define it before OpenGL 4.1, so it is provided here.
+pub const GL_RGB565: GLenum = 0x8d62;
+
+/// What a device gives up while it is in low-memory mode.
+///
+/// Pass these to `Device::enter_low_memory_mode()`, for example from the platform's memory
+/// pressure callback.
+///
+/// Swap chains shrink at their next swap, since the producer context is needed to destroy their
+/// surfaces. A swap chain never takes back the surfaces that consumers hold, so it can only shrink
+/// to its back buffer and front buffer without blocking until consumers give their surfaces back.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LowMemoryOptions {
+    /// Whether generic surfaces created from now on use 16-bit color: `GL_RGB565` for contexts
+    /// without alpha, and `GL_RGBA4` for contexts with it.
+    ///
+    /// Only `SurfaceAccess::GPUOnly` `SurfaceFormat::Rgba8` surfaces of contexts that didn't ask
+    /// for deep or float color are downgraded, because the CPU and other APIs expect 8-bit
+    /// channels. Check the format of each surface in `SurfaceInfo::format`, for example to turn
+    /// dithering on.
+    ///
+    /// This only has an effect on Linux and Android. Windows and macOS surfaces share their
+    /// storage with Direct3D and `IOSurface` in 8-bit formats.
+    pub prefer_rgb565: bool,
+    /// Whether to destroy the device's idle pooled surfaces immediately.
+    ///
+    /// Pooled surfaces are the placeholder pbuffers that EGL contexts share while no surface is
+    /// attached to them. They are recreated when they are next needed.
+    ///
+    /// Swap chains also destroy their spare surfaces, and the surfaces that consumers have given
+    /// back to them, at their next swap, and keep none until low-memory mode is left. Call
+    /// `SwapChains::purge_pools()` to destroy them without waiting for a swap.
+    pub purge_pools: bool,
+    /// How many surfaces each swap chain shrinks to at its next swap, counting its back buffer,
+    /// its front buffer and the surfaces that consumers hold.
+    ///
+    /// Swap chains normally keep up to three surfaces alive, so that a consumer that holds on to a
+    /// frame across a swap doesn't make the producer allocate a surface every other frame. Spare
+    /// surfaces beyond this depth are destroyed. Depths below two are taken as two.
+    pub min_chain_depth: u32,
+}
+
+impl LowMemoryOptions {
+    // Returns the sized internal format to allocate a generic surface with, on a device in
+    // low-memory mode with these options, or not in low-memory mode if `None`.
+    #[cfg(any(android_backend, generic_egl_platform))]
+    pub(crate) fn surface_format(
+        options: Option<LowMemoryOptions>,
+        attributes: &ContextAttributes,
+        access: SurfaceAccess,
+    ) -> GLenum {
+        let prefer_rgb565 = matches!(options, Some(options) if options.prefer_rgb565);
+        if !prefer_rgb565
+            || access != SurfaceAccess::GPUOnly
+            || matches!(attributes.color_depth, ColorDepth::Deep | ColorDepth::Float)
+        {
+            gl::RGBA8
+        } else if attributes.flags.contains(ContextAttributeFlags::ALPHA) {
+            gl::RGBA4
+        } else {
+            GL_RGB565
+        }
+    }
+}
+
+/// Whether the system may throw away a surface's contents to free memory.
+///
+/// Set it with `Device::set_surface_purgeable()`. Surfaces whose contents can be regenerated,
+/// such as cached thumbnails, can be made volatile so that under memory pressure the system
+/// discards them instead of killing the application.
+///
+/// How much memory this frees depends on the platform:
+///
+/// * On macOS, the surface's `IOSurface` is made purgeable with `IOSurfaceSetPurgeable()`.
+///
+/// * With ANGLE, the surface's Direct3D texture is offered to the system with
+///   `IDXGIDevice2::OfferResources()`.
+///
+/// * Elsewhere, including on Linux, surfaces are ordinary GL textures that the driver gives no
+///   way to offer back outside the context that owns them, so volatile surfaces stay resident
+///   and are never discarded. The state is still tracked and enforced, so that code written for
+///   the platforms above behaves the same.
+///
+/// Only generic surfaces can be made volatile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Purgeability {
+    /// The system must keep the surface's contents. This is the state that surfaces are created
+    /// in.
+    NonPurgeable,
+    /// The system may discard the surface's contents whenever it likes. The surface can't be
+    /// used until it has been taken back with `Device::reclaim_surface()`.
+    Volatile,
+}
+
+/// What became of a surface's contents while it was volatile, as reported by
+/// `Device::reclaim_surface()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReclaimResult {
+    /// The contents are as they were when the surface was made volatile.
+    Intact,
+    /// The system discarded the contents, which are now undefined and must be redrawn.
+    Discarded,
+}
+
+// How a surface's backing store is offered to the system and taken back, so that tests can mock
+// it.
+pub(crate) trait PurgeableBacking {
+    // Lets the system discard the contents of the backing store.
+    fn offer(&mut self) -> Result<(), Error>;
+    // Takes the backing store back, returning true if the system discarded its contents while it
+    // was offered.
+    fn reclaim(&mut self) -> Result<bool, Error>;
+}
+
+// The backing store of a surface on a platform that can't offer it to the system.
+#[allow(dead_code)]
+pub(crate) struct ResidentBacking;
+
+impl PurgeableBacking for ResidentBacking {
+    #[inline]
+    fn offer(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[inline]
+    fn reclaim(&mut self) -> Result<bool, Error> {
+        Ok(false)
+    }
+}
+
+// Where a surface is in the purgeability state machine.
+//
+// A surface is usable while it's non-purgeable and any discard has been reported. Making a
+// volatile surface non-purgeable with `set()` takes the backing store back, but if the contents
+// were discarded, the surface stays unusable until `reclaim()` has reported it, so that the loss
+// can't go unnoticed.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct PurgeState {
+    volatile: bool,
+    discarded: bool,
+}
+
+impl PurgeState {
+    #[inline]
+    pub(crate) fn purgeability(&self) -> Purgeability {
+        if self.volatile {
+            Purgeability::Volatile
+        } else {
+            Purgeability::NonPurgeable
+        }
+    }
+
+    pub(crate) fn set(
+        &mut self,
+        backing: &mut dyn PurgeableBacking,
+        purgeability: Purgeability,
+    ) -> Result<(), Error> {
+        match (self.volatile, purgeability) {
+            (false, Purgeability::Volatile) => {
+                backing.offer()?;
+                self.volatile = true;
+            }
+            (true, Purgeability::NonPurgeable) => {
+                self.discarded |= backing.reclaim()?;
+                self.volatile = false;
+            }
+            (false, Purgeability::NonPurgeable) | (true, Purgeability::Volatile) => {}
+        }
+        Ok(())
+    }
+
+    pub(crate) fn reclaim(
+        &mut self,
+        backing: &mut dyn PurgeableBacking,
+    ) -> Result<ReclaimResult, Error> {
+        self.set(backing, Purgeability::NonPurgeable)?;
+        if self.discarded {
+            self.discarded = false;
+            Ok(ReclaimResult::Discarded)
+        } else {
+            Ok(ReclaimResult::Intact)
+        }
+    }
+
+    // Checks that the surface may be rendered to or read from.
+    //
+    // This is debug validation: release builds skip the check, and using a surface that hasn't
+    // been reclaimed reads and writes undefined contents.
+    pub(crate) fn check_usable(&self) -> Result<(), Error> {
+        if !cfg!(debug_assertions) || (!self.volatile && !self.discarded) {
+            return Ok(());
+        }
+        warn!("Attempted to use a surface that hasn't been reclaimed");
+        Err(Error::SurfaceNotReclaimed)
+    }
+}
+
+// Returns the internal format, the format, and the type to pass to `glTexImage2D()` to allocate a
+// texture with the sized internal format `format`. The internal format is unsized where that's
+// enough, so that OpenGL ES 2.0 accepts it.
+#[allow(dead_code)]
+pub(crate) fn texture_format_and_type(format: GLenum) -> (GLenum, GLenum, GLenum) {
+    match format {
+        GL_RGB565 => (gl::RGB, gl::RGB, gl::UNSIGNED_SHORT_5_6_5),
+        gl::RGBA4 => (gl::RGBA, gl::RGBA, gl::UNSIGNED_SHORT_4_4_4_4),
+        gl::SRGB8_ALPHA8 => (gl::SRGB8_ALPHA8, gl::RGBA, gl::UNSIGNED_BYTE),
+        gl::RGBA16F => (gl::RGBA16F, gl::RGBA, gl::HALF_FLOAT),
+        gl::RGB10_A2 => (gl::RGB10_A2, gl::RGBA, gl::UNSIGNED_INT_2_10_10_10_REV),
+        _ => (gl::RGBA, gl::RGBA, gl::UNSIGNED_BYTE),
+    }
+}
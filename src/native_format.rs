@@ -0,0 +1,265 @@
+// surfman/surfman/src/native_format.rs
+//
+//! The formats that platform APIs know a surface's color buffer by.
+//!
+//! Video and capture pipelines negotiate formats with other components in the platform's own
+//! terms: DRM fourccs and modifiers on Linux and Android, `DXGI_FORMAT`s on Windows, and
+//! `IOSurface` pixel formats on macOS. `SurfaceInfo::native_format` reports a surface's format in
+//! those terms, and `NativeFormat::surface_format()` maps it back to a `SurfaceFormat`.
+//!
+//! Backends report the value that the platform holds wherever a platform API allocated the
+//! buffer: the `IOSurface` pixel format on macOS, the format of the Direct3D texture on Windows,
+//! and the DRM fourcc of the hardware buffer's format on Android. Generic surfaces that are plain
+//! OpenGL textures or renderbuffers, as on X11, Wayland, and OpenHarmony, report
+//! `NativeFormat::GlInternal`, and widget surfaces whose buffers belong to the window system
+//! report `NativeFormat::Unknown`.
+
+use crate::gl;
+use crate::gl::types::GLenum;
+use crate::memory::GL_RGB565;
+use crate::{ColorEncoding, SurfaceFormat};
+
+/// The DRM format modifier of buffers whose layout isn't known, such as Android hardware buffers,
+/// whose layout belongs to gralloc.
+pub const DRM_FORMAT_MOD_INVALID: u64 = 0x00ff_ffff_ffff_ffff;
+/// The DRM format modifier of buffers laid out linearly, one row after another.
+pub const DRM_FORMAT_MOD_LINEAR: u64 = 0;
+
+// `GL_BGRA8_EXT`, from `EXT_texture_format_BGRA8888`.
+const GL_BGRA8_EXT: GLenum = 0x93a1;
+
+// DRM fourccs, from `drm_fourcc.h`. Each names its channels from the most significant bit of a
+// little-endian pixel down, so `ABGR8888` is `GL_RGBA8` in memory order.
+const DRM_FORMAT_ABGR8888: u32 = fourcc(b"AB24");
+const DRM_FORMAT_ARGB8888: u32 = fourcc(b"AR24");
+const DRM_FORMAT_ABGR16161616F: u32 = fourcc(b"AB4H");
+const DRM_FORMAT_ABGR2101010: u32 = fourcc(b"AB30");
+const DRM_FORMAT_RGB565: u32 = fourcc(b"RG16");
+const DRM_FORMAT_ABGR4444: u32 = fourcc(b"AB12");
+
+// `DXGI_FORMAT`s, from `dxgiformat.h`.
+const DXGI_FORMAT_R16G16B16A16_FLOAT: u32 = 10;
+const DXGI_FORMAT_R10G10B10A2_UNORM: u32 = 24;
+const DXGI_FORMAT_R8G8B8A8_UNORM: u32 = 28;
+const DXGI_FORMAT_R8G8B8A8_UNORM_SRGB: u32 = 29;
+const DXGI_FORMAT_B5G6R5_UNORM: u32 = 85;
+const DXGI_FORMAT_B8G8R8A8_UNORM: u32 = 87;
+
+// Core Video pixel format types, which `IOSurface`s use, from `CVPixelBuffer.h`.
+const CV_PIXEL_FORMAT_TYPE_32_BGRA: u32 = fourcc_big_endian(b"BGRA");
+const CV_PIXEL_FORMAT_TYPE_64_RGBA_HALF: u32 = fourcc_big_endian(b"RGhA");
+const CV_PIXEL_FORMAT_TYPE_ARGB_2101010_LE_PACKED: u32 = fourcc_big_endian(b"l10r");
+const CV_PIXEL_FORMAT_TYPE_16_LE_565: u32 = fourcc_big_endian(b"L565");
+
+/// The format of a surface's color buffer, as a platform API knows it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NativeFormat {
+    /// A DRM fourcc, such as `DRM_FORMAT_ABGR8888`, and a format modifier, which is
+    /// `DRM_FORMAT_MOD_INVALID` where the buffer's layout isn't known.
+    DrmFourcc(u32, u64),
+    /// A `DXGI_FORMAT`, such as `DXGI_FORMAT_R8G8B8A8_UNORM`.
+    Dxgi(u32),
+    /// An `IOSurface` pixel format, which is a Core Video pixel format type such as
+    /// `kCVPixelFormatType_32BGRA`.
+    IoSurface(u32),
+    /// A sized OpenGL internal format, such as `GL_RGBA8`, for surfaces that no platform API
+    /// allocated.
+    GlInternal(GLenum),
+    /// The format isn't known, as for widget surfaces, whose buffers belong to the window system.
+    Unknown,
+}
+
+/// The kinds of `NativeFormat`, for asking `NativeFormat::from_surface_format()` for one of them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NativeFormatKind {
+    /// `NativeFormat::DrmFourcc`.
+    DrmFourcc,
+    /// `NativeFormat::Dxgi`.
+    Dxgi,
+    /// `NativeFormat::IoSurface`.
+    IoSurface,
+    /// `NativeFormat::GlInternal`.
+    GlInternal,
+}
+
+// A row of the table that maps formats between platforms. A platform without the format has
+// `None` in its column.
+struct FormatMapping {
+    gl_internal: GLenum,
+    surface_format: Option<SurfaceFormat>,
+    colorspace: ColorEncoding,
+    drm_fourcc: Option<u32>,
+    dxgi: Option<u32>,
+    io_surface: Option<u32>,
+}
+
+// Formats are looked up from the first row down, so each format that surfman allocates comes
+// before any row that only maps other components' formats back.
+const FORMAT_MAPPINGS: [FormatMapping; 7] = [
+    FormatMapping {
+        gl_internal: gl::RGBA8,
+        surface_format: Some(SurfaceFormat::Rgba8),
+        colorspace: ColorEncoding::Linear,
+        drm_fourcc: Some(DRM_FORMAT_ABGR8888),
+        dxgi: Some(DXGI_FORMAT_R8G8B8A8_UNORM),
+        // `IOSurface`s are always allocated BGRA, which OpenGL samples as RGBA.
+        io_surface: Some(CV_PIXEL_FORMAT_TYPE_32_BGRA),
+    },
+    FormatMapping {
+        gl_internal: gl::SRGB8_ALPHA8,
+        surface_format: Some(SurfaceFormat::Rgba8),
+        colorspace: ColorEncoding::Srgb,
+        // DRM fourccs and Core Video types don't say how values are encoded.
+        drm_fourcc: Some(DRM_FORMAT_ABGR8888),
+        dxgi: Some(DXGI_FORMAT_R8G8B8A8_UNORM_SRGB),
+        io_surface: Some(CV_PIXEL_FORMAT_TYPE_32_BGRA),
+    },
+    FormatMapping {
+        gl_internal: gl::RGBA16F,
+        surface_format: Some(SurfaceFormat::Rgba16F),
+        colorspace: ColorEncoding::Linear,
+        drm_fourcc: Some(DRM_FORMAT_ABGR16161616F),
+        dxgi: Some(DXGI_FORMAT_R16G16B16A16_FLOAT),
+        io_surface: Some(CV_PIXEL_FORMAT_TYPE_64_RGBA_HALF),
+    },
+    FormatMapping {
+        gl_internal: gl::RGB10_A2,
+        surface_format: Some(SurfaceFormat::Rgb10A2),
+        colorspace: ColorEncoding::Linear,
+        drm_fourcc: Some(DRM_FORMAT_ABGR2101010),
+        dxgi: Some(DXGI_FORMAT_R10G10B10A2_UNORM),
+        io_surface: Some(CV_PIXEL_FORMAT_TYPE_ARGB_2101010_LE_PACKED),
+    },
+    // The low-memory formats, which no `SurfaceFormat` asks for.
+    FormatMapping {
+        gl_internal: GL_RGB565,
+        surface_format: None,
+        colorspace: ColorEncoding::Linear,
+        drm_fourcc: Some(DRM_FORMAT_RGB565),
+        dxgi: Some(DXGI_FORMAT_B5G6R5_UNORM),
+        io_surface: Some(CV_PIXEL_FORMAT_TYPE_16_LE_565),
+    },
+    FormatMapping {
+        gl_internal: gl::RGBA4,
+        surface_format: None,
+        colorspace: ColorEncoding::Linear,
+        drm_fourcc: Some(DRM_FORMAT_ABGR4444),
+        dxgi: None,
+        io_surface: None,
+    },
+    // BGRA, which ANGLE may back 8-bit surfaces with, and which compositors and encoders often
+    // hand over.
+    FormatMapping {
+        gl_internal: GL_BGRA8_EXT,
+        surface_format: Some(SurfaceFormat::Rgba8),
+        colorspace: ColorEncoding::Linear,
+        drm_fourcc: Some(DRM_FORMAT_ARGB8888),
+        dxgi: Some(DXGI_FORMAT_B8G8R8A8_UNORM),
+        io_surface: None,
+    },
+];
+
+impl NativeFormat {
+    /// Returns the native format of the given kind that surfman allocates a surface in for
+    /// `format` and `colorspace`, or `Unknown` if the platform has no such format.
+    ///
+    /// DRM fourccs come with `DRM_FORMAT_MOD_INVALID`, since the layout depends on the allocator.
+    pub fn from_surface_format(
+        format: SurfaceFormat,
+        colorspace: ColorEncoding,
+        kind: NativeFormatKind,
+    ) -> NativeFormat {
+        FORMAT_MAPPINGS
+            .iter()
+            .find(|mapping| {
+                mapping.surface_format == Some(format) && mapping.colorspace == colorspace
+            })
+            .map_or(NativeFormat::Unknown, |mapping| mapping.native_format(kind))
+    }
+
+    /// Returns the `SurfaceFormat` with the same channels and precision as this format, or
+    /// `None` for `Unknown`, for formats that no `SurfaceFormat` stores, such as the low-memory
+    /// `GL_RGB565`, and for formats that aren't in surfman's table.
+    ///
+    /// Both `GL_RGBA8` and `GL_SRGB8_ALPHA8` are `SurfaceFormat::Rgba8`; see
+    /// `SurfaceInfo::colorspace` for how values are encoded.
+    pub fn surface_format(&self) -> Option<SurfaceFormat> {
+        self.mapping().and_then(|mapping| mapping.surface_format)
+    }
+
+    /// Returns the sized OpenGL internal format that this format corresponds to, or `None` for
+    /// `Unknown` and for formats that aren't in surfman's table.
+    ///
+    /// For formats that don't say how values are encoded, such as DRM fourccs, this is the
+    /// linear format.
+    pub fn gl_internal_format(&self) -> Option<GLenum> {
+        self.mapping().map(|mapping| mapping.gl_internal)
+    }
+
+    /// Returns the kind of this format, or `None` for `Unknown`.
+    pub fn kind(&self) -> Option<NativeFormatKind> {
+        match *self {
+            NativeFormat::DrmFourcc(..) => Some(NativeFormatKind::DrmFourcc),
+            NativeFormat::Dxgi(_) => Some(NativeFormatKind::Dxgi),
+            NativeFormat::IoSurface(_) => Some(NativeFormatKind::IoSurface),
+            NativeFormat::GlInternal(_) => Some(NativeFormatKind::GlInternal),
+            NativeFormat::Unknown => None,
+        }
+    }
+
+    // Returns the native format of the given kind for a surface whose color buffer has the sized
+    // internal format `gl_internal`, or `Unknown` for `GL_NONE`, which widget surfaces report, and
+    // for formats that the platform has no equivalent of.
+    #[allow(dead_code)]
+    pub(crate) fn of_gl_internal_format(
+        gl_internal: GLenum,
+        kind: NativeFormatKind,
+    ) -> NativeFormat {
+        if gl_internal == gl::NONE {
+            return NativeFormat::Unknown;
+        }
+        if kind == NativeFormatKind::GlInternal {
+            return NativeFormat::GlInternal(gl_internal);
+        }
+        FORMAT_MAPPINGS
+            .iter()
+            .find(|mapping| mapping.gl_internal == gl_internal)
+            .map_or(NativeFormat::Unknown, |mapping| mapping.native_format(kind))
+    }
+
+    fn mapping(&self) -> Option<&'static FormatMapping> {
+        FORMAT_MAPPINGS.iter().find(|mapping| match *self {
+            NativeFormat::DrmFourcc(fourcc, _) => mapping.drm_fourcc == Some(fourcc),
+            NativeFormat::Dxgi(format) => mapping.dxgi == Some(format),
+            NativeFormat::IoSurface(format) => mapping.io_surface == Some(format),
+            NativeFormat::GlInternal(format) => mapping.gl_internal == format,
+            NativeFormat::Unknown => false,
+        })
+    }
+}
+
+impl FormatMapping {
+    fn native_format(&self, kind: NativeFormatKind) -> NativeFormat {
+        let format = match kind {
+            NativeFormatKind::DrmFourcc => self
+                .drm_fourcc
+                .map(|fourcc| NativeFormat::DrmFourcc(fourcc, DRM_FORMAT_MOD_INVALID)),
+            NativeFormatKind::Dxgi => self.dxgi.map(NativeFormat::Dxgi),
+            NativeFormatKind::IoSurface => self.io_surface.map(NativeFormat::IoSurface),
+            NativeFormatKind::GlInternal => Some(NativeFormat::GlInternal(self.gl_internal)),
+        };
+        format.unwrap_or(NativeFormat::Unknown)
+    }
+}
+
+// Returns the DRM fourcc with the given code, whose first character is the least significant
+// byte.
+const fn fourcc(code: &[u8; 4]) -> u32 {
+    u32::from_le_bytes(*code)
+}
+
+// Returns the Core Video pixel format type with the given code, whose first character is the
+// most significant byte.
+const fn fourcc_big_endian(code: &[u8; 4]) -> u32 {
+    u32::from_be_bytes(*code)
+}
@@ -0,0 +1,323 @@
+// surfman/surfman/src/pause.rs
+//
+//! Holding the frames of a widget surface instead of presenting them.
+//!
+//! Visual regression tools need to stop a widget surface on an exact frame, inspect it, and carry
+//! on, without racing the application's render loop. `Device::pause_presentation()` pauses a
+//! widget surface: from then on, `Device::present_surface()` reads the frame back into memory
+//! instead of showing it, and returns `Error::PresentationPaused`, so the render loop keeps
+//! running and can tell that nothing reached the screen. The most recent of those frames is held,
+//! and `Device::read_surface_data()` reads a widget surface's held frame as it reads a generic
+//! surface. `Device::resume_presentation()` ends the pause, and either discards the held frame or
+//! draws it back into the surface and presents it.
+//!
+//! Dropping the `PausedPresentation` handle also ends the pause, discarding the held frame, since
+//! presenting it needs the surface's context. Resizing the surface ends the pause in the same
+//! way, since the held frame no longer fits.
+//!
+//! Pausing is supported for widget surfaces on the EGL backends, which are those of X11, Wayland,
+//! Android, OpenHarmony, and ANGLE. Each frame presented while paused is read back with
+//! `glReadPixels()`, which waits for the GPU to finish it.
+//!
+//! Swap chains can be paused too, with `SwapChain::pause()`. There, the held frame is simply the
+//! front buffer swapped in most recently, which consumers don't get until presentation resumes,
+//! and the frame that consumers were showing when presentation paused isn't recycled.
+
+use crate::gl;
+use crate::gl::types::GLint;
+use crate::gl_utils;
+use crate::readback;
+use crate::upload;
+use crate::{Error, GLVersion, Gl};
+use euclid::default::{Rect, Size2D};
+use std::os::raw::c_void;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+// The number of bytes in each pixel of a held frame.
+const BYTES_PER_PIXEL: usize = 4;
+
+/// The paused presentation of a widget surface, from `Device::pause_presentation()`, or of a swap
+/// chain, from `SwapChain::pause()`.
+///
+/// Pass this to `Device::resume_presentation()` or `SwapChain::resume()` to end the pause.
+/// Dropping it ends the pause too, discarding the held frame. Handles can be sent to and
+/// inspected from any thread.
+#[derive(Debug)]
+pub struct PausedPresentation {
+    shared: Arc<PauseShared>,
+}
+
+// What a `PausedPresentation` shares with the surface that it paused.
+#[derive(Debug, Default)]
+struct PauseShared {
+    // Set once the pause has ended, whether by resuming, resizing, or destroying the surface.
+    resumed: AtomicBool,
+    // Set when the handle is dropped, so that the surface ends the pause the next time it looks.
+    dropped: AtomicBool,
+    frames_held: AtomicU64,
+}
+
+impl PausedPresentation {
+    /// Returns true until the pause ends, when it's resumed, or because the surface or swap chain
+    /// was resized or destroyed.
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        !self.shared.resumed.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of frames that were presented during the pause.
+    ///
+    /// Each of them replaced the frame held before it, so only the last one can still be read or
+    /// presented.
+    #[inline]
+    pub fn frames_held(&self) -> u64 {
+        self.shared.frames_held.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for PausedPresentation {
+    #[inline]
+    fn drop(&mut self) {
+        self.shared.dropped.store(true, Ordering::SeqCst);
+    }
+}
+
+// A frame that was presented while presentation was paused, as 8-bit RGBA, bottom row first.
+pub(crate) struct HeldFrame {
+    size: Size2D<i32>,
+    pixels: Vec<u8>,
+}
+
+// Whether presentation is paused, and the frame held meanwhile: a `HeldFrame` for a widget
+// surface, and a surface for a swap chain.
+pub(crate) struct PresentPause<Frame> {
+    pause: Option<Pause<Frame>>,
+}
+
+struct Pause<Frame> {
+    shared: Arc<PauseShared>,
+    frame: Option<Frame>,
+}
+
+impl<Frame> Drop for Pause<Frame> {
+    fn drop(&mut self) {
+        self.shared.resumed.store(true, Ordering::SeqCst);
+    }
+}
+
+impl<Frame> Default for PresentPause<Frame> {
+    fn default() -> PresentPause<Frame> {
+        PresentPause { pause: None }
+    }
+}
+
+impl<Frame> PresentPause<Frame> {
+    // Pauses presentation, unless it's paused already.
+    //
+    // A pause whose handle was dropped is replaced, discarding its frame, so swap chains end it
+    // with `end_dropped()` first.
+    pub(crate) fn pause(&mut self) -> Result<PausedPresentation, Error> {
+        if self.is_paused() {
+            return Err(Error::PresentationPaused);
+        }
+        let shared = Arc::new(PauseShared::default());
+        self.pause = Some(Pause {
+            shared: shared.clone(),
+            frame: None,
+        });
+        Ok(PausedPresentation { shared })
+    }
+
+    // Returns true if presentation is paused, and the pause's handle hasn't been dropped.
+    pub(crate) fn is_paused(&self) -> bool {
+        self.pause.is_some() && !self.is_dropped()
+    }
+
+    fn is_dropped(&self) -> bool {
+        match self.pause {
+            Some(ref pause) => pause.shared.dropped.load(Ordering::SeqCst),
+            None => false,
+        }
+    }
+
+    // Ends the pause if its handle was dropped, and returns the frame that it held.
+    #[cfg(feature = "chains")]
+    pub(crate) fn end_dropped(&mut self) -> Option<Frame> {
+        if !self.is_dropped() {
+            return None;
+        }
+        self.end()
+    }
+
+    // Holds `frame`, which was presented while paused, in place of the frame held before it,
+    // which is returned. If presentation isn't paused, `frame` itself is returned.
+    pub(crate) fn hold(&mut self, frame: Frame) -> Option<Frame> {
+        match self.pause {
+            Some(ref mut pause) => {
+                pause.shared.frames_held.fetch_add(1, Ordering::SeqCst);
+                pause.frame.replace(frame)
+            }
+            None => Some(frame),
+        }
+    }
+
+    // Returns the frame held most recently, unless the pause's handle was dropped.
+    pub(crate) fn held_frame(&self) -> Option<&Frame> {
+        match self.pause {
+            Some(ref pause) if self.is_paused() => pause.frame.as_ref(),
+            _ => None,
+        }
+    }
+
+    // Returns the frame held most recently, even once the pause's handle was dropped, for the swap
+    // chain tests, which account for every surface.
+    #[cfg(all(test, feature = "chains"))]
+    pub(crate) fn frame(&self) -> Option<&Frame> {
+        self.pause.as_ref().and_then(|pause| pause.frame.as_ref())
+    }
+
+    #[cfg(feature = "chains")]
+    pub(crate) fn held_frame_mut(&mut self) -> Option<&mut Frame> {
+        self.pause.as_mut().and_then(|pause| pause.frame.as_mut())
+    }
+
+    // Takes the frame held most recently, without ending the pause.
+    #[cfg(feature = "chains")]
+    pub(crate) fn take_held_frame(&mut self) -> Option<Frame> {
+        self.pause.as_mut().and_then(|pause| pause.frame.take())
+    }
+
+    // Ends the pause that `paused` is the handle of, and returns the frame held most recently.
+    //
+    // A handle whose pause was already ended by a resize returns no frame.
+    pub(crate) fn resume(&mut self, paused: PausedPresentation) -> Result<Option<Frame>, Error> {
+        let ours = match self.pause {
+            Some(ref pause) => Arc::ptr_eq(&pause.shared, &paused.shared),
+            None => false,
+        };
+        if !ours {
+            if paused.is_paused() {
+                return Err(Error::IncompatiblePausedPresentation);
+            }
+            return Ok(None);
+        }
+        Ok(self.end())
+    }
+
+    // Ends the pause, because the surface was resized or destroyed, and returns the held frame.
+    pub(crate) fn force_resume(&mut self) -> Option<Frame> {
+        self.end()
+    }
+
+    fn end(&mut self) -> Option<Frame> {
+        self.pause.take().and_then(|mut pause| pause.frame.take())
+    }
+}
+
+impl HeldFrame {
+    #[cfg(test)]
+    pub(crate) fn new(size: Size2D<i32>, pixels: Vec<u8>) -> HeldFrame {
+        HeldFrame { size, pixels }
+    }
+
+    // Reads the whole of the current context's default framebuffer, which is the back buffer of
+    // the window surface that a frame was just presented to.
+    pub(crate) unsafe fn read(gl: &Gl, size: Size2D<i32>) -> Result<HeldFrame, Error> {
+        let mut pixels = vec![0; size.width as usize * size.height as usize * BYTES_PER_PIXEL];
+        readback::read_default_framebuffer(gl, size, &mut pixels)?;
+        Ok(HeldFrame { size, pixels })
+    }
+
+    // Copies a rectangle of the frame, measured from its top left, into `out` as tightly packed
+    // 8-bit RGBA, top row first, as `Device::read_surface_data()` does.
+    pub(crate) fn read_rect(&self, rect: Rect<i32>, out: &mut [u8]) -> Result<(), Error> {
+        readback::check_rect(self.size, rect)?;
+        let row_length = rect.size.width as usize * BYTES_PER_PIXEL;
+        let required = row_length * rect.size.height as usize;
+        if out.len() < required {
+            return Err(Error::BufferTooSmall {
+                required,
+                actual: out.len(),
+            });
+        }
+        if required == 0 {
+            return Ok(());
+        }
+        let stride = self.size.width as usize * BYTES_PER_PIXEL;
+        for (row, out_row) in out[..required].chunks_mut(row_length).enumerate() {
+            let y = (self.size.height - rect.origin.y - 1) as usize - row;
+            let start = y * stride + rect.origin.x as usize * BYTES_PER_PIXEL;
+            out_row.copy_from_slice(&self.pixels[start..start + row_length]);
+        }
+        Ok(())
+    }
+
+    // Draws the frame into the current context's default framebuffer, so that it can be presented.
+    //
+    // This needs `glBlitFramebuffer()`, which `Device::pause_presentation()` checks for.
+    pub(crate) unsafe fn draw(&self, gl: &Gl) -> Result<(), Error> {
+        gl_utils::drain_pre_existing_gl_errors(gl);
+        let gl3 = GLVersion::current(gl).major >= 3;
+        let saved_state = upload::SavedState::save(gl, gl3, gl::TEXTURE_2D);
+        let scissor_test = gl.IsEnabled(gl::SCISSOR_TEST) != gl::FALSE;
+        gl.Disable(gl::SCISSOR_TEST);
+        if gl3 {
+            gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, 0);
+            gl.PixelStorei(gl::UNPACK_ROW_LENGTH, 0);
+            gl.PixelStorei(gl::UNPACK_SKIP_ROWS, 0);
+            gl.PixelStorei(gl::UNPACK_SKIP_PIXELS, 0);
+        }
+        gl.PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+
+        let (width, height) = (self.size.width, self.size.height);
+        let (mut texture, mut framebuffer) = (0, 0);
+        gl.GenTextures(1, &mut texture);
+        gl.BindTexture(gl::TEXTURE_2D, texture);
+        gl.TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGBA as GLint,
+            width,
+            height,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            self.pixels.as_ptr() as *const c_void,
+        );
+        gl.GenFramebuffers(1, &mut framebuffer);
+        gl.BindFramebuffer(gl::READ_FRAMEBUFFER, framebuffer);
+        gl.FramebufferTexture2D(
+            gl::READ_FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::TEXTURE_2D,
+            texture,
+            0,
+        );
+        gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, 0);
+        gl.BlitFramebuffer(
+            0,
+            0,
+            width,
+            height,
+            0,
+            0,
+            width,
+            height,
+            gl::COLOR_BUFFER_BIT,
+            gl::NEAREST,
+        );
+        let error = gl.GetError();
+
+        gl.DeleteFramebuffers(1, &framebuffer);
+        gl.DeleteTextures(1, &texture);
+        if scissor_test {
+            gl.Enable(gl::SCISSOR_TEST);
+        }
+        saved_state.restore(gl);
+        if error != gl::NO_ERROR {
+            return Err(Error::Failed);
+        }
+        Ok(())
+    }
+}
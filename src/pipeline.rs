@@ -0,0 +1,510 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! A three-stage GPU pipeline: a producer renders frames, a filter post-processes them, and a
+//! presenter displays them.
+//!
+//! The producer and the filter each run on a thread that the pipeline starts, and the presenter
+//! is whichever thread calls `Pipeline::present`. Devices can't be shared between threads, so
+//! each stage thread opens its own device on the presenter's adapter, and creates its own context
+//! with the presenter's context descriptor. Frames travel through two swap chains: the producer's,
+//! which the filter consumes, and the filter's, which the presenter consumes.
+//!
+//! Each frame carries a `FrameInfo`, with its ID, its damage, and application metadata, which
+//! every stage may update. Stages hand frames on in lockstep: a stage doesn't swap its swap chain
+//! again until the next stage has taken the previous front buffer. So no frame is skipped and
+//! frames arrive in order, while each stage still works on a different frame at the same time.
+//! Each stage flushes its context before swapping, so the next stage sees finished rendering.
+//!
+//! Shutting down happens in two phases. First, the stages stop: the producer returns `false`, a
+//! stage fails, or the `Pipeline` is shut down, and each stage notices when its neighbours' ends
+//! of the channels close. Every consumer gives back the surfaces it took before it goes. Then each
+//! swap chain is destroyed by its producer, on its own thread, once its consumer has gone.
+
+use crate::chains::{PreserveBuffer, SwapChain, SwapChainAPI};
+use crate::connection::Connection as ConnectionAPI;
+use crate::device::Device as DeviceAPI;
+use crate::gl::types::GLuint;
+use crate::{Error, Gl, SurfaceAccess, SurfaceType};
+use euclid::default::{Point2D, Rect, Size2D};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+type ProducerCallback<Device, M> = Box<
+    dyn FnMut(
+            &mut Device,
+            &mut <Device as DeviceAPI>::Context,
+            &mut FrameInfo<M>,
+        ) -> Result<bool, Error>
+        + Send,
+>;
+type FilterCallback<Device, M> = Box<
+    dyn FnMut(
+            &mut Device,
+            &mut <Device as DeviceAPI>::Context,
+            GLuint,
+            &mut FrameInfo<M>,
+        ) -> Result<(), Error>
+        + Send,
+>;
+
+// The first error that a stage hit.
+type StageError = Arc<Mutex<Option<Error>>>;
+
+/// A frame as it moves through a `Pipeline`.
+#[derive(Clone, Debug)]
+pub struct FrameInfo<M> {
+    /// Counts the frames that the producer has started, from zero.
+    pub frame_id: u64,
+    /// The size of the frame in pixels.
+    pub size: Size2D<i32>,
+    /// The parts of the frame that changed since the previous frame.
+    ///
+    /// This starts out covering the whole frame. A stage that changes less, or more, than the
+    /// damage it was given should update it.
+    pub damage: Vec<Rect<i32>>,
+    /// Application data that travels with the frame.
+    pub metadata: M,
+}
+
+/// Configures and starts a `Pipeline`.
+pub struct PipelineBuilder<Device: DeviceAPI, M> {
+    size: Size2D<i32>,
+    surface_access: SurfaceAccess,
+    producer: Option<ProducerCallback<Device, M>>,
+    filter: Option<FilterCallback<Device, M>>,
+}
+
+/// A running producer, filter, and presenter pipeline.
+///
+/// Dropping the pipeline shuts it down, waiting for the stage threads to finish their current
+/// frames and destroy their swap chains and contexts. Use `shutdown` to find out whether that
+/// went cleanly.
+pub struct Pipeline<Device: DeviceAPI, M> {
+    swap_chain: SwapChain<Device>,
+    frames: Option<Receiver<FrameInfo<M>>>,
+    taken: Option<Sender<()>>,
+    error: StageError,
+    stages: Vec<JoinHandle<()>>,
+}
+
+// A stage thread's device and context, with a swap chain attached to the context.
+struct Stage<Device: DeviceAPI> {
+    device: Device,
+    context: Device::Context,
+    swap_chain: SwapChain<Device>,
+    gl: Gl,
+}
+
+impl<Device, M> Pipeline<Device, M>
+where
+    Device: DeviceAPI + 'static,
+    Device::Connection: ConnectionAPI<Device = Device> + Send,
+    <Device::Connection as ConnectionAPI>::Adapter: Send,
+    Device::ContextDescriptor: Send,
+    Device::Surface: Send,
+    M: Default + Send + 'static,
+{
+    /// Returns a builder for a pipeline whose stages pass metadata of type `M` along with each
+    /// frame.
+    pub fn builder() -> PipelineBuilder<Device, M> {
+        PipelineBuilder {
+            size: Size2D::zero(),
+            surface_access: SurfaceAccess::GPUOnly,
+            producer: None,
+            filter: None,
+        }
+    }
+
+    /// Waits for the next frame from the filter, and passes it to `callback` as an OpenGL
+    /// texture for `context`, along with its `FrameInfo`.
+    ///
+    /// The texture target is `device.surface_gl_texture_target()`. `context` must be the current
+    /// context. The frame goes back to the filter when the callback returns, even if it fails.
+    ///
+    /// Returns the presented frame's `FrameInfo`, or `None` once the producer has finished and
+    /// every frame has been presented. If a stage failed, its error is returned instead of
+    /// `None`, once, after the frames that got through.
+    pub fn present<F>(
+        &mut self,
+        device: &mut Device,
+        context: &mut Device::Context,
+        callback: F,
+    ) -> Result<Option<FrameInfo<M>>, Error>
+    where
+        F: FnOnce(&mut Device, &mut Device::Context, GLuint, &FrameInfo<M>) -> Result<(), Error>,
+    {
+        let info = match self.frames.as_ref().map(|frames| frames.recv()) {
+            Some(Ok(info)) => info,
+            Some(Err(_)) | None => {
+                return match self.error.lock().unwrap().take() {
+                    Some(err) => Err(err),
+                    None => Ok(None),
+                }
+            }
+        };
+
+        let surface_texture = take_frame(device, context, &self.swap_chain);
+        if let Some(ref taken) = self.taken {
+            let _ = taken.send(());
+        }
+        let surface_texture = surface_texture?;
+
+        let texture = device.surface_texture_object(&surface_texture);
+        let result = callback(device, context, texture, &info);
+        return_frame(device, context, &self.swap_chain, surface_texture);
+        result.map(|()| Some(info))
+    }
+
+    /// Shuts the pipeline down and waits for the stage threads to exit.
+    ///
+    /// Returns the first error that a stage hit and `present` didn't return, including errors
+    /// destroying the stages' swap chains and contexts. A stage thread that panicked is reported
+    /// as `Error::Failed`.
+    pub fn shutdown(mut self) -> Result<(), Error> {
+        self.stop();
+        match self.error.lock().unwrap().take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<Device: DeviceAPI, M> Pipeline<Device, M> {
+    // Closes the presenter's ends of the channels, so that the filter stops, and waits for the
+    // stage threads.
+    fn stop(&mut self) {
+        drop(self.frames.take());
+        drop(self.taken.take());
+        for stage in self.stages.drain(..) {
+            if stage.join().is_err() {
+                record_error(&self.error, Error::Failed);
+            }
+        }
+    }
+}
+
+impl<Device: DeviceAPI, M> Drop for Pipeline<Device, M> {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl<Device, M> PipelineBuilder<Device, M>
+where
+    Device: DeviceAPI + 'static,
+    Device::Connection: ConnectionAPI<Device = Device> + Send,
+    <Device::Connection as ConnectionAPI>::Adapter: Send,
+    Device::ContextDescriptor: Send,
+    Device::Surface: Send,
+    M: Default + Send + 'static,
+{
+    /// Sets the size of the frames, in pixels. This must be set.
+    pub fn size(mut self, size: Size2D<i32>) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Sets how the CPU may access the frames' surfaces. The default is `GPUOnly`.
+    pub fn surface_access(mut self, surface_access: SurfaceAccess) -> Self {
+        self.surface_access = surface_access;
+        self
+    }
+
+    /// Sets the producer, which is called on the producer thread to render each frame. This must
+    /// be set.
+    ///
+    /// The producer renders into the surface of its context, which is current. The surface's
+    /// framebuffer object is given by `device.context_surface_info(context)`. It returns `true`
+    /// to send the frame on, or `false` to finish the stream without sending it.
+    pub fn producer<F>(mut self, producer: F) -> Self
+    where
+        F: FnMut(&mut Device, &mut Device::Context, &mut FrameInfo<M>) -> Result<bool, Error>
+            + Send
+            + 'static,
+    {
+        self.producer = Some(Box::new(producer));
+        self
+    }
+
+    /// Sets the filter, which is called on the filter thread with each frame from the producer.
+    /// This must be set.
+    ///
+    /// The filter gets the producer's frame as an OpenGL texture for its context, with target
+    /// `device.surface_gl_texture_target()`, and renders its output into the surface of its
+    /// context, as the producer does.
+    pub fn filter<F>(mut self, filter: F) -> Self
+    where
+        F: FnMut(&mut Device, &mut Device::Context, GLuint, &mut FrameInfo<M>) -> Result<(), Error>
+            + Send
+            + 'static,
+    {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Starts the producer and filter threads.
+    ///
+    /// The stage threads open devices on the same adapter as `device`, and create contexts with
+    /// the same descriptor as `context`, which becomes the presenter's context. Returns
+    /// `Error::Failed` if the size is empty or a callback is missing, and otherwise any error that
+    /// a stage hit while setting up.
+    pub fn build(
+        self,
+        device: &Device,
+        context: &Device::Context,
+    ) -> Result<Pipeline<Device, M>, Error> {
+        let (mut producer, mut filter) = match (self.producer, self.filter) {
+            (Some(producer), Some(filter)) if !self.size.is_empty() => (producer, filter),
+            _ => return Err(Error::Failed),
+        };
+        let (size, surface_access) = (self.size, self.surface_access);
+        let error: StageError = Arc::new(Mutex::new(None));
+
+        let (produced_sender, produced_receiver) = mpsc::sync_channel(1);
+        let (produced_taken_sender, produced_taken_receiver) = mpsc::channel();
+        let (setup_sender, setup_receiver) = mpsc::channel();
+        let (connection, adapter) = (device.connection(), device.adapter());
+        let context_descriptor = device.context_descriptor(context);
+        let producer_error = error.clone();
+        let producer_thread = thread::spawn(move || {
+            let mut stage = match Stage::open(
+                &connection,
+                &adapter,
+                &context_descriptor,
+                size,
+                surface_access,
+            ) {
+                Ok(stage) => stage,
+                Err(err) => return setup_sender.send(Err(err)).unwrap(),
+            };
+            setup_sender.send(Ok(stage.swap_chain.clone())).unwrap();
+
+            for frame_id in 0.. {
+                let mut info = FrameInfo {
+                    frame_id,
+                    size,
+                    damage: vec![Rect::new(Point2D::zero(), size)],
+                    metadata: M::default(),
+                };
+                match producer(&mut stage.device, &mut stage.context, &mut info) {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(err) => {
+                        record_error(&producer_error, err);
+                        break;
+                    }
+                }
+                if let Err(err) = stage.present() {
+                    record_error(&producer_error, err);
+                    break;
+                }
+                if produced_sender.send(info).is_err() || produced_taken_receiver.recv().is_err() {
+                    break;
+                }
+            }
+
+            // The filter drops its end of the channel once it has given back all its surfaces.
+            drop(produced_sender);
+            while produced_taken_receiver.recv().is_ok() {}
+            if let Err(err) = stage.close() {
+                record_error(&producer_error, err);
+            }
+        });
+        let produced_swap_chain = match setup_receiver.recv() {
+            Ok(Ok(swap_chain)) => swap_chain,
+            Ok(Err(err)) => {
+                drop(producer_thread.join());
+                return Err(err);
+            }
+            Err(_) => {
+                drop(producer_thread.join());
+                return Err(Error::Failed);
+            }
+        };
+
+        let (filtered_sender, filtered_receiver) = mpsc::sync_channel(1);
+        let (filtered_taken_sender, filtered_taken_receiver) = mpsc::channel();
+        let (setup_sender, setup_receiver) = mpsc::channel();
+        let (connection, adapter) = (device.connection(), device.adapter());
+        let context_descriptor = device.context_descriptor(context);
+        let filter_error = error.clone();
+        let filter_thread = thread::spawn(move || {
+            let mut stage = match Stage::open(
+                &connection,
+                &adapter,
+                &context_descriptor,
+                size,
+                surface_access,
+            ) {
+                Ok(stage) => stage,
+                Err(err) => return setup_sender.send(Err(err)).unwrap(),
+            };
+            setup_sender.send(Ok(stage.swap_chain.clone())).unwrap();
+
+            for mut info in produced_receiver {
+                let input = take_frame(&stage.device, &mut stage.context, &produced_swap_chain);
+                let _ = produced_taken_sender.send(());
+                let input = match input {
+                    Ok(input) => input,
+                    Err(err) => {
+                        record_error(&filter_error, err);
+                        break;
+                    }
+                };
+
+                let texture = stage.device.surface_texture_object(&input);
+                let result = filter(&mut stage.device, &mut stage.context, texture, &mut info);
+                return_frame(
+                    &stage.device,
+                    &mut stage.context,
+                    &produced_swap_chain,
+                    input,
+                );
+                if let Err(err) = result.and_then(|()| stage.present()) {
+                    record_error(&filter_error, err);
+                    break;
+                }
+                if filtered_sender.send(info).is_err() || filtered_taken_receiver.recv().is_err() {
+                    break;
+                }
+            }
+
+            // Let the producer destroy its swap chain, then wait for the presenter to go before
+            // destroying ours.
+            drop(produced_taken_sender);
+            drop(filtered_sender);
+            while filtered_taken_receiver.recv().is_ok() {}
+            if let Err(err) = stage.close() {
+                record_error(&filter_error, err);
+            }
+        });
+        let filtered_swap_chain = match setup_receiver.recv() {
+            Ok(Ok(swap_chain)) => swap_chain,
+            Ok(Err(err)) => {
+                drop(filter_thread.join());
+                drop(producer_thread.join());
+                return Err(err);
+            }
+            Err(_) => {
+                drop(filter_thread.join());
+                drop(producer_thread.join());
+                return Err(Error::Failed);
+            }
+        };
+
+        Ok(Pipeline {
+            swap_chain: filtered_swap_chain,
+            frames: Some(filtered_receiver),
+            taken: Some(filtered_taken_sender),
+            error,
+            stages: vec![producer_thread, filter_thread],
+        })
+    }
+}
+
+impl<Device> Stage<Device>
+where
+    Device: DeviceAPI,
+    Device::Connection: ConnectionAPI<Device = Device>,
+{
+    fn open(
+        connection: &Device::Connection,
+        adapter: &<Device::Connection as ConnectionAPI>::Adapter,
+        context_descriptor: &Device::ContextDescriptor,
+        size: Size2D<i32>,
+        surface_access: SurfaceAccess,
+    ) -> Result<Stage<Device>, Error> {
+        let mut device = connection.create_device(adapter)?;
+        let mut context = device.create_context(context_descriptor, None)?;
+        match Stage::attach_swap_chain(&mut device, &mut context, size, surface_access) {
+            Ok(swap_chain) => {
+                let gl = Gl::load_with(device.proc_address_loader(&context));
+                Ok(Stage {
+                    device,
+                    context,
+                    swap_chain,
+                    gl,
+                })
+            }
+            Err(err) => {
+                drop(device.destroy_context(&mut context));
+                Err(err)
+            }
+        }
+    }
+
+    fn attach_swap_chain(
+        device: &mut Device,
+        context: &mut Device::Context,
+        size: Size2D<i32>,
+        surface_access: SurfaceAccess,
+    ) -> Result<SwapChain<Device>, Error> {
+        let surface_type = SurfaceType::Generic { size };
+        let surface = device.create_surface(context, surface_access, surface_type)?;
+        if let Err((err, mut surface)) = device.bind_surface_to_context(context, surface) {
+            drop(device.destroy_surface(context, &mut surface));
+            return Err(err);
+        }
+        device.make_context_current(context)?;
+        SwapChain::create_attached(device, context, surface_access)
+    }
+
+    // Swaps the finished frame into the front buffer, where the next stage can take it.
+    fn present(&mut self) -> Result<(), Error> {
+        unsafe {
+            self.gl.Flush();
+        }
+        self.swap_chain
+            .swap_buffers(&mut self.device, &mut self.context, PreserveBuffer::No)
+    }
+
+    fn close(mut self) -> Result<(), Error> {
+        let result = self.swap_chain.destroy(&mut self.device, &mut self.context);
+        self.device.destroy_context(&mut self.context).and(result)
+    }
+}
+
+// Takes the front buffer of `swap_chain`, and wraps it in a texture for `context`.
+fn take_frame<Device>(
+    device: &Device,
+    context: &mut Device::Context,
+    swap_chain: &SwapChain<Device>,
+) -> Result<Device::SurfaceTexture, Error>
+where
+    Device: DeviceAPI + 'static,
+    Device::Surface: Send,
+{
+    let surface = swap_chain.take_pending_surface().ok_or(Error::Failed)?;
+    device
+        .create_surface_texture(context, surface)
+        .map_err(|(err, surface)| {
+            swap_chain.recycle_surface(surface);
+            err
+        })
+}
+
+// Gives a frame taken with `take_frame` back to its swap chain for reuse.
+fn return_frame<Device>(
+    device: &Device,
+    context: &mut Device::Context,
+    swap_chain: &SwapChain<Device>,
+    surface_texture: Device::SurfaceTexture,
+) where
+    Device: DeviceAPI + 'static,
+    Device::Surface: Send,
+{
+    match device.destroy_surface_texture(context, surface_texture) {
+        Ok(surface) => swap_chain.recycle_surface(surface),
+        Err((err, _)) => panic!("Failed to destroy a pipeline surface texture: {:?}", err),
+    }
+}
+
+fn record_error(error: &StageError, err: Error) {
+    let mut error = error.lock().unwrap();
+    if error.is_none() {
+        *error = Some(err);
+    }
+}
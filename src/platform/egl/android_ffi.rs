@@ -1,14 +1,30 @@
 // surfman/surfman/src/platform/egl/android_ffi.rs
 
-use std::os::raw::c_int;
+use libc::{dlopen, dlsym, RTLD_LAZY};
+use std::mem;
+use std::os::raw::{c_char, c_int};
 
 pub(crate) const AHARDWAREBUFFER_FORMAT_R8G8B8A8_UNORM: u32 = 1;
+pub(crate) const AHARDWAREBUFFER_FORMAT_R5G6B5_UNORM: u32 = 4;
+pub(crate) const AHARDWAREBUFFER_FORMAT_R16G16B16A16_FLOAT: u32 = 0x16;
+pub(crate) const AHARDWAREBUFFER_FORMAT_R10G10B10A2_UNORM: u32 = 0x2b;
 
 pub(crate) const AHARDWAREBUFFER_USAGE_CPU_READ_NEVER: u64 = 0;
+pub(crate) const AHARDWAREBUFFER_USAGE_CPU_READ_RARELY: u64 = 2;
+pub(crate) const AHARDWAREBUFFER_USAGE_CPU_READ_OFTEN: u64 = 3;
 pub(crate) const AHARDWAREBUFFER_USAGE_CPU_WRITE_NEVER: u64 = 0 << 4;
+pub(crate) const AHARDWAREBUFFER_USAGE_CPU_WRITE_OFTEN: u64 = 3 << 4;
 pub(crate) const AHARDWAREBUFFER_USAGE_GPU_SAMPLED_IMAGE: u64 = 1 << 8;
 pub(crate) const AHARDWAREBUFFER_USAGE_GPU_FRAMEBUFFER: u64 = 1 << 9;
 
+pub(crate) const ANATIVEWINDOW_TRANSFORM_IDENTITY: i32 = 0;
+pub(crate) const ANATIVEWINDOW_TRANSFORM_ROTATE_90: i32 = 4;
+pub(crate) const ANATIVEWINDOW_TRANSFORM_ROTATE_180: i32 = 3;
+pub(crate) const ANATIVEWINDOW_TRANSFORM_ROTATE_270: i32 = 7;
+
+// `NATIVE_WINDOW_TRANSFORM_HINT`, from the platform's `system/window.h`.
+pub(crate) const NATIVE_WINDOW_TRANSFORM_HINT: c_int = 8;
+
 #[repr(C)]
 pub struct AHardwareBuffer {
     opaque: i32,
@@ -42,3 +58,47 @@ extern "C" {
     pub(crate) fn ANativeWindow_getWidth(window: *mut ANativeWindow) -> i32;
     pub(crate) fn ANativeWindow_getHeight(window: *mut ANativeWindow) -> i32;
 }
+
+// Functions of `libnativewindow` that not every Android version lets applications link against,
+// looked up when they're first needed. `ANativeWindow_setBuffersTransform()` arrived in API level
+// 26, and `ANativeWindow_query()` is only exported to the platform on some versions.
+#[allow(non_snake_case)]
+pub(crate) struct NativeWindowFunctions {
+    pub(crate) ANativeWindow_query:
+        Option<unsafe extern "C" fn(*const ANativeWindow, c_int, *mut c_int) -> c_int>,
+    pub(crate) ANativeWindow_setBuffersTransform:
+        Option<unsafe extern "C" fn(*mut ANativeWindow, i32) -> i32>,
+}
+
+lazy_static! {
+    pub(crate) static ref NATIVE_WINDOW_FUNCTIONS: NativeWindowFunctions =
+        unsafe { NativeWindowFunctions::load() };
+}
+
+impl NativeWindowFunctions {
+    unsafe fn load() -> NativeWindowFunctions {
+        let mut handle = dlopen(b"libnativewindow.so\0".as_ptr() as *const c_char, RTLD_LAZY);
+        if handle.is_null() {
+            handle = dlopen(b"libandroid.so\0".as_ptr() as *const c_char, RTLD_LAZY);
+        }
+        if handle.is_null() {
+            return NativeWindowFunctions {
+                ANativeWindow_query: None,
+                ANativeWindow_setBuffersTransform: None,
+            };
+        }
+        let symbol = |name: &[u8]| {
+            let symbol = dlsym(handle, name.as_ptr() as *const c_char);
+            if symbol.is_null() {
+                None
+            } else {
+                Some(symbol)
+            }
+        };
+        NativeWindowFunctions {
+            ANativeWindow_query: symbol(b"ANativeWindow_query\0").map(|f| mem::transmute(f)),
+            ANativeWindow_setBuffersTransform: symbol(b"ANativeWindow_setBuffersTransform\0")
+                .map(|f| mem::transmute(f)),
+        }
+    }
+}
@@ -6,8 +6,19 @@
 
 use super::device::{Adapter, Device, NativeDevice};
 use super::surface::NativeWidget;
+use crate::acceleration;
+use crate::context::NegotiatedVersions;
+use crate::egl;
+use crate::identity;
+use crate::lost::LostContexts;
+use crate::platform::generic::egl::device::{self, EGL_FUNCTIONS};
+use crate::CancelToken;
+use crate::DebugFill;
 use crate::Error;
 use crate::GLApi;
+use crate::LowMemoryOptions;
+use crate::SchedulingClass;
+use crate::{AdapterKey, AdapterKind};
 
 #[cfg(android_platform)]
 use super::android_ffi::ANativeWindow;
@@ -18,6 +29,11 @@ use euclid::default::Size2D;
 
 use std::os::raw::c_void;
 
+#[cfg(android_platform)]
+const ADAPTER_KEY_BACKEND: &str = "android";
+#[cfg(ohos_platform)]
+const ADAPTER_KEY_BACKEND: &str = "ohos";
+
 /// A connection to the display server.
 #[derive(Clone)]
 pub struct Connection;
@@ -26,6 +42,8 @@ pub struct Connection;
 #[derive(Clone)]
 pub struct NativeConnection;
 
+assert_send_sync!(NativeConnection);
+
 impl Connection {
     /// Connects to the default display.
     #[inline]
@@ -33,6 +51,23 @@ impl Connection {
         Ok(Connection)
     }
 
+    /// An alias for `Connection::new()`, as EGL on this platform needs no display server.
+    #[inline]
+    pub fn new_headless() -> Result<Connection, Error> {
+        Connection::new()
+    }
+
+    /// Connects to the display if it supports the given OpenGL API flavor.
+    ///
+    /// This backend only supports OpenGL ES, so other flavors return `Error::UnsupportedGLType`.
+    #[inline]
+    pub fn new_with_api(gl_api: GLApi) -> Result<Connection, Error> {
+        match gl_api {
+            GLApi::GLES => Connection::new(),
+            _ => Err(Error::UnsupportedGLType),
+        }
+    }
+
     /// An alias for `Connection::new()`, present for consistency with other backends.
     #[inline]
     pub unsafe fn from_native_connection(_: NativeConnection) -> Result<Connection, Error> {
@@ -51,6 +86,18 @@ impl Connection {
         GLApi::GLES
     }
 
+    /// Returns true if EGL advertises the named extension, either for the default display or as
+    /// a client extension.
+    ///
+    /// Display extensions are only reported once a device has initialized the display.
+    pub fn supports_extension(&self, name: &str) -> bool {
+        unsafe {
+            let egl_display = EGL_FUNCTIONS.with(|egl| egl.GetDisplay(egl::DEFAULT_DISPLAY));
+            device::display_supports_extension(egl_display, name)
+                || device::client_supports_extension(name)
+        }
+    }
+
     /// Returns the "best" adapter on this system.
     ///
     /// This is an alias for `Connection::create_hardware_adapter()`.
@@ -77,12 +124,63 @@ impl Connection {
         Ok(Adapter)
     }
 
+    /// Returns the adapter whose driver or device name contains `needle`, ignoring case.
+    ///
+    /// This platform has a single adapter and no way to query its name, so this always returns
+    /// `Error::NoAdapterFound`.
+    #[inline]
+    pub fn create_adapter_by_name(&self, _: &str) -> Result<Adapter, Error> {
+        Err(Error::NoAdapterFound)
+    }
+
+    /// Returns a key that describes the adapter, which can be saved and passed to
+    /// `find_adapter()` in a later run to find the same adapter again.
+    ///
+    /// This platform has a single adapter, so the key only records the backend.
+    #[inline]
+    pub fn adapter_key(&self, _: &Adapter) -> Result<AdapterKey, Error> {
+        Ok(AdapterKey {
+            backend: ADAPTER_KEY_BACKEND.to_owned(),
+            kind: AdapterKind::HighPerformance,
+            pci_id: None,
+            adapter_luid: None,
+            registry_id: None,
+            driver: None,
+        })
+    }
+
+    /// Returns the adapter that a key from `adapter_key()` describes.
+    ///
+    /// Returns `Error::IncompatibleAdapter` if the key was taken with another backend.
+    pub fn find_adapter(&self, key: &AdapterKey) -> Result<Adapter, Error> {
+        let candidate = self.adapter_key(&Adapter)?;
+        identity::find_adapter(ADAPTER_KEY_BACKEND, key, vec![(Adapter, candidate)])
+    }
+
+    /// Returns true if the two adapters are the same adapter.
+    ///
+    /// This platform has a single adapter, so this always returns true.
+    #[inline]
+    pub fn is_same_adapter(&self, _: &Adapter, _: &Adapter) -> bool {
+        true
+    }
+
+    /// Returns the adapter for the GPU that drives the output the window is on.
+    ///
+    /// This platform has a single adapter, so this always returns it.
+    #[inline]
+    pub fn adapter_for_widget(&self, _: &NativeWidget) -> Result<Adapter, Error> {
+        Ok(Adapter)
+    }
+
     /// Opens the hardware device corresponding to the given adapter.
     ///
     /// Device handles are local to a single thread.
     #[inline]
     pub fn create_device(&self, _: &Adapter) -> Result<Device, Error> {
-        Device::new()
+        let device = Device::new()?;
+        acceleration::check_device(&device);
+        Ok(device)
     }
 
     /// Wraps an Android `EGLDisplay` in a device and returns it.
@@ -98,6 +196,12 @@ impl Connection {
         Ok(Device {
             egl_display: native_device.0,
             display_is_owned: false,
+            cancel_token: CancelToken::new(),
+            scheduling_class: SchedulingClass::Interactive,
+            low_memory_options: None,
+            debug_fill: DebugFill::None,
+            lost_contexts: LostContexts::default(),
+            negotiated_versions: NegotiatedVersions::default(),
         })
     }
 
@@ -114,27 +218,26 @@ impl Connection {
     }
 
     #[cfg(android_platform)]
-    fn create_native_widget_from_ptr_impl(raw: *mut c_void) -> NativeWidget {
-        NativeWidget {
-            native_window: raw as *mut ANativeWindow,
-        }
+    unsafe fn create_native_widget_from_ptr_impl(raw: *mut c_void) -> NativeWidget {
+        NativeWidget::from_native_window(raw as *mut ANativeWindow)
     }
 
     #[cfg(ohos_platform)]
-    fn create_native_widget_from_ptr_impl(raw: *mut c_void) -> NativeWidget {
-        NativeWidget {
-            native_window: raw as *mut OHNativeWindow,
-        }
+    unsafe fn create_native_widget_from_ptr_impl(raw: *mut c_void) -> NativeWidget {
+        NativeWidget::from_native_window(raw as *mut OHNativeWindow)
     }
 
     /// Create a native widget from a raw pointer
+    ///
+    /// The real size of the window is queried from the platform. If it differs from `size`,
+    /// `native_widget_adjusted_size()` reports it.
     pub unsafe fn create_native_widget_from_ptr(
         &self,
         raw: *mut c_void,
-        _size: Size2D<i32>,
+        size: Size2D<i32>,
     ) -> NativeWidget {
         debug_assert!(!raw.is_null());
-        Self::create_native_widget_from_ptr_impl(raw)
+        check_native_widget_size(Self::create_native_widget_from_ptr_impl(raw), size)
     }
 
     #[cfg(all(feature = "sm-raw-window-handle-05", android_platform))]
@@ -145,9 +248,11 @@ impl Connection {
         use rwh_05::RawWindowHandle::AndroidNdk;
 
         match raw_handle {
-            AndroidNdk(handle) => Ok(NativeWidget {
-                native_window: handle.a_native_window as *mut _,
-            }),
+            AndroidNdk(handle) => unsafe {
+                Ok(NativeWidget::from_native_window(
+                    handle.a_native_window as *mut _,
+                ))
+            },
             _ => Err(Error::IncompatibleNativeWidget),
         }
     }
@@ -166,9 +271,10 @@ impl Connection {
     pub fn create_native_widget_from_raw_window_handle(
         &self,
         raw_handle: rwh_05::RawWindowHandle,
-        _size: Size2D<i32>,
+        size: Size2D<i32>,
     ) -> Result<NativeWidget, Error> {
-        create_native_widget_from_rwh_05_handle(raw_handle)
+        let native_widget = Self::create_native_widget_from_rwh_05_handle(raw_handle)?;
+        unsafe { Ok(check_native_widget_size(native_widget, size)) }
     }
 
     #[cfg(all(feature = "sm-raw-window-handle-06", android_platform))]
@@ -179,9 +285,11 @@ impl Connection {
         use rwh_06::RawWindowHandle::AndroidNdk;
 
         match handle.as_raw() {
-            AndroidNdk(handle) => Ok(NativeWidget {
-                native_window: handle.a_native_window.as_ptr() as *mut _,
-            }),
+            AndroidNdk(handle) => unsafe {
+                Ok(NativeWidget::from_native_window(
+                    handle.a_native_window.as_ptr() as *mut _,
+                ))
+            },
             _ => Err(Error::IncompatibleNativeWidget),
         }
     }
@@ -194,9 +302,11 @@ impl Connection {
         use rwh_06::RawWindowHandle::OhosNdk;
 
         match handle.as_raw() {
-            OhosNdk(handle) => Ok(NativeWidget {
-                native_window: handle.native_window.as_ptr().cast(),
-            }),
+            OhosNdk(handle) => unsafe {
+                Ok(NativeWidget::from_native_window(
+                    handle.native_window.as_ptr().cast(),
+                ))
+            },
             _ => Err(Error::IncompatibleNativeWidget),
         }
     }
@@ -207,10 +317,29 @@ impl Connection {
     pub fn create_native_widget_from_window_handle(
         &self,
         handle: rwh_06::WindowHandle,
-        _size: Size2D<i32>,
+        size: Size2D<i32>,
     ) -> Result<NativeWidget, Error> {
-        Self::create_native_widget_from_rwh_06_handle(handle)
+        let native_widget = Self::create_native_widget_from_rwh_06_handle(handle)?;
+        unsafe { Ok(check_native_widget_size(native_widget, size)) }
     }
+
+    /// Returns the real size of a native widget if it differs from the size that the widget was
+    /// created with.
+    #[inline]
+    pub fn native_widget_adjusted_size(&self, native_widget: &NativeWidget) -> Option<Size2D<i32>> {
+        native_widget.adjusted_size
+    }
+}
+
+// Records the real size of the window if it differs from the size that the caller gave.
+unsafe fn check_native_widget_size(
+    mut native_widget: NativeWidget,
+    size: Size2D<i32>,
+) -> NativeWidget {
+    native_widget.adjusted_size = native_widget
+        .size()
+        .and_then(|actual_size| crate::surface::adjusted_widget_size(size, actual_size));
+    native_widget
 }
 
 impl NativeConnection {
@@ -3,10 +3,16 @@
 //! A thread-local handle to the device.
 
 use super::connection::Connection;
+use crate::context::NegotiatedVersions;
 use crate::egl;
 use crate::egl::types::EGLDisplay;
-use crate::platform::generic::egl::device::EGL_FUNCTIONS;
-use crate::{Error, GLApi};
+use crate::lost::LostContexts;
+use crate::platform::generic::egl::context;
+use crate::platform::generic::egl::device::{self, EGL_FUNCTIONS};
+use crate::DebugFill;
+use crate::LowMemoryOptions;
+use crate::{CancelToken, DeviceIdentity, Error, GLApi};
+use crate::{SchedulingClass, SchedulingEnforcement};
 
 /// Represents a hardware display adapter that can be used for rendering (including the CPU).
 ///
@@ -20,12 +26,26 @@ pub struct Adapter;
 pub struct Device {
     pub(crate) egl_display: EGLDisplay,
     pub(crate) display_is_owned: bool,
+    pub(crate) cancel_token: CancelToken,
+    pub(crate) scheduling_class: SchedulingClass,
+    pub(crate) low_memory_options: Option<LowMemoryOptions>,
+    pub(crate) debug_fill: DebugFill,
+    pub(crate) lost_contexts: LostContexts,
+    pub(crate) negotiated_versions: NegotiatedVersions,
 }
 
 /// Wrapper for an `EGLDisplay`.
+///
+/// This is `Send` and `Sync`, since EGL is thread-safe. The display belongs to the `Device` that
+/// returned it, and is only valid while that device lives.
 #[derive(Clone, Copy)]
 pub struct NativeDevice(pub EGLDisplay);
 
+unsafe impl Send for NativeDevice {}
+unsafe impl Sync for NativeDevice {}
+
+assert_send_sync!(NativeDevice);
+
 impl Drop for Device {
     fn drop(&mut self) {
         EGL_FUNCTIONS.with(|egl| unsafe {
@@ -64,6 +84,12 @@ impl Device {
                 Ok(Device {
                     egl_display,
                     display_is_owned: true,
+                    cancel_token: CancelToken::new(),
+                    scheduling_class: SchedulingClass::Interactive,
+                    low_memory_options: None,
+                    debug_fill: DebugFill::None,
+                    lost_contexts: LostContexts::default(),
+                    negotiated_versions: NegotiatedVersions::default(),
                 })
             }
         })
@@ -92,4 +118,83 @@ impl Device {
     pub fn gl_api(&self) -> GLApi {
         GLApi::GLES
     }
+
+    /// Returns the identity of the physical GPU that this device renders with.
+    ///
+    /// Apps usually can't see the DRM device nodes, so this is often unknown and matches nothing.
+    #[inline]
+    pub fn identity(&self) -> DeviceIdentity {
+        unsafe { device::display_identity(self.egl_display) }
+    }
+
+    /// Returns a token that can cancel context and surface creation on this device from any
+    /// thread.
+    #[inline]
+    pub fn cancellation_token(&self) -> CancelToken {
+        self.cancel_token.clone()
+    }
+
+    /// Sets what kind of work this device's contexts do, and returns how that is enforced.
+    ///
+    /// `Background` devices create their contexts at `ContextPriority::Low` if the driver
+    /// supports `EGL_IMG_context_priority`. Otherwise, the device's swap chains are paced in
+    /// software. See `SchedulingEnforcement`.
+    pub fn set_scheduling_class(&mut self, class: SchedulingClass) -> SchedulingEnforcement {
+        self.scheduling_class = class;
+        self.scheduling_enforcement()
+    }
+
+    /// Returns the scheduling class that was last set with `set_scheduling_class()`.
+    #[inline]
+    pub fn scheduling_class(&self) -> SchedulingClass {
+        self.scheduling_class
+    }
+
+    /// Returns how this device enforces its scheduling class.
+    pub fn scheduling_enforcement(&self) -> SchedulingEnforcement {
+        let driver_priorities = unsafe {
+            device::display_supports_extension(self.egl_display, "EGL_IMG_context_priority")
+        };
+        self.scheduling_class.enforcement(driver_priorities, false)
+    }
+
+    /// Makes this device save memory at the expense of rendering quality until
+    /// `exit_low_memory_mode()` is called, and returns how many pooled surfaces were destroyed.
+    ///
+    /// Entering low-memory mode again replaces the options. See `LowMemoryOptions`.
+    pub fn enter_low_memory_mode(&mut self, options: LowMemoryOptions) -> usize {
+        self.low_memory_options = Some(options);
+        if !options.purge_pools {
+            return 0;
+        }
+        unsafe { context::purge_shared_placeholders(self.egl_display) }
+    }
+
+    /// Leaves low-memory mode, so that surfaces created from now on get their usual formats.
+    ///
+    /// Surfaces created in low-memory mode keep their formats until they are destroyed.
+    #[inline]
+    pub fn exit_low_memory_mode(&mut self) {
+        self.low_memory_options = None;
+    }
+
+    /// Returns the options that this device entered low-memory mode with, or `None` if it isn't
+    /// in low-memory mode.
+    #[inline]
+    pub fn low_memory_options(&self) -> Option<LowMemoryOptions> {
+        self.low_memory_options
+    }
+
+    /// Makes this device fill each surface that it creates from now on, and each surface that a
+    /// swap chain recycles, with a recognizable pattern. See `DebugFill`.
+    #[inline]
+    pub fn set_debug_surface_fill(&mut self, fill: DebugFill) {
+        self.debug_fill = fill;
+    }
+
+    /// Returns the debug fill that was last set with `set_debug_surface_fill()`.
+    #[inline]
+    pub fn debug_surface_fill(&self) -> DebugFill {
+        self.debug_fill
+    }
 }
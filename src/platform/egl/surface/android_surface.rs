@@ -2,39 +2,94 @@
 //
 //! Surface management for Android using the `GraphicBuffer` class and EGL.
 
+use super::super::android_ffi::AHARDWAREBUFFER_USAGE_CPU_READ_NEVER;
+use super::super::android_ffi::AHARDWAREBUFFER_USAGE_CPU_WRITE_OFTEN;
 use super::super::android_ffi::{
     AHardwareBuffer, AHardwareBuffer_Desc, AHARDWAREBUFFER_USAGE_GPU_SAMPLED_IMAGE,
 };
 use super::super::android_ffi::{AHardwareBuffer_allocate, AHardwareBuffer_release, ANativeWindow};
 use super::super::android_ffi::{ANativeWindow_getHeight, ANativeWindow_getWidth};
 use super::super::android_ffi::{
-    AHARDWAREBUFFER_FORMAT_R8G8B8A8_UNORM, AHARDWAREBUFFER_USAGE_CPU_READ_NEVER,
+    AHARDWAREBUFFER_FORMAT_R10G10B10A2_UNORM, AHARDWAREBUFFER_FORMAT_R16G16B16A16_FLOAT,
+};
+use super::super::android_ffi::{
+    AHARDWAREBUFFER_FORMAT_R5G6B5_UNORM, AHARDWAREBUFFER_FORMAT_R8G8B8A8_UNORM,
+};
+use super::super::android_ffi::{
+    AHARDWAREBUFFER_USAGE_CPU_READ_OFTEN, AHARDWAREBUFFER_USAGE_CPU_READ_RARELY,
 };
 use super::super::android_ffi::{
     AHARDWAREBUFFER_USAGE_CPU_WRITE_NEVER, AHARDWAREBUFFER_USAGE_GPU_FRAMEBUFFER,
 };
+use super::super::android_ffi::{
+    ANATIVEWINDOW_TRANSFORM_IDENTITY, ANATIVEWINDOW_TRANSFORM_ROTATE_180,
+    ANATIVEWINDOW_TRANSFORM_ROTATE_270, ANATIVEWINDOW_TRANSFORM_ROTATE_90,
+};
+use super::super::android_ffi::{NATIVE_WINDOW_FUNCTIONS, NATIVE_WINDOW_TRANSFORM_HINT};
 use super::super::context::{Context, GL_FUNCTIONS};
 use super::super::device::Device;
-use super::{Surface, SurfaceTexture};
+use super::{PreTransform, Surface, SurfaceTexture};
+use crate::bulk;
+use crate::clear::ClearRegion;
+use crate::convert::{self, Swizzle};
 use crate::egl;
-use crate::egl::types::{EGLSurface, EGLint};
+use crate::egl::types::{EGLDisplay, EGLSurface, EGLint};
 use crate::gl;
 use crate::gl::types::{GLenum, GLuint};
 use crate::gl_utils;
+use crate::invalidate::PostPresentInvalidation;
+use crate::leak_check::{self, TrackedObject};
+use crate::memory::{PurgeState, ResidentBacking, GL_RGB565};
+use crate::pause::{HeldFrame, PresentPause};
 use crate::platform::generic;
-use crate::platform::generic::egl::device::EGL_FUNCTIONS;
+use crate::platform::generic::egl::context::{config_color_bits, widget_config};
+use crate::platform::generic::egl::device::{self, EGL_FUNCTIONS};
 use crate::platform::generic::egl::ffi::EGLImageKHR;
 use crate::platform::generic::egl::ffi::EGL_EXTENSION_FUNCTIONS;
 use crate::platform::generic::egl::ffi::EGL_IMAGE_PRESERVED_KHR;
 use crate::platform::generic::egl::ffi::EGL_NATIVE_BUFFER_ANDROID;
 use crate::platform::generic::egl::ffi::EGL_NO_IMAGE_KHR;
+use crate::platform::generic::egl::ffi::EGL_SURFACE_ORIENTATION_ANGLE;
+use crate::platform::generic::egl::ffi::{
+    EGL_SURFACE_ORIENTATION_INVERT_X_ANGLE, EGL_SURFACE_ORIENTATION_INVERT_Y_ANGLE,
+};
+use crate::platform::generic::egl::surface::{
+    clear_window_surface, flush_window_surface, preserve_color_buffer, preserves_color_buffer,
+    set_swap_interval, supports_adaptive_swap, swap_window_surface, window_surface_size,
+    with_window_surface_current, BufferAge, EGLFence, FrameTimestamps, FrontBuffer, PresentFence,
+    PresentationFeedback,
+};
+use crate::presentation_time;
+use crate::readback;
 use crate::renderbuffers::Renderbuffers;
-use crate::{Error, SurfaceAccess, SurfaceID, SurfaceInfo, SurfaceType, WindowingApiError};
+use crate::surface::{check_surface_size, PresentHistory};
+use crate::upload;
+use crate::vsync::AutoVsync;
+use crate::BufferRelease;
+use crate::DmaBuf;
+use crate::GLCapabilities;
+use crate::InvalidateSet;
+use crate::LowMemoryOptions;
+use crate::PausedPresentation;
+use crate::PresentationStatistics;
+use crate::SurfaceFence;
+use crate::TextureOwnership;
+use crate::{AutoVsyncPolicy, AutoVsyncStats};
+use crate::{ColorBits, ColorEncoding, SurfaceOptions};
+use crate::{
+    ColorProfile, ColorProfileChangedHandler, Error, PresentRecord, Purgeability, ReclaimResult,
+    SurfaceAccess, SurfaceID, SurfaceInfo, SurfaceResizedHandler, SurfaceScaleChangedHandler,
+    SurfaceType, WindowingApiError,
+};
+use crate::{NativeFormat, NativeFormatKind};
+use crate::{SurfaceConfig, SurfaceIntent, SurfaceTransform};
 
-use euclid::default::Size2D;
+use euclid::default::{Rect, Size2D};
 use std::marker::PhantomData;
+use std::os::fd::BorrowedFd;
 use std::os::raw::c_void;
 use std::ptr;
+use std::time::{Duration, Instant};
 
 const SURFACE_GL_TEXTURE_TARGET: GLenum = crate::gl::TEXTURE_2D;
 
@@ -48,14 +103,26 @@ pub(crate) enum SurfaceObjects {
     },
     Window {
         egl_surface: EGLSurface,
+        // Null for surfaces that surfman didn't create from a window.
+        native_window: *mut ANativeWindow,
     },
 }
 
 /// An Android native window.
+///
+/// Widgets are `Send` and `Sync`, since the `ANativeWindow` functions can be called from any
+/// thread, so a widget can be handed to a rendering thread. The window isn't acquired, so it must
+/// outlive the widget and any surface created from it.
 pub struct NativeWidget {
     pub(crate) native_window: *mut ANativeWindow,
+    pub(crate) adjusted_size: Option<Size2D<i32>>,
 }
 
+unsafe impl Send for NativeWidget {}
+unsafe impl Sync for NativeWidget {}
+
+assert_send_sync!(NativeWidget);
+
 impl Device {
     /// Creates either a generic or a widget surface, depending on the supplied surface type.
     ///
@@ -64,48 +131,91 @@ impl Device {
     pub fn create_surface(
         &mut self,
         context: &Context,
-        _: SurfaceAccess,
+        access: SurfaceAccess,
         surface_type: SurfaceType<NativeWidget>,
     ) -> Result<Surface, Error> {
-        match surface_type {
-            SurfaceType::Generic { size } => self.create_generic_surface(context, &size),
+        self.create_surface_with_options(context, access, surface_type, SurfaceOptions::default())
+    }
+
+    /// Creates a surface of each of the given types, in order.
+    ///
+    /// The device's cancellation token is checked before each surface, as well as inside each
+    /// creation. If any surface fails or is cancelled, the ones created so far are destroyed and
+    /// the error is returned, so the batch creates either all of its surfaces or none.
+    pub fn create_surfaces<I>(
+        &mut self,
+        context: &mut Context,
+        access: SurfaceAccess,
+        surface_types: I,
+    ) -> Result<Vec<Surface>, Error>
+    where
+        I: IntoIterator<Item = SurfaceType<NativeWidget>>,
+    {
+        bulk::create_surfaces(self, context, access, surface_types)
+    }
+
+    /// Creates either a generic or a widget surface, with options such as its colorspace.
+    ///
+    /// `create_surface()` is this method with the default options. sRGB surfaces need
+    /// `EGL_EXT_image_gl_colorspace` for generic surfaces and `EGL_KHR_gl_colorspace` for widget
+    /// surfaces; without them, this returns `Error::UnsupportedSurfaceFormat`.
+    pub fn create_surface_with_options(
+        &mut self,
+        context: &Context,
+        access: SurfaceAccess,
+        surface_type: SurfaceType<NativeWidget>,
+        options: SurfaceOptions,
+    ) -> Result<Surface, Error> {
+        self.cancel_token.check()?;
+        let mut surface = match surface_type {
+            SurfaceType::Generic { size } => {
+                self.create_generic_surface(context, &size, access, options)
+            }
             SurfaceType::Widget { native_widget } => unsafe {
-                self.create_window_surface(context, native_widget.native_window)
+                self.create_window_surface(context, native_widget.native_window, access, options)
             },
-        }
+        }?;
+        self.debug_fill_new_surface(context, &mut surface);
+        Ok(surface)
     }
 
     fn create_generic_surface(
         &mut self,
         context: &Context,
         size: &Size2D<i32>,
+        access: SurfaceAccess,
+        options: SurfaceOptions,
     ) -> Result<Surface, Error> {
-        let _guard = self.temporarily_make_context_current(context)?;
+        let _guard = self.make_context_current_guarded(context)?;
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+
+        // Hardware buffers have no 4-bit-per-channel format, so only RGB565 saves memory here.
+        // sRGB surfaces are RGBA8 buffers that EGL images decode and encode.
+        let linear_format =
+            LowMemoryOptions::surface_format(self.low_memory_options, &context_attributes, access);
+        let capabilities = GL_FUNCTIONS.with(GLCapabilities::current);
+        check_surface_size(*size, &capabilities)?;
+        let format = options.generic_surface_format(&capabilities, linear_format)?;
+        let format = match format {
+            GL_RGB565 | gl::SRGB8_ALPHA8 | gl::RGBA16F | gl::RGB10_A2 => format,
+            _ => gl::RGBA8,
+        };
+        if options.colorspace == ColorEncoding::Srgb
+            && !unsafe {
+                device::display_supports_extension(self.egl_display, "EGL_EXT_image_gl_colorspace")
+            }
+        {
+            return Err(Error::UnsupportedSurfaceFormat);
+        }
 
         GL_FUNCTIONS.with(|gl| {
             unsafe {
                 // Create a native hardware buffer.
-                let hardware_buffer_desc = AHardwareBuffer_Desc {
-                    format: AHARDWAREBUFFER_FORMAT_R8G8B8A8_UNORM,
-                    height: size.height as u32,
-                    width: size.width as u32,
-                    layers: 1,
-                    rfu0: 0,
-                    rfu1: 0,
-                    stride: 10,
-                    usage: AHARDWAREBUFFER_USAGE_CPU_READ_NEVER
-                        | AHARDWAREBUFFER_USAGE_CPU_WRITE_NEVER
-                        | AHARDWAREBUFFER_USAGE_GPU_FRAMEBUFFER
-                        | AHARDWAREBUFFER_USAGE_GPU_SAMPLED_IMAGE,
-                };
-                let mut hardware_buffer = ptr::null_mut();
-                let result = AHardwareBuffer_allocate(&hardware_buffer_desc, &mut hardware_buffer);
-                if result != 0 {
-                    return Err(Error::SurfaceCreationFailed(WindowingApiError::Failed));
-                }
+                let hardware_buffer = allocate_hardware_buffer(size, format, access)?;
 
                 // Create an EGL image, and bind it to a texture.
-                let egl_image = self.create_egl_image(context, hardware_buffer);
+                let egl_image = self.create_egl_image(context, hardware_buffer, options.colorspace);
 
                 // Initialize and bind the image to the texture.
                 let texture_object =
@@ -119,8 +229,6 @@ impl Device {
                 );
 
                 // Bind renderbuffers as appropriate.
-                let context_descriptor = self.context_descriptor(context);
-                let context_attributes = self.context_descriptor_attributes(&context_descriptor);
                 let renderbuffers = Renderbuffers::new(gl, size, &context_attributes);
                 renderbuffers.bind_to_current_framebuffer(gl);
 
@@ -129,9 +237,24 @@ impl Device {
                     gl::FRAMEBUFFER_COMPLETE
                 );
 
+                leak_check::created(TrackedObject::Surface);
                 Ok(Surface {
                     size: *size,
                     context_id: context.id,
+                    access,
+                    format,
+                    colorspace: options.colorspace,
+                    color_bits: ColorBits::of_format(format),
+                    present_history: PresentHistory::default(),
+                    auto_vsync: None,
+                    present_fence: None,
+                    frame_timestamps: None,
+                    invalidation: PostPresentInvalidation::default(),
+                    buffer_age: BufferAge::default(),
+                    front_buffer: FrontBuffer::default(),
+                    pause: PresentPause::default(),
+                    preserves_contents: true,
+                    pre_transform: None,
                     objects: SurfaceObjects::HardwareBuffer {
                         hardware_buffer,
                         egl_image,
@@ -139,6 +262,7 @@ impl Device {
                         texture_object,
                         renderbuffers,
                     },
+                    purge_state: PurgeState::default(),
                     destroyed: false,
                 })
             }
@@ -149,28 +273,104 @@ impl Device {
         &mut self,
         context: &Context,
         native_window: *mut ANativeWindow,
+        access: SurfaceAccess,
+        options: SurfaceOptions,
     ) -> Result<Surface, Error> {
+        let context_descriptor = self.context_descriptor(context);
+        options.check_widget_format(&self.context_descriptor_attributes(&context_descriptor))?;
         let width = ANativeWindow_getWidth(native_window);
         let height = ANativeWindow_getHeight(native_window);
 
+        let mut window_surface_attributes = vec![];
+        if options.colorspace == ColorEncoding::Srgb {
+            if !device::display_supports_extension(self.egl_display, "EGL_KHR_gl_colorspace") {
+                return Err(Error::UnsupportedSurfaceFormat);
+            }
+            window_surface_attributes.push(egl::GL_COLORSPACE as EGLint);
+            window_surface_attributes.push(egl::GL_COLORSPACE_SRGB as EGLint);
+        }
+        window_surface_attributes.push(egl::NONE as EGLint);
+
+        let egl_config = widget_config(
+            self.egl_display,
+            self.context_to_egl_config(context),
+            &options,
+            None,
+            &|_| None,
+        )?;
         EGL_FUNCTIONS.with(|egl| {
             let egl_surface = egl.CreateWindowSurface(
                 self.egl_display,
-                self.context_to_egl_config(context),
+                egl_config,
                 native_window as *const c_void,
-                ptr::null(),
+                window_surface_attributes.as_ptr(),
             );
+            // Drivers refuse sRGB window surfaces for configs that they can't encode.
+            if egl_surface == egl::NO_SURFACE && options.colorspace == ColorEncoding::Srgb {
+                return Err(Error::UnsupportedSurfaceFormat);
+            }
             assert_ne!(egl_surface, egl::NO_SURFACE);
+            let requested = preserve_color_buffer(self.egl_display, egl_surface, &options)
+                .and_then(|preserved| {
+                    let front_buffer =
+                        FrontBuffer::request(self.egl_display, egl_surface, &options)?;
+                    Ok((front_buffer.requested() || preserved, front_buffer))
+                });
+            let (preserves_contents, front_buffer) = match requested {
+                Ok(requested) => requested,
+                Err(err) => {
+                    egl.DestroySurface(self.egl_display, egl_surface);
+                    return Err(err);
+                }
+            };
 
+            leak_check::created(TrackedObject::Surface);
             Ok(Surface {
                 context_id: context.id,
                 size: Size2D::new(width, height),
-                objects: SurfaceObjects::Window { egl_surface },
+                access,
+                format: gl::NONE,
+                colorspace: options.colorspace,
+                color_bits: config_color_bits(self.egl_display, egl_config),
+                present_history: PresentHistory::default(),
+                auto_vsync: None,
+                present_fence: None,
+                frame_timestamps: FrameTimestamps::new(self.egl_display, egl_surface),
+                invalidation: PostPresentInvalidation::default(),
+                buffer_age: BufferAge::new(self.egl_display),
+                front_buffer,
+                pause: PresentPause::default(),
+                preserves_contents,
+                pre_transform: if options.pre_transform {
+                    Some(PreTransform::default())
+                } else {
+                    None
+                },
+                objects: SurfaceObjects::Window {
+                    egl_surface,
+                    native_window,
+                },
+                purge_state: PurgeState::default(),
                 destroyed: false,
             })
         })
     }
 
+    /// Returns the access mode, type, and options that suit a surface of the given size, created
+    /// with the given context for the given use, on this device, along with hints for using it.
+    ///
+    /// Hardware buffers can be allocated where the CPU can see them, but this backend doesn't map
+    /// them yet, so the access mode is always `GPUOnly`.
+    pub fn recommended_surface_config(
+        &self,
+        context: &Context,
+        intent: SurfaceIntent<NativeWidget>,
+        size: Size2D<i32>,
+    ) -> Result<SurfaceConfig<NativeWidget>, Error> {
+        let capabilities = self.capabilities(context)?;
+        Ok(SurfaceConfig::recommend(intent, size, false, &capabilities))
+    }
+
     /// Creates a surface texture from an existing generic surface for use with the given context.
     ///
     /// The surface texture is local to the supplied context and takes ownership of the surface.
@@ -189,17 +389,24 @@ impl Device {
         unsafe {
             match surface.objects {
                 SurfaceObjects::Window { .. } => return Err((Error::WidgetAttached, surface)),
+                SurfaceObjects::HardwareBuffer { .. }
+                    if surface.purge_state.check_usable().is_err() =>
+                {
+                    return Err((Error::SurfaceNotReclaimed, surface))
+                }
                 SurfaceObjects::HardwareBuffer {
                     hardware_buffer, ..
                 } => GL_FUNCTIONS.with(|gl| {
-                    let _guard = match self.temporarily_make_context_current(context) {
+                    let _guard = match self.make_context_current_guarded(context) {
                         Ok(guard) => guard,
                         Err(err) => return Err((err, surface)),
                     };
 
-                    let local_egl_image = self.create_egl_image(context, hardware_buffer);
+                    let local_egl_image =
+                        self.create_egl_image(context, hardware_buffer, surface.colorspace);
                     let texture_object =
                         generic::egl::surface::bind_egl_image_to_gl_texture(gl, local_egl_image);
+                    leak_check::created(TrackedObject::SurfaceTexture);
                     Ok(SurfaceTexture {
                         surface,
                         local_egl_image,
@@ -217,39 +424,637 @@ impl Device {
     /// associated widgets until this method is called.
     ///
     /// The supplied context must match the context the surface was created with, or an
-    /// `IncompatibleSurface` error is returned.
+    /// `IncompatibleSurface` error is returned. The context doesn't need to be current, and the
+    /// context that was current before this call remains current afterward.
+    ///
+    /// A window that has been resized since the surface was last sized fails with
+    /// `SurfaceSizeMismatch` without presenting.
     pub fn present_surface(&self, context: &Context, surface: &mut Surface) -> Result<(), Error> {
+        self.present(context, surface, None)
+    }
+
+    /// Displays the changed parts of a widget surface on screen.
+    ///
+    /// `damage` lists the rectangles that changed since the last present, measured from the
+    /// bottom left as in OpenGL, and is passed on with `EGL_KHR_swap_buffers_with_damage` where
+    /// the display supports it. Otherwise, the whole surface is swapped, as `present_surface()`
+    /// does. An empty list means that nothing changed, and skips the swap entirely.
+    pub fn present_surface_with_damage(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        self.present(context, surface, Some(damage))
+    }
+
+    fn present(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: Option<&[Rect<i32>]>,
+    ) -> Result<(), Error> {
         if context.id != surface.context_id {
             return Err(Error::IncompatibleSurface);
         }
+        if !matches!(damage, Some([])) {
+            self.check_native_size(surface)?;
+        }
 
-        EGL_FUNCTIONS.with(|egl| unsafe {
-            match surface.objects {
-                SurfaceObjects::Window { egl_surface } => {
-                    egl.SwapBuffers(self.egl_display, egl_surface);
-                    Ok(())
+        let paused = surface.pause.is_paused();
+        match surface.objects {
+            SurfaceObjects::Window { .. } if paused && matches!(damage, Some([])) => {
+                Err(Error::PresentationPaused)
+            }
+            SurfaceObjects::Window { egl_surface, .. } if paused => unsafe {
+                let size = surface.size;
+                let frame = self.lost_contexts.check(context.id, || {
+                    let egl_context = context.egl_context;
+                    with_window_surface_current(self.egl_display, egl_surface, egl_context, || {
+                        GL_FUNCTIONS.with(|gl| HeldFrame::read(gl, size))
+                    })
+                })?;
+                surface.pause.hold(frame);
+                Err(Error::PresentationPaused)
+            },
+            SurfaceObjects::Window { .. } if matches!(damage, Some([])) => Ok(()),
+            // Frames rendered to the front buffer are already on screen once they're flushed.
+            SurfaceObjects::Window { egl_surface, .. } if surface.front_buffer.bound() => unsafe {
+                let submit = Instant::now();
+                self.lost_contexts.check(context.id, || {
+                    GL_FUNCTIONS.with(|gl| {
+                        flush_window_surface(gl, self.egl_display, egl_surface, context.egl_context)
+                    })
+                })?;
+                surface.buffer_age.front_buffer();
+                surface
+                    .present_history
+                    .record(submit, Some(Instant::now()), None);
+                Ok(())
+            },
+            SurfaceObjects::Window {
+                egl_surface,
+                native_window,
+            } => unsafe {
+                let submit = Instant::now();
+                if let Some(ref mut pre_transform) = surface.pre_transform {
+                    apply_pre_transform(
+                        self.egl_display,
+                        egl_surface,
+                        native_window,
+                        pre_transform,
+                    );
                 }
-                SurfaceObjects::HardwareBuffer { .. } => Err(Error::NoWidgetAttached),
+                let frame_id = surface.present_history.next_frame_id();
+                let frame_timestamps = &mut surface.frame_timestamps;
+                let (invalidation, buffer_age) =
+                    (&mut surface.invalidation, &mut surface.buffer_age);
+                let front_buffer = &mut surface.front_buffer;
+                let present_fence = self.lost_contexts.check(context.id, || {
+                    swap_window_surface(
+                        self.egl_display,
+                        egl_surface,
+                        context.egl_context,
+                        damage,
+                        |egl_display| {
+                            if let Some(ref mut frame_timestamps) = *frame_timestamps {
+                                frame_timestamps.presenting(frame_id)?;
+                            }
+                            Ok(PresentFence::insert(egl_display))
+                        },
+                        || {
+                            GL_FUNCTIONS.with(|gl| invalidation.invalidate(gl, 0));
+                            buffer_age.record(self.egl_display, egl_surface, invalidation.set());
+                            front_buffer.swapped(self.egl_display, context.egl_context, buffer_age);
+                        },
+                    )
+                })?;
+                surface.present_fence = Some(present_fence);
+                surface
+                    .present_history
+                    .record(submit, Some(Instant::now()), None);
+                if let Some(ref mut auto_vsync) = surface.auto_vsync {
+                    auto_vsync.update(&surface.present_history, |interval| {
+                        set_swap_interval(
+                            self.egl_display,
+                            egl_surface,
+                            context.egl_context,
+                            interval,
+                        )
+                    });
+                }
+                Ok(())
+            },
+            SurfaceObjects::HardwareBuffer { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Pauses the presentation of a widget surface: until it's resumed, each frame presented is
+    /// read back and held instead of shown, and `present_surface()` returns `PresentationPaused`.
+    ///
+    /// Surfaces that render to the front buffer return `UnsupportedPresentMode`, and contexts
+    /// without `glBlitFramebuffer()` return `RequiredExtensionUnavailable`.
+    pub fn pause_presentation(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<PausedPresentation, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if let SurfaceObjects::HardwareBuffer { .. } = surface.objects {
+            return Err(Error::NoWidgetAttached);
+        }
+        // Frames rendered to the front buffer are on screen as soon as they're drawn.
+        if surface.front_buffer.requested() {
+            return Err(Error::UnsupportedPresentMode);
+        }
+        if !self.capabilities(context)?.blit_framebuffer {
+            return Err(Error::RequiredExtensionUnavailable);
+        }
+        surface.pause.pause()
+    }
+
+    /// Ends a pause in the presentation of a widget surface.
+    ///
+    /// If `present_pending` is true, the frame held during the pause is drawn back into the
+    /// surface and presented as by `present_surface()`; otherwise, it's discarded.
+    pub fn resume_presentation(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        paused: PausedPresentation,
+        present_pending: bool,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        let egl_surface = match surface.objects {
+            SurfaceObjects::Window { egl_surface, .. } => egl_surface,
+            SurfaceObjects::HardwareBuffer { .. } => return Err(Error::NoWidgetAttached),
+        };
+        match surface.pause.resume(paused)? {
+            Some(frame) if present_pending => {
+                self.lost_contexts.check(context.id, || unsafe {
+                    let egl_context = context.egl_context;
+                    with_window_surface_current(self.egl_display, egl_surface, egl_context, || {
+                        GL_FUNCTIONS.with(|gl| frame.draw(gl))
+                    })
+                })?;
+                self.present_surface(context, surface)
             }
-        })
+            _ => Ok(()),
+        }
+    }
+
+    /// Displays the contents of a widget surface on screen, and returns a fence that signals once
+    /// the GPU has finished rendering it.
+    ///
+    /// The fence is an `EGL_SYNC_FENCE`, or a native fence where the display supports
+    /// `EGL_ANDROID_native_fence_sync`, inserted with the surface's context current right after
+    /// its buffers are swapped. Generic surfaces aren't presented, and only get the fence.
+    pub fn present_surface_with_fence(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<SurfaceFence, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if let SurfaceObjects::Window { .. } = surface.objects {
+            self.present_surface(context, surface)?;
+        }
+        let _guard = self.make_context_current_guarded(context)?;
+        let egl_fence =
+            GL_FUNCTIONS.with(|gl| unsafe { EGLFence::insert(gl, self.egl_display) })?;
+        Ok(SurfaceFence { egl_fence })
+    }
+
+    /// Makes the context wait on the GPU until the fence signals, with `eglWaitSync()`.
+    pub fn wait_fence(&self, context: &Context, fence: &SurfaceFence) -> Result<(), Error> {
+        let _guard = self.make_context_current_guarded(context)?;
+        unsafe { fence.egl_fence.wait(self.egl_display) }
+    }
+
+    /// Returns a native fence that signals when the sync file `fd` does, which needs
+    /// `EGL_ANDROID_native_fence_sync`. The descriptor is duplicated, so it stays the caller's.
+    pub fn import_sync_fd(&self, context: &Context, fd: BorrowedFd) -> Result<SurfaceFence, Error> {
+        let _guard = self.make_context_current_guarded(context)?;
+        let egl_fence = unsafe { EGLFence::import(self.egl_display, fd)? };
+        Ok(SurfaceFence { egl_fence })
     }
 
-    /// Resizes a widget surface.
+    /// Resizes a surface in place.
+    ///
+    /// Generic surfaces get a new hardware buffer of the new size, which is attached to their
+    /// existing framebuffer object, so their ID changes. Widget surfaces only record the size,
+    /// since EGL resizes window surfaces to follow their window.
     pub fn resize_surface(
         &self,
-        _context: &Context,
+        context: &Context,
         surface: &mut Surface,
         size: Size2D<i32>,
     ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        check_surface_size(size, &self.capabilities(context)?)?;
+
+        if let SurfaceObjects::HardwareBuffer {
+            ref mut hardware_buffer,
+            ref mut egl_image,
+            framebuffer_object,
+            ref mut texture_object,
+            ref renderbuffers,
+        } = surface.objects
+        {
+            surface.purge_state.check_usable()?;
+            let _guard = self.make_context_current_guarded(context)?;
+            unsafe {
+                let new_hardware_buffer =
+                    allocate_hardware_buffer(&size, surface.format, surface.access)?;
+                let new_egl_image =
+                    self.create_egl_image(context, new_hardware_buffer, surface.colorspace);
+                GL_FUNCTIONS.with(|gl| {
+                    let new_texture_object =
+                        generic::egl::surface::bind_egl_image_to_gl_texture(gl, new_egl_image);
+
+                    // Attach the new texture to the existing framebuffer, so that it stays valid.
+                    let mut old_framebuffer_object = 0;
+                    gl.GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut old_framebuffer_object);
+                    gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
+                    gl.FramebufferTexture2D(
+                        gl::FRAMEBUFFER,
+                        gl::COLOR_ATTACHMENT0,
+                        SURFACE_GL_TEXTURE_TARGET,
+                        new_texture_object,
+                        0,
+                    );
+                    renderbuffers.resize(gl, &size);
+                    debug_assert_eq!(
+                        gl.CheckFramebufferStatus(gl::FRAMEBUFFER),
+                        gl::FRAMEBUFFER_COMPLETE
+                    );
+                    gl.BindFramebuffer(gl::FRAMEBUFFER, old_framebuffer_object as GLuint);
+
+                    gl.DeleteTextures(1, texture_object);
+                    *texture_object = new_texture_object;
+                });
+
+                let result =
+                    (EGL_EXTENSION_FUNCTIONS.DestroyImageKHR)(self.egl_display, *egl_image);
+                assert_ne!(result, egl::FALSE);
+                *egl_image = new_egl_image;
+                AHardwareBuffer_release(*hardware_buffer);
+                *hardware_buffer = new_hardware_buffer;
+            }
+        }
+
+        // Window surfaces get new buffers of the new size.
+        surface.buffer_age.reset();
+        surface.pause.force_resume();
         surface.size = size;
         Ok(())
     }
 
+    /// Sets the number of vertical blanks that `present_surface()` waits for before showing each
+    /// frame on a widget surface, and returns the interval that took effect.
+    ///
+    /// Negative intervals are only honored if the surface's EGL config allows them, and are
+    /// otherwise replaced with their absolute value. Intervals outside the range that the config
+    /// supports return `UnsupportedOnThisPlatform`.
+    pub fn set_swap_interval(
+        &mut self,
+        context: &Context,
+        surface: &Surface,
+        interval: i32,
+    ) -> Result<i32, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        match surface.objects {
+            SurfaceObjects::Window { egl_surface, .. } => unsafe {
+                set_swap_interval(self.egl_display, egl_surface, context.egl_context, interval)
+            },
+            SurfaceObjects::HardwareBuffer { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Returns true if the EGL config of a widget surface allows the negative swap intervals of
+    /// adaptive vsync.
+    pub fn supports_adaptive_swap(&self, surface: &Surface) -> bool {
+        match surface.objects {
+            SurfaceObjects::Window { egl_surface, .. } => unsafe {
+                supports_adaptive_swap(self.egl_display, egl_surface)
+            },
+            SurfaceObjects::HardwareBuffer { .. } => false,
+        }
+    }
+
+    /// Lets surfman switch a widget surface between vsync and immediate presentation by itself,
+    /// following `policy`, or stops it with `None`.
+    ///
+    /// The frame time is measured from the return of `eglSwapBuffers()`.
+    pub fn set_auto_swap_interval(
+        &mut self,
+        context: &Context,
+        surface: &mut Surface,
+        policy: Option<AutoVsyncPolicy>,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        match surface.objects {
+            SurfaceObjects::Window { egl_surface, .. } => {
+                if policy.is_some() {
+                    unsafe {
+                        set_swap_interval(self.egl_display, egl_surface, context.egl_context, 1)?;
+                    }
+                }
+                surface.auto_vsync = policy.map(AutoVsync::new);
+                Ok(())
+            }
+            SurfaceObjects::HardwareBuffer { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Returns what automatic vsync has decided for a surface, or `None` if it isn't enabled.
+    #[inline]
+    pub fn auto_swap_interval_stats(&self, surface: &Surface) -> Option<AutoVsyncStats> {
+        surface.auto_vsync.as_ref().map(AutoVsync::stats)
+    }
+
+    /// Waits until the buffer of the last frame presented on a widget surface has been released,
+    /// or until `timeout` elapses, and reports whether the call had to block.
+    ///
+    /// Android gives no signal for the release of EGL's buffers, so this waits on a fence inserted
+    /// when the frame was presented, which signals once the GPU has finished rendering it.
+    pub fn wait_previous_buffer_released(
+        &self,
+        surface: &Surface,
+        timeout: Duration,
+    ) -> Result<BufferRelease, Error> {
+        match surface.objects {
+            SurfaceObjects::Window { .. } => match surface.present_fence {
+                None => Ok(BufferRelease::Released),
+                Some(ref present_fence) => present_fence.wait(timeout),
+            },
+            SurfaceObjects::HardwareBuffer { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Chooses which attachments of a surface are invalidated right after each present.
+    ///
+    /// The color buffer of a widget surface can only be invalidated if its EGL surface doesn't
+    /// preserve it across swaps, and that of a generic surface never can. Either returns
+    /// `PreservedColorBuffer`.
+    pub fn set_post_present_invalidation(
+        &self,
+        surface: &mut Surface,
+        set: InvalidateSet,
+    ) -> Result<(), Error> {
+        // Consumers read the color buffers of generic surfaces once they're presented, and the
+        // front buffer is on screen.
+        let color_preserved = match surface.objects {
+            SurfaceObjects::Window { egl_surface, .. } => unsafe {
+                surface.front_buffer.requested()
+                    || preserves_color_buffer(self.egl_display, egl_surface)
+            },
+            SurfaceObjects::HardwareBuffer { .. } => true,
+        };
+        surface.invalidation.change(set, color_preserved)
+    }
+
+    /// Returns which attachments of a surface are invalidated right after each present.
+    #[inline]
+    pub fn post_present_invalidation(&self, surface: &Surface) -> InvalidateSet {
+        surface.invalidation.set()
+    }
+
+    /// Invalidates the attachments of a generic surface that were chosen with
+    /// `set_post_present_invalidation()`, making the context current for the call.
+    ///
+    /// Widget surfaces are invalidated by `present_surface()`, and return `WidgetAttached`.
+    pub fn invalidate_surface(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        let framebuffer_object = match surface.objects {
+            SurfaceObjects::HardwareBuffer {
+                framebuffer_object, ..
+            } => framebuffer_object,
+            SurfaceObjects::Window { .. } => return Err(Error::WidgetAttached),
+        };
+        let _guard = self.make_context_current_guarded(context)?;
+        GL_FUNCTIONS.with(|gl| unsafe { surface.invalidation.invalidate(gl, framebuffer_object) });
+        Ok(())
+    }
+
+    /// Clears a region of a surface, measured from its top left, to `color`, and its depth and
+    /// stencil buffers to `depth` and `stencil` if they're given and the surface has them.
+    ///
+    /// Widget surfaces are made current with the context for the call; hardware buffer surfaces
+    /// are cleared through their framebuffer object. Either way, the state that the clear changes
+    /// is put back afterward.
+    pub fn clear_surface_region(
+        &self,
+        context: &mut Context,
+        surface: &mut Surface,
+        rect: Option<Rect<i32>>,
+        color: [f32; 4],
+        depth: Option<f32>,
+        stencil: Option<i32>,
+    ) -> Result<(), Error> {
+        let region = ClearRegion::new(rect, color, depth, stencil);
+        self.clear_surface_with(context, surface, &region)
+    }
+
+    /// Fills a surface with this device's debug fill, as if it had just been created, or does
+    /// nothing if the fill is `DebugFill::None`.
+    pub fn debug_fill_surface(
+        &self,
+        context: &mut Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        match self.debug_fill.region() {
+            Some(region) => self.clear_surface_with(context, surface, &region),
+            None => Ok(()),
+        }
+    }
+
+    // Fills a new surface with the debug fill, if there is one. The surface is usable either way,
+    // so failing to fill it is only logged.
+    fn debug_fill_new_surface(&self, context: &Context, surface: &mut Surface) {
+        if let Some(region) = self.debug_fill.region() {
+            if let Err(err) = self.clear_surface_with(context, surface, &region) {
+                warn!(
+                    "Failed to fill a new surface with the debug fill: {:?}",
+                    err
+                );
+            }
+        }
+    }
+
+    fn clear_surface_with(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        region: &ClearRegion,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        match surface.objects {
+            SurfaceObjects::HardwareBuffer {
+                framebuffer_object, ..
+            } => {
+                surface.purge_state.check_usable()?;
+                let _guard = self.make_context_current_guarded(context)?;
+                GL_FUNCTIONS
+                    .with(|gl| unsafe { region.clear(gl, framebuffer_object, surface.size) })
+            }
+            SurfaceObjects::Window { egl_surface, .. } => GL_FUNCTIONS.with(|gl| unsafe {
+                clear_window_surface(
+                    gl,
+                    self.egl_display,
+                    egl_surface,
+                    context.egl_context,
+                    surface.size,
+                    region,
+                )
+            }),
+        }
+    }
+
+    /// Returns how many frames ago the back buffer of a widget surface was last presented, or 0
+    /// if its contents are undefined.
+    ///
+    /// The age is queried with `EGL_EXT_buffer_age` right after each swap, while the surface is
+    /// still current and nothing has been drawn into it, so it's meant to be read after
+    /// `present_surface()` and before drawing the next frame.
+    pub fn surface_buffer_age(&self, surface: &Surface) -> Result<u32, Error> {
+        match surface.objects {
+            SurfaceObjects::Window { .. } => Ok(surface.buffer_age.get()),
+            SurfaceObjects::HardwareBuffer { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Returns the current size of a widget surface's native window, as EGL reports it.
+    pub fn query_surface_native_size(&self, surface: &Surface) -> Result<Size2D<i32>, Error> {
+        match surface.objects {
+            SurfaceObjects::Window { egl_surface, .. } => unsafe {
+                window_surface_size(self.egl_display, egl_surface).ok_or(Error::InvalidNativeWidget)
+            },
+            SurfaceObjects::HardwareBuffer { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Returns the rotation that the window system applies to a widget surface's frames to show
+    /// them on its display.
+    ///
+    /// This is the transform hint of the surface's window, which follows the rotation of the
+    /// display. Versions of Android that don't let applications query the hint or set the
+    /// transform of their buffers, which arrived in API level 26, report
+    /// `SurfaceTransform::Identity`, as do generic surfaces.
+    ///
+    /// For a surface created with `SurfaceOptions::pre_transform`, the next frame presented is
+    /// taken to be rendered with the transform that this returns.
+    pub fn surface_transform(&self, surface: &Surface) -> SurfaceTransform {
+        let transform = match surface.objects {
+            SurfaceObjects::Window { native_window, .. } if !native_window.is_null() => unsafe {
+                window_transform_hint(native_window)
+            },
+            _ => SurfaceTransform::Identity,
+        };
+        if let Some(ref pre_transform) = surface.pre_transform {
+            pre_transform.reported.set(transform);
+        }
+        transform
+    }
+
+    // Fails with `SurfaceSizeMismatch` if a widget surface's window has been resized since the
+    // surface was last sized. Other failures are left for the present itself to report.
+    fn check_native_size(&self, surface: &Surface) -> Result<(), Error> {
+        match self.query_surface_native_size(surface) {
+            Ok(actual) if actual != surface.size => Err(Error::SurfaceSizeMismatch {
+                expected: surface.size,
+                actual,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Registers a function to call with the ID and new size of each widget surface of this
+    /// device that is found to have been resized behind surfman's back.
+    ///
+    /// This backend doesn't watch native windows for resizes, so the handler is never called.
+    #[inline]
+    pub fn set_surface_resized_handler(&mut self, _: Option<SurfaceResizedHandler>) {}
+
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to have moved to a display with a different scale factor.
+    ///
+    /// Android recreates an activity's window when the display density changes, so the handler
+    /// is never called.
+    #[inline]
+    pub fn set_surface_scale_changed_handler(
+        &mut self,
+        _: Option<SurfaceScaleChangedHandler>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to be displayed with a different color profile.
+    ///
+    /// Android doesn't expose display color profiles through the NDK, so the handler is never
+    /// called.
+    #[inline]
+    pub fn set_color_profile_changed_handler(&mut self, _: Option<ColorProfileChangedHandler>) {}
+
+    /// Sets whether the system may discard the contents of a generic surface to free memory.
+    ///
+    /// Android gives no way to offer a hardware buffer back to the system, so volatile surfaces
+    /// stay resident and are never discarded. The state is still enforced: a volatile surface
+    /// must be taken back with `reclaim_surface()` before it's used again.
+    pub fn set_surface_purgeable(
+        &self,
+        surface: &mut Surface,
+        purgeability: Purgeability,
+    ) -> Result<(), Error> {
+        match surface.objects {
+            SurfaceObjects::Window { .. } => Err(Error::WidgetAttached),
+            SurfaceObjects::HardwareBuffer { .. } => {
+                surface.purge_state.set(&mut ResidentBacking, purgeability)
+            }
+        }
+    }
+
+    /// Makes a surface non-purgeable again, and reports whether the system discarded its contents
+    /// since it was made volatile.
+    ///
+    /// On this backend, the contents are always intact.
+    pub fn reclaim_surface(&self, surface: &mut Surface) -> Result<ReclaimResult, Error> {
+        match surface.objects {
+            SurfaceObjects::Window { .. } => Err(Error::WidgetAttached),
+            SurfaceObjects::HardwareBuffer { .. } => {
+                surface.purge_state.reclaim(&mut ResidentBacking)
+            }
+        }
+    }
+
     #[allow(non_snake_case)]
     unsafe fn create_egl_image(
         &self,
         _: &Context,
         hardware_buffer: *mut AHardwareBuffer,
+        colorspace: ColorEncoding,
     ) -> EGLImageKHR {
         // Get the native client buffer.
         let eglGetNativeClientBufferANDROID =
@@ -261,13 +1066,13 @@ impl Device {
             eglGetNativeClientBufferANDROID(hardware_buffer as *const AHardwareBuffer as *const _);
         assert!(!client_buffer.is_null());
 
-        // Create the EGL image.
-        let egl_image_attributes = [
-            EGL_IMAGE_PRESERVED_KHR as EGLint,
-            egl::TRUE as EGLint,
-            egl::NONE as EGLint,
-            0,
-        ];
+        // Create the EGL image. sRGB images decode when sampled and encode when rendered to.
+        let mut egl_image_attributes = vec![EGL_IMAGE_PRESERVED_KHR as EGLint, egl::TRUE as EGLint];
+        if colorspace == ColorEncoding::Srgb {
+            egl_image_attributes.push(egl::GL_COLORSPACE as EGLint);
+            egl_image_attributes.push(egl::GL_COLORSPACE_SRGB as EGLint);
+        }
+        egl_image_attributes.extend_from_slice(&[egl::NONE as EGLint, 0]);
         let egl_image = (EGL_EXTENSION_FUNCTIONS.CreateImageKHR)(
             self.egl_display,
             egl::NO_CONTEXT,
@@ -279,6 +1084,21 @@ impl Device {
         egl_image
     }
 
+    /// Wraps an existing GL texture in a generic surface.
+    ///
+    /// Surfaces on this platform are backed by hardware buffers, so this currently returns
+    /// `Unimplemented`.
+    pub fn create_surface_from_texture(
+        &mut self,
+        _: &mut Context,
+        _: GLuint,
+        _: &Size2D<i32>,
+        _: GLenum,
+        _: TextureOwnership,
+    ) -> Result<Surface, Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Destroys a surface.
     ///
     /// The supplied context must be the context the surface is associated with, or this returns
@@ -295,6 +1115,7 @@ impl Device {
             return Err(Error::IncompatibleSurface);
         }
 
+        let _guard = self.make_context_current_guarded(context);
         unsafe {
             match surface.objects {
                 SurfaceObjects::HardwareBuffer {
@@ -326,13 +1147,19 @@ impl Device {
                 }
                 SurfaceObjects::Window {
                     ref mut egl_surface,
+                    ..
                 } => EGL_FUNCTIONS.with(|egl| {
                     egl.DestroySurface(self.egl_display, *egl_surface);
                     *egl_surface = egl::NO_SURFACE;
                 }),
             }
         }
+        surface.present_fence = None;
+        surface.frame_timestamps = None;
 
+        if !surface.destroyed {
+            leak_check::destroyed(TrackedObject::Surface);
+        }
         surface.destroyed = true;
         Ok(())
     }
@@ -349,7 +1176,7 @@ impl Device {
         context: &mut Context,
         mut surface_texture: SurfaceTexture,
     ) -> Result<Surface, (Error, SurfaceTexture)> {
-        let _guard = self.temporarily_make_context_current(context);
+        let _guard = self.make_context_current_guarded(context);
         GL_FUNCTIONS.with(|gl| {
             unsafe {
                 gl.DeleteTextures(1, &surface_texture.texture_object);
@@ -364,17 +1191,79 @@ impl Device {
                 surface_texture.local_egl_image = EGL_NO_IMAGE_KHR;
             }
 
+            leak_check::destroyed(TrackedObject::SurfaceTexture);
             Ok(surface_texture.surface)
         })
     }
 
     /// Returns a pointer to the underlying surface data for reading or writing by the CPU.
     #[inline]
-    pub fn lock_surface_data<'s>(&self, _: &'s mut Surface) -> Result<SurfaceDataGuard<'s>, Error> {
+    pub fn lock_surface_data<'s>(
+        &self,
+        surface: &'s mut Surface,
+    ) -> Result<SurfaceDataGuard<'s>, Error> {
+        surface.access.check_cpu_access()?;
+        surface.purge_state.check_usable()?;
         // TODO(pcwalton)
         Err(Error::Unimplemented)
     }
 
+    /// Hardware buffers aren't exported as dma-bufs, so this returns `UnsupportedOnThisPlatform`.
+    pub fn export_surface_dma_buf(&self, _: &Surface) -> Result<DmaBuf, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Copies the contents of `source` into `destination` on the GPU, converting between their
+    /// formats and colorspaces and exchanging channels as `swizzle` says.
+    ///
+    /// Both surfaces must be generic surfaces of `context`, the same size, and `context` must be
+    /// current.
+    #[inline]
+    pub fn convert_surface(
+        &self,
+        context: &mut Context,
+        source: &Surface,
+        destination: &mut Surface,
+        swizzle: Swizzle,
+    ) -> Result<(), Error> {
+        convert::convert_surface(self, context, source, destination, swizzle)
+    }
+
+    /// Reads a rectangle of a generic surface, measured from its top left, into `out` as tightly
+    /// packed 8-bit RGBA, top row first.
+    ///
+    /// The surface must belong to `context`, which needn't be current. A widget surface whose
+    /// presentation is paused reads the frame that it holds.
+    pub fn read_surface_data(
+        &self,
+        context: &Context,
+        surface: &Surface,
+        rect: Rect<i32>,
+        out: &mut [u8],
+    ) -> Result<(), Error> {
+        match surface.pause.held_frame() {
+            Some(frame) if context.id == surface.context_id => frame.read_rect(rect, out),
+            Some(_) => Err(Error::IncompatibleSurface),
+            None => readback::read_surface_data(self, context, surface, rect, out),
+        }
+    }
+
+    /// Writes 8-bit RGBA pixels from `data`, top row first, into a rectangle of a generic surface,
+    /// measured from its top left.
+    ///
+    /// The surface must belong to `context`, which needn't be current.
+    #[inline]
+    pub fn write_surface_data(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        rect: Rect<i32>,
+        data: &[u8],
+        stride: usize,
+    ) -> Result<(), Error> {
+        upload::write_surface_data(self, context, surface, rect, data, stride)
+    }
+
     /// Returns the OpenGL texture target needed to read from this surface texture.
     ///
     /// This will be `GL_TEXTURE_2D` or `GL_TEXTURE_RECTANGLE`, depending on platform.
@@ -394,15 +1283,87 @@ impl Device {
             size: surface.size,
             id: surface.id(),
             context_id: surface.context_id,
+            access: surface.access,
+            format: surface.format,
+            purgeability: surface.purge_state.purgeability(),
+            colorspace: surface.colorspace,
+            color_bits: surface.color_bits,
+            preserves_contents: surface.preserves_contents,
             framebuffer_object: match surface.objects {
                 SurfaceObjects::HardwareBuffer {
                     framebuffer_object, ..
                 } => framebuffer_object,
                 SurfaceObjects::Window { .. } => 0,
             },
+            texture_target: match surface.objects {
+                SurfaceObjects::HardwareBuffer { .. } => SURFACE_GL_TEXTURE_TARGET,
+                SurfaceObjects::Window { .. } => gl::NONE,
+            },
+            stride: None,
+            // Hardware buffers are allocated in the format that matches `surface.format`.
+            native_format: NativeFormat::of_gl_internal_format(
+                surface.format,
+                NativeFormatKind::DrmFourcc,
+            ),
         }
     }
 
+    /// Returns the color profile of the output that a widget surface is displayed on.
+    ///
+    /// Android doesn't expose display color profiles through the NDK, so this always returns
+    /// `ColorProfile::AssumedSrgb`.
+    #[inline]
+    pub fn widget_color_profile(&self, _: &Surface) -> ColorProfile {
+        ColorProfile::AssumedSrgb
+    }
+
+    /// Returns true if a widget surface is on an output driven by a different GPU than this
+    /// device.
+    ///
+    /// This platform has a single adapter, so this always returns false.
+    #[inline]
+    pub fn presenting_cross_adapter(&self, _: &Surface) -> bool {
+        false
+    }
+
+    /// Returns the timestamps of the most recent presentations of a widget surface, oldest first.
+    ///
+    /// `compositor_ack` is when `eglSwapBuffers()` returned. `displayed` is reported where the EGL
+    /// display supports `EGL_ANDROID_get_frame_timestamps`, as it does from Android 8.0 on, once
+    /// the frame has been shown.
+    ///
+    /// Returns an empty list for generic surfaces and for widget surfaces that haven't been
+    /// presented yet.
+    pub fn presentation_timestamps(&self, surface: &Surface) -> Vec<PresentRecord> {
+        presentation_time::records_with_display_times(&surface.present_history, |_, statistics| {
+            if let Some(ref frame_timestamps) = surface.frame_timestamps {
+                frame_timestamps.update(statistics);
+            }
+        })
+    }
+
+    /// Returns when the window system presented the most recent frame of a widget surface that
+    /// is known to have reached the display, or the most recent frame if none is yet.
+    ///
+    /// All three times are reported where the EGL display supports
+    /// `EGL_ANDROID_get_frame_timestamps`, as it does from Android 8.0 on.
+    ///
+    /// Returns `Error::NoWidgetAttached` for generic surfaces, and `Error::NotPresented` if
+    /// nothing has been presented on the surface.
+    pub fn query_presentation_time(
+        &self,
+        surface: &Surface,
+    ) -> Result<PresentationStatistics, Error> {
+        if let SurfaceObjects::HardwareBuffer { .. } = surface.objects {
+            return Err(Error::NoWidgetAttached);
+        }
+        presentation_time::latest_statistics(&surface.present_history, |_, statistics| {
+            if let Some(ref frame_timestamps) = surface.frame_timestamps {
+                frame_timestamps.update(statistics);
+            }
+        })
+    }
+
     /// Returns the OpenGL texture object containing the contents of this surface.
     ///
     /// It is only legal to read from, not write to, this texture object.
@@ -410,13 +1371,159 @@ impl Device {
     pub fn surface_texture_object(&self, surface_texture: &SurfaceTexture) -> GLuint {
         surface_texture.texture_object
     }
+
+    /// Returns the sized internal format of the OpenGL texture of this surface texture, such as
+    /// `GL_RGBA8` or `GL_RGBA16F`.
+    ///
+    /// This is the `SurfaceInfo::format` of the wrapped surface, which tells shaders whether they
+    /// sample normalized or floating-point values.
+    #[inline]
+    pub fn surface_texture_format(&self, surface_texture: &SurfaceTexture) -> GLenum {
+        self.surface_info(&surface_texture.surface).format
+    }
 }
 
 impl NativeWidget {
     /// Creates a native widget type from an Android `NativeWindow`.
     #[inline]
     pub unsafe fn from_native_window(native_window: *mut ANativeWindow) -> NativeWidget {
-        NativeWidget { native_window }
+        NativeWidget {
+            native_window,
+            adjusted_size: None,
+        }
+    }
+
+    // Returns the current size of the window, or `None` if the window can't report it.
+    pub(crate) unsafe fn size(&self) -> Option<Size2D<i32>> {
+        let width = ANativeWindow_getWidth(self.native_window);
+        let height = ANativeWindow_getHeight(self.native_window);
+        if width < 0 || height < 0 {
+            return None;
+        }
+        Some(Size2D::new(width, height))
+    }
+}
+
+// GPU-only buffers can be allocated in memory the CPU can't map, so only ask for CPU usage when
+// the surface allows it.
+// Allocates a hardware buffer to back a generic surface with the given sized internal format, which
+// must be one that `create_generic_surface()` chooses.
+unsafe fn allocate_hardware_buffer(
+    size: &Size2D<i32>,
+    format: GLenum,
+    access: SurfaceAccess,
+) -> Result<*mut AHardwareBuffer, Error> {
+    let hardware_buffer_format = match format {
+        GL_RGB565 => AHARDWAREBUFFER_FORMAT_R5G6B5_UNORM,
+        gl::RGBA16F => AHARDWAREBUFFER_FORMAT_R16G16B16A16_FLOAT,
+        gl::RGB10_A2 => AHARDWAREBUFFER_FORMAT_R10G10B10A2_UNORM,
+        _ => AHARDWAREBUFFER_FORMAT_R8G8B8A8_UNORM,
+    };
+    let hardware_buffer_desc = AHardwareBuffer_Desc {
+        format: hardware_buffer_format,
+        height: size.height as u32,
+        width: size.width as u32,
+        layers: 1,
+        rfu0: 0,
+        rfu1: 0,
+        stride: 10,
+        usage: hardware_buffer_cpu_usage(access)
+            | AHARDWAREBUFFER_USAGE_GPU_FRAMEBUFFER
+            | AHARDWAREBUFFER_USAGE_GPU_SAMPLED_IMAGE,
+    };
+    let mut hardware_buffer = ptr::null_mut();
+    let result = AHardwareBuffer_allocate(&hardware_buffer_desc, &mut hardware_buffer);
+    if result != 0 {
+        return Err(Error::SurfaceCreationFailed(WindowingApiError::Failed));
+    }
+    Ok(hardware_buffer)
+}
+
+// Returns the transform hint of a window, or the identity if the platform doesn't let
+// applications both query it and tell the window how its buffers are transformed.
+unsafe fn window_transform_hint(native_window: *mut ANativeWindow) -> SurfaceTransform {
+    let functions = &*NATIVE_WINDOW_FUNCTIONS;
+    let query = match (
+        functions.ANativeWindow_query,
+        functions.ANativeWindow_setBuffersTransform,
+    ) {
+        (Some(query), Some(_)) => query,
+        _ => return SurfaceTransform::Identity,
+    };
+    let mut hint = ANATIVEWINDOW_TRANSFORM_IDENTITY;
+    if query(native_window, NATIVE_WINDOW_TRANSFORM_HINT, &mut hint) != 0 {
+        return SurfaceTransform::Identity;
+    }
+    match hint {
+        ANATIVEWINDOW_TRANSFORM_ROTATE_90 => SurfaceTransform::Rotate90,
+        ANATIVEWINDOW_TRANSFORM_ROTATE_180 => SurfaceTransform::Rotate180,
+        ANATIVEWINDOW_TRANSFORM_ROTATE_270 => SurfaceTransform::Rotate270,
+        _ => SurfaceTransform::Identity,
+    }
+}
+
+// Tells the window which transform the frame about to be presented was rendered with, if it has
+// changed since the last frame.
+//
+// Where the display has `EGL_ANGLE_surface_orientation`, the half turn of the transform is set as
+// the surface's `EGL_SURFACE_ORIENTATION_ANGLE`, inverting both axes, so that ANGLE accounts for
+// it, and the window is only told about the quarter turn that remains.
+unsafe fn apply_pre_transform(
+    egl_display: EGLDisplay,
+    egl_surface: EGLSurface,
+    native_window: *mut ANativeWindow,
+    pre_transform: &mut PreTransform,
+) {
+    let transform = pre_transform.reported.get();
+    if transform == pre_transform.applied || native_window.is_null() {
+        return;
+    }
+
+    let mut window_transform = transform;
+    if device::display_supports_extension(egl_display, "EGL_ANGLE_surface_orientation") {
+        let inverted =
+            EGL_SURFACE_ORIENTATION_INVERT_X_ANGLE | EGL_SURFACE_ORIENTATION_INVERT_Y_ANGLE;
+        let (orientation, remaining) = match transform {
+            SurfaceTransform::Rotate180 => (inverted, SurfaceTransform::Identity),
+            SurfaceTransform::Rotate270 => (inverted, SurfaceTransform::Rotate90),
+            other => (0, other),
+        };
+        let result = EGL_FUNCTIONS.with(|egl| {
+            egl.SurfaceAttrib(
+                egl_display,
+                egl_surface,
+                EGL_SURFACE_ORIENTATION_ANGLE as EGLint,
+                orientation,
+            )
+        });
+        if result != egl::FALSE {
+            window_transform = remaining;
+        }
+    }
+
+    if let Some(set_buffers_transform) = NATIVE_WINDOW_FUNCTIONS.ANativeWindow_setBuffersTransform {
+        let window_transform = match window_transform {
+            SurfaceTransform::Identity => ANATIVEWINDOW_TRANSFORM_IDENTITY,
+            SurfaceTransform::Rotate90 => ANATIVEWINDOW_TRANSFORM_ROTATE_90,
+            SurfaceTransform::Rotate180 => ANATIVEWINDOW_TRANSFORM_ROTATE_180,
+            SurfaceTransform::Rotate270 => ANATIVEWINDOW_TRANSFORM_ROTATE_270,
+        };
+        set_buffers_transform(native_window, window_transform);
+    }
+    pre_transform.applied = transform;
+}
+
+fn hardware_buffer_cpu_usage(access: SurfaceAccess) -> u64 {
+    match access {
+        SurfaceAccess::GPUOnly => {
+            AHARDWAREBUFFER_USAGE_CPU_READ_NEVER | AHARDWAREBUFFER_USAGE_CPU_WRITE_NEVER
+        }
+        SurfaceAccess::GPUCPU => {
+            AHARDWAREBUFFER_USAGE_CPU_READ_OFTEN | AHARDWAREBUFFER_USAGE_CPU_WRITE_OFTEN
+        }
+        SurfaceAccess::GPUCPUWriteCombined => {
+            AHARDWAREBUFFER_USAGE_CPU_READ_RARELY | AHARDWAREBUFFER_USAGE_CPU_WRITE_OFTEN
+        }
     }
 }
 
@@ -424,7 +1531,7 @@ impl Surface {
     pub(super) fn id(&self) -> SurfaceID {
         match self.objects {
             SurfaceObjects::HardwareBuffer { egl_image, .. } => SurfaceID(egl_image as usize),
-            SurfaceObjects::Window { egl_surface } => SurfaceID(egl_surface as usize),
+            SurfaceObjects::Window { egl_surface, .. } => SurfaceID(egl_surface as usize),
         }
     }
 }
@@ -3,10 +3,20 @@
 //! Surface management for Android and OpenHarmony using the `GraphicBuffer` class and EGL.
 
 use crate::context::ContextID;
-use crate::gl::types::GLuint;
+use crate::gl::types::{GLenum, GLuint};
+use crate::invalidate::PostPresentInvalidation;
+use crate::memory::PurgeState;
+use crate::pause::{HeldFrame, PresentPause};
 use crate::platform::generic::egl::ffi::EGLImageKHR;
+use crate::platform::generic::egl::surface::{
+    BufferAge, FrameTimestamps, FrontBuffer, PresentFence,
+};
+use crate::surface::PresentHistory;
+use crate::vsync::AutoVsync;
+use crate::{ColorBits, ColorEncoding, SurfaceAccess, SurfaceTransform};
 
 use euclid::default::Size2D;
+use std::cell::Cell;
 use std::fmt::{self, Debug, Formatter};
 use std::marker::PhantomData;
 use std::thread;
@@ -44,10 +54,40 @@ pub use ohos_surface::*;
 pub struct Surface {
     pub(crate) context_id: ContextID,
     pub(crate) size: Size2D<i32>,
+    pub(crate) access: SurfaceAccess,
+    pub(crate) format: GLenum,
+    pub(crate) colorspace: ColorEncoding,
+    pub(crate) color_bits: ColorBits,
+    pub(crate) present_history: PresentHistory,
+    pub(crate) auto_vsync: Option<AutoVsync>,
+    // A fence inserted when the last frame was presented, for widget surfaces.
+    pub(crate) present_fence: Option<PresentFence>,
+    // The frame timestamps that EGL reports for widget surfaces, if it reports any.
+    pub(crate) frame_timestamps: Option<FrameTimestamps>,
+    pub(crate) invalidation: PostPresentInvalidation,
+    pub(crate) buffer_age: BufferAge,
+    pub(crate) front_buffer: FrontBuffer,
+    pub(crate) pause: PresentPause<HeldFrame>,
+    // Whether the surface's contents survive presents.
+    pub(crate) preserves_contents: bool,
+    // Set for widget surfaces created with `SurfaceOptions::pre_transform`.
+    pub(crate) pre_transform: Option<PreTransform>,
     pub(crate) objects: SurfaceObjects,
+    pub(crate) purge_state: PurgeState,
     pub(crate) destroyed: bool,
 }
 
+// The transforms of a widget surface whose frames are rendered pre-rotated.
+#[cfg_attr(ohos_platform, allow(dead_code))]
+#[derive(Default)]
+pub(crate) struct PreTransform {
+    // The transform that `Device::surface_transform()` last reported, which the next frame is
+    // rendered with.
+    pub(crate) reported: Cell<SurfaceTransform>,
+    // The transform that the window was last told frames are rendered with.
+    pub(crate) applied: SurfaceTransform,
+}
+
 /// Represents an OpenGL texture that wraps a surface.
 ///
 /// Reading from the associated OpenGL texture reads from the surface. It is undefined behavior to
@@ -68,7 +108,17 @@ unsafe impl Send for Surface {}
 
 impl Debug for Surface {
     fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
-        write!(formatter, "Surface({:x})", self.id().0)
+        write!(formatter, "Surface({:x}, {:?})", self.id().0, self.access)
+    }
+}
+
+impl Surface {
+    /// Returns the ID of the context that this surface was created with.
+    ///
+    /// A surface can only be bound to the context that it was created with.
+    #[inline]
+    pub fn context_id(&self) -> ContextID {
+        self.context_id
     }
 }
 
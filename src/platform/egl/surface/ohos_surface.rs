@@ -3,20 +3,55 @@
 //! Surface management for OpenHarmony OS using EGL.
 
 use std::marker::PhantomData;
+use std::os::fd::BorrowedFd;
 use std::os::raw::c_void;
-use std::ptr;
+use std::time::{Duration, Instant};
 
-use euclid::default::Size2D;
+use euclid::default::{Rect, Size2D};
 use log::info;
 
+use crate::bulk;
+use crate::clear::ClearRegion;
+use crate::convert::{self, Swizzle};
 use crate::egl;
-use crate::egl::types::EGLSurface;
+use crate::egl::types::{EGLSurface, EGLint};
 use crate::gl;
 use crate::gl::types::{GLenum, GLuint};
-use crate::platform::generic::egl::device::EGL_FUNCTIONS;
+use crate::invalidate::PostPresentInvalidation;
+use crate::leak_check::{self, TrackedObject};
+use crate::memory::PurgeState;
+use crate::pause::{HeldFrame, PresentPause};
+use crate::platform::generic::egl::context::{config_color_bits, widget_config};
+use crate::platform::generic::egl::device::{self, EGL_FUNCTIONS};
 use crate::platform::generic::egl::ffi::EGL_EXTENSION_FUNCTIONS;
 use crate::platform::generic::egl::ffi::EGL_NO_IMAGE_KHR;
-use crate::{Error, SurfaceAccess, SurfaceID, SurfaceInfo, SurfaceType};
+use crate::platform::generic::egl::surface::{
+    clear_window_surface, flush_window_surface, preserve_color_buffer, preserves_color_buffer,
+    set_swap_interval, supports_adaptive_swap, swap_window_surface, window_surface_size,
+    with_window_surface_current, BufferAge, EGLFence, FrameTimestamps, FrontBuffer, PresentFence,
+    PresentationFeedback,
+};
+use crate::presentation_time;
+use crate::readback;
+use crate::surface::{check_surface_size, PresentHistory};
+use crate::upload;
+use crate::vsync::AutoVsync;
+use crate::BufferRelease;
+use crate::DmaBuf;
+use crate::InvalidateSet;
+use crate::NativeFormat;
+use crate::PausedPresentation;
+use crate::PresentationStatistics;
+use crate::SurfaceFence;
+use crate::TextureOwnership;
+use crate::{AutoVsyncPolicy, AutoVsyncStats};
+use crate::{ColorEncoding, SurfaceOptions};
+use crate::{
+    ColorProfile, ColorProfileChangedHandler, Error, PresentRecord, Purgeability, ReclaimResult,
+    SurfaceAccess, SurfaceID, SurfaceInfo, SurfaceResizedHandler, SurfaceScaleChangedHandler,
+    SurfaceType,
+};
+use crate::{SurfaceConfig, SurfaceIntent, SurfaceTransform};
 
 use super::super::context::{Context, GL_FUNCTIONS};
 use super::super::device::Device;
@@ -32,10 +67,17 @@ pub(crate) enum SurfaceObjects {
 }
 
 /// An OHOS native window.
+///
+/// This is neither `Send` nor `Sync`: create surfaces from it on the thread that got the window
+/// from its `XComponent`.
 pub struct NativeWidget {
     pub(crate) native_window: *mut OHNativeWindow,
+    pub(crate) adjusted_size: Option<Size2D<i32>>,
 }
 
+assert_not_send!(NativeWidget);
+assert_not_sync!(NativeWidget);
+
 impl Device {
     /// Creates either a generic or a widget surface, depending on the supplied surface type.
     ///
@@ -44,22 +86,57 @@ impl Device {
     pub fn create_surface(
         &mut self,
         context: &Context,
-        _: SurfaceAccess,
+        access: SurfaceAccess,
+        surface_type: SurfaceType<NativeWidget>,
+    ) -> Result<Surface, Error> {
+        self.create_surface_with_options(context, access, surface_type, SurfaceOptions::default())
+    }
+
+    /// Creates a surface of each of the given types, in order.
+    ///
+    /// The device's cancellation token is checked before each surface, as well as inside each
+    /// creation. If any surface fails or is cancelled, the ones created so far are destroyed and
+    /// the error is returned, so the batch creates either all of its surfaces or none.
+    pub fn create_surfaces<I>(
+        &mut self,
+        context: &mut Context,
+        access: SurfaceAccess,
+        surface_types: I,
+    ) -> Result<Vec<Surface>, Error>
+    where
+        I: IntoIterator<Item = SurfaceType<NativeWidget>>,
+    {
+        bulk::create_surfaces(self, context, access, surface_types)
+    }
+
+    /// Creates either a generic or a widget surface, with options such as its colorspace.
+    ///
+    /// `create_surface()` is this method with the default options. sRGB widget surfaces need
+    /// `EGL_KHR_gl_colorspace`; without it, this returns `Error::UnsupportedSurfaceFormat`.
+    pub fn create_surface_with_options(
+        &mut self,
+        context: &Context,
+        access: SurfaceAccess,
         surface_type: SurfaceType<NativeWidget>,
+        options: SurfaceOptions,
     ) -> Result<Surface, Error> {
         info!("Device create_surface with Context");
-        match surface_type {
-            SurfaceType::Generic { size } => self.create_generic_surface(context, &size),
+        self.cancel_token.check()?;
+        let mut surface = match surface_type {
+            SurfaceType::Generic { size } => self.create_generic_surface(context, &size, access),
             SurfaceType::Widget { native_widget } => unsafe {
-                self.create_window_surface(context, native_widget)
+                self.create_window_surface(context, native_widget, access, options)
             },
-        }
+        }?;
+        self.debug_fill_new_surface(context, &mut surface);
+        Ok(surface)
     }
 
     fn create_generic_surface(
         &mut self,
         _context: &Context,
         _size: &Size2D<i32>,
+        _access: SurfaceAccess,
     ) -> Result<Surface, Error> {
         Err(Error::Unimplemented)
     }
@@ -68,7 +145,11 @@ impl Device {
         &mut self,
         context: &Context,
         native_widget: NativeWidget,
+        access: SurfaceAccess,
+        options: SurfaceOptions,
     ) -> Result<Surface, Error> {
+        let context_descriptor = self.context_descriptor(context);
+        options.check_widget_format(&self.context_descriptor_attributes(&context_descriptor))?;
         let mut height: i32 = 0;
         let mut width: i32 = 0;
         // Safety: `OH_NativeWindow_NativeWindowHandleOpt` takes two output i32 pointers as
@@ -82,24 +163,89 @@ impl Device {
             )
         };
         assert_eq!(result, 0, "Failed to determine size of native window");
+
+        let mut window_surface_attributes = vec![];
+        if options.colorspace == ColorEncoding::Srgb {
+            if !device::display_supports_extension(self.egl_display, "EGL_KHR_gl_colorspace") {
+                return Err(Error::UnsupportedSurfaceFormat);
+            }
+            window_surface_attributes.push(egl::GL_COLORSPACE as EGLint);
+            window_surface_attributes.push(egl::GL_COLORSPACE_SRGB as EGLint);
+        }
+        window_surface_attributes.push(egl::NONE as EGLint);
+
+        let egl_config = widget_config(
+            self.egl_display,
+            self.context_to_egl_config(context),
+            &options,
+            None,
+            &|_| None,
+        )?;
         EGL_FUNCTIONS.with(|egl| {
             let egl_surface = egl.CreateWindowSurface(
                 self.egl_display,
-                self.context_to_egl_config(context),
+                egl_config,
                 native_widget.native_window as *const c_void,
-                ptr::null(),
+                window_surface_attributes.as_ptr(),
             );
+            // Drivers refuse sRGB window surfaces for configs that they can't encode.
+            if egl_surface == egl::NO_SURFACE && options.colorspace == ColorEncoding::Srgb {
+                return Err(Error::UnsupportedSurfaceFormat);
+            }
             assert_ne!(egl_surface, egl::NO_SURFACE);
+            let requested = preserve_color_buffer(self.egl_display, egl_surface, &options)
+                .and_then(|preserved| {
+                    let front_buffer =
+                        FrontBuffer::request(self.egl_display, egl_surface, &options)?;
+                    Ok((front_buffer.requested() || preserved, front_buffer))
+                });
+            let (preserves_contents, front_buffer) = match requested {
+                Ok(requested) => requested,
+                Err(err) => {
+                    egl.DestroySurface(self.egl_display, egl_surface);
+                    return Err(err);
+                }
+            };
 
+            leak_check::created(TrackedObject::Surface);
             Ok(Surface {
                 context_id: context.id,
                 size: Size2D::new(width, height),
+                access,
+                format: gl::NONE,
+                colorspace: options.colorspace,
+                color_bits: config_color_bits(self.egl_display, egl_config),
+                present_history: PresentHistory::default(),
+                auto_vsync: None,
+                present_fence: None,
+                frame_timestamps: FrameTimestamps::new(self.egl_display, egl_surface),
+                invalidation: PostPresentInvalidation::default(),
+                buffer_age: BufferAge::new(self.egl_display),
+                front_buffer,
+                pause: PresentPause::default(),
+                preserves_contents,
+                pre_transform: None,
                 objects: SurfaceObjects::Window { egl_surface },
+                purge_state: PurgeState::default(),
                 destroyed: false,
             })
         })
     }
 
+    /// Returns the access mode, type, and options that suit a surface of the given size, created
+    /// with the given context for the given use, on this device, along with hints for using it.
+    ///
+    /// This backend can't map surfaces for the CPU, so the access mode is always `GPUOnly`.
+    pub fn recommended_surface_config(
+        &self,
+        context: &Context,
+        intent: SurfaceIntent<NativeWidget>,
+        size: Size2D<i32>,
+    ) -> Result<SurfaceConfig<NativeWidget>, Error> {
+        let capabilities = self.capabilities(context)?;
+        Ok(SurfaceConfig::recommend(intent, size, false, &capabilities))
+    }
+
     /// Creates a surface texture from an existing generic surface for use with the given context.
     ///
     /// The surface texture is local to the supplied context and takes ownership of the surface.
@@ -126,33 +272,516 @@ impl Device {
     /// associated widgets until this method is called.
     ///
     /// The supplied context must match the context the surface was created with, or an
-    /// `IncompatibleSurface` error is returned.
+    /// `IncompatibleSurface` error is returned. The context doesn't need to be current, and the
+    /// context that was current before this call remains current afterward.
+    ///
+    /// A window that has been resized since the surface was last sized fails with
+    /// `SurfaceSizeMismatch` without presenting.
     pub fn present_surface(&self, context: &Context, surface: &mut Surface) -> Result<(), Error> {
+        self.present(context, surface, None)
+    }
+
+    /// Displays the changed parts of a widget surface on screen.
+    ///
+    /// `damage` lists the rectangles that changed since the last present, measured from the
+    /// bottom left as in OpenGL, and is passed on with `EGL_KHR_swap_buffers_with_damage` where
+    /// the display supports it. Otherwise, the whole surface is swapped, as `present_surface()`
+    /// does. An empty list means that nothing changed, and skips the swap entirely.
+    pub fn present_surface_with_damage(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        self.present(context, surface, Some(damage))
+    }
+
+    fn present(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: Option<&[Rect<i32>]>,
+    ) -> Result<(), Error> {
         if context.id != surface.context_id {
             return Err(Error::IncompatibleSurface);
         }
+        if !matches!(damage, Some([])) {
+            self.check_native_size(surface)?;
+        }
 
-        EGL_FUNCTIONS.with(|egl| unsafe {
-            match surface.objects {
-                SurfaceObjects::Window { egl_surface } => {
-                    egl.SwapBuffers(self.egl_display, egl_surface);
-                    Ok(())
+        let paused = surface.pause.is_paused();
+        match surface.objects {
+            SurfaceObjects::Window { .. } if paused && matches!(damage, Some([])) => {
+                Err(Error::PresentationPaused)
+            }
+            SurfaceObjects::Window { egl_surface } if paused => unsafe {
+                let size = surface.size;
+                let frame = self.lost_contexts.check(context.id, || {
+                    let egl_context = context.egl_context;
+                    with_window_surface_current(self.egl_display, egl_surface, egl_context, || {
+                        GL_FUNCTIONS.with(|gl| HeldFrame::read(gl, size))
+                    })
+                })?;
+                surface.pause.hold(frame);
+                Err(Error::PresentationPaused)
+            },
+            SurfaceObjects::Window { .. } if matches!(damage, Some([])) => Ok(()),
+            // Frames rendered to the front buffer are already on screen once they're flushed.
+            SurfaceObjects::Window { egl_surface } if surface.front_buffer.bound() => unsafe {
+                let submit = Instant::now();
+                self.lost_contexts.check(context.id, || {
+                    GL_FUNCTIONS.with(|gl| {
+                        flush_window_surface(gl, self.egl_display, egl_surface, context.egl_context)
+                    })
+                })?;
+                surface.buffer_age.front_buffer();
+                surface
+                    .present_history
+                    .record(submit, Some(Instant::now()), None);
+                Ok(())
+            },
+            SurfaceObjects::Window { egl_surface } => unsafe {
+                let submit = Instant::now();
+                let frame_id = surface.present_history.next_frame_id();
+                let frame_timestamps = &mut surface.frame_timestamps;
+                let (invalidation, buffer_age) =
+                    (&mut surface.invalidation, &mut surface.buffer_age);
+                let front_buffer = &mut surface.front_buffer;
+                let present_fence = self.lost_contexts.check(context.id, || {
+                    swap_window_surface(
+                        self.egl_display,
+                        egl_surface,
+                        context.egl_context,
+                        damage,
+                        |egl_display| {
+                            if let Some(ref mut frame_timestamps) = *frame_timestamps {
+                                frame_timestamps.presenting(frame_id)?;
+                            }
+                            Ok(PresentFence::insert(egl_display))
+                        },
+                        || {
+                            GL_FUNCTIONS.with(|gl| invalidation.invalidate(gl, 0));
+                            buffer_age.record(self.egl_display, egl_surface, invalidation.set());
+                            front_buffer.swapped(self.egl_display, context.egl_context, buffer_age);
+                        },
+                    )
+                })?;
+                surface.present_fence = Some(present_fence);
+                surface
+                    .present_history
+                    .record(submit, Some(Instant::now()), None);
+                if let Some(ref mut auto_vsync) = surface.auto_vsync {
+                    auto_vsync.update(&surface.present_history, |interval| {
+                        set_swap_interval(
+                            self.egl_display,
+                            egl_surface,
+                            context.egl_context,
+                            interval,
+                        )
+                    });
                 }
+                Ok(())
+            },
+        }
+    }
+
+    /// Pauses the presentation of a widget surface: until it's resumed, each frame presented is
+    /// read back and held instead of shown, and `present_surface()` returns `PresentationPaused`.
+    ///
+    /// Surfaces that render to the front buffer return `UnsupportedPresentMode`, and contexts
+    /// without `glBlitFramebuffer()` return `RequiredExtensionUnavailable`.
+    pub fn pause_presentation(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<PausedPresentation, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        // Frames rendered to the front buffer are on screen as soon as they're drawn.
+        if surface.front_buffer.requested() {
+            return Err(Error::UnsupportedPresentMode);
+        }
+        if !self.capabilities(context)?.blit_framebuffer {
+            return Err(Error::RequiredExtensionUnavailable);
+        }
+        surface.pause.pause()
+    }
+
+    /// Ends a pause in the presentation of a widget surface.
+    ///
+    /// If `present_pending` is true, the frame held during the pause is drawn back into the
+    /// surface and presented as by `present_surface()`; otherwise, it's discarded.
+    pub fn resume_presentation(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        paused: PausedPresentation,
+        present_pending: bool,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        let SurfaceObjects::Window { egl_surface } = surface.objects;
+        match surface.pause.resume(paused)? {
+            Some(frame) if present_pending => {
+                self.lost_contexts.check(context.id, || unsafe {
+                    let egl_context = context.egl_context;
+                    with_window_surface_current(self.egl_display, egl_surface, egl_context, || {
+                        GL_FUNCTIONS.with(|gl| frame.draw(gl))
+                    })
+                })?;
+                self.present_surface(context, surface)
             }
-        })
+            _ => Ok(()),
+        }
+    }
+
+    /// Displays the contents of a widget surface on screen, and returns a fence that signals once
+    /// the GPU has finished rendering it.
+    ///
+    /// The fence is an `EGL_SYNC_FENCE`, or a native fence where the display supports
+    /// `EGL_ANDROID_native_fence_sync`, inserted with the surface's context current right after
+    /// its buffers are swapped.
+    pub fn present_surface_with_fence(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<SurfaceFence, Error> {
+        self.present_surface(context, surface)?;
+        let _guard = self.make_context_current_guarded(context)?;
+        let egl_fence =
+            GL_FUNCTIONS.with(|gl| unsafe { EGLFence::insert(gl, self.egl_display) })?;
+        Ok(SurfaceFence { egl_fence })
+    }
+
+    /// Makes the context wait on the GPU until the fence signals, with `eglWaitSync()`.
+    pub fn wait_fence(&self, context: &Context, fence: &SurfaceFence) -> Result<(), Error> {
+        let _guard = self.make_context_current_guarded(context)?;
+        unsafe { fence.egl_fence.wait(self.egl_display) }
+    }
+
+    /// Returns a native fence that signals when the sync file `fd` does, which needs
+    /// `EGL_ANDROID_native_fence_sync`. The descriptor is duplicated, so it stays the caller's.
+    pub fn import_sync_fd(&self, context: &Context, fd: BorrowedFd) -> Result<SurfaceFence, Error> {
+        let _guard = self.make_context_current_guarded(context)?;
+        let egl_fence = unsafe { EGLFence::import(self.egl_display, fd)? };
+        Ok(SurfaceFence { egl_fence })
     }
 
     /// Resizes a widget surface.
+    ///
+    /// This platform only supports widget surfaces, whose EGL surfaces follow the size of their
+    /// window, so this only records the size.
     pub fn resize_surface(
         &self,
-        _context: &Context,
+        context: &Context,
         surface: &mut Surface,
         size: Size2D<i32>,
     ) -> Result<(), Error> {
+        check_surface_size(size, &self.capabilities(context)?)?;
+        // The EGL surface gets new buffers of the new size.
+        surface.buffer_age.reset();
+        surface.pause.force_resume();
         surface.size = size;
         Ok(())
     }
 
+    /// Sets the number of vertical blanks that `present_surface()` waits for before showing each
+    /// frame on a widget surface, and returns the interval that took effect.
+    ///
+    /// Negative intervals are only honored if the surface's EGL config allows them, and are
+    /// otherwise replaced with their absolute value. Intervals outside the range that the config
+    /// supports return `UnsupportedOnThisPlatform`.
+    pub fn set_swap_interval(
+        &mut self,
+        context: &Context,
+        surface: &Surface,
+        interval: i32,
+    ) -> Result<i32, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        match surface.objects {
+            SurfaceObjects::Window { egl_surface } => unsafe {
+                set_swap_interval(self.egl_display, egl_surface, context.egl_context, interval)
+            },
+        }
+    }
+
+    /// Returns true if the EGL config of a widget surface allows the negative swap intervals of
+    /// adaptive vsync.
+    pub fn supports_adaptive_swap(&self, surface: &Surface) -> bool {
+        match surface.objects {
+            SurfaceObjects::Window { egl_surface } => unsafe {
+                supports_adaptive_swap(self.egl_display, egl_surface)
+            },
+        }
+    }
+
+    /// Lets surfman switch a widget surface between vsync and immediate presentation by itself,
+    /// following `policy`, or stops it with `None`.
+    ///
+    /// The frame time is measured from the return of `eglSwapBuffers()`.
+    pub fn set_auto_swap_interval(
+        &mut self,
+        context: &Context,
+        surface: &mut Surface,
+        policy: Option<AutoVsyncPolicy>,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        match surface.objects {
+            SurfaceObjects::Window { egl_surface } => {
+                if policy.is_some() {
+                    unsafe {
+                        set_swap_interval(self.egl_display, egl_surface, context.egl_context, 1)?;
+                    }
+                }
+                surface.auto_vsync = policy.map(AutoVsync::new);
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns what automatic vsync has decided for a surface, or `None` if it isn't enabled.
+    #[inline]
+    pub fn auto_swap_interval_stats(&self, surface: &Surface) -> Option<AutoVsyncStats> {
+        surface.auto_vsync.as_ref().map(AutoVsync::stats)
+    }
+
+    /// Waits until the buffer of the last frame presented on a widget surface has been released,
+    /// or until `timeout` elapses, and reports whether the call had to block.
+    ///
+    /// OpenHarmony gives no signal for the release of EGL's buffers, so this waits on a fence inserted
+    /// when the frame was presented, which signals once the GPU has finished rendering it.
+    pub fn wait_previous_buffer_released(
+        &self,
+        surface: &Surface,
+        timeout: Duration,
+    ) -> Result<BufferRelease, Error> {
+        match surface.objects {
+            SurfaceObjects::Window { .. } => match surface.present_fence {
+                None => Ok(BufferRelease::Released),
+                Some(ref present_fence) => present_fence.wait(timeout),
+            },
+        }
+    }
+
+    /// Chooses which attachments of a surface are invalidated right after each present.
+    ///
+    /// The color buffer can only be invalidated if the EGL surface doesn't preserve it across
+    /// swaps, and otherwise returns `PreservedColorBuffer`.
+    pub fn set_post_present_invalidation(
+        &self,
+        surface: &mut Surface,
+        set: InvalidateSet,
+    ) -> Result<(), Error> {
+        // The front buffer is on screen.
+        let color_preserved = match surface.objects {
+            SurfaceObjects::Window { egl_surface } => unsafe {
+                surface.front_buffer.requested()
+                    || preserves_color_buffer(self.egl_display, egl_surface)
+            },
+        };
+        surface.invalidation.change(set, color_preserved)
+    }
+
+    /// Returns which attachments of a surface are invalidated right after each present.
+    #[inline]
+    pub fn post_present_invalidation(&self, surface: &Surface) -> InvalidateSet {
+        surface.invalidation.set()
+    }
+
+    /// Invalidates the attachments of a generic surface that were chosen with
+    /// `set_post_present_invalidation()`.
+    ///
+    /// This backend only has widget surfaces, which are invalidated by `present_surface()`, so
+    /// this always returns `WidgetAttached`.
+    pub fn invalidate_surface(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        Err(Error::WidgetAttached)
+    }
+
+    /// Clears a region of a surface, measured from its top left, to `color`, and its depth and
+    /// stencil buffers to `depth` and `stencil` if they're given and the surface has them.
+    ///
+    /// The surface is made current with the context for the call, and the state that the clear
+    /// changes is put back afterward.
+    pub fn clear_surface_region(
+        &self,
+        context: &mut Context,
+        surface: &mut Surface,
+        rect: Option<Rect<i32>>,
+        color: [f32; 4],
+        depth: Option<f32>,
+        stencil: Option<i32>,
+    ) -> Result<(), Error> {
+        let region = ClearRegion::new(rect, color, depth, stencil);
+        self.clear_surface_with(context, surface, &region)
+    }
+
+    /// Fills a surface with this device's debug fill, as if it had just been created, or does
+    /// nothing if the fill is `DebugFill::None`.
+    pub fn debug_fill_surface(
+        &self,
+        context: &mut Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        match self.debug_fill.region() {
+            Some(region) => self.clear_surface_with(context, surface, &region),
+            None => Ok(()),
+        }
+    }
+
+    // Fills a new surface with the debug fill, if there is one. The surface is usable either way,
+    // so failing to fill it is only logged.
+    fn debug_fill_new_surface(&self, context: &Context, surface: &mut Surface) {
+        if let Some(region) = self.debug_fill.region() {
+            if let Err(err) = self.clear_surface_with(context, surface, &region) {
+                warn!(
+                    "Failed to fill a new surface with the debug fill: {:?}",
+                    err
+                );
+            }
+        }
+    }
+
+    fn clear_surface_with(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        region: &ClearRegion,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        let SurfaceObjects::Window { egl_surface } = surface.objects;
+        GL_FUNCTIONS.with(|gl| unsafe {
+            clear_window_surface(
+                gl,
+                self.egl_display,
+                egl_surface,
+                context.egl_context,
+                surface.size,
+                region,
+            )
+        })
+    }
+
+    /// Returns how many frames ago the back buffer of a widget surface was last presented, or 0
+    /// if its contents are undefined.
+    ///
+    /// The age is queried with `EGL_EXT_buffer_age` right after each swap, while the surface is
+    /// still current and nothing has been drawn into it, so it's meant to be read after
+    /// `present_surface()` and before drawing the next frame.
+    #[inline]
+    pub fn surface_buffer_age(&self, surface: &Surface) -> Result<u32, Error> {
+        Ok(surface.buffer_age.get())
+    }
+
+    /// Returns the current size of a widget surface's native window, as EGL reports it.
+    pub fn query_surface_native_size(&self, surface: &Surface) -> Result<Size2D<i32>, Error> {
+        let SurfaceObjects::Window { egl_surface } = surface.objects;
+        unsafe { window_surface_size(self.egl_display, egl_surface) }
+            .ok_or(Error::InvalidNativeWidget)
+    }
+
+    /// Returns the rotation that the window system applies to a widget surface's frames to show
+    /// them on its display.
+    ///
+    /// This is always `SurfaceTransform::Identity`.
+    #[inline]
+    pub fn surface_transform(&self, _surface: &Surface) -> SurfaceTransform {
+        SurfaceTransform::Identity
+    }
+
+    // Fails with `SurfaceSizeMismatch` if a widget surface's window has been resized since the
+    // surface was last sized. Other failures are left for the present itself to report.
+    fn check_native_size(&self, surface: &Surface) -> Result<(), Error> {
+        match self.query_surface_native_size(surface) {
+            Ok(actual) if actual != surface.size => Err(Error::SurfaceSizeMismatch {
+                expected: surface.size,
+                actual,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Registers a function to call with the ID and new size of each widget surface of this
+    /// device that is found to have been resized behind surfman's back.
+    ///
+    /// This backend doesn't watch native windows for resizes, so the handler is never called.
+    #[inline]
+    pub fn set_surface_resized_handler(&mut self, _: Option<SurfaceResizedHandler>) {}
+
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to have moved to a display with a different scale factor.
+    ///
+    /// This backend doesn't watch native windows for density changes, so registering a handler
+    /// returns `UnsupportedOnThisPlatform`. Unregistering one succeeds.
+    #[inline]
+    pub fn set_surface_scale_changed_handler(
+        &mut self,
+        handler: Option<SurfaceScaleChangedHandler>,
+    ) -> Result<(), Error> {
+        match handler {
+            Some(_) => Err(Error::UnsupportedOnThisPlatform),
+            None => Ok(()),
+        }
+    }
+
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to be displayed with a different color profile.
+    ///
+    /// OpenHarmony doesn't expose display color profiles to native code, so the handler is never
+    /// called.
+    #[inline]
+    pub fn set_color_profile_changed_handler(&mut self, _: Option<ColorProfileChangedHandler>) {}
+
+    /// Sets whether the system may discard the contents of a generic surface to free memory.
+    ///
+    /// This platform only supports widget surfaces, so this always returns `WidgetAttached`.
+    pub fn set_surface_purgeable(
+        &self,
+        _surface: &mut Surface,
+        _purgeability: Purgeability,
+    ) -> Result<(), Error> {
+        Err(Error::WidgetAttached)
+    }
+
+    /// Makes a surface non-purgeable again, and reports whether the system discarded its contents
+    /// since it was made volatile.
+    ///
+    /// This platform only supports widget surfaces, so this always returns `WidgetAttached`.
+    pub fn reclaim_surface(&self, _surface: &mut Surface) -> Result<ReclaimResult, Error> {
+        Err(Error::WidgetAttached)
+    }
+
+    /// Wraps an existing GL texture in a generic surface.
+    ///
+    /// Surfaces on this platform are backed by native buffers, so this currently returns
+    /// `Unimplemented`.
+    pub fn create_surface_from_texture(
+        &mut self,
+        _: &mut Context,
+        _: GLuint,
+        _: &Size2D<i32>,
+        _: GLenum,
+        _: TextureOwnership,
+    ) -> Result<Surface, Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Destroys a surface.
     ///
     /// The supplied context must be the context the surface is associated with, or this returns
@@ -179,7 +808,12 @@ impl Device {
                 }),
             }
         }
+        surface.present_fence = None;
+        surface.frame_timestamps = None;
 
+        if !surface.destroyed {
+            leak_check::destroyed(TrackedObject::Surface);
+        }
         surface.destroyed = true;
         Ok(())
     }
@@ -196,7 +830,7 @@ impl Device {
         context: &mut Context,
         mut surface_texture: SurfaceTexture,
     ) -> Result<Surface, (Error, SurfaceTexture)> {
-        let _guard = self.temporarily_make_context_current(context);
+        let _guard = self.make_context_current_guarded(context);
         GL_FUNCTIONS.with(|gl| {
             unsafe {
                 gl.DeleteTextures(1, &surface_texture.texture_object);
@@ -217,11 +851,73 @@ impl Device {
 
     /// Returns a pointer to the underlying surface data for reading or writing by the CPU.
     #[inline]
-    pub fn lock_surface_data<'s>(&self, _: &'s mut Surface) -> Result<SurfaceDataGuard<'s>, Error> {
+    pub fn lock_surface_data<'s>(
+        &self,
+        surface: &'s mut Surface,
+    ) -> Result<SurfaceDataGuard<'s>, Error> {
+        surface.access.check_cpu_access()?;
+        surface.purge_state.check_usable()?;
         error!("lock_surface_data not implemented yet for OHOS");
         Err(Error::Unimplemented)
     }
 
+    /// Surfaces aren't exported as dma-bufs on OpenHarmony, so this returns
+    /// `UnsupportedOnThisPlatform`.
+    pub fn export_surface_dma_buf(&self, _: &Surface) -> Result<DmaBuf, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Copies the contents of `source` into `destination` on the GPU, converting between their
+    /// formats and colorspaces and exchanging channels as `swizzle` says.
+    ///
+    /// Both surfaces must be generic surfaces of `context`, the same size, and `context` must be
+    /// current.
+    #[inline]
+    pub fn convert_surface(
+        &self,
+        context: &mut Context,
+        source: &Surface,
+        destination: &mut Surface,
+        swizzle: Swizzle,
+    ) -> Result<(), Error> {
+        convert::convert_surface(self, context, source, destination, swizzle)
+    }
+
+    /// Reads a rectangle of a generic surface, measured from its top left, into `out` as tightly
+    /// packed 8-bit RGBA, top row first.
+    ///
+    /// The surface must belong to `context`, which needn't be current. A widget surface whose
+    /// presentation is paused reads the frame that it holds.
+    pub fn read_surface_data(
+        &self,
+        context: &Context,
+        surface: &Surface,
+        rect: Rect<i32>,
+        out: &mut [u8],
+    ) -> Result<(), Error> {
+        match surface.pause.held_frame() {
+            Some(frame) if context.id == surface.context_id => frame.read_rect(rect, out),
+            Some(_) => Err(Error::IncompatibleSurface),
+            None => readback::read_surface_data(self, context, surface, rect, out),
+        }
+    }
+
+    /// Writes 8-bit RGBA pixels from `data`, top row first, into a rectangle of a generic surface,
+    /// measured from its top left.
+    ///
+    /// The surface must belong to `context`, which needn't be current.
+    #[inline]
+    pub fn write_surface_data(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        rect: Rect<i32>,
+        data: &[u8],
+        stride: usize,
+    ) -> Result<(), Error> {
+        upload::write_surface_data(self, context, surface, rect, data, stride)
+    }
+
     /// Returns the OpenGL texture target needed to read from this surface texture.
     ///
     /// This will be `GL_TEXTURE_2D` or `GL_TEXTURE_RECTANGLE`, depending on platform.
@@ -241,12 +937,75 @@ impl Device {
             size: surface.size,
             id: surface.id(),
             context_id: surface.context_id,
+            access: surface.access,
+            format: surface.format,
+            purgeability: surface.purge_state.purgeability(),
+            colorspace: surface.colorspace,
+            color_bits: surface.color_bits,
+            preserves_contents: surface.preserves_contents,
             framebuffer_object: match surface.objects {
                 SurfaceObjects::Window { .. } => 0,
             },
+            texture_target: match surface.objects {
+                SurfaceObjects::Window { .. } => gl::NONE,
+            },
+            stride: None,
+            native_format: NativeFormat::Unknown,
         }
     }
 
+    /// Returns the color profile of the output that a widget surface is displayed on.
+    ///
+    /// OpenHarmony doesn't expose display color profiles to native code, so this always returns
+    /// `ColorProfile::AssumedSrgb`.
+    #[inline]
+    pub fn widget_color_profile(&self, _: &Surface) -> ColorProfile {
+        ColorProfile::AssumedSrgb
+    }
+
+    /// Returns true if a widget surface is on an output driven by a different GPU than this
+    /// device.
+    ///
+    /// This platform has a single adapter, so this always returns false.
+    #[inline]
+    pub fn presenting_cross_adapter(&self, _: &Surface) -> bool {
+        false
+    }
+
+    /// Returns the timestamps of the most recent presentations of a widget surface, oldest first.
+    ///
+    /// `compositor_ack` is when `eglSwapBuffers()` returned. `displayed` is reported where the EGL
+    /// display supports `EGL_ANDROID_get_frame_timestamps`, once the frame has been shown.
+    ///
+    /// Returns an empty list for generic surfaces and for widget surfaces that haven't been
+    /// presented yet.
+    pub fn presentation_timestamps(&self, surface: &Surface) -> Vec<PresentRecord> {
+        presentation_time::records_with_display_times(&surface.present_history, |_, statistics| {
+            if let Some(ref frame_timestamps) = surface.frame_timestamps {
+                frame_timestamps.update(statistics);
+            }
+        })
+    }
+
+    /// Returns when the window system presented the most recent frame of a widget surface that
+    /// is known to have reached the display, or the most recent frame if none is yet.
+    ///
+    /// The times are reported where the EGL display supports `EGL_ANDROID_get_frame_timestamps`.
+    /// Otherwise, only `requested_present` is.
+    ///
+    /// Returns `Error::NoWidgetAttached` for generic surfaces, and `Error::NotPresented` if
+    /// nothing has been presented on the surface.
+    pub fn query_presentation_time(
+        &self,
+        surface: &Surface,
+    ) -> Result<PresentationStatistics, Error> {
+        presentation_time::latest_statistics(&surface.present_history, |_, statistics| {
+            if let Some(ref frame_timestamps) = surface.frame_timestamps {
+                frame_timestamps.update(statistics);
+            }
+        })
+    }
+
     /// Returns the OpenGL texture object containing the contents of this surface.
     ///
     /// It is only legal to read from, not write to, this texture object.
@@ -254,13 +1013,43 @@ impl Device {
     pub fn surface_texture_object(&self, surface_texture: &SurfaceTexture) -> GLuint {
         surface_texture.texture_object
     }
+
+    /// Returns the sized internal format of the OpenGL texture of this surface texture, such as
+    /// `GL_RGBA8` or `GL_RGBA16F`.
+    ///
+    /// This is the `SurfaceInfo::format` of the wrapped surface, which tells shaders whether they
+    /// sample normalized or floating-point values.
+    #[inline]
+    pub fn surface_texture_format(&self, surface_texture: &SurfaceTexture) -> GLenum {
+        self.surface_info(&surface_texture.surface).format
+    }
 }
 
 impl NativeWidget {
     /// Creates a native widget type from an `OHNativeWindow`.
     #[inline]
     pub unsafe fn from_native_window(native_window: *mut OHNativeWindow) -> NativeWidget {
-        NativeWidget { native_window }
+        NativeWidget {
+            native_window,
+            adjusted_size: None,
+        }
+    }
+
+    // Returns the current size of the window, or `None` if the window can't report it.
+    pub(crate) unsafe fn size(&self) -> Option<Size2D<i32>> {
+        let (mut height, mut width): (i32, i32) = (0, 0);
+        // Safety: `OH_NativeWindow_NativeWindowHandleOpt` takes two output i32 pointers as
+        // variable arguments when called with `GET_BUFFER_GEOMETRY`.
+        let result = OH_NativeWindow_NativeWindowHandleOpt(
+            self.native_window,
+            NativeWindowOperation::GET_BUFFER_GEOMETRY,
+            &mut height as *mut i32,
+            &mut width as *mut i32,
+        );
+        if result != 0 {
+            return None;
+        }
+        Some(Size2D::new(width, height))
     }
 }
 
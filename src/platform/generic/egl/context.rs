@@ -2,36 +2,107 @@
 //
 //! Functionality common to backends using EGL contexts.
 
-use super::device::EGL_FUNCTIONS;
+use super::device::{self, EGL_FUNCTIONS};
 use super::error::ToWindowingApiError;
+use super::ffi::EGL_CONTEXT_OPENGL_NO_ERROR_KHR;
 use super::ffi::EGL_CONTEXT_OPENGL_PROFILE_MASK;
+use super::ffi::EGL_CONTEXT_OPENGL_ROBUST_ACCESS_EXT;
+use super::ffi::EGL_CONTEXT_PRIORITY_REALTIME_NV;
+use super::ffi::EGL_MUTABLE_RENDER_BUFFER_BIT_KHR;
+use super::ffi::{EGL_COLOR_COMPONENT_TYPE_EXT, EGL_COLOR_COMPONENT_TYPE_FLOAT_EXT};
+use super::ffi::{EGL_CONTEXT_FLAGS_KHR, EGL_CONTEXT_OPENGL_DEBUG_BIT_KHR};
 use super::ffi::{EGL_CONTEXT_MINOR_VERSION_KHR, EGL_CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT};
+use super::ffi::{EGL_CONTEXT_OPENGL_RESET_NOTIFICATION_STRATEGY_EXT, EGL_LOSE_CONTEXT_ON_RESET};
+use super::ffi::{
+    EGL_CONTEXT_OPENGL_RESET_NOTIFICATION_STRATEGY_KHR, EGL_CONTEXT_OPENGL_ROBUST_ACCESS_BIT_KHR,
+};
+use super::ffi::{EGL_CONTEXT_PRIORITY_HIGH_IMG, EGL_CONTEXT_PRIORITY_LEVEL_IMG};
+use super::ffi::{EGL_CONTEXT_PRIORITY_LOW_IMG, EGL_CONTEXT_PRIORITY_MEDIUM_IMG};
+use super::ffi::{EGL_CONTEXT_RELEASE_BEHAVIOR_KHR, EGL_CONTEXT_RELEASE_BEHAVIOR_NONE_KHR};
+#[cfg(all(free_unix, target_os = "linux"))]
+use super::kernel_priority::{self, KernelContexts};
 use super::surface::{EGLBackedSurface, ExternalEGLSurfaces};
 use crate::context::NativeContext as NativeContextInterface;
-use crate::context::{self, CREATE_CONTEXT_MUTEX};
+use crate::context::{self, ConfigCandidate, CREATE_CONTEXT_MUTEX};
+use crate::current::{self, SavedCurrent};
 use crate::egl;
-use crate::egl::types::{EGLConfig, EGLContext, EGLDisplay, EGLSurface, EGLint};
+use crate::egl::types::{EGLConfig, EGLContext, EGLDisplay, EGLSurface, EGLenum, EGLint};
+use crate::gl_utils;
+use crate::leak_check::{self, TrackedObject};
 use crate::surface::Framebuffer;
+use crate::CancelToken;
+use crate::ContextDescriptorAttributes;
+use crate::ContextPriority;
+use crate::SchedulingClass;
+use crate::{ColorBits, ColorDepth, Gl, PlaceholderPolicy, PresentMode};
 use crate::{ContextAttributeFlags, ContextAttributes, ContextID, Error, GLApi, GLVersion};
-use crate::{Gl, SurfaceInfo};
+use crate::{SurfaceInfo, SurfaceOptions, WindowingApiError};
 
+use log::debug;
+use std::cell::Cell;
+use std::collections::HashMap;
 use std::ffi::CString;
 use std::mem;
 use std::os::raw::{c_char, c_void};
 use std::ptr;
-use std::thread;
+use std::sync::Mutex;
+use std::thread::{self, ThreadId};
 
 #[allow(dead_code)]
 const DUMMY_PBUFFER_SIZE: EGLint = 16;
 const RGB_CHANNEL_BIT_DEPTH: EGLint = 8;
 
+thread_local! {
+    // The number of `BoundApiGuard`s alive on this thread, and the client API that the innermost
+    // one bound. Other code may rebind the API between guards, so this is only trusted while a
+    // guard is alive.
+    static BOUND_API: Cell<(usize, EGLenum)> = const { Cell::new((0, egl::NONE)) };
+}
+
+#[cfg(test)]
+thread_local! {
+    // Lets tests simulate a driver without `EGL_KHR_surfaceless_context` that limits how many
+    // pbuffers may be alive. While this is set, every context created on this thread needs a
+    // placeholder, and creating pbuffers beyond the limit fails.
+    pub(crate) static SIMULATED_PBUFFER_LIMIT: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+lazy_static! {
+    // Placeholder pbuffers shared among contexts, keyed by EGL display and config ID.
+    static ref SHARED_PLACEHOLDERS: Mutex<HashMap<(usize, EGLint), SharedPlaceholder>> =
+        Mutex::new(HashMap::new());
+}
+
 pub(crate) struct EGLBackedContext {
     pub(crate) egl_context: EGLContext,
     pub(crate) id: ContextID,
     framebuffer: Framebuffer<EGLBackedSurface, ExternalEGLSurfaces>,
+    pub(crate) placeholder: Placeholder,
+    pub(crate) placeholder_policy: PlaceholderPolicy,
     context_is_owned: bool,
 }
 
+// The surface that a context is made current with while no surface is attached to it.
+pub(crate) enum Placeholder {
+    // The context is made current with `EGL_NO_SURFACE`.
+    None,
+    // A pbuffer that belongs to this context alone.
+    Owned(EGLSurface),
+    // A pbuffer from the shared cache, keyed by this config ID.
+    Shared(EGLint),
+}
+
+struct SharedPlaceholder {
+    ref_count: usize,
+    // One pbuffer per thread that currently has it bound. Usually there is only one.
+    pbuffers: Vec<SharedPbuffer>,
+}
+
+struct SharedPbuffer {
+    egl_surface: usize,
+    current_thread: Option<ThreadId>,
+}
+
 /// Wrapper for a native `EGLContext`.
 #[derive(Clone, Copy)]
 pub struct NativeContext {
@@ -56,14 +127,29 @@ pub struct ContextDescriptor {
     pub(crate) egl_config_id: EGLint,
     pub(crate) gl_version: GLVersion,
     pub(crate) compatibility_profile: bool,
+    pub(crate) debug: bool,
+    pub(crate) robust_access: bool,
+    pub(crate) no_error: bool,
+    pub(crate) release_behavior_none: bool,
+    pub(crate) allow_lower_version: bool,
+    pub(crate) priority: ContextPriority,
+    pub(crate) placeholder_policy: PlaceholderPolicy,
 }
 
+/// Restores the context, draw surface, and read surface that were current on this thread when it
+/// was created, once it is dropped.
+///
+/// If no context was current, dropping the guard leaves no context current.
 #[must_use]
-pub(crate) struct CurrentContextGuard {
+pub struct CurrentContextGuard {
     egl_display: EGLDisplay,
     old_egl_draw_surface: EGLSurface,
     old_egl_read_surface: EGLSurface,
     old_egl_context: EGLContext,
+    tracked: SavedCurrent,
+    // Set if the context was already current for `Device::with_context_current()`, which puts
+    // back the previous context itself.
+    held: bool,
 }
 
 impl Drop for EGLBackedContext {
@@ -75,8 +161,33 @@ impl Drop for EGLBackedContext {
     }
 }
 
+// Binds an EGL client API on this thread, and restores the previously-bound API when dropped.
+//
+// `eglBindAPI` is thread-local state, and entry points such as `eglCreateContext` depend on it, so
+// they must be wrapped in one of these when GL and GLES contexts are mixed on the same thread.
+#[must_use]
+pub(crate) struct BoundApiGuard {
+    old_api: EGLenum,
+    api: EGLenum,
+}
+
+impl Drop for BoundApiGuard {
+    fn drop(&mut self) {
+        unsafe {
+            bind_api(self.api, self.old_api);
+        }
+        BOUND_API.with(|bound_api| {
+            let (depth, _) = bound_api.get();
+            bound_api.set((depth - 1, self.old_api));
+        });
+    }
+}
+
 impl Drop for CurrentContextGuard {
     fn drop(&mut self) {
+        if self.held {
+            return;
+        }
         EGL_FUNCTIONS.with(|egl| unsafe {
             if self.egl_display != egl::NO_DISPLAY {
                 egl.MakeCurrent(
@@ -85,8 +196,15 @@ impl Drop for CurrentContextGuard {
                     self.old_egl_read_surface,
                     self.old_egl_context,
                 );
+            } else {
+                // Nothing was current before, so release whatever was made current since.
+                let egl_display = egl.GetCurrentDisplay();
+                if egl_display != egl::NO_DISPLAY {
+                    drop(make_no_context_current(egl_display));
+                }
             }
-        })
+        });
+        current::restore(self.tracked);
     }
 }
 
@@ -96,8 +214,11 @@ impl EGLBackedContext {
         descriptor: &ContextDescriptor,
         share_with: Option<&EGLBackedContext>,
         gl_api: GLApi,
+        scheduling_class: SchedulingClass,
+        cancel_token: &CancelToken,
     ) -> Result<EGLBackedContext, Error> {
         let mut next_context_id = CREATE_CONTEXT_MUTEX.lock().unwrap();
+        cancel_token.check()?;
 
         // Create the context.
         let egl_context = create_context(
@@ -105,50 +226,97 @@ impl EGLBackedContext {
             descriptor,
             share_with.map_or(egl::NO_CONTEXT, |ctx| ctx.egl_context),
             gl_api,
+            scheduling_class,
         )?;
+        if let Err(err) = cancel_token.check() {
+            EGL_FUNCTIONS.with(|egl| egl.DestroyContext(egl_display, egl_context));
+            return Err(err);
+        }
+
+        // Most Linux drivers support surfaceless contexts, and then they never need a
+        // placeholder unless the caller asks for a shared one. The rest need a pbuffer to be made
+        // current with while no window surface is bound.
+        let surfaceless =
+            device::display_supports_extension(egl_display, "EGL_KHR_surfaceless_context");
+        #[cfg(test)]
+        let surfaceless =
+            surfaceless && SIMULATED_PBUFFER_LIMIT.with(|limit| limit.get()).is_none();
+        let mut placeholder = match Placeholder::new(
+            egl_display,
+            egl_context,
+            descriptor.placeholder_policy,
+            !surfaceless,
+        ) {
+            Ok(placeholder) => placeholder,
+            Err(err) => {
+                EGL_FUNCTIONS.with(|egl| egl.DestroyContext(egl_display, egl_context));
+                return Err(err);
+            }
+        };
+        if let Err(err) = cancel_token.check() {
+            placeholder.destroy(egl_display);
+            EGL_FUNCTIONS.with(|egl| egl.DestroyContext(egl_display, egl_context));
+            return Err(err);
+        }
 
         // Wrap and return it.
         let context = EGLBackedContext {
             egl_context,
             id: *next_context_id,
             framebuffer: Framebuffer::None,
+            placeholder,
+            placeholder_policy: descriptor.placeholder_policy,
             context_is_owned: true,
         };
         next_context_id.0 += 1;
+        leak_check::created(TrackedObject::Context);
         Ok(context)
     }
 
-    pub(crate) unsafe fn from_native_context(native_context: NativeContext) -> EGLBackedContext {
+    pub(crate) unsafe fn from_native_context(
+        egl_display: EGLDisplay,
+        native_context: NativeContext,
+    ) -> Result<EGLBackedContext, Error> {
+        if !native_context_belongs_to_display(egl_display, &native_context) {
+            return Err(Error::IncompatibleNativeContext);
+        }
+
         let mut next_context_id = CREATE_CONTEXT_MUTEX.lock().unwrap();
         let context = EGLBackedContext {
             egl_context: native_context.egl_context,
             id: *next_context_id,
-            framebuffer: Framebuffer::External(ExternalEGLSurfaces {
-                draw: native_context.egl_draw_surface,
-                read: native_context.egl_read_surface,
-            }),
+            framebuffer: native_context.framebuffer(),
+            placeholder: Placeholder::None,
+            placeholder_policy: PlaceholderPolicy::Owned,
             context_is_owned: false,
         };
         next_context_id.0 += 1;
-        context
+        leak_check::created(TrackedObject::Context);
+        Ok(context)
     }
 
     pub(crate) unsafe fn destroy(&mut self, egl_display: EGLDisplay) {
+        if self.egl_context == egl::NO_CONTEXT {
+            return;
+        }
+
         EGL_FUNCTIONS.with(|egl| {
-            egl.MakeCurrent(
-                egl_display,
-                egl::NO_SURFACE,
-                egl::NO_SURFACE,
-                egl::NO_CONTEXT,
-            );
+            // Leave any other context that the caller has made current alone.
+            if self.is_current() {
+                drop(make_no_context_current(egl_display));
+            }
+            self.placeholder.destroy(egl_display);
 
             if self.context_is_owned {
                 let result = egl.DestroyContext(egl_display, self.egl_context);
                 assert_ne!(result, egl::FALSE);
+                #[cfg(all(free_unix, target_os = "linux"))]
+                kernel_priority::forget(egl_display, self.egl_context);
             }
 
             self.egl_context = egl::NO_CONTEXT;
         });
+        leak_check::destroyed(TrackedObject::Context);
     }
 
     pub(crate) fn native_context(&self) -> NativeContext {
@@ -167,9 +335,21 @@ impl EGLBackedContext {
 
     pub(crate) unsafe fn make_current(&self, egl_display: EGLDisplay) -> Result<(), Error> {
         let egl_surfaces = match self.framebuffer {
-            Framebuffer::Surface(ref surface) => surface.egl_surfaces(),
             Framebuffer::External(ref surfaces) => (*surfaces).clone(),
-            Framebuffer::None => ExternalEGLSurfaces::default(),
+            // Texture-backed surfaces render through a framebuffer object, so the context is made
+            // current with the placeholder, as it is with no surface at all.
+            Framebuffer::Surface(ref surface) if surface.egl_surfaces().draw != egl::NO_SURFACE => {
+                surface.egl_surfaces()
+            }
+            Framebuffer::Surface(_) | Framebuffer::None => {
+                let placeholder = self
+                    .placeholder
+                    .egl_surface(egl_display, self.egl_context)?;
+                ExternalEGLSurfaces {
+                    draw: placeholder,
+                    read: placeholder,
+                }
+            }
         };
 
         EGL_FUNCTIONS.with(|egl| {
@@ -198,13 +378,20 @@ impl EGLBackedContext {
         surface: EGLBackedSurface,
     ) -> Result<(), (Error, EGLBackedSurface)> {
         if self.id != surface.context_id {
-            return Err((Error::IncompatibleSurface, surface));
+            let context_id = surface.context_id;
+            return Err((Error::SurfaceAlreadyBound { context_id }, surface));
+        }
+        if let Err(err) = surface.purge_state.check_usable() {
+            return Err((err, surface));
         }
 
         match self.framebuffer {
             Framebuffer::None => self.framebuffer = Framebuffer::Surface(surface),
             Framebuffer::External(_) => return Err((Error::ExternalRenderTarget, surface)),
-            Framebuffer::Surface(_) => return Err((Error::SurfaceAlreadyBound, surface)),
+            Framebuffer::Surface(_) => {
+                let context_id = self.id;
+                return Err((Error::SurfaceAlreadyBound { context_id }, surface));
+            }
         }
 
         // If we're current, call `make_context_current()` again to switch to the new framebuffer.
@@ -231,8 +418,18 @@ impl EGLBackedContext {
             Framebuffer::None | Framebuffer::External(_) => unreachable!(),
         };
 
-        // If we're current, we stay current, but with no surface attached.
-        surface.unbind(gl, egl_display, self.egl_context);
+        // If we're current, we stay current, but with the placeholder attached instead, so that
+        // the context stays usable even on displays without surfaceless contexts. Otherwise,
+        // multisampled surfaces still need the context briefly to resolve their samples.
+        if self.is_current() {
+            surface.unbind(gl, self.egl_context);
+            drop(self.make_current(egl_display));
+        } else if surface.is_multisampled() {
+            let _guard = CurrentContextGuard::new();
+            if self.make_current(egl_display).is_ok() {
+                surface.unbind(gl, self.egl_context);
+            }
+        }
 
         Ok(Some(surface))
     }
@@ -264,31 +461,56 @@ impl NativeContext {
             }
         })
     }
+
+    // The default framebuffer for a context wrapping this one. Without native surfaces, it is
+    // left free for surfman surfaces to be bound.
+    pub(crate) fn framebuffer<S>(&self) -> Framebuffer<S, ExternalEGLSurfaces> {
+        if self.egl_draw_surface == egl::NO_SURFACE && self.egl_read_surface == egl::NO_SURFACE {
+            return Framebuffer::None;
+        }
+        Framebuffer::External(ExternalEGLSurfaces {
+            draw: self.egl_draw_surface,
+            read: self.egl_read_surface,
+        })
+    }
 }
 
-impl crate::context::ContextDescriptorInterface for ContextDescriptor {
-    unsafe fn new(
+impl ContextDescriptor {
+    pub(crate) unsafe fn raw_parts(&self, egl_display: EGLDisplay) -> ContextDescriptorAttributes {
+        let egl_config = egl_config_from_id(egl_display, self.egl_config_id);
+        let config_attr = |attr: EGLenum| get_config_attr(egl_display, egl_config, attr as EGLint);
+        ContextDescriptorAttributes {
+            attributes: context::ContextDescriptorInterface::attributes(self, egl_display),
+            color_channel_size: config_attr(egl::RED_SIZE) as u8,
+            alpha_size: config_attr(egl::ALPHA_SIZE) as u8,
+            depth_size: config_attr(egl::DEPTH_SIZE) as u8,
+            stencil_size: config_attr(egl::STENCIL_SIZE) as u8,
+        }
+    }
+
+    // Like `ContextDescriptorInterface::new()`, but prefers configs whose native visual has the
+    // depth that windows are created with.
+    //
+    // `visual_depth` returns the depth of the native visual of a config, if it has one.
+    pub(crate) unsafe fn new_for_window_depth(
         egl_display: EGLDisplay,
         attributes: &ContextAttributes,
         extra_config_attributes: &[EGLint],
+        window_depth: Option<EGLint>,
+        visual_depth: &dyn Fn(EGLConfig) -> Option<EGLint>,
     ) -> Result<ContextDescriptor, Error> {
         let flags = attributes.flags;
+        context::check_attribute_flags(flags)?;
 
-        let alpha_size = if flags.contains(ContextAttributeFlags::ALPHA) {
-            8
-        } else {
+        // 10-bit configs only have 2 bits of alpha.
+        let alpha_size = if !flags.contains(ContextAttributeFlags::ALPHA) {
             0
-        };
-        let depth_size = if flags.contains(ContextAttributeFlags::DEPTH) {
-            24
+        } else if attributes.color_depth == ColorDepth::Deep {
+            2
         } else {
-            0
-        };
-        let stencil_size = if flags.contains(ContextAttributeFlags::STENCIL) {
             8
-        } else {
-            0
         };
+        let (depth_size, stencil_size) = context::requested_depth_stencil_sizes(attributes);
 
         let compatibility_profile = flags.contains(ContextAttributeFlags::COMPATIBILITY_PROFILE);
 
@@ -301,11 +523,34 @@ impl crate::context::ContextDescriptorInterface for ContextDescriptor {
             return Err(Error::UnsupportedGLProfile);
         }
 
-        // Create required config attributes.
+        // Debug contexts need `EGL_KHR_create_context`. Without it, fall back to an ordinary
+        // context.
+        let debug = flags.contains(ContextAttributeFlags::DEBUG)
+            && device::display_supports_extension(egl_display, "EGL_KHR_create_context");
+
+        // Unlike debugging, robustness can't be dropped, since callers rely on it for security.
+        let robust_access = flags.contains(ContextAttributeFlags::ROBUST_ACCESS);
+        if robust_access
+            && !device::display_supports_extension(egl_display, "EGL_EXT_create_context_robustness")
+        {
+            return Err(Error::RequiredExtensionUnavailable);
+        }
+
+        // Like debugging, skipping error checking is only a hint.
+        let no_error = flags.contains(ContextAttributeFlags::NO_ERROR)
+            && device::display_supports_extension(egl_display, "EGL_KHR_create_context_no_error");
+
+        let release_behavior_none = context::release_behavior_none(
+            flags,
+            device::display_supports_extension(egl_display, "EGL_KHR_context_flush_control"),
+        )?;
+
+        // Create config attributes.
         //
-        // We check these separately because `eglChooseConfig` on its own might give us 32-bit
+        // These are minimums. The exact channel sizes are checked separately by
+        // `context::choose_config()`, because `eglChooseConfig` on its own might give us 32-bit
         // color when 24-bit color is requested, and that can break code.
-        let required_config_attributes = [
+        let mut requested_config_attributes = vec![
             egl::RED_SIZE as EGLint,
             RGB_CHANNEL_BIT_DEPTH,
             egl::GREEN_SIZE as EGLint,
@@ -313,18 +558,24 @@ impl crate::context::ContextDescriptorInterface for ContextDescriptor {
             egl::BLUE_SIZE as EGLint,
             RGB_CHANNEL_BIT_DEPTH,
         ];
-
-        // Create config attributes.
-        let mut requested_config_attributes = required_config_attributes.to_vec();
         requested_config_attributes.extend_from_slice(&[
             egl::ALPHA_SIZE as EGLint,
             alpha_size,
             egl::DEPTH_SIZE as EGLint,
-            depth_size,
+            depth_size as EGLint,
             egl::STENCIL_SIZE as EGLint,
-            stencil_size,
+            stencil_size as EGLint,
         ]);
         requested_config_attributes.extend_from_slice(extra_config_attributes);
+
+        // `eglChooseConfig` only returns fixed-point configs unless asked otherwise. Float configs
+        // are never chosen unless float color was requested, so let it return both.
+        if attributes.color_depth == ColorDepth::Float
+            && device::display_supports_extension(egl_display, "EGL_EXT_pixel_format_float")
+        {
+            requested_config_attributes
+                .extend_from_slice(&[EGL_COLOR_COMPONENT_TYPE_EXT as EGLint, egl::DONT_CARE]);
+        }
         requested_config_attributes.extend_from_slice(&[egl::NONE as EGLint, 0, 0, 0]);
 
         EGL_FUNCTIONS.with(|egl| {
@@ -361,17 +612,14 @@ impl crate::context::ContextDescriptorInterface for ContextDescriptor {
             }
 
             // Sanitize configs.
-            let egl_config = configs
-                .into_iter()
-                .filter(|&egl_config| {
-                    required_config_attributes
-                        .chunks(2)
-                        .all(|pair| get_config_attr(egl_display, egl_config, pair[0]) == pair[1])
-                })
-                .next();
-            let egl_config = match egl_config {
+            configs.truncate(real_config_count as usize);
+            let candidates: Vec<_> = configs
+                .iter()
+                .map(|&egl_config| config_candidate(egl_display, egl_config, visual_depth))
+                .collect();
+            let egl_config = match context::choose_config(&candidates, attributes, window_depth) {
                 None => return Err(Error::NoPixelFormatFound),
-                Some(egl_config) => egl_config,
+                Some(index) => configs[index],
             };
 
             // Get the config ID and version.
@@ -382,9 +630,32 @@ impl crate::context::ContextDescriptorInterface for ContextDescriptor {
                 egl_config_id,
                 gl_version,
                 compatibility_profile,
+                debug,
+                robust_access,
+                no_error,
+                release_behavior_none,
+                allow_lower_version: flags.contains(ContextAttributeFlags::ALLOW_LOWER_VERSION),
+                priority: attributes.priority,
+                placeholder_policy: attributes.placeholder_policy,
             })
         })
     }
+}
+
+impl crate::context::ContextDescriptorInterface for ContextDescriptor {
+    unsafe fn new(
+        egl_display: EGLDisplay,
+        attributes: &ContextAttributes,
+        extra_config_attributes: &[EGLint],
+    ) -> Result<ContextDescriptor, Error> {
+        ContextDescriptor::new_for_window_depth(
+            egl_display,
+            attributes,
+            extra_config_attributes,
+            None,
+            &|_| None,
+        )
+    }
 
     unsafe fn from_egl_context(
         gl: &Gl,
@@ -398,11 +669,24 @@ impl crate::context::ContextDescriptorInterface for ContextDescriptor {
             egl.MakeCurrent(egl_display, egl::NO_SURFACE, egl::NO_SURFACE, egl_context);
             let gl_version = GLVersion::current(gl);
             let compatibility_profile = context::current_context_uses_compatibility_profile(gl);
+            let debug = context::current_context_is_debug(gl);
+            let robust_access = context::current_context_is_robust(gl);
+            let no_error = context::current_context_is_no_error(gl);
+            let release_behavior_none = context::current_context_release_behavior_is_none(gl);
+            let priority = context_priority(egl_display, egl_context);
 
+            // The placeholder policy isn't recorded by EGL. Backends fill it in from the context.
             ContextDescriptor {
                 egl_config_id,
                 gl_version,
                 compatibility_profile,
+                debug,
+                robust_access,
+                no_error,
+                release_behavior_none,
+                allow_lower_version: false,
+                priority,
+                placeholder_policy: PlaceholderPolicy::Owned,
             }
         })
     }
@@ -436,9 +720,11 @@ impl crate::context::ContextDescriptorInterface for ContextDescriptor {
     unsafe fn attributes(&self, egl_display: EGLDisplay) -> ContextAttributes {
         let egl_config = egl_config_from_id(egl_display, self.egl_config_id);
 
+        let red_size = get_config_attr(egl_display, egl_config, egl::RED_SIZE as EGLint);
         let alpha_size = get_config_attr(egl_display, egl_config, egl::ALPHA_SIZE as EGLint);
         let depth_size = get_config_attr(egl_display, egl_config, egl::DEPTH_SIZE as EGLint);
         let stencil_size = get_config_attr(egl_display, egl_config, egl::STENCIL_SIZE as EGLint);
+        let samples = get_config_attr(egl_display, egl_config, egl::SAMPLES as EGLint);
 
         // Convert to `surfman` context attribute flags.
         let mut attribute_flags = ContextAttributeFlags::empty();
@@ -450,15 +736,216 @@ impl crate::context::ContextDescriptorInterface for ContextDescriptor {
             ContextAttributeFlags::COMPATIBILITY_PROFILE,
             self.compatibility_profile,
         );
+        attribute_flags.set(ContextAttributeFlags::DEBUG, self.debug);
+        attribute_flags.set(ContextAttributeFlags::ROBUST_ACCESS, self.robust_access);
+        attribute_flags.set(ContextAttributeFlags::NO_ERROR, self.no_error);
+        attribute_flags.set(
+            ContextAttributeFlags::RELEASE_BEHAVIOR_NONE,
+            self.release_behavior_none,
+        );
 
         // Create appropriate context attributes.
         ContextAttributes {
             flags: attribute_flags,
             version: self.gl_version,
+            placeholder_policy: self.placeholder_policy,
+            color_depth: if config_is_float(egl_display, egl_config) {
+                ColorDepth::Float
+            } else if red_size > RGB_CHANNEL_BIT_DEPTH {
+                ColorDepth::Deep
+            } else {
+                ColorDepth::Standard
+            },
+            priority: self.priority,
+            samples: if samples > 1 { samples as u8 } else { 0 },
+            depth_size: Some(depth_size as u8),
+            stencil_size: Some(stencil_size as u8),
         }
     }
 }
 
+impl Placeholder {
+    pub(crate) unsafe fn new(
+        egl_display: EGLDisplay,
+        egl_context: EGLContext,
+        policy: PlaceholderPolicy,
+        needs_placeholder: bool,
+    ) -> Result<Placeholder, Error> {
+        let surfaceless =
+            device::display_supports_extension(egl_display, "EGL_KHR_surfaceless_context");
+
+        match policy {
+            PlaceholderPolicy::None if !surfaceless => Err(Error::RequiredExtensionUnavailable),
+            PlaceholderPolicy::None => Ok(Placeholder::None),
+            PlaceholderPolicy::Owned if needs_placeholder => {
+                let egl_config_id =
+                    get_context_attr(egl_display, egl_context, egl::CONFIG_ID as EGLint);
+                let egl_config = egl_config_from_id(egl_display, egl_config_id);
+                let surface_type =
+                    get_config_attr(egl_display, egl_config, egl::SURFACE_TYPE as EGLint);
+                if surface_type & egl::PBUFFER_BIT as EGLint == 0 {
+                    return Err(Error::RequiredExtensionUnavailable);
+                }
+                Ok(Placeholder::Owned(create_dummy_pbuffer(
+                    egl_display,
+                    egl_context,
+                )?))
+            }
+            PlaceholderPolicy::Owned => Ok(Placeholder::None),
+            PlaceholderPolicy::Shared => {
+                let egl_config_id =
+                    get_context_attr(egl_display, egl_context, egl::CONFIG_ID as EGLint);
+                let egl_config = egl_config_from_id(egl_display, egl_config_id);
+                let surface_type =
+                    get_config_attr(egl_display, egl_config, egl::SURFACE_TYPE as EGLint);
+                if surface_type & egl::PBUFFER_BIT as EGLint == 0 {
+                    // Configs without pbuffer support only exist on backends that bind
+                    // contexts surfacelessly anyway.
+                    return if surfaceless || !needs_placeholder {
+                        Ok(Placeholder::None)
+                    } else {
+                        Err(Error::RequiredExtensionUnavailable)
+                    };
+                }
+
+                let mut shared_placeholders = SHARED_PLACEHOLDERS.lock().unwrap();
+                shared_placeholders
+                    .entry((egl_display as usize, egl_config_id))
+                    .or_insert_with(|| SharedPlaceholder {
+                        ref_count: 0,
+                        pbuffers: vec![],
+                    })
+                    .ref_count += 1;
+                Ok(Placeholder::Shared(egl_config_id))
+            }
+        }
+    }
+
+    // Returns the surface to make current, claiming a shared pbuffer for this thread if
+    // necessary.
+    pub(crate) unsafe fn egl_surface(
+        &self,
+        egl_display: EGLDisplay,
+        egl_context: EGLContext,
+    ) -> Result<EGLSurface, Error> {
+        let egl_config_id = match *self {
+            Placeholder::None => return Ok(egl::NO_SURFACE),
+            Placeholder::Owned(egl_surface) => return Ok(egl_surface),
+            Placeholder::Shared(egl_config_id) => egl_config_id,
+        };
+
+        let this_thread = thread::current().id();
+        let mut shared_placeholders = SHARED_PLACEHOLDERS.lock().unwrap();
+        let shared_placeholder = shared_placeholders
+            .get_mut(&(egl_display as usize, egl_config_id))
+            .expect("Shared placeholder was destroyed while still in use!");
+
+        // Prefer the pbuffer this thread already has; otherwise take any free one. If every
+        // pbuffer is current on some other thread, create another one rather than violate EGL's
+        // rule against binding one surface on two threads.
+        let index = shared_placeholder
+            .pbuffers
+            .iter()
+            .position(|pbuffer| pbuffer.current_thread == Some(this_thread))
+            .or_else(|| {
+                shared_placeholder
+                    .pbuffers
+                    .iter()
+                    .position(|pbuffer| pbuffer.current_thread.is_none())
+            });
+        let index = match index {
+            Some(index) => index,
+            None => {
+                let egl_config = egl_config_from_id(egl_display, egl_config_id);
+                let _api_guard = BoundApiGuard::for_context(egl_display, egl_context);
+                let egl_surface = create_pbuffer_for_config(egl_display, egl_config)?;
+                shared_placeholder.pbuffers.push(SharedPbuffer {
+                    egl_surface: egl_surface as usize,
+                    current_thread: None,
+                });
+                shared_placeholder.pbuffers.len() - 1
+            }
+        };
+
+        let pbuffer = &mut shared_placeholder.pbuffers[index];
+        pbuffer.current_thread = Some(this_thread);
+        Ok(pbuffer.egl_surface as EGLSurface)
+    }
+
+    pub(crate) unsafe fn destroy(&mut self, egl_display: EGLDisplay) {
+        match mem::replace(self, Placeholder::None) {
+            Placeholder::None => {}
+            Placeholder::Owned(egl_surface) => EGL_FUNCTIONS.with(|egl| {
+                let result = egl.DestroySurface(egl_display, egl_surface);
+                assert_ne!(result, egl::FALSE);
+                leak_check::destroyed(TrackedObject::Placeholder);
+            }),
+            Placeholder::Shared(egl_config_id) => {
+                let key = (egl_display as usize, egl_config_id);
+                let mut shared_placeholders = SHARED_PLACEHOLDERS.lock().unwrap();
+                let shared_placeholder = shared_placeholders.get_mut(&key).unwrap();
+                shared_placeholder.ref_count -= 1;
+                if shared_placeholder.ref_count > 0 {
+                    return;
+                }
+
+                let shared_placeholder = shared_placeholders.remove(&key).unwrap();
+                EGL_FUNCTIONS.with(|egl| {
+                    for pbuffer in shared_placeholder.pbuffers {
+                        egl.DestroySurface(egl_display, pbuffer.egl_surface as EGLSurface);
+                        leak_check::destroyed(TrackedObject::Placeholder);
+                    }
+                });
+            }
+        }
+    }
+}
+
+// Marks all shared placeholders of the display as no longer current on this thread.
+//
+// Call this whenever the thread's current context is released.
+pub(crate) fn release_shared_placeholders(egl_display: EGLDisplay) {
+    let this_thread = thread::current().id();
+    let mut shared_placeholders = SHARED_PLACEHOLDERS.lock().unwrap();
+    for (&(display, _), shared_placeholder) in shared_placeholders.iter_mut() {
+        if display != egl_display as usize {
+            continue;
+        }
+        for pbuffer in &mut shared_placeholder.pbuffers {
+            if pbuffer.current_thread == Some(this_thread) {
+                pbuffer.current_thread = None;
+            }
+        }
+    }
+}
+
+// Destroys the shared placeholder pbuffers of the display that aren't current on any thread, and
+// returns how many were destroyed.
+//
+// Contexts keep their claim on the shared placeholder, and `Placeholder::egl_surface()` creates
+// a new pbuffer the next time one is needed.
+pub(crate) unsafe fn purge_shared_placeholders(egl_display: EGLDisplay) -> usize {
+    let mut shared_placeholders = SHARED_PLACEHOLDERS.lock().unwrap();
+    let mut purged = 0;
+    EGL_FUNCTIONS.with(|egl| {
+        for (&(display, _), shared_placeholder) in shared_placeholders.iter_mut() {
+            if display != egl_display as usize {
+                continue;
+            }
+            shared_placeholder.pbuffers.retain(|pbuffer| {
+                if pbuffer.current_thread.is_some() {
+                    return true;
+                }
+                egl.DestroySurface(egl_display, pbuffer.egl_surface as EGLSurface);
+                leak_check::destroyed(TrackedObject::Placeholder);
+                purged += 1;
+                false
+            });
+        }
+    });
+    purged
+}
+
 impl CurrentContextGuard {
     pub(crate) fn new() -> CurrentContextGuard {
         EGL_FUNCTIONS.with(|egl| unsafe {
@@ -467,9 +954,75 @@ impl CurrentContextGuard {
                 old_egl_draw_surface: egl.GetCurrentSurface(egl::DRAW as EGLint),
                 old_egl_read_surface: egl.GetCurrentSurface(egl::READ as EGLint),
                 old_egl_context: egl.GetCurrentContext(),
+                tracked: current::save(),
+                held: false,
             }
         })
     }
+
+    // Returns a guard that restores nothing, for a context that `current::held_for_bulk_ops()`
+    // says is current already.
+    pub(crate) fn held() -> CurrentContextGuard {
+        CurrentContextGuard {
+            egl_display: egl::NO_DISPLAY,
+            old_egl_draw_surface: egl::NO_SURFACE,
+            old_egl_read_surface: egl::NO_SURFACE,
+            old_egl_context: egl::NO_CONTEXT,
+            tracked: current::save(),
+            held: true,
+        }
+    }
+}
+
+impl BoundApiGuard {
+    pub(crate) unsafe fn new(gl_api: GLApi) -> BoundApiGuard {
+        BoundApiGuard::bind(match gl_api {
+            GLApi::GL => egl::OPENGL_API,
+            GLApi::GLES => egl::OPENGL_ES_API,
+        })
+    }
+
+    // Binds the client API that the given context was created for.
+    pub(crate) unsafe fn for_context(
+        egl_display: EGLDisplay,
+        egl_context: EGLContext,
+    ) -> BoundApiGuard {
+        let client_type = get_context_attr(egl_display, egl_context, egl::CONTEXT_CLIENT_TYPE as _);
+        BoundApiGuard::bind(client_type as EGLenum)
+    }
+
+    unsafe fn bind(api: EGLenum) -> BoundApiGuard {
+        let (depth, bound_api) = BOUND_API.with(Cell::get);
+        let old_api = if depth > 0 {
+            bound_api
+        } else {
+            EGL_FUNCTIONS.with(|egl| egl.QueryAPI())
+        };
+        bind_api(old_api, api);
+        BOUND_API.with(|bound_api| bound_api.set((depth + 1, api)));
+        BoundApiGuard { old_api, api }
+    }
+}
+
+// Calls `eglBindAPI`, unless the API is already bound. `EGL_NONE` means that no API was bound,
+// which can't be restored.
+unsafe fn bind_api(old_api: EGLenum, api: EGLenum) {
+    if old_api == api || api == egl::NONE {
+        return;
+    }
+    EGL_FUNCTIONS.with(|egl| {
+        let ok = egl.BindAPI(api);
+        assert_ne!(ok, egl::FALSE);
+    });
+}
+
+// Returns the `EGL_RENDERABLE_TYPE` bit that a config needs to support the given API and version.
+pub(crate) fn renderable_type_bit(gl_api: GLApi, version: GLVersion) -> EGLint {
+    match gl_api {
+        GLApi::GL => egl::OPENGL_BIT as EGLint,
+        GLApi::GLES if version.major >= 3 => egl::OPENGL_ES3_BIT as EGLint,
+        GLApi::GLES => egl::OPENGL_ES2_BIT as EGLint,
+    }
 }
 
 pub(crate) unsafe fn create_context(
@@ -477,52 +1030,191 @@ pub(crate) unsafe fn create_context(
     descriptor: &ContextDescriptor,
     share_with: EGLContext,
     gl_api: GLApi,
+    scheduling_class: SchedulingClass,
 ) -> Result<EGLContext, Error> {
-    EGL_FUNCTIONS.with(|egl| {
-        let ok = egl.BindAPI(match gl_api {
-            GLApi::GL => egl::OPENGL_API,
-            GLApi::GLES => egl::OPENGL_ES_API,
-        });
-        assert_ne!(ok, egl::FALSE);
-    });
+    // Contexts in a share group must use the same config.
+    if share_with != egl::NO_CONTEXT {
+        let shared_config_id = get_context_attr(egl_display, share_with, egl::CONFIG_ID as EGLint);
+        if shared_config_id != descriptor.egl_config_id {
+            return Err(Error::IncompatibleSharedContext);
+        }
+    }
 
+    if descriptor.robust_access
+        && gl_api == GLApi::GL
+        && !device::display_supports_extension(egl_display, "EGL_KHR_create_context")
+    {
+        return Err(Error::RequiredExtensionUnavailable);
+    }
+
+    let _api_guard = BoundApiGuard::new(gl_api);
     let egl_config = egl_config_from_id(egl_display, descriptor.egl_config_id);
 
-    let mut egl_context_attributes = vec![
-        egl::CONTEXT_CLIENT_VERSION as EGLint,
-        descriptor.gl_version.major as EGLint,
-        EGL_CONTEXT_MINOR_VERSION_KHR as EGLint,
-        descriptor.gl_version.minor as EGLint,
-    ];
+    // Priority is only a hint, so it's left out where the extension is missing.
+    let priority_level =
+        if device::display_supports_extension(egl_display, "EGL_IMG_context_priority") {
+            match scheduling_class.context_priority(descriptor.priority) {
+                ContextPriority::Low => Some(EGL_CONTEXT_PRIORITY_LOW_IMG),
+                ContextPriority::Medium => Some(EGL_CONTEXT_PRIORITY_MEDIUM_IMG),
+                ContextPriority::High => Some(EGL_CONTEXT_PRIORITY_HIGH_IMG),
+                ContextPriority::Realtime
+                    if device::display_supports_extension(
+                        egl_display,
+                        "EGL_NV_context_priority_realtime",
+                    ) =>
+                {
+                    Some(EGL_CONTEXT_PRIORITY_REALTIME_NV)
+                }
+                ContextPriority::Realtime => Some(EGL_CONTEXT_PRIORITY_HIGH_IMG),
+            }
+        } else {
+            None
+        };
 
-    // D3D11 ANGLE doesn't seem happy if EGL_CONTEXT_OPENGL_PROFILE_MASK is set
-    // to be a core profile.
-    if descriptor.compatibility_profile {
-        egl_context_attributes.extend(&[
-            EGL_CONTEXT_OPENGL_PROFILE_MASK as EGLint,
-            EGL_CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT,
-        ]);
-    }
+    // Otherwise, a low priority is set on the kernel contexts that the driver creates, if it's
+    // one that allows that.
+    #[cfg(all(free_unix, target_os = "linux"))]
+    let kernel_contexts = match scheduling_class.context_priority(descriptor.priority) {
+        ContextPriority::Low if priority_level.is_none() => Some(KernelContexts::list()),
+        _ => None,
+    };
+
+    // Without `EGL_KHR_create_context`, only the major version can be requested, and the driver
+    // picks the minor version itself.
+    let minor_version_supported =
+        device::display_supports_extension(egl_display, "EGL_KHR_create_context");
+    let versions = if minor_version_supported {
+        context::versions_to_try(descriptor.gl_version, descriptor.allow_lower_version)
+    } else {
+        vec![descriptor.gl_version]
+    };
+
+    // `hints` includes the attributes that can be dropped if context creation fails with them.
+    let context_attributes = |version: GLVersion, hints: bool| {
+        let mut egl_context_attributes = vec![
+            egl::CONTEXT_CLIENT_VERSION as EGLint,
+            version.major as EGLint,
+        ];
+        if minor_version_supported {
+            egl_context_attributes.extend(&[
+                EGL_CONTEXT_MINOR_VERSION_KHR as EGLint,
+                version.minor as EGLint,
+            ]);
+        }
 
-    // Include some extra zeroes to work around broken implementations.
-    //
-    // FIXME(pcwalton): Which implementations are those? (This is copied from Gecko.)
-    egl_context_attributes.extend(&[egl::NONE as EGLint, 0, 0, 0]);
+        // D3D11 ANGLE doesn't seem happy if EGL_CONTEXT_OPENGL_PROFILE_MASK is set
+        // to be a core profile. The profile mask is meaningless for OpenGL ES.
+        if descriptor.compatibility_profile && gl_api == GLApi::GL {
+            egl_context_attributes.extend(&[
+                EGL_CONTEXT_OPENGL_PROFILE_MASK as EGLint,
+                EGL_CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT,
+            ]);
+        }
 
-    EGL_FUNCTIONS.with(|egl| {
-        let egl_context = egl.CreateContext(
-            egl_display,
-            egl_config,
-            share_with,
-            egl_context_attributes.as_ptr(),
-        );
-        if egl_context == egl::NO_CONTEXT {
-            let err = egl.GetError();
-            let err = err.to_windowing_api_error();
-            return Err(Error::ContextCreationFailed(err));
+        let mut context_flags = 0;
+        if hints && descriptor.debug {
+            context_flags |= EGL_CONTEXT_OPENGL_DEBUG_BIT_KHR;
+        }
+
+        match priority_level {
+            Some(priority_level) if hints => egl_context_attributes
+                .extend(&[EGL_CONTEXT_PRIORITY_LEVEL_IMG as EGLint, priority_level]),
+            _ => {}
+        }
+
+        if hints && descriptor.no_error {
+            egl_context_attributes.extend(&[
+                EGL_CONTEXT_OPENGL_NO_ERROR_KHR as EGLint,
+                egl::TRUE as EGLint,
+            ]);
+        }
+
+        if descriptor.release_behavior_none {
+            egl_context_attributes.extend(&[
+                EGL_CONTEXT_RELEASE_BEHAVIOR_KHR as EGLint,
+                EGL_CONTEXT_RELEASE_BEHAVIOR_NONE_KHR,
+            ]);
+        }
+
+        // `EGL_EXT_create_context_robustness` only covers OpenGL ES. OpenGL contexts are made
+        // robust through `EGL_KHR_create_context` instead.
+        if descriptor.robust_access {
+            match gl_api {
+                GLApi::GL => {
+                    context_flags |= EGL_CONTEXT_OPENGL_ROBUST_ACCESS_BIT_KHR;
+                    egl_context_attributes.extend(&[
+                        EGL_CONTEXT_OPENGL_RESET_NOTIFICATION_STRATEGY_KHR as EGLint,
+                        EGL_LOSE_CONTEXT_ON_RESET,
+                    ]);
+                }
+                GLApi::GLES => {
+                    egl_context_attributes.extend(&[
+                        EGL_CONTEXT_OPENGL_ROBUST_ACCESS_EXT as EGLint,
+                        egl::TRUE as EGLint,
+                        EGL_CONTEXT_OPENGL_RESET_NOTIFICATION_STRATEGY_EXT as EGLint,
+                        EGL_LOSE_CONTEXT_ON_RESET,
+                    ]);
+                }
+            }
+        }
+
+        if context_flags != 0 {
+            egl_context_attributes.extend(&[EGL_CONTEXT_FLAGS_KHR as EGLint, context_flags]);
         }
 
-        Ok(egl_context)
+        // Include some extra zeroes to work around broken implementations.
+        //
+        // FIXME(pcwalton): Which implementations are those? (This is copied from Gecko.)
+        egl_context_attributes.extend(&[egl::NONE as EGLint, 0, 0, 0]);
+        egl_context_attributes
+    };
+
+    EGL_FUNCTIONS.with(|egl| {
+        let mut err = WindowingApiError::Failed;
+        for &version in &versions {
+            let mut egl_context = egl.CreateContext(
+                egl_display,
+                egl_config,
+                share_with,
+                context_attributes(version, true).as_ptr(),
+            );
+
+            // Some implementations advertise `EGL_KHR_create_context` but can't create debug
+            // contexts for every API, and some refuse priorities that the process isn't allowed.
+            // No-error contexts also can't share with ordinary ones. Fall back to an ordinary
+            // context.
+            if egl_context == egl::NO_CONTEXT
+                && (descriptor.debug || descriptor.no_error || priority_level.is_some())
+            {
+                let err = egl.GetError().to_windowing_api_error();
+                debug!(
+                    "Failed to create a context with debug, no-error, or priority hints ({:?}), \
+                     retrying without",
+                    err
+                );
+                egl_context = egl.CreateContext(
+                    egl_display,
+                    egl_config,
+                    share_with,
+                    context_attributes(version, false).as_ptr(),
+                );
+            }
+
+            if egl_context != egl::NO_CONTEXT {
+                #[cfg(all(free_unix, target_os = "linux"))]
+                if let Some(kernel_contexts) = kernel_contexts {
+                    kernel_contexts.lower_new_contexts(egl_display, egl_context);
+                }
+                return Ok(egl_context);
+            }
+
+            err = egl.GetError().to_windowing_api_error();
+            debug!(
+                "Failed to create a version {}.{} context ({:?})",
+                version.major, version.minor, err
+            );
+        }
+        Err(Error::ContextCreationFailed(err))
     })
 }
 
@@ -538,6 +1230,8 @@ pub(crate) unsafe fn make_no_context_current(egl_display: EGLDisplay) -> Result<
             let err = egl.GetError().to_windowing_api_error();
             return Err(Error::MakeCurrentFailed(err));
         }
+        release_shared_placeholders(egl_display);
+        current::released();
         Ok(())
     })
 }
@@ -555,6 +1249,197 @@ pub(crate) unsafe fn get_config_attr(
     })
 }
 
+// Returns true if the config has floating-point color channels. Without
+// `EGL_EXT_pixel_format_float`, every config is fixed-point.
+pub(crate) unsafe fn config_is_float(egl_display: EGLDisplay, egl_config: EGLConfig) -> bool {
+    device::display_supports_extension(egl_display, "EGL_EXT_pixel_format_float")
+        && get_config_attr(
+            egl_display,
+            egl_config,
+            EGL_COLOR_COMPONENT_TYPE_EXT as EGLint,
+        ) == EGL_COLOR_COMPONENT_TYPE_FLOAT_EXT as EGLint
+}
+
+// Returns the channel and buffer sizes of the config, for `context::choose_config()` and
+// `context::choose_widget_config()`.
+unsafe fn config_candidate(
+    egl_display: EGLDisplay,
+    egl_config: EGLConfig,
+    visual_depth: &dyn Fn(EGLConfig) -> Option<EGLint>,
+) -> ConfigCandidate {
+    let config_attr = |attr: EGLenum| get_config_attr(egl_display, egl_config, attr as EGLint);
+    ConfigCandidate {
+        red_size: config_attr(egl::RED_SIZE),
+        green_size: config_attr(egl::GREEN_SIZE),
+        blue_size: config_attr(egl::BLUE_SIZE),
+        alpha_size: config_attr(egl::ALPHA_SIZE),
+        depth_size: config_attr(egl::DEPTH_SIZE),
+        stencil_size: config_attr(egl::STENCIL_SIZE),
+        samples: config_attr(egl::SAMPLES),
+        visual_depth: visual_depth(egl_config),
+        float: config_is_float(egl_display, egl_config),
+    }
+}
+
+// Returns the config to create a widget surface with, for a context with the given config.
+//
+// `window_depth` is the depth of the window's visual on backends where the config must match it,
+// and `visual_depth` returns the depth of a config's visual there. The context's own config is
+// preferred when it will do. An explicit opacity that no config can provide fails with
+// `Error::NoPixelFormatFound`; without one, the context's config is used regardless, and EGL
+// reports any mismatch with the window.
+//
+// Single-buffered surfaces need a config with a mutable render buffer, and fail with
+// `Error::UnsupportedPresentMode` if there is none. Surfaces that preserve their buffer need one
+// with `EGL_SWAP_BEHAVIOR_PRESERVED_BIT`, and fail with `Error::UnsupportedSwapBehavior`.
+pub(crate) unsafe fn widget_config(
+    egl_display: EGLDisplay,
+    context_config: EGLConfig,
+    options: &SurfaceOptions,
+    window_depth: Option<EGLint>,
+    visual_depth: &dyn Fn(EGLConfig) -> Option<EGLint>,
+) -> Result<EGLConfig, Error> {
+    let opaque = options.opaque;
+    let single_buffer = options.present_mode == PresentMode::SingleBuffer;
+    let mut surface_type = egl::WINDOW_BIT as EGLint;
+    if single_buffer {
+        if !device::display_supports_extension(egl_display, "EGL_KHR_mutable_render_buffer") {
+            return Err(Error::UnsupportedPresentMode);
+        }
+        surface_type |= EGL_MUTABLE_RENDER_BUFFER_BIT_KHR;
+    }
+    if options.preserve_buffer {
+        surface_type |= egl::SWAP_BEHAVIOR_PRESERVED_BIT as EGLint;
+    }
+    // Without any extra surface type to ask for, the context's config is taken to suit a window.
+    let context_config_suits = surface_type == egl::WINDOW_BIT as EGLint || {
+        let config_surface_type =
+            get_config_attr(egl_display, context_config, egl::SURFACE_TYPE as EGLint);
+        config_surface_type & surface_type == surface_type
+    };
+
+    let context_candidate = config_candidate(egl_display, context_config, visual_depth);
+    let alpha_size = match opaque {
+        None => None,
+        Some(true) => Some(0),
+        Some(false) if context_candidate.alpha_size > 0 => Some(context_candidate.alpha_size),
+        // Deep-color configs have two bits of alpha; the others have as many as each channel.
+        Some(false) if context_candidate.red_size == 10 => Some(2),
+        Some(false) => Some(context_candidate.red_size),
+    };
+    if context_config_suits
+        && context::choose_widget_config(
+            &context_candidate,
+            &[context_candidate],
+            alpha_size,
+            window_depth,
+        )
+        .is_some()
+    {
+        return Ok(context_config);
+    }
+
+    // Ask for at least the context's sizes; `context::choose_widget_config()` checks for exact
+    // matches.
+    let mut config_attributes = vec![
+        egl::RED_SIZE as EGLint,
+        context_candidate.red_size,
+        egl::GREEN_SIZE as EGLint,
+        context_candidate.green_size,
+        egl::BLUE_SIZE as EGLint,
+        context_candidate.blue_size,
+        egl::ALPHA_SIZE as EGLint,
+        alpha_size.unwrap_or(0),
+        egl::DEPTH_SIZE as EGLint,
+        context_candidate.depth_size,
+        egl::STENCIL_SIZE as EGLint,
+        context_candidate.stencil_size,
+        egl::SURFACE_TYPE as EGLint,
+        surface_type,
+        egl::RENDERABLE_TYPE as EGLint,
+        get_config_attr(egl_display, context_config, egl::RENDERABLE_TYPE as EGLint),
+    ];
+    if context_candidate.float {
+        config_attributes
+            .extend_from_slice(&[EGL_COLOR_COMPONENT_TYPE_EXT as EGLint, egl::DONT_CARE]);
+    }
+    config_attributes.extend_from_slice(&[egl::NONE as EGLint, 0, 0, 0]);
+
+    let configs = EGL_FUNCTIONS.with(|egl| {
+        let mut config_count = 0;
+        let result = egl.ChooseConfig(
+            egl_display,
+            config_attributes.as_ptr(),
+            ptr::null_mut(),
+            0,
+            &mut config_count,
+        );
+        if result == egl::FALSE || config_count == 0 {
+            return vec![];
+        }
+        let mut configs = vec![ptr::null(); config_count as usize];
+        let result = egl.ChooseConfig(
+            egl_display,
+            config_attributes.as_ptr(),
+            configs.as_mut_ptr(),
+            config_count,
+            &mut config_count,
+        );
+        if result == egl::FALSE {
+            return vec![];
+        }
+        configs.truncate(config_count as usize);
+        configs
+    });
+
+    let candidates: Vec<_> = configs
+        .iter()
+        .map(|&egl_config| config_candidate(egl_display, egl_config, visual_depth))
+        .collect();
+    match context::choose_widget_config(&context_candidate, &candidates, alpha_size, window_depth) {
+        Some(index) => Ok(configs[index]),
+        None if single_buffer => Err(Error::UnsupportedPresentMode),
+        None if options.preserve_buffer => Err(Error::UnsupportedSwapBehavior),
+        None if opaque.is_none() => Ok(context_config),
+        None => Err(Error::NoPixelFormatFound),
+    }
+}
+
+// Returns the number of bits in each color channel of the config.
+pub(crate) unsafe fn config_color_bits(
+    egl_display: EGLDisplay,
+    egl_config: EGLConfig,
+) -> ColorBits {
+    let config_attr =
+        |attr: EGLenum| get_config_attr(egl_display, egl_config, attr as EGLint) as u8;
+    ColorBits {
+        red: config_attr(egl::RED_SIZE),
+        green: config_attr(egl::GREEN_SIZE),
+        blue: config_attr(egl::BLUE_SIZE),
+        alpha: config_attr(egl::ALPHA_SIZE),
+    }
+}
+
+// Returns true if `native_context` names a live context on `egl_display`. Unlike
+// `get_context_attr()`, this doesn't assert, so that foreign contexts can be checked.
+pub(crate) unsafe fn native_context_belongs_to_display(
+    egl_display: EGLDisplay,
+    native_context: &NativeContext,
+) -> bool {
+    if native_context.egl_context == egl::NO_CONTEXT {
+        return false;
+    }
+    EGL_FUNCTIONS.with(|egl| {
+        let mut config_id = 0;
+        egl.QueryContext(
+            egl_display,
+            native_context.egl_context,
+            egl::CONFIG_ID as EGLint,
+            &mut config_id,
+        ) != egl::FALSE
+    })
+}
+
 pub(crate) unsafe fn get_context_attr(
     egl_display: EGLDisplay,
     egl_context: EGLContext,
@@ -568,6 +1453,31 @@ pub(crate) unsafe fn get_context_attr(
     })
 }
 
+// Returns the priority that the context was actually granted, which may be lower than the one
+// that was requested.
+pub(crate) unsafe fn context_priority(
+    egl_display: EGLDisplay,
+    egl_context: EGLContext,
+) -> ContextPriority {
+    if !device::display_supports_extension(egl_display, "EGL_IMG_context_priority") {
+        #[cfg(all(free_unix, target_os = "linux"))]
+        if kernel_priority::is_lowered(egl_display, egl_context) {
+            return ContextPriority::Low;
+        }
+        return ContextPriority::Medium;
+    }
+    match get_context_attr(
+        egl_display,
+        egl_context,
+        EGL_CONTEXT_PRIORITY_LEVEL_IMG as EGLint,
+    ) {
+        EGL_CONTEXT_PRIORITY_LOW_IMG => ContextPriority::Low,
+        EGL_CONTEXT_PRIORITY_HIGH_IMG => ContextPriority::High,
+        EGL_CONTEXT_PRIORITY_REALTIME_NV => ContextPriority::Realtime,
+        _ => ContextPriority::Medium,
+    }
+}
+
 pub(crate) unsafe fn egl_config_from_id(
     egl_display: EGLDisplay,
     egl_config_id: EGLint,
@@ -603,15 +1513,60 @@ pub(crate) fn get_proc_address(symbol_name: &str) -> *const c_void {
     })
 }
 
+// Looks up an OpenGL or EGL entry point for `Device::get_proc_address()`, returning `None` if EGL
+// doesn't know it or it belongs to an extension that isn't supported. `gl_extensions` returns the
+// extensions of the context that the entry point is for.
+pub(crate) fn get_available_proc_address<F>(
+    egl_display: EGLDisplay,
+    symbol_name: &str,
+    gl_extensions: F,
+) -> Option<*const c_void>
+where
+    F: FnOnce() -> Vec<String>,
+{
+    if symbol_name.contains('\0') {
+        return None;
+    }
+    let address = get_proc_address(symbol_name);
+    if address.is_null() {
+        return None;
+    }
+    let available = gl_utils::entry_point_available(symbol_name, |api| match api {
+        "EGL" => unsafe { device::display_extensions(egl_display) },
+        _ => gl_extensions(),
+    });
+    if available {
+        Some(address)
+    } else {
+        None
+    }
+}
+
 // Creates and returns a dummy pbuffer surface for the given context. This is used as the default
 // framebuffer on some backends.
 #[allow(dead_code)]
 pub(crate) unsafe fn create_dummy_pbuffer(
     egl_display: EGLDisplay,
     egl_context: EGLContext,
-) -> EGLSurface {
+) -> Result<EGLSurface, Error> {
     let egl_config_id = get_context_attr(egl_display, egl_context, egl::CONFIG_ID as EGLint);
     let egl_config = egl_config_from_id(egl_display, egl_config_id);
+    let _api_guard = BoundApiGuard::for_context(egl_display, egl_context);
+    create_pbuffer_for_config(egl_display, egl_config)
+}
+
+// Fails with `Error::SurfaceCreationFailed` if the driver is out of surfaces, as drivers that
+// limit how many may be alive are.
+unsafe fn create_pbuffer_for_config(
+    egl_display: EGLDisplay,
+    egl_config: EGLConfig,
+) -> Result<EGLSurface, Error> {
+    #[cfg(test)]
+    if let Some(limit) = SIMULATED_PBUFFER_LIMIT.with(|limit| limit.get()) {
+        if TrackedObject::Placeholder.live_count() >= limit {
+            return Err(Error::SurfaceCreationFailed(WindowingApiError::BadAlloc));
+        }
+    }
 
     let pbuffer_attributes = [
         egl::WIDTH as EGLint,
@@ -627,7 +1582,11 @@ pub(crate) unsafe fn create_dummy_pbuffer(
     EGL_FUNCTIONS.with(|egl| {
         let pbuffer =
             egl.CreatePbufferSurface(egl_display, egl_config, pbuffer_attributes.as_ptr());
-        assert_ne!(pbuffer, egl::NO_SURFACE);
-        pbuffer
+        if pbuffer == egl::NO_SURFACE {
+            let err = egl.GetError().to_windowing_api_error();
+            return Err(Error::SurfaceCreationFailed(err));
+        }
+        leak_check::created(TrackedObject::Placeholder);
+        Ok(pbuffer)
     })
 }
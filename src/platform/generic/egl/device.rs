@@ -2,14 +2,27 @@
 //
 //! Functionality common to backends using EGL displays.
 
+use super::error::ToWindowingApiError;
+use super::ffi::{EGL_DEVICE_EXT, EGL_DRM_DEVICE_FILE_EXT, EGL_EXTENSION_FUNCTIONS};
+use super::ffi::{EGL_DRM_RENDER_NODE_FILE_EXT, EGL_NO_DEVICE_EXT, EGL_RENDERER_EXT};
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+use crate::diagnostics::{self, SystemProbe};
+use crate::egl;
+use crate::egl::types::EGLint;
+use crate::egl::types::{EGLAttrib, EGLDeviceEXT, EGLDisplay, EGLNativeDisplayType, EGLenum};
 use crate::egl::Egl;
+use crate::{DeviceIdentity, Error, GLApi};
+
+use log::info;
 
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
 use std::mem;
 use std::os::raw::{c_char, c_void};
 
 #[cfg(not(target_os = "windows"))]
 use libc::{dlopen, dlsym, RTLD_LAZY};
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+use std::os::unix::ffi::OsStringExt;
 #[cfg(target_os = "windows")]
 use winapi::shared::minwindef::HMODULE;
 #[cfg(target_os = "windows")]
@@ -17,6 +30,18 @@ use winapi::shared::ntdef::LPCSTR;
 #[cfg(target_os = "windows")]
 use winapi::um::libloaderapi;
 
+// Whether the kernel scheduling priority of contexts on the display can be lowered with DRM
+// ioctls, for drivers without `EGL_IMG_context_priority`.
+#[cfg(all(free_unix, target_os = "linux"))]
+pub(crate) fn kernel_priorities_supported(egl_display: EGLDisplay) -> bool {
+    super::kernel_priority::supported(egl_display)
+}
+
+#[cfg(not(all(free_unix, target_os = "linux")))]
+pub(crate) fn kernel_priorities_supported(_: EGLDisplay) -> bool {
+    false
+}
+
 thread_local! {
     pub static EGL_FUNCTIONS: Egl = Egl::load_with(get_proc_address);
 }
@@ -42,7 +67,21 @@ lazy_static! {
                 }
             }
         }
-        panic!("Unable to load the libEGL shared object");
+        // Sandboxes don't always put the host's driver on the linker's search path.
+        for directory in diagnostics::library_search_dirs(&SystemProbe) {
+            let path = directory.join("libEGL.so.1").into_os_string().into_vec();
+            let path = CString::new(path).unwrap();
+            unsafe {
+                let handle = dlopen(path.as_ptr(), RTLD_LAZY);
+                if !handle.is_null() {
+                    return EGLLibraryWrapper(handle);
+                }
+            }
+        }
+        match diagnostics::detect_sandbox(&SystemProbe) {
+            Some(sandbox) => panic!("Unable to load the libEGL shared object in {:?}", sandbox),
+            None => panic!("Unable to load the libEGL shared object"),
+        }
     };
 }
 
@@ -77,6 +116,278 @@ pub(crate) unsafe fn lookup_egl_extension(name: &'static [u8]) -> *mut c_void {
         .with(|egl| mem::transmute(egl.GetProcAddress(&name[0] as *const u8 as *const c_char)))
 }
 
+// The EGL entry points that `get_platform_display()` tries, in order.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum DisplayEntryPoint {
+    // Core EGL 1.5 `eglGetPlatformDisplay`, which takes `EGLAttrib` attributes.
+    GetPlatformDisplay,
+    // `eglGetPlatformDisplayEXT` from `EGL_EXT_platform_base`, which takes `EGLint` attributes.
+    GetPlatformDisplayEXT,
+    // Legacy `eglGetDisplay`, which leaves EGL to guess the platform of the native display.
+    GetDisplay,
+}
+
+// The entry points that `get_platform_display()` can create displays with, each `None` where the
+// EGL implementation lacks it. Tests substitute fakes for these.
+pub(crate) struct DisplayFunctions {
+    pub(crate) get_platform_display:
+        Option<unsafe fn(EGLenum, *mut c_void, *const EGLAttrib) -> EGLDisplay>,
+    pub(crate) get_platform_display_ext:
+        Option<unsafe fn(EGLenum, *mut c_void, *const EGLint) -> EGLDisplay>,
+    pub(crate) get_display: Option<unsafe fn(*mut c_void) -> EGLDisplay>,
+    pub(crate) get_error: unsafe fn() -> EGLint,
+}
+
+impl DisplayFunctions {
+    // Returns the entry points of the loaded EGL library.
+    pub(crate) fn system() -> DisplayFunctions {
+        unsafe fn get_platform_display(
+            platform: EGLenum,
+            native_display: *mut c_void,
+            attributes: *const EGLAttrib,
+        ) -> EGLDisplay {
+            EGL_FUNCTIONS.with(|egl| egl.GetPlatformDisplay(platform, native_display, attributes))
+        }
+        unsafe fn get_platform_display_ext(
+            platform: EGLenum,
+            native_display: *mut c_void,
+            attributes: *const EGLint,
+        ) -> EGLDisplay {
+            let get_platform_display_ext = EGL_EXTENSION_FUNCTIONS.GetPlatformDisplayEXT.unwrap();
+            get_platform_display_ext(platform, native_display, attributes)
+        }
+        unsafe fn get_display(native_display: *mut c_void) -> EGLDisplay {
+            EGL_FUNCTIONS.with(|egl| egl.GetDisplay(native_display as EGLNativeDisplayType))
+        }
+        unsafe fn get_error() -> EGLint {
+            EGL_FUNCTIONS.with(|egl| egl.GetError())
+        }
+
+        // libglvnd exports `eglGetProcAddress` stubs for any name, so only trust the EXT entry
+        // point if the client extension is advertised.
+        let has_ext = EGL_EXTENSION_FUNCTIONS.GetPlatformDisplayEXT.is_some()
+            && unsafe { client_supports_extension("EGL_EXT_platform_base") };
+        DisplayFunctions {
+            get_platform_display: if EGL_FUNCTIONS.with(|egl| egl.GetPlatformDisplay.is_loaded()) {
+                Some(get_platform_display)
+            } else {
+                None
+            },
+            get_platform_display_ext: if has_ext {
+                Some(get_platform_display_ext)
+            } else {
+                None
+            },
+            get_display: Some(get_display),
+            get_error,
+        }
+    }
+}
+
+// Creates the EGL display for a native display of the given platform, trying core
+// `eglGetPlatformDisplay`, then `eglGetPlatformDisplayEXT`, then `eglGetDisplay`.
+//
+// Older drivers, such as the Mesa of Ubuntu 18.04, only implement the EXT entry point; libglvnd
+// still exports the core one, which then fails with `EGL_BAD_PARAMETER`. Returns the display and
+// the entry point that created it, or `Error::PlatformDisplayFailed` with every entry point's
+// error.
+pub(crate) unsafe fn get_platform_display(
+    functions: &DisplayFunctions,
+    platform: EGLenum,
+    native_display: *mut c_void,
+) -> Result<(EGLDisplay, DisplayEntryPoint), Error> {
+    let last_error = || Some((functions.get_error)().to_windowing_api_error());
+
+    let get_platform_display_error = match functions.get_platform_display {
+        None => None,
+        Some(get_platform_display) => {
+            let attributes = [egl::NONE as EGLAttrib];
+            let egl_display = get_platform_display(platform, native_display, attributes.as_ptr());
+            if egl_display != egl::NO_DISPLAY {
+                return Ok((egl_display, DisplayEntryPoint::GetPlatformDisplay));
+            }
+            last_error()
+        }
+    };
+
+    let get_platform_display_ext_error = match functions.get_platform_display_ext {
+        None => None,
+        Some(get_platform_display_ext) => {
+            let attributes = [egl::NONE as EGLint];
+            let egl_display =
+                get_platform_display_ext(platform, native_display, attributes.as_ptr());
+            if egl_display != egl::NO_DISPLAY {
+                info!(
+                    "Created the EGL display with eglGetPlatformDisplayEXT after \
+                     eglGetPlatformDisplay failed: {:?}",
+                    get_platform_display_error
+                );
+                return Ok((egl_display, DisplayEntryPoint::GetPlatformDisplayEXT));
+            }
+            last_error()
+        }
+    };
+
+    let get_display_error = match functions.get_display {
+        None => None,
+        Some(get_display) => {
+            let egl_display = get_display(native_display);
+            if egl_display != egl::NO_DISPLAY {
+                info!(
+                    "Created the EGL display with eglGetDisplay after eglGetPlatformDisplay \
+                     failed ({:?}) and eglGetPlatformDisplayEXT failed ({:?})",
+                    get_platform_display_error, get_platform_display_ext_error
+                );
+                return Ok((egl_display, DisplayEntryPoint::GetDisplay));
+            }
+            last_error()
+        }
+    };
+
+    Err(Error::PlatformDisplayFailed {
+        get_platform_display: get_platform_display_error,
+        get_platform_display_ext: get_platform_display_ext_error,
+        get_display: get_display_error,
+    })
+}
+
+// Returns true if the given display advertises the named extension.
+pub(crate) unsafe fn display_supports_extension(egl_display: EGLDisplay, name: &str) -> bool {
+    display_string_contains(egl_display, egl::EXTENSIONS, name)
+}
+
+// Returns true if the EGL implementation advertises the named client extension, which doesn't
+// depend on any display.
+pub(crate) unsafe fn client_supports_extension(name: &str) -> bool {
+    display_string_contains(egl::NO_DISPLAY, egl::EXTENSIONS, name)
+}
+
+// Returns true if the given display can create contexts for the given API.
+pub(crate) unsafe fn display_supports_api(egl_display: EGLDisplay, gl_api: GLApi) -> bool {
+    let name = match gl_api {
+        GLApi::GL => "OpenGL",
+        GLApi::GLES => "OpenGL_ES",
+    };
+    display_string_contains(egl_display, egl::CLIENT_APIS, name)
+}
+
+// Returns the vendor and renderer names of the device backing the given display, if the EGL
+// implementation supports `EGL_EXT_device_query`.
+pub(crate) unsafe fn display_device_name(egl_display: EGLDisplay) -> Option<String> {
+    let query_device_string = EGL_EXTENSION_FUNCTIONS.QueryDeviceStringEXT?;
+    let device = display_device(egl_display)?;
+
+    // `EGL_VENDOR` and `EGL_RENDERER_EXT` come from `EGL_EXT_device_query_name`. Without it,
+    // fall back to the DRM device node, which at least distinguishes multiple GPUs.
+    let names: Vec<String> = [egl::VENDOR, EGL_RENDERER_EXT, EGL_DRM_DEVICE_FILE_EXT]
+        .iter()
+        .filter_map(|&name| {
+            let value = query_device_string(device, name as EGLint);
+            if value.is_null() {
+                None
+            } else {
+                Some(CStr::from_ptr(value).to_string_lossy().into_owned())
+            }
+        })
+        .collect();
+    if names.is_empty() {
+        None
+    } else {
+        Some(names.join(" "))
+    }
+}
+
+// Returns the identity of the device backing the given display, from `EGL_EXT_device_drm` and
+// `EGL_EXT_device_drm_render_node`.
+//
+// Mesa's software device reports `EGL_MESA_device_software` instead of a DRM node.
+#[cfg(not(target_os = "windows"))]
+pub(crate) unsafe fn display_identity(egl_display: EGLDisplay) -> DeviceIdentity {
+    let query_device_string = match EGL_EXTENSION_FUNCTIONS.QueryDeviceStringEXT {
+        Some(query_device_string) => query_device_string,
+        None => return DeviceIdentity::default(),
+    };
+    let device = match display_device(egl_display) {
+        Some(device) => device,
+        None => return DeviceIdentity::default(),
+    };
+    let device_string = |name: EGLenum| {
+        let value = query_device_string(device, name as EGLint);
+        if value.is_null() {
+            None
+        } else {
+            Some(CStr::from_ptr(value).to_string_lossy().into_owned())
+        }
+    };
+
+    let extensions = device_string(egl::EXTENSIONS).unwrap_or_default();
+    if extensions
+        .split_whitespace()
+        .any(|extension| extension == "EGL_MESA_device_software")
+    {
+        return DeviceIdentity::software();
+    }
+    [EGL_DRM_DEVICE_FILE_EXT, EGL_DRM_RENDER_NODE_FILE_EXT]
+        .iter()
+        .filter_map(|&name| device_string(name))
+        .find_map(DeviceIdentity::from_drm_node)
+        .unwrap_or_default()
+}
+
+// Returns the device backing the given display, if the EGL implementation supports
+// `EGL_EXT_device_query`.
+unsafe fn display_device(egl_display: EGLDisplay) -> Option<EGLDeviceEXT> {
+    let query_display_attrib = EGL_EXTENSION_FUNCTIONS.QueryDisplayAttribEXT?;
+
+    let mut device: EGLAttrib = 0;
+    if query_display_attrib(egl_display, EGL_DEVICE_EXT as EGLint, &mut device) == egl::FALSE {
+        return None;
+    }
+    let device = device as EGLDeviceEXT;
+    if device == EGL_NO_DEVICE_EXT {
+        return None;
+    }
+    Some(device)
+}
+
+// Returns the extensions of the display, followed by the client extensions that don't depend on a
+// display.
+pub(crate) unsafe fn display_extensions(egl_display: EGLDisplay) -> Vec<String> {
+    EGL_FUNCTIONS.with(|egl| {
+        let mut extensions = vec![];
+        for display in [egl_display, egl::NO_DISPLAY] {
+            let value = egl.QueryString(display, egl::EXTENSIONS as EGLint);
+            if value.is_null() {
+                continue;
+            }
+            extensions.extend(
+                CStr::from_ptr(value)
+                    .to_string_lossy()
+                    .split_whitespace()
+                    .map(|extension| extension.to_owned()),
+            );
+        }
+        extensions
+    })
+}
+
+unsafe fn display_string_contains(
+    egl_display: EGLDisplay,
+    string: egl::types::EGLenum,
+    name: &str,
+) -> bool {
+    EGL_FUNCTIONS.with(|egl| {
+        let value = egl.QueryString(egl_display, string as EGLint);
+        if value.is_null() {
+            return false;
+        }
+        CStr::from_ptr(value)
+            .to_string_lossy()
+            .split_whitespace()
+            .any(|word| word == name)
+    })
+}
+
 pub type EGLProcAddressRaw =
     unsafe extern "system" fn(*const c_char) -> Option<unsafe extern "system" fn()>;
 /// Get the EGLProcAddress c function
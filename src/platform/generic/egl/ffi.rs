@@ -5,9 +5,9 @@
 #![allow(dead_code)]
 
 use crate::egl::types::{EGLAttrib, EGLBoolean, EGLContext, EGLDeviceEXT, EGLDisplay, EGLSurface};
-use crate::egl::types::{EGLenum, EGLint};
+use crate::egl::types::{EGLSync, EGLenum, EGLint};
 
-use std::os::raw::c_void;
+use std::os::raw::{c_char, c_int, c_void};
 
 pub enum EGLClientBufferOpaque {}
 pub type EGLClientBuffer = *mut EGLClientBufferOpaque;
@@ -15,28 +15,84 @@ pub type EGLClientBuffer = *mut EGLClientBufferOpaque;
 pub enum EGLImageKHROpaque {}
 pub type EGLImageKHR = *mut EGLImageKHROpaque;
 
+pub type EGLnsecsANDROID = i64;
+pub type EGLuint64KHR = u64;
+
 pub const EGL_GL_TEXTURE_2D_KHR: EGLenum = 0x30b1;
 pub const EGL_IMAGE_PRESERVED_KHR: EGLenum = 0x30d2;
 pub const EGL_CONTEXT_MINOR_VERSION_KHR: EGLenum = 0x30fb;
+pub const EGL_CONTEXT_FLAGS_KHR: EGLenum = 0x30fc;
 pub const EGL_CONTEXT_OPENGL_PROFILE_MASK: EGLenum = 0x30fd;
+pub const EGL_BUFFER_AGE_EXT: EGLenum = 0x313d;
 pub const EGL_PLATFORM_DEVICE_EXT: EGLenum = 0x313f;
 pub const EGL_NATIVE_BUFFER_ANDROID: EGLenum = 0x3140;
+pub const EGL_SYNC_NATIVE_FENCE_ANDROID: EGLenum = 0x3144;
+pub const EGL_SYNC_NATIVE_FENCE_FD_ANDROID: EGLenum = 0x3145;
 pub const EGL_PLATFORM_X11_KHR: EGLenum = 0x31d5;
 pub const EGL_PLATFORM_WAYLAND_KHR: EGLenum = 0x31d8;
 pub const EGL_PLATFORM_SURFACELESS_MESA: EGLenum = 0x31dd;
+pub const EGL_TIMESTAMPS_ANDROID: EGLenum = 0x3430;
+pub const EGL_REQUESTED_PRESENT_TIME_ANDROID: EGLenum = 0x3434;
+pub const EGL_RENDERING_COMPLETE_TIME_ANDROID: EGLenum = 0x3435;
+pub const EGL_DISPLAY_PRESENT_TIME_ANDROID: EGLenum = 0x343a;
 pub const EGL_D3D_TEXTURE_2D_SHARE_HANDLE_ANGLE: EGLenum = 0x3200;
 pub const EGL_BAD_DEVICE_EXT: EGLenum = 0x322b;
 pub const EGL_DEVICE_EXT: EGLenum = 0x322c;
+pub const EGL_DRM_DEVICE_FILE_EXT: EGLenum = 0x3233;
+pub const EGL_LINUX_DMA_BUF_EXT: EGLenum = 0x3270;
+pub const EGL_LINUX_DRM_FOURCC_EXT: EGLenum = 0x3271;
+pub const EGL_DMA_BUF_PLANE0_FD_EXT: EGLenum = 0x3272;
+pub const EGL_DMA_BUF_PLANE0_OFFSET_EXT: EGLenum = 0x3273;
+pub const EGL_DMA_BUF_PLANE0_PITCH_EXT: EGLenum = 0x3274;
+pub const EGL_COLOR_COMPONENT_TYPE_EXT: EGLenum = 0x3339;
+pub const EGL_COLOR_COMPONENT_TYPE_FLOAT_EXT: EGLenum = 0x333b;
+pub const EGL_RENDERER_EXT: EGLenum = 0x335f;
+pub const EGL_DRM_RENDER_NODE_FILE_EXT: EGLenum = 0x3377;
 pub const EGL_D3D11_DEVICE_ANGLE: EGLenum = 0x33a1;
 pub const EGL_DXGI_KEYED_MUTEX_ANGLE: EGLenum = 0x33a2;
 pub const EGL_D3D_TEXTURE_ANGLE: EGLenum = 0x33a3;
+pub const EGL_SURFACE_ORIENTATION_ANGLE: EGLenum = 0x33a8;
+pub const EGL_DMA_BUF_PLANE0_MODIFIER_LO_EXT: EGLenum = 0x3443;
+pub const EGL_DMA_BUF_PLANE0_MODIFIER_HI_EXT: EGLenum = 0x3444;
 
 pub const EGL_NO_DEVICE_EXT: EGLDeviceEXT = 0 as EGLDeviceEXT;
 pub const EGL_NO_IMAGE_KHR: EGLImageKHR = 0 as EGLImageKHR;
 
+pub const EGL_MUTABLE_RENDER_BUFFER_BIT_KHR: EGLint = 0x1000;
+
+pub const EGL_NO_NATIVE_FENCE_FD_ANDROID: EGLint = -1;
+
+pub const EGL_TIMESTAMP_PENDING_ANDROID: EGLnsecsANDROID = -2;
+pub const EGL_TIMESTAMP_INVALID_ANDROID: EGLnsecsANDROID = -1;
+
+pub const EGL_SURFACE_ORIENTATION_INVERT_X_ANGLE: EGLint = 1;
+pub const EGL_SURFACE_ORIENTATION_INVERT_Y_ANGLE: EGLint = 2;
+
 pub const EGL_CONTEXT_OPENGL_CORE_PROFILE_BIT: EGLint = 1;
 pub const EGL_CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT: EGLint = 2;
 
+pub const EGL_CONTEXT_OPENGL_DEBUG_BIT_KHR: EGLint = 1;
+
+pub const EGL_CONTEXT_OPENGL_ROBUST_ACCESS_BIT_KHR: EGLint = 4;
+pub const EGL_CONTEXT_OPENGL_RESET_NOTIFICATION_STRATEGY_KHR: EGLenum = 0x31bd;
+
+pub const EGL_CONTEXT_OPENGL_ROBUST_ACCESS_EXT: EGLenum = 0x30bf;
+pub const EGL_CONTEXT_OPENGL_RESET_NOTIFICATION_STRATEGY_EXT: EGLenum = 0x3138;
+
+// `EGL_KHR_create_context` and `EGL_EXT_create_context_robustness` share this value.
+pub const EGL_LOSE_CONTEXT_ON_RESET: EGLint = 0x31bf;
+
+pub const EGL_CONTEXT_OPENGL_NO_ERROR_KHR: EGLenum = 0x31b3;
+
+pub const EGL_CONTEXT_RELEASE_BEHAVIOR_KHR: EGLenum = 0x2097;
+pub const EGL_CONTEXT_RELEASE_BEHAVIOR_NONE_KHR: EGLint = 0;
+
+pub const EGL_CONTEXT_PRIORITY_LEVEL_IMG: EGLenum = 0x3100;
+pub const EGL_CONTEXT_PRIORITY_HIGH_IMG: EGLint = 0x3101;
+pub const EGL_CONTEXT_PRIORITY_MEDIUM_IMG: EGLint = 0x3102;
+pub const EGL_CONTEXT_PRIORITY_LOW_IMG: EGLint = 0x3103;
+pub const EGL_CONTEXT_PRIORITY_REALTIME_NV: EGLint = 0x3357;
+
 #[allow(non_snake_case)]
 pub(crate) struct EGLExtensionFunctions {
     // Ubiquitous extensions assumed to be present
@@ -58,11 +114,67 @@ pub(crate) struct EGLExtensionFunctions {
             attrib_list: *const EGLAttrib,
         ) -> EGLDeviceEXT,
     >,
+    pub(crate) DupNativeFenceFDANDROID:
+        Option<extern "C" fn(dpy: EGLDisplay, sync: EGLSync) -> EGLint>,
+    pub(crate) GetFrameTimestampsANDROID: Option<
+        extern "C" fn(
+            dpy: EGLDisplay,
+            surface: EGLSurface,
+            frame_id: EGLuint64KHR,
+            num_timestamps: EGLint,
+            timestamps: *const EGLint,
+            values: *mut EGLnsecsANDROID,
+        ) -> EGLBoolean,
+    >,
+    pub(crate) GetFrameTimestampSupportedANDROID: Option<
+        extern "C" fn(dpy: EGLDisplay, surface: EGLSurface, timestamp: EGLint) -> EGLBoolean,
+    >,
+    pub(crate) ExportDMABUFImageMESA: Option<
+        extern "C" fn(
+            dpy: EGLDisplay,
+            image: EGLImageKHR,
+            fds: *mut c_int,
+            strides: *mut EGLint,
+            offsets: *mut EGLint,
+        ) -> EGLBoolean,
+    >,
+    pub(crate) ExportDMABUFImageQueryMESA: Option<
+        extern "C" fn(
+            dpy: EGLDisplay,
+            image: EGLImageKHR,
+            fourcc: *mut c_int,
+            num_planes: *mut c_int,
+            modifiers: *mut u64,
+        ) -> EGLBoolean,
+    >,
     pub(crate) GetNativeClientBufferANDROID:
         Option<extern "C" fn(buffer: *const c_void) -> EGLClientBuffer>,
+    pub(crate) GetNextFrameIdANDROID: Option<
+        extern "C" fn(
+            dpy: EGLDisplay,
+            surface: EGLSurface,
+            frame_id: *mut EGLuint64KHR,
+        ) -> EGLBoolean,
+    >,
+    pub(crate) GetPlatformDisplayEXT: Option<
+        extern "C" fn(
+            platform: EGLenum,
+            native_display: *mut c_void,
+            attrib_list: *const EGLint,
+        ) -> EGLDisplay,
+    >,
     pub(crate) QueryDeviceAttribEXT: Option<
         extern "C" fn(device: EGLDeviceEXT, attribute: EGLint, value: *mut EGLAttrib) -> EGLBoolean,
     >,
+    pub(crate) QueryDevicesEXT: Option<
+        extern "C" fn(
+            max_devices: EGLint,
+            devices: *mut EGLDeviceEXT,
+            num_devices: *mut EGLint,
+        ) -> EGLBoolean,
+    >,
+    pub(crate) QueryDeviceStringEXT:
+        Option<extern "C" fn(device: EGLDeviceEXT, name: EGLint) -> *const c_char>,
     pub(crate) QueryDisplayAttribEXT: Option<
         extern "C" fn(dpy: EGLDisplay, attribute: EGLint, value: *mut EGLAttrib) -> EGLBoolean,
     >,
@@ -74,6 +186,22 @@ pub(crate) struct EGLExtensionFunctions {
             value: *mut *mut c_void,
         ) -> EGLBoolean,
     >,
+    pub(crate) SwapBuffersWithDamageEXT: Option<
+        extern "C" fn(
+            dpy: EGLDisplay,
+            surface: EGLSurface,
+            rects: *const EGLint,
+            n_rects: EGLint,
+        ) -> EGLBoolean,
+    >,
+    pub(crate) SwapBuffersWithDamageKHR: Option<
+        extern "C" fn(
+            dpy: EGLDisplay,
+            surface: EGLSurface,
+            rects: *const EGLint,
+            n_rects: EGLint,
+        ) -> EGLBoolean,
+    >,
 }
 
 lazy_static! {
@@ -87,10 +215,23 @@ lazy_static! {
                 ImageTargetTexture2DOES: cast(get(b"glEGLImageTargetTexture2DOES\0")),
 
                 CreateDeviceANGLE: cast(get(b"eglCreateDeviceANGLE\0")),
+                DupNativeFenceFDANDROID: cast(get(b"eglDupNativeFenceFDANDROID\0")),
+                GetFrameTimestampsANDROID: cast(get(b"eglGetFrameTimestampsANDROID\0")),
+                GetFrameTimestampSupportedANDROID: cast(get(
+                    b"eglGetFrameTimestampSupportedANDROID\0",
+                )),
+                ExportDMABUFImageMESA: cast(get(b"eglExportDMABUFImageMESA\0")),
+                ExportDMABUFImageQueryMESA: cast(get(b"eglExportDMABUFImageQueryMESA\0")),
                 GetNativeClientBufferANDROID: cast(get(b"eglGetNativeClientBufferANDROID\0")),
+                GetNextFrameIdANDROID: cast(get(b"eglGetNextFrameIdANDROID\0")),
+                GetPlatformDisplayEXT: cast(get(b"eglGetPlatformDisplayEXT\0")),
                 QueryDeviceAttribEXT: cast(get(b"eglQueryDeviceAttribEXT\0")),
+                QueryDevicesEXT: cast(get(b"eglQueryDevicesEXT\0")),
+                QueryDeviceStringEXT: cast(get(b"eglQueryDeviceStringEXT\0")),
                 QueryDisplayAttribEXT: cast(get(b"eglQueryDisplayAttribEXT\0")),
                 QuerySurfacePointerANGLE: cast(get(b"eglQuerySurfacePointerANGLE\0")),
+                SwapBuffersWithDamageEXT: cast(get(b"eglSwapBuffersWithDamageEXT\0")),
+                SwapBuffersWithDamageKHR: cast(get(b"eglSwapBuffersWithDamageKHR\0")),
             }
         }
     };
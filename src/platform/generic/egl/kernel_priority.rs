@@ -0,0 +1,326 @@
+// surfman/surfman/src/platform/generic/egl/kernel_priority.rs
+//
+//! Lowering the kernel scheduling priority of contexts whose EGL driver can't do it itself.
+//!
+//! The GL driver creates the kernel contexts behind a GL context on its own DRM file descriptor,
+//! which surfman doesn't open. Since that file descriptor belongs to this process, the kernel
+//! contexts are found by listing the contexts on each of the process's DRM file descriptors
+//! before and after the GL context is created, and lowered with the i915 or amdgpu ioctls.
+//! Kernel contexts that the driver creates for another GL context at the same time may be
+//! lowered too.
+
+use crate::egl::types::{EGLContext, EGLDisplay};
+
+use log::debug;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::raw::{c_char, c_int};
+use std::os::unix::io::RawFd;
+use std::sync::{Mutex, MutexGuard};
+
+const IOC_WRITE: u32 = 1;
+const IOC_READ_WRITE: u32 = 3;
+const DRM_COMMAND_BASE: u32 = 0x40;
+
+// `_IOC()` from the Linux UAPI headers, for the DRM ioctl type.
+const fn drm_ioctl(direction: u32, number: u32, size: usize) -> u32 {
+    (direction << 30) | ((size as u32) << 16) | ((b'd' as u32) << 8) | number
+}
+
+// `struct drm_version`.
+#[repr(C)]
+struct DrmVersion {
+    version_major: c_int,
+    version_minor: c_int,
+    version_patchlevel: c_int,
+    name_len: usize,
+    name: *mut c_char,
+    date_len: usize,
+    date: *mut c_char,
+    desc_len: usize,
+    desc: *mut c_char,
+}
+
+// `struct drm_i915_gem_context_param`.
+#[repr(C)]
+struct I915ContextParam {
+    ctx_id: u32,
+    size: u32,
+    param: u64,
+    value: u64,
+}
+
+// The input of `union drm_amdgpu_ctx`. Its output is the same size.
+#[repr(C)]
+struct AmdgpuCtx {
+    op: u32,
+    flags: u32,
+    ctx_id: u32,
+    priority: i32,
+}
+
+// `struct drm_amdgpu_sched_in`.
+#[repr(C)]
+struct AmdgpuSched {
+    op: u32,
+    fd: u32,
+    priority: i32,
+    ctx_id: u32,
+}
+
+pub(crate) const DRM_IOCTL_VERSION: u32 =
+    drm_ioctl(IOC_READ_WRITE, 0x00, mem::size_of::<DrmVersion>());
+pub(crate) const DRM_IOCTL_I915_GEM_CONTEXT_GETPARAM: u32 = drm_ioctl(
+    IOC_READ_WRITE,
+    DRM_COMMAND_BASE + 0x34,
+    mem::size_of::<I915ContextParam>(),
+);
+pub(crate) const DRM_IOCTL_I915_GEM_CONTEXT_SETPARAM: u32 = drm_ioctl(
+    IOC_READ_WRITE,
+    DRM_COMMAND_BASE + 0x35,
+    mem::size_of::<I915ContextParam>(),
+);
+pub(crate) const DRM_IOCTL_AMDGPU_CTX: u32 = drm_ioctl(
+    IOC_READ_WRITE,
+    DRM_COMMAND_BASE + 0x02,
+    mem::size_of::<AmdgpuCtx>(),
+);
+pub(crate) const DRM_IOCTL_AMDGPU_SCHED: u32 = drm_ioctl(
+    IOC_WRITE,
+    DRM_COMMAND_BASE + 0x15,
+    mem::size_of::<AmdgpuSched>(),
+);
+
+const I915_CONTEXT_PARAM_PRIORITY: u64 = 0x6;
+const I915_CONTEXT_MIN_USER_PRIORITY: i64 = -1023;
+const AMDGPU_CTX_OP_QUERY_STATE2: u32 = 4;
+const AMDGPU_SCHED_OP_CONTEXT_PRIORITY_OVERRIDE: u32 = 2;
+const AMDGPU_CTX_PRIORITY_LOW: i32 = -512;
+
+// Kernel context IDs are handed out lowest first, so listing stops after this many missing IDs
+// in a row.
+const MAX_CONTEXT_ID_GAP: u32 = 16;
+const MAX_CONTEXT_ID: u32 = 4096;
+
+lazy_static! {
+    // The GL contexts whose kernel contexts were lowered, keyed by EGL display and context.
+    static ref LOWERED_CONTEXTS: Mutex<HashSet<(usize, usize)>> =
+        Mutex::new(HashSet::new());
+    // Whether `supported()` found lowerable contexts, keyed by the EGL display it was asked for.
+    // Swap chains ask on every frame, so the file descriptors are only listed once per display.
+    static ref SUPPORTED: Mutex<HashMap<usize, bool>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Driver {
+    I915,
+    Amdgpu,
+}
+
+// A DRM file descriptor of the process, and the kernel contexts on it.
+struct DrmFile {
+    fd: RawFd,
+    driver: Driver,
+    contexts: Vec<u32>,
+}
+
+// The kernel contexts on each of the process's DRM file descriptors whose driver can lower them,
+// from before a GL context is created.
+pub(crate) struct KernelContexts(Vec<DrmFile>);
+
+impl KernelContexts {
+    pub(crate) fn list() -> KernelContexts {
+        KernelContexts(
+            drm_files()
+                .into_iter()
+                .map(|(fd, driver)| DrmFile {
+                    fd,
+                    driver,
+                    contexts: list_contexts(fd, driver),
+                })
+                .collect(),
+        )
+    }
+
+    // Lowers the priority of the kernel contexts created since the list was made, on behalf of
+    // `egl_context`. Returns whether any were lowered.
+    pub(crate) fn lower_new_contexts(
+        self,
+        egl_display: EGLDisplay,
+        egl_context: EGLContext,
+    ) -> bool {
+        let mut lowered = false;
+        for file in self.0 {
+            for id in list_contexts(file.fd, file.driver) {
+                if file.contexts.contains(&id) {
+                    continue;
+                }
+                match lower_context(file.fd, file.driver, id) {
+                    Ok(()) => lowered = true,
+                    Err(err) => debug!(
+                        "Failed to lower the priority of {:?} context {}: {}",
+                        file.driver, id, err
+                    ),
+                }
+            }
+        }
+        if lowered {
+            lowered_contexts().insert((egl_display as usize, egl_context as usize));
+        }
+        lowered
+    }
+}
+
+// Whether the process has a DRM file descriptor whose kernel contexts can be lowered, as of when
+// this was first asked for `egl_display`. Lowering i915 contexts is always allowed, but amdgpu
+// only lets the DRM master override priorities.
+pub(crate) fn supported(egl_display: EGLDisplay) -> bool {
+    *SUPPORTED
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .entry(egl_display as usize)
+        .or_insert_with(any_lowerable_file)
+}
+
+fn any_lowerable_file() -> bool {
+    drm_files().into_iter().any(|(fd, driver)| match driver {
+        Driver::I915 => true,
+        // Context 0 never exists, so this fails either way, but only for a missing context once
+        // the permission check has passed.
+        Driver::Amdgpu => match lower_context(fd, driver, 0) {
+            Err(err) => !matches!(err.raw_os_error(), Some(libc::EACCES) | Some(libc::EPERM)),
+            Ok(()) => true,
+        },
+    })
+}
+
+// Whether the kernel contexts of `egl_context` were lowered.
+pub(crate) fn is_lowered(egl_display: EGLDisplay, egl_context: EGLContext) -> bool {
+    lowered_contexts().contains(&(egl_display as usize, egl_context as usize))
+}
+
+// Forgets a destroyed GL context, whose handle may be reused.
+pub(crate) fn forget(egl_display: EGLDisplay, egl_context: EGLContext) {
+    lowered_contexts().remove(&(egl_display as usize, egl_context as usize));
+}
+
+fn lowered_contexts() -> MutexGuard<'static, HashSet<(usize, usize)>> {
+    LOWERED_CONTEXTS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+}
+
+// The process's DRM file descriptors, with drivers that surfman can lower contexts of.
+fn drm_files() -> Vec<(RawFd, Driver)> {
+    let entries = match fs::read_dir("/proc/self/fd") {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            if !fs::read_link(entry.path()).ok()?.starts_with("/dev/dri/") {
+                return None;
+            }
+            let fd = entry.file_name().to_str()?.parse().ok()?;
+            Some((fd, driver(fd)?))
+        })
+        .collect()
+}
+
+fn driver(fd: RawFd) -> Option<Driver> {
+    let mut name = [0u8; 16];
+    unsafe {
+        let mut version: DrmVersion = mem::zeroed();
+        version.name_len = name.len();
+        version.name = name.as_mut_ptr() as *mut c_char;
+        ioctl(fd, DRM_IOCTL_VERSION, &mut version).ok()?;
+        match &name[..version.name_len.min(name.len())] {
+            b"i915" => Some(Driver::I915),
+            b"amdgpu" => Some(Driver::Amdgpu),
+            _ => None,
+        }
+    }
+}
+
+fn list_contexts(fd: RawFd, driver: Driver) -> Vec<u32> {
+    let mut contexts = vec![];
+    let mut gap = 0;
+    for id in 1..=MAX_CONTEXT_ID {
+        if context_exists(fd, driver, id) {
+            contexts.push(id);
+            gap = 0;
+        } else {
+            gap += 1;
+            if gap == MAX_CONTEXT_ID_GAP {
+                break;
+            }
+        }
+    }
+    contexts
+}
+
+fn context_exists(fd: RawFd, driver: Driver, id: u32) -> bool {
+    unsafe {
+        match driver {
+            Driver::I915 => {
+                let mut param = I915ContextParam {
+                    ctx_id: id,
+                    size: 0,
+                    param: I915_CONTEXT_PARAM_PRIORITY,
+                    value: 0,
+                };
+                ioctl(fd, DRM_IOCTL_I915_GEM_CONTEXT_GETPARAM, &mut param).is_ok()
+            }
+            Driver::Amdgpu => {
+                let mut ctx = AmdgpuCtx {
+                    op: AMDGPU_CTX_OP_QUERY_STATE2,
+                    flags: 0,
+                    ctx_id: id,
+                    priority: 0,
+                };
+                ioctl(fd, DRM_IOCTL_AMDGPU_CTX, &mut ctx).is_ok()
+            }
+        }
+    }
+}
+
+fn lower_context(fd: RawFd, driver: Driver, id: u32) -> io::Result<()> {
+    unsafe {
+        match driver {
+            Driver::I915 => {
+                let mut param = I915ContextParam {
+                    ctx_id: id,
+                    size: 0,
+                    param: I915_CONTEXT_PARAM_PRIORITY,
+                    value: I915_CONTEXT_MIN_USER_PRIORITY as u64,
+                };
+                ioctl(fd, DRM_IOCTL_I915_GEM_CONTEXT_SETPARAM, &mut param)
+            }
+            Driver::Amdgpu => {
+                let mut sched = AmdgpuSched {
+                    op: AMDGPU_SCHED_OP_CONTEXT_PRIORITY_OVERRIDE,
+                    fd: fd as u32,
+                    priority: AMDGPU_CTX_PRIORITY_LOW,
+                    ctx_id: id,
+                };
+                ioctl(fd, DRM_IOCTL_AMDGPU_SCHED, &mut sched)
+            }
+        }
+    }
+}
+
+// `drmIoctl()`, which retries interrupted calls.
+unsafe fn ioctl<T>(fd: RawFd, request: u32, arg: &mut T) -> io::Result<()> {
+    loop {
+        if libc::ioctl(fd, request as _, arg as *mut T) == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if !matches!(err.raw_os_error(), Some(libc::EINTR) | Some(libc::EAGAIN)) {
+            return Err(err);
+        }
+    }
+}
@@ -8,4 +8,6 @@ pub(crate) mod context;
 pub(crate) mod device;
 pub(crate) mod error;
 pub(crate) mod ffi;
+#[cfg(all(free_unix, target_os = "linux"))]
+pub(crate) mod kernel_priority;
 pub(crate) mod surface;
@@ -2,30 +2,70 @@
 //
 //! Functionality common to backends using EGL surfaces.
 
-use super::context::CurrentContextGuard;
-use super::device::EGL_FUNCTIONS;
-use super::ffi::EGL_GL_TEXTURE_2D_KHR;
+use super::context::{self, CurrentContextGuard};
+use super::device::{self, EGL_FUNCTIONS};
+use super::ffi::{EGL_BUFFER_AGE_EXT, EGL_GL_TEXTURE_2D_KHR};
+use crate::clear::ClearRegion;
+use crate::color::ColorProfileTracker;
 use crate::egl;
 use crate::egl::types::{EGLAttrib, EGLConfig, EGLContext, EGLDisplay, EGLSurface, EGLint};
+use crate::egl::types::{EGLBoolean, EGLSync, EGLTime, EGLenum};
 use crate::gl;
-use crate::gl::types::{GLint, GLuint};
+use crate::gl::types::{GLenum, GLint, GLuint};
 use crate::gl_utils;
+use crate::invalidate::PostPresentInvalidation;
+use crate::leak_check::{self, TrackedObject};
+use crate::memory::{self, PurgeState, ResidentBacking};
+use crate::pause::{HeldFrame, PresentPause};
 use crate::platform::generic::egl::error::ToWindowingApiError;
 use crate::platform::generic::egl::ffi::EGLClientBuffer;
 use crate::platform::generic::egl::ffi::EGLImageKHR;
 use crate::platform::generic::egl::ffi::EGL_EXTENSION_FUNCTIONS;
 use crate::platform::generic::egl::ffi::EGL_IMAGE_PRESERVED_KHR;
 use crate::platform::generic::egl::ffi::EGL_NO_IMAGE_KHR;
-use crate::renderbuffers::Renderbuffers;
+use crate::platform::generic::egl::ffi::EGL_NO_NATIVE_FENCE_FD_ANDROID;
+use crate::platform::generic::egl::ffi::{EGLnsecsANDROID, EGLuint64KHR};
+use crate::platform::generic::egl::ffi::{
+    EGL_DISPLAY_PRESENT_TIME_ANDROID, EGL_RENDERING_COMPLETE_TIME_ANDROID,
+    EGL_REQUESTED_PRESENT_TIME_ANDROID, EGL_TIMESTAMPS_ANDROID, EGL_TIMESTAMP_INVALID_ANDROID,
+    EGL_TIMESTAMP_PENDING_ANDROID,
+};
+use crate::platform::generic::egl::ffi::{
+    EGL_SYNC_NATIVE_FENCE_ANDROID, EGL_SYNC_NATIVE_FENCE_FD_ANDROID,
+};
+use crate::presentation_time::{self, PresentationStatistics};
+use crate::renderbuffers::{Multisample, Renderbuffers};
+use crate::surface::PresentHistory;
+use crate::vsync::AutoVsync;
+use crate::BufferRelease;
+#[cfg(free_unix)]
+use crate::DmaBuf;
+use crate::ColorProfile;
+use crate::GLCapabilities;
 use crate::Gl;
-use crate::{ContextAttributes, ContextID, Error, SurfaceID, SurfaceInfo};
+use crate::InvalidateSet;
+use crate::PausedPresentation;
+use crate::PresentRecord;
+use crate::SurfaceID;
+use crate::SurfaceInfo;
+use crate::TextureOwnership;
+#[cfg(unix)]
+use crate::WindowingApiError;
+use crate::{AutoVsyncPolicy, PresentMode, SurfaceOptions};
+use crate::{ColorBits, ColorEncoding, ContextAttributes, ContextID, Error, SurfaceAccess};
+use crate::{NativeFormat, NativeFormatKind};
+use crate::{Purgeability, ReclaimResult};
 
-use euclid::default::Size2D;
+use euclid::default::{Rect, Size2D};
+use std::collections::VecDeque;
 use std::fmt::{self, Debug, Formatter};
 use std::marker::PhantomData;
 use std::mem;
+#[cfg(unix)]
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd};
 use std::os::raw::c_void;
 use std::ptr;
+use std::time::{Duration, Instant};
 
 #[allow(dead_code)]
 #[derive(Clone)]
@@ -37,13 +77,131 @@ pub(crate) struct ExternalEGLSurfaces {
 pub struct EGLBackedSurface {
     pub(crate) context_id: ContextID,
     pub(crate) size: Size2D<i32>,
+    pub(crate) access: SurfaceAccess,
+    pub(crate) format: GLenum,
+    pub(crate) colorspace: ColorEncoding,
+    pub(crate) color_bits: ColorBits,
     pub(crate) objects: EGLSurfaceObjects,
+    pub(crate) present_history: PresentHistory,
+    pub(crate) auto_vsync: Option<AutoVsync>,
+    // How the window system signals the release of each frame presented on a widget surface, if
+    // the backend knows.
+    pub(crate) release_signal: Option<Box<dyn ReleaseSignal>>,
+    // Otherwise, a fence inserted when the last frame was presented.
+    pub(crate) present_fence: Option<PresentFence>,
+    // What the window system reports about when frames presented on a widget surface were shown,
+    // if it reports anything.
+    pub(crate) presentation_feedback: Option<Box<dyn PresentationFeedback>>,
+    pub(crate) invalidation: PostPresentInvalidation,
+    pub(crate) buffer_age: BufferAge,
+    pub(crate) front_buffer: FrontBuffer,
+    // Whether the surface's contents survive presents.
+    pub(crate) preserves_contents: bool,
+    pub(crate) pause: PresentPause<HeldFrame>,
+    pub(crate) purge_state: PurgeState,
+    // The color profile of the output that a widget surface was last seen on.
+    pub(crate) color_profile: ColorProfileTracker,
+    // What the window system reports about the color profile of the output that a widget surface
+    // is on, if the backend asks it directly.
+    pub(crate) color_profile_source: Option<Box<dyn ColorProfileSource>>,
     pub(crate) destroyed: bool,
 }
 
+// Something that signals when the window system has released the buffer of the last frame
+// presented on a widget surface.
+pub(crate) trait ReleaseSignal {
+    // Called just before each frame is presented, with the surface's context current.
+    fn presenting(&mut self) -> Result<(), Error>;
+    // Waits for the release of the last frame presented. If no frame has been presented yet, this
+    // returns `Released`.
+    fn wait(&self, timeout: Duration) -> Result<BufferRelease, Error>;
+}
+
+// Something that reports when the frames presented on a widget surface were shown.
+pub(crate) trait PresentationFeedback {
+    // Called just before each frame is swapped, with the surface's context current, with the
+    // `frame_id` that the frame's `PresentRecord` is to have.
+    fn presenting(&mut self, frame_id: u64) -> Result<(), Error>;
+    // Fills in what has been reported about the frame that `statistics` is of.
+    fn update(&self, statistics: &mut PresentationStatistics);
+}
+
+// Something that reports the color profile of the output that a widget surface is on.
+pub(crate) trait ColorProfileSource {
+    // Returns the profile that the window system last described, or `None` if it hasn't
+    // described one.
+    fn color_profile(&self) -> Option<ColorProfile>;
+}
+
+// The timestamps of `EGL_ANDROID_get_frame_timestamps` that `PresentationStatistics` holds.
+const FRAME_TIMESTAMP_NAMES: [EGLenum; 3] = [
+    EGL_REQUESTED_PRESENT_TIME_ANDROID,
+    EGL_RENDERING_COMPLETE_TIME_ANDROID,
+    EGL_DISPLAY_PRESENT_TIME_ANDROID,
+];
+
+// How many frames back `FrameTimestamps` remembers. Android keeps the timestamps of about this
+// many.
+const FRAME_TIMESTAMPS_LENGTH: usize = 8;
+
+// The timestamps that `EGL_ANDROID_get_frame_timestamps` reports for the frames presented on a
+// window surface.
+pub(crate) struct FrameTimestamps {
+    egl_display: EGLDisplay,
+    egl_surface: EGLSurface,
+    // The names of the timestamps that the surface reports, of `FRAME_TIMESTAMP_NAMES`.
+    names: Vec<EGLint>,
+    // The `frame_id`s of the most recent frames, with their EGL frame IDs, oldest first.
+    frames: VecDeque<(u64, EGLuint64KHR)>,
+}
+
+// The age of the back buffer of a window surface, as `EGL_EXT_buffer_age` reports it.
+//
+// EGL only reports the age while the surface is current and before anything is drawn into the
+// back buffer, so it's queried right after each swap, which leaves the surface in that state.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct BufferAge {
+    supported: bool,
+    age: u32,
+}
+
+// Whether a window surface renders to its front buffer, for `PresentMode::SingleBuffer`.
+//
+// `EGL_KHR_mutable_render_buffer` only switches to the front buffer at the next swap, so surfaces
+// start out double-buffered, and move over with their first present.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct FrontBuffer {
+    requested: bool,
+    bound: bool,
+}
+
+// A fence inserted just before a widget surface was presented. It signals once the GPU has
+// finished rendering the frame, which is as much as EGL reveals about its buffers.
+pub(crate) struct PresentFence {
+    egl_display: EGLDisplay,
+    // `NO_SYNC` if the display doesn't support fences.
+    egl_sync: EGLSync,
+}
+
+// The fence of a `SurfaceFence`, inserted just after a surface was presented, or imported from a
+// sync file.
+#[derive(Debug)]
+pub(crate) struct EGLFence {
+    egl_display: EGLDisplay,
+    egl_sync: EGLSync,
+    // Whether this is an `EGL_SYNC_NATIVE_FENCE_ANDROID`, which can be exported as a sync file,
+    // rather than an `EGL_SYNC_FENCE`.
+    #[cfg_attr(not(unix), allow(dead_code))]
+    native: bool,
+}
+
+// EGL sync objects belong to their display, not to a context or thread.
+unsafe impl Send for EGLFence {}
+unsafe impl Sync for EGLFence {}
+
 impl Debug for EGLBackedSurface {
     fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
-        write!(f, "Surface({:x})", self.id().0)
+        write!(f, "Surface({:x}, {:?})", self.id().0, self.access)
     }
 }
 
@@ -55,7 +213,11 @@ pub(crate) enum EGLSurfaceObjects {
         egl_image: EGLImageKHR,
         framebuffer_object: GLuint,
         texture_object: GLuint,
+        texture_ownership: TextureOwnership,
+        // Whether surfman allocated the texture's storage, and so may reallocate it on resize.
+        allocated: bool,
         renderbuffers: Renderbuffers,
+        multisample: Option<Multisample>,
     },
     Window {
         native_window: *const c_void,
@@ -76,6 +238,9 @@ impl Debug for EGLSurfaceTexture {
 }
 
 impl EGLBackedSurface {
+    /// Creates a surface backed by a new texture with the sized internal format `format`, which
+    /// is `GL_RGBA8` unless the device is in low-memory mode or the surface is sRGB.
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new_generic(
         gl: &Gl,
         egl_display: EGLDisplay,
@@ -83,54 +248,91 @@ impl EGLBackedSurface {
         context_id: ContextID,
         context_attributes: &ContextAttributes,
         size: &Size2D<i32>,
+        access: SurfaceAccess,
+        format: GLenum,
     ) -> EGLBackedSurface {
         unsafe {
             // Create our texture.
             let mut texture_object = 0;
             gl.GenTextures(1, &mut texture_object);
-            // Save the current texture binding
-            let mut old_texture_object = 0;
-            gl.GetIntegerv(gl::TEXTURE_BINDING_2D, &mut old_texture_object);
-            gl.BindTexture(gl::TEXTURE_2D, texture_object);
-            // Unbind PIXEL_UNPACK_BUFFER, because if it is bound,
-            // it can cause errors in glTexImage2D.
-            // TODO: should this be inside a check for GL 2.0?
-            let mut unpack_buffer = 0;
-            gl.GetIntegerv(gl::PIXEL_UNPACK_BUFFER_BINDING, &mut unpack_buffer);
-            if unpack_buffer != 0 {
-                gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, 0);
-            }
-            gl.TexImage2D(
-                gl::TEXTURE_2D,
-                0,
-                gl::RGBA as GLint,
-                size.width,
-                size.height,
-                0,
-                gl::RGBA,
-                gl::UNSIGNED_BYTE,
-                ptr::null(),
-            );
-            // Restore the old bindings
-            gl.BindTexture(gl::TEXTURE_2D, old_texture_object as _);
-            if unpack_buffer != 0 {
-                gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, unpack_buffer as _);
-            }
+            allocate_texture_storage(gl, texture_object, format, size);
 
-            EGLBackedSurface::new_from_gl_texture(
+            EGLBackedSurface::new_texture_image(
                 gl,
                 egl_display,
                 egl_context,
                 context_id,
                 context_attributes,
                 size,
+                access,
                 texture_object,
                 EGL_GL_TEXTURE_2D_KHR,
+                TextureOwnership::Owned,
+                Some(format),
             )
         }
     }
 
+    /// Wraps a GL texture that the caller created in this context, or in a context that shares
+    /// with it.
+    ///
+    /// The texture must be an RGBA8 texture of the given size. Fails with `IncompatibleTexture`
+    /// if the texture isn't visible to this context, which is the usual symptom of a texture
+    /// from a context that doesn't share with this one.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new_from_foreign_texture(
+        gl: &Gl,
+        egl_display: EGLDisplay,
+        egl_context: EGLContext,
+        context_id: ContextID,
+        context_attributes: &ContextAttributes,
+        size: &Size2D<i32>,
+        access: SurfaceAccess,
+        texture_object: GLuint,
+        texture_format: GLenum,
+        ownership: TextureOwnership,
+    ) -> Result<EGLBackedSurface, Error> {
+        if texture_format != gl::RGBA8 && texture_format != gl::RGBA {
+            return Err(Error::UnsupportedTextureFormat);
+        }
+
+        unsafe {
+            if texture_object == 0 || gl.IsTexture(texture_object) == gl::FALSE {
+                return Err(Error::IncompatibleTexture);
+            }
+
+            let mut surface = EGLBackedSurface::new_from_gl_texture(
+                gl,
+                egl_display,
+                egl_context,
+                context_id,
+                context_attributes,
+                size,
+                access,
+                texture_object,
+                EGL_GL_TEXTURE_2D_KHR,
+                ownership,
+            );
+            if let EGLSurfaceObjects::TextureImage {
+                egl_image,
+                ref mut texture_ownership,
+                ..
+            } = surface.objects
+            {
+                if egl_image == EGL_NO_IMAGE_KHR {
+                    let err = EGL_FUNCTIONS.with(|egl| egl.GetError().to_windowing_api_error());
+                    // Don't let a failed wrap delete a texture that the caller still owns.
+                    *texture_ownership = TextureOwnership::Borrowed;
+                    surface.destroy(gl, egl_display, context_id)?;
+                    return Err(Error::SurfaceCreationFailed(err));
+                }
+            }
+            Ok(surface)
+        }
+    }
+
     /// Create a new EGLBackedSurface from an existing GL texture
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new_from_gl_texture(
         gl: &Gl,
         egl_display: EGLDisplay,
@@ -138,49 +340,111 @@ impl EGLBackedSurface {
         context_id: ContextID,
         context_attributes: &ContextAttributes,
         size: &Size2D<i32>,
+        access: SurfaceAccess,
         texture_object: GLuint,
         egl_target: GLuint,
+        texture_ownership: TextureOwnership,
     ) -> EGLBackedSurface {
-        let egl_image_attribs = [
-            EGL_IMAGE_PRESERVED_KHR as EGLint,
-            egl::FALSE as EGLint,
-            egl::NONE as EGLint,
-            0,
-        ];
+        EGLBackedSurface::new_texture_image(
+            gl,
+            egl_display,
+            egl_context,
+            context_id,
+            context_attributes,
+            size,
+            access,
+            texture_object,
+            egl_target,
+            texture_ownership,
+            None,
+        )
+    }
 
+    // Wraps a texture in an EGL image and a framebuffer object.
+    //
+    // `multisample_format` is the sized internal format of a texture that surfman created itself.
+    // Only those textures are rendered to through multisampled renderbuffers, if the context
+    // attributes ask for them; textures from elsewhere are always rendered to directly.
+    #[allow(clippy::too_many_arguments)]
+    fn new_texture_image(
+        gl: &Gl,
+        egl_display: EGLDisplay,
+        egl_context: EGLContext,
+        context_id: ContextID,
+        context_attributes: &ContextAttributes,
+        size: &Size2D<i32>,
+        access: SurfaceAccess,
+        texture_object: GLuint,
+        egl_target: GLuint,
+        texture_ownership: TextureOwnership,
+        multisample_format: Option<GLenum>,
+    ) -> EGLBackedSurface {
         unsafe {
             // Create our image.
-            let egl_client_buffer = texture_object as usize as EGLClientBuffer;
-            let egl_image = (EGL_EXTENSION_FUNCTIONS.CreateImageKHR)(
-                egl_display,
-                egl_context,
-                egl_target,
-                egl_client_buffer,
-                egl_image_attribs.as_ptr(),
-            );
-            
+            let egl_image =
+                create_texture_image(egl_display, egl_context, egl_target, texture_object);
+
             // Create the framebuffer, and bind the texture to it.
             let framebuffer_object =
                 gl_utils::create_and_bind_framebuffer(gl, gl::TEXTURE_2D, texture_object);
 
-            // Bind renderbuffers as appropriate.
-            let renderbuffers = Renderbuffers::new(gl, size, context_attributes);
-            renderbuffers.bind_to_current_framebuffer(gl);
+            debug_assert_eq!(
+                gl.CheckFramebufferStatus(gl::FRAMEBUFFER),
+                gl::FRAMEBUFFER_COMPLETE
+            );
+
+            // Bind renderbuffers as appropriate. Multisampled surfaces keep their depth and
+            // stencil with their samples.
+            let multisample = multisample_format
+                .and_then(|format| Multisample::new(gl, size, context_attributes, format));
+            let renderbuffers = match multisample {
+                Some(_) => Renderbuffers::none(),
+                None => {
+                    let renderbuffers = Renderbuffers::new(gl, size, context_attributes);
+                    renderbuffers.bind_to_current_framebuffer(gl);
+                    renderbuffers
+                }
+            };
 
             debug_assert_eq!(
                 gl.CheckFramebufferStatus(gl::FRAMEBUFFER),
                 gl::FRAMEBUFFER_COMPLETE
             );
 
+            leak_check::created(TrackedObject::Surface);
+            let format = multisample_format.unwrap_or(gl::RGBA8);
             EGLBackedSurface {
                 context_id,
                 size: *size,
+                access,
+                format,
+                colorspace: match multisample_format {
+                    Some(gl::SRGB8_ALPHA8) => ColorEncoding::Srgb,
+                    _ => ColorEncoding::Linear,
+                },
+                color_bits: ColorBits::of_format(format),
                 objects: EGLSurfaceObjects::TextureImage {
                     egl_image,
                     framebuffer_object,
                     texture_object,
+                    texture_ownership,
+                    allocated: multisample_format.is_some(),
                     renderbuffers,
+                    multisample,
                 },
+                present_history: PresentHistory::default(),
+                auto_vsync: None,
+                release_signal: None,
+                present_fence: None,
+                presentation_feedback: None,
+                invalidation: PostPresentInvalidation::default(),
+                buffer_age: BufferAge::default(),
+                front_buffer: FrontBuffer::default(),
+                preserves_contents: true,
+                pause: PresentPause::default(),
+                purge_state: PurgeState::default(),
+                color_profile: ColorProfileTracker::default(),
+                color_profile_source: None,
                 destroyed: false,
             }
         }
@@ -192,26 +456,72 @@ impl EGLBackedSurface {
         native_window: *mut c_void,
         context_id: ContextID,
         size: &Size2D<i32>,
-    ) -> EGLBackedSurface {
+        access: SurfaceAccess,
+        options: &SurfaceOptions,
+    ) -> Result<EGLBackedSurface, Error> {
+        let colorspace = options.colorspace;
         EGL_FUNCTIONS.with(|egl| unsafe {
-            let window_surface_attribs = [egl::NONE as EGLAttrib];
+            let mut window_surface_attribs = vec![];
+            if colorspace == ColorEncoding::Srgb {
+                if !device::display_supports_extension(egl_display, "EGL_KHR_gl_colorspace") {
+                    return Err(Error::UnsupportedSurfaceFormat);
+                }
+                window_surface_attribs.push(egl::GL_COLORSPACE as EGLAttrib);
+                window_surface_attribs.push(egl::GL_COLORSPACE_SRGB as EGLAttrib);
+            }
+            window_surface_attribs.push(egl::NONE as EGLAttrib);
             let egl_surface = egl.CreatePlatformWindowSurface(
                 egl_display,
                 egl_config,
                 native_window,
                 window_surface_attribs.as_ptr(),
             );
+            // Drivers refuse sRGB window surfaces for configs that they can't encode.
+            if egl_surface == egl::NO_SURFACE && colorspace == ColorEncoding::Srgb {
+                return Err(Error::UnsupportedSurfaceFormat);
+            }
             assert_ne!(egl_surface, egl::NO_SURFACE);
+            let requested =
+                preserve_color_buffer(egl_display, egl_surface, options).and_then(|preserved| {
+                    let front_buffer = FrontBuffer::request(egl_display, egl_surface, options)?;
+                    Ok((front_buffer.requested() || preserved, front_buffer))
+                });
+            let (preserves_contents, front_buffer) = match requested {
+                Ok(requested) => requested,
+                Err(err) => {
+                    egl.DestroySurface(egl_display, egl_surface);
+                    return Err(err);
+                }
+            };
 
-            EGLBackedSurface {
+            leak_check::created(TrackedObject::Surface);
+            Ok(EGLBackedSurface {
                 context_id,
                 size: *size,
+                access,
+                format: gl::NONE,
+                colorspace,
+                color_bits: context::config_color_bits(egl_display, egl_config),
                 objects: EGLSurfaceObjects::Window {
                     native_window,
                     egl_surface,
                 },
+                present_history: PresentHistory::default(),
+                auto_vsync: None,
+                release_signal: None,
+                present_fence: None,
+                presentation_feedback: FrameTimestamps::new(egl_display, egl_surface)
+                    .map(|frame_timestamps| Box::new(frame_timestamps) as Box<_>),
+                invalidation: PostPresentInvalidation::default(),
+                buffer_age: BufferAge::new(egl_display),
+                front_buffer,
+                preserves_contents,
+                pause: PresentPause::default(),
+                purge_state: PurgeState::default(),
+                color_profile: ColorProfileTracker::default(),
+                color_profile_source: None,
                 destroyed: false,
-            }
+            })
         })
     }
 
@@ -224,7 +534,11 @@ impl EGLBackedSurface {
                 EGLSurfaceObjects::TextureImage { egl_image, .. } => egl_image,
                 EGLSurfaceObjects::Window { .. } => return Err((Error::WidgetAttached, self)),
             };
+            if let Err(err) = self.purge_state.check_usable() {
+                return Err((err, self));
+            }
             let texture_object = bind_egl_image_to_gl_texture(gl, egl_image);
+            leak_check::created(TrackedObject::SurfaceTexture);
             Ok(EGLSurfaceTexture {
                 surface: self,
                 texture_object,
@@ -242,6 +556,9 @@ impl EGLBackedSurface {
         if context_id != self.context_id {
             return Err(Error::IncompatibleSurface);
         }
+        if !self.destroyed {
+            leak_check::destroyed(TrackedObject::Surface);
+        }
 
         unsafe {
             match self.objects {
@@ -249,18 +566,29 @@ impl EGLBackedSurface {
                     ref mut egl_image,
                     ref mut framebuffer_object,
                     ref mut texture_object,
+                    texture_ownership,
                     ref mut renderbuffers,
+                    ref mut multisample,
+                    ..
                 } => {
                     gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
                     gl.DeleteFramebuffers(1, framebuffer_object);
                     *framebuffer_object = 0;
                     renderbuffers.destroy(gl);
+                    if let Some(mut multisample) = multisample.take() {
+                        multisample.destroy(gl);
+                    }
 
-                    let result = (EGL_EXTENSION_FUNCTIONS.DestroyImageKHR)(egl_display, *egl_image);
-                    assert_ne!(result, egl::FALSE);
-                    *egl_image = EGL_NO_IMAGE_KHR;
+                    if *egl_image != EGL_NO_IMAGE_KHR {
+                        let result =
+                            (EGL_EXTENSION_FUNCTIONS.DestroyImageKHR)(egl_display, *egl_image);
+                        assert_ne!(result, egl::FALSE);
+                        *egl_image = EGL_NO_IMAGE_KHR;
+                    }
 
-                    gl.DeleteTextures(1, texture_object);
+                    if texture_ownership == TextureOwnership::Owned {
+                        gl.DeleteTextures(1, texture_object);
+                    }
                     *texture_object = 0;
 
                     self.destroyed = true;
@@ -274,6 +602,10 @@ impl EGLBackedSurface {
                         egl.DestroySurface(egl_display, *egl_surface);
                         *egl_surface = egl::NO_SURFACE;
                     });
+                    self.release_signal = None;
+                    self.present_fence = None;
+                    self.presentation_feedback = None;
+                    self.color_profile_source = None;
 
                     self.destroyed = true;
                     Ok(Some(mem::replace(native_window, ptr::null())))
@@ -282,48 +614,391 @@ impl EGLBackedSurface {
         }
     }
 
-    // TODO(pcwalton): Damage regions.
-    pub(crate) fn present(
+    // Resizes the surface. The context that created the surface must be current.
+    //
+    // A generic surface has its texture and renderbuffers reallocated in place, so its framebuffer
+    // objects stay valid and bound, but it gets a new EGL image and hence a new ID. A widget
+    // surface only records the size, since EGL resizes window surfaces to follow their window.
+    pub(crate) fn resize(
+        &mut self,
+        gl: &Gl,
+        egl_display: EGLDisplay,
+        egl_context: EGLContext,
+        context_id: ContextID,
+        size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        if context_id != self.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        if let EGLSurfaceObjects::TextureImage {
+            ref mut egl_image,
+            texture_object,
+            allocated,
+            ref renderbuffers,
+            ref mut multisample,
+            ..
+        } = self.objects
+        {
+            if !allocated {
+                return Err(Error::ForeignTexture);
+            }
+            self.purge_state.check_usable()?;
+            unsafe {
+                // Respecifying the texture orphans the image, which keeps the old storage alive.
+                allocate_texture_storage(gl, texture_object, self.format, &size);
+                let result = (EGL_EXTENSION_FUNCTIONS.DestroyImageKHR)(egl_display, *egl_image);
+                assert_ne!(result, egl::FALSE);
+                *egl_image = create_texture_image(
+                    egl_display,
+                    egl_context,
+                    EGL_GL_TEXTURE_2D_KHR,
+                    texture_object,
+                );
+            }
+            renderbuffers.resize(gl, &size);
+            if let Some(ref mut multisample) = *multisample {
+                multisample.resize(gl, &size);
+            }
+        }
+
+        // Window surfaces get new buffers of the new size, which a held frame no longer fits.
+        self.buffer_age.reset();
+        self.pause.force_resume();
+        self.size = size;
+        Ok(())
+    }
+
+    pub(crate) fn set_swap_interval(
         &self,
         egl_display: EGLDisplay,
         egl_context: EGLContext,
+        context_id: ContextID,
+        interval: i32,
+    ) -> Result<i32, Error> {
+        if context_id != self.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        match self.objects {
+            EGLSurfaceObjects::Window { egl_surface, .. } => unsafe {
+                set_swap_interval(egl_display, egl_surface, egl_context, interval)
+            },
+            EGLSurfaceObjects::TextureImage { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    pub(crate) fn supports_adaptive_swap(&self, egl_display: EGLDisplay) -> bool {
+        match self.objects {
+            EGLSurfaceObjects::Window { egl_surface, .. } => unsafe {
+                supports_adaptive_swap(egl_display, egl_surface)
+            },
+            EGLSurfaceObjects::TextureImage { .. } => false,
+        }
+    }
+
+    pub(crate) fn set_auto_swap_interval(
+        &mut self,
+        egl_display: EGLDisplay,
+        egl_context: EGLContext,
+        context_id: ContextID,
+        policy: Option<AutoVsyncPolicy>,
     ) -> Result<(), Error> {
-        unsafe {
-            match self.objects {
-                EGLSurfaceObjects::Window { egl_surface, .. } => {
-                    // The surface must be bound to the current context in EGL 1.4. Temporarily
-                    // make this surface current to enforce this.
-                    let _guard = CurrentContextGuard::new();
+        if context_id != self.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        let egl_surface = match self.objects {
+            EGLSurfaceObjects::Window { egl_surface, .. } => egl_surface,
+            EGLSurfaceObjects::TextureImage { .. } => return Err(Error::NoWidgetAttached),
+        };
+        if policy.is_some() {
+            unsafe {
+                set_swap_interval(egl_display, egl_surface, egl_context, 1)?;
+            }
+        }
+        self.auto_vsync = policy.map(AutoVsync::new);
+        Ok(())
+    }
 
-                    EGL_FUNCTIONS.with(|egl| {
-                        egl.MakeCurrent(egl_display, egl_surface, egl_surface, egl_context);
-
-                        let ok = egl.SwapBuffers(egl_display, egl_surface);
-                        if ok != egl::FALSE {
-                            Ok(())
-                        } else {
-                            Err(Error::PresentFailed(
-                                egl.GetError().to_windowing_api_error(),
-                            ))
+    pub(crate) fn pause_presentation(
+        &mut self,
+        context_id: ContextID,
+        capabilities: &GLCapabilities,
+    ) -> Result<PausedPresentation, Error> {
+        if context_id != self.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if let EGLSurfaceObjects::TextureImage { .. } = self.objects {
+            return Err(Error::NoWidgetAttached);
+        }
+        // Frames rendered to the front buffer are on screen as soon as they're drawn.
+        if self.front_buffer.requested() {
+            return Err(Error::UnsupportedPresentMode);
+        }
+        if !capabilities.blit_framebuffer {
+            return Err(Error::RequiredExtensionUnavailable);
+        }
+        self.pause.pause()
+    }
+
+    // Ends a pause in presentation. If `present_pending` is true, the frame held during it is
+    // drawn back into the window, and true is returned, so that the caller presents it.
+    pub(crate) fn resume_presentation(
+        &mut self,
+        gl: &Gl,
+        egl_display: EGLDisplay,
+        egl_context: EGLContext,
+        context_id: ContextID,
+        paused: PausedPresentation,
+        present_pending: bool,
+    ) -> Result<bool, Error> {
+        if context_id != self.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        let egl_surface = match self.objects {
+            EGLSurfaceObjects::Window { egl_surface, .. } => egl_surface,
+            EGLSurfaceObjects::TextureImage { .. } => return Err(Error::NoWidgetAttached),
+        };
+        match self.pause.resume(paused)? {
+            Some(frame) if present_pending => unsafe {
+                with_window_surface_current(egl_display, egl_surface, egl_context, || {
+                    frame.draw(gl)
+                })?;
+                Ok(true)
+            },
+            _ => Ok(false),
+        }
+    }
+
+    // Presents a window surface. With `damage`, only those rectangles are passed on as having
+    // changed, and an empty list skips the swap. While presentation is paused, the frame is held
+    // instead.
+    pub(crate) fn present(
+        &mut self,
+        gl: &Gl,
+        egl_display: EGLDisplay,
+        egl_context: EGLContext,
+        context_id: ContextID,
+        damage: Option<&[Rect<i32>]>,
+    ) -> Result<(), Error> {
+        if context_id != self.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        let paused = self.pause.is_paused();
+        match self.objects {
+            EGLSurfaceObjects::Window { .. } if paused && matches!(damage, Some([])) => {
+                Err(Error::PresentationPaused)
+            }
+            EGLSurfaceObjects::Window { egl_surface, .. } if paused => unsafe {
+                let size = self.size;
+                let frame =
+                    with_window_surface_current(egl_display, egl_surface, egl_context, || {
+                        HeldFrame::read(gl, size)
+                    })?;
+                self.pause.hold(frame);
+                Err(Error::PresentationPaused)
+            },
+            EGLSurfaceObjects::Window { .. } if matches!(damage, Some([])) => Ok(()),
+            // Frames rendered to the front buffer are already on screen once they're flushed.
+            EGLSurfaceObjects::Window { egl_surface, .. } if self.front_buffer.bound() => unsafe {
+                let submit = Instant::now();
+                flush_window_surface(gl, egl_display, egl_surface, egl_context)?;
+                self.buffer_age.front_buffer();
+                self.present_history
+                    .record(submit, Some(Instant::now()), None);
+                Ok(())
+            },
+            EGLSurfaceObjects::Window { egl_surface, .. } => unsafe {
+                let submit = Instant::now();
+                let frame_id = self.present_history.next_frame_id();
+                let release_signal = &mut self.release_signal;
+                let presentation_feedback = &mut self.presentation_feedback;
+                let (invalidation, buffer_age) = (&mut self.invalidation, &mut self.buffer_age);
+                let front_buffer = &mut self.front_buffer;
+                let present_fence = swap_window_surface(
+                    egl_display,
+                    egl_surface,
+                    egl_context,
+                    damage,
+                    |egl_display| {
+                        if let Some(ref mut presentation_feedback) = *presentation_feedback {
+                            presentation_feedback.presenting(frame_id)?;
                         }
-                    })
+                        match *release_signal {
+                            Some(ref mut release_signal) => {
+                                release_signal.presenting().map(|_| None)
+                            }
+                            None => Ok(Some(PresentFence::insert(egl_display))),
+                        }
+                    },
+                    || {
+                        invalidation.invalidate(gl, 0);
+                        buffer_age.record(egl_display, egl_surface, invalidation.set());
+                        front_buffer.swapped(egl_display, egl_context, buffer_age);
+                    },
+                )?;
+                if present_fence.is_some() {
+                    self.present_fence = present_fence;
                 }
-                EGLSurfaceObjects::TextureImage { .. } => Err(Error::NoWidgetAttached),
+                // `eglSwapBuffers` returns once the buffer has been handed to the window system.
+                self.present_history
+                    .record(submit, Some(Instant::now()), None);
+                if let Some(ref mut auto_vsync) = self.auto_vsync {
+                    auto_vsync.update(&self.present_history, |interval| {
+                        set_swap_interval(egl_display, egl_surface, egl_context, interval)
+                    });
+                }
+                Ok(())
+            },
+            EGLSurfaceObjects::TextureImage { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    pub(crate) fn set_post_present_invalidation(
+        &mut self,
+        egl_display: EGLDisplay,
+        set: InvalidateSet,
+    ) -> Result<(), Error> {
+        // Consumers read the color buffers of generic surfaces once they're presented, and the
+        // front buffer is on screen.
+        let color_preserved = match self.objects {
+            EGLSurfaceObjects::Window { egl_surface, .. } => unsafe {
+                self.front_buffer.requested() || preserves_color_buffer(egl_display, egl_surface)
+            },
+            EGLSurfaceObjects::TextureImage { .. } => true,
+        };
+        self.invalidation.change(set, color_preserved)
+    }
+
+    pub(crate) fn invalidate(&mut self, gl: &Gl, context_id: ContextID) -> Result<(), Error> {
+        if context_id != self.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if let EGLSurfaceObjects::Window { .. } = self.objects {
+            return Err(Error::WidgetAttached);
+        }
+        // Multisampled surfaces are drawn into their multisample framebuffer.
+        let framebuffer_object = self.info().framebuffer_object;
+        unsafe { self.invalidation.invalidate(gl, framebuffer_object) };
+        Ok(())
+    }
+
+    // Clears a region of the surface. Generic surfaces are cleared through their framebuffer
+    // object, so the surface's context must be current.
+    pub(crate) fn clear_region(
+        &self,
+        gl: &Gl,
+        egl_display: EGLDisplay,
+        egl_context: EGLContext,
+        context_id: ContextID,
+        region: &ClearRegion,
+    ) -> Result<(), Error> {
+        if context_id != self.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        match self.objects {
+            EGLSurfaceObjects::Window { egl_surface, .. } => unsafe {
+                clear_window_surface(gl, egl_display, egl_surface, egl_context, self.size, region)
+            },
+            EGLSurfaceObjects::TextureImage { .. } => {
+                self.purge_state.check_usable()?;
+                // Multisampled surfaces are drawn into their multisample framebuffer.
+                let framebuffer_object = self.info().framebuffer_object;
+                unsafe { region.clear(gl, framebuffer_object, self.size) }
             }
         }
     }
 
+    pub(crate) fn is_widget(&self) -> bool {
+        matches!(self.objects, EGLSurfaceObjects::Window { .. })
+    }
+
+    pub(crate) fn buffer_age(&self) -> Result<u32, Error> {
+        match self.objects {
+            EGLSurfaceObjects::Window { .. } => Ok(self.buffer_age.get()),
+            EGLSurfaceObjects::TextureImage { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    pub(crate) fn wait_previous_buffer_released(
+        &self,
+        timeout: Duration,
+    ) -> Result<BufferRelease, Error> {
+        if let EGLSurfaceObjects::TextureImage { .. } = self.objects {
+            return Err(Error::NoWidgetAttached);
+        }
+        match (&self.release_signal, &self.present_fence) {
+            (Some(release_signal), _) => release_signal.wait(timeout),
+            (None, Some(present_fence)) => present_fence.wait(timeout),
+            (None, None) => Ok(BufferRelease::Released),
+        }
+    }
+
+    pub(crate) fn presentation_records(&self) -> Vec<PresentRecord> {
+        presentation_time::records_with_display_times(&self.present_history, |_, statistics| {
+            if let Some(ref presentation_feedback) = self.presentation_feedback {
+                presentation_feedback.update(statistics);
+            }
+        })
+    }
+
+    pub(crate) fn presentation_time(&self) -> Result<PresentationStatistics, Error> {
+        if let EGLSurfaceObjects::TextureImage { .. } = self.objects {
+            return Err(Error::NoWidgetAttached);
+        }
+        presentation_time::latest_statistics(&self.present_history, |_, statistics| {
+            if let Some(ref presentation_feedback) = self.presentation_feedback {
+                presentation_feedback.update(statistics);
+            }
+        })
+    }
+
+    // Sets whether the system may discard the surface's contents. EGL gives no way to offer a GL
+    // texture back to the system, so this only tracks the state.
+    pub(crate) fn set_purgeable(&mut self, purgeability: Purgeability) -> Result<(), Error> {
+        if let EGLSurfaceObjects::Window { .. } = self.objects {
+            return Err(Error::WidgetAttached);
+        }
+        self.purge_state.set(&mut ResidentBacking, purgeability)
+    }
+
+    pub(crate) fn reclaim(&mut self) -> Result<ReclaimResult, Error> {
+        if let EGLSurfaceObjects::Window { .. } = self.objects {
+            return Err(Error::WidgetAttached);
+        }
+        self.purge_state.reclaim(&mut ResidentBacking)
+    }
+
     pub(crate) fn info(&self) -> SurfaceInfo {
         SurfaceInfo {
             size: self.size,
             id: self.id(),
             context_id: self.context_id,
             framebuffer_object: match self.objects {
+                EGLSurfaceObjects::TextureImage {
+                    multisample: Some(ref multisample),
+                    ..
+                } => multisample.framebuffer_object,
                 EGLSurfaceObjects::TextureImage {
                     framebuffer_object, ..
                 } => framebuffer_object,
                 EGLSurfaceObjects::Window { .. } => 0,
             },
+            access: self.access,
+            format: self.format,
+            purgeability: self.purge_state.purgeability(),
+            colorspace: self.colorspace,
+            color_bits: self.color_bits,
+            preserves_contents: self.preserves_contents,
+            texture_target: match self.objects {
+                EGLSurfaceObjects::TextureImage { .. } => gl::TEXTURE_2D,
+                EGLSurfaceObjects::Window { .. } => gl::NONE,
+            },
+            stride: None,
+            native_format: NativeFormat::of_gl_internal_format(
+                self.format,
+                NativeFormatKind::GlInternal,
+            ),
         }
     }
 
@@ -334,6 +1009,18 @@ impl EGLBackedSurface {
         }
     }
 
+    // Returns the size of the frame in this widget surface's back buffer, as EGL reports it, or
+    // `None` for generic surfaces.
+    #[allow(dead_code)]
+    pub(crate) fn window_size(&self, egl_display: EGLDisplay) -> Option<Size2D<i32>> {
+        match self.objects {
+            EGLSurfaceObjects::TextureImage { .. } => None,
+            EGLSurfaceObjects::Window { egl_surface, .. } => unsafe {
+                window_surface_size(egl_display, egl_surface)
+            },
+        }
+    }
+
     pub(crate) fn native_window(&self) -> Result<*const c_void, Error> {
         match self.objects {
             EGLSurfaceObjects::TextureImage { .. } => Err(Error::NoWidgetAttached),
@@ -341,20 +1028,82 @@ impl EGLBackedSurface {
         }
     }
 
-    pub(crate) fn unbind(&self, gl: &Gl, egl_display: EGLDisplay, egl_context: EGLContext) {
-        // If we're current, we stay current, but with no surface attached.
+    // Exports the texture's EGL image as a single-plane dma-buf.
+    #[cfg(free_unix)]
+    pub(crate) fn export_dma_buf(&self, egl_display: EGLDisplay) -> Result<DmaBuf, Error> {
+        let egl_image = match self.objects {
+            EGLSurfaceObjects::Window { .. } => return Err(Error::WidgetAttached),
+            EGLSurfaceObjects::TextureImage {
+                multisample: Some(_),
+                ..
+            } => return Err(Error::UnsupportedSurfaceFormat),
+            EGLSurfaceObjects::TextureImage { egl_image, .. } => egl_image,
+        };
+        let (query, export) = match (
+            EGL_EXTENSION_FUNCTIONS.ExportDMABUFImageQueryMESA,
+            EGL_EXTENSION_FUNCTIONS.ExportDMABUFImageMESA,
+        ) {
+            (Some(query), Some(export)) => (query, export),
+            _ => return Err(Error::RequiredExtensionUnavailable),
+        };
+        unsafe {
+            if !device::display_supports_extension(egl_display, "EGL_MESA_image_dma_buf_export") {
+                return Err(Error::RequiredExtensionUnavailable);
+            }
+            let (mut fourcc, mut num_planes, mut modifier) = (0, 0, 0);
+            if query(
+                egl_display,
+                egl_image,
+                &mut fourcc,
+                &mut num_planes,
+                &mut modifier,
+            ) == egl::FALSE
+            {
+                let err = EGL_FUNCTIONS.with(|egl| egl.GetError().to_windowing_api_error());
+                return Err(Error::SurfaceExportFailed(err));
+            }
+            if num_planes != 1 {
+                return Err(Error::UnsupportedSurfaceFormat);
+            }
+            let (mut fd, mut stride, mut offset) = (-1, 0, 0);
+            if export(egl_display, egl_image, &mut fd, &mut stride, &mut offset) == egl::FALSE {
+                let err = EGL_FUNCTIONS.with(|egl| egl.GetError().to_windowing_api_error());
+                return Err(Error::SurfaceExportFailed(err));
+            }
+            Ok(DmaBuf {
+                fd: OwnedFd::from_raw_fd(fd),
+                size: self.size,
+                fourcc: fourcc as u32,
+                modifier,
+                offset: offset as u32,
+                stride: stride as u32,
+            })
+        }
+    }
+
+    // Unbinds this surface's framebuffer object if `egl_context` is current, after resolving its
+    // samples if it's multisampled. The caller then makes the context current again without the
+    // surface.
+    pub(crate) fn unbind(&self, gl: &Gl, egl_context: EGLContext) {
         unsafe {
             EGL_FUNCTIONS.with(|egl| {
                 if egl.GetCurrentContext() != egl_context {
                     return;
                 }
 
-                egl.MakeCurrent(egl_display, egl::NO_SURFACE, egl::NO_SURFACE, egl_context);
-
                 match self.objects {
                     EGLSurfaceObjects::TextureImage {
-                        framebuffer_object, ..
+                        framebuffer_object,
+                        ref multisample,
+                        ..
                     } => {
+                        if let Some(ref multisample) = *multisample {
+                            multisample.resolve(gl, framebuffer_object);
+                            gl_utils::unbind_framebuffer_if_necessary(
+                                gl,
+                                multisample.framebuffer_object,
+                            );
+                        }
                         gl_utils::unbind_framebuffer_if_necessary(gl, framebuffer_object);
                     }
                     EGLSurfaceObjects::Window { .. } => {}
@@ -363,6 +1112,17 @@ impl EGLBackedSurface {
         }
     }
 
+    // Returns true if this surface is rendered to through multisampled renderbuffers.
+    pub(crate) fn is_multisampled(&self) -> bool {
+        matches!(
+            self.objects,
+            EGLSurfaceObjects::TextureImage {
+                multisample: Some(_),
+                ..
+            }
+        )
+    }
+
     pub(crate) fn egl_surfaces(&self) -> ExternalEGLSurfaces {
         match self.objects {
             EGLSurfaceObjects::Window { egl_surface, .. } => ExternalEGLSurfaces {
@@ -379,6 +1139,7 @@ impl EGLSurfaceTexture {
         unsafe {
             gl.DeleteTextures(1, &self.texture_object);
             self.texture_object = 0;
+            leak_check::destroyed(TrackedObject::SurfaceTexture);
             self.surface
         }
     }
@@ -423,7 +1184,69 @@ pub(crate) unsafe fn create_pbuffer_surface(
 }
 
 #[allow(dead_code)]
+// Allocates uninitialized storage of the given size and sized internal format for a texture.
+unsafe fn allocate_texture_storage(
+    gl: &Gl,
+    texture_object: GLuint,
+    format: GLenum,
+    size: &Size2D<i32>,
+) {
+    // Save the current texture binding
+    let mut old_texture_object = 0;
+    gl.GetIntegerv(gl::TEXTURE_BINDING_2D, &mut old_texture_object);
+    gl.BindTexture(gl::TEXTURE_2D, texture_object);
+    // Unbind PIXEL_UNPACK_BUFFER, because if it is bound,
+    // it can cause errors in glTexImage2D.
+    // TODO: should this be inside a check for GL 2.0?
+    let mut unpack_buffer = 0;
+    gl.GetIntegerv(gl::PIXEL_UNPACK_BUFFER_BINDING, &mut unpack_buffer);
+    if unpack_buffer != 0 {
+        gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, 0);
+    }
+    let (internal_format, texture_format, texture_type) = memory::texture_format_and_type(format);
+    gl.TexImage2D(
+        gl::TEXTURE_2D,
+        0,
+        internal_format as GLint,
+        size.width,
+        size.height,
+        0,
+        texture_format,
+        texture_type,
+        ptr::null(),
+    );
+    // Restore the old bindings
+    gl.BindTexture(gl::TEXTURE_2D, old_texture_object as _);
+    if unpack_buffer != 0 {
+        gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, unpack_buffer as _);
+    }
+}
+
+// Wraps a texture in a new EGL image, whose contents needn't be preserved.
+unsafe fn create_texture_image(
+    egl_display: EGLDisplay,
+    egl_context: EGLContext,
+    egl_target: GLuint,
+    texture_object: GLuint,
+) -> EGLImageKHR {
+    let egl_image_attribs = [
+        EGL_IMAGE_PRESERVED_KHR as EGLint,
+        egl::FALSE as EGLint,
+        egl::NONE as EGLint,
+        0,
+    ];
+    let egl_client_buffer = texture_object as usize as EGLClientBuffer;
+    (EGL_EXTENSION_FUNCTIONS.CreateImageKHR)(
+        egl_display,
+        egl_context,
+        egl_target,
+        egl_client_buffer,
+        egl_image_attribs.as_ptr(),
+    )
+}
+
 pub(crate) unsafe fn bind_egl_image_to_gl_texture(gl: &Gl, egl_image: EGLImageKHR) -> GLuint {
+    gl_utils::drain_pre_existing_gl_errors(gl);
     let mut texture = 0;
     gl.GenTextures(1, &mut texture);
     debug_assert_ne!(texture, 0);
@@ -452,3 +1275,655 @@ pub(crate) unsafe fn bind_egl_image_to_gl_texture(gl: &Gl, egl_image: EGLImageKH
     debug_assert_eq!(gl.GetError(), gl::NO_ERROR);
     texture
 }
+
+// Returns the range of swap intervals that the config of a window surface supports.
+unsafe fn swap_interval_range(egl_display: EGLDisplay, egl_surface: EGLSurface) -> (i32, i32) {
+    let mut egl_config_id = 0;
+    EGL_FUNCTIONS.with(|egl| {
+        egl.QuerySurface(
+            egl_display,
+            egl_surface,
+            egl::CONFIG_ID as EGLint,
+            &mut egl_config_id,
+        );
+    });
+    let egl_config = context::egl_config_from_id(egl_display, egl_config_id);
+    let min_interval =
+        context::get_config_attr(egl_display, egl_config, egl::MIN_SWAP_INTERVAL as EGLint);
+    let max_interval =
+        context::get_config_attr(egl_display, egl_config, egl::MAX_SWAP_INTERVAL as EGLint);
+    (min_interval, max_interval)
+}
+
+// Returns true if the config of a window surface allows negative swap intervals.
+//
+// EGL has no extension for late swap tearing; drivers that implement it advertise it by accepting
+// negative intervals, as `GLX_EXT_swap_control_tear` does.
+pub(crate) unsafe fn supports_adaptive_swap(
+    egl_display: EGLDisplay,
+    egl_surface: EGLSurface,
+) -> bool {
+    swap_interval_range(egl_display, egl_surface).0 < 0
+}
+
+// Decides which swap interval to apply when `requested` is asked of a config that supports the
+// intervals from `min` to `max`.
+//
+// Negative intervals are turned into their positive counterparts if adaptive vsync isn't
+// supported. Past that, EGL silently clamps intervals to the range that the config supports, so
+// intervals outside that range are refused with `UnsupportedOnThisPlatform` instead.
+pub(crate) fn applied_swap_interval(
+    requested: i32,
+    min: i32,
+    max: i32,
+    adaptive_supported: bool,
+) -> Result<i32, Error> {
+    let interval = if requested < 0 && !adaptive_supported {
+        requested.saturating_abs()
+    } else {
+        requested
+    };
+    if interval < min || interval > max {
+        return Err(Error::UnsupportedOnThisPlatform);
+    }
+    Ok(interval)
+}
+
+// Sets the swap interval of a window surface, whatever context is current on this thread, and
+// returns the interval that took effect, as decided by `applied_swap_interval()`.
+pub(crate) unsafe fn set_swap_interval(
+    egl_display: EGLDisplay,
+    egl_surface: EGLSurface,
+    egl_context: EGLContext,
+    interval: i32,
+) -> Result<i32, Error> {
+    let _guard = CurrentContextGuard::new();
+    let (min_interval, max_interval) = swap_interval_range(egl_display, egl_surface);
+    let interval = applied_swap_interval(interval, min_interval, max_interval, min_interval < 0)?;
+
+    EGL_FUNCTIONS.with(|egl| {
+        if egl.MakeCurrent(egl_display, egl_surface, egl_surface, egl_context) == egl::FALSE {
+            let err = egl.GetError().to_windowing_api_error();
+            return Err(Error::MakeCurrentFailed(err));
+        }
+        if egl.SwapInterval(egl_display, interval) == egl::FALSE {
+            return Err(Error::UnsupportedOnThisPlatform);
+        }
+        Ok(interval)
+    })
+}
+
+// Clears a region of a window surface, whatever context is current on this thread.
+pub(crate) unsafe fn clear_window_surface(
+    gl: &Gl,
+    egl_display: EGLDisplay,
+    egl_surface: EGLSurface,
+    egl_context: EGLContext,
+    size: Size2D<i32>,
+    region: &ClearRegion,
+) -> Result<(), Error> {
+    let _guard = CurrentContextGuard::new();
+    EGL_FUNCTIONS.with(|egl| {
+        if egl.MakeCurrent(egl_display, egl_surface, egl_surface, egl_context) == egl::FALSE {
+            let err = egl.GetError().to_windowing_api_error();
+            return Err(Error::MakeCurrentFailed(err));
+        }
+        region.clear(gl, 0, size)
+    })
+}
+
+// Flushes rendering to a window surface that renders to its front buffer, whatever context is
+// current on this thread.
+pub(crate) unsafe fn flush_window_surface(
+    gl: &Gl,
+    egl_display: EGLDisplay,
+    egl_surface: EGLSurface,
+    egl_context: EGLContext,
+) -> Result<(), Error> {
+    with_window_surface_current(egl_display, egl_surface, egl_context, || {
+        gl.Flush();
+        Ok(())
+    })
+}
+
+// Calls `f` with a window surface and its context current, whatever context is current on this
+// thread, which is made current again afterward.
+pub(crate) unsafe fn with_window_surface_current<T>(
+    egl_display: EGLDisplay,
+    egl_surface: EGLSurface,
+    egl_context: EGLContext,
+    f: impl FnOnce() -> Result<T, Error>,
+) -> Result<T, Error> {
+    let _guard = CurrentContextGuard::new();
+    EGL_FUNCTIONS.with(|egl| {
+        if egl.MakeCurrent(egl_display, egl_surface, egl_surface, egl_context) == egl::FALSE {
+            let err = egl.GetError().to_windowing_api_error();
+            return Err(Error::MakeCurrentFailed(err));
+        }
+        f()
+    })
+}
+
+// Presents a window surface, whatever context is current on this thread.
+//
+// `damage` lists the rectangles that changed, from the bottom left, and is passed on with
+// `EGL_KHR_swap_buffers_with_damage` or `EGL_EXT_swap_buffers_with_damage` where the display
+// supports either. Otherwise, the whole surface is swapped.
+//
+// `before_swap` is called with the surface's context current, just before the swap, which
+// flushes whatever it submits. Its result is returned. `after_swap` is called once the swap has
+// succeeded, with the context still current.
+pub(crate) unsafe fn swap_window_surface<T>(
+    egl_display: EGLDisplay,
+    egl_surface: EGLSurface,
+    egl_context: EGLContext,
+    damage: Option<&[Rect<i32>]>,
+    before_swap: impl FnOnce(EGLDisplay) -> Result<T, Error>,
+    after_swap: impl FnOnce(),
+) -> Result<T, Error> {
+    let _guard = CurrentContextGuard::new();
+    EGL_FUNCTIONS.with(|egl| {
+        if egl.MakeCurrent(egl_display, egl_surface, egl_surface, egl_context) == egl::FALSE {
+            let err = egl.GetError().to_windowing_api_error();
+            return Err(Error::MakeCurrentFailed(err));
+        }
+        let result = before_swap(egl_display)?;
+        let swap_buffers_with_damage = match damage {
+            Some(damage) => swap_buffers_with_damage(egl_display).map(|swap| (swap, damage)),
+            None => None,
+        };
+        let ok = match swap_buffers_with_damage {
+            Some((swap_buffers_with_damage, damage)) => {
+                let rects: Vec<EGLint> = damage
+                    .iter()
+                    .flat_map(|rect| {
+                        let (origin, size) = (rect.origin, rect.size);
+                        [origin.x, origin.y, size.width, size.height]
+                    })
+                    .collect();
+                let rect_count = damage.len() as EGLint;
+                swap_buffers_with_damage(egl_display, egl_surface, rects.as_ptr(), rect_count)
+            }
+            None => egl.SwapBuffers(egl_display, egl_surface),
+        };
+        if ok == egl::FALSE {
+            let err = egl.GetError().to_windowing_api_error();
+            return Err(Error::PresentFailed(err));
+        }
+        after_swap();
+        Ok(result)
+    })
+}
+
+// Returns the entry point that swaps a window surface with damage rectangles, if the display
+// supports one. The KHR and EXT extensions take the same arguments.
+unsafe fn swap_buffers_with_damage(
+    egl_display: EGLDisplay,
+) -> Option<extern "C" fn(EGLDisplay, EGLSurface, *const EGLint, EGLint) -> EGLBoolean> {
+    if device::display_supports_extension(egl_display, "EGL_KHR_swap_buffers_with_damage") {
+        if let Some(swap_buffers_with_damage) = EGL_EXTENSION_FUNCTIONS.SwapBuffersWithDamageKHR {
+            return Some(swap_buffers_with_damage);
+        }
+    }
+    if device::display_supports_extension(egl_display, "EGL_EXT_swap_buffers_with_damage") {
+        return EGL_EXTENSION_FUNCTIONS.SwapBuffersWithDamageEXT;
+    }
+    None
+}
+
+// Returns true if a window surface keeps the contents of its color buffer across swaps.
+pub(crate) unsafe fn preserves_color_buffer(
+    egl_display: EGLDisplay,
+    egl_surface: EGLSurface,
+) -> bool {
+    EGL_FUNCTIONS.with(|egl| {
+        let mut swap_behavior = 0;
+        egl.QuerySurface(
+            egl_display,
+            egl_surface,
+            egl::SWAP_BEHAVIOR as EGLint,
+            &mut swap_behavior,
+        );
+        swap_behavior == egl::BUFFER_PRESERVED as EGLint
+    })
+}
+
+// Returns the size of a window surface as EGL reports it, which follows the native window on
+// Android and OpenHarmony, and the `wl_egl_window` on Wayland once a frame is drawn at its new
+// size, or `None` if the surface isn't valid.
+#[allow(dead_code)]
+pub(crate) unsafe fn window_surface_size(
+    egl_display: EGLDisplay,
+    egl_surface: EGLSurface,
+) -> Option<Size2D<i32>> {
+    EGL_FUNCTIONS.with(|egl| {
+        let (mut width, mut height) = (0, 0);
+        if egl.QuerySurface(egl_display, egl_surface, egl::WIDTH as EGLint, &mut width)
+            == egl::FALSE
+            || egl.QuerySurface(egl_display, egl_surface, egl::HEIGHT as EGLint, &mut height)
+                == egl::FALSE
+        {
+            return None;
+        }
+        Some(Size2D::new(width, height))
+    })
+}
+
+// Asks a new window surface to keep the contents of its color buffer across swaps, if the options
+// call for it, and returns whether it does. Its config must have
+// `EGL_SWAP_BEHAVIOR_PRESERVED_BIT`. Some window systems preserve the color buffer unasked.
+pub(crate) unsafe fn preserve_color_buffer(
+    egl_display: EGLDisplay,
+    egl_surface: EGLSurface,
+    options: &SurfaceOptions,
+) -> Result<bool, Error> {
+    if options.preserve_buffer {
+        let ok = EGL_FUNCTIONS.with(|egl| {
+            egl.SurfaceAttrib(
+                egl_display,
+                egl_surface,
+                egl::SWAP_BEHAVIOR as EGLint,
+                egl::BUFFER_PRESERVED as EGLint,
+            )
+        });
+        if ok == egl::FALSE || !preserves_color_buffer(egl_display, egl_surface) {
+            return Err(Error::UnsupportedSwapBehavior);
+        }
+    }
+    Ok(preserves_color_buffer(egl_display, egl_surface))
+}
+
+impl FrontBuffer {
+    // Asks a new window surface to render to its front buffer from its next swap on, if the
+    // options call for it. Its config must have a mutable render buffer.
+    pub(crate) unsafe fn request(
+        egl_display: EGLDisplay,
+        egl_surface: EGLSurface,
+        options: &SurfaceOptions,
+    ) -> Result<FrontBuffer, Error> {
+        if options.present_mode == PresentMode::DoubleBuffer {
+            return Ok(FrontBuffer::default());
+        }
+        EGL_FUNCTIONS.with(|egl| {
+            let ok = egl.SurfaceAttrib(
+                egl_display,
+                egl_surface,
+                egl::RENDER_BUFFER as EGLint,
+                egl::SINGLE_BUFFER as EGLint,
+            );
+            if ok == egl::FALSE {
+                return Err(Error::UnsupportedPresentMode);
+            }
+            Ok(FrontBuffer {
+                requested: true,
+                bound: false,
+            })
+        })
+    }
+
+    #[inline]
+    pub(crate) fn requested(&self) -> bool {
+        self.requested
+    }
+
+    #[inline]
+    pub(crate) fn bound(&self) -> bool {
+        self.bound
+    }
+
+    // Checks which buffer the context renders to, right after a swap with it current. The driver
+    // may keep rendering to the back buffer, in which case presents go on swapping.
+    pub(crate) unsafe fn swapped(
+        &mut self,
+        egl_display: EGLDisplay,
+        egl_context: EGLContext,
+        buffer_age: &mut BufferAge,
+    ) {
+        if !self.requested || self.bound {
+            return;
+        }
+        EGL_FUNCTIONS.with(|egl| {
+            let mut render_buffer = 0;
+            let ok = egl.QueryContext(
+                egl_display,
+                egl_context,
+                egl::RENDER_BUFFER as EGLint,
+                &mut render_buffer,
+            );
+            self.bound = ok != egl::FALSE && render_buffer == egl::SINGLE_BUFFER as EGLint;
+        });
+        if self.bound {
+            buffer_age.front_buffer();
+        }
+    }
+}
+
+impl BufferAge {
+    // Checks whether the display supports `EGL_EXT_buffer_age`. The age is 0 until the first
+    // present.
+    pub(crate) unsafe fn new(egl_display: EGLDisplay) -> BufferAge {
+        BufferAge {
+            supported: device::display_supports_extension(egl_display, "EGL_EXT_buffer_age"),
+            age: 0,
+        }
+    }
+
+    // Queries the age of the new back buffer of a window surface that has just been swapped. The
+    // surface must still be current, with nothing drawn into it. Invalidating the color buffer
+    // after the swap discards its contents, which makes the age 0.
+    pub(crate) unsafe fn record(
+        &mut self,
+        egl_display: EGLDisplay,
+        egl_surface: EGLSurface,
+        invalidated: InvalidateSet,
+    ) {
+        self.age = 0;
+        if !self.supported || invalidated.color {
+            return;
+        }
+        EGL_FUNCTIONS.with(|egl| {
+            let mut age = 0;
+            let ok = egl.QuerySurface(
+                egl_display,
+                egl_surface,
+                EGL_BUFFER_AGE_EXT as EGLint,
+                &mut age,
+            );
+            if ok != egl::FALSE && age > 0 {
+                self.age = age as u32;
+            }
+        })
+    }
+
+    // Forgets the age, for window surfaces whose buffers have been replaced.
+    #[inline]
+    pub(crate) fn reset(&mut self) {
+        self.age = 0;
+    }
+
+    // Records that the window surface renders to its front buffer, which always holds the last
+    // frame presented.
+    #[inline]
+    pub(crate) fn front_buffer(&mut self) {
+        self.age = 1;
+    }
+
+    #[inline]
+    pub(crate) fn get(&self) -> u32 {
+        self.age
+    }
+}
+
+impl PresentFence {
+    // Inserts a fence into the command stream of the current context.
+    pub(crate) unsafe fn insert(egl_display: EGLDisplay) -> PresentFence {
+        EGL_FUNCTIONS.with(|egl| {
+            let egl_sync = if egl.CreateSync.is_loaded() {
+                egl.CreateSync(egl_display, egl::SYNC_FENCE, ptr::null())
+            } else {
+                egl::NO_SYNC
+            };
+            PresentFence {
+                egl_display,
+                egl_sync,
+            }
+        })
+    }
+
+    pub(crate) fn wait(&self, timeout: Duration) -> Result<BufferRelease, Error> {
+        if self.egl_sync == egl::NO_SYNC {
+            return Err(Error::UnsupportedOnThisPlatform);
+        }
+        EGL_FUNCTIONS.with(|egl| unsafe {
+            // Poll first, to tell whether the wait blocks.
+            match egl.ClientWaitSync(self.egl_display, self.egl_sync, 0, 0) as EGLenum {
+                egl::CONDITION_SATISFIED => return Ok(BufferRelease::Released),
+                egl::TIMEOUT_EXPIRED => {}
+                _ => {
+                    let err = egl.GetError().to_windowing_api_error();
+                    return Err(Error::PresentFailed(err));
+                }
+            }
+            let timeout = timeout.as_nanos().min(u64::MAX as u128) as EGLTime;
+            match egl.ClientWaitSync(self.egl_display, self.egl_sync, 0, timeout) as EGLenum {
+                egl::CONDITION_SATISFIED => Ok(BufferRelease::Waited),
+                egl::TIMEOUT_EXPIRED => Ok(BufferRelease::TimedOut),
+                _ => {
+                    let err = egl.GetError().to_windowing_api_error();
+                    Err(Error::PresentFailed(err))
+                }
+            }
+        })
+    }
+}
+
+impl FrameTimestamps {
+    // Turns on the collection of timestamps for a window surface, if the display supports
+    // `EGL_ANDROID_get_frame_timestamps`.
+    pub(crate) unsafe fn new(
+        egl_display: EGLDisplay,
+        egl_surface: EGLSurface,
+    ) -> Option<FrameTimestamps> {
+        if !device::display_supports_extension(egl_display, "EGL_ANDROID_get_frame_timestamps") {
+            return None;
+        }
+        let get_frame_timestamp_supported =
+            EGL_EXTENSION_FUNCTIONS.GetFrameTimestampSupportedANDROID?;
+        EGL_EXTENSION_FUNCTIONS.GetFrameTimestampsANDROID?;
+        EGL_EXTENSION_FUNCTIONS.GetNextFrameIdANDROID?;
+        let names: Vec<EGLint> = FRAME_TIMESTAMP_NAMES
+            .iter()
+            .map(|&name| name as EGLint)
+            .filter(|&name| get_frame_timestamp_supported(egl_display, egl_surface, name) != 0)
+            .collect();
+        if names.is_empty() {
+            return None;
+        }
+        let enabled = EGL_FUNCTIONS.with(|egl| {
+            egl.SurfaceAttrib(
+                egl_display,
+                egl_surface,
+                EGL_TIMESTAMPS_ANDROID as EGLint,
+                egl::TRUE as EGLint,
+            )
+        });
+        if enabled == egl::FALSE {
+            return None;
+        }
+        Some(FrameTimestamps {
+            egl_display,
+            egl_surface,
+            names,
+            frames: VecDeque::new(),
+        })
+    }
+}
+
+impl PresentationFeedback for FrameTimestamps {
+    fn presenting(&mut self, frame_id: u64) -> Result<(), Error> {
+        let get_next_frame_id = EGL_EXTENSION_FUNCTIONS.GetNextFrameIdANDROID.unwrap();
+        let mut egl_frame_id = 0;
+        if get_next_frame_id(self.egl_display, self.egl_surface, &mut egl_frame_id) == egl::FALSE {
+            // The frame's timestamps just aren't reported.
+            return Ok(());
+        }
+        // A frame whose swap failed is presented again under the same `frame_id`.
+        if self
+            .frames
+            .back()
+            .is_some_and(|&(last, _)| last == frame_id)
+        {
+            self.frames.pop_back();
+        }
+        if self.frames.len() == FRAME_TIMESTAMPS_LENGTH {
+            self.frames.pop_front();
+        }
+        self.frames.push_back((frame_id, egl_frame_id));
+        Ok(())
+    }
+
+    fn update(&self, statistics: &mut PresentationStatistics) {
+        let egl_frame_id = match self
+            .frames
+            .iter()
+            .find(|&&(id, _)| id == statistics.frame_id)
+        {
+            Some(&(_, egl_frame_id)) => egl_frame_id,
+            None => return,
+        };
+        let get_frame_timestamps = EGL_EXTENSION_FUNCTIONS.GetFrameTimestampsANDROID.unwrap();
+        let mut values: Vec<EGLnsecsANDROID> = vec![0; self.names.len()];
+        // This fails once EGL has forgotten the frame.
+        if get_frame_timestamps(
+            self.egl_display,
+            self.egl_surface,
+            egl_frame_id,
+            self.names.len() as EGLint,
+            self.names.as_ptr(),
+            values.as_mut_ptr(),
+        ) == egl::FALSE
+        {
+            return;
+        }
+        for (&name, &value) in self.names.iter().zip(values.iter()) {
+            if value == EGL_TIMESTAMP_PENDING_ANDROID || value == EGL_TIMESTAMP_INVALID_ANDROID {
+                continue;
+            }
+            let time = presentation_time::instant_from_monotonic_nanos(value as u64);
+            match name as EGLenum {
+                EGL_REQUESTED_PRESENT_TIME_ANDROID => statistics.requested_present = time,
+                EGL_RENDERING_COMPLETE_TIME_ANDROID => statistics.gpu_finish = time,
+                _ => statistics.actual_present = time,
+            }
+        }
+    }
+}
+
+impl Drop for PresentFence {
+    fn drop(&mut self) {
+        if self.egl_sync != egl::NO_SYNC {
+            EGL_FUNCTIONS.with(|egl| unsafe {
+                egl.DestroySync(self.egl_display, self.egl_sync);
+            })
+        }
+    }
+}
+
+impl EGLFence {
+    // Inserts a fence into the command stream of the current context, and flushes it, so that
+    // contexts waiting on the fence don't wait for this one to flush.
+    //
+    // Where `EGL_ANDROID_native_fence_sync` is supported, the fence is a native fence, which the
+    // flush gives a sync file that `export_sync_fd()` can duplicate.
+    pub(crate) unsafe fn insert(gl: &Gl, egl_display: EGLDisplay) -> Result<EGLFence, Error> {
+        EGL_FUNCTIONS.with(|egl| {
+            if !egl.CreateSync.is_loaded() {
+                return Err(Error::RequiredExtensionUnavailable);
+            }
+            let native = supports_native_fences(egl_display);
+            let egl_sync = if native {
+                let attributes = [
+                    EGL_SYNC_NATIVE_FENCE_FD_ANDROID as EGLAttrib,
+                    EGL_NO_NATIVE_FENCE_FD_ANDROID as EGLAttrib,
+                    egl::NONE as EGLAttrib,
+                ];
+                let sync_type = EGL_SYNC_NATIVE_FENCE_ANDROID;
+                egl.CreateSync(egl_display, sync_type, attributes.as_ptr())
+            } else {
+                egl.CreateSync(egl_display, egl::SYNC_FENCE, ptr::null())
+            };
+            if egl_sync == egl::NO_SYNC {
+                let err = egl.GetError().to_windowing_api_error();
+                return Err(Error::FenceFailed(err));
+            }
+            gl.Flush();
+            Ok(EGLFence {
+                egl_display,
+                egl_sync,
+                native,
+            })
+        })
+    }
+
+    // Creates a native fence that signals when the sync file `fd` does, for the current context,
+    // which belongs to `egl_display`. `fd` is duplicated, and EGL takes the duplicate.
+    #[cfg(unix)]
+    pub(crate) unsafe fn import(
+        egl_display: EGLDisplay,
+        fd: BorrowedFd,
+    ) -> Result<EGLFence, Error> {
+        EGL_FUNCTIONS.with(|egl| {
+            if !egl.CreateSync.is_loaded() || !supports_native_fences(egl_display) {
+                return Err(Error::RequiredExtensionUnavailable);
+            }
+            let fd = fd
+                .try_clone_to_owned()
+                .map_err(|_| Error::FenceFailed(WindowingApiError::Failed))?;
+            let attributes = [
+                EGL_SYNC_NATIVE_FENCE_FD_ANDROID as EGLAttrib,
+                fd.as_raw_fd() as EGLAttrib,
+                egl::NONE as EGLAttrib,
+            ];
+            let sync_type = EGL_SYNC_NATIVE_FENCE_ANDROID;
+            let egl_sync = egl.CreateSync(egl_display, sync_type, attributes.as_ptr());
+            if egl_sync == egl::NO_SYNC {
+                // EGL only takes the descriptor if it succeeds, so it's closed on drop here.
+                let err = egl.GetError().to_windowing_api_error();
+                return Err(Error::FenceFailed(err));
+            }
+            let _ = fd.into_raw_fd();
+            Ok(EGLFence {
+                egl_display,
+                egl_sync,
+                native: true,
+            })
+        })
+    }
+
+    // Returns a new sync file that signals when the fence does.
+    #[cfg(unix)]
+    pub(crate) fn export_sync_fd(&self) -> Result<OwnedFd, Error> {
+        let dup_native_fence_fd = match EGL_EXTENSION_FUNCTIONS.DupNativeFenceFDANDROID {
+            Some(dup_native_fence_fd) if self.native => dup_native_fence_fd,
+            _ => return Err(Error::RequiredExtensionUnavailable),
+        };
+        let fd = dup_native_fence_fd(self.egl_display, self.egl_sync);
+        if fd == EGL_NO_NATIVE_FENCE_FD_ANDROID {
+            let err = EGL_FUNCTIONS.with(|egl| unsafe { egl.GetError() });
+            return Err(Error::FenceFailed(err.to_windowing_api_error()));
+        }
+        unsafe { Ok(OwnedFd::from_raw_fd(fd)) }
+    }
+
+    // Makes the current context, which belongs to `egl_display`, wait on the GPU until the fence
+    // signals.
+    pub(crate) unsafe fn wait(&self, egl_display: EGLDisplay) -> Result<(), Error> {
+        if egl_display != self.egl_display {
+            return Err(Error::IncompatibleSurfaceFence);
+        }
+        EGL_FUNCTIONS.with(|egl| {
+            if !egl.WaitSync.is_loaded() {
+                return Err(Error::RequiredExtensionUnavailable);
+            }
+            if egl.WaitSync(egl_display, self.egl_sync, 0) == egl::FALSE {
+                let err = egl.GetError().to_windowing_api_error();
+                return Err(Error::FenceFailed(err));
+            }
+            Ok(())
+        })
+    }
+}
+
+// Returns true if the display can create native fences and export them as sync files.
+unsafe fn supports_native_fences(egl_display: EGLDisplay) -> bool {
+    EGL_EXTENSION_FUNCTIONS.DupNativeFenceFDANDROID.is_some()
+        && device::display_supports_extension(egl_display, "EGL_ANDROID_native_fence_sync")
+}
+
+impl Drop for EGLFence {
+    fn drop(&mut self) {
+        EGL_FUNCTIONS.with(|egl| unsafe {
+            egl.DestroySync(self.egl_display, self.egl_sync);
+        })
+    }
+}
@@ -2,9 +2,10 @@
 //
 //! Backends that are not specific to any operating system.
 
-#[cfg(any(android_platform, angle, free_unix, ohos_platform))]
+#[cfg(any(android_backend, angle, generic_egl_platform, ohos_backend))]
 pub(crate) mod egl;
 
+#[cfg(any(android_backend, angle, generic_egl_platform, ohos_backend))]
 pub use egl::{context::ContextDescriptor, device::get_proc_address_raw};
 
 pub mod multi;
@@ -8,6 +8,7 @@ use crate::connection::Connection as ConnectionInterface;
 use crate::connection::NativeConnection as NativeConnectionInterface;
 use crate::device::Device as DeviceInterface;
 use crate::egl::types::EGLDisplay;
+use crate::AdapterKey;
 use crate::Error;
 use crate::GLApi;
 
@@ -16,6 +17,8 @@ use euclid::default::Size2D;
 use std::os::raw::c_void;
 
 /// A connection to the display server.
+///
+/// This is `Send` and `Sync` whenever both the default and alternate connections are.
 pub enum Connection<Def, Alt>
 where
     Def: DeviceInterface,
@@ -81,11 +84,79 @@ where
     Alt::Connection: ConnectionInterface<Device = Alt>,
 {
     /// Connects to the default display.
+    ///
+    /// On the X11 and Wayland backends, the `SURFMAN_DISPLAY` environment variable overrides
+    /// the default display. It can name either an X11 display, such as `:1`, or a Wayland
+    /// socket, such as `wayland-1`: each backend tries to connect to it in turn, so only the
+    /// backend that understands the name succeeds.
+    ///
+    /// A default backend that fails has already closed whatever display it opened, so nothing of
+    /// it is left behind when the alternate backend is tried. If both backends fail, returns
+    /// `Error::MultipleFailures` with both errors.
     #[inline]
     pub fn new() -> Result<Connection<Def, Alt>, Error> {
         match <Def::Connection>::new() {
             Ok(connection) => Ok(Connection::Default(connection)),
-            Err(_) => <Alt::Connection>::new().map(Connection::Alternate),
+            Err(default_error) => match <Alt::Connection>::new() {
+                Ok(connection) => Ok(Connection::Alternate(connection)),
+                Err(alternate_error) => {
+                    crate::diagnostics::warn_if_sandboxed();
+                    Err(Error::MultipleFailures(
+                        Box::new(default_error),
+                        Box::new(alternate_error),
+                    ))
+                }
+            },
+        }
+    }
+
+    /// Opens a connection that needs no display server, trying the default backend first.
+    ///
+    /// Only generic surfaces can be created on the resulting connection.
+    #[inline]
+    pub fn new_headless() -> Result<Connection<Def, Alt>, Error> {
+        match <Def::Connection>::new_headless() {
+            Ok(connection) => Ok(Connection::Default(connection)),
+            Err(default_error) => match <Alt::Connection>::new_headless() {
+                Ok(connection) => Ok(Connection::Alternate(connection)),
+                Err(alternate_error) => Err(Error::MultipleFailures(
+                    Box::new(default_error),
+                    Box::new(alternate_error),
+                )),
+            },
+        }
+    }
+
+    /// Connects to the default display, creating contexts for the given OpenGL API flavor.
+    #[inline]
+    pub fn new_with_api(gl_api: GLApi) -> Result<Connection<Def, Alt>, Error> {
+        match <Def::Connection>::new_with_api(gl_api) {
+            Ok(connection) => Ok(Connection::Default(connection)),
+            Err(default_error) => match <Alt::Connection>::new_with_api(gl_api) {
+                Ok(connection) => Ok(Connection::Alternate(connection)),
+                Err(alternate_error) => Err(Error::MultipleFailures(
+                    Box::new(default_error),
+                    Box::new(alternate_error),
+                )),
+            },
+        }
+    }
+
+    /// Wraps an existing native connection without taking ownership of it.
+    ///
+    /// The connection is of the same type as the native connection.
+    pub unsafe fn from_native_connection(
+        native_connection: NativeConnection<Def, Alt>,
+    ) -> Result<Connection<Def, Alt>, Error> {
+        match native_connection {
+            NativeConnection::Default(native_connection) => {
+                <Def::Connection>::from_native_connection(native_connection)
+                    .map(Connection::Default)
+            }
+            NativeConnection::Alternate(native_connection) => {
+                <Alt::Connection>::from_native_connection(native_connection)
+                    .map(Connection::Alternate)
+            }
         }
     }
 
@@ -109,6 +180,15 @@ where
         }
     }
 
+    /// Returns true if the window system binding advertises the named extension, either for
+    /// this connection's display or as a client extension.
+    pub fn supports_extension(&self, name: &str) -> bool {
+        match *self {
+            Connection::Default(ref connection) => connection.supports_extension(name),
+            Connection::Alternate(ref connection) => connection.supports_extension(name),
+        }
+    }
+
     /// Returns the "best" adapter on this system.
     ///
     /// This is an alias for `Connection::create_hardware_adapter()`.
@@ -159,8 +239,108 @@ where
         }
     }
 
+    /// Returns the adapter whose driver or device name contains `needle`, ignoring case.
+    ///
+    /// The backend this connection was opened with is searched first. If none of its adapters
+    /// match, the other backend is searched through a headless connection, and an adapter found
+    /// there belongs to that backend. `create_device()` opens such an adapter headlessly, so the
+    /// device can only create generic surfaces. Returns `Error::NoAdapterFound` if neither
+    /// backend has a match.
+    pub fn create_adapter_by_name(&self, needle: &str) -> Result<Adapter<Def, Alt>, Error> {
+        match *self {
+            Connection::Default(ref connection) => {
+                match connection.create_adapter_by_name(needle) {
+                    Err(Error::NoAdapterFound) => <Alt::Connection>::new_headless()
+                        .and_then(|connection| connection.create_adapter_by_name(needle))
+                        .map(Adapter::Alternate)
+                        .map_err(|_| Error::NoAdapterFound),
+                    result => result.map(Adapter::Default),
+                }
+            }
+            Connection::Alternate(ref connection) => {
+                match connection.create_adapter_by_name(needle) {
+                    Err(Error::NoAdapterFound) => <Def::Connection>::new_headless()
+                        .and_then(|connection| connection.create_adapter_by_name(needle))
+                        .map(Adapter::Default)
+                        .map_err(|_| Error::NoAdapterFound),
+                    result => result.map(Adapter::Alternate),
+                }
+            }
+        }
+    }
+
+    /// Returns a key that describes the adapter, which can be saved and passed to
+    /// `find_adapter()` in a later run to find the same adapter again.
+    ///
+    /// The key names the backend that enumerated the adapter, so that it is never matched with an
+    /// adapter from the other backend. Returns `Error::IncompatibleAdapter` if the adapter
+    /// belongs to the other backend.
+    pub fn adapter_key(&self, adapter: &Adapter<Def, Alt>) -> Result<AdapterKey, Error> {
+        match (self, adapter) {
+            (Connection::Default(connection), Adapter::Default(adapter)) => {
+                connection.adapter_key(adapter)
+            }
+            (Connection::Alternate(connection), Adapter::Alternate(adapter)) => {
+                connection.adapter_key(adapter)
+            }
+            _ => Err(Error::IncompatibleAdapter),
+        }
+    }
+
+    /// Returns the adapter that a key from `adapter_key()` describes.
+    ///
+    /// Returns `Error::IncompatibleAdapter` if the key was taken with another backend, including
+    /// the other backend of this connection, and `Error::NoAdapterFound` if the adapter's
+    /// hardware is no longer present.
+    pub fn find_adapter(&self, key: &AdapterKey) -> Result<Adapter<Def, Alt>, Error> {
+        match *self {
+            Connection::Default(ref connection) => {
+                connection.find_adapter(key).map(Adapter::Default)
+            }
+            Connection::Alternate(ref connection) => {
+                connection.find_adapter(key).map(Adapter::Alternate)
+            }
+        }
+    }
+
+    /// Returns true if the two adapters are the same adapter.
+    ///
+    /// Adapters from different backends are never the same.
+    pub fn is_same_adapter(&self, a: &Adapter<Def, Alt>, b: &Adapter<Def, Alt>) -> bool {
+        match (self, a, b) {
+            (Connection::Default(connection), Adapter::Default(a), Adapter::Default(b)) => {
+                connection.is_same_adapter(a, b)
+            }
+            (Connection::Alternate(connection), Adapter::Alternate(a), Adapter::Alternate(b)) => {
+                connection.is_same_adapter(a, b)
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the adapter for the GPU that drives the output the window is on.
+    pub fn adapter_for_widget(
+        &self,
+        native_widget: &NativeWidget<Def, Alt>,
+    ) -> Result<Adapter<Def, Alt>, Error> {
+        match (self, native_widget) {
+            (Connection::Default(connection), NativeWidget::Default(native_widget)) => connection
+                .adapter_for_widget(native_widget)
+                .map(Adapter::Default),
+            (Connection::Alternate(connection), NativeWidget::Alternate(native_widget)) => {
+                connection
+                    .adapter_for_widget(native_widget)
+                    .map(Adapter::Alternate)
+            }
+            _ => Err(Error::IncompatibleNativeWidget),
+        }
+    }
+
     /// Opens the hardware device corresponding to the given adapter.
     ///
+    /// An adapter of the other backend, as `create_adapter_by_name()` may return, is opened with
+    /// a headless connection of that backend. The device can then only create generic surfaces.
+    ///
     /// Device handles are local to a single thread.
     pub fn create_device(&self, adapter: &Adapter<Def, Alt>) -> Result<Device<Def, Alt>, Error> {
         match (self, adapter) {
@@ -170,7 +350,16 @@ where
             (Connection::Alternate(connection), Adapter::Alternate(adapter)) => {
                 connection.create_device(adapter).map(Device::Alternate)
             }
-            _ => Err(Error::IncompatibleAdapter),
+            (Connection::Default(_), Adapter::Alternate(adapter)) => {
+                <Alt::Connection>::new_headless()?
+                    .create_device(adapter)
+                    .map(Device::Alternate)
+            }
+            (Connection::Alternate(_), Adapter::Default(adapter)) => {
+                <Def::Connection>::new_headless()?
+                    .create_device(adapter)
+                    .map(Device::Default)
+            }
         }
     }
 
@@ -203,9 +392,13 @@ where
     ) -> Result<Connection<Def, Alt>, Error> {
         match <Def::Connection>::from_raw_display_handle(raw_handle) {
             Ok(connection) => Ok(Connection::Default(connection)),
-            Err(_) => {
-                <Alt::Connection>::from_raw_display_handle(raw_handle).map(Connection::Alternate)
-            }
+            Err(default_error) => match <Alt::Connection>::from_raw_display_handle(raw_handle) {
+                Ok(connection) => Ok(Connection::Alternate(connection)),
+                Err(alternate_error) => Err(Error::MultipleFailures(
+                    Box::new(default_error),
+                    Box::new(alternate_error),
+                )),
+            },
         }
     }
 
@@ -216,7 +409,13 @@ where
     ) -> Result<Connection<Def, Alt>, Error> {
         match <Def::Connection>::from_display_handle(handle) {
             Ok(connection) => Ok(Connection::Default(connection)),
-            Err(_) => <Alt::Connection>::from_display_handle(handle).map(Connection::Alternate),
+            Err(default_error) => match <Alt::Connection>::from_display_handle(handle) {
+                Ok(connection) => Ok(Connection::Alternate(connection)),
+                Err(alternate_error) => Err(Error::MultipleFailures(
+                    Box::new(default_error),
+                    Box::new(alternate_error),
+                )),
+            },
         }
     }
 
@@ -269,6 +468,23 @@ where
                 .map(NativeWidget::Alternate),
         }
     }
+
+    /// Returns the real size of a native widget if it differs from the size that the widget was
+    /// created with.
+    pub fn native_widget_adjusted_size(
+        &self,
+        native_widget: &NativeWidget<Def, Alt>,
+    ) -> Option<Size2D<i32>> {
+        match (self, native_widget) {
+            (Connection::Default(connection), NativeWidget::Default(native_widget)) => {
+                connection.native_widget_adjusted_size(native_widget)
+            }
+            (Connection::Alternate(connection), NativeWidget::Alternate(native_widget)) => {
+                connection.native_widget_adjusted_size(native_widget)
+            }
+            _ => panic!("Incompatible native widget!"),
+        }
+    }
 }
 
 impl<Def, Alt> ConnectionInterface for Connection<Def, Alt>
@@ -289,6 +505,23 @@ where
         Connection::new()
     }
 
+    #[inline]
+    fn new_headless() -> Result<Connection<Def, Alt>, Error> {
+        Connection::new_headless()
+    }
+
+    #[inline]
+    fn new_with_api(gl_api: GLApi) -> Result<Connection<Def, Alt>, Error> {
+        Connection::new_with_api(gl_api)
+    }
+
+    #[inline]
+    unsafe fn from_native_connection(
+        native_connection: NativeConnection<Def, Alt>,
+    ) -> Result<Connection<Def, Alt>, Error> {
+        Connection::from_native_connection(native_connection)
+    }
+
     #[inline]
     fn native_connection(&self) -> NativeConnection<Def, Alt> {
         Connection::native_connection(self)
@@ -299,6 +532,11 @@ where
         Connection::gl_api(self)
     }
 
+    #[inline]
+    fn supports_extension(&self, name: &str) -> bool {
+        Connection::supports_extension(self, name)
+    }
+
     #[inline]
     fn create_adapter(&self) -> Result<Adapter<Def, Alt>, Error> {
         Connection::create_adapter(self)
@@ -319,6 +557,34 @@ where
         Connection::create_software_adapter(self)
     }
 
+    #[inline]
+    fn create_adapter_by_name(&self, needle: &str) -> Result<Adapter<Def, Alt>, Error> {
+        Connection::create_adapter_by_name(self, needle)
+    }
+
+    #[inline]
+    fn adapter_key(&self, adapter: &Adapter<Def, Alt>) -> Result<AdapterKey, Error> {
+        Connection::adapter_key(self, adapter)
+    }
+
+    #[inline]
+    fn find_adapter(&self, key: &AdapterKey) -> Result<Adapter<Def, Alt>, Error> {
+        Connection::find_adapter(self, key)
+    }
+
+    #[inline]
+    fn is_same_adapter(&self, a: &Adapter<Def, Alt>, b: &Adapter<Def, Alt>) -> bool {
+        Connection::is_same_adapter(self, a, b)
+    }
+
+    #[inline]
+    fn adapter_for_widget(
+        &self,
+        native_widget: &NativeWidget<Def, Alt>,
+    ) -> Result<Adapter<Def, Alt>, Error> {
+        Connection::adapter_for_widget(self, native_widget)
+    }
+
     #[inline]
     fn create_device(&self, adapter: &Adapter<Def, Alt>) -> Result<Device<Def, Alt>, Error> {
         Connection::create_device(self, adapter)
@@ -370,4 +636,12 @@ where
     ) -> Result<Self::NativeWidget, Error> {
         Connection::create_native_widget_from_window_handle(self, handle, size)
     }
+
+    #[inline]
+    fn native_widget_adjusted_size(
+        &self,
+        native_widget: &NativeWidget<Def, Alt>,
+    ) -> Option<Size2D<i32>> {
+        Connection::native_widget_adjusted_size(self, native_widget)
+    }
 }
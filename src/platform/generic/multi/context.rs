@@ -4,11 +4,26 @@
 
 use super::device::Device;
 use super::surface::Surface;
+use crate::bulk::{self, BulkOps};
+use crate::connection::Connection as ConnectionInterface;
 use crate::context::{ContextDescriptorInterface, NativeContext as NativeContextInterface};
 use crate::device::Device as DeviceInterface;
-use crate::{ContextAttributes, ContextID, Error, SurfaceInfo};
+use crate::ContextDescriptorAttributes;
+use crate::ContextLostHandler;
+use crate::ContextPriority;
+use crate::GlError;
+use crate::SurfaceInfo;
+use crate::{ContextAttributeFlags, GLVersion};
+use crate::{
+    ContextAttributes, ContextID, ContextInfo, Error, GLCapabilities, RenderTargetInfo, ResetStatus,
+};
 
+use std::ops::RangeInclusive;
 use std::os::raw::c_void;
+use std::thread::ThreadId;
+
+// A context along with the descriptor that it was created from.
+type VersionedContext<Def, Alt> = (Context<Def, Alt>, ContextDescriptor<Def, Alt>);
 
 /// Represents an OpenGL rendering context.
 ///
@@ -40,7 +55,6 @@ where
 /// Information needed to create a context. Some APIs call this a "config" or a "pixel format".
 ///
 /// These are local to a device.
-#[derive(Clone)]
 pub enum ContextDescriptor<Def, Alt>
 where
     Def: DeviceInterface,
@@ -51,6 +65,25 @@ where
     /// The alternate context descriptor type.
     Alternate(Alt::ContextDescriptor),
 }
+
+impl<Def, Alt> Clone for ContextDescriptor<Def, Alt>
+where
+    Def: DeviceInterface,
+    Alt: DeviceInterface,
+    Def::ContextDescriptor: Clone,
+    Alt::ContextDescriptor: Clone,
+{
+    fn clone(&self) -> Self {
+        match self {
+            ContextDescriptor::Default(ref descriptor) => {
+                ContextDescriptor::Default(descriptor.clone())
+            }
+            ContextDescriptor::Alternate(ref descriptor) => {
+                ContextDescriptor::Alternate(descriptor.clone())
+            }
+        }
+    }
+}
 impl<Def, Alt> ContextDescriptorInterface for ContextDescriptor<Def, Alt>
 where
     Def: DeviceInterface,
@@ -96,6 +129,21 @@ where
     }
 }
 
+/// Makes the previously current context current again when dropped.
+///
+/// Returned by `make_context_current_guarded()`.
+#[must_use]
+pub enum CurrentContextGuard<Def, Alt>
+where
+    Def: DeviceInterface,
+    Alt: DeviceInterface,
+{
+    /// The default guard type.
+    Default(Def::CurrentContextGuard),
+    /// The alternate guard type.
+    Alternate(Alt::CurrentContextGuard),
+}
+
 /// Wraps a platform-specific native context.
 pub enum NativeContext<Def, Alt>
 where
@@ -180,6 +228,37 @@ where
         }
     }
 
+    /// Creates a context of the newest OpenGL or OpenGL ES version in `versions` that this device
+    /// supports, and returns it along with the descriptor that it was created from.
+    ///
+    /// The backend remembers the version that it settles on, so later calls with the same range
+    /// and flags don't search again. If the device supports no version in the range, this returns
+    /// `Error::UnsupportedGLVersion`.
+    pub fn create_versioned_context(
+        &mut self,
+        versions: RangeInclusive<GLVersion>,
+        flags: ContextAttributeFlags,
+    ) -> Result<VersionedContext<Def, Alt>, Error> {
+        match *self {
+            Device::Default(ref mut device) => device
+                .create_versioned_context(versions, flags)
+                .map(|(context, descriptor)| {
+                    (
+                        Context::Default(context),
+                        ContextDescriptor::Default(descriptor),
+                    )
+                }),
+            Device::Alternate(ref mut device) => device
+                .create_versioned_context(versions, flags)
+                .map(|(context, descriptor)| {
+                    (
+                        Context::Alternate(context),
+                        ContextDescriptor::Alternate(descriptor),
+                    )
+                }),
+        }
+    }
+
     /// Wraps an existing native context in a `Context` object.
     pub unsafe fn create_context_from_native_context(
         &self,
@@ -229,6 +308,21 @@ where
         }
     }
 
+    /// Returns the native context that is current on this thread, wrapped for this device's
+    /// backend.
+    ///
+    /// If no context is current, this returns a `NoCurrentContext` error.
+    pub fn current_native_context(&self) -> Result<NativeContext<Def, Alt>, Error> {
+        match *self {
+            Device::Default(ref device) => {
+                device.current_native_context().map(NativeContext::Default)
+            }
+            Device::Alternate(ref device) => device
+                .current_native_context()
+                .map(NativeContext::Alternate),
+        }
+    }
+
     /// Returns the descriptor that this context was created with.
     pub fn context_descriptor(&self, context: &Context<Def, Alt>) -> ContextDescriptor<Def, Alt> {
         match (self, context) {
@@ -257,6 +351,25 @@ where
         }
     }
 
+    /// Makes the context current for this thread, returning a guard that makes the previously
+    /// current context current again when dropped.
+    ///
+    /// The previous context is restored even if the thread panics while the guard is alive.
+    pub fn make_context_current_guarded(
+        &self,
+        context: &Context<Def, Alt>,
+    ) -> Result<CurrentContextGuard<Def, Alt>, Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => device
+                .make_context_current_guarded(context)
+                .map(CurrentContextGuard::Default),
+            (Device::Alternate(device), Context::Alternate(context)) => device
+                .make_context_current_guarded(context)
+                .map(CurrentContextGuard::Alternate),
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
     /// Removes the current OpenGL context from this thread.
     ///
     /// After calling this function, OpenGL rendering commands will fail until a new context is
@@ -268,14 +381,32 @@ where
         }
     }
 
+    /// Returns the thread that the context is current on, or `None` if it isn't current anywhere.
+    ///
+    /// Only contexts made current through surfman are tracked.
+    pub fn context_current_thread(&self, context: &Context<Def, Alt>) -> Option<ThreadId> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => {
+                device.context_current_thread(context)
+            }
+            (Device::Alternate(device), Context::Alternate(context)) => {
+                device.context_current_thread(context)
+            }
+            _ => None,
+        }
+    }
+
     /// Attaches a surface to a context for rendering.
     ///
-    /// This function takes ownership of the surface. The surface must have been created with this
-    /// context, or an `IncompatibleSurface` error is returned.
+    /// This function takes ownership of the surface. A surface belongs to the context that it was
+    /// created with, so binding it to any other context returns a `SurfaceAlreadyBound` error
+    /// naming that context. Check `Surface::context_id()` beforehand to avoid this, and wrap the
+    /// surface in a surface texture to read it from another context instead.
     ///
-    /// If this function is called with a surface already bound, a `SurfaceAlreadyBound` error is
-    /// returned. To avoid this error, first unbind the existing surface with
-    /// `unbind_surface_from_context`.
+    /// If this context already has a surface bound, a `SurfaceAlreadyBound` error naming this
+    /// context is returned. To avoid this error, first unbind the existing surface with
+    /// `unbind_surface_from_context`. A surface from the other backend returns
+    /// `IncompatibleSurface`.
     ///
     /// If an error is returned, the surface is returned alongside it.
     pub fn bind_surface_to_context(
@@ -333,22 +464,53 @@ where
             (Device::Alternate(device), ContextDescriptor::Alternate(context_descriptor)) => {
                 device.context_descriptor_attributes(context_descriptor)
             }
-            _ => panic!("Incompatible context!"),
+            _ => panic!("Incompatible context descriptor!"),
+        }
+    }
+
+    /// Returns the properties of the context descriptor as plain data, which can be sent to
+    /// another process.
+    pub fn context_descriptor_raw_parts(
+        &self,
+        context_descriptor: &ContextDescriptor<Def, Alt>,
+    ) -> ContextDescriptorAttributes {
+        match (self, context_descriptor) {
+            (Device::Default(device), ContextDescriptor::Default(context_descriptor)) => {
+                device.context_descriptor_raw_parts(context_descriptor)
+            }
+            (Device::Alternate(device), ContextDescriptor::Alternate(context_descriptor)) => {
+                device.context_descriptor_raw_parts(context_descriptor)
+            }
+            _ => panic!("Incompatible context descriptor!"),
         }
     }
 
-    /// Fetches the address of an OpenGL function associated with this context.
+    /// Creates a context descriptor equivalent to the one that `attributes` were taken from,
+    /// possibly on a device in another process.
+    pub fn create_context_descriptor_from_attributes(
+        &self,
+        attributes: &ContextDescriptorAttributes,
+    ) -> Result<ContextDescriptor<Def, Alt>, Error> {
+        match *self {
+            Device::Default(ref device) => device
+                .create_context_descriptor_from_attributes(attributes)
+                .map(ContextDescriptor::Default),
+            Device::Alternate(ref device) => device
+                .create_context_descriptor_from_attributes(attributes)
+                .map(ContextDescriptor::Alternate),
+        }
+    }
+
+    /// Fetches the address of an OpenGL function associated with this context, or `None` if the
+    /// function isn't available.
     ///
     /// OpenGL functions are local to a context. You should not use OpenGL functions on one context
     /// with any other context.
-    ///
-    /// This method is typically used with a function like `gl::load_with()` from the `gl` crate to
-    /// load OpenGL function pointers.
     pub fn get_proc_address(
         &self,
         context: &Context<Def, Alt>,
         symbol_name: &str,
-    ) -> *const c_void {
+    ) -> Option<*const c_void> {
         match (self, context) {
             (Device::Default(device), Context::Default(context)) => {
                 device.get_proc_address(context, symbol_name)
@@ -360,6 +522,23 @@ where
         }
     }
 
+    /// Returns a function that looks up the OpenGL functions of a context, for loaders like
+    /// `gl::load_with()` that expect a null pointer for functions that aren't available.
+    pub fn proc_address_loader<'a>(
+        &'a self,
+        context: &'a Context<Def, Alt>,
+    ) -> Box<dyn Fn(&str) -> *const c_void + 'a> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => {
+                device.proc_address_loader(context)
+            }
+            (Device::Alternate(device), Context::Alternate(context)) => {
+                device.proc_address_loader(context)
+            }
+            _ => panic!("Incompatible context!"),
+        }
+    }
+
     /// Returns a unique ID representing a context.
     ///
     /// This ID is unique to all currently-allocated contexts. If you destroy a context and create
@@ -372,6 +551,19 @@ where
         }
     }
 
+    /// Returns true if the given context is the current OpenGL context for this thread.
+    pub fn is_context_current(&self, context: &Context<Def, Alt>) -> bool {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => {
+                device.is_context_current(context)
+            }
+            (Device::Alternate(device), Context::Alternate(context)) => {
+                device.is_context_current(context)
+            }
+            _ => false,
+        }
+    }
+
     /// Returns various information about the surface attached to a context.
     ///
     /// This includes, most notably, the OpenGL framebuffer object needed to render to the surface.
@@ -389,4 +581,187 @@ where
             _ => Err(Error::IncompatibleContext),
         }
     }
+
+    /// Makes the context current, binds the framebuffer of its surface, and sets the viewport to
+    /// cover that surface.
+    ///
+    /// If no surface is attached, the context is still made current, the default framebuffer is
+    /// bound, and the viewport is left unchanged; the returned info then has a zero size and a
+    /// format of `GL_NONE`. On error, the previously-current context is restored.
+    pub fn begin_rendering(&self, context: &Context<Def, Alt>) -> Result<RenderTargetInfo, Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => device.begin_rendering(context),
+            (Device::Alternate(device), Context::Alternate(context)) => {
+                device.begin_rendering(context)
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Finishes rendering started with `begin_rendering()` by unbinding the surface's framebuffer,
+    /// flushing the context if `flush` is true.
+    ///
+    /// The context that was current before this call remains current afterward.
+    pub fn end_rendering(&self, context: &Context<Def, Alt>, flush: bool) -> Result<(), Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => {
+                device.end_rendering(context, flush)
+            }
+            (Device::Alternate(device), Context::Alternate(context)) => {
+                device.end_rendering(context, flush)
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Returns the OpenGL features beyond OpenGL ES 2.0 that the given context supports.
+    ///
+    /// The context that was current before this call remains current afterward.
+    pub fn capabilities(&self, context: &Context<Def, Alt>) -> Result<GLCapabilities, Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => device.capabilities(context),
+            (Device::Alternate(device), Context::Alternate(context)) => {
+                device.capabilities(context)
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Returns the names of the OpenGL extensions that the given context supports.
+    ///
+    /// The context that was current before this call remains current afterward.
+    pub fn gl_extensions(&self, context: &Context<Def, Alt>) -> Result<Vec<String>, Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => device.gl_extensions(context),
+            (Device::Alternate(device), Context::Alternate(context)) => {
+                device.gl_extensions(context)
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Returns what OpenGL reports about the given context: the version, profile, and flags that
+    /// it was actually granted, and the vendor and renderer strings.
+    ///
+    /// The context that was current before this call remains current afterward.
+    pub fn context_info(&self, context: &Context<Def, Alt>) -> Result<ContextInfo, Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => device.context_info(context),
+            (Device::Alternate(device), Context::Alternate(context)) => {
+                device.context_info(context)
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Returns true if the given context renders on a GPU, and false if it renders in software.
+    ///
+    /// A context from the other backend returns false.
+    pub fn context_is_hardware_accelerated(&self, context: &Context<Def, Alt>) -> bool {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => {
+                device.context_is_hardware_accelerated(context)
+            }
+            (Device::Alternate(device), Context::Alternate(context)) => {
+                device.context_is_hardware_accelerated(context)
+            }
+            _ => false,
+        }
+    }
+
+    /// Empties the OpenGL error queue of the given context, returning each error that
+    /// `glGetError()` reported until it reported none.
+    ///
+    /// The context that was current before this call remains current afterward.
+    pub fn drain_gl_errors(&self, context: &Context<Def, Alt>) -> Result<Vec<GlError>, Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => device.drain_gl_errors(context),
+            (Device::Alternate(device), Context::Alternate(context)) => {
+                device.drain_gl_errors(context)
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Returns whether the given context has been lost to a GPU reset.
+    ///
+    /// Only contexts created with `ContextAttributeFlags::ROBUST_ACCESS` are notified of resets.
+    /// The context that was current before this call remains current afterward.
+    pub fn context_reset_status(&self, context: &Context<Def, Alt>) -> Result<ResetStatus, Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => {
+                device.context_reset_status(context)
+            }
+            (Device::Alternate(device), Context::Alternate(context)) => {
+                device.context_reset_status(context)
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Returns true if the given context has been lost, for example to a GPU reset.
+    ///
+    /// Once a context is lost, `make_context_current()` returns `Error::ContextLost`, and the
+    /// context can only be destroyed. A context from the other backend is never reported lost.
+    /// The context that was current before this call remains current afterward.
+    pub fn poll_context_lost(&self, context: &Context<Def, Alt>) -> bool {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => {
+                device.poll_context_lost(context)
+            }
+            (Device::Alternate(device), Context::Alternate(context)) => {
+                device.poll_context_lost(context)
+            }
+            _ => false,
+        }
+    }
+
+    /// Registers a function to call with the ID of each context of this device that is found to
+    /// be lost, replacing any previous one, or unregisters it if `handler` is `None`.
+    pub fn set_context_lost_handler(&mut self, handler: Option<ContextLostHandler>) {
+        match *self {
+            Device::Default(ref mut device) => device.set_context_lost_handler(handler),
+            Device::Alternate(ref mut device) => device.set_context_lost_handler(handler),
+        }
+    }
+
+    /// Returns the scheduling priority that the given context was granted.
+    ///
+    /// This may be lower than the priority requested in the context attributes. Platforms that
+    /// can't prioritize contexts report `ContextPriority::Medium`.
+    pub fn context_priority(&self, context: &Context<Def, Alt>) -> ContextPriority {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => {
+                device.context_priority(context)
+            }
+            (Device::Alternate(device), Context::Alternate(context)) => {
+                device.context_priority(context)
+            }
+            _ => panic!("Incompatible context!"),
+        }
+    }
+}
+
+impl<Def, Alt> Device<Def, Alt>
+where
+    Def: DeviceInterface,
+    Alt: DeviceInterface,
+    Def::Connection: ConnectionInterface<Device = Def>,
+    Alt::Connection: ConnectionInterface<Device = Alt>,
+{
+    /// Makes the context current for this thread, runs `f` with the operations that can be run
+    /// on the context, and then makes the previously current context current again.
+    ///
+    /// Each operation is dispatched to the wrapped device, which skips making the context current
+    /// for it.
+    pub fn with_context_current<F, R>(
+        &self,
+        context: &mut Context<Def, Alt>,
+        f: F,
+    ) -> Result<R, Error>
+    where
+        F: FnOnce(&mut BulkOps<Self>) -> R,
+    {
+        bulk::with_context_current(self, context, f)
+    }
 }
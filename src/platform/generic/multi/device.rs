@@ -3,16 +3,50 @@
 //! A device abstraction that allows the choice of backends dynamically.
 
 use super::connection::Connection;
-use super::context::{Context, ContextDescriptor, NativeContext};
-use super::surface::{NativeWidget, Surface, SurfaceTexture};
+use super::context::{Context, ContextDescriptor, CurrentContextGuard, NativeContext};
+use super::surface::{NativeWidget, Surface, SurfaceDataGuard, SurfaceTexture};
 use crate::connection::Connection as ConnectionInterface;
-use crate::context::ContextAttributes;
+use crate::context::{ContextAttributeFlags, ContextAttributes, ContextDescriptorAttributes};
 use crate::device::Device as DeviceInterface;
 use crate::gl::types::{GLenum, GLuint};
-use crate::{ContextID, Error, GLApi, SurfaceAccess, SurfaceInfo, SurfaceType};
-use euclid::default::Size2D;
-
+use crate::BufferRelease;
+use crate::BulkOps;
+use crate::CancelToken;
+use crate::ContextLostHandler;
+use crate::DebugFill;
+use crate::DeviceIdentity;
+#[cfg(unix)]
+use crate::DmaBuf;
+use crate::GLVersion;
+use crate::GlError;
+use crate::InvalidateSet;
+use crate::LowMemoryOptions;
+use crate::PausedPresentation;
+use crate::PresentationStatistics;
+use crate::RenderTargetInfo;
+use crate::SurfaceFence;
+use crate::SurfaceOptions;
+use crate::SurfaceResizedHandler;
+use crate::SurfaceScaleChangedHandler;
+use crate::SurfaceType;
+use crate::Swizzle;
+use crate::{AutoVsyncPolicy, AutoVsyncStats};
+use crate::{
+    ColorProfile, ColorProfileChangedHandler, ContextID, ContextInfo, Error, GLApi, GLCapabilities,
+    PresentRecord,
+};
+use crate::{ContextPriority, ResetStatus, SurfaceAccess, SurfaceInfo, TextureOwnership};
+use crate::{Purgeability, ReclaimResult};
+use crate::{SchedulingClass, SchedulingEnforcement};
+use crate::{SurfaceConfig, SurfaceIntent, SurfaceTransform};
+use euclid::default::{Rect, Size2D};
+
+use std::ops::RangeInclusive;
+#[cfg(unix)]
+use std::os::fd::BorrowedFd;
 use std::os::raw::c_void;
+use std::thread::ThreadId;
+use std::time::Duration;
 
 /// Represents a hardware display adapter that can be used for rendering (including the CPU).
 ///
@@ -107,6 +141,90 @@ where
             Device::Alternate(ref device) => device.gl_api(),
         }
     }
+
+    /// Returns the identity of the physical GPU that this device renders with.
+    pub fn identity(&self) -> DeviceIdentity {
+        match *self {
+            Device::Default(ref device) => device.identity(),
+            Device::Alternate(ref device) => device.identity(),
+        }
+    }
+
+    /// Returns a token that can cancel context and surface creation on this device from any
+    /// thread.
+    pub fn cancellation_token(&self) -> CancelToken {
+        match *self {
+            Device::Default(ref device) => device.cancellation_token(),
+            Device::Alternate(ref device) => device.cancellation_token(),
+        }
+    }
+
+    /// Sets what kind of work this device's contexts do, and returns how that is enforced.
+    pub fn set_scheduling_class(&mut self, class: SchedulingClass) -> SchedulingEnforcement {
+        match *self {
+            Device::Default(ref mut device) => device.set_scheduling_class(class),
+            Device::Alternate(ref mut device) => device.set_scheduling_class(class),
+        }
+    }
+
+    /// Returns the scheduling class that was last set with `set_scheduling_class()`.
+    pub fn scheduling_class(&self) -> SchedulingClass {
+        match *self {
+            Device::Default(ref device) => device.scheduling_class(),
+            Device::Alternate(ref device) => device.scheduling_class(),
+        }
+    }
+
+    /// Returns how this device enforces its scheduling class.
+    pub fn scheduling_enforcement(&self) -> SchedulingEnforcement {
+        match *self {
+            Device::Default(ref device) => device.scheduling_enforcement(),
+            Device::Alternate(ref device) => device.scheduling_enforcement(),
+        }
+    }
+
+    /// Makes this device save memory at the expense of rendering quality, and returns how many
+    /// pooled surfaces were destroyed.
+    pub fn enter_low_memory_mode(&mut self, options: LowMemoryOptions) -> usize {
+        match *self {
+            Device::Default(ref mut device) => device.enter_low_memory_mode(options),
+            Device::Alternate(ref mut device) => device.enter_low_memory_mode(options),
+        }
+    }
+
+    /// Leaves low-memory mode, so that surfaces created from now on get their usual formats.
+    pub fn exit_low_memory_mode(&mut self) {
+        match *self {
+            Device::Default(ref mut device) => device.exit_low_memory_mode(),
+            Device::Alternate(ref mut device) => device.exit_low_memory_mode(),
+        }
+    }
+
+    /// Returns the options that this device entered low-memory mode with, or `None` if it isn't
+    /// in low-memory mode.
+    pub fn low_memory_options(&self) -> Option<LowMemoryOptions> {
+        match *self {
+            Device::Default(ref device) => device.low_memory_options(),
+            Device::Alternate(ref device) => device.low_memory_options(),
+        }
+    }
+
+    /// Makes this device fill each surface that it creates from now on, and each surface that a
+    /// swap chain recycles, with a recognizable pattern.
+    pub fn set_debug_surface_fill(&mut self, fill: DebugFill) {
+        match *self {
+            Device::Default(ref mut device) => device.set_debug_surface_fill(fill),
+            Device::Alternate(ref mut device) => device.set_debug_surface_fill(fill),
+        }
+    }
+
+    /// Returns the debug fill that was last set with `set_debug_surface_fill()`.
+    pub fn debug_surface_fill(&self) -> DebugFill {
+        match *self {
+            Device::Default(ref device) => device.debug_surface_fill(),
+            Device::Alternate(ref device) => device.debug_surface_fill(),
+        }
+    }
 }
 
 impl<Def, Alt> DeviceInterface for Device<Def, Alt>
@@ -119,9 +237,11 @@ where
     type Connection = Connection<Def, Alt>;
     type Context = Context<Def, Alt>;
     type ContextDescriptor = ContextDescriptor<Def, Alt>;
+    type CurrentContextGuard = CurrentContextGuard<Def, Alt>;
     type NativeContext = NativeContext<Def, Alt>;
     type Surface = Surface<Def, Alt>;
     type SurfaceTexture = SurfaceTexture<Def, Alt>;
+    type SurfaceDataGuard<'s> = SurfaceDataGuard<'s, Def, Alt>;
 
     // device.rs
 
@@ -145,6 +265,46 @@ where
         Device::gl_api(self)
     }
 
+    #[inline]
+    fn identity(&self) -> DeviceIdentity {
+        Device::identity(self)
+    }
+
+    #[inline]
+    fn cancellation_token(&self) -> CancelToken {
+        Device::cancellation_token(self)
+    }
+
+    #[inline]
+    fn set_scheduling_class(&mut self, class: SchedulingClass) -> SchedulingEnforcement {
+        Device::set_scheduling_class(self, class)
+    }
+
+    #[inline]
+    fn scheduling_class(&self) -> SchedulingClass {
+        Device::scheduling_class(self)
+    }
+
+    #[inline]
+    fn scheduling_enforcement(&self) -> SchedulingEnforcement {
+        Device::scheduling_enforcement(self)
+    }
+
+    #[inline]
+    fn enter_low_memory_mode(&mut self, options: LowMemoryOptions) -> usize {
+        Device::enter_low_memory_mode(self, options)
+    }
+
+    #[inline]
+    fn exit_low_memory_mode(&mut self) {
+        Device::exit_low_memory_mode(self)
+    }
+
+    #[inline]
+    fn low_memory_options(&self) -> Option<LowMemoryOptions> {
+        Device::low_memory_options(self)
+    }
+
     // context.rs
 
     #[inline]
@@ -164,6 +324,15 @@ where
         Device::create_context(self, descriptor, share_with)
     }
 
+    #[inline]
+    fn create_versioned_context(
+        &mut self,
+        versions: RangeInclusive<GLVersion>,
+        flags: ContextAttributeFlags,
+    ) -> Result<(Context<Def, Alt>, ContextDescriptor<Def, Alt>), Error> {
+        Device::create_versioned_context(self, versions, flags)
+    }
+
     #[inline]
     unsafe fn create_context_from_native_context(
         &self,
@@ -182,6 +351,11 @@ where
         Device::native_context(self, context)
     }
 
+    #[inline]
+    fn current_native_context(&self) -> Result<Self::NativeContext, Error> {
+        Device::current_native_context(self)
+    }
+
     #[inline]
     fn context_descriptor(&self, context: &Context<Def, Alt>) -> Self::ContextDescriptor {
         Device::context_descriptor(self, context)
@@ -192,11 +366,32 @@ where
         Device::make_context_current(self, context)
     }
 
+    #[inline]
+    fn make_context_current_guarded(
+        &self,
+        context: &Context<Def, Alt>,
+    ) -> Result<CurrentContextGuard<Def, Alt>, Error> {
+        Device::make_context_current_guarded(self, context)
+    }
+
+    #[inline]
+    fn with_context_current<F, R>(&self, context: &mut Context<Def, Alt>, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut BulkOps<Self>) -> R,
+    {
+        Device::with_context_current(self, context, f)
+    }
+
     #[inline]
     fn make_no_context_current(&self) -> Result<(), Error> {
         Device::make_no_context_current(self)
     }
 
+    #[inline]
+    fn context_current_thread(&self, context: &Context<Def, Alt>) -> Option<ThreadId> {
+        Device::context_current_thread(self, context)
+    }
+
     #[inline]
     fn context_descriptor_attributes(
         &self,
@@ -206,10 +401,38 @@ where
     }
 
     #[inline]
-    fn get_proc_address(&self, context: &Context<Def, Alt>, symbol_name: &str) -> *const c_void {
+    fn context_descriptor_raw_parts(
+        &self,
+        context_descriptor: &ContextDescriptor<Def, Alt>,
+    ) -> ContextDescriptorAttributes {
+        Device::context_descriptor_raw_parts(self, context_descriptor)
+    }
+
+    #[inline]
+    fn create_context_descriptor_from_attributes(
+        &self,
+        attributes: &ContextDescriptorAttributes,
+    ) -> Result<ContextDescriptor<Def, Alt>, Error> {
+        Device::create_context_descriptor_from_attributes(self, attributes)
+    }
+
+    #[inline]
+    fn get_proc_address(
+        &self,
+        context: &Context<Def, Alt>,
+        symbol_name: &str,
+    ) -> Option<*const c_void> {
         Device::get_proc_address(self, context, symbol_name)
     }
 
+    #[inline]
+    fn proc_address_loader<'a>(
+        &'a self,
+        context: &'a Context<Def, Alt>,
+    ) -> Box<dyn Fn(&str) -> *const c_void + 'a> {
+        Device::proc_address_loader(self, context)
+    }
+
     #[inline]
     fn bind_surface_to_context(
         &self,
@@ -232,6 +455,11 @@ where
         Device::context_id(self, context)
     }
 
+    #[inline]
+    fn is_context_current(&self, context: &Context<Def, Alt>) -> bool {
+        Device::is_context_current(self, context)
+    }
+
     #[inline]
     fn context_surface_info(
         &self,
@@ -240,6 +468,61 @@ where
         Device::context_surface_info(self, context)
     }
 
+    #[inline]
+    fn begin_rendering(&self, context: &Context<Def, Alt>) -> Result<RenderTargetInfo, Error> {
+        Device::begin_rendering(self, context)
+    }
+
+    #[inline]
+    fn end_rendering(&self, context: &Context<Def, Alt>, flush: bool) -> Result<(), Error> {
+        Device::end_rendering(self, context, flush)
+    }
+
+    #[inline]
+    fn capabilities(&self, context: &Context<Def, Alt>) -> Result<GLCapabilities, Error> {
+        Device::capabilities(self, context)
+    }
+
+    #[inline]
+    fn gl_extensions(&self, context: &Context<Def, Alt>) -> Result<Vec<String>, Error> {
+        Device::gl_extensions(self, context)
+    }
+
+    #[inline]
+    fn context_info(&self, context: &Context<Def, Alt>) -> Result<ContextInfo, Error> {
+        Device::context_info(self, context)
+    }
+
+    #[inline]
+    fn context_is_hardware_accelerated(&self, context: &Context<Def, Alt>) -> bool {
+        Device::context_is_hardware_accelerated(self, context)
+    }
+
+    #[inline]
+    fn drain_gl_errors(&self, context: &Context<Def, Alt>) -> Result<Vec<GlError>, Error> {
+        Device::drain_gl_errors(self, context)
+    }
+
+    #[inline]
+    fn context_reset_status(&self, context: &Context<Def, Alt>) -> Result<ResetStatus, Error> {
+        Device::context_reset_status(self, context)
+    }
+
+    #[inline]
+    fn poll_context_lost(&self, context: &Context<Def, Alt>) -> bool {
+        Device::poll_context_lost(self, context)
+    }
+
+    #[inline]
+    fn set_context_lost_handler(&mut self, handler: Option<ContextLostHandler>) {
+        Device::set_context_lost_handler(self, handler)
+    }
+
+    #[inline]
+    fn context_priority(&self, context: &Context<Def, Alt>) -> ContextPriority {
+        Device::context_priority(self, context)
+    }
+
     // surface.rs
 
     #[inline]
@@ -252,6 +535,40 @@ where
         Device::create_surface(self, context, surface_access, surface_type)
     }
 
+    #[inline]
+    fn create_surfaces<I>(
+        &mut self,
+        context: &mut Context<Def, Alt>,
+        surface_access: SurfaceAccess,
+        surface_types: I,
+    ) -> Result<Vec<Surface<Def, Alt>>, Error>
+    where
+        I: IntoIterator<Item = SurfaceType<NativeWidget<Def, Alt>>>,
+    {
+        Device::create_surfaces(self, context, surface_access, surface_types)
+    }
+
+    #[inline]
+    fn create_surface_with_options(
+        &mut self,
+        context: &Context<Def, Alt>,
+        surface_access: SurfaceAccess,
+        surface_type: SurfaceType<NativeWidget<Def, Alt>>,
+        options: SurfaceOptions,
+    ) -> Result<Surface<Def, Alt>, Error> {
+        Device::create_surface_with_options(self, context, surface_access, surface_type, options)
+    }
+
+    #[inline]
+    fn recommended_surface_config(
+        &self,
+        context: &Context<Def, Alt>,
+        intent: SurfaceIntent<NativeWidget<Def, Alt>>,
+        size: Size2D<i32>,
+    ) -> Result<SurfaceConfig<NativeWidget<Def, Alt>>, Error> {
+        Device::recommended_surface_config(self, context, intent, size)
+    }
+
     #[inline]
     fn create_surface_texture(
         &self,
@@ -272,6 +589,18 @@ where
         Device::create_surface_texture_from_gl(self, context, size, texture_object, egl_target)
     }
 
+    #[inline]
+    fn create_surface_from_texture(
+        &mut self,
+        context: &mut Context<Def, Alt>,
+        texture_object: GLuint,
+        size: &Size2D<i32>,
+        format: GLenum,
+        ownership: TextureOwnership,
+    ) -> Result<Surface<Def, Alt>, Error> {
+        Device::create_surface_from_texture(self, context, texture_object, size, format, ownership)
+    }
+
     #[inline]
     fn destroy_surface(
         &self,
@@ -304,6 +633,60 @@ where
         Device::present_surface(self, context, surface)
     }
 
+    #[inline]
+    fn present_surface_with_damage(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        Device::present_surface_with_damage(self, context, surface, damage)
+    }
+
+    #[inline]
+    fn pause_presentation(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+    ) -> Result<PausedPresentation, Error> {
+        Device::pause_presentation(self, context, surface)
+    }
+
+    #[inline]
+    fn resume_presentation(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        paused: PausedPresentation,
+        present_pending: bool,
+    ) -> Result<(), Error> {
+        Device::resume_presentation(self, context, surface, paused, present_pending)
+    }
+
+    #[inline]
+    fn present_surface_with_fence(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+    ) -> Result<SurfaceFence, Error> {
+        Device::present_surface_with_fence(self, context, surface)
+    }
+
+    #[inline]
+    fn wait_fence(&self, context: &Context<Def, Alt>, fence: &SurfaceFence) -> Result<(), Error> {
+        Device::wait_fence(self, context, fence)
+    }
+
+    #[inline]
+    #[cfg(unix)]
+    fn import_sync_fd(
+        &self,
+        context: &Context<Def, Alt>,
+        fd: BorrowedFd,
+    ) -> Result<SurfaceFence, Error> {
+        Device::import_sync_fd(self, context, fd)
+    }
+
     #[inline]
     fn resize_surface(
         &self,
@@ -314,13 +697,230 @@ where
         Device::resize_surface(self, context, surface, size)
     }
 
+    #[inline]
+    fn set_swap_interval(
+        &mut self,
+        context: &Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+        interval: i32,
+    ) -> Result<i32, Error> {
+        Device::set_swap_interval(self, context, surface, interval)
+    }
+
+    #[inline]
+    fn supports_adaptive_swap(&self, surface: &Surface<Def, Alt>) -> bool {
+        Device::supports_adaptive_swap(self, surface)
+    }
+
+    #[inline]
+    fn set_auto_swap_interval(
+        &mut self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        policy: Option<AutoVsyncPolicy>,
+    ) -> Result<(), Error> {
+        Device::set_auto_swap_interval(self, context, surface, policy)
+    }
+
+    #[inline]
+    fn auto_swap_interval_stats(&self, surface: &Surface<Def, Alt>) -> Option<AutoVsyncStats> {
+        Device::auto_swap_interval_stats(self, surface)
+    }
+
+    #[inline]
+    fn wait_previous_buffer_released(
+        &self,
+        surface: &Surface<Def, Alt>,
+        timeout: Duration,
+    ) -> Result<BufferRelease, Error> {
+        Device::wait_previous_buffer_released(self, surface, timeout)
+    }
+
+    #[inline]
+    fn set_post_present_invalidation(
+        &self,
+        surface: &mut Surface<Def, Alt>,
+        set: InvalidateSet,
+    ) -> Result<(), Error> {
+        Device::set_post_present_invalidation(self, surface, set)
+    }
+
+    #[inline]
+    fn post_present_invalidation(&self, surface: &Surface<Def, Alt>) -> InvalidateSet {
+        Device::post_present_invalidation(self, surface)
+    }
+
+    #[inline]
+    fn invalidate_surface(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+    ) -> Result<(), Error> {
+        Device::invalidate_surface(self, context, surface)
+    }
+
+    #[inline]
+    fn surface_buffer_age(&self, surface: &Surface<Def, Alt>) -> Result<u32, Error> {
+        Device::surface_buffer_age(self, surface)
+    }
+
+    #[inline]
+    fn query_surface_native_size(&self, surface: &Surface<Def, Alt>) -> Result<Size2D<i32>, Error> {
+        Device::query_surface_native_size(self, surface)
+    }
+
+    #[inline]
+    fn surface_transform(&self, surface: &Surface<Def, Alt>) -> SurfaceTransform {
+        Device::surface_transform(self, surface)
+    }
+
+    #[inline]
+    fn clear_surface_region(
+        &self,
+        context: &mut Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        rect: Option<Rect<i32>>,
+        color: [f32; 4],
+        depth: Option<f32>,
+        stencil: Option<i32>,
+    ) -> Result<(), Error> {
+        Device::clear_surface_region(self, context, surface, rect, color, depth, stencil)
+    }
+
+    #[inline]
+    fn set_debug_surface_fill(&mut self, fill: DebugFill) {
+        Device::set_debug_surface_fill(self, fill)
+    }
+
+    #[inline]
+    fn debug_surface_fill(&self) -> DebugFill {
+        Device::debug_surface_fill(self)
+    }
+
+    #[inline]
+    fn debug_fill_surface(
+        &self,
+        context: &mut Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+    ) -> Result<(), Error> {
+        Device::debug_fill_surface(self, context, surface)
+    }
+
+    #[inline]
+    fn set_surface_resized_handler(&mut self, handler: Option<SurfaceResizedHandler>) {
+        Device::set_surface_resized_handler(self, handler)
+    }
+
+    #[inline]
+    fn set_surface_scale_changed_handler(
+        &mut self,
+        handler: Option<SurfaceScaleChangedHandler>,
+    ) -> Result<(), Error> {
+        Device::set_surface_scale_changed_handler(self, handler)
+    }
+
+    #[inline]
+    fn set_surface_purgeable(
+        &self,
+        surface: &mut Surface<Def, Alt>,
+        purgeability: Purgeability,
+    ) -> Result<(), Error> {
+        Device::set_surface_purgeable(self, surface, purgeability)
+    }
+
+    #[inline]
+    fn reclaim_surface(&self, surface: &mut Surface<Def, Alt>) -> Result<ReclaimResult, Error> {
+        Device::reclaim_surface(self, surface)
+    }
+
     #[inline]
     fn surface_info(&self, surface: &Surface<Def, Alt>) -> SurfaceInfo {
         Device::surface_info(self, surface)
     }
 
+    #[inline]
+    fn lock_surface_data<'s>(
+        &self,
+        surface: &'s mut Surface<Def, Alt>,
+    ) -> Result<SurfaceDataGuard<'s, Def, Alt>, Error> {
+        Device::lock_surface_data(self, surface)
+    }
+
+    #[inline]
+    #[cfg(unix)]
+    fn export_surface_dma_buf(&self, surface: &Surface<Def, Alt>) -> Result<DmaBuf, Error> {
+        Device::export_surface_dma_buf(self, surface)
+    }
+
+    #[inline]
+    fn convert_surface(
+        &self,
+        context: &mut Context<Def, Alt>,
+        source: &Surface<Def, Alt>,
+        destination: &mut Surface<Def, Alt>,
+        swizzle: Swizzle,
+    ) -> Result<(), Error> {
+        Device::convert_surface(self, context, source, destination, swizzle)
+    }
+
+    #[inline]
+    fn read_surface_data(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+        rect: Rect<i32>,
+        out: &mut [u8],
+    ) -> Result<(), Error> {
+        Device::read_surface_data(self, context, surface, rect, out)
+    }
+
+    #[inline]
+    fn write_surface_data(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        rect: Rect<i32>,
+        data: &[u8],
+        stride: usize,
+    ) -> Result<(), Error> {
+        Device::write_surface_data(self, context, surface, rect, data, stride)
+    }
+
+    #[inline]
+    fn widget_color_profile(&self, surface: &Surface<Def, Alt>) -> ColorProfile {
+        Device::widget_color_profile(self, surface)
+    }
+
+    #[inline]
+    fn set_color_profile_changed_handler(&mut self, handler: Option<ColorProfileChangedHandler>) {
+        Device::set_color_profile_changed_handler(self, handler)
+    }
+
+    #[inline]
+    fn presenting_cross_adapter(&self, surface: &Surface<Def, Alt>) -> bool {
+        Device::presenting_cross_adapter(self, surface)
+    }
+
+    #[inline]
+    fn presentation_timestamps(&self, surface: &Surface<Def, Alt>) -> Vec<PresentRecord> {
+        Device::presentation_timestamps(self, surface)
+    }
+
+    #[inline]
+    fn query_presentation_time(
+        &self,
+        surface: &Surface<Def, Alt>,
+    ) -> Result<PresentationStatistics, Error> {
+        Device::query_presentation_time(self, surface)
+    }
+
     #[inline]
     fn surface_texture_object(&self, surface_texture: &SurfaceTexture<Def, Alt>) -> GLuint {
         Device::surface_texture_object(self, surface_texture)
     }
+
+    #[inline]
+    fn surface_texture_format(&self, surface_texture: &SurfaceTexture<Def, Alt>) -> GLenum {
+        Device::surface_texture_format(self, surface_texture)
+    }
 }
@@ -4,13 +4,34 @@
 
 use super::context::Context;
 use super::device::Device;
+use crate::bulk;
 use crate::connection::Connection as ConnectionInterface;
 use crate::device::Device as DeviceInterface;
 use crate::gl::types::{GLenum, GLuint};
-use crate::{Error, SurfaceAccess, SurfaceInfo, SurfaceType};
-use euclid::default::Size2D;
+use crate::BufferRelease;
+#[cfg(unix)]
+use crate::DmaBuf;
+use crate::InvalidateSet;
+use crate::PausedPresentation;
+use crate::PresentationStatistics;
+use crate::SurfaceFence;
+use crate::SurfaceScaleChangedHandler;
+use crate::Swizzle;
+use crate::TextureOwnership;
+use crate::{AutoVsyncPolicy, AutoVsyncStats};
+use crate::{
+    ColorProfile, ColorProfileChangedHandler, ContextID, Error, PresentRecord, SurfaceAccess,
+    SurfaceInfo,
+};
+use crate::{Purgeability, ReclaimResult};
+use crate::{SurfaceConfig, SurfaceIntent, SurfaceTransform};
+use crate::{SurfaceInterface, SurfaceOptions, SurfaceResizedHandler, SurfaceType};
+use euclid::default::{Rect, Size2D};
 
 use std::fmt::{self, Debug, Formatter};
+#[cfg(unix)]
+use std::os::fd::BorrowedFd;
+use std::time::Duration;
 
 /// Represents a hardware buffer of pixels that can be rendered to via the CPU or GPU and either
 /// displayed in a native widget or bound to a texture for reading.
@@ -59,6 +80,18 @@ where
     Alternate(Alt::SurfaceTexture),
 }
 
+/// Represents the CPU view of the pixel data of a surface.
+pub enum SurfaceDataGuard<'s, Def, Alt>
+where
+    Def: DeviceInterface,
+    Alt: DeviceInterface,
+{
+    /// The default surface data guard type.
+    Default(Def::SurfaceDataGuard<'s>),
+    /// The alternate surface data guard type.
+    Alternate(Alt::SurfaceDataGuard<'s>),
+}
+
 /// A native widget/window type that can dynamically switch between backends.
 pub enum NativeWidget<Def, Alt>
 where
@@ -75,9 +108,14 @@ impl<Def, Alt> Debug for Surface<Def, Alt>
 where
     Def: DeviceInterface,
     Alt: DeviceInterface,
+    Def::Surface: Debug,
+    Alt::Surface: Debug,
 {
     fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
-        write!(f, "Surface")
+        match *self {
+            Surface::Default(ref surface) => surface.fmt(f),
+            Surface::Alternate(ref surface) => surface.fmt(f),
+        }
     }
 }
 
@@ -85,9 +123,41 @@ impl<Def, Alt> Debug for SurfaceTexture<Def, Alt>
 where
     Def: DeviceInterface,
     Alt: DeviceInterface,
+    Def::SurfaceTexture: Debug,
+    Alt::SurfaceTexture: Debug,
 {
     fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
-        write!(f, "SurfaceTexture")
+        match *self {
+            SurfaceTexture::Default(ref surface_texture) => surface_texture.fmt(f),
+            SurfaceTexture::Alternate(ref surface_texture) => surface_texture.fmt(f),
+        }
+    }
+}
+
+impl<Def, Alt> Surface<Def, Alt>
+where
+    Def: DeviceInterface,
+    Alt: DeviceInterface,
+{
+    /// Returns the ID of the context that this surface was created with.
+    ///
+    /// A surface can only be bound to the context that it was created with.
+    pub fn context_id(&self) -> ContextID {
+        match *self {
+            Surface::Default(ref surface) => surface.context_id(),
+            Surface::Alternate(ref surface) => surface.context_id(),
+        }
+    }
+}
+
+impl<Def, Alt> SurfaceInterface for Surface<Def, Alt>
+where
+    Def: DeviceInterface,
+    Alt: DeviceInterface,
+{
+    #[inline]
+    fn context_id(&self) -> ContextID {
+        Surface::context_id(self)
     }
 }
 
@@ -105,6 +175,24 @@ where
         context: &Context<Def, Alt>,
         surface_access: SurfaceAccess,
         surface_type: SurfaceType<NativeWidget<Def, Alt>>,
+    ) -> Result<Surface<Def, Alt>, Error> {
+        self.create_surface_with_options(
+            context,
+            surface_access,
+            surface_type,
+            SurfaceOptions::default(),
+        )
+    }
+
+    /// Creates either a generic or a widget surface, with options such as its colorspace.
+    ///
+    /// `create_surface()` is this method with the default options.
+    pub fn create_surface_with_options(
+        &mut self,
+        context: &Context<Def, Alt>,
+        surface_access: SurfaceAccess,
+        surface_type: SurfaceType<NativeWidget<Def, Alt>>,
+        options: SurfaceOptions,
     ) -> Result<Surface<Def, Alt>, Error> {
         match (&mut *self, context) {
             (&mut Device::Default(ref mut device), Context::Default(context)) => {
@@ -118,7 +206,7 @@ where
                     }
                 };
                 device
-                    .create_surface(context, surface_access, surface_type)
+                    .create_surface_with_options(context, surface_access, surface_type, options)
                     .map(Surface::Default)
             }
             (&mut Device::Alternate(ref mut device), Context::Alternate(context)) => {
@@ -132,13 +220,51 @@ where
                     }
                 };
                 device
-                    .create_surface(context, surface_access, surface_type)
+                    .create_surface_with_options(context, surface_access, surface_type, options)
                     .map(Surface::Alternate)
             }
             _ => Err(Error::IncompatibleContext),
         }
     }
 
+    /// Returns the access mode, type, and options that suit a surface of the given size, created
+    /// with the given context for the given use, on this device, along with hints for using it.
+    ///
+    /// Returns `Error::IncompatibleNativeWidget` if the intent displays the surface in a widget
+    /// of the other device.
+    pub fn recommended_surface_config(
+        &self,
+        context: &Context<Def, Alt>,
+        intent: SurfaceIntent<NativeWidget<Def, Alt>>,
+        size: Size2D<i32>,
+    ) -> Result<SurfaceConfig<NativeWidget<Def, Alt>>, Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => {
+                let intent = intent
+                    .map_native_widget(|native_widget| match native_widget {
+                        NativeWidget::Default(native_widget) => Some(native_widget),
+                        NativeWidget::Alternate(_) => None,
+                    })
+                    .ok_or(Error::IncompatibleNativeWidget)?;
+                device
+                    .recommended_surface_config(context, intent, size)
+                    .map(|config| config.map_native_widget(NativeWidget::Default))
+            }
+            (Device::Alternate(device), Context::Alternate(context)) => {
+                let intent = intent
+                    .map_native_widget(|native_widget| match native_widget {
+                        NativeWidget::Alternate(native_widget) => Some(native_widget),
+                        NativeWidget::Default(_) => None,
+                    })
+                    .ok_or(Error::IncompatibleNativeWidget)?;
+                device
+                    .recommended_surface_config(context, intent, size)
+                    .map(|config| config.map_native_widget(NativeWidget::Alternate))
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
     /// Creates a surface texture from an existing generic surface for use with the given context.
     ///
     /// The surface texture is local to the supplied context and takes ownership of the surface.
@@ -217,6 +343,36 @@ where
         }
     }
 
+    /// Wraps an existing GL texture in a generic surface, without copying it.
+    ///
+    /// The texture must be a complete `TEXTURE_2D` of the given size and format, created in the
+    /// supplied context or in a context that shares with it. With `TextureOwnership::Borrowed`,
+    /// surfman never deletes the texture, and the caller must keep it alive and unchanged until
+    /// the surface is destroyed. With `TextureOwnership::Owned`, destroying the surface deletes
+    /// the texture.
+    pub fn create_surface_from_texture(
+        &mut self,
+        context: &mut Context<Def, Alt>,
+        texture_object: GLuint,
+        size: &Size2D<i32>,
+        format: GLenum,
+        ownership: TextureOwnership,
+    ) -> Result<Surface<Def, Alt>, Error> {
+        match (&mut *self, &mut *context) {
+            (&mut Device::Default(ref mut device), &mut Context::Default(ref mut context)) => {
+                device
+                    .create_surface_from_texture(context, texture_object, size, format, ownership)
+                    .map(Surface::Default)
+            }
+            (&mut Device::Alternate(ref mut device), &mut Context::Alternate(ref mut context)) => {
+                device
+                    .create_surface_from_texture(context, texture_object, size, format, ownership)
+                    .map(Surface::Alternate)
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
     /// Destroys a surface.
     ///
     /// The supplied context must be the context the surface is associated with, or this returns
@@ -292,7 +448,8 @@ where
     /// associated widgets until this method is called.
     ///
     /// The supplied context must match the context the surface was created with, or an
-    /// `IncompatibleSurface` error is returned.
+    /// `IncompatibleSurface` error is returned. The context doesn't need to be current, and the
+    /// context that was current before this call remains current afterward.
     pub fn present_surface(
         &self,
         context: &Context<Def, Alt>,
@@ -311,7 +468,138 @@ where
         }
     }
 
-    /// Resizes a widget surface.
+    /// Displays the changed parts of a widget surface on screen.
+    ///
+    /// `damage` is measured from the bottom left. An empty list skips the present.
+    pub fn present_surface_with_damage(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => match *surface {
+                Surface::Default(ref mut surface) => {
+                    device.present_surface_with_damage(context, surface, damage)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (Device::Alternate(device), Context::Alternate(context)) => match *surface {
+                Surface::Alternate(ref mut surface) => {
+                    device.present_surface_with_damage(context, surface, damage)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Pauses the presentation of a widget surface, so that the frames presented after this are
+    /// held instead of shown.
+    pub fn pause_presentation(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+    ) -> Result<PausedPresentation, Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => match *surface {
+                Surface::Default(ref mut surface) => device.pause_presentation(context, surface),
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (Device::Alternate(device), Context::Alternate(context)) => match *surface {
+                Surface::Alternate(ref mut surface) => device.pause_presentation(context, surface),
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Ends a pause in the presentation of a widget surface, and presents the frame held during it
+    /// if `present_pending` is true.
+    pub fn resume_presentation(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        paused: PausedPresentation,
+        present_pending: bool,
+    ) -> Result<(), Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => match *surface {
+                Surface::Default(ref mut surface) => {
+                    device.resume_presentation(context, surface, paused, present_pending)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (Device::Alternate(device), Context::Alternate(context)) => match *surface {
+                Surface::Alternate(ref mut surface) => {
+                    device.resume_presentation(context, surface, paused, present_pending)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Displays the contents of a widget surface on screen, and returns a fence that signals once
+    /// the GPU has finished rendering it.
+    pub fn present_surface_with_fence(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+    ) -> Result<SurfaceFence, Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => match *surface {
+                Surface::Default(ref mut surface) => {
+                    device.present_surface_with_fence(context, surface)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (Device::Alternate(device), Context::Alternate(context)) => match *surface {
+                Surface::Alternate(ref mut surface) => {
+                    device.present_surface_with_fence(context, surface)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Makes the context wait on the GPU until the fence signals.
+    pub fn wait_fence(
+        &self,
+        context: &Context<Def, Alt>,
+        fence: &SurfaceFence,
+    ) -> Result<(), Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => {
+                device.wait_fence(context, fence)
+            }
+            (Device::Alternate(device), Context::Alternate(context)) => {
+                device.wait_fence(context, fence)
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Returns a fence that signals when the sync file `fd` does.
+    #[cfg(unix)]
+    pub fn import_sync_fd(
+        &self,
+        context: &Context<Def, Alt>,
+        fd: BorrowedFd,
+    ) -> Result<SurfaceFence, Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => {
+                device.import_sync_fd(context, fd)
+            }
+            (Device::Alternate(device), Context::Alternate(context)) => {
+                device.import_sync_fd(context, fd)
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Resizes a surface in place.
     pub fn resize_surface(
         &self,
         context: &Context<Def, Alt>,
@@ -333,6 +621,406 @@ where
         }
     }
 
+    /// Sets the number of vertical blanks to wait for before showing each frame on a widget
+    /// surface, and returns the interval that took effect.
+    pub fn set_swap_interval(
+        &mut self,
+        context: &Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+        interval: i32,
+    ) -> Result<i32, Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => match *surface {
+                Surface::Default(ref surface) => {
+                    device.set_swap_interval(context, surface, interval)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (Device::Alternate(device), Context::Alternate(context)) => match *surface {
+                Surface::Alternate(ref surface) => {
+                    device.set_swap_interval(context, surface, interval)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Returns true if a widget surface accepts the negative swap intervals of adaptive vsync.
+    pub fn supports_adaptive_swap(&self, surface: &Surface<Def, Alt>) -> bool {
+        match (self, surface) {
+            (Device::Default(device), Surface::Default(surface)) => {
+                device.supports_adaptive_swap(surface)
+            }
+            (Device::Alternate(device), Surface::Alternate(surface)) => {
+                device.supports_adaptive_swap(surface)
+            }
+            _ => false,
+        }
+    }
+
+    /// Lets surfman switch a widget surface between vsync and immediate presentation by itself,
+    /// following `policy`, or stops it with `None`.
+    pub fn set_auto_swap_interval(
+        &mut self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        policy: Option<AutoVsyncPolicy>,
+    ) -> Result<(), Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => match *surface {
+                Surface::Default(ref mut surface) => {
+                    device.set_auto_swap_interval(context, surface, policy)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (Device::Alternate(device), Context::Alternate(context)) => match *surface {
+                Surface::Alternate(ref mut surface) => {
+                    device.set_auto_swap_interval(context, surface, policy)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Returns what automatic vsync has decided for a surface, or `None` if it isn't enabled.
+    pub fn auto_swap_interval_stats(&self, surface: &Surface<Def, Alt>) -> Option<AutoVsyncStats> {
+        match (self, surface) {
+            (Device::Default(device), Surface::Default(surface)) => {
+                device.auto_swap_interval_stats(surface)
+            }
+            (Device::Alternate(device), Surface::Alternate(surface)) => {
+                device.auto_swap_interval_stats(surface)
+            }
+            _ => None,
+        }
+    }
+
+    /// Waits until the window system has released the buffer of the last frame presented on a
+    /// widget surface, or until `timeout` elapses.
+    pub fn wait_previous_buffer_released(
+        &self,
+        surface: &Surface<Def, Alt>,
+        timeout: Duration,
+    ) -> Result<BufferRelease, Error> {
+        match (self, surface) {
+            (Device::Default(device), Surface::Default(surface)) => {
+                device.wait_previous_buffer_released(surface, timeout)
+            }
+            (Device::Alternate(device), Surface::Alternate(surface)) => {
+                device.wait_previous_buffer_released(surface, timeout)
+            }
+            _ => Err(Error::IncompatibleSurface),
+        }
+    }
+
+    /// Chooses which attachments of a surface are invalidated right after each present.
+    pub fn set_post_present_invalidation(
+        &self,
+        surface: &mut Surface<Def, Alt>,
+        set: InvalidateSet,
+    ) -> Result<(), Error> {
+        match (self, surface) {
+            (Device::Default(device), Surface::Default(surface)) => {
+                device.set_post_present_invalidation(surface, set)
+            }
+            (Device::Alternate(device), Surface::Alternate(surface)) => {
+                device.set_post_present_invalidation(surface, set)
+            }
+            _ => Err(Error::IncompatibleSurface),
+        }
+    }
+
+    /// Returns which attachments of a surface are invalidated right after each present.
+    ///
+    /// Surfaces of the other backend are never invalidated by this device.
+    pub fn post_present_invalidation(&self, surface: &Surface<Def, Alt>) -> InvalidateSet {
+        match (self, surface) {
+            (Device::Default(device), Surface::Default(surface)) => {
+                device.post_present_invalidation(surface)
+            }
+            (Device::Alternate(device), Surface::Alternate(surface)) => {
+                device.post_present_invalidation(surface)
+            }
+            _ => InvalidateSet::NONE,
+        }
+    }
+
+    /// Invalidates the attachments of a generic surface that were chosen with
+    /// `set_post_present_invalidation()`.
+    pub fn invalidate_surface(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+    ) -> Result<(), Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => match *surface {
+                Surface::Default(ref mut surface) => device.invalidate_surface(context, surface),
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (Device::Alternate(device), Context::Alternate(context)) => match *surface {
+                Surface::Alternate(ref mut surface) => device.invalidate_surface(context, surface),
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Clears a region of a surface, measured from its top left, and optionally its depth and
+    /// stencil buffers, without the caller loading OpenGL.
+    pub fn clear_surface_region(
+        &self,
+        context: &mut Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        rect: Option<Rect<i32>>,
+        color: [f32; 4],
+        depth: Option<f32>,
+        stencil: Option<i32>,
+    ) -> Result<(), Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => match *surface {
+                Surface::Default(ref mut surface) => {
+                    device.clear_surface_region(context, surface, rect, color, depth, stencil)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (Device::Alternate(device), Context::Alternate(context)) => match *surface {
+                Surface::Alternate(ref mut surface) => {
+                    device.clear_surface_region(context, surface, rect, color, depth, stencil)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Fills a surface with this device's debug fill, as if it had just been created, or does
+    /// nothing if the fill is `DebugFill::None`.
+    pub fn debug_fill_surface(
+        &self,
+        context: &mut Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+    ) -> Result<(), Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => match *surface {
+                Surface::Default(ref mut surface) => device.debug_fill_surface(context, surface),
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (Device::Alternate(device), Context::Alternate(context)) => match *surface {
+                Surface::Alternate(ref mut surface) => device.debug_fill_surface(context, surface),
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Asks the window system for the current size of a widget surface's native window, in device
+    /// pixels.
+    pub fn query_surface_native_size(
+        &self,
+        surface: &Surface<Def, Alt>,
+    ) -> Result<Size2D<i32>, Error> {
+        match (self, surface) {
+            (Device::Default(device), Surface::Default(surface)) => {
+                device.query_surface_native_size(surface)
+            }
+            (Device::Alternate(device), Surface::Alternate(surface)) => {
+                device.query_surface_native_size(surface)
+            }
+            _ => Err(Error::IncompatibleSurface),
+        }
+    }
+
+    /// Returns the rotation that the window system applies to a widget surface's frames to show
+    /// them on its display.
+    ///
+    /// Surfaces of the other backend are reported as `SurfaceTransform::Identity`.
+    pub fn surface_transform(&self, surface: &Surface<Def, Alt>) -> SurfaceTransform {
+        match (self, surface) {
+            (Device::Default(device), Surface::Default(surface)) => {
+                device.surface_transform(surface)
+            }
+            (Device::Alternate(device), Surface::Alternate(surface)) => {
+                device.surface_transform(surface)
+            }
+            _ => SurfaceTransform::Identity,
+        }
+    }
+
+    /// Returns how many frames ago the back buffer of a widget surface was last presented, or 0
+    /// if its contents are undefined.
+    pub fn surface_buffer_age(&self, surface: &Surface<Def, Alt>) -> Result<u32, Error> {
+        match (self, surface) {
+            (Device::Default(device), Surface::Default(surface)) => {
+                device.surface_buffer_age(surface)
+            }
+            (Device::Alternate(device), Surface::Alternate(surface)) => {
+                device.surface_buffer_age(surface)
+            }
+            _ => Err(Error::IncompatibleSurface),
+        }
+    }
+
+    /// Registers a function to call with the ID and new size of each widget surface of this
+    /// device that is found to have been resized behind surfman's back, replacing any previous
+    /// one, or unregisters it if `handler` is `None`.
+    pub fn set_surface_resized_handler(&mut self, handler: Option<SurfaceResizedHandler>) {
+        match *self {
+            Device::Default(ref mut device) => device.set_surface_resized_handler(handler),
+            Device::Alternate(ref mut device) => device.set_surface_resized_handler(handler),
+        }
+    }
+
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to have moved to a display with a different scale factor, replacing any previous
+    /// one, or unregisters it if `handler` is `None`.
+    pub fn set_surface_scale_changed_handler(
+        &mut self,
+        handler: Option<SurfaceScaleChangedHandler>,
+    ) -> Result<(), Error> {
+        match *self {
+            Device::Default(ref mut device) => device.set_surface_scale_changed_handler(handler),
+            Device::Alternate(ref mut device) => device.set_surface_scale_changed_handler(handler),
+        }
+    }
+
+    /// Sets whether the system may discard the contents of a generic surface to free memory.
+    ///
+    /// A volatile surface must be taken back with `reclaim_surface()` before it's used again.
+    pub fn set_surface_purgeable(
+        &self,
+        surface: &mut Surface<Def, Alt>,
+        purgeability: Purgeability,
+    ) -> Result<(), Error> {
+        match (self, surface) {
+            (Device::Default(device), Surface::Default(ref mut surface)) => {
+                device.set_surface_purgeable(surface, purgeability)
+            }
+            (Device::Alternate(device), Surface::Alternate(ref mut surface)) => {
+                device.set_surface_purgeable(surface, purgeability)
+            }
+            _ => Err(Error::IncompatibleSurface),
+        }
+    }
+
+    /// Makes a surface non-purgeable again, and reports whether the system discarded its contents
+    /// since it was made volatile.
+    pub fn reclaim_surface(&self, surface: &mut Surface<Def, Alt>) -> Result<ReclaimResult, Error> {
+        match (self, surface) {
+            (Device::Default(device), Surface::Default(ref mut surface)) => {
+                device.reclaim_surface(surface)
+            }
+            (Device::Alternate(device), Surface::Alternate(ref mut surface)) => {
+                device.reclaim_surface(surface)
+            }
+            _ => Err(Error::IncompatibleSurface),
+        }
+    }
+
+    /// Returns a pointer to the underlying surface data for reading or writing by the CPU.
+    pub fn lock_surface_data<'s>(
+        &self,
+        surface: &'s mut Surface<Def, Alt>,
+    ) -> Result<SurfaceDataGuard<'s, Def, Alt>, Error> {
+        match (self, surface) {
+            (Device::Default(device), Surface::Default(ref mut surface)) => device
+                .lock_surface_data(surface)
+                .map(SurfaceDataGuard::Default),
+            (Device::Alternate(device), Surface::Alternate(ref mut surface)) => device
+                .lock_surface_data(surface)
+                .map(SurfaceDataGuard::Alternate),
+            _ => Err(Error::IncompatibleSurface),
+        }
+    }
+
+    /// Exports the pixels of a generic surface as a dma-buf.
+    #[cfg(unix)]
+    pub fn export_surface_dma_buf(&self, surface: &Surface<Def, Alt>) -> Result<DmaBuf, Error> {
+        match (self, surface) {
+            (Device::Default(device), Surface::Default(surface)) => {
+                device.export_surface_dma_buf(surface)
+            }
+            (Device::Alternate(device), Surface::Alternate(surface)) => {
+                device.export_surface_dma_buf(surface)
+            }
+            _ => Err(Error::IncompatibleSurface),
+        }
+    }
+
+    /// Copies the contents of `source` into `destination` on the GPU, converting between their
+    /// formats and colorspaces and exchanging channels as `swizzle` says.
+    pub fn convert_surface(
+        &self,
+        context: &mut Context<Def, Alt>,
+        source: &Surface<Def, Alt>,
+        destination: &mut Surface<Def, Alt>,
+        swizzle: Swizzle,
+    ) -> Result<(), Error> {
+        match (self, context, source, destination) {
+            (
+                Device::Default(device),
+                Context::Default(ref mut context),
+                Surface::Default(source),
+                Surface::Default(ref mut destination),
+            ) => device.convert_surface(context, source, destination, swizzle),
+            (
+                Device::Alternate(device),
+                Context::Alternate(ref mut context),
+                Surface::Alternate(source),
+                Surface::Alternate(ref mut destination),
+            ) => device.convert_surface(context, source, destination, swizzle),
+            _ => Err(Error::IncompatibleSurface),
+        }
+    }
+
+    /// Reads a rectangle of a generic surface, measured from its top left, into `out` as tightly
+    /// packed 8-bit RGBA, top row first.
+    pub fn read_surface_data(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+        rect: Rect<i32>,
+        out: &mut [u8],
+    ) -> Result<(), Error> {
+        match (self, context, surface) {
+            (Device::Default(device), Context::Default(context), Surface::Default(surface)) => {
+                device.read_surface_data(context, surface, rect, out)
+            }
+            (
+                Device::Alternate(device),
+                Context::Alternate(context),
+                Surface::Alternate(surface),
+            ) => device.read_surface_data(context, surface, rect, out),
+            _ => Err(Error::IncompatibleSurface),
+        }
+    }
+
+    /// Writes 8-bit RGBA pixels from `data`, top row first, into a rectangle of a generic surface,
+    /// measured from its top left.
+    pub fn write_surface_data(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        rect: Rect<i32>,
+        data: &[u8],
+        stride: usize,
+    ) -> Result<(), Error> {
+        match (self, context, surface) {
+            (
+                Device::Default(device),
+                Context::Default(context),
+                Surface::Default(ref mut surface),
+            ) => device.write_surface_data(context, surface, rect, data, stride),
+            (
+                Device::Alternate(device),
+                Context::Alternate(context),
+                Surface::Alternate(ref mut surface),
+            ) => device.write_surface_data(context, surface, rect, data, stride),
+            _ => Err(Error::IncompatibleSurface),
+        }
+    }
+
     /// Returns the OpenGL texture target needed to read from this surface texture.
     ///
     /// This will be `GL_TEXTURE_2D` or `GL_TEXTURE_RECTANGLE`, depending on platform.
@@ -358,7 +1046,83 @@ where
             (Device::Alternate(device), Surface::Alternate(ref surface)) => {
                 device.surface_info(surface)
             }
-            _ => panic!("Incompatible context!"),
+            _ => panic!("Incompatible surface!"),
+        }
+    }
+
+    /// Returns the color profile of the output that a widget surface is displayed on.
+    pub fn widget_color_profile(&self, surface: &Surface<Def, Alt>) -> ColorProfile {
+        match (self, surface) {
+            (Device::Default(device), Surface::Default(ref surface)) => {
+                device.widget_color_profile(surface)
+            }
+            (Device::Alternate(device), Surface::Alternate(ref surface)) => {
+                device.widget_color_profile(surface)
+            }
+            _ => panic!("Incompatible surface!"),
+        }
+    }
+
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to be displayed with a different color profile, replacing any previous one, or
+    /// unregisters it if `handler` is `None`.
+    pub fn set_color_profile_changed_handler(
+        &mut self,
+        handler: Option<ColorProfileChangedHandler>,
+    ) {
+        match *self {
+            Device::Default(ref mut device) => device.set_color_profile_changed_handler(handler),
+            Device::Alternate(ref mut device) => device.set_color_profile_changed_handler(handler),
+        }
+    }
+
+    /// Returns true if a widget surface is on an output driven by a different GPU than this
+    /// device.
+    pub fn presenting_cross_adapter(&self, surface: &Surface<Def, Alt>) -> bool {
+        match (self, surface) {
+            (Device::Default(device), Surface::Default(ref surface)) => {
+                device.presenting_cross_adapter(surface)
+            }
+            (Device::Alternate(device), Surface::Alternate(ref surface)) => {
+                device.presenting_cross_adapter(surface)
+            }
+            _ => panic!("Incompatible surface!"),
+        }
+    }
+
+    /// Returns the timestamps of the most recent presentations of a widget surface, oldest first.
+    ///
+    /// Returns an empty list for generic surfaces and for widget surfaces that haven't been
+    /// presented yet.
+    pub fn presentation_timestamps(&self, surface: &Surface<Def, Alt>) -> Vec<PresentRecord> {
+        match (self, surface) {
+            (Device::Default(device), Surface::Default(ref surface)) => {
+                device.presentation_timestamps(surface)
+            }
+            (Device::Alternate(device), Surface::Alternate(ref surface)) => {
+                device.presentation_timestamps(surface)
+            }
+            _ => panic!("Incompatible surface!"),
+        }
+    }
+
+    /// Returns when the window system presented the most recent frame of a widget surface that
+    /// is known to have reached the display, or the most recent frame if none is yet.
+    ///
+    /// Returns `Error::NoWidgetAttached` for generic surfaces, and `Error::NotPresented` if
+    /// nothing has been presented on the surface.
+    pub fn query_presentation_time(
+        &self,
+        surface: &Surface<Def, Alt>,
+    ) -> Result<PresentationStatistics, Error> {
+        match (self, surface) {
+            (Device::Default(device), Surface::Default(surface)) => {
+                device.query_presentation_time(surface)
+            }
+            (Device::Alternate(device), Surface::Alternate(surface)) => {
+                device.query_presentation_time(surface)
+            }
+            _ => Err(Error::IncompatibleSurface),
         }
     }
 
@@ -373,7 +1137,46 @@ where
             (Device::Alternate(device), SurfaceTexture::Alternate(ref surface_texture)) => {
                 device.surface_texture_object(surface_texture)
             }
-            _ => panic!("Incompatible context!"),
+            _ => panic!("Incompatible surface texture!"),
+        }
+    }
+
+    /// Returns the sized internal format of the OpenGL texture of this surface texture, such as
+    /// `GL_RGBA8` or `GL_RGBA16F`.
+    pub fn surface_texture_format(&self, surface_texture: &SurfaceTexture<Def, Alt>) -> GLenum {
+        match (self, surface_texture) {
+            (Device::Default(device), SurfaceTexture::Default(ref surface_texture)) => {
+                device.surface_texture_format(surface_texture)
+            }
+            (Device::Alternate(device), SurfaceTexture::Alternate(ref surface_texture)) => {
+                device.surface_texture_format(surface_texture)
+            }
+            _ => panic!("Incompatible surface texture!"),
         }
     }
 }
+
+impl<Def, Alt> Device<Def, Alt>
+where
+    Def: DeviceInterface,
+    Alt: DeviceInterface,
+    Def::Connection: ConnectionInterface<Device = Def>,
+    Alt::Connection: ConnectionInterface<Device = Alt>,
+{
+    /// Creates a surface of each of the given types, in order.
+    ///
+    /// The device's cancellation token is checked before each surface, as well as inside each
+    /// creation. If any surface fails or is cancelled, the ones created so far are destroyed and
+    /// the error is returned, so the batch creates either all of its surfaces or none.
+    pub fn create_surfaces<I>(
+        &mut self,
+        context: &mut Context<Def, Alt>,
+        surface_access: SurfaceAccess,
+        surface_types: I,
+    ) -> Result<Vec<Surface<Def, Alt>>, Error>
+    where
+        I: IntoIterator<Item = SurfaceType<NativeWidget<Def, Alt>>>,
+    {
+        bulk::create_surfaces(self, context, surface_access, surface_types)
+    }
+}
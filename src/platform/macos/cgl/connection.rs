@@ -6,9 +6,11 @@
 //! global window server connection.
 
 use super::device::{Adapter, Device};
+use crate::acceleration;
 use crate::platform::macos::system::connection::Connection as SystemConnection;
 use crate::platform::macos::system::device::NativeDevice;
 use crate::platform::macos::system::surface::NativeWidget;
+use crate::AdapterKey;
 use crate::Error;
 use crate::GLApi;
 
@@ -29,6 +31,23 @@ impl Connection {
         SystemConnection::new().map(Connection)
     }
 
+    /// An alias for `Connection::new()`, as CGL pixel formats need no window.
+    #[inline]
+    pub fn new_headless() -> Result<Connection, Error> {
+        Connection::new()
+    }
+
+    /// Connects to the display if it supports the given OpenGL API flavor.
+    ///
+    /// This backend only supports OpenGL, so other flavors return `Error::UnsupportedGLType`.
+    #[inline]
+    pub fn new_with_api(gl_api: GLApi) -> Result<Connection, Error> {
+        match gl_api {
+            GLApi::GL => Connection::new(),
+            _ => Err(Error::UnsupportedGLType),
+        }
+    }
+
     /// An alias for `Connection::new()`, present for consistency with other backends.
     #[inline]
     pub unsafe fn from_native_connection(
@@ -49,6 +68,14 @@ impl Connection {
         GLApi::GL
     }
 
+    /// Returns false, since CGL has no extensions of its own.
+    ///
+    /// Use `Device::gl_extensions()` for OpenGL extensions.
+    #[inline]
+    pub fn supports_extension(&self, _: &str) -> bool {
+        false
+    }
+
     /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
     ///
     /// This is an alias for `Connection::create_hardware_adapter()`.
@@ -75,12 +102,48 @@ impl Connection {
         self.0.create_software_adapter().map(Adapter)
     }
 
+    /// Returns the adapter for the Metal device whose name contains `needle`, ignoring case.
+    #[inline]
+    pub fn create_adapter_by_name(&self, needle: &str) -> Result<Adapter, Error> {
+        self.0.create_adapter_by_name(needle).map(Adapter)
+    }
+
+    /// Returns a key that describes the adapter, which can be saved and passed to
+    /// `find_adapter()` in a later run to find the same adapter again.
+    #[inline]
+    pub fn adapter_key(&self, adapter: &Adapter) -> Result<AdapterKey, Error> {
+        self.0.adapter_key(&adapter.0)
+    }
+
+    /// Returns the adapter that a key from `adapter_key()` describes.
+    ///
+    /// Returns `Error::IncompatibleAdapter` if the key was taken with another backend, and
+    /// `Error::NoAdapterFound` if it doesn't describe an adapter on this system.
+    #[inline]
+    pub fn find_adapter(&self, key: &AdapterKey) -> Result<Adapter, Error> {
+        self.0.find_adapter(key).map(Adapter)
+    }
+
+    /// Returns true if the two adapters are the same adapter.
+    #[inline]
+    pub fn is_same_adapter(&self, a: &Adapter, b: &Adapter) -> bool {
+        self.0.is_same_adapter(&a.0, &b.0)
+    }
+
+    /// Returns the adapter for the GPU that drives the screen the window is on.
+    #[inline]
+    pub fn adapter_for_widget(&self, native_widget: &NativeWidget) -> Result<Adapter, Error> {
+        self.0.adapter_for_widget(native_widget).map(Adapter)
+    }
+
     /// Opens the hardware device corresponding to the given adapter.
     ///
     /// Device handles are local to a single thread.
     #[inline]
     pub fn create_device(&self, adapter: &Adapter) -> Result<Device, Error> {
-        self.0.create_device(&adapter.0).map(Device)
+        let device = Device(self.0.create_device(&adapter.0)?);
+        acceleration::check_device(&device);
+        Ok(device)
     }
 
     /// An alias for `connection.create_device()` with the default adapter.
@@ -123,19 +186,10 @@ impl Connection {
     pub fn create_native_widget_from_raw_window_handle(
         &self,
         raw_handle: rwh_05::RawWindowHandle,
-        _size: Size2D<i32>,
+        size: Size2D<i32>,
     ) -> Result<NativeWidget, Error> {
-        use crate::platform::macos::system::surface::NSView;
-        use cocoa::base::id;
-        use rwh_05::RawWindowHandle::AppKit;
-
-        match raw_handle {
-            AppKit(handle) => Ok(NativeWidget {
-                view: NSView(unsafe { msg_send![handle.ns_view as id, retain] }),
-                opaque: unsafe { msg_send![handle.ns_window as id, isOpaque] },
-            }),
-            _ => Err(Error::IncompatibleNativeWidget),
-        }
+        self.0
+            .create_native_widget_from_raw_window_handle(raw_handle, size)
     }
 
     /// Create a native widget type from the given `WindowHandle`.
@@ -144,26 +198,15 @@ impl Connection {
     pub fn create_native_widget_from_window_handle(
         &self,
         handle: rwh_06::WindowHandle,
-        _size: Size2D<i32>,
+        size: Size2D<i32>,
     ) -> Result<NativeWidget, Error> {
-        use crate::platform::macos::system::surface::NSView;
-        use cocoa::base::id;
-        use rwh_06::RawWindowHandle::AppKit;
-
-        match handle.as_raw() {
-            AppKit(handle) => {
-                let ns_view = handle.ns_view.as_ptr() as id;
-                // https://developer.apple.com/documentation/appkit/nsview/1483301-window
-                let ns_window: id = unsafe { msg_send![ns_view, window] };
-                Ok(NativeWidget {
-                    // Increment the nsview's reference count with retain. See:
-                    // https://developer.apple.com/documentation/objectivec/1418956-nsobject/1571946-retain
-                    view: NSView(unsafe { msg_send![ns_view, retain] }),
-                    // https://developer.apple.com/documentation/appkit/nswindow/1419086-isopaque
-                    opaque: unsafe { msg_send![ns_window, isOpaque] },
-                })
-            }
-            _ => Err(Error::IncompatibleNativeWidget),
-        }
+        self.0.create_native_widget_from_window_handle(handle, size)
+    }
+
+    /// Returns the real size of a native widget if it differs from the size that the widget was
+    /// created with.
+    #[inline]
+    pub fn native_widget_adjusted_size(&self, native_widget: &NativeWidget) -> Option<Size2D<i32>> {
+        self.0.native_widget_adjusted_size(native_widget)
     }
 }
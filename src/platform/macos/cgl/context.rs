@@ -6,12 +6,25 @@ use super::device::Device;
 use super::error::ToWindowingApiError;
 use super::ffi::{CGLReleaseContext, CGLRetainContext};
 use super::surface::Surface;
-use crate::context::{ContextID, CREATE_CONTEXT_MUTEX};
+use crate::acceleration;
+use crate::bulk::{self, BulkOps};
+use crate::context::{self, ContextID, CREATE_CONTEXT_MUTEX};
+use crate::convert;
+use crate::current::{self, SavedCurrent};
 use crate::gl_utils;
+use crate::info;
+use crate::leak_check::{self, TrackedObject};
 use crate::surface::Framebuffer;
-use crate::{ContextAttributeFlags, ContextAttributes, Error, GLVersion, Gl, SurfaceInfo};
+use crate::ContextDescriptorAttributes;
+use crate::ContextLostHandler;
+use crate::ContextPriority;
+use crate::GlError;
+use crate::{
+    ColorDepth, ContextInfo, GLCapabilities, PlaceholderPolicy, RenderTargetInfo, SurfaceInfo,
+};
+use crate::{ContextAttributeFlags, ContextAttributes, Error, GLVersion, Gl, ResetStatus};
 
-use cgl::{kCGLPFAAllowOfflineRenderers, kCGLPFAAlphaSize, kCGLPFADepthSize};
+use cgl::{kCGLPFAAccelerated, kCGLPFAAllowOfflineRenderers, kCGLPFAAlphaSize, kCGLPFADepthSize};
 use cgl::{kCGLPFAOpenGLProfile, kCGLPFAStencilSize};
 use cgl::{
     CGLChoosePixelFormat, CGLContextObj, CGLCreateContext, CGLDescribePixelFormat, CGLError,
@@ -24,14 +37,15 @@ use core_foundation::bundle::CFBundleGetFunctionPointerForName;
 use core_foundation::bundle::CFBundleRef;
 use core_foundation::string::CFString;
 use std::mem;
+use std::ops::RangeInclusive;
 use std::os::raw::c_void;
 use std::ptr;
 use std::str::FromStr;
-use std::thread;
+use std::thread::{self, ThreadId};
 
 // No CGL error occurred.
 #[allow(non_upper_case_globals)]
-const kCGLNoError: CGLError = 0;
+pub(crate) const kCGLNoError: CGLError = 0;
 
 // Choose a renderer compatible with GL 1.0.
 #[allow(non_upper_case_globals)]
@@ -43,6 +57,14 @@ const kCGLOGLPVersion_3_2_Core: CGLPixelFormatAttribute = 0x3200;
 #[allow(non_upper_case_globals)]
 const kCGLOGLPVersion_GL4_Core: CGLPixelFormatAttribute = 0x4100;
 
+// Pixel format attributes for multisampling.
+#[allow(non_upper_case_globals)]
+const kCGLPFASampleBuffers: CGLPixelFormatAttribute = 55;
+#[allow(non_upper_case_globals)]
+const kCGLPFASamples: CGLPixelFormatAttribute = 56;
+#[allow(non_upper_case_globals)]
+const kCGLPFAMultisample: CGLPixelFormatAttribute = 59;
+
 static OPENGL_FRAMEWORK_IDENTIFIER: &str = "com.apple.opengl";
 
 thread_local! {
@@ -131,10 +153,18 @@ impl Device {
     /// Creates a context descriptor with the given attributes.
     ///
     /// Context descriptors are local to this device.
+    ///
+    /// CGL has no debug or no-error contexts, so `ContextAttributeFlags::DEBUG` and
+    /// `ContextAttributeFlags::NO_ERROR` are ignored. It has no robust contexts either, so
+    /// `ContextAttributeFlags::ROBUST_ACCESS` fails with `Error::RequiredExtensionUnavailable`.
+    /// Contexts are always flushed when released, so `ContextAttributeFlags::RELEASE_BEHAVIOR_NONE`
+    /// fails the same way unless `ContextAttributeFlags::BEST_EFFORT` is present.
     pub fn create_context_descriptor(
         &self,
         attributes: &ContextAttributes,
     ) -> Result<ContextDescriptor, Error> {
+        context::check_attribute_flags(attributes.flags)?;
+        self.0.cancel_token.check()?;
         if attributes
             .flags
             .contains(ContextAttributeFlags::COMPATIBILITY_PROFILE)
@@ -142,6 +172,13 @@ impl Device {
         {
             return Err(Error::UnsupportedGLProfile);
         };
+        if attributes
+            .flags
+            .contains(ContextAttributeFlags::ROBUST_ACCESS)
+        {
+            return Err(Error::RequiredExtensionUnavailable);
+        }
+        context::release_behavior_none(attributes.flags, false)?;
 
         let profile = if attributes.version.major >= 4 {
             kCGLOGLPVersion_GL4_Core
@@ -157,55 +194,69 @@ impl Device {
         } else {
             0
         };
-        let depth_size = if flags.contains(ContextAttributeFlags::DEPTH) {
-            24
-        } else {
-            0
-        };
-        let stencil_size = if flags.contains(ContextAttributeFlags::STENCIL) {
-            8
-        } else {
-            0
-        };
-
-        let mut cgl_pixel_format_attributes = vec![
-            kCGLPFAOpenGLProfile,
-            profile,
-            kCGLPFAAlphaSize,
-            alpha_size,
-            kCGLPFADepthSize,
-            depth_size,
-            kCGLPFAStencilSize,
-            stencil_size,
-        ];
-
-        // This means "opt into the integrated GPU".
-        //
-        // https://supermegaultragroovy.com/2016/12/10/auto-graphics-switching/
-        if self.adapter().0.is_low_power {
-            cgl_pixel_format_attributes.push(kCGLPFAAllowOfflineRenderers);
-        }
-
-        cgl_pixel_format_attributes.extend_from_slice(&[0, 0]);
-
-        unsafe {
-            let (mut cgl_pixel_format, mut cgl_pixel_format_count) = (ptr::null_mut(), 0);
-            let err = CGLChoosePixelFormat(
-                cgl_pixel_format_attributes.as_ptr(),
-                &mut cgl_pixel_format,
-                &mut cgl_pixel_format_count,
-            );
-            if err != kCGLNoError {
-                return Err(Error::PixelFormatSelectionFailed(
-                    err.to_windowing_api_error(),
-                ));
+        let (depth_size, stencil_size) = context::requested_depth_stencil_sizes(attributes);
+        let (depth_size, stencil_size) = (
+            depth_size as CGLPixelFormatAttribute,
+            stencil_size as CGLPixelFormatAttribute,
+        );
+
+        // `kCGLPFASamples` is only a minimum, so each count is tried in turn until a pixel format
+        // with exactly that many samples turns up.
+        for samples in context::sample_counts_to_try(attributes) {
+            let mut cgl_pixel_format_attributes = vec![
+                kCGLPFAOpenGLProfile,
+                profile,
+                kCGLPFAAlphaSize,
+                alpha_size,
+                kCGLPFADepthSize,
+                depth_size,
+                kCGLPFAStencilSize,
+                stencil_size,
+            ];
+            if samples > 0 {
+                cgl_pixel_format_attributes.extend_from_slice(&[
+                    kCGLPFAMultisample,
+                    kCGLPFASampleBuffers,
+                    1,
+                    kCGLPFASamples,
+                    samples as CGLPixelFormatAttribute,
+                ]);
             }
-            if cgl_pixel_format_count == 0 {
-                return Err(Error::NoPixelFormatFound);
+
+            // This means "opt into the integrated GPU".
+            //
+            // https://supermegaultragroovy.com/2016/12/10/auto-graphics-switching/
+            if self.adapter().0.is_low_power {
+                cgl_pixel_format_attributes.push(kCGLPFAAllowOfflineRenderers);
             }
 
-            Ok(ContextDescriptor { cgl_pixel_format })
+            cgl_pixel_format_attributes.extend_from_slice(&[0, 0]);
+
+            unsafe {
+                let (mut cgl_pixel_format, mut cgl_pixel_format_count) = (ptr::null_mut(), 0);
+                let err = CGLChoosePixelFormat(
+                    cgl_pixel_format_attributes.as_ptr(),
+                    &mut cgl_pixel_format,
+                    &mut cgl_pixel_format_count,
+                );
+                if err != kCGLNoError {
+                    return Err(Error::PixelFormatSelectionFailed(
+                        err.to_windowing_api_error(),
+                    ));
+                }
+                if cgl_pixel_format_count == 0 {
+                    continue;
+                }
+
+                let context_descriptor = ContextDescriptor { cgl_pixel_format };
+                let granted_samples =
+                    get_pixel_format_attribute(&context_descriptor, kCGLPFASamples);
+                if granted_samples.max(1) == (samples as i32).max(1) {
+                    return Ok(context_descriptor);
+                }
+            }
         }
+        Err(Error::NoPixelFormatFound)
     }
 
     /// Creates a new OpenGL context.
@@ -221,6 +272,7 @@ impl Device {
         // will fail, returning `kCGLBadConnection`, if multiple threads try to open a display
         // connection simultaneously.
         let mut next_context_id = CREATE_CONTEXT_MUTEX.lock().unwrap();
+        self.0.cancel_token.check()?;
 
         unsafe {
             // Create the CGL context.
@@ -234,6 +286,10 @@ impl Device {
                 return Err(Error::ContextCreationFailed(err.to_windowing_api_error()));
             }
             debug_assert_ne!(cgl_context, ptr::null_mut());
+            if let Err(err) = self.0.cancel_token.check() {
+                CGLReleaseContext(cgl_context);
+                return Err(err);
+            }
 
             // Wrap and return the context.
             let context = Context {
@@ -242,10 +298,33 @@ impl Device {
                 framebuffer: Framebuffer::None,
             };
             next_context_id.0 += 1;
+            leak_check::created(TrackedObject::Context);
             Ok(context)
         }
     }
 
+    /// Creates a context of the newest OpenGL or OpenGL ES version in `versions` that this device
+    /// supports, and returns it along with the descriptor that it was created from.
+    ///
+    /// The version that this settles on is remembered, so later calls with the same range and
+    /// flags don't search again. If the device supports no version in the range, this returns
+    /// `Error::UnsupportedGLVersion`.
+    pub fn create_versioned_context(
+        &mut self,
+        versions: RangeInclusive<GLVersion>,
+        flags: ContextAttributeFlags,
+    ) -> Result<(Context, ContextDescriptor), Error> {
+        let gl_api = self.gl_api();
+        let mut negotiated_versions = mem::take(&mut self.0.negotiated_versions);
+        let result = negotiated_versions.create_context(gl_api, versions, flags, |attributes| {
+            let descriptor = self.create_context_descriptor(attributes)?;
+            let context = self.create_context(&descriptor, None)?;
+            Ok((context, descriptor))
+        });
+        self.0.negotiated_versions = negotiated_versions;
+        result
+    }
+
     /// Wraps a `CGLContext` in a `surfman` context and returns it.
     ///
     /// This function takes ownership of the native context and does not adjust its reference
@@ -261,6 +340,7 @@ impl Device {
             framebuffer: Framebuffer::None,
         };
         next_context_id.0 += 1;
+        leak_check::created(TrackedObject::Context);
         mem::forget(native_context);
         Ok(context)
     }
@@ -279,12 +359,18 @@ impl Device {
             self.destroy_surface(context, &mut surface)?;
         }
 
+        self.0.lost_contexts.forget(context.id);
+        current::forget(context.id);
+        convert::forget_context(context.id);
         unsafe {
-            CGLSetCurrentContext(ptr::null_mut());
+            if CGLGetCurrentContext() == context.cgl_context {
+                CGLSetCurrentContext(ptr::null_mut());
+            }
             CGLReleaseContext(context.cgl_context);
             context.cgl_context = ptr::null_mut();
         }
 
+        leak_check::destroyed(TrackedObject::Context);
         Ok(())
     }
 
@@ -301,14 +387,18 @@ impl Device {
     /// Makes the context the current OpenGL context for this thread.
     ///
     /// After calling this function, it is valid to use OpenGL rendering commands.
+    ///
+    /// Returns `Error::ContextLost` if the context has been lost; see `poll_context_lost()`.
     pub fn make_context_current(&self, context: &Context) -> Result<(), Error> {
-        unsafe {
-            let err = CGLSetCurrentContext(context.cgl_context);
-            if err != kCGLNoError {
-                return Err(Error::MakeCurrentFailed(err.to_windowing_api_error()));
-            }
-            Ok(())
-        }
+        self.0.lost_contexts.check(context.id, || {
+            current::make_current(context.id, || unsafe {
+                let err = CGLSetCurrentContext(context.cgl_context);
+                if err != kCGLNoError {
+                    return Err(Error::MakeCurrentFailed(err.to_windowing_api_error()));
+                }
+                Ok(())
+            })
+        })
     }
 
     /// Removes the current OpenGL context from this thread.
@@ -321,26 +411,56 @@ impl Device {
             if err != kCGLNoError {
                 return Err(Error::MakeCurrentFailed(err.to_windowing_api_error()));
             }
+            current::released();
             Ok(())
         }
     }
 
-    pub(crate) fn temporarily_make_context_current(
+    /// Returns the thread that the context is current on, or `None` if it isn't current anywhere.
+    ///
+    /// Only contexts made current through surfman are tracked.
+    #[inline]
+    pub fn context_current_thread(&self, context: &Context) -> Option<ThreadId> {
+        current::current_thread(context.id)
+    }
+
+    /// Makes the context current for this thread, returning a guard that makes the previously
+    /// current context current again when dropped.
+    ///
+    /// The previous context is restored even if the thread panics while the guard is alive.
+    pub fn make_context_current_guarded(
         &self,
         context: &Context,
     ) -> Result<CurrentContextGuard, Error> {
+        if current::held_for_bulk_ops(context.id) {
+            return Ok(CurrentContextGuard::held());
+        }
         let guard = CurrentContextGuard::new();
         self.make_context_current(context)?;
         Ok(guard)
     }
 
+    /// Makes the context current for this thread, runs `f` with the operations that can be run
+    /// on the context, and then makes the previously current context current again.
+    ///
+    /// The operations don't each make the context current and restore the previous context, as
+    /// they do when called on their own. If the context can't be made current, `f` isn't run.
+    pub fn with_context_current<F, R>(&self, context: &mut Context, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut BulkOps<Self>) -> R,
+    {
+        bulk::with_context_current(self, context, f)
+    }
+
     /// Attaches a surface to a context for rendering.
     ///
-    /// This function takes ownership of the surface. The surface must have been created with this
-    /// context, or an `IncompatibleSurface` error is returned.
+    /// This function takes ownership of the surface. A surface belongs to the context that it was
+    /// created with, so binding it to any other context returns a `SurfaceAlreadyBound` error
+    /// naming that context. Check `Surface::context_id()` beforehand to avoid this, and wrap the
+    /// surface in a surface texture to read it from another context instead.
     ///
-    /// If this function is called with a surface already bound, a `SurfaceAlreadyBound` error is
-    /// returned. To avoid this error, first unbind the existing surface with
+    /// If this context already has a surface bound, a `SurfaceAlreadyBound` error naming this
+    /// context is returned. To avoid this error, first unbind the existing surface with
     /// `unbind_surface_from_context`.
     ///
     /// If an error is returned, the surface is returned alongside it.
@@ -351,12 +471,19 @@ impl Device {
     ) -> Result<(), (Error, Surface)> {
         match context.framebuffer {
             Framebuffer::External(_) => return Err((Error::ExternalRenderTarget, new_surface)),
-            Framebuffer::Surface(_) => return Err((Error::SurfaceAlreadyBound, new_surface)),
+            Framebuffer::Surface(_) => {
+                let context_id = context.id;
+                return Err((Error::SurfaceAlreadyBound { context_id }, new_surface));
+            }
             Framebuffer::None => {}
         }
 
         if new_surface.context_id != context.id {
-            return Err((Error::IncompatibleSurface, new_surface));
+            let context_id = new_surface.context_id;
+            return Err((Error::SurfaceAlreadyBound { context_id }, new_surface));
+        }
+        if let Err(err) = new_surface.system_surface.purge_state.check_usable() {
+            return Err((err, new_surface));
         }
 
         context.framebuffer = Framebuffer::Surface(new_surface);
@@ -385,7 +512,14 @@ impl Device {
                 // TODO(pcwalton): Use `glClientWaitSync` instead to avoid starving the window
                 // server.
                 GL_FUNCTIONS.with(|gl| {
-                    let _guard = self.temporarily_make_context_current(context)?;
+                    let _guard = self.make_context_current_guarded(context)?;
+                    if let Some(ref multisample) = surface.multisample {
+                        multisample.resolve(gl, surface.framebuffer_object);
+                        gl_utils::unbind_framebuffer_if_necessary(
+                            gl,
+                            multisample.framebuffer_object,
+                        );
+                    }
                     unsafe {
                         gl.Flush();
                     }
@@ -407,6 +541,7 @@ impl Device {
             let depth_size = get_pixel_format_attribute(context_descriptor, kCGLPFADepthSize);
             let stencil_size = get_pixel_format_attribute(context_descriptor, kCGLPFAStencilSize);
             let gl_profile = get_pixel_format_attribute(context_descriptor, kCGLPFAOpenGLProfile);
+            let samples = get_pixel_format_attribute(context_descriptor, kCGLPFASamples);
 
             let mut attribute_flags = ContextAttributeFlags::empty();
             attribute_flags.set(ContextAttributeFlags::ALPHA, alpha_size != 0);
@@ -426,35 +561,83 @@ impl Device {
             return ContextAttributes {
                 flags: attribute_flags,
                 version,
+                placeholder_policy: PlaceholderPolicy::Owned,
+                color_depth: ColorDepth::Standard,
+                priority: ContextPriority::Medium,
+                samples: if samples > 1 { samples as u8 } else { 0 },
+                depth_size: Some(depth_size as u8),
+                stencil_size: Some(stencil_size as u8),
             };
         }
+    }
 
-        unsafe fn get_pixel_format_attribute(
-            context_descriptor: &ContextDescriptor,
-            attribute: CGLPixelFormatAttribute,
-        ) -> i32 {
-            let mut value = 0;
-            let err = CGLDescribePixelFormat(
-                context_descriptor.cgl_pixel_format,
-                0,
-                attribute,
-                &mut value,
-            );
-            debug_assert_eq!(err, kCGLNoError);
-            value
+    /// Returns the properties of the context descriptor as plain data, which can be sent to
+    /// another process.
+    pub fn context_descriptor_raw_parts(
+        &self,
+        context_descriptor: &ContextDescriptor,
+    ) -> ContextDescriptorAttributes {
+        unsafe {
+            // Color is always 8 bits per channel here; see `context_descriptor_attributes()`.
+            ContextDescriptorAttributes {
+                attributes: self.context_descriptor_attributes(context_descriptor),
+                color_channel_size: 8,
+                alpha_size: get_pixel_format_attribute(context_descriptor, kCGLPFAAlphaSize) as u8,
+                depth_size: get_pixel_format_attribute(context_descriptor, kCGLPFADepthSize) as u8,
+                stencil_size: get_pixel_format_attribute(context_descriptor, kCGLPFAStencilSize)
+                    as u8,
+            }
         }
     }
 
-    /// Fetches the address of an OpenGL function associated with this context.
+    /// Creates a context descriptor equivalent to the one that `attributes` were taken from,
+    /// possibly on a device in another process.
+    ///
+    /// If this device can't match the version and buffer sizes exactly, this returns
+    /// `Error::UnsupportedGLVersion` or `Error::NoPixelFormatFound`.
+    pub fn create_context_descriptor_from_attributes(
+        &self,
+        attributes: &ContextDescriptorAttributes,
+    ) -> Result<ContextDescriptor, Error> {
+        let context_descriptor = self.create_context_descriptor(&attributes.attributes)?;
+        attributes.check_recreated(&self.context_descriptor_raw_parts(&context_descriptor))?;
+        Ok(context_descriptor)
+    }
+
+    /// Fetches the address of an OpenGL function associated with this context, or `None` if the
+    /// function isn't available.
     ///
     /// OpenGL functions are local to a context. You should not use OpenGL functions on one context
     /// with any other context.
     ///
-    /// This method is typically used with a function like `gl::load_with()` from the `gl` crate to
-    /// load OpenGL function pointers.
-    #[inline]
-    pub fn get_proc_address(&self, _: &Context, symbol_name: &str) -> *const c_void {
-        get_proc_address(symbol_name)
+    /// Functions named with an extension suffix, like `glDrawBuffersAPPLE`, are only returned if
+    /// the context supports an extension from the same vendor. Checking may briefly make the
+    /// context current; the context that was current before this call remains current afterward.
+    pub fn get_proc_address(&self, context: &Context, symbol_name: &str) -> Option<*const c_void> {
+        let address = get_proc_address(symbol_name);
+        if address.is_null() {
+            return None;
+        }
+        let available = gl_utils::entry_point_available(symbol_name, |_| {
+            self.gl_extensions(context).unwrap_or_default()
+        });
+        if available {
+            Some(address)
+        } else {
+            None
+        }
+    }
+
+    /// Returns a function that looks up the OpenGL functions of a context, for loaders like
+    /// `gl::load_with()` that expect a null pointer for functions that aren't available.
+    pub fn proc_address_loader<'a>(
+        &'a self,
+        context: &'a Context,
+    ) -> Box<dyn Fn(&str) -> *const c_void + 'a> {
+        Box::new(move |symbol_name| {
+            self.get_proc_address(context, symbol_name)
+                .unwrap_or(ptr::null())
+        })
     }
 
     /// Returns various information about the surface attached to a context.
@@ -468,6 +651,124 @@ impl Device {
         }
     }
 
+    /// Makes the context current, binds the framebuffer of its surface, and sets the viewport to
+    /// cover that surface.
+    ///
+    /// If no surface is attached, the context is still made current, the default framebuffer is
+    /// bound, and the viewport is left unchanged; the returned info then has a zero size and a
+    /// format of `GL_NONE`. On error, the previously-current context is restored.
+    pub fn begin_rendering(&self, context: &Context) -> Result<RenderTargetInfo, Error> {
+        let guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        let surface_info = self.context_surface_info(context)?;
+        let attributes = self.context_descriptor_attributes(&self.context_descriptor(context));
+        let render_target_info = GL_FUNCTIONS
+            .with(|gl| gl_utils::bind_render_target(gl, surface_info.as_ref(), &attributes));
+        mem::forget(guard);
+        Ok(render_target_info)
+    }
+
+    /// Finishes rendering started with `begin_rendering()` by unbinding the surface's framebuffer,
+    /// flushing the context if `flush` is true.
+    ///
+    /// The context that was current before this call remains current afterward.
+    pub fn end_rendering(&self, context: &Context, flush: bool) -> Result<(), Error> {
+        let _guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        GL_FUNCTIONS.with(|gl| gl_utils::unbind_render_target(gl, flush));
+        Ok(())
+    }
+
+    /// Returns the OpenGL features beyond OpenGL ES 2.0 that the given context supports.
+    ///
+    /// The context that was current before this call remains current afterward.
+    pub fn capabilities(&self, context: &Context) -> Result<GLCapabilities, Error> {
+        let _guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        Ok(GL_FUNCTIONS.with(GLCapabilities::current))
+    }
+
+    /// Returns the names of the OpenGL extensions that the given context supports.
+    ///
+    /// The context that was current before this call remains current afterward.
+    pub fn gl_extensions(&self, context: &Context) -> Result<Vec<String>, Error> {
+        let _guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        Ok(GL_FUNCTIONS.with(info::current_extensions))
+    }
+
+    /// Returns what OpenGL reports about the given context: the version, profile, and flags that
+    /// it was actually granted, and the vendor and renderer strings.
+    ///
+    /// The context that was current before this call remains current afterward.
+    pub fn context_info(&self, context: &Context) -> Result<ContextInfo, Error> {
+        let _guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        Ok(GL_FUNCTIONS.with(ContextInfo::current))
+    }
+
+    /// Returns true unless the given context renders in software: its pixel format isn't
+    /// accelerated, as with the Apple Software Renderer, or its `GL_RENDERER` string names a
+    /// software rasterizer.
+    ///
+    /// A context that can't be made current, such as a lost one, returns false. The context that
+    /// was current before this call remains current afterward.
+    pub fn context_is_hardware_accelerated(&self, context: &Context) -> bool {
+        let context_descriptor = self.context_descriptor(context);
+        let accelerated =
+            unsafe { get_pixel_format_attribute(&context_descriptor, kCGLPFAAccelerated) != 0 };
+        accelerated && acceleration::context_is_hardware_accelerated(self, context)
+    }
+
+    /// Empties the OpenGL error queue of the given context, returning each error that
+    /// `glGetError()` reported until it reported none.
+    ///
+    /// The context that was current before this call remains current afterward.
+    pub fn drain_gl_errors(&self, context: &Context) -> Result<Vec<GlError>, Error> {
+        let _guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        Ok(GL_FUNCTIONS.with(gl_utils::drain_gl_errors))
+    }
+
+    /// Returns whether the given context has been lost to a GPU reset.
+    ///
+    /// Only contexts created with `ContextAttributeFlags::ROBUST_ACCESS` are notified of resets.
+    /// The context that was current before this call remains current afterward.
+    pub fn context_reset_status(&self, context: &Context) -> Result<ResetStatus, Error> {
+        let _guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        Ok(context::current_reset_status(|symbol_name| {
+            self.get_proc_address(context, symbol_name)
+        }))
+    }
+
+    /// Returns true if the given context has been lost, for example to a GPU reset.
+    ///
+    /// CGL doesn't report lost contexts itself, so a context counts as lost once
+    /// `context_reset_status()` reports a reset. From then on, `make_context_current()` returns
+    /// `Error::ContextLost`, and the context can only be destroyed. The context that was current
+    /// before this call remains current afterward.
+    pub fn poll_context_lost(&self, context: &Context) -> bool {
+        self.0
+            .lost_contexts
+            .poll(context.id, || self.context_reset_status(context))
+    }
+
+    /// Registers a function to call with the ID of each context of this device that is found to
+    /// be lost, replacing any previous one, or unregisters it if `handler` is `None`.
+    #[inline]
+    pub fn set_context_lost_handler(&mut self, handler: Option<ContextLostHandler>) {
+        self.0.lost_contexts.set_handler(handler)
+    }
+
+    /// Returns the scheduling priority that the given context was granted.
+    ///
+    /// This may be lower than the priority requested in the context attributes. Platforms that
+    /// can't prioritize contexts report `ContextPriority::Medium`.
+    pub fn context_priority(&self, _: &Context) -> ContextPriority {
+        ContextPriority::Medium
+    }
+
     /// Returns a unique ID representing a context.
     ///
     /// This ID is unique to all currently-allocated contexts. If you destroy a context and create
@@ -477,6 +778,12 @@ impl Device {
         context.id
     }
 
+    /// Returns true if the given context is the current OpenGL context for this thread.
+    #[inline]
+    pub fn is_context_current(&self, context: &Context) -> bool {
+        unsafe { CGLGetCurrentContext() == context.cgl_context }
+    }
+
     /// Given a context, returns its underlying CGL context object.
     ///
     /// The reference count on that context is incremented via `CGLRetainContext()` before
@@ -485,6 +792,30 @@ impl Device {
     pub fn native_context(&self, context: &Context) -> NativeContext {
         unsafe { NativeContext(CGLRetainContext(context.cgl_context)) }
     }
+
+    /// Returns the native context that is current on this thread, for wrapping with
+    /// `create_context_from_native_context()`.
+    ///
+    /// If no context is current, this returns a `NoCurrentContext` error.
+    #[inline]
+    pub fn current_native_context(&self) -> Result<NativeContext, Error> {
+        NativeContext::current()
+    }
+}
+
+unsafe fn get_pixel_format_attribute(
+    context_descriptor: &ContextDescriptor,
+    attribute: CGLPixelFormatAttribute,
+) -> i32 {
+    let mut value = 0;
+    let err = CGLDescribePixelFormat(
+        context_descriptor.cgl_pixel_format,
+        0,
+        attribute,
+        &mut value,
+    );
+    debug_assert_eq!(err, kCGLNoError);
+    value
 }
 
 fn get_proc_address(symbol_name: &str) -> *const c_void {
@@ -494,16 +825,26 @@ fn get_proc_address(symbol_name: &str) -> *const c_void {
     })
 }
 
+/// Restores the CGL context that was current on this thread when it was created, once it is
+/// dropped.
 #[must_use]
-pub(crate) struct CurrentContextGuard {
+pub struct CurrentContextGuard {
     old_cgl_context: CGLContextObj,
+    tracked: SavedCurrent,
+    // Set if the context was already current for `Device::with_context_current()`, which puts
+    // back the previous context itself.
+    held: bool,
 }
 
 impl Drop for CurrentContextGuard {
     fn drop(&mut self) {
+        if self.held {
+            return;
+        }
         unsafe {
             CGLSetCurrentContext(self.old_cgl_context);
         }
+        current::restore(self.tracked);
     }
 }
 
@@ -512,9 +853,21 @@ impl CurrentContextGuard {
         unsafe {
             CurrentContextGuard {
                 old_cgl_context: CGLGetCurrentContext(),
+                tracked: current::save(),
+                held: false,
             }
         }
     }
+
+    // Returns a guard that restores nothing, for a context that `current::held_for_bulk_ops()`
+    // says is current already.
+    fn held() -> CurrentContextGuard {
+        CurrentContextGuard {
+            old_cgl_context: ptr::null_mut(),
+            tracked: current::save(),
+            held: true,
+        }
+    }
 }
 
 impl Clone for NativeContext {
@@ -4,7 +4,10 @@
 
 use super::connection::Connection;
 use crate::platform::macos::system::device::{Adapter as SystemAdapter, Device as SystemDevice};
-use crate::GLApi;
+use crate::DebugFill;
+use crate::LowMemoryOptions;
+use crate::{CancelToken, DeviceIdentity, GLApi};
+use crate::{SchedulingClass, SchedulingEnforcement};
 
 pub use crate::platform::macos::system::device::NativeDevice;
 
@@ -44,4 +47,74 @@ impl Device {
     pub fn gl_api(&self) -> GLApi {
         GLApi::GL
     }
+
+    /// Returns the identity of the physical GPU that this device renders with: the registry ID
+    /// of its Metal device.
+    #[inline]
+    pub fn identity(&self) -> DeviceIdentity {
+        self.0.identity()
+    }
+
+    /// Returns a token that can cancel context and surface creation on this device from any
+    /// thread.
+    #[inline]
+    pub fn cancellation_token(&self) -> CancelToken {
+        self.0.cancel_token.clone()
+    }
+
+    /// Sets what kind of work this device's contexts do, and returns how that is enforced.
+    ///
+    /// CGL can't prioritize contexts, so `Background` devices have their swap chains paced in
+    /// software. See `SchedulingEnforcement`.
+    #[inline]
+    pub fn set_scheduling_class(&mut self, class: SchedulingClass) -> SchedulingEnforcement {
+        self.0.set_scheduling_class(class)
+    }
+
+    /// Returns the scheduling class that was last set with `set_scheduling_class()`.
+    #[inline]
+    pub fn scheduling_class(&self) -> SchedulingClass {
+        self.0.scheduling_class()
+    }
+
+    /// Returns how this device enforces its scheduling class.
+    #[inline]
+    pub fn scheduling_enforcement(&self) -> SchedulingEnforcement {
+        self.0.scheduling_enforcement()
+    }
+
+    /// Makes this device save memory at the expense of rendering quality until
+    /// `exit_low_memory_mode()` is called, and returns how many pooled surfaces were destroyed.
+    ///
+    /// CGL doesn't pool surfaces, so this always returns zero. See `LowMemoryOptions`.
+    #[inline]
+    pub fn enter_low_memory_mode(&mut self, options: LowMemoryOptions) -> usize {
+        self.0.enter_low_memory_mode(options)
+    }
+
+    /// Leaves low-memory mode, so that surfaces created from now on get their usual formats.
+    #[inline]
+    pub fn exit_low_memory_mode(&mut self) {
+        self.0.exit_low_memory_mode()
+    }
+
+    /// Returns the options that this device entered low-memory mode with, or `None` if it isn't
+    /// in low-memory mode.
+    #[inline]
+    pub fn low_memory_options(&self) -> Option<LowMemoryOptions> {
+        self.0.low_memory_options()
+    }
+
+    /// Makes this device fill each surface that it creates from now on, and each surface that a
+    /// swap chain recycles, with a recognizable pattern. See `DebugFill`.
+    #[inline]
+    pub fn set_debug_surface_fill(&mut self, fill: DebugFill) {
+        self.0.set_debug_surface_fill(fill)
+    }
+
+    /// Returns the debug fill that was last set with `set_debug_surface_fill()`.
+    #[inline]
+    pub fn debug_surface_fill(&self) -> DebugFill {
+        self.0.debug_surface_fill()
+    }
 }
@@ -2,10 +2,23 @@
 //
 //! FFI declarations not provided by the upstream `cgl` crate.
 
-use cgl::CGLContextObj;
+use crate::gl::types::{GLenum, GLsizei, GLuint};
+use cgl::{CGLContextObj, CGLError};
+use io_surface::IOSurfaceRef;
 
 #[link(name = "OpenGL", kind = "framework")]
 extern "C" {
     pub(crate) fn CGLRetainContext(ctx: CGLContextObj) -> CGLContextObj;
     pub(crate) fn CGLReleaseContext(ctx: CGLContextObj);
+    pub(crate) fn CGLTexImageIOSurface2D(
+        ctx: CGLContextObj,
+        target: GLenum,
+        internal_format: GLenum,
+        width: GLsizei,
+        height: GLsizei,
+        format: GLenum,
+        ty: GLenum,
+        io_surface: IOSurfaceRef,
+        plane: GLuint,
+    ) -> CGLError;
 }
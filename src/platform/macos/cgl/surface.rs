@@ -2,22 +2,51 @@
 //
 //! Surface management for macOS.
 
-use super::context::{Context, GL_FUNCTIONS};
+use super::context::{kCGLNoError, Context, GL_FUNCTIONS};
 use super::device::Device;
+use super::ffi::CGLTexImageIOSurface2D;
+use crate::bulk;
+use crate::clear::ClearRegion;
 use crate::context::ContextID;
+use crate::convert::{self, Swizzle};
 use crate::gl::types::{GLenum, GLint, GLuint};
 use crate::gl_utils;
+use crate::invalidate::PostPresentInvalidation;
+use crate::leak_check::{self, TrackedObject};
 use crate::platform::macos::system::surface::Surface as SystemSurface;
-use crate::renderbuffers::Renderbuffers;
-use crate::{gl, Error, SurfaceAccess, SurfaceID, SurfaceInfo, SurfaceType, WindowingApiError};
+use crate::readback;
+use crate::renderbuffers::{Multisample, Renderbuffers};
+use crate::surface::check_surface_size;
+use crate::upload;
+use crate::vsync::AutoVsync;
+use crate::BufferRelease;
+use crate::DmaBuf;
+use crate::ColorBits;
+use crate::ColorProfileChangedHandler;
+use crate::InvalidateSet;
+use crate::NativeFormat;
+use crate::PausedPresentation;
+use crate::PresentationStatistics;
+use crate::SurfaceFence;
+use crate::SurfaceFormat;
+use crate::SurfaceScaleChangedHandler;
+use crate::SurfaceTransform;
+use crate::SurfaceType;
+use crate::TextureOwnership;
+use crate::WindowingApiError;
+use crate::{gl, ColorProfile, Error, PresentRecord, SurfaceAccess, SurfaceID, SurfaceInfo};
+use crate::{AutoVsyncPolicy, AutoVsyncStats, SurfaceConfig, SurfaceIntent};
+use crate::{ColorEncoding, Purgeability, ReclaimResult, SurfaceOptions, SurfaceResizedHandler};
 
+use cgl::{kCGLCPSwapInterval, CGLContextObj, CGLGetCurrentContext, CGLSetParameter};
 use core_foundation::base::TCFType;
-use euclid::default::Size2D;
-use io_surface::{self, IOSurface};
+use euclid::default::{Rect, Size2D};
 use std::fmt::{self, Debug, Formatter};
 use std::marker::PhantomData;
+use std::os::fd::BorrowedFd;
+use std::time::Duration;
 
-pub use crate::platform::macos::system::surface::{NativeSurface, NativeWidget};
+pub use crate::platform::macos::system::surface::{NativeSurface, NativeWidget, SurfaceDataGuard};
 
 const SURFACE_GL_TEXTURE_TARGET: GLenum = gl::TEXTURE_RECTANGLE;
 
@@ -43,6 +72,12 @@ pub struct Surface {
     pub(crate) framebuffer_object: GLuint,
     pub(crate) texture_object: GLuint,
     pub(crate) renderbuffers: Renderbuffers,
+    pub(crate) multisample: Option<Multisample>,
+    pub(crate) invalidation: PostPresentInvalidation,
+    // The bounds of the damage of the last present, or `None` if it presented everything. The
+    // `IOSurface` about to be shown last held the frame before that, so it's out of date there too.
+    pub(crate) previous_damage: Option<Rect<i32>>,
+    pub(crate) auto_vsync: Option<AutoVsync>,
 }
 
 /// Represents an OpenGL texture that wraps a surface.
@@ -64,7 +99,12 @@ unsafe impl Send for Surface {}
 
 impl Debug for Surface {
     fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
-        write!(formatter, "Surface({:x})", self.id().0)
+        write!(
+            formatter,
+            "Surface({:x}, {:?})",
+            self.id().0,
+            self.system_surface.access
+        )
     }
 }
 
@@ -85,14 +125,52 @@ impl Device {
         access: SurfaceAccess,
         surface_type: SurfaceType<NativeWidget>,
     ) -> Result<Surface, Error> {
-        let mut system_surface = self.0.create_surface(access, surface_type)?;
+        self.create_surface_with_options(context, access, surface_type, SurfaceOptions::default())
+    }
+
+    /// Creates a surface of each of the given types, in order.
+    ///
+    /// The device's cancellation token is checked before each surface, as well as inside each
+    /// creation. If any surface fails or is cancelled, the ones created so far are destroyed and
+    /// the error is returned, so the batch creates either all of its surfaces or none.
+    pub fn create_surfaces<I>(
+        &mut self,
+        context: &mut Context,
+        access: SurfaceAccess,
+        surface_types: I,
+    ) -> Result<Vec<Surface>, Error>
+    where
+        I: IntoIterator<Item = SurfaceType<NativeWidget>>,
+    {
+        bulk::create_surfaces(self, context, access, surface_types)
+    }
+
+    /// Creates either a generic or a widget surface, with options such as its colorspace.
+    ///
+    /// `create_surface()` is this method with the default options. sRGB surfaces bind their
+    /// `IOSurface` with the `GL_SRGB8_ALPHA8` internal format, and `SurfaceFormat::Rgba16F` and
+    /// `Rgb10A2` surfaces with `GL_RGBA16F` and `GL_RGB10_A2`. Widget surfaces can have any
+    /// format, since Core Animation displays all three.
+    pub fn create_surface_with_options(
+        &mut self,
+        context: &Context,
+        access: SurfaceAccess,
+        surface_type: SurfaceType<NativeWidget>,
+        options: SurfaceOptions,
+    ) -> Result<Surface, Error> {
+        self.0.cancel_token.check()?;
+        if let SurfaceType::Generic { size } = surface_type {
+            check_surface_size(size, &self.capabilities(context)?)?;
+        }
+        let mut system_surface =
+            self.0
+                .create_surface_with_options(access, surface_type, options)?;
         self.0.set_surface_flipped(&mut system_surface, true);
 
-        let _guard = self.temporarily_make_context_current(context);
-        GL_FUNCTIONS.with(|gl| {
+        let _guard = self.make_context_current_guarded(context);
+        let mut surface = GL_FUNCTIONS.with(|gl| {
             unsafe {
-                let mut texture_object =
-                    self.bind_to_gl_texture(&system_surface.io_surface, &system_surface.size);
+                let mut texture_object = self.bind_to_gl_texture(&system_surface);
 
                 let mut framebuffer_object = 0;
                 gl.GenFramebuffers(1, &mut framebuffer_object);
@@ -109,9 +187,22 @@ impl Device {
                 let context_descriptor = self.context_descriptor(context);
                 let context_attributes = self.context_descriptor_attributes(&context_descriptor);
 
-                let mut renderbuffers =
-                    Renderbuffers::new(gl, &system_surface.size, &context_attributes);
-                renderbuffers.bind_to_current_framebuffer(gl);
+                // Multisampled surfaces keep their depth and stencil with their samples.
+                let mut multisample = Multisample::new(
+                    gl,
+                    &system_surface.size,
+                    &context_attributes,
+                    surface_format(&system_surface),
+                );
+                let mut renderbuffers = match multisample {
+                    Some(_) => Renderbuffers::none(),
+                    None => {
+                        let renderbuffers =
+                            Renderbuffers::new(gl, &system_surface.size, &context_attributes);
+                        renderbuffers.bind_to_current_framebuffer(gl);
+                        renderbuffers
+                    }
+                };
 
                 if gl.GetError() != gl::NO_ERROR
                     || gl.CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE
@@ -120,6 +211,9 @@ impl Device {
                     // the way to tell that it has failed is to look at the framebuffer status
                     // while the surface is attached.
                     renderbuffers.destroy(gl);
+                    if let Some(ref mut multisample) = multisample {
+                        multisample.destroy(gl);
+                    }
                     if framebuffer_object != 0 {
                         gl.DeleteFramebuffers(1, &mut framebuffer_object);
                     }
@@ -131,15 +225,37 @@ impl Device {
                     return Err(Error::SurfaceCreationFailed(WindowingApiError::Failed));
                 }
 
+                leak_check::created(TrackedObject::Surface);
                 Ok(Surface {
                     system_surface,
                     context_id: context.id,
                     framebuffer_object,
                     texture_object,
                     renderbuffers,
+                    multisample,
+                    invalidation: PostPresentInvalidation::default(),
+                    previous_damage: None,
+                    auto_vsync: None,
                 })
             }
-        })
+        })?;
+        self.debug_fill_new_surface(context, &mut surface);
+        Ok(surface)
+    }
+
+    /// Returns the access mode, type, and options that suit a surface of the given size, created
+    /// with the given context for the given use, on this device, along with hints for using it.
+    ///
+    /// IOSurfaces can be mapped by the CPU, so surfaces that move pixels between the CPU and the
+    /// GPU get CPU access.
+    pub fn recommended_surface_config(
+        &self,
+        context: &Context,
+        intent: SurfaceIntent<NativeWidget>,
+        size: Size2D<i32>,
+    ) -> Result<SurfaceConfig<NativeWidget>, Error> {
+        let capabilities = self.capabilities(context)?;
+        Ok(SurfaceConfig::recommend(intent, size, true, &capabilities))
     }
 
     /// Creates a surface texture from an existing generic surface for use with the given context.
@@ -160,13 +276,14 @@ impl Device {
         if surface.system_surface.view_info.is_some() {
             return Err((Error::WidgetAttached, surface));
         }
+        if let Err(err) = surface.system_surface.purge_state.check_usable() {
+            return Err((err, surface));
+        }
 
-        let _guard = self.temporarily_make_context_current(context).unwrap();
+        let _guard = self.make_context_current_guarded(context).unwrap();
 
-        let texture_object = self.bind_to_gl_texture(
-            &surface.system_surface.io_surface,
-            &surface.system_surface.size,
-        );
+        let texture_object = self.bind_to_gl_texture(&surface.system_surface);
+        leak_check::created(TrackedObject::SurfaceTexture);
         Ok(SurfaceTexture {
             surface,
             texture_object,
@@ -174,14 +291,15 @@ impl Device {
         })
     }
 
-    fn bind_to_gl_texture(&self, io_surface: &IOSurface, size: &Size2D<i32>) -> GLuint {
+    fn bind_to_gl_texture(&self, system_surface: &SystemSurface) -> GLuint {
         GL_FUNCTIONS.with(|gl| unsafe {
+            gl_utils::drain_pre_existing_gl_errors(gl);
             let mut texture = 0;
             gl.GenTextures(1, &mut texture);
             debug_assert_ne!(texture, 0);
 
             gl.BindTexture(gl::TEXTURE_RECTANGLE, texture);
-            io_surface.bind_to_gl_texture(size.width, size.height, true);
+            bind_io_surface(system_surface);
 
             gl.TexParameteri(
                 gl::TEXTURE_RECTANGLE,
@@ -212,6 +330,21 @@ impl Device {
         })
     }
 
+    /// Wraps an existing GL texture in a generic surface.
+    ///
+    /// Surfaces on this platform are `IOSurface`s, and CGL can't back an `IOSurface` with a
+    /// texture that OpenGL created, so this returns `UnsupportedOnThisPlatform`.
+    pub fn create_surface_from_texture(
+        &mut self,
+        _: &mut Context,
+        _: GLuint,
+        _: &Size2D<i32>,
+        _: GLenum,
+        _: TextureOwnership,
+    ) -> Result<Surface, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
     /// Destroys a surface.
     ///
     /// The supplied context must be the context the surface is associated with, or this returns
@@ -229,15 +362,22 @@ impl Device {
                 return Err(Error::IncompatibleSurface);
             }
 
+            let _guard = self.make_context_current_guarded(context);
             unsafe {
                 gl_utils::destroy_framebuffer(gl, surface.framebuffer_object);
                 surface.framebuffer_object = 0;
 
                 surface.renderbuffers.destroy(gl);
+                if let Some(mut multisample) = surface.multisample.take() {
+                    multisample.destroy(gl);
+                }
                 gl.DeleteTextures(1, &surface.texture_object);
                 surface.texture_object = 0;
             }
 
+            if !surface.system_surface.destroyed {
+                leak_check::destroyed(TrackedObject::Surface);
+            }
             self.0.destroy_surface(&mut surface.system_surface)
         })
     }
@@ -251,15 +391,17 @@ impl Device {
     /// occur.
     pub fn destroy_surface_texture(
         &self,
-        _: &mut Context,
+        context: &mut Context,
         mut surface_texture: SurfaceTexture,
     ) -> Result<Surface, (Error, SurfaceTexture)> {
+        let _guard = self.make_context_current_guarded(context);
         GL_FUNCTIONS.with(|gl| {
             unsafe {
                 gl.DeleteTextures(1, &surface_texture.texture_object);
                 surface_texture.texture_object = 0;
             }
 
+            leak_check::destroyed(TrackedObject::SurfaceTexture);
             Ok(surface_texture.surface)
         })
     }
@@ -272,6 +414,16 @@ impl Device {
         surface_texture.texture_object
     }
 
+    /// Returns the sized internal format of the OpenGL texture of this surface texture, such as
+    /// `GL_RGBA8` or `GL_RGBA16F`.
+    ///
+    /// This is the `SurfaceInfo::format` of the wrapped surface, which tells shaders whether they
+    /// sample normalized or floating-point values.
+    #[inline]
+    pub fn surface_texture_format(&self, surface_texture: &SurfaceTexture) -> GLenum {
+        surface_format(&surface_texture.surface.system_surface)
+    }
+
     /// Returns the OpenGL texture target needed to read from this surface texture.
     ///
     /// This will be `GL_TEXTURE_2D` or `GL_TEXTURE_RECTANGLE`, depending on platform.
@@ -286,26 +438,150 @@ impl Device {
     /// associated widgets until this method is called.
     ///
     /// The supplied context must match the context the surface was created with, or an
-    /// `IncompatibleSurface` error is returned.
-    pub fn present_surface(&self, _: &Context, surface: &mut Surface) -> Result<(), Error> {
-        self.0.present_surface(&mut surface.system_surface)?;
+    /// `IncompatibleSurface` error is returned. The context doesn't need to be current, and the
+    /// context that was current before this call remains current afterward.
+    ///
+    /// A view that has been resized since the surface was last sized fails with
+    /// `SurfaceSizeMismatch` without presenting.
+    pub fn present_surface(&self, context: &Context, surface: &mut Surface) -> Result<(), Error> {
+        self.present(context, surface, None)
+    }
 
-        GL_FUNCTIONS.with(|gl| {
-            unsafe {
-                let size = surface.system_surface.size;
-                gl.BindTexture(gl::TEXTURE_RECTANGLE, surface.texture_object);
-                surface
-                    .system_surface
-                    .io_surface
-                    .bind_to_gl_texture(size.width, size.height, true);
-                gl.BindTexture(gl::TEXTURE_RECTANGLE, 0);
-            }
+    /// Displays the changed parts of a widget surface on screen.
+    ///
+    /// `damage` lists the rectangles that changed since the last present, measured from the
+    /// bottom left as in OpenGL. Core Animation always shows the whole `IOSurface`, so this only
+    /// uses the damage to restrict the resolve of multisampled widgets to the bounds of the
+    /// damage of this present and the last one. An empty list means that nothing changed, and
+    /// skips the present entirely.
+    pub fn present_surface_with_damage(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        self.present(context, surface, Some(damage))
+    }
 
-            Ok(())
-        })
+    fn present(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: Option<&[Rect<i32>]>,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if surface.system_surface.view_info.is_none() {
+            return Err(Error::NoWidgetAttached);
+        }
+        if matches!(damage, Some([])) {
+            return Ok(());
+        }
+        let _guard = self.make_context_current_guarded(context)?;
+
+        // Resolve the samples of multisampled widgets into the buffer that's about to be shown,
+        // wherever it's out of date.
+        let bounds = Rect::from_size(surface.system_surface.size);
+        let damage = damage.map(|damage| {
+            damage
+                .iter()
+                .fold(Rect::zero(), |union, rect| union.union(rect))
+                .intersection(&bounds)
+                .unwrap_or_else(Rect::zero)
+        });
+        if let Some(ref multisample) = surface.multisample {
+            let resolve_rect = match (damage, surface.previous_damage) {
+                (Some(damage), Some(previous_damage)) => damage.union(&previous_damage),
+                _ => bounds,
+            };
+            GL_FUNCTIONS.with(|gl| unsafe {
+                multisample.resolve_rect(gl, surface.framebuffer_object, &resolve_rect);
+                gl.Flush();
+            });
+        }
+        let rescale_to = self
+            .0
+            .present_surface_for_rescale(&mut surface.system_surface)?;
+        surface.previous_damage = damage;
+
+        // Rebind the new front buffer to the texture in the surface's own context.
+
+        let framebuffer_object = draw_framebuffer_object(surface);
+        GL_FUNCTIONS.with(|gl| unsafe {
+            gl.BindTexture(gl::TEXTURE_RECTANGLE, surface.texture_object);
+            bind_io_surface(&surface.system_surface);
+            gl.BindTexture(gl::TEXTURE_RECTANGLE, 0);
+            surface.invalidation.invalidate(gl, framebuffer_object);
+        });
+
+        if let Some(ref mut auto_vsync) = surface.auto_vsync {
+            auto_vsync.update(&surface.system_surface.present_history, |interval| unsafe {
+                set_context_swap_interval(context.cgl_context, interval)
+            });
+        }
+        if let Some(size) = rescale_to {
+            self.resize_surface(context, surface, size)?;
+        }
+        Ok(())
+    }
+
+    /// Pausing presentation isn't supported on this backend: widget surfaces return
+    /// `UnsupportedOnThisPlatform`, and generic surfaces `NoWidgetAttached`.
+    pub fn pause_presentation(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<PausedPresentation, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if surface.system_surface.view_info.is_none() {
+            return Err(Error::NoWidgetAttached);
+        }
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Since presentation can't be paused on this backend, `paused` was returned for a surface of
+    /// another, so this returns `IncompatiblePausedPresentation`.
+    pub fn resume_presentation(
+        &self,
+        _: &Context,
+        _: &mut Surface,
+        _: PausedPresentation,
+        _: bool,
+    ) -> Result<(), Error> {
+        Err(Error::IncompatiblePausedPresentation)
+    }
+
+    /// Fences aren't supported on this backend, so this returns `UnsupportedOnThisPlatform`.
+    pub fn present_surface_with_fence(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<SurfaceFence, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        Err(Error::UnsupportedOnThisPlatform)
     }
 
-    /// Resizes a widget surface.
+    /// Since fences can't be created on this backend, `fence` was returned by a device of
+    /// another, so this returns `IncompatibleSurfaceFence`.
+    pub fn wait_fence(&self, _: &Context, _: &SurfaceFence) -> Result<(), Error> {
+        Err(Error::IncompatibleSurfaceFence)
+    }
+
+    /// Fences can't be created on this backend, so this returns `UnsupportedOnThisPlatform`.
+    pub fn import_sync_fd(&self, _: &Context, _: BorrowedFd) -> Result<SurfaceFence, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Resizes a surface in place.
+    ///
+    /// Generic surfaces get a new `IOSurface` of the new size, which is attached to their existing
+    /// framebuffer object, so their ID changes. Widget surfaces have their width rounded up to a
+    /// multiple of 4, which `surface_info()` reports.
     pub fn resize_surface(
         &self,
         context: &Context,
@@ -315,20 +591,18 @@ impl Device {
         if context.id != surface.context_id {
             return Err(Error::IncompatibleSurface);
         }
+        check_surface_size(size, &self.capabilities(context)?)?;
 
-        let _guard = self.temporarily_make_context_current(context);
+        let _guard = self.make_context_current_guarded(context);
         let _guard = self.temporarily_bind_framebuffer(surface.framebuffer_object);
 
         self.0.resize_surface(&mut surface.system_surface, size)?;
-
-        let context_descriptor = self.context_descriptor(context);
-        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        let size = surface.system_surface.size;
 
         GL_FUNCTIONS.with(|gl| {
             unsafe {
                 // Recreate the GL texture and bind it to the FBO
-                let texture_object =
-                    self.bind_to_gl_texture(&surface.system_surface.io_surface, &size);
+                let texture_object = self.bind_to_gl_texture(&surface.system_surface);
                 gl.FramebufferTexture2D(
                     gl::FRAMEBUFFER,
                     gl::COLOR_ATTACHMENT0,
@@ -337,15 +611,15 @@ impl Device {
                     0,
                 );
 
-                // Recreate the GL renderbuffers and bind them to the FBO
-                let renderbuffers = Renderbuffers::new(gl, &size, &context_attributes);
-                renderbuffers.bind_to_current_framebuffer(gl);
+                // Reallocate the GL renderbuffers in place, so that the FBOs stay valid
+                surface.renderbuffers.resize(gl, &size);
+                if let Some(ref mut multisample) = surface.multisample {
+                    multisample.resize(gl, &size);
+                }
 
                 gl.DeleteTextures(1, &surface.texture_object);
-                surface.renderbuffers.destroy(gl);
-
                 surface.texture_object = texture_object;
-                surface.renderbuffers = renderbuffers;
+                surface.previous_damage = None;
 
                 debug_assert_eq!(
                     (gl.GetError(), gl.CheckFramebufferStatus(gl::FRAMEBUFFER)),
@@ -383,10 +657,387 @@ impl Device {
             size: system_surface_info.size,
             id: system_surface_info.id,
             context_id: surface.context_id,
-            framebuffer_object: surface.framebuffer_object,
+            framebuffer_object: draw_framebuffer_object(surface),
+            access: surface.system_surface.access,
+            // Widgets are IOSurfaces too, which surfman always allocates with 8-bit channels.
+            format: surface_format(&surface.system_surface),
+            purgeability: surface.system_surface.purge_state.purgeability(),
+            colorspace: surface.system_surface.colorspace,
+            color_bits: ColorBits::of_format(surface_format(&surface.system_surface)),
+            // Presenting swaps a widget's `IOSurface` with the one that was on screen.
+            preserves_contents: surface.system_surface.view_info.is_none(),
+            // Widgets render to a texture of their `IOSurface` too.
+            texture_target: SURFACE_GL_TEXTURE_TARGET,
+            stride: Some(system_surface_info.stride),
+            native_format: NativeFormat::IoSurface(system_surface_info.pixel_format),
         }
     }
 
+    /// Sets the number of vertical blanks that `present_surface()` waits for before showing each
+    /// frame on a widget surface, and returns the interval that took effect.
+    ///
+    /// The interval is set on the context with `kCGLCPSwapInterval`, which only distinguishes
+    /// between 0 and 1, so other intervals return `UnsupportedOnThisPlatform`. CGL has no
+    /// adaptive vsync, so an interval of -1 is treated as 1.
+    pub fn set_swap_interval(
+        &mut self,
+        context: &Context,
+        surface: &Surface,
+        interval: i32,
+    ) -> Result<i32, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if surface.system_surface.view_info.is_none() {
+            return Err(Error::NoWidgetAttached);
+        }
+        let interval = interval.saturating_abs();
+        if interval != 0 && interval != 1 {
+            return Err(Error::UnsupportedOnThisPlatform);
+        }
+
+        unsafe { set_context_swap_interval(context.cgl_context, interval) }
+    }
+
+    /// Returns true if a widget surface accepts the negative swap intervals of adaptive vsync.
+    ///
+    /// CGL has no adaptive vsync, so this always returns false.
+    #[inline]
+    pub fn supports_adaptive_swap(&self, _: &Surface) -> bool {
+        false
+    }
+
+    /// Lets surfman switch a widget surface between vsync and immediate presentation by itself,
+    /// following `policy`, or stops it with `None`.
+    ///
+    /// The frame time is measured from the return of the previous `present_surface()`, once Core
+    /// Animation has been handed the frame.
+    pub fn set_auto_swap_interval(
+        &mut self,
+        context: &Context,
+        surface: &mut Surface,
+        policy: Option<AutoVsyncPolicy>,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if surface.system_surface.view_info.is_none() {
+            return Err(Error::NoWidgetAttached);
+        }
+        if policy.is_some() {
+            unsafe {
+                set_context_swap_interval(context.cgl_context, 1)?;
+            }
+        }
+        surface.auto_vsync = policy.map(AutoVsync::new);
+        Ok(())
+    }
+
+    /// Returns what automatic vsync has decided for a surface, or `None` if it isn't enabled.
+    #[inline]
+    pub fn auto_swap_interval_stats(&self, surface: &Surface) -> Option<AutoVsyncStats> {
+        surface.auto_vsync.as_ref().map(AutoVsync::stats)
+    }
+
+    /// Waits until the window server has released the buffer of the frame before the last one
+    /// presented on a widget surface, which the next frame is drawn into, or until `timeout`
+    /// elapses, and reports whether the call had to block.
+    pub fn wait_previous_buffer_released(
+        &self,
+        surface: &Surface,
+        timeout: Duration,
+    ) -> Result<BufferRelease, Error> {
+        self.0
+            .wait_previous_buffer_released(&surface.system_surface, timeout)
+    }
+
+    /// Chooses which attachments of a surface are invalidated right after each present.
+    ///
+    /// The color buffer of a generic surface is what consumers read once it's presented, so
+    /// invalidating it returns `PreservedColorBuffer`. Widget surfaces draw each frame into an
+    /// `IOSurface` that the window server has released, whose contents are stale anyway.
+    pub fn set_post_present_invalidation(
+        &self,
+        surface: &mut Surface,
+        set: InvalidateSet,
+    ) -> Result<(), Error> {
+        let color_preserved = surface.system_surface.view_info.is_none();
+        surface.invalidation.change(set, color_preserved)
+    }
+
+    /// Returns which attachments of a surface are invalidated right after each present.
+    #[inline]
+    pub fn post_present_invalidation(&self, surface: &Surface) -> InvalidateSet {
+        surface.invalidation.set()
+    }
+
+    /// Invalidates the attachments of a generic surface that were chosen with
+    /// `set_post_present_invalidation()`, making the context current for the call.
+    ///
+    /// Widget surfaces are invalidated by `present_surface()`, and return `WidgetAttached`.
+    pub fn invalidate_surface(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if surface.system_surface.view_info.is_some() {
+            return Err(Error::WidgetAttached);
+        }
+        let _guard = self.make_context_current_guarded(context)?;
+        let framebuffer_object = draw_framebuffer_object(surface);
+        GL_FUNCTIONS.with(|gl| unsafe { surface.invalidation.invalidate(gl, framebuffer_object) });
+        Ok(())
+    }
+
+    /// Clears a region of a surface, measured from its top left, to `color`, and its depth and
+    /// stencil buffers to `depth` and `stencil` if they're given and the surface has them.
+    ///
+    /// Widget surfaces draw into an `IOSurface` through a framebuffer object, as generic surfaces
+    /// do, so both are cleared the same way, with the context current for the call. The state
+    /// that the clear changes is put back afterward.
+    pub fn clear_surface_region(
+        &self,
+        context: &mut Context,
+        surface: &mut Surface,
+        rect: Option<Rect<i32>>,
+        color: [f32; 4],
+        depth: Option<f32>,
+        stencil: Option<i32>,
+    ) -> Result<(), Error> {
+        let region = ClearRegion::new(rect, color, depth, stencil);
+        self.clear_surface_with(context, surface, &region)
+    }
+
+    /// Fills a surface with this device's debug fill, as if it had just been created, or does
+    /// nothing if the fill is `DebugFill::None`.
+    pub fn debug_fill_surface(
+        &self,
+        context: &mut Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        match self.0.debug_surface_fill().region() {
+            Some(region) => self.clear_surface_with(context, surface, &region),
+            None => Ok(()),
+        }
+    }
+
+    // Fills a new surface with the debug fill, if there is one. The surface is usable either way,
+    // so failing to fill it is only logged.
+    fn debug_fill_new_surface(&self, context: &Context, surface: &mut Surface) {
+        if let Some(region) = self.0.debug_surface_fill().region() {
+            if let Err(err) = self.clear_surface_with(context, surface, &region) {
+                warn!(
+                    "Failed to fill a new surface with the debug fill: {:?}",
+                    err
+                );
+            }
+        }
+    }
+
+    fn clear_surface_with(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        region: &ClearRegion,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        let _guard = self.make_context_current_guarded(context)?;
+        let framebuffer_object = draw_framebuffer_object(surface);
+        let size = surface.system_surface.size;
+        GL_FUNCTIONS.with(|gl| unsafe { region.clear(gl, framebuffer_object, size) })
+    }
+
+    /// Returns how many frames ago the back buffer of a widget surface was last presented, or 0
+    /// if its contents are undefined.
+    ///
+    /// Widget surfaces draw each frame into whichever `IOSurface` the window server has released,
+    /// without tracking what it last held, so this always returns 0 for them.
+    pub fn surface_buffer_age(&self, surface: &Surface) -> Result<u32, Error> {
+        match surface.system_surface.view_info {
+            Some(_) => Ok(0),
+            None => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Returns the current size of a widget surface's view, in device pixels.
+    #[inline]
+    pub fn query_surface_native_size(&self, surface: &Surface) -> Result<Size2D<i32>, Error> {
+        self.0.query_surface_native_size(&surface.system_surface)
+    }
+
+    /// Returns the rotation that the window system applies to a widget surface's frames to show
+    /// them on its display.
+    ///
+    /// macOS rotates frames itself, so this is always `SurfaceTransform::Identity`.
+    #[inline]
+    pub fn surface_transform(&self, _surface: &Surface) -> SurfaceTransform {
+        SurfaceTransform::Identity
+    }
+
+    /// Registers a function to call with the ID and new size of each widget surface of this
+    /// device that is found to have been resized behind surfman's back.
+    ///
+    /// macOS widget surfaces only change size through `resize_surface()`, so the handler is never
+    /// called.
+    #[inline]
+    pub fn set_surface_resized_handler(&mut self, _: Option<SurfaceResizedHandler>) {}
+
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to have moved to a display with a different scale factor, replacing any previous
+    /// one, or unregisters it if `handler` is `None`.
+    ///
+    /// `present_surface()` compares the `backingScaleFactor` of the view's window with the one
+    /// that it last saw. Automatically rescaled surfaces are resized with `resize_surface()` once
+    /// the frame being presented has been shown.
+    #[inline]
+    pub fn set_surface_scale_changed_handler(
+        &mut self,
+        handler: Option<SurfaceScaleChangedHandler>,
+    ) -> Result<(), Error> {
+        self.0.set_surface_scale_changed_handler(handler)
+    }
+
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to be displayed with a different color profile, with the new profile, replacing any
+    /// previous one, or unregisters it if `handler` is `None`.
+    ///
+    /// Every 30 presents, `present_surface()` reads the color space of the view's screen again,
+    /// as `widget_color_profile()` does, and compares it with the profile that it last saw.
+    #[inline]
+    pub fn set_color_profile_changed_handler(
+        &mut self,
+        handler: Option<ColorProfileChangedHandler>,
+    ) {
+        self.0.set_color_profile_changed_handler(handler)
+    }
+
+    /// Sets whether the system may discard the contents of a generic surface to free memory.
+    ///
+    /// Volatile surfaces have their `IOSurface` made purgeable with `IOSurfaceSetPurgeable()`.
+    #[inline]
+    pub fn set_surface_purgeable(
+        &self,
+        surface: &mut Surface,
+        purgeability: Purgeability,
+    ) -> Result<(), Error> {
+        self.0
+            .set_surface_purgeable(&mut surface.system_surface, purgeability)
+    }
+
+    /// Makes a surface non-purgeable again, and reports whether the system discarded its contents
+    /// since it was made volatile.
+    #[inline]
+    pub fn reclaim_surface(&self, surface: &mut Surface) -> Result<ReclaimResult, Error> {
+        self.0.reclaim_surface(&mut surface.system_surface)
+    }
+
+    /// Returns a pointer to the underlying surface data for reading or writing by the CPU.
+    #[inline]
+    pub fn lock_surface_data<'s>(
+        &self,
+        surface: &'s mut Surface,
+    ) -> Result<SurfaceDataGuard<'s>, Error> {
+        self.0.lock_surface_data(&mut surface.system_surface)
+    }
+
+    /// `IOSurface`s aren't dma-bufs, so this returns `UnsupportedOnThisPlatform`.
+    pub fn export_surface_dma_buf(&self, _: &Surface) -> Result<DmaBuf, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Copies the contents of `source` into `destination` on the GPU, converting between their
+    /// formats and colorspaces and exchanging channels as `swizzle` says.
+    ///
+    /// Both surfaces must be generic surfaces of `context`, the same size, and `context` must be
+    /// current.
+    #[inline]
+    pub fn convert_surface(
+        &self,
+        context: &mut Context,
+        source: &Surface,
+        destination: &mut Surface,
+        swizzle: Swizzle,
+    ) -> Result<(), Error> {
+        convert::convert_surface(self, context, source, destination, swizzle)
+    }
+
+    /// Reads a rectangle of a generic surface, measured from its top left, into `out` as tightly
+    /// packed 8-bit RGBA, top row first.
+    ///
+    /// The surface must belong to `context`, which needn't be current.
+    #[inline]
+    pub fn read_surface_data(
+        &self,
+        context: &Context,
+        surface: &Surface,
+        rect: Rect<i32>,
+        out: &mut [u8],
+    ) -> Result<(), Error> {
+        readback::read_surface_data(self, context, surface, rect, out)
+    }
+
+    /// Writes 8-bit RGBA pixels from `data`, top row first, into a rectangle of a generic surface,
+    /// measured from its top left.
+    ///
+    /// The surface must belong to `context`, which needn't be current.
+    #[inline]
+    pub fn write_surface_data(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        rect: Rect<i32>,
+        data: &[u8],
+        stride: usize,
+    ) -> Result<(), Error> {
+        upload::write_surface_data(self, context, surface, rect, data, stride)
+    }
+
+    /// Returns the color profile of the screen that a widget surface is displayed on.
+    #[inline]
+    pub fn widget_color_profile(&self, surface: &Surface) -> ColorProfile {
+        self.0.widget_color_profile(&surface.system_surface)
+    }
+
+    /// Returns true if a widget surface is on a screen driven by a different GPU than this
+    /// device.
+    #[inline]
+    pub fn presenting_cross_adapter(&self, surface: &Surface) -> bool {
+        self.0.presenting_cross_adapter(&surface.system_surface)
+    }
+
+    /// Returns the timestamps of the most recent presentations of a widget surface, oldest first.
+    ///
+    /// `compositor_ack` is when the Core Animation transaction was committed, and `displayed` is
+    /// the display link's next vertical blank.
+    ///
+    /// Returns an empty list for generic surfaces and for widget surfaces that haven't been
+    /// presented yet.
+    #[inline]
+    pub fn presentation_timestamps(&self, surface: &Surface) -> Vec<PresentRecord> {
+        self.0.presentation_timestamps(&surface.system_surface)
+    }
+
+    /// Returns when the window system presented the most recent frame of a widget surface that
+    /// is known to have reached the display, or the most recent frame if none is yet.
+    ///
+    /// `actual_present` is the display link's next vertical blank after the Core Animation
+    /// transaction was committed. The GPU's finish isn't reported on this platform.
+    ///
+    /// Returns `Error::NoWidgetAttached` for generic surfaces, and `Error::NotPresented` if
+    /// nothing has been presented on the surface.
+    #[inline]
+    pub fn query_presentation_time(
+        &self,
+        surface: &Surface,
+    ) -> Result<PresentationStatistics, Error> {
+        self.0.query_presentation_time(&surface.system_surface)
+    }
+
     /// Returns the native `IOSurface` corresponding to this surface.
     ///
     /// The reference count is increased on the `IOSurface` before returning.
@@ -397,12 +1048,75 @@ impl Device {
 }
 
 impl Surface {
+    /// Returns the ID of the context that this surface was created with.
+    ///
+    /// A surface can only be bound to the context that it was created with.
+    #[inline]
+    pub fn context_id(&self) -> ContextID {
+        self.context_id
+    }
+
     #[inline]
     fn id(&self) -> SurfaceID {
         SurfaceID(self.system_surface.io_surface.as_concrete_TypeRef() as usize)
     }
 }
 
+// The sized internal format that a surface is bound to textures with.
+// Sets the swap interval of a context with `kCGLCPSwapInterval`.
+unsafe fn set_context_swap_interval(
+    cgl_context: CGLContextObj,
+    interval: i32,
+) -> Result<i32, Error> {
+    if CGLSetParameter(cgl_context, kCGLCPSwapInterval, &interval) != kCGLNoError {
+        return Err(Error::UnsupportedOnThisPlatform);
+    }
+    Ok(interval)
+}
+
+fn surface_format(system_surface: &SystemSurface) -> GLenum {
+    match (system_surface.format, system_surface.colorspace) {
+        (SurfaceFormat::Rgba8, ColorEncoding::Linear) => gl::RGBA8,
+        (SurfaceFormat::Rgba8, ColorEncoding::Srgb) => gl::SRGB8_ALPHA8,
+        (SurfaceFormat::Rgba16F, _) => gl::RGBA16F,
+        (SurfaceFormat::Rgb10A2, _) => gl::RGB10_A2,
+    }
+}
+
+// The framebuffer object that is drawn into: the multisample one of multisampled surfaces.
+fn draw_framebuffer_object(surface: &Surface) -> GLuint {
+    match surface.multisample {
+        Some(ref multisample) => multisample.framebuffer_object,
+        None => surface.framebuffer_object,
+    }
+}
+
+// Binds the `IOSurface` behind a surface to the current `GL_TEXTURE_RECTANGLE` texture.
+unsafe fn bind_io_surface(system_surface: &SystemSurface) {
+    let (internal_format, format, ty) = match (system_surface.format, system_surface.colorspace) {
+        (SurfaceFormat::Rgba8, ColorEncoding::Linear) => {
+            (gl::RGBA, gl::BGRA, gl::UNSIGNED_INT_8_8_8_8_REV)
+        }
+        (SurfaceFormat::Rgba8, ColorEncoding::Srgb) => {
+            (gl::SRGB8_ALPHA8, gl::BGRA, gl::UNSIGNED_INT_8_8_8_8_REV)
+        }
+        (SurfaceFormat::Rgba16F, _) => (gl::RGBA16F, gl::RGBA, gl::HALF_FLOAT),
+        (SurfaceFormat::Rgb10A2, _) => (gl::RGB10_A2, gl::BGRA, gl::UNSIGNED_INT_2_10_10_10_REV),
+    };
+    let err = CGLTexImageIOSurface2D(
+        CGLGetCurrentContext(),
+        gl::TEXTURE_RECTANGLE,
+        internal_format,
+        system_surface.size.width,
+        system_surface.size.height,
+        format,
+        ty,
+        system_surface.io_surface.as_concrete_TypeRef(),
+        0,
+    );
+    debug_assert_eq!(err, 0);
+}
+
 #[must_use]
 struct FramebufferGuard {
     draw: GLuint,
@@ -7,7 +7,10 @@
 
 use super::device::{Adapter, Device, NativeDevice};
 use super::surface::{NSView, NativeWidget};
+use crate::connection;
+use crate::identity;
 use crate::Error;
+use crate::{AdapterKey, AdapterKind};
 
 use cocoa::base::id;
 use core_foundation::base::TCFType;
@@ -18,10 +21,13 @@ use core_foundation::dictionary::{CFMutableDictionary, CFMutableDictionaryRef};
 use core_foundation::string::CFString;
 
 use euclid::default::Size2D;
+use metal::Device as MetalDevice;
 
 use std::os::raw::c_void;
 use std::str::FromStr;
 
+const ADAPTER_KEY_BACKEND: &str = "macos";
+
 /// A no-op connection.
 ///
 /// Connections to the CGS window server are implicit on macOS, so this is a zero-sized type.
@@ -34,6 +40,8 @@ pub struct Connection;
 #[derive(Clone)]
 pub struct NativeConnection;
 
+assert_send_sync!(NativeConnection);
+
 impl Connection {
     /// Connects to the default display.
     #[inline]
@@ -101,6 +109,70 @@ impl Connection {
         self.create_low_power_adapter()
     }
 
+    /// Returns the adapter for the Metal device whose name contains `needle`, ignoring case.
+    pub fn create_adapter_by_name(&self, needle: &str) -> Result<Adapter, Error> {
+        MetalDevice::all()
+            .into_iter()
+            .find(|device| connection::adapter_name_matches(device.name(), needle))
+            .map(|device| Adapter {
+                is_low_power: device.is_low_power(),
+            })
+            .ok_or(Error::NoAdapterFound)
+    }
+
+    /// Returns a key that describes the adapter, which can be saved and passed to
+    /// `find_adapter()` in a later run to find the same adapter again.
+    ///
+    /// The key records the name and IORegistry ID of the Metal device that the adapter renders
+    /// with, if there is one.
+    pub fn adapter_key(&self, adapter: &Adapter) -> Result<AdapterKey, Error> {
+        let kind = if adapter.is_low_power {
+            AdapterKind::LowPower
+        } else {
+            AdapterKind::HighPerformance
+        };
+        let metal_device = MetalDevice::all()
+            .into_iter()
+            .find(|device| device.is_low_power() == adapter.is_low_power);
+        Ok(AdapterKey {
+            backend: ADAPTER_KEY_BACKEND.to_owned(),
+            kind,
+            pci_id: None,
+            adapter_luid: None,
+            registry_id: metal_device.as_ref().map(|device| device.registry_id()),
+            driver: metal_device.as_ref().map(|device| device.name().to_owned()),
+        })
+    }
+
+    /// Returns the adapter that a key from `adapter_key()` describes.
+    ///
+    /// Returns `Error::IncompatibleAdapter` if the key was taken with another backend, and
+    /// `Error::NoAdapterFound` if it doesn't describe an adapter on this system.
+    pub fn find_adapter(&self, key: &AdapterKey) -> Result<Adapter, Error> {
+        let mut candidates = vec![];
+        for &is_low_power in &[false, true] {
+            let adapter = Adapter { is_low_power };
+            let candidate = self.adapter_key(&adapter)?;
+            candidates.push((adapter, candidate));
+        }
+        identity::find_adapter(ADAPTER_KEY_BACKEND, key, candidates)
+    }
+
+    /// Returns true if the two adapters are the same adapter.
+    #[inline]
+    pub fn is_same_adapter(&self, a: &Adapter, b: &Adapter) -> bool {
+        a.is_low_power == b.is_low_power
+    }
+
+    /// Returns the adapter for the GPU that drives the screen the window is on.
+    ///
+    /// macOS switches GPUs for the whole system rather than per screen, so this always returns
+    /// `Error::UnsupportedOnThisPlatform`.
+    #[inline]
+    pub fn adapter_for_widget(&self, _: &NativeWidget) -> Result<Adapter, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
     /// Opens the hardware device corresponding to the given adapter.
     ///
     /// Device handles are local to a single thread.
@@ -131,15 +203,15 @@ impl Connection {
     }
 
     /// Create a native widget from a raw pointer
+    ///
+    /// The real size of the view is queried from its window. If it differs from `size`,
+    /// `native_widget_adjusted_size()` reports it.
     pub unsafe fn create_native_widget_from_ptr(
         &self,
         raw: *mut c_void,
-        _size: Size2D<i32>,
+        size: Size2D<i32>,
     ) -> NativeWidget {
-        NativeWidget {
-            view: NSView(raw as id),
-            opaque: true,
-        }
+        native_widget(NSView(raw as id), true, size)
     }
 
     /// Create a native widget type from the given `RawWindowHandle`.
@@ -148,15 +220,16 @@ impl Connection {
     pub fn create_native_widget_from_raw_window_handle(
         &self,
         raw_handle: rwh_05::RawWindowHandle,
-        _size: Size2D<i32>,
+        size: Size2D<i32>,
     ) -> Result<NativeWidget, Error> {
         use rwh_05::RawWindowHandle::AppKit;
 
         match raw_handle {
-            AppKit(handle) => Ok(NativeWidget {
-                view: NSView(unsafe { msg_send![handle.ns_view as id, retain] }),
-                opaque: unsafe { msg_send![handle.ns_window as id, isOpaque] },
-            }),
+            AppKit(handle) => Ok(native_widget(
+                NSView(unsafe { msg_send![handle.ns_view as id, retain] }),
+                unsafe { msg_send![handle.ns_window as id, isOpaque] },
+                size,
+            )),
             _ => Err(Error::IncompatibleNativeWidget),
         }
     }
@@ -167,7 +240,7 @@ impl Connection {
     pub fn create_native_widget_from_window_handle(
         &self,
         handle: rwh_06::WindowHandle,
-        _size: Size2D<i32>,
+        size: Size2D<i32>,
     ) -> Result<NativeWidget, Error> {
         use rwh_06::RawWindowHandle::AppKit;
 
@@ -176,17 +249,36 @@ impl Connection {
                 let ns_view = handle.ns_view.as_ptr() as id;
                 // https://developer.apple.com/documentation/appkit/nsview/1483301-window
                 let ns_window: id = unsafe { msg_send![ns_view, window] };
-                Ok(NativeWidget {
+                Ok(native_widget(
                     // Increment the nsview's reference count with retain. See:
                     // https://developer.apple.com/documentation/objectivec/1418956-nsobject/1571946-retain
-                    view: NSView(unsafe { msg_send![ns_view, retain] }),
+                    NSView(unsafe { msg_send![ns_view, retain] }),
                     // https://developer.apple.com/documentation/appkit/nswindow/1419086-isopaque
-                    opaque: unsafe { msg_send![ns_window, isOpaque] },
-                })
+                    unsafe { msg_send![ns_window, isOpaque] },
+                    size,
+                ))
             }
             _ => Err(Error::IncompatibleNativeWidget),
         }
     }
+
+    /// Returns the real size of a native widget if it differs from the size that the widget was
+    /// created with.
+    #[inline]
+    pub fn native_widget_adjusted_size(&self, native_widget: &NativeWidget) -> Option<Size2D<i32>> {
+        native_widget.adjusted_size
+    }
+}
+
+fn native_widget(view: NSView, opaque: bool, size: Size2D<i32>) -> NativeWidget {
+    let mut native_widget = NativeWidget {
+        view,
+        opaque,
+        adjusted_size: None,
+    };
+    native_widget.adjusted_size = unsafe { native_widget.backing_size() }
+        .and_then(|actual_size| crate::surface::adjusted_widget_size(size, actual_size));
+    native_widget
 }
 
 impl NativeConnection {
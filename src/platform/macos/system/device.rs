@@ -3,10 +3,17 @@
 //! A handle to the device. (This is a no-op, because handles are implicit in `IOSurface`.)
 
 use super::connection::Connection;
-use crate::Error;
+use crate::context::NegotiatedVersions;
+use crate::lost::LostContexts;
+use crate::DebugFill;
+use crate::LowMemoryOptions;
+use crate::{CancelToken, DeviceIdentity, Error};
+use crate::{ColorProfile, SurfaceID, SurfaceScaleChanged};
+use crate::{SchedulingClass, SchedulingEnforcement};
 
 use metal::Device as MetalDevice;
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 /// Represents a hardware display adapter that can be used for rendering (including the CPU).
 ///
@@ -22,6 +29,16 @@ pub struct Adapter {
 #[derive(Clone)]
 pub struct Device {
     adapter: Adapter,
+    pub(crate) cancel_token: CancelToken,
+    scheduling_class: SchedulingClass,
+    low_memory_options: Option<LowMemoryOptions>,
+    debug_fill: DebugFill,
+    pub(crate) lost_contexts: LostContexts,
+    pub(crate) negotiated_versions: NegotiatedVersions,
+    pub(crate) surface_scale_changed_handler:
+        Option<Arc<dyn Fn(SurfaceID, SurfaceScaleChanged) + Send + Sync>>,
+    pub(crate) color_profile_changed_handler:
+        Option<Arc<dyn Fn(SurfaceID, &ColorProfile) + Send + Sync>>,
     phantom: PhantomData<*mut ()>,
 }
 
@@ -34,6 +51,14 @@ impl Device {
     pub(crate) fn new(adapter: Adapter) -> Result<Device, Error> {
         Ok(Device {
             adapter,
+            cancel_token: CancelToken::new(),
+            scheduling_class: SchedulingClass::Interactive,
+            low_memory_options: None,
+            debug_fill: DebugFill::None,
+            lost_contexts: LostContexts::default(),
+            negotiated_versions: NegotiatedVersions::default(),
+            surface_scale_changed_handler: None,
+            color_profile_changed_handler: None,
             phantom: PhantomData,
         })
     }
@@ -59,4 +84,74 @@ impl Device {
     pub fn adapter(&self) -> Adapter {
         self.adapter.clone()
     }
+
+    /// Returns the identity of the physical GPU that this device renders with: the registry ID
+    /// of its Metal device.
+    pub fn identity(&self) -> DeviceIdentity {
+        DeviceIdentity {
+            registry_id: Some(self.native_device().0.registry_id()),
+            ..DeviceIdentity::default()
+        }
+    }
+
+    /// Sets what kind of work this device's contexts do, and returns how that is enforced.
+    ///
+    /// macOS can't prioritize contexts, so `Background` devices have their swap chains paced in
+    /// software. See `SchedulingEnforcement`.
+    #[inline]
+    pub fn set_scheduling_class(&mut self, class: SchedulingClass) -> SchedulingEnforcement {
+        self.scheduling_class = class;
+        self.scheduling_enforcement()
+    }
+
+    /// Returns the scheduling class that was last set with `set_scheduling_class()`.
+    #[inline]
+    pub fn scheduling_class(&self) -> SchedulingClass {
+        self.scheduling_class
+    }
+
+    /// Returns how this device enforces its scheduling class.
+    #[inline]
+    pub fn scheduling_enforcement(&self) -> SchedulingEnforcement {
+        self.scheduling_class.enforcement(false, false)
+    }
+
+    /// Makes this device save memory at the expense of rendering quality until
+    /// `exit_low_memory_mode()` is called, and returns how many pooled surfaces were destroyed.
+    ///
+    /// The system backend doesn't pool surfaces, so this always returns zero. Entering low-memory mode again
+    /// replaces the options. See `LowMemoryOptions`.
+    #[inline]
+    pub fn enter_low_memory_mode(&mut self, options: LowMemoryOptions) -> usize {
+        self.low_memory_options = Some(options);
+        0
+    }
+
+    /// Leaves low-memory mode, so that surfaces created from now on get their usual formats.
+    ///
+    /// Surfaces created in low-memory mode keep their formats until they are destroyed.
+    #[inline]
+    pub fn exit_low_memory_mode(&mut self) {
+        self.low_memory_options = None;
+    }
+
+    /// Returns the options that this device entered low-memory mode with, or `None` if it isn't
+    /// in low-memory mode.
+    #[inline]
+    pub fn low_memory_options(&self) -> Option<LowMemoryOptions> {
+        self.low_memory_options
+    }
+
+    /// Makes this device fill each surface that it creates from now on, and each surface that a
+    /// swap chain recycles, with a recognizable pattern. See `DebugFill`.
+    #[inline]
+    pub fn set_debug_surface_fill(&mut self, fill: DebugFill) {
+        self.debug_fill = fill;
+    }
+
+    /// Returns the debug fill that was last set with `set_debug_surface_fill()`.
+    #[inline]
+    pub fn debug_surface_fill(&self) -> DebugFill {
+        self.debug_fill
+    }
 }
@@ -4,11 +4,14 @@
 
 #![allow(non_upper_case_globals)]
 
+use core_foundation::base::Boolean;
 use io_surface::IOSurfaceRef;
 use mach2::kern_return::kern_return_t;
 use std::os::raw::c_void;
 
 pub(crate) const kCVPixelFormatType_32BGRA: i32 = 0x42475241; // 'BGRA'
+pub(crate) const kCVPixelFormatType_64RGBAHalf: i32 = 0x52476841; // 'RGhA'
+pub(crate) const kCVPixelFormatType_ARGB2101010LEPacked: i32 = 0x6c313072; // 'l10r'
 
 pub(crate) const kCVReturnSuccess: i32 = 0;
 
@@ -18,6 +21,10 @@ pub(crate) const kIOMapCacheShift: i32 = 8;
 pub(crate) const kIOMapDefaultCache: i32 = kIODefaultCache << kIOMapCacheShift;
 pub(crate) const kIOMapWriteCombineCache: i32 = kIOWriteCombineCache << kIOMapCacheShift;
 
+pub(crate) const kIOSurfacePurgeableNonVolatile: u32 = 0;
+pub(crate) const kIOSurfacePurgeableVolatile: u32 = 1;
+pub(crate) const kIOSurfacePurgeableEmpty: u32 = 2;
+
 pub(crate) type IOSurfaceLockOptions = u32;
 
 #[link(name = "IOSurface", kind = "framework")]
@@ -25,6 +32,8 @@ extern "C" {
     pub(crate) fn IOSurfaceGetAllocSize(buffer: IOSurfaceRef) -> usize;
     pub(crate) fn IOSurfaceGetBaseAddress(buffer: IOSurfaceRef) -> *mut c_void;
     pub(crate) fn IOSurfaceGetBytesPerRow(buffer: IOSurfaceRef) -> usize;
+    pub(crate) fn IOSurfaceGetPixelFormat(buffer: IOSurfaceRef) -> u32;
+    pub(crate) fn IOSurfaceIsInUse(buffer: IOSurfaceRef) -> Boolean;
     pub(crate) fn IOSurfaceLock(
         buffer: IOSurfaceRef,
         options: IOSurfaceLockOptions,
@@ -35,4 +44,9 @@ extern "C" {
         options: IOSurfaceLockOptions,
         seed: *mut u32,
     ) -> kern_return_t;
+    pub(crate) fn IOSurfaceSetPurgeable(
+        buffer: IOSurfaceRef,
+        new_state: u32,
+        old_state: *mut u32,
+    ) -> kern_return_t;
 }
@@ -3,19 +3,36 @@
 //! Surface management for macOS.
 
 use super::device::Device;
+use super::ffi::IOSurfaceGetPixelFormat;
+use super::ffi::IOSurfaceIsInUse;
 use super::ffi::{kCVPixelFormatType_32BGRA, kIOMapDefaultCache, IOSurfaceLock, IOSurfaceUnlock};
+use super::ffi::{kCVPixelFormatType_64RGBAHalf, kCVPixelFormatType_ARGB2101010LEPacked};
 use super::ffi::{kCVReturnSuccess, kIOMapWriteCombineCache};
+use super::ffi::{kIOSurfacePurgeableEmpty, kIOSurfacePurgeableNonVolatile};
+use super::ffi::{kIOSurfacePurgeableVolatile, IOSurfaceSetPurgeable};
 use super::ffi::{IOSurfaceGetAllocSize, IOSurfaceGetBaseAddress, IOSurfaceGetBytesPerRow};
-use crate::{Error, SurfaceAccess, SurfaceID, SurfaceType, SystemSurfaceInfo};
+use crate::color::ColorProfileTracker;
+use crate::memory::{PurgeState, PurgeableBacking};
+use crate::presentation_time::{self, PresentationStatistics};
+use crate::scale::ScaleTracker;
+use crate::surface::PresentHistory;
+use crate::BufferRelease;
+use crate::ColorProfileChangedHandler;
+use crate::SurfaceScaleChangedHandler;
+use crate::SystemSurfaceInfo;
+use crate::{ColorEncoding, PresentMode, SurfaceFormat, SurfaceOptions};
+use crate::{ColorProfile, Error, PresentRecord, SurfaceAccess, SurfaceID, SurfaceType};
+use crate::{Purgeability, ReclaimResult};
 
 use cocoa::appkit::{NSScreen, NSView as NSViewMethods, NSWindow};
-use cocoa::base::{id, YES};
+use cocoa::base::{id, nil, YES};
 use cocoa::foundation::{NSPoint, NSRect, NSSize};
 use cocoa::quartzcore::{transaction, CALayer, CATransform3D};
 use core_foundation::base::TCFType;
 use core_foundation::dictionary::CFDictionary;
 use core_foundation::number::CFNumber;
 use core_foundation::string::CFString;
+use core_graphics::base::CGFloat;
 use core_graphics::geometry::{CGRect, CGSize, CG_ZERO_POINT};
 use euclid::default::Size2D;
 use io_surface::{self, kIOSurfaceBytesPerElement, kIOSurfaceBytesPerRow, IOSurface, IOSurfaceRef};
@@ -28,8 +45,11 @@ use std::os::raw::c_void;
 use std::slice;
 use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
-const BYTES_PER_PIXEL: i32 = 4;
+// How often `wait_previous_buffer_released()` checks whether the window server still uses a
+// buffer.
+const RELEASE_POLL_INTERVAL: Duration = Duration::from_millis(1);
 
 /// Represents a hardware buffer of pixels that can be rendered to via the CPU or GPU and either
 /// displayed in a native widget or bound to a texture for reading.
@@ -45,9 +65,17 @@ const BYTES_PER_PIXEL: i32 = 4;
 pub struct Surface {
     pub(crate) io_surface: IOSurface,
     pub(crate) size: Size2D<i32>,
-    access: SurfaceAccess,
+    pub(crate) access: SurfaceAccess,
+    pub(crate) format: SurfaceFormat,
+    pub(crate) colorspace: ColorEncoding,
+    pub(crate) present_history: PresentHistory,
+    pub(crate) purge_state: PurgeState,
     pub(crate) destroyed: bool,
     pub(crate) view_info: Option<ViewInfo>,
+    // Follows the scale of the display that a widget surface's view is on.
+    pub(crate) scale: Option<ScaleTracker>,
+    // The color profile of the screen that a widget surface's view was last seen on.
+    pub(crate) color_profile: ColorProfileTracker,
 }
 
 /// A wrapper around an `IOSurface`.
@@ -58,7 +86,7 @@ unsafe impl Send for Surface {}
 
 impl Debug for Surface {
     fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
-        write!(formatter, "Surface({:x})", self.id().0)
+        write!(formatter, "Surface({:x}, {:?})", self.id().0, self.access)
     }
 }
 
@@ -91,14 +119,24 @@ struct VblankCond {
 pub struct NSView(pub(crate) id);
 
 /// A native widget on macOS (`NSView`).
+///
+/// This is neither `Send` nor `Sync`, since AppKit views can only be used on the main thread.
 #[derive(Clone)]
 pub struct NativeWidget {
     /// The `NSView` object.
     pub view: NSView,
     /// A bool value that indicates whether widget's NSWindow is opaque.
     pub opaque: bool,
+    /// The real size of the view in device pixels, if it differed from the size that the widget
+    /// was created with.
+    ///
+    /// The `Connection` constructors fill this in. Surfaces always use the real size.
+    pub adjusted_size: Option<Size2D<i32>>,
 }
 
+assert_not_send!(NativeWidget);
+assert_not_sync!(NativeWidget);
+
 /// Represents the CPU view of the pixel data of this surface.
 pub struct SurfaceDataGuard<'a> {
     surface: &'a mut Surface,
@@ -107,6 +145,34 @@ pub struct SurfaceDataGuard<'a> {
     len: usize,
 }
 
+// The `IOSurface` behind a generic surface, which is offered to the system by making it
+// purgeable.
+struct IOSurfaceBacking<'a>(&'a IOSurface);
+
+impl<'a> IOSurfaceBacking<'a> {
+    fn set_purgeable(&mut self, new_state: u32) -> Result<u32, Error> {
+        unsafe {
+            let mut old_state = 0;
+            let result = IOSurfaceSetPurgeable(self.0.obj, new_state, &mut old_state);
+            if result != KERN_SUCCESS {
+                return Err(Error::Failed);
+            }
+            Ok(old_state)
+        }
+    }
+}
+
+impl<'a> PurgeableBacking for IOSurfaceBacking<'a> {
+    fn offer(&mut self) -> Result<(), Error> {
+        self.set_purgeable(kIOSurfacePurgeableVolatile).map(|_| ())
+    }
+
+    fn reclaim(&mut self) -> Result<bool, Error> {
+        let old_state = self.set_purgeable(kIOSurfacePurgeableNonVolatile)?;
+        Ok(old_state == kIOSurfacePurgeableEmpty)
+    }
+}
+
 impl Device {
     /// Creates either a generic or a widget surface, depending on the supplied surface type.
     pub fn create_surface(
@@ -114,17 +180,50 @@ impl Device {
         access: SurfaceAccess,
         surface_type: SurfaceType<NativeWidget>,
     ) -> Result<Surface, Error> {
+        self.create_surface_with_options(access, surface_type, SurfaceOptions::default())
+    }
+
+    /// Creates either a generic or a widget surface, with options such as its colorspace.
+    ///
+    /// `SurfaceFormat::Rgba8` surfaces hold 8-bit BGRA pixels, `Rgba16F` surfaces half-float RGBA
+    /// pixels, and `Rgb10A2` surfaces packed 10-bit pixels. The colorspace says how OpenGL should
+    /// interpret 8-bit pixels when it binds the surface to a texture; the other formats can't be
+    /// sRGB-encoded, and return `Error::UnsupportedSurfaceFormat`. `SurfaceOptions::opaque`
+    /// overrides the opacity of the native widget's window for the widget's layer.
+    ///
+    /// Core Animation only ever shows an `IOSurface` once it has been handed over, so widget
+    /// surfaces can't be single-buffered, and return `Error::UnsupportedPresentMode` for
+    /// `PresentMode::SingleBuffer`. Presenting swaps the widget's `IOSurface` with the one on
+    /// screen rather than copying it, so widget surfaces can't keep their contents either, and
+    /// return `Error::UnsupportedSwapBehavior` for `preserve_buffer`.
+    pub fn create_surface_with_options(
+        &mut self,
+        access: SurfaceAccess,
+        surface_type: SurfaceType<NativeWidget>,
+        options: SurfaceOptions,
+    ) -> Result<Surface, Error> {
+        if options.colorspace == ColorEncoding::Srgb && options.format != SurfaceFormat::Rgba8 {
+            return Err(Error::UnsupportedSurfaceFormat);
+        }
+        if let SurfaceType::Widget { .. } = surface_type {
+            if options.present_mode == PresentMode::SingleBuffer {
+                return Err(Error::UnsupportedPresentMode);
+            }
+            if options.preserve_buffer {
+                return Err(Error::UnsupportedSwapBehavior);
+            }
+        }
+
         unsafe {
             let size = match surface_type {
                 SurfaceType::Generic { size } => size,
                 SurfaceType::Widget { ref native_widget } => {
-                    let window: id = msg_send![native_widget.view.0, window];
-                    let bounds = window.convertRectToBacking(native_widget.view.0.bounds());
+                    let size = native_widget.backing_size().unwrap_or_default();
 
                     // The surface will not appear if its width is not a multiple of 4 (i.e. stride
                     // is a multiple of 16 bytes). Enforce this.
-                    let mut width = bounds.size.width as i32;
-                    let height = bounds.size.height as i32;
+                    let mut width = size.width;
+                    let height = size.height;
                     if width % 4 != 0 {
                         width += 4 - width % 4;
                     }
@@ -133,21 +232,38 @@ impl Device {
                 }
             };
 
-            let io_surface = self.create_io_surface(&size, access);
+            let io_surface = self.create_io_surface(&size, access, options.format);
 
             let view_info = match surface_type {
                 SurfaceType::Generic { .. } => None,
                 SurfaceType::Widget {
                     ref native_widget, ..
-                } => Some(self.create_view_info(&size, access, native_widget)),
+                } => Some(self.create_view_info(
+                    &size,
+                    access,
+                    options.format,
+                    options.opaque.unwrap_or(native_widget.opaque),
+                    native_widget,
+                )),
+            };
+            let scale = match view_info {
+                Some(ref view_info) => backing_scale(&view_info.view)
+                    .map(|scale| ScaleTracker::new(scale, options.auto_rescale)),
+                None => None,
             };
 
             Ok(Surface {
                 io_surface,
                 size,
                 access,
+                format: options.format,
+                colorspace: options.colorspace,
+                present_history: PresentHistory::default(),
+                purge_state: PurgeState::default(),
                 destroyed: false,
                 view_info,
+                scale,
+                color_profile: ColorProfileTracker::default(),
             })
         }
     }
@@ -172,9 +288,11 @@ impl Device {
         &mut self,
         size: &Size2D<i32>,
         surface_access: SurfaceAccess,
+        format: SurfaceFormat,
+        opaque: bool,
         native_widget: &NativeWidget,
     ) -> ViewInfo {
-        let front_surface = self.create_io_surface(size, surface_access);
+        let front_surface = self.create_io_surface(size, surface_access, format);
 
         let window: id = msg_send![native_widget.view.0, window];
         let device_description: CFDictionary<CFString, CFNumber> =
@@ -207,7 +325,6 @@ impl Device {
         }];
         let logical_size = logical_rect.size;
 
-        let opaque = native_widget.opaque;
         let layer = CALayer::new();
         let layer_size = CGSize::new(logical_size.width as f64, logical_size.height as f64);
         layer.set_frame(&CGRect::new(&CG_ZERO_POINT, &layer_size));
@@ -244,16 +361,162 @@ impl Device {
     ///
     /// Widget surfaces are internally double-buffered, so changes to them don't show up in their
     /// associated widgets until this method is called.
+    ///
+    /// A view that has been resized since the surface was last sized fails with
+    /// `SurfaceSizeMismatch` without presenting.
     pub fn present_surface(&self, surface: &mut Surface) -> Result<(), Error> {
-        surface.present()
+        match self.present_surface_for_rescale(surface)? {
+            Some(size) => self.resize_surface(surface, size),
+            None => Ok(()),
+        }
     }
 
-    /// Resizes a widget surface
+    // Presents a widget surface, and returns the size to resize it to afterward if it's
+    // automatically rescaled and has moved to a display with a different scale factor. The frame
+    // was drawn before the change was noticed, so it's shown as it is.
+    pub(crate) fn present_surface_for_rescale(
+        &self,
+        surface: &mut Surface,
+    ) -> Result<Option<Size2D<i32>>, Error> {
+        let rescale_to = self.update_scale(surface);
+        self.update_color_profile(surface);
+        if rescale_to.is_none() {
+            self.check_native_size(surface)?;
+        }
+        surface.present()?;
+        Ok(rescale_to)
+    }
+
+    // Reports a change in the scale of the display that a widget surface's view is on, and
+    // returns the size to resize an automatically rescaled surface to once this frame is shown.
+    fn update_scale(&self, surface: &mut Surface) -> Option<Size2D<i32>> {
+        let scale = unsafe { backing_scale(&surface.view_info.as_ref()?.view)? };
+        let native_size = self
+            .query_surface_native_size(surface)
+            .ok()
+            .map(padded_width);
+        let tracker = surface.scale.as_mut()?;
+        let (change, rescale_to) = tracker.update(scale, surface.size, native_size);
+        if let (Some(change), Some(handler)) = (change, &self.surface_scale_changed_handler) {
+            handler(surface.id(), change);
+        }
+        rescale_to
+    }
+
+    // Reports a change in the color profile of the screen that a widget surface's view is on, if
+    // one is noticed on this present.
+    fn update_color_profile(&self, surface: &mut Surface) {
+        let handler = match self.color_profile_changed_handler {
+            Some(ref handler) if surface.view_info.is_some() => handler,
+            _ => return,
+        };
+        let mut tracker = mem::take(&mut surface.color_profile);
+        let changed = tracker.update(|| self.widget_color_profile(surface));
+        surface.color_profile = tracker;
+        if let Some(profile) = changed {
+            handler(surface.id(), &profile);
+        }
+    }
+
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to have moved to a display with a different scale factor, replacing any previous
+    /// one, or unregisters it if `handler` is `None`.
+    ///
+    /// `present_surface()` compares the `backingScaleFactor` of the view's window with the one
+    /// that it last saw.
+    #[inline]
+    pub fn set_surface_scale_changed_handler(
+        &mut self,
+        handler: Option<SurfaceScaleChangedHandler>,
+    ) -> Result<(), Error> {
+        self.surface_scale_changed_handler = handler.map(Arc::from);
+        Ok(())
+    }
+
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to be displayed with a different color profile, with the new profile, replacing any
+    /// previous one, or unregisters it if `handler` is `None`.
+    ///
+    /// Every 30 presents, `present_surface()` reads the color space of the view's screen again,
+    /// as `widget_color_profile()` does, and compares it with the profile that it last saw.
+    #[inline]
+    pub fn set_color_profile_changed_handler(
+        &mut self,
+        handler: Option<ColorProfileChangedHandler>,
+    ) {
+        self.color_profile_changed_handler = handler.map(Arc::from);
+    }
+
+    /// Returns the current size of a widget surface's view, in device pixels.
+    pub fn query_surface_native_size(&self, surface: &Surface) -> Result<Size2D<i32>, Error> {
+        match surface.view_info {
+            Some(ref view_info) => {
+                unsafe { backing_size(&view_info.view) }.ok_or(Error::InvalidNativeWidget)
+            }
+            None => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    // Fails with `SurfaceSizeMismatch` if a widget surface's view has been resized since the
+    // surface was last sized, allowing for the padding of its width to a multiple of 4. Other
+    // failures are left for the present itself to report.
+    fn check_native_size(&self, surface: &Surface) -> Result<(), Error> {
+        match self.query_surface_native_size(surface) {
+            Ok(actual) if padded_width(actual) != surface.size => Err(Error::SurfaceSizeMismatch {
+                expected: surface.size,
+                actual,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Waits until the window server has released the `IOSurface` that the next frame will be
+    /// drawn into, which was shown before the last frame presented, or until `timeout` elapses.
+    ///
+    /// Core Animation doesn't notify anyone of the release, so this polls `IOSurfaceIsInUse()`.
+    pub fn wait_previous_buffer_released(
+        &self,
+        surface: &Surface,
+        timeout: Duration,
+    ) -> Result<BufferRelease, Error> {
+        if surface.view_info.is_none() {
+            return Err(Error::NoWidgetAttached);
+        }
+
+        let io_surface = surface.io_surface.as_concrete_TypeRef();
+        unsafe {
+            if IOSurfaceIsInUse(io_surface) == 0 {
+                return Ok(BufferRelease::Released);
+            }
+            let deadline = Instant::now() + timeout;
+            while IOSurfaceIsInUse(io_surface) != 0 {
+                if Instant::now() >= deadline {
+                    return Ok(BufferRelease::TimedOut);
+                }
+                thread::sleep(RELEASE_POLL_INTERVAL);
+            }
+        }
+        Ok(BufferRelease::Waited)
+    }
+
+    /// Resizes a surface.
+    ///
+    /// Generic surfaces are given a new `IOSurface` of the new size, with undefined contents.
     pub fn resize_surface(
         &self,
         surface: &mut Surface,
         mut size: Size2D<i32>,
     ) -> Result<(), Error> {
+        let view_info = match surface.view_info {
+            None => {
+                surface.purge_state.check_usable()?;
+                surface.io_surface = self.create_io_surface(&size, surface.access, surface.format);
+                surface.size = size;
+                return Ok(());
+            }
+            Some(ref mut view_info) => view_info,
+        };
+
         // The surface will not appear if its width is not a multiple of 4 (i.e. stride is a
         // multiple of 16 bytes). Enforce this.
         let width = size.width as i32;
@@ -261,11 +524,6 @@ impl Device {
             size.width = width + 4 - width % 4;
         }
 
-        let view_info = match surface.view_info {
-            None => return Err(Error::NoWidgetAttached),
-            Some(ref mut view_info) => view_info,
-        };
-
         transaction::begin();
         transaction::set_disable_actions(true);
 
@@ -286,7 +544,7 @@ impl Device {
                 .superlayer
                 .set_sublayer_transform(sublayer_transform);
 
-            view_info.front_surface = self.create_io_surface(&size, surface.access);
+            view_info.front_surface = self.create_io_surface(&size, surface.access, surface.format);
             view_info
                 .layer
                 .set_frame(&CGRect::new(&CG_ZERO_POINT, &layer_size));
@@ -295,11 +553,14 @@ impl Device {
                 .set_contents(view_info.front_surface.obj as id);
             view_info.layer.set_opaque(view_info.opaque);
             view_info.layer.set_contents_opaque(view_info.opaque);
-            surface.io_surface = self.create_io_surface(&size, surface.access);
+            surface.io_surface = self.create_io_surface(&size, surface.access, surface.format);
             surface.size = size;
         }
 
         transaction::commit();
+        if let Some(ref mut scale) = surface.scale {
+            scale.resized();
+        }
         Ok(())
     }
 
@@ -309,14 +570,50 @@ impl Device {
         &self,
         surface: &'s mut Surface,
     ) -> Result<SurfaceDataGuard<'s>, Error> {
+        surface.purge_state.check_usable()?;
         surface.lock_data()
     }
 
-    fn create_io_surface(&self, size: &Size2D<i32>, access: SurfaceAccess) -> IOSurface {
+    /// Sets whether the system may discard the contents of a generic surface to free memory.
+    ///
+    /// Volatile surfaces have their `IOSurface` made purgeable with `IOSurfaceSetPurgeable()`.
+    pub fn set_surface_purgeable(
+        &self,
+        surface: &mut Surface,
+        purgeability: Purgeability,
+    ) -> Result<(), Error> {
+        if surface.view_info.is_some() {
+            return Err(Error::WidgetAttached);
+        }
+        let mut backing = IOSurfaceBacking(&surface.io_surface);
+        surface.purge_state.set(&mut backing, purgeability)
+    }
+
+    /// Makes a surface non-purgeable again, and reports whether the system discarded its contents
+    /// since it was made volatile.
+    pub fn reclaim_surface(&self, surface: &mut Surface) -> Result<ReclaimResult, Error> {
+        if surface.view_info.is_some() {
+            return Err(Error::WidgetAttached);
+        }
+        let mut backing = IOSurfaceBacking(&surface.io_surface);
+        surface.purge_state.reclaim(&mut backing)
+    }
+
+    fn create_io_surface(
+        &self,
+        size: &Size2D<i32>,
+        access: SurfaceAccess,
+        format: SurfaceFormat,
+    ) -> IOSurface {
         let cache_mode = match access {
             SurfaceAccess::GPUCPUWriteCombined => kIOMapWriteCombineCache,
             SurfaceAccess::GPUOnly | SurfaceAccess::GPUCPU => kIOMapDefaultCache,
         };
+        let (pixel_format, bytes_per_pixel) = match format {
+            SurfaceFormat::Rgba8 => (kCVPixelFormatType_32BGRA, 4),
+            SurfaceFormat::Rgba16F => (kCVPixelFormatType_64RGBAHalf, 8),
+            SurfaceFormat::Rgb10A2 => (kCVPixelFormatType_ARGB2101010LEPacked, 4),
+        };
 
         unsafe {
             let properties = CFDictionary::from_CFType_pairs(&[
@@ -330,15 +627,15 @@ impl Device {
                 ),
                 (
                     CFString::wrap_under_get_rule(kIOSurfaceBytesPerElement),
-                    CFNumber::from(BYTES_PER_PIXEL).as_CFType(),
+                    CFNumber::from(bytes_per_pixel).as_CFType(),
                 ),
                 (
                     CFString::wrap_under_get_rule(kIOSurfaceBytesPerRow),
-                    CFNumber::from(size.width * BYTES_PER_PIXEL).as_CFType(),
+                    CFNumber::from(size.width * bytes_per_pixel).as_CFType(),
                 ),
                 (
                     CFString::wrap_under_get_rule(kIOSurfacePixelFormat),
-                    CFNumber::from(kCVPixelFormatType_32BGRA).as_CFType(),
+                    CFNumber::from(pixel_format).as_CFType(),
                 ),
                 (
                     CFString::wrap_under_get_rule(kIOSurfaceCacheMode),
@@ -356,9 +653,88 @@ impl Device {
         SystemSurfaceInfo {
             size: surface.size,
             id: surface.id(),
+            stride: unsafe { IOSurfaceGetBytesPerRow(surface.io_surface.as_concrete_TypeRef()) },
+            pixel_format: unsafe {
+                IOSurfaceGetPixelFormat(surface.io_surface.as_concrete_TypeRef())
+            },
+        }
+    }
+
+    /// Returns the color profile of the screen that a widget surface is displayed on.
+    ///
+    /// This is the ICC profile of the `NSScreen`'s color space, as configured in ColorSync.
+    pub fn widget_color_profile(&self, surface: &Surface) -> ColorProfile {
+        let view_info = match surface.view_info {
+            Some(ref view_info) => view_info,
+            None => return ColorProfile::AssumedSrgb,
+        };
+
+        unsafe {
+            let window: id = msg_send![view_info.view.0, window];
+            if window == nil {
+                return ColorProfile::AssumedSrgb;
+            }
+            let screen: id = msg_send![window, screen];
+            if screen == nil {
+                return ColorProfile::AssumedSrgb;
+            }
+            let color_space: id = msg_send![screen, colorSpace];
+            if color_space == nil {
+                return ColorProfile::AssumedSrgb;
+            }
+            let icc_data: id = msg_send![color_space, ICCProfileData];
+            if icc_data == nil {
+                return ColorProfile::AssumedSrgb;
+            }
+
+            let bytes: *const u8 = msg_send![icc_data, bytes];
+            let length: usize = msg_send![icc_data, length];
+            ColorProfile::from_icc(slice::from_raw_parts(bytes, length).to_vec())
+                .unwrap_or(ColorProfile::AssumedSrgb)
         }
     }
 
+    /// Returns true if a widget surface is on a screen driven by a different GPU than this
+    /// device.
+    ///
+    /// macOS switches GPUs for the whole system rather than per screen, so this always returns
+    /// false.
+    #[inline]
+    pub fn presenting_cross_adapter(&self, _: &Surface) -> bool {
+        false
+    }
+
+    /// Returns the timestamps of the most recent presentations of a widget surface, oldest first.
+    ///
+    /// `compositor_ack` is when the Core Animation transaction was committed, and `displayed` is
+    /// the display link's next vertical blank.
+    ///
+    /// Returns an empty list for generic surfaces and for widget surfaces that haven't been
+    /// presented yet.
+    #[inline]
+    pub fn presentation_timestamps(&self, surface: &Surface) -> Vec<PresentRecord> {
+        surface.present_history.records()
+    }
+
+    /// Returns when the window system presented the most recent frame of a widget surface that
+    /// is known to have reached the display, or the most recent frame if none is yet.
+    ///
+    /// `actual_present` is the display link's next vertical blank after the Core Animation
+    /// transaction was committed, as `displayed` is in `presentation_timestamps()`. The GPU's
+    /// finish isn't reported on this platform.
+    ///
+    /// Returns `Error::NoWidgetAttached` for generic surfaces, and `Error::NotPresented` if
+    /// nothing has been presented on the surface.
+    pub fn query_presentation_time(
+        &self,
+        surface: &Surface,
+    ) -> Result<PresentationStatistics, Error> {
+        if surface.view_info.is_none() {
+            return Err(Error::NoWidgetAttached);
+        }
+        presentation_time::latest_statistics(&surface.present_history, |_, _| {})
+    }
+
     /// Returns the native `IOSurface` corresponding to this surface.
     ///
     /// The reference count is increased on the `IOSurface` before returning.
@@ -378,6 +754,7 @@ impl Surface {
     }
 
     fn present(&mut self) -> Result<(), Error> {
+        let submit = Instant::now();
         unsafe {
             transaction::begin();
             transaction::set_disable_actions(true);
@@ -392,6 +769,7 @@ impl Surface {
                 .set_contents(view_info.front_surface.obj as id);
 
             transaction::commit();
+            let compositor_ack = Instant::now();
 
             // Wait for the next swap interval.
             let next_vblank_mutex_guard = view_info.next_vblank.mutex.lock().unwrap();
@@ -403,14 +781,14 @@ impl Surface {
                     .unwrap(),
             );
 
+            self.present_history
+                .record(submit, Some(compositor_ack), Some(Instant::now()));
             Ok(())
         }
     }
 
     pub(crate) fn lock_data(&mut self) -> Result<SurfaceDataGuard, Error> {
-        if !self.access.cpu_access_allowed() {
-            return Err(Error::SurfaceDataInaccessible);
-        }
+        self.access.check_cpu_access()?;
 
         unsafe {
             let mut seed = 0;
@@ -457,6 +835,44 @@ impl<'a> Drop for SurfaceDataGuard<'a> {
     }
 }
 
+impl NativeWidget {
+    // Returns the size of the view in device pixels, or `None` if it isn't in a window yet.
+    #[inline]
+    pub(crate) unsafe fn backing_size(&self) -> Option<Size2D<i32>> {
+        backing_size(&self.view)
+    }
+}
+
+// Pads a width to a multiple of 4, as widget surfaces do, since Core Animation doesn't show
+// `IOSurface`s whose rows aren't a multiple of 16 bytes.
+fn padded_width(size: Size2D<i32>) -> Size2D<i32> {
+    Size2D::new((size.width + 3) / 4 * 4, size.height)
+}
+
+// Returns the number of device pixels per point of the window that a view is in, or `None` if it
+// isn't in a window.
+unsafe fn backing_scale(view: &NSView) -> Option<f32> {
+    let window: id = msg_send![view.0, window];
+    if window == nil {
+        return None;
+    }
+    let scale: CGFloat = msg_send![window, backingScaleFactor];
+    Some(scale as f32)
+}
+
+// Returns the size of a view in device pixels, or `None` if it isn't in a window.
+unsafe fn backing_size(view: &NSView) -> Option<Size2D<i32>> {
+    let window: id = msg_send![view.0, window];
+    if window == nil {
+        return None;
+    }
+    let bounds = window.convertRectToBacking(view.0.bounds());
+    Some(Size2D::new(
+        bounds.size.width as i32,
+        bounds.size.height as i32,
+    ))
+}
+
 impl Drop for NativeWidget {
     #[inline]
     fn drop(&mut self) {
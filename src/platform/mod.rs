@@ -1,29 +1,38 @@
 // surfman/surfman/src/platform/mod.rs
 //
 //! Platform-specific backends.
+//!
+//! Each backend has its own `sm-*` feature. The default backend for a target is chosen from
+//! whichever of its backends are enabled.
+
+#[cfg(not(any_backend))]
+compile_error!(
+    "surfman has no backend for this target. Enable one of the `sm-android`, `sm-angle`, \
+     `sm-cgl`, `sm-generic-egl`, `sm-ohos`, `sm-wayland`, `sm-wgl` or `sm-x11` features."
+);
 
 pub mod generic;
 
-#[cfg(any(android_platform, ohos_platform))]
+#[cfg(any(android_backend, ohos_backend))]
 pub mod egl;
-#[cfg(any(android_platform, ohos_platform))]
+#[cfg(any(android_backend, ohos_backend))]
 pub use egl as default;
 
-#[cfg(macos_platform)]
+#[cfg(cgl_backend)]
 pub mod macos;
-#[cfg(macos_platform)]
+#[cfg(cgl_backend)]
 pub use macos::cgl as default;
-#[cfg(macos_platform)]
+#[cfg(cgl_backend)]
 pub use macos::system;
 
-#[cfg(free_unix)]
+#[cfg(generic_egl_platform)]
 pub mod unix;
-#[cfg(free_unix)]
+#[cfg(generic_egl_platform)]
 pub use unix::default;
 
-#[cfg(windows_platform)]
+#[cfg(any(angle, wgl_backend))]
 pub mod windows;
 #[cfg(angle_default)]
 pub use windows::angle as default;
-#[cfg(all(windows_platform, not(angle_default)))]
+#[cfg(all(wgl_backend, not(angle_default)))]
 pub use windows::wgl as default;
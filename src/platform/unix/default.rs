@@ -23,6 +23,7 @@ pub mod connection {
 pub mod context {
     use crate::platform::generic::multi::context::Context as MultiContext;
     use crate::platform::generic::multi::context::ContextDescriptor as MultiContextDescriptor;
+    use crate::platform::generic::multi::context::CurrentContextGuard as MultiCurrentContextGuard;
     use crate::platform::generic::multi::context::NativeContext as MultiNativeContext;
     use crate::platform::generic::multi::device::Device as MultiDevice;
     use crate::platform::unix::generic::device::Device as SWDevice;
@@ -54,6 +55,9 @@ pub mod context {
     /// These are local to a device.
     pub type ContextDescriptor = MultiContextDescriptor<HWDevice, SWDevice>;
 
+    /// Makes the previously current context current again when dropped.
+    pub type CurrentContextGuard = MultiCurrentContextGuard<HWDevice, SWDevice>;
+
     /// Either a Wayland or an X11 native context
     pub type NativeContext = MultiNativeContext<HWDevice, SWDevice>;
 }
@@ -89,6 +93,7 @@ pub mod surface {
     use crate::platform::generic::multi::device::Device as MultiDevice;
     use crate::platform::generic::multi::surface::NativeWidget as MultiNativeWidget;
     use crate::platform::generic::multi::surface::Surface as MultiSurface;
+    use crate::platform::generic::multi::surface::SurfaceDataGuard as MultiSurfaceDataGuard;
     use crate::platform::generic::multi::surface::SurfaceTexture as MultiSurfaceTexture;
     use crate::platform::unix::generic::device::Device as SWDevice;
     use crate::platform::unix::wayland::device::Device as WaylandDevice;
@@ -127,7 +132,6 @@ pub mod surface {
     /// the `destroy_surface_texture()` method, or a panic will occur.
     pub type SurfaceTexture = MultiSurfaceTexture<HWDevice, SWDevice>;
 
-    // FIXME(pcwalton): Revamp how this works.
-    #[doc(hidden)]
-    pub struct SurfaceDataGuard {}
+    /// Represents the CPU view of the pixel data of a surface.
+    pub type SurfaceDataGuard<'s> = MultiSurfaceDataGuard<'s, HWDevice, SWDevice>;
 }
@@ -4,18 +4,44 @@
 
 use super::device::{Adapter, Device, NativeDevice};
 use super::surface::NativeWidget;
+use crate::acceleration;
 use crate::connection::NativeConnection as NativeConnectionInterface;
 use crate::egl;
-use crate::egl::types::{EGLAttrib, EGLDisplay};
+use crate::egl::types::{EGLAttrib, EGLDisplay, EGLenum, EGLint};
 use crate::info::GLApi;
-use crate::platform::generic::egl::device::EGL_FUNCTIONS;
-use crate::platform::generic::egl::ffi::EGL_PLATFORM_SURFACELESS_MESA;
-use crate::Error;
+use crate::platform::generic::egl::device::{self, EGL_FUNCTIONS};
+use crate::platform::generic::egl::error::ToWindowingApiError;
+use crate::platform::generic::egl::ffi::{EGL_EXTENSION_FUNCTIONS, EGL_NO_DEVICE_EXT};
+use crate::platform::generic::egl::ffi::{EGL_PLATFORM_DEVICE_EXT, EGL_PLATFORM_SURFACELESS_MESA};
+use crate::{AdapterKey, Error};
 
 use euclid::default::Size2D;
+use log::warn;
 
+use std::collections::HashMap;
 use std::os::raw::c_void;
-use std::sync::Arc;
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+// The backend name in adapter keys.
+const ADAPTER_KEY_BACKEND: &str = "surfaceless";
+
+// Names the display that `Connection::new()` connects to on the X11 and Wayland backends, in
+// place of `DISPLAY` or `WAYLAND_DISPLAY`.
+#[cfg(any(x11_platform, wayland_platform))]
+pub(crate) static DISPLAY_ENV_VAR: &str = "SURFMAN_DISPLAY";
+
+lazy_static! {
+    // The EGL displays that connections are using, keyed by display. `eglGetPlatformDisplay`
+    // returns the same display for the same native display, so connections may share one.
+    static ref EGL_DISPLAY_USES: Mutex<HashMap<usize, EGLDisplayUse>> = Mutex::new(HashMap::new());
+}
+
+struct EGLDisplayUse {
+    ref_count: usize,
+    // True if an owned connection initialized the display, so it must terminate it too.
+    owned: bool,
+}
 
 /// A no-op connection.
 #[derive(Clone)]
@@ -35,42 +61,54 @@ impl NativeConnectionInterface for NativeConnection {
 /// Native connections.
 pub struct NativeConnectionWrapper {
     pub(crate) egl_display: EGLDisplay,
+    pub(crate) gl_api: GLApi,
+    _egl_display_ref: EGLDisplayRef,
 }
 
+// The wrapper only holds an EGL display, and EGL is thread-safe. This makes `Connection` both
+// `Send` and `Sync`.
 unsafe impl Send for NativeConnectionWrapper {}
 unsafe impl Sync for NativeConnectionWrapper {}
 
+assert_send_sync!(Connection);
+assert_send_sync!(NativeConnection);
+
 impl Connection {
     /// Opens a surfaceless Mesa display.
+    ///
+    /// If surfaceless Mesa is unavailable, as with NVIDIA's driver, this falls back to the first
+    /// EGL device that `EGL_EXT_platform_device` can open.
+    ///
+    /// The display is terminated when the last clone of this connection, and of any connection
+    /// sharing its display, is dropped.
     #[inline]
     pub fn new() -> Result<Connection, Error> {
+        Connection::new_with_api(GLApi::GL)
+    }
+
+    /// Opens a surfaceless Mesa display that creates contexts for the given OpenGL API flavor.
+    pub fn new_with_api(gl_api: GLApi) -> Result<Connection, Error> {
         unsafe {
-            EGL_FUNCTIONS.with(|egl| {
-                let egl_display_attributes = [egl::NONE as EGLAttrib];
-                let egl_display = egl.GetPlatformDisplay(
-                    EGL_PLATFORM_SURFACELESS_MESA,
-                    egl::DEFAULT_DISPLAY as *mut c_void,
-                    egl_display_attributes.as_ptr(),
-                );
-                if egl_display == egl::NO_DISPLAY {
-                    return Err(Error::ConnectionFailed);
-                }
-
-                let (mut egl_major_version, mut egl_minor_version) = (0, 0);
-                let ok =
-                    egl.Initialize(egl_display, &mut egl_major_version, &mut egl_minor_version);
-                if ok == egl::FALSE {
-                    return Err(Error::ConnectionFailed);
-                }
-
-                let native_connection =
-                    NativeConnection(Arc::new(NativeConnectionWrapper { egl_display }));
-
-                Connection::from_native_connection(native_connection)
-            })
+            let egl_display_ref = create_surfaceless_egl_display()?;
+            let egl_display = egl_display_ref.egl_display();
+            if !device::display_supports_api(egl_display, gl_api) {
+                return Err(Error::UnsupportedGLType);
+            }
+            let native_connection = NativeConnection(Arc::new(NativeConnectionWrapper {
+                egl_display,
+                gl_api,
+                _egl_display_ref: egl_display_ref,
+            }));
+            Connection::from_native_connection(native_connection)
         }
     }
 
+    /// An alias for `Connection::new()`, as this backend never needs a display server.
+    #[inline]
+    pub fn new_headless() -> Result<Connection, Error> {
+        Connection::new()
+    }
+
     /// An alias for `Connection::new()`, present for consistency with other backends.
     #[inline]
     pub unsafe fn from_native_connection(
@@ -90,7 +128,17 @@ impl Connection {
     /// Returns the OpenGL API flavor that this connection supports (OpenGL or OpenGL ES).
     #[inline]
     pub fn gl_api(&self) -> GLApi {
-        GLApi::GL
+        self.native_connection.gl_api
+    }
+
+    /// Returns true if EGL advertises the named extension, either for this connection's display
+    /// or as a client extension.
+    pub fn supports_extension(&self, name: &str) -> bool {
+        unsafe {
+            let egl_display = self.native_connection.egl_display;
+            device::display_supports_extension(egl_display, name)
+                || device::client_supports_extension(name)
+        }
     }
 
     /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
@@ -123,12 +171,57 @@ impl Connection {
         Ok(Adapter::software())
     }
 
+    /// Returns the adapter whose driver or device name contains `needle`, ignoring case.
+    ///
+    /// Mesa picks the GPU when the display is opened, so only that adapter can match. Requires
+    /// `EGL_EXT_device_query`; without it, this returns `Error::NoAdapterFound`.
+    #[inline]
+    pub fn create_adapter_by_name(&self, needle: &str) -> Result<Adapter, Error> {
+        unsafe { Adapter::from_display_name(self.native_connection.egl_display, needle) }
+    }
+
+    /// Returns a key that describes the adapter, which can be saved and passed to
+    /// `find_adapter()` in a later run to find the same adapter again.
+    #[inline]
+    pub fn adapter_key(&self, adapter: &Adapter) -> Result<AdapterKey, Error> {
+        unsafe { Ok(adapter.key(ADAPTER_KEY_BACKEND, self.native_connection.egl_display)) }
+    }
+
+    /// Returns the adapter that a key from `adapter_key()` describes.
+    ///
+    /// Mesa picks the GPU when the display is opened, so a hardware adapter is only found if the
+    /// display is on the same GPU as when the key was taken. Returns `Error::IncompatibleAdapter`
+    /// if the key was taken with another backend, and `Error::NoAdapterFound` if the GPU is gone.
+    #[inline]
+    pub fn find_adapter(&self, key: &AdapterKey) -> Result<Adapter, Error> {
+        unsafe { Adapter::find(ADAPTER_KEY_BACKEND, self.native_connection.egl_display, key) }
+    }
+
+    /// Returns true if the two adapters are the same adapter.
+    #[inline]
+    pub fn is_same_adapter(&self, a: &Adapter, b: &Adapter) -> bool {
+        match (self.adapter_key(a), self.adapter_key(b)) {
+            (Ok(a), Ok(b)) => a.matches(&b),
+            _ => false,
+        }
+    }
+
+    /// Returns the adapter for the GPU that drives the output the window is on.
+    ///
+    /// This backend has no windows, so this always returns `Error::UnsupportedOnThisPlatform`.
+    #[inline]
+    pub fn adapter_for_widget(&self, _: &NativeWidget) -> Result<Adapter, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
     /// Opens the hardware device corresponding to the given adapter.
     ///
     /// Device handles are local to a single thread.
     #[inline]
     pub fn create_device(&self, adapter: &Adapter) -> Result<Device, Error> {
-        Device::new(self, adapter)
+        let device = Device::new(self, adapter)?;
+        acceleration::check_device(&device);
+        Ok(device)
     }
 
     /// An alias for `connection.create_device()` with the default adapter.
@@ -182,4 +275,210 @@ impl Connection {
     ) -> Result<NativeWidget, Error> {
         Err(Error::IncompatibleNativeWidget)
     }
+
+    /// Returns the real size of a native widget if it differs from the size that the widget was
+    /// created with.
+    ///
+    /// This backend has no widgets, so this always returns `None`.
+    #[inline]
+    pub fn native_widget_adjusted_size(&self, _: &NativeWidget) -> Option<Size2D<i32>> {
+        None
+    }
+}
+
+/// A way of opening an EGL display without a display server.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum HeadlessPlatform {
+    /// `EGL_PLATFORM_SURFACELESS_MESA`, available on Mesa.
+    SurfacelessMesa,
+    /// `EGL_PLATFORM_DEVICE_EXT` on the first usable EGL device. This is the only option on
+    /// drivers without Mesa, such as NVIDIA's on headless servers.
+    Device,
+}
+
+// The order in which headless platforms are tried.
+pub(crate) static HEADLESS_PLATFORMS: [HeadlessPlatform; 2] =
+    [HeadlessPlatform::SurfacelessMesa, HeadlessPlatform::Device];
+
+// Returns the first display that `open` manages to open, trying platforms in the order of
+// `HEADLESS_PLATFORMS`.
+pub(crate) fn open_first_headless_platform<T, F>(mut open: F) -> Result<T, Error>
+where
+    F: FnMut(HeadlessPlatform) -> Option<T>,
+{
+    HEADLESS_PLATFORMS
+        .iter()
+        .find_map(|&platform| open(platform))
+        .ok_or(Error::ConnectionFailed)
+}
+
+// Opens and initializes an EGL display that needs no display server: the surfaceless Mesa
+// display if possible, and the first EGL device otherwise. The display is owned.
+//
+// The windowing backends use this for their headless connections too.
+pub(crate) unsafe fn create_surfaceless_egl_display() -> Result<EGLDisplayRef, Error> {
+    open_first_headless_platform(|platform| match platform {
+        HeadlessPlatform::SurfacelessMesa => open_egl_display(
+            EGL_PLATFORM_SURFACELESS_MESA,
+            egl::DEFAULT_DISPLAY as *mut c_void,
+        ),
+        HeadlessPlatform::Device => open_egl_device_display(),
+    })
+}
+
+unsafe fn open_egl_device_display() -> Option<EGLDisplayRef> {
+    let query_devices = EGL_EXTENSION_FUNCTIONS.QueryDevicesEXT?;
+
+    let mut device_count = 0;
+    if query_devices(0, ptr::null_mut(), &mut device_count) == egl::FALSE || device_count <= 0 {
+        return None;
+    }
+    let mut devices = vec![EGL_NO_DEVICE_EXT; device_count as usize];
+    if query_devices(device_count, devices.as_mut_ptr(), &mut device_count) == egl::FALSE {
+        return None;
+    }
+
+    devices
+        .into_iter()
+        .take(device_count as usize)
+        .find_map(|device| open_egl_display(EGL_PLATFORM_DEVICE_EXT, device as *mut c_void))
+}
+
+unsafe fn open_egl_display(
+    platform: EGLenum,
+    native_display: *mut c_void,
+) -> Option<EGLDisplayRef> {
+    EGLDisplayRef::open(
+        || {
+            let egl_display = EGL_FUNCTIONS.with(|egl| {
+                let egl_display_attributes = [egl::NONE as EGLAttrib];
+                egl.GetPlatformDisplay(platform, native_display, egl_display_attributes.as_ptr())
+            });
+            if egl_display == egl::NO_DISPLAY {
+                return Err(Error::ConnectionFailed);
+            }
+            Ok(egl_display)
+        },
+        true,
+    )
+    .ok()
+}
+
+// A connection's use of an EGL display.
+//
+// EGL displays aren't reference counted, so this keeps count of the connections using each one.
+// When the last use of a display drops, the display is terminated if an owned connection
+// initialized it. Displays that were initialized by borrowed connections, or outside `surfman`,
+// are never terminated.
+pub(crate) struct EGLDisplayRef {
+    egl_display: EGLDisplay,
+}
+
+impl EGLDisplayRef {
+    // Gets the EGL display for a native display with `get_display`, then initializes it if it
+    // isn't already and records a use of it.
+    //
+    // EGL returns the same display for the same native display, so connections racing to open one
+    // native display share an EGL display. The uses stay locked from getting the display to
+    // recording the use, so that the connections agree on which of them initialized it, and none
+    // of them gets it while the last connection to drop it is terminating it.
+    //
+    // Fails with whatever `get_display` fails with, or with `Error::ConnectionFailed` if the
+    // display couldn't be initialized.
+    pub(crate) unsafe fn open<F>(get_display: F, owned: bool) -> Result<EGLDisplayRef, Error>
+    where
+        F: FnOnce() -> Result<EGLDisplay, Error>,
+    {
+        let mut uses = EGL_DISPLAY_USES.lock().unwrap();
+        let egl_display = get_display()?;
+        EGLDisplayRef::initialize(&mut uses, egl_display, owned).ok_or_else(|| {
+            let err = EGL_FUNCTIONS.with(|egl| egl.GetError().to_windowing_api_error());
+            warn!("Failed to initialize the EGL display: {:?}", err);
+            Error::ConnectionFailed
+        })
+    }
+
+    // Returns `None` if the display couldn't be initialized.
+    unsafe fn initialize(
+        uses: &mut HashMap<usize, EGLDisplayUse>,
+        egl_display: EGLDisplay,
+        owned: bool,
+    ) -> Option<EGLDisplayRef> {
+        if let Some(display_use) = uses.get_mut(&(egl_display as usize)) {
+            display_use.ref_count += 1;
+            return Some(EGLDisplayRef { egl_display });
+        }
+
+        let initialized = EGL_FUNCTIONS.with(|egl| {
+            // Querying an uninitialized display fails.
+            if !egl
+                .QueryString(egl_display, egl::VENDOR as EGLint)
+                .is_null()
+            {
+                return Some(true);
+            }
+            let (mut egl_major_version, mut egl_minor_version) = (0, 0);
+            let ok = egl.Initialize(egl_display, &mut egl_major_version, &mut egl_minor_version);
+            if ok == egl::FALSE {
+                None
+            } else {
+                Some(false)
+            }
+        })?;
+
+        uses.insert(
+            egl_display as usize,
+            EGLDisplayUse {
+                ref_count: 1,
+                owned: owned && !initialized,
+            },
+        );
+        Some(EGLDisplayRef { egl_display })
+    }
+
+    // Records a use of a display that the caller has already initialized.
+    pub(crate) fn borrow(egl_display: EGLDisplay) -> EGLDisplayRef {
+        let mut uses = EGL_DISPLAY_USES.lock().unwrap();
+        uses.entry(egl_display as usize)
+            .or_insert(EGLDisplayUse {
+                ref_count: 0,
+                owned: false,
+            })
+            .ref_count += 1;
+        EGLDisplayRef { egl_display }
+    }
+
+    #[inline]
+    pub(crate) fn egl_display(&self) -> EGLDisplay {
+        self.egl_display
+    }
+}
+
+// Returns the number of connections using the display.
+#[cfg(test)]
+pub(crate) fn egl_display_use_count(egl_display: EGLDisplay) -> usize {
+    EGL_DISPLAY_USES
+        .lock()
+        .unwrap()
+        .get(&(egl_display as usize))
+        .map_or(0, |display_use| display_use.ref_count)
+}
+
+impl Drop for EGLDisplayRef {
+    fn drop(&mut self) {
+        let key = self.egl_display as usize;
+        let mut uses = EGL_DISPLAY_USES.lock().unwrap();
+        let display_use = uses.get_mut(&key).unwrap();
+        display_use.ref_count -= 1;
+        if display_use.ref_count > 0 {
+            return;
+        }
+
+        let display_use = uses.remove(&key).unwrap();
+        if display_use.owned {
+            EGL_FUNCTIONS.with(|egl| unsafe {
+                egl.Terminate(self.egl_display);
+            });
+        }
+    }
 }
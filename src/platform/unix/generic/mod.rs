@@ -2,6 +2,8 @@
 //
 //! The Mesa "surfaceless" backend, which only supports off-screen surfaces and cannot directly
 //! display surfaces on a screen.
+//!
+//! Where surfaceless Mesa isn't available, this backend opens an EGL device display instead.
 
 pub mod connection;
 pub mod context;
@@ -4,13 +4,40 @@
 
 use super::context::{Context, GL_FUNCTIONS};
 use super::device::Device;
+use crate::bulk;
+use crate::clear::ClearRegion;
+use crate::convert::{self, Swizzle};
 use crate::gl;
 use crate::gl::types::{GLenum, GLuint};
-use crate::platform::generic::egl::surface::{EGLBackedSurface, EGLSurfaceTexture};
-use crate::{Error, SurfaceAccess, SurfaceInfo, SurfaceType};
+use crate::platform::generic::egl::surface::{EGLBackedSurface, EGLFence, EGLSurfaceTexture};
+use crate::readback;
+use crate::surface::check_surface_size;
+use crate::upload;
+use crate::vsync::AutoVsync;
+use crate::BufferRelease;
+use crate::DmaBuf;
+use crate::ColorProfileChangedHandler;
+use crate::GLCapabilities;
+use crate::InvalidateSet;
+use crate::LowMemoryOptions;
+use crate::PausedPresentation;
+use crate::PresentationStatistics;
+use crate::SurfaceFence;
+use crate::SurfaceOptions;
+use crate::SurfaceResizedHandler;
+use crate::SurfaceScaleChangedHandler;
+use crate::TextureOwnership;
+use crate::{AutoVsyncPolicy, AutoVsyncStats};
+use crate::{
+    ColorProfile, ContextID, Error, PresentRecord, SurfaceAccess, SurfaceInfo, SurfaceType,
+};
+use crate::{Purgeability, ReclaimResult};
+use crate::{SurfaceConfig, SurfaceIntent, SurfaceTransform};
 
-use euclid::default::Size2D;
+use euclid::default::{Rect, Size2D};
 use std::marker::PhantomData;
+use std::os::fd::BorrowedFd;
+use std::time::Duration;
 
 // FIXME(pcwalton): Is this right, or should it be `TEXTURE_EXTERNAL_OES`?
 const SURFACE_GL_TEXTURE_TARGET: GLenum = gl::TEXTURE_2D;
@@ -50,6 +77,8 @@ pub struct SurfaceTexture(pub(crate) EGLSurfaceTexture);
 #[derive(Clone)]
 pub struct NativeWidget;
 
+assert_send_sync!(NativeWidget);
+
 unsafe impl Send for Surface {}
 
 impl Device {
@@ -60,23 +89,66 @@ impl Device {
     pub fn create_surface(
         &mut self,
         context: &Context,
-        _: SurfaceAccess,
+        access: SurfaceAccess,
+        surface_type: SurfaceType<NativeWidget>,
+    ) -> Result<Surface, Error> {
+        self.create_surface_with_options(context, access, surface_type, SurfaceOptions::default())
+    }
+
+    /// Creates a surface of each of the given types, in order.
+    ///
+    /// The device's cancellation token is checked before each surface, as well as inside each
+    /// creation. If any surface fails or is cancelled, the ones created so far are destroyed and
+    /// the error is returned, so the batch creates either all of its surfaces or none.
+    pub fn create_surfaces<I>(
+        &mut self,
+        context: &mut Context,
+        access: SurfaceAccess,
+        surface_types: I,
+    ) -> Result<Vec<Surface>, Error>
+    where
+        I: IntoIterator<Item = SurfaceType<NativeWidget>>,
+    {
+        bulk::create_surfaces(self, context, access, surface_types)
+    }
+
+    /// Creates either a generic or a widget surface, with options such as its colorspace.
+    ///
+    /// `create_surface()` is this method with the default options. Returns
+    /// `Error::UnsupportedSurfaceFormat` if the platform can't create a surface with the options.
+    pub fn create_surface_with_options(
+        &mut self,
+        context: &Context,
+        access: SurfaceAccess,
         surface_type: SurfaceType<NativeWidget>,
+        options: SurfaceOptions,
     ) -> Result<Surface, Error> {
-        match surface_type {
-            SurfaceType::Generic { size } => self.create_generic_surface(context, &size),
+        let mut surface = match surface_type {
+            SurfaceType::Generic { size } => {
+                self.create_generic_surface(context, &size, access, options)
+            }
             SurfaceType::Widget { .. } => Err(Error::UnsupportedOnThisPlatform),
-        }
+        }?;
+        self.debug_fill_new_surface(context, &mut surface);
+        Ok(surface)
     }
 
     fn create_generic_surface(
         &mut self,
         context: &Context,
         size: &Size2D<i32>,
+        access: SurfaceAccess,
+        options: SurfaceOptions,
     ) -> Result<Surface, Error> {
-        let _guard = self.temporarily_make_context_current(context)?;
+        let _guard = self.make_context_current_guarded(context)?;
         let context_descriptor = self.context_descriptor(context);
         let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        let linear_format =
+            LowMemoryOptions::surface_format(self.low_memory_options, &context_attributes, access);
+        let capabilities = GL_FUNCTIONS.with(GLCapabilities::current);
+        check_surface_size(*size, &capabilities)?;
+        let format = options.generic_surface_format(&capabilities, linear_format)?;
+        self.cancel_token.check()?;
         GL_FUNCTIONS.with(|gl| {
             Ok(Surface(EGLBackedSurface::new_generic(
                 gl,
@@ -85,10 +157,26 @@ impl Device {
                 context.0.id,
                 &context_attributes,
                 size,
+                access,
+                format,
             )))
         })
     }
 
+    /// Returns the access mode, type, and options that suit a surface of the given size, created
+    /// with the given context for the given use, on this device, along with hints for using it.
+    ///
+    /// This backend can't map surfaces for the CPU, so the access mode is always `GPUOnly`.
+    pub fn recommended_surface_config(
+        &self,
+        context: &Context,
+        intent: SurfaceIntent<NativeWidget>,
+        size: Size2D<i32>,
+    ) -> Result<SurfaceConfig<NativeWidget>, Error> {
+        let capabilities = self.capabilities(context)?;
+        Ok(SurfaceConfig::recommend(intent, size, false, &capabilities))
+    }
+
     /// Creates a surface texture from an existing generic surface for use with the given context.
     ///
     /// The surface texture is local to the supplied context and takes ownership of the surface.
@@ -104,7 +192,7 @@ impl Device {
         context: &mut Context,
         surface: Surface,
     ) -> Result<SurfaceTexture, (Error, Surface)> {
-        let _guard = match self.temporarily_make_context_current(context) {
+        let _guard = match self.make_context_current_guarded(context) {
             Ok(guard) => guard,
             Err(err) => return Err((err, surface)),
         };
@@ -134,7 +222,7 @@ impl Device {
     ) -> Result<SurfaceTexture, Error> {
         let context_descriptor = self.context_descriptor(context);
         let context_attributes = self.context_descriptor_attributes(&context_descriptor);
-        let _guard = match self.temporarily_make_context_current(context) {
+        let _guard = match self.make_context_current_guarded(context) {
             Ok(guard) => guard,
             Err(err) => return Err(err),
         };
@@ -147,8 +235,10 @@ impl Device {
                 context.0.id,
                 &context_attributes,
                 size,
+                SurfaceAccess::GPUOnly,
                 texture_object,
                 egl_target,
+                TextureOwnership::Owned,
             )
         });
         Ok(SurfaceTexture(EGLSurfaceTexture {
@@ -158,6 +248,45 @@ impl Device {
         }))
     }
 
+    /// Wraps an existing GL texture in a generic surface, without copying it.
+    ///
+    /// The texture must be a complete `TEXTURE_2D` of the given size and format, created in the
+    /// supplied context or in a context that shares with it. Only `GL_RGBA8` (or unsized
+    /// `GL_RGBA`) textures are supported. The surface can be rendered to by the supplied context
+    /// and wrapped in a `SurfaceTexture` by other contexts, like any other generic surface.
+    ///
+    /// With `TextureOwnership::Borrowed`, the texture is never deleted by surfman, and must stay
+    /// alive and unmodified in size and format until the surface is destroyed. With
+    /// `TextureOwnership::Owned`, `destroy_surface()` deletes the texture.
+    ///
+    /// Returns `IncompatibleTexture` if the texture doesn't exist in the supplied context.
+    pub fn create_surface_from_texture(
+        &mut self,
+        context: &mut Context,
+        texture_object: GLuint,
+        size: &Size2D<i32>,
+        format: GLenum,
+        ownership: TextureOwnership,
+    ) -> Result<Surface, Error> {
+        let _guard = self.make_context_current_guarded(context)?;
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        GL_FUNCTIONS.with(|gl| {
+            Ok(Surface(EGLBackedSurface::new_from_foreign_texture(
+                gl,
+                self.native_connection.egl_display,
+                context.0.egl_context,
+                context.0.id,
+                &context_attributes,
+                size,
+                SurfaceAccess::GPUOnly,
+                texture_object,
+                format,
+                ownership,
+            )?))
+        })
+    }
+
     /// Destroys a surface.
     ///
     /// The supplied context must be the context the surface is associated with, or this returns
@@ -170,6 +299,7 @@ impl Device {
         context: &mut Context,
         surface: &mut Surface,
     ) -> Result<(), Error> {
+        let _guard = self.make_context_current_guarded(context);
         GL_FUNCTIONS.with(|gl| {
             let egl_display = self.native_connection.egl_display;
             let window = surface.0.destroy(gl, egl_display, context.0.id)?;
@@ -190,7 +320,7 @@ impl Device {
         context: &mut Context,
         surface_texture: SurfaceTexture,
     ) -> Result<Surface, (Error, SurfaceTexture)> {
-        match self.temporarily_make_context_current(context) {
+        match self.make_context_current_guarded(context) {
             Ok(_guard) => GL_FUNCTIONS.with(|gl| Ok(Surface(surface_texture.0.destroy(gl)))),
             Err(err) => Err((err, surface_texture)),
         }
@@ -202,30 +332,445 @@ impl Device {
     /// associated widgets until this method is called.
     ///
     /// The supplied context must match the context the surface was created with, or an
-    /// `IncompatibleSurface` error is returned.
+    /// `IncompatibleSurface` error is returned. The context doesn't need to be current, and the
+    /// context that was current before this call remains current afterward.
     pub fn present_surface(&self, context: &Context, surface: &mut Surface) -> Result<(), Error> {
-        surface
-            .0
-            .present(self.native_connection.egl_display, context.0.egl_context)
+        let egl_display = self.native_connection.egl_display;
+        self.lost_contexts.check(context.0.id, || {
+            GL_FUNCTIONS.with(|gl| {
+                surface
+                    .0
+                    .present(gl, egl_display, context.0.egl_context, context.0.id, None)
+            })
+        })
+    }
+
+    /// Displays the changed parts of a widget surface on screen.
+    ///
+    /// `damage` lists the rectangles that changed since the last present, measured from the
+    /// bottom left as in OpenGL, and is passed on with `EGL_KHR_swap_buffers_with_damage` where
+    /// the display supports it, so that the compositor only has to recomposite those. Otherwise,
+    /// the whole surface is swapped, as `present_surface()` does. An empty list means that
+    /// nothing changed, and skips the swap entirely.
+    pub fn present_surface_with_damage(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        let egl_display = self.native_connection.egl_display;
+        self.lost_contexts.check(context.0.id, || {
+            GL_FUNCTIONS.with(|gl| {
+                let egl_context = context.0.egl_context;
+                surface
+                    .0
+                    .present(gl, egl_display, egl_context, context.0.id, Some(damage))
+            })
+        })
+    }
+
+    /// Pauses the presentation of a widget surface: until it's resumed, each frame presented is
+    /// read back and held instead of shown, and `present_surface()` returns `PresentationPaused`.
+    ///
+    /// Surfaces that render to the front buffer return `UnsupportedPresentMode`, and contexts
+    /// without `glBlitFramebuffer()` return `RequiredExtensionUnavailable`.
+    pub fn pause_presentation(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<PausedPresentation, Error> {
+        let capabilities = self.capabilities(context)?;
+        surface.0.pause_presentation(context.0.id, &capabilities)
+    }
+
+    /// Ends a pause in the presentation of a widget surface.
+    ///
+    /// If `present_pending` is true, the frame held during the pause is drawn back into the
+    /// surface and presented as by `present_surface()`; otherwise, it's discarded.
+    pub fn resume_presentation(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        paused: PausedPresentation,
+        present_pending: bool,
+    ) -> Result<(), Error> {
+        let egl_display = self.native_connection.egl_display;
+        let present = self.lost_contexts.check(context.0.id, || {
+            GL_FUNCTIONS.with(|gl| {
+                let egl_context = context.0.egl_context;
+                surface.0.resume_presentation(
+                    gl,
+                    egl_display,
+                    egl_context,
+                    context.0.id,
+                    paused,
+                    present_pending,
+                )
+            })
+        })?;
+        if present {
+            self.present_surface(context, surface)?;
+        }
+        Ok(())
+    }
+
+    /// Displays the contents of a widget surface on screen, and returns a fence that signals once
+    /// the GPU has finished rendering it.
+    ///
+    /// The fence is an `EGL_SYNC_FENCE`, or a native fence where the display supports
+    /// `EGL_ANDROID_native_fence_sync`, inserted with the surface's context current right after
+    /// its buffers are swapped. Generic surfaces aren't presented, and only get the fence.
+    pub fn present_surface_with_fence(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<SurfaceFence, Error> {
+        if context.0.id != surface.0.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if surface.0.is_widget() {
+            self.present_surface(context, surface)?;
+        }
+        let egl_display = self.native_connection.egl_display;
+        let _guard = self.make_context_current_guarded(context)?;
+        let egl_fence = GL_FUNCTIONS.with(|gl| unsafe { EGLFence::insert(gl, egl_display) })?;
+        Ok(SurfaceFence { egl_fence })
+    }
+
+    /// Makes the context wait on the GPU until the fence signals, with `eglWaitSync()`.
+    pub fn wait_fence(&self, context: &Context, fence: &SurfaceFence) -> Result<(), Error> {
+        let _guard = self.make_context_current_guarded(context)?;
+        unsafe { fence.egl_fence.wait(self.native_connection.egl_display) }
     }
 
-    /// Resizes a widget surface.
+    /// Returns a native fence that signals when the sync file `fd` does, which needs
+    /// `EGL_ANDROID_native_fence_sync`. The descriptor is duplicated, so it stays the caller's.
+    pub fn import_sync_fd(&self, context: &Context, fd: BorrowedFd) -> Result<SurfaceFence, Error> {
+        let _guard = self.make_context_current_guarded(context)?;
+        let egl_fence = unsafe { EGLFence::import(self.native_connection.egl_display, fd)? };
+        Ok(SurfaceFence { egl_fence })
+    }
+
+    /// Resizes a surface in place.
+    ///
+    /// Generic surfaces are reallocated at the new size, keeping their framebuffer objects.
+    /// EGL resizes window surfaces to follow their window, so widget surfaces only record the
+    /// size.
     pub fn resize_surface(
         &self,
-        _context: &Context,
+        context: &Context,
         surface: &mut Surface,
         size: Size2D<i32>,
     ) -> Result<(), Error> {
-        surface.0.size = size;
+        check_surface_size(size, &self.capabilities(context)?)?;
+        let _guard = self.make_context_current_guarded(context)?;
+        GL_FUNCTIONS.with(|gl| {
+            let egl_display = self.native_connection.egl_display;
+            surface
+                .0
+                .resize(gl, egl_display, context.0.egl_context, context.0.id, size)
+        })
+    }
+
+    /// Sets the number of vertical blanks that `present_surface()` waits for before showing each
+    /// frame on a widget surface, and returns the interval that took effect.
+    ///
+    /// Negative intervals are only honored if the surface's EGL config allows them, and are
+    /// otherwise replaced with their absolute value. Intervals outside the range that the config
+    /// supports return `UnsupportedOnThisPlatform`.
+    ///
+    /// Surfaceless devices have no widgets, so this always returns `NoWidgetAttached`.
+    pub fn set_swap_interval(
+        &mut self,
+        context: &Context,
+        surface: &Surface,
+        interval: i32,
+    ) -> Result<i32, Error> {
+        let egl_display = self.native_connection.egl_display;
+        surface
+            .0
+            .set_swap_interval(egl_display, context.0.egl_context, context.0.id, interval)
+    }
+
+    /// Returns true if the EGL config of a widget surface allows the negative swap intervals of
+    /// adaptive vsync.
+    ///
+    /// Surfaceless devices have no widgets, so this always returns false.
+    #[inline]
+    pub fn supports_adaptive_swap(&self, surface: &Surface) -> bool {
+        surface
+            .0
+            .supports_adaptive_swap(self.native_connection.egl_display)
+    }
+
+    /// Lets surfman switch a widget surface between vsync and immediate presentation by itself,
+    /// following `policy`, or stops it with `None`.
+    ///
+    /// The frame time is measured from the return of `eglSwapBuffers()`.
+    ///
+    /// Surfaceless devices have no widgets, so this always returns `NoWidgetAttached`.
+    pub fn set_auto_swap_interval(
+        &mut self,
+        context: &Context,
+        surface: &mut Surface,
+        policy: Option<AutoVsyncPolicy>,
+    ) -> Result<(), Error> {
+        let egl_display = self.native_connection.egl_display;
+        surface
+            .0
+            .set_auto_swap_interval(egl_display, context.0.egl_context, context.0.id, policy)
+    }
+
+    /// Returns what automatic vsync has decided for a surface, or `None` if it isn't enabled.
+    #[inline]
+    pub fn auto_swap_interval_stats(&self, surface: &Surface) -> Option<AutoVsyncStats> {
+        surface.0.auto_vsync.as_ref().map(AutoVsync::stats)
+    }
+
+    /// Waits until the buffer of the last frame presented on a widget surface has been released.
+    ///
+    /// Surfaceless devices have no widgets, so this always returns `NoWidgetAttached`.
+    pub fn wait_previous_buffer_released(
+        &self,
+        surface: &Surface,
+        timeout: Duration,
+    ) -> Result<BufferRelease, Error> {
+        surface.0.wait_previous_buffer_released(timeout)
+    }
+
+    /// Chooses which attachments of a surface are invalidated right after each present.
+    ///
+    /// The color buffer of a generic surface is what consumers read once it's presented, so
+    /// invalidating it returns `PreservedColorBuffer`.
+    pub fn set_post_present_invalidation(
+        &self,
+        surface: &mut Surface,
+        set: InvalidateSet,
+    ) -> Result<(), Error> {
+        surface
+            .0
+            .set_post_present_invalidation(self.native_connection.egl_display, set)
+    }
+
+    /// Returns which attachments of a surface are invalidated right after each present.
+    #[inline]
+    pub fn post_present_invalidation(&self, surface: &Surface) -> InvalidateSet {
+        surface.0.invalidation.set()
+    }
+
+    /// Invalidates the attachments of a generic surface that were chosen with
+    /// `set_post_present_invalidation()`, making the context current for the call.
+    ///
+    /// Widget surfaces are invalidated by `present_surface()`, and return `WidgetAttached`.
+    pub fn invalidate_surface(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        let _guard = self.make_context_current_guarded(context)?;
+        GL_FUNCTIONS.with(|gl| surface.0.invalidate(gl, context.0.id))
+    }
+
+    /// Clears a region of a surface, measured from its top left, to `color`, and its depth and
+    /// stencil buffers to `depth` and `stencil` if they're given and the surface has them.
+    ///
+    /// The context is made current for the call, and the state that the clear changes is put
+    /// back afterward.
+    pub fn clear_surface_region(
+        &self,
+        context: &mut Context,
+        surface: &mut Surface,
+        rect: Option<Rect<i32>>,
+        color: [f32; 4],
+        depth: Option<f32>,
+        stencil: Option<i32>,
+    ) -> Result<(), Error> {
+        let region = ClearRegion::new(rect, color, depth, stencil);
+        self.clear_surface_with(context, surface, &region)
+    }
+
+    /// Fills a surface with this device's debug fill, as if it had just been created, or does
+    /// nothing if the fill is `DebugFill::None`.
+    pub fn debug_fill_surface(
+        &self,
+        context: &mut Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        match self.debug_fill.region() {
+            Some(region) => self.clear_surface_with(context, surface, &region),
+            None => Ok(()),
+        }
+    }
+
+    // Fills a new surface with the debug fill, if there is one. The surface is usable either way,
+    // so failing to fill it is only logged.
+    fn debug_fill_new_surface(&self, context: &Context, surface: &mut Surface) {
+        if let Some(region) = self.debug_fill.region() {
+            if let Err(err) = self.clear_surface_with(context, surface, &region) {
+                warn!(
+                    "Failed to fill a new surface with the debug fill: {:?}",
+                    err
+                );
+            }
+        }
+    }
+
+    fn clear_surface_with(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        region: &ClearRegion,
+    ) -> Result<(), Error> {
+        let _guard = self.make_context_current_guarded(context)?;
+        GL_FUNCTIONS.with(|gl| {
+            surface.0.clear_region(
+                gl,
+                self.native_connection.egl_display,
+                context.0.egl_context,
+                context.0.id,
+                region,
+            )
+        })
+    }
+
+    /// Returns how many frames ago the back buffer of a widget surface was last presented.
+    ///
+    /// This backend has no widget surfaces, so this returns `NoWidgetAttached`.
+    #[inline]
+    pub fn surface_buffer_age(&self, surface: &Surface) -> Result<u32, Error> {
+        surface.0.buffer_age()
+    }
+
+    /// Asks the window system for the current size of a widget surface's native window.
+    ///
+    /// This backend has no widget surfaces, so this returns `NoWidgetAttached`.
+    #[inline]
+    pub fn query_surface_native_size(&self, _: &Surface) -> Result<Size2D<i32>, Error> {
+        Err(Error::NoWidgetAttached)
+    }
+
+    /// Returns the rotation that the window system applies to a widget surface's frames to show
+    /// them on its display.
+    ///
+    /// Surfaces here are never shown by a window system, so this is always
+    /// `SurfaceTransform::Identity`.
+    #[inline]
+    pub fn surface_transform(&self, _surface: &Surface) -> SurfaceTransform {
+        SurfaceTransform::Identity
+    }
+
+    /// Registers a function to call with the ID and new size of each widget surface of this
+    /// device that is found to have been resized behind surfman's back.
+    ///
+    /// Surfaceless devices have no widgets to be resized, so the handler is never called.
+    #[inline]
+    pub fn set_surface_resized_handler(&mut self, _: Option<SurfaceResizedHandler>) {}
+
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to have moved to a display with a different scale factor.
+    ///
+    /// Surfaceless devices have no widgets to move between displays, so the handler is never
+    /// called.
+    #[inline]
+    pub fn set_surface_scale_changed_handler(
+        &mut self,
+        _: Option<SurfaceScaleChangedHandler>,
+    ) -> Result<(), Error> {
         Ok(())
     }
 
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to be displayed with a different color profile.
+    ///
+    /// Surfaceless devices have no outputs, so the handler is never called.
+    #[inline]
+    pub fn set_color_profile_changed_handler(&mut self, _: Option<ColorProfileChangedHandler>) {}
+
+    /// Sets whether the system may discard the contents of a generic surface to free memory.
+    ///
+    /// Mesa gives no way to offer a surface's memory back to the system, so volatile surfaces stay
+    /// resident and are never discarded. The state is still enforced: a volatile surface must be
+    /// taken back with `reclaim_surface()` before it's used again.
+    pub fn set_surface_purgeable(
+        &self,
+        surface: &mut Surface,
+        purgeability: Purgeability,
+    ) -> Result<(), Error> {
+        surface.0.set_purgeable(purgeability)
+    }
+
+    /// Makes a surface non-purgeable again, and reports whether the system discarded its contents
+    /// since it was made volatile.
+    ///
+    /// On this backend, the contents are always intact.
+    pub fn reclaim_surface(&self, surface: &mut Surface) -> Result<ReclaimResult, Error> {
+        surface.0.reclaim()
+    }
+
     /// Returns a pointer to the underlying surface data for reading or writing by the CPU.
     #[inline]
-    pub fn lock_surface_data<'s>(&self, _: &'s mut Surface) -> Result<SurfaceDataGuard<'s>, Error> {
+    pub fn lock_surface_data<'s>(
+        &self,
+        surface: &'s mut Surface,
+    ) -> Result<SurfaceDataGuard<'s>, Error> {
+        surface.0.access.check_cpu_access()?;
+        surface.0.purge_state.check_usable()?;
         Err(Error::Unimplemented)
     }
 
+    /// Exports the texture of a generic surface as a dma-buf, which needs
+    /// `EGL_MESA_image_dma_buf_export`.
+    pub fn export_surface_dma_buf(&self, surface: &Surface) -> Result<DmaBuf, Error> {
+        surface.0.export_dma_buf(self.native_connection.egl_display)
+    }
+
+    /// Copies the contents of `source` into `destination` on the GPU, converting between their
+    /// formats and colorspaces and exchanging channels as `swizzle` says.
+    ///
+    /// Both surfaces must be generic surfaces of `context`, the same size, and `context` must be
+    /// current.
+    #[inline]
+    pub fn convert_surface(
+        &self,
+        context: &mut Context,
+        source: &Surface,
+        destination: &mut Surface,
+        swizzle: Swizzle,
+    ) -> Result<(), Error> {
+        convert::convert_surface(self, context, source, destination, swizzle)
+    }
+
+    /// Reads a rectangle of a generic surface, measured from its top left, into `out` as tightly
+    /// packed 8-bit RGBA, top row first.
+    ///
+    /// The surface must belong to `context`, which needn't be current.
+    #[inline]
+    pub fn read_surface_data(
+        &self,
+        context: &Context,
+        surface: &Surface,
+        rect: Rect<i32>,
+        out: &mut [u8],
+    ) -> Result<(), Error> {
+        readback::read_surface_data(self, context, surface, rect, out)
+    }
+
+    /// Writes 8-bit RGBA pixels from `data`, top row first, into a rectangle of a generic surface,
+    /// measured from its top left.
+    ///
+    /// The surface must belong to `context`, which needn't be current.
+    #[inline]
+    pub fn write_surface_data(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        rect: Rect<i32>,
+        data: &[u8],
+        stride: usize,
+    ) -> Result<(), Error> {
+        upload::write_surface_data(self, context, surface, rect, data, stride)
+    }
+
     /// Returns the OpenGL texture target needed to read from this surface texture.
     ///
     /// This will be `GL_TEXTURE_2D` or `GL_TEXTURE_RECTANGLE`, depending on platform.
@@ -244,6 +789,46 @@ impl Device {
         surface.0.info()
     }
 
+    /// Returns the color profile of the output that a widget surface is displayed on.
+    ///
+    /// This backend has no outputs, so this always returns `ColorProfile::AssumedSrgb`.
+    #[inline]
+    pub fn widget_color_profile(&self, _: &Surface) -> ColorProfile {
+        ColorProfile::AssumedSrgb
+    }
+
+    /// Returns true if a widget surface is on an output driven by a different GPU than this
+    /// device.
+    ///
+    /// This backend has no outputs, so this always returns false.
+    #[inline]
+    pub fn presenting_cross_adapter(&self, _: &Surface) -> bool {
+        false
+    }
+
+    /// Returns the timestamps of the most recent presentations of a widget surface, oldest first.
+    ///
+    /// `compositor_ack` is when `eglSwapBuffers()` returned. The display time isn't reported on
+    /// this platform.
+    ///
+    /// Returns an empty list for generic surfaces and for widget surfaces that haven't been
+    /// presented yet.
+    #[inline]
+    pub fn presentation_timestamps(&self, surface: &Surface) -> Vec<PresentRecord> {
+        surface.0.presentation_records()
+    }
+
+    /// Returns when the window system presented the most recent frame of a widget surface that
+    /// is known to have reached the display, or the most recent frame if none is yet.
+    ///
+    /// This backend has no widget surfaces, so this always returns `Error::NoWidgetAttached`.
+    pub fn query_presentation_time(
+        &self,
+        surface: &Surface,
+    ) -> Result<PresentationStatistics, Error> {
+        surface.0.presentation_time()
+    }
+
     /// Returns the OpenGL texture object containing the contents of this surface.
     ///
     /// It is only legal to read from, not write to, this texture object.
@@ -251,6 +836,26 @@ impl Device {
     pub fn surface_texture_object(&self, surface_texture: &SurfaceTexture) -> GLuint {
         surface_texture.0.texture_object
     }
+
+    /// Returns the sized internal format of the OpenGL texture of this surface texture, such as
+    /// `GL_RGBA8` or `GL_RGBA16F`.
+    ///
+    /// This is the `SurfaceInfo::format` of the wrapped surface, which tells shaders whether they
+    /// sample normalized or floating-point values.
+    #[inline]
+    pub fn surface_texture_format(&self, surface_texture: &SurfaceTexture) -> GLenum {
+        surface_texture.0.surface.info().format
+    }
+}
+
+impl Surface {
+    /// Returns the ID of the context that this surface was created with.
+    ///
+    /// A surface can only be bound to the context that it was created with.
+    #[inline]
+    pub fn context_id(&self) -> ContextID {
+        self.0.context_id
+    }
 }
 
 /// Represents the CPU view of the pixel data of this surface.
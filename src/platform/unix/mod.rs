@@ -2,16 +2,25 @@
 //
 //! Backends specific to Unix-like systems, particularly Linux.
 
-// The default when x11 is enabled, and wayland default is not explicitly selected.
-#[cfg(all(x11_platform, not(wayland_default)))]
+// The default when x11 and wayland are both enabled, and wayland default is not explicitly
+// selected.
+#[cfg(all(x11_platform, wayland_platform, not(wayland_default)))]
 pub mod default;
 
 #[cfg(wayland_default)]
 pub use wayland as default;
 
-#[cfg(free_unix)]
+#[cfg(all(x11_platform, not(wayland_platform)))]
+pub use x11 as default;
+
+#[cfg(not(any(x11_platform, wayland_platform)))]
+pub use generic as default;
+
 pub mod generic;
 
+#[cfg(all(test, wayland_platform))]
+mod multi;
+
 #[cfg(wayland_platform)]
 pub mod wayland;
 #[cfg(x11_platform)]
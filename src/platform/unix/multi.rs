@@ -0,0 +1,336 @@
+// surfman/surfman/src/platform/unix/multi.rs
+//
+//! A multi backend that switches between Wayland and surfaceless, for testing how the multi
+//! wrappers delegate to the backends they wrap.
+//!
+//! The shared tests run through it in place of the Wayland backend, so with a Wayland compositor
+//! every shared test reaches Wayland through the multi wrappers, and without one they reach the
+//! surfaceless alternate. As the shared tests call every backend method, a method that the multi
+//! wrappers don't delegate fails to compile here.
+
+/// Wayland or surfaceless display server connections.
+pub mod connection {
+    use crate::platform::generic::multi::connection::Connection as MultiConnection;
+    use crate::platform::generic::multi::connection::NativeConnection as MultiNativeConnection;
+    use crate::platform::unix::generic::device::Device as SWDevice;
+    use crate::platform::unix::wayland::device::Device as WaylandDevice;
+
+    pub type Connection = MultiConnection<WaylandDevice, SWDevice>;
+    pub type NativeConnection = MultiNativeConnection<WaylandDevice, SWDevice>;
+}
+
+/// OpenGL rendering contexts.
+pub mod context {
+    use crate::platform::generic::multi::context::Context as MultiContext;
+    use crate::platform::generic::multi::context::ContextDescriptor as MultiContextDescriptor;
+    use crate::platform::generic::multi::context::NativeContext as MultiNativeContext;
+    use crate::platform::unix::generic::device::Device as SWDevice;
+    use crate::platform::unix::wayland::device::Device as WaylandDevice;
+
+    pub type Context = MultiContext<WaylandDevice, SWDevice>;
+    pub type ContextDescriptor = MultiContextDescriptor<WaylandDevice, SWDevice>;
+    pub type NativeContext = MultiNativeContext<WaylandDevice, SWDevice>;
+}
+
+/// Thread-local handles to devices.
+pub mod device {
+    use crate::platform::generic::multi::device::Adapter as MultiAdapter;
+    use crate::platform::generic::multi::device::Device as MultiDevice;
+    use crate::platform::generic::multi::device::NativeDevice as MultiNativeDevice;
+    use crate::platform::unix::generic::device::Device as SWDevice;
+    use crate::platform::unix::wayland::device::Device as WaylandDevice;
+
+    pub type Adapter = MultiAdapter<WaylandDevice, SWDevice>;
+    pub type Device = MultiDevice<WaylandDevice, SWDevice>;
+    pub type NativeDevice = MultiNativeDevice<WaylandDevice, SWDevice>;
+}
+
+/// Hardware buffers of pixels.
+pub mod surface {
+    use crate::platform::generic::multi::surface::Surface as MultiSurface;
+    use crate::platform::generic::multi::surface::SurfaceDataGuard as MultiSurfaceDataGuard;
+    use crate::platform::generic::multi::surface::SurfaceTexture as MultiSurfaceTexture;
+    use crate::platform::unix::generic::device::Device as SWDevice;
+    use crate::platform::unix::wayland::device::Device as WaylandDevice;
+
+    pub type Surface = MultiSurface<WaylandDevice, SWDevice>;
+    pub type SurfaceDataGuard<'s> = MultiSurfaceDataGuard<'s, WaylandDevice, SWDevice>;
+    pub type SurfaceTexture = MultiSurfaceTexture<WaylandDevice, SWDevice>;
+}
+
+#[path = "../../tests.rs"]
+mod tests;
+
+use self::connection::{Connection, NativeConnection};
+use self::context::{Context, ContextDescriptor, NativeContext};
+use self::device::{Adapter, Device, NativeDevice};
+use self::surface::{Surface, SurfaceDataGuard, SurfaceTexture};
+use crate::platform::generic::multi::connection::Connection as MultiConnection;
+use crate::platform::generic::multi::device::Adapter as MultiAdapter;
+use crate::platform::generic::multi::device::Device as MultiDevice;
+use crate::platform::generic::multi::surface::Surface as MultiSurface;
+use crate::platform::unix::generic::connection::Connection as SWConnection;
+use crate::platform::unix::generic::device::Device as SWDevice;
+use crate::platform::unix::wayland::connection::Connection as WaylandConnection;
+use crate::{ContextAttributeFlags, ContextAttributes, Error, GLVersion};
+use crate::{SurfaceAccess, SurfaceType};
+
+use euclid::default::Size2D;
+use serial_test::serial;
+use std::panic::{self, AssertUnwindSafe};
+
+fn test_attributes() -> ContextAttributes {
+    ContextAttributes {
+        version: GLVersion::new(3, 0),
+        flags: ContextAttributeFlags::empty(),
+        ..Default::default()
+    }
+}
+
+// Returns the message of a panic raised with a string literal.
+fn panic_message<T>(result: std::thread::Result<T>) -> &'static str {
+    match result {
+        Ok(_) => panic!("Expected a panic"),
+        Err(payload) => payload.downcast_ref::<&'static str>().unwrap(),
+    }
+}
+
+// Tests that every call on a multi device goes to the backend that the device wraps, and that
+// everything it returns is wrapped in the same variant. The surfaceless alternate is always
+// tested, and the Wayland default only when a compositor is running.
+#[test]
+#[serial]
+fn test_multi_delegation_routes_to_variant() {
+    let mut connections = vec![Connection::Alternate(SWConnection::new().unwrap())];
+    if let Ok(connection) = WaylandConnection::new() {
+        connections.push(Connection::Default(connection));
+    }
+
+    for connection in connections {
+        let default = matches!(connection, Connection::Default(_));
+
+        let native_connection = connection.native_connection();
+        assert_eq!(
+            matches!(native_connection, NativeConnection::Default(_)),
+            default
+        );
+        let borrowed_connection =
+            unsafe { Connection::from_native_connection(native_connection).unwrap() };
+        assert_eq!(
+            matches!(borrowed_connection, Connection::Default(_)),
+            default
+        );
+
+        let adapter = connection.create_adapter().unwrap();
+        assert_eq!(matches!(adapter, Adapter::Default(_)), default);
+        let mut device = connection.create_device(&adapter).unwrap();
+        assert_eq!(matches!(device, Device::Default(_)), default);
+        assert_eq!(matches!(device.adapter(), Adapter::Default(_)), default);
+        assert_eq!(
+            matches!(device.connection(), Connection::Default(_)),
+            default
+        );
+        assert_eq!(
+            matches!(device.native_device(), NativeDevice::Default(_)),
+            default
+        );
+
+        let descriptor = device
+            .create_context_descriptor(&test_attributes())
+            .unwrap();
+        assert_eq!(matches!(descriptor, ContextDescriptor::Default(_)), default);
+        let mut context = device.create_context(&descriptor, None).unwrap();
+        assert_eq!(matches!(context, Context::Default(_)), default);
+        assert_eq!(
+            matches!(
+                device.context_descriptor(&context),
+                ContextDescriptor::Default(_)
+            ),
+            default
+        );
+        assert_eq!(
+            matches!(device.native_context(&context), NativeContext::Default(_)),
+            default
+        );
+        device.make_context_current(&context).unwrap();
+        assert_eq!(
+            matches!(
+                device.current_native_context().unwrap(),
+                NativeContext::Default(_)
+            ),
+            default
+        );
+
+        let mut surface = device
+            .create_surface(
+                &context,
+                SurfaceAccess::GPUCPU,
+                SurfaceType::Generic {
+                    size: Size2D::new(64, 64),
+                },
+            )
+            .unwrap();
+        assert_eq!(matches!(surface, Surface::Default(_)), default);
+        match device.lock_surface_data(&mut surface) {
+            Ok(guard) => assert_eq!(matches!(guard, SurfaceDataGuard::Default(_)), default),
+            // Neither backend can map surfaces yet.
+            Err(Error::Unimplemented) => {}
+            Err(err) => panic!("Failed to lock surface data: {:?}", err),
+        }
+        let surface_texture = device
+            .create_surface_texture(&mut context, surface)
+            .unwrap();
+        assert_eq!(
+            matches!(surface_texture, SurfaceTexture::Default(_)),
+            default
+        );
+        let mut surface = device
+            .destroy_surface_texture(&mut context, surface_texture)
+            .unwrap();
+        assert_eq!(matches!(surface, Surface::Default(_)), default);
+
+        device.destroy_surface(&mut context, &mut surface).unwrap();
+        device.destroy_context(&mut context).unwrap();
+    }
+}
+
+// Tests that adapters are searched for by name in both backends, and that an adapter of the other
+// backend opens a headless device of that backend.
+#[test]
+#[serial]
+fn test_multi_create_adapter_by_name() {
+    type Multi = MultiDevice<SWDevice, SWDevice>;
+    let connection: MultiConnection<SWDevice, SWDevice> =
+        MultiConnection::Default(SWConnection::new().unwrap());
+    match connection.create_adapter_by_name("no such adapter") {
+        Err(Error::NoAdapterFound) => {}
+        Err(err) => panic!("Unexpected error: {:?}", err),
+        Ok(_) => panic!("Found an adapter that shouldn't exist!"),
+    }
+
+    // Every name contains the empty string, so this fails only if adapter names are unavailable.
+    match connection.create_adapter_by_name("") {
+        Ok(adapter) => assert!(matches!(adapter, MultiAdapter::Default(_))),
+        Err(Error::NoAdapterFound) => {}
+        Err(err) => panic!("Failed to create adapter: {:?}", err),
+    }
+
+    let alternate_adapter =
+        MultiConnection::<SWDevice, SWDevice>::Alternate(SWConnection::new_headless().unwrap())
+            .create_adapter()
+            .unwrap();
+    let mut device: Multi = connection.create_device(&alternate_adapter).unwrap();
+    assert!(matches!(device, MultiDevice::Alternate(_)));
+    assert!(matches!(device.connection(), MultiConnection::Alternate(_)));
+    let descriptor = device
+        .create_context_descriptor(&test_attributes())
+        .unwrap();
+    let mut context = device.create_context(&descriptor, None).unwrap();
+    let mut surface = device
+        .create_surface(
+            &context,
+            SurfaceAccess::GPUOnly,
+            SurfaceType::Generic {
+                size: Size2D::new(64, 64),
+            },
+        )
+        .unwrap();
+    device.destroy_surface(&mut context, &mut surface).unwrap();
+    device.destroy_context(&mut context).unwrap();
+}
+
+// Tests that passing an object of one variant to a device of the other fails the same way
+// everywhere: with the `Incompatible` error for the first mismatched argument, or, for methods
+// that can't fail, with a panic naming it. This needs both variants to work, so both are
+// surfaceless.
+#[test]
+#[serial]
+fn test_multi_variant_mismatches() {
+    type Multi = MultiDevice<SWDevice, SWDevice>;
+    let default_connection: MultiConnection<SWDevice, SWDevice> =
+        MultiConnection::Default(SWConnection::new().unwrap());
+    let alternate_connection: MultiConnection<SWDevice, SWDevice> =
+        MultiConnection::Alternate(SWConnection::new().unwrap());
+
+    let default_adapter = default_connection.create_adapter().unwrap();
+    let alternate_adapter = alternate_connection.create_adapter().unwrap();
+
+    let mut default_device: Multi = default_connection.create_device(&default_adapter).unwrap();
+    let mut alternate_device: Multi = alternate_connection
+        .create_device(&alternate_adapter)
+        .unwrap();
+    let default_descriptor = default_device
+        .create_context_descriptor(&test_attributes())
+        .unwrap();
+    let alternate_descriptor = alternate_device
+        .create_context_descriptor(&test_attributes())
+        .unwrap();
+    assert!(matches!(
+        default_device.create_context(&alternate_descriptor, None),
+        Err(Error::IncompatibleContextDescriptor)
+    ));
+
+    let mut default_context = default_device
+        .create_context(&default_descriptor, None)
+        .unwrap();
+    let mut alternate_context = alternate_device
+        .create_context(&alternate_descriptor, None)
+        .unwrap();
+    assert!(matches!(
+        default_device.create_context(&default_descriptor, Some(&alternate_context)),
+        Err(Error::IncompatibleSharedContext)
+    ));
+    assert!(matches!(
+        default_device.make_context_current(&alternate_context),
+        Err(Error::IncompatibleContext)
+    ));
+
+    let surface_type = || SurfaceType::Generic {
+        size: Size2D::new(64, 64),
+    };
+    assert!(matches!(
+        default_device.create_surface(&alternate_context, SurfaceAccess::GPUCPU, surface_type()),
+        Err(Error::IncompatibleContext)
+    ));
+    let mut alternate_surface: MultiSurface<SWDevice, SWDevice> = alternate_device
+        .create_surface(&alternate_context, SurfaceAccess::GPUCPU, surface_type())
+        .unwrap();
+    assert!(matches!(
+        default_device.lock_surface_data(&mut alternate_surface),
+        Err(Error::IncompatibleSurface)
+    ));
+    assert!(matches!(
+        default_device.present_surface(&default_context, &mut alternate_surface),
+        Err(Error::IncompatibleSurface)
+    ));
+    assert!(matches!(
+        default_device.destroy_surface(&mut default_context, &mut alternate_surface),
+        Err(Error::IncompatibleSurface)
+    ));
+
+    let context_id = panic::catch_unwind(AssertUnwindSafe(|| {
+        default_device.context_id(&alternate_context)
+    }));
+    assert_eq!(panic_message(context_id), "Incompatible context!");
+    let attributes = panic::catch_unwind(AssertUnwindSafe(|| {
+        default_device.context_descriptor_attributes(&alternate_descriptor)
+    }));
+    assert_eq!(
+        panic_message(attributes),
+        "Incompatible context descriptor!"
+    );
+    let surface_info = panic::catch_unwind(AssertUnwindSafe(|| {
+        default_device.surface_info(&alternate_surface)
+    }));
+    assert_eq!(panic_message(surface_info), "Incompatible surface!");
+
+    alternate_device
+        .destroy_surface(&mut alternate_context, &mut alternate_surface)
+        .unwrap();
+    default_device
+        .destroy_context(&mut default_context)
+        .unwrap();
+    alternate_device
+        .destroy_context(&mut alternate_context)
+        .unwrap();
+}
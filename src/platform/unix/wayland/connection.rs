@@ -4,20 +4,29 @@
 
 use super::device::{Adapter, Device, NativeDevice};
 use super::surface::NativeWidget;
+use crate::acceleration;
 use crate::connection::NativeConnection as NativeConnectionInterface;
 use crate::egl;
-use crate::egl::types::{EGLAttrib, EGLDisplay};
+use crate::egl::types::EGLDisplay;
 use crate::info::GLApi;
-use crate::platform::generic::egl::device::EGL_FUNCTIONS;
+use crate::platform::generic::egl::device::{self, DisplayFunctions, EGL_FUNCTIONS};
 use crate::platform::generic::egl::ffi::EGL_PLATFORM_WAYLAND_KHR;
-use crate::Error;
+use crate::platform::unix::generic::connection::{self, EGLDisplayRef};
+use crate::thread_safety::ThreadBound;
+use crate::{AdapterKey, Error};
 
 use euclid::default::Size2D;
+use std::env;
+use std::ffi::{CString, OsStr};
 use std::os::raw::c_void;
+use std::os::unix::ffi::OsStrExt;
 use std::ptr;
 use std::sync::Arc;
 use wayland_sys::client::{wl_display, wl_proxy, WAYLAND_CLIENT_HANDLE};
 
+// The backend name in adapter keys.
+const ADAPTER_KEY_BACKEND: &str = "wayland";
+
 /// A connection to the Wayland server.
 #[derive(Clone)]
 pub struct Connection {
@@ -26,11 +35,24 @@ pub struct Connection {
 
 pub(crate) struct NativeConnectionWrapper {
     pub(crate) egl_display: EGLDisplay,
-    wayland_display: Option<*mut wl_display>,
+    egl_display_ref: Option<EGLDisplayRef>,
+    // The Wayland display, if known, which is disconnected on drop if `owns_wayland_display` is
+    // set.
+    pub(crate) wayland_display: Option<*mut wl_display>,
+    owns_wayland_display: bool,
+    pub(crate) headless: bool,
+    pub(crate) gl_api: GLApi,
 }
 
 /// An EGL display wrapping a Wayland display.
+///
+/// This is `Send` and `Sync`, since it only holds an EGL display and EGL is thread-safe. The
+/// display belongs to the `Connection` that returned it, and is only valid while that connection
+/// lives.
 pub struct NativeConnection(pub EGLDisplay);
+
+unsafe impl Send for NativeConnection {}
+unsafe impl Sync for NativeConnection {}
 impl NativeConnectionInterface for NativeConnection {
     fn egl_display(&self) -> EGLDisplay {
         self.0
@@ -39,25 +61,92 @@ impl NativeConnectionInterface for NativeConnection {
 
 unsafe impl Send for Connection {}
 
+// The Wayland display is only ever handed to EGL, which is thread-safe, and `libwayland-client`
+// is itself safe to call from multiple threads. Nothing else touches it until it's disconnected on
+// drop.
+unsafe impl Sync for Connection {}
+
+assert_send_sync!(Connection);
+assert_send_sync!(NativeConnection);
+
 impl Connection {
-    /// Connects to the default Wayland server.
+    /// Connects to the default Wayland server, as named by the `WAYLAND_DISPLAY` environment
+    /// variable.
+    ///
+    /// If the `SURFMAN_DISPLAY` environment variable is set, this connects to the socket it
+    /// names instead, as `from_wayland_socket()` does.
     #[inline]
     pub fn new() -> Result<Connection, Error> {
+        if let Some(socket_name) = env::var_os(connection::DISPLAY_ENV_VAR) {
+            return Connection::from_wayland_socket(&socket_name);
+        }
+
         unsafe {
             let wayland_display = (WAYLAND_CLIENT_HANDLE.wl_display_connect)(ptr::null());
             Connection::from_wayland_display(wayland_display, true)
         }
     }
 
+    /// Connects to the Wayland server listening on the given socket, such as `wayland-1`.
+    ///
+    /// The name is passed to `wl_display_connect()`, so it has the same format as the
+    /// `WAYLAND_DISPLAY` environment variable: relative names are looked up in
+    /// `XDG_RUNTIME_DIR`. Returns `Error::DisplayConnectionFailed` with the name if the server
+    /// can't be reached.
+    pub fn from_wayland_socket(socket_name: &OsStr) -> Result<Connection, Error> {
+        let connection_failed =
+            || Error::DisplayConnectionFailed(socket_name.to_string_lossy().into_owned());
+        let c_socket_name =
+            CString::new(socket_name.as_bytes()).map_err(|_| connection_failed())?;
+
+        unsafe {
+            let wayland_display =
+                (WAYLAND_CLIENT_HANDLE.wl_display_connect)(c_socket_name.as_ptr());
+            if wayland_display.is_null() {
+                return Err(connection_failed());
+            }
+            Connection::from_wayland_display(wayland_display, true)
+        }
+    }
+
+    /// Connects to the default Wayland server, creating contexts for the given OpenGL API flavor.
+    ///
+    /// Returns `Error::UnsupportedGLType` if the EGL implementation doesn't support that API.
+    pub fn new_with_api(gl_api: GLApi) -> Result<Connection, Error> {
+        let mut connection = Connection::new()?;
+        unsafe {
+            if !device::display_supports_api(connection.native_connection.egl_display, gl_api) {
+                return Err(Error::UnsupportedGLType);
+            }
+        }
+        Arc::get_mut(&mut connection.native_connection)
+            .unwrap()
+            .gl_api = gl_api;
+        Ok(connection)
+    }
+
+    /// Opens a connection without a Wayland compositor, using the surfaceless Mesa
+    /// platform or, failing that, the first EGL device.
+    ///
+    /// Only generic surfaces can be created on this connection. Attempting to create a widget
+    /// surface returns `Error::ConnectionRequired`.
+    pub fn new_headless() -> Result<Connection, Error> {
+        unsafe {
+            let egl_display_ref = connection::create_surfaceless_egl_display()?;
+            Connection::from_egl_display(egl_display_ref, None, false, true)
+        }
+    }
+
     /// Wraps an existing EGL display in a `Connection`.
     ///
     /// The display is not retained, as there is no way to do this in the EGL API. Therefore, it is
     /// the caller's responsibility to ensure that the EGL display remains alive as long as the
-    /// connection is.
+    /// connection is. The EGL display is never terminated.
     pub unsafe fn from_native_connection(
         native_connection: NativeConnection,
     ) -> Result<Connection, Error> {
-        Connection::from_egl_display(native_connection.0, None)
+        let egl_display_ref = EGLDisplayRef::borrow(native_connection.0);
+        Connection::from_egl_display(egl_display_ref, None, false, false)
     }
 
     /// Returns the underlying native connection.
@@ -69,7 +158,17 @@ impl Connection {
     /// Returns the OpenGL API flavor that this connection supports (OpenGL or OpenGL ES).
     #[inline]
     pub fn gl_api(&self) -> GLApi {
-        GLApi::GL
+        self.native_connection.gl_api
+    }
+
+    /// Returns true if EGL advertises the named extension, either for this connection's display
+    /// or as a client extension.
+    pub fn supports_extension(&self, name: &str) -> bool {
+        unsafe {
+            let egl_display = self.native_connection.egl_display;
+            device::display_supports_extension(egl_display, name)
+                || device::client_supports_extension(name)
+        }
     }
 
     /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
@@ -98,12 +197,58 @@ impl Connection {
         Ok(Adapter::software())
     }
 
+    /// Returns the adapter whose driver or device name contains `needle`, ignoring case.
+    ///
+    /// Mesa picks the GPU when the display is opened, so only that adapter can match. Requires
+    /// `EGL_EXT_device_query`; without it, this returns `Error::NoAdapterFound`.
+    #[inline]
+    pub fn create_adapter_by_name(&self, needle: &str) -> Result<Adapter, Error> {
+        unsafe { Adapter::from_display_name(self.native_connection.egl_display, needle) }
+    }
+
+    /// Returns a key that describes the adapter, which can be saved and passed to
+    /// `find_adapter()` in a later run to find the same adapter again.
+    #[inline]
+    pub fn adapter_key(&self, adapter: &Adapter) -> Result<AdapterKey, Error> {
+        unsafe { Ok(adapter.key(ADAPTER_KEY_BACKEND, self.native_connection.egl_display)) }
+    }
+
+    /// Returns the adapter that a key from `adapter_key()` describes.
+    ///
+    /// Mesa picks the GPU when the display is opened, so a hardware adapter is only found if the
+    /// display is on the same GPU as when the key was taken. Returns `Error::IncompatibleAdapter`
+    /// if the key was taken with another backend, and `Error::NoAdapterFound` if the GPU is gone.
+    #[inline]
+    pub fn find_adapter(&self, key: &AdapterKey) -> Result<Adapter, Error> {
+        unsafe { Adapter::find(ADAPTER_KEY_BACKEND, self.native_connection.egl_display, key) }
+    }
+
+    /// Returns true if the two adapters are the same adapter.
+    #[inline]
+    pub fn is_same_adapter(&self, a: &Adapter, b: &Adapter) -> bool {
+        match (self.adapter_key(a), self.adapter_key(b)) {
+            (Ok(a), Ok(b)) => a.matches(&b),
+            _ => false,
+        }
+    }
+
+    /// Returns the adapter for the GPU that drives the output the window is on.
+    ///
+    /// Wayland doesn't tell clients which GPU drives each output, so this always returns
+    /// `Error::UnsupportedOnThisPlatform`.
+    #[inline]
+    pub fn adapter_for_widget(&self, _: &NativeWidget) -> Result<Adapter, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
     /// Opens the hardware device corresponding to the given adapter.
     ///
     /// Device handles are local to a single thread.
     #[inline]
     pub fn create_device(&self, adapter: &Adapter) -> Result<Device, Error> {
-        Device::new(self, adapter)
+        let device = Device::new(self, adapter)?;
+        acceleration::check_device(&device);
+        Ok(device)
     }
 
     /// Opens the hardware device corresponding to the adapter wrapped in the given native
@@ -126,38 +271,50 @@ impl Connection {
             return Err(Error::ConnectionFailed);
         }
 
-        EGL_FUNCTIONS.with(|egl| {
-            let display_attributes = [egl::NONE as EGLAttrib];
-            let egl_display = egl.GetPlatformDisplay(
-                EGL_PLATFORM_WAYLAND_KHR,
-                wayland_display as *mut c_void,
-                display_attributes.as_ptr(),
-            );
-            if egl_display == egl::NO_DISPLAY {
-                return Err(Error::DeviceOpenFailed);
+        // Only connections that opened the Wayland display terminate the EGL display.
+        let egl_display_ref = EGLDisplayRef::open(
+            || {
+                let (egl_display, _) = device::get_platform_display(
+                    &DisplayFunctions::system(),
+                    EGL_PLATFORM_WAYLAND_KHR,
+                    wayland_display as *mut c_void,
+                )?;
+                Ok(egl_display)
+            },
+            is_owned,
+        );
+        match egl_display_ref {
+            Ok(egl_display_ref) => Connection::from_egl_display(
+                egl_display_ref,
+                Some(wayland_display),
+                is_owned,
+                false,
+            ),
+            // Don't leave the display connected when a multi connection falls back to its
+            // alternate backend.
+            Err(err) => {
+                if is_owned {
+                    (WAYLAND_CLIENT_HANDLE.wl_display_disconnect)(wayland_display);
+                }
+                Err(err)
             }
-
-            let (mut egl_major_version, mut egl_minor_version) = (0, 0);
-            let ok = egl.Initialize(egl_display, &mut egl_major_version, &mut egl_minor_version);
-            assert_ne!(ok, egl::FALSE);
-
-            let owned_display = if is_owned {
-                Some(wayland_display)
-            } else {
-                None
-            };
-            Connection::from_egl_display(egl_display, owned_display)
-        })
+        }
     }
 
     fn from_egl_display(
-        egl_display: EGLDisplay,
+        egl_display_ref: EGLDisplayRef,
         wayland_display: Option<*mut wl_display>,
+        owns_wayland_display: bool,
+        headless: bool,
     ) -> Result<Connection, Error> {
         Ok(Connection {
             native_connection: Arc::new(NativeConnectionWrapper {
-                egl_display,
+                egl_display: egl_display_ref.egl_display(),
+                egl_display_ref: Some(egl_display_ref),
                 wayland_display,
+                owns_wayland_display,
+                headless,
+                gl_api: GLApi::GL,
             }),
         })
     }
@@ -197,18 +354,27 @@ impl Connection {
     }
 
     /// Create a native widget from a raw pointer
+    ///
+    /// The widget is bound to the calling thread, which must be the one that owns the
+    /// `wl_surface`: it can be sent elsewhere, but only this thread can create a surface from it.
+    ///
+    /// Wayland can't report the size of a surface, so `size` is trusted, and it must be positive.
+    /// Creating a surface from a widget with an empty size fails with
+    /// `Error::InvalidNativeWidget`.
     pub unsafe fn create_native_widget_from_ptr(
         &self,
         raw: *mut c_void,
         size: Size2D<i32>,
     ) -> NativeWidget {
         NativeWidget {
-            wayland_surface: raw as *mut wl_proxy,
+            wayland_surface: ThreadBound::new(raw as *mut wl_proxy),
             size,
         }
     }
 
     /// Creates a native widget type from the given `RawWindowHandle`
+    ///
+    /// As with `create_native_widget_from_ptr()`, the widget is bound to the calling thread.
     #[cfg(feature = "sm-raw-window-handle-05")]
     pub fn create_native_widget_from_raw_window_handle(
         &self,
@@ -221,14 +387,19 @@ impl Connection {
             Wayland(handle) => handle.surface as *mut wl_proxy,
             _ => return Err(Error::IncompatibleNativeWidget),
         };
+        if window_size.is_empty() {
+            return Err(Error::InvalidNativeWidget);
+        }
 
         Ok(NativeWidget {
-            wayland_surface,
+            wayland_surface: ThreadBound::new(wayland_surface),
             size: window_size,
         })
     }
 
     /// Creates a native widget type from the given `WindowHandle`
+    ///
+    /// As with `create_native_widget_from_ptr()`, the widget is bound to the calling thread.
     #[cfg(feature = "sm-raw-window-handle-06")]
     pub fn create_native_widget_from_window_handle(
         &self,
@@ -241,19 +412,36 @@ impl Connection {
             Wayland(handle) => handle.surface.as_ptr() as *mut wl_proxy,
             _ => return Err(Error::IncompatibleNativeWidget),
         };
+        if window_size.is_empty() {
+            return Err(Error::InvalidNativeWidget);
+        }
 
         Ok(NativeWidget {
-            wayland_surface,
+            wayland_surface: ThreadBound::new(wayland_surface),
             size: window_size,
         })
     }
+
+    /// Returns the real size of a native widget if it differs from the size that the widget was
+    /// created with.
+    ///
+    /// Wayland can't report the size of a surface, so this always returns `None`.
+    #[inline]
+    pub fn native_widget_adjusted_size(&self, _: &NativeWidget) -> Option<Size2D<i32>> {
+        None
+    }
 }
 
 impl Drop for NativeConnectionWrapper {
     fn drop(&mut self) {
         unsafe {
-            if let Some(wayland_display) = self.wayland_display {
-                (WAYLAND_CLIENT_HANDLE.wl_display_disconnect)(wayland_display);
+            // Terminate EGL before disconnecting the Wayland display that it was opened on.
+            self.egl_display_ref.take();
+            match self.wayland_display {
+                Some(wayland_display) if self.owns_wayland_display => {
+                    (WAYLAND_CLIENT_HANDLE.wl_display_disconnect)(wayland_display);
+                }
+                _ => {}
             }
         }
     }
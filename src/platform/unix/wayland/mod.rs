@@ -9,7 +9,3 @@ pub mod surface;
 
 #[path = "../../../implementation/mod.rs"]
 mod implementation;
-
-#[cfg(test)]
-#[path = "../../../tests.rs"]
-mod tests;
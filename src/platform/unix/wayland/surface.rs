@@ -4,16 +4,57 @@
 
 use super::context::{Context, GL_FUNCTIONS};
 use super::device::Device;
+use crate::bulk;
+use crate::clear::ClearRegion;
+use crate::convert::{self, Swizzle};
 use crate::gl;
 use crate::gl::types::{GLenum, GLuint};
 use crate::platform::generic::egl::context;
-use crate::platform::generic::egl::surface::{EGLBackedSurface, EGLSurfaceTexture};
-use crate::{Error, SurfaceAccess, SurfaceInfo, SurfaceType};
+use crate::platform::generic::egl::surface::ReleaseSignal;
+use crate::platform::generic::egl::surface::{ColorProfileSource, PresentationFeedback};
+use crate::platform::generic::egl::surface::{EGLBackedSurface, EGLFence, EGLSurfaceTexture};
+use crate::presentation_time;
+use crate::readback;
+use crate::surface::{check_surface_size, clip_damage};
+use crate::thread_safety::ThreadBound;
+use crate::upload;
+use crate::vsync::AutoVsync;
+use crate::BufferRelease;
+use crate::DmaBuf;
+use crate::ColorProfileChangedHandler;
+use crate::GLCapabilities;
+use crate::InvalidateSet;
+use crate::LowMemoryOptions;
+use crate::PausedPresentation;
+use crate::PresentationStatistics;
+use crate::SurfaceFence;
+use crate::SurfaceOptions;
+use crate::SurfaceResizedHandler;
+use crate::SurfaceScaleChangedHandler;
+use crate::TextureOwnership;
+use crate::{AutoVsyncPolicy, AutoVsyncStats};
+use crate::{
+    ColorProfile, ColorSpace, ContextID, Error, PresentRecord, SurfaceAccess, SurfaceInfo,
+    SurfaceType,
+};
+use crate::{Purgeability, ReclaimResult, WindowingApiError};
+use crate::{SurfaceConfig, SurfaceIntent, SurfaceTransform};
 
-use euclid::default::Size2D;
+use euclid::default::{Rect, Size2D};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::ffi::CStr;
+use std::fs::File;
+use std::io;
 use std::marker::PhantomData;
-use std::os::raw::c_void;
-use wayland_sys::client::wl_proxy;
+use std::mem;
+use std::os::fd::{BorrowedFd, FromRawFd, OwnedFd};
+use std::os::raw::{c_char, c_void};
+use std::os::unix::fs::FileExt;
+use std::ptr;
+use std::time::{Duration, Instant};
+use wayland_sys::client::{wl_display, wl_event_queue, wl_proxy, WAYLAND_CLIENT_HANDLE};
+use wayland_sys::common::{wl_argument, wl_interface, wl_message};
 use wayland_sys::egl::{wl_egl_window, WAYLAND_EGL_HANDLE};
 
 // FIXME(pcwalton): Is this right, or should it be `TEXTURE_EXTERNAL_OES`?
@@ -51,12 +92,18 @@ pub struct Surface(pub(crate) EGLBackedSurface);
 pub struct SurfaceTexture(pub(crate) EGLSurfaceTexture);
 
 /// A wrapper for a Wayland surface, with associated size.
+///
+/// Widgets are `Send` and `Sync`, so they can be handed to a rendering thread, but the surface
+/// proxy belongs to the thread that created the widget, and only that thread can create a
+/// surface from it. Creating one on another thread returns `Error::WrongThread`.
 #[derive(Clone)]
 pub struct NativeWidget {
-    pub(crate) wayland_surface: *mut wl_proxy,
+    pub(crate) wayland_surface: ThreadBound<*mut wl_proxy>,
     pub(crate) size: Size2D<i32>,
 }
 
+assert_send_sync!(NativeWidget);
+
 unsafe impl Send for Surface {}
 
 impl Device {
@@ -67,29 +114,80 @@ impl Device {
     pub fn create_surface(
         &mut self,
         context: &Context,
-        _: SurfaceAccess,
+        access: SurfaceAccess,
+        surface_type: SurfaceType<NativeWidget>,
+    ) -> Result<Surface, Error> {
+        self.create_surface_with_options(context, access, surface_type, SurfaceOptions::default())
+    }
+
+    /// Creates a surface of each of the given types, in order.
+    ///
+    /// The device's cancellation token is checked before each surface, as well as inside each
+    /// creation. If any surface fails or is cancelled, the ones created so far are destroyed and
+    /// the error is returned, so the batch creates either all of its surfaces or none.
+    pub fn create_surfaces<I>(
+        &mut self,
+        context: &mut Context,
+        access: SurfaceAccess,
+        surface_types: I,
+    ) -> Result<Vec<Surface>, Error>
+    where
+        I: IntoIterator<Item = SurfaceType<NativeWidget>>,
+    {
+        bulk::create_surfaces(self, context, access, surface_types)
+    }
+
+    /// Creates either a generic or a widget surface, with options such as its colorspace.
+    ///
+    /// `create_surface()` is this method with the default options. Returns
+    /// `Error::UnsupportedSurfaceFormat` if the platform can't create a surface with the options.
+    pub fn create_surface_with_options(
+        &mut self,
+        context: &Context,
+        access: SurfaceAccess,
         surface_type: SurfaceType<NativeWidget>,
+        options: SurfaceOptions,
     ) -> Result<Surface, Error> {
-        match surface_type {
-            SurfaceType::Generic { size } => self.create_generic_surface(context, &size),
+        let mut surface = match surface_type {
+            SurfaceType::Generic { size } => {
+                self.create_generic_surface(context, &size, access, options)
+            }
+            SurfaceType::Widget { .. } if self.native_connection.headless => {
+                Err(Error::ConnectionRequired)
+            }
+            SurfaceType::Widget { native_widget } if native_widget.size.is_empty() => {
+                Err(Error::InvalidNativeWidget)
+            }
             SurfaceType::Widget { native_widget } => unsafe {
                 self.create_window_surface(
                     context,
-                    native_widget.wayland_surface,
+                    native_widget.wayland_surface.get()?,
                     &native_widget.size,
+                    access,
+                    options,
                 )
             },
-        }
+        }?;
+        self.debug_fill_new_surface(context, &mut surface);
+        Ok(surface)
     }
 
     fn create_generic_surface(
         &mut self,
         context: &Context,
         size: &Size2D<i32>,
+        access: SurfaceAccess,
+        options: SurfaceOptions,
     ) -> Result<Surface, Error> {
-        let _guard = self.temporarily_make_context_current(context)?;
+        let _guard = self.make_context_current_guarded(context)?;
         let context_descriptor = self.context_descriptor(context);
         let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        let linear_format =
+            LowMemoryOptions::surface_format(self.low_memory_options, &context_attributes, access);
+        let capabilities = GL_FUNCTIONS.with(GLCapabilities::current);
+        check_surface_size(*size, &capabilities)?;
+        let format = options.generic_surface_format(&capabilities, linear_format)?;
+        self.cancel_token.check()?;
         GL_FUNCTIONS.with(|gl| {
             Ok(Surface(EGLBackedSurface::new_generic(
                 gl,
@@ -98,6 +196,8 @@ impl Device {
                 context.0.id,
                 &context_attributes,
                 size,
+                access,
+                format,
             )))
         })
     }
@@ -107,24 +207,73 @@ impl Device {
         context: &Context,
         wayland_surface: *mut wl_proxy,
         size: &Size2D<i32>,
+        access: SurfaceAccess,
+        options: SurfaceOptions,
     ) -> Result<Surface, Error> {
+        self.cancel_token.check()?;
+        let context_descriptor = self.context_descriptor(context);
+        options.check_widget_format(&self.context_descriptor_attributes(&context_descriptor))?;
         let egl_window =
             (WAYLAND_EGL_HANDLE.wl_egl_window_create)(wayland_surface, size.width, size.height);
         assert!(!egl_window.is_null());
 
-        let context_descriptor = self.context_descriptor(context);
-        let egl_config = context::egl_config_from_id(
+        let context_config = context::egl_config_from_id(
             self.native_connection.egl_display,
             context_descriptor.egl_config_id,
         );
+        let egl_config = match context::widget_config(
+            self.native_connection.egl_display,
+            context_config,
+            &options,
+            None,
+            &|_| None,
+        ) {
+            Ok(egl_config) => egl_config,
+            Err(err) => {
+                (WAYLAND_EGL_HANDLE.wl_egl_window_destroy)(egl_window);
+                return Err(err);
+            }
+        };
 
-        Ok(Surface(EGLBackedSurface::new_window(
+        let mut surface = EGLBackedSurface::new_window(
             self.native_connection.egl_display,
             egl_config,
             egl_window as *mut c_void,
             context.0.id,
             size,
-        )))
+            access,
+            &options,
+        )
+        .inspect_err(|_| (WAYLAND_EGL_HANDLE.wl_egl_window_destroy)(egl_window))?;
+
+        // Without the Wayland display, releases are tracked with fences instead.
+        if let Some(wayland_display) = self.native_connection.wayland_display {
+            surface.release_signal = FrameCallbacks::new(wayland_display, wayland_surface)
+                .map(|frame_callbacks| Box::new(frame_callbacks) as Box<dyn ReleaseSignal>);
+            // EGL's own frame timestamps, where it has them, report more than the compositor.
+            if surface.presentation_feedback.is_none() {
+                surface.presentation_feedback =
+                    PresentationFeedbacks::new(wayland_display, wayland_surface)
+                        .map(|feedbacks| Box::new(feedbacks) as Box<dyn PresentationFeedback>);
+            }
+            surface.color_profile_source = ColorManagement::new(wayland_display, wayland_surface)
+                .map(|color_management| Box::new(color_management) as Box<dyn ColorProfileSource>);
+        }
+        Ok(Surface(surface))
+    }
+
+    /// Returns the access mode, type, and options that suit a surface of the given size, created
+    /// with the given context for the given use, on this device, along with hints for using it.
+    ///
+    /// This backend can't map surfaces for the CPU, so the access mode is always `GPUOnly`.
+    pub fn recommended_surface_config(
+        &self,
+        context: &Context,
+        intent: SurfaceIntent<NativeWidget>,
+        size: Size2D<i32>,
+    ) -> Result<SurfaceConfig<NativeWidget>, Error> {
+        let capabilities = self.capabilities(context)?;
+        Ok(SurfaceConfig::recommend(intent, size, false, &capabilities))
     }
 
     /// Creates a surface texture from an existing generic surface for use with the given context.
@@ -142,7 +291,7 @@ impl Device {
         context: &mut Context,
         surface: Surface,
     ) -> Result<SurfaceTexture, (Error, Surface)> {
-        let _guard = match self.temporarily_make_context_current(context) {
+        let _guard = match self.make_context_current_guarded(context) {
             Ok(guard) => guard,
             Err(err) => return Err((err, surface)),
         };
@@ -172,7 +321,7 @@ impl Device {
     ) -> Result<SurfaceTexture, Error> {
         let context_descriptor = self.context_descriptor(context);
         let context_attributes = self.context_descriptor_attributes(&context_descriptor);
-        let _guard = match self.temporarily_make_context_current(context) {
+        let _guard = match self.make_context_current_guarded(context) {
             Ok(guard) => guard,
             Err(err) => return Err(err),
         };
@@ -185,8 +334,10 @@ impl Device {
                 context.0.id,
                 &context_attributes,
                 size,
+                SurfaceAccess::GPUOnly,
                 texture_object,
                 egl_target,
+                TextureOwnership::Owned,
             )
         });
         Ok(SurfaceTexture(EGLSurfaceTexture {
@@ -196,6 +347,45 @@ impl Device {
         }))
     }
 
+    /// Wraps an existing GL texture in a generic surface, without copying it.
+    ///
+    /// The texture must be a complete `TEXTURE_2D` of the given size and format, created in the
+    /// supplied context or in a context that shares with it. Only `GL_RGBA8` (or unsized
+    /// `GL_RGBA`) textures are supported. The surface can be rendered to by the supplied context
+    /// and wrapped in a `SurfaceTexture` by other contexts, like any other generic surface.
+    ///
+    /// With `TextureOwnership::Borrowed`, the texture is never deleted by surfman, and must stay
+    /// alive and unmodified in size and format until the surface is destroyed. With
+    /// `TextureOwnership::Owned`, `destroy_surface()` deletes the texture.
+    ///
+    /// Returns `IncompatibleTexture` if the texture doesn't exist in the supplied context.
+    pub fn create_surface_from_texture(
+        &mut self,
+        context: &mut Context,
+        texture_object: GLuint,
+        size: &Size2D<i32>,
+        format: GLenum,
+        ownership: TextureOwnership,
+    ) -> Result<Surface, Error> {
+        let _guard = self.make_context_current_guarded(context)?;
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        GL_FUNCTIONS.with(|gl| {
+            Ok(Surface(EGLBackedSurface::new_from_foreign_texture(
+                gl,
+                self.native_connection.egl_display,
+                context.0.egl_context,
+                context.0.id,
+                &context_attributes,
+                size,
+                SurfaceAccess::GPUOnly,
+                texture_object,
+                format,
+                ownership,
+            )?))
+        })
+    }
+
     /// Destroys a surface.
     ///
     /// The supplied context must be the context the surface is associated with, or this returns
@@ -208,6 +398,7 @@ impl Device {
         context: &mut Context,
         surface: &mut Surface,
     ) -> Result<(), Error> {
+        let _guard = self.make_context_current_guarded(context);
         GL_FUNCTIONS.with(|gl| {
             let egl_display = self.native_connection.egl_display;
             if let Some(wayland_egl_window) = surface.0.destroy(gl, egl_display, context.0.id)? {
@@ -232,7 +423,7 @@ impl Device {
         context: &mut Context,
         surface_texture: SurfaceTexture,
     ) -> Result<Surface, (Error, SurfaceTexture)> {
-        match self.temporarily_make_context_current(context) {
+        match self.make_context_current_guarded(context) {
             Ok(_guard) => GL_FUNCTIONS.with(|gl| Ok(Surface(surface_texture.0.destroy(gl)))),
             Err(err) => Err((err, surface_texture)),
         }
@@ -244,40 +435,536 @@ impl Device {
     /// associated widgets until this method is called.
     ///
     /// The supplied context must match the context the surface was created with, or an
-    /// `IncompatibleSurface` error is returned.
+    /// `IncompatibleSurface` error is returned. The context doesn't need to be current, and the
+    /// context that was current before this call remains current afterward.
+    ///
+    /// If a toolkit resized the widget's `wl_egl_window` itself, this notices once a frame drawn
+    /// at the new size has been presented: the surface takes on that size, the frame's
+    /// `PresentRecord` is marked `resized_externally`, and the handler registered with
+    /// `set_surface_resized_handler()` is called.
     pub fn present_surface(&self, context: &Context, surface: &mut Surface) -> Result<(), Error> {
-        surface
-            .0
-            .present(self.native_connection.egl_display, context.0.egl_context)
+        self.present(context, surface, None)
+    }
+
+    /// Displays the changed parts of a widget surface on screen.
+    ///
+    /// `damage` lists the rectangles that changed since the last present, measured from the
+    /// bottom left as in OpenGL, and is passed on with `EGL_KHR_swap_buffers_with_damage` where
+    /// the display supports it. Mesa turns it into `wl_surface.damage_buffer` requests, so that
+    /// the compositor only has to recomposite those rectangles. Otherwise, the whole surface is
+    /// swapped, as `present_surface()` does. An empty list means that nothing changed, and skips
+    /// the swap entirely.
+    ///
+    /// Externally resized widgets are noticed as with `present_surface()`. Rectangles are clipped
+    /// to the surface, and those wholly outside it are dropped. If the frame was drawn at a size
+    /// that the toolkit gave the `wl_egl_window` behind surfman's back, the rectangles, which were
+    /// measured against the old size, are ignored and the whole frame is presented.
+    pub fn present_surface_with_damage(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        self.present(context, surface, Some(damage))
     }
 
-    /// Resizes a widget surface.
+    fn present(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: Option<&[Rect<i32>]>,
+    ) -> Result<(), Error> {
+        let egl_display = self.native_connection.egl_display;
+        let old_attached_size = attached_size(surface);
+        let clipped_damage;
+        let damage = match (damage, surface.0.window_size(egl_display)) {
+            (Some(damage), Some(frame_size)) if !damage.is_empty() => {
+                clipped_damage = clip_damage(damage, surface.0.size, frame_size);
+                clipped_damage.as_deref()
+            }
+            (damage, _) => damage,
+        };
+        self.lost_contexts.check(context.0.id, || {
+            GL_FUNCTIONS.with(|gl| {
+                let egl_context = context.0.egl_context;
+                surface
+                    .0
+                    .present(gl, egl_display, egl_context, context.0.id, damage)
+            })
+        })?;
+
+        // The attached size only changes when a frame drawn at a new size is presented. A change
+        // to a size that surfman didn't ask for means that the window was resized externally.
+        let new_attached_size = attached_size(surface);
+        if let Some(size) = new_attached_size {
+            if new_attached_size != old_attached_size && size != surface.0.size {
+                surface.0.size = size;
+                surface.0.present_history.mark_resized_externally();
+                if let Some(ref handler) = self.surface_resized_handler {
+                    handler(surface.0.id(), size);
+                }
+            }
+        }
+        self.update_color_profile(surface);
+        Ok(())
+    }
+
+    // Reports a change in the color profile of the output that a widget surface is displayed
+    // on, if one is noticed on this present.
+    fn update_color_profile(&self, surface: &mut Surface) {
+        let handler = match self.color_profile_changed_handler {
+            Some(ref handler) if surface.0.is_widget() => handler,
+            _ => return,
+        };
+        let mut tracker = mem::take(&mut surface.0.color_profile);
+        let changed = tracker.update(|| self.widget_color_profile(surface));
+        surface.0.color_profile = tracker;
+        if let Some(profile) = changed {
+            handler(surface.0.id(), &profile);
+        }
+    }
+
+    /// Pauses the presentation of a widget surface: until it's resumed, each frame presented is
+    /// read back and held instead of shown, and `present_surface()` returns `PresentationPaused`.
+    ///
+    /// Surfaces that render to the front buffer return `UnsupportedPresentMode`, and contexts
+    /// without `glBlitFramebuffer()` return `RequiredExtensionUnavailable`.
+    pub fn pause_presentation(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<PausedPresentation, Error> {
+        let capabilities = self.capabilities(context)?;
+        surface.0.pause_presentation(context.0.id, &capabilities)
+    }
+
+    /// Ends a pause in the presentation of a widget surface.
+    ///
+    /// If `present_pending` is true, the frame held during the pause is drawn back into the
+    /// surface and presented as by `present_surface()`; otherwise, it's discarded.
+    pub fn resume_presentation(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        paused: PausedPresentation,
+        present_pending: bool,
+    ) -> Result<(), Error> {
+        let egl_display = self.native_connection.egl_display;
+        let present = self.lost_contexts.check(context.0.id, || {
+            GL_FUNCTIONS.with(|gl| {
+                let egl_context = context.0.egl_context;
+                surface.0.resume_presentation(
+                    gl,
+                    egl_display,
+                    egl_context,
+                    context.0.id,
+                    paused,
+                    present_pending,
+                )
+            })
+        })?;
+        if present {
+            self.present_surface(context, surface)?;
+        }
+        Ok(())
+    }
+
+    /// Displays the contents of a widget surface on screen, and returns a fence that signals once
+    /// the GPU has finished rendering it.
+    ///
+    /// The fence is an `EGL_SYNC_FENCE`, or a native fence where the display supports
+    /// `EGL_ANDROID_native_fence_sync`, inserted with the surface's context current right after
+    /// its buffers are swapped. Generic surfaces aren't presented, and only get the fence.
+    pub fn present_surface_with_fence(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<SurfaceFence, Error> {
+        if context.0.id != surface.0.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if surface.0.is_widget() {
+            self.present_surface(context, surface)?;
+        }
+        let egl_display = self.native_connection.egl_display;
+        let _guard = self.make_context_current_guarded(context)?;
+        let egl_fence = GL_FUNCTIONS.with(|gl| unsafe { EGLFence::insert(gl, egl_display) })?;
+        Ok(SurfaceFence { egl_fence })
+    }
+
+    /// Makes the context wait on the GPU until the fence signals, with `eglWaitSync()`.
+    pub fn wait_fence(&self, context: &Context, fence: &SurfaceFence) -> Result<(), Error> {
+        let _guard = self.make_context_current_guarded(context)?;
+        unsafe { fence.egl_fence.wait(self.native_connection.egl_display) }
+    }
+
+    /// Returns a native fence that signals when the sync file `fd` does, which needs
+    /// `EGL_ANDROID_native_fence_sync`. The descriptor is duplicated, so it stays the caller's.
+    pub fn import_sync_fd(&self, context: &Context, fd: BorrowedFd) -> Result<SurfaceFence, Error> {
+        let _guard = self.make_context_current_guarded(context)?;
+        let egl_fence = unsafe { EGLFence::import(self.native_connection.egl_display, fd)? };
+        Ok(SurfaceFence { egl_fence })
+    }
+
+    /// Resizes a surface in place.
+    ///
+    /// Generic surfaces are reallocated at the new size, keeping their framebuffer objects.
+    /// Widget surfaces resize their `wl_egl_window`, which takes effect with the next frame.
     pub fn resize_surface(
         &self,
-        _context: &Context,
+        context: &Context,
         surface: &mut Surface,
         size: Size2D<i32>,
     ) -> Result<(), Error> {
-        let wayland_egl_window = surface.0.native_window()? as *mut c_void as *mut wl_egl_window;
-        unsafe {
-            (WAYLAND_EGL_HANDLE.wl_egl_window_resize)(
-                wayland_egl_window,
-                size.width,
-                size.height,
-                0,
-                0,
-            )
-        };
-        surface.0.size = size;
+        check_surface_size(size, &self.capabilities(context)?)?;
+        let _guard = self.make_context_current_guarded(context)?;
+        GL_FUNCTIONS.with(|gl| {
+            let egl_display = self.native_connection.egl_display;
+            surface
+                .0
+                .resize(gl, egl_display, context.0.egl_context, context.0.id, size)
+        })?;
+        if let Ok(native_window) = surface.0.native_window() {
+            let wayland_egl_window = native_window as *mut c_void as *mut wl_egl_window;
+            unsafe {
+                (WAYLAND_EGL_HANDLE.wl_egl_window_resize)(
+                    wayland_egl_window,
+                    size.width,
+                    size.height,
+                    0,
+                    0,
+                )
+            };
+        }
         Ok(())
     }
 
+    /// Sets the number of vertical blanks that `present_surface()` waits for before showing each
+    /// frame on a widget surface, and returns the interval that took effect.
+    ///
+    /// Negative intervals are only honored if the surface's EGL config allows them, and are
+    /// otherwise replaced with their absolute value. Intervals outside the range that the config
+    /// supports return `UnsupportedOnThisPlatform`.
+    pub fn set_swap_interval(
+        &mut self,
+        context: &Context,
+        surface: &Surface,
+        interval: i32,
+    ) -> Result<i32, Error> {
+        let egl_display = self.native_connection.egl_display;
+        surface
+            .0
+            .set_swap_interval(egl_display, context.0.egl_context, context.0.id, interval)
+    }
+
+    /// Returns true if the EGL config of a widget surface allows the negative swap intervals of
+    /// adaptive vsync.
+    #[inline]
+    pub fn supports_adaptive_swap(&self, surface: &Surface) -> bool {
+        surface
+            .0
+            .supports_adaptive_swap(self.native_connection.egl_display)
+    }
+
+    /// Lets surfman switch a widget surface between vsync and immediate presentation by itself,
+    /// following `policy`, or stops it with `None`.
+    ///
+    /// The frame time is measured from the return of `eglSwapBuffers()`.
+    pub fn set_auto_swap_interval(
+        &mut self,
+        context: &Context,
+        surface: &mut Surface,
+        policy: Option<AutoVsyncPolicy>,
+    ) -> Result<(), Error> {
+        let egl_display = self.native_connection.egl_display;
+        surface
+            .0
+            .set_auto_swap_interval(egl_display, context.0.egl_context, context.0.id, policy)
+    }
+
+    /// Returns what automatic vsync has decided for a surface, or `None` if it isn't enabled.
+    #[inline]
+    pub fn auto_swap_interval_stats(&self, surface: &Surface) -> Option<AutoVsyncStats> {
+        surface.0.auto_vsync.as_ref().map(AutoVsync::stats)
+    }
+
+    /// Waits until the buffer of the last frame presented on a widget surface has been released,
+    /// or until `timeout` elapses, and reports whether the call had to block.
+    ///
+    /// EGL keeps its `wl_buffer`s to itself, so this waits for a `wl_surface.frame` callback
+    /// requested with the frame instead. The compositor sends it once it starts showing the
+    /// frame, at which point it no longer reads the buffer of the frame before. Widgets of
+    /// connections made from an EGL display, whose Wayland display is unknown, wait on a fence
+    /// inserted when the frame was presented, which only signals that the GPU has finished
+    /// rendering it.
+    pub fn wait_previous_buffer_released(
+        &self,
+        surface: &Surface,
+        timeout: Duration,
+    ) -> Result<BufferRelease, Error> {
+        surface.0.wait_previous_buffer_released(timeout)
+    }
+
+    /// Chooses which attachments of a surface are invalidated right after each present.
+    ///
+    /// The color buffer of a widget surface can only be invalidated if its EGL surface doesn't
+    /// preserve it across swaps, and that of a generic surface never can. Either returns
+    /// `PreservedColorBuffer`.
+    pub fn set_post_present_invalidation(
+        &self,
+        surface: &mut Surface,
+        set: InvalidateSet,
+    ) -> Result<(), Error> {
+        surface
+            .0
+            .set_post_present_invalidation(self.native_connection.egl_display, set)
+    }
+
+    /// Returns which attachments of a surface are invalidated right after each present.
+    #[inline]
+    pub fn post_present_invalidation(&self, surface: &Surface) -> InvalidateSet {
+        surface.0.invalidation.set()
+    }
+
+    /// Invalidates the attachments of a generic surface that were chosen with
+    /// `set_post_present_invalidation()`, making the context current for the call.
+    ///
+    /// Widget surfaces are invalidated by `present_surface()`, and return `WidgetAttached`.
+    pub fn invalidate_surface(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        let _guard = self.make_context_current_guarded(context)?;
+        GL_FUNCTIONS.with(|gl| surface.0.invalidate(gl, context.0.id))
+    }
+
+    /// Clears a region of a surface, measured from its top left, to `color`, and its depth and
+    /// stencil buffers to `depth` and `stencil` if they're given and the surface has them.
+    ///
+    /// Widget surfaces are made current with the context for the call; generic surfaces are
+    /// cleared through their framebuffer object. Either way, the state that the clear changes is
+    /// put back afterward.
+    pub fn clear_surface_region(
+        &self,
+        context: &mut Context,
+        surface: &mut Surface,
+        rect: Option<Rect<i32>>,
+        color: [f32; 4],
+        depth: Option<f32>,
+        stencil: Option<i32>,
+    ) -> Result<(), Error> {
+        let region = ClearRegion::new(rect, color, depth, stencil);
+        self.clear_surface_with(context, surface, &region)
+    }
+
+    /// Fills a surface with this device's debug fill, as if it had just been created, or does
+    /// nothing if the fill is `DebugFill::None`.
+    pub fn debug_fill_surface(
+        &self,
+        context: &mut Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        match self.debug_fill.region() {
+            Some(region) => self.clear_surface_with(context, surface, &region),
+            None => Ok(()),
+        }
+    }
+
+    // Fills a new surface with the debug fill, if there is one. The surface is usable either way,
+    // so failing to fill it is only logged.
+    fn debug_fill_new_surface(&self, context: &Context, surface: &mut Surface) {
+        if let Some(region) = self.debug_fill.region() {
+            if let Err(err) = self.clear_surface_with(context, surface, &region) {
+                warn!(
+                    "Failed to fill a new surface with the debug fill: {:?}",
+                    err
+                );
+            }
+        }
+    }
+
+    fn clear_surface_with(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        region: &ClearRegion,
+    ) -> Result<(), Error> {
+        let _guard = self.make_context_current_guarded(context)?;
+        GL_FUNCTIONS.with(|gl| {
+            surface.0.clear_region(
+                gl,
+                self.native_connection.egl_display,
+                context.0.egl_context,
+                context.0.id,
+                region,
+            )
+        })
+    }
+
+    /// Returns how many frames ago the back buffer of a widget surface was last presented, or 0
+    /// if its contents are undefined.
+    ///
+    /// The age is queried with `EGL_EXT_buffer_age` right after each swap, while the surface is
+    /// still current and nothing has been drawn into it, so it's meant to be read after
+    /// `present_surface()` and before drawing the next frame.
+    #[inline]
+    pub fn surface_buffer_age(&self, surface: &Surface) -> Result<u32, Error> {
+        surface.0.buffer_age()
+    }
+
+    /// Returns the size of the buffer last attached to a widget surface's `wl_egl_window`, or the
+    /// surface's size if nothing has been presented yet.
+    ///
+    /// Wayland windows have no size of their own: each buffer that the client attaches sets it.
+    pub fn query_surface_native_size(&self, surface: &Surface) -> Result<Size2D<i32>, Error> {
+        surface.0.native_window()?;
+        Ok(attached_size(surface).unwrap_or(surface.0.size))
+    }
+
+    /// Returns the rotation that the window system applies to a widget surface's frames to show
+    /// them on its display.
+    ///
+    /// Wayland compositors only report the transforms of their outputs to the clients that
+    /// bind them, which surfman doesn't, so this is always `SurfaceTransform::Identity`.
+    #[inline]
+    pub fn surface_transform(&self, _surface: &Surface) -> SurfaceTransform {
+        SurfaceTransform::Identity
+    }
+
+    /// Registers a function to call with the ID and new size of each widget surface of this
+    /// device that is found to have been resized behind surfman's back, replacing any previous
+    /// one, or unregisters it if `handler` is `None`.
+    ///
+    /// See `present_surface()` for when such resizes are noticed.
+    #[inline]
+    pub fn set_surface_resized_handler(&mut self, handler: Option<SurfaceResizedHandler>) {
+        self.surface_resized_handler = handler;
+    }
+
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to have moved to a display with a different scale factor.
+    ///
+    /// Wayland compositors send a surface's preferred buffer scale to whoever listens to the
+    /// `wl_surface`, which is the toolkit that created it, and a `wl_surface` can only have one
+    /// listener, so surfman never hears of scale changes. Registering a handler therefore returns
+    /// `UnsupportedOnThisPlatform`; unregistering one succeeds. The toolkit resizes the
+    /// `wl_egl_window` to suit the new scale, which `set_surface_resized_handler()` reports.
+    #[inline]
+    pub fn set_surface_scale_changed_handler(
+        &mut self,
+        handler: Option<SurfaceScaleChangedHandler>,
+    ) -> Result<(), Error> {
+        match handler {
+            Some(_) => Err(Error::UnsupportedOnThisPlatform),
+            None => Ok(()),
+        }
+    }
+
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to be displayed with a different color profile, with the new profile, replacing any
+    /// previous one, or unregisters it if `handler` is `None`.
+    ///
+    /// Every 30 presents, `present_surface()` asks for the profile again, as
+    /// `widget_color_profile()` does, and compares it with the profile that it last saw. Without
+    /// the `wp_color_management_v1` protocol, the profile never changes.
+    #[inline]
+    pub fn set_color_profile_changed_handler(
+        &mut self,
+        handler: Option<ColorProfileChangedHandler>,
+    ) {
+        self.color_profile_changed_handler = handler;
+    }
+
+    /// Sets whether the system may discard the contents of a generic surface to free memory.
+    ///
+    /// Mesa gives no way to offer a surface's memory back to the system, so volatile surfaces stay
+    /// resident and are never discarded. The state is still enforced: a volatile surface must be
+    /// taken back with `reclaim_surface()` before it's used again.
+    pub fn set_surface_purgeable(
+        &self,
+        surface: &mut Surface,
+        purgeability: Purgeability,
+    ) -> Result<(), Error> {
+        surface.0.set_purgeable(purgeability)
+    }
+
+    /// Makes a surface non-purgeable again, and reports whether the system discarded its contents
+    /// since it was made volatile.
+    ///
+    /// On this backend, the contents are always intact.
+    pub fn reclaim_surface(&self, surface: &mut Surface) -> Result<ReclaimResult, Error> {
+        surface.0.reclaim()
+    }
+
     /// Returns a pointer to the underlying surface data for reading or writing by the CPU.
     #[inline]
-    pub fn lock_surface_data<'s>(&self, _: &'s mut Surface) -> Result<SurfaceDataGuard<'s>, Error> {
+    pub fn lock_surface_data<'s>(
+        &self,
+        surface: &'s mut Surface,
+    ) -> Result<SurfaceDataGuard<'s>, Error> {
+        surface.0.access.check_cpu_access()?;
+        surface.0.purge_state.check_usable()?;
         Err(Error::Unimplemented)
     }
 
+    /// Exports the texture of a generic surface as a dma-buf, which needs
+    /// `EGL_MESA_image_dma_buf_export`.
+    pub fn export_surface_dma_buf(&self, surface: &Surface) -> Result<DmaBuf, Error> {
+        surface.0.export_dma_buf(self.native_connection.egl_display)
+    }
+
+    /// Copies the contents of `source` into `destination` on the GPU, converting between their
+    /// formats and colorspaces and exchanging channels as `swizzle` says.
+    ///
+    /// Both surfaces must be generic surfaces of `context`, the same size, and `context` must be
+    /// current.
+    #[inline]
+    pub fn convert_surface(
+        &self,
+        context: &mut Context,
+        source: &Surface,
+        destination: &mut Surface,
+        swizzle: Swizzle,
+    ) -> Result<(), Error> {
+        convert::convert_surface(self, context, source, destination, swizzle)
+    }
+
+    /// Reads a rectangle of a generic surface, measured from its top left, into `out` as tightly
+    /// packed 8-bit RGBA, top row first.
+    ///
+    /// The surface must belong to `context`, which needn't be current. A widget surface whose
+    /// presentation is paused reads the frame that it holds.
+    pub fn read_surface_data(
+        &self,
+        context: &Context,
+        surface: &Surface,
+        rect: Rect<i32>,
+        out: &mut [u8],
+    ) -> Result<(), Error> {
+        match surface.0.pause.held_frame() {
+            Some(frame) if context.0.id == surface.0.context_id => frame.read_rect(rect, out),
+            Some(_) => Err(Error::IncompatibleSurface),
+            None => readback::read_surface_data(self, context, surface, rect, out),
+        }
+    }
+
+    /// Writes 8-bit RGBA pixels from `data`, top row first, into a rectangle of a generic surface,
+    /// measured from its top left.
+    ///
+    /// The surface must belong to `context`, which needn't be current.
+    #[inline]
+    pub fn write_surface_data(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        rect: Rect<i32>,
+        data: &[u8],
+        stride: usize,
+    ) -> Result<(), Error> {
+        upload::write_surface_data(self, context, surface, rect, data, stride)
+    }
+
     /// Returns the OpenGL texture target needed to read from this surface texture.
     ///
     /// This will be `GL_TEXTURE_2D` or `GL_TEXTURE_RECTANGLE`, depending on platform.
@@ -296,6 +983,62 @@ impl Device {
         surface.0.info()
     }
 
+    /// Returns the color profile of the output that a widget surface is displayed on.
+    ///
+    /// This asks compositors that offer the `wp_color_management_v1` protocol for the image
+    /// description that they would prefer the surface to be rendered in, which is that of its
+    /// output, and reports its ICC profile, or its primaries if it has none. The description is
+    /// fetched again whenever the compositor says that it has changed, and otherwise remembered.
+    ///
+    /// Compositors without the protocol, and connections made without the Wayland display,
+    /// report `ColorProfile::AssumedSrgb`, as does a compositor that hasn't managed to describe
+    /// the output yet.
+    pub fn widget_color_profile(&self, surface: &Surface) -> ColorProfile {
+        surface
+            .0
+            .color_profile_source
+            .as_ref()
+            .and_then(|color_profile_source| color_profile_source.color_profile())
+            .unwrap_or(ColorProfile::AssumedSrgb)
+    }
+
+    /// Returns true if a widget surface is on an output driven by a different GPU than this
+    /// device.
+    ///
+    /// Wayland doesn't tell clients which GPU drives each output, so this always returns false.
+    #[inline]
+    pub fn presenting_cross_adapter(&self, _: &Surface) -> bool {
+        false
+    }
+
+    /// Returns the timestamps of the most recent presentations of a widget surface, oldest first.
+    ///
+    /// `compositor_ack` is when `eglSwapBuffers()` returned. `displayed` is reported by
+    /// compositors that offer the `wp_presentation` protocol, and where the EGL display supports
+    /// `EGL_ANDROID_get_frame_timestamps`, once the frame has been shown.
+    ///
+    /// Returns an empty list for generic surfaces and for widget surfaces that haven't been
+    /// presented yet.
+    #[inline]
+    pub fn presentation_timestamps(&self, surface: &Surface) -> Vec<PresentRecord> {
+        surface.0.presentation_records()
+    }
+
+    /// Returns when the window system presented the most recent frame of a widget surface that
+    /// is known to have reached the display, or the most recent frame if none is yet.
+    ///
+    /// `actual_present` is reported by compositors that offer the `wp_presentation` protocol, and
+    /// all three times where the EGL display supports `EGL_ANDROID_get_frame_timestamps`.
+    ///
+    /// Returns `Error::NoWidgetAttached` for generic surfaces, and `Error::NotPresented` if
+    /// nothing has been presented on the surface.
+    pub fn query_presentation_time(
+        &self,
+        surface: &Surface,
+    ) -> Result<PresentationStatistics, Error> {
+        surface.0.presentation_time()
+    }
+
     /// Returns the OpenGL texture object containing the contents of this surface.
     ///
     /// It is only legal to read from, not write to, this texture object.
@@ -303,9 +1046,1374 @@ impl Device {
     pub fn surface_texture_object(&self, surface_texture: &SurfaceTexture) -> GLuint {
         surface_texture.0.texture_object
     }
+
+    /// Returns the sized internal format of the OpenGL texture of this surface texture, such as
+    /// `GL_RGBA8` or `GL_RGBA16F`.
+    ///
+    /// This is the `SurfaceInfo::format` of the wrapped surface, which tells shaders whether they
+    /// sample normalized or floating-point values.
+    #[inline]
+    pub fn surface_texture_format(&self, surface_texture: &SurfaceTexture) -> GLenum {
+        surface_texture.0.surface.info().format
+    }
+}
+
+impl Surface {
+    /// Returns the ID of the context that this surface was created with.
+    ///
+    /// A surface can only be bound to the context that it was created with.
+    #[inline]
+    pub fn context_id(&self) -> ContextID {
+        self.0.context_id
+    }
 }
 
 /// Represents the CPU view of the pixel data of this surface.
 pub struct SurfaceDataGuard<'a> {
     phantom: PhantomData<&'a ()>,
 }
+
+// Returns the size of the buffer last presented to a widget surface's `wl_egl_window`, or `None`
+// for generic surfaces and widgets that nothing has been presented to yet.
+fn attached_size(surface: &Surface) -> Option<Size2D<i32>> {
+    let wayland_egl_window = surface.0.native_window().ok()? as *mut c_void as *mut wl_egl_window;
+    let (mut width, mut height) = (0, 0);
+    unsafe {
+        (WAYLAND_EGL_HANDLE.wl_egl_window_get_attached_size)(
+            wayland_egl_window,
+            &mut width,
+            &mut height,
+        );
+    }
+    if width > 0 && height > 0 {
+        Some(Size2D::new(width, height))
+    } else {
+        None
+    }
+}
+
+// The opcode of the `wl_surface.frame` request.
+const WL_SURFACE_FRAME: u32 = 3;
+
+// The `wl_callback` interface, which `wayland-sys` doesn't declare.
+struct Messages<const N: usize>([wl_message; N]);
+unsafe impl<const N: usize> Sync for Messages<N> {}
+
+struct Interfaces<const N: usize>([*const wl_interface; N]);
+unsafe impl<const N: usize> Sync for Interfaces<N> {}
+
+static WL_CALLBACK_DONE_TYPES: Interfaces<1> = Interfaces([ptr::null()]);
+
+static WL_CALLBACK_EVENTS: Messages<1> = Messages([wl_message {
+    name: b"done\0" as *const u8 as *const c_char,
+    signature: b"u\0" as *const u8 as *const c_char,
+    types: &WL_CALLBACK_DONE_TYPES.0 as *const [*const wl_interface; 1] as *const _,
+}]);
+
+pub(crate) static WL_CALLBACK_INTERFACE: wl_interface = wl_interface {
+    name: b"wl_callback\0" as *const u8 as *const c_char,
+    version: 1,
+    request_count: 0,
+    requests: ptr::null(),
+    event_count: 1,
+    events: &WL_CALLBACK_EVENTS.0 as *const [wl_message; 1] as *const _,
+};
+
+#[repr(C)]
+struct WlCallbackListener {
+    done: unsafe extern "C" fn(data: *mut c_void, callback: *mut wl_proxy, time: u32),
+}
+
+static FRAME_CALLBACK_LISTENER: WlCallbackListener = WlCallbackListener { done: frame_done };
+
+unsafe extern "C" fn frame_done(data: *mut c_void, _: *mut wl_proxy, _: u32) {
+    (*(data as *const Cell<bool>)).set(true);
+}
+
+// Requests a `wl_surface.frame` callback with each frame presented on a widget surface. The
+// callbacks are delivered to an event queue of surfman's own, so that dispatching them never runs
+// the application's handlers.
+pub(crate) struct FrameCallbacks {
+    wayland_display: *mut wl_display,
+    queue: *mut wl_event_queue,
+    // A wrapper of the `wl_surface` that creates its callbacks on `queue`.
+    wayland_surface: *mut wl_proxy,
+    // The callback of the last frame presented, or null if none has been.
+    callback: *mut wl_proxy,
+    // Set when `callback` is called. Boxed, since the listener holds a pointer to it.
+    done: Box<Cell<bool>>,
+}
+
+impl FrameCallbacks {
+    pub(crate) unsafe fn new(
+        wayland_display: *mut wl_display,
+        wayland_surface: *mut wl_proxy,
+    ) -> Option<FrameCallbacks> {
+        let queue = (WAYLAND_CLIENT_HANDLE.wl_display_create_queue)(wayland_display);
+        if queue.is_null() {
+            return None;
+        }
+        let wrapper = (WAYLAND_CLIENT_HANDLE.wl_proxy_create_wrapper)(wayland_surface);
+        if wrapper.is_null() {
+            (WAYLAND_CLIENT_HANDLE.wl_event_queue_destroy)(queue);
+            return None;
+        }
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_set_queue)(wrapper, queue);
+        Some(FrameCallbacks {
+            wayland_display,
+            queue,
+            wayland_surface: wrapper,
+            callback: ptr::null_mut(),
+            done: Box::new(Cell::new(false)),
+        })
+    }
+
+    // Dispatches the events that have already been read for `queue`.
+    unsafe fn dispatch_pending(&self) -> Result<(), Error> {
+        if (WAYLAND_CLIENT_HANDLE.wl_display_dispatch_queue_pending)(
+            self.wayland_display,
+            self.queue,
+        ) < 0
+        {
+            return Err(Error::PresentFailed(WindowingApiError::Failed));
+        }
+        Ok(())
+    }
+}
+
+impl ReleaseSignal for FrameCallbacks {
+    fn presenting(&mut self) -> Result<(), Error> {
+        unsafe {
+            // The request is sent with the commit that `eglSwapBuffers()` makes.
+            let mut args = [wl_argument { n: 0 }];
+            let callback = (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor)(
+                self.wayland_surface,
+                WL_SURFACE_FRAME,
+                args.as_mut_ptr(),
+                &WL_CALLBACK_INTERFACE,
+            );
+            if callback.is_null() {
+                return Err(Error::PresentFailed(WindowingApiError::Failed));
+            }
+            if !self.callback.is_null() {
+                (WAYLAND_CLIENT_HANDLE.wl_proxy_destroy)(self.callback);
+            }
+            self.callback = callback;
+            self.done.set(false);
+            (WAYLAND_CLIENT_HANDLE.wl_proxy_add_listener)(
+                callback,
+                &FRAME_CALLBACK_LISTENER as *const WlCallbackListener as *mut extern "C" fn(),
+                &*self.done as *const Cell<bool> as *mut c_void,
+            );
+            Ok(())
+        }
+    }
+
+    fn wait(&self, timeout: Duration) -> Result<BufferRelease, Error> {
+        if self.callback.is_null() {
+            return Ok(BufferRelease::Released);
+        }
+
+        unsafe {
+            self.dispatch_pending()?;
+            if self.done.get() {
+                return Ok(BufferRelease::Released);
+            }
+
+            let deadline = Instant::now() + timeout;
+            let fd = (WAYLAND_CLIENT_HANDLE.wl_display_get_fd)(self.wayland_display);
+            while !self.done.get() {
+                if (WAYLAND_CLIENT_HANDLE.wl_display_prepare_read_queue)(
+                    self.wayland_display,
+                    self.queue,
+                ) != 0
+                {
+                    self.dispatch_pending()?;
+                    continue;
+                }
+                (WAYLAND_CLIENT_HANDLE.wl_display_flush)(self.wayland_display);
+
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining == Duration::from_secs(0) {
+                    (WAYLAND_CLIENT_HANDLE.wl_display_cancel_read)(self.wayland_display);
+                    return Ok(BufferRelease::TimedOut);
+                }
+                // Round up, so as not to spin for the last fraction of a millisecond.
+                let timeout_ms = remaining.as_nanos().div_ceil(1_000_000);
+                let mut poll_fd = libc::pollfd {
+                    fd,
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                let result = libc::poll(&mut poll_fd, 1, timeout_ms.min(i32::MAX as u128) as i32);
+                if result > 0 {
+                    if (WAYLAND_CLIENT_HANDLE.wl_display_read_events)(self.wayland_display) < 0 {
+                        return Err(Error::PresentFailed(WindowingApiError::Failed));
+                    }
+                } else {
+                    (WAYLAND_CLIENT_HANDLE.wl_display_cancel_read)(self.wayland_display);
+                    if result < 0 && io::Error::last_os_error().kind() != io::ErrorKind::Interrupted
+                    {
+                        return Err(Error::PresentFailed(WindowingApiError::Failed));
+                    }
+                }
+                self.dispatch_pending()?;
+            }
+            Ok(BufferRelease::Waited)
+        }
+    }
+}
+
+impl Drop for FrameCallbacks {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.callback.is_null() {
+                (WAYLAND_CLIENT_HANDLE.wl_proxy_destroy)(self.callback);
+            }
+            (WAYLAND_CLIENT_HANDLE.wl_proxy_wrapper_destroy)(self.wayland_surface);
+            (WAYLAND_CLIENT_HANDLE.wl_event_queue_destroy)(self.queue);
+        }
+    }
+}
+
+// The opcodes of the `wl_display.get_registry` and `wl_registry.bind` requests, and of the
+// `wp_presentation` requests.
+const WL_DISPLAY_GET_REGISTRY: u32 = 1;
+const WL_REGISTRY_BIND: u32 = 0;
+const WP_PRESENTATION_DESTROY: u32 = 0;
+const WP_PRESENTATION_FEEDBACK: u32 = 1;
+
+// The `wl_registry` interface, and the `wp_presentation` and `wp_presentation_feedback`
+// interfaces of the presentation time protocol, which `wayland-sys` doesn't declare either.
+static NULL_TYPES: Interfaces<8> = Interfaces([ptr::null(); 8]);
+
+static WL_REGISTRY_REQUESTS: Messages<1> = Messages([wl_message {
+    name: b"bind\0" as *const u8 as *const c_char,
+    signature: b"usun\0" as *const u8 as *const c_char,
+    types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+}]);
+
+static WL_REGISTRY_EVENTS: Messages<2> = Messages([
+    wl_message {
+        name: b"global\0" as *const u8 as *const c_char,
+        signature: b"usu\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"global_remove\0" as *const u8 as *const c_char,
+        signature: b"u\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+]);
+
+static WL_REGISTRY_INTERFACE: wl_interface = wl_interface {
+    name: b"wl_registry\0" as *const u8 as *const c_char,
+    version: 1,
+    request_count: 1,
+    requests: &WL_REGISTRY_REQUESTS.0 as *const [wl_message; 1] as *const _,
+    event_count: 2,
+    events: &WL_REGISTRY_EVENTS.0 as *const [wl_message; 2] as *const _,
+};
+
+static WP_PRESENTATION_FEEDBACK_TYPES: Interfaces<2> =
+    Interfaces([ptr::null(), &WP_PRESENTATION_FEEDBACK_INTERFACE]);
+
+static WP_PRESENTATION_REQUESTS: Messages<2> = Messages([
+    wl_message {
+        name: b"destroy\0" as *const u8 as *const c_char,
+        signature: b"\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"feedback\0" as *const u8 as *const c_char,
+        signature: b"on\0" as *const u8 as *const c_char,
+        types: &WP_PRESENTATION_FEEDBACK_TYPES.0 as *const [*const wl_interface; 2] as *const _,
+    },
+]);
+
+static WP_PRESENTATION_EVENTS: Messages<1> = Messages([wl_message {
+    name: b"clock_id\0" as *const u8 as *const c_char,
+    signature: b"u\0" as *const u8 as *const c_char,
+    types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+}]);
+
+static WP_PRESENTATION_INTERFACE: wl_interface = wl_interface {
+    name: b"wp_presentation\0" as *const u8 as *const c_char,
+    version: 1,
+    request_count: 2,
+    requests: &WP_PRESENTATION_REQUESTS.0 as *const [wl_message; 2] as *const _,
+    event_count: 1,
+    events: &WP_PRESENTATION_EVENTS.0 as *const [wl_message; 1] as *const _,
+};
+
+static WP_PRESENTATION_FEEDBACK_EVENTS: Messages<3> = Messages([
+    wl_message {
+        name: b"sync_output\0" as *const u8 as *const c_char,
+        signature: b"o\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"presented\0" as *const u8 as *const c_char,
+        signature: b"uuuuuuu\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"discarded\0" as *const u8 as *const c_char,
+        signature: b"\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+]);
+
+static WP_PRESENTATION_FEEDBACK_INTERFACE: wl_interface = wl_interface {
+    name: b"wp_presentation_feedback\0" as *const u8 as *const c_char,
+    version: 1,
+    request_count: 0,
+    requests: ptr::null(),
+    event_count: 3,
+    events: &WP_PRESENTATION_FEEDBACK_EVENTS.0 as *const [wl_message; 3] as *const _,
+};
+
+#[repr(C)]
+struct WlRegistryListener {
+    global: unsafe extern "C" fn(
+        data: *mut c_void,
+        registry: *mut wl_proxy,
+        name: u32,
+        interface: *const c_char,
+        version: u32,
+    ),
+    global_remove: unsafe extern "C" fn(data: *mut c_void, registry: *mut wl_proxy, name: u32),
+}
+
+static REGISTRY_LISTENER: WlRegistryListener = WlRegistryListener {
+    global: registry_global,
+    global_remove: registry_global_remove,
+};
+
+// A global that `bind_global()` looks for, and its name once the registry has listed it.
+struct GlobalSearch {
+    interface: &'static wl_interface,
+    name: Cell<Option<u32>>,
+}
+
+// Records the name of the global that a `GlobalSearch` looks for.
+unsafe extern "C" fn registry_global(
+    data: *mut c_void,
+    _: *mut wl_proxy,
+    name: u32,
+    interface: *const c_char,
+    _: u32,
+) {
+    let search = &*(data as *const GlobalSearch);
+    if CStr::from_ptr(interface) == CStr::from_ptr(search.interface.name) {
+        search.name.set(Some(name));
+    }
+}
+
+unsafe extern "C" fn registry_global_remove(_: *mut c_void, _: *mut wl_proxy, _: u32) {}
+
+#[repr(C)]
+struct WpPresentationListener {
+    clock_id: unsafe extern "C" fn(data: *mut c_void, presentation: *mut wl_proxy, clock_id: u32),
+}
+
+static PRESENTATION_LISTENER: WpPresentationListener = WpPresentationListener {
+    clock_id: presentation_clock_id,
+};
+
+unsafe extern "C" fn presentation_clock_id(data: *mut c_void, _: *mut wl_proxy, clock_id: u32) {
+    (*(data as *const Cell<Option<u32>>)).set(Some(clock_id));
+}
+
+#[repr(C)]
+struct WpPresentationFeedbackListener {
+    sync_output:
+        unsafe extern "C" fn(data: *mut c_void, feedback: *mut wl_proxy, output: *mut wl_proxy),
+    presented: unsafe extern "C" fn(
+        data: *mut c_void,
+        feedback: *mut wl_proxy,
+        tv_sec_hi: u32,
+        tv_sec_lo: u32,
+        tv_nsec: u32,
+        refresh: u32,
+        seq_hi: u32,
+        seq_lo: u32,
+        flags: u32,
+    ),
+    discarded: unsafe extern "C" fn(data: *mut c_void, feedback: *mut wl_proxy),
+}
+
+static PRESENTATION_FEEDBACK_LISTENER: WpPresentationFeedbackListener =
+    WpPresentationFeedbackListener {
+        sync_output: feedback_sync_output,
+        presented: feedback_presented,
+        discarded: feedback_discarded,
+    };
+
+unsafe extern "C" fn feedback_sync_output(_: *mut c_void, _: *mut wl_proxy, _: *mut wl_proxy) {}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn feedback_presented(
+    data: *mut c_void,
+    feedback: *mut wl_proxy,
+    tv_sec_hi: u32,
+    tv_sec_lo: u32,
+    tv_nsec: u32,
+    _: u32,
+    _: u32,
+    _: u32,
+    _: u32,
+) {
+    let frame = &*(data as *const PresentedFrame);
+    let seconds = ((tv_sec_hi as u64) << 32) | tv_sec_lo as u64;
+    let nanos = seconds
+        .checked_mul(1_000_000_000)
+        .and_then(|nanos| nanos.checked_add(tv_nsec as u64));
+    frame.presented.set(nanos);
+    frame.feedback_done(feedback);
+}
+
+unsafe extern "C" fn feedback_discarded(data: *mut c_void, feedback: *mut wl_proxy) {
+    (*(data as *const PresentedFrame)).feedback_done(feedback);
+}
+
+// How many frames back `PresentationFeedbacks` remembers.
+const PRESENTATION_FEEDBACKS_LENGTH: usize = 8;
+
+// A frame presented on a widget surface, and what the compositor has reported about it.
+struct PresentedFrame {
+    frame_id: u64,
+    // The frame's `wp_presentation_feedback`, until the compositor reports on the frame.
+    feedback: Cell<*mut wl_proxy>,
+    // When the frame was shown, in nanoseconds on the presentation clock. This stays `None` if
+    // the frame was never shown.
+    presented: Cell<Option<u64>>,
+}
+
+impl PresentedFrame {
+    // Destroys the frame's feedback, which the compositor has destroyed by reporting on it.
+    unsafe fn feedback_done(&self, feedback: *mut wl_proxy) {
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_destroy)(feedback);
+        self.feedback.set(ptr::null_mut());
+    }
+}
+
+impl Drop for PresentedFrame {
+    fn drop(&mut self) {
+        if !self.feedback.get().is_null() {
+            unsafe { (WAYLAND_CLIENT_HANDLE.wl_proxy_destroy)(self.feedback.get()) }
+        }
+    }
+}
+
+// Requests `wp_presentation` feedback for each frame presented on a widget surface, from
+// compositors that offer it. As with `FrameCallbacks`, the events are delivered to an event
+// queue of surfman's own.
+pub(crate) struct PresentationFeedbacks {
+    wayland_display: *mut wl_display,
+    queue: *mut wl_event_queue,
+    wayland_surface: *mut wl_proxy,
+    presentation: *mut wl_proxy,
+    // The clock that the compositor reports times on, once it has said which. Boxed, since the
+    // listener holds a pointer to it.
+    clock_id: Box<Cell<Option<u32>>>,
+    // The most recent frames, oldest first. Boxed, since their feedbacks' listeners hold pointers
+    // to them.
+    frames: VecDeque<Box<PresentedFrame>>,
+}
+
+impl PresentationFeedbacks {
+    pub(crate) unsafe fn new(
+        wayland_display: *mut wl_display,
+        wayland_surface: *mut wl_proxy,
+    ) -> Option<PresentationFeedbacks> {
+        let queue = (WAYLAND_CLIENT_HANDLE.wl_display_create_queue)(wayland_display);
+        if queue.is_null() {
+            return None;
+        }
+        let presentation = bind_global(wayland_display, queue, &WP_PRESENTATION_INTERFACE);
+        if presentation.is_null() {
+            (WAYLAND_CLIENT_HANDLE.wl_event_queue_destroy)(queue);
+            return None;
+        }
+        let clock_id = Box::new(Cell::new(None));
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_add_listener)(
+            presentation,
+            &PRESENTATION_LISTENER as *const WpPresentationListener as *mut extern "C" fn(),
+            &*clock_id as *const Cell<Option<u32>> as *mut c_void,
+        );
+        Some(PresentationFeedbacks {
+            wayland_display,
+            queue,
+            wayland_surface,
+            presentation,
+            clock_id,
+            frames: VecDeque::new(),
+        })
+    }
+}
+
+// Reads and dispatches whatever events have arrived for `queue`, without blocking.
+unsafe fn dispatch_arrived(
+    wayland_display: *mut wl_display,
+    queue: *mut wl_event_queue,
+) -> Result<(), Error> {
+    let failed = Err(Error::PresentFailed(WindowingApiError::Failed));
+    while (WAYLAND_CLIENT_HANDLE.wl_display_prepare_read_queue)(wayland_display, queue) != 0 {
+        if (WAYLAND_CLIENT_HANDLE.wl_display_dispatch_queue_pending)(wayland_display, queue) < 0 {
+            return failed;
+        }
+    }
+    (WAYLAND_CLIENT_HANDLE.wl_display_flush)(wayland_display);
+    let mut poll_fd = libc::pollfd {
+        fd: (WAYLAND_CLIENT_HANDLE.wl_display_get_fd)(wayland_display),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    if libc::poll(&mut poll_fd, 1, 0) > 0 {
+        if (WAYLAND_CLIENT_HANDLE.wl_display_read_events)(wayland_display) < 0 {
+            return failed;
+        }
+    } else {
+        (WAYLAND_CLIENT_HANDLE.wl_display_cancel_read)(wayland_display);
+    }
+    if (WAYLAND_CLIENT_HANDLE.wl_display_dispatch_queue_pending)(wayland_display, queue) < 0 {
+        return failed;
+    }
+    Ok(())
+}
+
+impl PresentationFeedback for PresentationFeedbacks {
+    fn presenting(&mut self, frame_id: u64) -> Result<(), Error> {
+        unsafe {
+            // As with frame callbacks, the request applies to the commit that `eglSwapBuffers()`
+            // makes. The feedback is created on the queue of `presentation`.
+            let mut args = [
+                wl_argument {
+                    o: self.wayland_surface as *const c_void,
+                },
+                wl_argument { n: 0 },
+            ];
+            let feedback = (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor)(
+                self.presentation,
+                WP_PRESENTATION_FEEDBACK,
+                args.as_mut_ptr(),
+                &WP_PRESENTATION_FEEDBACK_INTERFACE,
+            );
+            if feedback.is_null() {
+                return Err(Error::PresentFailed(WindowingApiError::Failed));
+            }
+
+            // A frame whose swap failed is presented again under the same `frame_id`.
+            if self
+                .frames
+                .back()
+                .is_some_and(|frame| frame.frame_id == frame_id)
+            {
+                self.frames.pop_back();
+            }
+            if self.frames.len() == PRESENTATION_FEEDBACKS_LENGTH {
+                self.frames.pop_front();
+            }
+
+            let frame = Box::new(PresentedFrame {
+                frame_id,
+                feedback: Cell::new(feedback),
+                presented: Cell::new(None),
+            });
+            (WAYLAND_CLIENT_HANDLE.wl_proxy_add_listener)(
+                feedback,
+                &PRESENTATION_FEEDBACK_LISTENER as *const WpPresentationFeedbackListener
+                    as *mut extern "C" fn(),
+                &*frame as *const PresentedFrame as *mut c_void,
+            );
+            self.frames.push_back(frame);
+            Ok(())
+        }
+    }
+
+    fn update(&self, statistics: &mut PresentationStatistics) {
+        unsafe {
+            if dispatch_arrived(self.wayland_display, self.queue).is_err() {
+                return;
+            }
+        }
+        let frame = self
+            .frames
+            .iter()
+            .find(|frame| frame.frame_id == statistics.frame_id);
+        if let (Some(frame), Some(clock_id)) = (frame, self.clock_id.get()) {
+            if let Some(nanos) = frame.presented.get() {
+                statistics.actual_present =
+                    presentation_time::instant_from_clock(clock_id as libc::clockid_t, nanos);
+            }
+        }
+    }
+}
+
+impl Drop for PresentationFeedbacks {
+    fn drop(&mut self) {
+        // The feedbacks have to be destroyed before their queue.
+        self.frames.clear();
+        unsafe {
+            (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array)(
+                self.presentation,
+                WP_PRESENTATION_DESTROY,
+                ptr::null_mut(),
+            );
+            (WAYLAND_CLIENT_HANDLE.wl_proxy_destroy)(self.presentation);
+            (WAYLAND_CLIENT_HANDLE.wl_event_queue_destroy)(self.queue);
+        }
+    }
+}
+
+// Binds the compositor's global of the given interface, at the interface's version, on `queue`, or
+// returns null if it has none.
+unsafe fn bind_global(
+    wayland_display: *mut wl_display,
+    queue: *mut wl_event_queue,
+    interface: &'static wl_interface,
+) -> *mut wl_proxy {
+    let wrapper = (WAYLAND_CLIENT_HANDLE.wl_proxy_create_wrapper)(wayland_display as *mut wl_proxy);
+    if wrapper.is_null() {
+        return ptr::null_mut();
+    }
+    (WAYLAND_CLIENT_HANDLE.wl_proxy_set_queue)(wrapper, queue);
+    let mut args = [wl_argument { n: 0 }];
+    let registry = (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor)(
+        wrapper,
+        WL_DISPLAY_GET_REGISTRY,
+        args.as_mut_ptr(),
+        &WL_REGISTRY_INTERFACE,
+    );
+    (WAYLAND_CLIENT_HANDLE.wl_proxy_wrapper_destroy)(wrapper);
+    if registry.is_null() {
+        return ptr::null_mut();
+    }
+
+    let search = GlobalSearch {
+        interface,
+        name: Cell::new(None),
+    };
+    (WAYLAND_CLIENT_HANDLE.wl_proxy_add_listener)(
+        registry,
+        &REGISTRY_LISTENER as *const WlRegistryListener as *mut extern "C" fn(),
+        &search as *const GlobalSearch as *mut c_void,
+    );
+    // The roundtrip delivers the `wl_registry.global` events of every global.
+    let listed = (WAYLAND_CLIENT_HANDLE.wl_display_roundtrip_queue)(wayland_display, queue) >= 0;
+    let global = match search.name.get() {
+        Some(name) if listed => {
+            let mut args = [
+                wl_argument { u: name },
+                wl_argument { s: interface.name },
+                wl_argument {
+                    u: interface.version as u32,
+                },
+                wl_argument { n: 0 },
+            ];
+            (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor_versioned)(
+                registry,
+                WL_REGISTRY_BIND,
+                args.as_mut_ptr(),
+                interface,
+                interface.version as u32,
+            )
+        }
+        _ => ptr::null_mut(),
+    };
+    (WAYLAND_CLIENT_HANDLE.wl_proxy_destroy)(registry);
+    global
+}
+
+// The opcodes of the color management protocol's requests.
+const WP_COLOR_MANAGER_DESTROY: u32 = 0;
+const WP_COLOR_MANAGER_GET_SURFACE_FEEDBACK: u32 = 3;
+const WP_COLOR_MANAGEMENT_SURFACE_FEEDBACK_DESTROY: u32 = 0;
+const WP_COLOR_MANAGEMENT_SURFACE_FEEDBACK_GET_PREFERRED: u32 = 1;
+const WP_IMAGE_DESCRIPTION_DESTROY: u32 = 0;
+const WP_IMAGE_DESCRIPTION_GET_INFORMATION: u32 = 1;
+
+// The values of the color management protocol's `primaries` enum that name color spaces surfman
+// knows.
+const WP_COLOR_MANAGER_PRIMARIES_SRGB: u32 = 1;
+const WP_COLOR_MANAGER_PRIMARIES_BT2020: u32 = 6;
+const WP_COLOR_MANAGER_PRIMARIES_DISPLAY_P3: u32 = 9;
+const WP_COLOR_MANAGER_PRIMARIES_ADOBE_RGB: u32 = 10;
+
+// The chromaticities of the red, green, and blue primaries and the white point of the color spaces
+// that surfman knows, as x and y coordinates multiplied by 1,000,000, which is how
+// `wp_image_description_info_v1.primaries` reports them.
+const KNOWN_PRIMARIES: [(ColorSpace, [i32; 8]); 4] = [
+    (
+        ColorSpace::Srgb,
+        [
+            640000, 330000, 300000, 600000, 150000, 60000, 312700, 329000,
+        ],
+    ),
+    (
+        ColorSpace::DisplayP3,
+        [
+            680000, 320000, 265000, 690000, 150000, 60000, 312700, 329000,
+        ],
+    ),
+    (
+        ColorSpace::Bt2020,
+        [
+            708000, 292000, 170000, 797000, 131000, 46000, 312700, 329000,
+        ],
+    ),
+    (
+        ColorSpace::AdobeRgb,
+        [
+            640000, 330000, 210000, 710000, 150000, 60000, 312700, 329000,
+        ],
+    ),
+];
+
+// How far reported chromaticities may be from those of a known color space, in the same units,
+// for rounding.
+const PRIMARIES_TOLERANCE: i32 = 1000;
+
+// The largest ICC profile that the protocol allows compositors to send.
+const MAX_ICC_FILE_SIZE: u32 = 32 * 1024 * 1024;
+
+// The `wp_color_manager_v1`, `wp_color_management_surface_feedback_v1`, `wp_image_description_v1`,
+// and `wp_image_description_info_v1` interfaces of the color management protocol, which
+// `wayland-sys` doesn't declare either. Only the requests that surfman makes have types, but every
+// event is declared, since the compositor may send any of them.
+static WP_COLOR_MANAGER_REQUESTS: Messages<7> = Messages([
+    wl_message {
+        name: b"destroy\0" as *const u8 as *const c_char,
+        signature: b"\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"get_output\0" as *const u8 as *const c_char,
+        signature: b"no\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"get_surface\0" as *const u8 as *const c_char,
+        signature: b"no\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"get_surface_feedback\0" as *const u8 as *const c_char,
+        signature: b"no\0" as *const u8 as *const c_char,
+        types: &WP_COLOR_MANAGEMENT_SURFACE_FEEDBACK_TYPES.0 as *const [*const wl_interface; 2]
+            as *const _,
+    },
+    wl_message {
+        name: b"create_icc_creator\0" as *const u8 as *const c_char,
+        signature: b"n\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"create_parametric_creator\0" as *const u8 as *const c_char,
+        signature: b"n\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"create_windows_scrgb\0" as *const u8 as *const c_char,
+        signature: b"n\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+]);
+
+static WP_COLOR_MANAGER_EVENTS: Messages<5> = Messages([
+    wl_message {
+        name: b"supported_intent\0" as *const u8 as *const c_char,
+        signature: b"u\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"supported_feature\0" as *const u8 as *const c_char,
+        signature: b"u\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"supported_tf_named\0" as *const u8 as *const c_char,
+        signature: b"u\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"supported_primaries_named\0" as *const u8 as *const c_char,
+        signature: b"u\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"done\0" as *const u8 as *const c_char,
+        signature: b"\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+]);
+
+static WP_COLOR_MANAGER_INTERFACE: wl_interface = wl_interface {
+    name: b"wp_color_manager_v1\0" as *const u8 as *const c_char,
+    version: 1,
+    request_count: 7,
+    requests: &WP_COLOR_MANAGER_REQUESTS.0 as *const [wl_message; 7] as *const _,
+    event_count: 5,
+    events: &WP_COLOR_MANAGER_EVENTS.0 as *const [wl_message; 5] as *const _,
+};
+
+static WP_COLOR_MANAGEMENT_SURFACE_FEEDBACK_TYPES: Interfaces<2> =
+    Interfaces([&WP_COLOR_MANAGEMENT_SURFACE_FEEDBACK_INTERFACE, ptr::null()]);
+
+static WP_IMAGE_DESCRIPTION_TYPES: Interfaces<1> = Interfaces([&WP_IMAGE_DESCRIPTION_INTERFACE]);
+
+static WP_COLOR_MANAGEMENT_SURFACE_FEEDBACK_REQUESTS: Messages<3> = Messages([
+    wl_message {
+        name: b"destroy\0" as *const u8 as *const c_char,
+        signature: b"\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"get_preferred\0" as *const u8 as *const c_char,
+        signature: b"n\0" as *const u8 as *const c_char,
+        types: &WP_IMAGE_DESCRIPTION_TYPES.0 as *const [*const wl_interface; 1] as *const _,
+    },
+    wl_message {
+        name: b"get_preferred_parametric\0" as *const u8 as *const c_char,
+        signature: b"n\0" as *const u8 as *const c_char,
+        types: &WP_IMAGE_DESCRIPTION_TYPES.0 as *const [*const wl_interface; 1] as *const _,
+    },
+]);
+
+static WP_COLOR_MANAGEMENT_SURFACE_FEEDBACK_EVENTS: Messages<1> = Messages([wl_message {
+    name: b"preferred_changed\0" as *const u8 as *const c_char,
+    signature: b"u\0" as *const u8 as *const c_char,
+    types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+}]);
+
+static WP_COLOR_MANAGEMENT_SURFACE_FEEDBACK_INTERFACE: wl_interface = wl_interface {
+    name: b"wp_color_management_surface_feedback_v1\0" as *const u8 as *const c_char,
+    version: 1,
+    request_count: 3,
+    requests: &WP_COLOR_MANAGEMENT_SURFACE_FEEDBACK_REQUESTS.0 as *const [wl_message; 3]
+        as *const _,
+    event_count: 1,
+    events: &WP_COLOR_MANAGEMENT_SURFACE_FEEDBACK_EVENTS.0 as *const [wl_message; 1] as *const _,
+};
+
+static WP_IMAGE_DESCRIPTION_INFO_TYPES: Interfaces<1> =
+    Interfaces([&WP_IMAGE_DESCRIPTION_INFO_INTERFACE]);
+
+static WP_IMAGE_DESCRIPTION_REQUESTS: Messages<2> = Messages([
+    wl_message {
+        name: b"destroy\0" as *const u8 as *const c_char,
+        signature: b"\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"get_information\0" as *const u8 as *const c_char,
+        signature: b"n\0" as *const u8 as *const c_char,
+        types: &WP_IMAGE_DESCRIPTION_INFO_TYPES.0 as *const [*const wl_interface; 1] as *const _,
+    },
+]);
+
+static WP_IMAGE_DESCRIPTION_EVENTS: Messages<2> = Messages([
+    wl_message {
+        name: b"failed\0" as *const u8 as *const c_char,
+        signature: b"us\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"ready\0" as *const u8 as *const c_char,
+        signature: b"u\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+]);
+
+static WP_IMAGE_DESCRIPTION_INTERFACE: wl_interface = wl_interface {
+    name: b"wp_image_description_v1\0" as *const u8 as *const c_char,
+    version: 1,
+    request_count: 2,
+    requests: &WP_IMAGE_DESCRIPTION_REQUESTS.0 as *const [wl_message; 2] as *const _,
+    event_count: 2,
+    events: &WP_IMAGE_DESCRIPTION_EVENTS.0 as *const [wl_message; 2] as *const _,
+};
+
+static WP_IMAGE_DESCRIPTION_INFO_EVENTS: Messages<11> = Messages([
+    wl_message {
+        name: b"done\0" as *const u8 as *const c_char,
+        signature: b"\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"icc_file\0" as *const u8 as *const c_char,
+        signature: b"hu\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"primaries\0" as *const u8 as *const c_char,
+        signature: b"iiiiiiii\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"primaries_named\0" as *const u8 as *const c_char,
+        signature: b"u\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"tf_power\0" as *const u8 as *const c_char,
+        signature: b"u\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"tf_named\0" as *const u8 as *const c_char,
+        signature: b"u\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"luminances\0" as *const u8 as *const c_char,
+        signature: b"uuu\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"target_primaries\0" as *const u8 as *const c_char,
+        signature: b"iiiiiiii\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"target_luminance\0" as *const u8 as *const c_char,
+        signature: b"uu\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"target_max_cll\0" as *const u8 as *const c_char,
+        signature: b"u\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+    wl_message {
+        name: b"target_max_fall\0" as *const u8 as *const c_char,
+        signature: b"u\0" as *const u8 as *const c_char,
+        types: &NULL_TYPES.0 as *const [*const wl_interface; 8] as *const _,
+    },
+]);
+
+static WP_IMAGE_DESCRIPTION_INFO_INTERFACE: wl_interface = wl_interface {
+    name: b"wp_image_description_info_v1\0" as *const u8 as *const c_char,
+    version: 1,
+    request_count: 0,
+    requests: ptr::null(),
+    event_count: 11,
+    events: &WP_IMAGE_DESCRIPTION_INFO_EVENTS.0 as *const [wl_message; 11] as *const _,
+};
+
+#[repr(C)]
+struct WpColorManagementSurfaceFeedbackListener {
+    preferred_changed: unsafe extern "C" fn(data: *mut c_void, feedback: *mut wl_proxy, u32),
+}
+
+static SURFACE_FEEDBACK_LISTENER: WpColorManagementSurfaceFeedbackListener =
+    WpColorManagementSurfaceFeedbackListener {
+        preferred_changed: surface_feedback_preferred_changed,
+    };
+
+unsafe extern "C" fn surface_feedback_preferred_changed(
+    data: *mut c_void,
+    _: *mut wl_proxy,
+    _: u32,
+) {
+    (*(data as *const Cell<bool>)).set(true);
+}
+
+#[repr(C)]
+struct WpImageDescriptionListener {
+    failed: unsafe extern "C" fn(
+        data: *mut c_void,
+        description: *mut wl_proxy,
+        cause: u32,
+        message: *const c_char,
+    ),
+    ready: unsafe extern "C" fn(data: *mut c_void, description: *mut wl_proxy, identity: u32),
+}
+
+static IMAGE_DESCRIPTION_LISTENER: WpImageDescriptionListener = WpImageDescriptionListener {
+    failed: image_description_failed,
+    ready: image_description_ready,
+};
+
+unsafe extern "C" fn image_description_failed(
+    data: *mut c_void,
+    _: *mut wl_proxy,
+    _: u32,
+    _: *const c_char,
+) {
+    (*(data as *const Cell<Option<bool>>)).set(Some(false));
+}
+
+unsafe extern "C" fn image_description_ready(data: *mut c_void, _: *mut wl_proxy, _: u32) {
+    (*(data as *const Cell<Option<bool>>)).set(Some(true));
+}
+
+#[repr(C)]
+struct WpImageDescriptionInfoListener {
+    done: unsafe extern "C" fn(data: *mut c_void, info: *mut wl_proxy),
+    icc_file: unsafe extern "C" fn(data: *mut c_void, info: *mut wl_proxy, icc: i32, size: u32),
+    primaries: unsafe extern "C" fn(
+        data: *mut c_void,
+        info: *mut wl_proxy,
+        r_x: i32,
+        r_y: i32,
+        g_x: i32,
+        g_y: i32,
+        b_x: i32,
+        b_y: i32,
+        w_x: i32,
+        w_y: i32,
+    ),
+    primaries_named: unsafe extern "C" fn(data: *mut c_void, info: *mut wl_proxy, primaries: u32),
+    tf_power: unsafe extern "C" fn(data: *mut c_void, info: *mut wl_proxy, eexp: u32),
+    tf_named: unsafe extern "C" fn(data: *mut c_void, info: *mut wl_proxy, tf: u32),
+    luminances: unsafe extern "C" fn(data: *mut c_void, info: *mut wl_proxy, u32, u32, u32),
+    target_primaries: unsafe extern "C" fn(
+        data: *mut c_void,
+        info: *mut wl_proxy,
+        i32,
+        i32,
+        i32,
+        i32,
+        i32,
+        i32,
+        i32,
+        i32,
+    ),
+    target_luminance: unsafe extern "C" fn(data: *mut c_void, info: *mut wl_proxy, u32, u32),
+    target_max_cll: unsafe extern "C" fn(data: *mut c_void, info: *mut wl_proxy, u32),
+    target_max_fall: unsafe extern "C" fn(data: *mut c_void, info: *mut wl_proxy, u32),
+}
+
+static IMAGE_DESCRIPTION_INFO_LISTENER: WpImageDescriptionInfoListener =
+    WpImageDescriptionInfoListener {
+        done: info_done,
+        icc_file: info_icc_file,
+        primaries: info_primaries,
+        primaries_named: info_primaries_named,
+        tf_power: info_ignore_u32,
+        tf_named: info_ignore_u32,
+        luminances: info_ignore_luminances,
+        target_primaries: info_ignore_primaries,
+        target_luminance: info_ignore_target_luminance,
+        target_max_cll: info_ignore_u32,
+        target_max_fall: info_ignore_u32,
+    };
+
+// The information that the compositor has sent about an image description.
+#[derive(Default)]
+pub(crate) struct ImageDescriptionInfo {
+    pub(crate) icc_file: Option<Vec<u8>>,
+    pub(crate) primaries: Option<[i32; 8]>,
+    pub(crate) primaries_named: Option<u32>,
+}
+
+// An image description's information as it arrives. Boxed, since the listener holds a pointer to
+// it.
+struct PendingInfo {
+    // The `wp_image_description_info_v1`, until the compositor is done with it.
+    info: Cell<*mut wl_proxy>,
+    received: RefCell<ImageDescriptionInfo>,
+}
+
+impl Drop for PendingInfo {
+    fn drop(&mut self) {
+        if !self.info.get().is_null() {
+            unsafe { (WAYLAND_CLIENT_HANDLE.wl_proxy_destroy)(self.info.get()) }
+        }
+    }
+}
+
+// The compositor destroys the information object once it has sent everything.
+unsafe extern "C" fn info_done(data: *mut c_void, info: *mut wl_proxy) {
+    (WAYLAND_CLIENT_HANDLE.wl_proxy_destroy)(info);
+    (*(data as *const PendingInfo)).info.set(ptr::null_mut());
+}
+
+unsafe extern "C" fn info_icc_file(data: *mut c_void, _: *mut wl_proxy, icc: i32, size: u32) {
+    // The descriptor is ours to close. It may only be read from the start, with `pread()`.
+    let file = File::from(OwnedFd::from_raw_fd(icc));
+    if size == 0 || size > MAX_ICC_FILE_SIZE {
+        return;
+    }
+    let mut icc_file = vec![0; size as usize];
+    if file.read_exact_at(&mut icc_file, 0).is_ok() {
+        (*(data as *const PendingInfo))
+            .received
+            .borrow_mut()
+            .icc_file = Some(icc_file);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn info_primaries(
+    data: *mut c_void,
+    _: *mut wl_proxy,
+    r_x: i32,
+    r_y: i32,
+    g_x: i32,
+    g_y: i32,
+    b_x: i32,
+    b_y: i32,
+    w_x: i32,
+    w_y: i32,
+) {
+    (*(data as *const PendingInfo))
+        .received
+        .borrow_mut()
+        .primaries = Some([r_x, r_y, g_x, g_y, b_x, b_y, w_x, w_y]);
+}
+
+unsafe extern "C" fn info_primaries_named(data: *mut c_void, _: *mut wl_proxy, primaries: u32) {
+    (*(data as *const PendingInfo))
+        .received
+        .borrow_mut()
+        .primaries_named = Some(primaries);
+}
+
+unsafe extern "C" fn info_ignore_u32(_: *mut c_void, _: *mut wl_proxy, _: u32) {}
+
+unsafe extern "C" fn info_ignore_luminances(
+    _: *mut c_void,
+    _: *mut wl_proxy,
+    _: u32,
+    _: u32,
+    _: u32,
+) {
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn info_ignore_primaries(
+    _: *mut c_void,
+    _: *mut wl_proxy,
+    _: i32,
+    _: i32,
+    _: i32,
+    _: i32,
+    _: i32,
+    _: i32,
+    _: i32,
+    _: i32,
+) {
+}
+
+unsafe extern "C" fn info_ignore_target_luminance(
+    _: *mut c_void,
+    _: *mut wl_proxy,
+    _: u32,
+    _: u32,
+) {
+}
+
+// Turns what the compositor has said about an image description into a color profile.
+//
+// An ICC profile wins if there is a well-formed one. Otherwise, the primaries are matched against
+// the color spaces that surfman knows, by name and then by their chromaticities, and anything else
+// is reported as sRGB.
+pub(crate) fn color_profile_from_description(info: ImageDescriptionInfo) -> ColorProfile {
+    let ImageDescriptionInfo {
+        icc_file,
+        primaries,
+        primaries_named,
+    } = info;
+    if let Some(profile) = icc_file.and_then(ColorProfile::from_icc) {
+        return profile;
+    }
+    let named = primaries_named.and_then(|primaries| match primaries {
+        WP_COLOR_MANAGER_PRIMARIES_SRGB => Some(ColorSpace::Srgb),
+        WP_COLOR_MANAGER_PRIMARIES_BT2020 => Some(ColorSpace::Bt2020),
+        WP_COLOR_MANAGER_PRIMARIES_DISPLAY_P3 => Some(ColorSpace::DisplayP3),
+        WP_COLOR_MANAGER_PRIMARIES_ADOBE_RGB => Some(ColorSpace::AdobeRgb),
+        _ => None,
+    });
+    let matched = || {
+        let primaries = primaries?;
+        KNOWN_PRIMARIES
+            .iter()
+            .find(|(_, known)| {
+                known
+                    .iter()
+                    .zip(primaries.iter())
+                    .all(|(known, reported)| (known - reported).abs() <= PRIMARIES_TOLERANCE)
+            })
+            .map(|&(color_space, _)| color_space)
+    };
+    match named.or_else(matched) {
+        Some(color_space) => ColorProfile::Named(color_space),
+        None => ColorProfile::AssumedSrgb,
+    }
+}
+
+// Asks the compositor what it would prefer a widget surface to be rendered in, which is the image
+// description of the output that it's mostly on, with the color management protocol. The events
+// are delivered to an event queue of surfman's own, as with `PresentationFeedbacks`.
+pub(crate) struct ColorManagement {
+    wayland_display: *mut wl_display,
+    queue: *mut wl_event_queue,
+    color_manager: *mut wl_proxy,
+    surface_feedback: *mut wl_proxy,
+    // Set when the compositor says that its preference has changed, and to begin with. Boxed,
+    // since the listener holds a pointer to it.
+    changed: Box<Cell<bool>>,
+    // The profile that the compositor last described.
+    profile: RefCell<Option<ColorProfile>>,
+}
+
+impl ColorManagement {
+    // Returns `None` if the compositor doesn't offer the color management protocol.
+    pub(crate) unsafe fn new(
+        wayland_display: *mut wl_display,
+        wayland_surface: *mut wl_proxy,
+    ) -> Option<ColorManagement> {
+        let queue = (WAYLAND_CLIENT_HANDLE.wl_display_create_queue)(wayland_display);
+        if queue.is_null() {
+            return None;
+        }
+        let color_manager = bind_global(wayland_display, queue, &WP_COLOR_MANAGER_INTERFACE);
+        if color_manager.is_null() {
+            (WAYLAND_CLIENT_HANDLE.wl_event_queue_destroy)(queue);
+            return None;
+        }
+        let mut args = [
+            wl_argument { n: 0 },
+            wl_argument {
+                o: wayland_surface as *const c_void,
+            },
+        ];
+        let surface_feedback = (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor)(
+            color_manager,
+            WP_COLOR_MANAGER_GET_SURFACE_FEEDBACK,
+            args.as_mut_ptr(),
+            &WP_COLOR_MANAGEMENT_SURFACE_FEEDBACK_INTERFACE,
+        );
+        if surface_feedback.is_null() {
+            destroy_proxy(color_manager, WP_COLOR_MANAGER_DESTROY);
+            (WAYLAND_CLIENT_HANDLE.wl_event_queue_destroy)(queue);
+            return None;
+        }
+        let changed = Box::new(Cell::new(true));
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_add_listener)(
+            surface_feedback,
+            &SURFACE_FEEDBACK_LISTENER as *const WpColorManagementSurfaceFeedbackListener
+                as *mut extern "C" fn(),
+            &*changed as *const Cell<bool> as *mut c_void,
+        );
+        Some(ColorManagement {
+            wayland_display,
+            queue,
+            color_manager,
+            surface_feedback,
+            changed,
+            profile: RefCell::new(None),
+        })
+    }
+
+    // Fetches the compositor's preferred image description, and the information about it, waiting
+    // for the compositor to reply. Returns `None` if it couldn't describe one.
+    unsafe fn query_preferred(&self) -> Option<ColorProfile> {
+        let mut args = [wl_argument { n: 0 }];
+        let description = (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor)(
+            self.surface_feedback,
+            WP_COLOR_MANAGEMENT_SURFACE_FEEDBACK_GET_PREFERRED,
+            args.as_mut_ptr(),
+            &WP_IMAGE_DESCRIPTION_INTERFACE,
+        );
+        if description.is_null() {
+            return None;
+        }
+        let ready: Box<Cell<Option<bool>>> = Box::new(Cell::new(None));
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_add_listener)(
+            description,
+            &IMAGE_DESCRIPTION_LISTENER as *const WpImageDescriptionListener
+                as *mut extern "C" fn(),
+            &*ready as *const Cell<Option<bool>> as *mut c_void,
+        );
+        let profile = self.query_information(description, &ready);
+        destroy_proxy(description, WP_IMAGE_DESCRIPTION_DESTROY);
+        profile
+    }
+
+    unsafe fn query_information(
+        &self,
+        description: *mut wl_proxy,
+        ready: &Cell<Option<bool>>,
+    ) -> Option<ColorProfile> {
+        while ready.get().is_none() {
+            if (WAYLAND_CLIENT_HANDLE.wl_display_roundtrip_queue)(self.wayland_display, self.queue)
+                < 0
+            {
+                return None;
+            }
+        }
+        if ready.get() != Some(true) {
+            return None;
+        }
+
+        let mut args = [wl_argument { n: 0 }];
+        let info = (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array_constructor)(
+            description,
+            WP_IMAGE_DESCRIPTION_GET_INFORMATION,
+            args.as_mut_ptr(),
+            &WP_IMAGE_DESCRIPTION_INFO_INTERFACE,
+        );
+        if info.is_null() {
+            return None;
+        }
+        let pending = Box::new(PendingInfo {
+            info: Cell::new(info),
+            received: RefCell::new(ImageDescriptionInfo::default()),
+        });
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_add_listener)(
+            info,
+            &IMAGE_DESCRIPTION_INFO_LISTENER as *const WpImageDescriptionInfoListener
+                as *mut extern "C" fn(),
+            &*pending as *const PendingInfo as *mut c_void,
+        );
+        // The compositor sends all of the information at once, so one roundtrip gets it.
+        if (WAYLAND_CLIENT_HANDLE.wl_display_roundtrip_queue)(self.wayland_display, self.queue) < 0
+            || !pending.info.get().is_null()
+        {
+            return None;
+        }
+        Some(color_profile_from_description(
+            pending.received.replace(ImageDescriptionInfo::default()),
+        ))
+    }
+}
+
+impl ColorProfileSource for ColorManagement {
+    fn color_profile(&self) -> Option<ColorProfile> {
+        unsafe {
+            if dispatch_arrived(self.wayland_display, self.queue).is_ok() && self.changed.get() {
+                // A change that arrives during the query is picked up next time. A failed query
+                // keeps the last profile described, and is tried again next time.
+                self.changed.set(false);
+                match self.query_preferred() {
+                    Some(profile) => *self.profile.borrow_mut() = Some(profile),
+                    None => self.changed.set(true),
+                }
+            }
+        }
+        self.profile.borrow().clone()
+    }
+}
+
+impl Drop for ColorManagement {
+    fn drop(&mut self) {
+        unsafe {
+            destroy_proxy(
+                self.surface_feedback,
+                WP_COLOR_MANAGEMENT_SURFACE_FEEDBACK_DESTROY,
+            );
+            destroy_proxy(self.color_manager, WP_COLOR_MANAGER_DESTROY);
+            (WAYLAND_CLIENT_HANDLE.wl_event_queue_destroy)(self.queue);
+        }
+    }
+}
+
+// Sends a protocol object's destructor request, with the given opcode, and destroys its proxy.
+unsafe fn destroy_proxy(proxy: *mut wl_proxy, destroy_opcode: u32) {
+    (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array)(proxy, destroy_opcode, ptr::null_mut());
+    (WAYLAND_CLIENT_HANDLE.wl_proxy_destroy)(proxy);
+}
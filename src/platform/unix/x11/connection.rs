@@ -3,31 +3,37 @@
 //! A wrapper for X11 server connections (`DISPLAY` variables).
 
 use super::device::{Device, NativeDevice};
-use super::surface::NativeWidget;
+use super::randr;
+use super::surface::{self, NativeWidget};
+use crate::acceleration;
 use crate::connection::NativeConnection as NativeConnectionInterface;
-use crate::egl;
-use crate::egl::types::{EGLAttrib, EGLDisplay};
+use crate::egl::types::EGLDisplay;
 use crate::error::Error;
 use crate::info::GLApi;
-use crate::platform::generic::egl::device::EGL_FUNCTIONS;
+use crate::platform::generic::egl::device::{self, DisplayFunctions};
 use crate::platform::generic::egl::ffi::EGL_PLATFORM_X11_KHR;
+use crate::platform::unix::generic::connection::{self, EGLDisplayRef};
 use crate::platform::unix::generic::device::Adapter;
+use crate::{identity, AdapterKey};
 
 use euclid::default::Size2D;
 
+use std::env;
+use std::ffi::{CStr, CString};
 use std::marker::PhantomData;
+use std::mem;
 use std::os::raw::c_void;
 use std::ptr;
-use std::sync::Arc;
-use x11::xlib::{Display, XCloseDisplay, XInitThreads, XLockDisplay, XOpenDisplay, XUnlockDisplay};
+use std::sync::{Arc, Once};
+use x11::xlib::{Display, VisualID, VisualIDMask, XCloseDisplay, XDefaultDepth, XDefaultScreen};
+use x11::xlib::{Window, XDisplayString, XVisualInfo};
+use x11::xlib::{XFree, XGetVisualInfo, XInitThreads, XLockDisplay, XOpenDisplay, XUnlockDisplay};
 
-lazy_static! {
-    static ref X_THREADS_INIT: () = {
-        unsafe {
-            XInitThreads();
-        }
-    };
-}
+// The backend name in adapter keys.
+const ADAPTER_KEY_BACKEND: &str = "x11";
+
+// Completed once `XInitThreads()` has been called, or the application has said that it was.
+static X_THREADS_INIT: Once = Once::new();
 
 /// A connection to the X11 display server.
 #[derive(Clone)]
@@ -37,13 +43,28 @@ pub struct Connection {
 
 unsafe impl Send for Connection {}
 
+// Xlib calls go through `NativeConnectionWrapper::lock_display()`, and `XInitThreads()` is called
+// before any display is opened (or is the caller's responsibility, for wrapped displays), so Xlib
+// serializes concurrent access. EGL is thread-safe, and headless connections have no X display at
+// all.
+unsafe impl Sync for Connection {}
+
 pub(crate) struct NativeConnectionWrapper {
     pub(crate) egl_display: EGLDisplay,
+    egl_display_ref: Option<EGLDisplayRef>,
     x11_display: *mut Display,
     x11_display_is_owned: bool,
+    pub(crate) headless: bool,
+    pub(crate) gl_api: GLApi,
 }
 
 /// Wrapper for an X11 and EGL display.
+///
+/// This is neither `Send` nor `Sync`. The Xlib display is borrowed from the `Connection` that
+/// returned it, is only valid while that connection lives, and is only safe to use from several
+/// threads if `XInitThreads()` was called before it was opened. Share the `Connection`, which is
+/// `Send` and `Sync`, rather than copying the display pointer to another thread, or call
+/// `Connection::try_clone_for_thread()` to give that thread a display of its own.
 #[derive(Clone)]
 pub struct NativeConnection {
     /// The EGL display associated with that X11 display.
@@ -53,6 +74,8 @@ pub struct NativeConnection {
     /// It is assumed that this EGL display is already initialized, via `eglInitialize()`.
     pub egl_display: EGLDisplay,
     /// The corresponding Xlib Display. This must be present; do not pass NULL.
+    ///
+    /// This is NULL for connections opened with `Connection::new_headless()`.
     pub x11_display: *mut Display,
 }
 impl NativeConnectionInterface for NativeConnection {
@@ -61,10 +84,16 @@ impl NativeConnectionInterface for NativeConnection {
     }
 }
 
+assert_send_sync!(Connection);
+assert_not_send!(NativeConnection);
+assert_not_sync!(NativeConnection);
+
 impl Drop for NativeConnectionWrapper {
     #[inline]
     fn drop(&mut self) {
         unsafe {
+            // Terminate EGL before closing the X display that it was opened on.
+            self.egl_display_ref.take();
             if self.x11_display_is_owned {
                 XCloseDisplay(self.x11_display);
             }
@@ -74,64 +103,185 @@ impl Drop for NativeConnectionWrapper {
 }
 
 impl Connection {
-    /// Connects to the default display.
+    /// Connects to the default display, as named by the `DISPLAY` environment variable.
+    ///
+    /// If the `SURFMAN_DISPLAY` environment variable is set, this connects to the display it
+    /// names instead, as `from_display_name()` does.
+    ///
+    /// Returns `Error::ConnectionFailed` if the X server can't be reached or has no usable EGL.
     #[inline]
     pub fn new() -> Result<Connection, Error> {
+        if let Some(display_name) = env::var_os(connection::DISPLAY_ENV_VAR) {
+            return match display_name.to_str() {
+                Some(display_name) => Connection::from_display_name(display_name),
+                None => Err(Error::DisplayConnectionFailed(
+                    display_name.to_string_lossy().into_owned(),
+                )),
+            };
+        }
+
         unsafe {
-            *X_THREADS_INIT;
+            init_x_threads();
 
             let x11_display = XOpenDisplay(ptr::null());
             if x11_display.is_null() {
                 return Err(Error::ConnectionFailed);
             }
+            Connection::from_x11_display(x11_display, true)
+        }
+    }
+
+    /// Connects to the display with the given name, such as `:1` or `host:0.0`.
+    ///
+    /// The name is passed to `XOpenDisplay()`, so it has the same format as the `DISPLAY`
+    /// environment variable. Returns `Error::DisplayConnectionFailed` with the name if the X
+    /// server can't be reached, and `Error::ConnectionFailed` if it has no usable EGL.
+    pub fn from_display_name(display_name: &str) -> Result<Connection, Error> {
+        let c_display_name = match CString::new(display_name) {
+            Ok(c_display_name) => c_display_name,
+            Err(_) => return Err(Error::DisplayConnectionFailed(display_name.to_owned())),
+        };
+
+        unsafe {
+            init_x_threads();
 
-            let egl_display = create_egl_display(x11_display);
+            let x11_display = XOpenDisplay(c_display_name.as_ptr());
+            if x11_display.is_null() {
+                return Err(Error::DisplayConnectionFailed(display_name.to_owned()));
+            }
+            Connection::from_x11_display(x11_display, true)
+        }
+    }
 
+    /// Opens a connection without an X server, using the surfaceless Mesa platform or, failing
+    /// that, the first EGL device.
+    ///
+    /// Only generic surfaces can be created on this connection. Attempting to create a widget
+    /// surface returns `Error::ConnectionRequired`.
+    pub fn new_headless() -> Result<Connection, Error> {
+        unsafe {
+            let egl_display_ref = connection::create_surfaceless_egl_display()?;
             Ok(Connection {
                 native_connection: Arc::new(NativeConnectionWrapper {
-                    x11_display,
-                    x11_display_is_owned: true,
-                    egl_display,
+                    x11_display: ptr::null_mut(),
+                    x11_display_is_owned: false,
+                    egl_display: egl_display_ref.egl_display(),
+                    egl_display_ref: Some(egl_display_ref),
+                    headless: true,
+                    gl_api: GLApi::GL,
                 }),
             })
         }
     }
 
+    /// Connects to the default display, creating contexts for the given OpenGL API flavor.
+    ///
+    /// Returns `Error::UnsupportedGLType` if the EGL implementation doesn't support that API.
+    pub fn new_with_api(gl_api: GLApi) -> Result<Connection, Error> {
+        let mut connection = Connection::new()?;
+        unsafe {
+            if !device::display_supports_api(connection.native_connection.egl_display, gl_api) {
+                return Err(Error::UnsupportedGLType);
+            }
+        }
+        Arc::get_mut(&mut connection.native_connection)
+            .unwrap()
+            .gl_api = gl_api;
+        Ok(connection)
+    }
+
+    /// Tells surfman that the application has already called `XInitThreads()`, so that surfman
+    /// doesn't call it again.
+    ///
+    /// Every connection calls `XInitThreads()` before surfman makes its first Xlib call, which
+    /// suffices as long as surfman is the first to use Xlib. It doesn't if the application, or a
+    /// library such as a windowing toolkit, has already opened a display: Xlib needs
+    /// `XInitThreads()` to come before any other Xlib call, and calling it while another thread
+    /// uses a display can deadlock. Such applications must call `XInitThreads()` themselves before
+    /// anything else touches Xlib, and then call this before creating any connection.
+    ///
+    /// This has no effect once a connection has been created.
+    #[inline]
+    pub fn assume_x_threads_initialized() {
+        X_THREADS_INIT.call_once(|| {});
+    }
+
     /// Wraps an existing X11 `Display` in a `Connection`.
     ///
     /// Important: Before calling this function, X11 must have be initialized in a thread-safe
     /// manner by using `XInitThreads()`. Otherwise, it will not be safe to use `surfman` from
-    /// multiple threads.
+    /// multiple threads. Call `assume_x_threads_initialized()` beforehand to tell surfman so.
     ///
     /// The display is not retained, as there is no way to do that in the X11 API. Therefore, it is
     /// the caller's responsibility to ensure that the display connection is not closed before this
-    /// `Connection` object is disposed of.
+    /// `Connection` object is disposed of. Likewise, the EGL display is never terminated.
     #[inline]
     pub unsafe fn from_native_connection(
         native_connection: NativeConnection,
     ) -> Result<Connection, Error> {
+        init_x_threads();
         Ok(Connection {
             native_connection: Arc::new(NativeConnectionWrapper {
                 egl_display: native_connection.egl_display,
+                egl_display_ref: Some(EGLDisplayRef::borrow(native_connection.egl_display)),
                 x11_display: native_connection.x11_display,
                 x11_display_is_owned: false,
+                headless: native_connection.x11_display.is_null(),
+                gl_api: GLApi::GL,
             }),
         })
     }
 
     fn from_x11_display(x11_display: *mut Display, is_owned: bool) -> Result<Connection, Error> {
         unsafe {
-            let egl_display = create_egl_display(x11_display);
+            let egl_display_ref = match create_egl_display(x11_display, is_owned) {
+                Ok(egl_display_ref) => egl_display_ref,
+                Err(err) => {
+                    if is_owned {
+                        XCloseDisplay(x11_display);
+                    }
+                    return Err(err);
+                }
+            };
             Ok(Connection {
                 native_connection: Arc::new(NativeConnectionWrapper {
-                    egl_display,
+                    egl_display: egl_display_ref.egl_display(),
+                    egl_display_ref: Some(egl_display_ref),
                     x11_display,
                     x11_display_is_owned: is_owned,
+                    headless: false,
+                    gl_api: GLApi::GL,
                 }),
             })
         }
     }
 
+    /// Opens a separate connection to the same X server, with a display of its own, for a thread
+    /// that makes its own Xlib calls.
+    ///
+    /// The new connection opens the display that this one's `NativeConnection` names, and closes
+    /// it when dropped, so the display pointer that it hands out can't outlive it the way a copy
+    /// of this one's can. Windows are identified by the X server, so widgets work with either
+    /// connection. Headless connections open another headless connection. Returns
+    /// `Error::DisplayConnectionFailed` if the X server can't be reached again.
+    pub fn try_clone_for_thread(&self) -> Result<Connection, Error> {
+        let mut connection = if self.native_connection.headless {
+            Connection::new_headless()?
+        } else {
+            let display_name = unsafe {
+                let display_guard = self.native_connection.lock_display();
+                CStr::from_ptr(XDisplayString(display_guard.display()))
+                    .to_string_lossy()
+                    .into_owned()
+            };
+            Connection::from_display_name(&display_name)?
+        };
+        Arc::get_mut(&mut connection.native_connection)
+            .unwrap()
+            .gl_api = self.native_connection.gl_api;
+        Ok(connection)
+    }
+
     /// Returns the underlying native connection.
     #[inline]
     pub fn native_connection(&self) -> NativeConnection {
@@ -144,7 +294,17 @@ impl Connection {
     /// Returns the OpenGL API flavor that this connection supports (OpenGL or OpenGL ES).
     #[inline]
     pub fn gl_api(&self) -> GLApi {
-        GLApi::GL
+        self.native_connection.gl_api
+    }
+
+    /// Returns true if EGL advertises the named extension, either for this connection's display
+    /// or as a client extension.
+    pub fn supports_extension(&self, name: &str) -> bool {
+        unsafe {
+            let egl_display = self.native_connection.egl_display;
+            device::display_supports_extension(egl_display, name)
+                || device::client_supports_extension(name)
+        }
     }
 
     /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
@@ -173,12 +333,79 @@ impl Connection {
         Ok(Adapter::software())
     }
 
+    /// Returns the adapter whose driver or device name contains `needle`, ignoring case.
+    ///
+    /// Mesa picks the GPU when the display is opened, so only that adapter can match. Requires
+    /// `EGL_EXT_device_query`; without it, this returns `Error::NoAdapterFound`.
+    #[inline]
+    pub fn create_adapter_by_name(&self, needle: &str) -> Result<Adapter, Error> {
+        unsafe { Adapter::from_display_name(self.native_connection.egl_display, needle) }
+    }
+
+    /// Returns a key that describes the adapter, which can be saved and passed to
+    /// `find_adapter()` in a later run to find the same adapter again.
+    #[inline]
+    pub fn adapter_key(&self, adapter: &Adapter) -> Result<AdapterKey, Error> {
+        unsafe { Ok(adapter.key(ADAPTER_KEY_BACKEND, self.native_connection.egl_display)) }
+    }
+
+    /// Returns the adapter that a key from `adapter_key()` describes.
+    ///
+    /// Mesa picks the GPU when the display is opened, so a hardware adapter is only found if the
+    /// display is on the same GPU as when the key was taken. Returns `Error::IncompatibleAdapter`
+    /// if the key was taken with another backend, and `Error::NoAdapterFound` if the GPU is gone.
+    #[inline]
+    pub fn find_adapter(&self, key: &AdapterKey) -> Result<Adapter, Error> {
+        unsafe { Adapter::find(ADAPTER_KEY_BACKEND, self.native_connection.egl_display, key) }
+    }
+
+    /// Returns true if the two adapters are the same adapter.
+    #[inline]
+    pub fn is_same_adapter(&self, a: &Adapter, b: &Adapter) -> bool {
+        match (self.adapter_key(a), self.adapter_key(b)) {
+            (Ok(a), Ok(b)) => a.matches(&b),
+            _ => false,
+        }
+    }
+
+    /// Returns the adapter for the GPU that drives the output the window is on.
+    ///
+    /// The GPU is found with RandR, which needs libXrandr, and by matching the EDIDs of the
+    /// displays that RandR reports with the DRM connectors in sysfs. Mesa renders on the GPU
+    /// driving the X screen, unless PRIME offloading moves rendering to another GPU, so this
+    /// returns a low-power adapter if the window is on an output of the X screen's GPU, and a
+    /// high-performance one otherwise.
+    ///
+    /// Returns `Error::NoAdapterFound` if the GPU can't be determined, and
+    /// `Error::ConnectionRequired` for headless connections.
+    pub fn adapter_for_widget(&self, native_widget: &NativeWidget) -> Result<Adapter, Error> {
+        if self.native_connection.headless {
+            return Err(Error::ConnectionRequired);
+        }
+        let presenting_identity = {
+            let display_guard = self.native_connection.lock_display();
+            unsafe {
+                randr::window_presenting_identity(display_guard.display(), native_widget.window)
+            }
+        }
+        .ok_or(Error::NoAdapterFound)?;
+        let display_identity =
+            unsafe { device::display_identity(self.native_connection.egl_display) };
+        if identity::identities_match(&presenting_identity, &display_identity) {
+            Ok(Adapter::low_power())
+        } else {
+            Ok(Adapter::hardware())
+        }
+    }
+
     /// Opens the hardware device corresponding to the given adapter.
     ///
     /// Device handles are local to a single thread.
     #[inline]
     pub fn create_device(&self, adapter: &Adapter) -> Result<Device, Error> {
-        Device::new(self, adapter)
+        let device = Device::new(self, adapter)?;
+        acceleration::check_device(&device);
+        Ok(device)
     }
 
     /// Opens the hardware device corresponding to the adapter wrapped in the given native
@@ -229,14 +456,15 @@ impl Connection {
     }
 
     /// Create a native widget from a raw pointer
+    ///
+    /// The real size of the window is queried from the X server. If it differs from `size`,
+    /// `native_widget_adjusted_size()` reports it.
     pub unsafe fn create_native_widget_from_ptr(
         &self,
         raw: *mut c_void,
-        _size: Size2D<i32>,
+        size: Size2D<i32>,
     ) -> NativeWidget {
-        NativeWidget {
-            window: std::mem::transmute(raw),
-        }
+        self.native_widget(std::mem::transmute(raw), size)
     }
 
     /// Create a native widget type from the given `RawWindowHandle`.
@@ -244,14 +472,12 @@ impl Connection {
     pub fn create_native_widget_from_raw_window_handle(
         &self,
         raw_handle: rwh_05::RawWindowHandle,
-        _size: Size2D<i32>,
+        size: Size2D<i32>,
     ) -> Result<NativeWidget, Error> {
         use rwh_05::RawWindowHandle::Xlib;
 
         match raw_handle {
-            Xlib(handle) => Ok(NativeWidget {
-                window: handle.window,
-            }),
+            Xlib(handle) => Ok(self.native_widget(handle.window, size)),
             _ => Err(Error::IncompatibleNativeWidget),
         }
     }
@@ -261,17 +487,32 @@ impl Connection {
     pub fn create_native_widget_from_window_handle(
         &self,
         handle: rwh_06::WindowHandle,
-        _size: Size2D<i32>,
+        size: Size2D<i32>,
     ) -> Result<NativeWidget, Error> {
         use rwh_06::RawWindowHandle::Xlib;
 
         match handle.as_raw() {
-            Xlib(handle) => Ok(NativeWidget {
-                window: handle.window,
-            }),
+            Xlib(handle) => Ok(self.native_widget(handle.window, size)),
             _ => Err(Error::IncompatibleNativeWidget),
         }
     }
+
+    /// Returns the real size of a native widget if it differs from the size that the widget was
+    /// created with.
+    #[inline]
+    pub fn native_widget_adjusted_size(&self, native_widget: &NativeWidget) -> Option<Size2D<i32>> {
+        native_widget.adjusted_size
+    }
+
+    fn native_widget(&self, window: Window, size: Size2D<i32>) -> NativeWidget {
+        let display_guard = self.native_connection.lock_display();
+        let actual_size = unsafe { surface::window_size(display_guard.display(), window) };
+        NativeWidget {
+            window,
+            adjusted_size: actual_size
+                .and_then(|actual_size| crate::surface::adjusted_widget_size(size, actual_size)),
+        }
+    }
 }
 
 impl NativeConnectionWrapper {
@@ -286,6 +527,45 @@ impl NativeConnectionWrapper {
             }
         }
     }
+
+    // Returns the depth of the default visual, which new windows get unless told otherwise.
+    pub(crate) fn default_depth(&self) -> i32 {
+        let display_guard = self.lock_display();
+        unsafe {
+            let display = display_guard.display();
+            XDefaultDepth(display, XDefaultScreen(display))
+        }
+    }
+
+    // Returns the depth of the visual with the given ID, if the X server offers it.
+    pub(crate) fn visual_depth(&self, visual_id: VisualID) -> Option<i32> {
+        if visual_id == 0 {
+            return None;
+        }
+
+        let display_guard = self.lock_display();
+        unsafe {
+            let mut template: XVisualInfo = mem::zeroed();
+            template.visualid = visual_id;
+            let mut visual_count = 0;
+            let visual_infos = XGetVisualInfo(
+                display_guard.display(),
+                VisualIDMask,
+                &mut template,
+                &mut visual_count,
+            );
+            if visual_infos.is_null() {
+                return None;
+            }
+            let depth = if visual_count > 0 {
+                Some((*visual_infos).depth)
+            } else {
+                None
+            };
+            XFree(visual_infos as *mut c_void);
+            depth
+        }
+    }
 }
 
 pub(crate) struct DisplayGuard<'a> {
@@ -308,19 +588,35 @@ impl<'a> DisplayGuard<'a> {
     }
 }
 
-unsafe fn create_egl_display(display: *mut Display) -> EGLDisplay {
-    EGL_FUNCTIONS.with(|egl| {
-        let display_attributes = [egl::NONE as EGLAttrib];
-        let egl_display = egl.GetPlatformDisplay(
-            EGL_PLATFORM_X11_KHR,
-            display as *mut c_void,
-            display_attributes.as_ptr(),
-        );
-
-        let (mut egl_major_version, mut egl_minor_version) = (0, 0);
-        let ok = egl.Initialize(egl_display, &mut egl_major_version, &mut egl_minor_version);
-        assert_ne!(ok, egl::FALSE);
-
-        egl_display
-    })
+// Calls `XInitThreads()`, unless it has been called already or the application has said that it
+// called it. This must come before surfman makes any other Xlib call, including through EGL.
+// Threads racing into it wait for the first to finish.
+fn init_x_threads() {
+    X_THREADS_INIT.call_once(|| unsafe {
+        XInitThreads();
+    });
+}
+
+// The EGL display is terminated with the last connection using it only if `owned` is true.
+//
+// Fails with `PlatformDisplayFailed` if EGL can't create a display for the X server at all, and
+// with `ConnectionFailed` if it can't initialize it, so that a multi connection can fall back to
+// its alternate backend.
+pub(crate) unsafe fn create_egl_display(
+    display: *mut Display,
+    owned: bool,
+) -> Result<EGLDisplayRef, Error> {
+    // With a null display, EGL opens its own with Xlib.
+    init_x_threads();
+    EGLDisplayRef::open(
+        || {
+            let (egl_display, _) = device::get_platform_display(
+                &DisplayFunctions::system(),
+                EGL_PLATFORM_X11_KHR,
+                display as *mut c_void,
+            )?;
+            Ok(egl_display)
+        },
+        owned,
+    )
 }
@@ -3,7 +3,15 @@
 //! A wrapper around X11 `EGLDisplay`s.
 
 use super::connection::{Connection, NativeConnectionWrapper};
-use crate::{Error, GLApi};
+use crate::context::NegotiatedVersions;
+use crate::lost::LostContexts;
+use crate::platform::generic::egl::context;
+use crate::platform::generic::egl::device;
+use crate::ColorProfileChangedHandler;
+use crate::DebugFill;
+use crate::LowMemoryOptions;
+use crate::{CancelToken, DeviceIdentity, Error, GLApi};
+use crate::{SchedulingClass, SchedulingEnforcement};
 
 use std::sync::Arc;
 
@@ -15,6 +23,13 @@ pub use crate::platform::unix::generic::device::Adapter;
 pub struct Device {
     pub(crate) native_connection: Arc<NativeConnectionWrapper>,
     pub(crate) adapter: Adapter,
+    pub(crate) cancel_token: CancelToken,
+    pub(crate) scheduling_class: SchedulingClass,
+    pub(crate) low_memory_options: Option<LowMemoryOptions>,
+    pub(crate) debug_fill: DebugFill,
+    pub(crate) lost_contexts: LostContexts,
+    pub(crate) negotiated_versions: NegotiatedVersions,
+    pub(crate) color_profile_changed_handler: Option<ColorProfileChangedHandler>,
 }
 
 /// Wraps an adapter.
@@ -26,12 +41,21 @@ pub struct NativeDevice {
     pub adapter: Adapter,
 }
 
+assert_send_sync!(NativeDevice);
+
 impl Device {
     #[inline]
     pub(crate) fn new(connection: &Connection, adapter: &Adapter) -> Result<Device, Error> {
         Ok(Device {
             native_connection: connection.native_connection.clone(),
             adapter: (*adapter).clone(),
+            cancel_token: CancelToken::new(),
+            scheduling_class: SchedulingClass::Interactive,
+            low_memory_options: None,
+            debug_fill: DebugFill::None,
+            lost_contexts: LostContexts::default(),
+            negotiated_versions: NegotiatedVersions::default(),
+            color_profile_changed_handler: None,
         })
     }
 
@@ -63,6 +87,91 @@ impl Device {
     /// Returns the OpenGL API flavor that this device supports (OpenGL or OpenGL ES).
     #[inline]
     pub fn gl_api(&self) -> GLApi {
-        GLApi::GL
+        self.native_connection.gl_api
+    }
+
+    /// Returns the identity of the physical GPU that this device renders with.
+    ///
+    /// This comes from `EGL_EXT_device_drm`. If the EGL implementation doesn't support it, the
+    /// identity is unknown and matches nothing.
+    #[inline]
+    pub fn identity(&self) -> DeviceIdentity {
+        unsafe { self.adapter.identity(self.native_connection.egl_display) }
+    }
+
+    /// Returns a token that can cancel context and surface creation on this device from any
+    /// thread.
+    #[inline]
+    pub fn cancellation_token(&self) -> CancelToken {
+        self.cancel_token.clone()
+    }
+
+    /// Sets what kind of work this device's contexts do, and returns how that is enforced.
+    ///
+    /// `Background` devices create their contexts at `ContextPriority::Low` if the driver
+    /// supports `EGL_IMG_context_priority`. Mesa only supports it on some drivers, so otherwise
+    /// surfman lowers the priority of the kernel contexts itself on i915 and amdgpu, where the
+    /// kernel allows that, and paces the device's swap chains in software elsewhere. See
+    /// `SchedulingEnforcement`.
+    pub fn set_scheduling_class(&mut self, class: SchedulingClass) -> SchedulingEnforcement {
+        self.scheduling_class = class;
+        self.scheduling_enforcement()
+    }
+
+    /// Returns the scheduling class that was last set with `set_scheduling_class()`.
+    #[inline]
+    pub fn scheduling_class(&self) -> SchedulingClass {
+        self.scheduling_class
+    }
+
+    /// Returns how this device enforces its scheduling class.
+    pub fn scheduling_enforcement(&self) -> SchedulingEnforcement {
+        let egl_display = self.native_connection.egl_display;
+        let driver_priorities =
+            unsafe { device::display_supports_extension(egl_display, "EGL_IMG_context_priority") };
+        let kernel_priorities =
+            !driver_priorities && device::kernel_priorities_supported(egl_display);
+        self.scheduling_class
+            .enforcement(driver_priorities, kernel_priorities)
+    }
+
+    /// Makes this device save memory at the expense of rendering quality until
+    /// `exit_low_memory_mode()` is called, and returns how many pooled surfaces were destroyed.
+    ///
+    /// Entering low-memory mode again replaces the options. See `LowMemoryOptions`.
+    pub fn enter_low_memory_mode(&mut self, options: LowMemoryOptions) -> usize {
+        self.low_memory_options = Some(options);
+        if !options.purge_pools {
+            return 0;
+        }
+        unsafe { context::purge_shared_placeholders(self.native_connection.egl_display) }
+    }
+
+    /// Leaves low-memory mode, so that surfaces created from now on get their usual formats.
+    ///
+    /// Surfaces created in low-memory mode keep their formats until they are destroyed.
+    #[inline]
+    pub fn exit_low_memory_mode(&mut self) {
+        self.low_memory_options = None;
+    }
+
+    /// Returns the options that this device entered low-memory mode with, or `None` if it isn't
+    /// in low-memory mode.
+    #[inline]
+    pub fn low_memory_options(&self) -> Option<LowMemoryOptions> {
+        self.low_memory_options
+    }
+
+    /// Makes this device fill each surface that it creates from now on, and each surface that a
+    /// swap chain recycles, with a recognizable pattern. See `DebugFill`.
+    #[inline]
+    pub fn set_debug_surface_fill(&mut self, fill: DebugFill) {
+        self.debug_fill = fill;
+    }
+
+    /// Returns the debug fill that was last set with `set_debug_surface_fill()`.
+    #[inline]
+    pub fn debug_surface_fill(&self) -> DebugFill {
+        self.debug_fill
     }
 }
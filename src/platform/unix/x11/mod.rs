@@ -7,6 +7,8 @@ pub mod context;
 pub mod device;
 pub mod surface;
 
+mod randr;
+
 #[path = "../../../implementation/mod.rs"]
 mod implementation;
 
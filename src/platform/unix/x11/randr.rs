@@ -0,0 +1,220 @@
+// surfman/surfman/src/platform/unix/x11/randr.rs
+//
+//! Finds the GPU that drives the output an X11 window is on, with RandR.
+//!
+//! RandR says where each CRTC is on the screen and which outputs it drives, but not which GPU
+//! the outputs belong to, so outputs are matched with DRM connectors by the EDID of the display
+//! that they both report. libXrandr is loaded when it's first needed, so that surfman doesn't
+//! need it to build or to start.
+
+use crate::identity::{self, DisplayOutput};
+use crate::DeviceIdentity;
+
+use euclid::default::{Point2D, Rect, Size2D};
+use libc::{dlopen, dlsym, RTLD_LAZY};
+use std::ffi::CString;
+use std::mem;
+use std::os::raw::{c_char, c_int, c_long, c_uchar, c_uint, c_ulong, c_ushort, c_void};
+use std::path::Path;
+use std::ptr;
+use std::slice;
+use x11::xlib::{self, Atom, Bool, Display, Time, Window};
+use x11::xlib::{XFree, XGetWindowAttributes, XInternAtom, XTranslateCoordinates};
+
+type RRCrtc = xlib::XID;
+type RROutput = xlib::XID;
+type RRMode = xlib::XID;
+
+#[repr(C)]
+struct XRRScreenResources {
+    timestamp: Time,
+    config_timestamp: Time,
+    ncrtc: c_int,
+    crtcs: *mut RRCrtc,
+    noutput: c_int,
+    outputs: *mut RROutput,
+    nmode: c_int,
+    modes: *mut c_void,
+}
+
+#[repr(C)]
+struct XRRCrtcInfo {
+    timestamp: Time,
+    x: c_int,
+    y: c_int,
+    width: c_uint,
+    height: c_uint,
+    mode: RRMode,
+    rotation: c_ushort,
+    noutput: c_int,
+    outputs: *mut RROutput,
+    rotations: c_ushort,
+    npossible: c_int,
+    possible: *mut RROutput,
+}
+
+#[allow(non_snake_case)]
+struct Xrandr {
+    XRRGetScreenResourcesCurrent:
+        unsafe extern "C" fn(*mut Display, Window) -> *mut XRRScreenResources,
+    XRRFreeScreenResources: unsafe extern "C" fn(*mut XRRScreenResources),
+    XRRGetCrtcInfo:
+        unsafe extern "C" fn(*mut Display, *mut XRRScreenResources, RRCrtc) -> *mut XRRCrtcInfo,
+    XRRFreeCrtcInfo: unsafe extern "C" fn(*mut XRRCrtcInfo),
+    XRRGetOutputProperty: unsafe extern "C" fn(
+        *mut Display,
+        RROutput,
+        Atom,
+        c_long,
+        c_long,
+        Bool,
+        Bool,
+        Atom,
+        *mut Atom,
+        *mut c_int,
+        *mut c_ulong,
+        *mut c_ulong,
+        *mut *mut c_uchar,
+    ) -> c_int,
+}
+
+lazy_static! {
+    static ref XRANDR: Option<Xrandr> = unsafe { Xrandr::load() };
+}
+
+impl Xrandr {
+    unsafe fn load() -> Option<Xrandr> {
+        let handle = dlopen(b"libXrandr.so.2\0".as_ptr() as *const c_char, RTLD_LAZY);
+        if handle.is_null() {
+            return None;
+        }
+        let symbol = |name: &[u8]| {
+            let symbol = dlsym(handle, name.as_ptr() as *const c_char);
+            if symbol.is_null() {
+                None
+            } else {
+                Some(symbol)
+            }
+        };
+        Some(Xrandr {
+            XRRGetScreenResourcesCurrent: mem::transmute(symbol(
+                b"XRRGetScreenResourcesCurrent\0",
+            )?),
+            XRRFreeScreenResources: mem::transmute(symbol(b"XRRFreeScreenResources\0")?),
+            XRRGetCrtcInfo: mem::transmute(symbol(b"XRRGetCrtcInfo\0")?),
+            XRRFreeCrtcInfo: mem::transmute(symbol(b"XRRFreeCrtcInfo\0")?),
+            XRRGetOutputProperty: mem::transmute(symbol(b"XRRGetOutputProperty\0")?),
+        })
+    }
+}
+
+// Returns the identity of the GPU that drives the output that the window covers the most of, or
+// `None` if libXrandr isn't available or the GPU can't be matched with a DRM device.
+pub(crate) unsafe fn window_presenting_identity(
+    display: *mut Display,
+    window: Window,
+) -> Option<DeviceIdentity> {
+    let xrandr = XRANDR.as_ref()?;
+
+    let mut window_attributes = mem::zeroed();
+    if XGetWindowAttributes(display, window, &mut window_attributes) == 0 {
+        return None;
+    }
+    let root_window = window_attributes.root;
+    let (mut x, mut y, mut child) = (0, 0, 0);
+    XTranslateCoordinates(
+        display,
+        window,
+        root_window,
+        0,
+        0,
+        &mut x,
+        &mut y,
+        &mut child,
+    );
+    let window_bounds = Rect::new(
+        Point2D::new(x, y),
+        Size2D::new(window_attributes.width, window_attributes.height),
+    );
+
+    let resources = (xrandr.XRRGetScreenResourcesCurrent)(display, root_window);
+    if resources.is_null() {
+        return None;
+    }
+    let edid_atom_name = CString::new("EDID").unwrap();
+    let edid_atom = XInternAtom(display, edid_atom_name.as_ptr(), xlib::True);
+    let connectors = identity::drm_connectors();
+
+    let mut outputs = vec![];
+    let crtcs = slice::from_raw_parts((*resources).crtcs, (*resources).ncrtc as usize);
+    for &crtc in crtcs {
+        let crtc_info = (xrandr.XRRGetCrtcInfo)(display, resources, crtc);
+        if crtc_info.is_null() {
+            continue;
+        }
+        // CRTCs without a mode are switched off.
+        if (*crtc_info).mode != 0 {
+            let crtc_outputs =
+                slice::from_raw_parts((*crtc_info).outputs, (*crtc_info).noutput as usize);
+            let identity = crtc_outputs
+                .iter()
+                .filter_map(|&output| read_edid(xrandr, display, output, edid_atom))
+                .filter_map(|edid| {
+                    let card = identity::drm_card_for_edid(&edid, &connectors)?;
+                    DeviceIdentity::from_drm_node(Path::new("/dev/dri").join(card))
+                })
+                .next()
+                .unwrap_or_default();
+            let bounds = Rect::new(
+                Point2D::new((*crtc_info).x, (*crtc_info).y),
+                Size2D::new((*crtc_info).width as i32, (*crtc_info).height as i32),
+            );
+            outputs.push(DisplayOutput { bounds, identity });
+        }
+        (xrandr.XRRFreeCrtcInfo)(crtc_info);
+    }
+    (xrandr.XRRFreeScreenResources)(resources);
+
+    identity::presenting_identity(&window_bounds, &outputs)
+}
+
+// Returns the EDID of the display attached to an output.
+unsafe fn read_edid(
+    xrandr: &Xrandr,
+    display: *mut Display,
+    output: RROutput,
+    edid_atom: Atom,
+) -> Option<Vec<u8>> {
+    if edid_atom == 0 {
+        return None;
+    }
+    let (mut actual_type, mut actual_format): (Atom, c_int) = (0, 0);
+    let (mut item_count, mut bytes_after): (c_ulong, c_ulong) = (0, 0);
+    let mut data: *mut c_uchar = ptr::null_mut();
+    let result = (xrandr.XRRGetOutputProperty)(
+        display,
+        output,
+        edid_atom,
+        0,
+        c_long::MAX / 4,
+        xlib::False,
+        xlib::False,
+        xlib::AnyPropertyType as Atom,
+        &mut actual_type,
+        &mut actual_format,
+        &mut item_count,
+        &mut bytes_after,
+        &mut data,
+    );
+    if result != xlib::Success as c_int || data.is_null() {
+        return None;
+    }
+
+    let edid = if actual_format == 8 && item_count > 0 {
+        Some(slice::from_raw_parts(data, item_count as usize).to_vec())
+    } else {
+        None
+    };
+    XFree(data as *mut c_void);
+    edid
+}
@@ -4,18 +4,52 @@
 
 use super::context::{Context, GL_FUNCTIONS};
 use super::device::Device;
+use super::randr;
+use crate::bulk;
+use crate::clear::ClearRegion;
+use crate::convert::{self, Swizzle};
 use crate::egl;
 use crate::egl::types::EGLint;
 use crate::gl;
 use crate::gl::types::{GLenum, GLuint};
+use crate::identities_match;
 use crate::platform::generic::egl::context;
-use crate::platform::generic::egl::surface::{EGLBackedSurface, EGLSurfaceTexture};
-use crate::{Error, SurfaceAccess, SurfaceInfo, SurfaceType};
+use crate::platform::generic::egl::surface::{EGLBackedSurface, EGLFence, EGLSurfaceTexture};
+use crate::readback;
+use crate::surface::check_surface_size;
+use crate::upload;
+use crate::vsync::AutoVsync;
+use crate::BufferRelease;
+use crate::DmaBuf;
+use crate::ColorProfileChangedHandler;
+use crate::GLCapabilities;
+use crate::InvalidateSet;
+use crate::LowMemoryOptions;
+use crate::PausedPresentation;
+use crate::PresentationStatistics;
+use crate::SurfaceFence;
+use crate::SurfaceOptions;
+use crate::SurfaceResizedHandler;
+use crate::SurfaceScaleChangedHandler;
+use crate::TextureOwnership;
+use crate::{AutoVsyncPolicy, AutoVsyncStats};
+use crate::{
+    ColorProfile, ContextID, Error, PresentRecord, SurfaceAccess, SurfaceInfo, SurfaceType,
+};
+use crate::{Purgeability, ReclaimResult};
+use crate::{SurfaceConfig, SurfaceIntent, SurfaceTransform};
 
-use euclid::default::Size2D;
+use euclid::default::{Rect, Size2D};
+use std::ffi::CString;
 use std::marker::PhantomData;
-use std::os::raw::c_void;
-use x11::xlib::{Window, XGetGeometry};
+use std::mem;
+use std::os::fd::BorrowedFd;
+use std::os::raw::{c_int, c_long, c_uchar, c_ulong, c_void};
+use std::ptr;
+use std::slice;
+use std::time::Duration;
+use x11::xlib::{self, Atom, VisualID, Window, XFree, XGetGeometry, XGetWindowAttributes};
+use x11::xlib::{XGetWindowProperty, XInternAtom, XRootWindow, XScreenNumberOfScreen};
 
 // FIXME(pcwalton): Is this right, or should it be `TEXTURE_EXTERNAL_OES`?
 const SURFACE_GL_TEXTURE_TARGET: GLenum = gl::TEXTURE_2D;
@@ -51,12 +85,18 @@ pub struct Surface(pub(crate) EGLBackedSurface);
 #[derive(Debug)]
 pub struct SurfaceTexture(pub(crate) EGLSurfaceTexture);
 
-/// A wrapper for a Wayland surface, with associated size.
+/// A wrapper for an X11 window, with associated size.
+///
+/// Widgets are `Send` and `Sync`: windows are identified by the X server, so a widget can be
+/// used from any thread, and with any connection to the same server.
 #[derive(Clone)]
 pub struct NativeWidget {
     pub(crate) window: Window,
+    pub(crate) adjusted_size: Option<Size2D<i32>>,
 }
 
+assert_send_sync!(NativeWidget);
+
 unsafe impl Send for Surface {}
 
 impl Device {
@@ -67,25 +107,71 @@ impl Device {
     pub fn create_surface(
         &mut self,
         context: &Context,
-        _: SurfaceAccess,
+        access: SurfaceAccess,
+        surface_type: SurfaceType<NativeWidget>,
+    ) -> Result<Surface, Error> {
+        self.create_surface_with_options(context, access, surface_type, SurfaceOptions::default())
+    }
+
+    /// Creates a surface of each of the given types, in order.
+    ///
+    /// The device's cancellation token is checked before each surface, as well as inside each
+    /// creation. If any surface fails or is cancelled, the ones created so far are destroyed and
+    /// the error is returned, so the batch creates either all of its surfaces or none.
+    pub fn create_surfaces<I>(
+        &mut self,
+        context: &mut Context,
+        access: SurfaceAccess,
+        surface_types: I,
+    ) -> Result<Vec<Surface>, Error>
+    where
+        I: IntoIterator<Item = SurfaceType<NativeWidget>>,
+    {
+        bulk::create_surfaces(self, context, access, surface_types)
+    }
+
+    /// Creates either a generic or a widget surface, with options such as its colorspace.
+    ///
+    /// `create_surface()` is this method with the default options. Returns
+    /// `Error::UnsupportedSurfaceFormat` if the platform can't create a surface with the options.
+    pub fn create_surface_with_options(
+        &mut self,
+        context: &Context,
+        access: SurfaceAccess,
         surface_type: SurfaceType<NativeWidget>,
+        options: SurfaceOptions,
     ) -> Result<Surface, Error> {
-        match surface_type {
-            SurfaceType::Generic { size } => self.create_generic_surface(context, &size),
+        let mut surface = match surface_type {
+            SurfaceType::Generic { size } => {
+                self.create_generic_surface(context, &size, access, options)
+            }
+            SurfaceType::Widget { .. } if self.native_connection.headless => {
+                Err(Error::ConnectionRequired)
+            }
             SurfaceType::Widget { native_widget } => unsafe {
-                self.create_window_surface(context, native_widget.window)
+                self.create_window_surface(context, native_widget.window, access, options)
             },
-        }
+        }?;
+        self.debug_fill_new_surface(context, &mut surface);
+        Ok(surface)
     }
 
     fn create_generic_surface(
         &mut self,
         context: &Context,
         size: &Size2D<i32>,
+        access: SurfaceAccess,
+        options: SurfaceOptions,
     ) -> Result<Surface, Error> {
-        let _guard = self.temporarily_make_context_current(context)?;
+        let _guard = self.make_context_current_guarded(context)?;
         let context_descriptor = self.context_descriptor(context);
         let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        let linear_format =
+            LowMemoryOptions::surface_format(self.low_memory_options, &context_attributes, access);
+        let capabilities = GL_FUNCTIONS.with(GLCapabilities::current);
+        check_surface_size(*size, &capabilities)?;
+        let format = options.generic_surface_format(&capabilities, linear_format)?;
+        self.cancel_token.check()?;
         GL_FUNCTIONS.with(|gl| {
             Ok(Surface(EGLBackedSurface::new_generic(
                 gl,
@@ -94,6 +180,8 @@ impl Device {
                 context.0.id,
                 &context_attributes,
                 size,
+                access,
+                format,
             )))
         })
     }
@@ -101,39 +189,76 @@ impl Device {
     unsafe fn create_window_surface(
         &mut self,
         context: &Context,
-        mut x11_window: Window,
+        x11_window: Window,
+        access: SurfaceAccess,
+        options: SurfaceOptions,
     ) -> Result<Surface, Error> {
+        self.cancel_token.check()?;
+        let context_descriptor = self.context_descriptor(context);
+        options.check_widget_format(&self.context_descriptor_attributes(&context_descriptor))?;
         let egl_config_id = context::get_context_attr(
             self.native_connection.egl_display,
             context.0.egl_context,
             egl::CONFIG_ID as EGLint,
         );
-        let egl_config =
-            context::egl_config_from_id(self.native_connection.egl_display, egl_config_id);
+        let egl_display = self.native_connection.egl_display;
+        let context_config = context::egl_config_from_id(egl_display, egl_config_id);
 
-        let display_guard = self.native_connection.lock_display();
-        let (mut root_window, mut x, mut y, mut width, mut height) = (0, 0, 0, 0, 0);
-        let (mut border_width, mut depth) = (0, 0);
-        XGetGeometry(
-            display_guard.display(),
-            x11_window,
-            &mut root_window,
-            &mut x,
-            &mut y,
-            &mut width,
-            &mut height,
-            &mut border_width,
-            &mut depth,
-        );
-        let size = Size2D::new(width as i32, height as i32);
+        let (size, window_depth) = {
+            let display_guard = self.native_connection.lock_display();
+            (
+                window_size(display_guard.display(), x11_window).unwrap_or_default(),
+                window_depth(display_guard.display(), x11_window),
+            )
+        };
 
-        Ok(Surface(EGLBackedSurface::new_window(
-            self.native_connection.egl_display,
+        // The config's visual must have the window's depth, or X fails with `BadMatch`.
+        let egl_config = context::widget_config(
+            egl_display,
+            context_config,
+            &options,
+            window_depth,
+            &|egl_config| {
+                let visual_id = context::get_config_attr(
+                    egl_display,
+                    egl_config,
+                    egl::NATIVE_VISUAL_ID as EGLint,
+                );
+                self.native_connection.visual_depth(visual_id as VisualID)
+            },
+        )?;
+
+        // EGL wants a pointer to the `Window`. Keep it boxed so that the surface can find the
+        // window again later; `destroy_surface()` frees it.
+        let x11_window = Box::into_raw(Box::new(x11_window));
+        EGLBackedSurface::new_window(
+            egl_display,
             egl_config,
-            &mut x11_window as *mut Window as *mut c_void,
+            x11_window as *mut c_void,
             context.0.id,
             &size,
-        )))
+            access,
+            &options,
+        )
+        .map(Surface)
+        .map_err(|err| {
+            drop(Box::from_raw(x11_window));
+            err
+        })
+    }
+
+    /// Returns the access mode, type, and options that suit a surface of the given size, created
+    /// with the given context for the given use, on this device, along with hints for using it.
+    ///
+    /// This backend can't map surfaces for the CPU, so the access mode is always `GPUOnly`.
+    pub fn recommended_surface_config(
+        &self,
+        context: &Context,
+        intent: SurfaceIntent<NativeWidget>,
+        size: Size2D<i32>,
+    ) -> Result<SurfaceConfig<NativeWidget>, Error> {
+        let capabilities = self.capabilities(context)?;
+        Ok(SurfaceConfig::recommend(intent, size, false, &capabilities))
     }
 
     /// Creates a surface texture from an existing generic surface for use with the given context.
@@ -151,7 +276,7 @@ impl Device {
         context: &mut Context,
         surface: Surface,
     ) -> Result<SurfaceTexture, (Error, Surface)> {
-        let _guard = match self.temporarily_make_context_current(context) {
+        let _guard = match self.make_context_current_guarded(context) {
             Ok(guard) => guard,
             Err(err) => return Err((err, surface)),
         };
@@ -181,7 +306,7 @@ impl Device {
     ) -> Result<SurfaceTexture, Error> {
         let context_descriptor = self.context_descriptor(context);
         let context_attributes = self.context_descriptor_attributes(&context_descriptor);
-        let _guard = match self.temporarily_make_context_current(context) {
+        let _guard = match self.make_context_current_guarded(context) {
             Ok(guard) => guard,
             Err(err) => return Err(err),
         };
@@ -194,8 +319,10 @@ impl Device {
                 context.0.id,
                 &context_attributes,
                 size,
+                SurfaceAccess::GPUOnly,
                 texture_object,
                 egl_target,
+                TextureOwnership::Owned,
             )
         });
         Ok(SurfaceTexture(EGLSurfaceTexture {
@@ -205,6 +332,45 @@ impl Device {
         }))
     }
 
+    /// Wraps an existing GL texture in a generic surface, without copying it.
+    ///
+    /// The texture must be a complete `TEXTURE_2D` of the given size and format, created in the
+    /// supplied context or in a context that shares with it. Only `GL_RGBA8` (or unsized
+    /// `GL_RGBA`) textures are supported. The surface can be rendered to by the supplied context
+    /// and wrapped in a `SurfaceTexture` by other contexts, like any other generic surface.
+    ///
+    /// With `TextureOwnership::Borrowed`, the texture is never deleted by surfman, and must stay
+    /// alive and unmodified in size and format until the surface is destroyed. With
+    /// `TextureOwnership::Owned`, `destroy_surface()` deletes the texture.
+    ///
+    /// Returns `IncompatibleTexture` if the texture doesn't exist in the supplied context.
+    pub fn create_surface_from_texture(
+        &mut self,
+        context: &mut Context,
+        texture_object: GLuint,
+        size: &Size2D<i32>,
+        format: GLenum,
+        ownership: TextureOwnership,
+    ) -> Result<Surface, Error> {
+        let _guard = self.make_context_current_guarded(context)?;
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+        GL_FUNCTIONS.with(|gl| {
+            Ok(Surface(EGLBackedSurface::new_from_foreign_texture(
+                gl,
+                self.native_connection.egl_display,
+                context.0.egl_context,
+                context.0.id,
+                &context_attributes,
+                size,
+                SurfaceAccess::GPUOnly,
+                texture_object,
+                format,
+                ownership,
+            )?))
+        })
+    }
+
     /// Destroys a surface.
     ///
     /// The supplied context must be the context the surface is associated with, or this returns
@@ -217,9 +383,14 @@ impl Device {
         context: &mut Context,
         surface: &mut Surface,
     ) -> Result<(), Error> {
+        let _guard = self.make_context_current_guarded(context);
         GL_FUNCTIONS.with(|gl| {
             let egl_display = self.native_connection.egl_display;
-            surface.0.destroy(gl, egl_display, context.0.id)?;
+            if let Some(x11_window) = surface.0.destroy(gl, egl_display, context.0.id)? {
+                unsafe {
+                    drop(Box::from_raw(x11_window as *mut Window));
+                }
+            }
             Ok(())
         })
     }
@@ -236,7 +407,7 @@ impl Device {
         context: &mut Context,
         surface_texture: SurfaceTexture,
     ) -> Result<Surface, (Error, SurfaceTexture)> {
-        match self.temporarily_make_context_current(context) {
+        match self.make_context_current_guarded(context) {
             Ok(_guard) => GL_FUNCTIONS.with(|gl| Ok(Surface(surface_texture.0.destroy(gl)))),
             Err(err) => Err((err, surface_texture)),
         }
@@ -248,30 +419,499 @@ impl Device {
     /// associated widgets until this method is called.
     ///
     /// The supplied context must match the context the surface was created with, or an
-    /// `IncompatibleSurface` error is returned.
+    /// `IncompatibleSurface` error is returned. The context doesn't need to be current, and the
+    /// context that was current before this call remains current afterward.
+    ///
+    /// The window's size is checked against the surface's with `query_surface_native_size()`
+    /// first, which is a round trip to the X server, and a window that has been resized since the
+    /// surface was last sized fails with `SurfaceSizeMismatch` without presenting.
     pub fn present_surface(&self, context: &Context, surface: &mut Surface) -> Result<(), Error> {
-        surface
-            .0
-            .present(self.native_connection.egl_display, context.0.egl_context)
+        self.check_native_size(surface)?;
+        let egl_display = self.native_connection.egl_display;
+        self.lost_contexts.check(context.0.id, || {
+            GL_FUNCTIONS.with(|gl| {
+                surface
+                    .0
+                    .present(gl, egl_display, context.0.egl_context, context.0.id, None)
+            })
+        })?;
+        self.update_color_profile(surface);
+        Ok(())
+    }
+
+    /// Displays the changed parts of a widget surface on screen.
+    ///
+    /// `damage` lists the rectangles that changed since the last present, measured from the
+    /// bottom left as in OpenGL, and is passed on with `EGL_KHR_swap_buffers_with_damage` where
+    /// the display supports it, so that the compositor only has to recomposite those. Otherwise,
+    /// the whole surface is swapped, as `present_surface()` does. An empty list means that
+    /// nothing changed, and skips the swap entirely.
+    ///
+    /// The window's size is checked as by `present_surface()`, unless the list is empty.
+    pub fn present_surface_with_damage(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        if !damage.is_empty() {
+            self.check_native_size(surface)?;
+        }
+        let egl_display = self.native_connection.egl_display;
+        self.lost_contexts.check(context.0.id, || {
+            GL_FUNCTIONS.with(|gl| {
+                let egl_context = context.0.egl_context;
+                surface
+                    .0
+                    .present(gl, egl_display, egl_context, context.0.id, Some(damage))
+            })
+        })?;
+        self.update_color_profile(surface);
+        Ok(())
+    }
+
+    // Reports a change in the color profile of the output that a widget surface is displayed
+    // on, if one is noticed on this present.
+    fn update_color_profile(&self, surface: &mut Surface) {
+        let handler = match self.color_profile_changed_handler {
+            Some(ref handler) if surface.0.is_widget() => handler,
+            _ => return,
+        };
+        let mut tracker = mem::take(&mut surface.0.color_profile);
+        let changed = tracker.update(|| self.widget_color_profile(surface));
+        surface.0.color_profile = tracker;
+        if let Some(profile) = changed {
+            handler(surface.0.id(), &profile);
+        }
+    }
+
+    /// Pauses the presentation of a widget surface: until it's resumed, each frame presented is
+    /// read back and held instead of shown, and `present_surface()` returns `PresentationPaused`.
+    ///
+    /// Surfaces that render to the front buffer return `UnsupportedPresentMode`, and contexts
+    /// without `glBlitFramebuffer()` return `RequiredExtensionUnavailable`.
+    pub fn pause_presentation(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<PausedPresentation, Error> {
+        let capabilities = self.capabilities(context)?;
+        surface.0.pause_presentation(context.0.id, &capabilities)
+    }
+
+    /// Ends a pause in the presentation of a widget surface.
+    ///
+    /// If `present_pending` is true, the frame held during the pause is drawn back into the
+    /// surface and presented as by `present_surface()`; otherwise, it's discarded.
+    pub fn resume_presentation(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        paused: PausedPresentation,
+        present_pending: bool,
+    ) -> Result<(), Error> {
+        let egl_display = self.native_connection.egl_display;
+        let present = self.lost_contexts.check(context.0.id, || {
+            GL_FUNCTIONS.with(|gl| {
+                let egl_context = context.0.egl_context;
+                surface.0.resume_presentation(
+                    gl,
+                    egl_display,
+                    egl_context,
+                    context.0.id,
+                    paused,
+                    present_pending,
+                )
+            })
+        })?;
+        if present {
+            self.present_surface(context, surface)?;
+        }
+        Ok(())
+    }
+
+    /// Displays the contents of a widget surface on screen, and returns a fence that signals once
+    /// the GPU has finished rendering it.
+    ///
+    /// The fence is an `EGL_SYNC_FENCE`, or a native fence where the display supports
+    /// `EGL_ANDROID_native_fence_sync`, inserted with the surface's context current right after
+    /// its buffers are swapped. Generic surfaces aren't presented, and only get the fence.
+    pub fn present_surface_with_fence(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<SurfaceFence, Error> {
+        if context.0.id != surface.0.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if surface.0.is_widget() {
+            self.present_surface(context, surface)?;
+        }
+        let egl_display = self.native_connection.egl_display;
+        let _guard = self.make_context_current_guarded(context)?;
+        let egl_fence = GL_FUNCTIONS.with(|gl| unsafe { EGLFence::insert(gl, egl_display) })?;
+        Ok(SurfaceFence { egl_fence })
     }
 
-    /// Resizes a widget surface.
+    /// Makes the context wait on the GPU until the fence signals, with `eglWaitSync()`.
+    pub fn wait_fence(&self, context: &Context, fence: &SurfaceFence) -> Result<(), Error> {
+        let _guard = self.make_context_current_guarded(context)?;
+        unsafe { fence.egl_fence.wait(self.native_connection.egl_display) }
+    }
+
+    /// Returns a native fence that signals when the sync file `fd` does, which needs
+    /// `EGL_ANDROID_native_fence_sync`. The descriptor is duplicated, so it stays the caller's.
+    pub fn import_sync_fd(&self, context: &Context, fd: BorrowedFd) -> Result<SurfaceFence, Error> {
+        let _guard = self.make_context_current_guarded(context)?;
+        let egl_fence = unsafe { EGLFence::import(self.native_connection.egl_display, fd)? };
+        Ok(SurfaceFence { egl_fence })
+    }
+
+    /// Resizes a surface in place.
+    ///
+    /// Generic surfaces are reallocated at the new size, keeping their framebuffer objects.
+    /// EGL resizes window surfaces to follow their window, so widget surfaces only record the
+    /// size.
     pub fn resize_surface(
         &self,
-        _context: &Context,
+        context: &Context,
         surface: &mut Surface,
         size: Size2D<i32>,
     ) -> Result<(), Error> {
-        surface.0.size = size;
+        check_surface_size(size, &self.capabilities(context)?)?;
+        let _guard = self.make_context_current_guarded(context)?;
+        GL_FUNCTIONS.with(|gl| {
+            let egl_display = self.native_connection.egl_display;
+            surface
+                .0
+                .resize(gl, egl_display, context.0.egl_context, context.0.id, size)
+        })
+    }
+
+    /// Sets the number of vertical blanks that `present_surface()` waits for before showing each
+    /// frame on a widget surface, and returns the interval that took effect.
+    ///
+    /// Negative intervals are only honored if the surface's EGL config allows them, and are
+    /// otherwise replaced with their absolute value. Intervals outside the range that the config
+    /// supports return `UnsupportedOnThisPlatform`.
+    pub fn set_swap_interval(
+        &mut self,
+        context: &Context,
+        surface: &Surface,
+        interval: i32,
+    ) -> Result<i32, Error> {
+        let egl_display = self.native_connection.egl_display;
+        surface
+            .0
+            .set_swap_interval(egl_display, context.0.egl_context, context.0.id, interval)
+    }
+
+    /// Returns true if the EGL config of a widget surface allows the negative swap intervals of
+    /// adaptive vsync.
+    #[inline]
+    pub fn supports_adaptive_swap(&self, surface: &Surface) -> bool {
+        surface
+            .0
+            .supports_adaptive_swap(self.native_connection.egl_display)
+    }
+
+    /// Lets surfman switch a widget surface between vsync and immediate presentation by itself,
+    /// following `policy`, or stops it with `None`.
+    ///
+    /// The frame time is measured from the return of `eglSwapBuffers()`.
+    pub fn set_auto_swap_interval(
+        &mut self,
+        context: &Context,
+        surface: &mut Surface,
+        policy: Option<AutoVsyncPolicy>,
+    ) -> Result<(), Error> {
+        let egl_display = self.native_connection.egl_display;
+        surface
+            .0
+            .set_auto_swap_interval(egl_display, context.0.egl_context, context.0.id, policy)
+    }
+
+    /// Returns what automatic vsync has decided for a surface, or `None` if it isn't enabled.
+    #[inline]
+    pub fn auto_swap_interval_stats(&self, surface: &Surface) -> Option<AutoVsyncStats> {
+        surface.0.auto_vsync.as_ref().map(AutoVsync::stats)
+    }
+
+    /// Waits until the buffer of the last frame presented on a widget surface has been released,
+    /// or until `timeout` elapses, and reports whether the call had to block.
+    ///
+    /// X11 gives no signal for the release of EGL's buffers, so this waits on a fence inserted
+    /// when the frame was presented, which signals once the GPU has finished rendering it.
+    pub fn wait_previous_buffer_released(
+        &self,
+        surface: &Surface,
+        timeout: Duration,
+    ) -> Result<BufferRelease, Error> {
+        surface.0.wait_previous_buffer_released(timeout)
+    }
+
+    /// Chooses which attachments of a surface are invalidated right after each present.
+    ///
+    /// The color buffer of a widget surface can only be invalidated if its EGL surface doesn't
+    /// preserve it across swaps, and that of a generic surface never can. Either returns
+    /// `PreservedColorBuffer`.
+    pub fn set_post_present_invalidation(
+        &self,
+        surface: &mut Surface,
+        set: InvalidateSet,
+    ) -> Result<(), Error> {
+        surface
+            .0
+            .set_post_present_invalidation(self.native_connection.egl_display, set)
+    }
+
+    /// Returns which attachments of a surface are invalidated right after each present.
+    #[inline]
+    pub fn post_present_invalidation(&self, surface: &Surface) -> InvalidateSet {
+        surface.0.invalidation.set()
+    }
+
+    /// Invalidates the attachments of a generic surface that were chosen with
+    /// `set_post_present_invalidation()`, making the context current for the call.
+    ///
+    /// Widget surfaces are invalidated by `present_surface()`, and return `WidgetAttached`.
+    pub fn invalidate_surface(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        let _guard = self.make_context_current_guarded(context)?;
+        GL_FUNCTIONS.with(|gl| surface.0.invalidate(gl, context.0.id))
+    }
+
+    /// Clears a region of a surface, measured from its top left, to `color`, and its depth and
+    /// stencil buffers to `depth` and `stencil` if they're given and the surface has them.
+    ///
+    /// Widget surfaces are made current with the context for the call; generic surfaces are
+    /// cleared through their framebuffer object. Either way, the state that the clear changes is
+    /// put back afterward.
+    pub fn clear_surface_region(
+        &self,
+        context: &mut Context,
+        surface: &mut Surface,
+        rect: Option<Rect<i32>>,
+        color: [f32; 4],
+        depth: Option<f32>,
+        stencil: Option<i32>,
+    ) -> Result<(), Error> {
+        let region = ClearRegion::new(rect, color, depth, stencil);
+        self.clear_surface_with(context, surface, &region)
+    }
+
+    /// Fills a surface with this device's debug fill, as if it had just been created, or does
+    /// nothing if the fill is `DebugFill::None`.
+    pub fn debug_fill_surface(
+        &self,
+        context: &mut Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        match self.debug_fill.region() {
+            Some(region) => self.clear_surface_with(context, surface, &region),
+            None => Ok(()),
+        }
+    }
+
+    // Fills a new surface with the debug fill, if there is one. The surface is usable either way,
+    // so failing to fill it is only logged.
+    fn debug_fill_new_surface(&self, context: &Context, surface: &mut Surface) {
+        if let Some(region) = self.debug_fill.region() {
+            if let Err(err) = self.clear_surface_with(context, surface, &region) {
+                warn!(
+                    "Failed to fill a new surface with the debug fill: {:?}",
+                    err
+                );
+            }
+        }
+    }
+
+    fn clear_surface_with(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        region: &ClearRegion,
+    ) -> Result<(), Error> {
+        let _guard = self.make_context_current_guarded(context)?;
+        GL_FUNCTIONS.with(|gl| {
+            surface.0.clear_region(
+                gl,
+                self.native_connection.egl_display,
+                context.0.egl_context,
+                context.0.id,
+                region,
+            )
+        })
+    }
+
+    /// Returns how many frames ago the back buffer of a widget surface was last presented, or 0
+    /// if its contents are undefined.
+    ///
+    /// The age is queried with `EGL_EXT_buffer_age` right after each swap, while the surface is
+    /// still current and nothing has been drawn into it, so it's meant to be read after
+    /// `present_surface()` and before drawing the next frame.
+    #[inline]
+    pub fn surface_buffer_age(&self, surface: &Surface) -> Result<u32, Error> {
+        surface.0.buffer_age()
+    }
+
+    /// Asks the X server for the current size of a widget surface's window, with
+    /// `XGetGeometry()`.
+    pub fn query_surface_native_size(&self, surface: &Surface) -> Result<Size2D<i32>, Error> {
+        let x11_window = unsafe { *(surface.0.native_window()? as *const Window) };
+        let display_guard = self.native_connection.lock_display();
+        unsafe { window_size(display_guard.display(), x11_window) }
+            .ok_or(Error::InvalidNativeWidget)
+    }
+
+    /// Returns the rotation that the window system applies to a widget surface's frames to show
+    /// them on its display.
+    ///
+    /// X11 rotates frames itself, so this is always `SurfaceTransform::Identity`.
+    #[inline]
+    pub fn surface_transform(&self, _surface: &Surface) -> SurfaceTransform {
+        SurfaceTransform::Identity
+    }
+
+    // Fails with `SurfaceSizeMismatch` if a widget surface's window has been resized since the
+    // surface was last sized. Other failures are left for the present itself to report.
+    fn check_native_size(&self, surface: &Surface) -> Result<(), Error> {
+        match self.query_surface_native_size(surface) {
+            Ok(actual) if actual != surface.0.size => Err(Error::SurfaceSizeMismatch {
+                expected: surface.0.size,
+                actual,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Registers a function to call with the ID and new size of each widget surface of this
+    /// device that is found to have been resized behind surfman's back.
+    ///
+    /// X11 widget surfaces only change size through `resize_surface()`, so the handler is never
+    /// called.
+    #[inline]
+    pub fn set_surface_resized_handler(&mut self, _: Option<SurfaceResizedHandler>) {}
+
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to have moved to a display with a different scale factor.
+    ///
+    /// X11 has no scale factor of its own for each window, only the Xft DPI of the whole screen,
+    /// so the handler is never called.
+    #[inline]
+    pub fn set_surface_scale_changed_handler(
+        &mut self,
+        _: Option<SurfaceScaleChangedHandler>,
+    ) -> Result<(), Error> {
         Ok(())
     }
 
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to be displayed with a different color profile, with the new profile, replacing any
+    /// previous one, or unregisters it if `handler` is `None`.
+    ///
+    /// Every 30 presents, `present_surface()` reads the `_ICC_PROFILE` property again, as
+    /// `widget_color_profile()` does, and compares it with the profile that it last saw.
+    #[inline]
+    pub fn set_color_profile_changed_handler(
+        &mut self,
+        handler: Option<ColorProfileChangedHandler>,
+    ) {
+        self.color_profile_changed_handler = handler;
+    }
+
+    /// Sets whether the system may discard the contents of a generic surface to free memory.
+    ///
+    /// Mesa gives no way to offer a surface's memory back to the system, so volatile surfaces stay
+    /// resident and are never discarded. The state is still enforced: a volatile surface must be
+    /// taken back with `reclaim_surface()` before it's used again.
+    pub fn set_surface_purgeable(
+        &self,
+        surface: &mut Surface,
+        purgeability: Purgeability,
+    ) -> Result<(), Error> {
+        surface.0.set_purgeable(purgeability)
+    }
+
+    /// Makes a surface non-purgeable again, and reports whether the system discarded its contents
+    /// since it was made volatile.
+    ///
+    /// On this backend, the contents are always intact.
+    pub fn reclaim_surface(&self, surface: &mut Surface) -> Result<ReclaimResult, Error> {
+        surface.0.reclaim()
+    }
+
     /// Returns a pointer to the underlying surface data for reading or writing by the CPU.
     #[inline]
-    pub fn lock_surface_data<'s>(&self, _: &'s mut Surface) -> Result<SurfaceDataGuard<'s>, Error> {
+    pub fn lock_surface_data<'s>(
+        &self,
+        surface: &'s mut Surface,
+    ) -> Result<SurfaceDataGuard<'s>, Error> {
+        surface.0.access.check_cpu_access()?;
+        surface.0.purge_state.check_usable()?;
         Err(Error::Unimplemented)
     }
 
+    /// Exports the texture of a generic surface as a dma-buf, which needs
+    /// `EGL_MESA_image_dma_buf_export`.
+    pub fn export_surface_dma_buf(&self, surface: &Surface) -> Result<DmaBuf, Error> {
+        surface.0.export_dma_buf(self.native_connection.egl_display)
+    }
+
+    /// Copies the contents of `source` into `destination` on the GPU, converting between their
+    /// formats and colorspaces and exchanging channels as `swizzle` says.
+    ///
+    /// Both surfaces must be generic surfaces of `context`, the same size, and `context` must be
+    /// current.
+    #[inline]
+    pub fn convert_surface(
+        &self,
+        context: &mut Context,
+        source: &Surface,
+        destination: &mut Surface,
+        swizzle: Swizzle,
+    ) -> Result<(), Error> {
+        convert::convert_surface(self, context, source, destination, swizzle)
+    }
+
+    /// Reads a rectangle of a generic surface, measured from its top left, into `out` as tightly
+    /// packed 8-bit RGBA, top row first.
+    ///
+    /// The surface must belong to `context`, which needn't be current. A widget surface whose
+    /// presentation is paused reads the frame that it holds.
+    pub fn read_surface_data(
+        &self,
+        context: &Context,
+        surface: &Surface,
+        rect: Rect<i32>,
+        out: &mut [u8],
+    ) -> Result<(), Error> {
+        match surface.0.pause.held_frame() {
+            Some(frame) if context.0.id == surface.0.context_id => frame.read_rect(rect, out),
+            Some(_) => Err(Error::IncompatibleSurface),
+            None => readback::read_surface_data(self, context, surface, rect, out),
+        }
+    }
+
+    /// Writes 8-bit RGBA pixels from `data`, top row first, into a rectangle of a generic surface,
+    /// measured from its top left.
+    ///
+    /// The surface must belong to `context`, which needn't be current.
+    #[inline]
+    pub fn write_surface_data(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        rect: Rect<i32>,
+        data: &[u8],
+        stride: usize,
+    ) -> Result<(), Error> {
+        upload::write_surface_data(self, context, surface, rect, data, stride)
+    }
+
     /// Returns the OpenGL texture target needed to read from this surface texture.
     ///
     /// This will be `GL_TEXTURE_2D` or `GL_TEXTURE_RECTANGLE`, depending on platform.
@@ -290,6 +930,74 @@ impl Device {
         surface.0.info()
     }
 
+    /// Returns the color profile of the output that a widget surface is displayed on.
+    ///
+    /// This reads the `_ICC_PROFILE` property that color management daemons set on the root
+    /// window of the widget's screen, as described by the X Color Management specification.
+    pub fn widget_color_profile(&self, surface: &Surface) -> ColorProfile {
+        let x11_window = match surface.0.native_window() {
+            Ok(x11_window) if !self.native_connection.headless => unsafe {
+                *(x11_window as *const Window)
+            },
+            _ => return ColorProfile::AssumedSrgb,
+        };
+
+        let display_guard = self.native_connection.lock_display();
+        unsafe { read_icc_profile_property(display_guard.display(), x11_window) }
+            .and_then(ColorProfile::from_icc)
+            .unwrap_or(ColorProfile::AssumedSrgb)
+    }
+
+    /// Returns true if a widget surface is on an output driven by a different GPU than this
+    /// device.
+    ///
+    /// The GPU is found as in `Connection::adapter_for_widget()`. Returns false for generic
+    /// surfaces, for headless connections, and if the GPU can't be determined.
+    pub fn presenting_cross_adapter(&self, surface: &Surface) -> bool {
+        let x11_window = match surface.0.native_window() {
+            Ok(x11_window) if !self.native_connection.headless => unsafe {
+                *(x11_window as *const Window)
+            },
+            _ => return false,
+        };
+
+        let presenting_identity = {
+            let display_guard = self.native_connection.lock_display();
+            unsafe { randr::window_presenting_identity(display_guard.display(), x11_window) }
+        };
+        match presenting_identity {
+            Some(presenting_identity) => !identities_match(&presenting_identity, &self.identity()),
+            None => false,
+        }
+    }
+
+    /// Returns the timestamps of the most recent presentations of a widget surface, oldest first.
+    ///
+    /// `compositor_ack` is when `eglSwapBuffers()` returned. `displayed` is reported where the EGL
+    /// display supports `EGL_ANDROID_get_frame_timestamps`, which X11 drivers don't as yet.
+    ///
+    /// Returns an empty list for generic surfaces and for widget surfaces that haven't been
+    /// presented yet.
+    #[inline]
+    pub fn presentation_timestamps(&self, surface: &Surface) -> Vec<PresentRecord> {
+        surface.0.presentation_records()
+    }
+
+    /// Returns when the window system presented the most recent frame of a widget surface that
+    /// is known to have reached the display, or the most recent frame if none is yet.
+    ///
+    /// The times are reported where the EGL display supports `EGL_ANDROID_get_frame_timestamps`,
+    /// which X11 drivers don't as yet. Otherwise, only `requested_present` is.
+    ///
+    /// Returns `Error::NoWidgetAttached` for generic surfaces, and `Error::NotPresented` if
+    /// nothing has been presented on the surface.
+    pub fn query_presentation_time(
+        &self,
+        surface: &Surface,
+    ) -> Result<PresentationStatistics, Error> {
+        surface.0.presentation_time()
+    }
+
     /// Returns the OpenGL texture object containing the contents of this surface.
     ///
     /// It is only legal to read from, not write to, this texture object.
@@ -297,9 +1005,112 @@ impl Device {
     pub fn surface_texture_object(&self, surface_texture: &SurfaceTexture) -> GLuint {
         surface_texture.0.texture_object
     }
+
+    /// Returns the sized internal format of the OpenGL texture of this surface texture, such as
+    /// `GL_RGBA8` or `GL_RGBA16F`.
+    ///
+    /// This is the `SurfaceInfo::format` of the wrapped surface, which tells shaders whether they
+    /// sample normalized or floating-point values.
+    #[inline]
+    pub fn surface_texture_format(&self, surface_texture: &SurfaceTexture) -> GLenum {
+        surface_texture.0.surface.info().format
+    }
+}
+
+impl Surface {
+    /// Returns the ID of the context that this surface was created with.
+    ///
+    /// A surface can only be bound to the context that it was created with.
+    #[inline]
+    pub fn context_id(&self) -> ContextID {
+        self.0.context_id
+    }
 }
 
 /// Represents the CPU view of the pixel data of this surface.
 pub struct SurfaceDataGuard<'a> {
     phantom: PhantomData<&'a ()>,
 }
+
+// Returns the size of an X window, or `None` if the server doesn't know the window.
+pub(crate) unsafe fn window_size(
+    display: *mut xlib::Display,
+    window: Window,
+) -> Option<Size2D<i32>> {
+    let (mut root_window, mut x, mut y, mut width, mut height) = (0, 0, 0, 0, 0);
+    let (mut border_width, mut depth) = (0, 0);
+    let status = XGetGeometry(
+        display,
+        window,
+        &mut root_window,
+        &mut x,
+        &mut y,
+        &mut width,
+        &mut height,
+        &mut border_width,
+        &mut depth,
+    );
+    if status == 0 {
+        return None;
+    }
+    Some(Size2D::new(width as i32, height as i32))
+}
+
+// Returns the depth of an X window's visual, or `None` if the server doesn't know the window.
+unsafe fn window_depth(display: *mut xlib::Display, window: Window) -> Option<EGLint> {
+    let mut window_attributes = std::mem::zeroed();
+    if XGetWindowAttributes(display, window, &mut window_attributes) == 0 {
+        return None;
+    }
+    Some(window_attributes.depth)
+}
+
+unsafe fn read_icc_profile_property(
+    display: *mut xlib::Display,
+    x11_window: Window,
+) -> Option<Vec<u8>> {
+    let mut window_attributes = std::mem::zeroed();
+    if XGetWindowAttributes(display, x11_window, &mut window_attributes) == 0 {
+        return None;
+    }
+
+    // Screen 0 uses `_ICC_PROFILE`; screen N uses `_ICC_PROFILE_N`.
+    let screen = XScreenNumberOfScreen(window_attributes.screen);
+    let atom_name = match screen {
+        0 => CString::new("_ICC_PROFILE").unwrap(),
+        _ => CString::new(format!("_ICC_PROFILE_{}", screen)).unwrap(),
+    };
+    let atom = XInternAtom(display, atom_name.as_ptr(), xlib::True);
+    if atom == 0 {
+        return None;
+    }
+
+    let (mut actual_type, mut actual_format): (Atom, c_int) = (0, 0);
+    let (mut item_count, mut bytes_after): (c_ulong, c_ulong) = (0, 0);
+    let mut data: *mut c_uchar = ptr::null_mut();
+    let result = XGetWindowProperty(
+        display,
+        XRootWindow(display, screen),
+        atom,
+        0,
+        c_long::MAX / 4,
+        xlib::False,
+        xlib::AnyPropertyType as Atom,
+        &mut actual_type,
+        &mut actual_format,
+        &mut item_count,
+        &mut bytes_after,
+        &mut data,
+    );
+    if result != xlib::Success as c_int || data.is_null() {
+        return None;
+    }
+
+    let profile = if actual_format == 8 {
+        Some(slice::from_raw_parts(data, item_count as usize).to_vec())
+    } else {
+        None
+    };
+    XFree(data as *mut c_void);
+    profile
+}
@@ -9,18 +9,24 @@
 
 use super::device::{Adapter, Device, NativeDevice, VendorPreference};
 use super::surface::NativeWidget;
+use crate::acceleration;
 use crate::egl::types::{EGLDisplay, EGLNativeWindowType};
+use crate::identity;
+use crate::platform::generic::egl::device;
+use crate::platform::windows::identity::{self as windows_identity, INTEL_PCI_ID};
+use crate::platform::windows::widget;
 use crate::Error;
 use crate::GLApi;
+use crate::{AdapterKey, AdapterKind};
 
 use euclid::default::Size2D;
 
 use std::os::raw::c_void;
 
-use winapi::shared::minwindef::UINT;
+use winapi::shared::windef::HWND;
 use winapi::um::d3dcommon::{D3D_DRIVER_TYPE_UNKNOWN, D3D_DRIVER_TYPE_WARP};
 
-const INTEL_PCI_ID: UINT = 0x8086;
+const ADAPTER_KEY_BACKEND: &str = "angle";
 
 /// A no-op connection.
 ///
@@ -40,6 +46,8 @@ pub struct Connection;
 #[derive(Clone)]
 pub struct NativeConnection;
 
+assert_send_sync!(NativeConnection);
+
 impl Connection {
     /// Connects to the default display.
     #[inline]
@@ -47,6 +55,23 @@ impl Connection {
         Ok(Connection)
     }
 
+    /// An alias for `Connection::new()`, as ANGLE renders offscreen through pbuffers.
+    #[inline]
+    pub fn new_headless() -> Result<Connection, Error> {
+        Connection::new()
+    }
+
+    /// Connects to the display if it supports the given OpenGL API flavor.
+    ///
+    /// This backend only supports OpenGL ES, so other flavors return `Error::UnsupportedGLType`.
+    #[inline]
+    pub fn new_with_api(gl_api: GLApi) -> Result<Connection, Error> {
+        match gl_api {
+            GLApi::GLES => Connection::new(),
+            _ => Err(Error::UnsupportedGLType),
+        }
+    }
+
     /// An alias for `Connection::new()`, present for consistency with other backends.
     #[inline]
     pub unsafe fn from_native_connection(_: NativeConnection) -> Result<Connection, Error> {
@@ -65,6 +90,14 @@ impl Connection {
         GLApi::GLES
     }
 
+    /// Returns true if ANGLE advertises the named EGL client extension.
+    ///
+    /// ANGLE displays belong to devices rather than connections, so display extensions aren't
+    /// reported here.
+    pub fn supports_extension(&self, name: &str) -> bool {
+        unsafe { device::client_supports_extension(name) }
+    }
+
     /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
     ///
     /// This is an alias for `Connection::create_hardware_adapter()`.
@@ -97,12 +130,81 @@ impl Connection {
         Adapter::new(D3D_DRIVER_TYPE_WARP, VendorPreference::None)
     }
 
+    /// Returns the DXGI adapter whose description contains `needle`, ignoring case.
+    #[inline]
+    pub fn create_adapter_by_name(&self, needle: &str) -> Result<Adapter, Error> {
+        Adapter::new(
+            D3D_DRIVER_TYPE_UNKNOWN,
+            VendorPreference::NameContains(needle.to_owned()),
+        )
+    }
+
+    /// Returns a key that describes the adapter, which can be saved and passed to
+    /// `find_adapter()` in a later run to find the same adapter again.
+    ///
+    /// Hardware adapters are recorded by their PCI IDs, with their LUID and description as hints
+    /// to tell identical GPUs apart.
+    pub fn adapter_key(&self, adapter: &Adapter) -> Result<AdapterKey, Error> {
+        if adapter.d3d_driver_type == D3D_DRIVER_TYPE_WARP {
+            return Ok(AdapterKey {
+                backend: ADAPTER_KEY_BACKEND.to_owned(),
+                kind: AdapterKind::Software,
+                pci_id: None,
+                adapter_luid: None,
+                registry_id: None,
+                driver: None,
+            });
+        }
+        Ok(windows_identity::dxgi_adapter_key(
+            ADAPTER_KEY_BACKEND,
+            &adapter.dxgi_adapter,
+        ))
+    }
+
+    /// Returns the adapter that a key from `adapter_key()` describes, even if DXGI now
+    /// enumerates adapters in a different order.
+    ///
+    /// Returns `Error::IncompatibleAdapter` if the key was taken with another backend, and
+    /// `Error::NoAdapterFound` if the GPU is no longer present.
+    pub fn find_adapter(&self, key: &AdapterKey) -> Result<Adapter, Error> {
+        let mut candidates = vec![];
+        for adapter in Adapter::all_hardware()? {
+            let candidate = self.adapter_key(&adapter)?;
+            candidates.push((adapter, candidate));
+        }
+        let software_adapter = self.create_software_adapter()?;
+        let candidate = self.adapter_key(&software_adapter)?;
+        candidates.push((software_adapter, candidate));
+        identity::find_adapter(ADAPTER_KEY_BACKEND, key, candidates)
+    }
+
+    /// Returns true if the two adapters are the same adapter.
+    pub fn is_same_adapter(&self, a: &Adapter, b: &Adapter) -> bool {
+        match (self.adapter_key(a), self.adapter_key(b)) {
+            (Ok(a), Ok(b)) => a.matches(&b) && a.adapter_luid == b.adapter_luid,
+            _ => false,
+        }
+    }
+
+    /// Returns the adapter for the GPU that drives the monitor the window is on.
+    ///
+    /// If the window spans several monitors, this is the GPU driving the monitor that it covers
+    /// the most of. Returns `Error::NoAdapterFound` if no DXGI adapter drives that monitor.
+    pub fn adapter_for_widget(&self, native_widget: &NativeWidget) -> Result<Adapter, Error> {
+        let window_handle = native_widget.egl_native_window as HWND;
+        windows_identity::presenting_dxgi_adapter(window_handle)
+            .map(Adapter::from_dxgi_adapter)
+            .ok_or(Error::NoAdapterFound)
+    }
+
     /// Opens the hardware device corresponding to the given adapter.
     ///
     /// Device handles are local to a single thread.
     #[inline]
     pub fn create_device(&self, adapter: &Adapter) -> Result<Device, Error> {
-        Device::new(adapter)
+        let device = Device::new(adapter)?;
+        acceleration::check_device(&device);
+        Ok(device)
     }
 
     /// Wraps a `NativeDevice` in a `Device` and returns it.
@@ -141,14 +243,15 @@ impl Connection {
     }
 
     /// Create a native widget from a raw pointer
+    ///
+    /// The real size of the window's client area is queried. If it differs from `size`,
+    /// `native_widget_adjusted_size()` reports it.
     pub unsafe fn create_native_widget_from_ptr(
         &self,
         raw: *mut c_void,
-        _size: Size2D<i32>,
+        size: Size2D<i32>,
     ) -> NativeWidget {
-        NativeWidget {
-            egl_native_window: raw as EGLNativeWindowType,
-        }
+        native_widget(raw as EGLNativeWindowType, size)
     }
 
     /// Create a native widget type from the given `RawWindowHandle`.
@@ -157,12 +260,10 @@ impl Connection {
     pub fn create_native_widget_from_raw_window_handle(
         &self,
         handle: rwh_05::RawWindowHandle,
-        _size: Size2D<i32>,
+        size: Size2D<i32>,
     ) -> Result<NativeWidget, Error> {
         if let rwh_05::RawWindowHandle::Win32(handle) = handle {
-            Ok(NativeWidget {
-                egl_native_window: handle.hwnd as EGLNativeWindowType,
-            })
+            Ok(native_widget(handle.hwnd as EGLNativeWindowType, size))
         } else {
             Err(Error::IncompatibleNativeWidget)
         }
@@ -174,16 +275,31 @@ impl Connection {
     pub fn create_native_widget_from_window_handle(
         &self,
         handle: rwh_06::WindowHandle,
-        _size: Size2D<i32>,
+        size: Size2D<i32>,
     ) -> Result<NativeWidget, Error> {
         if let rwh_06::RawWindowHandle::Win32(handle) = handle.as_raw() {
-            Ok(NativeWidget {
-                egl_native_window: handle.hwnd.get() as EGLNativeWindowType,
-            })
+            Ok(native_widget(
+                handle.hwnd.get() as EGLNativeWindowType,
+                size,
+            ))
         } else {
             Err(Error::IncompatibleNativeWidget)
         }
     }
+
+    /// Returns the real size of a native widget if it differs from the size that the widget was
+    /// created with.
+    #[inline]
+    pub fn native_widget_adjusted_size(&self, native_widget: &NativeWidget) -> Option<Size2D<i32>> {
+        native_widget.adjusted_size
+    }
+}
+
+fn native_widget(egl_native_window: EGLNativeWindowType, size: Size2D<i32>) -> NativeWidget {
+    NativeWidget {
+        egl_native_window,
+        adjusted_size: widget::adjusted_window_size(egl_native_window as HWND, size),
+    }
 }
 
 impl NativeConnection {
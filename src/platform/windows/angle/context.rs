@@ -4,23 +4,42 @@
 
 use super::device::Device;
 use super::surface::{Surface, Synchronization, Win32Objects};
+use crate::acceleration;
+use crate::bulk::{self, BulkOps};
 use crate::context::{ContextID, CREATE_CONTEXT_MUTEX};
+use crate::convert;
+use crate::current;
 use crate::egl;
 use crate::egl::types::{EGLConfig, EGLContext, EGLint};
-use crate::platform::generic::egl::context::{self, CurrentContextGuard};
+use crate::gl_utils;
+use crate::info;
+use crate::leak_check::{self, TrackedObject};
+use crate::platform::generic::egl::context;
 use crate::platform::generic::egl::device::EGL_FUNCTIONS;
 use crate::platform::generic::egl::error::ToWindowingApiError;
 use crate::platform::generic::egl::surface::ExternalEGLSurfaces;
 use crate::surface::Framebuffer;
-use crate::{ContextAttributes, Error, Gl, SurfaceInfo};
+use crate::ContextDescriptorAttributes;
+use crate::ContextLostHandler;
+use crate::ContextPriority;
+use crate::GlError;
+use crate::ResetStatus;
+use crate::{ContextAttributeFlags, GLVersion};
+use crate::{
+    ContextAttributes, ContextInfo, Error, GLCapabilities, Gl, RenderTargetInfo, SurfaceInfo,
+};
 
 use std::mem;
+use std::ops::RangeInclusive;
 use std::os::raw::c_void;
-use std::thread;
+use std::ptr;
+use std::thread::{self, ThreadId};
 use winapi::shared::winerror::S_OK;
 use winapi::um::winbase::INFINITE;
 
-pub use crate::platform::generic::egl::context::{ContextDescriptor, NativeContext};
+pub use crate::platform::generic::egl::context::{
+    ContextDescriptor, CurrentContextGuard, NativeContext,
+};
 
 thread_local! {
     #[doc(hidden)]
@@ -68,6 +87,9 @@ impl Device {
         &self,
         attributes: &ContextAttributes,
     ) -> Result<ContextDescriptor, Error> {
+        crate::context::check_profile(self.gl_api(), attributes)?;
+        self.cancel_token.check()?;
+
         unsafe {
             ContextDescriptor::new(
                 self.egl_display,
@@ -88,19 +110,29 @@ impl Device {
     ///
     /// The context initially has no surface attached. Until a surface is bound to it, rendering
     /// commands will fail or have no effect.
+    ///
+    /// If `share_with` is supplied, the new context shares textures, buffers, and other OpenGL
+    /// objects with it. It must have been created from a descriptor with the same EGL config, or
+    /// an `IncompatibleSharedContext` error is returned.
     pub fn create_context(
         &mut self,
         descriptor: &ContextDescriptor,
         share_with: Option<&Context>,
     ) -> Result<Context, Error> {
         let mut next_context_id = CREATE_CONTEXT_MUTEX.lock().unwrap();
+        self.cancel_token.check()?;
         unsafe {
             let egl_context = context::create_context(
                 self.egl_display,
                 descriptor,
                 share_with.map_or(egl::NO_CONTEXT, |ctx| ctx.egl_context),
                 self.gl_api(),
+                self.scheduling_class,
             )?;
+            if let Err(err) = self.cancel_token.check() {
+                EGL_FUNCTIONS.with(|egl| egl.DestroyContext(self.egl_display, egl_context));
+                return Err(err);
+            }
 
             let context = Context {
                 egl_context,
@@ -109,32 +141,64 @@ impl Device {
                 context_is_owned: true,
             };
             next_context_id.0 += 1;
+            leak_check::created(TrackedObject::Context);
             Ok(context)
         }
     }
 
+    /// Creates a context of the newest OpenGL or OpenGL ES version in `versions` that this device
+    /// supports, and returns it along with the descriptor that it was created from.
+    ///
+    /// The version that this settles on is remembered, so later calls with the same range and
+    /// flags don't search again. If the device supports no version in the range, this returns
+    /// `Error::UnsupportedGLVersion`.
+    pub fn create_versioned_context(
+        &mut self,
+        versions: RangeInclusive<GLVersion>,
+        flags: ContextAttributeFlags,
+    ) -> Result<(Context, ContextDescriptor), Error> {
+        let gl_api = self.gl_api();
+        let mut negotiated_versions = mem::take(&mut self.negotiated_versions);
+        let result = negotiated_versions.create_context(gl_api, versions, flags, |attributes| {
+            let descriptor = self.create_context_descriptor(attributes)?;
+            let context = self.create_context(&descriptor, None)?;
+            Ok((context, descriptor))
+        });
+        self.negotiated_versions = negotiated_versions;
+        result
+    }
+
     /// Wraps a native `EGLContext` in a context object.
     ///
     /// The underlying `EGLContext` is not retained, as there is no way to do this in the EGL API.
     /// Therefore, it is the caller's responsibility to keep it alive as long as this `Context`
-    /// remains alive.
+    /// remains alive. Destroying the `Context` leaves the `EGLContext` alive.
+    ///
+    /// If the native context has no draw or read surface, surfman surfaces can be bound to the
+    /// returned context like any other. Otherwise, the context keeps rendering to the native
+    /// surfaces, and binding a surface returns `Error::ExternalRenderTarget`.
+    ///
+    /// Returns `Error::IncompatibleNativeContext` if the `EGLContext` doesn't belong to this
+    /// device's display.
     pub unsafe fn create_context_from_native_context(
         &self,
         native_context: NativeContext,
     ) -> Result<Context, Error> {
+        if !context::native_context_belongs_to_display(self.egl_display, &native_context) {
+            return Err(Error::IncompatibleNativeContext);
+        }
+
         let mut next_context_id = CREATE_CONTEXT_MUTEX.lock().unwrap();
 
         // Create the context.
         let context = Context {
             egl_context: native_context.egl_context,
             id: *next_context_id,
-            framebuffer: Framebuffer::External(ExternalEGLSurfaces {
-                draw: native_context.egl_draw_surface,
-                read: native_context.egl_read_surface,
-            }),
+            framebuffer: native_context.framebuffer(),
             context_is_owned: false,
         };
         next_context_id.0 += 1;
+        leak_check::created(TrackedObject::Context);
 
         Ok(context)
     }
@@ -151,13 +215,19 @@ impl Device {
             self.destroy_surface(context, &mut surface)?;
         }
 
+        self.lost_contexts.forget(context.id);
+        current::forget(context.id);
+        convert::forget_context(context.id);
         EGL_FUNCTIONS.with(|egl| unsafe {
-            egl.MakeCurrent(
-                self.egl_display,
-                egl::NO_SURFACE,
-                egl::NO_SURFACE,
-                egl::NO_CONTEXT,
-            );
+            // Leave any other context that the caller has made current alone.
+            if egl.GetCurrentContext() == context.egl_context {
+                egl.MakeCurrent(
+                    self.egl_display,
+                    egl::NO_SURFACE,
+                    egl::NO_SURFACE,
+                    egl::NO_CONTEXT,
+                );
+            }
 
             if context.context_is_owned {
                 let result = egl.DestroyContext(self.egl_display, context.egl_context);
@@ -168,6 +238,7 @@ impl Device {
             context.egl_context = egl::NO_CONTEXT;
         });
 
+        leak_check::destroyed(TrackedObject::Context);
         Ok(())
     }
 
@@ -183,28 +254,32 @@ impl Device {
     /// Makes the context the current OpenGL context for this thread.
     ///
     /// After calling this function, it is valid to use OpenGL rendering commands.
+    ///
+    /// Returns `Error::ContextLost` if the context has been lost; see `poll_context_lost()`.
     pub fn make_context_current(&self, context: &Context) -> Result<(), Error> {
-        unsafe {
-            let (egl_draw_surface, egl_read_surface) = match context.framebuffer {
-                Framebuffer::Surface(ref surface) => (surface.egl_surface, surface.egl_surface),
-                Framebuffer::None => (egl::NO_SURFACE, egl::NO_SURFACE),
-                Framebuffer::External(ref surfaces) => (surfaces.draw, surfaces.read),
-            };
-
-            EGL_FUNCTIONS.with(|egl| {
-                let result = egl.MakeCurrent(
-                    self.egl_display,
-                    egl_draw_surface,
-                    egl_read_surface,
-                    context.egl_context,
-                );
-                if result == egl::FALSE {
-                    let err = egl.GetError().to_windowing_api_error();
-                    return Err(Error::MakeCurrentFailed(err));
-                }
-                Ok(())
+        self.lost_contexts.check(context.id, || {
+            current::make_current(context.id, || unsafe {
+                let (egl_draw_surface, egl_read_surface) = match context.framebuffer {
+                    Framebuffer::Surface(ref surface) => (surface.egl_surface, surface.egl_surface),
+                    Framebuffer::None => (egl::NO_SURFACE, egl::NO_SURFACE),
+                    Framebuffer::External(ref surfaces) => (surfaces.draw, surfaces.read),
+                };
+
+                EGL_FUNCTIONS.with(|egl| {
+                    let result = egl.MakeCurrent(
+                        self.egl_display,
+                        egl_draw_surface,
+                        egl_read_surface,
+                        context.egl_context,
+                    );
+                    if result == egl::FALSE {
+                        let err = egl.GetError().to_windowing_api_error();
+                        return Err(Error::MakeCurrentFailed(err));
+                    }
+                    Ok(())
+                })
             })
-        }
+        })
     }
 
     /// Removes the current OpenGL context from this thread.
@@ -215,16 +290,45 @@ impl Device {
         unsafe { context::make_no_context_current(self.egl_display) }
     }
 
-    pub(crate) fn temporarily_make_context_current(
+    /// Returns the thread that the context is current on, or `None` if it isn't current anywhere.
+    ///
+    /// Only contexts made current through surfman are tracked.
+    #[inline]
+    pub fn context_current_thread(&self, context: &Context) -> Option<ThreadId> {
+        current::current_thread(context.id)
+    }
+
+    /// Makes the context current for this thread, returning a guard that makes the previously
+    /// current context current again when dropped.
+    ///
+    /// The previous context is restored even if the thread panics while the guard is alive.
+    pub fn make_context_current_guarded(
         &self,
         context: &Context,
     ) -> Result<CurrentContextGuard, Error> {
+        if current::held_for_bulk_ops(context.id) {
+            return Ok(CurrentContextGuard::held());
+        }
         let guard = CurrentContextGuard::new();
         self.make_context_current(context)?;
         Ok(guard)
     }
 
-    pub(crate) fn context_is_current(&self, context: &Context) -> bool {
+    /// Makes the context current for this thread, runs `f` with the operations that can be run
+    /// on the context, and then makes the previously current context current again.
+    ///
+    /// The operations don't each make the context current and restore the previous context, as
+    /// they do when called on their own. If the context can't be made current, `f` isn't run.
+    pub fn with_context_current<F, R>(&self, context: &mut Context, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut BulkOps<Self>) -> R,
+    {
+        bulk::with_context_current(self, context, f)
+    }
+
+    /// Returns true if the given context is the current OpenGL context for this thread.
+    #[inline]
+    pub fn is_context_current(&self, context: &Context) -> bool {
         EGL_FUNCTIONS.with(|egl| unsafe { egl.GetCurrentContext() == context.egl_context })
     }
 
@@ -237,16 +341,56 @@ impl Device {
         unsafe { context_descriptor.attributes(self.egl_display) }
     }
 
-    /// Fetches the address of an OpenGL function associated with this context.
+    /// Returns the properties of the context descriptor as plain data, which can be sent to
+    /// another process.
+    #[inline]
+    pub fn context_descriptor_raw_parts(
+        &self,
+        context_descriptor: &ContextDescriptor,
+    ) -> ContextDescriptorAttributes {
+        unsafe { context_descriptor.raw_parts(self.egl_display) }
+    }
+
+    /// Creates a context descriptor equivalent to the one that `attributes` were taken from,
+    /// possibly on a device in another process.
+    ///
+    /// If this device can't match the version and buffer sizes exactly, this returns
+    /// `Error::UnsupportedGLVersion` or `Error::NoPixelFormatFound`.
+    pub fn create_context_descriptor_from_attributes(
+        &self,
+        attributes: &ContextDescriptorAttributes,
+    ) -> Result<ContextDescriptor, Error> {
+        let context_descriptor = self.create_context_descriptor(&attributes.attributes)?;
+        attributes.check_recreated(&self.context_descriptor_raw_parts(&context_descriptor))?;
+        Ok(context_descriptor)
+    }
+
+    /// Fetches the address of an OpenGL or EGL function associated with this context, or `None` if
+    /// the function isn't available.
     ///
     /// OpenGL functions are local to a context. You should not use OpenGL functions on one context
     /// with any other context.
     ///
-    /// This method is typically used with a function like `gl::load_with()` from the `gl` crate to
-    /// load OpenGL function pointers.
-    #[inline]
-    pub fn get_proc_address(&self, _: &Context, symbol_name: &str) -> *const c_void {
-        context::get_proc_address(symbol_name)
+    /// EGL returns addresses even for functions that the driver doesn't implement, so functions
+    /// named with an extension suffix, like `glDrawBuffersEXT`, are only returned if the context
+    /// or display supports an extension from the same vendor. Checking may briefly make the
+    /// context current; the context that was current before this call remains current afterward.
+    pub fn get_proc_address(&self, context: &Context, symbol_name: &str) -> Option<*const c_void> {
+        context::get_available_proc_address(self.egl_display, symbol_name, || {
+            self.gl_extensions(context).unwrap_or_default()
+        })
+    }
+
+    /// Returns a function that looks up the OpenGL functions of a context, for loaders like
+    /// `gl::load_with()` that expect a null pointer for functions that aren't available.
+    pub fn proc_address_loader<'a>(
+        &'a self,
+        context: &'a Context,
+    ) -> Box<dyn Fn(&str) -> *const c_void + 'a> {
+        Box::new(move |symbol_name| {
+            self.get_proc_address(context, symbol_name)
+                .unwrap_or(ptr::null())
+        })
     }
 
     #[inline]
@@ -259,11 +403,13 @@ impl Device {
 
     /// Attaches a surface to a context for rendering.
     ///
-    /// This function takes ownership of the surface. The surface must have been created with this
-    /// context, or an `IncompatibleSurface` error is returned.
+    /// This function takes ownership of the surface. A surface belongs to the context that it was
+    /// created with, so binding it to any other context returns a `SurfaceAlreadyBound` error
+    /// naming that context. Check `Surface::context_id()` beforehand to avoid this, and wrap the
+    /// surface in a surface texture to read it from another context instead.
     ///
-    /// If this function is called with a surface already bound, a `SurfaceAlreadyBound` error is
-    /// returned. To avoid this error, first unbind the existing surface with
+    /// If this context already has a surface bound, a `SurfaceAlreadyBound` error naming this
+    /// context is returned. To avoid this error, first unbind the existing surface with
     /// `unbind_surface_from_context`.
     ///
     /// If an error is returned, the surface is returned alongside it.
@@ -273,26 +419,33 @@ impl Device {
         surface: Surface,
     ) -> Result<(), (Error, Surface)> {
         if context.id != surface.context_id {
-            return Err((Error::IncompatibleSurface, surface));
+            let context_id = surface.context_id;
+            return Err((Error::SurfaceAlreadyBound { context_id }, surface));
+        }
+        if let Err(err) = surface.purge_state.check_usable() {
+            return Err((err, surface));
         }
 
         match context.framebuffer {
             Framebuffer::None => {}
             Framebuffer::External(_) => return Err((Error::ExternalRenderTarget, surface)),
-            Framebuffer::Surface(_) => return Err((Error::SurfaceAlreadyBound, surface)),
+            Framebuffer::Surface(_) => {
+                let context_id = context.id;
+                return Err((Error::SurfaceAlreadyBound { context_id }, surface));
+            }
         }
 
         // If the surface is synchronized with GLFinish, then finish.
         // FIXME(pcwalton): Is this necessary and sufficient?
         if surface.uses_gl_finish() {
-            if let Ok(_guard) = self.temporarily_make_context_current(context) {
+            if let Ok(_guard) = self.make_context_current_guarded(context) {
                 unsafe {
                     GL_FUNCTIONS.with(|gl| gl.Finish());
                 }
             }
         }
 
-        let is_current = self.context_is_current(context);
+        let is_current = self.is_context_current(context);
 
         match surface.win32_objects {
             Win32Objects::Pbuffer {
@@ -368,6 +521,121 @@ impl Device {
         }
     }
 
+    /// Makes the context current, binds the framebuffer of its surface, and sets the viewport to
+    /// cover that surface.
+    ///
+    /// If no surface is attached, the context is still made current, the default framebuffer is
+    /// bound, and the viewport is left unchanged; the returned info then has a zero size and a
+    /// format of `GL_NONE`. On error, the previously-current context is restored.
+    pub fn begin_rendering(&self, context: &Context) -> Result<RenderTargetInfo, Error> {
+        let guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        let surface_info = self.context_surface_info(context)?;
+        let attributes = self.context_descriptor_attributes(&self.context_descriptor(context));
+        let render_target_info = GL_FUNCTIONS
+            .with(|gl| gl_utils::bind_render_target(gl, surface_info.as_ref(), &attributes));
+        mem::forget(guard);
+        Ok(render_target_info)
+    }
+
+    /// Finishes rendering started with `begin_rendering()` by unbinding the surface's framebuffer,
+    /// flushing the context if `flush` is true.
+    ///
+    /// The context that was current before this call remains current afterward.
+    pub fn end_rendering(&self, context: &Context, flush: bool) -> Result<(), Error> {
+        let _guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        GL_FUNCTIONS.with(|gl| gl_utils::unbind_render_target(gl, flush));
+        Ok(())
+    }
+
+    /// Returns the OpenGL features beyond OpenGL ES 2.0 that the given context supports.
+    ///
+    /// The context that was current before this call remains current afterward.
+    pub fn capabilities(&self, context: &Context) -> Result<GLCapabilities, Error> {
+        let _guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        Ok(GL_FUNCTIONS.with(GLCapabilities::current))
+    }
+
+    /// Returns the names of the OpenGL extensions that the given context supports.
+    ///
+    /// The context that was current before this call remains current afterward.
+    pub fn gl_extensions(&self, context: &Context) -> Result<Vec<String>, Error> {
+        let _guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        Ok(GL_FUNCTIONS.with(info::current_extensions))
+    }
+
+    /// Returns what OpenGL reports about the given context: the version, profile, and flags that
+    /// it was actually granted, and the vendor and renderer strings.
+    ///
+    /// The context that was current before this call remains current afterward.
+    pub fn context_info(&self, context: &Context) -> Result<ContextInfo, Error> {
+        let _guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        Ok(GL_FUNCTIONS.with(ContextInfo::current))
+    }
+
+    /// Returns true unless the given context renders in software: the device is a software
+    /// rasterizer, such as WARP, which Direct3D reports as the adapter, or the context's
+    /// `GL_RENDERER` string names one.
+    ///
+    /// A context that can't be made current, such as a lost one, returns false. The context that
+    /// was current before this call remains current afterward.
+    pub fn context_is_hardware_accelerated(&self, context: &Context) -> bool {
+        acceleration::context_is_hardware_accelerated(self, context)
+    }
+
+    /// Empties the OpenGL error queue of the given context, returning each error that
+    /// `glGetError()` reported until it reported none.
+    ///
+    /// The context that was current before this call remains current afterward.
+    pub fn drain_gl_errors(&self, context: &Context) -> Result<Vec<GlError>, Error> {
+        let _guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        Ok(GL_FUNCTIONS.with(gl_utils::drain_gl_errors))
+    }
+
+    /// Returns whether the given context has been lost to a GPU reset.
+    ///
+    /// Only contexts created with `ContextAttributeFlags::ROBUST_ACCESS` are notified of resets.
+    /// The context that was current before this call remains current afterward.
+    pub fn context_reset_status(&self, context: &Context) -> Result<ResetStatus, Error> {
+        let _guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        Ok(crate::context::current_reset_status(|symbol_name| {
+            self.get_proc_address(context, symbol_name)
+        }))
+    }
+
+    /// Returns true if the given context has been lost, for example to a GPU reset or because
+    /// Direct3D removed the device.
+    ///
+    /// A context counts as lost once ANGLE reports `EGL_CONTEXT_LOST` while making it current or
+    /// presenting one of its surfaces, or once `context_reset_status()` reports a reset. From then
+    /// on, `make_context_current()` returns `Error::ContextLost`, and the context can only be
+    /// destroyed. The context that was current before this call remains current afterward.
+    pub fn poll_context_lost(&self, context: &Context) -> bool {
+        self.lost_contexts
+            .poll(context.id, || self.context_reset_status(context))
+    }
+
+    /// Registers a function to call with the ID of each context of this device that is found to
+    /// be lost, replacing any previous one, or unregisters it if `handler` is `None`.
+    #[inline]
+    pub fn set_context_lost_handler(&mut self, handler: Option<ContextLostHandler>) {
+        self.lost_contexts.set_handler(handler)
+    }
+
+    /// Returns the scheduling priority that the given context was granted.
+    ///
+    /// This may be lower than the priority requested in the context attributes. Platforms that
+    /// can't prioritize contexts report `ContextPriority::Medium`.
+    pub fn context_priority(&self, context: &Context) -> ContextPriority {
+        unsafe { context::context_priority(self.egl_display, context.egl_context) }
+    }
+
     /// Given a context, returns its underlying EGL context and attached surfaces.
     pub fn native_context(&self, context: &Context) -> NativeContext {
         let (egl_draw_surface, egl_read_surface) = match context.framebuffer {
@@ -382,4 +650,13 @@ impl Device {
             egl_read_surface,
         }
     }
+
+    /// Returns the native context that is current on this thread, for wrapping with
+    /// `create_context_from_native_context()`.
+    ///
+    /// If no context is current, this returns a `NoCurrentContext` error.
+    #[inline]
+    pub fn current_native_context(&self) -> Result<NativeContext, Error> {
+        NativeContext::current()
+    }
 }
@@ -3,13 +3,23 @@
 //! A thread-local handle to the device.
 
 use super::connection::Connection;
+use crate::connection;
+use crate::context::NegotiatedVersions;
 use crate::egl;
 use crate::egl::types::{EGLAttrib, EGLDeviceEXT, EGLDisplay, EGLint};
-use crate::platform::generic::egl::device::EGL_FUNCTIONS;
+use crate::lost::LostContexts;
+use crate::platform::generic::egl::context;
+use crate::platform::generic::egl::device::{self, EGL_FUNCTIONS};
 use crate::platform::generic::egl::ffi::EGL_DEVICE_EXT;
 use crate::platform::generic::egl::ffi::{EGL_D3D11_DEVICE_ANGLE, EGL_EXTENSION_FUNCTIONS};
 use crate::platform::generic::egl::ffi::{EGL_NO_DEVICE_EXT, EGL_PLATFORM_DEVICE_EXT};
-use crate::{Error, GLApi};
+use crate::platform::windows::identity;
+use crate::ColorProfileChangedHandler;
+use crate::DebugFill;
+use crate::LowMemoryOptions;
+use crate::SurfaceScaleChangedHandler;
+use crate::{CancelToken, DeviceIdentity, Error, GLApi};
+use crate::{SchedulingClass, SchedulingEnforcement};
 
 use std::cell::{RefCell, RefMut};
 use std::mem;
@@ -19,7 +29,9 @@ use winapi::shared::dxgi::{self, IDXGIAdapter, IDXGIDevice, IDXGIFactory1};
 use winapi::shared::minwindef::UINT;
 use winapi::shared::winerror::{self, S_OK};
 use winapi::um::d3d11::{D3D11CreateDevice, ID3D11Device, D3D11_SDK_VERSION};
-use winapi::um::d3dcommon::{D3D_DRIVER_TYPE, D3D_DRIVER_TYPE_UNKNOWN, D3D_FEATURE_LEVEL_9_3};
+use winapi::um::d3dcommon::D3D_FEATURE_LEVEL_9_3;
+use winapi::um::d3dcommon::{D3D_DRIVER_TYPE, D3D_DRIVER_TYPE_UNKNOWN, D3D_FEATURE_LEVEL};
+use winapi::um::d3dcommon::{D3D_FEATURE_LEVEL_10_0, D3D_FEATURE_LEVEL_11_0};
 use winapi::Interface;
 use wio::com::ComPtr;
 
@@ -34,6 +46,7 @@ thread_local! {
 pub struct Adapter {
     pub(crate) dxgi_adapter: ComPtr<IDXGIAdapter>,
     pub(crate) d3d_driver_type: D3D_DRIVER_TYPE,
+    pub(crate) minimum_feature_level: Option<D3D_FEATURE_LEVEL>,
 }
 
 unsafe impl Send for Adapter {}
@@ -46,15 +59,73 @@ pub struct Device {
     pub(crate) d3d11_device: ComPtr<ID3D11Device>,
     pub(crate) d3d_driver_type: D3D_DRIVER_TYPE,
     pub(crate) display_is_owned: bool,
+    pub(crate) cancel_token: CancelToken,
+    pub(crate) scheduling_class: SchedulingClass,
+    pub(crate) low_memory_options: Option<LowMemoryOptions>,
+    pub(crate) debug_fill: DebugFill,
+    pub(crate) lost_contexts: LostContexts,
+    pub(crate) negotiated_versions: NegotiatedVersions,
+    pub(crate) surface_scale_changed_handler: Option<SurfaceScaleChangedHandler>,
+    pub(crate) color_profile_changed_handler: Option<ColorProfileChangedHandler>,
 }
 
 pub(crate) enum VendorPreference {
     None,
     Prefer(UINT),
     Avoid(UINT),
+    NameContains(String),
+}
+
+/// What ANGLE's Direct3D 11 renderer offers on a device, as returned by
+/// `Device::angle_backend_info()`.
+///
+/// Old GPUs, Intel ones in particular, may only reach Direct3D feature level 9.3, where ANGLE
+/// offers little beyond what OpenGL ES 2.0 guarantees and shaders that need more fail to link.
+/// The limits here are also reported by `Device::capabilities()` on every platform.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AngleBackendInfo {
+    /// The feature level of the Direct3D 11 device that ANGLE renders with, such as
+    /// `D3D_FEATURE_LEVEL_9_3`.
+    pub d3d_feature_level: D3D_FEATURE_LEVEL,
+    /// How many four-component varyings ANGLE offers at this feature level, which is what
+    /// `GL_MAX_VARYING_VECTORS` reports.
+    pub max_varyings: u32,
+    /// Whether ANGLE lets vertex shaders sample textures at this feature level.
+    pub supports_vertex_texture_fetch: bool,
+    /// The description of the DXGI adapter, which ANGLE includes in its `GL_RENDERER` string.
+    pub renderer_string: String,
+}
+
+impl AngleBackendInfo {
+    // Returns what ANGLE offers on a device of the given feature level.
+    //
+    // ANGLE reserves two of the vertex shader's output registers for the position, and leaves the
+    // rest to varyings. Vertex textures need feature level 10.0.
+    pub(crate) fn new(
+        d3d_feature_level: D3D_FEATURE_LEVEL,
+        renderer_string: String,
+    ) -> AngleBackendInfo {
+        let output_registers = if d3d_feature_level >= D3D_FEATURE_LEVEL_11_0 {
+            32
+        } else if d3d_feature_level >= D3D_FEATURE_LEVEL_10_0 {
+            16
+        } else if d3d_feature_level >= D3D_FEATURE_LEVEL_9_3 {
+            10
+        } else {
+            8
+        };
+        AngleBackendInfo {
+            d3d_feature_level,
+            max_varyings: output_registers - 2,
+            supports_vertex_texture_fetch: d3d_feature_level >= D3D_FEATURE_LEVEL_10_0,
+            renderer_string,
+        }
+    }
 }
 
 /// Wraps a Direct3D 11 device and its associated EGL display.
+///
+/// This is neither `Send` nor `Sync`, since ANGLE's EGL displays aren't thread-safe.
 #[derive(Clone)]
 pub struct NativeDevice {
     /// The ANGLE EGL display.
@@ -65,29 +136,37 @@ pub struct NativeDevice {
     pub d3d_driver_type: D3D_DRIVER_TYPE,
 }
 
+assert_not_send!(NativeDevice);
+assert_not_sync!(NativeDevice);
+
+// Returns this thread's DXGI factory, creating it if necessary.
+fn dxgi_factory() -> Result<ComPtr<IDXGIFactory1>, Error> {
+    DXGI_FACTORY.with(|dxgi_factory_slot| unsafe {
+        let mut dxgi_factory_slot: RefMut<Option<ComPtr<IDXGIFactory1>>> =
+            dxgi_factory_slot.borrow_mut();
+        if dxgi_factory_slot.is_none() {
+            let mut dxgi_factory: *mut IDXGIFactory1 = ptr::null_mut();
+            let result = dxgi::CreateDXGIFactory1(
+                &IDXGIFactory1::uuidof(),
+                &mut dxgi_factory as *mut *mut IDXGIFactory1 as *mut *mut c_void,
+            );
+            if !winerror::SUCCEEDED(result) {
+                return Err(Error::Failed);
+            }
+            assert!(!dxgi_factory.is_null());
+            *dxgi_factory_slot = Some(ComPtr::from_raw(dxgi_factory));
+        }
+        Ok((*dxgi_factory_slot).clone().unwrap())
+    })
+}
+
 impl Adapter {
     pub(crate) fn new(
         d3d_driver_type: D3D_DRIVER_TYPE,
         vendor_preference: VendorPreference,
     ) -> Result<Adapter, Error> {
         unsafe {
-            let dxgi_factory = DXGI_FACTORY.with(|dxgi_factory_slot| {
-                let mut dxgi_factory_slot: RefMut<Option<ComPtr<IDXGIFactory1>>> =
-                    dxgi_factory_slot.borrow_mut();
-                if dxgi_factory_slot.is_none() {
-                    let mut dxgi_factory: *mut IDXGIFactory1 = ptr::null_mut();
-                    let result = dxgi::CreateDXGIFactory1(
-                        &IDXGIFactory1::uuidof(),
-                        &mut dxgi_factory as *mut *mut IDXGIFactory1 as *mut *mut c_void,
-                    );
-                    if !winerror::SUCCEEDED(result) {
-                        return Err(Error::Failed);
-                    }
-                    assert!(!dxgi_factory.is_null());
-                    *dxgi_factory_slot = Some(ComPtr::from_raw(dxgi_factory));
-                }
-                Ok((*dxgi_factory_slot).clone().unwrap())
-            })?;
+            let dxgi_factory = dxgi_factory()?;
 
             // Find the first adapter that matches the vendor preference.
             let mut adapter_index = 0;
@@ -107,6 +186,15 @@ impl Adapter {
                 let choose_this = match vendor_preference {
                     VendorPreference::Prefer(vendor_id) => vendor_id == adapter_desc.VendorId,
                     VendorPreference::Avoid(vendor_id) => vendor_id != adapter_desc.VendorId,
+                    VendorPreference::NameContains(ref needle) => {
+                        let description = &adapter_desc.Description;
+                        let length = description
+                            .iter()
+                            .position(|&unit| unit == 0)
+                            .unwrap_or(description.len());
+                        let name = String::from_utf16_lossy(&description[..length]);
+                        connection::adapter_name_matches(&name, needle)
+                    }
                     VendorPreference::None => true,
                 };
                 if choose_this {
@@ -121,12 +209,18 @@ impl Adapter {
                     return Ok(Adapter {
                         dxgi_adapter,
                         d3d_driver_type,
+                        minimum_feature_level: None,
                     });
                 }
 
                 adapter_index += 1;
             }
 
+            // A named adapter must match exactly; there's nothing sensible to fall back to.
+            if let VendorPreference::NameContains(_) = vendor_preference {
+                return Err(Error::NoAdapterFound);
+            }
+
             // Fallback: Go with the first adapter.
             let mut dxgi_adapter_1 = ptr::null_mut();
             let result = (*dxgi_factory).EnumAdapters1(0, &mut dxgi_adapter_1);
@@ -147,17 +241,63 @@ impl Adapter {
             Ok(Adapter {
                 dxgi_adapter,
                 d3d_driver_type,
+                minimum_feature_level: None,
             })
         }
     }
 
+    // Returns every hardware adapter on the system, in DXGI's enumeration order.
+    pub(crate) fn all_hardware() -> Result<Vec<Adapter>, Error> {
+        unsafe {
+            let dxgi_factory = dxgi_factory()?;
+            let mut adapters = vec![];
+            let mut adapter_index = 0;
+            loop {
+                let mut dxgi_adapter_1 = ptr::null_mut();
+                let result = (*dxgi_factory).EnumAdapters1(adapter_index, &mut dxgi_adapter_1);
+                if !winerror::SUCCEEDED(result) {
+                    return Ok(adapters);
+                }
+                assert!(!dxgi_adapter_1.is_null());
+                let dxgi_adapter_1 = ComPtr::from_raw(dxgi_adapter_1);
+
+                let mut dxgi_adapter: *mut IDXGIAdapter = ptr::null_mut();
+                let result = (*dxgi_adapter_1).QueryInterface(
+                    &IDXGIAdapter::uuidof(),
+                    &mut dxgi_adapter as *mut *mut IDXGIAdapter as *mut *mut c_void,
+                );
+                assert_eq!(result, S_OK);
+                adapters.push(Adapter {
+                    dxgi_adapter: ComPtr::from_raw(dxgi_adapter),
+                    d3d_driver_type: D3D_DRIVER_TYPE_UNKNOWN,
+                    minimum_feature_level: None,
+                });
+
+                adapter_index += 1;
+            }
+        }
+    }
+
     /// Create an Adapter instance wrapping an existing DXGI adapter.
     pub fn from_dxgi_adapter(adapter: ComPtr<IDXGIAdapter>) -> Adapter {
         Adapter {
             dxgi_adapter: adapter,
             d3d_driver_type: D3D_DRIVER_TYPE_UNKNOWN,
+            minimum_feature_level: None,
         }
     }
+
+    /// Returns this adapter, requiring that devices opened on it reach at least the given Direct3D
+    /// feature level, such as `D3D_FEATURE_LEVEL_10_0` to rule out devices without vertex texture
+    /// fetch.
+    ///
+    /// Opening a device on an adapter that only reaches a lower feature level fails with
+    /// `Error::NoAdapterFound`.
+    #[inline]
+    pub fn with_minimum_feature_level(mut self, feature_level: D3D_FEATURE_LEVEL) -> Adapter {
+        self.minimum_feature_level = Some(feature_level);
+        self
+    }
 }
 
 impl Device {
@@ -185,6 +325,11 @@ impl Device {
             }
             debug_assert!(d3d11_feature_level >= D3D_FEATURE_LEVEL_9_3);
             let d3d11_device = ComPtr::from_raw(d3d11_device);
+            if let Some(minimum_feature_level) = adapter.minimum_feature_level {
+                if d3d11_feature_level < minimum_feature_level {
+                    return Err(Error::NoAdapterFound);
+                }
+            }
 
             let eglCreateDeviceANGLE = EGL_EXTENSION_FUNCTIONS
                 .CreateDeviceANGLE
@@ -215,6 +360,14 @@ impl Device {
                     d3d11_device,
                     d3d_driver_type,
                     display_is_owned: true,
+                    cancel_token: CancelToken::new(),
+                    scheduling_class: SchedulingClass::Interactive,
+                    low_memory_options: None,
+                    debug_fill: DebugFill::None,
+                    lost_contexts: LostContexts::default(),
+                    negotiated_versions: NegotiatedVersions::default(),
+                    surface_scale_changed_handler: None,
+                    color_profile_changed_handler: None,
                 })
             })
         }
@@ -228,6 +381,14 @@ impl Device {
                 d3d11_device: ComPtr::from_raw(native_device.d3d11_device),
                 d3d_driver_type: native_device.d3d_driver_type,
                 display_is_owned: false,
+                cancel_token: CancelToken::new(),
+                scheduling_class: SchedulingClass::Interactive,
+                low_memory_options: None,
+                debug_fill: DebugFill::None,
+                lost_contexts: LostContexts::default(),
+                negotiated_versions: NegotiatedVersions::default(),
+                surface_scale_changed_handler: None,
+                color_profile_changed_handler: None,
             })
         }
     }
@@ -267,6 +428,14 @@ impl Device {
                 d3d11_device: ComPtr::from_raw(d3d11_device),
                 d3d_driver_type: D3D_DRIVER_TYPE_UNKNOWN,
                 display_is_owned: false,
+                cancel_token: CancelToken::new(),
+                scheduling_class: SchedulingClass::Interactive,
+                low_memory_options: None,
+                debug_fill: DebugFill::None,
+                lost_contexts: LostContexts::default(),
+                negotiated_versions: NegotiatedVersions::default(),
+                surface_scale_changed_handler: None,
+                color_profile_changed_handler: None,
             })
         }
     }
@@ -296,6 +465,7 @@ impl Device {
             Adapter {
                 dxgi_adapter,
                 d3d_driver_type: self.d3d_driver_type,
+                minimum_feature_level: None,
             }
         }
     }
@@ -317,6 +487,96 @@ impl Device {
     pub fn gl_api(&self) -> GLApi {
         GLApi::GLES
     }
+
+    /// Returns the identity of the physical GPU that this device renders with: the LUID of its
+    /// DXGI adapter.
+    pub fn identity(&self) -> DeviceIdentity {
+        identity::dxgi_adapter_identity(&self.adapter().dxgi_adapter)
+    }
+
+    /// Returns the Direct3D feature level that ANGLE renders with on this device, and the shader
+    /// limits that follow from it.
+    ///
+    /// The Direct3D device is the one that ANGLE reports through `EGL_ANGLE_device_d3d`, so this
+    /// is accurate for devices wrapped with `from_egl_display()` too.
+    pub fn angle_backend_info(&self) -> AngleBackendInfo {
+        let d3d_feature_level = unsafe { self.d3d11_device.GetFeatureLevel() };
+        let renderer_string = identity::dxgi_adapter_key("angle", &self.adapter().dxgi_adapter)
+            .driver
+            .unwrap_or_default();
+        AngleBackendInfo::new(d3d_feature_level, renderer_string)
+    }
+
+    /// Returns a token that can cancel context and surface creation on this device from any
+    /// thread.
+    #[inline]
+    pub fn cancellation_token(&self) -> CancelToken {
+        self.cancel_token.clone()
+    }
+
+    /// Sets what kind of work this device's contexts do, and returns how that is enforced.
+    ///
+    /// `Background` devices create their contexts at `ContextPriority::Low` if the driver
+    /// supports `EGL_IMG_context_priority`, which ANGLE doesn't on Direct3D 11. Otherwise, the
+    /// device's swap chains are paced in software. See `SchedulingEnforcement`.
+    pub fn set_scheduling_class(&mut self, class: SchedulingClass) -> SchedulingEnforcement {
+        self.scheduling_class = class;
+        self.scheduling_enforcement()
+    }
+
+    /// Returns the scheduling class that was last set with `set_scheduling_class()`.
+    #[inline]
+    pub fn scheduling_class(&self) -> SchedulingClass {
+        self.scheduling_class
+    }
+
+    /// Returns how this device enforces its scheduling class.
+    pub fn scheduling_enforcement(&self) -> SchedulingEnforcement {
+        let driver_priorities = unsafe {
+            device::display_supports_extension(self.egl_display, "EGL_IMG_context_priority")
+        };
+        self.scheduling_class.enforcement(driver_priorities, false)
+    }
+
+    /// Makes this device save memory at the expense of rendering quality until
+    /// `exit_low_memory_mode()` is called, and returns how many pooled surfaces were destroyed.
+    ///
+    /// Entering low-memory mode again replaces the options. See `LowMemoryOptions`.
+    pub fn enter_low_memory_mode(&mut self, options: LowMemoryOptions) -> usize {
+        self.low_memory_options = Some(options);
+        if !options.purge_pools {
+            return 0;
+        }
+        unsafe { context::purge_shared_placeholders(self.egl_display) }
+    }
+
+    /// Leaves low-memory mode, so that surfaces created from now on get their usual formats.
+    ///
+    /// Surfaces created in low-memory mode keep their formats until they are destroyed.
+    #[inline]
+    pub fn exit_low_memory_mode(&mut self) {
+        self.low_memory_options = None;
+    }
+
+    /// Returns the options that this device entered low-memory mode with, or `None` if it isn't
+    /// in low-memory mode.
+    #[inline]
+    pub fn low_memory_options(&self) -> Option<LowMemoryOptions> {
+        self.low_memory_options
+    }
+
+    /// Makes this device fill each surface that it creates from now on, and each surface that a
+    /// swap chain recycles, with a recognizable pattern. See `DebugFill`.
+    #[inline]
+    pub fn set_debug_surface_fill(&mut self, fill: DebugFill) {
+        self.debug_fill = fill;
+    }
+
+    /// Returns the debug fill that was last set with `set_debug_surface_fill()`.
+    #[inline]
+    pub fn debug_surface_fill(&self) -> DebugFill {
+        self.debug_fill
+    }
 }
 
 impl Drop for Device {
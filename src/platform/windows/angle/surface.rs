@@ -4,32 +4,75 @@
 
 use super::context::{Context, ContextDescriptor, GL_FUNCTIONS};
 use super::device::Device;
+use crate::bulk;
+use crate::clear::ClearRegion;
+use crate::color::ColorProfileTracker;
 use crate::context::ContextID;
+use crate::convert::{self, Swizzle};
 use crate::egl::types::EGLNativeWindowType;
 use crate::egl::types::EGLSurface;
 use crate::egl::{self, EGLint};
 use crate::gl;
 use crate::gl::types::{GLenum, GLint, GLuint};
-use crate::platform::generic::egl::device::EGL_FUNCTIONS;
+use crate::gl_utils;
+use crate::identities_match;
+use crate::invalidate::PostPresentInvalidation;
+use crate::leak_check::{self, TrackedObject};
+use crate::memory::{PurgeState, PurgeableBacking};
+use crate::pause::{HeldFrame, PresentPause};
+use crate::platform::generic::egl::context::{self, BoundApiGuard, CurrentContextGuard};
+use crate::platform::generic::egl::device::{self, EGL_FUNCTIONS};
 use crate::platform::generic::egl::error::ToWindowingApiError;
 use crate::platform::generic::egl::ffi::EGL_D3D_TEXTURE_2D_SHARE_HANDLE_ANGLE;
 use crate::platform::generic::egl::ffi::EGL_D3D_TEXTURE_ANGLE;
 use crate::platform::generic::egl::ffi::EGL_DXGI_KEYED_MUTEX_ANGLE;
 use crate::platform::generic::egl::ffi::EGL_EXTENSION_FUNCTIONS;
-use crate::{Error, SurfaceAccess, SurfaceID, SurfaceInfo, SurfaceType};
+use crate::platform::generic::egl::surface::{
+    clear_window_surface, preserve_color_buffer, preserves_color_buffer, set_swap_interval,
+    supports_adaptive_swap, swap_window_surface, with_window_surface_current, BufferAge, EGLFence,
+    FrameTimestamps, PresentFence, PresentationFeedback,
+};
+use crate::platform::windows::{color, identity, timing, widget};
+use crate::presentation_time;
+use crate::readback;
+use crate::scale::ScaleTracker;
+use crate::surface::{check_surface_size, PresentHistory};
+use crate::upload;
+use crate::vsync::AutoVsync;
+use crate::BufferRelease;
+use crate::InvalidateSet;
+use crate::NativeFormat;
+use crate::PausedPresentation;
+use crate::PresentationStatistics;
+use crate::SurfaceFence;
+use crate::TextureOwnership;
+use crate::{AutoVsyncPolicy, AutoVsyncStats};
+use crate::{ColorBits, ColorEncoding, PresentMode, SurfaceOptions};
+use crate::{
+    ColorProfile, ColorProfileChangedHandler, Error, PresentRecord, Purgeability, ReclaimResult,
+    SurfaceAccess, SurfaceID, SurfaceInfo, SurfaceResizedHandler, SurfaceScaleChangedHandler,
+    SurfaceType,
+};
+use crate::{SurfaceConfig, SurfaceIntent, SurfaceTransform};
 
-use euclid::default::Size2D;
+use euclid::default::{Rect, Size2D};
 use std::fmt::{self, Debug, Formatter};
 use std::marker::PhantomData;
+use std::mem;
 use std::os::raw::c_void;
 use std::ptr;
 use std::thread;
-use winapi::shared::dxgi::IDXGIKeyedMutex;
+use std::time::{Duration, Instant};
+use winapi::shared::dxgi::{IDXGIKeyedMutex, IDXGIResource};
+use winapi::shared::dxgi1_2::{IDXGIDevice2, DXGI_OFFER_RESOURCE_PRIORITY_LOW};
+use winapi::shared::minwindef::FALSE;
+use winapi::shared::windef::HWND;
 use winapi::shared::winerror::S_OK;
 use winapi::um::d3d11;
 use winapi::um::handleapi::INVALID_HANDLE_VALUE;
 use winapi::um::winbase::INFINITE;
 use winapi::um::winnt::HANDLE;
+use winapi::Interface;
 use wio::com::ComPtr;
 
 const SURFACE_GL_TEXTURE_TARGET: GLenum = gl::TEXTURE_2D;
@@ -55,6 +98,25 @@ pub struct Surface {
     pub(crate) size: Size2D<i32>,
     pub(crate) context_id: ContextID,
     pub(crate) context_descriptor: ContextDescriptor,
+    pub(crate) access: SurfaceAccess,
+    pub(crate) colorspace: ColorEncoding,
+    pub(crate) color_bits: ColorBits,
+    pub(crate) present_history: PresentHistory,
+    pub(crate) auto_vsync: Option<AutoVsync>,
+    // Follows the scale of the display that a widget surface's window is on.
+    pub(crate) scale: Option<ScaleTracker>,
+    // The color profile of the monitor that a widget surface's window was last seen on.
+    pub(crate) color_profile: ColorProfileTracker,
+    // A fence inserted when the last frame was presented, for widget surfaces.
+    pub(crate) present_fence: Option<PresentFence>,
+    // The frame timestamps that ANGLE reports for widget surfaces, if it reports any.
+    pub(crate) frame_timestamps: Option<FrameTimestamps>,
+    pub(crate) invalidation: PostPresentInvalidation,
+    pub(crate) buffer_age: BufferAge,
+    pub(crate) pause: PresentPause<HeldFrame>,
+    // Whether the surface's contents survive presents.
+    pub(crate) preserves_contents: bool,
+    pub(crate) purge_state: PurgeState,
     pub(crate) win32_objects: Win32Objects,
 }
 
@@ -79,7 +141,7 @@ unsafe impl Send for Surface {}
 
 impl Debug for Surface {
     fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
-        write!(f, "Surface({:x})", self.id().0)
+        write!(f, "Surface({:x}, {:?})", self.id().0, self.access)
     }
 }
 
@@ -98,15 +160,55 @@ impl Debug for SurfaceTexture {
 }
 
 pub(crate) enum Win32Objects {
-    Window,
+    Window {
+        window_handle: HWND,
+    },
     Pbuffer {
         share_handle: HANDLE,
         synchronization: Synchronization,
         // We keep a reference to the ComPtr in order to keep its refcount from becoming zero
         texture: Option<ComPtr<d3d11::ID3D11Texture2D>>,
+        // The format of the Direct3D texture, which ANGLE chooses for the config, if it could be
+        // read when the surface was created.
+        dxgi_format: Option<u32>,
     },
 }
 
+// The Direct3D texture behind a generic surface, which is offered to the system through DXGI.
+struct DxgiBacking {
+    dxgi_device: ComPtr<IDXGIDevice2>,
+    resource: ComPtr<IDXGIResource>,
+}
+
+impl PurgeableBacking for DxgiBacking {
+    fn offer(&mut self) -> Result<(), Error> {
+        unsafe {
+            let mut resource = self.resource.as_raw();
+            let result =
+                self.dxgi_device
+                    .OfferResources(1, &mut resource, DXGI_OFFER_RESOURCE_PRIORITY_LOW);
+            if result != S_OK {
+                return Err(Error::Failed);
+            }
+        }
+        Ok(())
+    }
+
+    fn reclaim(&mut self) -> Result<bool, Error> {
+        unsafe {
+            let mut resource = self.resource.as_raw();
+            let mut discarded = FALSE;
+            let result = self
+                .dxgi_device
+                .ReclaimResources(1, &mut resource, &mut discarded);
+            if result != S_OK {
+                return Err(Error::Failed);
+            }
+            Ok(discarded != FALSE)
+        }
+    }
+}
+
 pub(crate) enum Synchronization {
     KeyedMutex(ComPtr<IDXGIKeyedMutex>),
     GLFinish,
@@ -114,14 +216,25 @@ pub(crate) enum Synchronization {
 }
 
 /// Wraps an `EGLNativeWindowType`
+///
+/// This is neither `Send` nor `Sync`: a window belongs to the thread that created it, which can
+/// destroy it at any time, so create surfaces from it on that thread.
 #[repr(C)]
 pub struct NativeWidget {
     /// A native window
     ///
     /// This can be a top-level window or a control.
     pub egl_native_window: EGLNativeWindowType,
+    /// The real size of the window's client area, if it differed from the size that the widget
+    /// was created with.
+    ///
+    /// The `Connection` constructors fill this in. Surfaces always use the real size.
+    pub adjusted_size: Option<Size2D<i32>>,
 }
 
+assert_not_send!(NativeWidget);
+assert_not_sync!(NativeWidget);
+
 impl Device {
     /// Creates either a generic or a widget surface, depending on the supplied surface type.
     ///
@@ -130,15 +243,65 @@ impl Device {
     pub fn create_surface(
         &mut self,
         context: &Context,
-        _: SurfaceAccess,
+        access: SurfaceAccess,
         surface_type: SurfaceType<NativeWidget>,
     ) -> Result<Surface, Error> {
-        match surface_type {
-            SurfaceType::Generic { ref size } => self.create_pbuffer_surface(context, size, None),
-            SurfaceType::Widget { ref native_widget } => {
-                self.create_window_surface(context, native_widget)
+        self.create_surface_with_options(context, access, surface_type, SurfaceOptions::default())
+    }
+
+    /// Creates a surface of each of the given types, in order.
+    ///
+    /// The device's cancellation token is checked before each surface, as well as inside each
+    /// creation. If any surface fails or is cancelled, the ones created so far are destroyed and
+    /// the error is returned, so the batch creates either all of its surfaces or none.
+    pub fn create_surfaces<I>(
+        &mut self,
+        context: &mut Context,
+        access: SurfaceAccess,
+        surface_types: I,
+    ) -> Result<Vec<Surface>, Error>
+    where
+        I: IntoIterator<Item = SurfaceType<NativeWidget>>,
+    {
+        bulk::create_surfaces(self, context, access, surface_types)
+    }
+
+    /// Creates either a generic or a widget surface, with options such as its colorspace.
+    ///
+    /// `create_surface()` is this method with the default options. sRGB surfaces need
+    /// `EGL_KHR_gl_colorspace`; without it, this returns `Error::UnsupportedSurfaceFormat`.
+    /// Surfaces share the config of their context, so `SurfaceFormat::Rgb10A2` and
+    /// `SurfaceFormat::Rgba16F` need a context that was granted `ColorDepth::Deep` or
+    /// `ColorDepth::Float` respectively, and ANGLE creates the Direct3D swap chain of a widget
+    /// surface in the matching DXGI format.
+    pub fn create_surface_with_options(
+        &mut self,
+        context: &Context,
+        access: SurfaceAccess,
+        surface_type: SurfaceType<NativeWidget>,
+        options: SurfaceOptions,
+    ) -> Result<Surface, Error> {
+        self.cancel_token.check()?;
+        let context_descriptor = self.context_descriptor(context);
+        options.check_widget_format(&self.context_descriptor_attributes(&context_descriptor))?;
+        if options.colorspace == ColorEncoding::Srgb
+            && !unsafe {
+                device::display_supports_extension(self.egl_display, "EGL_KHR_gl_colorspace")
             }
+        {
+            return Err(Error::UnsupportedSurfaceFormat);
         }
+        let mut surface = match surface_type {
+            SurfaceType::Generic { ref size } => {
+                check_surface_size(*size, &self.capabilities(context)?)?;
+                self.create_pbuffer_surface(context, size, access, None, options.colorspace)
+            }
+            SurfaceType::Widget { ref native_widget } => {
+                self.create_window_surface(context, native_widget, access, options)
+            }
+        }?;
+        self.debug_fill_new_surface(context, &mut surface);
+        Ok(surface)
     }
 
     #[allow(non_snake_case)]
@@ -146,13 +309,15 @@ impl Device {
         &mut self,
         context: &Context,
         size: &Size2D<i32>,
+        access: SurfaceAccess,
         texture: Option<ComPtr<d3d11::ID3D11Texture2D>>,
+        colorspace: ColorEncoding,
     ) -> Result<Surface, Error> {
         let context_descriptor = self.context_descriptor(context);
         let egl_config = self.context_descriptor_to_egl_config(&context_descriptor);
 
         unsafe {
-            let attributes = [
+            let mut attributes = vec![
                 egl::WIDTH as EGLint,
                 size.width as EGLint,
                 egl::HEIGHT as EGLint,
@@ -161,12 +326,11 @@ impl Device {
                 egl::TEXTURE_RGBA as EGLint,
                 egl::TEXTURE_TARGET as EGLint,
                 egl::TEXTURE_2D as EGLint,
-                egl::NONE as EGLint,
-                0,
-                0,
-                0,
             ];
+            attributes.extend_from_slice(&colorspace_attributes(colorspace));
+            attributes.extend_from_slice(&[egl::NONE as EGLint, 0, 0, 0]);
 
+            let _api_guard = BoundApiGuard::for_context(self.egl_display, context.egl_context);
             EGL_FUNCTIONS.with(|egl| {
                 let egl_surface = if let Some(ref texture) = texture {
                     let surface = egl.CreatePbufferFromClientBuffer(
@@ -181,6 +345,10 @@ impl Device {
                 } else {
                     let surface =
                         egl.CreatePbufferSurface(self.egl_display, egl_config, attributes.as_ptr());
+                    // ANGLE refuses sRGB pbuffers for configs that it can't encode.
+                    if surface == egl::NO_SURFACE && colorspace == ColorEncoding::Srgb {
+                        return Err(Error::UnsupportedSurfaceFormat);
+                    }
                     assert_ne!(surface, egl::NO_SURFACE);
                     surface
                 };
@@ -220,50 +388,127 @@ impl Device {
                     Synchronization::None
                 };
 
+                let dxgi_format = self.pbuffer_dxgi_format(share_handle, texture.as_ref());
+
+                leak_check::created(TrackedObject::Surface);
                 Ok(Surface {
                     egl_surface,
                     size: *size,
                     context_id: context.id,
                     context_descriptor,
+                    access,
+                    colorspace,
+                    color_bits: context::config_color_bits(self.egl_display, egl_config),
+                    present_history: PresentHistory::default(),
+                    auto_vsync: None,
+                    scale: None,
+                    color_profile: ColorProfileTracker::default(),
+                    present_fence: None,
+                    frame_timestamps: None,
+                    invalidation: PostPresentInvalidation::default(),
+                    buffer_age: BufferAge::default(),
+                    pause: PresentPause::default(),
+                    preserves_contents: true,
+                    purge_state: PurgeState::default(),
                     win32_objects: Win32Objects::Pbuffer {
                         share_handle,
                         synchronization,
                         texture,
+                        dxgi_format,
                     },
                 })
             })
         }
     }
 
+    // Returns the format of the Direct3D texture behind a pbuffer: `texture` if the pbuffer wraps
+    // one, or else the texture that ANGLE shares through `share_handle`.
+    unsafe fn pbuffer_dxgi_format(
+        &self,
+        share_handle: HANDLE,
+        texture: Option<&ComPtr<d3d11::ID3D11Texture2D>>,
+    ) -> Option<u32> {
+        let mut desc: d3d11::D3D11_TEXTURE2D_DESC = mem::zeroed();
+        match texture {
+            Some(texture) => texture.GetDesc(&mut desc),
+            None => {
+                let mut shared_texture = ptr::null_mut();
+                let result = self.d3d11_device.OpenSharedResource(
+                    share_handle,
+                    &d3d11::ID3D11Texture2D::uuidof(),
+                    &mut shared_texture,
+                );
+                if result != S_OK || shared_texture.is_null() {
+                    return None;
+                }
+                let shared_texture =
+                    ComPtr::from_raw(shared_texture as *mut d3d11::ID3D11Texture2D);
+                shared_texture.GetDesc(&mut desc);
+            }
+        }
+        Some(desc.Format)
+    }
+
     /// Given a D3D11 texture, create a surface that wraps that texture. This method is unsafe
     /// in that the resulting surface is only valid on the current thread.
-    pub unsafe fn create_surface_from_texture(
+    pub unsafe fn create_surface_from_d3d_texture(
         &mut self,
         context: &Context,
         size: &Size2D<i32>,
         texture: ComPtr<d3d11::ID3D11Texture2D>,
     ) -> Result<Surface, Error> {
-        self.create_pbuffer_surface(context, size, Some(texture))
+        self.create_pbuffer_surface(
+            context,
+            size,
+            SurfaceAccess::GPUOnly,
+            Some(texture),
+            ColorEncoding::Linear,
+        )
     }
 
     fn create_window_surface(
         &mut self,
         context: &Context,
         native_widget: &NativeWidget,
+        access: SurfaceAccess,
+        options: SurfaceOptions,
     ) -> Result<Surface, Error> {
         let context_descriptor = self.context_descriptor(context);
-        let egl_config = self.context_descriptor_to_egl_config(&context_descriptor);
+        let colorspace = options.colorspace;
+        // DXGI swap chains always have a back buffer.
+        if options.present_mode == PresentMode::SingleBuffer {
+            return Err(Error::UnsupportedPresentMode);
+        }
 
         unsafe {
+            let egl_config = context::widget_config(
+                self.egl_display,
+                self.context_descriptor_to_egl_config(&context_descriptor),
+                &options,
+                None,
+                &|_| None,
+            )?;
             EGL_FUNCTIONS.with(|egl| {
-                let attributes = [egl::NONE as EGLint];
+                let mut attributes = colorspace_attributes(colorspace);
+                attributes.push(egl::NONE as EGLint);
                 let egl_surface = egl.CreateWindowSurface(
                     self.egl_display,
                     egl_config,
                     native_widget.egl_native_window,
                     attributes.as_ptr(),
                 );
+                if egl_surface == egl::NO_SURFACE && colorspace == ColorEncoding::Srgb {
+                    return Err(Error::UnsupportedSurfaceFormat);
+                }
                 assert_ne!(egl_surface, egl::NO_SURFACE);
+                let preserves_contents =
+                    match preserve_color_buffer(self.egl_display, egl_surface, &options) {
+                        Ok(preserves_contents) => preserves_contents,
+                        Err(err) => {
+                            egl.DestroySurface(self.egl_display, egl_surface);
+                            return Err(err);
+                        }
+                    };
 
                 let mut width = 0;
                 let mut height = 0;
@@ -282,17 +527,50 @@ impl Device {
                 assert_ne!(width, 0);
                 assert_ne!(height, 0);
 
+                let window_handle = native_widget.egl_native_window as HWND;
+                let scale = widget::window_scale(window_handle)
+                    .map(|scale| ScaleTracker::new(scale, options.auto_rescale));
+
+                leak_check::created(TrackedObject::Surface);
                 Ok(Surface {
                     egl_surface,
                     size: Size2D::new(width, height),
                     context_id: context.id,
                     context_descriptor,
-                    win32_objects: Win32Objects::Window,
+                    access,
+                    colorspace,
+                    color_bits: context::config_color_bits(self.egl_display, egl_config),
+                    present_history: PresentHistory::default(),
+                    auto_vsync: None,
+                    scale,
+                    color_profile: ColorProfileTracker::default(),
+                    present_fence: None,
+                    frame_timestamps: FrameTimestamps::new(self.egl_display, egl_surface),
+                    invalidation: PostPresentInvalidation::default(),
+                    buffer_age: BufferAge::new(self.egl_display),
+                    pause: PresentPause::default(),
+                    preserves_contents,
+                    purge_state: PurgeState::default(),
+                    win32_objects: Win32Objects::Window { window_handle },
                 })
             })
         }
     }
 
+    /// Returns the access mode, type, and options that suit a surface of the given size, created
+    /// with the given context for the given use, on this device, along with hints for using it.
+    ///
+    /// This backend can't map surfaces for the CPU, so the access mode is always `GPUOnly`.
+    pub fn recommended_surface_config(
+        &self,
+        context: &Context,
+        intent: SurfaceIntent<NativeWidget>,
+        size: Size2D<i32>,
+    ) -> Result<SurfaceConfig<NativeWidget>, Error> {
+        let capabilities = self.capabilities(context)?;
+        Ok(SurfaceConfig::recommend(intent, size, false, &capabilities))
+    }
+
     /// Creates a surface texture from an existing generic surface for use with the given context.
     ///
     /// The surface texture is local to the supplied context and takes ownership of the surface.
@@ -310,9 +588,12 @@ impl Device {
         surface: Surface,
     ) -> Result<SurfaceTexture, (Error, Surface)> {
         let share_handle = match surface.win32_objects {
-            Win32Objects::Window => return Err((Error::WidgetAttached, surface)),
+            Win32Objects::Window { .. } => return Err((Error::WidgetAttached, surface)),
             Win32Objects::Pbuffer { share_handle, .. } => share_handle,
         };
+        if let Err(err) = surface.purge_state.check_usable() {
+            return Err((err, surface));
+        }
 
         let local_egl_config = self.context_descriptor_to_egl_config(&surface.context_descriptor);
         EGL_FUNCTIONS.with(|egl| {
@@ -384,9 +665,11 @@ impl Device {
     ) -> Result<SurfaceTexture, (Error, Surface)> {
         EGL_FUNCTIONS.with(|egl| {
             unsafe {
-                let _guard = self.temporarily_make_context_current(context);
+                let _guard = self.make_context_current_guarded(context);
 
                 GL_FUNCTIONS.with(|gl| {
+                    gl_utils::drain_pre_existing_gl_errors(gl);
+
                     // Then bind that surface to the texture.
                     let mut texture = 0;
                     gl.GenTextures(1, &mut texture);
@@ -423,6 +706,7 @@ impl Device {
                     gl.BindTexture(gl::TEXTURE_2D, 0);
                     debug_assert_eq!(gl.GetError(), gl::NO_ERROR);
 
+                    leak_check::created(TrackedObject::SurfaceTexture);
                     Ok(SurfaceTexture {
                         surface,
                         local_egl_surface,
@@ -444,7 +728,8 @@ impl Device {
         size: &Size2D<i32>,
         texture: ComPtr<d3d11::ID3D11Texture2D>,
     ) -> Result<SurfaceTexture, Error> {
-        let surface = self.create_pbuffer_surface(context, size, Some(texture))?;
+        let surface =
+            self.create_pbuffer_surface(context, size, SurfaceAccess::GPUOnly, Some(texture))?;
         let local_egl_surface = surface.egl_surface;
         self.create_surface_texture_from_local_surface(context, surface, local_egl_surface, None)
             .map_err(|(err, mut surface)| {
@@ -453,6 +738,22 @@ impl Device {
             })
     }
 
+    /// Wraps an existing GL texture in a generic surface.
+    ///
+    /// Surfaces on this platform are backed by shared Direct3D textures, so this currently
+    /// returns `Unimplemented`. Use `create_surface_from_d3d_texture()` to wrap a Direct3D
+    /// texture instead.
+    pub fn create_surface_from_texture(
+        &mut self,
+        _: &mut Context,
+        _: GLuint,
+        _: &Size2D<i32>,
+        _: GLenum,
+        _: TextureOwnership,
+    ) -> Result<Surface, Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Destroys a surface.
     ///
     /// The supplied context must be the context the surface is associated with, or this returns
@@ -478,8 +779,13 @@ impl Device {
                     self.make_no_context_current()?;
                 }
 
+                if surface.egl_surface != egl::NO_SURFACE {
+                    leak_check::destroyed(TrackedObject::Surface);
+                }
                 egl.DestroySurface(self.egl_display, surface.egl_surface);
                 surface.egl_surface = egl::NO_SURFACE;
+                surface.present_fence = None;
+                surface.frame_timestamps = None;
                 if let Win32Objects::Pbuffer {
                     ref mut texture, ..
                 } = surface.win32_objects
@@ -500,9 +806,10 @@ impl Device {
     /// occur.
     pub fn destroy_surface_texture(
         &self,
-        _: &mut Context,
+        context: &mut Context,
         mut surface_texture: SurfaceTexture,
     ) -> Result<Surface, (Error, SurfaceTexture)> {
+        let _guard = self.make_context_current_guarded(context);
         unsafe {
             GL_FUNCTIONS.with(|gl| gl.DeleteTextures(1, &surface_texture.gl_texture));
             surface_texture.gl_texture = 0;
@@ -517,6 +824,7 @@ impl Device {
             })
         }
 
+        leak_check::destroyed(TrackedObject::SurfaceTexture);
         Ok(surface_texture.surface)
     }
 
@@ -532,42 +840,721 @@ impl Device {
     #[inline]
     pub fn lock_surface_data<'s>(
         &self,
-        _surface: &'s mut Surface,
+        surface: &'s mut Surface,
     ) -> Result<SurfaceDataGuard<'s>, Error> {
+        surface.access.check_cpu_access()?;
+        surface.purge_state.check_usable()?;
         Err(Error::Unimplemented)
     }
 
+    /// Copies the contents of `source` into `destination` on the GPU, converting between their
+    /// formats and colorspaces and exchanging channels as `swizzle` says.
+    ///
+    /// Both surfaces must be generic surfaces of `context`, the same size, and `context` must be
+    /// current.
+    #[inline]
+    pub fn convert_surface(
+        &self,
+        context: &mut Context,
+        source: &Surface,
+        destination: &mut Surface,
+        swizzle: Swizzle,
+    ) -> Result<(), Error> {
+        convert::convert_surface(self, context, source, destination, swizzle)
+    }
+
+    /// Reads a rectangle of a generic surface, measured from its top left, into `out` as tightly
+    /// packed 8-bit RGBA, top row first.
+    ///
+    /// The surface must belong to `context`, which needn't be current. A widget surface whose
+    /// presentation is paused reads the frame that it holds.
+    pub fn read_surface_data(
+        &self,
+        context: &Context,
+        surface: &Surface,
+        rect: Rect<i32>,
+        out: &mut [u8],
+    ) -> Result<(), Error> {
+        match surface.pause.held_frame() {
+            Some(frame) if context.id == surface.context_id => frame.read_rect(rect, out),
+            Some(_) => Err(Error::IncompatibleSurface),
+            None => readback::read_surface_data(self, context, surface, rect, out),
+        }
+    }
+
+    /// Writes 8-bit RGBA pixels from `data`, top row first, into a rectangle of a generic surface,
+    /// measured from its top left.
+    ///
+    /// The surface must belong to `context`, which needn't be current.
+    #[inline]
+    pub fn write_surface_data(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        rect: Rect<i32>,
+        data: &[u8],
+        stride: usize,
+    ) -> Result<(), Error> {
+        upload::write_surface_data(self, context, surface, rect, data, stride)
+    }
+
     /// Displays the contents of a widget surface on screen.
     ///
     /// Widget surfaces are internally double-buffered, so changes to them don't show up in their
     /// associated widgets until this method is called.
     ///
     /// The supplied context must match the context the surface was created with, or an
-    /// `IncompatibleSurface` error is returned.
-    pub fn present_surface(&self, _: &Context, surface: &mut Surface) -> Result<(), Error> {
-        match surface.win32_objects {
-            Win32Objects::Window { .. } => {}
+    /// `IncompatibleSurface` error is returned. The context doesn't need to be current, and the
+    /// context that was current before this call remains current afterward.
+    ///
+    /// A window that has been resized since the surface was last sized fails with
+    /// `SurfaceSizeMismatch` without presenting.
+    pub fn present_surface(&self, context: &Context, surface: &mut Surface) -> Result<(), Error> {
+        self.present(context, surface, None)
+    }
+
+    /// Displays the changed parts of a widget surface on screen.
+    ///
+    /// `damage` lists the rectangles that changed since the last present, measured from the
+    /// bottom left as in OpenGL, and is passed on with `EGL_KHR_swap_buffers_with_damage` where
+    /// the display supports it, which ANGLE passes on to DXGI as dirty rectangles. Otherwise, the
+    /// whole surface is swapped, as `present_surface()` does. An empty list means that nothing
+    /// changed, and skips the swap entirely.
+    pub fn present_surface_with_damage(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        self.present(context, surface, Some(damage))
+    }
+
+    fn present(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: Option<&[Rect<i32>]>,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        let window_handle = match surface.win32_objects {
+            Win32Objects::Window { window_handle } => window_handle,
             _ => return Err(Error::NoWidgetAttached),
+        };
+        if surface.pause.is_paused() {
+            return Err(self.hold_frame(context, surface, damage));
+        }
+        if matches!(damage, Some([])) {
+            return Ok(());
+        }
+        // A frame drawn before a change of scale was noticed is shown as it is.
+        let rescale_to = self.update_scale(surface, window_handle);
+        self.update_color_profile(surface, window_handle);
+        if rescale_to.is_none() {
+            self.check_native_size(surface)?;
         }
 
-        EGL_FUNCTIONS.with(|egl| unsafe {
-            let ok = egl.SwapBuffers(self.egl_display, surface.egl_surface);
-            assert_ne!(ok, egl::FALSE);
-            Ok(())
-        })
+        let submit = Instant::now();
+        let egl_surface = surface.egl_surface;
+        let frame_id = surface.present_history.next_frame_id();
+        let frame_timestamps = &mut surface.frame_timestamps;
+        let (invalidation, buffer_age) = (&mut surface.invalidation, &mut surface.buffer_age);
+        let present_fence = self.lost_contexts.check(context.id, || unsafe {
+            swap_window_surface(
+                self.egl_display,
+                egl_surface,
+                context.egl_context,
+                damage,
+                |egl_display| {
+                    if let Some(ref mut frame_timestamps) = *frame_timestamps {
+                        frame_timestamps.presenting(frame_id)?;
+                    }
+                    Ok(PresentFence::insert(egl_display))
+                },
+                || {
+                    GL_FUNCTIONS.with(|gl| invalidation.invalidate(gl, 0));
+                    buffer_age.record(self.egl_display, egl_surface, invalidation.set());
+                },
+            )
+        })?;
+        surface.present_fence = Some(present_fence);
+        surface
+            .present_history
+            .record(submit, Some(Instant::now()), None);
+        if let Some(ref mut auto_vsync) = surface.auto_vsync {
+            auto_vsync.update(&surface.present_history, |interval| unsafe {
+                set_swap_interval(self.egl_display, egl_surface, context.egl_context, interval)
+            });
+        }
+        if let Some(size) = rescale_to {
+            self.resize_surface(context, surface, size)?;
+        }
+        Ok(())
+    }
+
+    // Holds the frame being presented while presentation is paused, and returns the error that the
+    // present reports.
+    fn hold_frame(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: Option<&[Rect<i32>]>,
+    ) -> Error {
+        if matches!(damage, Some([])) {
+            return Error::PresentationPaused;
+        }
+        if let Err(err) = self.check_native_size(surface) {
+            return err;
+        }
+        let (egl_surface, size) = (surface.egl_surface, surface.size);
+        let frame = self.lost_contexts.check(context.id, || unsafe {
+            let egl_context = context.egl_context;
+            with_window_surface_current(self.egl_display, egl_surface, egl_context, || {
+                GL_FUNCTIONS.with(|gl| HeldFrame::read(gl, size))
+            })
+        });
+        match frame {
+            Ok(frame) => {
+                surface.pause.hold(frame);
+                Error::PresentationPaused
+            }
+            Err(err) => err,
+        }
+    }
+
+    // Reports a change in the scale of the display that a widget surface's window is on, and
+    // returns the size to resize an automatically rescaled surface to once this frame is shown.
+    fn update_scale(&self, surface: &mut Surface, window_handle: HWND) -> Option<Size2D<i32>> {
+        let scale = widget::window_scale(window_handle)?;
+        let native_size = self.query_surface_native_size(surface).ok();
+        let tracker = surface.scale.as_mut()?;
+        let (change, rescale_to) = tracker.update(scale, surface.size, native_size);
+        if let (Some(change), Some(handler)) = (change, &self.surface_scale_changed_handler) {
+            handler(surface.id(), change);
+        }
+        rescale_to
+    }
+
+    // Reports a change in the color profile of the monitor that a widget surface's window is on,
+    // if one is noticed on this present.
+    fn update_color_profile(&self, surface: &mut Surface, window_handle: HWND) {
+        let handler = match self.color_profile_changed_handler {
+            Some(ref handler) => handler,
+            None => return,
+        };
+        let changed = surface
+            .color_profile
+            .update(|| color::window_color_profile(window_handle));
+        if let Some(profile) = changed {
+            handler(surface.id(), &profile);
+        }
+    }
+
+    /// Pauses the presentation of a widget surface: until it's resumed, each frame presented is
+    /// read back and held instead of shown, and `present_surface()` returns `PresentationPaused`.
+    ///
+    /// Changes in the scale of the window's display aren't noticed while presentation is paused.
+    /// Contexts without `glBlitFramebuffer()` return `RequiredExtensionUnavailable`.
+    pub fn pause_presentation(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<PausedPresentation, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if let Win32Objects::Pbuffer { .. } = surface.win32_objects {
+            return Err(Error::NoWidgetAttached);
+        }
+        if !self.capabilities(context)?.blit_framebuffer {
+            return Err(Error::RequiredExtensionUnavailable);
+        }
+        surface.pause.pause()
+    }
+
+    /// Ends a pause in the presentation of a widget surface.
+    ///
+    /// If `present_pending` is true, the frame held during the pause is drawn back into the
+    /// surface and presented as by `present_surface()`; otherwise, it's discarded.
+    pub fn resume_presentation(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        paused: PausedPresentation,
+        present_pending: bool,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if let Win32Objects::Pbuffer { .. } = surface.win32_objects {
+            return Err(Error::NoWidgetAttached);
+        }
+        match surface.pause.resume(paused)? {
+            Some(frame) if present_pending => {
+                let egl_surface = surface.egl_surface;
+                self.lost_contexts.check(context.id, || unsafe {
+                    let egl_context = context.egl_context;
+                    with_window_surface_current(self.egl_display, egl_surface, egl_context, || {
+                        GL_FUNCTIONS.with(|gl| frame.draw(gl))
+                    })
+                })?;
+                self.present_surface(context, surface)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Displays the contents of a widget surface on screen, and returns a fence that signals once
+    /// the GPU has finished rendering it.
+    ///
+    /// The fence is an `EGL_SYNC_FENCE`, inserted with the surface's context current right after
+    /// its buffers are swapped. Generic surfaces aren't presented, and only get the fence.
+    pub fn present_surface_with_fence(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<SurfaceFence, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if let Win32Objects::Window { .. } = surface.win32_objects {
+            self.present_surface(context, surface)?;
+        }
+        let _guard = self.make_context_current_guarded(context)?;
+        let egl_fence =
+            GL_FUNCTIONS.with(|gl| unsafe { EGLFence::insert(gl, self.egl_display) })?;
+        Ok(SurfaceFence { egl_fence })
+    }
+
+    /// Makes the context wait on the GPU until the fence signals, with `eglWaitSync()`.
+    pub fn wait_fence(&self, context: &Context, fence: &SurfaceFence) -> Result<(), Error> {
+        let _guard = self.make_context_current_guarded(context)?;
+        unsafe { fence.egl_fence.wait(self.egl_display) }
     }
 
     /// Resizes a widget surface.
+    ///
+    /// ANGLE only notices that a window has been resized when its surface is made current, so
+    /// this makes the surface current with the context and records the size that ANGLE reports
+    /// afterward. Generic surfaces are pbuffers of a fixed size, so resizing them returns
+    /// `UnsupportedOnThisPlatform`.
     pub fn resize_surface(
         &self,
-        _context: &Context,
+        context: &Context,
         surface: &mut Surface,
         size: Size2D<i32>,
     ) -> Result<(), Error> {
-        surface.size = size;
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        check_surface_size(size, &self.capabilities(context)?)?;
+        if let Win32Objects::Pbuffer { .. } = surface.win32_objects {
+            return Err(Error::UnsupportedOnThisPlatform);
+        }
+
+        let _guard = CurrentContextGuard::new();
+        EGL_FUNCTIONS.with(|egl| unsafe {
+            let result = egl.MakeCurrent(
+                self.egl_display,
+                surface.egl_surface,
+                surface.egl_surface,
+                context.egl_context,
+            );
+            if result == egl::FALSE {
+                let err = egl.GetError().to_windowing_api_error();
+                return Err(Error::MakeCurrentFailed(err));
+            }
+
+            let (mut width, mut height) = (0, 0);
+            egl.QuerySurface(
+                self.egl_display,
+                surface.egl_surface,
+                egl::WIDTH as EGLint,
+                &mut width,
+            );
+            egl.QuerySurface(
+                self.egl_display,
+                surface.egl_surface,
+                egl::HEIGHT as EGLint,
+                &mut height,
+            );
+            surface.size = if width > 0 && height > 0 {
+                Size2D::new(width, height)
+            } else {
+                size
+            };
+            // The window's buffers have been replaced.
+            surface.buffer_age.reset();
+            surface.pause.force_resume();
+            if let Some(ref mut scale) = surface.scale {
+                scale.resized();
+            }
+            Ok(())
+        })
+    }
+
+    /// Sets the number of vertical blanks that `present_surface()` waits for before showing each
+    /// frame on a widget surface, and returns the interval that took effect.
+    ///
+    /// Negative intervals are only honored if the surface's EGL config allows them, and are
+    /// otherwise replaced with their absolute value. Intervals outside the range that the config
+    /// supports return `UnsupportedOnThisPlatform`.
+    pub fn set_swap_interval(
+        &mut self,
+        context: &Context,
+        surface: &Surface,
+        interval: i32,
+    ) -> Result<i32, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        match surface.win32_objects {
+            Win32Objects::Window { .. } => unsafe {
+                set_swap_interval(
+                    self.egl_display,
+                    surface.egl_surface,
+                    context.egl_context,
+                    interval,
+                )
+            },
+            Win32Objects::Pbuffer { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Returns true if the EGL config of a widget surface allows the negative swap intervals of
+    /// adaptive vsync.
+    pub fn supports_adaptive_swap(&self, surface: &Surface) -> bool {
+        match surface.win32_objects {
+            Win32Objects::Window { .. } => unsafe {
+                supports_adaptive_swap(self.egl_display, surface.egl_surface)
+            },
+            Win32Objects::Pbuffer { .. } => false,
+        }
+    }
+
+    /// Lets surfman switch a widget surface between vsync and immediate presentation by itself,
+    /// following `policy`, or stops it with `None`.
+    ///
+    /// The frame time is measured from the return of `eglSwapBuffers()`.
+    pub fn set_auto_swap_interval(
+        &mut self,
+        context: &Context,
+        surface: &mut Surface,
+        policy: Option<AutoVsyncPolicy>,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        match surface.win32_objects {
+            Win32Objects::Window { .. } => {
+                if policy.is_some() {
+                    unsafe {
+                        set_swap_interval(
+                            self.egl_display,
+                            surface.egl_surface,
+                            context.egl_context,
+                            1,
+                        )?;
+                    }
+                }
+                surface.auto_vsync = policy.map(AutoVsync::new);
+                Ok(())
+            }
+            Win32Objects::Pbuffer { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Returns what automatic vsync has decided for a surface, or `None` if it isn't enabled.
+    #[inline]
+    pub fn auto_swap_interval_stats(&self, surface: &Surface) -> Option<AutoVsyncStats> {
+        surface.auto_vsync.as_ref().map(AutoVsync::stats)
+    }
+
+    /// Waits until the buffer of the last frame presented on a widget surface has been released,
+    /// or until `timeout` elapses, and reports whether the call had to block.
+    ///
+    /// ANGLE gives no signal for the release of its swap chain's buffers, so this waits on a
+    /// fence inserted when the frame was presented, which signals once the GPU has finished
+    /// rendering it.
+    pub fn wait_previous_buffer_released(
+        &self,
+        surface: &Surface,
+        timeout: Duration,
+    ) -> Result<BufferRelease, Error> {
+        match surface.win32_objects {
+            Win32Objects::Window { .. } => match surface.present_fence {
+                None => Ok(BufferRelease::Released),
+                Some(ref present_fence) => present_fence.wait(timeout),
+            },
+            Win32Objects::Pbuffer { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Chooses which attachments of a surface are invalidated right after each present.
+    ///
+    /// The color buffer of a widget surface can only be invalidated if its EGL surface doesn't
+    /// preserve it across swaps, and that of a generic surface never can. Either returns
+    /// `PreservedColorBuffer`.
+    pub fn set_post_present_invalidation(
+        &self,
+        surface: &mut Surface,
+        set: InvalidateSet,
+    ) -> Result<(), Error> {
+        // Consumers read the color buffers of generic surfaces once they're presented.
+        let color_preserved = match surface.win32_objects {
+            Win32Objects::Window { .. } => unsafe {
+                preserves_color_buffer(self.egl_display, surface.egl_surface)
+            },
+            Win32Objects::Pbuffer { .. } => true,
+        };
+        surface.invalidation.change(set, color_preserved)
+    }
+
+    /// Returns which attachments of a surface are invalidated right after each present.
+    #[inline]
+    pub fn post_present_invalidation(&self, surface: &Surface) -> InvalidateSet {
+        surface.invalidation.set()
+    }
+
+    /// Invalidates the attachments of a generic surface that were chosen with
+    /// `set_post_present_invalidation()`.
+    ///
+    /// Generic surfaces are pbuffers, so the surface is made current with the context for the
+    /// call. Widget surfaces are invalidated by `present_surface()`, and return `WidgetAttached`.
+    pub fn invalidate_surface(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if let Win32Objects::Window { .. } = surface.win32_objects {
+            return Err(Error::WidgetAttached);
+        }
+
+        unsafe {
+            let _guard = CurrentContextGuard::new();
+            EGL_FUNCTIONS.with(|egl| {
+                let ok = egl.MakeCurrent(
+                    self.egl_display,
+                    surface.egl_surface,
+                    surface.egl_surface,
+                    context.egl_context,
+                );
+                if ok == egl::FALSE {
+                    let err = egl.GetError().to_windowing_api_error();
+                    return Err(Error::MakeCurrentFailed(err));
+                }
+                GL_FUNCTIONS.with(|gl| surface.invalidation.invalidate(gl, 0));
+                Ok(())
+            })
+        }
+    }
+
+    /// Clears a region of a surface, measured from its top left, to `color`, and its depth and
+    /// stencil buffers to `depth` and `stencil` if they're given and the surface has them.
+    ///
+    /// Every surface on this backend is an EGL surface, which is made current with the context for
+    /// the call. The state that the clear changes is put back afterward.
+    pub fn clear_surface_region(
+        &self,
+        context: &mut Context,
+        surface: &mut Surface,
+        rect: Option<Rect<i32>>,
+        color: [f32; 4],
+        depth: Option<f32>,
+        stencil: Option<i32>,
+    ) -> Result<(), Error> {
+        let region = ClearRegion::new(rect, color, depth, stencil);
+        self.clear_surface_with(context, surface, &region)
+    }
+
+    /// Fills a surface with this device's debug fill, as if it had just been created, or does
+    /// nothing if the fill is `DebugFill::None`.
+    pub fn debug_fill_surface(
+        &self,
+        context: &mut Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        match self.debug_fill.region() {
+            Some(region) => self.clear_surface_with(context, surface, &region),
+            None => Ok(()),
+        }
+    }
+
+    // Fills a new surface with the debug fill, if there is one. The surface is usable either way,
+    // so failing to fill it is only logged.
+    fn debug_fill_new_surface(&self, context: &Context, surface: &mut Surface) {
+        if let Some(region) = self.debug_fill.region() {
+            if let Err(err) = self.clear_surface_with(context, surface, &region) {
+                warn!(
+                    "Failed to fill a new surface with the debug fill: {:?}",
+                    err
+                );
+            }
+        }
+    }
+
+    fn clear_surface_with(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        region: &ClearRegion,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        if let Win32Objects::Pbuffer { .. } = surface.win32_objects {
+            surface.purge_state.check_usable()?;
+        }
+        GL_FUNCTIONS.with(|gl| unsafe {
+            clear_window_surface(
+                gl,
+                self.egl_display,
+                surface.egl_surface,
+                context.egl_context,
+                surface.size,
+                region,
+            )
+        })
+    }
+
+    /// Returns how many frames ago the back buffer of a widget surface was last presented, or 0
+    /// if its contents are undefined.
+    ///
+    /// The age is queried with `EGL_EXT_buffer_age` right after each swap, while the surface is
+    /// still current and nothing has been drawn into it, so it's meant to be read after
+    /// `present_surface()` and before drawing the next frame.
+    pub fn surface_buffer_age(&self, surface: &Surface) -> Result<u32, Error> {
+        match surface.win32_objects {
+            Win32Objects::Window { .. } => Ok(surface.buffer_age.get()),
+            _ => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Returns the current size of the client area of a widget surface's window.
+    pub fn query_surface_native_size(&self, surface: &Surface) -> Result<Size2D<i32>, Error> {
+        match surface.win32_objects {
+            Win32Objects::Window { window_handle } => {
+                widget::window_client_size(window_handle).ok_or(Error::InvalidNativeWidget)
+            }
+            _ => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Returns the rotation that the window system applies to a widget surface's frames to show
+    /// them on its display.
+    ///
+    /// Windows rotates frames itself, so this is always `SurfaceTransform::Identity`.
+    #[inline]
+    pub fn surface_transform(&self, _surface: &Surface) -> SurfaceTransform {
+        SurfaceTransform::Identity
+    }
+
+    // Fails with `SurfaceSizeMismatch` if a widget surface's window has been resized since the
+    // surface was last sized. Other failures are left for the present itself to report.
+    fn check_native_size(&self, surface: &Surface) -> Result<(), Error> {
+        match self.query_surface_native_size(surface) {
+            Ok(actual) if actual != surface.size => Err(Error::SurfaceSizeMismatch {
+                expected: surface.size,
+                actual,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Registers a function to call with the ID and new size of each widget surface of this
+    /// device that is found to have been resized behind surfman's back.
+    ///
+    /// ANGLE resizes window surfaces to follow their window itself, so the handler is never
+    /// called.
+    #[inline]
+    pub fn set_surface_resized_handler(&mut self, _: Option<SurfaceResizedHandler>) {}
+
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to have moved to a display with a different scale factor, replacing any previous
+    /// one, or unregisters it if `handler` is `None`.
+    ///
+    /// `present_surface()` compares the DPI that `GetDpiForWindow()` reports for the surface's
+    /// window with the one that it last saw, so changes are only noticed on Windows 10 version
+    /// 1607 and later, in processes that are aware of the DPI of each monitor.
+    #[inline]
+    pub fn set_surface_scale_changed_handler(
+        &mut self,
+        handler: Option<SurfaceScaleChangedHandler>,
+    ) -> Result<(), Error> {
+        self.surface_scale_changed_handler = handler;
         Ok(())
     }
 
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to be displayed with a different color profile, with the new profile, replacing any
+    /// previous one, or unregisters it if `handler` is `None`.
+    ///
+    /// Every 30 presents, `present_surface()` asks Windows color management for the profile of
+    /// the window's monitor again, as `widget_color_profile()` does, and compares it with the
+    /// profile that it last saw.
+    #[inline]
+    pub fn set_color_profile_changed_handler(
+        &mut self,
+        handler: Option<ColorProfileChangedHandler>,
+    ) {
+        self.color_profile_changed_handler = handler;
+    }
+
+    /// Sets whether the system may discard the contents of a generic surface to free memory.
+    ///
+    /// Volatile surfaces have their Direct3D texture offered to the system with
+    /// `IDXGIDevice2::OfferResources()`. This requires DXGI 1.2, from Windows 8 onward.
+    pub fn set_surface_purgeable(
+        &self,
+        surface: &mut Surface,
+        purgeability: Purgeability,
+    ) -> Result<(), Error> {
+        let mut backing = self.dxgi_backing(surface)?;
+        surface.purge_state.set(&mut backing, purgeability)
+    }
+
+    /// Makes a surface non-purgeable again, and reports whether the system discarded its contents
+    /// since it was made volatile.
+    pub fn reclaim_surface(&self, surface: &mut Surface) -> Result<ReclaimResult, Error> {
+        let mut backing = self.dxgi_backing(surface)?;
+        surface.purge_state.reclaim(&mut backing)
+    }
+
+    fn dxgi_backing(&self, surface: &Surface) -> Result<DxgiBacking, Error> {
+        let share_handle = match surface.win32_objects {
+            Win32Objects::Window { .. } => return Err(Error::WidgetAttached),
+            Win32Objects::Pbuffer { share_handle, .. } => share_handle,
+        };
+
+        unsafe {
+            let dxgi_device = self
+                .d3d11_device
+                .cast::<IDXGIDevice2>()
+                .map_err(|_| Error::UnsupportedOnThisPlatform)?;
+
+            let mut resource = ptr::null_mut();
+            let result = self.d3d11_device.OpenSharedResource(
+                share_handle,
+                &IDXGIResource::uuidof(),
+                &mut resource,
+            );
+            if result != S_OK || resource.is_null() {
+                return Err(Error::Failed);
+            }
+
+            Ok(DxgiBacking {
+                dxgi_device,
+                resource: ComPtr::from_raw(resource as *mut IDXGIResource),
+            })
+        }
+    }
+
     /// Returns various information about the surface, including the framebuffer object needed to
     /// render to this surface.
     ///
@@ -581,9 +1568,116 @@ impl Device {
             id: surface.id(),
             context_id: surface.context_id,
             framebuffer_object: 0,
+            access: surface.access,
+            format: match (&surface.win32_objects, surface.colorspace) {
+                (Win32Objects::Pbuffer { .. }, ColorEncoding::Linear)
+                    if surface.color_bits.red == 10 =>
+                {
+                    gl::RGB10_A2
+                }
+                (Win32Objects::Pbuffer { .. }, ColorEncoding::Linear)
+                    if surface.color_bits.red == 16 =>
+                {
+                    gl::RGBA16F
+                }
+                (Win32Objects::Pbuffer { .. }, ColorEncoding::Linear) => gl::RGBA8,
+                (Win32Objects::Pbuffer { .. }, ColorEncoding::Srgb) => gl::SRGB8_ALPHA8,
+                (Win32Objects::Window { .. }, _) => gl::NONE,
+            },
+            purgeability: surface.purge_state.purgeability(),
+            colorspace: surface.colorspace,
+            color_bits: surface.color_bits,
+            preserves_contents: surface.preserves_contents,
+            texture_target: match surface.win32_objects {
+                Win32Objects::Pbuffer { .. } => SURFACE_GL_TEXTURE_TARGET,
+                Win32Objects::Window { .. } => gl::NONE,
+            },
+            stride: None,
+            native_format: match surface.win32_objects {
+                Win32Objects::Pbuffer {
+                    dxgi_format: Some(dxgi_format),
+                    ..
+                } => NativeFormat::Dxgi(dxgi_format),
+                Win32Objects::Pbuffer { .. } => NativeFormat::Unknown,
+                Win32Objects::Window { .. } => NativeFormat::Unknown,
+            },
+        }
+    }
+
+    /// Returns the color profile of the output that a widget surface is displayed on.
+    ///
+    /// This is the ICC profile that Windows color management assigns to the monitor.
+    pub fn widget_color_profile(&self, surface: &Surface) -> ColorProfile {
+        match surface.win32_objects {
+            Win32Objects::Window { window_handle } => color::window_color_profile(window_handle),
+            Win32Objects::Pbuffer { .. } => ColorProfile::AssumedSrgb,
         }
     }
 
+    /// Returns true if a widget surface is on a monitor driven by a different GPU than this
+    /// device.
+    ///
+    /// Windows copies such frames between the GPUs when presenting. Returns false for generic
+    /// surfaces and if no DXGI adapter drives the monitor.
+    pub fn presenting_cross_adapter(&self, surface: &Surface) -> bool {
+        let window_handle = match surface.win32_objects {
+            Win32Objects::Window { window_handle } => window_handle,
+            Win32Objects::Pbuffer { .. } => return false,
+        };
+        match identity::presenting_dxgi_adapter(window_handle) {
+            Some(dxgi_adapter) => !identities_match(
+                &identity::dxgi_adapter_identity(&dxgi_adapter),
+                &self.identity(),
+            ),
+            None => false,
+        }
+    }
+
+    /// Returns the timestamps of the most recent presentations of a widget surface, oldest first.
+    ///
+    /// `compositor_ack` is when `eglSwapBuffers()` returned. `displayed` is reported where ANGLE
+    /// supports `EGL_ANDROID_get_frame_timestamps`, and otherwise estimated from the Desktop
+    /// Window Manager's timing, as `query_presentation_time()` does, once the frame has been
+    /// shown.
+    ///
+    /// Returns an empty list for generic surfaces and for widget surfaces that haven't been
+    /// presented yet.
+    pub fn presentation_timestamps(&self, surface: &Surface) -> Vec<PresentRecord> {
+        presentation_time::records_with_display_times(
+            &surface.present_history,
+            |record, statistics| {
+                if let Some(ref frame_timestamps) = surface.frame_timestamps {
+                    frame_timestamps.update(statistics);
+                }
+                timing::estimate_actual_present(record, statistics);
+            },
+        )
+    }
+
+    /// Returns when the window system presented the most recent frame of a widget surface that
+    /// is known to have reached the display, or the most recent frame if none is yet.
+    ///
+    /// All three times are reported where ANGLE supports `EGL_ANDROID_get_frame_timestamps`.
+    /// Otherwise, `actual_present` is estimated from the Desktop Window Manager's timing, as the
+    /// first vblank after the frame's swap, once that vblank has passed.
+    ///
+    /// Returns `Error::NoWidgetAttached` for generic surfaces, and `Error::NotPresented` if
+    /// nothing has been presented on the surface.
+    pub fn query_presentation_time(
+        &self,
+        surface: &Surface,
+    ) -> Result<PresentationStatistics, Error> {
+        if let Win32Objects::Pbuffer { .. } = surface.win32_objects {
+            return Err(Error::NoWidgetAttached);
+        }
+        presentation_time::latest_statistics(&surface.present_history, |record, statistics| {
+            if let Some(ref frame_timestamps) = surface.frame_timestamps {
+                frame_timestamps.update(statistics);
+            }
+            timing::estimate_actual_present(record, statistics);
+        })
+    }
+
     /// Returns the OpenGL texture object containing the contents of this surface.
     ///
     /// It is only legal to read from, not write to, this texture object.
@@ -591,9 +1685,27 @@ impl Device {
     pub fn surface_texture_object(&self, surface_texture: &SurfaceTexture) -> GLuint {
         surface_texture.gl_texture
     }
+
+    /// Returns the sized internal format of the OpenGL texture of this surface texture, such as
+    /// `GL_RGBA8` or `GL_RGBA16F`.
+    ///
+    /// This is the `SurfaceInfo::format` of the wrapped surface, which tells shaders whether they
+    /// sample normalized or floating-point values.
+    #[inline]
+    pub fn surface_texture_format(&self, surface_texture: &SurfaceTexture) -> GLenum {
+        self.surface_info(&surface_texture.surface).format
+    }
 }
 
 impl Surface {
+    /// Returns the ID of the context that this surface was created with.
+    ///
+    /// A surface can only be bound to the context that it was created with.
+    #[inline]
+    pub fn context_id(&self) -> ContextID {
+        self.context_id
+    }
+
     #[inline]
     fn id(&self) -> SurfaceID {
         SurfaceID(self.egl_surface as usize)
@@ -615,3 +1727,14 @@ impl Surface {
 pub struct SurfaceDataGuard<'a> {
     phantom: PhantomData<&'a ()>,
 }
+
+// Returns the EGL attributes that give a surface the colorspace.
+fn colorspace_attributes(colorspace: ColorEncoding) -> Vec<EGLint> {
+    match colorspace {
+        ColorEncoding::Linear => vec![],
+        ColorEncoding::Srgb => vec![
+            egl::GL_COLORSPACE as EGLint,
+            egl::GL_COLORSPACE_SRGB as EGLint,
+        ],
+    }
+}
@@ -0,0 +1,46 @@
+// surfman/surfman/src/platform/windows/color.rs
+//
+//! Color profile queries shared by the Windows backends.
+
+use crate::ColorProfile;
+
+use std::ffi::OsString;
+use std::fs;
+use std::os::windows::ffi::OsStringExt;
+use std::ptr;
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::windef::HWND;
+use winapi::um::wingdi;
+use winapi::um::winuser;
+
+// Returns the ICC profile that Windows color management associates with the monitor the window
+// is on.
+pub(crate) fn window_color_profile(window_handle: HWND) -> ColorProfile {
+    unsafe {
+        let dc = winuser::GetDC(window_handle);
+        if dc.is_null() {
+            return ColorProfile::AssumedSrgb;
+        }
+
+        // The first call only reports the length of the profile path.
+        let mut path_length: DWORD = 0;
+        wingdi::GetICMProfileW(dc, &mut path_length, ptr::null_mut());
+        let mut path = vec![0; path_length as usize];
+        let ok =
+            path_length > 0 && wingdi::GetICMProfileW(dc, &mut path_length, path.as_mut_ptr()) != 0;
+        winuser::ReleaseDC(window_handle, dc);
+        if !ok {
+            return ColorProfile::AssumedSrgb;
+        }
+
+        let path_length = path
+            .iter()
+            .position(|&unit| unit == 0)
+            .unwrap_or(path.len());
+        let path = OsString::from_wide(&path[..path_length]);
+        fs::read(path)
+            .ok()
+            .and_then(ColorProfile::from_icc)
+            .unwrap_or(ColorProfile::AssumedSrgb)
+    }
+}
@@ -0,0 +1,128 @@
+// surfman/surfman/src/platform/windows/identity.rs
+//
+//! Device identity queries shared by the Windows backends.
+
+use crate::DeviceIdentity;
+#[cfg(angle)]
+use crate::{AdapterKey, AdapterKind};
+
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+use winapi::shared::dxgi::{self, IDXGIAdapter, IDXGIAdapter1, IDXGIFactory1};
+use winapi::shared::windef::HWND;
+use winapi::shared::winerror;
+use winapi::um::winuser::{self, MONITOR_DEFAULTTONEAREST};
+use winapi::Interface;
+use wio::com::ComPtr;
+
+// The PCI IDs of WARP, the Microsoft Basic Render Driver.
+const WARP_VENDOR_ID: u32 = 0x1414;
+const WARP_DEVICE_ID: u32 = 0x8c;
+
+// Intel makes the integrated GPUs that ANGLE treats as low-power.
+#[cfg(angle)]
+pub(crate) const INTEL_PCI_ID: u32 = 0x8086;
+
+// Returns the identity of the given DXGI adapter: its LUID, and whether it's WARP.
+pub(crate) fn dxgi_adapter_identity(dxgi_adapter: &ComPtr<IDXGIAdapter>) -> DeviceIdentity {
+    unsafe {
+        let mut adapter_desc = mem::zeroed();
+        if !winerror::SUCCEEDED(dxgi_adapter.GetDesc(&mut adapter_desc)) {
+            return DeviceIdentity::default();
+        }
+
+        let luid = adapter_desc.AdapterLuid;
+        DeviceIdentity {
+            adapter_luid: Some(((luid.HighPart as u32 as u64) << 32) | luid.LowPart as u64),
+            software: adapter_desc.VendorId == WARP_VENDOR_ID
+                && adapter_desc.DeviceId == WARP_DEVICE_ID,
+            ..DeviceIdentity::default()
+        }
+    }
+}
+
+// Returns the key of the given DXGI adapter: its PCI IDs, LUID, and description. Intel adapters
+// are low-power and WARP is software, as when choosing an adapter.
+#[cfg(angle)]
+pub(crate) fn dxgi_adapter_key(backend: &str, dxgi_adapter: &ComPtr<IDXGIAdapter>) -> AdapterKey {
+    let mut key = AdapterKey {
+        backend: backend.to_owned(),
+        kind: AdapterKind::HighPerformance,
+        pci_id: None,
+        adapter_luid: None,
+        registry_id: None,
+        driver: None,
+    };
+    unsafe {
+        let mut adapter_desc = mem::zeroed();
+        if !winerror::SUCCEEDED(dxgi_adapter.GetDesc(&mut adapter_desc)) {
+            return key;
+        }
+
+        key.kind = match (adapter_desc.VendorId, adapter_desc.DeviceId) {
+            (WARP_VENDOR_ID, WARP_DEVICE_ID) => AdapterKind::Software,
+            (INTEL_PCI_ID, _) => AdapterKind::LowPower,
+            _ => AdapterKind::HighPerformance,
+        };
+        key.pci_id = Some((adapter_desc.VendorId, adapter_desc.DeviceId));
+        let luid = adapter_desc.AdapterLuid;
+        key.adapter_luid = Some(((luid.HighPart as u32 as u64) << 32) | luid.LowPart as u64);
+        let description = &adapter_desc.Description;
+        let length = description
+            .iter()
+            .position(|&unit| unit == 0)
+            .unwrap_or(description.len());
+        key.driver = Some(String::from_utf16_lossy(&description[..length]));
+        key
+    }
+}
+
+// Returns the DXGI adapter that drives the monitor that the window covers the most of, or `None`
+// if no adapter has an output on that monitor.
+pub(crate) fn presenting_dxgi_adapter(window_handle: HWND) -> Option<ComPtr<IDXGIAdapter>> {
+    unsafe {
+        let monitor = winuser::MonitorFromWindow(window_handle, MONITOR_DEFAULTTONEAREST);
+        if monitor.is_null() {
+            return None;
+        }
+
+        let mut dxgi_factory: *mut IDXGIFactory1 = ptr::null_mut();
+        let result = dxgi::CreateDXGIFactory1(
+            &IDXGIFactory1::uuidof(),
+            &mut dxgi_factory as *mut *mut IDXGIFactory1 as *mut *mut c_void,
+        );
+        if !winerror::SUCCEEDED(result) || dxgi_factory.is_null() {
+            return None;
+        }
+        let dxgi_factory = ComPtr::from_raw(dxgi_factory);
+
+        let mut adapter_index = 0;
+        loop {
+            let mut dxgi_adapter_1: *mut IDXGIAdapter1 = ptr::null_mut();
+            if !winerror::SUCCEEDED(dxgi_factory.EnumAdapters1(adapter_index, &mut dxgi_adapter_1))
+            {
+                return None;
+            }
+            let dxgi_adapter: ComPtr<IDXGIAdapter> = ComPtr::from_raw(dxgi_adapter_1).up();
+            adapter_index += 1;
+
+            let mut output_index = 0;
+            loop {
+                let mut dxgi_output = ptr::null_mut();
+                if !winerror::SUCCEEDED(dxgi_adapter.EnumOutputs(output_index, &mut dxgi_output)) {
+                    break;
+                }
+                let dxgi_output = ComPtr::from_raw(dxgi_output);
+                output_index += 1;
+
+                let mut output_desc = mem::zeroed();
+                if winerror::SUCCEEDED(dxgi_output.GetDesc(&mut output_desc))
+                    && output_desc.Monitor == monitor
+                {
+                    return Some(dxgi_adapter);
+                }
+            }
+        }
+    }
+}
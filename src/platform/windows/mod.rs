@@ -2,8 +2,13 @@
 //
 //! Windows support, either via the native WGL interface or Google's ANGLE library.
 
-#[cfg(feature = "sm-angle")]
+#[cfg(angle)]
 pub mod angle;
 
-#[cfg(not(feature = "sm-no-wgl"))]
+mod color;
+mod identity;
+mod timing;
+mod widget;
+
+#[cfg(wgl_backend)]
 pub mod wgl;
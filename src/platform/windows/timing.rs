@@ -0,0 +1,51 @@
+// surfman/surfman/src/platform/windows/timing.rs
+//
+//! Presentation timing shared by the Windows backends.
+
+use crate::presentation_time::{self, PresentationStatistics};
+use crate::PresentRecord;
+
+use std::mem;
+use std::ptr;
+use winapi::shared::winerror;
+use winapi::um::dwmapi::{self, DWM_TIMING_INFO};
+
+// Estimates when a frame presented on a window reached the display, from the Desktop Window
+// Manager's vblank timing, unless the frame's statistics already say.
+//
+// DWM shows a window's new frame at the first vblank after the frame is swapped, at the earliest,
+// so that vblank is reported once it has passed. Frames that DWM takes longer to compose are shown
+// a refresh or more later than reported.
+pub(crate) fn estimate_actual_present(
+    record: &PresentRecord,
+    statistics: &mut PresentationStatistics,
+) {
+    if statistics.actual_present.is_some() {
+        return;
+    }
+    let swapped = match record.compositor_ack {
+        Some(swapped) => swapped,
+        None => return,
+    };
+    unsafe {
+        let mut timing_info: DWM_TIMING_INFO = mem::zeroed();
+        timing_info.cbSize = mem::size_of::<DWM_TIMING_INFO>() as u32;
+        // Since Windows 8.1, DWM only reports the timing of the whole desktop.
+        let result = dwmapi::DwmGetCompositionTimingInfo(ptr::null_mut(), &mut timing_info);
+        if !winerror::SUCCEEDED(result) {
+            return;
+        }
+        let (vblank, refresh_period) = (timing_info.qpcVBlank, timing_info.qpcRefreshPeriod);
+        let last_vblank = presentation_time::instant_from_performance_counter(vblank);
+        let previous_vblank = presentation_time::instant_from_performance_counter(
+            vblank.saturating_sub(refresh_period),
+        );
+        if let (Some(last_vblank), Some(previous_vblank)) = (last_vblank, previous_vblank) {
+            statistics.actual_present = presentation_time::first_vblank_after(
+                swapped,
+                last_vblank,
+                last_vblank - previous_vblank,
+            );
+        }
+    }
+}
@@ -4,10 +4,16 @@
 //!
 //! Window server connections are implicit in the Win32 API, so this is a zero-sized type.
 
+use super::context::WGL_EXTENSION_FUNCTIONS;
 use super::device::{Adapter, Device, NativeDevice};
 use super::surface::NativeWidget;
+use crate::acceleration;
+use crate::identity;
+use crate::platform::windows::identity as windows_identity;
+use crate::platform::windows::widget;
 use crate::Error;
 use crate::GLApi;
+use crate::{AdapterKey, AdapterKind};
 
 use euclid::default::Size2D;
 
@@ -15,6 +21,8 @@ use std::os::raw::c_void;
 
 use winapi::shared::windef::HWND;
 
+const ADAPTER_KEY_BACKEND: &str = "wgl";
+
 /// Represents a connection to the display server.
 ///
 /// Window server connections are implicit in the Win32 API, so this is a zero-sized type.
@@ -27,6 +35,8 @@ pub struct Connection;
 #[derive(Clone)]
 pub struct NativeConnection;
 
+assert_send_sync!(NativeConnection);
+
 impl Connection {
     /// Connects to the default display.
     #[inline]
@@ -34,6 +44,23 @@ impl Connection {
         Ok(Connection)
     }
 
+    /// An alias for `Connection::new()`, as WGL renders offscreen through a hidden window.
+    #[inline]
+    pub fn new_headless() -> Result<Connection, Error> {
+        Connection::new()
+    }
+
+    /// Connects to the display if it supports the given OpenGL API flavor.
+    ///
+    /// This backend only supports OpenGL, so other flavors return `Error::UnsupportedGLType`.
+    #[inline]
+    pub fn new_with_api(gl_api: GLApi) -> Result<Connection, Error> {
+        match gl_api {
+            GLApi::GL => Connection::new(),
+            _ => Err(Error::UnsupportedGLType),
+        }
+    }
+
     /// An alias for `Connection::new()`, present for consistency with other backends.
     #[inline]
     pub unsafe fn from_native_connection(_: NativeConnection) -> Result<Connection, Error> {
@@ -52,6 +79,14 @@ impl Connection {
         GLApi::GL
     }
 
+    /// Returns true if WGL advertises the named extension.
+    pub fn supports_extension(&self, name: &str) -> bool {
+        WGL_EXTENSION_FUNCTIONS
+            .extensions
+            .iter()
+            .any(|extension| extension == name)
+    }
+
     /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
     ///
     /// This is an alias for `Connection::create_hardware_adapter()`.
@@ -78,10 +113,73 @@ impl Connection {
         self.create_low_power_adapter()
     }
 
+    /// Returns the adapter whose driver or device name contains `needle`, ignoring case.
+    ///
+    /// WGL always renders on the GPU driving the window, so adapters can't be chosen by name and
+    /// this always returns `Error::NoAdapterFound`.
+    #[inline]
+    pub fn create_adapter_by_name(&self, _: &str) -> Result<Adapter, Error> {
+        Err(Error::NoAdapterFound)
+    }
+
+    /// Returns a key that describes the adapter, which can be saved and passed to
+    /// `find_adapter()` in a later run to find the same adapter again.
+    ///
+    /// WGL can't tell which GPU an adapter will render with, so the key only records whether a
+    /// high-performance or low-power adapter was asked for.
+    pub fn adapter_key(&self, adapter: &Adapter) -> Result<AdapterKey, Error> {
+        let kind = match *adapter {
+            Adapter::HighPerformance => AdapterKind::HighPerformance,
+            Adapter::LowPower => AdapterKind::LowPower,
+        };
+        Ok(AdapterKey {
+            backend: ADAPTER_KEY_BACKEND.to_owned(),
+            kind,
+            pci_id: None,
+            adapter_luid: None,
+            registry_id: None,
+            driver: None,
+        })
+    }
+
+    /// Returns the adapter that a key from `adapter_key()` describes.
+    ///
+    /// Returns `Error::IncompatibleAdapter` if the key was taken with another backend, and
+    /// `Error::NoAdapterFound` if it doesn't describe a WGL adapter.
+    pub fn find_adapter(&self, key: &AdapterKey) -> Result<Adapter, Error> {
+        let mut candidates = vec![];
+        for adapter in [Adapter::HighPerformance, Adapter::LowPower] {
+            let candidate = self.adapter_key(&adapter)?;
+            candidates.push((adapter, candidate));
+        }
+        identity::find_adapter(ADAPTER_KEY_BACKEND, key, candidates)
+    }
+
+    /// Returns true if the two adapters are the same adapter.
+    pub fn is_same_adapter(&self, a: &Adapter, b: &Adapter) -> bool {
+        match (self.adapter_key(a), self.adapter_key(b)) {
+            (Ok(a), Ok(b)) => a.matches(&b),
+            _ => false,
+        }
+    }
+
+    /// Returns the adapter for the GPU that drives the monitor the window is on.
+    ///
+    /// If the window spans several monitors, this is the GPU driving the monitor that it covers
+    /// the most of. As when choosing an adapter, Intel GPUs are low-power and all others are
+    /// high-performance. Returns `Error::NoAdapterFound` if no DXGI adapter drives that monitor.
+    pub fn adapter_for_widget(&self, native_widget: &NativeWidget) -> Result<Adapter, Error> {
+        windows_identity::presenting_dxgi_adapter(native_widget.window_handle)
+            .map(|dxgi_adapter| Adapter::from_dxgi_adapter(&dxgi_adapter))
+            .ok_or(Error::NoAdapterFound)
+    }
+
     /// Opens a device.
     #[inline]
     pub fn create_device(&self, adapter: &Adapter) -> Result<Device, Error> {
-        Device::new(adapter)
+        let device = Device::new(adapter)?;
+        acceleration::check_device(&device);
+        Ok(device)
     }
 
     /// Creates a `Device` from a Direct3D 11 device and associated GL/DX interop handle.
@@ -112,14 +210,15 @@ impl Connection {
     }
 
     /// Create a native widget from a raw pointer
+    ///
+    /// The real size of the window's client area is queried. If it differs from `size`,
+    /// `native_widget_adjusted_size()` reports it.
     pub unsafe fn create_native_widget_from_ptr(
         &self,
         raw: *mut c_void,
-        _size: Size2D<i32>,
+        size: Size2D<i32>,
     ) -> NativeWidget {
-        NativeWidget {
-            window_handle: raw as HWND,
-        }
+        native_widget(raw as HWND, size)
     }
 
     /// Create a native widget type from the given `RawWindowHandle`.
@@ -127,14 +226,12 @@ impl Connection {
     pub fn create_native_widget_from_raw_window_handle(
         &self,
         raw_handle: rwh_05::RawWindowHandle,
-        _size: Size2D<i32>,
+        size: Size2D<i32>,
     ) -> Result<NativeWidget, Error> {
         use rwh_05::RawWindowHandle::Win32;
 
         match raw_handle {
-            Win32(handle) => Ok(NativeWidget {
-                window_handle: handle.hwnd as HWND,
-            }),
+            Win32(handle) => Ok(native_widget(handle.hwnd as HWND, size)),
             _ => Err(Error::IncompatibleNativeWidget),
         }
     }
@@ -144,17 +241,29 @@ impl Connection {
     pub fn create_native_widget_from_window_handle(
         &self,
         handle: rwh_06::WindowHandle,
-        _size: Size2D<i32>,
+        size: Size2D<i32>,
     ) -> Result<NativeWidget, Error> {
         use rwh_06::RawWindowHandle::Win32;
 
         match handle.as_raw() {
-            Win32(handle) => Ok(NativeWidget {
-                window_handle: handle.hwnd.get() as HWND,
-            }),
+            Win32(handle) => Ok(native_widget(handle.hwnd.get() as HWND, size)),
             _ => Err(Error::IncompatibleNativeWidget),
         }
     }
+
+    /// Returns the real size of a native widget if it differs from the size that the widget was
+    /// created with.
+    #[inline]
+    pub fn native_widget_adjusted_size(&self, native_widget: &NativeWidget) -> Option<Size2D<i32>> {
+        native_widget.adjusted_size
+    }
+}
+
+fn native_widget(window_handle: HWND, size: Size2D<i32>) -> NativeWidget {
+    NativeWidget {
+        window_handle,
+        adjusted_size: widget::adjusted_window_size(window_handle, size),
+    }
 }
 
 impl NativeConnection {
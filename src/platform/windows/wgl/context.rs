@@ -4,10 +4,25 @@
 
 use super::device::{DCGuard, Device, HiddenWindow};
 use super::surface::{Surface, Win32Objects};
+use crate::acceleration;
+use crate::bulk::{self, BulkOps};
 use crate::context::{self, CREATE_CONTEXT_MUTEX};
+use crate::convert;
+use crate::current::{self, SavedCurrent};
+use crate::gl_utils;
+use crate::info;
+use crate::leak_check::{self, TrackedObject};
 use crate::surface::Framebuffer;
-use crate::{ContextAttributeFlags, ContextAttributes, ContextID, Error, GLVersion};
-use crate::{SurfaceInfo, WindowingApiError};
+use crate::ContextDescriptorAttributes;
+use crate::ContextLostHandler;
+use crate::GLVersion;
+use crate::GlError;
+use crate::ResetStatus;
+use crate::{
+    ColorDepth, ContextInfo, GLCapabilities, PlaceholderPolicy, RenderTargetInfo, SurfaceInfo,
+    WindowingApiError,
+};
+use crate::{ContextAttributeFlags, ContextAttributes, ContextID, ContextPriority, Error};
 
 use crate::gl;
 use crate::gl::types::{GLenum, GLint, GLuint};
@@ -15,13 +30,16 @@ use crate::Gl;
 use std::borrow::Cow;
 use std::ffi::{CStr, CString};
 use std::mem;
+use std::ops::RangeInclusive;
 use std::os::raw::{c_char, c_int, c_void};
 use std::ptr;
-use std::thread;
+use std::thread::{self, ThreadId};
 use winapi::shared::minwindef::{BOOL, FALSE, FLOAT, HMODULE, LPARAM, LPVOID, LRESULT, UINT};
 use winapi::shared::minwindef::{WORD, WPARAM};
 use winapi::shared::ntdef::{HANDLE, LPCSTR};
 use winapi::shared::windef::{HBRUSH, HDC, HGLRC, HWND};
+use winapi::shared::winerror::ERROR_BUSY;
+use winapi::um::errhandlingapi::GetLastError;
 use winapi::um::libloaderapi;
 use winapi::um::wingdi::{self, PFD_DOUBLEBUFFER, PFD_DRAW_TO_WINDOW, PFD_MAIN_PLANE};
 use winapi::um::wingdi::{wglCreateContext, wglDeleteContext, wglGetCurrentContext};
@@ -36,15 +54,26 @@ const WGL_SUPPORT_OPENGL_ARB: GLenum = 0x2010;
 const WGL_DOUBLE_BUFFER_ARB: GLenum = 0x2011;
 const WGL_PIXEL_TYPE_ARB: GLenum = 0x2013;
 const WGL_COLOR_BITS_ARB: GLenum = 0x2014;
+const WGL_RED_BITS_ARB: GLenum = 0x2015;
 const WGL_ALPHA_BITS_ARB: GLenum = 0x201b;
 const WGL_DEPTH_BITS_ARB: GLenum = 0x2022;
 const WGL_STENCIL_BITS_ARB: GLenum = 0x2023;
+const WGL_SAMPLE_BUFFERS_ARB: GLenum = 0x2041;
+const WGL_SAMPLES_ARB: GLenum = 0x2042;
 const WGL_FULL_ACCELERATION_ARB: GLenum = 0x2027;
 const WGL_TYPE_RGBA_ARB: GLenum = 0x202b;
 const WGL_CONTEXT_MAJOR_VERSION_ARB: GLenum = 0x2091;
 const WGL_CONTEXT_MINOR_VERSION_ARB: GLenum = 0x2092;
+const WGL_CONTEXT_FLAGS_ARB: GLenum = 0x2094;
 const WGL_CONTEXT_PROFILE_MASK_ARB: GLenum = 0x9126;
-
+const WGL_CONTEXT_RESET_NOTIFICATION_STRATEGY_ARB: GLenum = 0x8256;
+const WGL_CONTEXT_OPENGL_NO_ERROR_ARB: GLenum = 0x31b3;
+const WGL_CONTEXT_RELEASE_BEHAVIOR_ARB: GLenum = 0x2097;
+const WGL_CONTEXT_RELEASE_BEHAVIOR_NONE_ARB: GLenum = 0;
+
+const WGL_CONTEXT_DEBUG_BIT_ARB: GLenum = 0x00000001;
+const WGL_CONTEXT_ROBUST_ACCESS_BIT_ARB: GLenum = 0x00000004;
+const WGL_LOSE_CONTEXT_ON_RESET_ARB: GLenum = 0x8252;
 const WGL_CONTEXT_CORE_PROFILE_BIT_ARB: GLenum = 0x00000001;
 const WGL_CONTEXT_COMPATIBILITY_PROFILE_BIT_ARB: GLenum = 0x00000002;
 
@@ -55,6 +84,8 @@ pub(crate) struct WGLExtensionFunctions {
         unsafe extern "C" fn(hDC: HDC, shareContext: HGLRC, attribList: *const c_int) -> HGLRC,
     >,
     GetExtensionsStringARB: Option<unsafe extern "C" fn(hdc: HDC) -> *const c_char>,
+    pub(crate) SwapIntervalEXT: Option<unsafe extern "C" fn(interval: c_int) -> BOOL>,
+    pub(crate) extensions: Vec<String>,
     pub(crate) pixel_format_functions: Option<WGLPixelFormatExtensionFunctions>,
     pub(crate) dx_interop_functions: Option<WGLDXInteropExtensionFunctions>,
 }
@@ -107,6 +138,11 @@ pub struct ContextDescriptor {
     pixel_format: c_int,
     gl_version: GLVersion,
     compatibility_profile: bool,
+    debug: bool,
+    robust_access: bool,
+    no_error: bool,
+    release_behavior_none: bool,
+    allow_lower_version: bool,
 }
 
 /// Represents an OpenGL rendering context.
@@ -141,9 +177,12 @@ enum ContextStatus {
     Destroyed,
 }
 
-/// Wrapper for a WGL `HGLRC`.
+/// Wrapper for a WGL `HGLRC`, and a device context with the pixel format that it was created for.
+///
+/// The device context may be null if it isn't known, but it's needed to wrap the context with
+/// `Device::create_context_from_native_context()` or to share with it.
 #[derive(Clone)]
-pub struct NativeContext(pub HGLRC);
+pub struct NativeContext(pub HGLRC, pub HDC);
 
 thread_local! {
     static OPENGL_LIBRARY: HMODULE = {
@@ -168,72 +207,137 @@ impl Device {
         attributes: &ContextAttributes,
     ) -> Result<ContextDescriptor, Error> {
         let flags = attributes.flags;
+        context::check_attribute_flags(flags)?;
+        self.cancel_token.check()?;
         let alpha_bits = if flags.contains(ContextAttributeFlags::ALPHA) {
             8
         } else {
             0
         };
-        let depth_bits = if flags.contains(ContextAttributeFlags::DEPTH) {
-            24
-        } else {
-            0
-        };
-        let stencil_bits = if flags.contains(ContextAttributeFlags::STENCIL) {
-            8
-        } else {
-            0
-        };
+        let (depth_bits, stencil_bits) = context::requested_depth_stencil_sizes(attributes);
+        let (depth_bits, stencil_bits) = (depth_bits as c_int, stencil_bits as c_int);
         let compatibility_profile = flags.contains(ContextAttributeFlags::COMPATIBILITY_PROFILE);
+        let debug = flags.contains(ContextAttributeFlags::DEBUG);
+
+        // Robustness can't be dropped like debugging can, since callers rely on it for security.
+        let robust_access = flags.contains(ContextAttributeFlags::ROBUST_ACCESS);
+        if robust_access
+            && !WGL_EXTENSION_FUNCTIONS
+                .extensions
+                .iter()
+                .any(|extension| extension == "WGL_ARB_create_context_robustness")
+        {
+            return Err(Error::RequiredExtensionUnavailable);
+        }
 
-        let attrib_i_list = [
-            WGL_DRAW_TO_WINDOW_ARB as c_int,
-            gl::TRUE as c_int,
-            WGL_SUPPORT_OPENGL_ARB as c_int,
-            gl::TRUE as c_int,
-            WGL_DOUBLE_BUFFER_ARB as c_int,
-            gl::TRUE as c_int,
-            WGL_PIXEL_TYPE_ARB as c_int,
-            WGL_TYPE_RGBA_ARB as c_int,
-            WGL_ACCELERATION_ARB as c_int,
-            WGL_FULL_ACCELERATION_ARB as c_int,
-            WGL_COLOR_BITS_ARB as c_int,
-            32,
-            WGL_ALPHA_BITS_ARB as c_int,
-            alpha_bits,
-            WGL_DEPTH_BITS_ARB as c_int,
-            depth_bits,
-            WGL_STENCIL_BITS_ARB as c_int,
-            stencil_bits,
-            0,
-        ];
-
-        let wglChoosePixelFormatARB = match WGL_EXTENSION_FUNCTIONS.pixel_format_functions {
-            None => return Err(Error::RequiredExtensionUnavailable),
-            Some(ref pixel_format_functions) => pixel_format_functions.ChoosePixelFormatARB,
-        };
+        let no_error = flags.contains(ContextAttributeFlags::NO_ERROR)
+            && WGL_EXTENSION_FUNCTIONS
+                .extensions
+                .iter()
+                .any(|extension| extension == "WGL_ARB_create_context_no_error");
+
+        let release_behavior_none = context::release_behavior_none(
+            flags,
+            WGL_EXTENSION_FUNCTIONS
+                .extensions
+                .iter()
+                .any(|extension| extension == "WGL_ARB_context_flush_control"),
+        )?;
+
+        let (wglChoosePixelFormatARB, wglGetPixelFormatAttribivARB) =
+            match WGL_EXTENSION_FUNCTIONS.pixel_format_functions {
+                None => return Err(Error::RequiredExtensionUnavailable),
+                Some(ref pixel_format_functions) => (
+                    pixel_format_functions.ChoosePixelFormatARB,
+                    pixel_format_functions.GetPixelFormatAttribivARB,
+                ),
+            };
 
         let hidden_window_dc = self.hidden_window.get_dc();
         unsafe {
-            let (mut pixel_format, mut pixel_format_count) = (0, 0);
-            let ok = wglChoosePixelFormatARB(
-                hidden_window_dc.dc,
-                attrib_i_list.as_ptr(),
-                ptr::null(),
-                1,
-                &mut pixel_format,
-                &mut pixel_format_count,
-            );
-            if ok == FALSE {
-                return Err(Error::PixelFormatSelectionFailed(WindowingApiError::Failed));
-            }
-            if pixel_format_count == 0 {
-                return Err(Error::NoPixelFormatFound);
+            // `WGL_SAMPLES_ARB` is only a minimum, so each count is tried in turn until a pixel
+            // format with exactly that many samples turns up.
+            let mut chosen_pixel_format = None;
+            for samples in context::sample_counts_to_try(attributes) {
+                let mut attrib_i_list = vec![
+                    WGL_DRAW_TO_WINDOW_ARB as c_int,
+                    gl::TRUE as c_int,
+                    WGL_SUPPORT_OPENGL_ARB as c_int,
+                    gl::TRUE as c_int,
+                    WGL_DOUBLE_BUFFER_ARB as c_int,
+                    gl::TRUE as c_int,
+                    WGL_PIXEL_TYPE_ARB as c_int,
+                    WGL_TYPE_RGBA_ARB as c_int,
+                    WGL_ACCELERATION_ARB as c_int,
+                    WGL_FULL_ACCELERATION_ARB as c_int,
+                    WGL_COLOR_BITS_ARB as c_int,
+                    32,
+                    WGL_ALPHA_BITS_ARB as c_int,
+                    alpha_bits,
+                    WGL_DEPTH_BITS_ARB as c_int,
+                    depth_bits,
+                    WGL_STENCIL_BITS_ARB as c_int,
+                    stencil_bits,
+                ];
+                if samples > 0 {
+                    attrib_i_list.extend_from_slice(&[
+                        WGL_SAMPLE_BUFFERS_ARB as c_int,
+                        1,
+                        WGL_SAMPLES_ARB as c_int,
+                        samples as c_int,
+                    ]);
+                }
+                attrib_i_list.push(0);
+
+                let (mut pixel_format, mut pixel_format_count) = (0, 0);
+                let ok = wglChoosePixelFormatARB(
+                    hidden_window_dc.dc,
+                    attrib_i_list.as_ptr(),
+                    ptr::null(),
+                    1,
+                    &mut pixel_format,
+                    &mut pixel_format_count,
+                );
+                if ok == FALSE {
+                    return Err(Error::PixelFormatSelectionFailed(WindowingApiError::Failed));
+                }
+                if pixel_format_count == 0 {
+                    continue;
+                }
+
+                let attrib_name = WGL_SAMPLES_ARB as c_int;
+                let mut granted_samples = 0;
+                let ok = wglGetPixelFormatAttribivARB(
+                    hidden_window_dc.dc,
+                    pixel_format,
+                    0,
+                    1,
+                    &attrib_name,
+                    &mut granted_samples,
+                );
+                // Without `WGL_ARB_multisample`, the query fails and there are no samples.
+                if ok == FALSE {
+                    granted_samples = 0;
+                }
+                if granted_samples.max(1) == (samples as c_int).max(1) {
+                    chosen_pixel_format = Some(pixel_format);
+                    break;
+                }
             }
+            let pixel_format = match chosen_pixel_format {
+                None => return Err(Error::NoPixelFormatFound),
+                Some(pixel_format) => pixel_format,
+            };
 
             Ok(ContextDescriptor {
                 pixel_format,
                 gl_version: attributes.version,
                 compatibility_profile,
+                debug,
+                robust_access,
+                no_error,
+                release_behavior_none,
+                allow_lower_version: flags.contains(ContextAttributeFlags::ALLOW_LOWER_VERSION),
             })
         }
     }
@@ -242,11 +346,71 @@ impl Device {
     ///
     /// The context initially has no surface attached. Until a surface is bound to it, rendering
     /// commands will fail or have no effect.
-    #[allow(non_snake_case)]
     pub fn create_context(
         &mut self,
         descriptor: &ContextDescriptor,
         share_with: Option<&Context>,
+    ) -> Result<Context, Error> {
+        let share_glrc = share_with.map_or(ptr::null_mut(), |ctx| ctx.glrc);
+        self.create_context_sharing_glrc(descriptor, share_glrc)
+    }
+
+    /// Creates a context of the newest OpenGL or OpenGL ES version in `versions` that this device
+    /// supports, and returns it along with the descriptor that it was created from.
+    ///
+    /// The version that this settles on is remembered, so later calls with the same range and
+    /// flags don't search again. If the device supports no version in the range, this returns
+    /// `Error::UnsupportedGLVersion`.
+    pub fn create_versioned_context(
+        &mut self,
+        versions: RangeInclusive<GLVersion>,
+        flags: ContextAttributeFlags,
+    ) -> Result<(Context, ContextDescriptor), Error> {
+        let gl_api = self.gl_api();
+        let mut negotiated_versions = mem::take(&mut self.negotiated_versions);
+        let result = negotiated_versions.create_context(gl_api, versions, flags, |attributes| {
+            let descriptor = self.create_context_descriptor(attributes)?;
+            let context = self.create_context(&descriptor, None)?;
+            Ok((context, descriptor))
+        });
+        self.negotiated_versions = negotiated_versions;
+        result
+    }
+
+    /// Creates a new OpenGL context that shares textures, buffers, and other OpenGL objects with
+    /// a native context that surfman didn't create, such as one that the application already
+    /// renders with.
+    ///
+    /// The new context uses the pixel format of the native context's device context, so that
+    /// surfaces created for it are compatible with the native context, and otherwise has the
+    /// given attributes. If the device context is null or has no pixel format, this returns
+    /// `IncompatibleNativeContext`.
+    ///
+    /// Drivers may refuse to share with a context that is current on another thread, so the native
+    /// context must not be current on any other thread during this call, or this returns
+    /// `NativeContextCurrentElsewhere`. It may be current on this thread.
+    pub unsafe fn create_shared_context_with_native(
+        &mut self,
+        native_context: &NativeContext,
+        attributes: &ContextAttributes,
+    ) -> Result<Context, Error> {
+        let mut descriptor = self.create_context_descriptor(attributes)?;
+        descriptor.pixel_format = native_context_pixel_format(native_context)?;
+
+        // Check that no other thread has the native context current, by making it current here.
+        if wglGetCurrentContext() != native_context.0 {
+            let _guard = CurrentContextGuard::new();
+            make_native_context_current(native_context.1, native_context.0)?;
+        }
+
+        self.create_context_sharing_glrc(&descriptor, native_context.0)
+    }
+
+    #[allow(non_snake_case)]
+    fn create_context_sharing_glrc(
+        &mut self,
+        descriptor: &ContextDescriptor,
+        share_glrc: HGLRC,
     ) -> Result<Context, Error> {
         let wglCreateContextAttribsARB = match WGL_EXTENSION_FUNCTIONS.CreateContextAttribsARB {
             None => return Err(Error::RequiredExtensionUnavailable),
@@ -254,6 +418,7 @@ impl Device {
         };
 
         let mut next_context_id = CREATE_CONTEXT_MUTEX.lock().unwrap();
+        self.cancel_token.check()?;
         unsafe {
             let (glrc, gl);
 
@@ -272,23 +437,75 @@ impl Device {
                 } else {
                     WGL_CONTEXT_CORE_PROFILE_BIT_ARB
                 };
-                let wgl_attributes = [
-                    WGL_CONTEXT_MAJOR_VERSION_ARB as c_int,
-                    descriptor.gl_version.major as c_int,
-                    WGL_CONTEXT_MINOR_VERSION_ARB as c_int,
-                    descriptor.gl_version.minor as c_int,
-                    WGL_CONTEXT_PROFILE_MASK_ARB as c_int,
-                    profile_mask as c_int,
-                    0,
-                ];
-                glrc = wglCreateContextAttribsARB(
-                    dc,
-                    share_with.map_or(ptr::null_mut(), |ctx| ctx.glrc),
-                    wgl_attributes.as_ptr(),
-                );
+                // `hints` includes the attributes that can be dropped if the driver refuses them.
+                let wgl_attributes = |version: GLVersion, hints: bool| {
+                    let mut context_flags = if hints && descriptor.debug {
+                        WGL_CONTEXT_DEBUG_BIT_ARB
+                    } else {
+                        0
+                    };
+                    if descriptor.robust_access {
+                        context_flags |= WGL_CONTEXT_ROBUST_ACCESS_BIT_ARB;
+                    }
+                    let mut wgl_attributes = vec![
+                        WGL_CONTEXT_MAJOR_VERSION_ARB as c_int,
+                        version.major as c_int,
+                        WGL_CONTEXT_MINOR_VERSION_ARB as c_int,
+                        version.minor as c_int,
+                        WGL_CONTEXT_PROFILE_MASK_ARB as c_int,
+                        profile_mask as c_int,
+                        WGL_CONTEXT_FLAGS_ARB as c_int,
+                        context_flags as c_int,
+                    ];
+                    if descriptor.robust_access {
+                        wgl_attributes.extend_from_slice(&[
+                            WGL_CONTEXT_RESET_NOTIFICATION_STRATEGY_ARB as c_int,
+                            WGL_LOSE_CONTEXT_ON_RESET_ARB as c_int,
+                        ]);
+                    }
+                    if descriptor.release_behavior_none {
+                        wgl_attributes.extend_from_slice(&[
+                            WGL_CONTEXT_RELEASE_BEHAVIOR_ARB as c_int,
+                            WGL_CONTEXT_RELEASE_BEHAVIOR_NONE_ARB as c_int,
+                        ]);
+                    }
+                    if hints && descriptor.no_error {
+                        wgl_attributes
+                            .extend_from_slice(&[WGL_CONTEXT_OPENGL_NO_ERROR_ARB as c_int, 1]);
+                    }
+                    wgl_attributes.push(0);
+                    wgl_attributes
+                };
+                let mut created_glrc = ptr::null_mut();
+                for version in
+                    context::versions_to_try(descriptor.gl_version, descriptor.allow_lower_version)
+                {
+                    created_glrc = wglCreateContextAttribsARB(
+                        dc,
+                        share_glrc,
+                        wgl_attributes(version, true).as_ptr(),
+                    );
+                    // Fall back to an ordinary context if the driver refuses a debug or no-error
+                    // one.
+                    if created_glrc.is_null() && (descriptor.debug || descriptor.no_error) {
+                        created_glrc = wglCreateContextAttribsARB(
+                            dc,
+                            share_glrc,
+                            wgl_attributes(version, false).as_ptr(),
+                        );
+                    }
+                    if !created_glrc.is_null() {
+                        break;
+                    }
+                }
+                glrc = created_glrc;
                 if glrc.is_null() {
                     return Err(Error::ContextCreationFailed(WindowingApiError::Failed));
                 }
+                if let Err(err) = self.cancel_token.check() {
+                    wglDeleteContext(glrc);
+                    return Err(err);
+                }
 
                 // Temporarily make the context current.
                 let _guard = CurrentContextGuard::new();
@@ -309,6 +526,7 @@ impl Device {
                 status: ContextStatus::Owned,
             };
             next_context_id.0 += 1;
+            leak_check::created(TrackedObject::Context);
             Ok(context)
         }
     }
@@ -318,20 +536,29 @@ impl Device {
     /// The `HGLRC` is not retained, as there is no way to do this in the Win32 API. Therefore, it
     /// is the caller's responsibility to make sure the OpenGL context is not destroyed before this
     /// `Context` is.
+    ///
+    /// The pixel format of the native context's device context becomes the pixel format of the
+    /// returned context, which its descriptor reports and its surfaces are created with. If the
+    /// device context is null or has no pixel format, this returns `IncompatibleNativeContext`.
+    ///
+    /// The native context must not be current on any other thread during this call, or this
+    /// returns `NativeContextCurrentElsewhere`. It may be current on this thread.
     pub unsafe fn create_context_from_native_context(
         &self,
         native_context: NativeContext,
     ) -> Result<Context, Error> {
+        let pixel_format = native_context_pixel_format(&native_context)?;
         let mut next_context_id = CREATE_CONTEXT_MUTEX.lock().unwrap();
         let hidden_window = HiddenWindow::new();
 
-        // Load the GL functions.
+        // Give the hidden window the native context's pixel format, so that the context can be
+        // made current with it, and load the GL functions.
         let gl = {
             let hidden_window_dc = hidden_window.get_dc();
             let dc = hidden_window_dc.dc;
+            set_dc_pixel_format(dc, pixel_format);
             let _guard = CurrentContextGuard::new();
-            let ok = wglMakeCurrent(dc, native_context.0);
-            assert_ne!(ok, FALSE);
+            make_native_context_current(dc, native_context.0)?;
             Gl::load_with(get_proc_address)
         };
 
@@ -344,6 +571,7 @@ impl Device {
             status: ContextStatus::Referenced,
         };
         next_context_id.0 += 1;
+        leak_check::created(TrackedObject::Context);
         Ok(context)
     }
 
@@ -369,8 +597,12 @@ impl Device {
             }
         }
 
+        self.lost_contexts.forget(context.id);
+        current::forget(context.id);
+        convert::forget_context(context.id);
         context.glrc = ptr::null_mut();
         context.status = ContextStatus::Destroyed;
+        leak_check::destroyed(TrackedObject::Context);
         Ok(())
     }
 
@@ -380,16 +612,26 @@ impl Device {
             let dc_guard = self.get_context_dc(context);
             let pixel_format = wingdi::GetPixelFormat(dc_guard.dc);
 
-            let _guard = self.temporarily_make_context_current(context);
+            let _guard = self.make_context_current_guarded(context);
 
             let gl_version = GLVersion::current(&context.gl);
             let compatibility_profile =
                 context::current_context_uses_compatibility_profile(&context.gl);
+            let debug = context::current_context_is_debug(&context.gl);
+            let robust_access = context::current_context_is_robust(&context.gl);
+            let no_error = context::current_context_is_no_error(&context.gl);
+            let release_behavior_none =
+                context::current_context_release_behavior_is_none(&context.gl);
 
             ContextDescriptor {
                 pixel_format,
                 gl_version,
                 compatibility_profile,
+                debug,
+                robust_access,
+                no_error,
+                release_behavior_none,
+                allow_lower_version: false,
             }
         }
     }
@@ -433,9 +675,30 @@ impl Device {
                 attrib_value_i_list[2],
             );
 
+            // Queried separately, since this fails without `WGL_ARB_multisample`.
+            let attrib_name = WGL_SAMPLES_ARB as c_int;
+            let mut samples = 0;
+            let ok = wglGetPixelFormatAttribivARB(
+                dc_guard.dc,
+                context_descriptor.pixel_format,
+                0,
+                1,
+                &attrib_name,
+                &mut samples,
+            );
+            if ok == FALSE {
+                samples = 0;
+            }
+
             let mut attributes = ContextAttributes {
                 version: context_descriptor.gl_version,
                 flags: ContextAttributeFlags::empty(),
+                placeholder_policy: PlaceholderPolicy::Owned,
+                color_depth: ColorDepth::Standard,
+                priority: ContextPriority::Medium,
+                samples: if samples > 1 { samples as u8 } else { 0 },
+                depth_size: Some(depth_bits as u8),
+                stencil_size: Some(stencil_bits as u8),
             };
             if alpha_bits > 0 {
                 attributes.flags.insert(ContextAttributeFlags::ALPHA);
@@ -446,11 +709,84 @@ impl Device {
             if stencil_bits > 0 {
                 attributes.flags.insert(ContextAttributeFlags::STENCIL);
             }
+            attributes
+                .flags
+                .set(ContextAttributeFlags::DEBUG, context_descriptor.debug);
+            attributes.flags.set(
+                ContextAttributeFlags::ROBUST_ACCESS,
+                context_descriptor.robust_access,
+            );
+            attributes
+                .flags
+                .set(ContextAttributeFlags::NO_ERROR, context_descriptor.no_error);
+            attributes.flags.set(
+                ContextAttributeFlags::RELEASE_BEHAVIOR_NONE,
+                context_descriptor.release_behavior_none,
+            );
 
             attributes
         }
     }
 
+    /// Returns the properties of the context descriptor as plain data, which can be sent to
+    /// another process.
+    pub fn context_descriptor_raw_parts(
+        &self,
+        context_descriptor: &ContextDescriptor,
+    ) -> ContextDescriptorAttributes {
+        let wglGetPixelFormatAttribivARB = WGL_EXTENSION_FUNCTIONS
+            .pixel_format_functions
+            .as_ref()
+            .expect(
+                "How did you make a context descriptor without \
+                                            pixel format extensions?",
+            )
+            .GetPixelFormatAttribivARB;
+
+        let dc_guard = self.hidden_window.get_dc();
+
+        unsafe {
+            let attrib_name_i_list = [
+                WGL_RED_BITS_ARB as c_int,
+                WGL_ALPHA_BITS_ARB as c_int,
+                WGL_DEPTH_BITS_ARB as c_int,
+                WGL_STENCIL_BITS_ARB as c_int,
+            ];
+            let mut attrib_value_i_list = [0; 4];
+            let ok = wglGetPixelFormatAttribivARB(
+                dc_guard.dc,
+                context_descriptor.pixel_format,
+                0,
+                attrib_name_i_list.len() as UINT,
+                attrib_name_i_list.as_ptr(),
+                attrib_value_i_list.as_mut_ptr(),
+            );
+            assert_ne!(ok, FALSE);
+
+            ContextDescriptorAttributes {
+                attributes: self.context_descriptor_attributes(context_descriptor),
+                color_channel_size: attrib_value_i_list[0] as u8,
+                alpha_size: attrib_value_i_list[1] as u8,
+                depth_size: attrib_value_i_list[2] as u8,
+                stencil_size: attrib_value_i_list[3] as u8,
+            }
+        }
+    }
+
+    /// Creates a context descriptor equivalent to the one that `attributes` were taken from,
+    /// possibly on a device in another process.
+    ///
+    /// If this device can't match the version and buffer sizes exactly, this returns
+    /// `Error::UnsupportedGLVersion` or `Error::NoPixelFormatFound`.
+    pub fn create_context_descriptor_from_attributes(
+        &self,
+        attributes: &ContextDescriptorAttributes,
+    ) -> Result<ContextDescriptor, Error> {
+        let context_descriptor = self.create_context_descriptor(&attributes.attributes)?;
+        attributes.check_recreated(&self.context_descriptor_raw_parts(&context_descriptor))?;
+        Ok(context_descriptor)
+    }
+
     pub(crate) fn temporarily_bind_framebuffer<'a>(
         &self,
         context: &'a Context,
@@ -463,28 +799,54 @@ impl Device {
         }
     }
 
-    pub(crate) fn temporarily_make_context_current(
+    /// Makes the context current for this thread, returning a guard that makes the previously
+    /// current context current again when dropped.
+    ///
+    /// The previous context is restored even if the thread panics while the guard is alive.
+    pub fn make_context_current_guarded(
         &self,
         context: &Context,
     ) -> Result<CurrentContextGuard, Error> {
+        if current::held_for_bulk_ops(context.id) {
+            return Ok(CurrentContextGuard::held());
+        }
         let guard = CurrentContextGuard::new();
         self.make_context_current(context)?;
         Ok(guard)
     }
 
+    /// Makes the context current for this thread, runs `f` with the operations that can be run
+    /// on the context, and then makes the previously current context current again.
+    ///
+    /// The operations don't each make the context current and restore the previous context, as
+    /// they do when called on their own. If the context can't be made current, `f` isn't run.
+    pub fn with_context_current<F, R>(&self, context: &mut Context, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut BulkOps<Self>) -> R,
+    {
+        bulk::with_context_current(self, context, f)
+    }
+
     /// Makes the context the current OpenGL context for this thread.
     ///
     /// After calling this function, it is valid to use OpenGL rendering commands.
+    ///
+    /// Returns `Error::ContextLost` if the context has been lost; see `poll_context_lost()`.
     pub fn make_context_current(&self, context: &Context) -> Result<(), Error> {
-        unsafe {
-            let dc_guard = self.get_context_dc(context);
-            let ok = wglMakeCurrent(dc_guard.dc, context.glrc);
-            if ok != FALSE {
-                Ok(())
-            } else {
-                Err(Error::MakeCurrentFailed(WindowingApiError::Failed))
-            }
-        }
+        self.lost_contexts.check(context.id, || {
+            current::make_current(context.id, || unsafe {
+                let dc_guard = self.get_context_dc(context);
+                let ok = wglMakeCurrent(dc_guard.dc, context.glrc);
+                if ok != FALSE {
+                    Ok(())
+                } else if GetLastError() == ERROR_BUSY {
+                    // Another thread has the context current.
+                    Err(Error::MakeCurrentFailed(WindowingApiError::BadAccess))
+                } else {
+                    Err(Error::MakeCurrentFailed(WindowingApiError::Failed))
+                }
+            })
+        })
     }
 
     /// Removes the current OpenGL context from this thread.
@@ -496,6 +858,7 @@ impl Device {
         unsafe {
             let ok = wglMakeCurrent(ptr::null_mut(), ptr::null_mut());
             if ok != FALSE {
+                current::released();
                 Ok(())
             } else {
                 Err(Error::MakeCurrentFailed(WindowingApiError::Failed))
@@ -503,30 +866,70 @@ impl Device {
         }
     }
 
-    /// Fetches the address of an OpenGL function associated with this context.
+    /// Returns the thread that the context is current on, or `None` if it isn't current anywhere.
+    ///
+    /// Only contexts made current through surfman are tracked.
+    #[inline]
+    pub fn context_current_thread(&self, context: &Context) -> Option<ThreadId> {
+        current::current_thread(context.id)
+    }
+
+    /// Fetches the address of an OpenGL or WGL function associated with this context, or `None` if
+    /// the function isn't available.
     ///
     /// OpenGL functions are local to a context. You should not use OpenGL functions on one context
     /// with any other context.
     ///
-    /// This method is typically used with a function like `gl::load_with()` from the `gl` crate to
-    /// load OpenGL function pointers.
-    #[inline]
-    pub fn get_proc_address(&self, _: &Context, symbol_name: &str) -> *const c_void {
-        get_proc_address(symbol_name)
+    /// Functions named with an extension suffix, like `glDrawBuffersEXT`, are only returned if the
+    /// context supports an extension from the same vendor. Checking may briefly make the context
+    /// current; the context that was current before this call remains current afterward.
+    pub fn get_proc_address(&self, context: &Context, symbol_name: &str) -> Option<*const c_void> {
+        if symbol_name.contains('\0') {
+            return None;
+        }
+        let address = get_proc_address(symbol_name);
+        // Some drivers return small integers instead of null for functions they don't have.
+        if matches!(address as isize, -1 | 0 | 1 | 2 | 3) {
+            return None;
+        }
+        let available = gl_utils::entry_point_available(symbol_name, |api| match api {
+            "WGL" => WGL_EXTENSION_FUNCTIONS.extensions.clone(),
+            _ => self.gl_extensions(context).unwrap_or_default(),
+        });
+        if available {
+            Some(address)
+        } else {
+            None
+        }
     }
 
+    /// Returns a function that looks up the OpenGL functions of a context, for loaders like
+    /// `gl::load_with()` that expect a null pointer for functions that aren't available.
+    pub fn proc_address_loader<'a>(
+        &'a self,
+        context: &'a Context,
+    ) -> Box<dyn Fn(&str) -> *const c_void + 'a> {
+        Box::new(move |symbol_name| {
+            self.get_proc_address(context, symbol_name)
+                .unwrap_or(ptr::null())
+        })
+    }
+
+    /// Returns true if the given context is the current OpenGL context for this thread.
     #[inline]
-    fn context_is_current(&self, context: &Context) -> bool {
+    pub fn is_context_current(&self, context: &Context) -> bool {
         unsafe { wglGetCurrentContext() == context.glrc }
     }
 
     /// Attaches a surface to a context for rendering.
     ///
-    /// This function takes ownership of the surface. The surface must have been created with this
-    /// context, or an `IncompatibleSurface` error is returned.
+    /// This function takes ownership of the surface. A surface belongs to the context that it was
+    /// created with, so binding it to any other context returns a `SurfaceAlreadyBound` error
+    /// naming that context. Check `Surface::context_id()` beforehand to avoid this, and wrap the
+    /// surface in a surface texture to read it from another context instead.
     ///
-    /// If this function is called with a surface already bound, a `SurfaceAlreadyBound` error is
-    /// returned. To avoid this error, first unbind the existing surface with
+    /// If this context already has a surface bound, a `SurfaceAlreadyBound` error naming this
+    /// context is returned. To avoid this error, first unbind the existing surface with
     /// `unbind_surface_from_context`.
     ///
     /// If an error is returned, the surface is returned alongside it.
@@ -536,16 +939,23 @@ impl Device {
         surface: Surface,
     ) -> Result<(), (Error, Surface)> {
         if context.id != surface.context_id {
-            return Err((Error::IncompatibleSurface, surface));
+            let context_id = surface.context_id;
+            return Err((Error::SurfaceAlreadyBound { context_id }, surface));
+        }
+        if let Err(err) = surface.purge_state.check_usable() {
+            return Err((err, surface));
         }
 
         match context.framebuffer {
             Framebuffer::None => {}
             Framebuffer::External(()) => return Err((Error::ExternalRenderTarget, surface)),
-            Framebuffer::Surface(_) => return Err((Error::SurfaceAlreadyBound, surface)),
+            Framebuffer::Surface(_) => {
+                let context_id = context.id;
+                return Err((Error::SurfaceAlreadyBound { context_id }, surface));
+            }
         }
 
-        let is_current = self.context_is_current(context);
+        let is_current = self.is_context_current(context);
 
         self.lock_surface(&surface);
         context.framebuffer = Framebuffer::Surface(surface);
@@ -568,6 +978,21 @@ impl Device {
     ) -> Result<Option<Surface>, Error> {
         match mem::replace(&mut context.framebuffer, Framebuffer::None) {
             Framebuffer::Surface(surface) => {
+                // Resolve multisampled surfaces while the texture is still locked for GL.
+                if let Win32Objects::Texture {
+                    gl_framebuffer,
+                    multisample: Some(ref multisample),
+                    ..
+                } = surface.win32_objects
+                {
+                    if let Ok(_guard) = self.make_context_current_guarded(context) {
+                        multisample.resolve(&context.gl, gl_framebuffer);
+                        gl_utils::unbind_framebuffer_if_necessary(
+                            &context.gl,
+                            multisample.framebuffer_object,
+                        );
+                    }
+                }
                 self.unlock_surface(&surface);
                 Ok(Some(surface))
             }
@@ -613,10 +1038,139 @@ impl Device {
         }
     }
 
-    /// Given a context, returns its underlying `HGLRC`.
+    /// Makes the context current, binds the framebuffer of its surface, and sets the viewport to
+    /// cover that surface.
+    ///
+    /// If no surface is attached, the context is still made current, the default framebuffer is
+    /// bound, and the viewport is left unchanged; the returned info then has a zero size and a
+    /// format of `GL_NONE`. On error, the previously-current context is restored.
+    pub fn begin_rendering(&self, context: &Context) -> Result<RenderTargetInfo, Error> {
+        let guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        let surface_info = self.context_surface_info(context)?;
+        let attributes = self.context_descriptor_attributes(&self.context_descriptor(context));
+        let render_target_info =
+            gl_utils::bind_render_target(&context.gl, surface_info.as_ref(), &attributes);
+        mem::forget(guard);
+        Ok(render_target_info)
+    }
+
+    /// Finishes rendering started with `begin_rendering()` by unbinding the surface's framebuffer,
+    /// flushing the context if `flush` is true.
+    ///
+    /// The context that was current before this call remains current afterward.
+    pub fn end_rendering(&self, context: &Context, flush: bool) -> Result<(), Error> {
+        let _guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        gl_utils::unbind_render_target(&context.gl, flush);
+        Ok(())
+    }
+
+    /// Returns the OpenGL features beyond OpenGL ES 2.0 that the given context supports.
+    ///
+    /// The context that was current before this call remains current afterward.
+    pub fn capabilities(&self, context: &Context) -> Result<GLCapabilities, Error> {
+        let _guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        Ok(GLCapabilities::current(&context.gl))
+    }
+
+    /// Returns the names of the OpenGL extensions that the given context supports.
+    ///
+    /// The context that was current before this call remains current afterward.
+    pub fn gl_extensions(&self, context: &Context) -> Result<Vec<String>, Error> {
+        let _guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        Ok(info::current_extensions(&context.gl))
+    }
+
+    /// Returns what OpenGL reports about the given context: the version, profile, and flags that
+    /// it was actually granted, and the vendor and renderer strings.
+    ///
+    /// The context that was current before this call remains current afterward.
+    pub fn context_info(&self, context: &Context) -> Result<ContextInfo, Error> {
+        let _guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        Ok(ContextInfo::current(&context.gl))
+    }
+
+    /// Returns true unless the given context renders in software: the device is a software
+    /// rasterizer, such as WARP, which Direct3D reports as the adapter, or the context's
+    /// `GL_RENDERER` string names one.
+    ///
+    /// A context that can't be made current, such as a lost one, returns false. The context that
+    /// was current before this call remains current afterward.
+    pub fn context_is_hardware_accelerated(&self, context: &Context) -> bool {
+        acceleration::context_is_hardware_accelerated(self, context)
+    }
+
+    /// Empties the OpenGL error queue of the given context, returning each error that
+    /// `glGetError()` reported until it reported none.
+    ///
+    /// The context that was current before this call remains current afterward.
+    pub fn drain_gl_errors(&self, context: &Context) -> Result<Vec<GlError>, Error> {
+        let _guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        Ok(gl_utils::drain_gl_errors(&context.gl))
+    }
+
+    /// Returns whether the given context has been lost to a GPU reset.
+    ///
+    /// Only contexts created with `ContextAttributeFlags::ROBUST_ACCESS` are notified of resets.
+    /// The context that was current before this call remains current afterward.
+    pub fn context_reset_status(&self, context: &Context) -> Result<ResetStatus, Error> {
+        let _guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        Ok(context::current_reset_status(|symbol_name| {
+            self.get_proc_address(context, symbol_name)
+        }))
+    }
+
+    /// Returns true if the given context has been lost, for example to a GPU reset.
+    ///
+    /// WGL doesn't report lost contexts itself, so a context counts as lost once
+    /// `context_reset_status()` reports a reset. From then on, `make_context_current()` returns
+    /// `Error::ContextLost`, and the context can only be destroyed. The context that was current
+    /// before this call remains current afterward.
+    pub fn poll_context_lost(&self, context: &Context) -> bool {
+        self.lost_contexts
+            .poll(context.id, || self.context_reset_status(context))
+    }
+
+    /// Registers a function to call with the ID of each context of this device that is found to
+    /// be lost, replacing any previous one, or unregisters it if `handler` is `None`.
+    #[inline]
+    pub fn set_context_lost_handler(&mut self, handler: Option<ContextLostHandler>) {
+        self.lost_contexts.set_handler(handler)
+    }
+
+    /// Returns the scheduling priority that the given context was granted.
+    ///
+    /// This may be lower than the priority requested in the context attributes. Platforms that
+    /// can't prioritize contexts report `ContextPriority::Medium`.
+    pub fn context_priority(&self, _: &Context) -> ContextPriority {
+        ContextPriority::Medium
+    }
+
+    /// Given a context, returns its underlying `HGLRC`, and the device context of the hidden window
+    /// that it is made current with while no widget surface is bound to it.
     #[inline]
     pub fn native_context(&self, context: &Context) -> NativeContext {
-        NativeContext(context.glrc)
+        // The hidden window's class has its own DC, which stays valid after it's released.
+        let dc = context
+            .hidden_window
+            .as_ref()
+            .map_or(ptr::null_mut(), |hidden_window| hidden_window.get_dc().dc);
+        NativeContext(context.glrc, dc)
+    }
+
+    /// Returns the native context that is current on this thread, for wrapping with
+    /// `create_context_from_native_context()`.
+    ///
+    /// If no context is current, this returns a `NoCurrentContext` error.
+    #[inline]
+    pub fn current_native_context(&self) -> Result<NativeContext, Error> {
+        NativeContext::current()
     }
 }
 
@@ -629,7 +1183,7 @@ impl NativeContext {
         unsafe {
             let glrc = wglGetCurrentContext();
             if glrc != ptr::null_mut() {
-                Ok(NativeContext(glrc))
+                Ok(NativeContext(glrc, wglGetCurrentDC()))
             } else {
                 Err(Error::NoCurrentContext)
             }
@@ -742,6 +1296,11 @@ extern "system" fn extension_loader_window_proc(
                     None => Cow::Borrowed(""),
                 };
 
+                (*wgl_extension_functions).extensions = extensions
+                    .split_whitespace()
+                    .map(|extension| extension.to_owned())
+                    .collect();
+
                 // Load function pointers.
                 for extension in extensions.split(' ') {
                     if extension == "WGL_ARB_pixel_format" {
@@ -763,6 +1322,12 @@ extern "system" fn extension_loader_window_proc(
                             ));
                         continue;
                     }
+                    if extension == "WGL_EXT_swap_control" {
+                        (*wgl_extension_functions).SwapIntervalEXT = mem::transmute(
+                            wglGetProcAddress(&b"wglSwapIntervalEXT\0"[0] as *const u8 as LPCSTR),
+                        );
+                        continue;
+                    }
                     if extension == "WGL_NV_DX_interop" {
                         (*wgl_extension_functions).dx_interop_functions =
                             Some(WGLDXInteropExtensionFunctions {
@@ -841,31 +1406,55 @@ impl<'a> FramebufferGuard<'a> {
     }
 }
 
+/// Restores the HDC and HGLRC that were current on this thread when it was created, once it is
+/// dropped.
 #[must_use]
-pub(crate) struct CurrentContextGuard {
+pub struct CurrentContextGuard {
     old_dc: HDC,
     old_glrc: HGLRC,
+    tracked: SavedCurrent,
+    // Set if the context was already current for `Device::with_context_current()`, which puts
+    // back the previous context itself.
+    held: bool,
 }
 
 impl Drop for CurrentContextGuard {
     #[inline]
     fn drop(&mut self) {
+        if self.held {
+            return;
+        }
         unsafe {
             wglMakeCurrent(self.old_dc, self.old_glrc);
         }
+        current::restore(self.tracked);
     }
 }
 
 impl CurrentContextGuard {
     #[inline]
-    fn new() -> CurrentContextGuard {
+    pub(crate) fn new() -> CurrentContextGuard {
         unsafe {
             CurrentContextGuard {
                 old_dc: wglGetCurrentDC(),
                 old_glrc: wglGetCurrentContext(),
+                tracked: current::save(),
+                held: false,
             }
         }
     }
+
+    // Returns a guard that restores nothing, for a context that `current::held_for_bulk_ops()`
+    // says is current already.
+    #[inline]
+    pub(crate) fn held() -> CurrentContextGuard {
+        CurrentContextGuard {
+            old_dc: ptr::null_mut(),
+            old_glrc: ptr::null_mut(),
+            tracked: current::save(),
+            held: true,
+        }
+    }
 }
 
 fn get_proc_address(symbol_name: &str) -> *const c_void {
@@ -883,6 +1472,30 @@ fn get_proc_address(symbol_name: &str) -> *const c_void {
     }
 }
 
+// Returns the pixel format of the native context's device context, which is the pixel format that
+// the context was created for.
+unsafe fn native_context_pixel_format(native_context: &NativeContext) -> Result<c_int, Error> {
+    if native_context.1.is_null() {
+        return Err(Error::IncompatibleNativeContext);
+    }
+    match wingdi::GetPixelFormat(native_context.1) {
+        0 => Err(Error::IncompatibleNativeContext),
+        pixel_format => Ok(pixel_format),
+    }
+}
+
+// Makes a native context current on this thread. WGL refuses with `ERROR_BUSY` while another
+// thread has the context current.
+unsafe fn make_native_context_current(dc: HDC, glrc: HGLRC) -> Result<(), Error> {
+    if wglMakeCurrent(dc, glrc) != FALSE {
+        return Ok(());
+    }
+    match GetLastError() {
+        ERROR_BUSY => Err(Error::NativeContextCurrentElsewhere),
+        _ => Err(Error::MakeCurrentFailed(WindowingApiError::Failed)),
+    }
+}
+
 pub(crate) fn set_dc_pixel_format(dc: HDC, pixel_format: c_int) {
     unsafe {
         let mut pixel_format_descriptor = mem::zeroed();
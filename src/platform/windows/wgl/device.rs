@@ -4,7 +4,15 @@
 
 use super::connection::Connection;
 use super::context::WGL_EXTENSION_FUNCTIONS;
-use crate::{Error, GLApi};
+use crate::context::NegotiatedVersions;
+use crate::lost::LostContexts;
+use crate::platform::windows::identity;
+use crate::ColorProfileChangedHandler;
+use crate::DebugFill;
+use crate::LowMemoryOptions;
+use crate::SurfaceScaleChangedHandler;
+use crate::{CancelToken, DeviceIdentity, Error, GLApi};
+use crate::{SchedulingClass, SchedulingEnforcement};
 
 use std::marker::PhantomData;
 use std::mem;
@@ -56,9 +64,20 @@ pub struct Device {
     pub(crate) d3d11_device_context: ComPtr<ID3D11DeviceContext>,
     pub(crate) gl_dx_interop_device: HANDLE,
     pub(crate) hidden_window: HiddenWindow,
+    pub(crate) cancel_token: CancelToken,
+    pub(crate) scheduling_class: SchedulingClass,
+    pub(crate) low_memory_options: Option<LowMemoryOptions>,
+    pub(crate) debug_fill: DebugFill,
+    pub(crate) lost_contexts: LostContexts,
+    pub(crate) negotiated_versions: NegotiatedVersions,
+    pub(crate) surface_scale_changed_handler: Option<SurfaceScaleChangedHandler>,
+    pub(crate) color_profile_changed_handler: Option<ColorProfileChangedHandler>,
 }
 
 /// Wraps a Direct3D 11 device and its associated GL/DX interop device.
+///
+/// This is neither `Send` nor `Sync`, since the interop device belongs to the OpenGL context of
+/// the thread that opened it.
 #[derive(Clone)]
 pub struct NativeDevice {
     /// The Direct3D 11 device.
@@ -71,6 +90,9 @@ pub struct NativeDevice {
     pub gl_dx_interop_device: HANDLE,
 }
 
+assert_not_send!(NativeDevice);
+assert_not_sync!(NativeDevice);
+
 impl Adapter {
     pub(crate) fn set_exported_variables(&self) {
         unsafe {
@@ -166,6 +188,14 @@ impl Device {
                 d3d11_device_context,
                 gl_dx_interop_device,
                 hidden_window,
+                cancel_token: CancelToken::new(),
+                scheduling_class: SchedulingClass::Interactive,
+                low_memory_options: None,
+                debug_fill: DebugFill::None,
+                lost_contexts: LostContexts::default(),
+                negotiated_versions: NegotiatedVersions::default(),
+                surface_scale_changed_handler: None,
+                color_profile_changed_handler: None,
             })
         }
     }
@@ -201,6 +231,14 @@ impl Device {
                 d3d11_device_context,
                 gl_dx_interop_device,
                 hidden_window,
+                cancel_token: CancelToken::new(),
+                scheduling_class: SchedulingClass::Interactive,
+                low_memory_options: None,
+                debug_fill: DebugFill::None,
+                lost_contexts: LostContexts::default(),
+                negotiated_versions: NegotiatedVersions::default(),
+                surface_scale_changed_handler: None,
+                color_profile_changed_handler: None,
             })
         }
     }
@@ -237,10 +275,92 @@ impl Device {
     pub fn gl_api(&self) -> GLApi {
         GLApi::GL
     }
+
+    /// Returns the identity of the physical GPU that this device renders with: the LUID of its
+    /// DXGI adapter.
+    pub fn identity(&self) -> DeviceIdentity {
+        unsafe {
+            let dxgi_device: ComPtr<IDXGIDevice> = self.d3d11_device.cast().unwrap();
+            let mut dxgi_adapter = ptr::null_mut();
+            let result = dxgi_device.GetAdapter(&mut dxgi_adapter);
+            if result != S_OK || dxgi_adapter.is_null() {
+                return DeviceIdentity::default();
+            }
+            identity::dxgi_adapter_identity(&ComPtr::from_raw(dxgi_adapter))
+        }
+    }
+
+    /// Returns a token that can cancel context and surface creation on this device from any
+    /// thread.
+    #[inline]
+    pub fn cancellation_token(&self) -> CancelToken {
+        self.cancel_token.clone()
+    }
+
+    /// Sets what kind of work this device's contexts do, and returns how that is enforced.
+    ///
+    /// WGL can't prioritize contexts, so `Background` devices have their swap chains paced in
+    /// software. See `SchedulingEnforcement`.
+    #[inline]
+    pub fn set_scheduling_class(&mut self, class: SchedulingClass) -> SchedulingEnforcement {
+        self.scheduling_class = class;
+        self.scheduling_enforcement()
+    }
+
+    /// Returns the scheduling class that was last set with `set_scheduling_class()`.
+    #[inline]
+    pub fn scheduling_class(&self) -> SchedulingClass {
+        self.scheduling_class
+    }
+
+    /// Returns how this device enforces its scheduling class.
+    #[inline]
+    pub fn scheduling_enforcement(&self) -> SchedulingEnforcement {
+        self.scheduling_class.enforcement(false, false)
+    }
+
+    /// Makes this device save memory at the expense of rendering quality until
+    /// `exit_low_memory_mode()` is called, and returns how many pooled surfaces were destroyed.
+    ///
+    /// WGL doesn't pool surfaces, so this always returns zero. Entering low-memory mode again
+    /// replaces the options. See `LowMemoryOptions`.
+    #[inline]
+    pub fn enter_low_memory_mode(&mut self, options: LowMemoryOptions) -> usize {
+        self.low_memory_options = Some(options);
+        0
+    }
+
+    /// Leaves low-memory mode, so that surfaces created from now on get their usual formats.
+    ///
+    /// Surfaces created in low-memory mode keep their formats until they are destroyed.
+    #[inline]
+    pub fn exit_low_memory_mode(&mut self) {
+        self.low_memory_options = None;
+    }
+
+    /// Returns the options that this device entered low-memory mode with, or `None` if it isn't
+    /// in low-memory mode.
+    #[inline]
+    pub fn low_memory_options(&self) -> Option<LowMemoryOptions> {
+        self.low_memory_options
+    }
+
+    /// Makes this device fill each surface that it creates from now on, and each surface that a
+    /// swap chain recycles, with a recognizable pattern. See `DebugFill`.
+    #[inline]
+    pub fn set_debug_surface_fill(&mut self, fill: DebugFill) {
+        self.debug_fill = fill;
+    }
+
+    /// Returns the debug fill that was last set with `set_debug_surface_fill()`.
+    #[inline]
+    pub fn debug_surface_fill(&self) -> DebugFill {
+        self.debug_fill
+    }
 }
 
 impl Adapter {
-    fn from_dxgi_adapter(dxgi_adapter: &ComPtr<IDXGIAdapter>) -> Adapter {
+    pub(crate) fn from_dxgi_adapter(dxgi_adapter: &ComPtr<IDXGIAdapter>) -> Adapter {
         unsafe {
             let mut adapter_desc = mem::zeroed();
             let result = dxgi_adapter.GetDesc(&mut adapter_desc);
@@ -2,28 +2,56 @@
 //
 //! An implementation of the GPU device for Windows using WGL/Direct3D interoperability.
 
-use super::context::{self, Context, WGL_EXTENSION_FUNCTIONS};
-use super::device::Device;
+use super::context::{self, Context, CurrentContextGuard, WGL_EXTENSION_FUNCTIONS};
+use super::device::{DCGuard, Device};
+use crate::bulk;
+use crate::clear::ClearRegion;
+use crate::color::ColorProfileTracker;
+use crate::convert::{self, Swizzle};
 use crate::error::WindowingApiError;
-use crate::renderbuffers::Renderbuffers;
-use crate::{ContextID, Error, SurfaceAccess, SurfaceID, SurfaceInfo, SurfaceType};
+use crate::platform::windows::{color, identity, timing, widget};
+use crate::presentation_time;
+use crate::readback;
+use crate::renderbuffers::{Multisample, Renderbuffers};
+use crate::scale::ScaleTracker;
+use crate::surface::{check_surface_size, PresentHistory};
+use crate::upload;
+use crate::vsync::AutoVsync;
+use crate::ColorProfileChangedHandler;
+use crate::NativeFormat;
+use crate::PausedPresentation;
+use crate::PresentationStatistics;
+use crate::SurfaceFence;
+use crate::SurfaceScaleChangedHandler;
+use crate::{AutoVsyncPolicy, AutoVsyncStats};
+use crate::{BufferRelease, ColorBits, PresentMode, SurfaceID, SurfaceOptions, SurfaceType};
+use crate::{ColorEncoding, ColorProfile, ContextID, Error, PresentRecord, SurfaceAccess};
+use crate::{GLCapabilities, InvalidateSet, Purgeability, ReclaimResult, SurfaceInfo};
+use crate::{SurfaceConfig, SurfaceIntent, SurfaceResizedHandler, SurfaceTransform};
 
 use crate::gl;
 use crate::gl::types::{GLenum, GLint, GLuint};
 use crate::gl_utils;
-use euclid::default::Size2D;
+use crate::identities_match;
+use crate::invalidate::PostPresentInvalidation;
+use crate::leak_check::{self, TrackedObject};
+use crate::memory::{PurgeState, ResidentBacking};
+use crate::TextureOwnership;
+use euclid::default::{Rect, Size2D};
 use std::fmt::{self, Debug, Formatter};
 use std::marker::PhantomData;
 use std::mem;
 use std::os::raw::c_void;
 use std::ptr;
 use std::thread;
+use std::time::{Duration, Instant};
 use winapi::shared::dxgi::IDXGIResource;
-use winapi::shared::dxgiformat::DXGI_FORMAT_R8G8B8A8_UNORM;
+use winapi::shared::dxgiformat::{DXGI_FORMAT_R10G10B10A2_UNORM, DXGI_FORMAT_R16G16B16A16_FLOAT};
+use winapi::shared::dxgiformat::{DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_FORMAT_R8G8B8A8_UNORM_SRGB};
 use winapi::shared::dxgitype::DXGI_SAMPLE_DESC;
 use winapi::shared::minwindef::{FALSE, UINT};
 use winapi::shared::ntdef::HANDLE;
-use winapi::shared::windef::HWND;
+use winapi::shared::windef::{HGLRC, HWND};
 use winapi::shared::winerror;
 use winapi::um::d3d11::{ID3D11Texture2D, D3D11_USAGE_DEFAULT};
 use winapi::um::d3d11::{D3D11_BIND_RENDER_TARGET, D3D11_BIND_SHADER_RESOURCE};
@@ -58,6 +86,18 @@ const WGL_ACCESS_READ_WRITE_NV: GLenum = 0x0001;
 pub struct Surface {
     pub(crate) size: Size2D<i32>,
     pub(crate) context_id: ContextID,
+    pub(crate) access: SurfaceAccess,
+    pub(crate) format: GLenum,
+    pub(crate) colorspace: ColorEncoding,
+    pub(crate) color_bits: ColorBits,
+    pub(crate) present_history: PresentHistory,
+    pub(crate) auto_vsync: Option<AutoVsync>,
+    // Follows the scale of the display that a widget surface's window is on.
+    pub(crate) scale: Option<ScaleTracker>,
+    // The color profile of the monitor that a widget surface's window was last seen on.
+    pub(crate) color_profile: ColorProfileTracker,
+    pub(crate) invalidation: PostPresentInvalidation,
+    pub(crate) purge_state: PurgeState,
     pub(crate) win32_objects: Win32Objects,
     pub(crate) destroyed: bool,
 }
@@ -70,6 +110,7 @@ pub(crate) enum Win32Objects {
         gl_texture: GLuint,
         gl_framebuffer: GLuint,
         renderbuffers: Renderbuffers,
+        multisample: Option<Multisample>,
     },
     Widget {
         window_handle: HWND,
@@ -98,7 +139,7 @@ unsafe impl Send for Surface {}
 
 impl Debug for Surface {
     fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
-        write!(f, "Surface({:x})", self.id().0)
+        write!(f, "Surface({:x}, {:?})", self.id().0, self.access)
     }
 }
 
@@ -117,13 +158,24 @@ impl Debug for SurfaceTexture {
 }
 
 /// Wraps a Windows `HWND` window handle.
+///
+/// This is neither `Send` nor `Sync`: a window belongs to the thread that created it, which can
+/// destroy it at any time, so create surfaces from it on that thread.
 pub struct NativeWidget {
     /// A window handle.
     ///
     /// This can be a top-level window or a control.
     pub window_handle: HWND,
+    /// The real size of the window's client area, if it differed from the size that the widget
+    /// was created with.
+    ///
+    /// The `Connection` constructors fill this in. Surfaces always use the real size.
+    pub adjusted_size: Option<Size2D<i32>>,
 }
 
+assert_not_send!(NativeWidget);
+assert_not_sync!(NativeWidget);
+
 impl Device {
     /// Creates either a generic or a widget surface, depending on the supplied surface type.
     ///
@@ -132,21 +184,81 @@ impl Device {
     pub fn create_surface(
         &mut self,
         context: &Context,
-        _: SurfaceAccess,
+        access: SurfaceAccess,
         surface_type: SurfaceType<NativeWidget>,
     ) -> Result<Surface, Error> {
-        match surface_type {
-            SurfaceType::Generic { size } => self.create_generic_surface(context, &size),
+        self.create_surface_with_options(context, access, surface_type, SurfaceOptions::default())
+    }
+
+    /// Creates a surface of each of the given types, in order.
+    ///
+    /// The device's cancellation token is checked before each surface, as well as inside each
+    /// creation. If any surface fails or is cancelled, the ones created so far are destroyed and
+    /// the error is returned, so the batch creates either all of its surfaces or none.
+    pub fn create_surfaces<I>(
+        &mut self,
+        context: &mut Context,
+        access: SurfaceAccess,
+        surface_types: I,
+    ) -> Result<Vec<Surface>, Error>
+    where
+        I: IntoIterator<Item = SurfaceType<NativeWidget>>,
+    {
+        bulk::create_surfaces(self, context, access, surface_types)
+    }
+
+    /// Creates either a generic or a widget surface, with options such as its colorspace.
+    ///
+    /// `create_surface()` is this method with the default options. Widget surfaces share the
+    /// pixel format of their context, which is always linear `SurfaceFormat::Rgba8` and has the
+    /// context's alpha channel, so widget surfaces with other options, including an explicit
+    /// `opaque`, return `Error::UnsupportedSurfaceFormat`. They can't be single-buffered either,
+    /// and return `Error::UnsupportedPresentMode` for `PresentMode::SingleBuffer`, nor keep their
+    /// contents across presents, and return `Error::UnsupportedSwapBehavior` for
+    /// `preserve_buffer`.
+    pub fn create_surface_with_options(
+        &mut self,
+        context: &Context,
+        access: SurfaceAccess,
+        surface_type: SurfaceType<NativeWidget>,
+        options: SurfaceOptions,
+    ) -> Result<Surface, Error> {
+        self.cancel_token.check()?;
+        let mut surface = match surface_type {
+            SurfaceType::Generic { size } => {
+                self.create_generic_surface(context, &size, access, options)
+            }
             SurfaceType::Widget { native_widget } => {
-                self.create_widget_surface(context, native_widget)
+                // The window's pixel format, which is the context's, is double-buffered.
+                if options.present_mode == PresentMode::SingleBuffer {
+                    return Err(Error::UnsupportedPresentMode);
+                }
+                // `SwapBuffers()` leaves the back buffer undefined.
+                if options.preserve_buffer {
+                    return Err(Error::UnsupportedSwapBehavior);
+                }
+                // The options that don't concern the pixel format are free to set.
+                let format_options = SurfaceOptions {
+                    pre_transform: false,
+                    auto_rescale: false,
+                    ..options
+                };
+                if format_options != SurfaceOptions::default() {
+                    return Err(Error::UnsupportedSurfaceFormat);
+                }
+                self.create_widget_surface(context, native_widget, access, options.auto_rescale)
             }
-        }
+        }?;
+        self.debug_fill_new_surface(context, &mut surface);
+        Ok(surface)
     }
 
     fn create_generic_surface(
         &mut self,
         context: &Context,
         size: &Size2D<i32>,
+        access: SurfaceAccess,
+        options: SurfaceOptions,
     ) -> Result<Surface, Error> {
         let dx_interop_functions = match WGL_EXTENSION_FUNCTIONS.dx_interop_functions {
             None => return Err(Error::RequiredExtensionUnavailable),
@@ -154,7 +266,18 @@ impl Device {
         };
 
         unsafe {
-            let _guard = self.temporarily_make_context_current(context)?;
+            let _guard = self.make_context_current_guarded(context)?;
+
+            // The Direct3D texture is what OpenGL renders to, so the two formats must match.
+            let capabilities = GLCapabilities::current(&context.gl);
+            check_surface_size(*size, &capabilities)?;
+            let format = options.generic_surface_format(&capabilities, gl::RGBA8)?;
+            let dxgi_format = match format {
+                gl::SRGB8_ALPHA8 => DXGI_FORMAT_R8G8B8A8_UNORM_SRGB,
+                gl::RGBA16F => DXGI_FORMAT_R16G16B16A16_FLOAT,
+                gl::RGB10_A2 => DXGI_FORMAT_R10G10B10A2_UNORM,
+                _ => DXGI_FORMAT_R8G8B8A8_UNORM,
+            };
 
             // Create the Direct3D 11 texture.
             let d3d11_texture2d_desc = D3D11_TEXTURE2D_DESC {
@@ -162,7 +285,7 @@ impl Device {
                 Height: size.height as UINT,
                 MipLevels: 1,
                 ArraySize: 1,
-                Format: DXGI_FORMAT_R8G8B8A8_UNORM,
+                Format: dxgi_format,
                 SampleDesc: DXGI_SAMPLE_DESC {
                     Count: 1,
                     Quality: 0,
@@ -244,18 +367,37 @@ impl Device {
                 0,
             );
 
-            // Create renderbuffers as appropriate, and attach them.
+            // Create renderbuffers as appropriate, and attach them. Multisampled surfaces keep
+            // their depth and stencil with their samples.
             let context_descriptor = self.context_descriptor(context);
             let context_attributes = self.context_descriptor_attributes(&context_descriptor);
-            let renderbuffers = Renderbuffers::new(&context.gl, &size, &context_attributes);
-            renderbuffers.bind_to_current_framebuffer(&context.gl);
+            let multisample = Multisample::new(&context.gl, &size, &context_attributes, format);
+            let renderbuffers = match multisample {
+                Some(_) => Renderbuffers::none(),
+                None => {
+                    let renderbuffers = Renderbuffers::new(&context.gl, &size, &context_attributes);
+                    renderbuffers.bind_to_current_framebuffer(&context.gl);
+                    renderbuffers
+                }
+            };
 
             // FIXME(pcwalton): Do we need to acquire the keyed mutex, or does the GL driver do
             // that?
 
+            leak_check::created(TrackedObject::Surface);
             Ok(Surface {
                 size: *size,
                 context_id: context.id,
+                access,
+                format,
+                colorspace: options.colorspace,
+                color_bits: ColorBits::of_format(format),
+                present_history: PresentHistory::default(),
+                auto_vsync: None,
+                scale: None,
+                color_profile: ColorProfileTracker::default(),
+                invalidation: PostPresentInvalidation::default(),
+                purge_state: PurgeState::default(),
                 win32_objects: Win32Objects::Texture {
                     d3d11_texture,
                     dxgi_share_handle,
@@ -263,6 +405,7 @@ impl Device {
                     gl_texture,
                     gl_framebuffer,
                     renderbuffers,
+                    multisample,
                 },
                 destroyed: false,
             })
@@ -273,6 +416,8 @@ impl Device {
         &mut self,
         context: &Context,
         native_widget: NativeWidget,
+        access: SurfaceAccess,
+        auto_rescale: bool,
     ) -> Result<Surface, Error> {
         unsafe {
             // Get the bounds of the native HWND.
@@ -289,13 +434,33 @@ impl Device {
                 let window_dc = winuser::GetDC(native_widget.window_handle);
                 context::set_dc_pixel_format(window_dc, pixel_format);
             }
+            let descriptor_parts =
+                self.context_descriptor_raw_parts(&self.context_descriptor(context));
+            let scale = widget::window_scale(native_widget.window_handle)
+                .map(|scale| ScaleTracker::new(scale, auto_rescale));
 
+            leak_check::created(TrackedObject::Surface);
             Ok(Surface {
                 size: Size2D::new(
                     widget_rect.right - widget_rect.left,
                     widget_rect.bottom - widget_rect.top,
                 ),
                 context_id: context.id,
+                access,
+                format: gl::NONE,
+                colorspace: ColorEncoding::Linear,
+                color_bits: ColorBits {
+                    red: descriptor_parts.color_channel_size,
+                    green: descriptor_parts.color_channel_size,
+                    blue: descriptor_parts.color_channel_size,
+                    alpha: descriptor_parts.alpha_size,
+                },
+                present_history: PresentHistory::default(),
+                auto_vsync: None,
+                scale,
+                color_profile: ColorProfileTracker::default(),
+                invalidation: PostPresentInvalidation::default(),
+                purge_state: PurgeState::default(),
                 win32_objects: Win32Objects::Widget {
                     window_handle: native_widget.window_handle,
                 },
@@ -304,6 +469,22 @@ impl Device {
         }
     }
 
+    /// Wraps an existing GL texture in a generic surface.
+    ///
+    /// Surfaces on this platform are Direct3D textures shared with OpenGL through
+    /// `WGL_NV_DX_interop`, which can't adopt a texture that OpenGL created, so this returns
+    /// `UnsupportedOnThisPlatform`.
+    pub fn create_surface_from_texture(
+        &mut self,
+        _: &mut Context,
+        _: GLuint,
+        _: &Size2D<i32>,
+        _: GLenum,
+        _: TextureOwnership,
+    ) -> Result<Surface, Error> {
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
     /// Destroys a surface.
     ///
     /// The supplied context must be the context the surface is associated with, or this returns
@@ -325,7 +506,7 @@ impl Device {
             return Err(Error::IncompatibleSurface);
         }
 
-        let _guard = self.temporarily_make_context_current(context)?;
+        let _guard = self.make_context_current_guarded(context)?;
 
         unsafe {
             match surface.win32_objects {
@@ -334,10 +515,14 @@ impl Device {
                     ref mut gl_texture,
                     ref mut gl_framebuffer,
                     ref mut renderbuffers,
+                    ref mut multisample,
                     d3d11_texture: _,
                     dxgi_share_handle: _,
                 } => {
                     renderbuffers.destroy(&context.gl);
+                    if let Some(mut multisample) = multisample.take() {
+                        multisample.destroy(&context.gl);
+                    }
 
                     gl_utils::destroy_framebuffer(&context.gl, *gl_framebuffer);
                     *gl_framebuffer = 0;
@@ -355,12 +540,29 @@ impl Device {
                 Win32Objects::Widget { window_handle: _ } => {}
             }
 
+            if !surface.destroyed {
+                leak_check::destroyed(TrackedObject::Surface);
+            }
             surface.destroyed = true;
         }
 
         Ok(())
     }
 
+    /// Returns the access mode, type, and options that suit a surface of the given size, created
+    /// with the given context for the given use, on this device, along with hints for using it.
+    ///
+    /// This backend can't map surfaces for the CPU, so the access mode is always `GPUOnly`.
+    pub fn recommended_surface_config(
+        &self,
+        context: &Context,
+        intent: SurfaceIntent<NativeWidget>,
+        size: Size2D<i32>,
+    ) -> Result<SurfaceConfig<NativeWidget>, Error> {
+        let capabilities = self.capabilities(context)?;
+        Ok(SurfaceConfig::recommend(intent, size, false, &capabilities))
+    }
+
     /// Creates a surface texture from an existing generic surface for use with the given context.
     ///
     /// The surface texture is local to the supplied context and takes ownership of the surface.
@@ -382,13 +584,16 @@ impl Device {
                 dxgi_share_handle, ..
             } => dxgi_share_handle,
         };
+        if let Err(err) = surface.purge_state.check_usable() {
+            return Err((err, surface));
+        }
 
         let dx_interop_functions = WGL_EXTENSION_FUNCTIONS
             .dx_interop_functions
             .as_ref()
             .expect("How did you make a surface without DX interop?");
 
-        let _guard = match self.temporarily_make_context_current(context) {
+        let _guard = match self.make_context_current_guarded(context) {
             Ok(guard) => guard,
             Err(err) => return Err((err, surface)),
         };
@@ -458,6 +663,7 @@ impl Device {
             );
 
             // Finish up.
+            leak_check::created(TrackedObject::SurfaceTexture);
             Ok(SurfaceTexture {
                 surface,
                 local_d3d11_texture,
@@ -485,7 +691,7 @@ impl Device {
             .as_ref()
             .expect("How did you make a surface without DX interop?");
 
-        let _guard = match self.temporarily_make_context_current(context) {
+        let _guard = match self.make_context_current_guarded(context) {
             Ok(guard) => guard,
             Err(err) => return Err((err, surface_texture)),
         };
@@ -512,6 +718,7 @@ impl Device {
             surface_texture.gl_texture = 0;
         }
 
+        leak_check::destroyed(TrackedObject::SurfaceTexture);
         Ok(surface_texture.surface)
     }
 
@@ -567,11 +774,60 @@ impl Device {
     #[inline]
     pub fn lock_surface_data<'s>(
         &self,
-        _surface: &'s mut Surface,
+        surface: &'s mut Surface,
     ) -> Result<SurfaceDataGuard<'s>, Error> {
+        surface.access.check_cpu_access()?;
+        surface.purge_state.check_usable()?;
         Err(Error::Unimplemented)
     }
 
+    /// Copies the contents of `source` into `destination` on the GPU, converting between their
+    /// formats and colorspaces and exchanging channels as `swizzle` says.
+    ///
+    /// Both surfaces must be generic surfaces of `context`, the same size, and `context` must be
+    /// current.
+    #[inline]
+    pub fn convert_surface(
+        &self,
+        context: &mut Context,
+        source: &Surface,
+        destination: &mut Surface,
+        swizzle: Swizzle,
+    ) -> Result<(), Error> {
+        convert::convert_surface(self, context, source, destination, swizzle)
+    }
+
+    /// Reads a rectangle of a generic surface, measured from its top left, into `out` as tightly
+    /// packed 8-bit RGBA, top row first.
+    ///
+    /// The surface must belong to `context`, which needn't be current.
+    #[inline]
+    pub fn read_surface_data(
+        &self,
+        context: &Context,
+        surface: &Surface,
+        rect: Rect<i32>,
+        out: &mut [u8],
+    ) -> Result<(), Error> {
+        readback::read_surface_data(self, context, surface, rect, out)
+    }
+
+    /// Writes 8-bit RGBA pixels from `data`, top row first, into a rectangle of a generic surface,
+    /// measured from its top left.
+    ///
+    /// The surface must belong to `context`, which needn't be current.
+    #[inline]
+    pub fn write_surface_data(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        rect: Rect<i32>,
+        data: &[u8],
+        stride: usize,
+    ) -> Result<(), Error> {
+        upload::write_surface_data(self, context, surface, rect, data, stride)
+    }
+
     /// Returns the OpenGL texture target needed to read from this surface texture.
     ///
     /// This will be `GL_TEXTURE_2D` or `GL_TEXTURE_RECTANGLE`, depending on platform.
@@ -586,33 +842,510 @@ impl Device {
     /// associated widgets until this method is called.
     ///
     /// The supplied context must match the context the surface was created with, or an
-    /// `IncompatibleSurface` error is returned.
-    pub fn present_surface(&self, _: &Context, surface: &mut Surface) -> Result<(), Error> {
+    /// `IncompatibleSurface` error is returned. The context doesn't need to be current.
+    ///
+    /// A window that has been resized since the surface was last sized fails with
+    /// `SurfaceSizeMismatch` without presenting.
+    pub fn present_surface(&self, context: &Context, surface: &mut Surface) -> Result<(), Error> {
+        self.present(context, surface, None)
+    }
+
+    /// Displays the changed parts of a widget surface on screen.
+    ///
+    /// `damage` lists the rectangles that changed since the last present, measured from the
+    /// bottom left as in OpenGL. WGL has no way to swap part of a window, so the whole surface is
+    /// swapped as `present_surface()` does, unless the list is empty. An empty list means that
+    /// nothing changed, and skips the swap entirely.
+    pub fn present_surface_with_damage(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        self.present(context, surface, Some(damage))
+    }
+
+    fn present(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: Option<&[Rect<i32>]>,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
         let window_handle = match surface.win32_objects {
             Win32Objects::Widget { window_handle } => window_handle,
             _ => return Err(Error::NoWidgetAttached),
         };
+        if matches!(damage, Some([])) {
+            return Ok(());
+        }
+        // A frame drawn before a change of scale was noticed is shown as it is.
+        let rescale_to = self.update_scale(surface, window_handle);
+        self.update_color_profile(surface, window_handle);
+        if rescale_to.is_none() {
+            self.check_native_size(surface)?;
+        }
 
+        let submit = Instant::now();
         unsafe {
             let dc = winuser::GetDC(window_handle);
             let ok = wingdi::SwapBuffers(dc);
             assert_ne!(ok, FALSE);
+            if !surface.invalidation.set().is_empty() {
+                // The window's buffers can only be invalidated with its DC current, which the
+                // guard undoes before the DC is released.
+                let _guard = CurrentContextGuard::new();
+                if wingdi::wglMakeCurrent(dc, context.glrc) != FALSE {
+                    surface.invalidation.invalidate(&context.gl, 0);
+                }
+            }
             winuser::ReleaseDC(window_handle, dc);
-            Ok(())
         }
+        surface
+            .present_history
+            .record(submit, Some(Instant::now()), None);
+        if let Some(ref mut auto_vsync) = surface.auto_vsync {
+            auto_vsync.update(&surface.present_history, |interval| unsafe {
+                set_window_swap_interval(window_handle, context.glrc, interval)
+            });
+        }
+        if let Some(size) = rescale_to {
+            self.resize_surface(context, surface, size)?;
+        }
+        Ok(())
+    }
+
+    // Reports a change in the scale of the display that a widget surface's window is on, and
+    // returns the size to resize an automatically rescaled surface to once this frame is shown.
+    fn update_scale(&self, surface: &mut Surface, window_handle: HWND) -> Option<Size2D<i32>> {
+        let scale = widget::window_scale(window_handle)?;
+        let native_size = self.query_surface_native_size(surface).ok();
+        let tracker = surface.scale.as_mut()?;
+        let (change, rescale_to) = tracker.update(scale, surface.size, native_size);
+        if let (Some(change), Some(handler)) = (change, &self.surface_scale_changed_handler) {
+            handler(surface.id(), change);
+        }
+        rescale_to
+    }
+
+    // Reports a change in the color profile of the monitor that a widget surface's window is on,
+    // if one is noticed on this present.
+    fn update_color_profile(&self, surface: &mut Surface, window_handle: HWND) {
+        let handler = match self.color_profile_changed_handler {
+            Some(ref handler) => handler,
+            None => return,
+        };
+        let changed = surface
+            .color_profile
+            .update(|| color::window_color_profile(window_handle));
+        if let Some(profile) = changed {
+            handler(surface.id(), &profile);
+        }
+    }
+
+    /// Pausing presentation isn't supported on this backend: widget surfaces return
+    /// `UnsupportedOnThisPlatform`, and generic surfaces `NoWidgetAttached`.
+    pub fn pause_presentation(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<PausedPresentation, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        match surface.win32_objects {
+            Win32Objects::Widget { .. } => Err(Error::UnsupportedOnThisPlatform),
+            _ => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Since presentation can't be paused on this backend, `paused` was returned for a surface of
+    /// another, so this returns `IncompatiblePausedPresentation`.
+    pub fn resume_presentation(
+        &self,
+        _: &Context,
+        _: &mut Surface,
+        _: PausedPresentation,
+        _: bool,
+    ) -> Result<(), Error> {
+        Err(Error::IncompatiblePausedPresentation)
+    }
+
+    /// Fences aren't supported on this backend, so this returns `UnsupportedOnThisPlatform`.
+    pub fn present_surface_with_fence(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<SurfaceFence, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        Err(Error::UnsupportedOnThisPlatform)
+    }
+
+    /// Since fences can't be created on this backend, `fence` was returned by a device of
+    /// another, so this returns `IncompatibleSurfaceFence`.
+    pub fn wait_fence(&self, _: &Context, _: &SurfaceFence) -> Result<(), Error> {
+        Err(Error::IncompatibleSurfaceFence)
     }
 
     /// Resizes a widget surface.
+    ///
+    /// WGL draws to the window's own buffers, which follow its size, so this only records the
+    /// size. Generic surfaces can't be reallocated in place while their Direct3D texture is
+    /// registered with the GL driver, so resizing them returns `UnsupportedOnThisPlatform`.
     pub fn resize_surface(
         &self,
-        _scontext: &Context,
+        context: &Context,
         surface: &mut Surface,
         size: Size2D<i32>,
     ) -> Result<(), Error> {
+        check_surface_size(size, &self.capabilities(context)?)?;
+        if let Win32Objects::Texture { .. } = surface.win32_objects {
+            return Err(Error::UnsupportedOnThisPlatform);
+        }
         surface.size = size;
+        if let Some(ref mut scale) = surface.scale {
+            scale.resized();
+        }
+        Ok(())
+    }
+
+    /// Sets the number of vertical blanks that `present_surface()` waits for before showing each
+    /// frame on a widget surface, and returns the interval that took effect.
+    ///
+    /// This requires `WGL_EXT_swap_control`; without it, or if the driver refuses the interval,
+    /// `UnsupportedOnThisPlatform` is returned. Negative intervals also require
+    /// `WGL_EXT_swap_control_tear`, and are replaced with their absolute value without it.
+    pub fn set_swap_interval(
+        &mut self,
+        context: &Context,
+        surface: &Surface,
+        interval: i32,
+    ) -> Result<i32, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        match surface.win32_objects {
+            Win32Objects::Widget { window_handle } => unsafe {
+                set_window_swap_interval(window_handle, context.glrc, interval)
+            },
+            _ => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Returns true if a widget surface accepts the negative swap intervals of adaptive vsync,
+    /// which requires `WGL_EXT_swap_control_tear`.
+    pub fn supports_adaptive_swap(&self, surface: &Surface) -> bool {
+        match surface.win32_objects {
+            Win32Objects::Widget { .. } => {
+                WGL_EXTENSION_FUNCTIONS.SwapIntervalEXT.is_some() && supports_swap_control_tear()
+            }
+            _ => false,
+        }
+    }
+
+    /// Lets surfman switch a widget surface between vsync and immediate presentation by itself,
+    /// following `policy`, or stops it with `None`.
+    ///
+    /// The frame time is measured from the return of `SwapBuffers()`. Without
+    /// `WGL_EXT_swap_control`, this returns `UnsupportedOnThisPlatform`.
+    pub fn set_auto_swap_interval(
+        &mut self,
+        context: &Context,
+        surface: &mut Surface,
+        policy: Option<AutoVsyncPolicy>,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        let window_handle = match surface.win32_objects {
+            Win32Objects::Widget { window_handle } => window_handle,
+            _ => return Err(Error::NoWidgetAttached),
+        };
+        if policy.is_some() {
+            unsafe {
+                set_window_swap_interval(window_handle, context.glrc, 1)?;
+            }
+        }
+        surface.auto_vsync = policy.map(AutoVsync::new);
+        Ok(())
+    }
+
+    /// Returns what automatic vsync has decided for a surface, or `None` if it isn't enabled.
+    #[inline]
+    pub fn auto_swap_interval_stats(&self, surface: &Surface) -> Option<AutoVsyncStats> {
+        surface.auto_vsync.as_ref().map(AutoVsync::stats)
+    }
+
+    /// Waits until the buffer of the last frame presented on a widget surface has been released.
+    ///
+    /// WGL gives no way to observe when the window's buffers are released, so this returns
+    /// `UnsupportedOnThisPlatform` for widget surfaces.
+    pub fn wait_previous_buffer_released(
+        &self,
+        surface: &Surface,
+        _: Duration,
+    ) -> Result<BufferRelease, Error> {
+        match surface.win32_objects {
+            Win32Objects::Widget { .. } => Err(Error::UnsupportedOnThisPlatform),
+            _ => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Chooses which attachments of a surface are invalidated right after each present.
+    ///
+    /// The color buffer of a generic surface is what consumers read once it's presented, so
+    /// invalidating it returns `PreservedColorBuffer`.
+    pub fn set_post_present_invalidation(
+        &self,
+        surface: &mut Surface,
+        set: InvalidateSet,
+    ) -> Result<(), Error> {
+        let color_preserved = match surface.win32_objects {
+            Win32Objects::Widget { .. } => false,
+            Win32Objects::Texture { .. } => true,
+        };
+        surface.invalidation.change(set, color_preserved)
+    }
+
+    /// Returns which attachments of a surface are invalidated right after each present.
+    #[inline]
+    pub fn post_present_invalidation(&self, surface: &Surface) -> InvalidateSet {
+        surface.invalidation.set()
+    }
+
+    /// Invalidates the attachments of a generic surface that were chosen with
+    /// `set_post_present_invalidation()`, making the context current for the call.
+    ///
+    /// Widget surfaces are invalidated by `present_surface()`, and return `WidgetAttached`.
+    pub fn invalidate_surface(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        let framebuffer_object = match surface.win32_objects {
+            Win32Objects::Widget { .. } => return Err(Error::WidgetAttached),
+            Win32Objects::Texture {
+                multisample: Some(ref multisample),
+                ..
+            } => multisample.framebuffer_object,
+            Win32Objects::Texture { gl_framebuffer, .. } => gl_framebuffer,
+        };
+
+        let _guard = self.make_context_current_guarded(context)?;
+        // The surface isn't bound to the context, so its texture must be locked for GL to use it.
+        self.lock_surface(surface);
+        unsafe {
+            surface
+                .invalidation
+                .invalidate(&context.gl, framebuffer_object)
+        };
+        self.unlock_surface(surface);
         Ok(())
     }
 
+    /// Clears a region of a surface, measured from its top left, to `color`, and its depth and
+    /// stencil buffers to `depth` and `stencil` if they're given and the surface has them.
+    ///
+    /// Widget surfaces are cleared with the context current on the window's DC; generic surfaces
+    /// are cleared through their framebuffer object. Either way, the state that the clear changes
+    /// is put back afterward.
+    pub fn clear_surface_region(
+        &self,
+        context: &mut Context,
+        surface: &mut Surface,
+        rect: Option<Rect<i32>>,
+        color: [f32; 4],
+        depth: Option<f32>,
+        stencil: Option<i32>,
+    ) -> Result<(), Error> {
+        let region = ClearRegion::new(rect, color, depth, stencil);
+        self.clear_surface_with(context, surface, &region)
+    }
+
+    /// Fills a surface with this device's debug fill, as if it had just been created, or does
+    /// nothing if the fill is `DebugFill::None`.
+    pub fn debug_fill_surface(
+        &self,
+        context: &mut Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        match self.debug_fill.region() {
+            Some(region) => self.clear_surface_with(context, surface, &region),
+            None => Ok(()),
+        }
+    }
+
+    // Fills a new surface with the debug fill, if there is one. The surface is usable either way,
+    // so failing to fill it is only logged.
+    fn debug_fill_new_surface(&self, context: &Context, surface: &mut Surface) {
+        if let Some(region) = self.debug_fill.region() {
+            if let Err(err) = self.clear_surface_with(context, surface, &region) {
+                warn!(
+                    "Failed to fill a new surface with the debug fill: {:?}",
+                    err
+                );
+            }
+        }
+    }
+
+    fn clear_surface_with(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        region: &ClearRegion,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        let framebuffer_object = match surface.win32_objects {
+            Win32Objects::Widget { window_handle } => unsafe {
+                let guard = CurrentContextGuard::new();
+                let dc = winuser::GetDC(window_handle);
+                let result = if wingdi::wglMakeCurrent(dc, context.glrc) != FALSE {
+                    region.clear(&context.gl, 0, surface.size)
+                } else {
+                    Err(Error::MakeCurrentFailed(WindowingApiError::Failed))
+                };
+                // The guard must restore the previous context before the DC is released.
+                drop(guard);
+                winuser::ReleaseDC(window_handle, dc);
+                return result;
+            },
+            Win32Objects::Texture {
+                multisample: Some(ref multisample),
+                ..
+            } => multisample.framebuffer_object,
+            Win32Objects::Texture { gl_framebuffer, .. } => gl_framebuffer,
+        };
+
+        surface.purge_state.check_usable()?;
+        let _guard = self.make_context_current_guarded(context)?;
+        // The surface isn't bound to the context, so its texture must be locked for GL to use it.
+        self.lock_surface(surface);
+        let result = unsafe { region.clear(&context.gl, framebuffer_object, surface.size) };
+        self.unlock_surface(surface);
+        result
+    }
+
+    /// Returns how many frames ago the back buffer of a widget surface was last presented, or 0
+    /// if its contents are undefined.
+    ///
+    /// WGL has no way to query the age of the window's back buffer, so this always returns 0 for
+    /// widget surfaces.
+    pub fn surface_buffer_age(&self, surface: &Surface) -> Result<u32, Error> {
+        match surface.win32_objects {
+            Win32Objects::Widget { .. } => Ok(0),
+            Win32Objects::Texture { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Returns the current size of the client area of a widget surface's window.
+    pub fn query_surface_native_size(&self, surface: &Surface) -> Result<Size2D<i32>, Error> {
+        match surface.win32_objects {
+            Win32Objects::Widget { window_handle } => {
+                widget::window_client_size(window_handle).ok_or(Error::InvalidNativeWidget)
+            }
+            _ => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Returns the rotation that the window system applies to a widget surface's frames to show
+    /// them on its display.
+    ///
+    /// Windows rotates frames itself, so this is always `SurfaceTransform::Identity`.
+    #[inline]
+    pub fn surface_transform(&self, _surface: &Surface) -> SurfaceTransform {
+        SurfaceTransform::Identity
+    }
+
+    // Fails with `SurfaceSizeMismatch` if a widget surface's window has been resized since the
+    // surface was last sized. Other failures are left for the present itself to report.
+    fn check_native_size(&self, surface: &Surface) -> Result<(), Error> {
+        match self.query_surface_native_size(surface) {
+            Ok(actual) if actual != surface.size => Err(Error::SurfaceSizeMismatch {
+                expected: surface.size,
+                actual,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Registers a function to call with the ID and new size of each widget surface of this
+    /// device that is found to have been resized behind surfman's back.
+    ///
+    /// WGL draws to the window's own buffers, which follow its size, so the handler is never
+    /// called.
+    #[inline]
+    pub fn set_surface_resized_handler(&mut self, _: Option<SurfaceResizedHandler>) {}
+
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to have moved to a display with a different scale factor, replacing any previous
+    /// one, or unregisters it if `handler` is `None`.
+    ///
+    /// `present_surface()` compares the DPI that `GetDpiForWindow()` reports for the surface's
+    /// window with the one that it last saw, so changes are only noticed on Windows 10 version
+    /// 1607 and later, in processes that are aware of the DPI of each monitor.
+    #[inline]
+    pub fn set_surface_scale_changed_handler(
+        &mut self,
+        handler: Option<SurfaceScaleChangedHandler>,
+    ) -> Result<(), Error> {
+        self.surface_scale_changed_handler = handler;
+        Ok(())
+    }
+
+    /// Registers a function to call with the ID of each widget surface of this device that is
+    /// found to be displayed with a different color profile, with the new profile, replacing any
+    /// previous one, or unregisters it if `handler` is `None`.
+    ///
+    /// Every 30 presents, `present_surface()` asks Windows color management for the profile of
+    /// the window's monitor again, as `widget_color_profile()` does, and compares it with the
+    /// profile that it last saw.
+    #[inline]
+    pub fn set_color_profile_changed_handler(
+        &mut self,
+        handler: Option<ColorProfileChangedHandler>,
+    ) {
+        self.color_profile_changed_handler = handler;
+    }
+
+    /// Sets whether the system may discard the contents of a generic surface to free memory.
+    ///
+    /// The surface's Direct3D texture stays registered with the GL driver through
+    /// `WGL_NV_DX_interop`, so it can't be offered to the system, and volatile surfaces are never
+    /// discarded. The state is still enforced: a volatile surface must be taken back with
+    /// `reclaim_surface()` before it's used again.
+    pub fn set_surface_purgeable(
+        &self,
+        surface: &mut Surface,
+        purgeability: Purgeability,
+    ) -> Result<(), Error> {
+        match surface.win32_objects {
+            Win32Objects::Widget { .. } => Err(Error::WidgetAttached),
+            Win32Objects::Texture { .. } => {
+                surface.purge_state.set(&mut ResidentBacking, purgeability)
+            }
+        }
+    }
+
+    /// Makes a surface non-purgeable again, and reports whether the system discarded its contents
+    /// since it was made volatile.
+    ///
+    /// On this backend, the contents are always intact.
+    pub fn reclaim_surface(&self, surface: &mut Surface) -> Result<ReclaimResult, Error> {
+        match surface.win32_objects {
+            Win32Objects::Widget { .. } => Err(Error::WidgetAttached),
+            Win32Objects::Texture { .. } => surface.purge_state.reclaim(&mut ResidentBacking),
+        }
+    }
+
     /// Returns various information about the surface, including the framebuffer object needed to
     /// render to this surface.
     ///
@@ -626,12 +1359,107 @@ impl Device {
             id: surface.id(),
             context_id: surface.context_id,
             framebuffer_object: match surface.win32_objects {
+                Win32Objects::Texture {
+                    multisample: Some(ref multisample),
+                    ..
+                } => multisample.framebuffer_object,
                 Win32Objects::Texture { gl_framebuffer, .. } => gl_framebuffer,
                 Win32Objects::Widget { .. } => 0,
             },
+            access: surface.access,
+            format: surface.format,
+            purgeability: surface.purge_state.purgeability(),
+            colorspace: surface.colorspace,
+            color_bits: surface.color_bits,
+            preserves_contents: match surface.win32_objects {
+                Win32Objects::Texture { .. } => true,
+                Win32Objects::Widget { .. } => false,
+            },
+            texture_target: match surface.win32_objects {
+                Win32Objects::Texture { .. } => SURFACE_GL_TEXTURE_TARGET,
+                Win32Objects::Widget { .. } => gl::NONE,
+            },
+            stride: None,
+            native_format: match surface.win32_objects {
+                Win32Objects::Texture {
+                    ref d3d11_texture, ..
+                } => unsafe {
+                    let mut desc: D3D11_TEXTURE2D_DESC = mem::zeroed();
+                    d3d11_texture.GetDesc(&mut desc);
+                    NativeFormat::Dxgi(desc.Format)
+                },
+                Win32Objects::Widget { .. } => NativeFormat::Unknown,
+            },
         }
     }
 
+    /// Returns the color profile of the output that a widget surface is displayed on.
+    ///
+    /// This is the ICC profile that Windows color management assigns to the monitor.
+    pub fn widget_color_profile(&self, surface: &Surface) -> ColorProfile {
+        match surface.win32_objects {
+            Win32Objects::Widget { window_handle } => color::window_color_profile(window_handle),
+            Win32Objects::Texture { .. } => ColorProfile::AssumedSrgb,
+        }
+    }
+
+    /// Returns true if a widget surface is on a monitor driven by a different GPU than this
+    /// device.
+    ///
+    /// Windows copies such frames between the GPUs when presenting. Returns false for generic
+    /// surfaces and if no DXGI adapter drives the monitor.
+    pub fn presenting_cross_adapter(&self, surface: &Surface) -> bool {
+        let window_handle = match surface.win32_objects {
+            Win32Objects::Widget { window_handle } => window_handle,
+            Win32Objects::Texture { .. } => return false,
+        };
+        match identity::presenting_dxgi_adapter(window_handle) {
+            Some(dxgi_adapter) => !identities_match(
+                &identity::dxgi_adapter_identity(&dxgi_adapter),
+                &self.identity(),
+            ),
+            None => false,
+        }
+    }
+
+    /// Returns the timestamps of the most recent presentations of a widget surface, oldest first.
+    ///
+    /// `compositor_ack` is when `SwapBuffers()` returned. `displayed` is estimated from the
+    /// Desktop Window Manager's timing, as `query_presentation_time()` does, once the frame has
+    /// been shown.
+    ///
+    /// Returns an empty list for generic surfaces and for widget surfaces that haven't been
+    /// presented yet.
+    #[inline]
+    pub fn presentation_timestamps(&self, surface: &Surface) -> Vec<PresentRecord> {
+        presentation_time::records_with_display_times(
+            &surface.present_history,
+            timing::estimate_actual_present,
+        )
+    }
+
+    /// Returns when the window system presented the most recent frame of a widget surface that
+    /// is known to have reached the display, or the most recent frame if none is yet.
+    ///
+    /// `actual_present` is estimated from the Desktop Window Manager's timing, as the first vblank
+    /// after the frame's `SwapBuffers()`, once that vblank has passed. The GPU's finish isn't
+    /// reported on this platform.
+    ///
+    /// Returns `Error::NoWidgetAttached` for generic surfaces, and `Error::NotPresented` if
+    /// nothing has been presented on the surface.
+    pub fn query_presentation_time(
+        &self,
+        surface: &Surface,
+    ) -> Result<PresentationStatistics, Error> {
+        if let Win32Objects::Texture { .. } = surface.win32_objects {
+            return Err(Error::NoWidgetAttached);
+        }
+        presentation_time::latest_statistics(
+            &surface.present_history,
+            timing::estimate_actual_present,
+        )
+    }
+
     /// Returns the OpenGL texture object containing the contents of this surface.
     ///
     /// It is only legal to read from, not write to, this texture object.
@@ -639,9 +1467,27 @@ impl Device {
     pub fn surface_texture_object(&self, surface_texture: &SurfaceTexture) -> GLuint {
         surface_texture.gl_texture
     }
+
+    /// Returns the sized internal format of the OpenGL texture of this surface texture, such as
+    /// `GL_RGBA8` or `GL_RGBA16F`.
+    ///
+    /// This is the `SurfaceInfo::format` of the wrapped surface, which tells shaders whether they
+    /// sample normalized or floating-point values.
+    #[inline]
+    pub fn surface_texture_format(&self, surface_texture: &SurfaceTexture) -> GLenum {
+        surface_texture.surface.format
+    }
 }
 
 impl Surface {
+    /// Returns the ID of the context that this surface was created with.
+    ///
+    /// A surface can only be bound to the context that it was created with.
+    #[inline]
+    pub fn context_id(&self) -> ContextID {
+        self.context_id
+    }
+
     pub(crate) fn id(&self) -> SurfaceID {
         match self.win32_objects {
             Win32Objects::Texture {
@@ -656,3 +1502,38 @@ impl Surface {
 pub struct SurfaceDataGuard<'a> {
     phantom: PhantomData<&'a ()>,
 }
+
+// Sets the swap interval of a window with `WGL_EXT_swap_control`, with `glrc` current on it.
+unsafe fn set_window_swap_interval(
+    window_handle: HWND,
+    glrc: HGLRC,
+    mut interval: i32,
+) -> Result<i32, Error> {
+    let swap_interval = match WGL_EXTENSION_FUNCTIONS.SwapIntervalEXT {
+        Some(swap_interval) => swap_interval,
+        None => return Err(Error::UnsupportedOnThisPlatform),
+    };
+    if interval < 0 && !supports_swap_control_tear() {
+        interval = interval.saturating_abs();
+    }
+
+    // The swap interval applies to the drawable that is current when it's set. The DC guard is
+    // declared first so that the previous context is restored before the window's DC is
+    // released.
+    let dc_guard = DCGuard::new(winuser::GetDC(window_handle), Some(window_handle));
+    let _guard = CurrentContextGuard::new();
+    if wingdi::wglMakeCurrent(dc_guard.dc, glrc) == FALSE {
+        return Err(Error::MakeCurrentFailed(WindowingApiError::Failed));
+    }
+    if swap_interval(interval) == FALSE {
+        return Err(Error::UnsupportedOnThisPlatform);
+    }
+    Ok(interval)
+}
+
+fn supports_swap_control_tear() -> bool {
+    WGL_EXTENSION_FUNCTIONS
+        .extensions
+        .iter()
+        .any(|extension| extension == "WGL_EXT_swap_control_tear")
+}
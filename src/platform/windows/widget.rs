@@ -0,0 +1,64 @@
+// surfman/surfman/src/platform/windows/widget.rs
+//
+//! Native widget queries shared by the Windows backends.
+
+use euclid::default::Size2D;
+use std::mem;
+use std::ptr;
+use winapi::shared::minwindef::{FALSE, UINT};
+use winapi::shared::ntdef::LPCSTR;
+use winapi::shared::windef::HWND;
+use winapi::um::{libloaderapi, winuser};
+
+// The DPI of a display at a scale factor of 1.
+const DEFAULT_DPI: f32 = 96.0;
+
+lazy_static! {
+    // `GetDpiForWindow()`, which only Windows 10 version 1607 and later have.
+    static ref GET_DPI_FOR_WINDOW: Option<unsafe extern "system" fn(HWND) -> UINT> = unsafe {
+        let user32 = libloaderapi::GetModuleHandleA(&b"user32.dll\0"[0] as *const u8 as LPCSTR);
+        let symbol = if user32.is_null() {
+            ptr::null_mut()
+        } else {
+            libloaderapi::GetProcAddress(user32, &b"GetDpiForWindow\0"[0] as *const u8 as LPCSTR)
+        };
+        if symbol.is_null() {
+            None
+        } else {
+            Some(mem::transmute(symbol))
+        }
+    };
+}
+
+// Returns the size of the client area of a window, or `None` if the handle isn't a window.
+pub(crate) fn window_client_size(window_handle: HWND) -> Option<Size2D<i32>> {
+    unsafe {
+        let mut client_rect = mem::zeroed();
+        if winuser::GetClientRect(window_handle, &mut client_rect) == FALSE {
+            return None;
+        }
+        Some(Size2D::new(
+            client_rect.right - client_rect.left,
+            client_rect.bottom - client_rect.top,
+        ))
+    }
+}
+
+// Returns the real size of a window if it differs from the size that the caller gave for it.
+pub(crate) fn adjusted_window_size(window_handle: HWND, size: Size2D<i32>) -> Option<Size2D<i32>> {
+    window_client_size(window_handle)
+        .and_then(|actual_size| crate::surface::adjusted_widget_size(size, actual_size))
+}
+
+// Returns the scale factor of the display that a window is on, or `None` if Windows is too old to
+// say or the handle isn't a window.
+//
+// Unless the process is aware of the DPI of each monitor, Windows scales its windows itself and
+// reports the same scale for every display.
+pub(crate) fn window_scale(window_handle: HWND) -> Option<f32> {
+    let get_dpi_for_window = (*GET_DPI_FOR_WINDOW)?;
+    match unsafe { get_dpi_for_window(window_handle) } {
+        0 => None,
+        dpi => Some(dpi as f32 / DEFAULT_DPI),
+    }
+}
@@ -0,0 +1,217 @@
+// surfman/surfman/src/presentation_time.rs
+//
+//! Measuring when presented frames reach the display.
+//!
+//! `Device::presentation_timestamps()` only times what the CPU sees of a present: the call, and
+//! the return of the swap. `Device::query_presentation_time()` reports what the window system
+//! says happened to a frame afterward, which is what latency has to be measured against:
+//!
+//! * Where the EGL display supports `EGL_ANDROID_get_frame_timestamps`, as on Android, EGL reports
+//!   when each frame was requested to be shown, when the GPU finished rendering it, and when the
+//!   display showed it.
+//!
+//! * On Wayland, compositors that offer the `wp_presentation` protocol report when each frame was
+//!   shown.
+//!
+//! * Otherwise, on Windows, when each frame was shown is estimated from the Desktop Window
+//!   Manager's vblank timing, as the first vblank after the frame's swap.
+//!
+//! * On macOS, each frame is taken to be shown at the display link's next vertical blank after
+//!   its Core Animation transaction is committed.
+//!
+//! Elsewhere, such as on X11, only when each frame was requested to be shown is reported.
+//!
+//! All times are converted to `Instant`s, whatever clock the platform reports them in, so they
+//! can be compared with the `PresentRecord`s of `Device::presentation_timestamps()`, which fill
+//! in `displayed` from the same reports.
+
+use crate::surface::PresentHistory;
+use crate::{Error, PresentRecord};
+
+use std::convert::TryFrom;
+#[cfg(windows)]
+use std::mem;
+use std::time::{Duration, Instant};
+#[cfg(windows)]
+use winapi::um::profileapi::{QueryPerformanceCounter, QueryPerformanceFrequency};
+
+/// When the window system presented a frame of a widget surface, as far as it reports.
+///
+/// Subtract `requested_present` from `actual_present` to measure the latency that presentation
+/// adds. Each time is `None` where the platform doesn't report it, or hasn't yet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PresentationStatistics {
+    /// The `frame_id` of the frame's `PresentRecord`.
+    pub frame_id: u64,
+    /// When the frame was asked to be shown.
+    ///
+    /// This is when `present_surface()` was called, unless the platform reports a time of its own.
+    pub requested_present: Option<Instant>,
+    /// When the frame reached the display.
+    pub actual_present: Option<Instant>,
+    /// When the GPU finished rendering the frame.
+    pub gpu_finish: Option<Instant>,
+}
+
+// Implements `Device::query_presentation_time()` for a widget surface with the given history.
+//
+// `update` fills in what the platform reports about a presented frame. This returns the
+// statistics of the most recent frame that is known to have reached the display, or of the most
+// recent frame if none is.
+pub(crate) fn latest_statistics<F>(
+    history: &PresentHistory,
+    mut update: F,
+) -> Result<PresentationStatistics, Error>
+where
+    F: FnMut(&PresentRecord, &mut PresentationStatistics),
+{
+    let mut latest = None;
+    for record in history.newest_first() {
+        let mut statistics = PresentationStatistics {
+            frame_id: record.frame_id,
+            requested_present: Some(record.submit),
+            actual_present: record.displayed,
+            gpu_finish: None,
+        };
+        update(record, &mut statistics);
+        if statistics.actual_present.is_some() {
+            return Ok(statistics);
+        }
+        latest.get_or_insert(statistics);
+    }
+    latest.ok_or(Error::NotPresented)
+}
+
+// Implements `Device::presentation_timestamps()` for a widget surface with the given history,
+// filling in when each frame reached the display from what `update` reports, as
+// `latest_statistics()` does.
+pub(crate) fn records_with_display_times<F>(
+    history: &PresentHistory,
+    mut update: F,
+) -> Vec<PresentRecord>
+where
+    F: FnMut(&PresentRecord, &mut PresentationStatistics),
+{
+    let mut records = history.records();
+    for record in &mut records {
+        if record.displayed.is_some() {
+            continue;
+        }
+        let mut statistics = PresentationStatistics {
+            frame_id: record.frame_id,
+            requested_present: Some(record.submit),
+            actual_present: None,
+            gpu_finish: None,
+        };
+        update(record, &mut statistics);
+        record.displayed = statistics.actual_present;
+    }
+    records
+}
+
+// Converts a time on a clock that counts `ticks_per_second`, which read `now_ticks` at `now`,
+// to an `Instant`. Returns `None` if the time can't be represented.
+pub(crate) fn instant_at(
+    now: Instant,
+    now_ticks: u64,
+    ticks: u64,
+    ticks_per_second: u64,
+) -> Option<Instant> {
+    if ticks_per_second == 0 {
+        return None;
+    }
+    let duration = |ticks: u64| {
+        let nanos = ticks as u128 * 1_000_000_000 / ticks_per_second as u128;
+        Duration::from_nanos(nanos.min(u64::MAX as u128) as u64)
+    };
+    if ticks <= now_ticks {
+        now.checked_sub(duration(now_ticks - ticks))
+    } else {
+        now.checked_add(duration(ticks - now_ticks))
+    }
+}
+
+// Converts a time in nanoseconds on the given POSIX clock to an `Instant`.
+#[cfg(unix)]
+pub(crate) fn instant_from_clock(clock_id: libc::clockid_t, nanos: u64) -> Option<Instant> {
+    unsafe {
+        let mut now = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        if libc::clock_gettime(clock_id, &mut now) != 0 {
+            return None;
+        }
+        let now_nanos = (now.tv_sec as u64)
+            .checked_mul(1_000_000_000)?
+            .checked_add(now.tv_nsec as u64)?;
+        instant_at(Instant::now(), now_nanos, nanos, 1_000_000_000)
+    }
+}
+
+// Converts a time in nanoseconds on the system's monotonic clock, which EGL reports frame
+// timestamps in, to an `Instant`.
+#[cfg(unix)]
+#[cfg_attr(
+    not(any(android_backend, generic_egl_platform, ohos_backend)),
+    allow(dead_code)
+)]
+pub(crate) fn instant_from_monotonic_nanos(nanos: u64) -> Option<Instant> {
+    instant_from_clock(libc::CLOCK_MONOTONIC, nanos)
+}
+
+// Converts a time in nanoseconds on the system's monotonic clock, which EGL reports frame
+// timestamps in, to an `Instant`. On Windows, that's the performance counter.
+#[cfg(windows)]
+pub(crate) fn instant_from_monotonic_nanos(nanos: u64) -> Option<Instant> {
+    let (now, now_ticks, ticks_per_second) = performance_counter_now()?;
+    let now_nanos = now_ticks as u128 * 1_000_000_000 / ticks_per_second as u128;
+    instant_at(
+        now,
+        now_nanos.min(u64::MAX as u128) as u64,
+        nanos,
+        1_000_000_000,
+    )
+}
+
+// Converts a reading of the performance counter, which DWM reports vblanks in, to an `Instant`.
+#[cfg(windows)]
+pub(crate) fn instant_from_performance_counter(ticks: u64) -> Option<Instant> {
+    let (now, now_ticks, ticks_per_second) = performance_counter_now()?;
+    instant_at(now, now_ticks, ticks, ticks_per_second)
+}
+
+// Returns the current `Instant`, the performance counter, and its frequency.
+#[cfg(windows)]
+fn performance_counter_now() -> Option<(Instant, u64, u64)> {
+    unsafe {
+        let (mut ticks, mut ticks_per_second) = (mem::zeroed(), mem::zeroed());
+        if QueryPerformanceFrequency(&mut ticks_per_second) == 0
+            || QueryPerformanceCounter(&mut ticks) == 0
+        {
+            return None;
+        }
+        let ticks = *ticks.QuadPart() as u64;
+        let ticks_per_second = *ticks_per_second.QuadPart() as u64;
+        if ticks_per_second == 0 {
+            return None;
+        }
+        Some((Instant::now(), ticks, ticks_per_second))
+    }
+}
+
+// Returns the first vblank at or after `swapped`, given the time of the last vblank and the
+// refresh period, or `None` if that vblank hasn't happened yet.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub(crate) fn first_vblank_after(
+    swapped: Instant,
+    last_vblank: Instant,
+    refresh_period: Duration,
+) -> Option<Instant> {
+    if swapped > last_vblank || refresh_period.is_zero() {
+        return None;
+    }
+    let periods = (last_vblank - swapped).as_nanos() / refresh_period.as_nanos();
+    let periods = u32::try_from(periods).ok()?;
+    last_vblank.checked_sub(refresh_period.checked_mul(periods)?)
+}
@@ -0,0 +1,492 @@
+// surfman/surfman/src/producer_abi.rs
+//
+//! A versioned C ABI for swap chain producers written in other languages.
+//!
+//! Producers in C, C++, Zig and the like join a swap chain through a `ProducerChain`, which the
+//! Rust side creates from a device, a context and the swap chain's settings. The foreign producer
+//! gets an opaque `SurfmanChain` handle for it, and drives it with two entry points:
+//!
+//! * `surfman_chain_acquire()` fills in a `SurfmanFrameDesc` that describes the back buffer as a
+//!   native image handle, with its size, format, stride and layout. The producer imports the
+//!   handle into its own GL or Vulkan and renders into it there.
+//!
+//! * `surfman_chain_submit()` hands the frame back with a sync file that signals when the
+//!   producer's rendering has finished, and the rectangles that changed since the last frame.
+//!   The chain waits for the fence and swaps buffers. The pixels never pass through the CPU.
+//!
+//! The C declarations are in `include/surfman_producer.h`. Producers that don't link surfman,
+//! such as plugins loaded at run time, get the entry points from `surfman_producer_api()`, which
+//! also negotiates the ABI version: it returns null for versions that this build doesn't speak.
+//! The producer then names the handle types that it can import in each `SurfmanFrameDesc`, and
+//! the chain picks one of them. Every entry point returns one of the `SURFMAN_*` status codes,
+//! and never unwinds.
+//!
+//! Version 1 of the ABI hands out dma-bufs, which `Device::export_surface_dma_buf()` exports.
+//! The handle types for `IOSurface`s and Direct3D shared handles are reserved for later versions.
+//!
+//! This module is only available on Linux, where dma-bufs and sync files are.
+
+use crate::chains::{PreserveBuffer, SwapChain};
+use crate::device::Device as DeviceAPI;
+use crate::frame_export;
+use crate::{DmaBuf, Error, SurfaceAccess};
+use euclid::default::Rect;
+use log::debug;
+use sparkle::gl::Gl;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::raw::c_int;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::rc::Rc;
+use std::slice;
+
+/// The version of the producer ABI that this build of surfman speaks.
+pub const SURFMAN_PRODUCER_ABI_VERSION: u32 = 1;
+
+/// The call succeeded.
+pub const SURFMAN_OK: i32 = 0;
+/// The caller asked for a version of the ABI that this build doesn't speak.
+pub const SURFMAN_ERROR_VERSION_MISMATCH: i32 = 1;
+/// A pointer was null, or a damage rectangle had a negative size.
+pub const SURFMAN_ERROR_INVALID_ARGUMENT: i32 = 2;
+/// `surfman_chain_acquire()` was called while a frame was already acquired.
+pub const SURFMAN_ERROR_ALREADY_ACQUIRED: i32 = 3;
+/// `surfman_chain_submit()` was called without an acquired frame.
+pub const SURFMAN_ERROR_NOT_ACQUIRED: i32 = 4;
+/// The chain requires a fence, and the submission had none.
+pub const SURFMAN_ERROR_FENCE_REQUIRED: i32 = 5;
+/// A damage rectangle reached outside the frame.
+pub const SURFMAN_ERROR_DAMAGE_OUT_OF_BOUNDS: i32 = 6;
+/// Waiting for the fence failed.
+pub const SURFMAN_ERROR_FENCE_FAILED: i32 = 7;
+/// The chain can't hand out its frames as any of the handle types that the producer accepts.
+pub const SURFMAN_ERROR_UNSUPPORTED_HANDLE_TYPE: i32 = 8;
+/// Surfman failed to export or swap the frame.
+pub const SURFMAN_ERROR_FAILED: i32 = 9;
+
+/// A dma-buf file descriptor, which the producer imports with `EGL_EXT_image_dma_buf_import` or
+/// `VK_EXT_external_memory_dma_buf`.
+pub const SURFMAN_IMAGE_HANDLE_DMA_BUF: u32 = 1 << 0;
+/// An `IOSurface` ID. Reserved for a later version of the ABI.
+pub const SURFMAN_IMAGE_HANDLE_IOSURFACE: u32 = 1 << 1;
+/// A Direct3D shared handle. Reserved for a later version of the ABI.
+pub const SURFMAN_IMAGE_HANDLE_D3D_SHARED: u32 = 1 << 2;
+
+/// The opaque handle that foreign producers know a `ProducerChain` by.
+#[repr(C)]
+pub struct SurfmanChain {
+    _private: [u8; 0],
+}
+
+/// Describes an acquired frame. The producer sets `abi_version` and `handle_types`;
+/// `surfman_chain_acquire()` fills in the rest.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SurfmanFrameDesc {
+    /// The version of the ABI that the producer was built against.
+    pub abi_version: u32,
+    /// The `SURFMAN_IMAGE_HANDLE_*` types that the producer can import, or'd together.
+    pub handle_types: u32,
+    /// What `handle` is: the one of `handle_types` that the chain picked.
+    pub handle_type: u32,
+    /// The width of the frame in pixels.
+    pub width: i32,
+    /// The height of the frame in pixels.
+    pub height: i32,
+    /// The DRM fourcc of the pixels' format.
+    pub format: u32,
+    /// The native image handle. A dma-buf file descriptor stays owned by the chain until the
+    /// frame is submitted, so producers that keep it, or hand it to an API that takes ownership
+    /// of it like Vulkan does, must duplicate it first.
+    pub handle: i64,
+    /// The DRM format modifier that describes how the pixels are laid out in memory.
+    pub modifier: u64,
+    /// Identifies the image that the frame is in. Frames with the same ID are the same image,
+    /// so producers can keep what they imported for it.
+    pub buffer_id: u64,
+    /// Counts the frames that the chain has handed out, starting at 0.
+    pub frame_id: u64,
+    /// The offset of the frame's first pixel from the start of the image, in bytes.
+    pub offset: u32,
+    /// The number of bytes between the start of one row and the start of the next.
+    pub stride: u32,
+}
+
+/// A damaged rectangle of a frame, measured in pixels from its first pixel in memory.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SurfmanRect {
+    /// The left edge.
+    pub x: i32,
+    /// The edge nearest the first row.
+    pub y: i32,
+    /// The width.
+    pub width: i32,
+    /// The height.
+    pub height: i32,
+}
+
+/// The entry points of a version of the producer ABI, for producers that don't link surfman.
+#[repr(C)]
+pub struct SurfmanProducerApi {
+    /// The version of the ABI that these entry points speak.
+    pub abi_version: u32,
+    /// `surfman_chain_acquire()`.
+    pub acquire: unsafe extern "C" fn(*mut SurfmanChain, *mut SurfmanFrameDesc) -> i32,
+    /// `surfman_chain_submit()`.
+    pub submit: unsafe extern "C" fn(*mut SurfmanChain, c_int, *const SurfmanRect, usize) -> i32,
+}
+
+static PRODUCER_API_V1: SurfmanProducerApi = SurfmanProducerApi {
+    abi_version: 1,
+    acquire: surfman_chain_acquire,
+    submit: surfman_chain_submit,
+};
+
+/// Whether a `ProducerChain` makes producers pass a fence with each frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FencePolicy {
+    /// Frames may be submitted without a fence, if the producer has finished rendering them.
+    Optional,
+    /// Frames submitted without a fence fail with `SURFMAN_ERROR_FENCE_REQUIRED`.
+    Required,
+}
+
+/// A swap chain that a foreign producer renders into through the C ABI.
+///
+/// The chain owns the device and context that it was created with, and the context's surface is
+/// the swap chain's back buffer, which must not be multisampled. Consumers take frames from
+/// `swap_chain()` as usual. The foreign producer must only call into the chain from one thread at
+/// a time, and the context is made current on that thread.
+pub struct ProducerChain<Device: DeviceAPI + 'static> {
+    state: *mut ChainState<Device>,
+    handle: Box<ChainHandle>,
+}
+
+// What a `SurfmanChain` pointer points to. It and the chain state are on the heap, so the
+// pointers stay valid while the `ProducerChain` moves.
+struct ChainHandle {
+    chain: *mut dyn ForeignChain,
+}
+
+trait ForeignChain {
+    fn acquire(&mut self, frame: &mut SurfmanFrameDesc) -> i32;
+    fn submit(&mut self, fence: Option<OwnedFd>, damage: &[SurfmanRect]) -> i32;
+}
+
+struct ChainState<Device: DeviceAPI> {
+    device: Device,
+    context: Device::Context,
+    gl: Rc<Gl>,
+    swap_chain: SwapChain<Device>,
+    fence_policy: FencePolicy,
+    // The acquired frame's dma-buf, which is closed once the frame is submitted.
+    acquired: Option<DmaBuf>,
+    damage: Vec<Rect<i32>>,
+    next_frame_id: u64,
+}
+
+impl<Device: DeviceAPI + 'static> ProducerChain<Device> {
+    /// Creates a chain whose back buffer is the surface bound to `context`, as with an attached
+    /// `SwapChain`.
+    pub fn new(
+        mut device: Device,
+        mut context: Device::Context,
+        fence_policy: FencePolicy,
+    ) -> Result<ProducerChain<Device>, (Error, Device, Device::Context)> {
+        let swap_chain =
+            match SwapChain::create_attached(&mut device, &mut context, SurfaceAccess::GPUOnly) {
+                Ok(swap_chain) => swap_chain,
+                Err(err) => return Err((err, device, context)),
+            };
+        if let Err(err) = device.make_context_current(&context) {
+            return Err((err, device, context));
+        }
+        let gl = frame_export::load_gl(&device, &context);
+
+        let state = Box::into_raw(Box::new(ChainState {
+            device,
+            context,
+            gl,
+            swap_chain,
+            fence_policy,
+            acquired: None,
+            damage: vec![],
+            next_frame_id: 0,
+        }));
+        let handle = Box::new(ChainHandle { chain: state });
+        Ok(ProducerChain { state, handle })
+    }
+
+    /// Returns the handle to pass to the foreign producer. It's valid until the chain is
+    /// destroyed.
+    pub fn as_raw(&mut self) -> *mut SurfmanChain {
+        &mut *self.handle as *mut ChainHandle as *mut SurfmanChain
+    }
+
+    /// Returns the device that the chain renders with.
+    pub fn device(&self) -> &Device {
+        unsafe { &(*self.state).device }
+    }
+
+    /// Returns the context that the chain renders with. Its surface is the back buffer.
+    pub fn context(&self) -> &Device::Context {
+        unsafe { &(*self.state).context }
+    }
+
+    /// Returns the swap chain that frames are submitted to, for consumers to take them from.
+    pub fn swap_chain(&self) -> &SwapChain<Device> {
+        unsafe { &(*self.state).swap_chain }
+    }
+
+    /// Returns the rectangles of the last submitted frame that changed since the frame before,
+    /// measured as in `SurfmanRect`. Empty rectangles are left out, and a producer that reported
+    /// no damage changed the whole frame.
+    pub fn damage(&self) -> &[Rect<i32>] {
+        unsafe { &(*self.state).damage }
+    }
+
+    /// Destroys the chain, and returns its device and context. The swap chain is destroyed too.
+    pub fn destroy(mut self) -> Result<(Device, Device::Context), Error> {
+        let state = mem::replace(&mut self.state, ptr::null_mut());
+        let mut state = unsafe { *Box::from_raw(state) };
+        state.acquired = None;
+        state
+            .swap_chain
+            .destroy(&mut state.device, &mut state.context)?;
+        Ok((state.device, state.context))
+    }
+}
+
+impl<Device: DeviceAPI + 'static> Drop for ProducerChain<Device> {
+    // Frees the chain state, unless `destroy` already took it.
+    fn drop(&mut self) {
+        if !self.state.is_null() {
+            drop(unsafe { Box::from_raw(self.state) });
+        }
+    }
+}
+
+impl<Device: DeviceAPI> ForeignChain for ChainState<Device> {
+    fn acquire(&mut self, frame: &mut SurfmanFrameDesc) -> i32 {
+        if frame.abi_version != SURFMAN_PRODUCER_ABI_VERSION {
+            return SURFMAN_ERROR_VERSION_MISMATCH;
+        }
+        if self.acquired.is_some() {
+            return SURFMAN_ERROR_ALREADY_ACQUIRED;
+        }
+        if frame.handle_types & SURFMAN_IMAGE_HANDLE_DMA_BUF == 0 {
+            return SURFMAN_ERROR_UNSUPPORTED_HANDLE_TYPE;
+        }
+
+        let dma_buf = match self.export_back_buffer() {
+            Ok(dma_buf) => dma_buf,
+            Err(Error::RequiredExtensionUnavailable)
+            | Err(Error::UnsupportedOnThisPlatform)
+            | Err(Error::UnsupportedSurfaceFormat) => return SURFMAN_ERROR_UNSUPPORTED_HANDLE_TYPE,
+            Err(err) => {
+                debug!("Failed to export a frame for a foreign producer: {:?}", err);
+                return SURFMAN_ERROR_FAILED;
+            }
+        };
+        // A dma-buf is one file however many times it's exported, so its inode identifies it.
+        let buffer_id = unsafe {
+            let mut stat: libc::stat = mem::zeroed();
+            match libc::fstat(dma_buf.fd.as_raw_fd(), &mut stat) {
+                0 => stat.st_ino,
+                _ => return SURFMAN_ERROR_FAILED,
+            }
+        };
+
+        *frame = SurfmanFrameDesc {
+            abi_version: SURFMAN_PRODUCER_ABI_VERSION,
+            handle_types: frame.handle_types,
+            handle_type: SURFMAN_IMAGE_HANDLE_DMA_BUF,
+            width: dma_buf.size.width,
+            height: dma_buf.size.height,
+            format: dma_buf.fourcc,
+            handle: dma_buf.fd.as_raw_fd() as i64,
+            modifier: dma_buf.modifier,
+            buffer_id,
+            frame_id: self.next_frame_id,
+            offset: dma_buf.offset,
+            stride: dma_buf.stride,
+        };
+        self.next_frame_id += 1;
+        self.acquired = Some(dma_buf);
+        SURFMAN_OK
+    }
+
+    fn submit(&mut self, fence: Option<OwnedFd>, damage: &[SurfmanRect]) -> i32 {
+        let size = match self.acquired {
+            Some(ref dma_buf) => dma_buf.size,
+            None => return SURFMAN_ERROR_NOT_ACQUIRED,
+        };
+        if fence.is_none() && self.fence_policy == FencePolicy::Required {
+            return SURFMAN_ERROR_FENCE_REQUIRED;
+        }
+        let bounds = Rect::from_size(size);
+        let mut rects = Vec::with_capacity(damage.len());
+        for rect in damage {
+            if rect.width < 0 || rect.height < 0 {
+                return SURFMAN_ERROR_INVALID_ARGUMENT;
+            }
+            let rect = Rect::new(
+                euclid::point2(rect.x, rect.y),
+                euclid::size2(rect.width, rect.height),
+            );
+            if !bounds.contains_rect(&rect) {
+                return SURFMAN_ERROR_DAMAGE_OUT_OF_BOUNDS;
+            }
+            if !rect.is_empty() {
+                rects.push(rect);
+            }
+        }
+        // No damage rectangles means that the whole frame changed.
+        if damage.is_empty() {
+            rects.push(bounds);
+        }
+
+        if let Some(fence) = fence {
+            if let Err(err) = wait_sync_fd(&fence) {
+                debug!("Failed to wait for a foreign producer's fence: {:?}", err);
+                return SURFMAN_ERROR_FENCE_FAILED;
+            }
+        }
+        self.acquired = None;
+        self.damage = rects;
+
+        match self.swap_buffers() {
+            Ok(()) => SURFMAN_OK,
+            Err(err) => {
+                debug!("Failed to present a foreign producer's frame: {:?}", err);
+                SURFMAN_ERROR_FAILED
+            }
+        }
+    }
+}
+
+impl<Device: DeviceAPI> ChainState<Device> {
+    // Exports the back buffer, which is bound to the context, once surfman's own rendering to it
+    // has finished.
+    fn export_back_buffer(&mut self) -> Result<DmaBuf, Error> {
+        self.device.make_context_current(&self.context)?;
+        self.gl.finish();
+        let surface = match self.device.unbind_surface_from_context(&mut self.context)? {
+            Some(surface) => surface,
+            None => return Err(Error::Failed),
+        };
+        let result = self.device.export_surface_dma_buf(&surface);
+        if let Err((err, mut surface)) = self
+            .device
+            .bind_surface_to_context(&mut self.context, surface)
+        {
+            drop(self.device.destroy_surface(&mut self.context, &mut surface));
+            return Err(err);
+        }
+        result
+    }
+
+    // Swaps buffers. The new back buffer starts out as a copy of the frame, on the GPU, so the
+    // producer only needs to redraw what changes.
+    fn swap_buffers(&mut self) -> Result<(), Error> {
+        self.device.make_context_current(&self.context)?;
+        self.swap_chain.swap_buffers(
+            &mut self.device,
+            &mut self.context,
+            PreserveBuffer::Yes(&self.gl),
+        )
+    }
+}
+
+// Blocks until a sync file signals. Sync files become readable once they have signaled.
+fn wait_sync_fd(fence: &OwnedFd) -> Result<(), Error> {
+    let mut poll_fd = libc::pollfd {
+        fd: fence.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    loop {
+        match unsafe { libc::poll(&mut poll_fd, 1, -1) } {
+            1 if poll_fd.revents & libc::POLLIN != 0 => return Ok(()),
+            -1 if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted => {}
+            _ => return Err(Error::Failed),
+        }
+    }
+}
+
+// Runs an entry point, turning a panic into `SURFMAN_ERROR_FAILED` rather than unwinding into
+// foreign code.
+fn catch_panic<F: FnOnce() -> i32>(f: F) -> i32 {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(SURFMAN_ERROR_FAILED)
+}
+
+/// Returns the version of the producer ABI that this build of surfman speaks.
+#[no_mangle]
+pub extern "C" fn surfman_producer_abi_version() -> u32 {
+    SURFMAN_PRODUCER_ABI_VERSION
+}
+
+/// Returns the entry points of version `abi_version` of the producer ABI, or null if this build
+/// doesn't speak that version.
+#[no_mangle]
+pub extern "C" fn surfman_producer_api(abi_version: u32) -> *const SurfmanProducerApi {
+    match abi_version {
+        1 => &PRODUCER_API_V1,
+        _ => ptr::null(),
+    }
+}
+
+/// Acquires the back buffer of a chain, and describes it in `out_frame`, whose `abi_version` and
+/// `handle_types` the caller must set.
+///
+/// # Safety
+///
+/// `chain` must come from `ProducerChain::as_raw()`, and the chain must still exist. `out_frame`
+/// must be null or point to a `SurfmanFrameDesc`.
+#[no_mangle]
+pub unsafe extern "C" fn surfman_chain_acquire(
+    chain: *mut SurfmanChain,
+    out_frame: *mut SurfmanFrameDesc,
+) -> i32 {
+    if chain.is_null() || out_frame.is_null() {
+        return SURFMAN_ERROR_INVALID_ARGUMENT;
+    }
+    let chain = &mut *(*(chain as *mut ChainHandle)).chain;
+    catch_panic(|| chain.acquire(&mut *out_frame))
+}
+
+/// Submits the acquired frame of a chain.
+///
+/// `fence_fd` is a sync file that signals once the producer has finished rendering the frame,
+/// or -1 for none. The chain takes ownership of it, whether or not the call succeeds. `damage`
+/// points to `n_damage` rectangles that changed since the last frame; if `n_damage` is 0, the
+/// whole frame changed. A submission that fails validation leaves the frame acquired.
+///
+/// # Safety
+///
+/// `chain` must come from `ProducerChain::as_raw()`, and the chain must still exist. `damage`
+/// must point to `n_damage` rectangles, and may be null if `n_damage` is 0. `fence_fd` must be
+/// -1 or an open file descriptor that nothing else owns.
+#[no_mangle]
+pub unsafe extern "C" fn surfman_chain_submit(
+    chain: *mut SurfmanChain,
+    fence_fd: c_int,
+    damage: *const SurfmanRect,
+    n_damage: usize,
+) -> i32 {
+    let fence = match fence_fd {
+        fence_fd if fence_fd >= 0 => Some(OwnedFd::from_raw_fd(fence_fd)),
+        _ => None,
+    };
+    if chain.is_null() || (damage.is_null() && n_damage != 0) {
+        return SURFMAN_ERROR_INVALID_ARGUMENT;
+    }
+    let damage = match n_damage {
+        0 => &[],
+        _ => slice::from_raw_parts(damage, n_damage),
+    };
+    let chain = &mut *(*(chain as *mut ChainHandle)).chain;
+    catch_panic(|| chain.submit(fence, damage))
+}
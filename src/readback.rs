@@ -0,0 +1,336 @@
+// surfman/surfman/src/readback.rs
+//
+//! Reading the pixels of generic surfaces back into memory.
+//!
+//! `Device::read_surface_data` reads a rectangle of a generic surface into a buffer that the
+//! caller provides, as tightly packed 8-bit RGBA with the top row first, so that headless
+//! applications and tests can get at their pixels without binding framebuffers themselves.
+//! Rectangles are measured from the top left of the surface, as `Device::clear_surface_region`
+//! measures them.
+//!
+//! The surface's framebuffer is read with `glReadPixels()`, through a temporary single-sampled
+//! renderbuffer if the context is multisampled. Every binding and pixel store parameter that the
+//! read touches is put back afterward, so callers that use OpenGL on the same context don't see
+//! any of it.
+
+use crate::device::Device as DeviceAPI;
+use crate::gl;
+use crate::gl::types::{GLenum, GLint, GLuint};
+use crate::gl_utils;
+use crate::{ContextID, Error, GLVersion, Gl, SurfaceInfo};
+use euclid::default::{Point2D, Rect, Size2D};
+use std::os::raw::c_void;
+
+// The number of bytes in each pixel that `read_surface_data()` writes.
+const BYTES_PER_PIXEL: usize = 4;
+
+// The state that reading changes, saved so that it can be put back.
+struct SavedState {
+    framebuffer: GLint,
+    // The separate read framebuffer binding, and the pixel store parameters and pixel pack buffer
+    // binding, of OpenGL 3.0 and OpenGL ES 3.0.
+    gl3_state: Option<Gl3State>,
+    pack_alignment: GLint,
+}
+
+struct Gl3State {
+    read_framebuffer: GLint,
+    pack_buffer: GLint,
+    pack_row_length: GLint,
+    pack_skip_rows: GLint,
+    pack_skip_pixels: GLint,
+}
+
+// Implements `Device::read_surface_data` for every backend.
+pub(crate) fn read_surface_data<Device: DeviceAPI>(
+    device: &Device,
+    context: &Device::Context,
+    surface: &Device::Surface,
+    rect: Rect<i32>,
+    out: &mut [u8],
+) -> Result<(), Error> {
+    let info = device.surface_info(surface);
+    check_surface_rect(&info, device.context_id(context), rect)?;
+    let required = rect.size.width as usize * rect.size.height as usize * BYTES_PER_PIXEL;
+    if out.len() < required {
+        return Err(Error::BufferTooSmall {
+            required,
+            actual: out.len(),
+        });
+    }
+    if required == 0 {
+        return Ok(());
+    }
+
+    let _guard = device.make_context_current_guarded(context)?;
+    let samples = device
+        .context_descriptor_attributes(&device.context_descriptor(context))
+        .samples;
+    if samples > 0 && !device.capabilities(context)?.blit_framebuffer {
+        return Err(Error::RequiredExtensionUnavailable);
+    }
+
+    let gl = Gl::load_with(device.proc_address_loader(context));
+    let out = &mut out[..required];
+    gl_utils::drain_pre_existing_gl_errors(&gl);
+    unsafe {
+        let gl3 = GLVersion::current(&gl).major >= 3;
+        let saved_state = SavedState::save(&gl, gl3);
+        let result = read(
+            &gl,
+            gl3,
+            info.framebuffer_object,
+            info.format,
+            info.size.height,
+            rect,
+            samples > 0,
+            out,
+        );
+        saved_state.restore(&gl);
+        result?;
+    }
+
+    // OpenGL returns the bottom row first.
+    let stride = rect.size.width as usize * BYTES_PER_PIXEL;
+    let rows = rect.size.height as usize;
+    for row in 0..rows / 2 {
+        let (top, bottom) = out.split_at_mut((rows - row - 1) * stride);
+        top[row * stride..(row + 1) * stride].swap_with_slice(&mut bottom[..stride]);
+    }
+    Ok(())
+}
+
+// Checks that `rect` lies within a generic surface of the context with the given ID.
+pub(crate) fn check_surface_rect(
+    info: &SurfaceInfo,
+    context_id: ContextID,
+    rect: Rect<i32>,
+) -> Result<(), Error> {
+    if info.context_id != context_id {
+        return Err(Error::IncompatibleSurface);
+    }
+    if info.framebuffer_object == 0 {
+        return Err(Error::WidgetAttached);
+    }
+    check_rect(info.size, rect)
+}
+
+// Checks that `rect` lies within an image of the given size.
+pub(crate) fn check_rect(size: Size2D<i32>, rect: Rect<i32>) -> Result<(), Error> {
+    if rect.size.width < 0 || rect.size.height < 0 || !Rect::from_size(size).contains_rect(&rect) {
+        return Err(Error::RectOutOfBounds(rect));
+    }
+    Ok(())
+}
+
+// Reads the whole of the current context's default framebuffer into `out`, bottom row first.
+pub(crate) unsafe fn read_default_framebuffer(
+    gl: &Gl,
+    size: Size2D<i32>,
+    out: &mut [u8],
+) -> Result<(), Error> {
+    gl_utils::drain_pre_existing_gl_errors(gl);
+    let gl3 = GLVersion::current(gl).major >= 3;
+    let saved_state = SavedState::save(gl, gl3);
+    let rect = Rect::from_size(size);
+    // Multisampled default framebuffers are resolved as they're read.
+    let result = read(gl, gl3, 0, gl::RGBA8, size.height, rect, false, out);
+    saved_state.restore(gl);
+    result
+}
+
+/// Reads a rectangle of a generic surface, measured from its top left, into a new image.
+///
+/// This is `Device::read_surface_data()` into a buffer of the right size, and fails as it does.
+#[cfg(feature = "image")]
+pub fn read_surface_data_to_image<Device: DeviceAPI>(
+    device: &Device,
+    context: &Device::Context,
+    surface: &Device::Surface,
+    rect: Rect<i32>,
+) -> Result<image::RgbaImage, Error> {
+    let size = rect.size.max(Size2D::zero());
+    let mut pixels = vec![0; size.width as usize * size.height as usize * BYTES_PER_PIXEL];
+    device.read_surface_data(context, surface, rect, &mut pixels)?;
+    Ok(image::RgbaImage::from_raw(size.width as u32, size.height as u32, pixels).unwrap())
+}
+
+// Reads `rect` of a framebuffer of the given color format and height into `out`, bottom row
+// first, with the state that reading needs already saved.
+#[allow(clippy::too_many_arguments)]
+unsafe fn read(
+    gl: &Gl,
+    gl3: bool,
+    framebuffer_object: GLuint,
+    format: GLenum,
+    height: i32,
+    rect: Rect<i32>,
+    multisampled: bool,
+    out: &mut [u8],
+) -> Result<(), Error> {
+    let read_target = if gl3 {
+        gl::READ_FRAMEBUFFER
+    } else {
+        gl::FRAMEBUFFER
+    };
+    let size = rect.size;
+    let gl_rect = Rect::new(Point2D::new(rect.origin.x, height - rect.max_y()), size);
+    gl.BindFramebuffer(read_target, framebuffer_object);
+    if gl.CheckFramebufferStatus(read_target) != gl::FRAMEBUFFER_COMPLETE {
+        return Err(Error::UnsupportedSurfaceFormat);
+    }
+    if gl3 {
+        gl.BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+        gl.PixelStorei(gl::PACK_ROW_LENGTH, 0);
+        gl.PixelStorei(gl::PACK_SKIP_ROWS, 0);
+        gl.PixelStorei(gl::PACK_SKIP_PIXELS, 0);
+    }
+    gl.PixelStorei(gl::PACK_ALIGNMENT, 4);
+
+    // Multisampled framebuffers can't be read from, so resolve the rectangle into a renderbuffer
+    // of the same format first.
+    let resolved = if multisampled {
+        let resolved = Resolved::new(gl, format, size);
+        gl.BlitFramebuffer(
+            gl_rect.min_x(),
+            gl_rect.min_y(),
+            gl_rect.max_x(),
+            gl_rect.max_y(),
+            0,
+            0,
+            size.width,
+            size.height,
+            gl::COLOR_BUFFER_BIT,
+            gl::NEAREST,
+        );
+        gl.BindFramebuffer(gl::READ_FRAMEBUFFER, resolved.framebuffer);
+        Some(resolved)
+    } else {
+        None
+    };
+    let origin = if resolved.is_some() {
+        Point2D::zero()
+    } else {
+        gl_rect.origin
+    };
+
+    // Floating-point color buffers can only be read as floats on OpenGL ES.
+    if format == gl::RGBA16F {
+        let mut floats = vec![0.0f32; out.len()];
+        read_pixels(gl, origin.x, origin.y, size, gl::FLOAT, floats.as_mut_ptr());
+        for (byte, float) in out.iter_mut().zip(floats) {
+            *byte = (float.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+    } else {
+        read_pixels(
+            gl,
+            origin.x,
+            origin.y,
+            size,
+            gl::UNSIGNED_BYTE,
+            out.as_mut_ptr(),
+        );
+    }
+    let error = gl.GetError();
+
+    if let Some(resolved) = resolved {
+        resolved.destroy(gl);
+    }
+    if error != gl::NO_ERROR {
+        return Err(Error::Failed);
+    }
+    Ok(())
+}
+
+unsafe fn read_pixels<T>(gl: &Gl, x: GLint, y: GLint, size: Size2D<i32>, ty: GLenum, out: *mut T) {
+    gl.ReadPixels(
+        x,
+        y,
+        size.width,
+        size.height,
+        gl::RGBA,
+        ty,
+        out as *mut c_void,
+    );
+}
+
+// A single-sampled renderbuffer that a multisampled surface is resolved into, bound as the draw
+// framebuffer.
+struct Resolved {
+    renderbuffer: GLuint,
+    framebuffer: GLuint,
+}
+
+impl Resolved {
+    unsafe fn new(gl: &Gl, format: GLenum, size: Size2D<i32>) -> Resolved {
+        let (mut renderbuffer, mut framebuffer) = (0, 0);
+        gl.GenRenderbuffers(1, &mut renderbuffer);
+        gl.BindRenderbuffer(gl::RENDERBUFFER, renderbuffer);
+        gl.RenderbufferStorage(gl::RENDERBUFFER, format, size.width, size.height);
+        gl.BindRenderbuffer(gl::RENDERBUFFER, 0);
+        gl.GenFramebuffers(1, &mut framebuffer);
+        gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, framebuffer);
+        gl.FramebufferRenderbuffer(
+            gl::DRAW_FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::RENDERBUFFER,
+            renderbuffer,
+        );
+        Resolved {
+            renderbuffer,
+            framebuffer,
+        }
+    }
+
+    unsafe fn destroy(self, gl: &Gl) {
+        gl.DeleteFramebuffers(1, &self.framebuffer);
+        gl.DeleteRenderbuffers(1, &self.renderbuffer);
+    }
+}
+
+impl SavedState {
+    unsafe fn save(gl: &Gl, gl3: bool) -> SavedState {
+        let mut saved_state = SavedState {
+            framebuffer: 0,
+            gl3_state: None,
+            pack_alignment: 0,
+        };
+        gl.GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut saved_state.framebuffer);
+        gl.GetIntegerv(gl::PACK_ALIGNMENT, &mut saved_state.pack_alignment);
+        if gl3 {
+            let mut gl3_state = Gl3State {
+                read_framebuffer: 0,
+                pack_buffer: 0,
+                pack_row_length: 0,
+                pack_skip_rows: 0,
+                pack_skip_pixels: 0,
+            };
+            gl.GetIntegerv(
+                gl::READ_FRAMEBUFFER_BINDING,
+                &mut gl3_state.read_framebuffer,
+            );
+            gl.GetIntegerv(gl::PIXEL_PACK_BUFFER_BINDING, &mut gl3_state.pack_buffer);
+            gl.GetIntegerv(gl::PACK_ROW_LENGTH, &mut gl3_state.pack_row_length);
+            gl.GetIntegerv(gl::PACK_SKIP_ROWS, &mut gl3_state.pack_skip_rows);
+            gl.GetIntegerv(gl::PACK_SKIP_PIXELS, &mut gl3_state.pack_skip_pixels);
+            saved_state.gl3_state = Some(gl3_state);
+        }
+        saved_state
+    }
+
+    unsafe fn restore(&self, gl: &Gl) {
+        gl.PixelStorei(gl::PACK_ALIGNMENT, self.pack_alignment);
+        // `GL_FRAMEBUFFER_BINDING` is the draw framebuffer binding where the two are separate.
+        match self.gl3_state {
+            None => gl.BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer as GLuint),
+            Some(ref gl3_state) => {
+                gl.PixelStorei(gl::PACK_SKIP_PIXELS, gl3_state.pack_skip_pixels);
+                gl.PixelStorei(gl::PACK_SKIP_ROWS, gl3_state.pack_skip_rows);
+                gl.PixelStorei(gl::PACK_ROW_LENGTH, gl3_state.pack_row_length);
+                gl.BindBuffer(gl::PIXEL_PACK_BUFFER, gl3_state.pack_buffer as GLuint);
+                gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.framebuffer as GLuint);
+                gl.BindFramebuffer(gl::READ_FRAMEBUFFER, gl3_state.read_framebuffer as GLuint);
+            }
+        }
+    }
+}
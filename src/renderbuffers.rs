@@ -2,13 +2,14 @@
 //
 //! A utility module for backends that wrap surfaces in OpenGL FBOs.
 
-use crate::context::{ContextAttributeFlags, ContextAttributes};
+use crate::context::{self, ContextAttributes};
 use crate::gl;
-use crate::gl::types::GLuint;
-use crate::Gl;
+use crate::gl::types::{GLenum, GLsizei, GLuint};
+use crate::gl_utils;
+use crate::{GLCapabilities, Gl};
 use std::thread;
 
-use euclid::default::Size2D;
+use euclid::default::{Rect, Size2D};
 
 pub(crate) enum Renderbuffers {
     IndividualDepthStencil { depth: GLuint, stencil: GLuint },
@@ -32,50 +33,86 @@ impl Drop for Renderbuffers {
     }
 }
 
+// A multisampled color renderbuffer, with multisampled depth and stencil renderbuffers to match,
+// that a surface is rendered to in place of its texture. The samples are resolved into the texture
+// with `resolve()`.
+pub(crate) struct Multisample {
+    pub(crate) framebuffer_object: GLuint,
+    color_renderbuffer: GLuint,
+    renderbuffers: Renderbuffers,
+    samples: GLsizei,
+    size: Size2D<i32>,
+}
+
 impl Renderbuffers {
     pub(crate) fn new(
         gl: &Gl,
         size: &Size2D<i32>,
         attributes: &ContextAttributes,
     ) -> Renderbuffers {
+        Renderbuffers::with_samples(gl, size, attributes, 0)
+    }
+
+    // Returns renderbuffers without depth or stencil, for the texture framebuffer of a surface
+    // whose depth and stencil live in its `Multisample` instead.
+    #[allow(dead_code)]
+    pub(crate) fn none() -> Renderbuffers {
+        Renderbuffers::IndividualDepthStencil {
+            depth: 0,
+            stencil: 0,
+        }
+    }
+
+    fn with_samples(
+        gl: &Gl,
+        size: &Size2D<i32>,
+        attributes: &ContextAttributes,
+        samples: GLsizei,
+    ) -> Renderbuffers {
+        let (depth_size, stencil_size) = context::requested_depth_stencil_sizes(attributes);
+        if depth_size == 0 && stencil_size == 0 {
+            return Renderbuffers::IndividualDepthStencil {
+                depth: 0,
+                stencil: 0,
+            };
+        }
+
+        // OpenGL ES 2.0 has neither packed depth/stencil nor 24-bit depth renderbuffers without
+        // extensions. Sizes that aren't available fall back to the next smaller format.
+        let capabilities = GLCapabilities::current(gl);
+        let float_depth = depth_size > 24 && capabilities.depth32f;
         unsafe {
-            if attributes
-                .flags
-                .contains(ContextAttributeFlags::DEPTH | ContextAttributeFlags::STENCIL)
-            {
+            if depth_size > 0 && stencil_size > 0 && capabilities.packed_depth_stencil {
+                let format = if float_depth {
+                    gl::DEPTH32F_STENCIL8
+                } else {
+                    gl::DEPTH24_STENCIL8
+                };
                 let mut renderbuffer = 0;
                 gl.GenRenderbuffers(1, &mut renderbuffer);
                 gl.BindRenderbuffer(gl::RENDERBUFFER, renderbuffer);
-                gl.RenderbufferStorage(
-                    gl::RENDERBUFFER,
-                    gl::DEPTH24_STENCIL8,
-                    size.width,
-                    size.height,
-                );
+                renderbuffer_storage(gl, samples, format, size);
                 gl.BindRenderbuffer(gl::RENDERBUFFER, 0);
                 return Renderbuffers::CombinedDepthStencil(renderbuffer);
             }
 
             let (mut depth_renderbuffer, mut stencil_renderbuffer) = (0, 0);
-            if attributes.flags.contains(ContextAttributeFlags::DEPTH) {
+            if depth_size > 0 {
                 gl.GenRenderbuffers(1, &mut depth_renderbuffer);
                 gl.BindRenderbuffer(gl::RENDERBUFFER, depth_renderbuffer);
-                gl.RenderbufferStorage(
-                    gl::RENDERBUFFER,
-                    gl::DEPTH_COMPONENT24,
-                    size.width,
-                    size.height,
-                );
+                let depth_format = if float_depth {
+                    gl::DEPTH_COMPONENT32F
+                } else if depth_size > 16 && capabilities.depth24 {
+                    gl::DEPTH_COMPONENT24
+                } else {
+                    gl::DEPTH_COMPONENT16
+                };
+                renderbuffer_storage(gl, samples, depth_format, size);
             }
-            if attributes.flags.contains(ContextAttributeFlags::STENCIL) {
+            if stencil_size > 0 {
                 gl.GenRenderbuffers(1, &mut stencil_renderbuffer);
                 gl.BindRenderbuffer(gl::RENDERBUFFER, stencil_renderbuffer);
-                gl.RenderbufferStorage(
-                    gl::RENDERBUFFER,
-                    gl::STENCIL_INDEX8,
-                    size.width,
-                    size.height,
-                );
+                renderbuffer_storage(gl, samples, gl::STENCIL_INDEX8, size);
             }
             gl.BindRenderbuffer(gl::RENDERBUFFER, 0);
 
@@ -124,6 +161,26 @@ impl Renderbuffers {
         }
     }
 
+    // Reallocates the renderbuffers at a new size, keeping their names so that the framebuffer
+    // they're attached to stays valid. Their contents are lost.
+    #[allow(dead_code)]
+    pub(crate) fn resize(&self, gl: &Gl, size: &Size2D<i32>) {
+        self.resize_with_samples(gl, size, 0)
+    }
+
+    fn resize_with_samples(&self, gl: &Gl, size: &Size2D<i32>, samples: GLsizei) {
+        let renderbuffers = match *self {
+            Renderbuffers::CombinedDepthStencil(renderbuffer) => [renderbuffer, 0],
+            Renderbuffers::IndividualDepthStencil { depth, stencil } => [depth, stencil],
+        };
+        for &renderbuffer in renderbuffers
+            .iter()
+            .filter(|&&renderbuffer| renderbuffer != 0)
+        {
+            unsafe { reallocate_renderbuffer(gl, renderbuffer, samples, size) }
+        }
+    }
+
     pub(crate) fn destroy(&mut self, gl: &Gl) {
         unsafe {
             gl.BindRenderbuffer(gl::RENDERBUFFER, 0);
@@ -152,3 +209,161 @@ impl Renderbuffers {
         }
     }
 }
+
+impl Multisample {
+    // Creates the multisampled renderbuffers for a surface of the given size whose texture has the
+    // sized internal format `format`, and leaves their framebuffer bound.
+    //
+    // Returns `None` if the attributes don't ask for multisampling, or if the current context
+    // can't resolve samples because it lacks `glBlitFramebuffer()`. The sample count is clamped
+    // to `GL_MAX_SAMPLES`.
+    #[allow(dead_code)]
+    pub(crate) fn new(
+        gl: &Gl,
+        size: &Size2D<i32>,
+        attributes: &ContextAttributes,
+        format: GLenum,
+    ) -> Option<Multisample> {
+        let samples = context::requested_samples(attributes) as GLsizei;
+        if samples == 0 || !GLCapabilities::current(gl).blit_framebuffer {
+            return None;
+        }
+
+        unsafe {
+            let mut max_samples = 0;
+            gl.GetIntegerv(gl::MAX_SAMPLES, &mut max_samples);
+            let samples = samples.min(max_samples);
+            if samples <= 1 {
+                return None;
+            }
+
+            let mut color_renderbuffer = 0;
+            gl.GenRenderbuffers(1, &mut color_renderbuffer);
+            gl.BindRenderbuffer(gl::RENDERBUFFER, color_renderbuffer);
+            renderbuffer_storage(gl, samples, format, size);
+            gl.BindRenderbuffer(gl::RENDERBUFFER, 0);
+
+            let mut framebuffer_object = 0;
+            gl.GenFramebuffers(1, &mut framebuffer_object);
+            gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
+            gl.FramebufferRenderbuffer(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::RENDERBUFFER,
+                color_renderbuffer,
+            );
+            let renderbuffers = Renderbuffers::with_samples(gl, size, attributes, samples);
+            renderbuffers.bind_to_current_framebuffer(gl);
+
+            Some(Multisample {
+                framebuffer_object,
+                color_renderbuffer,
+                renderbuffers,
+                samples,
+                size: *size,
+            })
+        }
+    }
+
+    // Resolves the samples into the texture attached to `framebuffer_object`. The context that
+    // created this must be current.
+    //
+    // The framebuffer bindings and the scissor test, which would clip the blit, are restored
+    // afterward.
+    pub(crate) fn resolve(&self, gl: &Gl, framebuffer_object: GLuint) {
+        self.resolve_rect(gl, framebuffer_object, &Rect::from_size(self.size))
+    }
+
+    // Resolves only the samples inside `rect`, measured from the bottom left, which the caller
+    // has clamped to the renderbuffers' size.
+    pub(crate) fn resolve_rect(&self, gl: &Gl, framebuffer_object: GLuint, rect: &Rect<i32>) {
+        unsafe {
+            let (mut old_draw_framebuffer, mut old_read_framebuffer) = (0, 0);
+            gl.GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut old_draw_framebuffer);
+            gl.GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut old_read_framebuffer);
+            let scissor_test = gl.IsEnabled(gl::SCISSOR_TEST) != gl::FALSE;
+            if scissor_test {
+                gl.Disable(gl::SCISSOR_TEST);
+            }
+
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, self.framebuffer_object);
+            gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, framebuffer_object);
+            let (min, max) = (rect.min(), rect.max());
+            gl.BlitFramebuffer(
+                min.x,
+                min.y,
+                max.x,
+                max.y,
+                min.x,
+                min.y,
+                max.x,
+                max.y,
+                gl::COLOR_BUFFER_BIT,
+                gl::NEAREST,
+            );
+
+            if scissor_test {
+                gl.Enable(gl::SCISSOR_TEST);
+            }
+            gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, old_draw_framebuffer as GLuint);
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, old_read_framebuffer as GLuint);
+        }
+    }
+
+    // Reallocates the renderbuffers at a new size, keeping their names and their framebuffer so
+    // that it stays bound. Their samples are lost.
+    #[allow(dead_code)]
+    pub(crate) fn resize(&mut self, gl: &Gl, size: &Size2D<i32>) {
+        unsafe { reallocate_renderbuffer(gl, self.color_renderbuffer, self.samples, size) };
+        self.renderbuffers
+            .resize_with_samples(gl, size, self.samples);
+        self.size = *size;
+    }
+
+    pub(crate) fn destroy(&mut self, gl: &Gl) {
+        unsafe {
+            if self.framebuffer_object != 0 {
+                gl_utils::destroy_framebuffer(gl, self.framebuffer_object);
+                self.framebuffer_object = 0;
+            }
+            self.renderbuffers.destroy(gl);
+            if self.color_renderbuffer != 0 {
+                gl.DeleteRenderbuffers(1, &self.color_renderbuffer);
+                self.color_renderbuffer = 0;
+            }
+        }
+    }
+}
+
+// Allocates new storage of the given size for a renderbuffer, in the format it already has.
+unsafe fn reallocate_renderbuffer(
+    gl: &Gl,
+    renderbuffer: GLuint,
+    samples: GLsizei,
+    size: &Size2D<i32>,
+) {
+    gl.BindRenderbuffer(gl::RENDERBUFFER, renderbuffer);
+    let mut format = 0;
+    gl.GetRenderbufferParameteriv(
+        gl::RENDERBUFFER,
+        gl::RENDERBUFFER_INTERNAL_FORMAT,
+        &mut format,
+    );
+    renderbuffer_storage(gl, samples, format as GLenum, size);
+    gl.BindRenderbuffer(gl::RENDERBUFFER, 0);
+}
+
+// Allocates storage for the bound renderbuffer, multisampled if `samples` is more than 1.
+unsafe fn renderbuffer_storage(gl: &Gl, samples: GLsizei, format: GLenum, size: &Size2D<i32>) {
+    if samples > 1 {
+        gl.RenderbufferStorageMultisample(
+            gl::RENDERBUFFER,
+            samples,
+            format,
+            size.width,
+            size.height,
+        );
+    } else {
+        gl.RenderbufferStorage(gl::RENDERBUFFER, format, size.width, size.height);
+    }
+}
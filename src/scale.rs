@@ -0,0 +1,126 @@
+// surfman/surfman/src/scale.rs
+//
+//! Noticing when a widget surface moves to a display with a different scale factor.
+//!
+//! Moving a window between a display at 100% and one at 200% changes how many device pixels it
+//! covers, but a widget surface keeps the size that it was given until it's resized, so its frames
+//! come out blurry or cropped, or are refused by `Device::present_surface()` with
+//! `Error::SurfaceSizeMismatch`. On Windows and macOS, each present compares the scale of the
+//! display that the widget is on with the last one seen, and reports a change to the handler
+//! registered with `Device::set_surface_scale_changed_handler()`.
+//!
+//! Surfaces created with `SurfaceOptions::auto_rescale` also resize themselves to their window's
+//! new size, once the window system reports it. The present that does so shows the frame that was
+//! drawn before the change without checking its size, since it was drawn before anyone knew, and
+//! the next frame is drawn at the new size.
+
+use crate::SurfaceID;
+
+use euclid::default::Size2D;
+
+// How many presentations after a scale change an automatically rescaled surface waits for its
+// window to take on a new size before it stops waiting, so that a resize unrelated to the change
+// still fails to present as usual.
+const PENDING_PRESENTS: u32 = 8;
+
+/// A change in the scale factor of the display showing a widget surface, as reported to a
+/// `SurfaceScaleChangedHandler`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SurfaceScaleChanged {
+    /// The new scale factor: the number of device pixels per logical pixel, such as 2.0 on a
+    /// display at 200%.
+    pub new_scale: f32,
+    /// The size in device pixels that the surface should be resized to, to stay crisp.
+    ///
+    /// This is the size of the widget's window if the window has already taken on a new size, and
+    /// otherwise the surface's size scaled by the change.
+    pub suggested_size: Size2D<i32>,
+}
+
+/// A function that a device calls with the ID of each widget surface that it finds has moved to a
+/// display with a different scale factor, and what changed.
+///
+/// Register one with `Device::set_surface_scale_changed_handler()`. It is called on the thread
+/// that presented the surface, from inside `Device::present_surface()`, so it must not call back
+/// into that device.
+pub type SurfaceScaleChangedHandler = Box<dyn Fn(SurfaceID, SurfaceScaleChanged) + Send + Sync>;
+
+// Follows the scale of the display showing a widget surface, one presentation at a time.
+//
+// This only looks at the scales and sizes that it's given, and leaves reporting the change and
+// resizing the surface to the caller, so that it can be driven with made-up notifications.
+#[cfg_attr(not(any(target_os = "windows", target_os = "macos")), allow(dead_code))]
+pub(crate) struct ScaleTracker {
+    scale: f32,
+    auto_rescale: bool,
+    // How many more presentations an automatic resize waits for the window to change size.
+    pending: u32,
+}
+
+#[cfg_attr(not(any(target_os = "windows", target_os = "macos")), allow(dead_code))]
+impl ScaleTracker {
+    // Starts out with the scale that the widget had when its surface was created.
+    pub(crate) fn new(scale: f32, auto_rescale: bool) -> ScaleTracker {
+        ScaleTracker {
+            scale,
+            auto_rescale,
+            pending: 0,
+        }
+    }
+
+    // Takes the scale of the display that the widget is on now, the surface's size, and the size
+    // of the widget's window in device pixels if it's known. Call this before every present.
+    //
+    // Returns the change to report if the scale changed, and for automatically rescaled surfaces,
+    // the size to resize the surface to once the frame has been presented. The frame was drawn
+    // before the change was known, so it should be presented without checking its size.
+    pub(crate) fn update(
+        &mut self,
+        scale: f32,
+        size: Size2D<i32>,
+        native_size: Option<Size2D<i32>>,
+    ) -> (Option<SurfaceScaleChanged>, Option<Size2D<i32>>) {
+        let mut change = None;
+        if scale > 0.0 && scale != self.scale {
+            let suggested_size = match native_size {
+                Some(native_size) if native_size != size => native_size,
+                _ => scaled_size(size, scale / self.scale),
+            };
+            change = Some(SurfaceScaleChanged {
+                new_scale: scale,
+                suggested_size,
+            });
+            self.scale = scale;
+            if self.auto_rescale {
+                self.pending = PENDING_PRESENTS;
+            }
+        }
+
+        if self.pending == 0 {
+            return (change, None);
+        }
+        self.pending -= 1;
+        // Only the window's own size is worth resizing to: anything else would be refused by the
+        // next present as stale.
+        match native_size {
+            Some(native_size) if native_size != size => {
+                self.pending = 0;
+                (change, Some(native_size))
+            }
+            _ => (change, None),
+        }
+    }
+
+    // Records that the application resized the surface itself, which takes the place of any
+    // automatic resize still waiting, so that the surface isn't resized twice.
+    #[inline]
+    pub(crate) fn resized(&mut self) {
+        self.pending = 0;
+    }
+}
+
+#[cfg_attr(not(any(target_os = "windows", target_os = "macos")), allow(dead_code))]
+fn scaled_size(size: Size2D<i32>, ratio: f32) -> Size2D<i32> {
+    let scale = |length: i32| ((length as f32 * ratio).round() as i32).max(1);
+    Size2D::new(scale(size.width), scale(size.height))
+}
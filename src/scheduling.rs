@@ -0,0 +1,119 @@
+// surfman/surfman/src/scheduling.rs
+//
+//! Keeping background GPU work from starving interactive rendering on the same GPU.
+//!
+//! `EGL_IMG_context_priority` is how the driver is asked to lower the kernel scheduling priority
+//! of a context. On Linux, drivers without it have the priority of the kernel contexts that they
+//! create lowered with the i915 or amdgpu DRM ioctls instead, where the kernel allows that.
+
+#[cfg(any(android_backend, angle, generic_egl_platform, ohos_backend))]
+use crate::ContextPriority;
+
+#[cfg(feature = "chains")]
+use std::time::{Duration, Instant};
+
+// The shortest time between two frames of a swap chain on a device whose background work is
+// paced in software: about 20 frames per second.
+#[cfg(feature = "chains")]
+pub(crate) const BACKGROUND_FRAME_INTERVAL: Duration = Duration::from_millis(50);
+
+/// What kind of work a device's contexts do, for sharing the GPU fairly with other work.
+///
+/// Set it with `Device::set_scheduling_class()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchedulingClass {
+    /// Rendering that the user is waiting on, such as a UI.
+    ///
+    /// This is the default, and leaves scheduling to the driver.
+    Interactive,
+    /// Work that should yield the GPU to interactive rendering, such as indexing or thumbnailing.
+    Background,
+}
+
+/// How a device enforces its scheduling class, as returned by `Device::set_scheduling_class()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchedulingEnforcement {
+    /// Nothing is enforced, because the class is `Interactive`.
+    None,
+    /// Contexts created from now on ask the driver for `ContextPriority::Low`, whatever their
+    /// attributes request, and the driver schedules them behind other contexts.
+    ///
+    /// Contexts that already exist keep their priority. Check the priority that a context was
+    /// granted with `Device::context_priority()`.
+    ContextPriority,
+    /// The driver can't prioritize contexts, so surfman lowers the kernel scheduling priority of
+    /// the contexts created from now on itself, with the i915 or amdgpu DRM ioctls.
+    ///
+    /// This only happens on Linux. amdgpu only allows it to the DRM master, such as a compositor.
+    /// As with `ContextPriority`, contexts that already exist keep their priority, and
+    /// `Device::context_priority()` reports `ContextPriority::Low` for the contexts that were
+    /// lowered.
+    KernelPriority,
+    /// The driver can't prioritize contexts, so surfman paces the device's swap chains instead:
+    /// `SwapChain::swap_buffers()` yields the CPU after every frame, and waits so that frames are
+    /// at least 50 ms apart.
+    ///
+    /// Rendering that doesn't go through a swap chain isn't slowed down.
+    Software,
+}
+
+impl Default for SchedulingClass {
+    #[inline]
+    fn default() -> SchedulingClass {
+        SchedulingClass::Interactive
+    }
+}
+
+impl SchedulingClass {
+    // Returns the priority to create a context at, given the priority its attributes request.
+    #[cfg(any(android_backend, angle, generic_egl_platform, ohos_backend))]
+    pub(crate) fn context_priority(self, requested: ContextPriority) -> ContextPriority {
+        match self {
+            SchedulingClass::Interactive => requested,
+            SchedulingClass::Background => ContextPriority::Low,
+        }
+    }
+
+    // Returns how the class is enforced on a device whose driver can, or can't, prioritize
+    // contexts, and whose contexts' kernel priorities surfman can, or can't, lower itself.
+    pub(crate) fn enforcement(
+        self,
+        driver_priorities: bool,
+        kernel_priorities: bool,
+    ) -> SchedulingEnforcement {
+        match self {
+            SchedulingClass::Interactive => SchedulingEnforcement::None,
+            SchedulingClass::Background if driver_priorities => {
+                SchedulingEnforcement::ContextPriority
+            }
+            SchedulingClass::Background if kernel_priorities => {
+                SchedulingEnforcement::KernelPriority
+            }
+            SchedulingClass::Background => SchedulingEnforcement::Software,
+        }
+    }
+}
+
+// Spaces the frames of a swap chain out when its device's class is enforced in software.
+#[cfg(feature = "chains")]
+#[derive(Default)]
+pub(crate) struct FramePacer {
+    // When the last frame was allowed to be presented.
+    last_frame: Option<Instant>,
+}
+
+#[cfg(feature = "chains")]
+impl FramePacer {
+    // Returns how long a producer that presented a frame at `now` should wait before starting the
+    // next one, and counts the frame as presented at the end of the wait.
+    pub(crate) fn delay(&mut self, now: Instant) -> Duration {
+        let delay = match self.last_frame {
+            Some(last_frame) => {
+                BACKGROUND_FRAME_INTERVAL.saturating_sub(now.saturating_duration_since(last_frame))
+            }
+            None => Duration::ZERO,
+        };
+        self.last_frame = Some(now + delay);
+        delay
+    }
+}
@@ -3,10 +3,24 @@
 //! Information related to hardware surfaces.
 
 use crate::context::ContextID;
+use crate::gl;
+use crate::memory::GL_RGB565;
+use crate::Error;
+use crate::NativeFormat;
+use crate::Purgeability;
+use crate::{ColorDepth, ContextAttributes, GLApi, GLCapabilities};
 
-use crate::gl::types::GLuint;
-use euclid::default::Size2D;
+use crate::gl::types::{GLenum, GLuint};
+use euclid::default::{Point2D, Rect, Size2D};
+use log::warn;
+use std::collections::VecDeque;
 use std::fmt::{self, Display, Formatter};
+#[cfg(unix)]
+use std::os::fd::OwnedFd;
+use std::time::Instant;
+
+// The number of presentations that each widget surface remembers.
+const PRESENT_HISTORY_LENGTH: usize = 64;
 
 /// Various data about the surface.
 pub struct SystemSurfaceInfo {
@@ -14,9 +28,14 @@ pub struct SystemSurfaceInfo {
     pub size: Size2D<i32>,
     /// The ID of the surface. This should be globally unique for each currently-allocated surface.
     pub id: SurfaceID,
+    /// The number of bytes in each row of the surface's `IOSurface`.
+    pub stride: usize,
+    /// The pixel format of the surface's `IOSurface`, from `IOSurfaceGetPixelFormat()`.
+    pub pixel_format: u32,
 }
 
 /// Various data about the surface.
+#[derive(Debug)]
 pub struct SurfaceInfo {
     /// The surface's size, in device pixels.
     pub size: Size2D<i32>,
@@ -28,6 +47,284 @@ pub struct SurfaceInfo {
     ///
     /// This is only valid when the surface is actually attached to a context.
     pub framebuffer_object: GLuint,
+    /// How the CPU may access the surface data, as requested when the surface was created.
+    pub access: SurfaceAccess,
+    /// The sized internal format of the surface's color buffer, such as `GL_RGBA8`.
+    ///
+    /// Surfaces created in low-memory mode may be `memory::GL_RGB565` or `GL_RGBA4` instead.
+    /// This is `GL_NONE` for widget surfaces, whose format belongs to the window system.
+    pub format: GLenum,
+    /// Whether the system may discard the surface's contents, so that memory accounting can leave
+    /// volatile surfaces out of the application's resident footprint.
+    pub purgeability: Purgeability,
+    /// How the surface's color buffer is encoded, as requested when the surface was created.
+    pub colorspace: ColorEncoding,
+    /// The number of bits in each channel of the surface's color buffer.
+    ///
+    /// For widget surfaces, these come from the config or pixel format that the window system
+    /// actually granted, so applications can tell whether a deep-color request fell back to 8
+    /// bits per channel.
+    pub color_bits: ColorBits,
+    /// Whether the surface's contents survive `Device::present_surface()`.
+    ///
+    /// Widget surfaces report what the window system actually does, which may be to preserve
+    /// them even without `SurfaceOptions::preserve_buffer`. Generic surfaces always keep theirs.
+    pub preserves_contents: bool,
+    /// The texture target that the surface's color texture is bound to, such as `GL_TEXTURE_2D`.
+    ///
+    /// This is `Device::surface_gl_texture_target()`: `GL_TEXTURE_RECTANGLE` on macOS and
+    /// `GL_TEXTURE_2D` elsewhere. It's `GL_NONE` for widget surfaces on every platform but macOS,
+    /// since they have no texture of their own.
+    pub texture_target: GLenum,
+    /// The number of bytes in each row of the surface data that `Device::lock_surface_data()`
+    /// returns.
+    ///
+    /// This is only `Some` on macOS, where every surface is backed by an `IOSurface` that can be
+    /// locked; it's `None` on the other platforms, which don't implement `lock_surface_data()`.
+    pub stride: Option<usize>,
+    /// The format of the surface's color buffer, as the platform API that allocated it knows it.
+    ///
+    /// This is the `IOSurface` pixel format on macOS, the format of the Direct3D texture on
+    /// Windows, and the DRM fourcc of the hardware buffer's format on Android. Surfaces that are
+    /// plain OpenGL textures or renderbuffers report `NativeFormat::GlInternal`, and widget
+    /// surfaces report `NativeFormat::Unknown` unless, as on macOS, surfman allocated their
+    /// buffers itself. See the `native_format` module.
+    pub native_format: NativeFormat,
+}
+
+/// The number of bits in each channel of a color buffer.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct ColorBits {
+    /// The number of bits in the red channel.
+    pub red: u8,
+    /// The number of bits in the green channel.
+    pub green: u8,
+    /// The number of bits in the blue channel.
+    pub blue: u8,
+    /// The number of bits in the alpha channel.
+    pub alpha: u8,
+}
+
+impl ColorBits {
+    // The channel sizes of a sized internal format that surfman allocates generic surfaces in.
+    // Other formats report zero bits.
+    pub(crate) fn of_format(format: GLenum) -> ColorBits {
+        let (red, green, blue, alpha) = match format {
+            gl::RGBA8 | gl::SRGB8_ALPHA8 => (8, 8, 8, 8),
+            gl::RGB8 => (8, 8, 8, 0),
+            GL_RGB565 => (5, 6, 5, 0),
+            gl::RGBA4 => (4, 4, 4, 4),
+            gl::RGBA16F => (16, 16, 16, 16),
+            gl::RGB10_A2 => (10, 10, 10, 2),
+            _ => (0, 0, 0, 0),
+        };
+        ColorBits {
+            red,
+            green,
+            blue,
+            alpha,
+        }
+    }
+}
+
+/// A function that a device calls with the ID and new size of each widget surface that it finds
+/// was resized by someone other than surfman.
+///
+/// Register one with `Device::set_surface_resized_handler()`. It is called on the thread that
+/// noticed the resize, from inside the device method that noticed it, so it must not call back
+/// into that device.
+pub type SurfaceResizedHandler = Box<dyn Fn(SurfaceID, Size2D<i32>) + Send + Sync>;
+
+/// Methods that the surfaces of every backend have.
+pub trait SurfaceInterface {
+    /// Returns the ID of the context that this surface was created with, which is the only
+    /// context that it can be bound to.
+    fn context_id(&self) -> ContextID;
+}
+
+/// Information about the render target that `Device::begin_rendering()` set up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderTargetInfo {
+    /// The OpenGL framebuffer object that was bound.
+    ///
+    /// This is zero for widget surfaces and when no surface is attached to the context.
+    pub framebuffer_object: GLuint,
+    /// The size of the render target, in device pixels.
+    ///
+    /// This is zero when no surface is attached to the context.
+    pub size: Size2D<i32>,
+    /// The lower-left corner of the viewport that was set, in device pixels.
+    pub origin: Point2D<i32>,
+    /// The sized internal format of the color buffer, such as `GL_RGBA8`.
+    ///
+    /// This is `GL_NONE` when no surface is attached to the context.
+    pub format: GLenum,
+}
+
+/// The timing of one presentation of a widget surface, as far as surfman can observe it.
+///
+/// Subtract `submit` from `displayed` (or, failing that, `compositor_ack`) to measure the latency
+/// that presentation adds. Later timestamps are `None` when the platform doesn't report them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PresentRecord {
+    /// Counts presentations of the surface, starting at zero.
+    pub frame_id: u64,
+    /// When `present_surface()` was called.
+    pub submit: Instant,
+    /// When the buffer was handed to the window system's compositor.
+    pub compositor_ack: Option<Instant>,
+    /// When the frame reached the display.
+    ///
+    /// This is what `Device::query_presentation_time()` reports as `actual_present` for the
+    /// frame, once the platform has reported it.
+    pub displayed: Option<Instant>,
+    /// Whether presenting this frame revealed that the widget had been resized behind surfman's
+    /// back, as `Device::set_surface_resized_handler()` reports.
+    pub resized_externally: bool,
+}
+
+/// How `Device::wait_previous_buffer_released()` found the buffer of the last frame presented on
+/// a widget surface.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BufferRelease {
+    /// It had already been released, so the call returned without blocking.
+    Released,
+    /// The call blocked until it was released.
+    Waited,
+    /// It was still in use when the timeout elapsed.
+    TimedOut,
+}
+
+/// The pixels of a generic surface, exported as a Linux dma-buf by
+/// `Device::export_surface_dma_buf()`.
+///
+/// The dma-buf shares memory with the surface, so another GL context, Vulkan, or another process
+/// can import it and render into the surface or sample from it without a copy.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct DmaBuf {
+    /// The dma-buf's file descriptor, which belongs to the caller.
+    pub fd: OwnedFd,
+    /// The size of the image in pixels.
+    pub size: Size2D<i32>,
+    /// The DRM fourcc of the pixels' format, from `drm_fourcc.h`.
+    pub fourcc: u32,
+    /// The DRM format modifier that describes how the pixels are laid out in memory.
+    pub modifier: u64,
+    /// The offset of the first pixel from the start of the dma-buf, in bytes.
+    pub offset: u32,
+    /// The number of bytes between the start of one row and the start of the next.
+    pub stride: u32,
+}
+
+// The most recent presentations of a widget surface, oldest first.
+#[derive(Default)]
+pub(crate) struct PresentHistory {
+    records: VecDeque<PresentRecord>,
+}
+
+impl PresentHistory {
+    pub(crate) fn record(
+        &mut self,
+        submit: Instant,
+        compositor_ack: Option<Instant>,
+        displayed: Option<Instant>,
+    ) {
+        let frame_id = self.next_frame_id();
+        if self.records.len() == PRESENT_HISTORY_LENGTH {
+            self.records.pop_front();
+        }
+        self.records.push_back(PresentRecord {
+            frame_id,
+            submit,
+            compositor_ack,
+            displayed,
+            resized_externally: false,
+        });
+    }
+
+    // Marks the most recent presentation as having revealed an external resize of the widget.
+    pub(crate) fn mark_resized_externally(&mut self) {
+        if let Some(record) = self.records.back_mut() {
+            record.resized_externally = true;
+        }
+    }
+
+    pub(crate) fn records(&self) -> Vec<PresentRecord> {
+        self.records.iter().cloned().collect()
+    }
+
+    // Returns the presentations, most recent first.
+    pub(crate) fn newest_first(&self) -> impl Iterator<Item = &PresentRecord> {
+        self.records.iter().rev()
+    }
+
+    // Returns the `frame_id` that the next presentation will be recorded with.
+    pub(crate) fn next_frame_id(&self) -> u64 {
+        self.records.back().map_or(0, |record| record.frame_id + 1)
+    }
+
+    // Returns the two most recent presentations, oldest first.
+    pub(crate) fn latest_pair(&self) -> Option<(&PresentRecord, &PresentRecord)> {
+        let mut records = self.records.iter().rev();
+        let latest = records.next()?;
+        Some((records.next()?, latest))
+    }
+}
+
+// Checks a size that a surface is about to be created at or resized to: both sides must be
+// positive, and no longer than the longest side of a texture, beyond which drivers fail in ways of
+// their own, if they don't crash.
+pub(crate) fn check_surface_size(
+    size: Size2D<i32>,
+    capabilities: &GLCapabilities,
+) -> Result<(), Error> {
+    let max_size = capabilities.max_texture_size.min(i32::MAX as u32) as i32;
+    if size.width <= 0 || size.height <= 0 || size.width > max_size || size.height > max_size {
+        return Err(Error::InvalidSurfaceSize(size));
+    }
+    Ok(())
+}
+
+// Clips the damage rectangles given for a widget surface of `surface_size` to the frame being
+// presented, which is `frame_size`, dropping those that lie wholly outside it. If the widget has
+// been resized behind surfman's back, the two sizes differ, and the rectangles, which were measured
+// against the old size, can't describe the new frame: `None` is returned so that the whole frame
+// is presented.
+#[allow(dead_code)]
+pub(crate) fn clip_damage(
+    damage: &[Rect<i32>],
+    surface_size: Size2D<i32>,
+    frame_size: Size2D<i32>,
+) -> Option<Vec<Rect<i32>>> {
+    if surface_size != frame_size {
+        return None;
+    }
+    let bounds = Rect::from_size(frame_size);
+    Some(
+        damage
+            .iter()
+            .filter_map(|rect| rect.intersection(&bounds))
+            .filter(|rect| !rect.is_empty())
+            .collect(),
+    )
+}
+
+// Compares the size that the caller gave for a native widget with the size that the platform
+// reports for it, and returns the platform's size if the two disagree.
+#[allow(dead_code)]
+pub(crate) fn adjusted_widget_size(
+    size: Size2D<i32>,
+    actual_size: Size2D<i32>,
+) -> Option<Size2D<i32>> {
+    if size == actual_size {
+        return None;
+    }
+    warn!(
+        "Native widget was created with size {}x{}, but it is {}x{}",
+        size.width, size.height, actual_size.width, actual_size.height
+    );
+    Some(actual_size)
 }
 
 // The default framebuffer for a context.
@@ -66,8 +363,11 @@ impl Display for SurfaceID {
 pub enum SurfaceAccess {
     /// The surface data is accessible by the GPU only.
     ///
-    /// The `lock_surface_data()` method will return the `SurfaceDataInaccessible` error when
-    /// called on this surface.
+    /// The backend may place the surface in memory that the CPU can't map, and may use tiled or
+    /// compressed layouts for it. Rendering to the surface, sampling from it through a
+    /// `SurfaceTexture`, and reading it back with OpenGL calls such as `glReadPixels` all still
+    /// work; only direct CPU access is ruled out. Methods that map the surface data, such as
+    /// `lock_surface_data()`, return `Error::InvalidSurfaceAccess` when called on this surface.
     ///
     /// This is typically the flag you will want to use.
     GPUOnly,
@@ -92,6 +392,254 @@ pub enum SurfaceAccess {
     GPUCPUWriteCombined,
 }
 
+/// Who deletes an OpenGL texture that has been wrapped in a surface with
+/// `create_surface_from_texture()`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TextureOwnership {
+    /// The caller keeps ownership of the texture, and surfman never deletes it.
+    ///
+    /// The texture must not be deleted, resized, or respecified (for example, with
+    /// `glTexImage2D`) until the surface has been destroyed. Doing so leaves the surface reading
+    /// and writing stale or freed memory.
+    Borrowed,
+    /// Ownership of the texture passes to the surface, and the texture is deleted when the surface
+    /// is destroyed.
+    ///
+    /// The caller must not delete the texture itself.
+    Owned,
+}
+
+/// How the values in a surface's color buffer are encoded.
+///
+/// Rendering to an `Srgb` surface encodes linear values with the sRGB transfer function as they
+/// are written, and sampling from its surface textures decodes them again. With desktop OpenGL,
+/// as with any sRGB framebuffer, writes are only encoded while `GL_FRAMEBUFFER_SRGB` is enabled;
+/// OpenGL ES always encodes them.
+///
+/// This is the transfer function of the stored values, not the primaries that a `ColorSpace`
+/// names.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorEncoding {
+    /// Values are stored as they are written.
+    ///
+    /// This is the default.
+    Linear,
+    /// Values are stored sRGB-encoded: `GL_SRGB8_ALPHA8` for generic surfaces, and
+    /// `EGL_GL_COLORSPACE_SRGB` or the platform's equivalent for widget surfaces.
+    Srgb,
+}
+
+impl Default for ColorEncoding {
+    #[inline]
+    fn default() -> ColorEncoding {
+        ColorEncoding::Linear
+    }
+}
+
+/// The storage of a surface's color buffer.
+///
+/// Generic surfaces allocate their textures in this format. Widget surfaces take theirs from the
+/// pixel format of their context, so they can only have the formats that it offers; see
+/// `ContextAttributes::color_depth`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SurfaceFormat {
+    /// 8-bit normalized channels, `GL_RGBA8`.
+    ///
+    /// This is the default.
+    Rgba8,
+    /// 16-bit floating-point channels, `GL_RGBA16F`, for HDR rendering.
+    ///
+    /// Generic surfaces need `GLCapabilities::color_buffer_half_float`. Widget surfaces need a
+    /// context that was granted `ColorDepth::Float`.
+    Rgba16F,
+    /// 10-bit normalized color channels with 2 bits of alpha, `GL_RGB10_A2`, for deep-color
+    /// displays.
+    ///
+    /// Generic surfaces need OpenGL ES 3.0 on OpenGL ES. Widget surfaces need a context that was
+    /// granted `ColorDepth::Deep`; check `SurfaceInfo::color_bits` to see what the window system
+    /// actually provided.
+    Rgb10A2,
+}
+
+impl Default for SurfaceFormat {
+    #[inline]
+    fn default() -> SurfaceFormat {
+        SurfaceFormat::Rgba8
+    }
+}
+
+/// How a widget surface's frames reach the screen.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PresentMode {
+    /// Frames are drawn into a back buffer, which `Device::present_surface()` swaps with the one
+    /// on screen.
+    ///
+    /// This is the default.
+    DoubleBuffer,
+    /// Frames are drawn straight into the buffer on screen, and `Device::present_surface()` only
+    /// flushes them, for overlays that can't afford a frame of swap latency. Partly drawn frames
+    /// may be seen, and the window system may tear them.
+    ///
+    /// This needs `EGL_KHR_mutable_render_buffer`. The first present still swaps, which moves
+    /// rendering to the front buffer from then on. Widget surfaces that can't render this way
+    /// fail to be created with `Error::UnsupportedPresentMode`, so that callers can fall back to
+    /// `DoubleBuffer`.
+    SingleBuffer,
+}
+
+impl Default for PresentMode {
+    #[inline]
+    fn default() -> PresentMode {
+        PresentMode::DoubleBuffer
+    }
+}
+
+/// A clockwise rotation that the window system applies to a widget surface's frames to show them
+/// on a rotated display, as reported by `Device::surface_transform()`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SurfaceTransform {
+    /// Frames are shown as they are.
+    Identity,
+    /// Frames are rotated by 90 degrees.
+    Rotate90,
+    /// Frames are rotated by 180 degrees.
+    Rotate180,
+    /// Frames are rotated by 270 degrees.
+    Rotate270,
+}
+
+impl SurfaceTransform {
+    /// Returns true if the transform swaps the width and height of frames, so that a frame
+    /// rendered pre-rotated by it is laid out as if the surface were turned on its side.
+    #[inline]
+    pub fn swaps_dimensions(self) -> bool {
+        matches!(
+            self,
+            SurfaceTransform::Rotate90 | SurfaceTransform::Rotate270
+        )
+    }
+}
+
+impl Default for SurfaceTransform {
+    #[inline]
+    fn default() -> SurfaceTransform {
+        SurfaceTransform::Identity
+    }
+}
+
+/// Options for creating a surface with `Device::create_surface_with_options()`.
+///
+/// `Device::create_surface()` uses the default options.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct SurfaceOptions {
+    /// How the surface's color buffer is encoded.
+    ///
+    /// Creating an sRGB surface fails with `Error::UnsupportedSurfaceFormat` where the platform
+    /// can't encode one, rather than quietly creating a linear surface.
+    pub colorspace: ColorEncoding,
+    /// How the surface's color buffer is stored.
+    ///
+    /// Only `SurfaceFormat::Rgba8` surfaces can be sRGB-encoded. Creating a surface in a format
+    /// that the platform can't allocate fails with `Error::UnsupportedSurfaceFormat`.
+    pub format: SurfaceFormat,
+    /// Whether a widget surface should be opaque, or have an alpha channel that the window system
+    /// can composite with whatever is behind the window. Generic surfaces ignore this.
+    ///
+    /// `None` keeps the alpha channel of the context's pixel format, or on macOS the opacity of
+    /// the native widget's window. On EGL, `Some(true)` renders through a config with no alpha
+    /// and `Some(false)` through one with an alpha channel, otherwise compatible with the
+    /// context's, failing with `Error::NoPixelFormatFound` if there is none.
+    ///
+    /// The window has the final say: a translucent surface only looks translucent if the window
+    /// was created with a visual or format that has alpha and the compositor honors it. On X11,
+    /// the config must also match the depth of the window's visual, or the window system would
+    /// fail with `BadMatch`, so a translucent surface needs a window with a 32-bit ARGB visual and
+    /// an opaque one needs a window without. With `None`, X11 picks whichever config matches the
+    /// window. WGL widget surfaces always share the context's pixel format, and fail with
+    /// `Error::UnsupportedSurfaceFormat` if this is set.
+    pub opaque: Option<bool>,
+    /// Whether a widget surface is double-buffered, or renders straight to the screen. Generic
+    /// surfaces ignore this.
+    pub present_mode: PresentMode,
+    /// Whether a widget surface should keep its contents across `Device::present_surface()`, so
+    /// that each frame only needs to draw what changed. Generic surfaces ignore this, since they
+    /// always keep theirs.
+    ///
+    /// On EGL, this renders through a config with `EGL_SWAP_BEHAVIOR_PRESERVED_BIT` and sets
+    /// `EGL_SWAP_BEHAVIOR` to `EGL_BUFFER_PRESERVED`. Widget surfaces that can't keep their
+    /// contents fail to be created with `Error::UnsupportedSwapBehavior`, rather than flickering
+    /// with whatever the swap left behind.
+    pub preserve_buffer: bool,
+    /// Whether the application renders each frame of a widget surface already rotated by the
+    /// transform that `Device::surface_transform()` last reported, so that the compositor can
+    /// show it without a rotation pass of its own. Generic surfaces ignore this.
+    ///
+    /// `Device::present_surface()` tells the window system which transform each frame was
+    /// rendered with. Only Android does this, and everywhere else `surface_transform()` reports
+    /// `SurfaceTransform::Identity`, so frames are rendered as usual.
+    pub pre_transform: bool,
+    /// Whether a widget surface resizes itself to its window's new size when it moves to a
+    /// display with a different scale factor, rather than waiting for the application to resize
+    /// it. Generic surfaces ignore this.
+    ///
+    /// The resize happens in the first `Device::present_surface()` after the window system
+    /// reports the new size, once that frame has been shown, so that the next frame is drawn at
+    /// the new size. If the application resizes the surface itself first, it isn't resized again.
+    /// Only Windows and macOS notice scale changes; see
+    /// `Device::set_surface_scale_changed_handler()`.
+    pub auto_rescale: bool,
+}
+
+impl SurfaceOptions {
+    // Returns the sized internal format to allocate a generic surface with these options, on a
+    // context with the given capabilities, given the format that a linear `Rgba8` surface would
+    // get, which may be smaller in low-memory mode. There is no sRGB format smaller than
+    // `GL_SRGB8_ALPHA8`, so sRGB surfaces never get smaller.
+    #[allow(dead_code)]
+    pub(crate) fn generic_surface_format(
+        &self,
+        capabilities: &GLCapabilities,
+        linear_format: GLenum,
+    ) -> Result<GLenum, Error> {
+        // `GL_SRGB8_ALPHA8` and `GL_RGB10_A2` are core from OpenGL 2.1 and OpenGL ES 3.0.
+        let gles2 = capabilities.gl_api == GLApi::GLES && capabilities.version.major < 3;
+        match (self.format, self.colorspace) {
+            (SurfaceFormat::Rgba8, ColorEncoding::Linear) => Ok(linear_format),
+            (SurfaceFormat::Rgba8, ColorEncoding::Srgb) if !gles2 => Ok(gl::SRGB8_ALPHA8),
+            (SurfaceFormat::Rgba16F, ColorEncoding::Linear)
+                if capabilities.color_buffer_half_float =>
+            {
+                Ok(gl::RGBA16F)
+            }
+            (SurfaceFormat::Rgb10A2, ColorEncoding::Linear) if !gles2 => Ok(gl::RGB10_A2),
+            _ => Err(Error::UnsupportedSurfaceFormat),
+        }
+    }
+
+    // Returns an error unless a widget surface of a context with the given attributes can be
+    // created with these options. Only the format is checked, since the colorspace is chosen
+    // when the window surface is created.
+    #[allow(dead_code)]
+    pub(crate) fn check_widget_format(&self, attributes: &ContextAttributes) -> Result<(), Error> {
+        match self.format {
+            SurfaceFormat::Rgba8 => Ok(()),
+            SurfaceFormat::Rgba16F
+                if attributes.color_depth == ColorDepth::Float
+                    && self.colorspace == ColorEncoding::Linear =>
+            {
+                Ok(())
+            }
+            SurfaceFormat::Rgb10A2
+                if attributes.color_depth == ColorDepth::Deep
+                    && self.colorspace == ColorEncoding::Linear =>
+            {
+                Ok(())
+            }
+            _ => Err(Error::UnsupportedSurfaceFormat),
+        }
+    }
+}
+
 /// Information specific to the type of surface: generic or widget.
 #[derive(Clone)]
 pub enum SurfaceType<NativeWidget> {
@@ -116,8 +664,144 @@ pub enum SurfaceType<NativeWidget> {
     },
 }
 
-impl SurfaceAccess {
+/// What a surface will be used for.
+///
+/// Pass one to `Device::recommended_surface_config()` to find out which access mode, type, and
+/// options suit that use on the current device.
+#[derive(Clone)]
+pub enum SurfaceIntent<NativeWidget> {
+    /// Rendered to and then sampled or read back with OpenGL calls by the context that created it.
+    OffscreenRenderTarget,
+    /// Rendered to by one context and sampled by another through a `SurfaceTexture`.
+    SharedWithOtherContext,
+    /// Shown in a native widget (window or view).
+    DisplayedInWindow(NativeWidget),
+    /// Rendered to on the GPU and then read back by the CPU, most frames.
+    CpuReadbackHeavy,
+    /// Filled with frames, typically decoded video, by the CPU, and then sampled on the GPU.
+    VideoImport,
+}
+
+/// The surface that `Device::recommended_surface_config()` considers best for a `SurfaceIntent`
+/// on the current device.
+///
+/// Pass `access`, `surface_type`, and `options` to `Device::create_surface_with_options()`, as
+/// they are or after adjusting them, and follow `hints` when using the surface.
+#[derive(Clone)]
+pub struct SurfaceConfig<NativeWidget> {
+    /// Whether and how the CPU should have access to the surface data.
+    ///
+    /// `GPUOnly` unless the CPU can map surfaces on this backend and the intent moves pixels
+    /// between the CPU and the surface. Where mapping is unimplemented, CPU access only costs
+    /// memory locality.
+    pub access: SurfaceAccess,
+    /// A widget surface for `SurfaceIntent::DisplayedInWindow`, and a generic surface of the
+    /// requested size otherwise.
+    pub surface_type: SurfaceType<NativeWidget>,
+    /// The format and colorspace to create the surface with.
+    pub options: SurfaceOptions,
+    /// How to use the surface once it's created.
+    pub hints: SurfaceHints,
+}
+
+/// How to use a surface created from a `SurfaceConfig`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct SurfaceHints {
+    /// Whether to move pixels between the CPU and the surface by mapping it with
+    /// `Device::lock_surface_data()`. Otherwise, read pixels back with `glReadPixels()` and upload
+    /// them with `glTexSubImage2D()` on a `SurfaceTexture`.
+    pub map_for_cpu_access: bool,
+    /// Whether the contexts that sample the surface can wait for the rendering context with a
+    /// fence (`glFenceSync()`), rather than the rendering context calling `glFinish()`.
+    pub fence_sync: bool,
+}
+
+impl<NativeWidget> SurfaceIntent<NativeWidget> {
+    // Converts the native widget of a `DisplayedInWindow` intent with `f`, returning `None` if
+    // `f` does. Used by the multi backend to hand intents to the devices it wraps.
     #[allow(dead_code)]
+    pub(crate) fn map_native_widget<F, W>(self, f: F) -> Option<SurfaceIntent<W>>
+    where
+        F: FnOnce(NativeWidget) -> Option<W>,
+    {
+        Some(match self {
+            SurfaceIntent::OffscreenRenderTarget => SurfaceIntent::OffscreenRenderTarget,
+            SurfaceIntent::SharedWithOtherContext => SurfaceIntent::SharedWithOtherContext,
+            SurfaceIntent::DisplayedInWindow(native_widget) => {
+                SurfaceIntent::DisplayedInWindow(f(native_widget)?)
+            }
+            SurfaceIntent::CpuReadbackHeavy => SurfaceIntent::CpuReadbackHeavy,
+            SurfaceIntent::VideoImport => SurfaceIntent::VideoImport,
+        })
+    }
+}
+
+impl<NativeWidget> SurfaceConfig<NativeWidget> {
+    // Converts the native widget of a widget surface config with `f`.
+    #[allow(dead_code)]
+    pub(crate) fn map_native_widget<F, W>(self, f: F) -> SurfaceConfig<W>
+    where
+        F: FnOnce(NativeWidget) -> W,
+    {
+        let surface_type = match self.surface_type {
+            SurfaceType::Generic { size } => SurfaceType::Generic { size },
+            SurfaceType::Widget { native_widget } => SurfaceType::Widget {
+                native_widget: f(native_widget),
+            },
+        };
+        SurfaceConfig {
+            access: self.access,
+            surface_type,
+            options: self.options,
+            hints: self.hints,
+        }
+    }
+
+    // Returns the config for a surface of the given size that will be used as the intent says,
+    // created by a context with the given capabilities on a backend whose surfaces the CPU can
+    // map with `lock_surface_data()` if `cpu_mappable`.
+    #[allow(dead_code)]
+    pub(crate) fn recommend(
+        intent: SurfaceIntent<NativeWidget>,
+        size: Size2D<i32>,
+        cpu_mappable: bool,
+        capabilities: &GLCapabilities,
+    ) -> SurfaceConfig<NativeWidget> {
+        let (access, map_for_cpu_access, fence_sync) = match intent {
+            SurfaceIntent::OffscreenRenderTarget | SurfaceIntent::DisplayedInWindow(_) => {
+                (SurfaceAccess::GPUOnly, false, false)
+            }
+            SurfaceIntent::SharedWithOtherContext => {
+                (SurfaceAccess::GPUOnly, false, capabilities.fence_sync)
+            }
+            // Reads from write-combined memory are uncached, so readback wants plain `GPUCPU`.
+            SurfaceIntent::CpuReadbackHeavy if cpu_mappable => (SurfaceAccess::GPUCPU, true, false),
+            SurfaceIntent::VideoImport if cpu_mappable => {
+                (SurfaceAccess::GPUCPUWriteCombined, true, false)
+            }
+            SurfaceIntent::CpuReadbackHeavy | SurfaceIntent::VideoImport => {
+                (SurfaceAccess::GPUOnly, false, false)
+            }
+        };
+        let surface_type = match intent {
+            SurfaceIntent::DisplayedInWindow(native_widget) => {
+                SurfaceType::Widget { native_widget }
+            }
+            _ => SurfaceType::Generic { size },
+        };
+        SurfaceConfig {
+            access,
+            surface_type,
+            options: SurfaceOptions::default(),
+            hints: SurfaceHints {
+                map_for_cpu_access,
+                fence_sync,
+            },
+        }
+    }
+}
+
+impl SurfaceAccess {
     #[inline]
     pub(crate) fn cpu_access_allowed(self) -> bool {
         match self {
@@ -125,4 +809,25 @@ impl SurfaceAccess {
             SurfaceAccess::GPUCPU | SurfaceAccess::GPUCPUWriteCombined => true,
         }
     }
+
+    /// Checks that a surface with this access mode may be mapped by the CPU.
+    ///
+    /// This is cheap enough to call on every entry point that hands out surface data. Debug
+    /// builds also log the attempt, since it's usually a bug in the caller rather than something
+    /// to recover from.
+    pub(crate) fn check_cpu_access(self) -> Result<(), Error> {
+        if self.cpu_access_allowed() {
+            return Ok(());
+        }
+        if cfg!(debug_assertions) {
+            warn!(
+                "Attempted to access the data of a {:?} surface from the CPU",
+                self
+            );
+        }
+        Err(Error::InvalidSurfaceAccess {
+            required: SurfaceAccess::GPUCPU,
+            actual: self,
+        })
+    }
 }
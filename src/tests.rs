@@ -9,19 +9,106 @@
 #![allow(missing_docs)]
 
 use super::connection::Connection;
-use super::context::{Context, ContextDescriptor, NativeContext};
+use super::context::{Context, ContextDescriptor};
 use super::device::{Adapter, Device};
 use super::surface::Surface;
+use crate::acceleration;
+#[cfg(feature = "chains")]
+use crate::chains::{PreserveBuffer, ReallocationPolicy, SwapChain, SwapChains};
+#[cfg(feature = "chains")]
+use crate::chains::{SurfaceBinding, SurfaceProvider, SwapChainState};
+use crate::color::{ColorProfileTracker, PROFILE_CHECK_INTERVAL};
+use crate::connection;
+use crate::context::{self, ConfigCandidate};
+use crate::convert::{self, Swizzle};
+use crate::cpu_layer::{CpuLayer, MAX_DAMAGE_RECTS};
+use crate::current;
+#[cfg(feature = "sm-debug-view")]
+use crate::debug_view;
+use crate::diagnostics::{self, EnvironmentProbe, EnvironmentReport, Restriction, Sandbox};
+use crate::error::{self, GlError};
+#[cfg(feature = "chains")]
+use crate::frame_export::{Backpressure, ExportedFrame, FrameExporter, MAX_READBACKS_IN_FLIGHT};
 use crate::gl;
 use crate::gl::types::{GLenum, GLuint};
-use crate::{ContextAttributeFlags, ContextAttributes, Error, GLApi, GLVersion, Gl, SurfaceAccess};
-use crate::{SurfaceType, WindowingApiError};
-
-use euclid::default::Size2D;
+use crate::gl_utils;
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos"))
+))]
+use crate::identity::{drm_card_for_edid, DrmConnector};
+use crate::identity::{presenting_identity, DisplayOutput};
+use crate::info::CLAMP_TO_GLES2_ENV_VAR;
+use crate::invalidate::{self, PostPresentInvalidation};
+use crate::leak_check::{self, LeakCheckScope, TrackedObject};
+use crate::lost::LostContexts;
+use crate::memory::{PurgeState, PurgeableBacking, GL_RGB565};
+use crate::native_format::{self, NativeFormat, NativeFormatKind};
+use crate::pause::{HeldFrame, PresentPause};
+#[cfg(feature = "chains")]
+use crate::pipeline::{FrameInfo, Pipeline};
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos"))
+))]
+use crate::platform::generic::egl::context::SIMULATED_PBUFFER_LIMIT;
+#[cfg(all(free_unix, target_os = "linux"))]
+use crate::platform::generic::egl::kernel_priority;
+#[cfg(any(android_backend, angle, generic_egl_platform, ohos_backend))]
+use crate::platform::generic::egl::surface::applied_swap_interval;
+use crate::presentation_time;
+#[cfg(feature = "image")]
+use crate::readback;
+use crate::scale::ScaleTracker;
+#[cfg(feature = "chains")]
+use crate::scheduling::{FramePacer, BACKGROUND_FRAME_INTERVAL};
+use crate::surface::{clip_damage, PresentHistory};
+use crate::thread_safety::ThreadBound;
+use crate::vsync::AutoVsync;
+use crate::BufferRelease;
+use crate::ContextID;
+use crate::DebugFill;
+use crate::InvalidateSet;
+use crate::LowMemoryOptions;
+#[cfg(feature = "chains")]
+use crate::PausedPresentation;
+use crate::ResetStatus;
+use crate::SoftwareFallbackDetected;
+#[cfg(feature = "chains")]
+use crate::SurfaceID;
+use crate::SurfaceScaleChanged;
+use crate::TextureOwnership;
+use crate::WindowingApiError;
+use crate::{identities_match, AdapterKey, AdapterKind, DeviceIdentity};
+use crate::{AutoVsyncPolicy, VsyncDecision};
+use crate::{
+    ColorBits, ColorDepth, ColorEncoding, ColorProfile, ColorSpace, ContextAttributeFlags,
+    ContextAttributes, ContextPriority, Error,
+};
+use crate::{GLApi, GLCapabilities, GLVersion, Gl, PlaceholderPolicy, SurfaceAccess, SurfaceType};
+use crate::{PresentMode, Purgeability, ReclaimResult, SurfaceFormat, SurfaceInfo, SurfaceOptions};
+use crate::{PresentRecord, PresentationStatistics};
+use crate::{SchedulingClass, SchedulingEnforcement};
+use crate::{SurfaceConfig, SurfaceHints, SurfaceIntent, SurfaceTransform};
+
+use euclid::default::{Point2D, Rect, Size2D};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use serial_test::serial;
+use std::env;
+#[cfg(unix)]
+use std::fs::File;
+use std::mem;
+#[cfg(unix)]
+use std::os::fd::AsFd;
 use std::os::raw::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
+use std::time::Instant;
 
 static GL_VERSIONS: [GLVersion; 6] = [
     GLVersion { major: 2, minor: 0 },
@@ -50,697 +137,9305 @@ pub fn test_adapter_creation() {
 
 #[cfg_attr(not(feature = "sm-test"), test)]
 #[serial]
-pub fn test_device_creation() {
+pub fn test_create_adapter_by_name() {
+    assert!(connection::adapter_name_matches(
+        "NVIDIA GeForce RTX 3080",
+        "geforce"
+    ));
+    assert!(!connection::adapter_name_matches(
+        "Mesa Intel(R) UHD",
+        "radeon"
+    ));
+
     let connection = Connection::new().unwrap();
-    let adapter = connection
-        .create_low_power_adapter()
-        .expect("Failed to create adapter!");
+    match connection.create_adapter_by_name("no such adapter") {
+        Err(Error::NoAdapterFound) => {}
+        Err(err) => panic!("Unexpected error: {:?}", err),
+        Ok(_) => panic!("Found an adapter that shouldn't exist!"),
+    }
+
+    // Every name contains the empty string, so this fails only if adapter names are unavailable.
+    let adapter = match connection.create_adapter_by_name("") {
+        Ok(adapter) => adapter,
+        Err(Error::NoAdapterFound) => return,
+        Err(err) => panic!("Failed to create adapter: {:?}", err),
+    };
     match connection.create_device(&adapter) {
-        Ok(_) => {}
-        Err(Error::RequiredExtensionUnavailable) => {
-            // Can't run these tests on this hardware.
-        }
+        Ok(_) | Err(Error::RequiredExtensionUnavailable) => {}
         Err(err) => panic!("Failed to create device: {:?}", err),
     }
 }
 
+// Tests that adapter keys survive being saved as strings, and find the same adapter again.
 #[cfg_attr(not(feature = "sm-test"), test)]
 #[serial]
-pub fn test_device_accessors() {
+pub fn test_adapter_keys() {
     let connection = Connection::new().unwrap();
-    let adapter = connection.create_low_power_adapter().unwrap();
-    let device = match connection.create_device(&adapter) {
-        Ok(device) => device,
-        Err(Error::RequiredExtensionUnavailable) => {
-            // Can't run these tests on this hardware.
-            return;
+    let low_power_adapter = connection.create_low_power_adapter().unwrap();
+    let software_adapter = connection.create_software_adapter().unwrap();
+    for adapter in &[low_power_adapter.clone(), software_adapter] {
+        let key = connection.adapter_key(adapter).unwrap();
+        let parsed_key: AdapterKey = key.to_string().parse().unwrap();
+        assert_eq!(parsed_key, key);
+        let found_adapter = connection.find_adapter(&parsed_key).unwrap();
+        assert!(connection.is_same_adapter(&found_adapter, adapter));
+    }
+
+    // A GPU that has been removed isn't found.
+    let mut key = connection.adapter_key(&low_power_adapter).unwrap();
+    key.pci_id = Some((0xffff, 0xffff));
+    match connection.find_adapter(&key) {
+        Err(Error::NoAdapterFound) => {}
+        Err(err) => panic!("Unexpected error: {:?}", err),
+        Ok(_) => panic!("Found an adapter that shouldn't exist!"),
+    }
+
+    // Keys from other backends are rejected.
+    key.backend = "other".to_owned();
+    match connection.find_adapter(&key) {
+        Err(Error::IncompatibleAdapter) => {}
+        Err(err) => panic!("Unexpected error: {:?}", err),
+        Ok(_) => panic!("Found an adapter from another backend!"),
+    }
+
+    // Driver names may contain the characters that separate fields.
+    let key = AdapterKey {
+        backend: "x11".to_owned(),
+        kind: AdapterKind::LowPower,
+        pci_id: Some((0x8086, 0x9a49)),
+        adapter_luid: Some(0x1_0000_d1e5),
+        registry_id: None,
+        driver: Some("Mesa; Intel(R) kind=software".to_owned()),
+    };
+    assert_eq!(key.to_string().parse::<AdapterKey>().unwrap(), key);
+    assert!("kind=software".parse::<AdapterKey>().is_err());
+    assert!("backend=x11;kind=fast".parse::<AdapterKey>().is_err());
+
+    #[cfg(feature = "serde")]
+    {
+        use serde::de::value::{Error as ValueError, StrDeserializer};
+        use serde::de::{Deserialize, IntoDeserializer};
+
+        let string = key.to_string();
+        let deserializer: StrDeserializer<ValueError> = string.as_str().into_deserializer();
+        assert_eq!(AdapterKey::deserialize(deserializer).unwrap(), key);
+        let deserializer: StrDeserializer<ValueError> = "kind=software".into_deserializer();
+        assert!(AdapterKey::deserialize(deserializer).is_err());
+    }
+}
+
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos"))
+))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_headless_platform_fallback_order() {
+    use crate::platform::unix::generic::connection::{self as generic, HeadlessPlatform};
+
+    // Surfaceless Mesa is preferred, so EGL devices aren't even enumerated when it works.
+    let mut tried = vec![];
+    let opened = generic::open_first_headless_platform(|platform| {
+        tried.push(platform);
+        Some(platform)
+    });
+    assert_eq!(opened.unwrap(), HeadlessPlatform::SurfacelessMesa);
+    assert_eq!(tried, [HeadlessPlatform::SurfacelessMesa]);
+
+    // Without Mesa, fall back to an EGL device.
+    let mut tried = vec![];
+    let opened = generic::open_first_headless_platform(|platform| {
+        tried.push(platform);
+        if platform == HeadlessPlatform::Device {
+            Some(platform)
+        } else {
+            None
         }
-        Err(err) => panic!("Failed to create device: {:?}", err),
+    });
+    assert_eq!(opened.unwrap(), HeadlessPlatform::Device);
+    assert_eq!(
+        tried,
+        [HeadlessPlatform::SurfacelessMesa, HeadlessPlatform::Device]
+    );
+
+    // If nothing opens, the connection fails.
+    match generic::open_first_headless_platform(|_| None::<HeadlessPlatform>) {
+        Err(Error::ConnectionFailed) => {}
+        other => panic!("Expected `ConnectionFailed`, got {:?}", other),
+    }
+}
+
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos"))
+))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_egl_display_terminated_with_last_connection() {
+    use crate::connection::NativeConnection as _;
+    use crate::egl;
+    use crate::egl::types::{EGLDisplay, EGLint};
+    use crate::platform::generic::egl::device::EGL_FUNCTIONS;
+
+    fn is_initialized(egl_display: EGLDisplay) -> bool {
+        EGL_FUNCTIONS.with(|egl| unsafe {
+            !egl.QueryString(egl_display, egl::VENDOR as EGLint)
+                .is_null()
+        })
+    }
+
+    let connection = match Connection::new_headless() {
+        Ok(connection) => connection,
+        Err(Error::ConnectionFailed) => return,
+        Err(err) => panic!("Failed to open a headless connection: {:?}", err),
     };
-    drop(device.connection());
-    drop(device.adapter());
+    let egl_display = connection.native_connection().egl_display();
+    assert!(is_initialized(egl_display));
+
+    // A second connection shares the display, which stays alive until both are dropped.
+    let other_connection = Connection::new_headless().unwrap();
+    assert_eq!(
+        other_connection.native_connection().egl_display(),
+        egl_display
+    );
+    let clone = connection.clone();
+    drop(connection);
+    drop(other_connection);
+    assert!(is_initialized(egl_display));
+    drop(clone);
+    assert!(!is_initialized(egl_display));
+
+    // Connections wrapping a display never terminate it.
+    let connection = Connection::new_headless().unwrap();
+    let native_connection = connection.native_connection();
+    let egl_display = native_connection.egl_display();
+    let borrowed_connection = unsafe { Connection::from_native_connection(native_connection) };
+    drop(borrowed_connection.unwrap());
+    assert!(is_initialized(egl_display));
+    drop(connection);
+    assert!(!is_initialized(egl_display));
 }
 
-// Tests that all combinations of flags result in the creation of valid context descriptors and
-// contexts.
+// Tests that threads racing to connect either all fail alike or all get connections, which share
+// their EGL displays' use counts coherently and terminate them once all are dropped.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos"))
+))]
 #[cfg_attr(not(feature = "sm-test"), test)]
 #[serial]
-pub fn test_context_creation() {
-    let connection = Connection::new().unwrap();
-    let adapter = connection
-        .create_low_power_adapter()
-        .expect("Failed to create adapter!");
-    let mut device = match connection.create_device(&adapter) {
-        Ok(device) => device,
-        Err(Error::RequiredExtensionUnavailable) => {
-            // Can't run these tests on this hardware.
-            return;
+pub fn test_concurrent_connections() {
+    use crate::connection::NativeConnection as _;
+    use crate::egl;
+    use crate::egl::types::{EGLDisplay, EGLint};
+    use crate::platform::generic::egl::device::EGL_FUNCTIONS;
+    use crate::platform::unix::generic::connection as generic;
+    use std::collections::HashMap;
+    use std::sync::Barrier;
+
+    const THREAD_COUNT: usize = 16;
+
+    fn is_initialized(egl_display: EGLDisplay) -> bool {
+        EGL_FUNCTIONS.with(|egl| unsafe {
+            !egl.QueryString(egl_display, egl::VENDOR as EGLint)
+                .is_null()
+        })
+    }
+
+    let barrier = Arc::new(Barrier::new(THREAD_COUNT));
+    let threads: Vec<_> = (0..THREAD_COUNT)
+        .map(|_| {
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                Connection::new()
+            })
+        })
+        .collect();
+    let results: Vec<_> = threads
+        .into_iter()
+        .map(|thread| thread.join().unwrap())
+        .collect();
+
+    let mut connections = vec![];
+    let mut errors = vec![];
+    for result in results {
+        match result {
+            Ok(connection) => connections.push(connection),
+            Err(err) => errors.push(format!("{:?}", err)),
         }
-        Err(err) => panic!("Failed to create device: {:?}", err),
-    };
+    }
+    if connections.is_empty() {
+        assert!(errors.iter().all(|err| *err == errors[0]), "{:?}", errors);
+        return;
+    }
+    assert!(errors.is_empty(), "Some connections failed: {:?}", errors);
 
-    let gl_api = device.gl_api();
-    let versions = match gl_api {
-        GLApi::GL => &GL_VERSIONS[..],
-        GLApi::GLES => &GL_ES_VERSIONS[..],
-    };
+    let mut connection_counts: HashMap<usize, usize> = HashMap::new();
+    for connection in &connections {
+        *connection_counts
+            .entry(connection.native_connection().egl_display() as usize)
+            .or_default() += 1;
+    }
+    for (&egl_display, &connection_count) in &connection_counts {
+        let egl_display = egl_display as EGLDisplay;
+        assert!(is_initialized(egl_display));
+        assert_eq!(
+            generic::egl_display_use_count(egl_display),
+            connection_count
+        );
+    }
 
-    for &version in versions {
-        for flag_bits in 0..(ContextAttributeFlags::all().bits() + 1) {
-            let flags = ContextAttributeFlags::from_bits_truncate(flag_bits);
-            let attributes = ContextAttributes { version, flags };
-            let descriptor = match device.create_context_descriptor(&attributes) {
-                Ok(descriptor) => descriptor,
-                Err(Error::UnsupportedGLProfile) | Err(Error::UnsupportedGLVersion) => {
-                    // Nothing we can do about this. Go on to the next one.
-                    continue;
-                }
-                Err(err) => panic!("Context descriptor creation failed: {:?}", err),
-            };
+    drop(connections);
+    for &egl_display in connection_counts.keys() {
+        let egl_display = egl_display as EGLDisplay;
+        assert_eq!(generic::egl_display_use_count(egl_display), 0);
+        assert!(!is_initialized(egl_display));
+    }
+}
 
-            match device.create_context(&descriptor, None) {
-                Ok(mut context) => {
-                    // Verify that the attributes round-trip.
-                    let actual_descriptor = device.context_descriptor(&context);
-                    let actual_attributes =
-                        device.context_descriptor_attributes(&actual_descriptor);
-                    if !actual_attributes.flags.contains(attributes.flags) {
-                        device.destroy_context(&mut context).unwrap();
-                        panic!(
-                            "Expected at least attribute flags {:?} for {:?} {:?} but got \
-                                {:?}",
-                            attributes.flags, gl_api, version, actual_attributes.flags
-                        );
-                    }
-                    if actual_attributes.version.major < attributes.version.major
-                        || (actual_attributes.version.major == attributes.version.major
-                            && actual_attributes.version.minor < attributes.version.minor)
-                    {
-                        device.destroy_context(&mut context).unwrap();
-                        panic!(
-                            "Expected at least GL version {:?} but got version {:?}",
-                            attributes, actual_attributes
-                        );
-                    }
+// Tests that single-buffered widget surfaces only get configs with a mutable render buffer, and
+// fail with `UnsupportedPresentMode` where the display has none.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos"))
+))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_single_buffer_widget_config() {
+    use crate::connection::NativeConnection as _;
+    use crate::egl;
+    use crate::egl::types::EGLint;
+    use crate::platform::generic::egl::context as egl_context;
+    use crate::platform::generic::egl::device::{self as egl_device, EGL_FUNCTIONS};
+    use crate::platform::generic::egl::ffi::EGL_MUTABLE_RENDER_BUFFER_BIT_KHR;
+    use std::ptr;
+
+    let connection = match Connection::new_headless() {
+        Ok(connection) => connection,
+        Err(Error::ConnectionFailed) => return,
+        Err(err) => panic!("Failed to open a headless connection: {:?}", err),
+    };
+    let egl_display = connection.native_connection().egl_display();
+    let mut egl_config = ptr::null();
+    let mut config_count = 0;
+    EGL_FUNCTIONS.with(|egl| unsafe {
+        egl.GetConfigs(egl_display, &mut egl_config, 1, &mut config_count);
+    });
+    if config_count == 0 {
+        return;
+    }
 
-                    device.destroy_context(&mut context).unwrap();
-                }
-                Err(Error::ContextCreationFailed(WindowingApiError::BadPixelFormat))
-                | Err(Error::ContextCreationFailed(WindowingApiError::BadConfig))
-                | Err(Error::ContextCreationFailed(WindowingApiError::BadMatch)) => {
-                    // This is OK, as it just means the GL implementation didn't support the
-                    // requested GL version.
-                }
-                Err(error) => {
-                    panic!(
-                        "Failed to create context ({:?}/{:?}): {:?}",
-                        version, flags, error
-                    )
-                }
-            }
+    let widget_config = |present_mode| unsafe {
+        let options = SurfaceOptions {
+            present_mode,
+            ..SurfaceOptions::default()
+        };
+        egl_context::widget_config(egl_display, egl_config, &options, None, &|_| None)
+    };
+    assert_eq!(
+        widget_config(PresentMode::DoubleBuffer).ok(),
+        Some(egl_config)
+    );
+    let mutable_render_buffer = unsafe {
+        egl_device::display_supports_extension(egl_display, "EGL_KHR_mutable_render_buffer")
+    };
+    match widget_config(PresentMode::SingleBuffer) {
+        Ok(egl_config) => {
+            assert!(mutable_render_buffer);
+            let surface_type = unsafe {
+                egl_context::get_config_attr(egl_display, egl_config, egl::SURFACE_TYPE as EGLint)
+            };
+            assert_ne!(surface_type & EGL_MUTABLE_RENDER_BUFFER_BIT_KHR, 0);
         }
+        Err(Error::UnsupportedPresentMode) => {}
+        Err(err) => panic!("Choosing a single-buffered config failed with {:?}", err),
     }
 }
 
-// Tests that newly-created contexts are not immediately made current (issue #7).
+// Tests that widget surfaces that preserve their buffer only get configs that can, and fail with
+// `UnsupportedSwapBehavior` where the display has none.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos"))
+))]
 #[cfg_attr(not(feature = "sm-test"), test)]
 #[serial]
-pub fn test_newly_created_contexts_are_not_current() {
-    let connection = Connection::new().unwrap();
-    let adapter = connection
-        .create_low_power_adapter()
-        .expect("Failed to create adapter!");
-    let mut device = match connection.create_device(&adapter) {
-        Ok(device) => device,
-        Err(Error::RequiredExtensionUnavailable) => {
-            // Can't run these tests on this hardware.
-            return;
-        }
-        Err(err) => panic!("Failed to create device: {:?}", err),
+pub fn test_preserved_swap_widget_config() {
+    use crate::connection::NativeConnection as _;
+    use crate::egl;
+    use crate::egl::types::EGLint;
+    use crate::platform::generic::egl::context as egl_context;
+    use crate::platform::generic::egl::device::EGL_FUNCTIONS;
+    use std::ptr;
+
+    let connection = match Connection::new_headless() {
+        Ok(connection) => connection,
+        Err(Error::ConnectionFailed) => return,
+        Err(err) => panic!("Failed to open a headless connection: {:?}", err),
     };
+    let egl_display = connection.native_connection().egl_display();
+    let mut egl_config = ptr::null();
+    let mut config_count = 0;
+    EGL_FUNCTIONS.with(|egl| unsafe {
+        egl.GetConfigs(egl_display, &mut egl_config, 1, &mut config_count);
+    });
+    if config_count == 0 {
+        return;
+    }
 
-    let context_descriptor = device
-        .create_context_descriptor(&ContextAttributes {
-            version: GLVersion::new(3, 0),
-            flags: ContextAttributeFlags::empty(),
-        })
-        .unwrap();
-
-    // Make no context current.
-    device.make_no_context_current().unwrap();
+    let options = SurfaceOptions {
+        preserve_buffer: true,
+        ..SurfaceOptions::default()
+    };
+    match unsafe { egl_context::widget_config(egl_display, egl_config, &options, None, &|_| None) }
+    {
+        Ok(egl_config) => {
+            let surface_type = unsafe {
+                egl_context::get_config_attr(egl_display, egl_config, egl::SURFACE_TYPE as EGLint)
+            };
+            assert_ne!(surface_type & egl::SWAP_BEHAVIOR_PRESERVED_BIT as EGLint, 0);
+            assert_ne!(surface_type & egl::WINDOW_BIT as EGLint, 0);
+        }
+        Err(Error::UnsupportedSwapBehavior) => {}
+        Err(err) => panic!("Choosing a preserving config failed with {:?}", err),
+    }
+}
 
-    let mut context = device.create_context(&context_descriptor, None).unwrap();
-    let gl = Gl::load_with(|symbol| device.get_proc_address(&context, symbol));
+// Tests that an X server without usable EGL fails the connection instead of aborting.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos")),
+    feature = "sm-x11"
+))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_x11_egl_display_failure() {
+    use crate::platform::unix::x11::connection as x11;
+    use crate::platform::unix::x11::connection::Connection as X11Connection;
 
+    // With a null display, EGL opens its own connection to `DISPLAY`, which can't succeed here.
+    let old_display = env::var_os("DISPLAY");
+    env::set_var("DISPLAY", "surfman-bogus-host:99");
     unsafe {
-        // Check to make sure GL calls don't work before a context is made current.
-        clear(&gl, &[255, 0, 0, 255]);
-        assert_ne!(get_pixel_from_bottom_row(&gl), [255, 0, 0, 255]);
-
-        // Make a context current.
-        let surface = make_surface(&mut device, &context);
-        device
-            .bind_surface_to_context(&mut context, surface)
-            .unwrap();
-        device.make_context_current(&context).unwrap();
-        let framebuffer_object = device
-            .context_surface_info(&context)
-            .unwrap()
-            .unwrap()
-            .framebuffer_object;
-        gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
+        match x11::create_egl_display(std::ptr::null_mut(), true) {
+            Err(Error::ConnectionFailed) => {}
+            Err(err) => panic!("Expected `ConnectionFailed`, got {:?}", err),
+            Ok(_) => panic!("Expected `ConnectionFailed`, got a display"),
+        }
+    }
+    match X11Connection::new() {
+        Err(Error::ConnectionFailed) => {}
+        Err(err) => panic!("Expected `ConnectionFailed`, got {:?}", err),
+        Ok(_) => panic!("Expected `ConnectionFailed`, got a connection"),
+    }
+    match old_display {
+        Some(old_display) => env::set_var("DISPLAY", old_display),
+        None => env::remove_var("DISPLAY"),
+    }
+}
 
-        // Now that a context is current, GL calls should work.
-        clear(&gl, &[0, 255, 0, 255]);
-        assert_eq!(get_pixel_from_bottom_row(&gl), [0, 255, 0, 255]);
+// Tests that EGL display creation falls back from core `eglGetPlatformDisplay` to
+// `eglGetPlatformDisplayEXT` to `eglGetDisplay`, and reports every failure when all three fail.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos"))
+))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_egl_platform_display_fallback() {
+    use crate::egl;
+    use crate::egl::types::{EGLAttrib, EGLDisplay, EGLenum, EGLint};
+    use crate::platform::generic::egl::device::{self, DisplayEntryPoint, DisplayFunctions};
+    use std::cell::Cell;
+    use std::os::raw::c_void;
+
+    thread_local! {
+        static FAKE_ERROR: Cell<EGLint> = const { Cell::new(egl::SUCCESS as EGLint) };
+    }
+    let fake_display = 0x5eed as EGLDisplay;
 
-        // Remove the surface.
-        let mut surface = device
-            .unbind_surface_from_context(&mut context)
-            .unwrap()
-            .unwrap();
-        device.make_no_context_current().unwrap();
+    unsafe fn fail(error: EGLenum) -> EGLDisplay {
+        FAKE_ERROR.with(|fake_error| fake_error.set(error as EGLint));
+        egl::NO_DISPLAY
+    }
+    unsafe fn core_ok(_: EGLenum, _: *mut c_void, _: *const EGLAttrib) -> EGLDisplay {
+        0x5eed as EGLDisplay
+    }
+    unsafe fn core_bad_parameter(_: EGLenum, _: *mut c_void, _: *const EGLAttrib) -> EGLDisplay {
+        fail(egl::BAD_PARAMETER)
+    }
+    unsafe fn ext_ok(_: EGLenum, _: *mut c_void, _: *const EGLint) -> EGLDisplay {
+        0x5eed as EGLDisplay
+    }
+    unsafe fn ext_bad_parameter(_: EGLenum, _: *mut c_void, _: *const EGLint) -> EGLDisplay {
+        fail(egl::BAD_PARAMETER)
+    }
+    unsafe fn legacy_ok(_: *mut c_void) -> EGLDisplay {
+        0x5eed as EGLDisplay
+    }
+    unsafe fn legacy_bad_display(_: *mut c_void) -> EGLDisplay {
+        fail(egl::BAD_DISPLAY)
+    }
+    unsafe fn get_error() -> EGLint {
+        FAKE_ERROR.with(|fake_error| fake_error.replace(egl::SUCCESS as EGLint))
+    }
 
-        // GL calls shouldn't work any longer.
-        clear(&gl, &[255, 0, 0, 255]);
-        assert_ne!(get_pixel_from_bottom_row(&gl), [255, 0, 0, 255]);
+    let cases: Vec<(DisplayFunctions, DisplayEntryPoint)> = vec![
+        (
+            DisplayFunctions {
+                get_platform_display: Some(core_ok),
+                get_platform_display_ext: Some(ext_ok),
+                get_display: Some(legacy_ok),
+                get_error,
+            },
+            DisplayEntryPoint::GetPlatformDisplay,
+        ),
+        // Mesa in Ubuntu 18.04 behind libglvnd.
+        (
+            DisplayFunctions {
+                get_platform_display: Some(core_bad_parameter),
+                get_platform_display_ext: Some(ext_ok),
+                get_display: Some(legacy_ok),
+                get_error,
+            },
+            DisplayEntryPoint::GetPlatformDisplayEXT,
+        ),
+        (
+            DisplayFunctions {
+                get_platform_display: None,
+                get_platform_display_ext: Some(ext_bad_parameter),
+                get_display: Some(legacy_ok),
+                get_error,
+            },
+            DisplayEntryPoint::GetDisplay,
+        ),
+    ];
+    for (functions, expected) in cases {
+        let result = unsafe { device::get_platform_display(&functions, 0, std::ptr::null_mut()) };
+        match result {
+            Ok((egl_display, entry_point)) => {
+                assert_eq!(egl_display, fake_display);
+                assert_eq!(entry_point, expected);
+            }
+            Err(err) => panic!("Expected a display from {:?}, got {:?}", expected, err),
+        }
+    }
 
-        // Clean up.
-        device.destroy_surface(&mut context, &mut surface).unwrap();
-        device.destroy_context(&mut context).unwrap();
+    let functions = DisplayFunctions {
+        get_platform_display: Some(core_bad_parameter),
+        get_platform_display_ext: None,
+        get_display: Some(legacy_bad_display),
+        get_error,
+    };
+    match unsafe { device::get_platform_display(&functions, 0, std::ptr::null_mut()) } {
+        Err(
+            err @ Error::PlatformDisplayFailed {
+                get_platform_display: Some(WindowingApiError::BadParameter),
+                get_platform_display_ext: None,
+                get_display: Some(WindowingApiError::BadDisplay),
+            },
+        ) => assert_eq!(
+            err.to_string(),
+            "couldn't create an EGL display: eglGetPlatformDisplay: BadParameter; \
+             eglGetPlatformDisplayEXT: unavailable; eglGetDisplay: BadDisplay"
+        ),
+        other => panic!("Expected `PlatformDisplayFailed`, got {:?}", other),
     }
 }
 
-// Tests a simple case of one context being shared with another.
+// Tests that an X11 connection cloned for another thread has a display of its own, and works on
+// that thread.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos")),
+    feature = "sm-x11"
+))]
 #[cfg_attr(not(feature = "sm-test"), test)]
 #[serial]
-pub fn test_context_sharing() {
-    let connection = Connection::new().unwrap();
-    let adapter = connection
-        .create_low_power_adapter()
-        .expect("Failed to create adapter!");
-    let mut device = match connection.create_device(&adapter) {
-        Ok(device) => device,
-        Err(Error::RequiredExtensionUnavailable) => {
-            // Can't run these tests on this hardware.
-            return;
-        }
-        Err(err) => panic!("Failed to create device: {:?}", err),
+pub fn test_x11_try_clone_for_thread() {
+    use crate::platform::unix::x11::connection::Connection as X11Connection;
+
+    let connection = match X11Connection::new() {
+        Ok(connection) => connection,
+        Err(Error::ConnectionFailed) => return,
+        Err(err) => panic!("Failed to open X11 connection: {:?}", err),
     };
+    let clone = connection.try_clone_for_thread().unwrap();
+    let display = connection.native_connection().x11_display;
+    let clone_display = clone.native_connection().x11_display;
+    assert!(!clone_display.is_null());
+    assert_ne!(clone_display, display);
+    assert_eq!(clone.gl_api(), connection.gl_api());
 
-    let context_descriptor = device
-        .create_context_descriptor(&ContextAttributes {
-            version: GLVersion::new(3, 0),
-            flags: ContextAttributeFlags::empty(),
-        })
-        .unwrap();
+    thread::spawn(move || {
+        let adapter = clone.create_adapter().unwrap();
+        clone.create_device(&adapter).unwrap();
+    })
+    .join()
+    .unwrap();
 
-    let mut parent_context = device.create_context(&context_descriptor, None).unwrap();
-    let mut child1_context = device
-        .create_context(&context_descriptor, Some(&parent_context))
-        .unwrap();
-    let mut child2_context = device
-        .create_context(&context_descriptor, Some(&parent_context))
-        .unwrap();
-    let mut child3_context = device
-        .create_context(&context_descriptor, Some(&child1_context))
+    let headless = X11Connection::new_headless()
+        .unwrap()
+        .try_clone_for_thread()
         .unwrap();
-
-    device.destroy_context(&mut child3_context).unwrap();
-    device.destroy_context(&mut child2_context).unwrap();
-    device.destroy_context(&mut child1_context).unwrap();
-    device.destroy_context(&mut parent_context).unwrap();
+    assert!(headless.native_connection().x11_display.is_null());
+}
+
+// Tests that X11 native widgets report the real size of their window when the caller's size is
+// wrong.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos")),
+    feature = "sm-x11"
+))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_x11_native_widget_size_adjustment() {
+    use crate::platform::unix::x11::connection::Connection as X11Connection;
+    use x11::xlib::{XCreateSimpleWindow, XDefaultRootWindow, XDestroyWindow, XSync};
+
+    let connection = match X11Connection::new() {
+        Ok(connection) => connection,
+        Err(Error::ConnectionFailed) => return,
+        Err(err) => panic!("Failed to open X11 connection: {:?}", err),
+    };
+    let display = connection.native_connection().x11_display;
+    let window_size = Size2D::new(64, 48);
+    unsafe {
+        let window = XCreateSimpleWindow(
+            display,
+            XDefaultRootWindow(display),
+            0,
+            0,
+            window_size.width as u32,
+            window_size.height as u32,
+            0,
+            0,
+            0,
+        );
+        XSync(display, 0);
+
+        let native_widget =
+            connection.create_native_widget_from_ptr(window as *mut c_void, Size2D::new(640, 480));
+        assert_eq!(
+            connection.native_widget_adjusted_size(&native_widget),
+            Some(window_size)
+        );
+        let native_widget =
+            connection.create_native_widget_from_ptr(window as *mut c_void, window_size);
+        assert_eq!(connection.native_widget_adjusted_size(&native_widget), None);
+
+        XDestroyWindow(display, window);
+    }
+}
+
+// Tests that a Wayland widget surface takes on the size of its `wl_egl_window` when a toolkit
+// resizes the window itself, and reports it once a frame at the new size has been presented.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos")),
+    feature = "sm-wayland",
+    feature = "sm-raw-window-handle-06"
+))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_wayland_external_resize() {
+    use crate::platform::unix::wayland::connection::Connection as WaylandConnection;
+    use crate::platform::unix::wayland::context::Context as WaylandContext;
+    use crate::platform::unix::wayland::device::Device as WaylandDevice;
+    use crate::platform::unix::wayland::surface::Surface as WaylandSurface;
+    use rwh_06::{HasDisplayHandle, HasWindowHandle};
+    use wayland_sys::egl::{wl_egl_window, WAYLAND_EGL_HANDLE};
+    use winit::dpi::PhysicalSize;
+    use winit::event_loop::EventLoopBuilder;
+    use winit::platform::wayland::EventLoopBuilderExtWayland;
+    use winit::window::WindowBuilder;
+
+    let event_loop = match EventLoopBuilder::new()
+        .with_wayland()
+        .with_any_thread(true)
+        .build()
+    {
+        Ok(event_loop) => event_loop,
+        Err(_) => return,
+    };
+    let window_size = Size2D::new(64, 48);
+    let window = WindowBuilder::new()
+        .with_inner_size(PhysicalSize::new(
+            window_size.width as u32,
+            window_size.height as u32,
+        ))
+        .build(&event_loop)
+        .unwrap();
+
+    let connection =
+        WaylandConnection::from_display_handle(window.display_handle().unwrap()).unwrap();
+    let native_widget = connection
+        .create_native_widget_from_window_handle(window.window_handle().unwrap(), window_size)
+        .unwrap();
+    let adapter = connection.create_adapter().unwrap();
+    let mut device = connection.create_device(&adapter).unwrap();
+    let descriptor = device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::empty(),
+            ..Default::default()
+        })
+        .unwrap();
+    let mut context = device.create_context(&descriptor, None).unwrap();
+    let gl = Gl::load_with(|symbol| {
+        device
+            .get_proc_address(&context, symbol)
+            .unwrap_or(std::ptr::null())
+    });
+    let surface = device
+        .create_surface(
+            &context,
+            SurfaceAccess::GPUOnly,
+            SurfaceType::Widget { native_widget },
+        )
+        .unwrap();
+
+    let resizes = Arc::new(Mutex::new(vec![]));
+    let handler_resizes = resizes.clone();
+    device.set_surface_resized_handler(Some(Box::new(move |surface_id, size| {
+        handler_resizes.lock().unwrap().push((surface_id, size));
+    })));
+
+    // Draws a frame at whatever size the window's buffer is, then presents it.
+    let present_frame = |device: &WaylandDevice,
+                         context: &mut WaylandContext,
+                         surface: WaylandSurface|
+     -> WaylandSurface {
+        device.bind_surface_to_context(context, surface).unwrap();
+        device.begin_rendering(context).unwrap();
+        clear(&gl, &[0, 255, 0, 255]);
+        device.end_rendering(context, true).unwrap();
+        let mut surface = device
+            .unbind_surface_from_context(context)
+            .unwrap()
+            .unwrap();
+        device.present_surface(context, &mut surface).unwrap();
+        surface
+    };
+
+    let surface = present_frame(&device, &mut context, surface);
+    assert_eq!(device.surface_info(&surface).size, window_size);
+    assert!(resizes.lock().unwrap().is_empty());
+
+    // Resizing through surfman isn't reported.
+    let mut surface = surface;
+    device
+        .resize_surface(&context, &mut surface, Size2D::new(80, 60))
+        .unwrap();
+    let surface = present_frame(&device, &mut context, surface);
+    let surface = present_frame(&device, &mut context, surface);
+    assert_eq!(device.surface_info(&surface).size, Size2D::new(80, 60));
+    assert!(resizes.lock().unwrap().is_empty());
+
+    // Resize the window behind surfman's back, as a toolkit would. The next frame is drawn at the
+    // new size, and presenting it brings the surface up to date.
+    let external_size = Size2D::new(96, 72);
+    unsafe {
+        let wayland_egl_window = surface.0.native_window().unwrap() as *mut wl_egl_window;
+        (WAYLAND_EGL_HANDLE.wl_egl_window_resize)(
+            wayland_egl_window,
+            external_size.width,
+            external_size.height,
+            0,
+            0,
+        );
+    }
+    let surface = present_frame(&device, &mut context, surface);
+    assert_eq!(device.surface_info(&surface).size, external_size);
+    assert_eq!(
+        *resizes.lock().unwrap(),
+        vec![(device.surface_info(&surface).id, external_size)]
+    );
+    let records = device.presentation_timestamps(&surface);
+    let resized: Vec<_> = records
+        .iter()
+        .map(|record| record.resized_externally)
+        .collect();
+    assert_eq!(resized, [false, false, false, true]);
+
+    // Damage measured against the old size is ignored once the window has been resized again.
+    let resized_size = Size2D::new(48, 36);
+    unsafe {
+        let wayland_egl_window = surface.0.native_window().unwrap() as *mut wl_egl_window;
+        (WAYLAND_EGL_HANDLE.wl_egl_window_resize)(
+            wayland_egl_window,
+            resized_size.width,
+            resized_size.height,
+            0,
+            0,
+        );
+    }
+    device
+        .bind_surface_to_context(&mut context, surface)
+        .unwrap();
+    device.begin_rendering(&context).unwrap();
+    clear(&gl, &[0, 0, 255, 255]);
+    device.end_rendering(&context, true).unwrap();
+    let mut surface = device
+        .unbind_surface_from_context(&mut context)
+        .unwrap()
+        .unwrap();
+    let damage = [Rect::new(Point2D::new(64, 48), Size2D::new(32, 24))];
+    device
+        .present_surface_with_damage(&context, &mut surface, &damage)
+        .unwrap();
+    assert_eq!(device.surface_info(&surface).size, resized_size);
+    assert_eq!(device.presentation_timestamps(&surface).len(), 5);
+
+    device.destroy_surface(&mut context, &mut surface).unwrap();
+    device.destroy_context(&mut context).unwrap();
+}
+
+// Tests that a Wayland widget surface reports the assumed sRGB profile when the compositor doesn't
+// offer the color management protocol, and a profile either way once frames have been presented.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos")),
+    feature = "sm-wayland",
+    feature = "sm-raw-window-handle-06"
+))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_wayland_color_profile_fallback() {
+    use crate::platform::unix::wayland::connection::Connection as WaylandConnection;
+    use rwh_06::{HasDisplayHandle, HasWindowHandle};
+    use winit::dpi::PhysicalSize;
+    use winit::event_loop::EventLoopBuilder;
+    use winit::platform::wayland::EventLoopBuilderExtWayland;
+    use winit::window::WindowBuilder;
+
+    let event_loop = match EventLoopBuilder::new()
+        .with_wayland()
+        .with_any_thread(true)
+        .build()
+    {
+        Ok(event_loop) => event_loop,
+        Err(_) => return,
+    };
+    let window_size = Size2D::new(64, 48);
+    let window = WindowBuilder::new()
+        .with_inner_size(PhysicalSize::new(
+            window_size.width as u32,
+            window_size.height as u32,
+        ))
+        .build(&event_loop)
+        .unwrap();
+
+    let connection =
+        WaylandConnection::from_display_handle(window.display_handle().unwrap()).unwrap();
+    let native_widget = connection
+        .create_native_widget_from_window_handle(window.window_handle().unwrap(), window_size)
+        .unwrap();
+    let adapter = connection.create_adapter().unwrap();
+    let mut device = connection.create_device(&adapter).unwrap();
+    let descriptor = device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::empty(),
+            ..Default::default()
+        })
+        .unwrap();
+    let mut context = device.create_context(&descriptor, None).unwrap();
+    let mut surface = device
+        .create_surface(
+            &context,
+            SurfaceAccess::GPUOnly,
+            SurfaceType::Widget { native_widget },
+        )
+        .unwrap();
+
+    let changes = Arc::new(Mutex::new(0));
+    let handler_changes = changes.clone();
+    device.set_color_profile_changed_handler(Some(Box::new(move |_, _| {
+        *handler_changes.lock().unwrap() += 1;
+    })));
+    for _ in 0..2 {
+        device.present_surface(&context, &mut surface).unwrap();
+    }
+    let profile = device.widget_color_profile(&surface);
+    if surface.0.color_profile_source.is_none() {
+        assert_eq!(profile, ColorProfile::AssumedSrgb);
+    }
+    assert_eq!(*changes.lock().unwrap(), 0);
+
+    let mut generic_surface = device
+        .create_surface(
+            &context,
+            SurfaceAccess::GPUOnly,
+            SurfaceType::Generic { size: window_size },
+        )
+        .unwrap();
+    assert_eq!(
+        device.widget_color_profile(&generic_surface),
+        ColorProfile::AssumedSrgb
+    );
+
+    device
+        .destroy_surface(&mut context, &mut generic_surface)
+        .unwrap();
+    device.destroy_surface(&mut context, &mut surface).unwrap();
+    device.destroy_context(&mut context).unwrap();
+}
+
+// Tests that the image descriptions of the Wayland color management protocol are turned into the
+// profiles that surfman knows.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos")),
+    feature = "sm-wayland"
+))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_color_profile_from_image_description() {
+    use crate::platform::unix::wayland::surface::{
+        color_profile_from_description, ImageDescriptionInfo,
+    };
+
+    // Named primaries win over chromaticities.
+    let info = ImageDescriptionInfo {
+        primaries: Some([
+            640000, 330000, 300000, 600000, 150000, 60000, 312700, 329000,
+        ]),
+        primaries_named: Some(9),
+        ..ImageDescriptionInfo::default()
+    };
+    assert_eq!(
+        color_profile_from_description(info),
+        ColorProfile::Named(ColorSpace::DisplayP3)
+    );
+
+    // Chromaticities are matched with some tolerance for rounding.
+    let info = ImageDescriptionInfo {
+        primaries: Some([
+            708100, 291900, 170000, 797000, 131000, 46000, 312700, 329000,
+        ]),
+        ..ImageDescriptionInfo::default()
+    };
+    assert_eq!(
+        color_profile_from_description(info),
+        ColorProfile::Named(ColorSpace::Bt2020)
+    );
+
+    // Unknown primaries, such as those of PAL, and no information at all are taken as sRGB.
+    let info = ImageDescriptionInfo {
+        primaries: Some([
+            640000, 330000, 290000, 600000, 150000, 60000, 312700, 329000,
+        ]),
+        primaries_named: Some(3),
+        ..ImageDescriptionInfo::default()
+    };
+    assert_eq!(
+        color_profile_from_description(info),
+        ColorProfile::AssumedSrgb
+    );
+    assert_eq!(
+        color_profile_from_description(ImageDescriptionInfo::default()),
+        ColorProfile::AssumedSrgb
+    );
+
+    // A malformed ICC profile falls back to the primaries.
+    let info = ImageDescriptionInfo {
+        icc_file: Some(vec![0; 16]),
+        primaries_named: Some(10),
+        ..ImageDescriptionInfo::default()
+    };
+    assert_eq!(
+        color_profile_from_description(info),
+        ColorProfile::Named(ColorSpace::AdobeRgb)
+    );
+}
+
+// Tests that a Wayland device refuses a scale change handler that it could never call, rather than
+// dropping it, and still lets one be unregistered.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos")),
+    feature = "sm-wayland"
+))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_wayland_scale_changed_handler() {
+    use crate::platform::unix::wayland::connection::Connection as WaylandConnection;
+
+    let connection = match WaylandConnection::new() {
+        Ok(connection) => connection,
+        Err(_) => return,
+    };
+    let adapter = connection.create_adapter().unwrap();
+    let mut device = connection.create_device(&adapter).unwrap();
+    assert!(matches!(
+        device.set_surface_scale_changed_handler(Some(Box::new(|_, _| {}))),
+        Err(Error::UnsupportedOnThisPlatform)
+    ));
+    device.set_surface_scale_changed_handler(None).unwrap();
+}
+
+// Tests the buffer ages of an X11 widget surface as frames are presented on it.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos")),
+    feature = "sm-x11"
+))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_x11_buffer_age() {
+    use crate::platform::unix::x11::connection::Connection as X11Connection;
+    use x11::xlib::{XCreateSimpleWindow, XDefaultRootWindow, XDestroyWindow, XMapWindow, XSync};
+
+    let connection = match X11Connection::new() {
+        Ok(connection) => connection,
+        Err(Error::ConnectionFailed) => return,
+        Err(err) => panic!("Failed to open X11 connection: {:?}", err),
+    };
+    let display = connection.native_connection().x11_display;
+    let window_size = Size2D::new(64, 48);
+    unsafe {
+        let window = XCreateSimpleWindow(
+            display,
+            XDefaultRootWindow(display),
+            0,
+            0,
+            window_size.width as u32,
+            window_size.height as u32,
+            0,
+            0,
+            0,
+        );
+        XMapWindow(display, window);
+        XSync(display, 0);
+
+        let native_widget =
+            connection.create_native_widget_from_ptr(window as *mut c_void, window_size);
+        let adapter = connection.create_adapter().unwrap();
+        let mut device = connection.create_device(&adapter).unwrap();
+        check_widget_buffer_ages(&mut device, native_widget);
+
+        XDestroyWindow(display, window);
+    }
+}
+
+// Tests the buffer ages of a Wayland widget surface as frames are presented on it.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos")),
+    feature = "sm-wayland",
+    feature = "sm-raw-window-handle-06"
+))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_wayland_buffer_age() {
+    use crate::platform::unix::wayland::connection::Connection as WaylandConnection;
+    use rwh_06::{HasDisplayHandle, HasWindowHandle};
+    use winit::dpi::PhysicalSize;
+    use winit::event_loop::EventLoopBuilder;
+    use winit::platform::wayland::EventLoopBuilderExtWayland;
+    use winit::window::WindowBuilder;
+
+    let event_loop = match EventLoopBuilder::new()
+        .with_wayland()
+        .with_any_thread(true)
+        .build()
+    {
+        Ok(event_loop) => event_loop,
+        Err(_) => return,
+    };
+    let window_size = Size2D::new(64, 48);
+    let window = WindowBuilder::new()
+        .with_inner_size(PhysicalSize::new(
+            window_size.width as u32,
+            window_size.height as u32,
+        ))
+        .build(&event_loop)
+        .unwrap();
+
+    let connection =
+        WaylandConnection::from_display_handle(window.display_handle().unwrap()).unwrap();
+    let native_widget = connection
+        .create_native_widget_from_window_handle(window.window_handle().unwrap(), window_size)
+        .unwrap();
+    let adapter = connection.create_adapter().unwrap();
+    let mut device = connection.create_device(&adapter).unwrap();
+    check_widget_buffer_ages(&mut device, native_widget);
+}
+
+// Presents frames on a widget surface of `native_widget` and checks the buffer ages reported
+// between them. The age starts at 0 and never exceeds the number of frames presented. Once the
+// surface has cycled through its buffers, each one comes back equally old: 2 frames with the
+// default double buffering. Invalidating the color buffer after presents, and resizing, make the
+// age 0, since the contents are then undefined.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos")),
+    any(feature = "sm-x11", feature = "sm-wayland")
+))]
+fn check_widget_buffer_ages<D>(
+    device: &mut D,
+    native_widget: <D::Connection as connection::Connection>::NativeWidget,
+) where
+    D: crate::device::Device,
+{
+    let descriptor = device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::empty(),
+            ..Default::default()
+        })
+        .unwrap();
+    let mut context = device.create_context(&descriptor, None).unwrap();
+    let gl = Gl::load_with(|symbol| {
+        device
+            .get_proc_address(&context, symbol)
+            .unwrap_or(std::ptr::null())
+    });
+    let mut surface = device
+        .create_surface(
+            &context,
+            SurfaceAccess::GPUOnly,
+            SurfaceType::Widget { native_widget },
+        )
+        .unwrap();
+    let device = &*device;
+    assert_eq!(device.surface_buffer_age(&surface).unwrap(), 0);
+
+    let present_frame = |context: &mut D::Context, surface: D::Surface| -> D::Surface {
+        if let Err((err, _)) = device.bind_surface_to_context(context, surface) {
+            panic!("Failed to bind the surface: {:?}", err);
+        }
+        device.make_context_current(context).unwrap();
+        let framebuffer_object = device
+            .context_surface_info(context)
+            .unwrap()
+            .unwrap()
+            .framebuffer_object;
+        unsafe {
+            gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
+        }
+        clear(&gl, &[0, 255, 0, 255]);
+        let mut surface = device
+            .unbind_surface_from_context(context)
+            .unwrap()
+            .unwrap();
+        device.present_surface(context, &mut surface).unwrap();
+        surface
+    };
+
+    let mut ages = vec![];
+    for frame_count in 1..=6 {
+        surface = present_frame(&mut context, surface);
+        let age = device.surface_buffer_age(&surface).unwrap();
+        assert!(
+            age <= frame_count,
+            "Age {} after {} frames",
+            age,
+            frame_count
+        );
+        ages.push(age);
+    }
+    if ages[2] != 0 {
+        assert!(
+            ages[2..].iter().all(|&age| age == ages[2]),
+            "Ages {:?} don't settle",
+            ages
+        );
+    }
+
+    let color = InvalidateSet {
+        color: true,
+        ..InvalidateSet::default()
+    };
+    match device.set_post_present_invalidation(&mut surface, color) {
+        Ok(()) => {
+            surface = present_frame(&mut context, surface);
+            assert_eq!(device.surface_buffer_age(&surface).unwrap(), 0);
+            device
+                .set_post_present_invalidation(&mut surface, InvalidateSet::default())
+                .unwrap();
+        }
+        Err(Error::PreservedColorBuffer) => {}
+        Err(err) => panic!("Failed to invalidate the color buffer: {:?}", err),
+    }
+
+    surface = present_frame(&mut context, surface);
+    device
+        .resize_surface(&context, &mut surface, Size2D::new(80, 60))
+        .unwrap();
+    assert_eq!(device.surface_buffer_age(&surface).unwrap(), 0);
+
+    device.destroy_surface(&mut context, &mut surface).unwrap();
+    device.destroy_context(&mut context).unwrap();
+}
+
+// Tests that generic surfaces have no buffer age.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_buffer_age_generic_surface() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let mut surface = make_surface(&mut env.device, &env.context);
+    match env.device.surface_buffer_age(&surface) {
+        Err(Error::NoWidgetAttached) => {}
+        result => panic!("Expected `NoWidgetAttached`, got {:?}", result),
+    }
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that generic surfaces, which are never presented, can't have their presentation paused.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_pause_presentation_generic_surface() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let mut surface = make_surface(&mut env.device, &env.context);
+    match env.device.pause_presentation(&env.context, &mut surface) {
+        Err(Error::NoWidgetAttached) => {}
+        result => panic!("Expected `NoWidgetAttached`, got {:?}", result),
+    }
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that a fence from a generic surface, which only follows the commands issued so far, can be
+// waited on by another context, more than once.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_present_surface_with_fence_generic_surface() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let mut other_context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+
+    let mut surface = make_surface(&mut env.device, &env.context);
+    match env
+        .device
+        .present_surface_with_fence(&other_context, &mut surface)
+    {
+        Err(Error::IncompatibleSurface) => {}
+        result => panic!("Expected `IncompatibleSurface`, got {:?}", result),
+    }
+    match env
+        .device
+        .present_surface_with_fence(&env.context, &mut surface)
+    {
+        Ok(fence) => {
+            for _ in 0..2 {
+                match env.device.wait_fence(&other_context, &fence) {
+                    Ok(()) | Err(Error::RequiredExtensionUnavailable) => {}
+                    Err(err) => panic!("Failed to wait for the fence: {:?}", err),
+                }
+            }
+        }
+        Err(Error::RequiredExtensionUnavailable) | Err(Error::UnsupportedOnThisPlatform) => {}
+        Err(err) => panic!("Failed to insert a fence: {:?}", err),
+    }
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut other_context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that a fence exported as a sync file can be imported by another context, waited on, and
+// exported again, and that displays without native fences refuse both.
+#[cfg(unix)]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_sync_fd_round_trip() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let mut other_context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+
+    let mut surface = make_surface(&mut env.device, &env.context);
+    let fence = match env
+        .device
+        .present_surface_with_fence(&env.context, &mut surface)
+    {
+        Ok(fence) => Some(fence),
+        Err(Error::RequiredExtensionUnavailable) | Err(Error::UnsupportedOnThisPlatform) => None,
+        Err(err) => panic!("Failed to insert a fence: {:?}", err),
+    };
+    match fence.as_ref().map(|fence| fence.export_sync_fd()) {
+        Some(Ok(fd)) => {
+            let imported = env
+                .device
+                .import_sync_fd(&other_context, fd.as_fd())
+                .unwrap();
+            match env.device.wait_fence(&other_context, &imported) {
+                Ok(()) | Err(Error::RequiredExtensionUnavailable) => {}
+                Err(err) => panic!("Failed to wait for the imported fence: {:?}", err),
+            }
+            imported.export_sync_fd().unwrap();
+        }
+        Some(Err(Error::RequiredExtensionUnavailable)) | None => {
+            // Without native fences, nothing can be imported, whatever the descriptor.
+            let file = File::open("/dev/null").unwrap();
+            match env.device.import_sync_fd(&other_context, file.as_fd()) {
+                Err(Error::RequiredExtensionUnavailable)
+                | Err(Error::UnsupportedOnThisPlatform) => {}
+                result => panic!("Expected the import to be refused, got {:?}", result),
+            }
+        }
+        Some(Err(err)) => panic!("Failed to export the fence: {:?}", err),
+    }
+    drop(fence);
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut other_context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests pausing and resuming presentation, apart from any surface: only one pause at a time, only
+// the last frame held, and pauses ended by their handle, by dropping it, or by a resize.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_present_pause() {
+    let frame = |value| HeldFrame::new(Size2D::new(1, 1), vec![value; 4]);
+    let mut pause = PresentPause::default();
+    assert!(!pause.is_paused());
+    assert!(pause.hold(frame(1)).is_some());
+
+    let paused = pause.pause().unwrap();
+    assert!(pause.is_paused() && paused.is_paused());
+    match pause.pause() {
+        Err(Error::PresentationPaused) => {}
+        result => panic!("Expected `PresentationPaused`, got {:?}", result),
+    }
+    assert!(pause.held_frame().is_none());
+    assert!(pause.hold(frame(2)).is_none());
+    assert!(pause.hold(frame(3)).is_some());
+    assert_eq!(paused.frames_held(), 2);
+    let mut pixel = [0; 4];
+    pause
+        .held_frame()
+        .unwrap()
+        .read_rect(Rect::new(Point2D::zero(), Size2D::new(1, 1)), &mut pixel)
+        .unwrap();
+    assert_eq!(pixel, [3; 4]);
+
+    // Another surface's pause can't end this one.
+    let mut other = PresentPause::<HeldFrame>::default();
+    let other_paused = other.pause().unwrap();
+    match pause.resume(other_paused).map(|frame| frame.is_some()) {
+        Err(Error::IncompatiblePausedPresentation) => {}
+        result => panic!(
+            "Expected `IncompatiblePausedPresentation`, got {:?}",
+            result
+        ),
+    }
+    assert!(pause.is_paused());
+
+    let mut pixel = [0; 4];
+    let held = pause.resume(paused).unwrap().unwrap();
+    held.read_rect(Rect::new(Point2D::zero(), Size2D::new(1, 1)), &mut pixel)
+        .unwrap();
+    assert_eq!(pixel, [3; 4]);
+    assert!(!pause.is_paused());
+
+    // Dropping the handle ends the pause and discards the held frame.
+    let paused = pause.pause().unwrap();
+    pause.hold(frame(4));
+    drop(paused);
+    assert!(!pause.is_paused());
+    assert!(pause.held_frame().is_none());
+
+    // A resize ends the pause, after which its handle resumes nothing.
+    let paused = pause.pause().unwrap();
+    pause.hold(frame(5));
+    assert!(pause.force_resume().is_some());
+    assert!(!paused.is_paused());
+    assert_eq!(paused.frames_held(), 1);
+    assert!(pause.resume(paused).unwrap().is_none());
+}
+
+// Tests that held frames are read top row first, as `read_surface_data()` reads surfaces.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_held_frame_read_rect() {
+    // Two rows of two pixels, bottom row first, each pixel's channels holding its index.
+    let pixels = (0..4).flat_map(|index| vec![index; 4]).collect();
+    let frame = HeldFrame::new(Size2D::new(2, 2), pixels);
+
+    let mut out = [0; 16];
+    frame
+        .read_rect(Rect::new(Point2D::zero(), Size2D::new(2, 2)), &mut out)
+        .unwrap();
+    assert_eq!(out, [2, 2, 2, 2, 3, 3, 3, 3, 0, 0, 0, 0, 1, 1, 1, 1]);
+
+    let mut out = [0; 4];
+    frame
+        .read_rect(Rect::new(Point2D::new(1, 1), Size2D::new(1, 1)), &mut out)
+        .unwrap();
+    assert_eq!(out, [1; 4]);
+
+    match frame.read_rect(Rect::new(Point2D::zero(), Size2D::new(2, 1)), &mut out) {
+        Err(Error::BufferTooSmall {
+            required: 8,
+            actual: 4,
+        }) => {}
+        result => panic!("Expected `BufferTooSmall`, got {:?}", result),
+    }
+    assert!(frame
+        .read_rect(Rect::new(Point2D::new(1, 1), Size2D::new(2, 1)), &mut out)
+        .is_err());
+}
+
+// Tests that Wayland widget surfaces track the release of their frames with `wl_surface.frame`
+// callbacks, against a fake compositor that answers each commit.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos")),
+    feature = "sm-wayland"
+))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_wayland_frame_callbacks() {
+    use crate::platform::generic::egl::surface::ReleaseSignal;
+    use crate::platform::unix::wayland::surface::{FrameCallbacks, WL_CALLBACK_INTERFACE};
+    use std::io::{Read, Write};
+    use std::os::raw::c_char;
+    use std::os::unix::io::IntoRawFd;
+    use std::os::unix::net::UnixStream;
+    use std::ptr;
+    use wayland_sys::client::{wl_display, wl_proxy, WAYLAND_CLIENT_HANDLE};
+    use wayland_sys::common::{wl_interface, wl_message};
+
+    struct Messages([wl_message; 7]);
+    unsafe impl Sync for Messages {}
+    struct Interfaces([*const wl_interface; 4]);
+    unsafe impl Sync for Interfaces {}
+
+    static NO_TYPES: Interfaces = Interfaces([ptr::null(); 4]);
+    static FRAME_TYPES: Interfaces = Interfaces([
+        &WL_CALLBACK_INTERFACE,
+        ptr::null(),
+        ptr::null(),
+        ptr::null(),
+    ]);
+    macro_rules! message {
+        ($name:expr, $signature:expr, $types:expr) => {
+            wl_message {
+                name: $name as *const u8 as *const c_char,
+                signature: $signature as *const u8 as *const c_char,
+                types: &$types.0 as *const [*const wl_interface; 4] as *const _,
+            }
+        };
+    }
+    static WL_SURFACE_REQUESTS: Messages = Messages([
+        message!(b"destroy\0", b"\0", NO_TYPES),
+        message!(b"attach\0", b"?oii\0", NO_TYPES),
+        message!(b"damage\0", b"iiii\0", NO_TYPES),
+        message!(b"frame\0", b"n\0", FRAME_TYPES),
+        message!(b"set_opaque_region\0", b"?o\0", NO_TYPES),
+        message!(b"set_input_region\0", b"?o\0", NO_TYPES),
+        message!(b"commit\0", b"\0", NO_TYPES),
+    ]);
+    static WL_SURFACE_INTERFACE: wl_interface = wl_interface {
+        name: b"wl_surface\0" as *const u8 as *const c_char,
+        version: 1,
+        request_count: 7,
+        requests: &WL_SURFACE_REQUESTS.0 as *const [wl_message; 7] as *const _,
+        event_count: 0,
+        events: ptr::null(),
+    };
+    const WL_SURFACE_FRAME: u32 = 3;
+    const WL_SURFACE_COMMIT: u32 = 6;
+
+    if !wayland_sys::client::is_lib_available() {
+        return;
+    }
+    let (client_stream, mut compositor_stream) = UnixStream::pair().unwrap();
+
+    unsafe {
+        let wayland_display: *mut wl_display =
+            (WAYLAND_CLIENT_HANDLE.wl_display_connect_to_fd)(client_stream.into_raw_fd());
+        assert!(!wayland_display.is_null());
+        // Creating the proxy doesn't tell the compositor, which takes the ID on trust.
+        let wayland_surface = (WAYLAND_CLIENT_HANDLE.wl_proxy_create)(
+            wayland_display as *mut wl_proxy,
+            &WL_SURFACE_INTERFACE,
+        );
+        let surface_id = (WAYLAND_CLIENT_HANDLE.wl_proxy_get_id)(wayland_surface);
+
+        // Answer each commit by calling the frame callback that came with it.
+        let compositor = thread::spawn(move || {
+            let read_u32 =
+                |bytes: &[u8]| u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            let mut pending_callback = None;
+            let mut header = [0; 8];
+            while compositor_stream.read_exact(&mut header).is_ok() {
+                let (object_id, size_and_opcode) = (read_u32(&header[0..]), read_u32(&header[4..]));
+                let mut body = vec![0; (size_and_opcode >> 16) as usize - header.len()];
+                compositor_stream.read_exact(&mut body).unwrap();
+                if object_id != surface_id {
+                    continue;
+                }
+                match size_and_opcode & 0xffff {
+                    WL_SURFACE_FRAME => pending_callback = Some(read_u32(&body)),
+                    WL_SURFACE_COMMIT => {
+                        if let Some(callback_id) = pending_callback.take() {
+                            // `wl_callback.done`, then `wl_display.delete_id`.
+                            let mut events = vec![];
+                            for &word in &[callback_id, 12 << 16, 0, 1, 12 << 16 | 1, callback_id] {
+                                events.extend_from_slice(&u32::to_ne_bytes(word));
+                            }
+                            compositor_stream.write_all(&events).unwrap();
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        let mut frame_callbacks = FrameCallbacks::new(wayland_display, wayland_surface).unwrap();
+        assert!(matches!(
+            frame_callbacks.wait(Duration::from_secs(0)),
+            Ok(BufferRelease::Released)
+        ));
+
+        // The callback only comes once the frame is committed.
+        frame_callbacks.presenting().unwrap();
+        assert!(matches!(
+            frame_callbacks.wait(Duration::from_millis(20)),
+            Ok(BufferRelease::TimedOut)
+        ));
+
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_marshal_array)(
+            wayland_surface,
+            WL_SURFACE_COMMIT,
+            [].as_mut_ptr(),
+        );
+        assert!(matches!(
+            frame_callbacks.wait(Duration::from_secs(5)),
+            Ok(BufferRelease::Waited)
+        ));
+        assert!(matches!(
+            frame_callbacks.wait(Duration::from_secs(0)),
+            Ok(BufferRelease::Released)
+        ));
+
+        drop(frame_callbacks);
+        (WAYLAND_CLIENT_HANDLE.wl_proxy_destroy)(wayland_surface);
+        (WAYLAND_CLIENT_HANDLE.wl_display_disconnect)(wayland_display);
+        compositor.join().unwrap();
+    }
+}
+
+// Tests that connections to named displays fail with the name, and that `SURFMAN_DISPLAY`
+// overrides the default display.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos")),
+    any(feature = "sm-x11", feature = "sm-wayland")
+))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_display_name_overrides() {
+    let bogus_name = "surfman-bogus-host:99";
+    let expect_display_failure = |result: Result<_, Error>| match result {
+        Err(Error::DisplayConnectionFailed(ref display_name)) if display_name == bogus_name => {}
+        Err(err) => panic!("Expected `DisplayConnectionFailed`, got {:?}", err),
+        Ok(_) => panic!("Expected `DisplayConnectionFailed`, got a connection"),
+    };
+
+    #[cfg(feature = "sm-x11")]
+    {
+        use crate::platform::unix::x11::connection::Connection as X11Connection;
+        expect_display_failure(X11Connection::from_display_name(bogus_name).map(drop));
+    }
+    #[cfg(feature = "sm-wayland")]
+    {
+        use crate::platform::unix::wayland::connection::Connection as WaylandConnection;
+        use std::ffi::OsStr;
+        expect_display_failure(
+            WaylandConnection::from_wayland_socket(OsStr::new(bogus_name)).map(drop),
+        );
+    }
+
+    // The override wins over `DISPLAY` and `WAYLAND_DISPLAY`, even if they name a real server.
+    let old_display = env::var_os("SURFMAN_DISPLAY");
+    env::set_var("SURFMAN_DISPLAY", bogus_name);
+    #[cfg(feature = "sm-x11")]
+    expect_display_failure(crate::platform::unix::x11::connection::Connection::new().map(drop));
+    #[cfg(feature = "sm-wayland")]
+    expect_display_failure(crate::platform::unix::wayland::connection::Connection::new().map(drop));
+    match old_display {
+        Some(old_display) => env::set_var("SURFMAN_DISPLAY", old_display),
+        None => env::remove_var("SURFMAN_DISPLAY"),
+    }
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_device_creation() {
+    let connection = Connection::new().unwrap();
+    let adapter = connection
+        .create_low_power_adapter()
+        .expect("Failed to create adapter!");
+    match connection.create_device(&adapter) {
+        Ok(_) => {}
+        Err(Error::RequiredExtensionUnavailable) => {
+            // Can't run these tests on this hardware.
+        }
+        Err(err) => panic!("Failed to create device: {:?}", err),
+    }
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_device_accessors() {
+    let connection = Connection::new().unwrap();
+    let adapter = connection.create_low_power_adapter().unwrap();
+    let device = match connection.create_device(&adapter) {
+        Ok(device) => device,
+        Err(Error::RequiredExtensionUnavailable) => {
+            // Can't run these tests on this hardware.
+            return;
+        }
+        Err(err) => panic!("Failed to create device: {:?}", err),
+    };
+    drop(device.connection());
+    drop(device.adapter());
+}
+
+// Tests that devices on the same adapter report the same identity, and how identities match.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_device_identity() {
+    let connection = Connection::new().unwrap();
+    let adapter = connection.create_low_power_adapter().unwrap();
+    let device = match connection.create_device(&adapter) {
+        Ok(device) => device,
+        Err(Error::RequiredExtensionUnavailable) => {
+            // Can't run these tests on this hardware.
+            return;
+        }
+        Err(err) => panic!("Failed to create device: {:?}", err),
+    };
+    let identity = device.identity();
+    let other_device = connection.create_device(&adapter).unwrap();
+    assert_eq!(other_device.identity(), identity);
+    // An identity the platform couldn't determine can't be matched, not even with itself.
+    assert_eq!(
+        identities_match(&identity, &identity),
+        identity != DeviceIdentity::default()
+    );
+
+    // Software adapters report the synthetic software identity.
+    #[cfg(all(
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_env = "ohos"))
+    ))]
+    {
+        let adapter = connection.create_software_adapter().unwrap();
+        let device = connection.create_device(&adapter).unwrap();
+        assert_eq!(device.identity(), DeviceIdentity::software());
+    }
+
+    // A GPU matches APIs that only know one of its DRM nodes.
+    let gpu = DeviceIdentity {
+        drm_primary_node: Some(0xe200),
+        drm_render_node: Some(0xe280),
+        ..DeviceIdentity::default()
+    };
+    let primary_only = DeviceIdentity {
+        drm_render_node: None,
+        ..gpu.clone()
+    };
+    let render_only = DeviceIdentity {
+        drm_primary_node: None,
+        ..gpu.clone()
+    };
+    let other_gpu = DeviceIdentity {
+        drm_primary_node: Some(0xe201),
+        drm_render_node: Some(0xe281),
+        ..DeviceIdentity::default()
+    };
+    assert!(identities_match(&gpu, &primary_only));
+    assert!(identities_match(&render_only, &gpu));
+    assert!(!identities_match(&primary_only, &render_only));
+    assert!(!identities_match(&gpu, &other_gpu));
+
+    let software = DeviceIdentity::software();
+    assert!(identities_match(&software, &software));
+    assert!(!identities_match(&software, &gpu));
+    assert!(!identities_match(
+        &DeviceIdentity::default(),
+        &DeviceIdentity::default()
+    ));
+}
+
+// Tests that the software renderer table matches the renderer strings of software rasterizers,
+// and none of those of GPUs.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_software_renderer_patterns() {
+    let software = [
+        "llvmpipe (LLVM 15.0.7, 256 bits)",
+        "softpipe",
+        "Mesa DRI swrast",
+        "SwiftShader Device (Subzero) (0x0000C0DE)",
+        "ANGLE (Microsoft, Microsoft Basic Render Driver Direct3D11 vs_5_0 ps_5_0, D3D11)",
+        "GDI Generic",
+        "Apple Software Renderer",
+    ];
+    for renderer in &software {
+        assert!(acceleration::is_software_renderer(renderer), "{}", renderer);
+    }
+    let hardware = [
+        "Mesa Intel(R) UHD Graphics 620 (KBL GT2)",
+        "AMD Radeon RX 6800 (radeonsi, navi21, LLVM 15.0.7, DRM 3.49, 6.1.0)",
+        "NVIDIA GeForce RTX 3080/PCIe/SSE2",
+        "ANGLE (NVIDIA, NVIDIA GeForce RTX 3080 Direct3D11 vs_5_0 ps_5_0, D3D11)",
+        "Apple M1",
+        "Adreno (TM) 640",
+    ];
+    for renderer in &hardware {
+        assert!(
+            !acceleration::is_software_renderer(renderer),
+            "{}",
+            renderer
+        );
+    }
+}
+
+// Tests that contexts on the software adapter aren't hardware accelerated, that the software
+// fallback handler hears about hardware devices that render in software and no others, that
+// contexts agree with their device, and that environment reports include the result.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_context_is_hardware_accelerated() {
+    let reported = Arc::new(Mutex::new(vec![]));
+    let handler_reported = reported.clone();
+    acceleration::set_software_fallback_handler(Some(Box::new(move |event| {
+        handler_reported.lock().unwrap().push(event.clone());
+    })));
+
+    let connection = Connection::new().unwrap();
+    let adapters = [
+        connection.create_software_adapter().unwrap(),
+        connection.create_low_power_adapter().unwrap(),
+    ];
+    for adapter in &adapters {
+        let mut device = match connection.create_device(adapter) {
+            Ok(device) => device,
+            Err(Error::RequiredExtensionUnavailable) => continue,
+            Err(err) => panic!("Failed to create device: {:?}", err),
+        };
+        let key = connection.adapter_key(adapter).unwrap();
+        let events = mem::take(&mut *reported.lock().unwrap());
+        let software_device = device.identity().software
+            || key
+                .driver
+                .as_deref()
+                .is_some_and(acceleration::is_software_renderer);
+        if key.kind == AdapterKind::Software || !software_device {
+            assert!(events.is_empty());
+        } else {
+            assert_eq!(
+                events,
+                [SoftwareFallbackDetected {
+                    requested: key.kind,
+                    renderer: key.driver.clone(),
+                }]
+            );
+        }
+
+        let context_descriptor = device
+            .create_context_descriptor(&ContextAttributes {
+                version: GLVersion::new(3, 0),
+                flags: ContextAttributeFlags::empty(),
+                ..Default::default()
+            })
+            .unwrap();
+        let mut context = device.create_context(&context_descriptor, None).unwrap();
+        let renderer = device.context_info(&context).unwrap().renderer;
+        let accelerated = device.context_is_hardware_accelerated(&context);
+        if key.kind == AdapterKind::Software || software_device {
+            assert!(!accelerated, "{}", renderer);
+        }
+        if acceleration::is_software_renderer(&renderer) {
+            assert!(!accelerated, "{}", renderer);
+        }
+
+        // Bug reports include the result.
+        let mut report = EnvironmentReport::probe();
+        assert_eq!(report.hardware_accelerated, None);
+        report.check_context(&device, &context);
+        assert_eq!(report.hardware_accelerated, Some(accelerated));
+        let kind = if accelerated { "hardware" } else { "software" };
+        assert!(report
+            .to_string()
+            .contains(&format!("renderer: {} ({})", renderer, kind)));
+        device.destroy_context(&mut context).unwrap();
+    }
+
+    acceleration::set_software_fallback_handler(None);
+}
+
+// Tests which GPU presents a window on a made-up desktop with two GPUs side by side.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_presenting_identity() {
+    let gpu = |luid| DeviceIdentity {
+        adapter_luid: Some(luid),
+        ..DeviceIdentity::default()
+    };
+    let output = |x, identity| DisplayOutput {
+        bounds: Rect::new(Point2D::new(x, 0), Size2D::new(1920, 1080)),
+        identity,
+    };
+    let window = |x, y| Rect::new(Point2D::new(x, y), Size2D::new(800, 600));
+    let outputs = [output(0, gpu(1)), output(1920, gpu(2))];
+    let presenter = |x, y| presenting_identity(&window(x, y), &outputs);
+
+    assert_eq!(presenter(100, 100), Some(gpu(1)));
+    assert_eq!(presenter(2000, 100), Some(gpu(2)));
+    // A window spanning both outputs belongs to the one it covers the most of.
+    assert_eq!(presenter(1600, 100), Some(gpu(2)));
+    assert_eq!(presenter(1320, 100), Some(gpu(1)));
+    // Ties go to the first output.
+    assert_eq!(presenter(1520, 100), Some(gpu(1)));
+    // A window off screen belongs to the nearest output.
+    assert_eq!(presenter(5000, 100), Some(gpu(2)));
+    assert_eq!(presenter(-900, -900), Some(gpu(1)));
+
+    // Outputs whose GPU isn't known can't be presented on by any particular GPU.
+    let outputs = [output(0, gpu(1)), output(1920, DeviceIdentity::default())];
+    assert_eq!(presenting_identity(&window(2000, 100), &outputs), None);
+    assert_eq!(presenting_identity(&window(100, 100), &[]), None);
+}
+
+// Tests matching displays reported by the window system with the DRM connectors they're on.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos"))
+))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_drm_card_for_edid() {
+    let edid = |serial: u8, extension: u8| {
+        let mut edid = vec![0; 256];
+        edid[..8].copy_from_slice(&[0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00]);
+        edid[12] = serial;
+        edid[200] = extension;
+        edid
+    };
+    let connector = |card: &str, edid| DrmConnector {
+        card: card.to_owned(),
+        edid,
+    };
+    let connectors = [
+        connector("card0", edid(1, 0)),
+        connector("card1", edid(2, 0)),
+    ];
+
+    assert_eq!(drm_card_for_edid(&edid(1, 0), &connectors), Some("card0"));
+    assert_eq!(drm_card_for_edid(&edid(2, 0), &connectors), Some("card1"));
+    // Identical displays are told apart by their serial numbers.
+    assert_eq!(drm_card_for_edid(&edid(3, 0), &connectors), None);
+    // Extension blocks may be missing or differ.
+    assert_eq!(drm_card_for_edid(&edid(2, 7), &connectors), Some("card1"));
+    assert_eq!(
+        drm_card_for_edid(&edid(2, 0)[..128], &connectors),
+        Some("card1")
+    );
+    assert_eq!(drm_card_for_edid(&edid(2, 0)[..100], &connectors), None);
+    assert_eq!(drm_card_for_edid(&edid(1, 0), &[]), None);
+}
+
+// Tests the shader limits that ANGLE reports at each Direct3D feature level, without needing a
+// device of each level.
+#[cfg(all(target_os = "windows", feature = "sm-angle"))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_angle_backend_info() {
+    use crate::platform::windows::angle::device::AngleBackendInfo;
+    use winapi::um::d3dcommon::{D3D_FEATURE_LEVEL_10_0, D3D_FEATURE_LEVEL_10_1};
+    use winapi::um::d3dcommon::{D3D_FEATURE_LEVEL_11_0, D3D_FEATURE_LEVEL_11_1};
+    use winapi::um::d3dcommon::{D3D_FEATURE_LEVEL_9_1, D3D_FEATURE_LEVEL_9_3};
+
+    let expectations = [
+        (D3D_FEATURE_LEVEL_9_1, 6, false),
+        (D3D_FEATURE_LEVEL_9_3, 8, false),
+        (D3D_FEATURE_LEVEL_10_0, 14, true),
+        (D3D_FEATURE_LEVEL_10_1, 14, true),
+        (D3D_FEATURE_LEVEL_11_0, 30, true),
+        (D3D_FEATURE_LEVEL_11_1, 30, true),
+    ];
+    for &(feature_level, max_varyings, vertex_texture_fetch) in &expectations {
+        let info = AngleBackendInfo::new(feature_level, "Mock Adapter".to_owned());
+        assert_eq!(info.d3d_feature_level, feature_level);
+        assert_eq!(info.max_varyings, max_varyings);
+        assert_eq!(info.supports_vertex_texture_fetch, vertex_texture_fetch);
+        assert_eq!(info.renderer_string, "Mock Adapter");
+    }
+}
+
+// Tests that EGL/WGL and OpenGL extensions can be queried through the connection and device.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_extension_queries() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    assert!(!env.connection.supports_extension("EGL_SURFMAN_bogus"));
+    assert!(!env.connection.supports_extension(""));
+
+    let extensions = env.device.gl_extensions(&env.context).unwrap();
+    assert!(!extensions.is_empty());
+    assert!(extensions
+        .iter()
+        .all(|extension| !extension.is_empty() && !extension.contains(' ')));
+
+    // The context doesn't need to be current.
+    env.device.make_no_context_current().unwrap();
+    assert_eq!(env.device.gl_extensions(&env.context).unwrap(), extensions);
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that functions that the driver doesn't provide are reported as missing.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_get_proc_address() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    assert!(env
+        .device
+        .get_proc_address(&env.context, "glClear")
+        .is_some());
+    for bogus_name in &["glBogusEntryPointSURFMAN", "", "gl\0Clear"] {
+        assert_eq!(env.device.get_proc_address(&env.context, bogus_name), None);
+    }
+
+    let loader = env.device.proc_address_loader(&env.context);
+    assert!(!loader("glClear").is_null());
+    assert!(loader("glBogusEntryPointSURFMAN").is_null());
+    drop(loader);
+
+    // Extension entry points are only trusted if an extension from their vendor is supported.
+    let oes_extensions = |_: &str| vec!["GL_OES_texture_3D".to_owned()];
+    assert!(gl_utils::entry_point_available(
+        "glTexImage3DOES",
+        oes_extensions
+    ));
+    assert!(!gl_utils::entry_point_available(
+        "glTexImage3DOES",
+        |_| vec![]
+    ));
+    assert!(gl_utils::entry_point_available("glTexImage3D", |_| vec![]));
+    assert!(!gl_utils::entry_point_available(
+        "eglCreateImageKHR",
+        |api| {
+            assert_eq!(api, "EGL");
+            vec!["GL_KHR_debug".to_owned()]
+        }
+    ));
+
+    // The context doesn't need to be current.
+    env.device.make_no_context_current().unwrap();
+    assert_eq!(
+        env.device
+            .get_proc_address(&env.context, "glBogusEntryPointSURFMAN"),
+        None
+    );
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that the guard from `make_context_current_guarded()` restores the previous context.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_make_context_current_guarded() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let mut other_context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    let clear_color = |gl: &Gl| {
+        let mut clear_color = [0.0; 4];
+        unsafe {
+            gl.GetFloatv(gl::COLOR_CLEAR_VALUE, clear_color.as_mut_ptr());
+        }
+        clear_color
+    };
+
+    env.device.make_context_current(&env.context).unwrap();
+    unsafe {
+        env.gl.ClearColor(0.25, 0.5, 0.75, 1.0);
+    }
+    {
+        let _guard = env
+            .device
+            .make_context_current_guarded(&other_context)
+            .unwrap();
+        assert_eq!(clear_color(&env.gl), [0.0; 4]);
+    }
+    assert_eq!(clear_color(&env.gl), [0.25, 0.5, 0.75, 1.0]);
+
+    // The previous context is restored when unwinding, too.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _guard = env
+            .device
+            .make_context_current_guarded(&other_context)
+            .unwrap();
+        panic!("Unwinding with a guard alive");
+    }));
+    assert!(result.is_err());
+    assert_eq!(clear_color(&env.gl), [0.25, 0.5, 0.75, 1.0]);
+
+    // If nothing was current, nothing is current afterward.
+    env.device.make_no_context_current().unwrap();
+    drop(
+        env.device
+            .make_context_current_guarded(&other_context)
+            .unwrap(),
+    );
+    assert!(env.device.current_native_context().is_err());
+
+    env.device.destroy_context(&mut other_context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that the operations that `with_context_current()` runs leave surfaces as they are left
+// when called one by one, and that the previously current context is current again afterward.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_with_context_current() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let mut other_context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    let new_size = Size2D::new(320, 240);
+    let rect = Rect::new(Point2D::new(16, 32), Size2D::new(64, 48));
+    let (red, green) = ([1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.0, 1.0]);
+    let read_back = |device: &Device, context: &Context, surface: &Surface| {
+        let mut pixels = vec![0; 320 * 240 * 4];
+        let rect = Rect::new(Point2D::zero(), new_size);
+        device
+            .read_surface_data(context, surface, rect, &mut pixels)
+            .unwrap();
+        pixels
+    };
+
+    // One by one.
+    let mut surface = make_surface(&mut env.device, &env.context);
+    let mut textured = make_surface(&mut env.device, &env.context);
+    let foreign = make_surface(&mut env.device, &other_context);
+    env.device.make_context_current(&other_context).unwrap();
+    env.device
+        .resize_surface(&env.context, &mut surface, new_size)
+        .unwrap();
+    for &(rect, color) in &[(None, red), (Some(rect), green)] {
+        env.device
+            .clear_surface_region(&mut env.context, &mut surface, rect, color, None, None)
+            .unwrap();
+    }
+    let expected = read_back(&env.device, &env.context, &surface);
+    let surface_texture = env
+        .device
+        .create_surface_texture(&mut env.context, textured)
+        .unwrap();
+    assert!(env.device.is_context_current(&other_context));
+    textured = env
+        .device
+        .destroy_surface_texture(&mut env.context, surface_texture)
+        .unwrap();
+    let mut foreign = foreign;
+    let foreign_error = env.device.destroy_surface(&mut env.context, &mut foreign);
+    assert!(matches!(foreign_error, Err(Error::IncompatibleSurface)));
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device
+        .destroy_surface(&mut env.context, &mut textured)
+        .unwrap();
+    assert!(env.device.is_context_current(&other_context));
+
+    // All at once.
+    let mut surface = make_surface(&mut env.device, &env.context);
+    let textured = make_surface(&mut env.device, &env.context);
+    let surface_texture = env
+        .device
+        .create_surface_texture(&mut env.context, textured)
+        .unwrap();
+    env.device.make_context_current(&other_context).unwrap();
+    let pixels = env
+        .device
+        .with_context_current(&mut env.context, |ops| {
+            assert!(ops.device().is_context_current(ops.context()));
+            ops.resize_surface(&mut surface, new_size).unwrap();
+            ops.clear_surface_region(&mut surface, None, red, None, None)
+                .unwrap();
+            ops.clear_surface_region(&mut surface, Some(rect), green, None, None)
+                .unwrap();
+            let pixels = read_back(ops.device(), ops.context(), &surface);
+            let mut textured = ops.destroy_surface_texture(surface_texture).unwrap();
+            assert_eq!(
+                format!("{:?}", ops.destroy_surface(&mut foreign)),
+                format!("{:?}", foreign_error)
+            );
+            ops.destroy_surface(&mut surface).unwrap();
+            ops.destroy_surface(&mut textured).unwrap();
+            pixels
+        })
+        .unwrap();
+    assert!(pixels == expected);
+    assert!(env.device.is_context_current(&other_context));
+
+    // Operations still make the context current if another context was made current meanwhile,
+    // and the previous context is restored if the closure panics.
+    let mut surface = make_surface(&mut env.device, &env.context);
+    env.device.make_no_context_current().unwrap();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        env.device
+            .with_context_current(&mut env.context, |ops| {
+                ops.device().make_context_current(&other_context).unwrap();
+                ops.clear_surface_region(&mut surface, None, red, None, None)
+                    .unwrap();
+                assert!(ops.device().is_context_current(&other_context));
+                ops.destroy_surface(&mut surface).unwrap();
+                panic!("Unwinding inside `with_context_current()`");
+            })
+            .unwrap();
+    }));
+    assert!(result.is_err());
+    assert!(env.device.current_native_context().is_err());
+
+    env.device
+        .destroy_surface(&mut other_context, &mut foreign)
+        .unwrap();
+    env.device.destroy_context(&mut other_context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that destroying all of a context's swap chains destroys every one of their surfaces, with
+// the context made current once, and leaves the previously current context current.
+#[cfg(feature = "chains")]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_swap_chains_destroy_all() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let mut other_context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    let swap_chains = SwapChains::new();
+    for id in 0..4 {
+        swap_chains
+            .create_detached_swap_chain(
+                id,
+                Size2D::new(64, 64),
+                &mut env.device,
+                &mut env.context,
+                SurfaceAccess::GPUOnly,
+            )
+            .unwrap();
+    }
+    env.device.make_context_current(&other_context).unwrap();
+    swap_chains
+        .destroy_all(&mut env.device, &mut env.context)
+        .unwrap();
+    assert!(env.device.is_context_current(&other_context));
+    assert_eq!(
+        swap_chains.iter(&mut env.device, &mut env.context).count(),
+        0
+    );
+
+    env.device.destroy_context(&mut other_context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that purging the pools of swap chains destroys the surfaces that consumers gave back.
+#[cfg(feature = "chains")]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_swap_chains_purge_pools() {
+    use crate::chains::{SwapChainAPI, SwapChainsAPI};
+
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let swap_chains = SwapChains::new();
+    swap_chains
+        .create_detached_swap_chain(
+            0,
+            Size2D::new(64, 64),
+            &mut env.device,
+            &mut env.context,
+            SurfaceAccess::GPUOnly,
+        )
+        .unwrap();
+    let swap_chain = swap_chains.get(0).unwrap();
+
+    let mut taken = vec![];
+    for _ in 0..2 {
+        swap_chain
+            .swap_buffers(&mut env.device, &mut env.context, PreserveBuffer::No)
+            .unwrap();
+        taken.push(swap_chain.take_surface().unwrap());
+    }
+    for surface in taken {
+        swap_chain.recycle_surface(surface);
+    }
+    assert_eq!(
+        swap_chains
+            .purge_pools(&mut env.device, &mut env.context)
+            .unwrap(),
+        2
+    );
+    assert_eq!(
+        swap_chains
+            .purge_pools(&mut env.device, &mut env.context)
+            .unwrap(),
+        0
+    );
+
+    // The swap chain allocates surfaces again as it needs them.
+    swap_chain
+        .swap_buffers(&mut env.device, &mut env.context, PreserveBuffer::No)
+        .unwrap();
+    let surface = swap_chain.take_surface().unwrap();
+    swap_chain.recycle_surface(surface);
+
+    swap_chains
+        .destroy_all(&mut env.device, &mut env.context)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that devices report which of their contexts is current on this thread.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_is_context_current() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let mut other_context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    assert_ne!(
+        env.device.context_id(&env.context),
+        env.device.context_id(&other_context)
+    );
+
+    env.device.make_context_current(&env.context).unwrap();
+    assert!(env.device.is_context_current(&env.context));
+    assert!(!env.device.is_context_current(&other_context));
+    {
+        let _guard = env
+            .device
+            .make_context_current_guarded(&other_context)
+            .unwrap();
+        assert!(!env.device.is_context_current(&env.context));
+        assert!(env.device.is_context_current(&other_context));
+    }
+    assert!(env.device.is_context_current(&env.context));
+
+    env.device.make_no_context_current().unwrap();
+    assert!(!env.device.is_context_current(&env.context));
+    assert!(!env.device.is_context_current(&other_context));
+
+    env.device.destroy_context(&mut other_context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that devices report which thread each context is current on, and that making a context
+// current while another thread has it names that thread.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_context_current_thread() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let main_thread = thread::current().id();
+    assert_eq!(
+        env.device.context_current_thread(&env.context),
+        Some(main_thread)
+    );
+    env.device.make_no_context_current().unwrap();
+    assert_eq!(env.device.context_current_thread(&env.context), None);
+
+    // Devices and contexts aren't `Sync`, but one thread at a time uses them here.
+    struct Shared<'a>(&'a Device, &'a Context);
+    unsafe impl<'a> Send for Shared<'a> {}
+
+    let (current_sender, current_receiver) = mpsc::channel();
+    let (release_sender, release_receiver) = mpsc::channel::<()>();
+    let shared = Shared(&env.device, &env.context);
+    thread::scope(|scope| {
+        let holder = thread::Builder::new()
+            .name("surfman-current-holder".to_owned())
+            .spawn_scoped(scope, move || {
+                let shared = shared;
+                shared.0.make_context_current(shared.1).unwrap();
+                assert_eq!(
+                    shared.0.context_current_thread(shared.1),
+                    Some(thread::current().id())
+                );
+                current_sender.send(()).unwrap();
+                release_receiver.recv().unwrap();
+                shared.0.make_no_context_current().unwrap();
+            })
+            .unwrap();
+        current_receiver.recv().unwrap();
+
+        let holder_thread = holder.thread().id();
+        assert_eq!(
+            env.device.context_current_thread(&env.context),
+            Some(holder_thread)
+        );
+        match env.device.make_context_current(&env.context) {
+            Err(err @ Error::ContextCurrentOnAnotherThread { .. }) => {
+                assert!(err.to_string().contains("surfman-current-holder"));
+                if let Error::ContextCurrentOnAnotherThread {
+                    context_id,
+                    thread,
+                    thread_name,
+                    ..
+                } = err
+                {
+                    assert_eq!(context_id, env.device.context_id(&env.context));
+                    assert_eq!(thread, holder_thread);
+                    assert_eq!(thread_name.as_deref(), Some("surfman-current-holder"));
+                }
+            }
+            // Some drivers let two threads have a context current at once.
+            Ok(()) => env.device.make_no_context_current().unwrap(),
+            Err(err) => panic!("Making a busy context current returned {:?}", err),
+        }
+
+        release_sender.send(()).unwrap();
+        holder.join().unwrap();
+    });
+    assert_eq!(env.device.context_current_thread(&env.context), None);
+
+    env.device.make_context_current(&env.context).unwrap();
+    assert_eq!(
+        env.device.context_current_thread(&env.context),
+        Some(main_thread)
+    );
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that a refused make-current names the thread holding the context, and that contexts stop
+// being attributed to a thread once it releases them.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_current_context_holder_diagnostics() {
+    let context_id = ContextID(u64::MAX);
+    let (current_sender, current_receiver) = mpsc::channel();
+    let (release_sender, release_receiver) = mpsc::channel::<()>();
+    let (released_sender, released_receiver) = mpsc::channel();
+    let holder = thread::Builder::new()
+        .name("surfman-diagnostics-holder".to_owned())
+        .spawn(move || {
+            current::make_current(context_id, || Ok(())).unwrap();
+            current_sender.send(()).unwrap();
+            release_receiver.recv().unwrap();
+            current::released();
+            released_sender.send(()).unwrap();
+        })
+        .unwrap();
+    current_receiver.recv().unwrap();
+    let holder_thread = holder.thread().id();
+    assert_eq!(current::current_thread(context_id), Some(holder_thread));
+
+    thread::sleep(Duration::from_millis(10));
+    let bad_access = || Err(Error::MakeCurrentFailed(WindowingApiError::BadAccess));
+    match current::make_current(context_id, bad_access) {
+        Err(Error::ContextCurrentOnAnotherThread {
+            context_id: busy_context_id,
+            thread,
+            thread_name,
+            current_for,
+        }) => {
+            assert_eq!(busy_context_id, context_id);
+            assert_eq!(thread, holder_thread);
+            assert_eq!(thread_name.as_deref(), Some("surfman-diagnostics-holder"));
+            assert!(current_for >= Duration::from_millis(10));
+        }
+        other => panic!("Refused make-current returned {:?}", other),
+    }
+    // Other failures pass through untouched.
+    let failed = || Err(Error::MakeCurrentFailed(WindowingApiError::BadContext));
+    assert!(matches!(
+        current::make_current(context_id, failed),
+        Err(Error::MakeCurrentFailed(WindowingApiError::BadContext))
+    ));
+
+    release_sender.send(()).unwrap();
+    released_receiver.recv().unwrap();
+    assert_eq!(current::current_thread(context_id), None);
+    // With no holder to name, the error is left as it was.
+    assert!(matches!(
+        current::make_current(context_id, bad_access),
+        Err(Error::MakeCurrentFailed(WindowingApiError::BadAccess))
+    ));
+    holder.join().unwrap();
+
+    current::make_current(context_id, || Ok(())).unwrap();
+    assert_eq!(
+        current::current_thread(context_id),
+        Some(thread::current().id())
+    );
+    current::forget(context_id);
+    assert_eq!(current::current_thread(context_id), None);
+}
+
+// Tests that a context is only held for bulk operations while it's marked and still current, and
+// that nested marks are unwound in order.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_bulk_ops_scope() {
+    let (context_id, other_context_id) = (ContextID(u64::MAX - 1), ContextID(u64::MAX - 2));
+    current::make_current(context_id, || Ok(())).unwrap();
+    assert!(!current::held_for_bulk_ops(context_id));
+    {
+        let _scope = current::begin_bulk_ops(context_id);
+        assert!(current::held_for_bulk_ops(context_id));
+        assert!(!current::held_for_bulk_ops(other_context_id));
+        {
+            current::make_current(other_context_id, || Ok(())).unwrap();
+            let _scope = current::begin_bulk_ops(other_context_id);
+            assert!(current::held_for_bulk_ops(other_context_id));
+            assert!(!current::held_for_bulk_ops(context_id));
+        }
+        // Still marked, but no longer current.
+        assert!(!current::held_for_bulk_ops(context_id));
+        current::make_current(context_id, || Ok(())).unwrap();
+        assert!(current::held_for_bulk_ops(context_id));
+        current::released();
+        assert!(!current::held_for_bulk_ops(context_id));
+        current::make_current(context_id, || Ok(())).unwrap();
+    }
+    assert!(!current::held_for_bulk_ops(context_id));
+    current::forget(context_id);
+    current::forget(other_context_id);
+}
+
+// Tests that surfman's own operations on another context leave the caller's context current.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_internal_operations_preserve_current_context() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let mut other_context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+
+    // Stand in for another GL library that owns the current context and some objects in it.
+    env.device.make_context_current(&env.context).unwrap();
+    let mut texture = 0;
+    unsafe {
+        env.gl.ClearColor(0.25, 0.5, 0.75, 1.0);
+        env.gl.GenTextures(1, &mut texture);
+        env.gl.BindTexture(gl::TEXTURE_2D, texture);
+    }
+    let check_current = |env: &BasicEnvironment| unsafe {
+        let mut clear_color = [0.0; 4];
+        env.gl
+            .GetFloatv(gl::COLOR_CLEAR_VALUE, clear_color.as_mut_ptr());
+        assert_eq!(clear_color, [0.25, 0.5, 0.75, 1.0]);
+        assert_eq!(env.gl.IsTexture(texture), gl::TRUE);
+    };
+
+    let surface = env
+        .device
+        .create_surface(
+            &other_context,
+            SurfaceAccess::GPUOnly,
+            SurfaceType::Generic {
+                size: Size2D::new(64, 64),
+            },
+        )
+        .unwrap();
+    check_current(&env);
+    let surface_texture = env
+        .device
+        .create_surface_texture(&mut other_context, surface)
+        .unwrap();
+    check_current(&env);
+    let mut surface = env
+        .device
+        .destroy_surface_texture(&mut other_context, surface_texture)
+        .unwrap();
+    check_current(&env);
+    env.device
+        .destroy_surface(&mut other_context, &mut surface)
+        .unwrap();
+    check_current(&env);
+    env.device.destroy_context(&mut other_context).unwrap();
+    check_current(&env);
+
+    unsafe {
+        env.gl.DeleteTextures(1, &texture);
+    }
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that cancelling a device's token from another thread stops context and surface creation
+// until the token is reset.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_cancellation_token() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let cancel_token = env.device.cancellation_token();
+    assert!(!cancel_token.is_cancelled());
+
+    let remote_token = env.device.cancellation_token();
+    thread::spawn(move || remote_token.cancel()).join().unwrap();
+    assert!(cancel_token.is_cancelled());
+
+    let context_attributes = env
+        .device
+        .context_descriptor_attributes(&env.context_descriptor);
+    assert!(matches!(
+        env.device.create_context_descriptor(&context_attributes),
+        Err(Error::Cancelled)
+    ));
+    assert!(matches!(
+        env.device.create_context(&env.context_descriptor, None),
+        Err(Error::Cancelled)
+    ));
+    assert!(matches!(
+        env.device.create_surface(
+            &env.context,
+            SurfaceAccess::GPUOnly,
+            SurfaceType::Generic {
+                size: Size2D::new(64, 64),
+            },
+        ),
+        Err(Error::Cancelled)
+    ));
+
+    // Once the token is reset, creation works again.
+    cancel_token.reset();
+    let mut other_context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    let mut surface = env
+        .device
+        .create_surface(
+            &other_context,
+            SurfaceAccess::GPUOnly,
+            SurfaceType::Generic {
+                size: Size2D::new(64, 64),
+            },
+        )
+        .unwrap();
+
+    // Destruction is never cancelled.
+    cancel_token.cancel();
+    env.device
+        .destroy_surface(&mut other_context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut other_context).unwrap();
+    cancel_token.reset();
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that a batch of surfaces is created in order, and that cancelling it partway through
+// destroys the surfaces that it had already created.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_create_surfaces_cancellation() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let generic = |width| SurfaceType::Generic {
+        size: Size2D::new(width, 64),
+    };
+
+    let scope = leak_check::scope();
+    let mut surfaces = env
+        .device
+        .create_surfaces(
+            &mut env.context,
+            SurfaceAccess::GPUOnly,
+            (1..=3).map(|index| generic(index * 32)),
+        )
+        .unwrap();
+    let widths: Vec<i32> = surfaces
+        .iter()
+        .map(|surface| env.device.surface_info(surface).size.width)
+        .collect();
+    assert_eq!(widths, [32, 64, 96]);
+    for surface in &mut surfaces {
+        env.device
+            .destroy_surface(&mut env.context, surface)
+            .unwrap();
+    }
+    scope.finish().unwrap();
+
+    // The token is cancelled once two surfaces have been asked for, so the third isn't created.
+    let cancel_token = env.device.cancellation_token();
+    let scope = leak_check::scope();
+    let surface_types = (1..=4).map(|index| {
+        if index == 3 {
+            cancel_token.cancel();
+        }
+        generic(index * 32)
+    });
+    assert!(matches!(
+        env.device
+            .create_surfaces(&mut env.context, SurfaceAccess::GPUOnly, surface_types),
+        Err(Error::Cancelled)
+    ));
+    scope.finish().unwrap();
+    cancel_token.reset();
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that the version and flags a context was granted can be queried.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_context_info() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let info = env.device.context_info(&env.context).unwrap();
+    assert_eq!(info.gl_api, env.device.gl_api());
+    assert_eq!(info.version, env.capabilities.version);
+    assert!(!info.renderer.is_empty());
+    // Every implementation offers at least what OpenGL ES 2.0 guarantees.
+    assert!(env.capabilities.max_varying_vectors >= 8);
+
+    // What was granted is at least what was requested.
+    let requested = env
+        .device
+        .context_descriptor_attributes(&env.context_descriptor);
+    assert!(
+        (info.version.major, info.version.minor)
+            >= (requested.version.major, requested.version.minor)
+    );
+
+    // The flags agree with the descriptor, which is also read back from the context.
+    let context_flags = ContextAttributeFlags::COMPATIBILITY_PROFILE
+        | ContextAttributeFlags::DEBUG
+        | ContextAttributeFlags::ROBUST_ACCESS
+        | ContextAttributeFlags::NO_ERROR;
+    let descriptor = env.device.context_descriptor(&env.context);
+    let granted = env.device.context_descriptor_attributes(&descriptor);
+    assert_eq!(info.flags, granted.flags & context_flags);
+
+    // Querying another context leaves this one current.
+    let mut other_context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    env.device.make_context_current(&env.context).unwrap();
+    unsafe {
+        env.gl.ClearColor(0.25, 0.5, 0.75, 1.0);
+    }
+    assert_eq!(env.device.context_info(&other_context).unwrap(), info);
+    let mut clear_color = [0.0; 4];
+    unsafe {
+        env.gl
+            .GetFloatv(gl::COLOR_CLEAR_VALUE, clear_color.as_mut_ptr());
+    }
+    assert_eq!(clear_color, [0.25, 0.5, 0.75, 1.0]);
+
+    env.device.destroy_context(&mut other_context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that all combinations of flags result in the creation of valid context descriptors and
+// contexts.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_context_creation() {
+    let connection = Connection::new().unwrap();
+    let adapter = connection
+        .create_low_power_adapter()
+        .expect("Failed to create adapter!");
+    let mut device = match connection.create_device(&adapter) {
+        Ok(device) => device,
+        Err(Error::RequiredExtensionUnavailable) => {
+            // Can't run these tests on this hardware.
+            return;
+        }
+        Err(err) => panic!("Failed to create device: {:?}", err),
+    };
+
+    let gl_api = device.gl_api();
+    let versions = match gl_api {
+        GLApi::GL => &GL_VERSIONS[..],
+        GLApi::GLES => &GL_ES_VERSIONS[..],
+    };
+
+    for &version in versions {
+        for flag_bits in 0..(ContextAttributeFlags::all().bits() + 1) {
+            let flags = ContextAttributeFlags::from_bits_truncate(flag_bits);
+            let attributes = ContextAttributes {
+                version,
+                flags,
+                ..Default::default()
+            };
+            let descriptor = match device.create_context_descriptor(&attributes) {
+                Ok(descriptor) => descriptor,
+                Err(Error::UnsupportedGLProfile) | Err(Error::UnsupportedGLVersion) => {
+                    // Nothing we can do about this. Go on to the next one.
+                    continue;
+                }
+                Err(Error::RequiredExtensionUnavailable)
+                    if flags.contains(ContextAttributeFlags::ROBUST_ACCESS) =>
+                {
+                    // Robust contexts aren't available everywhere.
+                    continue;
+                }
+                Err(Error::RequiredExtensionUnavailable)
+                    if flags.contains(ContextAttributeFlags::RELEASE_BEHAVIOR_NONE)
+                        && !flags.contains(ContextAttributeFlags::BEST_EFFORT) =>
+                {
+                    // Neither is flush control.
+                    continue;
+                }
+                Err(Error::ConflictingContextAttributeFlags)
+                    if flags.contains(ContextAttributeFlags::NO_ERROR) =>
+                {
+                    // `test_no_error_flag_conflicts()` covers these combinations.
+                    continue;
+                }
+                Err(err) => panic!("Context descriptor creation failed: {:?}", err),
+            };
+
+            match device.create_context(&descriptor, None) {
+                Ok(mut context) => {
+                    // Verify that the attributes round-trip. Platforms without debug or
+                    // no-error contexts may drop those flags, best-effort mode may drop flush
+                    // control, and lower minor versions may be granted if they're allowed.
+                    let actual_descriptor = device.context_descriptor(&context);
+                    let actual_attributes =
+                        device.context_descriptor_attributes(&actual_descriptor);
+                    let mut expected_flags = attributes.flags
+                        - ContextAttributeFlags::DEBUG
+                        - ContextAttributeFlags::NO_ERROR
+                        - ContextAttributeFlags::BEST_EFFORT
+                        - ContextAttributeFlags::ALLOW_LOWER_VERSION
+                        - ContextAttributeFlags::EXACT_SAMPLES;
+                    if flags.contains(ContextAttributeFlags::BEST_EFFORT) {
+                        expected_flags -= ContextAttributeFlags::RELEASE_BEHAVIOR_NONE;
+                    }
+                    if !actual_attributes.flags.contains(expected_flags) {
+                        device.destroy_context(&mut context).unwrap();
+                        panic!(
+                            "Expected at least attribute flags {:?} for {:?} {:?} but got \
+                                {:?}",
+                            attributes.flags, gl_api, version, actual_attributes.flags
+                        );
+                    }
+                    let minimum_version =
+                        if flags.contains(ContextAttributeFlags::ALLOW_LOWER_VERSION) {
+                            GLVersion::new(version.major, 0)
+                        } else {
+                            version
+                        };
+                    if actual_attributes.version.major < minimum_version.major
+                        || (actual_attributes.version.major == minimum_version.major
+                            && actual_attributes.version.minor < minimum_version.minor)
+                    {
+                        device.destroy_context(&mut context).unwrap();
+                        panic!(
+                            "Expected at least GL version {:?} but got version {:?}",
+                            attributes, actual_attributes
+                        );
+                    }
+
+                    device.destroy_context(&mut context).unwrap();
+                }
+                Err(Error::ContextCreationFailed(WindowingApiError::BadPixelFormat))
+                | Err(Error::ContextCreationFailed(WindowingApiError::BadConfig))
+                | Err(Error::ContextCreationFailed(WindowingApiError::BadMatch)) => {
+                    // This is OK, as it just means the GL implementation didn't support the
+                    // requested GL version.
+                }
+                Err(error) => {
+                    panic!(
+                        "Failed to create context ({:?}/{:?}): {:?}",
+                        version, flags, error
+                    )
+                }
+            }
+        }
+    }
+}
+
+// Tests that versioned context creation settles on the newest version in the range that the
+// device supports, sticks with it, and fails cleanly if even the lowest version is unsupported.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_create_versioned_context() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let versions = match env.device.gl_api() {
+        GLApi::GL => GLVersion::new(3, 0)..=GLVersion::new(4, 6),
+        GLApi::GLES => GLVersion::new(2, 0)..=GLVersion::new(3, 2),
+    };
+    let flags = ContextAttributeFlags::empty();
+
+    let (mut context, descriptor) = env
+        .device
+        .create_versioned_context(versions.clone(), flags)
+        .unwrap();
+    let version = env
+        .device
+        .context_descriptor_attributes(&descriptor)
+        .version;
+    assert!(versions.contains(&version));
+    env.device.make_context_current(&context).unwrap();
+    assert!(GLVersion::current(&env.gl) >= version);
+    env.device.destroy_context(&mut context).unwrap();
+
+    // Asking again settles on the same version.
+    let (mut context, descriptor) = env
+        .device
+        .create_versioned_context(versions.clone(), flags)
+        .unwrap();
+    assert_eq!(
+        env.device
+            .context_descriptor_attributes(&descriptor)
+            .version,
+        version
+    );
+    env.device.destroy_context(&mut context).unwrap();
+
+    // Versions above the newest supported one are skipped.
+    let (mut context, descriptor) = env
+        .device
+        .create_versioned_context(version..=GLVersion::new(9, 9), flags)
+        .unwrap();
+    assert_eq!(
+        env.device
+            .context_descriptor_attributes(&descriptor)
+            .version,
+        version
+    );
+    env.device.destroy_context(&mut context).unwrap();
+
+    // No implementation supports version 9, so a range that starts there has nothing to offer.
+    match env
+        .device
+        .create_versioned_context(GLVersion::new(9, 0)..=GLVersion::new(9, 9), flags)
+    {
+        Err(Error::UnsupportedGLVersion) => {}
+        Ok((mut context, _)) => {
+            env.device.destroy_context(&mut context).unwrap();
+            panic!("Created a context of an unsupported version!");
+        }
+        Err(err) => panic!("Expected `UnsupportedGLVersion` but got {:?}", err),
+    }
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that the debug flag round-trips when the platform creates a debug context, and is
+// dropped rather than failing context creation when it can't.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_debug_context_flag() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let attributes = env
+        .device
+        .context_descriptor_attributes(&env.device.context_descriptor(&env.context));
+    assert!(!attributes.flags.contains(ContextAttributeFlags::DEBUG));
+
+    let debug_descriptor = env
+        .device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::DEBUG,
+            ..Default::default()
+        })
+        .unwrap();
+    let mut debug_context = env.device.create_context(&debug_descriptor, None).unwrap();
+    let attributes = env
+        .device
+        .context_descriptor_attributes(&env.device.context_descriptor(&debug_context));
+
+    // The reported flag must agree with what OpenGL says about the context.
+    env.device.make_context_current(&debug_context).unwrap();
+    assert_eq!(
+        attributes.flags.contains(ContextAttributeFlags::DEBUG),
+        context::current_context_is_debug(&env.gl)
+    );
+
+    env.device.destroy_context(&mut debug_context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that priority hints never fail context creation, and that a context is never granted a
+// higher priority than it asked for.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_context_priority() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    assert_eq!(
+        env.device.context_priority(&env.context),
+        ContextPriority::Medium
+    );
+
+    for &priority in &[
+        ContextPriority::Low,
+        ContextPriority::Medium,
+        ContextPriority::High,
+        ContextPriority::Realtime,
+    ] {
+        let descriptor = env
+            .device
+            .create_context_descriptor(&ContextAttributes {
+                version: GLVersion::new(3, 0),
+                flags: ContextAttributeFlags::empty(),
+                priority,
+                ..Default::default()
+            })
+            .unwrap();
+        let mut context = env.device.create_context(&descriptor, None).unwrap();
+
+        let granted_priority = env.device.context_priority(&context);
+        assert!(
+            granted_priority <= priority || granted_priority == ContextPriority::Medium,
+            "Asked for {:?} priority but was granted {:?}",
+            priority,
+            granted_priority
+        );
+        let attributes = env
+            .device
+            .context_descriptor_attributes(&env.device.context_descriptor(&context));
+        assert_eq!(attributes.priority, granted_priority);
+
+        env.device.destroy_context(&mut context).unwrap();
+    }
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that a background scheduling class is enforced the way the device reports.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_scheduling_class() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    assert_eq!(env.device.scheduling_class(), SchedulingClass::Interactive);
+    assert_eq!(
+        env.device.scheduling_enforcement(),
+        SchedulingEnforcement::None
+    );
+
+    let enforcement = env.device.set_scheduling_class(SchedulingClass::Background);
+    assert_ne!(enforcement, SchedulingEnforcement::None);
+    assert_eq!(env.device.scheduling_class(), SchedulingClass::Background);
+    assert_eq!(env.device.scheduling_enforcement(), enforcement);
+
+    // Contexts created from now on don't get the high priority they ask for.
+    let descriptor = env
+        .device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::empty(),
+            priority: ContextPriority::High,
+            ..Default::default()
+        })
+        .unwrap();
+    let mut context = env.device.create_context(&descriptor, None).unwrap();
+    match enforcement {
+        SchedulingEnforcement::ContextPriority => {
+            assert!(env.device.context_priority(&context) < ContextPriority::High)
+        }
+        SchedulingEnforcement::KernelPriority => {
+            assert_eq!(env.device.context_priority(&context), ContextPriority::Low)
+        }
+        _ => {}
+    }
+    env.device.destroy_context(&mut context).unwrap();
+
+    assert_eq!(
+        env.device
+            .set_scheduling_class(SchedulingClass::Interactive),
+        SchedulingEnforcement::None
+    );
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that the DRM ioctls that lower kernel context priorities have the numbers that the Linux
+// UAPI headers give them.
+#[cfg(all(free_unix, target_os = "linux", target_pointer_width = "64"))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_kernel_priority_ioctls() {
+    assert_eq!(kernel_priority::DRM_IOCTL_VERSION, 0xc040_6400);
+    assert_eq!(
+        kernel_priority::DRM_IOCTL_I915_GEM_CONTEXT_GETPARAM,
+        0xc018_6474
+    );
+    assert_eq!(
+        kernel_priority::DRM_IOCTL_I915_GEM_CONTEXT_SETPARAM,
+        0xc018_6475
+    );
+    assert_eq!(kernel_priority::DRM_IOCTL_AMDGPU_CTX, 0xc010_6442);
+    assert_eq!(kernel_priority::DRM_IOCTL_AMDGPU_SCHED, 0x4010_6455);
+}
+
+// Tests that on i915 and amdgpu, background contexts get a low kernel priority when the EGL driver
+// can't give them one. This only runs on hardware whose driver lacks `EGL_IMG_context_priority`,
+// and for amdgpu, as the DRM master.
+#[cfg(all(free_unix, target_os = "linux"))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_kernel_context_priority() {
+    let has_render_node = std::fs::read_dir("/dev/dri")
+        .map(|entries| {
+            entries.flatten().any(|entry| {
+                let name = entry.file_name();
+                name.to_string_lossy().starts_with("renderD")
+            })
+        })
+        .unwrap_or(false);
+    if !has_render_node {
+        return;
+    }
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let enforcement = env.device.set_scheduling_class(SchedulingClass::Background);
+    if enforcement != SchedulingEnforcement::KernelPriority {
+        env.device.destroy_context(&mut env.context).unwrap();
+        return;
+    }
+
+    let mut context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    assert_eq!(env.device.context_priority(&context), ContextPriority::Low);
+    env.device.destroy_context(&mut context).unwrap();
+
+    // Interactive contexts are left alone.
+    env.device
+        .set_scheduling_class(SchedulingClass::Interactive);
+    let mut context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    assert_eq!(
+        env.device.context_priority(&context),
+        ContextPriority::Medium
+    );
+    env.device.destroy_context(&mut context).unwrap();
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that swap chains on a device whose background class is enforced in software are paced.
+#[cfg(feature = "chains")]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_swap_chain_background_pacing() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let swap_chain =
+        SwapChain::create_attached(&mut env.device, &mut env.context, SurfaceAccess::GPUOnly)
+            .unwrap();
+
+    // Pacing keeps frames apart, but doesn't hold back frames that are already late.
+    let mut pacer = FramePacer::default();
+    let start = Instant::now();
+    assert_eq!(pacer.delay(start), Duration::ZERO);
+    let early = Duration::from_millis(10);
+    assert_eq!(
+        pacer.delay(start + early),
+        BACKGROUND_FRAME_INTERVAL - early
+    );
+    let late = BACKGROUND_FRAME_INTERVAL * 4;
+    assert_eq!(pacer.delay(start + late), Duration::ZERO);
+
+    let enforcement = env.device.set_scheduling_class(SchedulingClass::Background);
+    let start = Instant::now();
+    for _ in 0..3 {
+        render_swap_chain_frame(&mut env, &swap_chain, &[255, 255, 255, 255]);
+    }
+    if enforcement == SchedulingEnforcement::Software {
+        assert!(start.elapsed() >= BACKGROUND_FRAME_INTERVAL * 2);
+    }
+
+    // Interactive swap chains aren't held back.
+    env.device
+        .set_scheduling_class(SchedulingClass::Interactive);
+    let start = Instant::now();
+    for _ in 0..3 {
+        render_swap_chain_frame(&mut env, &swap_chain, &[255, 255, 255, 255]);
+    }
+    assert!(start.elapsed() < BACKGROUND_FRAME_INTERVAL * 2);
+
+    swap_chain
+        .destroy(&mut env.device, &mut env.context)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that low-memory mode purges idle placeholders and downgrades new surfaces to 16 bits.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_low_memory_mode() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    assert_eq!(env.device.low_memory_options(), None);
+
+    // Leave an idle pbuffer in the shared placeholder pool.
+    let descriptor = env
+        .device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::empty(),
+            placeholder_policy: PlaceholderPolicy::Shared,
+            ..Default::default()
+        })
+        .unwrap();
+    let mut context = env.device.create_context(&descriptor, None).unwrap();
+    env.device.make_context_current(&context).unwrap();
+    env.device.make_no_context_current().unwrap();
+
+    let options = LowMemoryOptions {
+        prefer_rgb565: true,
+        purge_pools: true,
+        min_chain_depth: 2,
+    };
+    assert!(env.device.enter_low_memory_mode(options) > 0);
+    assert_eq!(env.device.low_memory_options(), Some(options));
+    assert_eq!(env.device.enter_low_memory_mode(options), 0);
+
+    // Purged placeholders are recreated on demand.
+    env.device.make_context_current(&context).unwrap();
+
+    let surface = make_surface(&mut env.device, &context);
+    assert_eq!(env.device.surface_info(&surface).format, GL_RGB565);
+    let mut cpu_surface = env
+        .device
+        .create_surface(
+            &context,
+            SurfaceAccess::GPUCPU,
+            SurfaceType::Generic {
+                size: Size2D::new(640, 480),
+            },
+        )
+        .unwrap();
+    assert_eq!(env.device.surface_info(&cpu_surface).format, gl::RGBA8);
+    env.device
+        .destroy_surface(&mut context, &mut cpu_surface)
+        .unwrap();
+
+    // 16-bit surfaces can be rendered to like any other.
+    env.device
+        .bind_surface_to_context(&mut context, surface)
+        .unwrap();
+    let info = env.device.begin_rendering(&context).unwrap();
+    assert_eq!(info.format, GL_RGB565);
+    clear(&env.gl, &[255, 0, 0, 255]);
+    assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+    env.device.end_rendering(&context, true).unwrap();
+    let mut surface = env
+        .device
+        .unbind_surface_from_context(&mut context)
+        .unwrap()
+        .unwrap();
+    env.device
+        .destroy_surface(&mut context, &mut surface)
+        .unwrap();
+
+    env.device.exit_low_memory_mode();
+    assert_eq!(env.device.low_memory_options(), None);
+    let mut surface = make_surface(&mut env.device, &context);
+    assert_eq!(env.device.surface_info(&surface).format, gl::RGBA8);
+    env.device
+        .destroy_surface(&mut context, &mut surface)
+        .unwrap();
+
+    env.device.destroy_context(&mut context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that volatile surfaces report their state and can't be used until they're reclaimed.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_surface_purgeability() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let mut context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    let mut surface = make_surface(&mut env.device, &context);
+    assert_eq!(
+        env.device.surface_info(&surface).purgeability,
+        Purgeability::NonPurgeable
+    );
+    env.device
+        .set_surface_purgeable(&mut surface, Purgeability::Volatile)
+        .unwrap();
+    env.device
+        .set_surface_purgeable(&mut surface, Purgeability::Volatile)
+        .unwrap();
+    assert_eq!(
+        env.device.surface_info(&surface).purgeability,
+        Purgeability::Volatile
+    );
+
+    // Using the surface before reclaiming it is caught in debug builds.
+    if cfg!(debug_assertions) {
+        surface = match env.device.bind_surface_to_context(&mut context, surface) {
+            Err((Error::SurfaceNotReclaimed, surface)) => surface,
+            result => panic!("Binding a volatile surface returned {:?}", result.err()),
+        };
+    }
+
+    // Nothing else needs the memory, so the contents survive. Linux never discards them.
+    let result = env.device.reclaim_surface(&mut surface).unwrap();
+    if cfg!(target_os = "linux") {
+        assert_eq!(result, ReclaimResult::Intact);
+    }
+    assert_eq!(
+        env.device.reclaim_surface(&mut surface).unwrap(),
+        ReclaimResult::Intact
+    );
+    assert_eq!(
+        env.device.surface_info(&surface).purgeability,
+        Purgeability::NonPurgeable
+    );
+
+    env.device
+        .bind_surface_to_context(&mut context, surface)
+        .unwrap();
+    let mut surface = env
+        .device
+        .unbind_surface_from_context(&mut context)
+        .unwrap()
+        .unwrap();
+    env.device
+        .destroy_surface(&mut context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that robust contexts are either created as requested or refused outright.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_robust_access_flag() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let attributes = env
+        .device
+        .context_descriptor_attributes(&env.device.context_descriptor(&env.context));
+    assert!(!attributes
+        .flags
+        .contains(ContextAttributeFlags::ROBUST_ACCESS));
+    assert_eq!(
+        env.device.context_reset_status(&env.context).unwrap(),
+        ResetStatus::NoError
+    );
+
+    // Robustness must never be silently dropped.
+    let robust_descriptor = match env.device.create_context_descriptor(&ContextAttributes {
+        version: GLVersion::new(3, 0),
+        flags: ContextAttributeFlags::ROBUST_ACCESS,
+        ..Default::default()
+    }) {
+        Ok(descriptor) => descriptor,
+        Err(Error::RequiredExtensionUnavailable) => {
+            env.device.destroy_context(&mut env.context).unwrap();
+            return;
+        }
+        Err(err) => panic!("Failed to create a robust context descriptor: {:?}", err),
+    };
+    let mut robust_context = env.device.create_context(&robust_descriptor, None).unwrap();
+    let attributes = env
+        .device
+        .context_descriptor_attributes(&env.device.context_descriptor(&robust_context));
+    assert!(attributes
+        .flags
+        .contains(ContextAttributeFlags::ROBUST_ACCESS));
+    assert_eq!(
+        env.device.context_reset_status(&robust_context).unwrap(),
+        ResetStatus::NoError
+    );
+
+    env.device.make_context_current(&robust_context).unwrap();
+    assert!(context::current_context_is_robust(&env.gl));
+
+    env.device.destroy_context(&mut robust_context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that leak check scopes catch objects that outlive them, unless they're allowed to.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_leak_check() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let leaked = |leaks: &[leak_check::Leak], object| {
+        leaks
+            .iter()
+            .any(|leak| leak.object == object && leak.after == leak.before + 1)
+    };
+
+    let scope = leak_check::scope();
+    let mut other_context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    let surface = make_surface(&mut env.device, &env.context);
+    let leaks = scope.finish().unwrap_err();
+    assert!(leaked(&leaks, TrackedObject::Context));
+    assert!(leaked(&leaks, TrackedObject::Surface));
+    assert!(!leaked(&leaks, TrackedObject::SurfaceTexture));
+
+    let mut scope = leak_check::scope();
+    let surface_texture = env
+        .device
+        .create_surface_texture(&mut other_context, surface)
+        .unwrap();
+    assert!(leaked(&scope.leaks(), TrackedObject::SurfaceTexture));
+    scope.allow(TrackedObject::SurfaceTexture, 1);
+    scope.finish().unwrap();
+
+    // Destroying everything brings the counts back to where the environment started.
+    let scope = leak_check::scope();
+    let mut surface = env
+        .device
+        .destroy_surface_texture(&mut other_context, surface_texture)
+        .unwrap();
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut other_context).unwrap();
+    scope.finish().unwrap();
+
+    assert!(leaked(&env.leak_check.leaks(), TrackedObject::Context));
+    env.device.destroy_context(&mut env.context).unwrap();
+    assert!(env.leak_check.leaks().is_empty());
+}
+
+// Tests that lost contexts are reported once, and fail with `ContextLost` until destroyed.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_context_lost() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let reported = Arc::new(Mutex::new(vec![]));
+    let handler_reported = reported.clone();
+    env.device
+        .set_context_lost_handler(Some(Box::new(move |context_id| {
+            handler_reported.lock().unwrap().push(context_id)
+        })));
+    assert!(!env.device.poll_context_lost(&env.context));
+    env.device.make_context_current(&env.context).unwrap();
+    assert!(reported.lock().unwrap().is_empty());
+
+    // A real reset can't be provoked portably, so drive the bookkeeping with the errors that EGL
+    // reports for one.
+    let context_id = env.device.context_id(&env.context);
+    let mut lost_contexts = LostContexts::default();
+    let lost_reported = reported.clone();
+    lost_contexts.set_handler(Some(Box::new(move |context_id| {
+        lost_reported.lock().unwrap().push(context_id)
+    })));
+    assert!(lost_contexts.check(context_id, || Ok(())).is_ok());
+    match lost_contexts.check(context_id, || -> Result<(), Error> {
+        Err(Error::MakeCurrentFailed(WindowingApiError::BadMatch))
+    }) {
+        Err(Error::MakeCurrentFailed(WindowingApiError::BadMatch)) => {}
+        result => panic!("Unrelated errors shouldn't lose the context: {:?}", result),
+    }
+    assert!(!lost_contexts.is_lost(context_id));
+    match lost_contexts.check(context_id, || -> Result<(), Error> {
+        Err(Error::PresentFailed(WindowingApiError::ContextLost))
+    }) {
+        Err(Error::ContextLost) => {}
+        result => panic!("Expected `ContextLost`, got {:?}", result),
+    }
+    assert_eq!(*reported.lock().unwrap(), vec![context_id]);
+
+    // Once lost, the driver isn't asked again, and the handler isn't called again.
+    match lost_contexts.check(context_id, || -> Result<(), Error> {
+        panic!("A lost context was made current")
+    }) {
+        Err(Error::ContextLost) => {}
+        result => panic!("Expected `ContextLost`, got {:?}", result),
+    }
+    assert!(lost_contexts.poll(context_id, || Ok(ResetStatus::NoError)));
+    assert_eq!(reported.lock().unwrap().len(), 1);
+
+    // A reset reported by the driver loses the context too.
+    lost_contexts.forget(context_id);
+    assert!(!lost_contexts.poll(context_id, || Ok(ResetStatus::NoError)));
+    assert!(lost_contexts.poll(context_id, || Ok(ResetStatus::InnocentContextReset)));
+    assert_eq!(reported.lock().unwrap().len(), 2);
+
+    env.device.set_context_lost_handler(None);
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that no-error contexts can't be combined with debug or robust ones, and that they can be
+// created on their own.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_no_error_flag_conflicts() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let attributes_with_flags = |flags| ContextAttributes {
+        version: GLVersion::new(3, 0),
+        flags,
+        ..Default::default()
+    };
+
+    for &conflicting_flags in &[
+        ContextAttributeFlags::DEBUG,
+        ContextAttributeFlags::ROBUST_ACCESS,
+        ContextAttributeFlags::DEBUG | ContextAttributeFlags::ROBUST_ACCESS,
+    ] {
+        let flags = ContextAttributeFlags::NO_ERROR | conflicting_flags;
+        match env
+            .device
+            .create_context_descriptor(&attributes_with_flags(flags))
+        {
+            Err(Error::ConflictingContextAttributeFlags) => {}
+            Err(err) => panic!("Expected a flag conflict for {:?} but got {:?}", flags, err),
+            Ok(_) => panic!("Expected a flag conflict for {:?}", flags),
+        }
+    }
+
+    // On its own, the flag is only a hint.
+    let descriptor = env
+        .device
+        .create_context_descriptor(&attributes_with_flags(ContextAttributeFlags::NO_ERROR))
+        .unwrap();
+    let mut context = env.device.create_context(&descriptor, None).unwrap();
+    let attributes = env
+        .device
+        .context_descriptor_attributes(&env.device.context_descriptor(&context));
+    assert!(!attributes.flags.contains(ContextAttributeFlags::DEBUG));
+
+    env.device.destroy_context(&mut context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that contexts that don't flush on release are only created without flush control in
+// best-effort mode, and that their descriptors report what was granted.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_release_behavior_flag() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let attributes_with_flags = |flags| ContextAttributes {
+        version: GLVersion::new(3, 0),
+        flags,
+        ..Default::default()
+    };
+
+    let supported = match env.device.create_context_descriptor(&attributes_with_flags(
+        ContextAttributeFlags::RELEASE_BEHAVIOR_NONE,
+    )) {
+        Ok(descriptor) => {
+            let attributes = env.device.context_descriptor_attributes(&descriptor);
+            assert!(attributes
+                .flags
+                .contains(ContextAttributeFlags::RELEASE_BEHAVIOR_NONE));
+            true
+        }
+        Err(Error::RequiredExtensionUnavailable) => false,
+        Err(err) => panic!("Failed to create context descriptor: {:?}", err),
+    };
+
+    // In best-effort mode, descriptor creation succeeds either way.
+    let descriptor = env
+        .device
+        .create_context_descriptor(&attributes_with_flags(
+            ContextAttributeFlags::RELEASE_BEHAVIOR_NONE | ContextAttributeFlags::BEST_EFFORT,
+        ))
+        .unwrap();
+    let attributes = env.device.context_descriptor_attributes(&descriptor);
+    assert_eq!(
+        attributes
+            .flags
+            .contains(ContextAttributeFlags::RELEASE_BEHAVIOR_NONE),
+        supported
+    );
+    assert!(!attributes
+        .flags
+        .contains(ContextAttributeFlags::BEST_EFFORT));
+
+    let mut context = env.device.create_context(&descriptor, None).unwrap();
+    let info = env.device.context_info(&context).unwrap();
+    assert_eq!(
+        info.flags
+            .contains(ContextAttributeFlags::RELEASE_BEHAVIOR_NONE),
+        supported
+    );
+
+    env.device.destroy_context(&mut context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that newly-created contexts are not immediately made current (issue #7).
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_newly_created_contexts_are_not_current() {
+    let connection = Connection::new().unwrap();
+    let adapter = connection
+        .create_low_power_adapter()
+        .expect("Failed to create adapter!");
+    let mut device = match connection.create_device(&adapter) {
+        Ok(device) => device,
+        Err(Error::RequiredExtensionUnavailable) => {
+            // Can't run these tests on this hardware.
+            return;
+        }
+        Err(err) => panic!("Failed to create device: {:?}", err),
+    };
+
+    let context_descriptor = device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::empty(),
+            ..Default::default()
+        })
+        .unwrap();
+
+    // Make no context current.
+    device.make_no_context_current().unwrap();
+
+    let mut context = device.create_context(&context_descriptor, None).unwrap();
+    let gl = Gl::load_with(device.proc_address_loader(&context));
+
+    unsafe {
+        // Check to make sure GL calls don't work before a context is made current.
+        clear(&gl, &[255, 0, 0, 255]);
+        assert_ne!(get_pixel_from_bottom_row(&gl), [255, 0, 0, 255]);
+
+        // Make a context current.
+        let surface = make_surface(&mut device, &context);
+        device
+            .bind_surface_to_context(&mut context, surface)
+            .unwrap();
+        device.make_context_current(&context).unwrap();
+        let framebuffer_object = device
+            .context_surface_info(&context)
+            .unwrap()
+            .unwrap()
+            .framebuffer_object;
+        gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
+
+        // Now that a context is current, GL calls should work.
+        clear(&gl, &[0, 255, 0, 255]);
+        assert_eq!(get_pixel_from_bottom_row(&gl), [0, 255, 0, 255]);
+
+        // Remove the surface.
+        let mut surface = device
+            .unbind_surface_from_context(&mut context)
+            .unwrap()
+            .unwrap();
+        device.make_no_context_current().unwrap();
+
+        // GL calls shouldn't work any longer.
+        clear(&gl, &[255, 0, 0, 255]);
+        assert_ne!(get_pixel_from_bottom_row(&gl), [255, 0, 0, 255]);
+
+        // Clean up.
+        device.destroy_surface(&mut context, &mut surface).unwrap();
+        device.destroy_context(&mut context).unwrap();
+    }
+}
+
+// Tests a simple case of one context being shared with another.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_context_sharing() {
+    let connection = Connection::new().unwrap();
+    let adapter = connection
+        .create_low_power_adapter()
+        .expect("Failed to create adapter!");
+    let mut device = match connection.create_device(&adapter) {
+        Ok(device) => device,
+        Err(Error::RequiredExtensionUnavailable) => {
+            // Can't run these tests on this hardware.
+            return;
+        }
+        Err(err) => panic!("Failed to create device: {:?}", err),
+    };
+
+    let context_descriptor = device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::empty(),
+            ..Default::default()
+        })
+        .unwrap();
+
+    let mut parent_context = device.create_context(&context_descriptor, None).unwrap();
+    let mut child1_context = device
+        .create_context(&context_descriptor, Some(&parent_context))
+        .unwrap();
+    let mut child2_context = device
+        .create_context(&context_descriptor, Some(&parent_context))
+        .unwrap();
+    let mut child3_context = device
+        .create_context(&context_descriptor, Some(&child1_context))
+        .unwrap();
+
+    device.destroy_context(&mut child3_context).unwrap();
+    device.destroy_context(&mut child2_context).unwrap();
+    device.destroy_context(&mut child1_context).unwrap();
+    device.destroy_context(&mut parent_context).unwrap();
+}
+
+// Tests that a texture created in one context can be read in a context that shares with it, and
+// that contexts can only share with contexts that use the same config.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_shared_context_objects() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // Upload a texture in the environment's context.
+    let texture = unsafe {
+        let mut texture = 0;
+        env.gl.GenTextures(1, &mut texture);
+        env.gl.BindTexture(gl::TEXTURE_2D, texture);
+        env.gl.TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGBA as _,
+            1,
+            1,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            [255u8, 0, 255, 255].as_ptr() as *const c_void,
+        );
+        env.gl.BindTexture(gl::TEXTURE_2D, 0);
+        env.gl.Finish();
+        texture
+    };
+
+    // Read it back through a framebuffer in a shared context.
+    let mut shared_context = env
+        .device
+        .create_context(&env.context_descriptor, Some(&env.context))
+        .unwrap();
+    env.device.make_context_current(&shared_context).unwrap();
+    unsafe {
+        assert_eq!(env.gl.IsTexture(texture), gl::TRUE);
+        let mut framebuffer = 0;
+        env.gl.GenFramebuffers(1, &mut framebuffer);
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+        env.gl.FramebufferTexture2D(
+            gl::FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::TEXTURE_2D,
+            texture,
+            0,
+        );
+        assert_eq!(
+            env.gl.CheckFramebufferStatus(gl::FRAMEBUFFER),
+            gl::FRAMEBUFFER_COMPLETE
+        );
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 255, 255]);
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        env.gl.DeleteFramebuffers(1, &framebuffer);
+        env.gl.DeleteTextures(1, &texture);
+    }
+    env.device.destroy_context(&mut shared_context).unwrap();
+
+    // On EGL, a context with a different config can't join the share group.
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    {
+        let depth_descriptor = env
+            .device
+            .create_context_descriptor(&ContextAttributes {
+                version: GLVersion::new(3, 0),
+                flags: ContextAttributeFlags::DEPTH | ContextAttributeFlags::STENCIL,
+                ..Default::default()
+            })
+            .unwrap();
+        let depth_attributes = env.device.context_descriptor_attributes(&depth_descriptor);
+        let attributes = env
+            .device
+            .context_descriptor_attributes(&env.context_descriptor);
+        if depth_attributes.flags != attributes.flags {
+            match env
+                .device
+                .create_context(&depth_descriptor, Some(&env.context))
+            {
+                Err(Error::IncompatibleSharedContext) => {}
+                Ok(mut context) => {
+                    env.device.destroy_context(&mut context).unwrap();
+                    panic!("Shared a context with a different config!");
+                }
+                Err(err) => panic!("Sharing with a different config returned {:?}", err),
+            }
+        }
+    }
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that a WGL context wrapped through its native handles reports the pixel format that it was
+// created with, that new contexts can share with it, and that neither works while another thread
+// has it current.
+#[cfg(all(target_os = "windows", feature = "sm-wgl", not(feature = "sm-no-wgl")))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_wgl_native_context_round_trip() {
+    use crate::platform::windows::wgl::connection::Connection as WglConnection;
+    use winapi::shared::windef::{HDC, HGLRC};
+    use winapi::um::wingdi::wglMakeCurrent;
+
+    let connection = WglConnection::new().unwrap();
+    let adapter = connection.create_adapter().unwrap();
+    let mut device = match connection.create_device(&adapter) {
+        Ok(device) => device,
+        Err(Error::RequiredExtensionUnavailable) => return,
+        Err(err) => panic!("Failed to create device: {:?}", err),
+    };
+    let attributes = ContextAttributes {
+        version: GLVersion::new(3, 0),
+        flags: ContextAttributeFlags::ALPHA | ContextAttributeFlags::DEPTH,
+        ..Default::default()
+    };
+    let descriptor = device.create_context_descriptor(&attributes).unwrap();
+    let mut context = device.create_context(&descriptor, None).unwrap();
+    let native_context = device.native_context(&context);
+    assert!(!native_context.1.is_null());
+
+    let mut wrapped_context =
+        unsafe { device.create_context_from_native_context(native_context.clone()) }.unwrap();
+    let original_attributes =
+        device.context_descriptor_attributes(&device.context_descriptor(&context));
+    let wrapped_attributes =
+        device.context_descriptor_attributes(&device.context_descriptor(&wrapped_context));
+    assert_eq!(wrapped_attributes.flags, original_attributes.flags);
+    assert_eq!(wrapped_attributes.version, original_attributes.version);
+    device.destroy_context(&mut wrapped_context).unwrap();
+
+    // A context that shares with the native one sees its textures.
+    device.make_context_current(&context).unwrap();
+    let gl = Gl::load_with(device.proc_address_loader(&context));
+    let texture = unsafe {
+        let mut texture = 0;
+        gl.GenTextures(1, &mut texture);
+        gl.BindTexture(gl::TEXTURE_2D, texture);
+        gl.BindTexture(gl::TEXTURE_2D, 0);
+        texture
+    };
+    let mut shared_context =
+        unsafe { device.create_shared_context_with_native(&native_context, &attributes) }.unwrap();
+    device.make_context_current(&shared_context).unwrap();
+    let shared_gl = Gl::load_with(device.proc_address_loader(&shared_context));
+    unsafe {
+        assert_eq!(shared_gl.IsTexture(texture), gl::TRUE);
+    }
+    device.destroy_context(&mut shared_context).unwrap();
+    device.make_no_context_current().unwrap();
+
+    // Make the native context current on another thread until told to release it.
+    let (glrc, dc) = (native_context.0 as usize, native_context.1 as usize);
+    let (current_sender, current_receiver) = mpsc::channel();
+    let (release_sender, release_receiver) = mpsc::channel::<()>();
+    let thread = thread::spawn(move || unsafe {
+        assert_ne!(wglMakeCurrent(dc as HDC, glrc as HGLRC), 0);
+        current_sender.send(()).unwrap();
+        release_receiver.recv().unwrap();
+        wglMakeCurrent(std::ptr::null_mut(), std::ptr::null_mut());
+    });
+    current_receiver.recv().unwrap();
+
+    match unsafe { device.create_context_from_native_context(native_context.clone()) } {
+        Err(Error::NativeContextCurrentElsewhere) => {}
+        Err(err) => panic!("Wrapping a busy context returned {:?}", err),
+        Ok(mut context) => {
+            device.destroy_context(&mut context).unwrap();
+            panic!("Wrapped a context that another thread has current!");
+        }
+    }
+    match unsafe { device.create_shared_context_with_native(&native_context, &attributes) } {
+        Err(Error::NativeContextCurrentElsewhere) => {}
+        Err(err) => panic!("Sharing with a busy context returned {:?}", err),
+        Ok(mut context) => {
+            device.destroy_context(&mut context).unwrap();
+            panic!("Shared with a context that another thread has current!");
+        }
+    }
+
+    release_sender.send(()).unwrap();
+    thread.join().unwrap();
+    device.destroy_context(&mut context).unwrap();
+}
+
+// Tests that a raw GL texture can be wrapped in a surface and sampled by another context.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_surface_from_texture() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // Render to a raw texture in the environment's context.
+    let texture = unsafe {
+        let mut texture = 0;
+        env.gl.GenTextures(1, &mut texture);
+        env.gl.BindTexture(gl::TEXTURE_2D, texture);
+        env.gl
+            .TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as _);
+        env.gl.TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGBA8 as _,
+            16,
+            16,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            std::ptr::null(),
+        );
+        env.gl.BindTexture(gl::TEXTURE_2D, 0);
+        let framebuffer = make_fbo(&env.gl, gl::TEXTURE_2D, texture);
+        clear(&env.gl, &[255, 0, 255, 255]);
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        env.gl.DeleteFramebuffers(1, &framebuffer);
+        env.gl.Finish();
+        texture
+    };
+    let size = Size2D::new(16, 16);
+
+    let mut surface = match env.device.create_surface_from_texture(
+        &mut env.context,
+        texture,
+        &size,
+        gl::RGBA8,
+        TextureOwnership::Borrowed,
+    ) {
+        Ok(surface) => surface,
+        Err(Error::UnsupportedOnThisPlatform) | Err(Error::Unimplemented) => {
+            env.device.destroy_context(&mut env.context).unwrap();
+            return;
+        }
+        Err(err) => panic!("Failed to wrap the texture: {:?}", err),
+    };
+    assert_eq!(env.device.surface_info(&surface).size, size);
+
+    // Sample the wrapped surface from another context, as a consumer would.
+    let mut other_context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    env.device.make_context_current(&other_context).unwrap();
+    let surface_texture = env
+        .device
+        .create_surface_texture(&mut other_context, surface)
+        .unwrap();
+    unsafe {
+        let framebuffer = make_fbo(
+            &env.gl,
+            env.device.surface_gl_texture_target(),
+            env.device.surface_texture_object(&surface_texture),
+        );
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 255, 255]);
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        env.gl.DeleteFramebuffers(1, &framebuffer);
+    }
+    surface = env
+        .device
+        .destroy_surface_texture(&mut other_context, surface_texture)
+        .unwrap();
+
+    // A texture from a context that doesn't share with this one is rejected.
+    match env.device.create_surface_from_texture(
+        &mut other_context,
+        texture,
+        &size,
+        gl::RGBA8,
+        TextureOwnership::Borrowed,
+    ) {
+        Err(Error::IncompatibleTexture) => {}
+        Ok(mut surface) => {
+            env.device
+                .destroy_surface(&mut other_context, &mut surface)
+                .unwrap();
+            panic!("Wrapped a texture from an unshared context!");
+        }
+        Err(err) => panic!("Wrapping an unshared texture returned {:?}", err),
+    }
+    env.device.destroy_context(&mut other_context).unwrap();
+
+    // surfman can't reallocate a texture that it didn't create.
+    match env
+        .device
+        .resize_surface(&env.context, &mut surface, Size2D::new(32, 32))
+    {
+        Err(Error::ForeignTexture) => {}
+        other => panic!("Expected `ForeignTexture`, got {:?}", other),
+    }
+    assert_eq!(env.device.surface_info(&surface).size, size);
+
+    // Destroying a borrowed surface leaves the texture alone.
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.make_context_current(&env.context).unwrap();
+    unsafe {
+        assert_eq!(env.gl.IsTexture(texture), gl::TRUE);
+    }
+
+    match env.device.create_surface_from_texture(
+        &mut env.context,
+        texture,
+        &size,
+        gl::RGB8,
+        TextureOwnership::Borrowed,
+    ) {
+        Err(Error::UnsupportedTextureFormat) => {}
+        result => panic!("Wrapping an RGB8 texture returned {:?}", result.map(|_| ())),
+    }
+
+    // Destroying an owned surface deletes the texture.
+    let mut surface = env
+        .device
+        .create_surface_from_texture(
+            &mut env.context,
+            texture,
+            &size,
+            gl::RGBA8,
+            TextureOwnership::Owned,
+        )
+        .unwrap();
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.make_context_current(&env.context).unwrap();
+    unsafe {
+        assert_eq!(env.gl.IsTexture(texture), gl::FALSE);
+    }
+
+    env.device.destroy_context(&mut env.context).unwrap();
 }
 
 // Tests that generic surfaces can be created.
 #[cfg_attr(not(feature = "sm-test"), test)]
 #[serial]
-pub fn test_generic_surface_creation() {
+pub fn test_generic_surface_creation() {
+    let connection = Connection::new().unwrap();
+    let adapter = connection
+        .create_low_power_adapter()
+        .expect("Failed to create adapter!");
+    let mut device = match connection.create_device(&adapter) {
+        Ok(device) => device,
+        Err(Error::RequiredExtensionUnavailable) => {
+            // Can't run these tests on this hardware.
+            return;
+        }
+        Err(err) => panic!("Failed to create device: {:?}", err),
+    };
+
+    let descriptor = device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::empty(),
+            ..Default::default()
+        })
+        .unwrap();
+
+    let mut context = device.create_context(&descriptor, None).unwrap();
+    let context_id = device.context_id(&context);
+
+    let surfaces: Vec<_> = [
+        SurfaceAccess::GPUOnly,
+        SurfaceAccess::GPUCPU,
+        SurfaceAccess::GPUCPUWriteCombined,
+    ]
+    .iter()
+    .map(|&access| {
+        let surface = device
+            .create_surface(
+                &context,
+                access,
+                SurfaceType::Generic {
+                    size: Size2D::new(640, 480),
+                },
+            )
+            .unwrap();
+        let info = device.surface_info(&surface);
+        assert_eq!(info.size, Size2D::new(640, 480));
+        assert_eq!(info.context_id, context_id);
+        surface
+    })
+    .collect();
+
+    // Make sure all IDs for extant surfaces are distinct.
+    for (surface_index, surface) in surfaces.iter().enumerate() {
+        for (other_surface_index, other_surface) in surfaces.iter().enumerate() {
+            if surface_index != other_surface_index {
+                assert_ne!(
+                    device.surface_info(surface).id,
+                    device.surface_info(other_surface).id
+                );
+            }
+        }
+    }
+
+    for mut surface in surfaces.into_iter() {
+        device.destroy_surface(&mut context, &mut surface).unwrap();
+    }
+
+    device.destroy_context(&mut context).unwrap();
+}
+
+// Tests that a headless connection can render to generic surfaces.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_headless_connection() {
+    let connection = match Connection::new_headless() {
+        Ok(connection) => connection,
+        Err(Error::ConnectionFailed) => {
+            // Can't run these tests on this system.
+            return;
+        }
+        Err(err) => panic!("Failed to open headless connection: {:?}", err),
+    };
+    let adapter = connection
+        .create_low_power_adapter()
+        .expect("Failed to create adapter!");
+    let mut device = match connection.create_device(&adapter) {
+        Ok(device) => device,
+        Err(Error::RequiredExtensionUnavailable) => {
+            // Can't run these tests on this hardware.
+            return;
+        }
+        Err(err) => panic!("Failed to create device: {:?}", err),
+    };
+
+    let descriptor = device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::empty(),
+            ..Default::default()
+        })
+        .unwrap();
+
+    let mut context = device.create_context(&descriptor, None).unwrap();
+    let surface = make_surface(&mut device, &context);
+    device
+        .bind_surface_to_context(&mut context, surface)
+        .unwrap();
+    device.make_context_current(&context).unwrap();
+
+    let gl = Gl::load_with(device.proc_address_loader(&context));
+    bind_context_fbo(&gl, &device, &context);
+    clear(&gl, &[0, 255, 0, 255]);
+    assert_eq!(get_pixel_from_bottom_row(&gl), [0, 255, 0, 255]);
+
+    device.destroy_context(&mut context).unwrap();
+}
+
+// Tests that a connection opened for OpenGL ES creates working OpenGL ES contexts.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_gles_connection() {
+    let connection = match Connection::new_with_api(GLApi::GLES) {
+        Ok(connection) => connection,
+        Err(Error::ConnectionFailed) | Err(Error::UnsupportedGLType) => {
+            // Can't run these tests on this system.
+            return;
+        }
+        Err(err) => panic!("Failed to open OpenGL ES connection: {:?}", err),
+    };
+    assert_eq!(connection.gl_api(), GLApi::GLES);
+    let adapter = connection
+        .create_low_power_adapter()
+        .expect("Failed to create adapter!");
+    let mut device = match connection.create_device(&adapter) {
+        Ok(device) => device,
+        Err(Error::RequiredExtensionUnavailable) => {
+            // Can't run these tests on this hardware.
+            return;
+        }
+        Err(err) => panic!("Failed to create device: {:?}", err),
+    };
+    assert_eq!(device.gl_api(), GLApi::GLES);
+
+    let descriptor = device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::empty(),
+            ..Default::default()
+        })
+        .unwrap();
+
+    let mut context = device.create_context(&descriptor, None).unwrap();
+    let surface = make_surface(&mut device, &context);
+    device
+        .bind_surface_to_context(&mut context, surface)
+        .unwrap();
+    device.make_context_current(&context).unwrap();
+
+    let gl = Gl::load_with(device.proc_address_loader(&context));
+    unsafe {
+        let version = gl.GetString(gl::VERSION);
+        assert!(!version.is_null());
+        let version = std::ffi::CStr::from_ptr(version as *const _).to_string_lossy();
+        assert!(
+            version.starts_with("OpenGL ES"),
+            "Unexpected version: {}",
+            version
+        );
+    }
+    bind_context_fbo(&gl, &device, &context);
+    clear(&gl, &[0, 0, 255, 255]);
+    assert_eq!(get_pixel_from_bottom_row(&gl), [0, 0, 255, 255]);
+
+    device.destroy_context(&mut context).unwrap();
+}
+
+// Tests that OpenGL ES contexts are granted at least the minor version they request when the driver
+// supports it, and that lower minor versions are only granted with `ALLOW_LOWER_VERSION`.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_gles_minor_versions() {
+    let connection = match Connection::new_with_api(GLApi::GLES) {
+        Ok(connection) => connection,
+        Err(Error::ConnectionFailed) | Err(Error::UnsupportedGLType) => {
+            // Can't run these tests on this system.
+            return;
+        }
+        Err(err) => panic!("Failed to open OpenGL ES connection: {:?}", err),
+    };
+    let adapter = connection
+        .create_low_power_adapter()
+        .expect("Failed to create adapter!");
+    let mut device = match connection.create_device(&adapter) {
+        Ok(device) => device,
+        Err(Error::RequiredExtensionUnavailable) => {
+            // Can't run these tests on this hardware.
+            return;
+        }
+        Err(err) => panic!("Failed to create device: {:?}", err),
+    };
+
+    let attributes = |version, flags| ContextAttributes {
+        version,
+        flags,
+        ..Default::default()
+    };
+    // Returns the version that the context was granted, checking that its descriptor agrees and
+    // doesn't report `ALLOW_LOWER_VERSION`.
+    let granted_version = |device: &mut Device, context: &mut Context| {
+        let info = device.context_info(context).unwrap();
+        let descriptor = device.context_descriptor(context);
+        let descriptor_attributes = device.context_descriptor_attributes(&descriptor);
+        assert_eq!(descriptor_attributes.version, info.version);
+        assert!(!descriptor_attributes
+            .flags
+            .contains(ContextAttributeFlags::ALLOW_LOWER_VERSION));
+        device.destroy_context(context).unwrap();
+        info.version
+    };
+
+    for &version in GL_ES_VERSIONS.iter().filter(|version| version.major == 3) {
+        let descriptor = device
+            .create_context_descriptor(&attributes(version, ContextAttributeFlags::empty()))
+            .unwrap();
+        match device.create_context(&descriptor, None) {
+            Ok(mut context) => {
+                let granted = granted_version(&mut device, &mut context);
+                assert_eq!(granted.major, 3);
+                assert!(
+                    granted.minor >= version.minor,
+                    "Requested OpenGL ES {:?} but got {:?}",
+                    version,
+                    granted
+                );
+            }
+            Err(Error::ContextCreationFailed(_)) => {
+                // The driver doesn't support this version, so a lower one must be granted when
+                // it's allowed.
+                let descriptor = device
+                    .create_context_descriptor(&attributes(
+                        version,
+                        ContextAttributeFlags::ALLOW_LOWER_VERSION,
+                    ))
+                    .unwrap();
+                let mut context = device.create_context(&descriptor, None).unwrap();
+                let granted = granted_version(&mut device, &mut context);
+                assert_eq!(granted.major, 3);
+                assert!(granted.minor < version.minor);
+            }
+            Err(err) => panic!(
+                "Failed to create OpenGL ES {:?} context: {:?}",
+                version, err
+            ),
+        }
+    }
+
+    // No driver supports OpenGL ES 3.9, so this always falls back.
+    let descriptor = device
+        .create_context_descriptor(&attributes(
+            GLVersion::new(3, 9),
+            ContextAttributeFlags::ALLOW_LOWER_VERSION,
+        ))
+        .unwrap();
+    let mut context = device.create_context(&descriptor, None).unwrap();
+    let granted = granted_version(&mut device, &mut context);
+    assert_eq!(granted.major, 3);
+    assert!(granted.minor < 9);
+}
+
+// Tests that context descriptors can be recreated from their raw parts on another device, as a
+// child process would, and that the recreated contexts have the same version and buffers.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_context_descriptor_raw_parts() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let other_connection = Connection::new().unwrap();
+    let other_adapter = other_connection
+        .create_low_power_adapter()
+        .expect("Failed to create adapter!");
+    let mut other_device = other_connection.create_device(&other_adapter).unwrap();
+
+    for &flags in &[
+        ContextAttributeFlags::empty(),
+        ContextAttributeFlags::ALPHA,
+        ContextAttributeFlags::DEPTH | ContextAttributeFlags::STENCIL,
+        ContextAttributeFlags::ALPHA
+            | ContextAttributeFlags::DEPTH
+            | ContextAttributeFlags::STENCIL,
+    ] {
+        let descriptor = env
+            .device
+            .create_context_descriptor(&ContextAttributes {
+                version: GLVersion::new(3, 0),
+                flags,
+                ..Default::default()
+            })
+            .unwrap();
+        let raw_parts = env.device.context_descriptor_raw_parts(&descriptor);
+        assert_eq!(
+            raw_parts.attributes,
+            env.device.context_descriptor_attributes(&descriptor)
+        );
+        assert_eq!(
+            raw_parts.alpha_size != 0,
+            flags.contains(ContextAttributeFlags::ALPHA)
+        );
+        assert_eq!(
+            raw_parts.depth_size != 0,
+            flags.contains(ContextAttributeFlags::DEPTH)
+        );
+        assert_eq!(
+            raw_parts.stencil_size != 0,
+            flags.contains(ContextAttributeFlags::STENCIL)
+        );
+
+        let other_descriptor = other_device
+            .create_context_descriptor_from_attributes(&raw_parts)
+            .unwrap();
+        assert_eq!(
+            other_device.context_descriptor_raw_parts(&other_descriptor),
+            raw_parts
+        );
+
+        let mut context = env.device.create_context(&descriptor, None).unwrap();
+        let mut other_context = other_device
+            .create_context(&other_descriptor, None)
+            .unwrap();
+        let granted = env
+            .device
+            .context_descriptor_raw_parts(&env.device.context_descriptor(&context));
+        let other_granted = other_device
+            .context_descriptor_raw_parts(&other_device.context_descriptor(&other_context));
+        assert_eq!(other_granted.attributes.version, granted.attributes.version);
+        assert_eq!(
+            (
+                other_granted.color_channel_size,
+                other_granted.alpha_size,
+                other_granted.depth_size,
+                other_granted.stencil_size
+            ),
+            (
+                granted.color_channel_size,
+                granted.alpha_size,
+                granted.depth_size,
+                granted.stencil_size
+            )
+        );
+
+        env.device.destroy_context(&mut context).unwrap();
+        other_device.destroy_context(&mut other_context).unwrap();
+    }
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that OpenGL ES devices reject the compatibility profile and that OpenGL devices report the
+// profile they actually grant.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_context_profiles() {
+    let attributes = |version, flags| ContextAttributes {
+        version,
+        flags,
+        ..Default::default()
+    };
+
+    for &gl_api in &[GLApi::GL, GLApi::GLES] {
+        let connection = match Connection::new_with_api(gl_api) {
+            Ok(connection) => connection,
+            Err(Error::ConnectionFailed) | Err(Error::UnsupportedGLType) => continue,
+            Err(err) => panic!("Failed to open {:?} connection: {:?}", gl_api, err),
+        };
+        let adapter = connection
+            .create_low_power_adapter()
+            .expect("Failed to create adapter!");
+        let mut device = match connection.create_device(&adapter) {
+            Ok(device) => device,
+            Err(Error::RequiredExtensionUnavailable) => continue,
+            Err(err) => panic!("Failed to create device: {:?}", err),
+        };
+
+        let compatibility_attributes = attributes(
+            GLVersion::new(3, 0),
+            ContextAttributeFlags::COMPATIBILITY_PROFILE,
+        );
+        match (
+            gl_api,
+            device.create_context_descriptor(&compatibility_attributes),
+        ) {
+            (GLApi::GLES, Err(Error::UnsupportedGLProfile)) => continue,
+            (GLApi::GLES, result) => panic!(
+                "Expected OpenGL ES to reject the compatibility profile but got {:?}",
+                result.map(|_| ())
+            ),
+            (GLApi::GL, Ok(_)) | (GLApi::GL, Err(Error::UnsupportedGLProfile)) => {}
+            (GLApi::GL, Err(err)) => panic!("Unexpected error: {:?}", err),
+        }
+
+        // Core profiles start at OpenGL 3.2.
+        let core_attributes = attributes(GLVersion::new(3, 2), ContextAttributeFlags::empty());
+        let descriptor = match device.create_context_descriptor(&core_attributes) {
+            Ok(descriptor) => descriptor,
+            Err(_) => continue,
+        };
+        let mut context = match device.create_context(&descriptor, None) {
+            Ok(context) => context,
+            Err(Error::ContextCreationFailed(_)) => continue,
+            Err(err) => panic!("Failed to create context: {:?}", err),
+        };
+        let granted_attributes =
+            device.context_descriptor_attributes(&device.context_descriptor(&context));
+        assert!(!granted_attributes
+            .flags
+            .contains(ContextAttributeFlags::COMPATIBILITY_PROFILE));
+        device.destroy_context(&mut context).unwrap();
+    }
+}
+
+// Tests that OpenGL and OpenGL ES devices keep working when their operations are interleaved on
+// one thread, since EGL's bound client API is per-thread state.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_mixed_gl_apis_on_one_thread() {
+    let mut devices = vec![];
+    for &gl_api in &[GLApi::GL, GLApi::GLES] {
+        let connection = match Connection::new_with_api(gl_api) {
+            Ok(connection) => connection,
+            Err(Error::ConnectionFailed) | Err(Error::UnsupportedGLType) => return,
+            Err(err) => panic!("Failed to open {:?} connection: {:?}", gl_api, err),
+        };
+        let adapter = connection
+            .create_low_power_adapter()
+            .expect("Failed to create adapter!");
+        match connection.create_device(&adapter) {
+            Ok(device) => devices.push(device),
+            Err(Error::RequiredExtensionUnavailable) => return,
+            Err(err) => panic!("Failed to create device: {:?}", err),
+        }
+    }
+
+    let colors = [[255, 0, 0, 255], [0, 0, 255, 255]];
+    for iteration in 0..4 {
+        for (device, color) in devices.iter_mut().zip(colors.iter()) {
+            let descriptor = device
+                .create_context_descriptor(&ContextAttributes {
+                    version: GLVersion::new(3, 0),
+                    flags: ContextAttributeFlags::empty(),
+                    ..Default::default()
+                })
+                .unwrap();
+            let mut context = device.create_context(&descriptor, None).unwrap();
+            let surface = make_surface(device, &context);
+            device
+                .bind_surface_to_context(&mut context, surface)
+                .unwrap();
+            device.make_context_current(&context).unwrap();
+
+            let gl = Gl::load_with(device.proc_address_loader(&context));
+            bind_context_fbo(&gl, device, &context);
+            clear(&gl, color);
+            assert_eq!(
+                get_pixel_from_bottom_row(&gl),
+                *color,
+                "{:?} device failed in iteration {}",
+                device.gl_api(),
+                iteration
+            );
+
+            device.destroy_context(&mut context).unwrap();
+        }
+    }
+}
+
+// Tests that `begin_rendering()` binds the attached surface and that it has well-defined behavior
+// when no surface is attached.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_begin_rendering() {
+    let connection = Connection::new().unwrap();
+    let adapter = connection
+        .create_low_power_adapter()
+        .expect("Failed to create adapter!");
+    let mut device = match connection.create_device(&adapter) {
+        Ok(device) => device,
+        Err(Error::RequiredExtensionUnavailable) => {
+            // Can't run these tests on this hardware.
+            return;
+        }
+        Err(err) => panic!("Failed to create device: {:?}", err),
+    };
+
+    let descriptor = device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::empty(),
+            ..Default::default()
+        })
+        .unwrap();
+
+    let mut context = device.create_context(&descriptor, None).unwrap();
+    let surface = make_surface(&mut device, &context);
+    device
+        .bind_surface_to_context(&mut context, surface)
+        .unwrap();
+    let mut surfaceless_context = device.create_context(&descriptor, None).unwrap();
+
+    let gl = Gl::load_with(device.proc_address_loader(&context));
+    unsafe {
+        let info = device.begin_rendering(&context).unwrap();
+        assert_eq!(info.framebuffer_object, context_fbo(&device, &context));
+        assert_eq!(info.size, Size2D::new(640, 480));
+        assert_eq!(info.origin, Point2D::zero());
+        assert_eq!(info.format, gl::RGB8);
+        assert_eq!(get_framebuffer_binding(&gl), info.framebuffer_object);
+        assert_eq!(get_viewport(&gl), [0, 0, 640, 480]);
+        clear(&gl, &[0, 255, 0, 255]);
+        assert_eq!(get_pixel_from_bottom_row(&gl), [0, 255, 0, 255]);
+        device.end_rendering(&context, true).unwrap();
+        assert_eq!(get_framebuffer_binding(&gl), 0);
+
+        // Without a surface, the default framebuffer is bound and the viewport is left alone.
+        let info = device.begin_rendering(&surfaceless_context).unwrap();
+        assert_eq!(info.framebuffer_object, 0);
+        assert_eq!(info.size, Size2D::zero());
+        assert_eq!(info.format, gl::NONE);
+        assert_eq!(get_framebuffer_binding(&gl), 0);
+        gl.Viewport(1, 2, 3, 4);
+        device.begin_rendering(&surfaceless_context).unwrap();
+        assert_eq!(get_viewport(&gl), [1, 2, 3, 4]);
+        device.end_rendering(&surfaceless_context, false).unwrap();
+
+        // Switching back rebinds the surface's framebuffer in its own context.
+        device.begin_rendering(&context).unwrap();
+        assert_eq!(get_framebuffer_binding(&gl), context_fbo(&device, &context));
+        assert_eq!(get_pixel_from_bottom_row(&gl), [0, 255, 0, 255]);
+        device.end_rendering(&context, false).unwrap();
+    }
+
+    device.destroy_context(&mut surfaceless_context).unwrap();
+    device.destroy_context(&mut context).unwrap();
+}
+
+// Tests that connections can be shared between threads without wrapping them in a mutex.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_connection_is_send_and_sync() {
+    fn assert_send_and_sync<T: Send + Sync>() {}
+    assert_send_and_sync::<Connection>();
+    assert_send_and_sync::<crate::Connection>();
+}
+
+// Tests that a thread-bound handle can be sent to another thread, but only used on the thread that
+// created it.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_thread_bound_handle() {
+    let handle = ThreadBound::new(42);
+    assert_eq!(handle.get().unwrap(), 42);
+
+    let owner = thread::current().id();
+    let result = thread::spawn(move || handle.get()).join().unwrap();
+    match result {
+        Err(Error::WrongThread {
+            owner: actual_owner,
+        }) => assert_eq!(actual_owner, owner),
+        other => panic!("Expected the handle to refuse another thread: {:?}", other),
+    }
+    assert_eq!(handle.get().unwrap(), 42);
+}
+
+// Tests that basic GL commands work.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_gl() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    unsafe {
+        // Check basic clear.
+        env.gl.ClearColor(0.0, 1.0, 0.0, 1.0);
+        env.gl.Clear(gl::COLOR_BUFFER_BIT);
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+
+        // Check that GL commands don't work after `make_no_context_current()`.
+        //
+        // The `glGetError()` calls are there to clear any errors.
+        env.device.make_no_context_current().unwrap();
+        env.gl.ClearColor(1.0, 0.0, 0.0, 1.0);
+        env.gl.GetError();
+        env.gl.Clear(gl::COLOR_BUFFER_BIT);
+        env.gl.GetError();
+        env.device.make_context_current(&env.context).unwrap();
+
+        let framebuffer_object = env
+            .device
+            .context_surface_info(&env.context)
+            .unwrap()
+            .unwrap()
+            .framebuffer_object;
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+
+        // Make sure GL commands don't work when no surface is attached.
+        //
+        // The `glGetError()` calls are there to clear any errors.
+        let green_surface = env
+            .device
+            .unbind_surface_from_context(&mut env.context)
+            .unwrap()
+            .unwrap();
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        env.gl.ClearColor(1.0, 0.0, 0.0, 1.0);
+        env.gl.GetError();
+        env.gl.Clear(gl::COLOR_BUFFER_BIT);
+        env.gl.GetError();
+        env.device
+            .bind_surface_to_context(&mut env.context, green_surface)
+            .unwrap();
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+
+        // Make sure GL commands go to the right surface.
+        let green_surface = env
+            .device
+            .unbind_surface_from_context(&mut env.context)
+            .unwrap()
+            .unwrap();
+        let red_surface = make_surface(&mut env.device, &env.context);
+        env.device
+            .bind_surface_to_context(&mut env.context, red_surface)
+            .unwrap();
+        let red_framebuffer_object = env
+            .device
+            .context_surface_info(&env.context)
+            .unwrap()
+            .unwrap()
+            .framebuffer_object;
+        env.gl
+            .BindFramebuffer(gl::FRAMEBUFFER, red_framebuffer_object);
+        env.gl.ClearColor(1.0, 0.0, 0.0, 1.0);
+        env.gl.Clear(gl::COLOR_BUFFER_BIT);
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+
+        let mut red_surface = env
+            .device
+            .unbind_surface_from_context(&mut env.context)
+            .unwrap()
+            .unwrap();
+        env.device
+            .bind_surface_to_context(&mut env.context, green_surface)
+            .unwrap();
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+
+        // Clean up.
+        env.device
+            .destroy_surface(&mut env.context, &mut red_surface)
+            .unwrap();
+    }
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_surface_texture_blit_framebuffer() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    unsafe {
+        clear(&env.gl, &[0, 255, 0, 255]);
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+
+        let green_surface = env
+            .device
+            .unbind_surface_from_context(&mut env.context)
+            .unwrap()
+            .unwrap();
+        let green_surface_texture = env
+            .device
+            .create_surface_texture(&mut env.context, green_surface)
+            .unwrap();
+
+        let main_surface = make_surface(&mut env.device, &env.context);
+        env.device
+            .bind_surface_to_context(&mut env.context, main_surface)
+            .unwrap();
+
+        let main_framebuffer_object = env
+            .device
+            .context_surface_info(&env.context)
+            .unwrap()
+            .unwrap()
+            .framebuffer_object;
+        env.gl
+            .BindFramebuffer(gl::FRAMEBUFFER, main_framebuffer_object);
+        clear(&env.gl, &[255, 0, 0, 255]);
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+
+        let mut green_framebuffer_object = make_fbo(
+            &env.gl,
+            env.device.surface_gl_texture_target(),
+            env.device.surface_texture_object(&green_surface_texture),
+        );
+
+        // Blit to main framebuffer.
+        let blitted = blit_fbo(
+            &env.gl,
+            &env.capabilities,
+            main_framebuffer_object,
+            green_framebuffer_object,
+        );
+        env.gl
+            .BindFramebuffer(gl::FRAMEBUFFER, main_framebuffer_object);
+        check_gl(&env.gl);
+        if blitted {
+            assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+        }
+
+        // Clean up.
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        check_gl(&env.gl);
+        env.gl.DeleteFramebuffers(1, &mut green_framebuffer_object);
+
+        let mut green_surface = env
+            .device
+            .destroy_surface_texture(&mut env.context, green_surface_texture)
+            .unwrap();
+        env.device
+            .destroy_surface(&mut env.context, &mut green_surface)
+            .unwrap();
+        env.device.destroy_context(&mut env.context).unwrap();
+    }
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_cross_device_surface_texture_blit_framebuffer() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let mut other_device = env.connection.create_device(&env.adapter).unwrap();
+
+    unsafe {
+        clear(&env.gl, &[255, 0, 0, 255]);
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+
+        let mut other_context = other_device
+            .create_context(&env.context_descriptor, None)
+            .unwrap();
+        let other_surface = make_surface(&mut other_device, &other_context);
+        other_device
+            .bind_surface_to_context(&mut other_context, other_surface)
+            .unwrap();
+        other_device.make_context_current(&other_context).unwrap();
+        bind_context_fbo(&env.gl, &other_device, &other_context);
+
+        clear(&env.gl, &[0, 255, 0, 255]);
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+
+        let green_surface = other_device
+            .unbind_surface_from_context(&mut other_context)
+            .unwrap()
+            .unwrap();
+        let green_surface_texture = env
+            .device
+            .create_surface_texture(&mut env.context, green_surface)
+            .unwrap();
+
+        env.device.make_context_current(&env.context).unwrap();
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+
+        let mut green_framebuffer_object = make_fbo(
+            &env.gl,
+            env.device.surface_gl_texture_target(),
+            env.device.surface_texture_object(&green_surface_texture),
+        );
+
+        // Blit to main framebuffer.
+        let blitted = blit_fbo(
+            &env.gl,
+            &env.capabilities,
+            context_fbo(&env.device, &env.context),
+            green_framebuffer_object,
+        );
+        bind_context_fbo(&env.gl, &env.device, &env.context);
+        if blitted {
+            assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+        }
+
+        // Clean up.
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        check_gl(&env.gl);
+        env.gl.DeleteFramebuffers(1, &mut green_framebuffer_object);
+
+        let mut green_surface = env
+            .device
+            .destroy_surface_texture(&mut env.context, green_surface_texture)
+            .unwrap();
+        other_device
+            .destroy_surface(&mut other_context, &mut green_surface)
+            .unwrap();
+        other_device.destroy_context(&mut other_context).unwrap();
+        env.device.destroy_context(&mut env.context).unwrap();
+    }
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_cross_thread_surface_texture_blit_framebuffer() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let (to_main_sender, to_main_receiver) = mpsc::channel();
+    let (to_worker_sender, to_worker_receiver) = mpsc::channel();
+
+    let other_connection = env.connection.clone();
+    let other_adapter = env.adapter.clone();
+    let other_context_descriptor = env.context_descriptor.clone();
+    let worker = thread::spawn(move || {
+        let mut device = other_connection.create_device(&other_adapter).unwrap();
+        let mut context = device
+            .create_context(&other_context_descriptor, None)
+            .unwrap();
+        let gl = Gl::load_with(device.proc_address_loader(&context));
+
+        let surface = make_surface(&mut device, &context);
+        device
+            .bind_surface_to_context(&mut context, surface)
+            .unwrap();
+        device.make_context_current(&context).unwrap();
+        bind_context_fbo(&gl, &device, &context);
+
+        clear(&gl, &[0, 255, 0, 255]);
+        assert_eq!(get_pixel_from_bottom_row(&gl), [0, 255, 0, 255]);
+
+        let surface = device
+            .unbind_surface_from_context(&mut context)
+            .unwrap()
+            .unwrap();
+        to_main_sender.send(surface).unwrap();
+
+        let mut surface = to_worker_receiver.recv().unwrap();
+        device.destroy_surface(&mut context, &mut surface).unwrap();
+        device.destroy_context(&mut context).unwrap();
+    });
+
+    unsafe {
+        clear(&env.gl, &[255, 0, 0, 255]);
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+
+        let green_surface = to_main_receiver.recv().unwrap();
+        let green_surface_texture = env
+            .device
+            .create_surface_texture(&mut env.context, green_surface)
+            .unwrap();
+
+        env.device.make_context_current(&env.context).unwrap();
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+
+        let mut green_framebuffer_object = make_fbo(
+            &env.gl,
+            env.device.surface_gl_texture_target(),
+            env.device.surface_texture_object(&green_surface_texture),
+        );
+
+        // Blit to main framebuffer.
+        let blitted = blit_fbo(
+            &env.gl,
+            &env.capabilities,
+            context_fbo(&env.device, &env.context),
+            green_framebuffer_object,
+        );
+        bind_context_fbo(&env.gl, &env.device, &env.context);
+        if blitted {
+            assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+        }
+
+        // Clean up.
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        check_gl(&env.gl);
+        env.gl.DeleteFramebuffers(1, &mut green_framebuffer_object);
+
+        let green_surface = env
+            .device
+            .destroy_surface_texture(&mut env.context, green_surface_texture)
+            .unwrap();
+        to_worker_sender.send(green_surface).unwrap();
+
+        env.device.destroy_context(&mut env.context).unwrap();
+    }
+    worker.join().unwrap();
+}
+
+#[cfg(feature = "chains")]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_frame_export() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let swap_chain =
+        SwapChain::create_attached(&mut env.device, &mut env.context, SurfaceAccess::GPUOnly)
+            .unwrap();
+
+    // With `Block`, every frame is exported, in order, including when there are more frames than
+    // readbacks in flight.
+    let colors = [
+        [255, 0, 0, 255],
+        [0, 255, 0, 255],
+        [0, 0, 255, 255],
+        [255, 255, 0, 255],
+        [0, 255, 255, 255],
+    ];
+    assert!(colors.len() > MAX_READBACKS_IN_FLIGHT);
+    let (sender, receiver) = mpsc::channel();
+    let exporter = FrameExporter::new(
+        &env.device,
+        &env.context,
+        &swap_chain,
+        1,
+        Backpressure::Block,
+        move |frame: ExportedFrame| {
+            assert_eq!(frame.size, Size2D::new(640, 480));
+            assert_eq!(frame.stride, 640 * 4);
+            let (first, last) = (&frame.data[0..4], &frame.data[frame.data.len() - 4..]);
+            sender
+                .send((frame.frame_id, first.to_vec(), last.to_vec()))
+                .unwrap();
+        },
+    )
+    .unwrap();
+    for color in &colors {
+        render_swap_chain_frame(&mut env, &swap_chain, color);
+        assert!(exporter.capture().is_some());
+    }
+    drop(exporter);
+    let exported: Vec<_> = receiver.iter().collect();
+    let expected: Vec<_> = colors
+        .iter()
+        .enumerate()
+        .map(|(frame_id, color)| (frame_id as u64, color.to_vec(), color.to_vec()))
+        .collect();
+    assert_eq!(exported, expected);
+
+    // With `Drop`, the render thread doesn't wait for a slow callback.
+    let callback_time = Duration::from_millis(500);
+    let (sender, receiver) = mpsc::channel();
+    let exporter = FrameExporter::new(
+        &env.device,
+        &env.context,
+        &swap_chain,
+        1,
+        Backpressure::Drop,
+        move |frame: ExportedFrame| {
+            thread::sleep(callback_time);
+            sender.send(frame.frame_id).unwrap();
+        },
+    )
+    .unwrap();
+    let mut capture_time = Duration::default();
+    for _ in 0..8 {
+        render_swap_chain_frame(&mut env, &swap_chain, &[255, 255, 255, 255]);
+        let start = Instant::now();
+        exporter.capture();
+        capture_time += start.elapsed();
+    }
+    assert!(capture_time < callback_time);
+    let dropped_frames = exporter.dropped_frames();
+    drop(exporter);
+    let exported: Vec<u64> = receiver.iter().collect();
+    assert!(dropped_frames > 0);
+    assert_eq!(exported.len() as u64 + dropped_frames, 8);
+    assert!(exported.windows(2).all(|ids| ids[0] < ids[1]));
+
+    swap_chain
+        .destroy(&mut env.device, &mut env.context)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that generic surfaces export as dma-bufs that describe them, and that each export is the
+// same buffer.
+#[cfg(unix)]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_export_surface_dma_buf() {
+    use std::os::fd::AsRawFd;
+
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let mut surface = make_surface(&mut env.device, &env.context);
+    let dma_buf = match env.device.export_surface_dma_buf(&surface) {
+        Ok(dma_buf) => dma_buf,
+        Err(Error::RequiredExtensionUnavailable) | Err(Error::UnsupportedOnThisPlatform) => {
+            // Can't run this test on this platform.
+            env.device
+                .destroy_surface(&mut env.context, &mut surface)
+                .unwrap();
+            env.device.destroy_context(&mut env.context).unwrap();
+            return;
+        }
+        Err(err) => panic!("Failed to export the surface: {:?}", err),
+    };
+    assert_eq!(dma_buf.size, Size2D::new(640, 480));
+    assert!(dma_buf.stride >= 640 * 4);
+    assert_ne!(dma_buf.fourcc, 0);
+
+    // A dma-buf is one file however many times it's exported.
+    let inode = |fd: i32| unsafe {
+        let mut stat: libc::stat = std::mem::zeroed();
+        assert_eq!(libc::fstat(fd, &mut stat), 0);
+        stat.st_ino
+    };
+    let again = env.device.export_surface_dma_buf(&surface).unwrap();
+    assert_ne!(again.fd.as_raw_fd(), dma_buf.fd.as_raw_fd());
+    assert_eq!(inode(again.fd.as_raw_fd()), inode(dma_buf.fd.as_raw_fd()));
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that a producer written in C can render frames into a chain through their dma-bufs with
+// its own GL context, that the chain keeps the damage it reports, and that submissions breaking
+// the ABI's rules fail.
+#[cfg(all(feature = "chains", target_os = "linux"))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_foreign_producer() {
+    use crate::chains::SwapChainAPI;
+    use crate::egl;
+    use crate::egl::types::EGLint;
+    use crate::platform::generic::egl::device::EGL_FUNCTIONS;
+    use crate::platform::generic::egl::ffi::EGL_DMA_BUF_PLANE0_PITCH_EXT;
+    use crate::platform::generic::egl::ffi::{EGL_DMA_BUF_PLANE0_FD_EXT, EGL_LINUX_DMA_BUF_EXT};
+    use crate::platform::generic::egl::ffi::{
+        EGL_DMA_BUF_PLANE0_MODIFIER_HI_EXT, EGL_NO_IMAGE_KHR,
+    };
+    use crate::platform::generic::egl::ffi::{
+        EGL_DMA_BUF_PLANE0_MODIFIER_LO_EXT, EGL_LINUX_DRM_FOURCC_EXT,
+    };
+    use crate::platform::generic::egl::ffi::{
+        EGL_DMA_BUF_PLANE0_OFFSET_EXT, EGL_EXTENSION_FUNCTIONS,
+    };
+    use crate::producer_abi::{self, FencePolicy, ProducerChain, SurfmanChain};
+    use crate::producer_abi::{SurfmanFrameDesc, SurfmanProducerApi, SurfmanRect};
+    use std::ffi::CString;
+    use std::fs;
+    use std::mem;
+    use std::os::raw::c_int;
+    use std::process::{self, Command};
+    use std::ptr;
+
+    const DRM_FORMAT_MOD_INVALID: u64 = 0x00ff_ffff_ffff_ffff;
+
+    type Render =
+        extern "C" fn(*mut c_void, *const SurfmanFrameDesc, SurfmanRect, *const u8) -> c_int;
+    type TestProducer = unsafe extern "C" fn(
+        extern "C" fn(u32) -> *const SurfmanProducerApi,
+        *mut SurfmanChain,
+        Render,
+        *mut c_void,
+    ) -> c_int;
+
+    // The producer's own GL, in a context on another device. It renders into each frame through
+    // the frame's dma-buf, as a foreign producer's GL or Vulkan would.
+    struct Renderer {
+        device: Device,
+        context: Context,
+        gl: Gl,
+    }
+
+    extern "C" fn render(
+        renderer: *mut c_void,
+        frame: *const SurfmanFrameDesc,
+        rect: SurfmanRect,
+        color: *const u8,
+    ) -> c_int {
+        unsafe {
+            let Renderer {
+                ref device,
+                ref context,
+                ref gl,
+            } = *(renderer as *const Renderer);
+            let frame = &*frame;
+            let color = &*(color as *const [u8; 4]);
+            device.make_context_current(context).unwrap();
+
+            let mut attributes = vec![
+                egl::WIDTH as EGLint,
+                frame.width,
+                egl::HEIGHT as EGLint,
+                frame.height,
+                EGL_LINUX_DRM_FOURCC_EXT as EGLint,
+                frame.format as EGLint,
+                EGL_DMA_BUF_PLANE0_FD_EXT as EGLint,
+                frame.handle as EGLint,
+                EGL_DMA_BUF_PLANE0_OFFSET_EXT as EGLint,
+                frame.offset as EGLint,
+                EGL_DMA_BUF_PLANE0_PITCH_EXT as EGLint,
+                frame.stride as EGLint,
+            ];
+            if frame.modifier != DRM_FORMAT_MOD_INVALID {
+                attributes.extend([
+                    EGL_DMA_BUF_PLANE0_MODIFIER_LO_EXT as EGLint,
+                    frame.modifier as u32 as EGLint,
+                    EGL_DMA_BUF_PLANE0_MODIFIER_HI_EXT as EGLint,
+                    (frame.modifier >> 32) as u32 as EGLint,
+                ]);
+            }
+            attributes.push(egl::NONE as EGLint);
+            let egl_display = EGL_FUNCTIONS.with(|egl| egl.GetCurrentDisplay());
+            let egl_image = (EGL_EXTENSION_FUNCTIONS.CreateImageKHR)(
+                egl_display,
+                egl::NO_CONTEXT,
+                EGL_LINUX_DMA_BUF_EXT,
+                ptr::null_mut(),
+                attributes.as_ptr(),
+            );
+            if egl_image == EGL_NO_IMAGE_KHR {
+                return 1;
+            }
+
+            let mut texture = 0;
+            gl.GenTextures(1, &mut texture);
+            gl.BindTexture(gl::TEXTURE_2D, texture);
+            (EGL_EXTENSION_FUNCTIONS.ImageTargetTexture2DOES)(gl::TEXTURE_2D, egl_image);
+            let framebuffer_object = make_fbo(gl, gl::TEXTURE_2D, texture);
+            gl.Enable(gl::SCISSOR_TEST);
+            gl.Scissor(rect.x, rect.y, rect.width, rect.height);
+            clear(gl, color);
+            gl.Disable(gl::SCISSOR_TEST);
+            gl.Finish();
+            check_gl(gl);
+
+            gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl.DeleteFramebuffers(1, &framebuffer_object);
+            gl.DeleteTextures(1, &texture);
+            (EGL_EXTENSION_FUNCTIONS.DestroyImageKHR)(egl_display, egl_image);
+            0
+        }
+    }
+
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // Build the producer as a shared library, as a plugin would be.
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let library = env::temp_dir().join(format!("libsurfman_test_producer_{}.so", process::id()));
+    let compiler = env::var_os("CC").unwrap_or_else(|| "cc".into());
+    let output = match Command::new(compiler)
+        .args(["-shared", "-fPIC", "-std=c99", "-Wall", "-Werror", "-I"])
+        .arg(manifest_dir.join("include"))
+        .arg(manifest_dir.join("tests").join("c").join("test_producer.c"))
+        .arg("-o")
+        .arg(&library)
+        .output()
+    {
+        Ok(output) => output,
+        Err(_) => {
+            // Can't run this test without a C compiler.
+            env.device.destroy_context(&mut env.context).unwrap();
+            return;
+        }
+    };
+    assert!(
+        output.status.success(),
+        "Failed to build the test producer:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let mut device = env.connection.create_device(&env.adapter).unwrap();
+    let mut context = device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    let surface = make_surface(&mut device, &context);
+    device
+        .bind_surface_to_context(&mut context, surface)
+        .unwrap();
+    let mut chain = match ProducerChain::new(device, context, FencePolicy::Required) {
+        Ok(chain) => chain,
+        Err((err, ..)) => panic!("Failed to create the producer chain: {:?}", err),
+    };
+
+    let mut renderer_device = env.connection.create_device(&env.adapter).unwrap();
+    let renderer_context = renderer_device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    let mut renderer = Renderer {
+        gl: Gl::load_with(renderer_device.proc_address_loader(&renderer_context)),
+        device: renderer_device,
+        context: renderer_context,
+    };
+
+    let result = unsafe {
+        let path = CString::new(library.to_str().unwrap()).unwrap();
+        let handle = libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+        assert!(!handle.is_null());
+        let symbol = libc::dlsym(handle, b"surfman_test_producer\0".as_ptr() as *const _);
+        assert!(!symbol.is_null());
+        let test_producer: TestProducer = mem::transmute(symbol);
+        let result = test_producer(
+            producer_abi::surfman_producer_api,
+            chain.as_raw(),
+            render,
+            &mut renderer as *mut Renderer as *mut c_void,
+        );
+        libc::dlclose(handle);
+        result
+    };
+    fs::remove_file(&library).unwrap();
+    renderer
+        .device
+        .destroy_context(&mut renderer.context)
+        .unwrap();
+
+    // The chain can't hand out dma-bufs here, which the producer checked the errors up to.
+    if result != -1 {
+        assert_eq!(
+            result, 0,
+            "The test producer failed a check on line {}",
+            result
+        );
+
+        // The second frame damaged the top left quarter, and the chain copied the rest from the
+        // first frame.
+        let top_left = Rect::new(Point2D::new(0, 0), Size2D::new(320, 240));
+        assert_eq!(chain.damage(), &[top_left]);
+        let surface = chain.swap_chain().take_surface().unwrap();
+        let surface_texture = env
+            .device
+            .create_surface_texture(&mut env.context, surface)
+            .unwrap();
+        let texture = env.device.surface_texture_object(&surface_texture);
+        let target = env.device.surface_gl_texture_target();
+        unsafe {
+            let framebuffer_object = make_fbo(&env.gl, target, texture);
+            for (point, expected) in [
+                (Point2D::new(0, 0), [0, 255, 0, 255]),
+                (Point2D::new(639, 479), [255, 0, 0, 255]),
+                (Point2D::new(639, 0), [255, 0, 0, 255]),
+            ] {
+                let mut pixel = [0u8; 4];
+                env.gl.ReadPixels(
+                    point.x,
+                    point.y,
+                    1,
+                    1,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    pixel.as_mut_ptr() as *mut c_void,
+                );
+                assert_eq!(pixel, expected);
+            }
+            env.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+            env.gl.DeleteFramebuffers(1, &framebuffer_object);
+        }
+        let surface = env
+            .device
+            .destroy_surface_texture(&mut env.context, surface_texture)
+            .unwrap();
+        chain.swap_chain().recycle_surface(surface);
+    }
+
+    let (device, mut context) = chain.destroy().unwrap();
+    device.destroy_context(&mut context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that swap chains fill the surfaces that they recycle with the debug fill, and that exported
+// frames are tagged with it.
+#[cfg(feature = "chains")]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_swap_chain_debug_surface_fill() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let swap_chain =
+        SwapChain::create_attached(&mut env.device, &mut env.context, SurfaceAccess::GPUOnly)
+            .unwrap();
+    let fill = DebugFill::SolidColor([1.0, 0.0, 1.0, 1.0]);
+    env.device.set_debug_surface_fill(fill);
+
+    let (sender, receiver) = mpsc::channel();
+    let exporter = FrameExporter::new(
+        &env.device,
+        &env.context,
+        &swap_chain,
+        1,
+        Backpressure::Block,
+        move |frame: ExportedFrame| {
+            sender
+                .send((frame.debug_fill, frame.data[0..4].to_vec()))
+                .unwrap();
+        },
+    )
+    .unwrap();
+
+    // The first front buffer is recycled as the back buffer of the second swap, and is filled
+    // again by the time it's presented by the third, without being drawn to.
+    render_swap_chain_frame(&mut env, &swap_chain, &[0, 0, 255, 255]);
+    render_swap_chain_frame(&mut env, &swap_chain, &[0, 0, 255, 255]);
+    swap_chain
+        .swap_buffers(&mut env.device, &mut env.context, PreserveBuffer::No)
+        .unwrap();
+    assert!(exporter.capture().is_some());
+    drop(exporter);
+    let exported: Vec<_> = receiver.iter().collect();
+    assert_eq!(exported, vec![(fill, vec![255, 0, 255, 255])]);
+
+    swap_chain
+        .destroy(&mut env.device, &mut env.context)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that frames pass through every stage of a pipeline in order, with their metadata.
+#[cfg(feature = "chains")]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_pipeline() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let mut pipeline = make_test_pipeline(&env, 6, None, None);
+
+    let gl = &env.gl;
+    let mut presented = vec![];
+    while let Some(info) = pipeline
+        .present(
+            &mut env.device,
+            &mut env.context,
+            |device, context, texture, info: &FrameInfo<u64>| {
+                let pixel = read_texture_pixel(gl, device, context, texture);
+                assert_eq!(pixel, [info.frame_id as u8 * 40, 255, 0, 255]);
+                Ok(())
+            },
+        )
+        .unwrap()
+    {
+        assert_eq!(info.size, Size2D::new(640, 480));
+        assert_eq!(info.damage, vec![Rect::new(Point2D::zero(), info.size)]);
+        presented.push((info.frame_id, info.metadata));
+    }
+    let expected: Vec<_> = (0..6)
+        .map(|frame_id| (frame_id, frame_id * 10 + 1))
+        .collect();
+    assert_eq!(presented, expected);
+    pipeline.shutdown().unwrap();
+
+    // Shutting down in the middle of the stream stops the stages.
+    let pipeline = make_test_pipeline(&env, u64::MAX, None, None);
+    thread::sleep(Duration::from_millis(100));
+    pipeline.shutdown().unwrap();
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that a failure in any stage ends the stream cleanly, and is reported.
+#[cfg(feature = "chains")]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_pipeline_stage_failures() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let present_all = |env: &mut BasicEnvironment, pipeline: &mut Pipeline<Device, u64>| {
+        let mut frame_ids = vec![];
+        loop {
+            match pipeline.present(&mut env.device, &mut env.context, |_, _, _, _| Ok(())) {
+                Ok(Some(info)) => frame_ids.push(info.frame_id),
+                Ok(None) => return Ok(frame_ids),
+                Err(err) => return Err((frame_ids, err)),
+            }
+        }
+    };
+
+    // The frames before a producer failure still arrive.
+    let mut pipeline = make_test_pipeline(&env, 6, Some(3), None);
+    match present_all(&mut env, &mut pipeline) {
+        Err((frame_ids, Error::Unimplemented)) => assert_eq!(frame_ids, vec![0, 1, 2]),
+        result => panic!("Unexpected result: {:?}", result),
+    }
+    pipeline.shutdown().unwrap();
+
+    // So do the frames before a filter failure.
+    let mut pipeline = make_test_pipeline(&env, 6, None, Some(2));
+    match present_all(&mut env, &mut pipeline) {
+        Err((frame_ids, Error::UnsupportedOnThisPlatform)) => assert_eq!(frame_ids, vec![0, 1]),
+        result => panic!("Unexpected result: {:?}", result),
+    }
+    pipeline.shutdown().unwrap();
+
+    // A failure in the presenter is returned from `present`, and the stages still shut down.
+    let mut pipeline = make_test_pipeline(&env, 6, None, None);
+    for frame_id in 0..2 {
+        let result = pipeline.present(&mut env.device, &mut env.context, |_, _, _, info| {
+            if info.frame_id == 1 {
+                return Err(Error::Failed);
+            }
+            Ok(())
+        });
+        match result {
+            Ok(Some(info)) if frame_id == 0 => assert_eq!(info.frame_id, 0),
+            Err(Error::Failed) if frame_id == 1 => {}
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+    pipeline.shutdown().unwrap();
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that swap chain surfaces are retired on schedule without dropping frames.
+#[cfg(feature = "chains")]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_swap_chain_reallocation() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let swap_chain =
+        SwapChain::create_attached(&mut env.device, &mut env.context, SurfaceAccess::GPUOnly)
+            .unwrap();
+
+    // Reallocation is off by default.
+    for _ in 0..8 {
+        render_swap_chain_frame(&mut env, &swap_chain, &[255, 255, 255, 255]);
+    }
+    assert_eq!(swap_chain.stats().frames, 8);
+    assert_eq!(swap_chain.stats().reallocations, 0);
+
+    // The two surfaces alternate as the back buffer, so over 12 frames one is used 7 times and
+    // the other 6 times. With at most 3 frames each, that takes 5 surfaces in all.
+    swap_chain
+        .destroy(&mut env.device, &mut env.context)
+        .unwrap();
+    let surface = env
+        .device
+        .create_surface(
+            &env.context,
+            SurfaceAccess::GPUOnly,
+            SurfaceType::Generic {
+                size: Size2D::new(640, 480),
+            },
+        )
+        .unwrap();
+    env.device
+        .bind_surface_to_context(&mut env.context, surface)
+        .unwrap();
+    let swap_chain =
+        SwapChain::create_attached(&mut env.device, &mut env.context, SurfaceAccess::GPUOnly)
+            .unwrap();
+    swap_chain.set_reallocation_policy(ReallocationPolicy {
+        max_frames_per_buffer: Some(3),
+        realloc_on_resize_history: false,
+    });
+    for frame in 0..12 {
+        let color = [frame * 20, 255 - frame * 20, 0, 255];
+        render_swap_chain_frame(&mut env, &swap_chain, &color);
+
+        // Every frame reaches the front buffer intact.
+        let front_buffer = swap_chain.take_pending_surface().unwrap();
+        let front_buffer = env
+            .device
+            .create_surface_texture(&mut env.context, front_buffer)
+            .unwrap();
+        unsafe {
+            let framebuffer = make_fbo(
+                &env.gl,
+                env.device.surface_gl_texture_target(),
+                env.device.surface_texture_object(&front_buffer),
+            );
+            assert_eq!(get_pixel_from_bottom_row(&env.gl), color);
+            env.gl.DeleteFramebuffers(1, &framebuffer);
+        }
+        let front_buffer = env
+            .device
+            .destroy_surface_texture(&mut env.context, front_buffer)
+            .unwrap();
+        swap_chain.restore_pending_surface(front_buffer);
+    }
+    assert_eq!(swap_chain.stats().frames, 12);
+    assert_eq!(swap_chain.stats().reallocations, 3);
+
+    // Surfaces that survive a series of resizes are retired once the size settles.
+    swap_chain.set_reallocation_policy(ReallocationPolicy {
+        max_frames_per_buffer: None,
+        realloc_on_resize_history: true,
+    });
+    let reallocations = swap_chain.stats().reallocations;
+    for size in &[(320, 240), (640, 480), (320, 240), (640, 480)] {
+        swap_chain
+            .resize(
+                &mut env.device,
+                &mut env.context,
+                Size2D::new(size.0, size.1),
+            )
+            .unwrap();
+        render_swap_chain_frame(&mut env, &swap_chain, &[0, 0, 255, 255]);
+    }
+    for _ in 0..58 {
+        render_swap_chain_frame(&mut env, &swap_chain, &[0, 0, 255, 255]);
+    }
+    assert_eq!(swap_chain.stats().reallocations, reallocations);
+    for _ in 0..3 {
+        render_swap_chain_frame(&mut env, &swap_chain, &[0, 0, 255, 255]);
+    }
+    assert_eq!(swap_chain.stats().reallocations, reallocations + 2);
+
+    swap_chain
+        .destroy(&mut env.device, &mut env.context)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that swap chains hand over the attachment, and refuse what would deadlock or lose a
+// buffer.
+#[cfg(feature = "chains")]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_swap_chain_attachment() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let attached =
+        SwapChain::create_attached(&mut env.device, &mut env.context, SurfaceAccess::GPUOnly)
+            .unwrap();
+    let detached = SwapChain::create_detached(
+        &mut env.device,
+        &mut env.context,
+        SurfaceAccess::GPUOnly,
+        Size2D::new(640, 480),
+    )
+    .unwrap();
+
+    // A swap chain can't take the attachment from itself.
+    assert!(matches!(
+        attached.take_attachment_from(&mut env.device, &mut env.context, &attached.clone()),
+        Err(Error::Failed)
+    ));
+    detached
+        .take_attachment_from(&mut env.device, &mut env.context, &attached)
+        .unwrap();
+    assert!(detached.is_attached());
+    assert!(!attached.is_attached());
+
+    // The producer can't swap while it holds the back buffer as a texture.
+    let surface_texture = detached
+        .take_surface_texture(&env.device, &mut env.context)
+        .unwrap();
+    assert!(matches!(
+        detached.swap_buffers(&mut env.device, &mut env.context, PreserveBuffer::No),
+        Err(Error::Failed)
+    ));
+    assert!(matches!(
+        detached.resize(&mut env.device, &mut env.context, Size2D::new(320, 240)),
+        Err(Error::Failed)
+    ));
+    detached
+        .recycle_surface_texture(&env.device, &mut env.context, surface_texture)
+        .unwrap();
+    detached
+        .swap_buffers(&mut env.device, &mut env.context, PreserveBuffer::No)
+        .unwrap();
+    let front_buffer = detached.take_pending_surface().unwrap();
+    detached.restore_pending_surface(front_buffer);
+
+    attached.destroy(&mut env.device, &mut env.context).unwrap();
+    detached.destroy(&mut env.device, &mut env.context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that CPU layers upload exactly what was damaged, with random damage in each frame.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_cpu_layer_damage() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let size = Size2D::new(64, 48);
+    let mut layer = match CpuLayer::new(&mut env.device, &mut env.context, size) {
+        Ok(layer) => layer,
+        Err(Error::RequiredExtensionUnavailable) => {
+            env.device.destroy_context(&mut env.context).unwrap();
+            return;
+        }
+        Err(err) => panic!("Failed to create a CPU layer: {:?}", err),
+    };
+    let texture_fbo = make_fbo(
+        &env.gl,
+        env.device.surface_gl_texture_target(),
+        layer.texture_object(),
+    );
+    let read_texture = |gl: &Gl| {
+        let mut pixels = vec![0; 64 * 48 * 4];
+        unsafe {
+            gl.BindFramebuffer(gl::FRAMEBUFFER, texture_fbo);
+            gl.ReadPixels(
+                0,
+                0,
+                64,
+                48,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as *mut c_void,
+            );
+            check_gl(gl);
+        }
+        pixels
+    };
+    fn fill(pixels: &mut [u8], rect: &Rect<i32>, color: &[u8; 4]) {
+        for y in rect.min_y()..rect.max_y() {
+            for x in rect.min_x()..rect.max_x() {
+                let index = (y * 64 + x) as usize * 4;
+                pixels[index..index + 4].copy_from_slice(color);
+            }
+        }
+    }
+
+    // Draw into the layer and into a copy of what the texture should hold. Some frames have
+    // more damage than the layer tracks separately, and some damage is partly outside the layer.
+    let bounds = Rect::new(Point2D::zero(), size);
+    let mut expected = vec![0; 64 * 48 * 4];
+    let mut rng = StdRng::seed_from_u64(0x5eed);
+    for frame in 0..32 {
+        for _ in 0..rng.gen_range(0..MAX_DAMAGE_RECTS + 4) {
+            let rect = Rect::new(
+                Point2D::new(rng.gen_range(-8..64), rng.gen_range(-8..48)),
+                Size2D::new(rng.gen_range(1..24), rng.gen_range(1..24)),
+            );
+            let color: [u8; 4] = rng.gen();
+            if let Some(rect) = rect.intersection(&bounds) {
+                fill(layer.pixels_mut(), &rect, &color);
+                fill(&mut expected, &rect, &color);
+            }
+            layer.add_damage(&rect);
+        }
+        layer.commit().unwrap();
+        assert!(layer.frame_damage().len() <= MAX_DAMAGE_RECTS);
+        assert!(layer
+            .frame_damage()
+            .iter()
+            .all(|rect| bounds.contains_rect(rect)));
+        assert!(layer.committed_pixels() == &expected[..]);
+        assert!(layer.pixels() == &expected[..]);
+        assert!(
+            read_texture(&env.gl) == expected,
+            "Mismatch in frame {}",
+            frame
+        );
+    }
+
+    // Undamaged changes aren't uploaded.
+    layer.pixels_mut()[0..4].copy_from_slice(&[1, 2, 3, 4]);
+    layer.commit().unwrap();
+    assert!(layer.frame_damage().is_empty());
+    assert!(read_texture(&env.gl) == expected);
+
+    unsafe {
+        env.gl.DeleteFramebuffers(1, &texture_fbo);
+    }
+    bind_context_fbo(&env.gl, &env.device, &env.context);
+    layer.destroy(&env.device, &mut env.context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that surface textures are not upside-down.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_surface_texture_right_side_up() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    unsafe {
+        clear(&env.gl, &[255, 0, 0, 255]);
+        clear_bottom_row(&env.gl, &[0, 255, 0, 255]);
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+
+        let subsurface = env
+            .device
+            .unbind_surface_from_context(&mut env.context)
+            .unwrap()
+            .unwrap();
+        let subsurface_texture = env
+            .device
+            .create_surface_texture(&mut env.context, subsurface)
+            .unwrap();
+
+        let main_surface = make_surface(&mut env.device, &env.context);
+        env.device
+            .bind_surface_to_context(&mut env.context, main_surface)
+            .unwrap();
+
+        let main_framebuffer_object = env
+            .device
+            .context_surface_info(&env.context)
+            .unwrap()
+            .unwrap()
+            .framebuffer_object;
+        env.gl
+            .BindFramebuffer(gl::FRAMEBUFFER, main_framebuffer_object);
+        clear(&env.gl, &[255, 0, 0, 255]);
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+
+        let mut subframebuffer_object = make_fbo(
+            &env.gl,
+            env.device.surface_gl_texture_target(),
+            env.device.surface_texture_object(&subsurface_texture),
+        );
+
+        // Blit to main framebuffer.
+        let blitted = blit_fbo(
+            &env.gl,
+            &env.capabilities,
+            main_framebuffer_object,
+            subframebuffer_object,
+        );
+        env.gl
+            .BindFramebuffer(gl::FRAMEBUFFER, main_framebuffer_object);
+        check_gl(&env.gl);
+        if blitted {
+            assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+            assert_eq!(
+                get_pixel_from_second_from_bottom_row(&env.gl),
+                [255, 0, 0, 255]
+            );
+        }
+
+        // Clean up.
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        check_gl(&env.gl);
+        env.gl.DeleteFramebuffers(1, &mut subframebuffer_object);
+
+        let mut subsurface = env
+            .device
+            .destroy_surface_texture(&mut env.context, subsurface_texture)
+            .unwrap();
+        env.device
+            .destroy_surface(&mut env.context, &mut subsurface)
+            .unwrap();
+        env.device.destroy_context(&mut env.context).unwrap();
+    }
+}
+
+// Tests that multisampled generic surfaces are resolved into their textures when unbound.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_multisampled_surface() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let attributes = ContextAttributes {
+        version: GLVersion::new(3, 0),
+        flags: ContextAttributeFlags::DEPTH,
+        samples: 4,
+        ..Default::default()
+    };
+    let descriptor = env.device.create_context_descriptor(&attributes).unwrap();
+    let granted_samples = env
+        .device
+        .context_descriptor_attributes(&descriptor)
+        .samples;
+    assert!(granted_samples <= 4 && granted_samples != 1);
+
+    let mut context = env.device.create_context(&descriptor, None).unwrap();
+    let surface = make_surface(&mut env.device, &context);
+    env.device
+        .bind_surface_to_context(&mut context, surface)
+        .unwrap();
+    env.device.make_context_current(&context).unwrap();
+    bind_context_fbo(&env.gl, &env.device, &context);
+    unsafe {
+        assert_eq!(
+            env.gl.CheckFramebufferStatus(gl::FRAMEBUFFER),
+            gl::FRAMEBUFFER_COMPLETE
+        );
+        env.gl.Viewport(0, 0, 640, 480);
+    }
+    clear(&env.gl, &[0, 255, 0, 255]);
+
+    let surface = env
+        .device
+        .unbind_surface_from_context(&mut context)
+        .unwrap()
+        .unwrap();
+    let surface_texture = env
+        .device
+        .create_surface_texture(&mut context, surface)
+        .unwrap();
+    unsafe {
+        let mut framebuffer_object = make_fbo(
+            &env.gl,
+            env.device.surface_gl_texture_target(),
+            env.device.surface_texture_object(&surface_texture),
+        );
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        env.gl.DeleteFramebuffers(1, &mut framebuffer_object);
+    }
+
+    let mut surface = env
+        .device
+        .destroy_surface_texture(&mut context, surface_texture)
+        .unwrap();
+    env.device
+        .destroy_surface(&mut context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that sRGB generic surfaces are allocated sRGB-encoded and report it, and that surfaces
+// are linear by default.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_srgb_surface() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let mut context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    let mut linear_surface = make_surface(&mut env.device, &context);
+    assert_eq!(
+        env.device.surface_info(&linear_surface).colorspace,
+        ColorEncoding::Linear
+    );
+    env.device
+        .destroy_surface(&mut context, &mut linear_surface)
+        .unwrap();
+
+    let options = SurfaceOptions {
+        colorspace: ColorEncoding::Srgb,
+        ..SurfaceOptions::default()
+    };
+    let surface_type = SurfaceType::Generic {
+        size: Size2D::new(640, 480),
+    };
+    let surface = match env.device.create_surface_with_options(
+        &context,
+        SurfaceAccess::GPUOnly,
+        surface_type,
+        options,
+    ) {
+        Ok(surface) => surface,
+        Err(Error::UnsupportedSurfaceFormat) => {
+            env.device.destroy_context(&mut context).unwrap();
+            env.device.destroy_context(&mut env.context).unwrap();
+            return;
+        }
+        Err(err) => panic!("Failed to create an sRGB surface: {:?}", err),
+    };
+    let info = env.device.surface_info(&surface);
+    assert_eq!(info.colorspace, ColorEncoding::Srgb);
+    assert_eq!(info.format, gl::SRGB8_ALPHA8);
+
+    env.device
+        .bind_surface_to_context(&mut context, surface)
+        .unwrap();
+    env.device.make_context_current(&context).unwrap();
+    bind_context_fbo(&env.gl, &env.device, &context);
+    unsafe {
+        let mut encoding = 0;
+        env.gl.GetFramebufferAttachmentParameteriv(
+            gl::FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::FRAMEBUFFER_ATTACHMENT_COLOR_ENCODING,
+            &mut encoding,
+        );
+        check_gl(&env.gl);
+        assert_eq!(encoding as GLenum, gl::SRGB);
+    }
+
+    let mut surface = env
+        .device
+        .unbind_surface_from_context(&mut context)
+        .unwrap()
+        .unwrap();
+    env.device
+        .destroy_surface(&mut context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that half-float generic surfaces report their format both on the surface and on the
+// surface texture that wraps it.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_half_float_surface() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let options = SurfaceOptions {
+        format: SurfaceFormat::Rgba16F,
+        ..SurfaceOptions::default()
+    };
+    let surface_type = SurfaceType::Generic {
+        size: Size2D::new(640, 480),
+    };
+    let surface = match env.device.create_surface_with_options(
+        &env.context,
+        SurfaceAccess::GPUOnly,
+        surface_type,
+        options,
+    ) {
+        Ok(surface) => surface,
+        Err(Error::UnsupportedSurfaceFormat) => {
+            assert!(!env.capabilities.color_buffer_half_float);
+            env.device.destroy_context(&mut env.context).unwrap();
+            return;
+        }
+        Err(err) => panic!("Failed to create a half-float surface: {:?}", err),
+    };
+    let surface_info = env.device.surface_info(&surface);
+    assert_eq!(surface_info.format, gl::RGBA16F);
+    assert_eq!(surface_info.color_bits, ColorBits::of_format(gl::RGBA16F));
+    assert_eq!(surface_info.color_bits.alpha, 16);
+
+    let surface_texture = env
+        .device
+        .create_surface_texture(&mut env.context, surface)
+        .unwrap();
+    assert_eq!(
+        env.device.surface_texture_format(&surface_texture),
+        gl::RGBA16F
+    );
+
+    let mut surface = env
+        .device
+        .destroy_surface_texture(&mut env.context, surface_texture)
+        .unwrap();
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that converting between generic surfaces of every format that the device can create, with
+// and without exchanging red and blue, matches converting their pixels on the CPU, and that plain
+// copies are blitted while the rest go through the conversion shader.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_convert_surface() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let size = Size2D::new(16, 8);
+    let mut surfaces = vec![];
+    for &(format, colorspace) in &[
+        (SurfaceFormat::Rgba8, ColorEncoding::Linear),
+        (SurfaceFormat::Rgba8, ColorEncoding::Srgb),
+        (SurfaceFormat::Rgba16F, ColorEncoding::Linear),
+        (SurfaceFormat::Rgb10A2, ColorEncoding::Linear),
+    ] {
+        match env.device.create_surface_with_options(
+            &env.context,
+            SurfaceAccess::GPUOnly,
+            SurfaceType::Generic { size },
+            SurfaceOptions {
+                format,
+                colorspace,
+                ..SurfaceOptions::default()
+            },
+        ) {
+            Ok(surface) => surfaces.push(surface),
+            Err(Error::UnsupportedSurfaceFormat) => {}
+            Err(err) => panic!("Failed to create a {:?} surface: {:?}", format, err),
+        }
+    }
+
+    // Fill each surface with two colors side by side.
+    for surface in &surfaces {
+        let framebuffer_object = env.device.surface_info(surface).framebuffer_object;
+        unsafe {
+            env.gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
+            env.gl.Enable(gl::SCISSOR_TEST);
+            for &(x, color) in &[(0, [1.0, 0.5, 0.0, 1.0]), (8, [0.0, 0.25, 1.0, 0.5])] {
+                env.gl.Scissor(x, 0, 8, 8);
+                env.gl.ClearColor(color[0], color[1], color[2], color[3]);
+                env.gl.Clear(gl::COLOR_BUFFER_BIT);
+            }
+            env.gl.Disable(gl::SCISSOR_TEST);
+            env.gl.Scissor(0, 0, 640, 480);
+            check_gl(&env.gl);
+        }
+    }
+
+    let context_fbo = context_fbo(&env.device, &env.context);
+    let initial_stats = convert::stats();
+    for source_index in 0..surfaces.len() {
+        for destination_index in 0..surfaces.len() {
+            if source_index == destination_index {
+                continue;
+            }
+            let (source, destination) = if source_index < destination_index {
+                let (head, tail) = surfaces.split_at_mut(destination_index);
+                (&head[source_index], &mut tail[0])
+            } else {
+                let (head, tail) = surfaces.split_at_mut(source_index);
+                (&tail[0], &mut head[destination_index])
+            };
+            let source_info = env.device.surface_info(source);
+            let destination_info = env.device.surface_info(destination);
+
+            for &swizzle in &[Swizzle::Identity, Swizzle::SwapRedBlue] {
+                let source_pixels =
+                    [2, 12].map(|x| read_pixel_as_float(&env.gl, &source_info, x, 4));
+                bind_context_fbo(&env.gl, &env.device, &env.context);
+
+                let stats = convert::stats();
+                let blitted = swizzle == Swizzle::Identity
+                    && source_info.colorspace == destination_info.colorspace;
+                match env
+                    .device
+                    .convert_surface(&mut env.context, source, destination, swizzle)
+                {
+                    Ok(()) => {}
+                    Err(Error::RequiredExtensionUnavailable)
+                        if !blitted && env.capabilities.version.major < 3 =>
+                    {
+                        continue
+                    }
+                    Err(err) => panic!("Failed to convert a surface: {:?}", err),
+                }
+                let new_stats = convert::stats();
+                assert_eq!(new_stats.blits - stats.blits, blitted as u64);
+                assert_eq!(
+                    new_stats.shader_passes - stats.shader_passes,
+                    !blitted as u64
+                );
+                assert_eq!(get_framebuffer_binding(&env.gl), context_fbo);
+                assert_eq!(get_viewport(&env.gl), [0, 0, 640, 480]);
+
+                for (&x, source_pixel) in [2, 12].iter().zip(source_pixels.iter()) {
+                    let expected = convert_pixel(
+                        source_pixel,
+                        source_info.colorspace,
+                        destination_info.colorspace,
+                        swizzle,
+                    );
+                    let actual = read_pixel_as_float(&env.gl, &destination_info, x, 4);
+                    let tolerance = match destination_info.format {
+                        gl::RGBA16F => [0.003; 4],
+                        gl::RGB10_A2 => [1.5 / 1023.0, 1.5 / 1023.0, 1.5 / 1023.0, 1.5 / 3.0],
+                        _ => [1.5 / 255.0; 4],
+                    };
+                    for channel in 0..4 {
+                        assert!(
+                            (actual[channel] - expected[channel]).abs() <= tolerance[channel],
+                            "Converting {:x} to {:x} with {:?} gave {:?}, expected {:?}",
+                            source_info.format,
+                            destination_info.format,
+                            swizzle,
+                            actual,
+                            expected
+                        );
+                    }
+                }
+                bind_context_fbo(&env.gl, &env.device, &env.context);
+            }
+        }
+    }
+    // The conversion shader is compiled at most once per context.
+    assert!(convert::stats().programs_compiled - initial_stats.programs_compiled <= 1);
+
+    let mut small_surface = env
+        .device
+        .create_surface(
+            &env.context,
+            SurfaceAccess::GPUOnly,
+            SurfaceType::Generic {
+                size: Size2D::new(8, 8),
+            },
+        )
+        .unwrap();
+    assert!(matches!(
+        env.device.convert_surface(
+            &mut env.context,
+            &surfaces[0],
+            &mut small_surface,
+            Swizzle::Identity
+        ),
+        Err(Error::SurfaceSizeMismatch {
+            expected,
+            actual,
+        }) if expected == Size2D::new(8, 8) && actual == size
+    ));
+
+    surfaces.push(small_surface);
+    for mut surface in surfaces {
+        env.device
+            .destroy_surface(&mut env.context, &mut surface)
+            .unwrap();
+    }
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that generic surface formats follow the capabilities of the context: sRGB and 10-bit
+// surfaces need OpenGL ES 3.0 or any desktop OpenGL version that surfman supports, half-float
+// surfaces need renderable half-float color buffers, and linear 8-bit surfaces keep the format
+// that they would otherwise have.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_generic_surface_format_for_options() {
+    let capabilities = |gl_api, version, color_buffer_half_float| GLCapabilities {
+        gl_api,
+        version,
+        blit_framebuffer: false,
+        invalidate_framebuffer: false,
+        read_buffer: false,
+        fence_sync: false,
+        packed_depth_stencil: false,
+        depth24: false,
+        depth32f: false,
+        max_varying_vectors: 8,
+        vertex_texture_fetch: false,
+        color_buffer_half_float,
+        max_texture_size: 2048,
+    };
+    let options = |format, colorspace| SurfaceOptions {
+        format,
+        colorspace,
+        ..SurfaceOptions::default()
+    };
+    let gles2 = capabilities(GLApi::GLES, GLVersion::new(2, 0), false);
+    let gles3 = capabilities(GLApi::GLES, GLVersion::new(3, 0), false);
+    let gles3_half_float = capabilities(GLApi::GLES, GLVersion::new(3, 0), true);
+    let gl2 = capabilities(GLApi::GL, GLVersion::new(2, 1), false);
+
+    let linear = SurfaceOptions::default();
+    let srgb = options(SurfaceFormat::Rgba8, ColorEncoding::Srgb);
+    assert!(matches!(
+        linear.generic_surface_format(&gles2, GL_RGB565),
+        Ok(GL_RGB565)
+    ));
+    assert!(matches!(
+        srgb.generic_surface_format(&gles2, gl::RGBA8),
+        Err(Error::UnsupportedSurfaceFormat)
+    ));
+    assert!(matches!(
+        srgb.generic_surface_format(&gles3, GL_RGB565),
+        Ok(gl::SRGB8_ALPHA8)
+    ));
+    assert!(matches!(
+        srgb.generic_surface_format(&gl2, gl::RGBA8),
+        Ok(gl::SRGB8_ALPHA8)
+    ));
+
+    let half_float = options(SurfaceFormat::Rgba16F, ColorEncoding::Linear);
+    assert!(matches!(
+        half_float.generic_surface_format(&gles3, gl::RGBA8),
+        Err(Error::UnsupportedSurfaceFormat)
+    ));
+    assert!(matches!(
+        half_float.generic_surface_format(&gles3_half_float, gl::RGBA8),
+        Ok(gl::RGBA16F)
+    ));
+    assert!(matches!(
+        options(SurfaceFormat::Rgba16F, ColorEncoding::Srgb)
+            .generic_surface_format(&gles3_half_float, gl::RGBA8),
+        Err(Error::UnsupportedSurfaceFormat)
+    ));
+
+    let rgb10_a2 = options(SurfaceFormat::Rgb10A2, ColorEncoding::Linear);
+    assert!(matches!(
+        rgb10_a2.generic_surface_format(&gles2, gl::RGBA8),
+        Err(Error::UnsupportedSurfaceFormat)
+    ));
+    assert!(matches!(
+        rgb10_a2.generic_surface_format(&gles3, GL_RGB565),
+        Ok(gl::RGB10_A2)
+    ));
+}
+
+// Tests the surface recommended for each intent on backends that can and can't map surfaces for
+// the CPU, for contexts with and without fence sync objects.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_recommended_surface_config_table() {
+    use crate::SurfaceIntent::{CpuReadbackHeavy, DisplayedInWindow, OffscreenRenderTarget};
+    use crate::SurfaceIntent::{SharedWithOtherContext, VideoImport};
+
+    let capabilities = |fence_sync| GLCapabilities {
+        gl_api: GLApi::GL,
+        version: GLVersion::new(3, 3),
+        blit_framebuffer: true,
+        invalidate_framebuffer: false,
+        read_buffer: true,
+        fence_sync,
+        packed_depth_stencil: true,
+        depth24: true,
+        depth32f: true,
+        max_varying_vectors: 16,
+        vertex_texture_fetch: true,
+        color_buffer_half_float: true,
+        max_texture_size: 16384,
+    };
+    let (gpu, cpu) = (SurfaceAccess::GPUOnly, SurfaceAccess::GPUCPU);
+    let write_combined = SurfaceAccess::GPUCPUWriteCombined;
+
+    // Intent, whether the backend can map surfaces, whether the context has fence sync objects,
+    // and the recommended access, `map_for_cpu_access`, and `fence_sync`.
+    let table = [
+        (OffscreenRenderTarget, false, true, gpu, false, false),
+        (OffscreenRenderTarget, true, true, gpu, false, false),
+        (SharedWithOtherContext, false, true, gpu, false, true),
+        (SharedWithOtherContext, true, false, gpu, false, false),
+        (DisplayedInWindow(()), false, true, gpu, false, false),
+        (DisplayedInWindow(()), true, true, gpu, false, false),
+        (CpuReadbackHeavy, false, true, gpu, false, false),
+        (CpuReadbackHeavy, true, true, cpu, true, false),
+        (VideoImport, false, true, gpu, false, false),
+        (VideoImport, true, true, write_combined, true, false),
+    ];
+    let size = Size2D::new(64, 32);
+    for (intent, cpu_mappable, fence_sync, access, map_for_cpu_access, fence_sync_hint) in
+        table.iter().cloned()
+    {
+        let widget = matches!(intent, SurfaceIntent::DisplayedInWindow(_));
+        let config =
+            SurfaceConfig::recommend(intent, size, cpu_mappable, &capabilities(fence_sync));
+        assert_eq!(config.access, access);
+        assert_eq!(
+            config.hints,
+            SurfaceHints {
+                map_for_cpu_access,
+                fence_sync: fence_sync_hint,
+            }
+        );
+        assert_eq!(config.options, SurfaceOptions::default());
+        match config.surface_type {
+            SurfaceType::Generic { size: surface_size } => {
+                assert!(!widget);
+                assert_eq!(surface_size, size);
+            }
+            SurfaceType::Widget { native_widget: () } => assert!(widget),
+        }
+    }
+}
+
+// Tests that widget surface formats follow the color depth that the context was granted, and
+// that surface formats report the channel sizes that they store.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_widget_surface_format_for_options() {
+    let attributes = |color_depth| ContextAttributes {
+        version: GLVersion::new(3, 0),
+        flags: ContextAttributeFlags::empty(),
+        color_depth,
+        ..Default::default()
+    };
+    let options = |format, colorspace| SurfaceOptions {
+        format,
+        colorspace,
+        ..SurfaceOptions::default()
+    };
+    let rgb10_a2 = options(SurfaceFormat::Rgb10A2, ColorEncoding::Linear);
+    assert!(rgb10_a2
+        .check_widget_format(&attributes(ColorDepth::Deep))
+        .is_ok());
+    for &color_depth in &[ColorDepth::Standard, ColorDepth::Float] {
+        assert!(matches!(
+            rgb10_a2.check_widget_format(&attributes(color_depth)),
+            Err(Error::UnsupportedSurfaceFormat)
+        ));
+    }
+    assert!(matches!(
+        options(SurfaceFormat::Rgb10A2, ColorEncoding::Srgb)
+            .check_widget_format(&attributes(ColorDepth::Deep)),
+        Err(Error::UnsupportedSurfaceFormat)
+    ));
+    assert!(SurfaceOptions::default()
+        .check_widget_format(&attributes(ColorDepth::Deep))
+        .is_ok());
+
+    let color_bits = |red, green, blue, alpha| ColorBits {
+        red,
+        green,
+        blue,
+        alpha,
+    };
+    assert_eq!(ColorBits::of_format(gl::RGBA8), color_bits(8, 8, 8, 8));
+    assert_eq!(ColorBits::of_format(GL_RGB565), color_bits(5, 6, 5, 0));
+    assert_eq!(
+        ColorBits::of_format(gl::RGB10_A2),
+        color_bits(10, 10, 10, 2)
+    );
+    assert_eq!(ColorBits::of_format(gl::NONE), ColorBits::default());
+}
+
+// Reruns part of the suite as though the device only had OpenGL ES 2.0, to make sure that no
+// OpenGL ES 3.0 entry point gets called.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_gles2_capability_clamp() {
+    let _clamp = ClampToGLES2::new();
+
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    assert_eq!(env.capabilities, env.capabilities.clamp_to_gles2());
+    assert_eq!(env.capabilities.version, GLVersion::new(2, 0));
+    assert!(!env.capabilities.blit_framebuffer);
+    assert!(!env.capabilities.fence_sync);
+    assert!(!env.capabilities.vertex_texture_fetch);
+    assert_eq!(env.capabilities.max_varying_vectors, 8);
+
+    // Without `GL_OES_depth24`, this falls back to a 16-bit depth renderbuffer.
+    let depth_descriptor = env
+        .device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::DEPTH,
+            ..Default::default()
+        })
+        .unwrap();
+    let mut depth_context = env.device.create_context(&depth_descriptor, None).unwrap();
+    let surface = make_surface(&mut env.device, &depth_context);
+    env.device
+        .bind_surface_to_context(&mut depth_context, surface)
+        .unwrap();
+    env.device.make_context_current(&depth_context).unwrap();
+    bind_context_fbo(&env.gl, &env.device, &depth_context);
+    unsafe {
+        assert_eq!(
+            env.gl.CheckFramebufferStatus(gl::FRAMEBUFFER),
+            gl::FRAMEBUFFER_COMPLETE
+        );
+    }
+    env.device.destroy_context(&mut depth_context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+
+    test_generic_surface_creation();
+    test_begin_rendering();
+    test_surface_texture_blit_framebuffer();
+    test_cross_device_surface_texture_blit_framebuffer();
+    test_surface_texture_right_side_up();
+}
+
+#[cfg(not(any(target_os = "android", target_env = "ohos")))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_depth_and_stencil() {
+    let connection = Connection::new().unwrap();
+    let adapter = connection
+        .create_low_power_adapter()
+        .expect("Failed to create adapter!");
+    let mut device = match connection.create_device(&adapter) {
+        Ok(device) => device,
+        Err(Error::RequiredExtensionUnavailable) => {
+            // Can't run these tests on this hardware.
+            return;
+        }
+        Err(err) => panic!("Failed to create device: {:?}", err),
+    };
+
+    // Can't readback from the depth or stencil buffer on OpenGL ES, so just disable this test.
+    if device.gl_api() == GLApi::GLES {
+        return;
+    }
+
+    // Check depth.
+
+    let depth_context_descriptor = device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::DEPTH,
+            ..Default::default()
+        })
+        .unwrap();
+
+    let mut depth_context = device
+        .create_context(&depth_context_descriptor, None)
+        .unwrap();
+    let depth_surface = make_surface(&mut device, &depth_context);
+    device
+        .bind_surface_to_context(&mut depth_context, depth_surface)
+        .unwrap();
+    device.make_context_current(&depth_context).unwrap();
+
+    let gl = Gl::load_with(device.proc_address_loader(&depth_context));
+
+    unsafe {
+        let framebuffer_object = device
+            .context_surface_info(&depth_context)
+            .unwrap()
+            .unwrap()
+            .framebuffer_object;
+        gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
+        gl.Viewport(0, 0, 640, 480);
+
+        gl.ClearDepth(0.5);
+        gl.Clear(gl::DEPTH_BUFFER_BIT);
+
+        let mut depth_value: f32 = -1.0;
+        gl.ReadPixels(
+            0,
+            0,
+            1,
+            1,
+            gl::DEPTH_COMPONENT,
+            gl::FLOAT,
+            (&mut depth_value) as *mut f32 as *mut c_void,
+        );
+        assert!(
+            approx_eq(depth_value, 0.5),
+            "actual depth value was {}, but expected 0.5",
+            depth_value
+        );
+    }
+
+    device.destroy_context(&mut depth_context).unwrap();
+
+    // Check stencil.
+
+    let stencil_context_descriptor = device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::STENCIL,
+            ..Default::default()
+        })
+        .unwrap();
+
+    let mut stencil_context = device
+        .create_context(&stencil_context_descriptor, None)
+        .unwrap();
+    let stencil_surface = make_surface(&mut device, &stencil_context);
+    device
+        .bind_surface_to_context(&mut stencil_context, stencil_surface)
+        .unwrap();
+    device.make_context_current(&stencil_context).unwrap();
+
+    let gl = Gl::load_with(device.proc_address_loader(&stencil_context));
+
+    unsafe {
+        let framebuffer_object = device
+            .context_surface_info(&stencil_context)
+            .unwrap()
+            .unwrap()
+            .framebuffer_object;
+        gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
+        gl.Viewport(0, 0, 640, 480);
+
+        gl.ClearStencil(99);
+        gl.Clear(gl::STENCIL_BUFFER_BIT);
+
+        let mut stencil_value: u8 = 200;
+        gl.ReadPixels(
+            0,
+            0,
+            1,
+            1,
+            gl::STENCIL_INDEX,
+            gl::UNSIGNED_BYTE,
+            (&mut stencil_value) as *mut u8 as *mut c_void,
+        );
+        assert_eq!(stencil_value, 99);
+    }
+
+    device.destroy_context(&mut stencil_context).unwrap();
+}
+
+// Tests that clearing a region of a surface only changes that region, measured from the top left
+// and clamped to the surface, and leaves the caller's OpenGL state alone.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_clear_surface_region() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let mut surface = make_surface(&mut env.device, &env.context);
+
+    unsafe {
+        env.gl.ClearColor(0.25, 0.5, 0.75, 1.0);
+        env.gl.Scissor(1, 2, 3, 4);
+    }
+    let (red, green, blue) = (
+        [1.0, 0.0, 0.0, 1.0],
+        [0.0, 1.0, 0.0, 1.0],
+        [0.0, 0.0, 1.0, 1.0],
+    );
+    for &(rect, color) in &[
+        (None, red),
+        (
+            Some(Rect::new(Point2D::new(16, 32), Size2D::new(64, 48))),
+            green,
+        ),
+        // Only the top right corner of this one is inside the surface.
+        (
+            Some(Rect::new(Point2D::new(600, -20), Size2D::new(100, 40))),
+            blue,
+        ),
+        (
+            Some(Rect::new(Point2D::new(640, 0), Size2D::new(10, 10))),
+            blue,
+        ),
+        (
+            Some(Rect::new(Point2D::new(0, 0), Size2D::new(0, 10))),
+            blue,
+        ),
+    ] {
+        env.device
+            .clear_surface_region(&mut env.context, &mut surface, rect, color, None, None)
+            .unwrap();
+    }
+
+    assert!(env.device.is_context_current(&env.context));
+    unsafe {
+        let mut clear_color = [0.0; 4];
+        env.gl
+            .GetFloatv(gl::COLOR_CLEAR_VALUE, clear_color.as_mut_ptr());
+        assert_eq!(clear_color, [0.25, 0.5, 0.75, 1.0]);
+        let mut scissor_box = [0; 4];
+        env.gl
+            .GetIntegerv(gl::SCISSOR_BOX, scissor_box.as_mut_ptr());
+        assert_eq!(scissor_box, [1, 2, 3, 4]);
+        assert_eq!(env.gl.IsEnabled(gl::SCISSOR_TEST), gl::FALSE);
+    }
+
+    let framebuffer_object = env.device.surface_info(&surface).framebuffer_object;
+    let (red, green, blue) = ([255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]);
+    for &(x, y, expected) in &[
+        (16, 32, green),
+        (79, 79, green),
+        (15, 32, red),
+        (16, 31, red),
+        (80, 79, red),
+        (79, 80, red),
+        (600, 0, blue),
+        (639, 19, blue),
+        (599, 0, red),
+        (639, 20, red),
+        (639, 479, red),
+        (0, 0, red),
+    ] {
+        let pixel = get_pixel_from_top_left(&env.gl, framebuffer_object, x, y);
+        assert_eq!(pixel, expected, "wrong color at ({}, {})", x, y);
+    }
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that reading a surface back gives its pixels top row first, measured from the top left,
+// leaves the rest of the buffer and the caller's OpenGL state alone, and refuses rects outside the
+// surface and buffers that are too small.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_read_surface_data() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let mut surface = make_surface(&mut env.device, &env.context);
+
+    // Red, with a green top left quarter and a blue bottom right quarter.
+    for &(rect, color) in &[
+        (None, [1.0, 0.0, 0.0, 1.0]),
+        (
+            Some(Rect::new(Point2D::new(0, 0), Size2D::new(320, 240))),
+            [0.0, 1.0, 0.0, 1.0],
+        ),
+        (
+            Some(Rect::new(Point2D::new(320, 240), Size2D::new(320, 240))),
+            [0.0, 0.0, 1.0, 1.0],
+        ),
+    ] {
+        env.device
+            .clear_surface_region(&mut env.context, &mut surface, rect, color, None, None)
+            .unwrap();
+    }
+
+    let context_fbo = context_fbo(&env.device, &env.context);
+    unsafe {
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, context_fbo);
+        env.gl.PixelStorei(gl::PACK_ALIGNMENT, 1);
+    }
+    let mut pixels = [7; 4 * 2 * 4 + 4];
+    let rect = Rect::new(Point2D::new(318, 239), Size2D::new(4, 2));
+    env.device
+        .read_surface_data(&env.context, &surface, rect, &mut pixels)
+        .unwrap();
+    let (red, green, blue) = ([255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]);
+    let expected = [green, green, red, red, red, red, blue, blue];
+    for (index, pixel) in pixels.chunks(4).take(expected.len()).enumerate() {
+        assert_eq!(pixel, expected[index], "wrong color at pixel {}", index);
+    }
+    assert_eq!(pixels[32..], [7; 4]);
+
+    assert!(env.device.is_context_current(&env.context));
+    unsafe {
+        let (mut framebuffer, mut pack_alignment) = (0, 0);
+        env.gl
+            .GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut framebuffer);
+        env.gl.GetIntegerv(gl::PACK_ALIGNMENT, &mut pack_alignment);
+        assert_eq!(framebuffer as GLuint, context_fbo);
+        assert_eq!(pack_alignment, 1);
+    }
+
+    // The whole surface reads as it was drawn.
+    let mut pixels = vec![0; 640 * 480 * 4];
+    let bounds = Rect::new(Point2D::new(0, 0), Size2D::new(640, 480));
+    env.device
+        .read_surface_data(&env.context, &surface, bounds, &mut pixels)
+        .unwrap();
+    for &(x, y, expected) in &[
+        (0, 0, green),
+        (639, 0, red),
+        (0, 479, red),
+        (639, 479, blue),
+    ] {
+        let offset = (y * 640 + x) * 4;
+        assert_eq!(
+            pixels[offset..offset + 4],
+            expected,
+            "wrong color at ({}, {})",
+            x,
+            y
+        );
+    }
+
+    match env
+        .device
+        .read_surface_data(&env.context, &surface, rect, &mut [0; 31])
+    {
+        Err(Error::BufferTooSmall {
+            required: 32,
+            actual: 31,
+        }) => {}
+        result => panic!("Expected a too small buffer to be refused: {:?}", result),
+    }
+    for &rect in &[
+        Rect::new(Point2D::new(638, 0), Size2D::new(4, 2)),
+        Rect::new(Point2D::new(-1, 0), Size2D::new(4, 2)),
+        Rect::new(Point2D::new(0, 0), Size2D::new(4, -2)),
+    ] {
+        match env
+            .device
+            .read_surface_data(&env.context, &surface, rect, &mut pixels)
+        {
+            Err(Error::RectOutOfBounds(out_of_bounds)) => assert_eq!(out_of_bounds, rect),
+            result => panic!("Expected {:?} to be out of bounds: {:?}", rect, result),
+        }
+    }
+
+    #[cfg(feature = "image")]
+    {
+        let image = readback::read_surface_data_to_image(&env.device, &env.context, &surface, rect)
+            .unwrap();
+        assert_eq!(image.dimensions(), (4, 2));
+        assert_eq!(image.get_pixel(0, 0).0, green);
+        assert_eq!(image.get_pixel(3, 1).0, blue);
+    }
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that writing pixels into a surface puts them where reading them back finds them, honors the
+// stride, leaves the caller's OpenGL state alone, and refuses bad strides, short buffers, and rects
+// outside the surface.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_write_surface_data() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let mut surface = make_surface(&mut env.device, &env.context);
+    env.device
+        .clear_surface_region(
+            &mut env.context,
+            &mut surface,
+            None,
+            [0.0, 0.0, 0.0, 1.0],
+            None,
+            None,
+        )
+        .unwrap();
+
+    // Two rows of three pixels, each row padded to 16 bytes.
+    let (red, green, blue, black) = (
+        [255, 0, 0, 255],
+        [0, 255, 0, 255],
+        [0, 0, 255, 255],
+        [0, 0, 0, 255],
+    );
+    let mut data = vec![];
+    for row in &[[red, green, blue], [blue, red, green]] {
+        for pixel in row {
+            data.extend_from_slice(pixel);
+        }
+        data.extend_from_slice(&[9; 4]);
+    }
+    let rect = Rect::new(Point2D::new(100, 50), Size2D::new(3, 2));
+
+    let context_fbo = context_fbo(&env.device, &env.context);
+    unsafe {
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, context_fbo);
+        env.gl.PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+    }
+    env.device
+        .write_surface_data(&env.context, &mut surface, rect, &data[..28], 16)
+        .unwrap();
+    assert!(env.device.is_context_current(&env.context));
+    unsafe {
+        let (mut framebuffer, mut unpack_alignment) = (0, 0);
+        env.gl
+            .GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut framebuffer);
+        env.gl
+            .GetIntegerv(gl::UNPACK_ALIGNMENT, &mut unpack_alignment);
+        assert_eq!(framebuffer as GLuint, context_fbo);
+        assert_eq!(unpack_alignment, 1);
+    }
+
+    // Read back a border of one pixel around what was written.
+    let border = Rect::new(Point2D::new(99, 49), Size2D::new(5, 4));
+    let mut pixels = [0; 5 * 4 * 4];
+    env.device
+        .read_surface_data(&env.context, &surface, border, &mut pixels)
+        .unwrap();
+    let expected = [
+        [black, black, black, black, black],
+        [black, red, green, blue, black],
+        [black, blue, red, green, black],
+        [black, black, black, black, black],
+    ];
+    for (index, pixel) in pixels.chunks(4).enumerate() {
+        let (x, y) = (index % 5, index / 5);
+        assert_eq!(pixel, expected[y][x], "wrong color at ({}, {})", x, y);
+    }
+
+    match env
+        .device
+        .write_surface_data(&env.context, &mut surface, rect, &data, 11)
+    {
+        Err(Error::InvalidStride(11)) => {}
+        result => panic!("Expected a short stride to be refused: {:?}", result),
+    }
+    match env
+        .device
+        .write_surface_data(&env.context, &mut surface, rect, &data[..27], 16)
+    {
+        Err(Error::BufferTooSmall {
+            required: 28,
+            actual: 27,
+        }) => {}
+        result => panic!("Expected a short buffer to be refused: {:?}", result),
+    }
+    let out_of_bounds = Rect::new(Point2D::new(638, 479), Size2D::new(3, 2));
+    match env
+        .device
+        .write_surface_data(&env.context, &mut surface, out_of_bounds, &data, 16)
+    {
+        Err(Error::RectOutOfBounds(rect)) => assert_eq!(rect, out_of_bounds),
+        result => panic!(
+            "Expected {:?} to be out of bounds: {:?}",
+            out_of_bounds, result
+        ),
+    }
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that GL and EGL error codes translate to the errors that they stand for, and that codes
+// that aren't errors translate to `Failed`.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_error_translation() {
+    assert_eq!(
+        error::from_gl(gl::INVALID_ENUM),
+        WindowingApiError::BadEnumeration
+    );
+    assert_eq!(
+        error::from_gl(gl::INVALID_VALUE),
+        WindowingApiError::BadValue
+    );
+    assert_eq!(
+        error::from_gl(gl::INVALID_OPERATION),
+        WindowingApiError::BadOperation
+    );
+    assert_eq!(
+        error::from_gl(gl::INVALID_FRAMEBUFFER_OPERATION),
+        WindowingApiError::BadFramebufferOperation
+    );
+    assert_eq!(
+        error::from_gl(gl::OUT_OF_MEMORY),
+        WindowingApiError::BadAlloc
+    );
+    assert_eq!(error::from_gl(0x0507), WindowingApiError::ContextLost);
+    assert_eq!(error::from_gl(gl::NO_ERROR), WindowingApiError::Failed);
+
+    assert_eq!(error::from_egl(0x3002), WindowingApiError::BadAccess);
+    assert_eq!(error::from_egl(0x300e), WindowingApiError::ContextLost);
+    assert_eq!(error::from_egl(0x3000), WindowingApiError::Failed);
+
+    let gl_error = GlError::new(gl::INVALID_VALUE);
+    assert_eq!(gl_error.error, WindowingApiError::BadValue);
+    assert_eq!(gl_error.code, gl::INVALID_VALUE);
+    assert_eq!(gl_error.to_string(), "BadValue (0x0501)");
+}
+
+// Tests that draining a context's GL errors returns what the application left in the queue, and
+// empties it.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_drain_gl_errors() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    assert_eq!(env.device.drain_gl_errors(&env.context).unwrap(), vec![]);
+    unsafe {
+        env.gl.Enable(0xffff);
+    }
+    assert_eq!(
+        env.device.drain_gl_errors(&env.context).unwrap(),
+        vec![GlError::new(gl::INVALID_ENUM)]
+    );
+    assert_eq!(env.device.drain_gl_errors(&env.context).unwrap(), vec![]);
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that a GL error that the application left in the queue doesn't make surfman's next checked
+// operation fail, and is drained rather than left for the application to find afterward.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_pre_existing_gl_errors_not_blamed_on_surfman() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let mut surface = make_surface(&mut env.device, &env.context);
+
+    env.device.make_context_current(&env.context).unwrap();
+    unsafe {
+        env.gl.Enable(0xffff);
+    }
+    env.device
+        .clear_surface_region(
+            &mut env.context,
+            &mut surface,
+            None,
+            [0.0, 1.0, 0.0, 1.0],
+            None,
+            None,
+        )
+        .unwrap();
+    assert_eq!(env.device.drain_gl_errors(&env.context).unwrap(), vec![]);
+
+    let framebuffer_object = env.device.surface_info(&surface).framebuffer_object;
+    assert_eq!(
+        get_pixel_from_top_left(&env.gl, framebuffer_object, 0, 0),
+        [0, 255, 0, 255]
+    );
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that clearing a region of a surface clears its depth and stencil buffers only when asked
+// to, and only in that region.
+#[cfg(not(any(target_os = "android", target_env = "ohos")))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_clear_surface_region_depth_and_stencil() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    // Can't read back from the depth or stencil buffer on OpenGL ES.
+    if env.device.gl_api() == GLApi::GLES {
+        env.device.destroy_context(&mut env.context).unwrap();
+        return;
+    }
+
+    let descriptor = env
+        .device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::DEPTH | ContextAttributeFlags::STENCIL,
+            ..Default::default()
+        })
+        .unwrap();
+    let mut context = env.device.create_context(&descriptor, None).unwrap();
+    let mut surface = make_surface(&mut env.device, &context);
+    let black = [0.0, 0.0, 0.0, 1.0];
+    let rect = Rect::new(Point2D::new(100, 100), Size2D::new(50, 50));
+    env.device
+        .clear_surface_region(&mut context, &mut surface, None, black, Some(1.0), Some(0))
+        .unwrap();
+    env.device
+        .clear_surface_region(
+            &mut context,
+            &mut surface,
+            Some(rect),
+            black,
+            Some(0.25),
+            Some(7),
+        )
+        .unwrap();
+    // Leaving depth and stencil out leaves them alone.
+    env.device
+        .clear_surface_region(&mut context, &mut surface, Some(rect), black, None, None)
+        .unwrap();
+
+    env.device.make_context_current(&context).unwrap();
+    let gl = Gl::load_with(env.device.proc_address_loader(&context));
+    let framebuffer_object = env.device.surface_info(&surface).framebuffer_object;
+    for &(x, y, depth, stencil) in &[(120, 120, 0.25, 7), (99, 120, 1.0, 0), (120, 150, 1.0, 0)] {
+        unsafe {
+            gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
+            let mut depth_value: f32 = -1.0;
+            gl.ReadPixels(
+                x,
+                480 - 1 - y,
+                1,
+                1,
+                gl::DEPTH_COMPONENT,
+                gl::FLOAT,
+                (&mut depth_value) as *mut f32 as *mut c_void,
+            );
+            assert!(
+                approx_eq(depth_value, depth),
+                "depth at ({}, {}) was {}, but expected {}",
+                x,
+                y,
+                depth_value,
+                depth
+            );
+            let mut stencil_value: u8 = 200;
+            gl.ReadPixels(
+                x,
+                480 - 1 - y,
+                1,
+                1,
+                gl::STENCIL_INDEX,
+                gl::UNSIGNED_BYTE,
+                (&mut stencil_value) as *mut u8 as *mut c_void,
+            );
+            assert_eq!(stencil_value, stencil, "wrong stencil at ({}, {})", x, y);
+        }
+    }
+
+    env.device
+        .destroy_surface(&mut context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that a checkerboard debug fill covers new surfaces, starting with its first color at the
+// top left.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_debug_surface_fill_checkerboard() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    assert_eq!(env.device.debug_surface_fill(), DebugFill::None);
+    let fill = DebugFill::Checkerboard { cell: 16 };
+    env.device.set_debug_surface_fill(fill);
+    assert_eq!(env.device.debug_surface_fill(), fill);
+
+    unsafe {
+        env.gl.ClearColor(0.25, 0.5, 0.75, 1.0);
+        env.gl.Scissor(1, 2, 3, 4);
+    }
+    let mut surface = make_surface(&mut env.device, &env.context);
+    unsafe {
+        let mut clear_color = [0.0; 4];
+        env.gl
+            .GetFloatv(gl::COLOR_CLEAR_VALUE, clear_color.as_mut_ptr());
+        assert_eq!(clear_color, [0.25, 0.5, 0.75, 1.0]);
+        let mut scissor_box = [0; 4];
+        env.gl
+            .GetIntegerv(gl::SCISSOR_BOX, scissor_box.as_mut_ptr());
+        assert_eq!(scissor_box, [1, 2, 3, 4]);
+        assert_eq!(env.gl.IsEnabled(gl::SCISSOR_TEST), gl::FALSE);
+    }
+
+    let framebuffer_object = env.device.surface_info(&surface).framebuffer_object;
+    let (magenta, black) = ([255, 0, 255, 255], [0, 0, 0, 255]);
+    for &(x, y, expected) in &[
+        (0, 0, magenta),
+        (15, 15, magenta),
+        (16, 0, black),
+        (0, 16, black),
+        (31, 31, magenta),
+        (47, 16, black),
+        (48, 16, magenta),
+        (639, 0, black),
+        (639, 479, magenta),
+    ] {
+        let pixel = get_pixel_from_top_left(&env.gl, framebuffer_object, x, y);
+        assert_eq!(pixel, expected, "wrong color at ({}, {})", x, y);
+    }
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that a solid debug fill covers the color and depth of new surfaces, that surfaces can be
+// filled again as they're reused, and that turning the fill off leaves their contents alone.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_debug_surface_fill_solid_color() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let descriptor = env
+        .device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::DEPTH,
+            ..Default::default()
+        })
+        .unwrap();
+    let mut context = env.device.create_context(&descriptor, None).unwrap();
+    env.device
+        .set_debug_surface_fill(DebugFill::SolidColor([0.0, 1.0, 0.0, 1.0]));
+    let mut surface = make_surface(&mut env.device, &context);
+
+    env.device.make_context_current(&context).unwrap();
+    let gl = Gl::load_with(env.device.proc_address_loader(&context));
+    let framebuffer_object = env.device.surface_info(&surface).framebuffer_object;
+    for &(x, y) in &[(0, 0), (320, 240), (639, 479)] {
+        let pixel = get_pixel_from_top_left(&gl, framebuffer_object, x, y);
+        assert_eq!(pixel, [0, 255, 0, 255], "wrong color at ({}, {})", x, y);
+    }
+    // Can't read back from the depth buffer on OpenGL ES.
+    if env.device.gl_api() == GLApi::GL {
+        unsafe {
+            let mut depth_value: f32 = -1.0;
+            gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
+            gl.ReadPixels(
+                320,
+                240,
+                1,
+                1,
+                gl::DEPTH_COMPONENT,
+                gl::FLOAT,
+                (&mut depth_value) as *mut f32 as *mut c_void,
+            );
+            assert!(approx_eq(depth_value, DebugFill::DEPTH));
+        }
+    }
+
+    // A reused surface is filled again...
+    let blue = [0.0, 0.0, 1.0, 1.0];
+    env.device
+        .clear_surface_region(&mut context, &mut surface, None, blue, None, None)
+        .unwrap();
+    env.device
+        .debug_fill_surface(&mut context, &mut surface)
+        .unwrap();
+    let pixel = get_pixel_from_top_left(&gl, framebuffer_object, 320, 240);
+    assert_eq!(pixel, [0, 255, 0, 255]);
+
+    // ...unless the fill has been turned off.
+    env.device
+        .clear_surface_region(&mut context, &mut surface, None, blue, None, None)
+        .unwrap();
+    env.device.set_debug_surface_fill(DebugFill::None);
+    env.device
+        .debug_fill_surface(&mut context, &mut surface)
+        .unwrap();
+    let pixel = get_pixel_from_top_left(&gl, framebuffer_object, 320, 240);
+    assert_eq!(pixel, [0, 0, 255, 255]);
+
+    env.device
+        .destroy_surface(&mut context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that debug fills are stored as the surface's format stores them: unclamped in half-float
+// surfaces, and without alpha in the RGB565 surfaces of low-memory mode.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_debug_surface_fill_formats() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    env.device
+        .set_debug_surface_fill(DebugFill::SolidColor([2.0, 0.5, 0.0, 0.5]));
+
+    let options = SurfaceOptions {
+        format: SurfaceFormat::Rgba16F,
+        ..SurfaceOptions::default()
+    };
+    let surface_type = SurfaceType::Generic {
+        size: Size2D::new(640, 480),
+    };
+    match env.device.create_surface_with_options(
+        &env.context,
+        SurfaceAccess::GPUOnly,
+        surface_type,
+        options,
+    ) {
+        Ok(mut surface) => {
+            let surface_info = env.device.surface_info(&surface);
+            let pixel = read_pixel_as_float(&env.gl, &surface_info, 0, 0);
+            assert_eq!(pixel, [2.0, 0.5, 0.0, 0.5]);
+            env.device
+                .destroy_surface(&mut env.context, &mut surface)
+                .unwrap();
+        }
+        Err(Error::UnsupportedSurfaceFormat) => {
+            assert!(!env.capabilities.color_buffer_half_float)
+        }
+        Err(err) => panic!("Failed to create a half-float surface: {:?}", err),
+    }
+
+    env.device
+        .set_debug_surface_fill(DebugFill::SolidColor([1.0, 0.0, 1.0, 0.5]));
+    env.device.enter_low_memory_mode(LowMemoryOptions {
+        prefer_rgb565: true,
+        ..LowMemoryOptions::default()
+    });
+    let mut surface = make_surface(&mut env.device, &env.context);
+    let surface_info = env.device.surface_info(&surface);
+    assert_eq!(surface_info.format, GL_RGB565);
+    let pixel = get_pixel_from_top_left(&env.gl, surface_info.framebuffer_object, 0, 0);
+    assert_eq!(pixel, [255, 0, 255, 255]);
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that explicit depth and stencil sizes override the flags, are reported back, and size the
+// renderbuffers of generic surfaces.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_depth_and_stencil_sizes() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    for &(flags, depth_size, stencil_size) in &[
+        (ContextAttributeFlags::DEPTH, Some(0), None),
+        (ContextAttributeFlags::empty(), Some(16), None),
+        (ContextAttributeFlags::empty(), Some(24), Some(8)),
+        (ContextAttributeFlags::DEPTH, Some(32), None),
+    ] {
+        let attributes = ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags,
+            depth_size,
+            stencil_size,
+            ..Default::default()
+        };
+        let descriptor = match env.device.create_context_descriptor(&attributes) {
+            Ok(descriptor) => descriptor,
+            // Not every platform offers every size.
+            Err(Error::NoPixelFormatFound) => continue,
+            Err(err) => panic!("Context descriptor creation failed: {:?}", err),
+        };
+        let (requested_depth_size, requested_stencil_size) =
+            context::requested_depth_stencil_sizes(&attributes);
+        let actual_attributes = env.device.context_descriptor_attributes(&descriptor);
+        let actual_depth_size = actual_attributes.depth_size.unwrap();
+        let actual_stencil_size = actual_attributes.stencil_size.unwrap();
+        assert!(actual_depth_size >= requested_depth_size);
+        assert!(actual_stencil_size >= requested_stencil_size);
+        assert_eq!(
+            actual_attributes
+                .flags
+                .contains(ContextAttributeFlags::DEPTH),
+            actual_depth_size != 0
+        );
+        assert_eq!(
+            actual_attributes
+                .flags
+                .contains(ContextAttributeFlags::STENCIL),
+            actual_stencil_size != 0
+        );
+
+        let mut context = env.device.create_context(&descriptor, None).unwrap();
+        let surface = make_surface(&mut env.device, &context);
+        env.device
+            .bind_surface_to_context(&mut context, surface)
+            .unwrap();
+        env.device.make_context_current(&context).unwrap();
+        bind_context_fbo(&env.gl, &env.device, &context);
+        unsafe {
+            assert_eq!(
+                env.gl.CheckFramebufferStatus(gl::FRAMEBUFFER),
+                gl::FRAMEBUFFER_COMPLETE
+            );
+            if actual_depth_size > 0 {
+                let (mut renderbuffer_depth_size, mut component_type) = (0, 0);
+                env.gl.GetFramebufferAttachmentParameteriv(
+                    gl::FRAMEBUFFER,
+                    gl::DEPTH_ATTACHMENT,
+                    gl::FRAMEBUFFER_ATTACHMENT_DEPTH_SIZE,
+                    &mut renderbuffer_depth_size,
+                );
+                env.gl.GetFramebufferAttachmentParameteriv(
+                    gl::FRAMEBUFFER,
+                    gl::DEPTH_ATTACHMENT,
+                    gl::FRAMEBUFFER_ATTACHMENT_COMPONENT_TYPE,
+                    &mut component_type,
+                );
+                assert!(renderbuffer_depth_size >= requested_depth_size as i32);
+                if actual_depth_size > 24 && env.capabilities.depth32f {
+                    assert_eq!(component_type as GLenum, gl::FLOAT);
+                }
+            }
+        }
+        env.device.destroy_context(&mut context).unwrap();
+    }
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Make sure that the current native context can be fetched and that they can be correctly wrapped
+// in `surfman` contexts.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_get_native_context() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let native_context = env.device.current_native_context().unwrap();
+
+    unsafe {
+        clear(&env.gl, &[0, 255, 0, 255]);
+
+        let mut other_context = env
+            .device
+            .create_context_from_native_context(native_context)
+            .unwrap();
+
+        env.device.make_context_current(&other_context).unwrap();
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+
+        env.device.destroy_context(&mut other_context).unwrap();
+    }
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that an adopted native context gets a matching descriptor, can have surfaces bound to it,
+// and outlives the `Context` that wraps it.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_adopt_native_context() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let mut native_owner = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    let native_context = env.device.native_context(&native_owner);
+    let mut adopted_context = unsafe {
+        env.device
+            .create_context_from_native_context(native_context)
+            .unwrap()
+    };
+    let owner_descriptor = env.device.context_descriptor(&native_owner);
+    let adopted_descriptor = env.device.context_descriptor(&adopted_context);
+    assert_eq!(
+        env.device.context_descriptor_raw_parts(&adopted_descriptor),
+        env.device.context_descriptor_raw_parts(&owner_descriptor)
+    );
+
+    let surface = make_surface(&mut env.device, &adopted_context);
+    env.device
+        .bind_surface_to_context(&mut adopted_context, surface)
+        .unwrap();
+    env.device.make_context_current(&adopted_context).unwrap();
+    let gl = Gl::load_with(env.device.proc_address_loader(&adopted_context));
+    bind_context_fbo(&gl, &env.device, &adopted_context);
+    clear(&gl, &[0, 0, 255, 255]);
+    assert_eq!(get_pixel_from_bottom_row(&gl), [0, 0, 255, 255]);
+    env.device.destroy_context(&mut adopted_context).unwrap();
+
+    // The native context is still alive, and still works through its owner.
+    env.device.make_context_current(&native_owner).unwrap();
+    let gl = Gl::load_with(env.device.proc_address_loader(&native_owner));
+    unsafe {
+        assert!(!gl.GetString(gl::VERSION).is_null());
+        assert_eq!(gl.GetError(), gl::NO_ERROR);
+    }
+    env.device.destroy_context(&mut native_owner).unwrap();
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that resizing a generic surface reallocates it in place, keeping its framebuffer object
+// valid and bound, and that surface textures then see the new storage.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_resize_surface() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let mut surface = make_surface(&mut env.device, &env.context);
+    let framebuffer_object = env.device.surface_info(&surface).framebuffer_object;
+    env.device.make_context_current(&env.context).unwrap();
+    unsafe {
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
+    }
+
+    let new_size = Size2D::new(200, 100);
+    match env
+        .device
+        .resize_surface(&env.context, &mut surface, new_size)
+    {
+        Ok(()) => {}
+        Err(Error::UnsupportedOnThisPlatform) => {
+            env.device
+                .destroy_surface(&mut env.context, &mut surface)
+                .unwrap();
+            env.device.destroy_context(&mut env.context).unwrap();
+            return;
+        }
+        Err(err) => panic!("Failed to resize the surface: {:?}", err),
+    }
+    let info = env.device.surface_info(&surface);
+    assert_eq!(info.size, new_size);
+    assert_eq!(info.framebuffer_object, framebuffer_object);
+
+    unsafe {
+        assert_eq!(get_framebuffer_binding(&env.gl), framebuffer_object);
+        assert_eq!(
+            env.gl.CheckFramebufferStatus(gl::FRAMEBUFFER),
+            gl::FRAMEBUFFER_COMPLETE
+        );
+        clear(&env.gl, &[0, 255, 0, 255]);
+        let mut pixel = [0u8; 4];
+        env.gl.ReadPixels(
+            new_size.width - 1,
+            new_size.height - 1,
+            1,
+            1,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            pixel.as_mut_ptr() as *mut c_void,
+        );
+        assert_eq!(pixel, [0, 255, 0, 255]);
+        env.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        env.gl.Flush();
+    }
+
+    let surface_texture = env
+        .device
+        .create_surface_texture(&mut env.context, surface)
+        .unwrap();
+    let texture = env.device.surface_texture_object(&surface_texture);
+    assert_eq!(
+        read_texture_pixel(&env.gl, &env.device, &env.context, texture),
+        [0, 255, 0, 255]
+    );
+    let mut surface = env
+        .device
+        .destroy_surface_texture(&mut env.context, surface_texture)
+        .unwrap();
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that surfaces can't be created or resized with a side that is zero, negative, or longer
+// than the largest texture, and that a refused resize leaves the surface as it was.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_invalid_surface_sizes() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let max_texture_size = env
+        .device
+        .capabilities(&env.context)
+        .unwrap()
+        .max_texture_size as i32;
+    let invalid_sizes = [
+        Size2D::new(0, 0),
+        Size2D::new(640, 0),
+        Size2D::new(-1, 480),
+        Size2D::new(640, -480),
+        Size2D::new(max_texture_size + 1, 16),
+        Size2D::new(16, max_texture_size + 1),
+    ];
+
+    for &size in &invalid_sizes {
+        match env.device.create_surface(
+            &env.context,
+            SurfaceAccess::GPUOnly,
+            SurfaceType::Generic { size },
+        ) {
+            Err(Error::InvalidSurfaceSize(invalid_size)) => assert_eq!(invalid_size, size),
+            Err(err) => panic!("Creating a {:?} surface failed with {:?}", size, err),
+            Ok(mut surface) => {
+                env.device
+                    .destroy_surface(&mut env.context, &mut surface)
+                    .unwrap();
+                panic!("Created a {:?} surface", size);
+            }
+        }
+    }
+
+    let mut surface = make_surface(&mut env.device, &env.context);
+    for &size in &invalid_sizes {
+        match env.device.resize_surface(&env.context, &mut surface, size) {
+            Err(Error::InvalidSurfaceSize(invalid_size)) => assert_eq!(invalid_size, size),
+            result => panic!("Resizing a surface to {:?} returned {:?}", size, result),
+        }
+        assert_eq!(
+            env.device.surface_info(&surface).size,
+            Size2D::new(640, 480)
+        );
+    }
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that generic surfaces, which are never presented, refuse a swap interval, adaptive or not.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_set_swap_interval_generic_surface() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let mut surface = make_surface(&mut env.device, &env.context);
+    assert!(!env.device.supports_adaptive_swap(&surface));
+    for interval in [0, 1, -1] {
+        match env
+            .device
+            .set_swap_interval(&env.context, &surface, interval)
+        {
+            Err(Error::NoWidgetAttached) => {}
+            result => panic!("Expected `NoWidgetAttached`, got {:?}", result),
+        }
+    }
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that waiting for a buffer release on a surface with no widget reports `NoWidgetAttached`.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_wait_previous_buffer_released_generic_surface() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let mut surface = make_surface(&mut env.device, &env.context);
+    match env
+        .device
+        .wait_previous_buffer_released(&surface, Duration::from_millis(1))
+    {
+        Err(Error::NoWidgetAttached) => {}
+        result => panic!("Expected `NoWidgetAttached`, got {:?}", result),
+    }
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that querying the native size of a surface with no widget reports `NoWidgetAttached`.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_query_surface_native_size_generic_surface() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let mut surface = make_surface(&mut env.device, &env.context);
+    match env.device.query_surface_native_size(&surface) {
+        Err(Error::NoWidgetAttached) => {}
+        result => panic!("Expected `NoWidgetAttached`, got {:?}", result),
+    }
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that surfaces with no widget, which no window system shows, report no rotation, even
+// when created to be rendered pre-rotated.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_surface_transform_generic_surface() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let options = SurfaceOptions {
+        pre_transform: true,
+        ..SurfaceOptions::default()
+    };
+    let mut surface = env
+        .device
+        .create_surface_with_options(
+            &env.context,
+            SurfaceAccess::GPUOnly,
+            SurfaceType::Generic {
+                size: Size2D::new(640, 480),
+            },
+            options,
+        )
+        .unwrap();
+    assert_eq!(
+        env.device.surface_transform(&surface),
+        SurfaceTransform::Identity
+    );
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that only quarter turns swap the width and height of frames.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_surface_transform_swaps_dimensions() {
+    assert_eq!(SurfaceTransform::default(), SurfaceTransform::Identity);
+    assert!(!SurfaceTransform::Identity.swaps_dimensions());
+    assert!(SurfaceTransform::Rotate90.swaps_dimensions());
+    assert!(!SurfaceTransform::Rotate180.swaps_dimensions());
+    assert!(SurfaceTransform::Rotate270.swaps_dimensions());
+}
+
+// Tests that surfaces invalidate depth and stencil by default, and that the color buffer can only
+// be invalidated where its contents aren't preserved.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_post_present_invalidation_rules() {
+    let mut invalidation = PostPresentInvalidation::default();
+    assert_eq!(
+        invalidation.set(),
+        InvalidateSet {
+            color: false,
+            depth: true,
+            stencil: true,
+        }
+    );
+    assert!(InvalidateSet::NONE.is_empty());
+    assert!(!InvalidateSet::default().is_empty());
+
+    let everything = InvalidateSet {
+        color: true,
+        depth: true,
+        stencil: true,
+    };
+    assert!(matches!(
+        invalidation.change(everything, true),
+        Err(Error::PreservedColorBuffer)
+    ));
+    assert_eq!(invalidation.set(), InvalidateSet::default());
+    invalidation.change(everything, false).unwrap();
+    assert_eq!(invalidation.set(), everything);
+    invalidation.change(InvalidateSet::NONE, true).unwrap();
+    assert!(invalidation.set().is_empty());
+}
+
+// Tests that generic surfaces refuse to invalidate their color buffer, and that
+// `invalidate_surface()` invalidates the attachments that were chosen.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_post_present_invalidation_generic_surface() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let mut surface = make_surface(&mut env.device, &env.context);
+    assert_eq!(
+        env.device.post_present_invalidation(&surface),
+        InvalidateSet::default()
+    );
+    let color = InvalidateSet {
+        color: true,
+        ..InvalidateSet::NONE
+    };
+    assert!(matches!(
+        env.device
+            .set_post_present_invalidation(&mut surface, color),
+        Err(Error::PreservedColorBuffer)
+    ));
+    assert_eq!(
+        env.device.post_present_invalidation(&surface),
+        InvalidateSet::default()
+    );
+
+    let depth = InvalidateSet {
+        depth: true,
+        ..InvalidateSet::NONE
+    };
+    env.device
+        .set_post_present_invalidation(&mut surface, depth)
+        .unwrap();
+    assert_eq!(env.device.post_present_invalidation(&surface), depth);
+    let before = invalidate::stats();
+    env.device
+        .invalidate_surface(&env.context, &mut surface)
+        .unwrap();
+    let after = invalidate::stats();
+    if GLCapabilities::current(&env.gl).invalidate_framebuffer {
+        assert!(after.invalidations > before.invalidations);
+        assert!(after.depth > before.depth);
+    }
+    assert_eq!(after.color, before.color);
+
+    // Nothing is invalidated once the set is empty.
+    env.device
+        .set_post_present_invalidation(&mut surface, InvalidateSet::NONE)
+        .unwrap();
+    let before = invalidate::stats();
+    env.device
+        .invalidate_surface(&env.context, &mut surface)
+        .unwrap();
+    assert_eq!(invalidate::stats().invalidations, before.invalidations);
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that a context stays usable for offscreen rendering after its surface is unbound, and that
+// binding the surface again restores it.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_unbind_and_rebind_surface() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    for &policy in &[PlaceholderPolicy::Owned, PlaceholderPolicy::Shared] {
+        let mut attributes = env
+            .device
+            .context_descriptor_attributes(&env.context_descriptor);
+        attributes.placeholder_policy = policy;
+        let context_descriptor = env.device.create_context_descriptor(&attributes).unwrap();
+        let mut context = env
+            .device
+            .create_context(&context_descriptor, None)
+            .unwrap();
+        let surface = make_surface(&mut env.device, &context);
+        env.device
+            .bind_surface_to_context(&mut context, surface)
+            .unwrap();
+        env.device.make_context_current(&context).unwrap();
+        let gl = Gl::load_with(env.device.proc_address_loader(&context));
+        bind_context_fbo(&gl, &env.device, &context);
+        clear(&gl, &[0, 255, 0, 255]);
+
+        let surface = env
+            .device
+            .unbind_surface_from_context(&mut context)
+            .unwrap()
+            .unwrap();
+        assert!(env.device.context_surface_info(&context).unwrap().is_none());
+        env.device.make_context_current(&context).unwrap();
+        unsafe {
+            let mut texture = 0;
+            gl.GenTextures(1, &mut texture);
+            gl.BindTexture(gl::TEXTURE_2D, texture);
+            gl.TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA as _,
+                16,
+                16,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                std::ptr::null(),
+            );
+            let framebuffer_object = make_fbo(&gl, gl::TEXTURE_2D, texture);
+            clear(&gl, &[255, 0, 0, 255]);
+            assert_eq!(get_pixel_from_bottom_row(&gl), [255, 0, 0, 255]);
+            gl.DeleteFramebuffers(1, &framebuffer_object);
+            gl.DeleteTextures(1, &texture);
+        }
+
+        env.device
+            .bind_surface_to_context(&mut context, surface)
+            .unwrap();
+        env.device.make_context_current(&context).unwrap();
+        bind_context_fbo(&gl, &env.device, &context);
+        assert_eq!(get_pixel_from_bottom_row(&gl), [0, 255, 0, 255]);
+        clear(&gl, &[0, 0, 255, 255]);
+        assert_eq!(get_pixel_from_bottom_row(&gl), [0, 0, 255, 255]);
+
+        env.device.destroy_context(&mut context).unwrap();
+    }
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Simulates, on this thread, a driver that can't make contexts current without a surface and that
+// only lets `limit` more pbuffers be alive. The simulation ends when this is dropped.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos"))
+))]
+struct SimulatedPbufferLimit;
+
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos"))
+))]
+impl SimulatedPbufferLimit {
+    fn new(limit: usize) -> SimulatedPbufferLimit {
+        let limit = TrackedObject::Placeholder.live_count() + limit;
+        SIMULATED_PBUFFER_LIMIT.with(|simulated_limit| simulated_limit.set(Some(limit)));
+        SimulatedPbufferLimit
+    }
+}
+
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos"))
+))]
+impl Drop for SimulatedPbufferLimit {
+    fn drop(&mut self) {
+        SIMULATED_PBUFFER_LIMIT.with(|simulated_limit| simulated_limit.set(None));
+    }
+}
+
+// Creates a device for the placeholder tests, or returns `None` if there is none to test.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos"))
+))]
+fn create_placeholder_test_device() -> Option<Device> {
     let connection = Connection::new().unwrap();
     let adapter = connection
         .create_low_power_adapter()
         .expect("Failed to create adapter!");
-    let mut device = match connection.create_device(&adapter) {
-        Ok(device) => device,
+    match connection.create_device(&adapter) {
+        Ok(device) => Some(device),
         Err(Error::RequiredExtensionUnavailable) => {
             // Can't run these tests on this hardware.
-            return;
+            None
         }
         Err(err) => panic!("Failed to create device: {:?}", err),
+    }
+}
+
+// Tests that many contexts can share placeholder surfaces and still be made current on a driver
+// that runs out of pbuffers, where contexts with placeholders of their own fail.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos"))
+))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_shared_placeholder_contexts() {
+    const PBUFFER_LIMIT: usize = 16;
+    const CONTEXT_COUNT: usize = 256;
+
+    let mut device = match create_placeholder_test_device() {
+        Some(device) => device,
+        None => return,
+    };
+    let placeholders = TrackedObject::Placeholder.live_count();
+    let _pbuffer_limit = SimulatedPbufferLimit::new(PBUFFER_LIMIT);
+
+    // With a pbuffer per context, context creation fails once the driver runs out.
+    let owned_descriptor = device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::empty(),
+            placeholder_policy: PlaceholderPolicy::Owned,
+            ..Default::default()
+        })
+        .unwrap();
+    let mut owned_contexts = vec![];
+    let owned_error = loop {
+        match device.create_context(&owned_descriptor, None) {
+            Ok(context) if owned_contexts.len() < CONTEXT_COUNT => owned_contexts.push(context),
+            Ok(mut context) => {
+                device.destroy_context(&mut context).unwrap();
+                break None;
+            }
+            Err(err) => break Some(err),
+        }
+    };
+    for mut context in owned_contexts.drain(..) {
+        device.destroy_context(&mut context).unwrap();
+    }
+    match owned_error {
+        Some(Error::SurfaceCreationFailed(_)) => {}
+        // The driver's configs can't have pbuffers, so there are no placeholders to share.
+        Some(Error::RequiredExtensionUnavailable) => return,
+        other => panic!("Expected owned placeholders to run out, got {:?}", other),
+    }
+    assert_eq!(TrackedObject::Placeholder.live_count(), placeholders);
+
+    // Shared placeholders need only one pbuffer for all the contexts on this thread.
+    let shared_descriptor = device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::empty(),
+            placeholder_policy: PlaceholderPolicy::Shared,
+            ..Default::default()
+        })
+        .unwrap();
+    let mut shared_contexts = vec![];
+    for _ in 0..CONTEXT_COUNT {
+        let context = device.create_context(&shared_descriptor, None).unwrap();
+        device.make_context_current(&context).unwrap();
+        shared_contexts.push(context);
+    }
+    assert_eq!(TrackedObject::Placeholder.live_count(), placeholders + 1);
+
+    let attributes =
+        device.context_descriptor_attributes(&device.context_descriptor(&shared_contexts[0]));
+    assert_eq!(attributes.placeholder_policy, PlaceholderPolicy::Shared);
+
+    device.make_no_context_current().unwrap();
+    for mut context in shared_contexts {
+        device.destroy_context(&mut context).unwrap();
+    }
+    assert_eq!(TrackedObject::Placeholder.live_count(), placeholders);
+}
+
+// Tests that contexts sharing placeholder pbuffers can be current on two threads at once, each
+// with a pbuffer of its own, and that released pbuffers are reused.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos"))
+))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_shared_placeholders_on_two_threads() {
+    let mut device = match create_placeholder_test_device() {
+        Some(device) => device,
+        None => return,
     };
+    let placeholders = TrackedObject::Placeholder.live_count();
 
     let descriptor = device
         .create_context_descriptor(&ContextAttributes {
             version: GLVersion::new(3, 0),
             flags: ContextAttributeFlags::empty(),
+            placeholder_policy: PlaceholderPolicy::Shared,
+            ..Default::default()
         })
         .unwrap();
+    let mut main_context = device.create_context(&descriptor, None).unwrap();
+    let mut worker_context = device.create_context(&descriptor, None).unwrap();
+
+    // Devices and contexts aren't `Sync`, but the two threads use different contexts here.
+    struct Shared<'a>(&'a Device, &'a Context);
+    unsafe impl<'a> Send for Shared<'a> {}
+
+    let (current_sender, current_receiver) = mpsc::channel();
+    let (release_sender, release_receiver) = mpsc::channel::<()>();
+    let shared = Shared(&device, &worker_context);
+    thread::scope(|scope| {
+        let worker = scope.spawn(move || {
+            let shared = shared;
+            shared.0.make_context_current(shared.1).unwrap();
+            assert_eq!(
+                shared.0.context_current_thread(shared.1),
+                Some(thread::current().id())
+            );
+            current_sender.send(()).unwrap();
+            release_receiver.recv().unwrap();
+            shared.0.make_no_context_current().unwrap();
+        });
+        current_receiver.recv().unwrap();
+
+        // The worker's pbuffer is current there, so this thread gets a second one.
+        device.make_context_current(&main_context).unwrap();
+        assert_eq!(
+            device.context_current_thread(&main_context),
+            Some(thread::current().id())
+        );
+        assert_eq!(
+            device.context_current_thread(&worker_context),
+            Some(worker.thread().id())
+        );
+        assert_eq!(TrackedObject::Placeholder.live_count(), placeholders + 2);
 
-    let mut context = device.create_context(&descriptor, None).unwrap();
-    let context_id = device.context_id(&context);
+        release_sender.send(()).unwrap();
+        worker.join().unwrap();
+    });
 
-    let surfaces: Vec<_> = [
+    // Both pbuffers are free again, so switching contexts on this thread needs no more.
+    device.make_context_current(&worker_context).unwrap();
+    device.make_context_current(&main_context).unwrap();
+    assert_eq!(TrackedObject::Placeholder.live_count(), placeholders + 2);
+
+    device.make_no_context_current().unwrap();
+    device.destroy_context(&mut main_context).unwrap();
+    device.destroy_context(&mut worker_context).unwrap();
+    assert_eq!(TrackedObject::Placeholder.live_count(), placeholders);
+}
+
+// Tests that ICC profiles are validated and that well-known color spaces are recognized.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_color_profile_from_icc() {
+    // ICC v2 `desc` element.
+    let mut desc = b"desc\0\0\0\0".to_vec();
+    desc.extend_from_slice(&18u32.to_be_bytes());
+    desc.extend_from_slice(b"sRGB IEC61966-2.1\0");
+    let profile = ColorProfile::from_icc(make_icc_profile(&desc)).unwrap();
+    assert_eq!(profile.color_space(), Some(ColorSpace::Srgb));
+    assert!(profile.icc_data().is_some());
+
+    // ICC v4 `mluc` element.
+    let text: Vec<u8> = "Display P3"
+        .encode_utf16()
+        .flat_map(|unit| unit.to_be_bytes())
+        .collect();
+    let mut mluc = b"mluc\0\0\0\0".to_vec();
+    mluc.extend_from_slice(&1u32.to_be_bytes());
+    mluc.extend_from_slice(&12u32.to_be_bytes());
+    mluc.extend_from_slice(b"enUS");
+    mluc.extend_from_slice(&(text.len() as u32).to_be_bytes());
+    mluc.extend_from_slice(&28u32.to_be_bytes());
+    mluc.extend_from_slice(&text);
+    let profile = ColorProfile::from_icc(make_icc_profile(&mluc)).unwrap();
+    assert_eq!(profile.color_space(), Some(ColorSpace::DisplayP3));
+
+    // Unknown descriptions keep the data but have no color space.
+    let mut desc = b"desc\0\0\0\0".to_vec();
+    desc.extend_from_slice(&8u32.to_be_bytes());
+    desc.extend_from_slice(b"Monitor\0");
+    let profile = ColorProfile::from_icc(make_icc_profile(&desc)).unwrap();
+    assert_eq!(profile.color_space(), None);
+
+    // Malformed data is rejected.
+    let mut bad_signature = make_icc_profile(&desc);
+    bad_signature[36..40].copy_from_slice(b"nope");
+    assert_eq!(ColorProfile::from_icc(bad_signature), None);
+    assert_eq!(ColorProfile::from_icc(vec![0; 64]), None);
+
+    assert_eq!(
+        ColorProfile::AssumedSrgb.color_space(),
+        Some(ColorSpace::Srgb)
+    );
+    assert_eq!(ColorProfile::AssumedSrgb.icc_data(), None);
+}
+
+// Tests that color profile changes are noticed on the first check after they happen, and that
+// the profile is only queried every `PROFILE_CHECK_INTERVAL` presents.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_color_profile_tracker() {
+    let mut desc = b"desc\0\0\0\0".to_vec();
+    desc.extend_from_slice(&11u32.to_be_bytes());
+    desc.extend_from_slice(b"Display P3\0");
+    let display_p3 = ColorProfile::from_icc(make_icc_profile(&desc)).unwrap();
+
+    let mut tracker = ColorProfileTracker::default();
+    let mut queries = 0;
+    let mut current = ColorProfile::AssumedSrgb;
+    let mut changes = vec![];
+    for present in 0..(PROFILE_CHECK_INTERVAL * 3) {
+        if present == 5 {
+            current = display_p3.clone();
+        }
+        let change = tracker.update(|| {
+            queries += 1;
+            current.clone()
+        });
+        changes.extend(change.map(|profile| (present, profile)));
+    }
+
+    // The first check only records the profile, and the change is seen by the second.
+    assert_eq!(queries, 3);
+    assert_eq!(changes, vec![(PROFILE_CHECK_INTERVAL, display_p3)]);
+}
+
+// Tests that generic surfaces report the assumed sRGB profile.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_generic_surface_color_profile() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let mut surface = make_surface(&mut env.device, &env.context);
+    assert_eq!(
+        env.device.widget_color_profile(&surface),
+        ColorProfile::AssumedSrgb
+    );
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that only surfaces created with CPU access can have their data locked by the CPU.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_surface_access_validation() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    for &access in &[
         SurfaceAccess::GPUOnly,
         SurfaceAccess::GPUCPU,
         SurfaceAccess::GPUCPUWriteCombined,
-    ]
-    .iter()
-    .map(|&access| {
-        let surface = device
+    ] {
+        let mut surface = env
+            .device
             .create_surface(
-                &context,
+                &env.context,
                 access,
                 SurfaceType::Generic {
-                    size: Size2D::new(640, 480),
+                    size: Size2D::new(64, 64),
                 },
             )
             .unwrap();
-        let info = device.surface_info(&surface);
-        assert_eq!(info.size, Size2D::new(640, 480));
-        assert_eq!(info.context_id, context_id);
-        surface
-    })
-    .collect();
+        assert_eq!(env.device.surface_info(&surface).access, access);
+        assert!(format!("{:?}", surface).contains(&format!("{:?}", access)));
+
+        match env.device.lock_surface_data(&mut surface) {
+            Err(Error::InvalidSurfaceAccess { required, actual }) => {
+                assert_eq!(access, SurfaceAccess::GPUOnly);
+                assert_eq!(required, SurfaceAccess::GPUCPU);
+                assert_eq!(actual, SurfaceAccess::GPUOnly);
+            }
+            // Backends that can't map surfaces yet still report `Unimplemented`.
+            Ok(_) | Err(_) => assert_ne!(access, SurfaceAccess::GPUOnly),
+        }
+
+        env.device
+            .destroy_surface(&mut env.context, &mut surface)
+            .unwrap();
+    }
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that surfaces can be created as recommended for each offscreen intent, and that CPU access
+// is only recommended where the surface can then be mapped.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_recommended_surface_config() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let size = Size2D::new(64, 32);
+    for intent in [
+        SurfaceIntent::OffscreenRenderTarget,
+        SurfaceIntent::SharedWithOtherContext,
+        SurfaceIntent::CpuReadbackHeavy,
+        SurfaceIntent::VideoImport,
+    ] {
+        let shared = matches!(intent, SurfaceIntent::SharedWithOtherContext);
+        let config = env
+            .device
+            .recommended_surface_config(&env.context, intent, size)
+            .unwrap();
+        assert_eq!(
+            config.hints.fence_sync,
+            shared && env.capabilities.fence_sync
+        );
+        if !config.hints.map_for_cpu_access {
+            assert_eq!(config.access, SurfaceAccess::GPUOnly);
+        }
+
+        let mut surface = env
+            .device
+            .create_surface_with_options(
+                &env.context,
+                config.access,
+                config.surface_type,
+                config.options,
+            )
+            .unwrap();
+        let surface_info = env.device.surface_info(&surface);
+        assert_eq!(surface_info.size, size);
+        assert_eq!(surface_info.access, config.access);
+        if config.hints.map_for_cpu_access {
+            assert!(env.device.lock_surface_data(&mut surface).is_ok());
+        }
+        env.device
+            .destroy_surface(&mut env.context, &mut surface)
+            .unwrap();
+    }
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that presenting a surface with a context other than the one it was created with fails the
+// same way on every backend.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_present_surface_ownership() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let mut other_context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    let mut surface = make_surface(&mut env.device, &env.context);
+
+    match env.device.present_surface(&other_context, &mut surface) {
+        Err(Error::IncompatibleSurface) => {}
+        result => panic!("Presenting with the wrong context returned {:?}", result),
+    }
+    // Ownership is checked before the surface type, and the owning context needn't be current.
+    env.device.make_context_current(&other_context).unwrap();
+    match env.device.present_surface(&env.context, &mut surface) {
+        Err(Error::NoWidgetAttached) => {}
+        result => panic!("Presenting a generic surface returned {:?}", result),
+    }
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut other_context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that generic surfaces, which are never presented, ignore the present mode.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_generic_surface_ignores_present_mode() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    assert_eq!(
+        SurfaceOptions::default().present_mode,
+        PresentMode::DoubleBuffer
+    );
+
+    let options = SurfaceOptions {
+        present_mode: PresentMode::SingleBuffer,
+        ..SurfaceOptions::default()
+    };
+    let surface_type = SurfaceType::Generic {
+        size: Size2D::new(640, 480),
+    };
+    let mut surface = env
+        .device
+        .create_surface_with_options(&env.context, SurfaceAccess::GPUOnly, surface_type, options)
+        .unwrap();
+    match env.device.present_surface(&env.context, &mut surface) {
+        Err(Error::NoWidgetAttached) => {}
+        result => panic!("Presenting a generic surface returned {:?}", result),
+    }
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that generic surfaces report that they keep their contents, whether or not they were
+// asked to.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_generic_surface_preserves_contents() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    assert!(!SurfaceOptions::default().preserve_buffer);
+
+    for &preserve_buffer in &[false, true] {
+        let options = SurfaceOptions {
+            preserve_buffer,
+            ..SurfaceOptions::default()
+        };
+        let surface_type = SurfaceType::Generic {
+            size: Size2D::new(640, 480),
+        };
+        let mut surface = env
+            .device
+            .create_surface_with_options(
+                &env.context,
+                SurfaceAccess::GPUOnly,
+                surface_type,
+                options,
+            )
+            .unwrap();
+        assert!(env.device.surface_info(&surface).preserves_contents);
+        env.device
+            .destroy_surface(&mut env.context, &mut surface)
+            .unwrap();
+    }
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that generic surfaces report the texture target and mapped stride documented for
+// `SurfaceInfo`.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_surface_info_details() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let mut surface = env
+        .device
+        .create_surface(
+            &env.context,
+            SurfaceAccess::GPUCPU,
+            SurfaceType::Generic {
+                size: Size2D::new(640, 480),
+            },
+        )
+        .unwrap();
 
-    // Make sure all IDs for extant surfaces are distinct.
-    for (surface_index, surface) in surfaces.iter().enumerate() {
-        for (other_surface_index, other_surface) in surfaces.iter().enumerate() {
-            if surface_index != other_surface_index {
-                assert_ne!(
-                    device.surface_info(surface).id,
-                    device.surface_info(other_surface).id
-                );
-            }
-        }
+    let info = env.device.surface_info(&surface);
+    assert_eq!(info.texture_target, env.device.surface_gl_texture_target());
+    assert_ne!(info.format, gl::NONE);
+    if cfg!(target_os = "macos") {
+        assert!(info.stride.unwrap() >= 640 * 4);
+    } else {
+        assert_eq!(info.stride, None);
     }
 
-    for mut surface in surfaces.into_iter() {
-        device.destroy_surface(&mut context, &mut surface).unwrap();
+    // The surface's texture is bound on the target that the info reports.
+    let surface_texture = env
+        .device
+        .create_surface_texture(&mut env.context, surface)
+        .unwrap();
+    unsafe {
+        gl_utils::drain_pre_existing_gl_errors(&env.gl);
+        env.gl.BindTexture(
+            info.texture_target,
+            env.device.surface_texture_object(&surface_texture),
+        );
+        assert_eq!(env.gl.GetError(), gl::NO_ERROR);
+        env.gl.BindTexture(info.texture_target, 0);
     }
+    surface = env
+        .device
+        .destroy_surface_texture(&mut env.context, surface_texture)
+        .unwrap();
 
-    device.destroy_context(&mut context).unwrap();
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
 }
 
-// Tests that basic GL commands work.
+// Tests that generic surfaces of every format that the device can create report the native format
+// that their backend allocates them in, and that it maps back to the surface's format.
 #[cfg_attr(not(feature = "sm-test"), test)]
 #[serial]
-pub fn test_gl() {
+pub fn test_surface_native_format() {
     let mut env = match BasicEnvironment::new() {
         None => return,
         Some(env) => env,
     };
 
-    unsafe {
-        // Check basic clear.
-        env.gl.ClearColor(0.0, 1.0, 0.0, 1.0);
-        env.gl.Clear(gl::COLOR_BUFFER_BIT);
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
-
-        // Check that GL commands don't work after `make_no_context_current()`.
-        //
-        // The `glGetError()` calls are there to clear any errors.
-        env.device.make_no_context_current().unwrap();
-        env.gl.ClearColor(1.0, 0.0, 0.0, 1.0);
-        env.gl.GetError();
-        env.gl.Clear(gl::COLOR_BUFFER_BIT);
-        env.gl.GetError();
-        env.device.make_context_current(&env.context).unwrap();
+    for &(format, colorspace) in &[
+        (SurfaceFormat::Rgba8, ColorEncoding::Linear),
+        (SurfaceFormat::Rgba8, ColorEncoding::Srgb),
+        (SurfaceFormat::Rgba16F, ColorEncoding::Linear),
+        (SurfaceFormat::Rgb10A2, ColorEncoding::Linear),
+    ] {
+        let mut surface = match env.device.create_surface_with_options(
+            &env.context,
+            SurfaceAccess::GPUOnly,
+            SurfaceType::Generic {
+                size: Size2D::new(16, 16),
+            },
+            SurfaceOptions {
+                format,
+                colorspace,
+                ..SurfaceOptions::default()
+            },
+        ) {
+            Ok(surface) => surface,
+            Err(Error::UnsupportedSurfaceFormat) => continue,
+            Err(err) => panic!("Failed to create a {:?} surface: {:?}", format, err),
+        };
 
-        let framebuffer_object = env
-            .device
-            .context_surface_info(&env.context)
-            .unwrap()
-            .unwrap()
-            .framebuffer_object;
-        env.gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+        let info = env.device.surface_info(&surface);
+        let native_format = info.native_format;
+        if cfg!(target_os = "macos") {
+            assert_eq!(native_format.kind(), Some(NativeFormatKind::IoSurface));
+        } else if cfg!(target_os = "android") {
+            assert_eq!(native_format.kind(), Some(NativeFormatKind::DrmFourcc));
+        } else if cfg!(windows) {
+            // ANGLE's texture can't always be opened, and then the format isn't known.
+            assert_ne!(native_format.kind(), Some(NativeFormatKind::GlInternal));
+        } else {
+            assert_eq!(native_format, NativeFormat::GlInternal(info.format));
+        }
+        if native_format != NativeFormat::Unknown {
+            assert_eq!(native_format.surface_format(), Some(format));
+        }
 
-        // Make sure GL commands don't work when no surface is attached.
-        //
-        // The `glGetError()` calls are there to clear any errors.
-        let green_surface = env
-            .device
-            .unbind_surface_from_context(&mut env.context)
-            .unwrap()
-            .unwrap();
-        env.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
-        env.gl.ClearColor(1.0, 0.0, 0.0, 1.0);
-        env.gl.GetError();
-        env.gl.Clear(gl::COLOR_BUFFER_BIT);
-        env.gl.GetError();
         env.device
-            .bind_surface_to_context(&mut env.context, green_surface)
+            .destroy_surface(&mut env.context, &mut surface)
             .unwrap();
-        env.gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+    }
 
-        // Make sure GL commands go to the right surface.
-        let green_surface = env
-            .device
-            .unbind_surface_from_context(&mut env.context)
-            .unwrap()
-            .unwrap();
-        let red_surface = make_surface(&mut env.device, &env.context);
-        env.device
-            .bind_surface_to_context(&mut env.context, red_surface)
-            .unwrap();
-        let red_framebuffer_object = env
-            .device
-            .context_surface_info(&env.context)
-            .unwrap()
-            .unwrap()
-            .framebuffer_object;
-        env.gl
-            .BindFramebuffer(gl::FRAMEBUFFER, red_framebuffer_object);
-        env.gl.ClearColor(1.0, 0.0, 0.0, 1.0);
-        env.gl.Clear(gl::COLOR_BUFFER_BIT);
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+    env.device.destroy_context(&mut env.context).unwrap();
+}
 
-        let mut red_surface = env
-            .device
-            .unbind_surface_from_context(&mut env.context)
-            .unwrap()
-            .unwrap();
-        env.device
-            .bind_surface_to_context(&mut env.context, green_surface)
-            .unwrap();
-        env.gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+// Tests the table that maps native formats to and from surface formats, including formats that
+// aren't in it.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_native_format_table() {
+    let kinds = [
+        NativeFormatKind::DrmFourcc,
+        NativeFormatKind::Dxgi,
+        NativeFormatKind::IoSurface,
+        NativeFormatKind::GlInternal,
+    ];
+    let formats = [
+        SurfaceFormat::Rgba8,
+        SurfaceFormat::Rgba16F,
+        SurfaceFormat::Rgb10A2,
+    ];
+    for &format in &formats {
+        for &colorspace in &[ColorEncoding::Linear, ColorEncoding::Srgb] {
+            for &kind in &kinds {
+                let native_format = NativeFormat::from_surface_format(format, colorspace, kind);
+                // Only 8-bit surfaces can be sRGB-encoded.
+                if colorspace == ColorEncoding::Srgb && format != SurfaceFormat::Rgba8 {
+                    assert_eq!(native_format, NativeFormat::Unknown);
+                    continue;
+                }
+                assert_eq!(native_format.kind(), Some(kind));
+                assert_eq!(native_format.surface_format(), Some(format));
+            }
+        }
+    }
 
-        // Clean up.
-        env.device
-            .destroy_surface(&mut env.context, &mut red_surface)
-            .unwrap();
+    // The values that other components know these formats by.
+    let rgba8 =
+        |kind| NativeFormat::from_surface_format(SurfaceFormat::Rgba8, ColorEncoding::Linear, kind);
+    assert_eq!(
+        rgba8(NativeFormatKind::DrmFourcc),
+        NativeFormat::DrmFourcc(0x3432_4241, native_format::DRM_FORMAT_MOD_INVALID)
+    );
+    assert_eq!(rgba8(NativeFormatKind::Dxgi), NativeFormat::Dxgi(28));
+    assert_eq!(
+        rgba8(NativeFormatKind::IoSurface),
+        NativeFormat::IoSurface(0x4247_5241)
+    );
+    assert_eq!(
+        rgba8(NativeFormatKind::GlInternal),
+        NativeFormat::GlInternal(gl::RGBA8)
+    );
+    assert_eq!(
+        NativeFormat::from_surface_format(
+            SurfaceFormat::Rgba8,
+            ColorEncoding::Srgb,
+            NativeFormatKind::Dxgi
+        ),
+        NativeFormat::Dxgi(29)
+    );
+
+    // Backends map their allocated formats through the same table, and report widget surfaces,
+    // whose format is `GL_NONE`, as unknown.
+    for &kind in &kinds {
+        assert_eq!(
+            NativeFormat::of_gl_internal_format(gl::RGB10_A2, kind),
+            NativeFormat::from_surface_format(SurfaceFormat::Rgb10A2, ColorEncoding::Linear, kind)
+        );
+        assert_eq!(
+            NativeFormat::of_gl_internal_format(gl::NONE, kind),
+            NativeFormat::Unknown
+        );
     }
 
-    env.device.destroy_context(&mut env.context).unwrap();
+    // Low-memory formats have native formats, but no surface format.
+    let rgb565 = NativeFormat::of_gl_internal_format(GL_RGB565, NativeFormatKind::DrmFourcc);
+    assert_eq!(
+        rgb565,
+        NativeFormat::DrmFourcc(0x3631_4752, native_format::DRM_FORMAT_MOD_INVALID)
+    );
+    assert_eq!(rgb565.surface_format(), None);
+    assert_eq!(rgb565.gl_internal_format(), Some(GL_RGB565));
+    assert_eq!(
+        NativeFormat::of_gl_internal_format(gl::RGBA4, NativeFormatKind::Dxgi),
+        NativeFormat::Unknown
+    );
+
+    // BGRA from other components maps to 8-bit surfaces too.
+    assert_eq!(
+        NativeFormat::Dxgi(87).surface_format(),
+        Some(SurfaceFormat::Rgba8)
+    );
+    assert_eq!(
+        NativeFormat::DrmFourcc(0x3432_5241, native_format::DRM_FORMAT_MOD_LINEAR).surface_format(),
+        Some(SurfaceFormat::Rgba8)
+    );
+
+    // Formats that aren't in the table, and unknown ones, map to nothing.
+    for &native_format in &[
+        NativeFormat::DrmFourcc(0x3231_5659, native_format::DRM_FORMAT_MOD_LINEAR),
+        NativeFormat::Dxgi(0),
+        NativeFormat::IoSurface(0),
+        NativeFormat::GlInternal(gl::RGB8),
+        NativeFormat::Unknown,
+    ] {
+        assert_eq!(native_format.surface_format(), None);
+        assert_eq!(native_format.gl_internal_format(), None);
+    }
+    assert_eq!(NativeFormat::Unknown.kind(), None);
 }
 
+// Tests that presenting with damage checks the context and the surface type just like presenting
+// without it, even when there is no damage and the swap would be skipped.
 #[cfg_attr(not(feature = "sm-test"), test)]
 #[serial]
-pub fn test_surface_texture_blit_framebuffer() {
+pub fn test_present_surface_with_damage_misuse() {
     let mut env = match BasicEnvironment::new() {
         None => return,
         Some(env) => env,
     };
+    let mut other_context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    let mut surface = make_surface(&mut env.device, &env.context);
 
-    unsafe {
-        clear(&env.gl, &[0, 255, 0, 255]);
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
-
-        let green_surface = env
+    let damage = [Rect::new(Point2D::new(0, 0), Size2D::new(16, 16))];
+    for damage in &[&damage[..], &[]] {
+        match env
             .device
-            .unbind_surface_from_context(&mut env.context)
-            .unwrap()
-            .unwrap();
-        let green_surface_texture = env
+            .present_surface_with_damage(&other_context, &mut surface, damage)
+        {
+            Err(Error::IncompatibleSurface) => {}
+            result => panic!("Presenting with the wrong context returned {:?}", result),
+        }
+        match env
             .device
-            .create_surface_texture(&mut env.context, green_surface)
-            .unwrap();
+            .present_surface_with_damage(&env.context, &mut surface, damage)
+        {
+            Err(Error::NoWidgetAttached) => {}
+            result => panic!("Presenting a generic surface returned {:?}", result),
+        }
+    }
 
-        let main_surface = make_surface(&mut env.device, &env.context);
-        env.device
-            .bind_surface_to_context(&mut env.context, main_surface)
-            .unwrap();
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut other_context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
 
-        let main_framebuffer_object = env
-            .device
-            .context_surface_info(&env.context)
-            .unwrap()
-            .unwrap()
-            .framebuffer_object;
-        env.gl
-            .BindFramebuffer(gl::FRAMEBUFFER, main_framebuffer_object);
-        clear(&env.gl, &[255, 0, 0, 255]);
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+// Tests that damage rectangles are clipped to the frame being presented, and ignored if the widget
+// was resized behind surfman's back.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_clip_damage() {
+    let size = Size2D::new(64, 48);
+    let damage = [
+        Rect::new(Point2D::new(8, 8), Size2D::new(16, 16)),
+        Rect::new(Point2D::new(56, 40), Size2D::new(16, 16)),
+        Rect::new(Point2D::new(-8, 0), Size2D::new(16, 4)),
+        Rect::new(Point2D::new(64, 0), Size2D::new(16, 16)),
+        Rect::new(Point2D::new(0, 0), Size2D::new(0, 16)),
+    ];
+    assert_eq!(
+        clip_damage(&damage, size, size),
+        Some(vec![
+            Rect::new(Point2D::new(8, 8), Size2D::new(16, 16)),
+            Rect::new(Point2D::new(56, 40), Size2D::new(8, 8)),
+            Rect::new(Point2D::new(0, 0), Size2D::new(8, 4)),
+        ])
+    );
+    assert_eq!(clip_damage(&damage, size, Size2D::new(96, 72)), None);
+    assert_eq!(clip_damage(&damage, size, Size2D::new(32, 24)), None);
+}
 
-        let mut green_framebuffer_object = make_fbo(
-            &env.gl,
-            env.device.surface_gl_texture_target(),
-            env.device.surface_texture_object(&green_surface_texture),
-        );
+// Tests that EGL swap intervals degrade to vsync without adaptive vsync, and are refused rather
+// than clamped when the config doesn't support them.
+#[cfg(any(android_backend, angle, generic_egl_platform, ohos_backend))]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_applied_swap_interval() {
+    assert_eq!(applied_swap_interval(1, 0, 1, false).unwrap(), 1);
+    assert_eq!(applied_swap_interval(0, 0, 1, false).unwrap(), 0);
+    assert_eq!(applied_swap_interval(-1, 0, 1, false).unwrap(), 1);
+    assert_eq!(applied_swap_interval(-1, -1, 1, true).unwrap(), -1);
+    assert_eq!(
+        applied_swap_interval(i32::MIN, 0, i32::MAX, false).unwrap(),
+        i32::MAX
+    );
+    assert!(matches!(
+        applied_swap_interval(2, 0, 1, false),
+        Err(Error::UnsupportedOnThisPlatform)
+    ));
+    assert!(matches!(
+        applied_swap_interval(-2, 0, 1, false),
+        Err(Error::UnsupportedOnThisPlatform)
+    ));
+    assert!(matches!(
+        applied_swap_interval(-2, -1, 1, true),
+        Err(Error::UnsupportedOnThisPlatform)
+    ));
+    assert!(matches!(
+        applied_swap_interval(0, 1, 1, false),
+        Err(Error::UnsupportedOnThisPlatform)
+    ));
+}
 
-        // Blit to main framebuffer.
-        blit_fbo(&env.gl, main_framebuffer_object, green_framebuffer_object);
-        env.gl
-            .BindFramebuffer(gl::FRAMEBUFFER, main_framebuffer_object);
-        check_gl(&env.gl);
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+// Tests that binding a surface to a context that can't take it names the context that is in the
+// way, and hands the surface back.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_bind_surface_misuse() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let mut other_context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    let other_context_id = env.device.context_id(&other_context);
+    let surface = make_surface(&mut env.device, &env.context);
+    assert_eq!(surface.context_id(), env.device.context_id(&env.context));
+
+    // The surface belongs to the environment's context.
+    let surface = match env
+        .device
+        .bind_surface_to_context(&mut other_context, surface)
+    {
+        Err((Error::SurfaceAlreadyBound { context_id }, surface)) => {
+            assert_eq!(context_id, surface.context_id());
+            surface
+        }
+        result => panic!("Binding to the wrong context returned {:?}", result.err()),
+    };
+    assert!(env
+        .device
+        .context_surface_info(&other_context)
+        .unwrap()
+        .is_none());
+
+    // The environment's context already has a surface bound.
+    let mut surface = match env
+        .device
+        .bind_surface_to_context(&mut env.context, surface)
+    {
+        Err((Error::SurfaceAlreadyBound { context_id }, surface)) => {
+            assert_eq!(context_id, env.device.context_id(&env.context));
+            surface
+        }
+        result => panic!("Binding a second surface returned {:?}", result.err()),
+    };
 
-        // Clean up.
-        env.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
-        check_gl(&env.gl);
-        env.gl.DeleteFramebuffers(1, &mut green_framebuffer_object);
+    // A surface of the other context binds to it.
+    let other_surface = make_surface(&mut env.device, &other_context);
+    assert_eq!(other_surface.context_id(), other_context_id);
+    env.device
+        .bind_surface_to_context(&mut other_context, other_surface)
+        .unwrap();
 
-        let mut green_surface = env
-            .device
-            .destroy_surface_texture(&mut env.context, green_surface_texture)
-            .unwrap();
-        env.device
-            .destroy_surface(&mut env.context, &mut green_surface)
-            .unwrap();
-        env.device.destroy_context(&mut env.context).unwrap();
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut other_context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that presentation history is empty for generic surfaces and bounded for widget surfaces.
+#[cfg_attr(not(feature = "sm-test"), test)]
+#[serial]
+pub fn test_presentation_timestamps() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    let mut surface = make_surface(&mut env.device, &env.context);
+
+    // Generic surfaces are never presented, so they have no history, even after trying.
+    assert!(env.device.presentation_timestamps(&surface).is_empty());
+    drop(env.device.present_surface(&env.context, &mut surface));
+    assert!(env.device.presentation_timestamps(&surface).is_empty());
+
+    // Widget surfaces keep only their most recent presentations.
+    let mut history = PresentHistory::default();
+    let start = Instant::now();
+    for _ in 0..70 {
+        history.record(Instant::now(), Some(Instant::now()), None);
+    }
+    let records = history.records();
+    assert_eq!(records.len(), 64);
+    for (record, frame_id) in records.iter().zip(6..) {
+        assert_eq!(record.frame_id, frame_id);
+        assert!(record.submit >= start);
+        assert!(record.compositor_ack.unwrap() >= record.submit);
+        assert_eq!(record.displayed, None);
+        assert!(!record.resized_externally);
     }
+
+    // An external resize is recorded against the frame that revealed it.
+    history.mark_resized_externally();
+    let resized: Vec<_> = history
+        .records()
+        .iter()
+        .map(|record| record.resized_externally)
+        .collect();
+    assert_eq!(resized.iter().filter(|&&resized| resized).count(), 1);
+    assert_eq!(resized.last(), Some(&true));
+
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
 }
 
+// Tests that generic surfaces have no presentation times, and that widget surfaces report the
+// latest frame known to have been shown, or else the latest frame, and fill in display times from
+// the same reports.
 #[cfg_attr(not(feature = "sm-test"), test)]
 #[serial]
-pub fn test_cross_device_surface_texture_blit_framebuffer() {
+pub fn test_query_presentation_time() {
     let mut env = match BasicEnvironment::new() {
         None => return,
         Some(env) => env,
     };
+    let mut surface = make_surface(&mut env.device, &env.context);
+    match env.device.query_presentation_time(&surface) {
+        Err(Error::NoWidgetAttached) => {}
+        result => panic!("expected NoWidgetAttached, got {:?}", result),
+    }
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
 
-    let mut other_device = env.connection.create_device(&env.adapter).unwrap();
+    let mut history = PresentHistory::default();
+    let no_update = |_: &PresentRecord, _: &mut PresentationStatistics| {};
+    match presentation_time::latest_statistics(&history, no_update) {
+        Err(Error::NotPresented) => {}
+        result => panic!("expected NotPresented, got {:?}", result),
+    }
 
-    unsafe {
-        clear(&env.gl, &[255, 0, 0, 255]);
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+    let start = Instant::now();
+    for frame in 0..3 {
+        history.record(start + Duration::from_millis(frame * 16), None, None);
+    }
+    // Without reports, the latest frame is returned, requested when it was submitted.
+    let statistics = presentation_time::latest_statistics(&history, no_update).unwrap();
+    assert_eq!(statistics.frame_id, 2);
+    assert_eq!(
+        statistics.requested_present,
+        Some(start + Duration::from_millis(32))
+    );
+    assert_eq!(statistics.actual_present, None);
+    assert_eq!(statistics.gpu_finish, None);
+
+    // Once a frame has been shown, it's returned over later frames that haven't been.
+    let shown = start + Duration::from_millis(40);
+    let statistics = presentation_time::latest_statistics(&history, |record, statistics| {
+        if record.frame_id == 1 {
+            statistics.actual_present = Some(shown);
+        }
+    })
+    .unwrap();
+    assert_eq!(statistics.frame_id, 1);
+    assert_eq!(
+        statistics.requested_present,
+        Some(start + Duration::from_millis(16))
+    );
+    assert_eq!(statistics.actual_present, Some(shown));
+
+    // The same reports fill in when each frame was displayed in its timestamps.
+    let records = presentation_time::records_with_display_times(&history, |record, statistics| {
+        if record.frame_id == 1 {
+            statistics.actual_present = Some(shown);
+        }
+    });
+    let displayed: Vec<_> = records.iter().map(|record| record.displayed).collect();
+    assert_eq!(displayed, [None, Some(shown), None]);
+}
 
-        let mut other_context = other_device
-            .create_context(&env.context_descriptor, None)
-            .unwrap();
-        let other_surface = make_surface(&mut other_device, &other_context);
-        other_device
-            .bind_surface_to_context(&mut other_context, other_surface)
-            .unwrap();
-        other_device.make_context_current(&other_context).unwrap();
-        bind_context_fbo(&env.gl, &other_device, &other_context);
+// Tests converting times on other clocks to `Instant`s, and finding the vblank that showed a
+// frame.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_presentation_time_clocks() {
+    let now = Instant::now() + Duration::from_secs(10);
+    assert_eq!(
+        presentation_time::instant_at(now, 5_000, 3_000, 1_000),
+        Some(now - Duration::from_secs(2))
+    );
+    assert_eq!(
+        presentation_time::instant_at(now, 5_000, 5_500, 1_000),
+        Some(now + Duration::from_millis(500))
+    );
+    assert_eq!(presentation_time::instant_at(now, 5_000, 3_000, 0), None);
+    #[cfg(unix)]
+    {
+        let earlier = Instant::now();
+        let mut monotonic = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut monotonic) };
+        let nanos = monotonic.tv_sec as u64 * 1_000_000_000 + monotonic.tv_nsec as u64;
+        let instant = presentation_time::instant_from_clock(libc::CLOCK_MONOTONIC, nanos).unwrap();
+        assert!(instant >= earlier - Duration::from_millis(100));
+        assert!(instant <= Instant::now() + Duration::from_millis(100));
+    }
 
-        clear(&env.gl, &[0, 255, 0, 255]);
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+    // With a vblank every 16ms, the last at `now`, a frame swapped 20ms ago was shown 16ms ago.
+    let refresh_period = Duration::from_millis(16);
+    assert_eq!(
+        presentation_time::first_vblank_after(now - Duration::from_millis(20), now, refresh_period),
+        Some(now - refresh_period)
+    );
+    // One swapped at a vblank was shown at it.
+    assert_eq!(
+        presentation_time::first_vblank_after(now - refresh_period, now, refresh_period),
+        Some(now - refresh_period)
+    );
+    // One swapped since the last vblank hasn't been shown yet.
+    assert_eq!(
+        presentation_time::first_vblank_after(now + Duration::from_millis(1), now, refresh_period),
+        None
+    );
+}
 
-        let green_surface = other_device
-            .unbind_surface_from_context(&mut other_context)
-            .unwrap()
-            .unwrap();
-        let green_surface_texture = env
-            .device
-            .create_surface_texture(&mut env.context, green_surface)
-            .unwrap();
+// Presents `frames` frames that each take `frame_time` to render on a 60 Hz display, and lets
+// `auto_vsync` look at each, with the window system simulated. Returns the swap intervals that it
+// set.
+fn present_auto_vsync_frames(
+    auto_vsync: &mut AutoVsync,
+    history: &mut PresentHistory,
+    clock: &mut Instant,
+    frame_time: Duration,
+    frames: usize,
+) -> Vec<i32> {
+    let refresh_interval = Duration::from_micros(16_667);
+    let mut intervals = vec![];
+    for _ in 0..frames {
+        // With vsync, presenting blocks until the next vertical blank.
+        let blocked = match auto_vsync.stats().decision {
+            VsyncDecision::Vsync => {
+                let into_refresh = frame_time.as_micros() % refresh_interval.as_micros();
+                refresh_interval - Duration::from_micros(into_refresh as u64)
+            }
+            VsyncDecision::Immediate => Duration::from_micros(500),
+        };
+        let submit = *clock;
+        history.record(submit, Some(submit + blocked), None);
+        auto_vsync.update(history, |interval| {
+            intervals.push(interval);
+            Ok(interval)
+        });
+        *clock = submit + blocked + frame_time;
+    }
+    intervals
+}
 
-        env.device.make_context_current(&env.context).unwrap();
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+// Tests that automatic vsync turns vsync off once frames render well within the refresh
+// interval, and back on once they take longer, without switching while they're in between.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_auto_vsync_switches_with_hysteresis() {
+    let policy = AutoVsyncPolicy {
+        target_margin_ms: 2.0,
+    };
+    let mut auto_vsync = AutoVsync::new(policy);
+    let mut history = PresentHistory::default();
+    let mut clock = Instant::now();
+    let mut present = |auto_vsync: &mut AutoVsync, frame_time_ms, frames| {
+        let frame_time = Duration::from_millis(frame_time_ms);
+        present_auto_vsync_frames(auto_vsync, &mut history, &mut clock, frame_time, frames)
+    };
 
-        let mut green_framebuffer_object = make_fbo(
-            &env.gl,
-            env.device.surface_gl_texture_target(),
-            env.device.surface_texture_object(&green_surface_texture),
-        );
+    // Nothing is decided until the refresh interval has been measured.
+    let stats = auto_vsync.stats();
+    assert_eq!(stats.decision, VsyncDecision::Vsync);
+    assert_eq!(stats.refresh_interval, None);
+    assert_eq!(stats.frame_time, None);
+
+    // Frames that take 5 ms are sped up, but only after a run of them.
+    assert!(present(&mut auto_vsync, 5, 10).is_empty());
+    assert_eq!(present(&mut auto_vsync, 5, 30), vec![0]);
+    let stats = auto_vsync.stats();
+    assert_eq!(stats.decision, VsyncDecision::Immediate);
+    assert_eq!(stats.switches, 1);
+    assert_eq!(stats.refresh_interval, Some(Duration::from_micros(16_667)));
+    assert!(stats.frame_time.unwrap() < Duration::from_millis(6));
+
+    // Frames within the margin of the refresh interval keep the decision either way.
+    assert!(present(&mut auto_vsync, 15, 100).is_empty());
+    assert_eq!(auto_vsync.stats().decision, VsyncDecision::Immediate);
+
+    // Frames that take longer than the refresh interval turn vsync back on.
+    assert_eq!(present(&mut auto_vsync, 20, 60), vec![1]);
+    let stats = auto_vsync.stats();
+    assert_eq!(stats.decision, VsyncDecision::Vsync);
+    assert_eq!(stats.switches, 2);
+    assert!(present(&mut auto_vsync, 20, 100).is_empty());
+    assert!(present(&mut auto_vsync, 15, 100).is_empty());
+    assert_eq!(auto_vsync.stats().switches, 2);
+
+    // A single fast frame doesn't count as a run.
+    for _ in 0..10 {
+        assert!(present(&mut auto_vsync, 20, 10).is_empty());
+        assert!(present(&mut auto_vsync, 5, 1).is_empty());
+    }
+    assert_eq!(auto_vsync.stats().decision, VsyncDecision::Vsync);
+}
 
-        // Blit to main framebuffer.
-        blit_fbo(
-            &env.gl,
-            context_fbo(&env.device, &env.context),
-            green_framebuffer_object,
-        );
-        bind_context_fbo(&env.gl, &env.device, &env.context);
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+// Tests that automatic vsync leaves a surface alone once the platform refuses a swap interval.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_auto_vsync_refused_interval() {
+    let mut auto_vsync = AutoVsync::new(AutoVsyncPolicy::default());
+    let mut history = PresentHistory::default();
+    let mut clock = Instant::now();
+    let mut attempts = 0;
+    for _ in 0..200 {
+        history.record(clock, Some(clock + Duration::from_millis(12)), None);
+        auto_vsync.update(&history, |_| {
+            attempts += 1;
+            Err(Error::UnsupportedOnThisPlatform)
+        });
+        clock += Duration::from_micros(16_667);
+    }
+    assert_eq!(attempts, 1);
+    let stats = auto_vsync.stats();
+    assert_eq!(stats.decision, VsyncDecision::Vsync);
+    assert_eq!(stats.switches, 0);
+}
 
-        // Clean up.
-        env.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
-        check_gl(&env.gl);
-        env.gl.DeleteFramebuffers(1, &mut green_framebuffer_object);
+// Tests that a change of scale is reported once, suggesting the window's new size if it has one
+// already and the surface's size scaled by the change otherwise.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_scale_tracker_reports_changes() {
+    let (small, large) = (Size2D::new(800, 600), Size2D::new(1600, 1200));
+    let mut tracker = ScaleTracker::new(1.0, false);
+    assert_eq!(tracker.update(1.0, small, Some(small)), (None, None));
+
+    let change = SurfaceScaleChanged {
+        new_scale: 2.0,
+        suggested_size: large,
+    };
+    assert_eq!(
+        tracker.update(2.0, small, Some(small)),
+        (Some(change), None)
+    );
+    assert_eq!(tracker.update(2.0, small, Some(small)), (None, None));
+
+    let change = SurfaceScaleChanged {
+        new_scale: 1.25,
+        suggested_size: Size2D::new(1000, 750),
+    };
+    assert_eq!(
+        tracker.update(1.25, large, Some(Size2D::new(1000, 750))),
+        (Some(change), None)
+    );
+    assert_eq!(tracker.update(0.0, large, None), (None, None));
+}
 
-        let mut green_surface = env
-            .device
-            .destroy_surface_texture(&mut env.context, green_surface_texture)
-            .unwrap();
-        other_device
-            .destroy_surface(&mut other_context, &mut green_surface)
-            .unwrap();
-        other_device.destroy_context(&mut other_context).unwrap();
-        env.device.destroy_context(&mut env.context).unwrap();
+// Tests that automatically rescaled surfaces are resized to their window's new size once it has
+// one, but not if the application has resized them first, nor long after the change.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_scale_tracker_auto_rescale() {
+    let (small, large) = (Size2D::new(800, 600), Size2D::new(1600, 1200));
+
+    let mut tracker = ScaleTracker::new(1.0, true);
+    let (change, resize_to) = tracker.update(2.0, small, Some(small));
+    assert!(change.is_some());
+    assert_eq!(resize_to, None);
+    assert_eq!(tracker.update(2.0, small, Some(large)), (None, Some(large)));
+    assert_eq!(tracker.update(2.0, large, Some(large)), (None, None));
+
+    // The application resizes the surface itself, which takes the place of the automatic resize.
+    let mut tracker = ScaleTracker::new(1.0, true);
+    tracker.update(2.0, small, Some(small));
+    tracker.resized();
+    assert_eq!(tracker.update(2.0, small, Some(large)), (None, None));
+
+    // The window only takes on a new size long after the change.
+    let mut tracker = ScaleTracker::new(1.0, true);
+    for _ in 0..100 {
+        assert_eq!(tracker.update(2.0, small, Some(small)).1, None);
     }
+    assert_eq!(tracker.update(2.0, small, Some(large)), (None, None));
+
+    // Surfaces that aren't automatically rescaled are left to the application.
+    let mut tracker = ScaleTracker::new(1.0, false);
+    let (_, resize_to) = tracker.update(2.0, small, Some(large));
+    assert_eq!(resize_to, None);
 }
 
+// Tests that automatic vsync can only be enabled on widget surfaces.
 #[cfg_attr(not(feature = "sm-test"), test)]
 #[serial]
-pub fn test_cross_thread_surface_texture_blit_framebuffer() {
+pub fn test_auto_swap_interval_generic_surface() {
     let mut env = match BasicEnvironment::new() {
         None => return,
         Some(env) => env,
     };
 
-    let (to_main_sender, to_main_receiver) = mpsc::channel();
-    let (to_worker_sender, to_worker_receiver) = mpsc::channel();
-
-    let other_connection = env.connection.clone();
-    let other_adapter = env.adapter.clone();
-    let other_context_descriptor = env.context_descriptor.clone();
-    thread::spawn(move || {
-        let mut device = other_connection.create_device(&other_adapter).unwrap();
-        let mut context = device
-            .create_context(&other_context_descriptor, None)
-            .unwrap();
-        let gl = Gl::load_with(|symbol| device.get_proc_address(&context, symbol));
-
-        let surface = make_surface(&mut device, &context);
-        device
-            .bind_surface_to_context(&mut context, surface)
-            .unwrap();
-        device.make_context_current(&context).unwrap();
-        bind_context_fbo(&gl, &device, &context);
-
-        clear(&gl, &[0, 255, 0, 255]);
-        assert_eq!(get_pixel_from_bottom_row(&gl), [0, 255, 0, 255]);
+    let mut surface = make_surface(&mut env.device, &env.context);
+    let policy = Some(AutoVsyncPolicy::default());
+    match env
+        .device
+        .set_auto_swap_interval(&env.context, &mut surface, policy)
+    {
+        Err(Error::NoWidgetAttached) => {}
+        result => panic!("Expected `NoWidgetAttached`, got {:?}", result),
+    }
+    assert_eq!(env.device.auto_swap_interval_stats(&surface), None);
 
-        let surface = device
-            .unbind_surface_from_context(&mut context)
-            .unwrap()
-            .unwrap();
-        to_main_sender.send(surface).unwrap();
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
 
-        let mut surface = to_worker_receiver.recv().unwrap();
-        device.destroy_surface(&mut context, &mut surface).unwrap();
-        device.destroy_context(&mut context).unwrap();
-    });
+// Tests config selection against the visuals offered by 24-, 30- and 32-bit X servers.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_choose_config_for_visual_depths() {
+    let candidate = |red_size, alpha_size, visual_depth| ConfigCandidate {
+        red_size,
+        green_size: red_size,
+        blue_size: red_size,
+        alpha_size,
+        depth_size: 0,
+        stencil_size: 0,
+        samples: 0,
+        visual_depth,
+        float: false,
+    };
+    let attributes = |flags, color_depth| ContextAttributes {
+        version: GLVersion::new(3, 0),
+        flags,
+        color_depth,
+        ..Default::default()
+    };
+    let (opaque, alpha) = (ContextAttributeFlags::empty(), ContextAttributeFlags::ALPHA);
+
+    // A `DefaultDepth 24` server.
+    let configs = [
+        candidate(8, 8, Some(32)),
+        candidate(8, 0, Some(24)),
+        candidate(8, 8, Some(24)),
+    ];
+    let choose = |flags, color_depth| {
+        context::choose_config(&configs, &attributes(flags, color_depth), Some(24))
+    };
+    assert_eq!(choose(opaque, ColorDepth::Auto), Some(1));
+    assert_eq!(choose(alpha, ColorDepth::Auto), Some(0));
+    assert_eq!(choose(opaque, ColorDepth::Deep), Some(1));
+
+    // A `DefaultDepth 30` server, whose only 8-bit visual is the ARGB one used for translucency.
+    let configs = [
+        candidate(10, 0, Some(30)),
+        candidate(10, 2, Some(30)),
+        candidate(8, 8, Some(32)),
+    ];
+    let choose = |flags, color_depth| {
+        context::choose_config(&configs, &attributes(flags, color_depth), Some(30))
+    };
+    assert_eq!(choose(opaque, ColorDepth::Auto), Some(0));
+    assert_eq!(choose(opaque, ColorDepth::Standard), Some(2));
+    assert_eq!(choose(opaque, ColorDepth::Deep), Some(0));
+    assert_eq!(choose(alpha, ColorDepth::Auto), Some(2));
+    assert_eq!(choose(alpha, ColorDepth::Deep), Some(1));
+
+    // A `DefaultDepth 32` server.
+    let configs = [candidate(8, 0, Some(24)), candidate(8, 8, Some(32))];
+    let choose = |flags, color_depth| {
+        context::choose_config(&configs, &attributes(flags, color_depth), Some(32))
+    };
+    assert_eq!(choose(opaque, ColorDepth::Auto), Some(1));
+    assert_eq!(choose(opaque, ColorDepth::Standard), Some(1));
+
+    // Without windows, visuals don't matter and 8 bits per channel is preferred.
+    let configs = [candidate(10, 0, None), candidate(8, 0, None)];
+    let choose =
+        |color_depth| context::choose_config(&configs, &attributes(opaque, color_depth), None);
+    assert_eq!(choose(ColorDepth::Auto), Some(1));
+    assert_eq!(choose(ColorDepth::Deep), Some(0));
+
+    // Formats that aren't 8 or 10 bits per channel are never chosen.
+    let configs = [ConfigCandidate {
+        red_size: 5,
+        green_size: 6,
+        blue_size: 5,
+        alpha_size: 0,
+        depth_size: 0,
+        stencil_size: 0,
+        samples: 0,
+        visual_depth: Some(16),
+        float: false,
+    }];
+    assert_eq!(
+        context::choose_config(&configs, &attributes(opaque, ColorDepth::Auto), Some(16)),
+        None
+    );
+}
 
-    unsafe {
-        clear(&env.gl, &[255, 0, 0, 255]);
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+// Tests that widget surfaces pick a config with the requested opacity that the context can render
+// through, and that matches the depth of the window's visual where that matters.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_choose_widget_config_for_opacity() {
+    let candidate = |alpha_size, depth_size, visual_depth| ConfigCandidate {
+        red_size: 8,
+        green_size: 8,
+        blue_size: 8,
+        alpha_size,
+        depth_size,
+        stencil_size: 0,
+        samples: 0,
+        visual_depth,
+        float: false,
+    };
+    let context_config = candidate(8, 24, Some(32));
+    let configs = [
+        candidate(8, 24, Some(32)),
+        candidate(0, 0, Some(24)),
+        candidate(0, 24, Some(24)),
+    ];
+    let choose = |alpha_size, window_depth| {
+        context::choose_widget_config(&context_config, &configs, alpha_size, window_depth)
+    };
 
-        let green_surface = to_main_receiver.recv().unwrap();
-        let green_surface_texture = env
-            .device
-            .create_surface_texture(&mut env.context, green_surface)
-            .unwrap();
+    // Without a preference, the context's alpha wins unless the window's visual rules it out.
+    assert_eq!(choose(None, None), Some(0));
+    assert_eq!(choose(None, Some(24)), Some(2));
 
-        env.device.make_context_current(&env.context).unwrap();
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+    // Opaque widgets need a config without alpha, but with the context's depth buffer.
+    assert_eq!(choose(Some(0), None), Some(2));
+    assert_eq!(choose(Some(8), None), Some(0));
 
-        let mut green_framebuffer_object = make_fbo(
-            &env.gl,
-            env.device.surface_gl_texture_target(),
-            env.device.surface_texture_object(&green_surface_texture),
-        );
+    // A translucent widget can't be shown in a window without an ARGB visual, nor an opaque one
+    // in a window with one.
+    assert_eq!(choose(Some(8), Some(24)), None);
+    assert_eq!(choose(Some(0), Some(32)), None);
+}
 
-        // Blit to main framebuffer.
-        blit_fbo(
-            &env.gl,
-            context_fbo(&env.device, &env.context),
-            green_framebuffer_object,
-        );
-        bind_context_fbo(&env.gl, &env.device, &env.context);
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+// Tests that config selection picks the smallest depth and stencil buffers that are large enough.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_choose_config_for_depth_and_stencil_sizes() {
+    let candidate = |depth_size, stencil_size| ConfigCandidate {
+        red_size: 8,
+        green_size: 8,
+        blue_size: 8,
+        alpha_size: 0,
+        depth_size,
+        stencil_size,
+        samples: 0,
+        visual_depth: None,
+        float: false,
+    };
+    let attributes = |flags, depth_size, stencil_size| ContextAttributes {
+        version: GLVersion::new(3, 0),
+        flags,
+        depth_size,
+        stencil_size,
+        ..Default::default()
+    };
+    let (none, depth) = (ContextAttributeFlags::empty(), ContextAttributeFlags::DEPTH);
+
+    let configs = [
+        candidate(32, 0),
+        candidate(24, 8),
+        candidate(0, 0),
+        candidate(16, 0),
+        candidate(24, 0),
+    ];
+    let choose = |flags, depth_size, stencil_size| {
+        context::choose_config(&configs, &attributes(flags, depth_size, stencil_size), None)
+    };
+    assert_eq!(choose(none, None, None), Some(2));
+    assert_eq!(choose(depth, None, None), Some(4));
+    assert_eq!(choose(depth, Some(0), None), Some(2));
+    assert_eq!(choose(none, Some(16), None), Some(3));
+    assert_eq!(choose(none, Some(20), None), Some(4));
+    assert_eq!(choose(none, Some(32), None), Some(0));
+    assert_eq!(choose(none, None, Some(8)), Some(1));
+    assert_eq!(choose(none, Some(32), Some(8)), None);
+}
 
-        // Clean up.
-        env.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
-        check_gl(&env.gl);
-        env.gl.DeleteFramebuffers(1, &mut green_framebuffer_object);
+// Tests that floating-point configs are chosen only when asked for, and that asking for them
+// falls back to fixed-point configs when there are none.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_choose_config_for_float_color() {
+    let candidate = |channel_size, float| ConfigCandidate {
+        red_size: channel_size,
+        green_size: channel_size,
+        blue_size: channel_size,
+        alpha_size: channel_size,
+        depth_size: 0,
+        stencil_size: 0,
+        samples: 0,
+        visual_depth: None,
+        float,
+    };
+    let attributes = |color_depth| ContextAttributes {
+        version: GLVersion::new(3, 0),
+        flags: ContextAttributeFlags::ALPHA,
+        color_depth,
+        ..Default::default()
+    };
 
-        let green_surface = env
-            .device
-            .destroy_surface_texture(&mut env.context, green_surface_texture)
-            .unwrap();
-        to_worker_sender.send(green_surface).unwrap();
+    let configs = [candidate(16, true), candidate(8, false)];
+    let choose = |color_depth| context::choose_config(&configs, &attributes(color_depth), None);
+    assert_eq!(choose(ColorDepth::Auto), Some(1));
+    assert_eq!(choose(ColorDepth::Deep), Some(1));
+    assert_eq!(choose(ColorDepth::Float), Some(0));
+
+    let configs = [candidate(8, false)];
+    assert_eq!(
+        context::choose_config(&configs, &attributes(ColorDepth::Float), None),
+        Some(0)
+    );
+}
 
-        env.device.destroy_context(&mut env.context).unwrap();
-    }
+// Tests that config selection falls back to lower sample counts unless exact ones are required.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_choose_config_for_samples() {
+    let candidate = |samples| ConfigCandidate {
+        red_size: 8,
+        green_size: 8,
+        blue_size: 8,
+        alpha_size: 0,
+        depth_size: 0,
+        stencil_size: 0,
+        samples,
+        visual_depth: None,
+        float: false,
+    };
+    let attributes = |flags, samples| ContextAttributes {
+        version: GLVersion::new(3, 0),
+        flags,
+        samples,
+        ..Default::default()
+    };
+    let (lenient, exact) = (
+        ContextAttributeFlags::empty(),
+        ContextAttributeFlags::EXACT_SAMPLES,
+    );
+
+    let configs = [candidate(4), candidate(0), candidate(2), candidate(1)];
+    let choose =
+        |flags, samples| context::choose_config(&configs, &attributes(flags, samples), None);
+    assert_eq!(choose(lenient, 0), Some(1));
+    assert_eq!(choose(lenient, 1), Some(1));
+    assert_eq!(choose(lenient, 4), Some(0));
+    assert_eq!(choose(lenient, 3), Some(2));
+    assert_eq!(choose(lenient, 16), Some(0));
+    assert_eq!(choose(exact, 3), None);
+    assert_eq!(choose(exact, 2), Some(2));
+
+    assert_eq!(context::sample_counts_to_try(&attributes(lenient, 1)), [0]);
+    assert_eq!(
+        context::sample_counts_to_try(&attributes(lenient, 4)),
+        [4, 3, 2, 0]
+    );
+    assert_eq!(context::sample_counts_to_try(&attributes(exact, 4)), [4]);
 }
 
-// Tests that surface textures are not upside-down.
+// Tests that debug views read back the contents of a generic surface, leaving the caller's
+// framebuffer bindings alone, and refuse surfaces that they can't read.
+#[cfg(feature = "sm-debug-view")]
 #[cfg_attr(not(feature = "sm-test"), test)]
 #[serial]
-pub fn test_surface_texture_right_side_up() {
+pub fn test_debug_view_snapshot() {
     let mut env = match BasicEnvironment::new() {
         None => return,
         Some(env) => env,
     };
 
+    let mut context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    let surface = make_surface(&mut env.device, &context);
+    env.device
+        .bind_surface_to_context(&mut context, surface)
+        .unwrap();
+    env.device.make_context_current(&context).unwrap();
+    bind_context_fbo(&env.gl, &env.device, &context);
     unsafe {
-        clear(&env.gl, &[255, 0, 0, 255]);
-        clear_bottom_row(&env.gl, &[0, 255, 0, 255]);
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
-
-        let subsurface = env
-            .device
-            .unbind_surface_from_context(&mut env.context)
-            .unwrap()
-            .unwrap();
-        let subsurface_texture = env
-            .device
-            .create_surface_texture(&mut env.context, subsurface)
-            .unwrap();
-
-        let main_surface = make_surface(&mut env.device, &env.context);
-        env.device
-            .bind_surface_to_context(&mut env.context, main_surface)
-            .unwrap();
-
-        let main_framebuffer_object = env
-            .device
-            .context_surface_info(&env.context)
-            .unwrap()
-            .unwrap()
-            .framebuffer_object;
-        env.gl
-            .BindFramebuffer(gl::FRAMEBUFFER, main_framebuffer_object);
-        clear(&env.gl, &[255, 0, 0, 255]);
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+        env.gl.Viewport(0, 0, 640, 480);
+    }
+    clear(&env.gl, &[255, 0, 255, 255]);
+    let mut surface = env
+        .device
+        .unbind_surface_from_context(&mut context)
+        .unwrap()
+        .unwrap();
 
-        let mut subframebuffer_object = make_fbo(
-            &env.gl,
-            env.device.surface_gl_texture_target(),
-            env.device.surface_texture_object(&subsurface_texture),
-        );
+    let mut other_surface = make_surface(&mut env.device, &context);
+    env.device
+        .bind_surface_to_context(&mut context, other_surface)
+        .unwrap();
+    bind_context_fbo(&env.gl, &env.device, &context);
+    let snapshot = debug_view::Snapshot::read(&env.device, &context, &surface).unwrap();
+    assert_eq!(snapshot.size, Size2D::new(640, 480));
+    assert_eq!(snapshot.pixels.len(), 640 * 480 * 4);
+    assert!(snapshot
+        .pixels
+        .chunks(4)
+        .all(|pixel| pixel == [255, 0, 255, 255]));
+    assert_eq!(
+        get_framebuffer_binding(&env.gl),
+        context_fbo(&env.device, &context)
+    );
+    other_surface = env
+        .device
+        .unbind_surface_from_context(&mut context)
+        .unwrap()
+        .unwrap();
+    env.device
+        .destroy_surface(&mut context, &mut other_surface)
+        .unwrap();
 
-        // Blit to main framebuffer.
-        blit_fbo(&env.gl, main_framebuffer_object, subframebuffer_object);
-        env.gl
-            .BindFramebuffer(gl::FRAMEBUFFER, main_framebuffer_object);
-        check_gl(&env.gl);
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
-        assert_eq!(
-            get_pixel_from_second_from_bottom_row(&env.gl),
-            [255, 0, 0, 255]
-        );
+    env.device.make_no_context_current().unwrap();
+    assert!(matches!(
+        debug_view::Snapshot::read(&env.device, &context, &surface),
+        Err(Error::NoCurrentContext)
+    ));
 
-        // Clean up.
-        env.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
-        check_gl(&env.gl);
-        env.gl.DeleteFramebuffers(1, &mut subframebuffer_object);
+    env.device
+        .destroy_surface(&mut context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
 
-        let mut subsurface = env
-            .device
-            .destroy_surface_texture(&mut env.context, subsurface_texture)
-            .unwrap();
-        env.device
-            .destroy_surface(&mut env.context, &mut subsurface)
-            .unwrap();
-        env.device.destroy_context(&mut env.context).unwrap();
-    }
+// Tests that debug views letterbox images to keep their aspect ratio.
+#[cfg(feature = "sm-debug-view")]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_debug_view_fit_rect() {
+    let rect = |x, y, width, height| Rect::new(Point2D::new(x, y), Size2D::new(width, height));
+    let fit = |image: (i32, i32), window: (i32, i32)| {
+        debug_view::fit_rect(Size2D::from(image), Size2D::from(window))
+    };
+    assert_eq!(fit((640, 480), (640, 480)), rect(0, 0, 640, 480));
+    assert_eq!(fit((320, 240), (640, 480)), rect(0, 0, 640, 480));
+    assert_eq!(fit((640, 480), (800, 480)), rect(80, 0, 640, 480));
+    assert_eq!(fit((640, 480), (640, 600)), rect(0, 60, 640, 480));
+    assert_eq!(fit((100, 1), (50, 50)), rect(0, 25, 50, 0));
+    assert_eq!(fit((0, 480), (640, 480)), Rect::zero());
+    assert_eq!(fit((640, 480), (0, 0)), Rect::zero());
 }
 
-#[cfg(not(any(target_os = "android", target_env = "ohos")))]
+// Tests that the color depth of a context descriptor reports the depth that was actually chosen.
 #[cfg_attr(not(feature = "sm-test"), test)]
 #[serial]
-pub fn test_depth_and_stencil() {
+pub fn test_color_depth_round_trip() {
     let connection = Connection::new().unwrap();
     let adapter = connection
         .create_low_power_adapter()
         .expect("Failed to create adapter!");
-    let mut device = match connection.create_device(&adapter) {
+    let device = match connection.create_device(&adapter) {
         Ok(device) => device,
         Err(Error::RequiredExtensionUnavailable) => {
             // Can't run these tests on this hardware.
@@ -749,137 +9444,804 @@ pub fn test_depth_and_stencil() {
         Err(err) => panic!("Failed to create device: {:?}", err),
     };
 
-    // Can't readback from the depth or stencil buffer on OpenGL ES, so just disable this test.
-    if device.gl_api() == GLApi::GLES {
-        return;
+    for &color_depth in &[ColorDepth::Auto, ColorDepth::Standard, ColorDepth::Deep] {
+        let descriptor = device
+            .create_context_descriptor(&ContextAttributes {
+                version: GLVersion::new(3, 0),
+                flags: ContextAttributeFlags::empty(),
+                color_depth,
+                ..Default::default()
+            })
+            .unwrap();
+        let attributes = device.context_descriptor_attributes(&descriptor);
+        assert_ne!(attributes.color_depth, ColorDepth::Auto);
+        if color_depth == ColorDepth::Standard {
+            assert_eq!(attributes.color_depth, ColorDepth::Standard);
+        }
+
+        // Asking for what was reported must give the same result.
+        let descriptor = device.create_context_descriptor(&attributes).unwrap();
+        assert_eq!(
+            device.context_descriptor_attributes(&descriptor),
+            attributes
+        );
+    }
+}
+
+// Tests that errors from both backends of a multi connection are reported.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_multiple_failures_display() {
+    let error = Error::MultipleFailures(
+        Box::new(Error::ConnectionFailed),
+        Box::new(Error::RequiredExtensionUnavailable),
+    );
+    assert_eq!(
+        error.to_string(),
+        "default backend failed: ConnectionFailed; alternate backend failed: \
+         RequiredExtensionUnavailable"
+    );
+}
+
+// A pretend system for testing environment detection.
+#[derive(Default)]
+struct MockEnvironmentProbe {
+    env_vars: Vec<(&'static str, &'static str)>,
+    paths: Vec<&'static str>,
+    openable_paths: Vec<&'static str>,
+}
+
+impl EnvironmentProbe for MockEnvironmentProbe {
+    fn env_var(&self, name: &str) -> Option<String> {
+        self.env_vars
+            .iter()
+            .find(|&&(var_name, _)| var_name == name)
+            .map(|&(_, value)| value.to_owned())
+    }
+
+    fn path_exists(&self, path: &Path) -> bool {
+        self.paths
+            .iter()
+            .any(|&mock_path| Path::new(mock_path) == path)
+    }
+
+    fn can_open(&self, path: &Path) -> bool {
+        self.openable_paths
+            .iter()
+            .any(|&mock_path| Path::new(mock_path) == path)
+    }
+
+    fn read_dir(&self, path: &Path) -> Vec<PathBuf> {
+        self.paths
+            .iter()
+            .map(PathBuf::from)
+            .filter(|mock_path| mock_path.parent() == Some(path))
+            .collect()
+    }
+}
+
+// Tests sandbox detection and the restrictions reported for it.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_environment_report() {
+    // An unsandboxed Wayland session with a GPU.
+    let probe = MockEnvironmentProbe {
+        env_vars: vec![
+            ("DISPLAY", ":0"),
+            ("WAYLAND_DISPLAY", "wayland-0"),
+            ("XDG_RUNTIME_DIR", "/run/user/1000"),
+            ("XDG_SESSION_TYPE", "wayland"),
+        ],
+        paths: vec![
+            "/dev/dri/card0",
+            "/dev/dri/renderD128",
+            "/run/user/1000/bus",
+            "/run/user/1000/wayland-0",
+            "/tmp/.X11-unix/X0",
+        ],
+        openable_paths: vec!["/dev/dri/renderD128"],
+    };
+    let report = EnvironmentReport::from_probe(&probe);
+    assert_eq!(report.sandbox, None);
+    assert_eq!(report.restrictions, vec![Restriction::XWaylandOnly]);
+
+    // A Flatpak without `--device=dri` or `--socket=wayland`, whose X11 socket is missing.
+    let probe = MockEnvironmentProbe {
+        env_vars: vec![
+            ("DISPLAY", ":99.0"),
+            ("DBUS_SESSION_BUS_ADDRESS", "unix:path=/run/flatpak/bus"),
+        ],
+        paths: vec!["/.flatpak-info", "/dev/dri/renderD128", "/run/flatpak/bus"],
+        openable_paths: vec![],
+    };
+    let report = EnvironmentReport::from_probe(&probe);
+    assert_eq!(report.sandbox, Some(Sandbox::Flatpak));
+    assert_eq!(
+        report.restrictions,
+        vec![
+            Restriction::NoRenderNodeAccess,
+            Restriction::NoX11,
+            Restriction::NoWayland,
+        ]
+    );
+    assert!(report.to_string().contains("sandbox: Flatpak"));
+
+    // A snap on an X11 session with no session bus.
+    let probe = MockEnvironmentProbe {
+        env_vars: vec![("SNAP", "/snap/app/1"), ("DISPLAY", ":0")],
+        paths: vec!["/dev/dri/renderD128", "/tmp/.X11-unix/X0"],
+        openable_paths: vec!["/dev/dri/renderD128"],
+    };
+    let report = EnvironmentReport::from_probe(&probe);
+    assert_eq!(report.sandbox, Some(Sandbox::Snap));
+    assert!(report.is_restricted(Restriction::NoWayland));
+    assert!(report.is_restricted(Restriction::NoPortals));
+    assert!(!report.is_restricted(Restriction::NoX11));
+    assert!(!report.is_restricted(Restriction::XWaylandOnly));
+}
+
+// Tests that system libraries are only looked for outside the linker's search path in sandboxes.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_library_search_dirs() {
+    assert!(diagnostics::library_search_dirs(&MockEnvironmentProbe::default()).is_empty());
+
+    let probe = MockEnvironmentProbe {
+        paths: vec!["/.flatpak-info"],
+        ..MockEnvironmentProbe::default()
+    };
+    let flatpak_dirs = diagnostics::library_search_dirs(&probe);
+    let probe = MockEnvironmentProbe {
+        env_vars: vec![("SNAP", "/snap/app/1")],
+        ..MockEnvironmentProbe::default()
+    };
+    let snap_dirs = diagnostics::library_search_dirs(&probe);
+    if cfg!(target_arch = "x86_64") {
+        assert_eq!(
+            flatpak_dirs[0],
+            Path::new("/usr/lib/x86_64-linux-gnu/GL/default/lib")
+        );
+        assert_eq!(
+            snap_dirs[0],
+            Path::new("/snap/app/1/usr/lib/x86_64-linux-gnu")
+        );
+    }
+    assert!(snap_dirs.iter().all(|dir| dir.starts_with("/snap/app/1")));
+}
+
+// A pretend backing store for testing the purgeability state machine.
+#[derive(Default)]
+struct MockPurgeableBacking {
+    offers: usize,
+    reclaims: usize,
+    // Whether the system discards the contents while they're offered.
+    discard: bool,
+}
+
+impl PurgeableBacking for MockPurgeableBacking {
+    fn offer(&mut self) -> Result<(), Error> {
+        self.offers += 1;
+        Ok(())
+    }
+
+    fn reclaim(&mut self) -> Result<bool, Error> {
+        self.reclaims += 1;
+        Ok(self.discard)
+    }
+}
+
+// Tests that surfaces are offered and reclaimed once per transition, and that discarded contents
+// are reported before the surface can be used again.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_purge_state_machine() {
+    let mut backing = MockPurgeableBacking::default();
+    let mut state = PurgeState::default();
+    assert_eq!(state.purgeability(), Purgeability::NonPurgeable);
+    assert!(state.check_usable().is_ok());
+
+    // Non-purgeable surfaces have nothing to reclaim.
+    state.set(&mut backing, Purgeability::NonPurgeable).unwrap();
+    assert_eq!(state.reclaim(&mut backing).unwrap(), ReclaimResult::Intact);
+    assert_eq!((backing.offers, backing.reclaims), (0, 0));
+
+    // Surfaces are offered once, no matter how often they're made volatile.
+    state.set(&mut backing, Purgeability::Volatile).unwrap();
+    state.set(&mut backing, Purgeability::Volatile).unwrap();
+    assert_eq!(state.purgeability(), Purgeability::Volatile);
+    assert_eq!((backing.offers, backing.reclaims), (1, 0));
+    assert_eq!(state.check_usable().is_err(), cfg!(debug_assertions));
+    assert_eq!(state.reclaim(&mut backing).unwrap(), ReclaimResult::Intact);
+    assert_eq!((backing.offers, backing.reclaims), (1, 1));
+    assert!(state.check_usable().is_ok());
+
+    // A discard is reported by `reclaim()`, even if the surface was made non-purgeable first.
+    backing.discard = true;
+    state.set(&mut backing, Purgeability::Volatile).unwrap();
+    state.set(&mut backing, Purgeability::NonPurgeable).unwrap();
+    assert_eq!(state.purgeability(), Purgeability::NonPurgeable);
+    assert_eq!(state.check_usable().is_err(), cfg!(debug_assertions));
+    assert_eq!(
+        state.reclaim(&mut backing).unwrap(),
+        ReclaimResult::Discarded
+    );
+    assert_eq!((backing.offers, backing.reclaims), (2, 2));
+    assert!(state.check_usable().is_ok());
+    assert_eq!(state.reclaim(&mut backing).unwrap(), ReclaimResult::Intact);
+}
+
+// A pretend surface for checking the swap chain state machine.
+#[cfg(feature = "chains")]
+#[derive(Debug)]
+struct ModelSurface {
+    id: usize,
+    size: Size2D<i32>,
+    // The frame last drawn to the surface.
+    frame: usize,
+}
+
+// A pretend producer context for checking the swap chain state machine. It keeps track of the
+// surfaces that are alive, so that lost surfaces, and surfaces destroyed while still in use, show
+// up, and logs the surfaces it invalidates.
+#[cfg(feature = "chains")]
+#[derive(Default)]
+struct ModelProducer {
+    next_id: usize,
+    live: Vec<usize>,
+    bound: Option<ModelSurface>,
+    invalidated: Vec<usize>,
+    low_memory_options: Option<LowMemoryOptions>,
+}
+
+#[cfg(feature = "chains")]
+impl SurfaceBinding for ModelProducer {
+    type Surface = ModelSurface;
+
+    fn bind_surface(&mut self, surface: ModelSurface) -> Result<(), (Error, ModelSurface)> {
+        if self.bound.is_some() {
+            return Err((Error::Failed, surface));
+        }
+        self.bound = Some(surface);
+        Ok(())
     }
 
-    // Check depth.
+    fn unbind_surface(&mut self) -> Result<ModelSurface, Error> {
+        self.bound.take().ok_or(Error::Failed)
+    }
 
-    let depth_context_descriptor = device
-        .create_context_descriptor(&ContextAttributes {
-            version: GLVersion::new(3, 0),
-            flags: ContextAttributeFlags::DEPTH,
-        })
-        .unwrap();
+    fn destroy_surface(&mut self, surface: &mut ModelSurface) -> Result<(), Error> {
+        let index = self.live.iter().position(|&id| id == surface.id);
+        self.live
+            .swap_remove(index.expect("Surface destroyed twice"));
+        Ok(())
+    }
+}
 
-    let mut depth_context = device
-        .create_context(&depth_context_descriptor, None)
-        .unwrap();
-    let depth_surface = make_surface(&mut device, &depth_context);
-    device
-        .bind_surface_to_context(&mut depth_context, depth_surface)
-        .unwrap();
-    device.make_context_current(&depth_context).unwrap();
+#[cfg(feature = "chains")]
+impl SurfaceProvider for ModelProducer {
+    fn context_id(&self) -> ContextID {
+        ContextID(0)
+    }
 
-    let gl = Gl::load_with(|symbol| device.get_proc_address(&depth_context, symbol));
+    fn surface_id(&self, surface: &ModelSurface) -> SurfaceID {
+        SurfaceID(surface.id)
+    }
 
-    unsafe {
-        let framebuffer_object = device
-            .context_surface_info(&depth_context)
-            .unwrap()
-            .unwrap()
-            .framebuffer_object;
-        gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
-        gl.Viewport(0, 0, 640, 480);
+    fn surface_size(&self, surface: &ModelSurface) -> Size2D<i32> {
+        surface.size
+    }
 
-        gl.ClearDepth(0.5);
-        gl.Clear(gl::DEPTH_BUFFER_BIT);
+    fn create_surface(&mut self, size: Size2D<i32>) -> Result<ModelSurface, Error> {
+        self.next_id += 1;
+        self.live.push(self.next_id);
+        Ok(ModelSurface {
+            id: self.next_id,
+            size,
+            frame: 0,
+        })
+    }
 
-        let mut depth_value: f32 = -1.0;
-        gl.ReadPixels(
-            0,
-            0,
-            1,
-            1,
-            gl::DEPTH_COMPONENT,
-            gl::FLOAT,
-            (&mut depth_value) as *mut f32 as *mut c_void,
-        );
+    fn invalidate_surface(&mut self, surface: &mut ModelSurface) -> Result<(), Error> {
         assert!(
-            approx_eq(depth_value, 0.5),
-            "actual depth value was {}, but expected 0.5",
-            depth_value
+            self.live.contains(&surface.id),
+            "Destroyed surface invalidated"
         );
+        self.invalidated.push(surface.id);
+        Ok(())
     }
 
-    device.destroy_context(&mut depth_context).unwrap();
+    fn debug_fill_surface(&mut self, _: &mut ModelSurface) -> Result<(), Error> {
+        Ok(())
+    }
 
-    // Check stencil.
+    fn low_memory_options(&self) -> Option<LowMemoryOptions> {
+        self.low_memory_options
+    }
+}
 
-    let stencil_context_descriptor = device
-        .create_context_descriptor(&ContextAttributes {
-            version: GLVersion::new(3, 0),
-            flags: ContextAttributeFlags::STENCIL,
-        })
-        .unwrap();
+// The steps of the threads using a pair of swap chains. Each one is a single call made with the
+// swap chain locked, so every interleaving of the threads is some order of their steps.
+#[cfg(feature = "chains")]
+#[derive(Clone, Copy, Debug)]
+enum ModelStep {
+    // The producer, on the first swap chain unless noted.
+    Swap,
+    Resize,
+    TakeBackBuffer,
+    ReplaceBackBuffer,
+    // Moves the attachment to whichever swap chain is detached.
+    SwapAttachment,
+    Destroy,
+    // A consumer, such as a compositor.
+    Take,
+    Recycle,
+    // A secondary consumer, such as a `FrameExporter`.
+    TakePending,
+    Restore,
+    // A consumer pausing presentation, and resuming it or dropping the handle.
+    Pause,
+    Resume,
+    DropPause,
+}
 
-    let mut stencil_context = device
-        .create_context(&stencil_context_descriptor, None)
-        .unwrap();
-    let stencil_surface = make_surface(&mut device, &stencil_context);
-    device
-        .bind_surface_to_context(&mut stencil_context, stencil_surface)
-        .unwrap();
-    device.make_context_current(&stencil_context).unwrap();
+// The producer context, two swap chains, and the surfaces that each thread holds.
+#[cfg(feature = "chains")]
+struct SwapChainModel {
+    producer: ModelProducer,
+    swap_chains: [SwapChainState<ModelSurface>; 2],
+    held: Vec<Vec<ModelSurface>>,
+    paused: Vec<Option<PausedPresentation>>,
+}
 
-    let gl = Gl::load_with(|symbol| device.get_proc_address(&stencil_context, symbol));
+#[cfg(feature = "chains")]
+impl SwapChainModel {
+    fn new(attached: bool, policy: ReallocationPolicy, threads: usize) -> SwapChainModel {
+        let size = Size2D::new(64, 64);
+        let mut producer = ModelProducer::default();
+        let surface = producer.create_surface(size).unwrap();
+        let first = if attached {
+            let id = producer.surface_id(&surface);
+            producer.bind_surface(surface).unwrap();
+            SwapChainState::attached(size, id)
+        } else {
+            let id = producer.surface_id(&surface);
+            SwapChainState::detached(size, surface, id)
+        };
+        let surface = producer.create_surface(size).unwrap();
+        let id = producer.surface_id(&surface);
+        let mut swap_chains = [first, SwapChainState::detached(size, surface, id)];
+        for swap_chain in &mut swap_chains {
+            swap_chain.set_reallocation_policy(policy);
+        }
+        SwapChainModel {
+            producer,
+            swap_chains,
+            held: (0..threads).map(|_| vec![]).collect(),
+            paused: (0..threads).map(|_| None).collect(),
+        }
+    }
 
-    unsafe {
-        let framebuffer_object = device
-            .context_surface_info(&stencil_context)
-            .unwrap()
-            .unwrap()
-            .framebuffer_object;
-        gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
-        gl.Viewport(0, 0, 640, 480);
+    fn step(&mut self, thread: usize, step: ModelStep) {
+        let (producer, held) = (&mut self.producer, &mut self.held[thread]);
+        let paused = &mut self.paused[thread];
+        let (first, second) = self.swap_chains.split_at_mut(1);
+        let (first, second) = (&mut first[0], &mut second[0]);
+        // Producer steps may fail, for example after `Destroy`, but must leave the state intact.
+        match step {
+            ModelStep::Swap => {
+                if first.swap_buffers(producer).is_ok() {
+                    first.invalidate_front_buffer(producer).unwrap();
+                }
+            }
+            ModelStep::Resize => {
+                let size = if first.size().width == 64 { 32 } else { 64 };
+                let _ = first.resize(producer, Size2D::new(size, size));
+            }
+            ModelStep::TakeBackBuffer => held.extend(first.take_back_buffer(producer).ok()),
+            ModelStep::ReplaceBackBuffer => {
+                if let Some(surface) = held.pop() {
+                    first.replace_back_buffer(producer, surface).unwrap();
+                }
+            }
+            ModelStep::SwapAttachment if first.is_attached() => {
+                let _ = second.take_attachment_from(producer, first);
+            }
+            ModelStep::SwapAttachment => {
+                let _ = first.take_attachment_from(producer, second);
+            }
+            ModelStep::Destroy => first.destroy(producer).unwrap(),
+            ModelStep::Take => held.extend(first.take_surface()),
+            ModelStep::TakePending => held.extend(first.take_pending_surface()),
+            ModelStep::Recycle => {
+                if let Some(surface) = held.pop() {
+                    first.recycle_surface(surface);
+                }
+            }
+            ModelStep::Restore => {
+                if let Some(surface) = held.pop() {
+                    first.restore_pending_surface(surface);
+                }
+            }
+            ModelStep::Pause => *paused = first.pause().ok(),
+            ModelStep::Resume => {
+                if let Some(paused) = paused.take() {
+                    first.resume(paused, true).unwrap();
+                }
+            }
+            ModelStep::DropPause => *paused = None,
+        }
+    }
 
-        gl.ClearStencil(99);
-        gl.Clear(gl::STENCIL_BUFFER_BIT);
+    // Every live surface is in exactly one place, and nothing else is.
+    fn check(&self, attached_count: usize) -> Result<(), String> {
+        let mut located: Vec<usize> = self
+            .swap_chains
+            .iter()
+            .flat_map(|swap_chain| swap_chain.surfaces())
+            .chain(self.producer.bound.iter())
+            .chain(self.held.iter().flatten())
+            .map(|surface| surface.id)
+            .collect();
+        let mut live = self.producer.live.clone();
+        located.sort();
+        live.sort();
+        if located != live {
+            return Err(format!("surfaces {:?} are where {:?} live", located, live));
+        }
+        let attached = self
+            .swap_chains
+            .iter()
+            .filter(|swap_chain| swap_chain.is_attached());
+        if attached.count() != attached_count {
+            return Err("the attachment was lost or duplicated".to_owned());
+        }
+        Ok(())
+    }
+}
 
-        let mut stencil_value: u8 = 200;
-        gl.ReadPixels(
-            0,
-            0,
-            1,
-            1,
-            gl::STENCIL_INDEX,
-            gl::UNSIGNED_BYTE,
-            (&mut stencil_value) as *mut u8 as *mut c_void,
-        );
-        assert_eq!(stencil_value, 99);
+// An interleaving of model steps, each with the index of the thread that takes it.
+#[cfg(feature = "chains")]
+type ModelSchedule = [(usize, ModelStep)];
+
+// Calls `f` with every interleaving of the steps of `threads`.
+#[cfg(feature = "chains")]
+fn for_each_schedule(threads: &[&[ModelStep]], f: &mut dyn FnMut(&ModelSchedule)) {
+    fn visit(
+        threads: &[&[ModelStep]],
+        positions: &mut [usize],
+        schedule: &mut Vec<(usize, ModelStep)>,
+        f: &mut dyn FnMut(&ModelSchedule),
+    ) {
+        let mut finished = true;
+        for thread in 0..threads.len() {
+            if let Some(&step) = threads[thread].get(positions[thread]) {
+                finished = false;
+                positions[thread] += 1;
+                schedule.push((thread, step));
+                visit(threads, positions, schedule, f);
+                schedule.pop();
+                positions[thread] -= 1;
+            }
+        }
+        if finished {
+            f(schedule);
+        }
+    }
+    visit(threads, &mut vec![0; threads.len()], &mut vec![], f);
+}
+
+// Checks the swap chain state machine under every interleaving of a producer and two consumers,
+// for attached and detached swap chains and each kind of reallocation policy. No surface may be in
+// two places, get lost, or be destroyed while in use, and destroying the swap chains again once
+// everyone is done must leave no surface behind.
+#[cfg(feature = "chains")]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_swap_chain_state_machine() {
+    use ModelStep::*;
+    let producers: [&[ModelStep]; 2] = [
+        &[Swap, Resize, Swap, Destroy],
+        &[
+            TakeBackBuffer,
+            Swap,
+            ReplaceBackBuffer,
+            SwapAttachment,
+            Swap,
+            Destroy,
+        ],
+    ];
+    let consumer: &[ModelStep] = &[Take, Recycle, Take, Recycle];
+    let secondary_consumer: &[ModelStep] = &[TakePending, Restore];
+    let policies = [
+        ReallocationPolicy::default(),
+        ReallocationPolicy {
+            max_frames_per_buffer: Some(1),
+            realloc_on_resize_history: false,
+        },
+        ReallocationPolicy {
+            max_frames_per_buffer: Some(2),
+            realloc_on_resize_history: true,
+        },
+    ];
+
+    for &producer in &producers {
+        for &attached in &[true, false] {
+            for &policy in &policies {
+                let threads = [producer, consumer, secondary_consumer];
+                for_each_schedule(&threads, &mut |schedule| {
+                    let attached_count = attached as usize;
+                    let mut model = SwapChainModel::new(attached, policy, threads.len());
+                    for (index, &(thread, step)) in schedule.iter().enumerate() {
+                        model.step(thread, step);
+                        if let Err(err) = model.check(attached_count) {
+                            panic!("After {:?}: {}", &schedule[..=index], err);
+                        }
+                    }
+                    for swap_chain in &mut model.swap_chains {
+                        swap_chain.destroy(&mut model.producer).unwrap();
+                    }
+                    assert!(model.producer.live.is_empty(), "Leaked in {:?}", schedule);
+                });
+            }
+        }
     }
+}
 
-    device.destroy_context(&mut stencil_context).unwrap();
+// Checks that each swap invalidates the new front buffer, and only that surface.
+#[cfg(feature = "chains")]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_swap_chain_invalidates_front_buffer() {
+    for &attached in &[true, false] {
+        let mut model = SwapChainModel::new(attached, ReallocationPolicy::default(), 1);
+        for _ in 0..3 {
+            model.step(0, ModelStep::Swap);
+            let front_buffer = model.swap_chains[0].take_pending_surface().unwrap();
+            assert_eq!(model.producer.invalidated.last(), Some(&front_buffer.id));
+            model.swap_chains[0].restore_pending_surface(front_buffer);
+        }
+        assert_eq!(model.producer.invalidated.len(), 3);
+        for swap_chain in &mut model.swap_chains {
+            swap_chain.destroy(&mut model.producer).unwrap();
+        }
+    }
 }
 
-// Make sure that the current native context can be fetched and that they can be correctly wrapped
-// in `surfman` contexts.
+// Checks that swap chains keep spare surfaces across swaps up to their usual depth, shrink to the
+// depth that low-memory mode asks for at the next swap, and purge their pools.
+#[cfg(feature = "chains")]
 #[cfg_attr(not(feature = "sm-test"), test)]
-#[serial]
-pub fn test_get_native_context() {
-    let mut env = match BasicEnvironment::new() {
-        None => return,
-        Some(env) => env,
+pub fn test_swap_chain_depth() {
+    let mut model = SwapChainModel::new(true, ReallocationPolicy::default(), 1);
+    // Only count the surfaces of the first swap chain.
+    model.swap_chains[1].destroy(&mut model.producer).unwrap();
+    let swap_and_take = |model: &mut SwapChainModel| {
+        model.step(0, ModelStep::Swap);
+        model.step(0, ModelStep::Take);
+        model.check(1).unwrap();
+    };
+    let recycle_all = |model: &mut SwapChainModel| {
+        while !model.held[0].is_empty() {
+            model.step(0, ModelStep::Recycle);
+        }
+        model.check(1).unwrap();
+    };
+    // Holds two frames across a swap, and gives them back before the next one.
+    let grow = |model: &mut SwapChainModel| {
+        model.step(0, ModelStep::Take);
+        swap_and_take(model);
+        recycle_all(model);
+        model.step(0, ModelStep::Swap);
+        model.check(1).unwrap();
     };
 
-    let native_context = NativeContext::current().unwrap();
+    // A consumer holding three frames makes the swap chain grow to four surfaces.
+    for _ in 0..3 {
+        swap_and_take(&mut model);
+    }
+    assert_eq!(model.producer.live.len(), 4);
+    recycle_all(&mut model);
+    assert_eq!(model.producer.live.len(), 4);
+
+    // Once they're given back, it shrinks to three at the next swap, and keeps its spare surface
+    // across swaps instead of reallocating it.
+    model.step(0, ModelStep::Swap);
+    assert_eq!(model.producer.live.len(), 3);
+    let next_id = model.producer.next_id;
+    for _ in 0..3 {
+        model.step(0, ModelStep::Swap);
+        model.check(1).unwrap();
+        assert_eq!(model.producer.live.len(), 3);
+    }
+    assert_eq!(model.producer.next_id, next_id);
+
+    // Low-memory mode shrinks it to its minimum depth at the next swap, and keeps it there.
+    for &min_chain_depth in &[0, 2] {
+        model.producer.low_memory_options = Some(LowMemoryOptions {
+            min_chain_depth,
+            ..LowMemoryOptions::default()
+        });
+        for _ in 0..2 {
+            model.step(0, ModelStep::Swap);
+            model.check(1).unwrap();
+            assert_eq!(model.producer.live.len(), 2);
+        }
+        model.producer.low_memory_options = None;
+        grow(&mut model);
+        assert_eq!(model.producer.live.len(), 3);
+    }
 
-    unsafe {
-        clear(&env.gl, &[0, 255, 0, 255]);
+    // Surfaces that consumers hold are never taken back, even beyond the depth.
+    model.producer.low_memory_options = Some(LowMemoryOptions {
+        min_chain_depth: 2,
+        ..LowMemoryOptions::default()
+    });
+    model.step(0, ModelStep::Take);
+    swap_and_take(&mut model);
+    assert_eq!(model.held[0].len(), 2);
+    assert_eq!(model.producer.live.len(), 3);
+    recycle_all(&mut model);
+
+    // Purging destroys the surfaces given back since the last swap, and the spare surfaces.
+    model.producer.low_memory_options = None;
+    assert_eq!(
+        model.swap_chains[0]
+            .purge_pools(&mut model.producer)
+            .unwrap(),
+        2
+    );
+    model.check(1).unwrap();
+    assert_eq!(model.producer.live.len(), 1);
+    model.step(0, ModelStep::Swap);
+    grow(&mut model);
+    assert_eq!(model.producer.live.len(), 3);
+    assert_eq!(
+        model.swap_chains[0]
+            .purge_pools(&mut model.producer)
+            .unwrap(),
+        1
+    );
+    assert_eq!(model.producer.live.len(), 2);
+
+    // With `purge_pools`, no spare surfaces are kept, whatever the depth.
+    grow(&mut model);
+    assert_eq!(model.producer.live.len(), 3);
+    model.producer.low_memory_options = Some(LowMemoryOptions {
+        purge_pools: true,
+        min_chain_depth: 3,
+        ..LowMemoryOptions::default()
+    });
+    model.step(0, ModelStep::Swap);
+    model.check(1).unwrap();
+    assert_eq!(model.producer.live.len(), 2);
+
+    // Resizing destroys the spare surfaces, which are the wrong size.
+    model.producer.low_memory_options = None;
+    grow(&mut model);
+    assert_eq!(model.producer.live.len(), 3);
+    model.step(0, ModelStep::Resize);
+    model.check(1).unwrap();
+    assert_eq!(model.producer.live.len(), 2);
+
+    model.swap_chains[0].destroy(&mut model.producer).unwrap();
+    assert!(model.producer.live.is_empty());
+}
 
-        let mut other_context = env
-            .device
-            .create_context_from_native_context(native_context)
-            .unwrap();
+// Checks the swap chain state machine under every interleaving of a producer, a consumer, and a
+// thread that pauses presentation and then resumes it or drops the handle.
+#[cfg(feature = "chains")]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_swap_chain_pause_state_machine() {
+    use ModelStep::*;
+    let producer: &[ModelStep] = &[Swap, Resize, Swap];
+    let consumer: &[ModelStep] = &[Take, Recycle, Take, Recycle];
+    for &pauser in &[&[Pause, Resume][..], &[Pause, DropPause][..]] {
+        for &attached in &[true, false] {
+            let threads = [producer, consumer, pauser];
+            for_each_schedule(&threads, &mut |schedule| {
+                let attached_count = attached as usize;
+                let policy = ReallocationPolicy::default();
+                let mut model = SwapChainModel::new(attached, policy, threads.len());
+                for (index, &(thread, step)) in schedule.iter().enumerate() {
+                    model.step(thread, step);
+                    if let Err(err) = model.check(attached_count) {
+                        panic!("After {:?}: {}", &schedule[..=index], err);
+                    }
+                }
+                for swap_chain in &mut model.swap_chains {
+                    swap_chain.destroy(&mut model.producer).unwrap();
+                }
+                assert!(model.producer.live.is_empty(), "Leaked in {:?}", schedule);
+            });
+        }
+    }
+}
 
-        env.device.make_context_current(&other_context).unwrap();
-        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+// Scripts cycles of pausing a swap chain, grabbing the held frame, and resuming it, and checks
+// that consumers see each frame that they should exactly once, and no other.
+#[cfg(feature = "chains")]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_swap_chain_pause_frames() {
+    let mut model = SwapChainModel::new(true, ReallocationPolicy::default(), 1);
+    let mut next_frame = 0;
+    let mut draw_and_swap = |model: &mut SwapChainModel| {
+        next_frame += 1;
+        model.producer.bound.as_mut().unwrap().frame = next_frame;
+        model.step(0, ModelStep::Swap);
+        model.check(1).unwrap();
+        next_frame
+    };
+    let take = |model: &mut SwapChainModel| {
+        let surface = model.swap_chains[0].take_pending_surface();
+        let frame = surface.as_ref().map(|surface| surface.frame);
+        model.swap_chains[0].recycle_surface(surface?);
+        model.check(1).unwrap();
+        frame
+    };
+    let held = |model: &SwapChainModel| model.swap_chains[0].held_surface().map(|s| s.frame);
 
-        env.device.destroy_context(&mut other_context).unwrap();
+    let frame = draw_and_swap(&mut model);
+    assert_eq!(take(&mut model), Some(frame));
+
+    // While paused, the frame on show stays on show, and only the last frame drawn is held.
+    let paused = model.swap_chains[0].pause().unwrap();
+    match model.swap_chains[0].pause() {
+        Err(Error::PresentationPaused) => {}
+        result => panic!("Expected `PresentationPaused`, got {:?}", result),
     }
+    draw_and_swap(&mut model);
+    let last = draw_and_swap(&mut model);
+    assert_eq!(held(&model), Some(last));
+    assert_eq!(paused.frames_held(), 2);
+    assert_eq!(take(&mut model), None);
+    model.swap_chains[0].resume(paused, true).unwrap();
+    assert_eq!(held(&model), None);
+    assert_eq!(take(&mut model), Some(last));
+    assert_eq!(take(&mut model), None);
+
+    // A consumer's front buffer is kept as the front buffer while paused, instead of recycled.
+    let frame = draw_and_swap(&mut model);
+    let on_show = model.swap_chains[0].take_pending_surface().unwrap();
+    assert_eq!(on_show.frame, frame);
+    let paused = model.swap_chains[0].pause().unwrap();
+    model.swap_chains[0].recycle_surface(on_show);
+    let discarded = draw_and_swap(&mut model);
+    assert_eq!(held(&model), Some(discarded));
+    assert_eq!(take(&mut model), Some(frame));
+    // Discarding the held frame leaves the frame on show as the front buffer, once more.
+    model.swap_chains[0].resume(paused, false).unwrap();
+    assert_eq!(take(&mut model), Some(frame));
+    assert_eq!(take(&mut model), None);
+
+    // Dropping the handle discards the held frame once the swap chain next looks.
+    let paused = model.swap_chains[0].pause().unwrap();
+    draw_and_swap(&mut model);
+    drop(paused);
+    assert_eq!(held(&model), None);
+    let frame = draw_and_swap(&mut model);
+    assert_eq!(take(&mut model), Some(frame));
+
+    // A resize resumes, discarding the held frame, and the handle then resumes nothing.
+    let paused = model.swap_chains[0].pause().unwrap();
+    draw_and_swap(&mut model);
+    model.step(0, ModelStep::Resize);
+    model.check(1).unwrap();
+    assert!(!paused.is_paused());
+    model.swap_chains[0].resume(paused, true).unwrap();
+    assert_eq!(take(&mut model), None);
+
+    // Another swap chain's pause can't end this one's.
+    let paused = model.swap_chains[0].pause().unwrap();
+    let other_paused = model.swap_chains[1].pause().unwrap();
+    match model.swap_chains[0].resume(other_paused, true) {
+        Err(Error::IncompatiblePausedPresentation) => {}
+        result => panic!(
+            "Expected `IncompatiblePausedPresentation`, got {:?}",
+            result
+        ),
+    }
+    let frame = draw_and_swap(&mut model);
+    model.swap_chains[0].resume(paused, true).unwrap();
+    assert_eq!(take(&mut model), Some(frame));
 
-    env.device.destroy_context(&mut env.context).unwrap();
+    for swap_chain in &mut model.swap_chains {
+        swap_chain.destroy(&mut model.producer).unwrap();
+    }
+    assert!(model.producer.live.is_empty());
 }
 
 fn bind_context_fbo(gl: &Gl, device: &Device, context: &Context) {
@@ -889,6 +10251,23 @@ fn bind_context_fbo(gl: &Gl, device: &Device, context: &Context) {
     }
 }
 
+// Clears the back buffer of an attached swap chain to `color`, then flushes and swaps buffers.
+#[cfg(feature = "chains")]
+fn render_swap_chain_frame(
+    env: &mut BasicEnvironment,
+    swap_chain: &SwapChain<Device>,
+    color: &[u8; 4],
+) {
+    bind_context_fbo(&env.gl, &env.device, &env.context);
+    clear(&env.gl, color);
+    unsafe {
+        env.gl.Flush();
+    }
+    swap_chain
+        .swap_buffers(&mut env.device, &mut env.context, PreserveBuffer::No)
+        .unwrap();
+}
+
 fn context_fbo(device: &Device, context: &Context) -> GLuint {
     device
         .context_surface_info(context)
@@ -909,7 +10288,27 @@ fn make_surface(device: &mut Device, context: &Context) -> Surface {
         .unwrap()
 }
 
-fn blit_fbo(gl: &Gl, dest_fbo: GLuint, src_fbo: GLuint) {
+// Builds a minimal RGB display profile whose only tag is the given `desc` element.
+fn make_icc_profile(desc_element: &[u8]) -> Vec<u8> {
+    let size = 144 + desc_element.len();
+    let mut data = vec![0; 128];
+    data[0..4].copy_from_slice(&(size as u32).to_be_bytes());
+    data[12..16].copy_from_slice(b"mntr");
+    data[16..20].copy_from_slice(b"RGB ");
+    data[36..40].copy_from_slice(b"acsp");
+    data.extend_from_slice(&1u32.to_be_bytes());
+    data.extend_from_slice(b"desc");
+    data.extend_from_slice(&144u32.to_be_bytes());
+    data.extend_from_slice(&(desc_element.len() as u32).to_be_bytes());
+    data.extend_from_slice(desc_element);
+    data
+}
+
+// Returns false without blitting if the context has no `glBlitFramebuffer`.
+fn blit_fbo(gl: &Gl, capabilities: &GLCapabilities, dest_fbo: GLuint, src_fbo: GLuint) -> bool {
+    if !capabilities.blit_framebuffer {
+        return false;
+    }
     unsafe {
         gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, dest_fbo);
         check_gl(gl);
@@ -929,6 +10328,65 @@ fn blit_fbo(gl: &Gl, dest_fbo: GLuint, src_fbo: GLuint) {
         );
         check_gl(gl);
     }
+    true
+}
+
+// Builds a pipeline that produces `frame_count` frames, each a solid color that depends on its
+// frame ID, with the frame ID times ten as metadata. The filter checks the color, turns on the
+// green channel, and adds one to the metadata. The producer and filter fail with
+// `Error::Unimplemented` and `Error::UnsupportedOnThisPlatform` at the given frames.
+#[cfg(feature = "chains")]
+fn make_test_pipeline(
+    env: &BasicEnvironment,
+    frame_count: u64,
+    producer_fails_at: Option<u64>,
+    filter_fails_at: Option<u64>,
+) -> Pipeline<Device, u64> {
+    Pipeline::builder()
+        .size(Size2D::new(640, 480))
+        .producer(move |device: &mut Device, context: &mut Context, info| {
+            if info.frame_id == frame_count {
+                return Ok(false);
+            }
+            if Some(info.frame_id) == producer_fails_at {
+                return Err(Error::Unimplemented);
+            }
+            let gl = Gl::load_with(device.proc_address_loader(context));
+            bind_context_fbo(&gl, device, context);
+            clear(&gl, &[info.frame_id as u8 * 40, 0, 0, 255]);
+            info.metadata = info.frame_id * 10;
+            Ok(true)
+        })
+        .filter(
+            move |device: &mut Device, context: &mut Context, texture, info| {
+                if Some(info.frame_id) == filter_fails_at {
+                    return Err(Error::UnsupportedOnThisPlatform);
+                }
+                let gl = Gl::load_with(device.proc_address_loader(context));
+                let color = info.frame_id as u8 * 40;
+                assert_eq!(
+                    read_texture_pixel(&gl, device, context, texture),
+                    [color, 0, 0, 255]
+                );
+                bind_context_fbo(&gl, device, context);
+                clear(&gl, &[color, 255, 0, 255]);
+                info.metadata += 1;
+                Ok(())
+            },
+        )
+        .build(&env.device, &env.context)
+        .unwrap()
+}
+
+// Reads the bottom left pixel of a surface texture.
+fn read_texture_pixel(gl: &Gl, device: &Device, context: &Context, texture: GLuint) -> [u8; 4] {
+    unsafe {
+        let mut framebuffer_object = make_fbo(gl, device.surface_gl_texture_target(), texture);
+        let pixel = get_pixel_from_bottom_row(gl);
+        bind_context_fbo(gl, device, context);
+        gl.DeleteFramebuffers(1, &mut framebuffer_object);
+        pixel
+    }
 }
 
 fn make_fbo(gl: &Gl, texture_target: GLenum, texture: GLuint) -> GLuint {
@@ -954,17 +10412,38 @@ fn make_fbo(gl: &Gl, texture_target: GLenum, texture: GLuint) -> GLuint {
     }
 }
 
+// Clamps capabilities to OpenGL ES 2.0 until dropped, even if a test panics.
+struct ClampToGLES2;
+
+impl ClampToGLES2 {
+    fn new() -> ClampToGLES2 {
+        env::set_var(CLAMP_TO_GLES2_ENV_VAR, "1");
+        ClampToGLES2
+    }
+}
+
+impl Drop for ClampToGLES2 {
+    fn drop(&mut self) {
+        env::remove_var(CLAMP_TO_GLES2_ENV_VAR);
+    }
+}
+
 struct BasicEnvironment {
+    // Checks that the test destroyed everything that it created. It's declared first so that it's
+    // dropped first.
+    leak_check: LeakCheckScope,
     connection: Connection,
     adapter: Adapter,
     device: Device,
     context_descriptor: ContextDescriptor,
     context: Context,
     gl: Gl,
+    capabilities: GLCapabilities,
 }
 
 impl BasicEnvironment {
     fn new() -> Option<BasicEnvironment> {
+        let leak_check = leak_check::scope();
         let connection = Connection::new().unwrap();
         let adapter = connection
             .create_low_power_adapter()
@@ -982,6 +10461,7 @@ impl BasicEnvironment {
             .create_context_descriptor(&ContextAttributes {
                 version: GLVersion::new(3, 0),
                 flags: ContextAttributeFlags::empty(),
+                ..Default::default()
             })
             .unwrap();
 
@@ -992,7 +10472,7 @@ impl BasicEnvironment {
             .unwrap();
         device.make_context_current(&context).unwrap();
 
-        let gl = Gl::load_with(|symbol| device.get_proc_address(&context, symbol));
+        let gl = Gl::load_with(device.proc_address_loader(&context));
 
         unsafe {
             let framebuffer_object = device
@@ -1004,13 +10484,16 @@ impl BasicEnvironment {
             gl.Viewport(0, 0, 640, 480);
         }
 
+        let capabilities = device.capabilities(&context).unwrap();
         Some(BasicEnvironment {
+            leak_check,
             connection,
             adapter,
             device,
             context_descriptor,
             context,
             gl,
+            capabilities,
         })
     }
 }
@@ -1043,6 +10526,22 @@ fn clear_bottom_row(gl: &Gl, color: &[u8; 4]) {
     }
 }
 
+fn get_framebuffer_binding(gl: &Gl) -> GLuint {
+    unsafe {
+        let mut framebuffer_object = 0;
+        gl.GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut framebuffer_object);
+        framebuffer_object as GLuint
+    }
+}
+
+fn get_viewport(gl: &Gl) -> [i32; 4] {
+    unsafe {
+        let mut viewport = [0; 4];
+        gl.GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        viewport
+    }
+}
+
 fn get_pixel_from_bottom_row(gl: &Gl) -> [u8; 4] {
     unsafe {
         let mut pixel: [u8; 4] = [0; 4];
@@ -1059,6 +10558,24 @@ fn get_pixel_from_bottom_row(gl: &Gl) -> [u8; 4] {
     }
 }
 
+// Reads a pixel of a 640x480 framebuffer, measured from its top left.
+fn get_pixel_from_top_left(gl: &Gl, framebuffer_object: GLuint, x: i32, y: i32) -> [u8; 4] {
+    unsafe {
+        let mut pixel: [u8; 4] = [0; 4];
+        gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_object);
+        gl.ReadPixels(
+            x,
+            480 - 1 - y,
+            1,
+            1,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            pixel.as_mut_ptr() as *mut c_void,
+        );
+        pixel
+    }
+}
+
 fn get_pixel_from_second_from_bottom_row(gl: &Gl) -> [u8; 4] {
     unsafe {
         let mut pixel: [u8; 4] = [0; 4];
@@ -1075,6 +10592,94 @@ fn get_pixel_from_second_from_bottom_row(gl: &Gl) -> [u8; 4] {
     }
 }
 
+// Reads a pixel of an unbound generic surface as normalized or floating-point values, without
+// decoding sRGB.
+fn read_pixel_as_float(gl: &Gl, surface_info: &SurfaceInfo, x: i32, y: i32) -> [f32; 4] {
+    unsafe {
+        gl.BindFramebuffer(gl::FRAMEBUFFER, surface_info.framebuffer_object);
+        let pixel = match surface_info.format {
+            gl::RGBA16F => {
+                let mut pixel = [0.0; 4];
+                gl.ReadPixels(
+                    x,
+                    y,
+                    1,
+                    1,
+                    gl::RGBA,
+                    gl::FLOAT,
+                    pixel.as_mut_ptr() as *mut c_void,
+                );
+                pixel
+            }
+            gl::RGB10_A2 => {
+                let mut packed: u32 = 0;
+                gl.ReadPixels(
+                    x,
+                    y,
+                    1,
+                    1,
+                    gl::RGBA,
+                    gl::UNSIGNED_INT_2_10_10_10_REV,
+                    &mut packed as *mut u32 as *mut c_void,
+                );
+                [
+                    (packed & 0x3ff) as f32 / 1023.0,
+                    ((packed >> 10) & 0x3ff) as f32 / 1023.0,
+                    ((packed >> 20) & 0x3ff) as f32 / 1023.0,
+                    (packed >> 30) as f32 / 3.0,
+                ]
+            }
+            _ => {
+                let mut pixel: [u8; 4] = [0; 4];
+                gl.ReadPixels(
+                    x,
+                    y,
+                    1,
+                    1,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    pixel.as_mut_ptr() as *mut c_void,
+                );
+                pixel.map(|value| value as f32 / 255.0)
+            }
+        };
+        check_gl(gl);
+        pixel
+    }
+}
+
+// Converts a pixel as `Device::convert_surface()` should, for reference.
+fn convert_pixel(
+    pixel: &[f32; 4],
+    source_colorspace: ColorEncoding,
+    destination_colorspace: ColorEncoding,
+    swizzle: Swizzle,
+) -> [f32; 4] {
+    let mut linear = *pixel;
+    if source_colorspace == ColorEncoding::Srgb {
+        for value in &mut linear[0..3] {
+            *value = if *value <= 0.04045 {
+                *value / 12.92
+            } else {
+                ((*value + 0.055) / 1.055).powf(2.4)
+            };
+        }
+    }
+    if swizzle == Swizzle::SwapRedBlue {
+        linear.swap(0, 2);
+    }
+    if destination_colorspace == ColorEncoding::Srgb {
+        for value in &mut linear[0..3] {
+            *value = if *value <= 0.0031308 {
+                *value * 12.92
+            } else {
+                1.055 * value.powf(1.0 / 2.4) - 0.055
+            };
+        }
+    }
+    linear
+}
+
 fn check_gl(gl: &Gl) {
     unsafe {
         assert_eq!(gl.GetError(), gl::NO_ERROR);
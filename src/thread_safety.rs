@@ -0,0 +1,105 @@
+// surfman/surfman/src/thread_safety.rs
+//
+//! Which native wrapper types can cross threads, and the guard for those that can only be used on
+//! one.
+//!
+//! Each `NativeConnection`, `NativeDevice`, and `NativeWidget` is `Send` and `Sync` exactly when
+//! the object that it wraps can be used from any thread, and the assertions next to each type keep
+//! it that way. `Connection` is `Send` and `Sync` on every backend, so sharing it is the way to
+//! get a connection onto another thread; a `NativeConnection` that holds a raw X11 `Display`
+//! pointer can't be sent, and `Connection::try_clone_for_thread()` opens a display of its own for
+//! a thread that needs one.
+//!
+//! Some native objects can be moved between threads but only used on the one that created them,
+//! such as Wayland surfaces, whose proxies belong to the event queue of the thread that made them.
+//! Widgets wrap those in a `ThreadBound`, so that they can be sent to a rendering thread like any
+//! other widget, and using one on the wrong thread returns `Error::WrongThread` instead of
+//! crashing.
+
+use crate::Error;
+
+use std::thread::{self, ThreadId};
+
+// Fails to compile unless the type is `Send` and `Sync`.
+macro_rules! assert_send_sync {
+    ($ty:ty) => {
+        const _: fn() = || {
+            fn assert_send_sync<T: ?Sized + Send + Sync>() {}
+            assert_send_sync::<$ty>();
+        };
+    };
+}
+
+// Fails to compile if the type is `Send`: the call is ambiguous when both impls apply.
+#[allow(unused_macros)]
+macro_rules! assert_not_send {
+    ($ty:ty) => {
+        const _: fn() = || {
+            trait AmbiguousIfSend<A> {
+                fn some_item() {}
+            }
+            impl<T: ?Sized> AmbiguousIfSend<()> for T {}
+            impl<T: ?Sized + Send> AmbiguousIfSend<u8> for T {}
+            let _ = <$ty as AmbiguousIfSend<_>>::some_item;
+        };
+    };
+}
+
+// Fails to compile if the type is `Sync`.
+#[allow(unused_macros)]
+macro_rules! assert_not_sync {
+    ($ty:ty) => {
+        const _: fn() = || {
+            trait AmbiguousIfSync<A> {
+                fn some_item() {}
+            }
+            impl<T: ?Sized> AmbiguousIfSync<()> for T {}
+            impl<T: ?Sized + Sync> AmbiguousIfSync<u8> for T {}
+            let _ = <$ty as AmbiguousIfSync<_>>::some_item;
+        };
+    };
+}
+
+/// A native handle that can be moved and shared between threads, but only used on the thread
+/// that created it.
+///
+/// Only `Copy` handles can be bound, since they have no destructor that could run on another
+/// thread; whatever the handle refers to is owned elsewhere.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(not(wayland_platform), allow(dead_code))]
+pub(crate) struct ThreadBound<T>
+where
+    T: Copy,
+{
+    value: T,
+    owner: ThreadId,
+}
+
+// The handle can only be reached through `get()`, which refuses every thread but its owner.
+unsafe impl<T> Send for ThreadBound<T> where T: Copy {}
+unsafe impl<T> Sync for ThreadBound<T> where T: Copy {}
+
+#[cfg_attr(not(wayland_platform), allow(dead_code))]
+impl<T> ThreadBound<T>
+where
+    T: Copy,
+{
+    // Binds the handle to the calling thread.
+    #[inline]
+    pub(crate) fn new(value: T) -> ThreadBound<T> {
+        ThreadBound {
+            value,
+            owner: thread::current().id(),
+        }
+    }
+
+    // Returns the handle if this is the thread that it's bound to, and `Error::WrongThread`
+    // otherwise.
+    #[inline]
+    pub(crate) fn get(&self) -> Result<T, Error> {
+        if thread::current().id() != self.owner {
+            return Err(Error::WrongThread { owner: self.owner });
+        }
+        Ok(self.value)
+    }
+}
@@ -0,0 +1,296 @@
+// surfman/surfman/src/upload.rs
+//
+//! Writing pixels from memory into generic surfaces.
+//!
+//! `Device::write_surface_data` is the inverse of `Device::read_surface_data`: it copies 8-bit
+//! RGBA pixels, top row first, into a rectangle of a generic surface, measured from its top left,
+//! so that software renderers can hand their frames to the GPU without looking up the surface's
+//! OpenGL objects themselves.
+//!
+//! The pixels are uploaded with `glTexSubImage2D()` to the texture attached to the surface's
+//! framebuffer. Where pixel buffer objects are available, the rows are flipped straight into a
+//! mapped one, which the upload then reads from without stalling the CPU; elsewhere they're
+//! flipped into a temporary buffer first. Every binding and pixel store parameter that the upload
+//! touches is put back afterward.
+
+use crate::cpu_layer;
+use crate::device::Device as DeviceAPI;
+use crate::gl;
+use crate::gl::types::{GLenum, GLint, GLuint};
+use crate::gl_utils;
+use crate::readback;
+use crate::{Error, GLVersion, Gl, SurfaceInfo};
+use euclid::default::{Rect, Size2D};
+use std::os::raw::c_void;
+use std::ptr;
+use std::slice;
+
+// The number of bytes in each pixel that `write_surface_data()` reads.
+const BYTES_PER_PIXEL: usize = 4;
+
+// The state that uploading changes, saved so that it can be put back. Drawing a held frame back
+// into a window changes the same state.
+pub(crate) struct SavedState {
+    framebuffer: GLint,
+    texture_target: GLenum,
+    texture: GLint,
+    // The separate read framebuffer binding, and the pixel store parameters and pixel unpack
+    // buffer binding, of OpenGL 3.0 and OpenGL ES 3.0.
+    gl3_state: Option<Gl3State>,
+    unpack_alignment: GLint,
+}
+
+struct Gl3State {
+    read_framebuffer: GLint,
+    unpack_buffer: GLint,
+    unpack_row_length: GLint,
+    unpack_skip_rows: GLint,
+    unpack_skip_pixels: GLint,
+}
+
+// Implements `Device::write_surface_data` for every backend.
+pub(crate) fn write_surface_data<Device: DeviceAPI>(
+    device: &Device,
+    context: &Device::Context,
+    surface: &mut Device::Surface,
+    rect: Rect<i32>,
+    data: &[u8],
+    stride: usize,
+) -> Result<(), Error> {
+    let info = device.surface_info(surface);
+    readback::check_surface_rect(&info, device.context_id(context), rect)?;
+    let row_length = rect.size.width as usize * BYTES_PER_PIXEL;
+    if stride < row_length {
+        return Err(Error::InvalidStride(stride));
+    }
+    let required = match rect.size.height as usize {
+        0 => 0,
+        height => stride * (height - 1) + row_length,
+    };
+    if data.len() < required {
+        return Err(Error::BufferTooSmall {
+            required,
+            actual: data.len(),
+        });
+    }
+    if rect.is_empty() {
+        return Ok(());
+    }
+
+    let _guard = device.make_context_current_guarded(context)?;
+    let texture_target = device.surface_gl_texture_target();
+    let gl = Gl::load_with(device.proc_address_loader(context));
+    gl_utils::drain_pre_existing_gl_errors(&gl);
+    unsafe {
+        let gl3 = GLVersion::current(&gl).major >= 3;
+        let saved_state = SavedState::save(&gl, gl3, texture_target);
+        let result = upload(&gl, gl3, texture_target, &info, rect, data, stride);
+        saved_state.restore(&gl);
+        result
+    }
+}
+
+// Uploads `rect` of the surface from `data`, with the state that uploading needs already saved.
+unsafe fn upload(
+    gl: &Gl,
+    gl3: bool,
+    texture_target: GLenum,
+    info: &SurfaceInfo,
+    rect: Rect<i32>,
+    data: &[u8],
+    stride: usize,
+) -> Result<(), Error> {
+    let framebuffer_target = if gl3 {
+        gl::READ_FRAMEBUFFER
+    } else {
+        gl::FRAMEBUFFER
+    };
+    gl.BindFramebuffer(framebuffer_target, info.framebuffer_object);
+    let (mut attachment_type, mut texture) = (0, 0);
+    gl.GetFramebufferAttachmentParameteriv(
+        framebuffer_target,
+        gl::COLOR_ATTACHMENT0,
+        gl::FRAMEBUFFER_ATTACHMENT_OBJECT_TYPE,
+        &mut attachment_type,
+    );
+    gl.GetFramebufferAttachmentParameteriv(
+        framebuffer_target,
+        gl::COLOR_ATTACHMENT0,
+        gl::FRAMEBUFFER_ATTACHMENT_OBJECT_NAME,
+        &mut texture,
+    );
+    // Surfaces whose color buffer is a renderbuffer can't be uploaded to.
+    if attachment_type as GLenum != gl::TEXTURE {
+        return Err(Error::UnsupportedSurfaceFormat);
+    }
+    gl.BindTexture(texture_target, texture as GLuint);
+    if gl3 {
+        gl.PixelStorei(gl::UNPACK_ROW_LENGTH, 0);
+        gl.PixelStorei(gl::UNPACK_SKIP_ROWS, 0);
+        gl.PixelStorei(gl::UNPACK_SKIP_PIXELS, 0);
+    }
+    gl.PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+
+    // Floating-point color buffers can only be uploaded to from floats on OpenGL ES.
+    let (ty, channel_size) = if info.format == gl::RGBA16F {
+        (gl::FLOAT, 4)
+    } else {
+        (gl::UNSIGNED_BYTE, 1)
+    };
+    let size = rect.size;
+    let length = size.width as usize * size.height as usize * BYTES_PER_PIXEL * channel_size;
+    let y = info.size.height - rect.max_y();
+
+    if gl3 {
+        let mut buffer = 0;
+        gl.GenBuffers(1, &mut buffer);
+        gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, buffer);
+        gl.BufferData(
+            gl::PIXEL_UNPACK_BUFFER,
+            length as _,
+            ptr::null(),
+            gl::STREAM_DRAW,
+        );
+        let mapped = gl.MapBufferRange(
+            gl::PIXEL_UNPACK_BUFFER,
+            0,
+            length as _,
+            gl::MAP_WRITE_BIT | gl::MAP_INVALIDATE_BUFFER_BIT,
+        );
+        if mapped.is_null() {
+            gl.DeleteBuffers(1, &buffer);
+            return Err(Error::Failed);
+        }
+        stage(
+            data,
+            stride,
+            size,
+            ty,
+            slice::from_raw_parts_mut(mapped as *mut u8, length),
+        );
+        let unmapped = gl.UnmapBuffer(gl::PIXEL_UNPACK_BUFFER) != gl::FALSE;
+        if unmapped {
+            tex_sub_image(gl, texture_target, rect.origin.x, y, size, ty, ptr::null());
+        }
+        // The buffer lives on until the upload has read it.
+        gl.DeleteBuffers(1, &buffer);
+        if !unmapped {
+            return Err(Error::Failed);
+        }
+    } else {
+        let mut staging = vec![0; length];
+        stage(data, stride, size, ty, &mut staging);
+        tex_sub_image(
+            gl,
+            texture_target,
+            rect.origin.x,
+            y,
+            size,
+            ty,
+            staging.as_ptr(),
+        );
+    }
+
+    match gl.GetError() {
+        gl::NO_ERROR => Ok(()),
+        // OpenGL ES only converts between a few formats and pixel types.
+        gl::INVALID_OPERATION => Err(Error::UnsupportedSurfaceFormat),
+        _ => Err(Error::Failed),
+    }
+}
+
+// Copies the rows of `data`, top row first, into `staging`, bottom row first and tightly packed,
+// converting the channels to floats if `ty` is `GL_FLOAT`.
+fn stage(data: &[u8], stride: usize, size: Size2D<i32>, ty: GLenum, staging: &mut [u8]) {
+    let row_length = size.width as usize * BYTES_PER_PIXEL;
+    let staging_row_length = staging.len() / size.height as usize;
+    for (row, staging_row) in staging.chunks_mut(staging_row_length).rev().enumerate() {
+        let data_row = &data[row * stride..row * stride + row_length];
+        if ty == gl::FLOAT {
+            for (channel, bytes) in data_row.iter().zip(staging_row.chunks_mut(4)) {
+                bytes.copy_from_slice(&(*channel as f32 / 255.0).to_ne_bytes());
+            }
+        } else {
+            staging_row.copy_from_slice(data_row);
+        }
+    }
+}
+
+unsafe fn tex_sub_image(
+    gl: &Gl,
+    texture_target: GLenum,
+    x: GLint,
+    y: GLint,
+    size: Size2D<i32>,
+    ty: GLenum,
+    pixels: *const u8,
+) {
+    gl.TexSubImage2D(
+        texture_target,
+        0,
+        x,
+        y,
+        size.width,
+        size.height,
+        gl::RGBA,
+        ty,
+        pixels as *const c_void,
+    );
+}
+
+impl SavedState {
+    pub(crate) unsafe fn save(gl: &Gl, gl3: bool, texture_target: GLenum) -> SavedState {
+        let mut saved_state = SavedState {
+            framebuffer: 0,
+            texture_target,
+            texture: 0,
+            gl3_state: None,
+            unpack_alignment: 0,
+        };
+        gl.GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut saved_state.framebuffer);
+        gl.GetIntegerv(
+            cpu_layer::texture_binding(texture_target),
+            &mut saved_state.texture,
+        );
+        gl.GetIntegerv(gl::UNPACK_ALIGNMENT, &mut saved_state.unpack_alignment);
+        if gl3 {
+            let mut gl3_state = Gl3State {
+                read_framebuffer: 0,
+                unpack_buffer: 0,
+                unpack_row_length: 0,
+                unpack_skip_rows: 0,
+                unpack_skip_pixels: 0,
+            };
+            gl.GetIntegerv(
+                gl::READ_FRAMEBUFFER_BINDING,
+                &mut gl3_state.read_framebuffer,
+            );
+            gl.GetIntegerv(
+                gl::PIXEL_UNPACK_BUFFER_BINDING,
+                &mut gl3_state.unpack_buffer,
+            );
+            gl.GetIntegerv(gl::UNPACK_ROW_LENGTH, &mut gl3_state.unpack_row_length);
+            gl.GetIntegerv(gl::UNPACK_SKIP_ROWS, &mut gl3_state.unpack_skip_rows);
+            gl.GetIntegerv(gl::UNPACK_SKIP_PIXELS, &mut gl3_state.unpack_skip_pixels);
+            saved_state.gl3_state = Some(gl3_state);
+        }
+        saved_state
+    }
+
+    pub(crate) unsafe fn restore(&self, gl: &Gl) {
+        gl.PixelStorei(gl::UNPACK_ALIGNMENT, self.unpack_alignment);
+        gl.BindTexture(self.texture_target, self.texture as GLuint);
+        // `GL_FRAMEBUFFER_BINDING` is the draw framebuffer binding where the two are separate.
+        match self.gl3_state {
+            None => gl.BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer as GLuint),
+            Some(ref gl3_state) => {
+                gl.PixelStorei(gl::UNPACK_SKIP_PIXELS, gl3_state.unpack_skip_pixels);
+                gl.PixelStorei(gl::UNPACK_SKIP_ROWS, gl3_state.unpack_skip_rows);
+                gl.PixelStorei(gl::UNPACK_ROW_LENGTH, gl3_state.unpack_row_length);
+                gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, gl3_state.unpack_buffer as GLuint);
+                gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.framebuffer as GLuint);
+                gl.BindFramebuffer(gl::READ_FRAMEBUFFER, gl3_state.read_framebuffer as GLuint);
+            }
+        }
+    }
+}
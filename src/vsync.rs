@@ -0,0 +1,195 @@
+// surfman/surfman/src/vsync.rs
+//
+//! Switching widget surfaces between vsync and immediate presentation to suit their frame rate.
+//!
+//! An application that renders faster than the display refreshes gets its frames on screen sooner
+//! without vsync, at the cost of tearing; one that renders slower only tears without it.
+//! `Device::set_auto_swap_interval()` chooses between the two for a widget surface from the
+//! timestamps of its presentations, which `Device::presentation_timestamps()` also reports.
+//!
+//! The frame time of each presentation is how long the application took to render it: the time
+//! from the return of the previous `present_surface()` to the call that presented it. The refresh
+//! interval of the display is taken to be the shortest interval between two frames presented with
+//! vsync.
+
+use crate::surface::PresentHistory;
+use crate::Error;
+
+use log::warn;
+use std::time::Duration;
+
+// How many presentations in a row must call for the other decision before the surface switches.
+const SWITCH_FRAMES: u32 = 16;
+
+// How many presentations after each switch go unmeasured, while the window system's queue of
+// buffers fills or drains.
+const SETTLE_FRAMES: u32 = 4;
+
+// The weight of each new frame time in the smoothed frame time.
+const SMOOTHING: f64 = 0.125;
+
+/// How `Device::set_auto_swap_interval()` chooses between vsync and immediate presentation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AutoVsyncPolicy {
+    /// How many milliseconds shorter than the refresh interval of the display the frame time must
+    /// be before vsync is turned off.
+    ///
+    /// Vsync is turned back on once the frame time reaches the refresh interval, so this is also
+    /// the band within which the surface doesn't switch back and forth.
+    pub target_margin_ms: f32,
+}
+
+impl Default for AutoVsyncPolicy {
+    #[inline]
+    fn default() -> AutoVsyncPolicy {
+        AutoVsyncPolicy {
+            target_margin_ms: 2.0,
+        }
+    }
+}
+
+/// How a surface under automatic vsync is presented at the moment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VsyncDecision {
+    /// Frames wait for a vertical blank, with a swap interval of 1.
+    ///
+    /// Surfaces start out this way, and return to it once frames take as long as the refresh
+    /// interval.
+    Vsync,
+    /// Frames are shown as soon as they're presented, with a swap interval of 0, because they're
+    /// rendered faster than the display refreshes.
+    Immediate,
+}
+
+/// What automatic vsync has found and decided for a surface, as reported by
+/// `Device::auto_swap_interval_stats()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AutoVsyncStats {
+    /// How the surface is presented at the moment.
+    pub decision: VsyncDecision,
+    /// How many times the surface has switched between vsync and immediate presentation.
+    pub switches: u64,
+    /// The refresh interval of the display, or `None` until it has been measured.
+    pub refresh_interval: Option<Duration>,
+    /// The smoothed frame time, or `None` until it has been measured.
+    pub frame_time: Option<Duration>,
+}
+
+// Decides when a widget surface under automatic vsync switches, one presentation at a time.
+//
+// This only looks at the presentation history that it's given, and leaves setting the swap
+// interval to the caller, so that it can be driven with made-up timestamps.
+pub(crate) struct AutoVsync {
+    policy: AutoVsyncPolicy,
+    decision: VsyncDecision,
+    switches: u64,
+    refresh_interval: Option<Duration>,
+    frame_time: Option<Duration>,
+    // The frame ID of the last presentation looked at.
+    last_frame_id: Option<u64>,
+    // How many more presentations go unmeasured.
+    settling: u32,
+    // How many presentations in a row have called for the other decision.
+    streak: u32,
+    // Set once the platform refuses a swap interval, after which the surface is left alone.
+    refused: bool,
+}
+
+impl AutoVsync {
+    // Starts out with vsync, which the caller sets up.
+    pub(crate) fn new(policy: AutoVsyncPolicy) -> AutoVsync {
+        AutoVsync {
+            policy,
+            decision: VsyncDecision::Vsync,
+            switches: 0,
+            refresh_interval: None,
+            frame_time: None,
+            last_frame_id: None,
+            settling: SETTLE_FRAMES,
+            streak: 0,
+            refused: false,
+        }
+    }
+
+    // Measures the latest presentation in `history`, and switches with `set_swap_interval` once
+    // enough of them in a row call for it. Call this after every present.
+    pub(crate) fn update<F>(&mut self, history: &PresentHistory, set_swap_interval: F)
+    where
+        F: FnOnce(i32) -> Result<i32, Error>,
+    {
+        let (previous, latest) = match history.latest_pair() {
+            Some(pair) => pair,
+            None => return,
+        };
+        if self.last_frame_id == Some(latest.frame_id) || self.refused {
+            return;
+        }
+        self.last_frame_id = Some(latest.frame_id);
+        if self.settling > 0 {
+            self.settling -= 1;
+            return;
+        }
+
+        // The previous present returned once the window system took its buffer, at which point
+        // the application started on this frame.
+        let started = previous.compositor_ack.unwrap_or(previous.submit);
+        let frame_time = latest.submit.saturating_duration_since(started);
+        let frame_time = match self.frame_time {
+            Some(smoothed) => smoothed.mul_f64(1.0 - SMOOTHING) + frame_time.mul_f64(SMOOTHING),
+            None => frame_time,
+        };
+        self.frame_time = Some(frame_time);
+        if self.decision == VsyncDecision::Vsync {
+            let interval = latest.submit.saturating_duration_since(previous.submit);
+            self.refresh_interval = Some(match self.refresh_interval {
+                Some(refresh_interval) => refresh_interval.min(interval),
+                None => interval,
+            });
+        }
+        let refresh_interval = match self.refresh_interval {
+            Some(refresh_interval) => refresh_interval,
+            None => return,
+        };
+
+        let margin = Duration::from_secs_f32(self.policy.target_margin_ms.max(0.0) / 1000.0);
+        let (next_decision, interval) = match self.decision {
+            VsyncDecision::Vsync if frame_time + margin < refresh_interval => {
+                (VsyncDecision::Immediate, 0)
+            }
+            VsyncDecision::Immediate if frame_time >= refresh_interval => (VsyncDecision::Vsync, 1),
+            _ => {
+                self.streak = 0;
+                return;
+            }
+        };
+        self.streak += 1;
+        if self.streak < SWITCH_FRAMES {
+            return;
+        }
+
+        self.streak = 0;
+        match set_swap_interval(interval) {
+            Ok(_) => {
+                self.decision = next_decision;
+                self.switches += 1;
+                self.settling = SETTLE_FRAMES;
+            }
+            Err(err) => {
+                warn!(
+                    "Couldn't switch a surface to {:?} presentation, so it's left as it is: {:?}",
+                    next_decision, err
+                );
+                self.refused = true;
+            }
+        }
+    }
+
+    pub(crate) fn stats(&self) -> AutoVsyncStats {
+        AutoVsyncStats {
+            decision: self.decision,
+            switches: self.switches,
+            refresh_interval: self.refresh_interval,
+            frame_time: self.frame_time,
+        }
+    }
+}
@@ -0,0 +1,103 @@
+/* surfman/surfman/tests/c/test_producer.c
+ *
+ * A producer written in C, which `test_foreign_producer()` builds as a shared library and runs
+ * against a chain that requires fences. It only uses surfman through the entry points that it's
+ * given, like a plugin would, and renders through a renderer that the test passes in, which
+ * imports each frame's dma-buf into its own GL context. No pixels pass through the CPU.
+ *
+ * It submits two frames. The first is red all over. The second paints the top left quarter green
+ * and damages only that, so the consumer should see green over the red that the chain copied
+ * from the first frame. Along the way it checks that surfman rejects calls that break the rules
+ * of the ABI.
+ *
+ * Returns 0 on success, -1 if the chain can't hand out dma-bufs here, or the line of the first
+ * check that failed.
+ */
+
+#define _GNU_SOURCE
+
+#include "surfman_producer.h"
+
+#include <stdint.h>
+#include <sys/eventfd.h>
+
+#define CHECK(condition)      \
+    do {                      \
+        if (!(condition))     \
+            return __LINE__;  \
+    } while (0)
+
+/* Renders `color` into `rect` of `frame` through its handle, and returns 0 once the rendering
+ * has finished. */
+typedef int (*SurfmanTestRender)(void *renderer,
+                                 const SurfmanFrameDesc *frame,
+                                 SurfmanRect rect,
+                                 const uint8_t color[4]);
+
+/* The renderer has finished by the time it returns, so a sync file stand-in that has already
+ * signaled will do: an eventfd with a nonzero count is readable. */
+static int signaled_fence(void)
+{
+    return eventfd(1, EFD_CLOEXEC);
+}
+
+int surfman_test_producer(SurfmanGetProducerApi get_api,
+                          SurfmanChain *chain,
+                          SurfmanTestRender render,
+                          void *renderer)
+{
+    static const uint8_t red[4] = { 255, 0, 0, 255 };
+    static const uint8_t green[4] = { 0, 255, 0, 255 };
+
+    /* Version negotiation. */
+    CHECK(get_api(SURFMAN_PRODUCER_ABI_VERSION + 1) == NULL);
+    const SurfmanProducerApi *api = get_api(SURFMAN_PRODUCER_ABI_VERSION);
+    CHECK(api != NULL);
+    CHECK(api->abi_version == SURFMAN_PRODUCER_ABI_VERSION);
+
+    SurfmanFrameDesc frame = { 0 };
+    CHECK(api->submit(chain, -1, NULL, 0) == SURFMAN_ERROR_NOT_ACQUIRED);
+    frame.abi_version = SURFMAN_PRODUCER_ABI_VERSION + 1;
+    frame.handle_types = SURFMAN_IMAGE_HANDLE_DMA_BUF;
+    CHECK(api->acquire(chain, &frame) == SURFMAN_ERROR_VERSION_MISMATCH);
+    frame.abi_version = SURFMAN_PRODUCER_ABI_VERSION;
+    CHECK(api->acquire(NULL, &frame) == SURFMAN_ERROR_INVALID_ARGUMENT);
+
+    /* Handle type negotiation. */
+    frame.handle_types = SURFMAN_IMAGE_HANDLE_IOSURFACE | SURFMAN_IMAGE_HANDLE_D3D_SHARED;
+    CHECK(api->acquire(chain, &frame) == SURFMAN_ERROR_UNSUPPORTED_HANDLE_TYPE);
+    frame.handle_types = SURFMAN_IMAGE_HANDLE_DMA_BUF | SURFMAN_IMAGE_HANDLE_IOSURFACE;
+
+    /* The first frame. */
+    int32_t status = api->acquire(chain, &frame);
+    if (status == SURFMAN_ERROR_UNSUPPORTED_HANDLE_TYPE)
+        return -1;
+    CHECK(status == SURFMAN_OK);
+    CHECK(api->acquire(chain, &frame) == SURFMAN_ERROR_ALREADY_ACQUIRED);
+    CHECK(frame.handle_type == SURFMAN_IMAGE_HANDLE_DMA_BUF);
+    CHECK(frame.handle >= 0);
+    CHECK(frame.frame_id == 0);
+    CHECK(frame.width > 1 && frame.height > 1);
+    CHECK(frame.stride >= (uint32_t)frame.width * 4);
+
+    SurfmanRect whole = { 0, 0, frame.width, frame.height };
+    CHECK(render(renderer, &frame, whole, red) == 0);
+
+    /* Rejected submissions leave the frame acquired. */
+    CHECK(api->submit(chain, -1, NULL, 0) == SURFMAN_ERROR_FENCE_REQUIRED);
+    SurfmanRect outside = { frame.width - 1, 0, 2, 1 };
+    CHECK(api->submit(chain, signaled_fence(), &outside, 1) == SURFMAN_ERROR_DAMAGE_OUT_OF_BOUNDS);
+    SurfmanRect negative = { 0, 0, -1, 1 };
+    CHECK(api->submit(chain, signaled_fence(), &negative, 1) == SURFMAN_ERROR_INVALID_ARGUMENT);
+    CHECK(api->submit(chain, signaled_fence(), NULL, 0) == SURFMAN_OK);
+
+    /* The second frame, which is another image. */
+    uint64_t buffer_id = frame.buffer_id;
+    CHECK(api->acquire(chain, &frame) == SURFMAN_OK);
+    CHECK(frame.frame_id == 1);
+    CHECK(frame.buffer_id != buffer_id);
+    SurfmanRect top_left = { 0, 0, frame.width / 2, frame.height / 2 };
+    CHECK(render(renderer, &frame, top_left, green) == 0);
+    CHECK(api->submit(chain, signaled_fence(), &top_left, 1) == SURFMAN_OK);
+    return 0;
+}
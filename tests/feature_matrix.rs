@@ -0,0 +1,72 @@
+// surfman/surfman/tests/feature_matrix.rs
+//
+//! Checks that the backend features build on their own and in combination.
+//!
+//! Each set of features is built with `--no-default-features`, in a separate target directory so
+//! that it doesn't contend with the build running these tests.
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_env = "ohos"))
+))]
+static FEATURE_SETS: [&str; 5] = [
+    "sm-generic-egl",
+    "sm-wayland",
+    "sm-x11",
+    "sm-x11 sm-wayland",
+    "sm-x11 sm-wayland-default",
+];
+
+#[cfg(target_os = "macos")]
+static FEATURE_SETS: [&str; 1] = ["sm-cgl"];
+
+#[cfg(target_os = "windows")]
+static FEATURE_SETS: [&str; 4] = ["sm-wgl", "sm-angle", "sm-wgl sm-angle", "sm-no-wgl"];
+
+#[cfg(target_os = "android")]
+static FEATURE_SETS: [&str; 1] = ["sm-android"];
+
+#[cfg(target_env = "ohos")]
+static FEATURE_SETS: [&str; 1] = ["sm-ohos"];
+
+fn check_with_features(features: &str) -> Output {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    Command::new(env!("CARGO"))
+        .current_dir(manifest_dir)
+        .env(
+            "CARGO_TARGET_DIR",
+            manifest_dir.join("target").join("feature-matrix"),
+        )
+        .args([
+            "check",
+            "--lib",
+            "--no-default-features",
+            "--features",
+            features,
+        ])
+        .output()
+        .expect("Failed to run cargo!")
+}
+
+#[test]
+fn test_backend_feature_sets() {
+    for features in &FEATURE_SETS {
+        let output = check_with_features(features);
+        assert!(
+            output.status.success(),
+            "Failed to build with features `{}`:\n{}",
+            features,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
+
+#[test]
+fn test_no_backend_features() {
+    let output = check_with_features("");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("surfman has no backend"));
+}